@@ -0,0 +1,126 @@
+/*
+ * C declarations for the ARCADIA native library, kept in sync with src/ffi.rs. Build the
+ * library with the `ffi` feature and the "cdylib" crate-type to get arcadia.dll, libarcadia.so
+ * or libarcadia.dylib.
+ */
+
+#ifndef ARCADIA_H
+#define ARCADIA_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/*
+ * Every function returning ArcadiaStatus reports failures through it; the message is then
+ * available from arcadia_last_error() on the same thread. Pointer arguments must be null or
+ * valid, and strings NUL-terminated UTF-8. Strings written to `out_json` parameters are owned by
+ * the caller and released with arcadia_string_free().
+ */
+
+typedef enum ArcadiaStatus {
+  ARCADIA_STATUS_OK = 0,
+  ARCADIA_STATUS_NULL_POINTER = 1,
+  ARCADIA_STATUS_INVALID_UTF8 = 2,
+  ARCADIA_STATUS_INVALID_ARGUMENT = 3,
+  ARCADIA_STATUS_NOT_FOUND = 4,
+  ARCADIA_STATUS_FAILED = 5,
+  ARCADIA_STATUS_PANIC = 6,
+} ArcadiaStatus;
+
+typedef struct ArcadiaDialogue ArcadiaDialogue;
+
+typedef struct ArcadiaEmotion ArcadiaEmotion;
+
+typedef struct ArcadiaVectorIndex ArcadiaVectorIndex;
+
+typedef struct ArcadiaMood {
+  float tension;
+  float valence;
+  float energy;
+} ArcadiaMood;
+
+/*
+ * Returns the generated text for `prompt`; the string must stay valid until the callback is
+ * called again or the calling function returns. Null means generation failed.
+ */
+typedef const char *(*ArcadiaGenerateFn)(void *user_data, const char *prompt);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/* Message of the last failed call on this thread, or null; valid until the next failure */
+const char *arcadia_last_error(void);
+
+void arcadia_string_free(char *s);
+
+ArcadiaStatus arcadia_vector_index_new(const char *url, const char *api_key, ArcadiaVectorIndex **out);
+
+void arcadia_vector_index_free(ArcadiaVectorIndex *index);
+
+ArcadiaStatus arcadia_vector_create_collection(ArcadiaVectorIndex *index,
+                                               const char *name,
+                                               size_t dimension,
+                                               const char *model);
+
+/* `payload_json` may be null; otherwise it must be a JSON object */
+ArcadiaStatus arcadia_vector_upsert(ArcadiaVectorIndex *index,
+                                    const char *collection,
+                                    const char *id,
+                                    const float *vector,
+                                    size_t len,
+                                    const char *payload_json);
+
+/* Writes a JSON array of {"id", "score", "payload"} to `out_json` */
+ArcadiaStatus arcadia_vector_search(ArcadiaVectorIndex *index,
+                                    const char *collection,
+                                    const float *vector,
+                                    size_t len,
+                                    size_t limit,
+                                    char **out_json);
+
+ArcadiaEmotion *arcadia_emotion_new(void);
+
+void arcadia_emotion_free(ArcadiaEmotion *emotion);
+
+ArcadiaStatus arcadia_emotion_set_player_mood(ArcadiaEmotion *emotion,
+                                              const char *player,
+                                              ArcadiaMood mood);
+
+ArcadiaStatus arcadia_emotion_publish_target(ArcadiaEmotion *emotion, ArcadiaMood mood);
+
+ArcadiaStatus arcadia_emotion_target(ArcadiaEmotion *emotion, ArcadiaMood *out);
+
+/*
+ * `request_json` is {"actions": [...], "goal": {...}, "state": {...}}; writes
+ * {"goal", "actions": [names], "cost"}, or returns ARCADIA_STATUS_NOT_FOUND when no plan exists
+ */
+ArcadiaStatus arcadia_goap_plan(const char *request_json, char **out_json);
+
+ArcadiaStatus arcadia_dialogue_start(const char *tree_toml, ArcadiaDialogue **out);
+
+void arcadia_dialogue_free(ArcadiaDialogue *dialogue);
+
+/* Writes {"id", "speaker", "text", "choices": [{"index", "text"}], "ai", "finished"} */
+ArcadiaStatus arcadia_dialogue_current(ArcadiaDialogue *dialogue, char **out_json);
+
+/* `context_json` may be null or {"state": {...}, "relationships": {"id": value}} */
+ArcadiaStatus arcadia_dialogue_choose(ArcadiaDialogue *dialogue,
+                                      size_t index,
+                                      const char *context_json);
+
+ArcadiaStatus arcadia_dialogue_advance(ArcadiaDialogue *dialogue);
+
+/* Let the NPC answer inside an AI node; writes {"line", "fell_back", "exited"} */
+ArcadiaStatus arcadia_dialogue_ai_turn(ArcadiaDialogue *dialogue,
+                                       ArcadiaGenerateFn generate,
+                                       void *user_data,
+                                       const char *player_input,
+                                       char **out_json);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* ARCADIA_H */
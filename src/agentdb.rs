@@ -0,0 +1,188 @@
+// AgentDB
+//
+// Small document store for agent state that is not a vector: learning progress, statistics,
+// bandit arms, schedules. Records are JSON values grouped in tables, and every table belongs to a
+// namespace so games and save slots sharing a process never see each other's data.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::namespace::{Namespace, NamespaceQuota};
+use crate::security::encryption::{Encryption, EncryptionError};
+use crate::versioning::{self, MigrationError, MigrationRegistry, AGENTDB_FORMAT, AGENTDB_VERSION};
+use crate::vector_index::PLAYER_FIELD;
+
+#[derive(Debug)]
+pub enum AgentDbError {
+    QuotaExceeded { namespace: String, limit: usize },
+    Io(std::io::Error),
+    Serialization(serde_json::Error),
+    Encryption(EncryptionError),
+    Migration(MigrationError),
+}
+
+impl fmt::Display for AgentDbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AgentDbError::QuotaExceeded { namespace, limit } => {
+                write!(f, "namespace '{}' exceeded its quota of {} records", namespace, limit)
+            }
+            AgentDbError::Io(err) => write!(f, "agentdb io error: {}", err),
+            AgentDbError::Serialization(err) => write!(f, "agentdb serialization error: {}", err),
+            AgentDbError::Encryption(err) => write!(f, "agentdb encryption error: {}", err),
+            AgentDbError::Migration(err) => write!(f, "agentdb migration error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for AgentDbError {}
+
+impl From<std::io::Error> for AgentDbError {
+    fn from(err: std::io::Error) -> Self {
+        AgentDbError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for AgentDbError {
+    fn from(err: serde_json::Error) -> Self {
+        AgentDbError::Serialization(err)
+    }
+}
+
+impl From<EncryptionError> for AgentDbError {
+    fn from(err: EncryptionError) -> Self {
+        AgentDbError::Encryption(err)
+    }
+}
+
+impl From<MigrationError> for AgentDbError {
+    fn from(err: MigrationError) -> Self {
+        match err {
+            MigrationError::Encryption(err) => AgentDbError::Encryption(err),
+            other => AgentDbError::Migration(other),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AgentDb {
+    // Qualified table name ("save-1__curriculum") -> key -> record
+    tables: HashMap<String, BTreeMap<String, Value>>,
+    #[serde(skip)]
+    quotas: HashMap<Namespace, NamespaceQuota>,
+}
+
+impl AgentDb {
+    pub fn new() -> Self {
+        AgentDb::default()
+    }
+
+    pub fn set_quota(&mut self, namespace: &Namespace, quota: NamespaceQuota) {
+        self.quotas.insert(namespace.clone(), quota);
+    }
+
+    // Number of records stored in a namespace
+    pub fn record_count(&self, namespace: &Namespace) -> usize {
+        self.tables
+            .iter()
+            .filter(|(name, _)| namespace.owns(name))
+            .map(|(_, table)| table.len())
+            .sum()
+    }
+
+    pub fn put(&mut self, namespace: &Namespace, table: &str, key: &str, value: Value) -> Result<(), AgentDbError> {
+        let qualified = namespace.qualify(table);
+        let is_new = !self.tables.get(&qualified).is_some_and(|t| t.contains_key(key));
+        if is_new {
+            if let Some(limit) = self.quotas.get(namespace).and_then(|q| q.max_records) {
+                if self.record_count(namespace) >= limit {
+                    return Err(AgentDbError::QuotaExceeded { namespace: namespace.to_string(), limit });
+                }
+            }
+        }
+        self.tables.entry(qualified).or_default().insert(key.to_string(), value);
+        Ok(())
+    }
+
+    pub fn get(&self, namespace: &Namespace, table: &str, key: &str) -> Option<&Value> {
+        self.tables.get(&namespace.qualify(table))?.get(key)
+    }
+
+    pub fn delete(&mut self, namespace: &Namespace, table: &str, key: &str) -> Option<Value> {
+        self.tables.get_mut(&namespace.qualify(table))?.remove(key)
+    }
+
+    // All records of a table in key order
+    pub fn scan(&self, namespace: &Namespace, table: &str) -> impl Iterator<Item = (&String, &Value)> {
+        self.tables.get(&namespace.qualify(table)).into_iter().flatten()
+    }
+
+    // Records whose key starts with `prefix`
+    pub fn scan_prefix<'a>(
+        &'a self,
+        namespace: &Namespace,
+        table: &str,
+        prefix: &'a str,
+    ) -> impl Iterator<Item = (&'a String, &'a Value)> + 'a {
+        self.tables
+            .get(&namespace.qualify(table))
+            .into_iter()
+            .flat_map(move |t| t.range(prefix.to_string()..).take_while(move |(k, _)| k.starts_with(prefix)))
+    }
+
+    // Table names (unqualified) in a namespace
+    pub fn tables(&self, namespace: &Namespace) -> Vec<&str> {
+        self.tables.keys().filter_map(|name| namespace.local(name)).collect()
+    }
+
+    // Remove every table of a namespace, returning the number of records removed
+    pub fn purge_namespace(&mut self, namespace: &Namespace) -> usize {
+        let removed = self.record_count(namespace);
+        self.tables.retain(|name, _| !namespace.owns(name));
+        removed
+    }
+
+    // Records about a player in every namespace: keyed "<player>" or "<player>:<...>", or carrying
+    // a player_id field. Returns (qualified table, key, record).
+    pub fn player_records(&self, player_id: &str) -> Vec<(&str, &str, &Value)> {
+        let owns_key = |key: &str| key == player_id || key.strip_prefix(player_id).is_some_and(|r| r.starts_with(':'));
+        self.tables
+            .iter()
+            .flat_map(|(table, records)| records.iter().map(move |(key, value)| (table.as_str(), key.as_str(), value)))
+            .filter(|(_, key, value)| owns_key(key) || value.get(PLAYER_FIELD).and_then(Value::as_str) == Some(player_id))
+            .collect()
+    }
+
+    pub(crate) fn delete_qualified(&mut self, qualified_table: &str, key: &str) -> Option<Value> {
+        self.tables.get_mut(qualified_table)?.remove(key)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), AgentDbError> {
+        self.save_with(path, None)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, AgentDbError> {
+        AgentDb::load_with(path, None)
+    }
+
+    // Save as the current schema version, encrypting the file when `encryption` is set
+    pub fn save_with(&self, path: &Path, encryption: Option<&Encryption>) -> Result<(), AgentDbError> {
+        versioning::write_versioned(path, AGENTDB_FORMAT, AGENTDB_VERSION, serde_json::to_value(self)?, encryption)?;
+        Ok(())
+    }
+
+    // Load a plaintext or encrypted file of any known version; encrypted files need `encryption`
+    pub fn load_with(path: &Path, encryption: Option<&Encryption>) -> Result<Self, AgentDbError> {
+        AgentDb::load_migrating(path, &MigrationRegistry::with_builtin(), encryption)
+    }
+
+    // Load with game-registered migration steps in addition to the built-in ones
+    pub fn load_migrating(path: &Path, registry: &MigrationRegistry, encryption: Option<&Encryption>) -> Result<Self, AgentDbError> {
+        let outcome = versioning::read_versioned(path, AGENTDB_FORMAT, registry, encryption)?;
+        Ok(serde_json::from_value(outcome.data)?)
+    }
+}
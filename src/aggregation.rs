@@ -0,0 +1,316 @@
+// Payload aggregation over vector collections
+//
+// Designers ask questions like "how many memories does Zara have about the player, by topic?"
+// that need counts and statistics, not a dump of the collection. `VectorIndex::aggregate` runs a
+// list of aggregations over the searchable points of a collection that match a payload filter,
+// without copying any point:
+//
+//   count                          matching points
+//   count_by   {field}             points per value of a payload field; array fields count each
+//                                  element, so a point tagged with two topics counts for both
+//   stats      {field}             count/min/max/sum/mean of a numeric field
+//   histogram  {field, buckets}    equal-width buckets between the field's min and max
+//
+// `search_faceted` returns the usual top hits together with value counts of the given fields
+// among those hits, for search UIs that show "12 about combat, 3 about trade" next to the results.
+// Aggregations deserialize from {"op": "count_by", "field": "topic"}, the form the debug server's
+// `aggregate` command accepts.
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::vector_index::{unix_now, SearchResult, VectorIndex, VectorIndexError, VectorPoint};
+
+// Points match when every listed field equals the given value; an array field matches when it
+// contains the value
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PayloadFilter {
+    pub equals: BTreeMap<String, Value>,
+}
+
+impl PayloadFilter {
+    pub fn new() -> Self {
+        PayloadFilter::default()
+    }
+
+    pub fn field(mut self, field: &str, value: impl Into<Value>) -> Self {
+        self.equals.insert(field.to_string(), value.into());
+        self
+    }
+
+    pub fn matches(&self, payload: &HashMap<String, Value>) -> bool {
+        self.equals.iter().all(|(field, expected)| match payload.get(field) {
+            Some(Value::Array(values)) if !expected.is_array() => values.contains(expected),
+            Some(value) => value == expected,
+            None => false,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Aggregation {
+    Count,
+    CountBy { field: String },
+    Stats { field: String },
+    Histogram { field: String, buckets: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct NumericStats {
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub sum: f64,
+    pub mean: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct HistogramBucket {
+    // [from, to); the last bucket includes `to`
+    pub from: f64,
+    pub to: f64,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum AggregateResult {
+    Count { count: usize },
+    CountBy {
+        field: String,
+        counts: BTreeMap<String, usize>,
+        // Matching points without the field
+        missing: usize,
+    },
+    Stats {
+        field: String,
+        // None when no matching point has a numeric value for the field
+        stats: Option<NumericStats>,
+    },
+    Histogram { field: String, buckets: Vec<HistogramBucket> },
+}
+
+// Facet key of a payload value: strings as they are, everything else as JSON
+fn facet_key(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn count_values<'a>(
+    payloads: impl Iterator<Item = &'a HashMap<String, Value>>,
+    field: &str,
+) -> (BTreeMap<String, usize>, usize) {
+    let mut counts = BTreeMap::new();
+    let mut missing = 0;
+    for payload in payloads {
+        match payload.get(field) {
+            Some(Value::Array(values)) => {
+                for value in values {
+                    *counts.entry(facet_key(value)).or_default() += 1;
+                }
+            }
+            Some(Value::Null) | None => missing += 1,
+            Some(value) => *counts.entry(facet_key(value)).or_default() += 1,
+        }
+    }
+    (counts, missing)
+}
+
+fn numbers(points: &[&VectorPoint], field: &str) -> Vec<f64> {
+    points
+        .iter()
+        .filter_map(|point| point.payload.get(field).and_then(Value::as_f64))
+        .filter(|value| value.is_finite())
+        .collect()
+}
+
+fn stats(values: &[f64]) -> Option<NumericStats> {
+    if values.is_empty() {
+        return None;
+    }
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let sum: f64 = values.iter().sum();
+    Some(NumericStats { count: values.len(), min, max, sum, mean: sum / values.len() as f64 })
+}
+
+fn histogram(values: &[f64], buckets: usize) -> Vec<HistogramBucket> {
+    let Some(NumericStats { min, max, .. }) = stats(values) else { return Vec::new() };
+    // A single value (or a constant field) gets one bucket
+    let buckets = if max > min { buckets.max(1) } else { 1 };
+    let width = (max - min) / buckets as f64;
+    let mut result: Vec<HistogramBucket> = (0..buckets)
+        .map(|i| HistogramBucket {
+            from: min + width * i as f64,
+            to: if i + 1 == buckets { max } else { min + width * (i + 1) as f64 },
+            count: 0,
+        })
+        .collect();
+    for value in values {
+        let index = if width > 0.0 { (((value - min) / width) as usize).min(buckets - 1) } else { 0 };
+        result[index].count += 1;
+    }
+    result
+}
+
+// Run `aggregations` over the points that pass `filter`
+pub fn aggregate_points(points: &[&VectorPoint], filter: &PayloadFilter, aggregations: &[Aggregation]) -> Vec<AggregateResult> {
+    let matching: Vec<&VectorPoint> = points.iter().copied().filter(|point| filter.matches(&point.payload)).collect();
+    aggregations
+        .iter()
+        .map(|aggregation| match aggregation {
+            Aggregation::Count => AggregateResult::Count { count: matching.len() },
+            Aggregation::CountBy { field } => {
+                let (counts, missing) = count_values(matching.iter().map(|point| &point.payload), field);
+                AggregateResult::CountBy { field: field.clone(), counts, missing }
+            }
+            Aggregation::Stats { field } => AggregateResult::Stats { field: field.clone(), stats: stats(&numbers(&matching, field)) },
+            Aggregation::Histogram { field, buckets } => {
+                AggregateResult::Histogram { field: field.clone(), buckets: histogram(&numbers(&matching, field), *buckets) }
+            }
+        })
+        .collect()
+}
+
+// Value counts of each field among search hits
+pub fn facets(results: &[SearchResult], fields: &[&str]) -> BTreeMap<String, BTreeMap<String, usize>> {
+    fields
+        .iter()
+        .map(|field| (field.to_string(), count_values(results.iter().map(|result| &result.payload), field).0))
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct FacetedResults {
+    pub results: Vec<SearchResult>,
+    // Field -> value -> hits
+    pub facets: BTreeMap<String, BTreeMap<String, usize>>,
+}
+
+impl VectorIndex {
+    pub fn aggregate(
+        &self,
+        collection: &str,
+        filter: &PayloadFilter,
+        aggregations: &[Aggregation],
+    ) -> Result<Vec<AggregateResult>, VectorIndexError> {
+        let now = unix_now();
+        let points: Vec<&VectorPoint> =
+            self.collection(collection)?.points.values().filter(|point| point.is_searchable(now)).collect();
+        Ok(aggregate_points(&points, filter, aggregations))
+    }
+
+    // Top `limit` hits with value counts of `facet_fields` among them
+    pub fn search_faceted(
+        &self,
+        collection: &str,
+        vector: &[f32],
+        limit: usize,
+        facet_fields: &[&str],
+    ) -> Result<FacetedResults, VectorIndexError> {
+        let results = self.search(collection, vector, limit)?;
+        let facets = facets(&results, facet_fields);
+        Ok(FacetedResults { results, facets })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    use crate::vector_index::{VectorIndexConfig, EXPIRES_AT_FIELD};
+
+    fn memory(id: &str, vector: Vec<f32>, payload: Value) -> VectorPoint {
+        let mut point = VectorPoint::new(id, vector);
+        point.payload = serde_json::from_value(payload).unwrap();
+        point
+    }
+
+    // Zara's memories of the player
+    fn memories() -> Vec<VectorPoint> {
+        vec![
+            memory("m1", vec![1.0, 0.0], json!({"npc": "zara", "topic": ["combat", "trade"], "importance": 2.0})),
+            memory("m2", vec![0.9, 0.1], json!({"npc": "zara", "topic": "combat", "importance": 8.0})),
+            memory("m3", vec![0.0, 1.0], json!({"npc": "zara", "importance": 5.0})),
+            memory("m4", vec![1.0, 0.0], json!({"npc": "brom", "topic": "trade", "importance": 1.0})),
+        ]
+    }
+
+    #[test]
+    fn filters_match_scalars_and_array_elements() {
+        let points = memories();
+        let combat = PayloadFilter::new().field("topic", "combat");
+        let matching: Vec<&str> = points.iter().filter(|p| combat.matches(&p.payload)).map(|p| p.id.as_str()).collect();
+        assert_eq!(matching, vec!["m1", "m2"]);
+        assert!(!PayloadFilter::new().field("mood", "calm").matches(&points[0].payload));
+        assert!(PayloadFilter::new().matches(&points[3].payload));
+    }
+
+    #[test]
+    fn counts_stats_and_histograms_cover_the_matching_points() {
+        let points = memories();
+        let refs: Vec<&VectorPoint> = points.iter().collect();
+        let aggregations: Vec<Aggregation> = serde_json::from_value(json!([
+            {"op": "count"},
+            {"op": "count_by", "field": "topic"},
+            {"op": "stats", "field": "importance"},
+            {"op": "histogram", "field": "importance", "buckets": 3},
+        ]))
+        .unwrap();
+        let results = aggregate_points(&refs, &PayloadFilter::new().field("npc", "zara"), &aggregations);
+
+        assert_eq!(results[0], AggregateResult::Count { count: 3 });
+        let counts = BTreeMap::from([("combat".to_string(), 2), ("trade".to_string(), 1)]);
+        assert_eq!(results[1], AggregateResult::CountBy { field: "topic".to_string(), counts, missing: 1 });
+        let stats = NumericStats { count: 3, min: 2.0, max: 8.0, sum: 15.0, mean: 5.0 };
+        assert_eq!(results[2], AggregateResult::Stats { field: "importance".to_string(), stats: Some(stats) });
+        let AggregateResult::Histogram { buckets, .. } = &results[3] else { panic!("expected a histogram") };
+        let counts: Vec<(f64, f64, usize)> = buckets.iter().map(|b| (b.from, b.to, b.count)).collect();
+        assert_eq!(counts, vec![(2.0, 4.0, 1), (4.0, 6.0, 1), (6.0, 8.0, 1)]);
+    }
+
+    #[test]
+    fn empty_and_constant_fields_degrade_gracefully() {
+        assert_eq!(stats(&[]), None);
+        assert!(histogram(&[], 4).is_empty());
+        let constant = histogram(&[3.0, 3.0], 4);
+        assert_eq!(constant, vec![HistogramBucket { from: 3.0, to: 3.0, count: 2 }]);
+        // Non-numeric values are not counted
+        let points = [memory("x", vec![1.0], json!({"importance": "high"}))];
+        let importance = [Aggregation::Stats { field: "importance".to_string() }];
+        let results = aggregate_points(&[&points[0]], &PayloadFilter::new(), &importance);
+        assert_eq!(results[0], AggregateResult::Stats { field: "importance".to_string(), stats: None });
+    }
+
+    #[test]
+    fn index_aggregates_skip_expired_points_and_facet_search_hits() {
+        let mut index = VectorIndex::new(VectorIndexConfig {
+            url: String::new(),
+            api_key: String::new(),
+            default_ttl_secs: None,
+            collection_ttl_secs: Default::default(),
+        });
+        index.create_collection("memories", 2, "test").unwrap();
+        for point in memories() {
+            index.upsert("memories", point).unwrap();
+        }
+        let mut expired = memory("m5", vec![1.0, 0.0], json!({"npc": "zara", "topic": "combat"}));
+        expired.payload.insert(EXPIRES_AT_FIELD.to_string(), Value::from(1u64));
+        index.upsert("memories", expired).unwrap();
+
+        let zara = PayloadFilter::new().field("npc", "zara");
+        assert_eq!(index.aggregate("memories", &zara, &[Aggregation::Count]).unwrap(), vec![AggregateResult::Count { count: 3 }]);
+        assert!(index.aggregate("missing", &zara, &[Aggregation::Count]).is_err());
+
+        let faceted = index.search_faceted("memories", &[1.0, 0.0], 3, &["topic", "npc"]).unwrap();
+        assert_eq!(faceted.results.len(), 3);
+        assert_eq!(faceted.facets["topic"], BTreeMap::from([("combat".to_string(), 2), ("trade".to_string(), 2)]));
+        assert_eq!(faceted.facets["npc"], BTreeMap::from([("brom".to_string(), 1), ("zara".to_string(), 2)]));
+    }
+}
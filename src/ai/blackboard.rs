@@ -0,0 +1,391 @@
+// Per-entity blackboards
+//
+// Each entity gets one typed key/value store that every AI module (emotion, GOAP, reasoning,
+// self-awareness) reads and writes instead of keeping private copies of state. Writes that change
+// a value are recorded in a change log; watchers poll the changes under a key prefix from their
+// own cursor. Projections are watchers that mirror blackboard keys into a GOAP WorldState or a
+// DecisionContext, applying only what changed since their last sync.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use serde_json::{json, Value};
+
+use crate::ai::coordination::BlackboardValue;
+use crate::ai::decision::DecisionContext;
+use crate::ai::goap::{StateValue, WorldState};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlackboardChange {
+    pub seq: u64,
+    pub key: String,
+    // None when the key was created / removed
+    pub old: Option<BlackboardValue>,
+    pub new: Option<BlackboardValue>,
+    pub writer: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WatchId(u64);
+
+#[derive(Debug, Clone)]
+struct Watcher {
+    prefix: String,
+    // Next change sequence number this watcher has not seen
+    cursor: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Blackboard {
+    entries: BTreeMap<String, BlackboardValue>,
+    log: VecDeque<BlackboardChange>,
+    next_seq: u64,
+    watchers: HashMap<WatchId, Watcher>,
+    next_watch: u64,
+}
+
+impl Blackboard {
+    pub fn new() -> Self {
+        Blackboard::default()
+    }
+
+    // Write a value; returns true if it changed
+    pub fn set(&mut self, key: &str, value: BlackboardValue, writer: &str) -> bool {
+        let old = self.entries.get(key);
+        if old == Some(&value) {
+            return false;
+        }
+        let old = self.entries.insert(key.to_string(), value.clone());
+        self.record(key, old, Some(value), writer);
+        true
+    }
+
+    pub fn remove(&mut self, key: &str, writer: &str) -> Option<BlackboardValue> {
+        let old = self.entries.remove(key)?;
+        self.record(key, Some(old.clone()), None, writer);
+        Some(old)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&BlackboardValue> {
+        self.entries.get(key)
+    }
+
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.entries.get(key)? {
+            BlackboardValue::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn get_int(&self, key: &str) -> Option<i64> {
+        match self.entries.get(key)? {
+            BlackboardValue::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn get_float(&self, key: &str) -> Option<f32> {
+        match self.entries.get(key)? {
+            BlackboardValue::Float(value) => Some(*value),
+            BlackboardValue::Int(value) => Some(*value as f32),
+            _ => None,
+        }
+    }
+
+    pub fn get_text(&self, key: &str) -> Option<&str> {
+        match self.entries.get(key)? {
+            BlackboardValue::Text(value) | BlackboardValue::Entity(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_position(&self, key: &str) -> Option<[f32; 3]> {
+        match self.entries.get(key)? {
+            BlackboardValue::Position(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (&String, &BlackboardValue)> {
+        self.entries.iter()
+    }
+
+    // Start watching keys under `prefix` ("" for all); only later changes are reported
+    pub fn watch(&mut self, prefix: &str) -> WatchId {
+        let id = WatchId(self.next_watch);
+        self.next_watch += 1;
+        self.watchers.insert(id, Watcher { prefix: prefix.to_string(), cursor: self.next_seq });
+        id
+    }
+
+    pub fn unwatch(&mut self, id: WatchId) {
+        self.watchers.remove(&id);
+        self.compact();
+    }
+
+    // Changes under the watcher's prefix since its last poll
+    pub fn poll(&mut self, id: WatchId) -> Vec<BlackboardChange> {
+        let Some(watcher) = self.watchers.get_mut(&id) else {
+            return Vec::new();
+        };
+        let changes = self
+            .log
+            .iter()
+            .filter(|c| c.seq >= watcher.cursor && c.key.starts_with(watcher.prefix.as_str()))
+            .cloned()
+            .collect();
+        watcher.cursor = self.next_seq;
+        self.compact();
+        changes
+    }
+
+    fn record(&mut self, key: &str, old: Option<BlackboardValue>, new: Option<BlackboardValue>, writer: &str) {
+        // Nobody is listening, so there is nothing to keep
+        if self.watchers.is_empty() {
+            self.next_seq += 1;
+            return;
+        }
+        self.log.push_back(BlackboardChange {
+            seq: self.next_seq,
+            key: key.to_string(),
+            old,
+            new,
+            writer: writer.to_string(),
+        });
+        self.next_seq += 1;
+    }
+
+    // Drop log entries every watcher has seen
+    fn compact(&mut self) {
+        let oldest = self.watchers.values().map(|w| w.cursor).min().unwrap_or(self.next_seq);
+        while self.log.front().is_some_and(|c| c.seq < oldest) {
+            self.log.pop_front();
+        }
+    }
+}
+
+// One blackboard per entity
+#[derive(Debug, Default)]
+pub struct Blackboards {
+    boards: HashMap<String, Blackboard>,
+}
+
+impl Blackboards {
+    pub fn new() -> Self {
+        Blackboards::default()
+    }
+
+    pub fn entity(&mut self, id: &str) -> &mut Blackboard {
+        self.boards.entry(id.to_string()).or_default()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Blackboard> {
+        self.boards.get(id)
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<Blackboard> {
+        self.boards.remove(id)
+    }
+}
+
+// Destination of a projection
+pub trait StateSink {
+    fn put(&mut self, key: &str, value: &BlackboardValue);
+    fn clear(&mut self, key: &str);
+}
+
+impl StateSink for WorldState {
+    // GOAP matches facts by equality, so floats and positions are not projected
+    fn put(&mut self, key: &str, value: &BlackboardValue) {
+        match value {
+            BlackboardValue::Bool(v) => self.set(key, StateValue::Bool(*v)),
+            BlackboardValue::Int(v) => self.set(key, StateValue::Int(*v)),
+            BlackboardValue::Text(v) | BlackboardValue::Entity(v) => self.set(key, StateValue::Text(v.clone())),
+            BlackboardValue::Float(_) | BlackboardValue::Position(_) => {}
+        }
+    }
+
+    fn clear(&mut self, key: &str) {
+        self.remove(key);
+    }
+}
+
+impl StateSink for DecisionContext {
+    fn put(&mut self, key: &str, value: &BlackboardValue) {
+        self.world_state.insert(key.to_string(), to_json(value));
+    }
+
+    fn clear(&mut self, key: &str) {
+        self.world_state.remove(key);
+    }
+}
+
+pub fn to_json(value: &BlackboardValue) -> Value {
+    match value {
+        BlackboardValue::Bool(v) => json!(v),
+        BlackboardValue::Int(v) => json!(v),
+        BlackboardValue::Float(v) => json!(v),
+        BlackboardValue::Text(v) | BlackboardValue::Entity(v) => json!(v),
+        BlackboardValue::Position(v) => json!(v),
+    }
+}
+
+// Inverse of to_json; entity references come back as text, and three numbers as a position
+pub fn from_json(value: &Value) -> Option<BlackboardValue> {
+    match value {
+        Value::Bool(v) => Some(BlackboardValue::Bool(*v)),
+        Value::Number(n) => match n.as_i64() {
+            Some(v) => Some(BlackboardValue::Int(v)),
+            None => n.as_f64().map(|v| BlackboardValue::Float(v as f32)),
+        },
+        Value::String(v) => Some(BlackboardValue::Text(v.clone())),
+        Value::Array(items) if items.len() == 3 => {
+            let mut position = [0.0; 3];
+            for (slot, item) in position.iter_mut().zip(items) {
+                *slot = item.as_f64()? as f32;
+            }
+            Some(BlackboardValue::Position(position))
+        }
+        _ => None,
+    }
+}
+
+// Mirrors blackboard keys under a prefix into a sink, with the prefix stripped
+pub struct Projection {
+    prefix: String,
+    watch: Option<WatchId>,
+}
+
+impl Projection {
+    pub fn new(prefix: &str) -> Self {
+        Projection { prefix: prefix.to_string(), watch: None }
+    }
+
+    // Apply changes since the last sync; the first sync copies every matching key
+    pub fn sync(&mut self, board: &mut Blackboard, sink: &mut impl StateSink) {
+        let watch = match self.watch {
+            Some(watch) => watch,
+            None => {
+                for (key, value) in board.entries.iter().filter(|(k, _)| k.starts_with(self.prefix.as_str())) {
+                    sink.put(&key[self.prefix.len()..], value);
+                }
+                let watch = board.watch(&self.prefix);
+                self.watch = Some(watch);
+                return;
+            }
+        };
+        for change in board.poll(watch) {
+            let key = &change.key[self.prefix.len()..];
+            match &change.new {
+                Some(value) => sink.put(key, value),
+                None => sink.clear(key),
+            }
+        }
+    }
+
+    pub fn detach(&mut self, board: &mut Blackboard) {
+        if let Some(watch) = self.watch.take() {
+            board.unwatch(watch);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(changes: &[BlackboardChange]) -> Vec<&str> {
+        changes.iter().map(|c| c.key.as_str()).collect()
+    }
+
+    #[test]
+    fn typed_getters_read_matching_variants() {
+        let mut board = Blackboard::new();
+        board.set("alert", BlackboardValue::Bool(true), "perception");
+        board.set("ammo", BlackboardValue::Int(3), "combat");
+        board.set("target", BlackboardValue::Entity("player-1".to_string()), "goap");
+        board.set("home", BlackboardValue::Position([1.0, 0.0, 2.0]), "schedule");
+        assert_eq!(board.get_bool("alert"), Some(true));
+        assert_eq!(board.get_float("ammo"), Some(3.0));
+        assert_eq!(board.get_text("target"), Some("player-1"));
+        assert_eq!(board.get_position("home"), Some([1.0, 0.0, 2.0]));
+        assert_eq!(board.get_int("alert"), None);
+        assert_eq!(board.get_bool("missing"), None);
+    }
+
+    #[test]
+    fn watchers_see_only_later_changes_under_their_prefix() {
+        let mut board = Blackboard::new();
+        board.set("emotion.fear", BlackboardValue::Float(0.2), "emotion");
+        let watch = board.watch("emotion.");
+        assert!(!board.set("emotion.fear", BlackboardValue::Float(0.2), "emotion"));
+        board.set("emotion.fear", BlackboardValue::Float(0.6), "emotion");
+        board.set("goap.goal", BlackboardValue::Text("flee".to_string()), "goap");
+        board.remove("emotion.fear", "emotion");
+
+        let changes = board.poll(watch);
+        assert_eq!(keys(&changes), vec!["emotion.fear", "emotion.fear"]);
+        assert_eq!(changes[0].old, Some(BlackboardValue::Float(0.2)));
+        assert_eq!(changes[1].new, None);
+        assert!(board.poll(watch).is_empty());
+    }
+
+    #[test]
+    fn the_log_only_keeps_what_some_watcher_has_not_seen() {
+        let mut board = Blackboard::new();
+        board.set("a", BlackboardValue::Int(1), "test");
+        assert!(board.log.is_empty());
+
+        let fast = board.watch("");
+        let slow = board.watch("");
+        board.set("a", BlackboardValue::Int(2), "test");
+        board.poll(fast);
+        assert_eq!(board.log.len(), 1);
+        board.set("b", BlackboardValue::Int(1), "test");
+        assert_eq!(keys(&board.poll(slow)), vec!["a", "b"]);
+        assert_eq!(board.log.len(), 1);
+        board.unwatch(fast);
+        assert!(board.log.is_empty());
+        assert!(board.poll(fast).is_empty());
+    }
+
+    #[test]
+    fn projections_mirror_keys_into_world_state() {
+        let mut board = Blackboard::new();
+        board.set("goap.has_weapon", BlackboardValue::Bool(true), "inventory");
+        board.set("goap.speed", BlackboardValue::Float(1.5), "movement");
+        board.set("emotion.fear", BlackboardValue::Float(0.4), "emotion");
+
+        let mut state = WorldState::new();
+        let mut projection = Projection::new("goap.");
+        projection.sync(&mut board, &mut state);
+        assert_eq!(state.get("has_weapon"), Some(&StateValue::Bool(true)));
+        assert_eq!(state.get("speed"), None);
+        assert_eq!(state.iter().count(), 1);
+
+        board.set("goap.ammo", BlackboardValue::Int(0), "combat");
+        board.remove("goap.has_weapon", "inventory");
+        projection.sync(&mut board, &mut state);
+        assert_eq!(state.get("ammo"), Some(&StateValue::Int(0)));
+        assert_eq!(state.get("has_weapon"), None);
+
+        projection.detach(&mut board);
+        assert!(board.watchers.is_empty());
+    }
+
+    #[test]
+    fn decision_context_projection_round_trips_through_json() {
+        let mut boards = Blackboards::new();
+        let board = boards.entity("npc-1");
+        board.set("ctx.home", BlackboardValue::Position([1.0, 2.0, 3.0]), "schedule");
+        board.set("ctx.mood", BlackboardValue::Float(0.5), "emotion");
+
+        let mut context = DecisionContext::new("npc-1");
+        Projection::new("ctx.").sync(boards.entity("npc-1"), &mut context);
+        assert_eq!(from_json(&context.world_state["home"]), Some(BlackboardValue::Position([1.0, 2.0, 3.0])));
+        assert_eq!(from_json(&context.world_state["mood"]), Some(BlackboardValue::Float(0.5)));
+        assert_eq!(from_json(&json!(7)), Some(BlackboardValue::Int(7)));
+        assert_eq!(from_json(&json!(["a", 1, 2])), None);
+        assert!(boards.remove("npc-1").is_some());
+        assert!(boards.get("npc-1").is_none());
+    }
+}
@@ -0,0 +1,337 @@
+// Decision confidence calibration
+//
+// Reasoning modules report a confidence with each decision, but nothing guarantees that choices
+// made "90% sure" work out nine times in ten. A Calibrator keeps a window of (reported confidence,
+// outcome) pairs, measures how far off they are (reliability curve, Brier score, expected
+// calibration error) and fits a temperature that rescales confidences in logit space to match the
+// observed success rate.
+//
+// Calibrated decisions also get an `uncertainty`: the entropy of the calibrated confidence, raised
+// while too few outcomes have been seen to trust the calibration. Decisions that are both uncertain
+// and high-impact can be flagged for designer review on the event bus.
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::decision::Decision;
+use crate::events::EventBus;
+
+// Topic on which decisions needing review are published
+pub const REVIEW_TOPIC: &str = "decision.review";
+
+const MIN_TEMPERATURE: f32 = 0.05;
+const MAX_TEMPERATURE: f32 = 20.0;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CalibrationConfig {
+    // Reliability curve resolution
+    pub bins: usize,
+    // Most recent outcomes kept
+    pub window: usize,
+    // Outcomes needed before the temperature is fitted and before uncertainty drops fully
+    pub min_samples: usize,
+    // Re-fit the temperature after this many new outcomes
+    pub refit_every: usize,
+    // Decisions at or above both thresholds are flagged for review
+    pub review_uncertainty: f32,
+    pub review_impact: f32,
+}
+
+impl Default for CalibrationConfig {
+    fn default() -> Self {
+        CalibrationConfig {
+            bins: 10,
+            window: 2_000,
+            min_samples: 50,
+            refit_every: 25,
+            review_uncertainty: 0.6,
+            review_impact: 0.7,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CalibrationBin {
+    pub lower: f32,
+    pub upper: f32,
+    pub count: usize,
+    pub mean_confidence: f32,
+    // Fraction of the bin's decisions that succeeded
+    pub accuracy: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CalibrationReport {
+    pub samples: usize,
+    pub temperature: f32,
+    // Mean squared error of the reported and of the calibrated confidences
+    pub brier_raw: f32,
+    pub brier_calibrated: f32,
+    // Count-weighted mean |confidence - accuracy| over the bins
+    pub ece_raw: f32,
+    pub ece_calibrated: f32,
+    // Reliability curve of the calibrated confidences
+    pub bins: Vec<CalibrationBin>,
+}
+
+pub struct Calibrator {
+    config: CalibrationConfig,
+    samples: VecDeque<(f32, bool)>,
+    temperature: f32,
+    since_fit: usize,
+}
+
+impl Calibrator {
+    pub fn new(config: CalibrationConfig) -> Self {
+        Calibrator { config, samples: VecDeque::new(), temperature: 1.0, since_fit: 0 }
+    }
+
+    pub fn temperature(&self) -> f32 {
+        self.temperature
+    }
+
+    pub fn samples(&self) -> usize {
+        self.samples.len()
+    }
+
+    // Record how a decision with this reported confidence turned out
+    pub fn observe(&mut self, raw_confidence: f32, success: bool) {
+        if self.samples.len() >= self.config.window.max(1) {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((raw_confidence.clamp(0.0, 1.0), success));
+        self.since_fit += 1;
+        if self.samples.len() >= self.config.min_samples && self.since_fit >= self.config.refit_every {
+            self.fit();
+        }
+    }
+
+    pub fn resolve(&mut self, decision: &Decision, success: bool) {
+        self.observe(decision.raw_confidence, success);
+    }
+
+    pub fn calibrated(&self, raw_confidence: f32) -> f32 {
+        scale(raw_confidence, self.temperature)
+    }
+
+    // Fill in the decision's calibrated confidence and uncertainty
+    pub fn calibrate(&self, decision: &mut Decision) {
+        decision.confidence = self.calibrated(decision.raw_confidence);
+        decision.uncertainty = self.uncertainty(decision.confidence);
+    }
+
+    // Calibrate, then publish the decision on REVIEW_TOPIC if it needs a designer's look
+    pub fn calibrate_and_review(&self, decision: &mut Decision, bus: Option<&mut EventBus>) -> bool {
+        self.calibrate(decision);
+        let review = self.needs_review(decision);
+        if let (true, Some(bus)) = (review, bus) {
+            bus.emit(
+                REVIEW_TOPIC,
+                &decision.entity,
+                json!({
+                    "entity": decision.entity,
+                    "choice": decision.choice,
+                    "tick": decision.tick,
+                    "confidence": decision.confidence,
+                    "raw_confidence": decision.raw_confidence,
+                    "uncertainty": decision.uncertainty,
+                    "impact": decision.impact,
+                }),
+            );
+        }
+        review
+    }
+
+    pub fn needs_review(&self, decision: &Decision) -> bool {
+        decision.uncertainty >= self.config.review_uncertainty && decision.impact >= self.config.review_impact
+    }
+
+    // Temperature minimising the log loss over the window, by golden-section search on log T
+    pub fn fit(&mut self) {
+        self.since_fit = 0;
+        if self.samples.len() < self.config.min_samples {
+            return;
+        }
+        let loss = |log_t: f32| {
+            let t = log_t.exp();
+            self.samples
+                .iter()
+                .map(|&(p, success)| {
+                    let q = scale(p, t).clamp(1e-6, 1.0 - 1e-6);
+                    -(if success { q.ln() } else { (1.0 - q).ln() })
+                })
+                .sum::<f32>()
+        };
+        let ratio = (5f32.sqrt() - 1.0) / 2.0;
+        let (mut low, mut high) = (MIN_TEMPERATURE.ln(), MAX_TEMPERATURE.ln());
+        let mut a = high - ratio * (high - low);
+        let mut b = low + ratio * (high - low);
+        let (mut loss_a, mut loss_b) = (loss(a), loss(b));
+        for _ in 0..40 {
+            if loss_a < loss_b {
+                high = b;
+                b = a;
+                loss_b = loss_a;
+                a = high - ratio * (high - low);
+                loss_a = loss(a);
+            } else {
+                low = a;
+                a = b;
+                loss_a = loss_b;
+                b = low + ratio * (high - low);
+                loss_b = loss(b);
+            }
+        }
+        self.temperature = ((low + high) / 2.0).exp();
+    }
+
+    pub fn report(&self) -> CalibrationReport {
+        let calibrated: Vec<(f32, bool)> = self.samples.iter().map(|&(p, s)| (self.calibrated(p), s)).collect();
+        let raw: Vec<(f32, bool)> = self.samples.iter().copied().collect();
+        let raw_bins = self.reliability(&raw);
+        let bins = self.reliability(&calibrated);
+        CalibrationReport {
+            samples: self.samples.len(),
+            temperature: self.temperature,
+            brier_raw: brier(&raw),
+            brier_calibrated: brier(&calibrated),
+            ece_raw: ece(&raw_bins, raw.len()),
+            ece_calibrated: ece(&bins, calibrated.len()),
+            bins,
+        }
+    }
+
+    // Normalised binary entropy, pushed towards 1 while the window is still small
+    fn uncertainty(&self, confidence: f32) -> f32 {
+        let p = confidence.clamp(1e-6, 1.0 - 1e-6);
+        let entropy = -(p * p.log2() + (1.0 - p) * (1.0 - p).log2());
+        let support = (self.samples.len() as f32 / self.config.min_samples.max(1) as f32).min(1.0);
+        (1.0 - (1.0 - entropy) * support).clamp(0.0, 1.0)
+    }
+
+    fn reliability(&self, samples: &[(f32, bool)]) -> Vec<CalibrationBin> {
+        let count = self.config.bins.max(1);
+        let mut sums = vec![(0usize, 0f32, 0usize); count];
+        for &(p, success) in samples {
+            let bin = ((p * count as f32) as usize).min(count - 1);
+            sums[bin].0 += 1;
+            sums[bin].1 += p;
+            sums[bin].2 += success as usize;
+        }
+        sums.into_iter()
+            .enumerate()
+            .map(|(i, (n, total, successes))| CalibrationBin {
+                lower: i as f32 / count as f32,
+                upper: (i + 1) as f32 / count as f32,
+                count: n,
+                mean_confidence: if n == 0 { 0.0 } else { total / n as f32 },
+                accuracy: if n == 0 { 0.0 } else { successes as f32 / n as f32 },
+            })
+            .collect()
+    }
+}
+
+impl Default for Calibrator {
+    fn default() -> Self {
+        Calibrator::new(CalibrationConfig::default())
+    }
+}
+
+// Divide the logit by the temperature; T > 1 softens overconfident predictions
+fn scale(p: f32, temperature: f32) -> f32 {
+    let p = p.clamp(1e-6, 1.0 - 1e-6);
+    let logit = (p / (1.0 - p)).ln() / temperature;
+    1.0 / (1.0 + (-logit).exp())
+}
+
+fn brier(samples: &[(f32, bool)]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    samples.iter().map(|&(p, s)| (p - s as u8 as f32).powi(2)).sum::<f32>() / samples.len() as f32
+}
+
+fn ece(bins: &[CalibrationBin], total: usize) -> f32 {
+    if total == 0 {
+        return 0.0;
+    }
+    bins.iter().map(|bin| bin.count as f32 * (bin.mean_confidence - bin.accuracy).abs()).sum::<f32>() / total as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reports 90% but succeeds on 6 out of every 10 decisions
+    fn overconfident(calibrator: &mut Calibrator, decisions: usize) {
+        for i in 0..decisions {
+            calibrator.observe(0.9, i % 10 < 6);
+        }
+    }
+
+    #[test]
+    fn temperature_is_fitted_once_enough_outcomes_are_in() {
+        let mut calibrator = Calibrator::default();
+        overconfident(&mut calibrator, 40);
+        assert_eq!(calibrator.temperature(), 1.0);
+        assert!((calibrator.calibrated(0.9) - 0.9).abs() < 1e-6);
+
+        overconfident(&mut calibrator, 60);
+        assert!(calibrator.temperature() > 1.0);
+        assert!((calibrator.calibrated(0.9) - 0.6).abs() < 0.01);
+        // Calibration only rescales, so the ordering of confidences is kept
+        assert!(calibrator.calibrated(0.3) < calibrator.calibrated(0.5));
+        assert!((calibrator.calibrated(0.5) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn report_shows_the_improvement() {
+        let mut calibrator = Calibrator::default();
+        overconfident(&mut calibrator, 100);
+        calibrator.observe(1.0, true);
+        let report = calibrator.report();
+        assert_eq!((report.samples, report.bins.len()), (101, 10));
+        assert!(report.brier_calibrated < report.brier_raw);
+        assert!(report.ece_calibrated < report.ece_raw);
+        // A certain decision lands in the top bin
+        assert_eq!(report.bins[9].count, 1);
+    }
+
+    #[test]
+    fn window_keeps_only_recent_outcomes() {
+        let mut calibrator = Calibrator::new(CalibrationConfig { window: 10, ..CalibrationConfig::default() });
+        overconfident(&mut calibrator, 15);
+        assert_eq!(calibrator.samples(), 10);
+    }
+
+    #[test]
+    fn uncertain_high_impact_decisions_are_flagged() {
+        let mut bus = EventBus::new(8);
+        let sub = bus.subscribe(REVIEW_TOPIC);
+        let calibrator = Calibrator::default();
+
+        // Nothing observed yet: every confidence is untrusted
+        let mut risky = Decision::new("warlord", "declare_war", 0.95);
+        risky.impact = 0.9;
+        assert!(calibrator.calibrate_and_review(&mut risky, Some(&mut bus)));
+        assert_eq!(risky.uncertainty, 1.0);
+        let mut minor = Decision::new("warlord", "taunt", 0.95);
+        minor.impact = 0.1;
+        assert!(!calibrator.calibrate_and_review(&mut minor, Some(&mut bus)));
+        let events = bus.drain(sub);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].payload["choice"], "declare_war");
+
+        // A well-supported, decisive confidence no longer needs a look
+        let mut calibrator = Calibrator::default();
+        for _ in 0..100 {
+            calibrator.observe(0.99, true);
+        }
+        let mut decision = Decision::new("warlord", "declare_war", 0.99);
+        decision.impact = 0.9;
+        assert!(!calibrator.calibrate_and_review(&mut decision, None));
+        assert!(decision.uncertainty < 0.2);
+    }
+}
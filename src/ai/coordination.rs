@@ -0,0 +1,432 @@
+// Multi-agent coordination
+//
+// A squad shares a blackboard of typed entries (enemy sightings, claimed cover points, orders).
+// Entries can be owned by one agent and expire after a number of ticks. Work is distributed with
+// a contract-net style auction: each agent bids its GOAP plan cost for a task and the cheapest
+// bidders win. Winning assignments carry the task goal to hand to the agent's own planner.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::ai::goap::{Goal, Planner, WorldState};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlackboardValue {
+    Bool(bool),
+    Int(i64),
+    Float(f32),
+    Text(String),
+    Position([f32; 3]),
+    Entity(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct BlackboardEntry {
+    pub value: BlackboardValue,
+    // Only the owner may overwrite or remove an owned entry
+    pub owner: Option<String>,
+    pub written_by: String,
+    pub updated_at: u64,
+    pub expires_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlackboardError {
+    OwnedByOther { key: String, owner: String },
+    NotFound(String),
+}
+
+impl fmt::Display for BlackboardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlackboardError::OwnedByOther { key, owner } => write!(f, "'{}' is owned by {}", key, owner),
+            BlackboardError::NotFound(key) => write!(f, "'{}' is not on the blackboard", key),
+        }
+    }
+}
+
+impl std::error::Error for BlackboardError {}
+
+// Shared squad memory; time is measured in simulation ticks
+#[derive(Debug, Default)]
+pub struct SharedBlackboard {
+    entries: HashMap<String, BlackboardEntry>,
+    tick: u64,
+}
+
+impl SharedBlackboard {
+    pub fn new() -> Self {
+        SharedBlackboard::default()
+    }
+
+    // Advance time and drop expired entries; returns the keys that expired
+    pub fn advance(&mut self, tick: u64) -> Vec<String> {
+        self.tick = tick;
+        let expired: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(_, e)| e.expires_at.is_some_and(|at| at <= tick))
+            .map(|(k, _)| k.clone())
+            .collect();
+        for key in &expired {
+            self.entries.remove(key);
+        }
+        expired
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    fn check_owner(&self, key: &str, agent: &str) -> Result<(), BlackboardError> {
+        match self.entries.get(key).and_then(|e| e.owner.as_ref()) {
+            Some(owner) if owner != agent => Err(BlackboardError::OwnedByOther {
+                key: key.to_string(),
+                owner: owner.clone(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    // Write an entry, keeping any existing owner; `ttl` is in ticks
+    pub fn write(&mut self, key: &str, value: BlackboardValue, agent: &str, ttl: Option<u64>) -> Result<(), BlackboardError> {
+        self.check_owner(key, agent)?;
+        let owner = self.entries.get(key).and_then(|e| e.owner.clone());
+        self.entries.insert(
+            key.to_string(),
+            BlackboardEntry {
+                value,
+                owner,
+                written_by: agent.to_string(),
+                updated_at: self.tick,
+                expires_at: ttl.map(|t| self.tick + t),
+            },
+        );
+        Ok(())
+    }
+
+    // Take ownership of an entry (creating it if needed)
+    pub fn claim(&mut self, key: &str, value: BlackboardValue, agent: &str, ttl: Option<u64>) -> Result<(), BlackboardError> {
+        self.check_owner(key, agent)?;
+        self.entries.insert(
+            key.to_string(),
+            BlackboardEntry {
+                value,
+                owner: Some(agent.to_string()),
+                written_by: agent.to_string(),
+                updated_at: self.tick,
+                expires_at: ttl.map(|t| self.tick + t),
+            },
+        );
+        Ok(())
+    }
+
+    pub fn release(&mut self, key: &str, agent: &str) -> Result<(), BlackboardError> {
+        self.check_owner(key, agent)?;
+        let entry = self
+            .entries
+            .get_mut(key)
+            .ok_or_else(|| BlackboardError::NotFound(key.to_string()))?;
+        entry.owner = None;
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &str, agent: &str) -> Result<BlackboardEntry, BlackboardError> {
+        self.check_owner(key, agent)?;
+        self.entries
+            .remove(key)
+            .ok_or_else(|| BlackboardError::NotFound(key.to_string()))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&BlackboardEntry> {
+        self.entries.get(key)
+    }
+
+    pub fn value(&self, key: &str) -> Option<&BlackboardValue> {
+        self.entries.get(key).map(|e| &e.value)
+    }
+
+    // Entries whose key starts with a prefix, e.g. "enemy."
+    pub fn with_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = (&'a String, &'a BlackboardEntry)> + 'a {
+        self.entries.iter().filter(move |(k, _)| k.starts_with(prefix))
+    }
+
+    pub fn owned_by<'a>(&'a self, agent: &'a str) -> impl Iterator<Item = &'a String> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(_, e)| e.owner.as_deref() == Some(agent))
+            .map(|(k, _)| k)
+    }
+}
+
+// A unit of work offered to the squad
+#[derive(Debug, Clone)]
+pub struct Task {
+    pub id: String,
+    pub goal: Goal,
+    // Higher-priority tasks are auctioned first
+    pub priority: f32,
+}
+
+// Something that can price a task
+pub trait Bidder {
+    fn agent_id(&self) -> &str;
+    // Cost of achieving the task, or None if the agent cannot do it
+    fn bid(&self, task: &Task) -> Option<f32>;
+}
+
+// Bids the cost of the agent's cheapest GOAP plan for the task goal
+pub struct GoapBidder<'a> {
+    pub agent_id: String,
+    pub state: WorldState,
+    pub planner: &'a Planner,
+    // Multiplier applied to bids, e.g. to make wounded agents reluctant
+    pub cost_scale: f32,
+}
+
+impl<'a> Bidder for GoapBidder<'a> {
+    fn agent_id(&self) -> &str {
+        &self.agent_id
+    }
+
+    fn bid(&self, task: &Task) -> Option<f32> {
+        self.planner
+            .estimate_cost(&self.state, &task.goal)
+            .map(|cost| cost * self.cost_scale)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Bid {
+    pub agent: String,
+    pub task: String,
+    pub cost: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Assignment {
+    pub task: String,
+    pub agent: String,
+    pub cost: f32,
+    // Goal to hand to the winning agent's planner
+    pub goal: Goal,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct AuctionResult {
+    pub assignments: Vec<Assignment>,
+    pub unassigned: Vec<String>,
+    pub bids: Vec<Bid>,
+}
+
+pub struct TaskAuction {
+    // Tasks a single agent may win in one round
+    pub capacity_per_agent: usize,
+    // Ticks an assignment claim stays on the blackboard
+    pub claim_ttl: Option<u64>,
+}
+
+impl Default for TaskAuction {
+    fn default() -> Self {
+        TaskAuction {
+            capacity_per_agent: 1,
+            claim_ttl: None,
+        }
+    }
+}
+
+impl TaskAuction {
+    // Collect bids for every task and award tasks in priority order to the cheapest bidder with
+    // spare capacity. Tasks already claimed on the blackboard ("task.<id>") are skipped.
+    pub fn run(&self, tasks: &[Task], bidders: &[&dyn Bidder], blackboard: Option<&mut SharedBlackboard>) -> AuctionResult {
+        let claimed: HashSet<String> = blackboard
+            .as_ref()
+            .map(|b| tasks.iter().filter(|t| b.get(&task_key(&t.id)).is_some()).map(|t| t.id.clone()).collect())
+            .unwrap_or_default();
+
+        let mut result = AuctionResult::default();
+        for task in tasks.iter().filter(|t| !claimed.contains(&t.id)) {
+            for bidder in bidders {
+                if let Some(cost) = bidder.bid(task) {
+                    result.bids.push(Bid { agent: bidder.agent_id().to_string(), task: task.id.clone(), cost });
+                }
+            }
+        }
+
+        let mut ordered: Vec<&Task> = tasks.iter().filter(|t| !claimed.contains(&t.id)).collect();
+        ordered.sort_by(|a, b| b.priority.total_cmp(&a.priority));
+
+        let mut load: HashMap<&str, usize> = HashMap::new();
+        for task in ordered {
+            let winner = result
+                .bids
+                .iter()
+                .filter(|b| b.task == task.id)
+                .filter(|b| load.get(b.agent.as_str()).copied().unwrap_or(0) < self.capacity_per_agent)
+                .min_by(|a, b| a.cost.total_cmp(&b.cost));
+            match winner {
+                Some(bid) => {
+                    *load.entry(bid.agent.as_str()).or_default() += 1;
+                    result.assignments.push(Assignment {
+                        task: task.id.clone(),
+                        agent: bid.agent.clone(),
+                        cost: bid.cost,
+                        goal: task.goal.clone(),
+                    });
+                }
+                None => result.unassigned.push(task.id.clone()),
+            }
+        }
+
+        if let Some(blackboard) = blackboard {
+            for assignment in &result.assignments {
+                // Cannot fail: unclaimed tasks were filtered above
+                let _ = blackboard.claim(
+                    &task_key(&assignment.task),
+                    BlackboardValue::Entity(assignment.agent.clone()),
+                    &assignment.agent,
+                    self.claim_ttl,
+                );
+            }
+        }
+        result
+    }
+}
+
+pub fn task_key(task_id: &str) -> String {
+    format!("task.{}", task_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Bids a fixed cost per task id and skips tasks it has no price for
+    struct FixedBidder {
+        agent: &'static str,
+        costs: Vec<(&'static str, f32)>,
+    }
+
+    impl Bidder for FixedBidder {
+        fn agent_id(&self) -> &str {
+            self.agent
+        }
+
+        fn bid(&self, task: &Task) -> Option<f32> {
+            self.costs.iter().find(|(id, _)| *id == task.id).map(|(_, cost)| *cost)
+        }
+    }
+
+    fn task(id: &str, priority: f32) -> Task {
+        Task { id: id.to_string(), goal: Goal::new(id, priority), priority }
+    }
+
+    fn winners(result: &AuctionResult) -> Vec<(&str, &str)> {
+        result.assignments.iter().map(|a| (a.task.as_str(), a.agent.as_str())).collect()
+    }
+
+    #[test]
+    fn the_cheapest_bidder_wins_and_unpriced_tasks_stay_unassigned() {
+        let scout = FixedBidder { agent: "scout", costs: vec![("flank", 2.0), ("cover", 5.0)] };
+        let medic = FixedBidder { agent: "medic", costs: vec![("flank", 4.0), ("cover", 1.5)] };
+        let tasks = [task("flank", 1.0), task("cover", 0.5), task("heal", 0.2)];
+        let result = TaskAuction::default().run(&tasks, &[&scout, &medic], None);
+
+        assert_eq!(winners(&result), [("flank", "scout"), ("cover", "medic")]);
+        assert_eq!(result.assignments[1].cost, 1.5);
+        assert_eq!(result.assignments[0].goal.name, "flank");
+        assert_eq!(result.unassigned, ["heal"]);
+        assert_eq!(result.bids.len(), 4);
+    }
+
+    #[test]
+    fn agents_at_capacity_lose_to_pricier_bidders() {
+        let scout = FixedBidder { agent: "scout", costs: vec![("flank", 1.0), ("cover", 1.0), ("guard", 1.0)] };
+        let medic = FixedBidder { agent: "medic", costs: vec![("cover", 3.0)] };
+        let tasks = [task("guard", 0.1), task("flank", 0.9), task("cover", 0.5)];
+
+        let result = TaskAuction::default().run(&tasks, &[&scout, &medic], None);
+        // Priority order: flank goes to the scout, who is then full
+        assert_eq!(winners(&result), [("flank", "scout"), ("cover", "medic")]);
+        assert_eq!(result.unassigned, ["guard"]);
+
+        let auction = TaskAuction { capacity_per_agent: 2, claim_ttl: None };
+        let result = auction.run(&tasks, &[&scout, &medic], None);
+        // Room for two: the scout wins the two higher-priority tasks and nobody else bids on guard
+        assert_eq!(winners(&result), [("flank", "scout"), ("cover", "scout")]);
+        assert_eq!(result.unassigned, ["guard"]);
+    }
+
+    #[test]
+    fn assignments_are_claimed_on_the_blackboard_and_not_auctioned_again() {
+        let scout = FixedBidder { agent: "scout", costs: vec![("flank", 2.0)] };
+        let medic = FixedBidder { agent: "medic", costs: vec![("flank", 3.0)] };
+        let auction = TaskAuction { capacity_per_agent: 1, claim_ttl: Some(5) };
+        let mut blackboard = SharedBlackboard::new();
+
+        let result = auction.run(&[task("flank", 1.0)], &[&scout, &medic], Some(&mut blackboard));
+        assert_eq!(winners(&result), [("flank", "scout")]);
+        let claim = blackboard.get(&task_key("flank")).unwrap();
+        assert_eq!(claim.owner.as_deref(), Some("scout"));
+        assert_eq!(claim.expires_at, Some(5));
+
+        let again = auction.run(&[task("flank", 1.0)], &[&scout, &medic], Some(&mut blackboard));
+        assert!(again.assignments.is_empty() && again.unassigned.is_empty() && again.bids.is_empty());
+        // Once the claim expires the task is open again
+        blackboard.advance(5);
+        let reopened = auction.run(&[task("flank", 1.0)], &[&medic], Some(&mut blackboard));
+        assert_eq!(winners(&reopened), [("flank", "medic")]);
+    }
+
+    #[test]
+    fn only_the_owner_may_change_an_owned_entry() {
+        let mut blackboard = SharedBlackboard::new();
+        blackboard.claim("cover.north", BlackboardValue::Position([1.0, 0.0, 2.0]), "scout", None).unwrap();
+        let refused = blackboard.write("cover.north", BlackboardValue::Bool(false), "medic", None);
+        assert_eq!(refused, Err(BlackboardError::OwnedByOther { key: "cover.north".to_string(), owner: "scout".to_string() }));
+        assert!(blackboard.claim("cover.north", BlackboardValue::Bool(true), "medic", None).is_err());
+        assert!(blackboard.remove("cover.north", "medic").is_err());
+        assert_eq!(blackboard.value("cover.north"), Some(&BlackboardValue::Position([1.0, 0.0, 2.0])));
+
+        // The owner's writes keep the claim
+        blackboard.write("cover.north", BlackboardValue::Bool(true), "scout", None).unwrap();
+        assert_eq!(blackboard.get("cover.north").unwrap().owner.as_deref(), Some("scout"));
+        // Unowned entries are open to everyone
+        blackboard.write("enemy.1", BlackboardValue::Entity("orc".to_string()), "scout", None).unwrap();
+        blackboard.write("enemy.1", BlackboardValue::Entity("troll".to_string()), "medic", None).unwrap();
+        assert_eq!(blackboard.get("enemy.1").unwrap().written_by, "medic");
+    }
+
+    #[test]
+    fn entries_expire_once_their_ttl_has_passed() {
+        let mut blackboard = SharedBlackboard::new();
+        blackboard.advance(10);
+        blackboard.write("enemy.1", BlackboardValue::Int(3), "scout", Some(4)).unwrap();
+        blackboard.write("order", BlackboardValue::Text("hold".to_string()), "leader", None).unwrap();
+
+        assert!(blackboard.advance(13).is_empty());
+        assert_eq!(blackboard.advance(14), ["enemy.1"]);
+        assert!(blackboard.value("enemy.1").is_none());
+        assert!(blackboard.advance(1_000).is_empty());
+        assert_eq!(blackboard.tick(), 1_000);
+        assert!(blackboard.value("order").is_some());
+    }
+
+    #[test]
+    fn released_entries_can_be_claimed_by_another_agent() {
+        let mut blackboard = SharedBlackboard::new();
+        blackboard.claim("cover.north", BlackboardValue::Bool(true), "scout", None).unwrap();
+        blackboard.claim("cover.south", BlackboardValue::Bool(true), "scout", None).unwrap();
+        let mut owned: Vec<&String> = blackboard.owned_by("scout").collect();
+        owned.sort();
+        assert_eq!(owned, ["cover.north", "cover.south"]);
+        assert_eq!(blackboard.with_prefix("cover.").count(), 2);
+
+        assert!(blackboard.release("cover.north", "medic").is_err());
+        blackboard.release("cover.north", "scout").unwrap();
+        blackboard.claim("cover.north", BlackboardValue::Bool(true), "medic", None).unwrap();
+        assert_eq!(blackboard.owned_by("medic").collect::<Vec<_>>(), ["cover.north"]);
+        assert_eq!(blackboard.release("gone", "scout"), Err(BlackboardError::NotFound("gone".to_string())));
+        assert_eq!(blackboard.remove("cover.north", "medic").unwrap().owner.as_deref(), Some("medic"));
+    }
+}
@@ -0,0 +1,75 @@
+// Decision context
+//
+// What a decision-making module (utility scoring, reasoning, self-awareness) is given about the
+// entity it decides for. `world_state` holds the entity's facts as JSON values and is normally
+// filled from the entity's blackboard through a Projection.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DecisionContext {
+    pub entity: String,
+    pub tick: u64,
+    pub world_state: BTreeMap<String, Value>,
+}
+
+impl DecisionContext {
+    pub fn new(entity: &str) -> Self {
+        DecisionContext { entity: entity.to_string(), tick: 0, world_state: BTreeMap::new() }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.world_state.get(key)
+    }
+
+    pub fn number(&self, key: &str) -> Option<f64> {
+        self.world_state.get(key).and_then(Value::as_f64)
+    }
+
+    pub fn flag(&self, key: &str) -> bool {
+        self.world_state.get(key).and_then(Value::as_bool).unwrap_or(false)
+    }
+}
+
+// A choice made by a decision-making module. `raw_confidence` is the module's own estimate;
+// `confidence` and `uncertainty` are filled in by a Calibrator.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Decision {
+    pub entity: String,
+    pub choice: String,
+    pub tick: u64,
+    pub raw_confidence: f32,
+    pub confidence: f32,
+    // 0 when the calibrated confidence is trustworthy and decisive, 1 when it says nothing
+    pub uncertainty: f32,
+    // How much is at stake, 0..1, set by the caller
+    pub impact: f32,
+}
+
+impl Decision {
+    pub fn new(entity: &str, choice: &str, confidence: f32) -> Self {
+        let confidence = confidence.clamp(0.0, 1.0);
+        Decision {
+            entity: entity.to_string(),
+            choice: choice.to_string(),
+            tick: 0,
+            raw_confidence: confidence,
+            confidence,
+            uncertainty: 1.0,
+            impact: 0.0,
+        }
+    }
+
+    pub fn at_tick(mut self, tick: u64) -> Self {
+        self.tick = tick;
+        self
+    }
+
+    pub fn impact(mut self, impact: f32) -> Self {
+        self.impact = impact.clamp(0.0, 1.0);
+        self
+    }
+}
@@ -0,0 +1,77 @@
+// Plan execution
+//
+// Planners (GOAP and HTN) both produce a Plan: an ordered list of actions. The executor walks a
+// plan one action at a time, re-checking each action's preconditions against the agent's current
+// WorldState before handing it out, and reports when the world has drifted so the agent can
+// replan with whichever planner it uses.
+
+use crate::ai::goap::{Action, Plan, WorldState};
+
+#[derive(Debug, Clone)]
+pub enum ExecutionStatus<'a> {
+    // Perform this action, then call `complete` or `fail`
+    Execute(&'a Action),
+    // The next action's preconditions no longer hold; replan
+    Invalidated(&'a Action),
+    Failed(&'a Action),
+    Finished,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlanExecutor {
+    plan: Plan,
+    index: usize,
+    failed: bool,
+}
+
+impl PlanExecutor {
+    pub fn new(plan: Plan) -> Self {
+        PlanExecutor { plan, index: 0, failed: false }
+    }
+
+    pub fn plan(&self) -> &Plan {
+        &self.plan
+    }
+
+    pub fn current(&self) -> Option<&Action> {
+        self.plan.actions.get(self.index)
+    }
+
+    pub fn remaining(&self) -> &[Action] {
+        &self.plan.actions[self.index.min(self.plan.actions.len())..]
+    }
+
+    pub fn remaining_cost(&self) -> f32 {
+        self.remaining().iter().map(|a| a.cost).sum()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.index >= self.plan.actions.len()
+    }
+
+    pub fn status(&self, state: &WorldState) -> ExecutionStatus<'_> {
+        match self.current() {
+            None => ExecutionStatus::Finished,
+            Some(action) if self.failed => ExecutionStatus::Failed(action),
+            Some(action) if !action.is_applicable(state) => ExecutionStatus::Invalidated(action),
+            Some(action) => ExecutionStatus::Execute(action),
+        }
+    }
+
+    // The current action succeeded; apply its effects to `state` and move on
+    pub fn complete(&mut self, state: &mut WorldState) {
+        if let Some(action) = self.current() {
+            *state = state.apply(&action.effects);
+            self.index += 1;
+        }
+    }
+
+    pub fn fail(&mut self) {
+        self.failed = true;
+    }
+
+    // Swap in a fresh plan, e.g. after invalidation
+    pub fn replace(&mut self, plan: Plan) {
+        *self = PlanExecutor::new(plan);
+    }
+}
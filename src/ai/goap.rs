@@ -0,0 +1,393 @@
+// Goal-oriented action planning (GOAP)
+//
+// NPC behaviour is described as actions with preconditions, effects and costs. The planner runs
+// A* over world states to find the cheapest action sequence that satisfies a goal. Costs are
+// accumulated as `Scalar`, so with the "deterministic" feature every peer picks the same plan.
+// Costs must be finite and non-negative, which the planner checks when actions are registered: the
+// heuristic (the cheapest action while the goal is unmet) is only admissible under that condition,
+// and A* then returns the cheapest plan.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BinaryHeap, HashMap};
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use super::plan_explain::{ExplanationBuilder, PlanExplanation, SearchOutcome};
+use crate::fixed::{Scalar, SimScalar};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum StateValue {
+    Bool(bool),
+    Int(i64),
+    Text(String),
+}
+
+impl From<bool> for StateValue {
+    fn from(value: bool) -> Self {
+        StateValue::Bool(value)
+    }
+}
+
+impl From<i64> for StateValue {
+    fn from(value: i64) -> Self {
+        StateValue::Int(value)
+    }
+}
+
+impl From<&str> for StateValue {
+    fn from(value: &str) -> Self {
+        StateValue::Text(value.to_string())
+    }
+}
+
+// Facts an agent believes about the world ("has_weapon" = true, "ammo" = 3)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct WorldState {
+    facts: BTreeMap<String, StateValue>,
+}
+
+impl WorldState {
+    pub fn new() -> Self {
+        WorldState::default()
+    }
+
+    pub fn with(mut self, key: &str, value: impl Into<StateValue>) -> Self {
+        self.set(key, value);
+        self
+    }
+
+    pub fn set(&mut self, key: &str, value: impl Into<StateValue>) {
+        self.facts.insert(key.to_string(), value.into());
+    }
+
+    pub fn get(&self, key: &str) -> Option<&StateValue> {
+        self.facts.get(key)
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<StateValue> {
+        self.facts.remove(key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &StateValue)> {
+        self.facts.iter()
+    }
+
+    // True if every condition holds in this state
+    pub fn satisfies(&self, conditions: &[(String, StateValue)]) -> bool {
+        conditions.iter().all(|(key, value)| self.facts.get(key) == Some(value))
+    }
+
+    pub fn unsatisfied(&self, conditions: &[(String, StateValue)]) -> usize {
+        conditions.iter().filter(|(key, value)| self.facts.get(key) != Some(value)).count()
+    }
+
+    pub fn apply(&self, effects: &[(String, StateValue)]) -> WorldState {
+        let mut next = self.clone();
+        for (key, value) in effects {
+            next.facts.insert(key.clone(), value.clone());
+        }
+        next
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Action {
+    pub name: String,
+    pub cost: f32,
+    pub preconditions: Vec<(String, StateValue)>,
+    pub effects: Vec<(String, StateValue)>,
+}
+
+impl Action {
+    pub fn new(name: &str, cost: f32) -> Self {
+        Action {
+            name: name.to_string(),
+            cost,
+            preconditions: Vec::new(),
+            effects: Vec::new(),
+        }
+    }
+
+    pub fn requires(mut self, key: &str, value: impl Into<StateValue>) -> Self {
+        self.preconditions.push((key.to_string(), value.into()));
+        self
+    }
+
+    pub fn effect(mut self, key: &str, value: impl Into<StateValue>) -> Self {
+        self.effects.push((key.to_string(), value.into()));
+        self
+    }
+
+    pub fn is_applicable(&self, state: &WorldState) -> bool {
+        state.satisfies(&self.preconditions)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Goal {
+    pub name: String,
+    pub priority: f32,
+    pub conditions: Vec<(String, StateValue)>,
+}
+
+impl Goal {
+    pub fn new(name: &str, priority: f32) -> Self {
+        Goal {
+            name: name.to_string(),
+            priority,
+            conditions: Vec::new(),
+        }
+    }
+
+    pub fn wants(mut self, key: &str, value: impl Into<StateValue>) -> Self {
+        self.conditions.push((key.to_string(), value.into()));
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Plan {
+    pub goal: String,
+    pub actions: Vec<Action>,
+    pub cost: f32,
+}
+
+impl Plan {
+    pub fn action_names(&self) -> Vec<&str> {
+        self.actions.iter().map(|a| a.name.as_str()).collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GoapError {
+    // Negative, NaN or infinite action cost
+    InvalidCost { action: String, cost: f32 },
+}
+
+impl fmt::Display for GoapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GoapError::InvalidCost { action, cost } => {
+                write!(f, "action '{}' has cost {}; costs must be finite and non-negative", action, cost)
+            }
+        }
+    }
+}
+
+impl std::error::Error for GoapError {}
+
+fn check_cost(action: &Action) -> Result<(), GoapError> {
+    if action.cost.is_finite() && action.cost >= 0.0 {
+        Ok(())
+    } else {
+        Err(GoapError::InvalidCost { action: action.name.clone(), cost: action.cost })
+    }
+}
+
+struct Node {
+    estimate: Scalar,
+    cost: Scalar,
+    state: WorldState,
+}
+
+impl PartialEq for Node {
+    fn eq(&self, other: &Self) -> bool {
+        self.estimate == other.estimate
+    }
+}
+
+impl Eq for Node {}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Node {
+    // Reversed: BinaryHeap is a max-heap and we want the cheapest estimate first
+    fn cmp(&self, other: &Self) -> Ordering {
+        SimScalar::total_cmp(&other.estimate, &self.estimate)
+    }
+}
+
+pub struct Planner {
+    actions: Vec<Action>,
+    // Search is abandoned after expanding this many states
+    pub max_expansions: usize,
+}
+
+impl Planner {
+    pub fn new(actions: Vec<Action>) -> Result<Self, GoapError> {
+        actions.iter().try_for_each(check_cost)?;
+        Ok(Planner {
+            actions,
+            max_expansions: 10_000,
+        })
+    }
+
+    pub fn actions(&self) -> &[Action] {
+        &self.actions
+    }
+
+    pub fn add_action(&mut self, action: Action) -> Result<(), GoapError> {
+        check_cost(&action)?;
+        self.actions.push(action);
+        Ok(())
+    }
+
+    pub fn remove_action(&mut self, name: &str) {
+        self.actions.retain(|a| a.name != name);
+    }
+
+    // Cheapest action sequence from `start` to a state satisfying `goal`
+    pub fn plan(&self, start: &WorldState, goal: &Goal) -> Option<Plan> {
+        self.search(start, goal, None)
+    }
+
+    // Same search as `plan`, also recording why each branch was taken or rejected
+    pub fn plan_explained(&self, start: &WorldState, goal: &Goal) -> (Option<Plan>, PlanExplanation) {
+        let mut trace = ExplanationBuilder::new(&goal.name);
+        let plan = self.search(start, goal, Some(&mut trace));
+        (plan, trace.finish())
+    }
+
+    fn search(&self, start: &WorldState, goal: &Goal, mut trace: Option<&mut ExplanationBuilder>) -> Option<Plan> {
+        let costs: Vec<Scalar> = self.actions.iter().map(|a| Scalar::from_f32(a.cost)).collect();
+        // One action may satisfy every unmet condition at once, so the only safe lower bound on the
+        // remaining cost is a single cheapest action
+        let min_cost = costs.iter().copied().reduce(Scalar::min).unwrap_or(Scalar::ZERO);
+        let heuristic = |state: &WorldState| {
+            if state.satisfies(&goal.conditions) {
+                Scalar::ZERO
+            } else {
+                min_cost
+            }
+        };
+
+        let mut open = BinaryHeap::new();
+        let mut best_cost: HashMap<WorldState, Scalar> = HashMap::new();
+        let mut came_from: HashMap<WorldState, (WorldState, usize)> = HashMap::new();
+
+        best_cost.insert(start.clone(), Scalar::ZERO);
+        open.push(Node { estimate: heuristic(start), cost: Scalar::ZERO, state: start.clone() });
+        if let Some(trace) = trace.as_deref_mut() {
+            trace.start(start, heuristic(start).to_f32());
+        }
+
+        let mut expansions = 0;
+        while let Some(Node { cost, state, .. }) = open.pop() {
+            if state.satisfies(&goal.conditions) {
+                if let Some(trace) = trace.as_deref_mut() {
+                    trace.found(&state);
+                }
+                return Some(self.reconstruct(goal, &came_from, state, cost));
+            }
+            if best_cost.get(&state).is_some_and(|best| cost > *best) {
+                continue;
+            }
+            expansions += 1;
+            if expansions > self.max_expansions {
+                if let Some(trace) = trace.as_deref_mut() {
+                    trace.outcome = SearchOutcome::ExpansionLimit;
+                }
+                return None;
+            }
+            if let Some(trace) = trace.as_deref_mut() {
+                trace.expanded(&state);
+            }
+            for (index, action) in self.actions.iter().enumerate() {
+                if !action.is_applicable(&state) {
+                    if let Some(trace) = trace.as_deref_mut() {
+                        trace.unmet(&state, action);
+                    }
+                    continue;
+                }
+                let next = state.apply(&action.effects);
+                let next_cost = cost + costs[index];
+                let known = best_cost.get(&next).copied().filter(|best| next_cost >= *best);
+                if let Some(trace) = trace.as_deref_mut() {
+                    let h = heuristic(&next).to_f32();
+                    trace.branch(&state, &next, action, next_cost.to_f32(), h, known.map(|best| best.to_f32()));
+                }
+                if known.is_none() {
+                    best_cost.insert(next.clone(), next_cost);
+                    came_from.insert(next.clone(), (state.clone(), index));
+                    open.push(Node { estimate: next_cost + heuristic(&next), cost: next_cost, state: next });
+                }
+            }
+        }
+        None
+    }
+
+    // Cost of the cheapest plan, used for bidding on tasks
+    pub fn estimate_cost(&self, start: &WorldState, goal: &Goal) -> Option<f32> {
+        self.plan(start, goal).map(|plan| plan.cost)
+    }
+
+    fn reconstruct(
+        &self,
+        goal: &Goal,
+        came_from: &HashMap<WorldState, (WorldState, usize)>,
+        mut state: WorldState,
+        cost: Scalar,
+    ) -> Plan {
+        let mut actions = Vec::new();
+        while let Some((previous, index)) = came_from.get(&state) {
+            actions.push(self.actions[*index].clone());
+            state = previous.clone();
+        }
+        actions.reverse();
+        Plan { goal: goal.name.clone(), actions, cost: cost.to_f32() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_cheapest_plan_when_one_action_meets_several_conditions() {
+        let planner = Planner::new(vec![
+            Action::new("direct", 2.5).effect("a", true).effect("b", true).effect("c", true),
+            Action::new("prep", 1.0).effect("ready", true),
+            Action::new("all", 1.0).requires("ready", true).effect("a", true).effect("b", true).effect("c", true),
+        ])
+        .unwrap();
+        let goal = Goal::new("abc", 1.0).wants("a", true).wants("b", true).wants("c", true);
+        let plan = planner.plan(&WorldState::new(), &goal).unwrap();
+        assert_eq!(plan.action_names(), vec!["prep", "all"]);
+        assert!((plan.cost - 2.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn rejects_negative_and_non_finite_costs() {
+        let err = Planner::new(vec![Action::new("free_lunch", -1.0)]).err().unwrap();
+        assert_eq!(err, GoapError::InvalidCost { action: "free_lunch".to_string(), cost: -1.0 });
+        assert!(Planner::new(vec![Action::new("nan", f32::NAN)]).is_err());
+
+        let mut planner = Planner::new(Vec::new()).unwrap();
+        assert!(planner.add_action(Action::new("forever", f32::INFINITY)).is_err());
+        assert!(planner.add_action(Action::new("rest", 0.0)).is_ok());
+        assert_eq!(planner.actions().len(), 1);
+    }
+
+    #[test]
+    fn unreachable_goal_has_no_plan() {
+        let planner = Planner::new(vec![Action::new("chop", 1.0).requires("has_axe", true).effect("has_wood", true)]).unwrap();
+        let goal = Goal::new("wood", 1.0).wants("has_wood", true);
+        assert!(planner.plan(&WorldState::new(), &goal).is_none());
+        let plan = planner.plan(&WorldState::new().with("has_axe", true), &goal).unwrap();
+        assert_eq!(plan.action_names(), vec!["chop"]);
+    }
+
+    #[test]
+    fn satisfied_goal_needs_no_actions() {
+        let planner = Planner::new(vec![Action::new("chop", 1.0).effect("has_wood", true)]).unwrap();
+        let goal = Goal::new("wood", 1.0).wants("has_wood", true);
+        let plan = planner.plan(&WorldState::new().with("has_wood", true), &goal).unwrap();
+        assert!(plan.actions.is_empty());
+        assert_eq!(plan.cost, 0.0);
+    }
+}
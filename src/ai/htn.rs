@@ -0,0 +1,270 @@
+// Hierarchical task network (HTN) planning
+//
+// An alternative to GOAP for authored behaviour. Compound tasks list methods in priority order;
+// each method has preconditions and a sequence of subtasks. The planner decomposes the root task
+// depth first, simulating primitive actions on a WorldState, and backtracks to the next method
+// when a decomposition dead-ends. The search keeps its backtrack points on an explicit stack, so a
+// deep or self-recursive domain ends in HtnError::Exhausted rather than overflowing the call stack.
+// Primitive tasks are GOAP actions and subtasks may also be GOAP goals solved by a GOAP planner, so
+// both produce a goap::Plan for the same PlanExecutor.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ai::goap::{Action, Goal, Plan, Planner, StateValue, WorldState};
+
+#[derive(Debug, Clone)]
+pub enum HtnTask {
+    Primitive(Action),
+    // Name of a compound task in the domain
+    Compound(String),
+    // Solved by the domain's GOAP planner from the state reached so far
+    Goal(Goal),
+}
+
+#[derive(Debug, Clone)]
+pub struct Method {
+    pub name: String,
+    pub preconditions: Vec<(String, StateValue)>,
+    pub subtasks: Vec<HtnTask>,
+}
+
+impl Method {
+    pub fn new(name: &str) -> Self {
+        Method { name: name.to_string(), preconditions: Vec::new(), subtasks: Vec::new() }
+    }
+
+    pub fn requires(mut self, key: &str, value: impl Into<StateValue>) -> Self {
+        self.preconditions.push((key.to_string(), value.into()));
+        self
+    }
+
+    pub fn then(mut self, task: HtnTask) -> Self {
+        self.subtasks.push(task);
+        self
+    }
+
+    pub fn then_action(self, action: Action) -> Self {
+        self.then(HtnTask::Primitive(action))
+    }
+
+    pub fn then_task(self, name: &str) -> Self {
+        self.then(HtnTask::Compound(name.to_string()))
+    }
+
+    pub fn then_goal(self, goal: Goal) -> Self {
+        self.then(HtnTask::Goal(goal))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompoundTask {
+    pub name: String,
+    // Tried in order; the first that decomposes fully wins
+    pub methods: Vec<Method>,
+}
+
+impl CompoundTask {
+    pub fn new(name: &str) -> Self {
+        CompoundTask { name: name.to_string(), methods: Vec::new() }
+    }
+
+    pub fn method(mut self, method: Method) -> Self {
+        self.methods.push(method);
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HtnError {
+    UnknownTask(String),
+    // No method of the root task decomposes from this state
+    NoPlan(String),
+    // The search expanded more than max_expansions tasks
+    Exhausted(String),
+    // A Goal subtask was used but the domain has no GOAP planner
+    NoGoapPlanner(String),
+}
+
+impl fmt::Display for HtnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HtnError::UnknownTask(name) => write!(f, "unknown compound task '{}'", name),
+            HtnError::NoPlan(name) => write!(f, "no decomposition of '{}' applies", name),
+            HtnError::Exhausted(name) => write!(f, "planning '{}' exceeded the expansion limit", name),
+            HtnError::NoGoapPlanner(goal) => write!(f, "goal subtask '{}' needs a GOAP planner", goal),
+        }
+    }
+}
+
+impl std::error::Error for HtnError {}
+
+pub struct HtnPlanner {
+    tasks: HashMap<String, CompoundTask>,
+    goap: Option<Planner>,
+    // Search is abandoned after expanding this many tasks
+    pub max_expansions: usize,
+}
+
+// A point in the search: the state reached, the tasks still to decompose (next one last) and the
+// actions planned so far
+struct Frame {
+    state: WorldState,
+    agenda: Vec<HtnTask>,
+    actions: Vec<Action>,
+}
+
+impl HtnPlanner {
+    pub fn new() -> Self {
+        HtnPlanner { tasks: HashMap::new(), goap: None, max_expansions: 10_000 }
+    }
+
+    pub fn add_task(&mut self, task: CompoundTask) {
+        self.tasks.insert(task.name.clone(), task);
+    }
+
+    // GOAP planner used for Goal subtasks
+    pub fn with_goap(mut self, planner: Planner) -> Self {
+        self.goap = Some(planner);
+        self
+    }
+
+    pub fn task(&self, name: &str) -> Option<&CompoundTask> {
+        self.tasks.get(name)
+    }
+
+    // Decompose the compound task `root` from `start` into a plan of primitive actions
+    pub fn plan(&self, start: &WorldState, root: &str) -> Result<Plan, HtnError> {
+        if !self.tasks.contains_key(root) {
+            return Err(HtnError::UnknownTask(root.to_string()));
+        }
+        let actions = self.seek(start, root)?;
+        let cost = actions.iter().map(|a| a.cost).sum();
+        Ok(Plan { goal: root.to_string(), actions, cost })
+    }
+
+    fn seek(&self, start: &WorldState, root: &str) -> Result<Vec<Action>, HtnError> {
+        let first = Frame {
+            state: start.clone(),
+            agenda: vec![HtnTask::Compound(root.to_string())],
+            actions: Vec::new(),
+        };
+        // Backtrack points, the next alternative on top
+        let mut pending = vec![first];
+        let mut expansions = 0;
+
+        'frames: while let Some(mut frame) = pending.pop() {
+            while let Some(task) = frame.agenda.pop() {
+                expansions += 1;
+                if expansions > self.max_expansions {
+                    return Err(HtnError::Exhausted(root.to_string()));
+                }
+                match task {
+                    HtnTask::Primitive(action) => {
+                        if !action.is_applicable(&frame.state) {
+                            continue 'frames;
+                        }
+                        frame.state = frame.state.apply(&action.effects);
+                        frame.actions.push(action);
+                    }
+                    HtnTask::Goal(goal) => {
+                        let planner = self.goap.as_ref().ok_or_else(|| HtnError::NoGoapPlanner(goal.name.clone()))?;
+                        let Some(plan) = planner.plan(&frame.state, &goal) else { continue 'frames };
+                        frame.state = plan.actions.iter().fold(frame.state, |s, a| s.apply(&a.effects));
+                        frame.actions.extend(plan.actions);
+                    }
+                    HtnTask::Compound(name) => {
+                        let task = self.tasks.get(&name).ok_or_else(|| HtnError::UnknownTask(name.clone()))?;
+                        let mut methods = task.methods.iter().filter(|m| frame.state.satisfies(&m.preconditions));
+                        let Some(chosen) = methods.next() else { continue 'frames };
+                        let alternatives: Vec<&Method> = methods.collect();
+                        for method in alternatives.into_iter().rev() {
+                            let mut agenda = frame.agenda.clone();
+                            agenda.extend(method.subtasks.iter().rev().cloned());
+                            pending.push(Frame { state: frame.state.clone(), agenda, actions: frame.actions.clone() });
+                        }
+                        frame.agenda.extend(chosen.subtasks.iter().rev().cloned());
+                    }
+                }
+            }
+            return Ok(frame.actions);
+        }
+        Err(HtnError::NoPlan(root.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn domain() -> HtnPlanner {
+        let mut planner = HtnPlanner::new();
+        planner.add_task(
+            CompoundTask::new("get_food")
+                .method(
+                    Method::new("buy")
+                        .requires("has_money", true)
+                        .then_action(Action::new("walk_to_market", 1.0))
+                        .then_action(Action::new("buy_bread", 1.0).requires("market_open", true).effect("has_food", true)),
+                )
+                .method(Method::new("forage").then_action(Action::new("forage", 3.0).effect("has_food", true))),
+        );
+        planner
+    }
+
+    #[test]
+    fn backtracks_to_the_next_method_on_a_dead_end() {
+        let planner = domain();
+        let start = WorldState::new().with("has_money", true).with("market_open", false);
+        let plan = planner.plan(&start, "get_food").unwrap();
+        assert_eq!(plan.action_names(), vec!["forage"]);
+        assert_eq!(plan.cost, 3.0);
+
+        let open = start.with("market_open", true);
+        assert_eq!(planner.plan(&open, "get_food").unwrap().action_names(), vec!["walk_to_market", "buy_bread"]);
+    }
+
+    #[test]
+    fn self_recursive_task_is_exhausted_instead_of_overflowing() {
+        let mut planner = HtnPlanner::new();
+        planner.add_task(
+            CompoundTask::new("loop").method(Method::new("again").then_action(Action::new("step", 1.0)).then_task("loop")),
+        );
+        planner.max_expansions = 200_000;
+        let result = std::thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(move || planner.plan(&WorldState::new(), "loop"))
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(result.unwrap_err(), HtnError::Exhausted("loop".to_string()));
+    }
+
+    #[test]
+    fn reports_unknown_tasks_and_missing_goap_planner() {
+        let mut planner = domain();
+        assert_eq!(planner.plan(&WorldState::new(), "sleep").unwrap_err(), HtnError::UnknownTask("sleep".to_string()));
+
+        planner.add_task(CompoundTask::new("eat").method(Method::new("plan").then_goal(Goal::new("fed", 1.0).wants("fed", true))));
+        assert_eq!(planner.plan(&WorldState::new(), "eat").unwrap_err(), HtnError::NoGoapPlanner("fed".to_string()));
+    }
+
+    #[test]
+    fn goal_subtasks_are_solved_by_goap() {
+        let goap = Planner::new(vec![Action::new("eat", 1.0).requires("has_food", true).effect("fed", true)]).unwrap();
+        let mut planner = domain().with_goap(goap);
+        planner.add_task(
+            CompoundTask::new("dinner")
+                .method(Method::new("cook").then_task("get_food").then_goal(Goal::new("fed", 1.0).wants("fed", true))),
+        );
+        let plan = planner.plan(&WorldState::new(), "dinner").unwrap();
+        assert_eq!(plan.action_names(), vec!["forage", "eat"]);
+    }
+
+    #[test]
+    fn no_applicable_method_is_no_plan() {
+        let mut planner = HtnPlanner::new();
+        planner.add_task(CompoundTask::new("fight").method(Method::new("armed").requires("armed", true)));
+        assert_eq!(planner.plan(&WorldState::new(), "fight").unwrap_err(), HtnError::NoPlan("fight".to_string()));
+    }
+}
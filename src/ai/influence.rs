@@ -0,0 +1,330 @@
+// Influence maps
+//
+// Layered scalar fields over the spatial grid (threat, resources, visibility, or any named
+// layer). Sources such as enemies or ore deposits stamp influence around their position each
+// tick; existing influence decays over time so it lingers after a source moves, and a blur pass
+// spreads it to neighbouring cells. Layers with no sources and no remaining influence are skipped,
+// so the per-tick cost follows what is actually active. Planners read the maps directly or
+// through facts projected into a GOAP WorldState ("threat_high", "threat_level").
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::Deserialize;
+
+use crate::ai::goap::WorldState;
+use crate::spatial::{distance, Cell, GridSpec};
+
+pub const THREAT: &str = "threat";
+pub const RESOURCES: &str = "resources";
+pub const VISIBILITY: &str = "visibility";
+
+// Values below this are treated as zero so idle layers go quiet
+const EPSILON: f32 = 1e-4;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LayerConfig {
+    // Fraction of influence lost per second
+    pub decay_per_second: f32,
+    // Share of each cell's value replaced by its neighbours' average per update
+    pub blur: f32,
+    // Values above this project "<layer>_high" = true
+    pub threshold: f32,
+}
+
+impl Default for LayerConfig {
+    fn default() -> Self {
+        LayerConfig { decay_per_second: 0.5, blur: 0.2, threshold: 0.5 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Falloff {
+    Constant,
+    Linear,
+    Quadratic,
+}
+
+impl Falloff {
+    fn weight(self, distance: f32, radius: f32) -> f32 {
+        let t = if radius > 0.0 { (1.0 - distance / radius).max(0.0) } else { 1.0 };
+        match self {
+            Falloff::Constant => 1.0,
+            Falloff::Linear => t,
+            Falloff::Quadratic => t * t,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InfluenceSource {
+    pub layer: String,
+    pub position: [f32; 2],
+    pub strength: f32,
+    pub radius: f32,
+    pub falloff: Falloff,
+}
+
+impl InfluenceSource {
+    pub fn new(layer: &str, position: [f32; 2], strength: f32, radius: f32) -> Self {
+        InfluenceSource { layer: layer.to_string(), position, strength, radius, falloff: Falloff::Linear }
+    }
+
+    pub fn with_falloff(mut self, falloff: Falloff) -> Self {
+        self.falloff = falloff;
+        self
+    }
+}
+
+struct Layer {
+    config: LayerConfig,
+    values: Vec<f32>,
+    // Any cell above EPSILON; inactive layers are skipped by update
+    active: bool,
+}
+
+pub struct InfluenceMap {
+    grid: GridSpec,
+    layers: BTreeMap<String, Layer>,
+    sources: HashMap<String, InfluenceSource>,
+}
+
+impl InfluenceMap {
+    pub fn new(grid: GridSpec) -> Self {
+        InfluenceMap { grid, layers: BTreeMap::new(), sources: HashMap::new() }
+    }
+
+    // Threat, resources and visibility layers with default settings
+    pub fn with_default_layers(grid: GridSpec) -> Self {
+        let mut map = InfluenceMap::new(grid);
+        for layer in [THREAT, RESOURCES, VISIBILITY] {
+            map.add_layer(layer, LayerConfig::default());
+        }
+        map
+    }
+
+    pub fn grid(&self) -> &GridSpec {
+        &self.grid
+    }
+
+    pub fn add_layer(&mut self, name: &str, config: LayerConfig) {
+        let values = vec![0.0; self.grid.len()];
+        self.layers.insert(name.to_string(), Layer { config, values, active: false });
+    }
+
+    pub fn layer_names(&self) -> impl Iterator<Item = &str> {
+        self.layers.keys().map(String::as_str)
+    }
+
+    // Add or move a source; sources persist until removed
+    pub fn set_source(&mut self, id: &str, source: InfluenceSource) {
+        self.sources.insert(id.to_string(), source);
+    }
+
+    pub fn remove_source(&mut self, id: &str) -> Option<InfluenceSource> {
+        self.sources.remove(id)
+    }
+
+    pub fn move_source(&mut self, id: &str, position: [f32; 2]) -> bool {
+        match self.sources.get_mut(id) {
+            Some(source) => {
+                source.position = position;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Advance by `dt` seconds: decay, stamp current sources, blur
+    pub fn update(&mut self, dt: f32) {
+        let grid = self.grid;
+        for (name, layer) in self.layers.iter_mut() {
+            let sources: Vec<&InfluenceSource> = self.sources.values().filter(|s| &s.layer == name).collect();
+            if !layer.active && sources.is_empty() {
+                continue;
+            }
+
+            let retain = (1.0 - layer.config.decay_per_second.clamp(0.0, 1.0)).powf(dt.max(0.0));
+            for value in layer.values.iter_mut() {
+                *value *= retain;
+            }
+
+            // Overlapping sources add up; the result replaces decayed influence where stronger
+            let mut stamp: HashMap<usize, f32> = HashMap::new();
+            for source in sources {
+                for cell in grid.cells_within(source.position, source.radius) {
+                    let d = distance(grid.center_of(cell), source.position);
+                    *stamp.entry(grid.index(cell)).or_default() += source.strength * source.falloff.weight(d, source.radius);
+                }
+            }
+            for (index, value) in stamp {
+                let current = &mut layer.values[index];
+                if value.abs() > current.abs() {
+                    *current = value;
+                }
+            }
+
+            if layer.config.blur > 0.0 {
+                blur(&grid, &mut layer.values, layer.config.blur.clamp(0.0, 1.0));
+            }
+
+            layer.active = false;
+            for value in layer.values.iter_mut() {
+                if value.abs() < EPSILON {
+                    *value = 0.0;
+                } else {
+                    layer.active = true;
+                }
+            }
+        }
+    }
+
+    pub fn value_at(&self, layer: &str, cell: Cell) -> f32 {
+        self.layers.get(layer).map_or(0.0, |l| l.values[self.grid.index(cell)])
+    }
+
+    // Influence at a world position; zero outside the grid or for unknown layers
+    pub fn value(&self, layer: &str, position: [f32; 2]) -> f32 {
+        self.grid.cell_of(position).map_or(0.0, |cell| self.value_at(layer, cell))
+    }
+
+    // Weighted sum of layers, e.g. [(RESOURCES, 1.0), (THREAT, -2.0)]
+    pub fn combined(&self, weights: &[(&str, f32)], position: [f32; 2]) -> f32 {
+        weights.iter().map(|(layer, weight)| self.value(layer, position) * weight).sum()
+    }
+
+    // Centre of the lowest-valued cell within `radius`, e.g. where to retreat
+    pub fn lowest_within(&self, layer: &str, position: [f32; 2], radius: f32) -> Option<[f32; 2]> {
+        self.extreme_within(layer, position, radius, |a, b| a < b)
+    }
+
+    pub fn highest_within(&self, layer: &str, position: [f32; 2], radius: f32) -> Option<[f32; 2]> {
+        self.extreme_within(layer, position, radius, |a, b| a > b)
+    }
+
+    fn extreme_within(&self, layer: &str, position: [f32; 2], radius: f32, better: impl Fn(f32, f32) -> bool) -> Option<[f32; 2]> {
+        let values = &self.layers.get(layer)?.values;
+        let mut best: Option<(Cell, f32, f32)> = None;
+        for cell in self.grid.cells_within(position, radius) {
+            let value = values[self.grid.index(cell)];
+            let d = distance(self.grid.center_of(cell), position);
+            // Ties go to the closer cell so agents do not wander across flat regions
+            let wins = match best {
+                None => true,
+                Some((_, best_value, best_d)) => better(value, best_value) || (value == best_value && d < best_d),
+            };
+            if wins {
+                best = Some((cell, value, d));
+            }
+        }
+        best.map(|(cell, _, _)| self.grid.center_of(cell))
+    }
+
+    // Write "<layer>_level" (value in tenths) and "<layer>_high" facts for a position
+    pub fn project(&self, position: [f32; 2], state: &mut WorldState) {
+        for (name, layer) in &self.layers {
+            let value = self.value(name, position);
+            state.set(&format!("{}_level", name), (value * 10.0).round() as i64);
+            state.set(&format!("{}_high", name), value > layer.config.threshold);
+        }
+    }
+}
+
+// One pass of neighbour averaging
+fn blur(grid: &GridSpec, values: &mut [f32], amount: f32) {
+    let source = values.to_vec();
+    for (index, value) in values.iter_mut().enumerate() {
+        let cell = grid.cell_at(index);
+        let (sum, count) = grid
+            .neighbors(cell)
+            .fold((0.0, 0), |(sum, count), n| (sum + source[grid.index(n)], count + 1));
+        if count > 0 {
+            *value = source[index] * (1.0 - amount) + (sum / count as f32) * amount;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::goap::StateValue;
+
+    fn still(decay_per_second: f32) -> LayerConfig {
+        LayerConfig { decay_per_second, blur: 0.0, threshold: 0.5 }
+    }
+
+    #[test]
+    fn sources_stamp_with_falloff_and_overlap_adds_up() {
+        let mut map = InfluenceMap::new(GridSpec::new(10, 10, 1.0));
+        map.add_layer(THREAT, still(0.0));
+        map.set_source("orc", InfluenceSource::new(THREAT, [2.5, 2.5], 1.0, 4.0));
+        map.update(0.1);
+        assert_eq!(map.value(THREAT, [2.5, 2.5]), 1.0);
+        assert_eq!(map.value(THREAT, [4.5, 2.5]), 0.5);
+        assert_eq!(map.value(THREAT, [9.5, 9.5]), 0.0);
+
+        map.set_source("troll", InfluenceSource::new(THREAT, [4.5, 2.5], 1.0, 2.0).with_falloff(Falloff::Constant));
+        map.update(0.1);
+        assert_eq!(map.value(THREAT, [4.5, 2.5]), 1.5);
+        assert_eq!(map.value(RESOURCES, [2.5, 2.5]), 0.0);
+    }
+
+    #[test]
+    fn influence_lingers_after_a_source_leaves_then_goes_quiet() {
+        let mut map = InfluenceMap::new(GridSpec::new(4, 4, 1.0));
+        map.add_layer(THREAT, still(0.5));
+        map.set_source("orc", InfluenceSource::new(THREAT, [0.5, 0.5], 1.0, 0.5));
+        map.update(1.0);
+        map.move_source("orc", [3.5, 3.5]);
+        map.update(1.0);
+        assert_eq!(map.value(THREAT, [0.5, 0.5]), 0.5);
+        assert_eq!(map.value(THREAT, [3.5, 3.5]), 1.0);
+
+        map.remove_source("orc");
+        for _ in 0..20 {
+            map.update(1.0);
+        }
+        assert_eq!(map.value(THREAT, [3.5, 3.5]), 0.0);
+        assert!(!map.layers[THREAT].active);
+        assert!(!map.move_source("orc", [0.0, 0.0]));
+    }
+
+    #[test]
+    fn blur_spreads_influence_to_neighbours() {
+        let mut map = InfluenceMap::new(GridSpec::new(3, 1, 1.0));
+        map.add_layer(RESOURCES, LayerConfig { decay_per_second: 0.0, blur: 0.5, threshold: 0.5 });
+        map.set_source("ore", InfluenceSource::new(RESOURCES, [1.5, 0.5], 1.0, 0.5));
+        map.update(1.0);
+        assert_eq!(map.value_at(RESOURCES, (1, 0)), 0.5);
+        assert_eq!(map.value_at(RESOURCES, (0, 0)), 0.5);
+    }
+
+    #[test]
+    fn queries_find_safe_and_rich_cells() {
+        let mut map = InfluenceMap::with_default_layers(GridSpec::new(5, 5, 1.0));
+        map.set_source("orc", InfluenceSource::new(THREAT, [0.5, 2.5], 1.0, 3.0));
+        map.set_source("ore", InfluenceSource::new(RESOURCES, [4.5, 4.5], 1.0, 1.0));
+        map.update(0.0);
+
+        assert_eq!(map.lowest_within(THREAT, [1.5, 2.5], 3.0), Some([4.5, 2.5]));
+        assert_eq!(map.highest_within(RESOURCES, [2.5, 2.5], 4.0), Some([4.5, 4.5]));
+        assert_eq!(map.lowest_within("noise", [1.5, 2.5], 3.0), None);
+        let score = map.combined(&[(RESOURCES, 1.0), (THREAT, -2.0)], [0.5, 2.5]);
+        assert!(score < -1.0);
+    }
+
+    #[test]
+    fn projection_writes_level_and_high_facts() {
+        let mut map = InfluenceMap::with_default_layers(GridSpec::new(3, 3, 1.0));
+        map.add_layer(THREAT, still(0.0));
+        map.set_source("orc", InfluenceSource::new(THREAT, [1.5, 1.5], 0.74, 1.0));
+        map.update(0.0);
+        let mut state = WorldState::new();
+        map.project([1.5, 1.5], &mut state);
+        assert_eq!(state.get("threat_level"), Some(&StateValue::Int(7)));
+        assert_eq!(state.get("threat_high"), Some(&StateValue::Bool(true)));
+        assert_eq!(state.get("resources_high"), Some(&StateValue::Bool(false)));
+        assert_eq!(map.layer_names().collect::<Vec<_>>(), vec![RESOURCES, THREAT, VISIBILITY]);
+    }
+}
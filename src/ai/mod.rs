@@ -0,0 +1,13 @@
+// AI decision making: planners, plan execution and multi-agent coordination
+
+pub mod blackboard;
+pub mod calibration;
+pub mod coordination;
+pub mod decision;
+pub mod executor;
+pub mod goap;
+pub mod htn;
+pub mod influence;
+pub mod plan_explain;
+pub mod profiles;
+pub mod schedule;
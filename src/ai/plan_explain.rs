@@ -0,0 +1,416 @@
+// GOAP plan explanations
+//
+// `Planner::plan_explained` records the A* search as it runs: every expanded world state, every
+// action considered from it and what happened to it (taken, rejected because a precondition did
+// not hold, rejected because the resulting state was already reachable more cheaply, or later
+// superseded by a cheaper route). The finished explanation carries the chosen path with a cost
+// breakdown, serialises to JSON for tools, and exports the search graph as Graphviz DOT so a
+// designer can see why an NPC settled on a strange action sequence.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use super::goap::{Action, StateValue, WorldState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchOutcome {
+    Found,
+    // The open list ran dry
+    Unreachable,
+    // Gave up after `max_expansions`
+    ExpansionLimit,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum BranchOutcome {
+    // The action led to a new best route to its state
+    Taken,
+    // A cheaper route to the same state replaced this one later in the search
+    Superseded,
+    // The action's preconditions did not hold
+    PreconditionUnmet { missing: Vec<(String, StateValue)> },
+    // The resulting state was already reachable for `known_cost`
+    Costlier { known_cost: f32 },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchNode {
+    pub id: usize,
+    pub state: WorldState,
+    // Cheapest known cost from the start
+    pub cost: f32,
+    // Heuristic estimate of the remaining cost
+    pub heuristic: f32,
+    // Order in which the node was expanded, if it was
+    pub expanded: Option<usize>,
+    pub on_plan: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchBranch {
+    pub from: usize,
+    // None when the action was not applicable
+    pub to: Option<usize>,
+    pub action: String,
+    pub action_cost: f32,
+    // Total cost of reaching `to` this way
+    pub cost: f32,
+    pub outcome: BranchOutcome,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CostStep {
+    pub action: String,
+    pub cost: f32,
+    pub total: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlanExplanation {
+    pub goal: String,
+    pub outcome: SearchOutcome,
+    pub nodes: Vec<SearchNode>,
+    pub branches: Vec<SearchBranch>,
+    // Node ids from the start to the goal state
+    pub path: Vec<usize>,
+    pub cost_breakdown: Vec<CostStep>,
+}
+
+impl PlanExplanation {
+    pub fn expansions(&self) -> usize {
+        self.nodes.iter().filter(|node| node.expanded.is_some()).count()
+    }
+
+    // Branches that were considered but not part of the final search tree
+    pub fn rejected(&self) -> impl Iterator<Item = &SearchBranch> {
+        self.branches.iter().filter(|branch| branch.outcome != BranchOutcome::Taken)
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::to_value(self).unwrap_or(serde_json::Value::Null)
+    }
+
+    // Graphviz export of the search graph. Inapplicable actions are left out unless
+    // `include_unmet` is set, since every expanded state usually has many of them.
+    pub fn to_dot(&self, include_unmet: bool) -> String {
+        let mut dot = String::new();
+        let _ = writeln!(dot, "digraph \"{}\" {{", escape(&self.goal));
+        let _ = writeln!(dot, "  rankdir=LR;");
+        let _ = writeln!(dot, "  node [shape=box, fontname=\"monospace\", fontsize=10];");
+        let _ = writeln!(dot, "  edge [fontname=\"monospace\", fontsize=9];");
+
+        let start = self.nodes.first().map(|node| &node.state);
+        for node in &self.nodes {
+            let mut label = match start {
+                Some(start) if node.id != 0 => state_diff(start, &node.state),
+                _ => format!("start\n{}", facts(&node.state)),
+            };
+            let _ = write!(label, "\ng={:.2} h={:.2}", node.cost, node.heuristic);
+            if let Some(order) = node.expanded {
+                let _ = write!(label, " #{}", order);
+            }
+            let style = if node.on_plan {
+                ", style=\"bold,filled\", fillcolor=\"palegreen\""
+            } else if node.expanded.is_none() {
+                ", style=dashed, color=gray50"
+            } else {
+                ""
+            };
+            let _ = writeln!(dot, "  n{} [label=\"{}\"{}];", node.id, escape(&label), style);
+        }
+
+        let on_path: Vec<(usize, usize)> = self.path.windows(2).map(|pair| (pair[0], pair[1])).collect();
+        for (index, branch) in self.branches.iter().enumerate() {
+            let label = format!("{} (+{:.2})", branch.action, branch.action_cost);
+            match (&branch.outcome, branch.to) {
+                (BranchOutcome::PreconditionUnmet { missing }, _) => {
+                    if !include_unmet {
+                        continue;
+                    }
+                    let missing: Vec<String> = missing.iter().map(|(key, value)| fact(key, value)).collect();
+                    let _ = writeln!(dot, "  u{} [shape=point, color=gray60];", index);
+                    let _ = writeln!(
+                        dot,
+                        "  n{} -> u{} [label=\"{}\\nneeds {}\", style=dotted, color=gray60, fontcolor=gray40];",
+                        branch.from,
+                        index,
+                        escape(&branch.action),
+                        escape(&missing.join(", ")),
+                    );
+                }
+                (BranchOutcome::Costlier { known_cost }, Some(to)) => {
+                    let _ = writeln!(
+                        dot,
+                        "  n{} -> n{} [label=\"{}\\ng={:.2} >= {:.2}\", style=dashed, color=red, fontcolor=red];",
+                        branch.from,
+                        to,
+                        escape(&label),
+                        branch.cost,
+                        known_cost,
+                    );
+                }
+                (BranchOutcome::Superseded, Some(to)) => {
+                    let _ = writeln!(
+                        dot,
+                        "  n{} -> n{} [label=\"{}\\nsuperseded\", style=dashed, color=orange, fontcolor=orange];",
+                        branch.from,
+                        to,
+                        escape(&label)
+                    );
+                }
+                (_, Some(to)) => {
+                    let style = if on_path.contains(&(branch.from, to)) { ", penwidth=2.5, color=darkgreen" } else { "" };
+                    let _ = writeln!(dot, "  n{} -> n{} [label=\"{}\"{}];", branch.from, to, escape(&label), style);
+                }
+                (_, None) => {}
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+// Collects the explanation while `Planner::plan_explained` searches
+pub(crate) struct ExplanationBuilder {
+    goal: String,
+    nodes: Vec<SearchNode>,
+    ids: HashMap<WorldState, usize>,
+    branches: Vec<SearchBranch>,
+    // Branch that currently holds the best route into each node
+    best_branch: HashMap<usize, usize>,
+    expansions: usize,
+    pub(crate) outcome: SearchOutcome,
+    goal_state: Option<usize>,
+}
+
+impl ExplanationBuilder {
+    pub(crate) fn new(goal: &str) -> Self {
+        ExplanationBuilder {
+            goal: goal.to_string(),
+            nodes: Vec::new(),
+            ids: HashMap::new(),
+            branches: Vec::new(),
+            best_branch: HashMap::new(),
+            expansions: 0,
+            outcome: SearchOutcome::Unreachable,
+            goal_state: None,
+        }
+    }
+
+    fn node(&mut self, state: &WorldState, cost: f32, heuristic: f32) -> usize {
+        if let Some(&id) = self.ids.get(state) {
+            return id;
+        }
+        let id = self.nodes.len();
+        self.nodes.push(SearchNode { id, state: state.clone(), cost, heuristic, expanded: None, on_plan: false });
+        self.ids.insert(state.clone(), id);
+        id
+    }
+
+    pub(crate) fn start(&mut self, state: &WorldState, heuristic: f32) {
+        self.node(state, 0.0, heuristic);
+    }
+
+    pub(crate) fn expanded(&mut self, state: &WorldState) {
+        let id = self.node(state, 0.0, 0.0);
+        self.expansions += 1;
+        self.nodes[id].expanded = Some(self.expansions);
+    }
+
+    pub(crate) fn unmet(&mut self, from: &WorldState, action: &Action) {
+        let from = self.node(from, 0.0, 0.0);
+        let missing = action
+            .preconditions
+            .iter()
+            .filter(|(key, value)| self.nodes[from].state.get(key) != Some(value))
+            .cloned()
+            .collect();
+        self.branches.push(SearchBranch {
+            from,
+            to: None,
+            action: action.name.clone(),
+            action_cost: action.cost,
+            cost: self.nodes[from].cost + action.cost,
+            outcome: BranchOutcome::PreconditionUnmet { missing },
+        });
+    }
+
+    // `known` is the previous best cost of `to` when this branch did not improve on it
+    pub(crate) fn branch(&mut self, from: &WorldState, to: &WorldState, action: &Action, cost: f32, heuristic: f32, known: Option<f32>) {
+        let from = self.node(from, 0.0, 0.0);
+        let to_id = self.node(to, cost, heuristic);
+        let outcome = match known {
+            Some(known_cost) => BranchOutcome::Costlier { known_cost },
+            None => {
+                self.nodes[to_id].cost = cost;
+                self.nodes[to_id].heuristic = heuristic;
+                if let Some(previous) = self.best_branch.insert(to_id, self.branches.len()) {
+                    self.branches[previous].outcome = BranchOutcome::Superseded;
+                }
+                BranchOutcome::Taken
+            }
+        };
+        self.branches.push(SearchBranch {
+            from,
+            to: Some(to_id),
+            action: action.name.clone(),
+            action_cost: action.cost,
+            cost,
+            outcome,
+        });
+    }
+
+    pub(crate) fn found(&mut self, state: &WorldState) {
+        self.outcome = SearchOutcome::Found;
+        self.goal_state = self.ids.get(state).copied();
+    }
+
+    pub(crate) fn finish(mut self) -> PlanExplanation {
+        let mut path = Vec::new();
+        let mut cost_breakdown = Vec::new();
+        if let Some(mut current) = self.goal_state {
+            path.push(current);
+            while let Some(&branch) = self.best_branch.get(&current) {
+                let branch = &self.branches[branch];
+                cost_breakdown.push(CostStep { action: branch.action.clone(), cost: branch.action_cost, total: branch.cost });
+                current = branch.from;
+                path.push(current);
+            }
+            path.reverse();
+            cost_breakdown.reverse();
+        }
+        for &id in &path {
+            self.nodes[id].on_plan = true;
+        }
+        PlanExplanation {
+            goal: self.goal,
+            outcome: self.outcome,
+            nodes: self.nodes,
+            branches: self.branches,
+            path,
+            cost_breakdown,
+        }
+    }
+}
+
+fn fact(key: &str, value: &StateValue) -> String {
+    match value {
+        StateValue::Bool(v) => format!("{}={}", key, v),
+        StateValue::Int(v) => format!("{}={}", key, v),
+        StateValue::Text(v) => format!("{}={:?}", key, v),
+    }
+}
+
+fn facts(state: &WorldState) -> String {
+    state.iter().map(|(key, value)| fact(key, value)).collect::<Vec<_>>().join("\n")
+}
+
+// Only the facts that differ from the start state, to keep labels short
+fn state_diff(start: &WorldState, state: &WorldState) -> String {
+    let changed: Vec<String> = state
+        .iter()
+        .filter(|(key, value)| start.get(key) != Some(*value))
+        .map(|(key, value)| fact(key, value))
+        .collect();
+    if changed.is_empty() {
+        "(unchanged)".to_string()
+    } else {
+        changed.join("\n")
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::goap::{Goal, Planner};
+
+    // Finishing after preparing (cost 2) beats the direct route (cost 3)
+    fn planner() -> Planner {
+        Planner::new(vec![
+            Action::new("direct", 3.0).effect("done", true),
+            Action::new("prep", 1.0).effect("ready", true),
+            Action::new("finish", 1.0).requires("ready", true).effect("done", true),
+            Action::new("unlock", 1.0).requires("key", true).effect("done", true),
+        ])
+        .unwrap()
+    }
+
+    fn explain() -> PlanExplanation {
+        planner().plan_explained(&WorldState::new(), &Goal::new("finish job", 1.0).wants("done", true)).1
+    }
+
+    fn outcome_of<'a>(explanation: &'a PlanExplanation, from: usize, action: &str) -> &'a BranchOutcome {
+        &explanation.branches.iter().find(|b| b.from == from && b.action == action).unwrap().outcome
+    }
+
+    #[test]
+    fn the_chosen_path_comes_with_a_cost_breakdown() {
+        let explanation = explain();
+        assert_eq!(explanation.outcome, SearchOutcome::Found);
+        assert_eq!(explanation.expansions(), 2);
+        let steps: Vec<(&str, f32)> = explanation.cost_breakdown.iter().map(|s| (s.action.as_str(), s.total)).collect();
+        assert_eq!(steps, vec![("prep", 1.0), ("finish", 2.0)]);
+
+        let goal = *explanation.path.last().unwrap();
+        assert_eq!(explanation.path.first(), Some(&0));
+        assert_eq!(explanation.nodes[goal].state.get("done"), Some(&StateValue::Bool(true)));
+        assert_eq!(explanation.nodes.iter().filter(|n| n.on_plan).count(), 3);
+    }
+
+    #[test]
+    fn rejected_branches_say_why() {
+        let explanation = explain();
+        let ready = explanation.path[1];
+        assert_eq!(
+            outcome_of(&explanation, 0, "finish"),
+            &BranchOutcome::PreconditionUnmet { missing: vec![("ready".to_string(), StateValue::Bool(true))] }
+        );
+        assert_eq!(outcome_of(&explanation, ready, "prep"), &BranchOutcome::Costlier { known_cost: 1.0 });
+        // `direct` reached ready+done first, then `finish` got there cheaper
+        assert_eq!(outcome_of(&explanation, ready, "direct"), &BranchOutcome::Superseded);
+        assert_eq!(outcome_of(&explanation, ready, "finish"), &BranchOutcome::Taken);
+        assert_eq!(explanation.rejected().count(), 5);
+
+        let json = explanation.to_json();
+        assert_eq!(json["outcome"], "found");
+        assert!(json["branches"].as_array().unwrap().iter().any(|b| b["outcome"]["kind"] == "costlier"));
+    }
+
+    #[test]
+    fn dot_export_marks_the_plan_and_rejections() {
+        let explanation = explain();
+        let dot = explanation.to_dot(false);
+        assert!(dot.starts_with("digraph \"finish job\" {"));
+        assert!(dot.contains("fillcolor=\"palegreen\""));
+        assert!(dot.contains("penwidth=2.5"));
+        assert!(dot.contains("superseded"));
+        assert!(dot.contains("g=2.00 >= 1.00"));
+        // The direct route's goal state was never expanded
+        assert!(dot.contains("style=dashed, color=gray50"));
+        assert!(!dot.contains("needs"));
+        assert!(explanation.to_dot(true).contains("needs key=true"));
+    }
+
+    #[test]
+    fn failed_searches_record_why() {
+        let planner = planner();
+        let (plan, explanation) = planner.plan_explained(&WorldState::new(), &Goal::new("open", 1.0).wants("key", true));
+        assert!(plan.is_none());
+        assert_eq!(explanation.outcome, SearchOutcome::Unreachable);
+        assert!(explanation.path.is_empty() && explanation.cost_breakdown.is_empty());
+
+        let mut planner = planner;
+        planner.max_expansions = 1;
+        let (_, explanation) = planner.plan_explained(&WorldState::new(), &Goal::new("open", 1.0).wants("key", true));
+        assert_eq!(explanation.outcome, SearchOutcome::ExpansionLimit);
+        assert_eq!(explanation.expansions(), 1);
+    }
+}
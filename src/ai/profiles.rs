@@ -0,0 +1,365 @@
+// Behavior profiles
+//
+// A profile ("aggressive", "defensive", "cinematic") bundles how an NPC behaves: which GOAP
+// actions it may use and how expensive each feels, weights for utility scoring, the mood it
+// steers encounters towards and the tone of its dialogue. Profiles are loaded from the
+// [behavior_profiles] section of the aiTOML manifest and switched at runtime, globally or for
+// single NPCs.
+//
+// Switching crossfades: numeric parameters blend from whatever the NPC was using at the moment of
+// the switch (even mid-fade) to the new profile over `crossfade_secs`, so behavior does not pop.
+// Discrete choices (action set, dialogue tone) flip halfway through the fade.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use serde::Deserialize;
+
+use crate::ai::goap::{Action, GoapError, Planner};
+use crate::emotion::MoodVector;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+pub struct MoodTarget {
+    pub tension: f32,
+    pub valence: f32,
+    pub energy: f32,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct BehaviorProfile {
+    // GOAP actions this profile may use; empty allows every action
+    pub actions: Vec<String>,
+    // Multipliers on action costs; below 1 makes an action more attractive
+    pub action_costs: BTreeMap<String, f32>,
+    pub utility_weights: BTreeMap<String, f32>,
+    pub emotion_target: Option<MoodTarget>,
+    pub dialogue_tone: Option<String>,
+}
+
+fn default_crossfade() -> f64 {
+    2.0
+}
+
+// The [behavior_profiles] section of the aiTOML manifest
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProfileSet {
+    // Profile active for every NPC without its own
+    pub default: String,
+    #[serde(default = "default_crossfade")]
+    pub crossfade_secs: f64,
+    pub profiles: HashMap<String, BehaviorProfile>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ManifestSection {
+    behavior_profiles: ProfileSet,
+}
+
+impl ProfileSet {
+    // Parse a manifest containing a [behavior_profiles] section
+    pub fn from_toml(contents: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str::<ManifestSection>(contents).map(|m| m.behavior_profiles)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProfileError {
+    UnknownProfile(String),
+}
+
+impl fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProfileError::UnknownProfile(name) => write!(f, "unknown behavior profile '{}'", name),
+        }
+    }
+}
+
+impl std::error::Error for ProfileError {}
+
+// Parameters an NPC should use right now
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileParams {
+    // Profile being faded to (or fully active)
+    pub profile: String,
+    // 0 at the switch, 1 once the fade is complete
+    pub blend: f32,
+    pub allowed_actions: Vec<String>,
+    pub action_costs: BTreeMap<String, f32>,
+    pub utility_weights: BTreeMap<String, f32>,
+    pub emotion_target: MoodVector,
+    pub dialogue_tone: String,
+}
+
+impl ProfileParams {
+    fn of(name: &str, profile: &BehaviorProfile) -> Self {
+        let mood = profile.emotion_target.unwrap_or_default();
+        ProfileParams {
+            profile: name.to_string(),
+            blend: 1.0,
+            allowed_actions: profile.actions.clone(),
+            action_costs: profile.action_costs.clone(),
+            utility_weights: profile.utility_weights.clone(),
+            emotion_target: MoodVector::new(mood.tension, mood.valence, mood.energy),
+            dialogue_tone: profile.dialogue_tone.clone().unwrap_or_else(|| "neutral".to_string()),
+        }
+    }
+
+    // Interpolate towards `to`; keys missing on one side count as a neutral 1.0
+    fn blend(&self, to: &ProfileParams, t: f32) -> ProfileParams {
+        let discrete = if t >= 0.5 { to } else { self };
+        ProfileParams {
+            profile: to.profile.clone(),
+            blend: t,
+            allowed_actions: discrete.allowed_actions.clone(),
+            action_costs: blend_map(&self.action_costs, &to.action_costs, t),
+            utility_weights: blend_map(&self.utility_weights, &to.utility_weights, t),
+            emotion_target: self.emotion_target.lerp(to.emotion_target, t),
+            dialogue_tone: discrete.dialogue_tone.clone(),
+        }
+    }
+
+    pub fn allows(&self, action: &str) -> bool {
+        self.allowed_actions.is_empty() || self.allowed_actions.iter().any(|a| a == action)
+    }
+
+    pub fn utility_weight(&self, consideration: &str) -> f32 {
+        self.utility_weights.get(consideration).copied().unwrap_or(1.0)
+    }
+
+    // Planner over the allowed actions of `library`, with costs scaled by the profile; fails when a
+    // scaled cost is negative or not finite
+    pub fn planner(&self, library: &[Action]) -> Result<Planner, GoapError> {
+        let actions = library
+            .iter()
+            .filter(|a| self.allows(&a.name))
+            .map(|a| {
+                let mut action = a.clone();
+                action.cost *= self.action_costs.get(&a.name).copied().unwrap_or(1.0);
+                action
+            })
+            .collect();
+        Planner::new(actions)
+    }
+}
+
+fn blend_map(from: &BTreeMap<String, f32>, to: &BTreeMap<String, f32>, t: f32) -> BTreeMap<String, f32> {
+    from.keys()
+        .chain(to.keys())
+        .map(|key| {
+            let a = from.get(key).copied().unwrap_or(1.0);
+            let b = to.get(key).copied().unwrap_or(1.0);
+            (key.clone(), a + (b - a) * t)
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+struct Fade {
+    from: ProfileParams,
+    // None fades back to whatever the global profile is
+    to: Option<String>,
+    started_at: f64,
+}
+
+pub struct BehaviorProfiles {
+    set: ProfileSet,
+    global: Fade,
+    // NPCs with their own profile; the rest follow the global one
+    npcs: HashMap<String, Fade>,
+}
+
+impl BehaviorProfiles {
+    pub fn new(set: ProfileSet) -> Result<Self, ProfileError> {
+        let default = set.profiles.get(&set.default).ok_or_else(|| ProfileError::UnknownProfile(set.default.clone()))?;
+        let global = Fade { from: ProfileParams::of(&set.default, default), to: Some(set.default.clone()), started_at: f64::MIN };
+        Ok(BehaviorProfiles { set, global, npcs: HashMap::new() })
+    }
+
+    pub fn profile_names(&self) -> Vec<&str> {
+        let mut names: Vec<&str> = self.set.profiles.keys().map(String::as_str).collect();
+        names.sort();
+        names
+    }
+
+    // Add or replace a profile, e.g. after the manifest was edited; active fades pick it up
+    pub fn insert(&mut self, name: &str, profile: BehaviorProfile) {
+        self.set.profiles.insert(name.to_string(), profile);
+    }
+
+    fn resolve(&self, fade: &Fade, now: f64) -> ProfileParams {
+        let target = match &fade.to {
+            Some(name) => match self.set.profiles.get(name) {
+                Some(profile) => ProfileParams::of(name, profile),
+                None => return fade.from.clone(),
+            },
+            None => self.resolve(&self.global, now),
+        };
+        let t = if self.set.crossfade_secs <= 0.0 {
+            1.0
+        } else {
+            ((now - fade.started_at) / self.set.crossfade_secs).clamp(0.0, 1.0) as f32
+        };
+        if t >= 1.0 {
+            target
+        } else {
+            fade.from.blend(&target, t)
+        }
+    }
+
+    pub fn params(&self, npc: &str, now: f64) -> ProfileParams {
+        self.resolve(self.npcs.get(npc).unwrap_or(&self.global), now)
+    }
+
+    pub fn global_params(&self, now: f64) -> ProfileParams {
+        self.resolve(&self.global, now)
+    }
+
+    fn check(&self, profile: &str) -> Result<(), ProfileError> {
+        if self.set.profiles.contains_key(profile) {
+            Ok(())
+        } else {
+            Err(ProfileError::UnknownProfile(profile.to_string()))
+        }
+    }
+
+    // Switch the global profile; NPCs following it crossfade from where they are
+    pub fn switch_global(&mut self, profile: &str, now: f64) -> Result<(), ProfileError> {
+        self.check(profile)?;
+        let from = self.resolve(&self.global, now);
+        self.global = Fade { from, to: Some(profile.to_string()), started_at: now };
+        Ok(())
+    }
+
+    pub fn switch_npc(&mut self, npc: &str, profile: &str, now: f64) -> Result<(), ProfileError> {
+        self.check(profile)?;
+        let from = self.params(npc, now);
+        self.npcs.insert(npc.to_string(), Fade { from, to: Some(profile.to_string()), started_at: now });
+        Ok(())
+    }
+
+    // Return an NPC to the global profile, fading from its own
+    pub fn follow_global(&mut self, npc: &str, now: f64) {
+        if let Some(own) = self.npcs.remove(npc) {
+            let from = self.resolve(&own, now);
+            self.npcs.insert(npc.to_string(), Fade { from, to: None, started_at: now });
+        }
+    }
+
+    // Forget per-NPC state, e.g. when the NPC despawns
+    pub fn forget(&mut self, npc: &str) {
+        self.npcs.remove(npc);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r#"
+[behavior_profiles]
+default = "defensive"
+
+[behavior_profiles.profiles.aggressive]
+actions = ["charge", "shoot"]
+action_costs = { charge = 0.5 }
+utility_weights = { threat = 2.0 }
+emotion_target = { tension = 0.8, valence = 0.0, energy = 1.0 }
+dialogue_tone = "taunting"
+
+[behavior_profiles.profiles.defensive]
+actions = ["take_cover", "shoot"]
+utility_weights = { threat = 0.5 }
+emotion_target = { tension = 0.4, valence = 0.0, energy = 0.2 }
+"#;
+
+    fn profiles() -> BehaviorProfiles {
+        BehaviorProfiles::new(ProfileSet::from_toml(MANIFEST).unwrap()).unwrap()
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn manifest_loads_with_defaults() {
+        let set = ProfileSet::from_toml(MANIFEST).unwrap();
+        assert_eq!(set.crossfade_secs, 2.0);
+        let profiles = BehaviorProfiles::new(set.clone()).unwrap();
+        assert_eq!(profiles.profile_names(), vec!["aggressive", "defensive"]);
+
+        let params = profiles.params("anyone", 0.0);
+        assert_eq!((params.profile.as_str(), params.blend, params.dialogue_tone.as_str()), ("defensive", 1.0, "neutral"));
+        assert!(params.allows("take_cover") && !params.allows("charge"));
+        assert_eq!(params.utility_weight("stealth"), 1.0);
+
+        let missing = ProfileSet { default: "cinematic".to_string(), ..set };
+        assert_eq!(BehaviorProfiles::new(missing).err(), Some(ProfileError::UnknownProfile("cinematic".to_string())));
+    }
+
+    #[test]
+    fn global_switch_crossfades_numbers_and_flips_choices_halfway() {
+        let mut profiles = profiles();
+        profiles.switch_global("aggressive", 10.0).unwrap();
+
+        let early = profiles.global_params(10.5);
+        assert_eq!((early.profile.as_str(), early.blend), ("aggressive", 0.25));
+        assert!(close(early.utility_weight("threat"), 0.875));
+        // Missing cost multipliers count as 1.0
+        assert!(close(early.action_costs["charge"], 0.875));
+        assert!(close(early.emotion_target.energy, 0.4));
+        assert_eq!(early.dialogue_tone, "neutral");
+        assert!(early.allows("take_cover"));
+
+        let late = profiles.global_params(11.0);
+        assert_eq!(late.dialogue_tone, "taunting");
+        assert!(late.allows("charge") && !late.allows("take_cover"));
+        assert_eq!(profiles.global_params(20.0).utility_weight("threat"), 2.0);
+    }
+
+    #[test]
+    fn switching_mid_fade_starts_from_the_blended_values() {
+        let mut profiles = profiles();
+        profiles.switch_global("aggressive", 0.0).unwrap();
+        profiles.switch_global("defensive", 1.0).unwrap();
+        // Halfway to aggressive was 1.25; fading back from there
+        assert!(close(profiles.global_params(1.0).utility_weight("threat"), 1.25));
+        assert!(close(profiles.global_params(2.0).utility_weight("threat"), 0.875));
+        assert!(profiles.switch_global("cinematic", 2.0).is_err());
+    }
+
+    #[test]
+    fn npcs_can_override_and_return_to_the_global_profile() {
+        let mut profiles = profiles();
+        profiles.switch_npc("orc", "aggressive", 0.0).unwrap();
+        assert_eq!(profiles.params("orc", 5.0).profile, "aggressive");
+        assert_eq!(profiles.params("guard", 5.0).profile, "defensive");
+        assert_eq!(profiles.switch_npc("orc", "cinematic", 5.0), Err(ProfileError::UnknownProfile("cinematic".to_string())));
+
+        profiles.follow_global("orc", 5.0);
+        let returning = profiles.params("orc", 6.0);
+        assert_eq!((returning.profile.as_str(), returning.blend), ("defensive", 0.5));
+        assert!(close(returning.utility_weight("threat"), 1.25));
+        assert_eq!(profiles.params("orc", 7.0), profiles.params("guard", 7.0));
+
+        profiles.switch_npc("orc", "aggressive", 8.0).unwrap();
+        profiles.forget("orc");
+        assert_eq!(profiles.params("orc", 8.0).profile, "defensive");
+    }
+
+    #[test]
+    fn planners_use_the_allowed_actions_with_scaled_costs() {
+        let library = [Action::new("charge", 4.0), Action::new("shoot", 2.0), Action::new("take_cover", 1.0)];
+        let mut profiles = profiles();
+        profiles.switch_global("aggressive", 0.0).unwrap();
+        let planner = profiles.global_params(10.0).planner(&library).unwrap();
+        let costs: Vec<(&str, f32)> = planner.actions().iter().map(|a| (a.name.as_str(), a.cost)).collect();
+        assert_eq!(costs, vec![("charge", 2.0), ("shoot", 2.0)]);
+
+        let reckless = BehaviorProfile { action_costs: BTreeMap::from([("shoot".to_string(), -1.0)]), ..Default::default() };
+        profiles.insert("reckless", reckless);
+        profiles.switch_global("reckless", 0.0).unwrap();
+        assert!(matches!(profiles.global_params(10.0).planner(&library), Err(GoapError::InvalidCost { .. })));
+    }
+}
@@ -0,0 +1,333 @@
+// NPC daily routines
+//
+// A schedule lists activities (work, eat, sleep, patrol) with an in-game time window and the GOAP
+// goal the NPC should pursue during it; windows may wrap past midnight. Each update the routine
+// system tells the planner which goal is current for an NPC. Interruptions (combat, a fire, the
+// player starting a conversation) carry their own goal; one that outranks the current activity
+// takes over and the activity is recorded as suspended. When the interruption ends the NPC goes
+// back to its routine, and activities marked `resume_late` are still finished after their window
+// has passed. Routine state is persisted through the entity lifecycle.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::ai::goap::Goal;
+use crate::lifecycle::EntityLifecycle;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Activity {
+    pub name: String,
+    // Hours of the day; end before start wraps past midnight
+    pub start_hour: f64,
+    pub end_hour: f64,
+    // Goal handed to the planner; its priority ranks it against interruptions
+    pub goal: Goal,
+    // Keep pursuing after the window if an interruption made the NPC miss it
+    #[serde(default)]
+    pub resume_late: bool,
+}
+
+impl Activity {
+    pub fn new(name: &str, start_hour: f64, end_hour: f64, goal: Goal) -> Self {
+        Activity { name: name.to_string(), start_hour, end_hour, goal, resume_late: false }
+    }
+
+    pub fn resume_late(mut self) -> Self {
+        self.resume_late = true;
+        self
+    }
+
+    // Day the window containing `hour` opened on, if `hour` is inside it
+    fn window_day(&self, day: u64, hour: f64) -> Option<u64> {
+        if self.start_hour <= self.end_hour {
+            (self.start_hour <= hour && hour < self.end_hour).then_some(day)
+        } else if hour >= self.start_hour {
+            Some(day)
+        } else if hour < self.end_hour {
+            day.checked_sub(1)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Schedule {
+    pub activities: Vec<Activity>,
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Schedule::default()
+    }
+
+    pub fn with(mut self, activity: Activity) -> Self {
+        self.activities.push(activity);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interruption {
+    // e.g. "combat"; used to end it again
+    pub reason: String,
+    pub goal: Goal,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Suspended {
+    pub activity: String,
+    // Day the activity's window opened
+    pub day: u64,
+    pub interrupted_by: String,
+    pub at_hour: f64,
+}
+
+// Everything about an NPC's routine that survives a save
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RoutineState {
+    // Schedule name
+    pub schedule: String,
+    // (activity, day) pairs finished
+    pub completed: Vec<(String, u64)>,
+    // Oldest first
+    pub interruptions: Vec<Interruption>,
+    pub suspended: Vec<Suspended>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GoalSource {
+    Routine { activity: String, day: u64, late: bool },
+    Interruption(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct CurrentGoal {
+    pub goal: Goal,
+    pub source: GoalSource,
+}
+
+#[derive(Default)]
+pub struct RoutineSystem {
+    schedules: HashMap<String, Schedule>,
+    npcs: HashMap<String, RoutineState>,
+}
+
+impl RoutineSystem {
+    pub fn new() -> Self {
+        RoutineSystem::default()
+    }
+
+    pub fn add_schedule(&mut self, name: &str, schedule: Schedule) {
+        self.schedules.insert(name.to_string(), schedule);
+    }
+
+    pub fn assign(&mut self, npc: &str, schedule: &str) {
+        let state = self.npcs.entry(npc.to_string()).or_default();
+        state.schedule = schedule.to_string();
+    }
+
+    pub fn state(&self, npc: &str) -> Option<&RoutineState> {
+        self.npcs.get(npc)
+    }
+
+    // The scheduled activity due at this time, ignoring interruptions
+    fn scheduled(&self, state: &RoutineState, day: u64, hour: f64) -> Option<(&Activity, u64, bool)> {
+        let schedule = self.schedules.get(&state.schedule)?;
+        let done = |name: &str, day: u64| state.completed.iter().any(|(a, d)| a == name && *d == day);
+        let on_time = schedule
+            .activities
+            .iter()
+            .filter_map(|a| a.window_day(day, hour).map(|d| (a, d, false)))
+            .filter(|(a, d, _)| !done(&a.name, *d));
+        // Missed activities only count once their window has closed
+        let late = state.suspended.iter().filter_map(|s| {
+            let activity = schedule.activities.iter().find(|a| a.name == s.activity)?;
+            let open = activity.window_day(day, hour) == Some(s.day);
+            (activity.resume_late && !open && !done(&s.activity, s.day)).then_some((activity, s.day, true))
+        });
+        on_time.chain(late).max_by(|a, b| a.0.goal.priority.total_cmp(&b.0.goal.priority))
+    }
+
+    // Goal the planner should pursue for `npc` right now
+    pub fn current_goal(&self, npc: &str, day: u64, hour: f64) -> Option<CurrentGoal> {
+        let state = self.npcs.get(npc)?;
+        let interruption = state.interruptions.iter().max_by(|a, b| a.goal.priority.total_cmp(&b.goal.priority));
+        let routine = self.scheduled(state, day, hour).map(|(a, d, late)| CurrentGoal {
+            goal: a.goal.clone(),
+            source: GoalSource::Routine { activity: a.name.clone(), day: d, late },
+        });
+        match (interruption, routine) {
+            (Some(i), Some(routine)) if routine.goal.priority > i.goal.priority => Some(routine),
+            (Some(i), _) => Some(CurrentGoal { goal: i.goal.clone(), source: GoalSource::Interruption(i.reason.clone()) }),
+            (None, routine) => routine,
+        }
+    }
+
+    // Start an interruption; returns the activity it suspended, if it outranked one
+    pub fn interrupt(&mut self, npc: &str, reason: &str, goal: Goal, day: u64, hour: f64) -> Option<Suspended> {
+        let suspended = {
+            let state = self.npcs.get(npc)?;
+            self.scheduled(state, day, hour)
+                .filter(|(a, _, _)| goal.priority >= a.goal.priority)
+                .map(|(a, d, _)| Suspended { activity: a.name.clone(), day: d, interrupted_by: reason.to_string(), at_hour: hour })
+        };
+        let state = self.npcs.get_mut(npc)?;
+        state.interruptions.retain(|i| i.reason != reason);
+        state.interruptions.push(Interruption { reason: reason.to_string(), goal });
+        if let Some(suspended) = &suspended {
+            if !state.suspended.iter().any(|s| s.activity == suspended.activity && s.day == suspended.day) {
+                state.suspended.push(suspended.clone());
+            }
+        }
+        suspended
+    }
+
+    pub fn end_interruption(&mut self, npc: &str, reason: &str) -> bool {
+        let Some(state) = self.npcs.get_mut(npc) else {
+            return false;
+        };
+        let before = state.interruptions.len();
+        state.interruptions.retain(|i| i.reason != reason);
+        state.interruptions.len() != before
+    }
+
+    // The NPC reached the activity's goal; it is not scheduled again until its next window
+    pub fn complete(&mut self, npc: &str, activity: &str, day: u64) {
+        if let Some(state) = self.npcs.get_mut(npc) {
+            if !state.completed.iter().any(|(a, d)| a == activity && *d == day) {
+                state.completed.push((activity.to_string(), day));
+            }
+            state.suspended.retain(|s| !(s.activity == activity && s.day == day));
+        }
+    }
+
+    // Drop bookkeeping older than `keep_days`, e.g. once per in-game day
+    pub fn prune(&mut self, today: u64, keep_days: u64) {
+        let oldest = today.saturating_sub(keep_days);
+        for state in self.npcs.values_mut() {
+            state.completed.retain(|(_, d)| *d >= oldest);
+            state.suspended.retain(|s| s.day >= oldest);
+        }
+    }
+}
+
+impl EntityLifecycle for RoutineSystem {
+    fn subsystem(&self) -> &str {
+        "routine"
+    }
+
+    fn spawned(&mut self, entity: &str, restored: Option<&Value>) {
+        if let Some(state) = restored.and_then(|r| serde_json::from_value::<RoutineState>(r.clone()).ok()) {
+            self.npcs.insert(entity.to_string(), state);
+        }
+    }
+
+    fn despawned(&mut self, entity: &str) -> Option<Value> {
+        self.npcs.remove(entity).and_then(|state| serde_json::to_value(state).ok())
+    }
+
+    fn persist(&self, entity: &str) -> Option<Value> {
+        self.npcs.get(entity).and_then(|state| serde_json::to_value(state).ok())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn routines() -> RoutineSystem {
+        let schedule = Schedule::new()
+            .with(Activity::new("work", 8.0, 18.0, Goal::new("forge", 2.0).wants("forged", true)).resume_late())
+            .with(Activity::new("eat", 18.0, 20.0, Goal::new("dine", 1.0).wants("fed", true)))
+            .with(Activity::new("sleep", 22.0, 6.0, Goal::new("rest", 1.0).wants("rested", true)));
+        let mut system = RoutineSystem::new();
+        system.add_schedule("smith", schedule);
+        system.assign("hilda", "smith");
+        system
+    }
+
+    fn source(system: &RoutineSystem, day: u64, hour: f64) -> Option<GoalSource> {
+        system.current_goal("hilda", day, hour).map(|g| g.source)
+    }
+
+    fn routine(activity: &str, day: u64, late: bool) -> Option<GoalSource> {
+        Some(GoalSource::Routine { activity: activity.to_string(), day, late })
+    }
+
+    #[test]
+    fn windows_pick_the_activity_and_wrap_past_midnight() {
+        let system = routines();
+        assert_eq!(source(&system, 3, 9.0), routine("work", 3, false));
+        assert_eq!(source(&system, 3, 23.0), routine("sleep", 3, false));
+        // Early morning still belongs to the previous night's sleep
+        assert_eq!(source(&system, 4, 2.0), routine("sleep", 3, false));
+        assert_eq!(source(&system, 0, 2.0), None);
+        assert_eq!(source(&system, 3, 21.0), None);
+        assert!(system.current_goal("stranger", 3, 9.0).is_none());
+    }
+
+    #[test]
+    fn interruptions_take_over_only_when_they_outrank_the_activity() {
+        let mut system = routines();
+        let suspended = system.interrupt("hilda", "combat", Goal::new("fight", 5.0), 1, 10.0).unwrap();
+        assert_eq!((suspended.activity.as_str(), suspended.day, suspended.at_hour), ("work", 1, 10.0));
+        assert_eq!(source(&system, 1, 10.0), Some(GoalSource::Interruption("combat".to_string())));
+        assert!(system.end_interruption("hilda", "combat"));
+        assert!(!system.end_interruption("hilda", "combat"));
+        assert_eq!(source(&system, 1, 11.0), routine("work", 1, false));
+
+        // A chat does not pull her away from the forge
+        assert!(system.interrupt("hilda", "chat", Goal::new("talk", 1.5), 1, 11.0).is_none());
+        assert_eq!(source(&system, 1, 11.0), routine("work", 1, false));
+        // After hours the work suspended by combat still outranks the chat
+        assert_eq!(source(&system, 1, 21.0), routine("work", 1, true));
+        system.complete("hilda", "work", 1);
+        assert_eq!(source(&system, 1, 21.0), Some(GoalSource::Interruption("chat".to_string())));
+    }
+
+    #[test]
+    fn missed_activities_resume_late_until_completed() {
+        let mut system = routines();
+        system.interrupt("hilda", "fire", Goal::new("extinguish", 5.0), 1, 17.0);
+        system.end_interruption("hilda", "fire");
+        // Work outranks dinner and was marked resume_late
+        assert_eq!(source(&system, 1, 18.5), routine("work", 1, true));
+        system.complete("hilda", "work", 1);
+        assert!(system.state("hilda").unwrap().suspended.is_empty());
+        assert_eq!(source(&system, 1, 18.5), routine("eat", 1, false));
+
+        // Dinner is not resumed once its window has passed
+        system.interrupt("hilda", "fire", Goal::new("extinguish", 5.0), 1, 19.0);
+        system.end_interruption("hilda", "fire");
+        assert_eq!(source(&system, 1, 21.0), None);
+    }
+
+    #[test]
+    fn completed_activities_wait_for_their_next_window() {
+        let mut system = routines();
+        system.complete("hilda", "work", 2);
+        system.complete("hilda", "work", 2);
+        assert_eq!(source(&system, 2, 9.0), None);
+        assert_eq!(source(&system, 3, 9.0), routine("work", 3, false));
+        assert_eq!(system.state("hilda").unwrap().completed.len(), 1);
+
+        system.prune(10, 3);
+        assert!(system.state("hilda").unwrap().completed.is_empty());
+    }
+
+    #[test]
+    fn routine_state_survives_despawn() {
+        let mut system = routines();
+        system.interrupt("hilda", "combat", Goal::new("fight", 5.0), 1, 10.0);
+        let snapshot = system.despawned("hilda").unwrap();
+        assert!(system.state("hilda").is_none());
+
+        system.spawned("hilda", Some(&snapshot));
+        let state = system.state("hilda").unwrap();
+        assert_eq!((state.schedule.as_str(), state.interruptions.len(), state.suspended.len()), ("smith", 1, 1));
+        assert_eq!(system.persist("hilda"), Some(snapshot));
+    }
+}
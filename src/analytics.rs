@@ -0,0 +1,317 @@
+// Post-game session summaries
+//
+// A SessionRecorder follows one play session: it subscribes to the event bus, which serves as the
+// engine's audit log, keeps the events on designer-chosen key topics, samples the player's mood
+// for an emotional arc, and tracks difficulty changes, NPC relationship deltas and telemetry
+// totals. At the end of the session it produces a SessionSummary for post-game screens, which can
+// also be turned into PARIS feedback samples.
+//
+// Difficulty and relationship changes are picked up from "difficulty.changed" ({ "from", "to",
+// "reason" }) and "social.relationship_changed" ({ "npc", "delta" }) events, or recorded directly.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::emotion::{EmotionAdaptiveExperiences, MoodVector};
+use crate::events::{Event, EventBus, SubscriptionId};
+use crate::paris::feedback::{FeedbackSample, FeedbackSource};
+use crate::vector_index::unix_now;
+
+pub const DIFFICULTY_TOPIC: &str = "difficulty.changed";
+pub const RELATIONSHIP_TOPIC: &str = "social.relationship_changed";
+
+// Points kept in the summary's emotional arc
+const ARC_POINTS: usize = 64;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyEvent {
+    // Seconds since session start
+    pub at: u64,
+    pub topic: String,
+    pub source: String,
+    pub payload: Value,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ArcPoint {
+    pub at: u64,
+    pub tension: f32,
+    pub valence: f32,
+    pub energy: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DifficultyChange {
+    pub at: u64,
+    pub from: f64,
+    pub to: f64,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionSummary {
+    pub session_id: String,
+    pub player_id: String,
+    // Unix seconds
+    pub started_at: u64,
+    pub ended_at: u64,
+    pub key_events: Vec<KeyEvent>,
+    // Evenly downsampled mood samples, ready to chart
+    pub emotional_arc: Vec<ArcPoint>,
+    pub difficulty_changes: Vec<DifficultyChange>,
+    // Net relationship change per NPC over the session
+    pub relationship_deltas: BTreeMap<String, f64>,
+    // Event counts per topic and summed numeric telemetry fields
+    pub telemetry: BTreeMap<String, f64>,
+    // Events lost because the recorder's inbox overflowed
+    pub dropped_events: u64,
+}
+
+impl SessionSummary {
+    pub fn duration_secs(&self) -> u64 {
+        self.ended_at.saturating_sub(self.started_at)
+    }
+
+    pub fn to_json(&self) -> Value {
+        serde_json::to_value(self).unwrap_or(Value::Null)
+    }
+
+    pub fn mean_mood(&self) -> Option<MoodVector> {
+        if self.emotional_arc.is_empty() {
+            return None;
+        }
+        let n = self.emotional_arc.len() as f32;
+        Some(MoodVector::new(
+            self.emotional_arc.iter().map(|p| p.tension).sum::<f32>() / n,
+            self.emotional_arc.iter().map(|p| p.valence).sum::<f32>() / n,
+            self.emotional_arc.iter().map(|p| p.energy).sum::<f32>() / n,
+        ))
+    }
+
+    // "session.*" metrics for the PARIS feedback windows
+    pub fn feedback_samples(&self) -> Vec<FeedbackSample> {
+        let metric = |name: &str, value: f64| FeedbackSample::new(&format!("session.{}", name), value, FeedbackSource::Metrics);
+        let mut samples = vec![
+            metric("duration_secs", self.duration_secs() as f64),
+            metric("key_events", self.key_events.len() as f64),
+            metric("difficulty_changes", self.difficulty_changes.len() as f64),
+        ];
+        if let Some(mood) = self.mean_mood() {
+            samples.push(metric("mean_tension", mood.tension as f64));
+            samples.push(metric("mean_valence", mood.valence as f64));
+        }
+        if let (Some(first), Some(last)) = (self.emotional_arc.first(), self.emotional_arc.last()) {
+            samples.push(metric("valence_change", (last.valence - first.valence) as f64));
+        }
+        if !self.relationship_deltas.is_empty() {
+            let total: f64 = self.relationship_deltas.values().sum();
+            samples.push(metric("relationship_change", total));
+        }
+        samples
+    }
+}
+
+pub struct SessionRecorder {
+    session_id: String,
+    player_id: String,
+    started_at: u64,
+    subscription: SubscriptionId,
+    key_topics: Vec<String>,
+    key_events: Vec<KeyEvent>,
+    moods: Vec<ArcPoint>,
+    difficulty_changes: Vec<DifficultyChange>,
+    relationship_deltas: BTreeMap<String, f64>,
+    telemetry: BTreeMap<String, f64>,
+}
+
+impl SessionRecorder {
+    // Subscribes to every topic; `key_topics` are prefixes worth listing individually
+    pub fn start(session_id: &str, player_id: &str, key_topics: &[&str], bus: &mut EventBus) -> Self {
+        SessionRecorder {
+            session_id: session_id.to_string(),
+            player_id: player_id.to_string(),
+            started_at: unix_now(),
+            subscription: bus.subscribe(""),
+            key_topics: key_topics.iter().map(|t| t.to_string()).collect(),
+            key_events: Vec::new(),
+            moods: Vec::new(),
+            difficulty_changes: Vec::new(),
+            relationship_deltas: BTreeMap::new(),
+            telemetry: BTreeMap::new(),
+        }
+    }
+
+    fn elapsed(&self) -> u64 {
+        unix_now().saturating_sub(self.started_at)
+    }
+
+    // Drain the bus; call every few frames so the inbox does not overflow
+    pub fn ingest(&mut self, bus: &mut EventBus) {
+        for event in bus.drain(self.subscription) {
+            self.observe(event);
+        }
+    }
+
+    fn observe(&mut self, event: Event) {
+        let at = self.elapsed();
+        *self.telemetry.entry(format!("{}.count", event.topic)).or_default() += 1.0;
+        if let Value::Object(fields) = &event.payload {
+            for (field, value) in fields {
+                if let Some(number) = value.as_f64() {
+                    *self.telemetry.entry(format!("{}.{}", event.topic, field)).or_default() += number;
+                }
+            }
+        }
+
+        match event.topic.as_str() {
+            DIFFICULTY_TOPIC => {
+                let number = |key: &str| event.payload.get(key).and_then(Value::as_f64).unwrap_or(0.0);
+                let reason = event.payload.get("reason").and_then(Value::as_str).unwrap_or_default();
+                self.record_difficulty(number("from"), number("to"), reason);
+            }
+            RELATIONSHIP_TOPIC => {
+                if let (Some(npc), Some(delta)) = (
+                    event.payload.get("npc").and_then(Value::as_str),
+                    event.payload.get("delta").and_then(Value::as_f64),
+                ) {
+                    self.record_relationship(npc, delta);
+                }
+            }
+            _ => {}
+        }
+
+        if self.key_topics.iter().any(|prefix| event.topic.starts_with(prefix.as_str())) {
+            self.key_events.push(KeyEvent { at, topic: event.topic, source: event.source, payload: event.payload });
+        }
+    }
+
+    pub fn record_difficulty(&mut self, from: f64, to: f64, reason: &str) {
+        let at = self.elapsed();
+        self.difficulty_changes.push(DifficultyChange { at, from, to, reason: reason.to_string() });
+    }
+
+    pub fn record_relationship(&mut self, npc: &str, delta: f64) {
+        *self.relationship_deltas.entry(npc.to_string()).or_default() += delta;
+    }
+
+    pub fn record_mood(&mut self, mood: MoodVector) {
+        let at = self.elapsed();
+        self.moods.push(ArcPoint { at, tension: mood.tension, valence: mood.valence, energy: mood.energy });
+    }
+
+    // Sample the player's current mood estimate, if the emotion system has one
+    pub fn sample_mood(&mut self, emotion: &EmotionAdaptiveExperiences) {
+        if let Some(mood) = emotion.player_mood(&self.player_id) {
+            self.record_mood(mood);
+        }
+    }
+
+    pub fn finish(mut self, bus: &mut EventBus) -> SessionSummary {
+        self.ingest(bus);
+        let dropped_events = bus.dropped(self.subscription);
+        bus.unsubscribe(self.subscription);
+        SessionSummary {
+            session_id: self.session_id,
+            player_id: self.player_id,
+            started_at: self.started_at,
+            ended_at: unix_now(),
+            key_events: self.key_events,
+            emotional_arc: downsample(&self.moods, ARC_POINTS),
+            difficulty_changes: self.difficulty_changes,
+            relationship_deltas: self.relationship_deltas,
+            telemetry: self.telemetry,
+            dropped_events,
+        }
+    }
+}
+
+// Keep at most `max` points, evenly spaced and always including the first and last
+fn downsample(points: &[ArcPoint], max: usize) -> Vec<ArcPoint> {
+    if points.len() <= max || max < 2 {
+        return points.to_vec();
+    }
+    let step = (points.len() - 1) as f64 / (max - 1) as f64;
+    (0..max).map(|i| points[(i as f64 * step).round() as usize]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn point(valence: f32) -> ArcPoint {
+        ArcPoint { at: 0, tension: 0.5, valence, energy: 0.5 }
+    }
+
+    #[test]
+    fn recorder_collects_key_events_changes_and_telemetry() {
+        let mut bus = EventBus::new(64);
+        let mut recorder = SessionRecorder::start("s1", "p1", &["quest."], &mut bus);
+        bus.emit("quest.completed", "quests", json!({ "quest": "lost_ring", "xp": 50 }));
+        bus.emit("combat.hit", "combat", json!({ "damage": 12.5 }));
+        bus.emit("combat.hit", "combat", json!({ "damage": 7.5 }));
+        bus.emit(DIFFICULTY_TOPIC, "director", json!({ "from": 1.0, "to": 0.8, "reason": "deaths" }));
+        bus.emit(RELATIONSHIP_TOPIC, "social", json!({ "npc": "zara", "delta": 0.25 }));
+        recorder.ingest(&mut bus);
+        recorder.record_relationship("zara", -0.5);
+        bus.emit("quest.failed", "quests", json!({ "quest": "escort" }));
+
+        let summary = recorder.finish(&mut bus);
+        let topics: Vec<&str> = summary.key_events.iter().map(|e| e.topic.as_str()).collect();
+        assert_eq!(topics, vec!["quest.completed", "quest.failed"]);
+        assert_eq!(summary.telemetry["combat.hit.count"], 2.0);
+        assert_eq!(summary.telemetry["combat.hit.damage"], 20.0);
+        assert_eq!(summary.telemetry["quest.completed.xp"], 50.0);
+        assert_eq!(summary.difficulty_changes[0].reason, "deaths");
+        assert_eq!(summary.difficulty_changes[0].to, 0.8);
+        assert_eq!(summary.relationship_deltas["zara"], -0.25);
+        assert_eq!(summary.dropped_events, 0);
+        assert_eq!(summary.to_json()["player_id"], "p1");
+    }
+
+    #[test]
+    fn overflowing_inbox_is_reported() {
+        let mut bus = EventBus::new(2);
+        let recorder = SessionRecorder::start("s1", "p1", &[], &mut bus);
+        for i in 0..5 {
+            bus.emit("tick", "loop", json!({ "i": i }));
+        }
+        let summary = recorder.finish(&mut bus);
+        assert_eq!(summary.dropped_events, 3);
+        assert_eq!(summary.telemetry["tick.count"], 2.0);
+    }
+
+    #[test]
+    fn emotional_arc_is_downsampled_keeping_both_ends() {
+        let points: Vec<ArcPoint> = (0..200).map(|i| point(i as f32 / 199.0)).collect();
+        let arc = downsample(&points, 5);
+        let valences: Vec<f32> = arc.iter().map(|p| p.valence).collect();
+        assert_eq!(valences.len(), 5);
+        assert_eq!((valences[0], valences[4]), (0.0, 1.0));
+        assert!(valences.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(downsample(&points[..3], 5).len(), 3);
+    }
+
+    #[test]
+    fn summaries_become_feedback_samples() {
+        let mut bus = EventBus::new(8);
+        let mut recorder = SessionRecorder::start("s1", "p1", &[], &mut bus);
+        let mut emotion = EmotionAdaptiveExperiences::new();
+        recorder.sample_mood(&emotion);
+        emotion.set_player_mood("p1", MoodVector::new(0.6, -0.4, 0.5));
+        recorder.sample_mood(&emotion);
+        recorder.record_mood(MoodVector::new(0.2, 0.4, 0.5));
+        recorder.record_relationship("zara", 0.5);
+
+        let summary = recorder.finish(&mut bus);
+        assert_eq!(summary.emotional_arc.len(), 2);
+        let samples: BTreeMap<String, f64> = summary.feedback_samples().into_iter().map(|s| (s.metric, s.value)).collect();
+        assert!((samples["session.mean_tension"] - 0.4).abs() < 1e-6);
+        assert!((samples["session.valence_change"] - 0.8).abs() < 1e-6);
+        assert_eq!(samples["session.relationship_change"], 0.5);
+        assert_eq!(samples["session.key_events"], 0.0);
+        assert!(samples.contains_key("session.duration_secs"));
+    }
+}
@@ -0,0 +1,401 @@
+// Anomaly detection on engine metrics
+//
+// An AnomalyDetector keeps an exponentially weighted mean and variance per metric and flags
+// samples that land more than `threshold` standard deviations away from it. Metrics with a daily
+// (or other) rhythm can be given a season: the period is split into buckets, each with its own
+// baseline, so the evening peak is compared against previous evenings rather than the night.
+//
+// Besides spikes and drops, the detector watches for oscillation: a metric whose direction keeps
+// flipping with a meaningful amplitude, which is how AI feedback loops usually show up in
+// production (difficulty bouncing between two levels, an adaptation loop fighting itself).
+//
+// Anomalies are queued and published as "diagnostics.anomaly" events by `publish`. Difficulty
+// changes already on the event bus can be fed in with `watch_difficulty` and `ingest`.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::analytics::DIFFICULTY_TOPIC;
+use crate::events::{EventBus, SubscriptionId};
+
+pub const ANOMALY_TOPIC: &str = "diagnostics.anomaly";
+
+// Core metrics, with defaults from `AnomalyDetector::with_core_metrics`
+pub const TICK_TIME_MS: &str = "engine.tick_ms";
+pub const SEARCH_LATENCY_MS: &str = "vector_index.search_ms";
+pub const ADAPTATION_RATE: &str = "paris.adaptations_per_min";
+pub const ERROR_RATE: &str = "engine.error_rate";
+pub const DIFFICULTY: &str = "difficulty.level";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    // Only increases are anomalous (latencies, error rates)
+    Above,
+    Below,
+    Both,
+}
+
+#[derive(Debug, Clone)]
+pub struct MetricConfig {
+    // EWMA weight of each new sample
+    pub alpha: f64,
+    // Standard deviations from the baseline that count as anomalous
+    pub threshold: f64,
+    pub direction: Direction,
+    // Samples per baseline before it is trusted
+    pub warmup: usize,
+    // Floor on the standard deviation, so perfectly flat metrics do not alarm on noise
+    pub min_std: f64,
+    // Seconds during which a repeat of the same anomaly is not reported again
+    pub cooldown_secs: u64,
+    // Seasonal baselines: (period in seconds, buckets per period)
+    pub season: Option<(u64, usize)>,
+    // Oscillation check: recent samples examined, and the share of direction reversals among
+    // them (with a step of at least `min_std`) that counts as oscillating. 0 disables it.
+    pub oscillation_window: usize,
+    pub oscillation_ratio: f64,
+}
+
+impl Default for MetricConfig {
+    fn default() -> Self {
+        MetricConfig {
+            alpha: 0.05,
+            threshold: 4.0,
+            direction: Direction::Both,
+            warmup: 30,
+            min_std: 1e-3,
+            cooldown_secs: 60,
+            season: None,
+            oscillation_window: 0,
+            oscillation_ratio: 0.7,
+        }
+    }
+}
+
+impl MetricConfig {
+    pub fn direction(mut self, direction: Direction) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    pub fn threshold(mut self, threshold: f64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    pub fn min_std(mut self, min_std: f64) -> Self {
+        self.min_std = min_std;
+        self
+    }
+
+    pub fn seasonal(mut self, period_secs: u64, buckets: usize) -> Self {
+        self.season = Some((period_secs.max(1), buckets.max(1)));
+        self
+    }
+
+    pub fn oscillation(mut self, window: usize, ratio: f64) -> Self {
+        self.oscillation_window = window;
+        self.oscillation_ratio = ratio;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyKind {
+    Spike,
+    Drop,
+    Oscillation,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Anomaly {
+    pub metric: String,
+    pub kind: AnomalyKind,
+    pub value: f64,
+    // Baseline mean at the time; for oscillation, the mean of the window
+    pub expected: f64,
+    // Standard deviations from the baseline; for oscillation, the reversal share
+    pub score: f64,
+    pub at: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Baseline {
+    mean: f64,
+    variance: f64,
+    samples: usize,
+}
+
+impl Baseline {
+    fn update(&mut self, value: f64, alpha: f64) {
+        if self.samples == 0 {
+            self.mean = value;
+        } else {
+            let diff = value - self.mean;
+            self.mean += alpha * diff;
+            self.variance = (1.0 - alpha) * (self.variance + alpha * diff * diff);
+        }
+        self.samples += 1;
+    }
+}
+
+struct Metric {
+    config: MetricConfig,
+    baselines: Vec<Baseline>,
+    recent: VecDeque<f64>,
+    // Last report time per kind, for the cooldown
+    reported: BTreeMap<&'static str, u64>,
+}
+
+impl Metric {
+    fn new(config: MetricConfig) -> Self {
+        let buckets = config.season.map_or(1, |(_, buckets)| buckets);
+        Metric { config, baselines: vec![Baseline::default(); buckets], recent: VecDeque::new(), reported: BTreeMap::new() }
+    }
+
+    fn bucket(&self, at: u64) -> usize {
+        match self.config.season {
+            Some((period, buckets)) => ((at % period) as usize * buckets / period as usize).min(buckets - 1),
+            None => 0,
+        }
+    }
+
+    fn cooled_down(&mut self, kind: AnomalyKind, at: u64) -> bool {
+        let key = match kind {
+            AnomalyKind::Spike => "spike",
+            AnomalyKind::Drop => "drop",
+            AnomalyKind::Oscillation => "oscillation",
+        };
+        match self.reported.get(key) {
+            Some(&last) if at.saturating_sub(last) < self.config.cooldown_secs => false,
+            _ => {
+                self.reported.insert(key, at);
+                true
+            }
+        }
+    }
+
+    // Share of interior points where the series changes direction by at least `min_std`
+    fn reversal_share(&self) -> f64 {
+        let steps: Vec<f64> = self
+            .recent
+            .iter()
+            .zip(self.recent.iter().skip(1))
+            .map(|(a, b)| b - a)
+            .filter(|step| step.abs() >= self.config.min_std)
+            .collect();
+        if steps.len() < 2 {
+            return 0.0;
+        }
+        let reversals = steps.windows(2).filter(|pair| pair[0].signum() != pair[1].signum()).count();
+        reversals as f64 / (steps.len() - 1) as f64
+    }
+}
+
+pub struct AnomalyDetector {
+    metrics: BTreeMap<String, Metric>,
+    default_config: MetricConfig,
+    pending: Vec<Anomaly>,
+    difficulty: Option<SubscriptionId>,
+}
+
+impl AnomalyDetector {
+    // Metrics observed without being configured use `default_config`
+    pub fn new(default_config: MetricConfig) -> Self {
+        AnomalyDetector { metrics: BTreeMap::new(), default_config, pending: Vec::new(), difficulty: None }
+    }
+
+    pub fn with_core_metrics() -> Self {
+        let mut detector = AnomalyDetector::new(MetricConfig::default());
+        detector.configure(TICK_TIME_MS, MetricConfig::default().direction(Direction::Above).min_std(0.5));
+        detector.configure(SEARCH_LATENCY_MS, MetricConfig::default().direction(Direction::Above).min_std(1.0));
+        detector.configure(ADAPTATION_RATE, MetricConfig::default().min_std(0.5).oscillation(12, 0.8));
+        detector.configure(ERROR_RATE, MetricConfig::default().direction(Direction::Above).min_std(0.01));
+        detector.configure(DIFFICULTY, MetricConfig::default().threshold(5.0).min_std(0.05).oscillation(10, 0.7));
+        detector
+    }
+
+    // Replaces the metric's baseline
+    pub fn configure(&mut self, metric: &str, config: MetricConfig) {
+        self.metrics.insert(metric.to_string(), Metric::new(config));
+    }
+
+    // Check `value` against the metric's baseline, then fold it in. `at` is in seconds and picks
+    // the seasonal bucket.
+    pub fn observe(&mut self, metric: &str, value: f64, at: u64) -> Vec<Anomaly> {
+        if !value.is_finite() {
+            return Vec::new();
+        }
+        let default_config = &self.default_config;
+        let state = self.metrics.entry(metric.to_string()).or_insert_with(|| Metric::new(default_config.clone()));
+        let config = state.config.clone();
+        let mut found = Vec::new();
+
+        let bucket = state.bucket(at);
+        let baseline = &state.baselines[bucket];
+        if baseline.samples >= config.warmup {
+            let std = baseline.variance.sqrt().max(config.min_std);
+            let score = (value - baseline.mean) / std;
+            let kind = match config.direction {
+                Direction::Above | Direction::Both if score >= config.threshold => Some(AnomalyKind::Spike),
+                Direction::Below | Direction::Both if score <= -config.threshold => Some(AnomalyKind::Drop),
+                _ => None,
+            };
+            let expected = baseline.mean;
+            if let Some(kind) = kind.filter(|kind| state.cooled_down(*kind, at)) {
+                found.push(Anomaly { metric: metric.to_string(), kind, value, expected, score, at });
+            }
+        }
+        state.baselines[bucket].update(value, config.alpha);
+
+        if config.oscillation_window > 2 {
+            if state.recent.len() >= config.oscillation_window {
+                state.recent.pop_front();
+            }
+            state.recent.push_back(value);
+            if state.recent.len() >= config.oscillation_window {
+                let share = state.reversal_share();
+                if share >= config.oscillation_ratio && state.cooled_down(AnomalyKind::Oscillation, at) {
+                    let expected = state.recent.iter().sum::<f64>() / state.recent.len() as f64;
+                    found.push(Anomaly { metric: metric.to_string(), kind: AnomalyKind::Oscillation, value, expected, score: share, at });
+                }
+            }
+        }
+
+        self.pending.extend(found.iter().cloned());
+        found
+    }
+
+    // Feed "difficulty.changed" events into the DIFFICULTY metric on `ingest`
+    pub fn watch_difficulty(&mut self, bus: &mut EventBus) {
+        if self.difficulty.is_none() {
+            self.difficulty = Some(bus.subscribe(DIFFICULTY_TOPIC));
+        }
+    }
+
+    pub fn ingest(&mut self, bus: &mut EventBus, at: u64) {
+        let Some(subscription) = self.difficulty else { return };
+        for event in bus.drain(subscription) {
+            if let Some(level) = event.payload.get("to").and_then(Value::as_f64) {
+                self.observe(DIFFICULTY, level, at);
+            }
+        }
+    }
+
+    // Baseline mean and standard deviation for the bucket `at` falls in
+    pub fn baseline(&self, metric: &str, at: u64) -> Option<(f64, f64)> {
+        let state = self.metrics.get(metric)?;
+        let baseline = &state.baselines[state.bucket(at)];
+        (baseline.samples > 0).then(|| (baseline.mean, baseline.variance.sqrt()))
+    }
+
+    pub fn pending(&self) -> &[Anomaly] {
+        &self.pending
+    }
+
+    // Publish queued anomalies on ANOMALY_TOPIC; returns the number published
+    pub fn publish(&mut self, events: &mut EventBus) -> usize {
+        let count = self.pending.len();
+        for anomaly in self.pending.drain(..) {
+            events.emit(ANOMALY_TOPIC, &anomaly.metric, json!(anomaly));
+        }
+        count
+    }
+}
+
+impl Default for AnomalyDetector {
+    fn default() -> Self {
+        AnomalyDetector::with_core_metrics()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> MetricConfig {
+        MetricConfig { warmup: 5, min_std: 1.0, ..MetricConfig::default() }
+    }
+
+    fn feed(detector: &mut AnomalyDetector, metric: &str, values: &[f64], start: u64) -> Vec<Anomaly> {
+        values.iter().enumerate().flat_map(|(i, &value)| detector.observe(metric, value, start + i as u64)).collect()
+    }
+
+    #[test]
+    fn spikes_are_flagged_once_the_baseline_is_warm() {
+        let mut detector = AnomalyDetector::new(config().direction(Direction::Above));
+        assert!(feed(&mut detector, "tick", &[10.0, 10.0, 50.0], 0).is_empty());
+        let mut detector = AnomalyDetector::new(config().direction(Direction::Above));
+        assert!(feed(&mut detector, "tick", &[10.0; 5], 0).is_empty());
+        assert_eq!(detector.baseline("tick", 0), Some((10.0, 0.0)));
+
+        let found = detector.observe("tick", 20.0, 100);
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].kind, found[0].expected, found[0].score), (AnomalyKind::Spike, 10.0, 10.0));
+        // Drops don't matter for this metric, and repeats wait out the cooldown
+        assert!(detector.observe("tick", -50.0, 101).is_empty());
+        assert!(detector.observe("tick", 80.0, 102).is_empty());
+        assert_eq!(detector.observe("tick", 200.0, 200)[0].kind, AnomalyKind::Spike);
+        assert!(detector.observe("tick", f64::NAN, 300).is_empty());
+        assert_eq!(detector.pending().len(), 2);
+    }
+
+    #[test]
+    fn drops_are_flagged_when_configured() {
+        let mut detector = AnomalyDetector::new(config());
+        feed(&mut detector, "players", &[100.0; 5], 0);
+        let found = detector.observe("players", 90.0, 10);
+        assert_eq!(found[0].kind, AnomalyKind::Drop);
+        assert_eq!(found[0].score, -10.0);
+    }
+
+    #[test]
+    fn seasonal_metrics_compare_against_the_same_time_of_day() {
+        let mut detector = AnomalyDetector::new(config());
+        detector.configure("players", config().direction(Direction::Above).seasonal(86_400, 2));
+        let (night, day) = (0, 43_200);
+        feed(&mut detector, "players", &[5.0; 5], night);
+        feed(&mut detector, "players", &[100.0; 5], day);
+        assert_eq!(detector.baseline("players", night + 100).unwrap().0, 5.0);
+        assert_eq!(detector.baseline("players", day + 100).unwrap().0, 100.0);
+
+        assert!(detector.observe("players", 100.0, day + 86_400).is_empty());
+        assert_eq!(detector.observe("players", 100.0, night + 86_400)[0].kind, AnomalyKind::Spike);
+    }
+
+    #[test]
+    fn direction_flips_count_as_oscillation() {
+        let mut detector = AnomalyDetector::new(config());
+        // A long warmup keeps spike detection out of the way
+        detector.configure("difficulty", MetricConfig { warmup: 100, ..config() }.min_std(0.05).oscillation(6, 0.8));
+        assert!(feed(&mut detector, "difficulty", &[0.1, 0.2, 0.3, 0.4, 0.5, 0.6], 0).is_empty());
+
+        let found = feed(&mut detector, "difficulty", &[0.4, 0.6, 0.4, 0.6, 0.4, 0.6], 10);
+        let oscillation: Vec<&Anomaly> = found.iter().filter(|a| a.kind == AnomalyKind::Oscillation).collect();
+        assert_eq!(oscillation.len(), 1);
+        assert!(oscillation[0].score >= 0.8);
+    }
+
+    #[test]
+    fn difficulty_events_feed_the_detector_and_anomalies_are_published() {
+        let mut bus = EventBus::new(64);
+        let sub = bus.subscribe(ANOMALY_TOPIC);
+        let mut detector = AnomalyDetector::with_core_metrics();
+        detector.watch_difficulty(&mut bus);
+        for i in 0..10 {
+            let level = if i % 2 == 0 { 0.3 } else { 0.7 };
+            bus.emit(DIFFICULTY_TOPIC, "dda", json!({ "from": 1.0 - level, "to": level }));
+        }
+        detector.ingest(&mut bus, 50);
+        assert_eq!(detector.pending().len(), 1);
+
+        assert_eq!(detector.publish(&mut bus), 1);
+        let events = bus.drain(sub);
+        assert_eq!((events[0].source.as_str(), &events[0].payload["kind"]), (DIFFICULTY, &json!("oscillation")));
+        assert!(detector.pending().is_empty());
+    }
+}
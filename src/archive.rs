@@ -0,0 +1,336 @@
+// Archive tier for forgotten memories
+//
+// NPC memories are soft-deleted rather than destroyed (`VectorIndex::soft_delete`): they stop
+// showing up in search but stay in the collection, and `undelete` brings them back. Points that
+// stay deleted past a grace period can be moved to a VectorArchive, a cheaper tier outside the
+// live index: vectors are quantized to one signed byte per dimension with a per-point scale
+// (a quarter of the size, cosine similarity within about a percent), and the archive is saved to
+// its own versioned file, optionally encrypted, rather than the hot snapshot.
+//
+// Narrative tools can browse the archive with `search` and `resurrect` a memory for plot
+// purposes: the point goes back into its collection with the dequantized vector, its payload and
+// the time it was resurrected, and is searchable again. Archived payloads still name their player,
+// so the archive is one of the stores a privacy deletion goes through (security/privacy.rs).
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::embeddings::cosine_similarity;
+use crate::security::encryption::Encryption;
+use crate::vector_index::{SearchResult, VectorIndex, VectorIndexError, VectorPoint, DELETED_AT_FIELD};
+use crate::versioning::{self, MigrationError, MigrationRegistry};
+
+pub const VECTOR_ARCHIVE_FORMAT: &str = "vector_archive";
+pub const VECTOR_ARCHIVE_VERSION: u32 = 1;
+
+// Payload fields set on a resurrected point
+pub const ARCHIVED_AT_FIELD: &str = "archived_at";
+pub const RESURRECTED_AT_FIELD: &str = "resurrected_at";
+
+// Int8 vector: component i is codes[i] * scale
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuantizedVector {
+    pub scale: f32,
+    pub codes: Vec<i8>,
+}
+
+impl QuantizedVector {
+    pub fn quantize(vector: &[f32]) -> Self {
+        let max = vector.iter().filter(|v| v.is_finite()).fold(0.0f32, |max, v| max.max(v.abs()));
+        let scale = if max > 0.0 { max / i8::MAX as f32 } else { 1.0 };
+        let codes = vector
+            .iter()
+            .map(|v| if v.is_finite() { (v / scale).round().clamp(-127.0, 127.0) as i8 } else { 0 })
+            .collect();
+        QuantizedVector { scale, codes }
+    }
+
+    pub fn dequantize(&self) -> Vec<f32> {
+        self.codes.iter().map(|&code| code as f32 * self.scale).collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchivedPoint {
+    pub id: String,
+    pub vector: QuantizedVector,
+    pub payload: BTreeMap<String, Value>,
+    pub deleted_at: u64,
+    pub archived_at: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ArchiveReport {
+    // Collection -> points moved out of the live index
+    pub archived: BTreeMap<String, usize>,
+    pub errors: Vec<String>,
+}
+
+impl ArchiveReport {
+    pub fn total_archived(&self) -> usize {
+        self.archived.values().sum()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VectorArchive {
+    // Collection -> id -> point
+    collections: BTreeMap<String, BTreeMap<String, ArchivedPoint>>,
+}
+
+impl VectorArchive {
+    pub fn new() -> Self {
+        VectorArchive::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.collections.values().map(BTreeMap::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, collection: &str, id: &str) -> Option<&ArchivedPoint> {
+        self.collections.get(collection)?.get(id)
+    }
+
+    // (collection, point) over the whole archive
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &ArchivedPoint)> {
+        self.collections
+            .iter()
+            .flat_map(|(collection, points)| points.values().map(move |point| (collection.as_str(), point)))
+    }
+
+    // Archived ids of a collection in order
+    pub fn ids(&self, collection: &str) -> Vec<String> {
+        self.collections.get(collection).map(|points| points.keys().cloned().collect()).unwrap_or_default()
+    }
+
+    // Move points soft-deleted at least `grace_secs` ago from every collection into the archive
+    pub fn archive_deleted(&mut self, index: &mut VectorIndex, grace_secs: u64, now: u64) -> ArchiveReport {
+        let mut report = ArchiveReport::default();
+        let names: Vec<String> = index.collection_names().into_iter().map(str::to_string).collect();
+        for collection in names {
+            let due: Vec<String> = match index.deleted(&collection) {
+                Ok(deleted) => deleted
+                    .into_iter()
+                    .filter(|(_, deleted_at)| now.saturating_sub(*deleted_at) >= grace_secs)
+                    .map(|(id, _)| id)
+                    .collect(),
+                Err(err) => {
+                    report.errors.push(format!("{}: {}", collection, err));
+                    continue;
+                }
+            };
+            if due.is_empty() {
+                continue;
+            }
+            match self.archive(index, &collection, &due, now) {
+                Ok(moved) => {
+                    report.archived.insert(collection, moved);
+                }
+                Err(err) => report.errors.push(format!("{}: {}", collection, err)),
+            }
+        }
+        report
+    }
+
+    // Move the given points into the archive whether or not they were soft-deleted first
+    pub fn archive(&mut self, index: &mut VectorIndex, collection: &str, ids: &[String], now: u64) -> Result<usize, VectorIndexError> {
+        let target = index.collection(collection)?;
+        let points: Vec<ArchivedPoint> = ids
+            .iter()
+            .filter_map(|id| target.points.get(id.as_str()))
+            .map(|point| {
+                let mut payload: BTreeMap<String, Value> = point.payload.clone().into_iter().collect();
+                payload.remove(DELETED_AT_FIELD);
+                ArchivedPoint {
+                    id: point.id.clone(),
+                    vector: QuantizedVector::quantize(&point.vector),
+                    payload,
+                    deleted_at: point.deleted_at().unwrap_or(now),
+                    archived_at: now,
+                }
+            })
+            .collect();
+        let moved: Vec<String> = points.iter().map(|point| point.id.clone()).collect();
+        // Removed from the index (and its remote mirror) first, so a failure leaves the point live
+        index.delete(collection, &moved)?;
+        let archived = self.collections.entry(collection.to_string()).or_default();
+        for point in points {
+            archived.insert(point.id.clone(), point);
+        }
+        Ok(moved.len())
+    }
+
+    // Bring an archived point back into its collection, searchable again
+    pub fn resurrect(&mut self, index: &mut VectorIndex, collection: &str, id: &str, now: u64) -> Result<VectorPoint, VectorIndexError> {
+        let archived = self.get(collection, id).ok_or_else(|| VectorIndexError::PointNotFound(id.to_string()))?;
+        let mut point = VectorPoint::new(id, archived.vector.dequantize());
+        point.payload = archived.payload.clone().into_iter().collect();
+        point.payload.insert(ARCHIVED_AT_FIELD.to_string(), Value::from(archived.archived_at));
+        point.payload.insert(RESURRECTED_AT_FIELD.to_string(), Value::from(now));
+        index.upsert(collection, point.clone())?;
+        if let Some(points) = self.collections.get_mut(collection) {
+            points.remove(id);
+        }
+        Ok(point)
+    }
+
+    // Drop archived points for good; returns the number removed
+    pub fn purge(&mut self, collection: &str, ids: &[String]) -> usize {
+        let Some(points) = self.collections.get_mut(collection) else { return 0 };
+        ids.iter().filter(|id| points.remove(id.as_str()).is_some()).count()
+    }
+
+    // Top `limit` archived points of a collection by cosine similarity to `vector`
+    pub fn search(&self, collection: &str, vector: &[f32], limit: usize) -> Vec<SearchResult> {
+        let mut results: Vec<SearchResult> = self
+            .collections
+            .get(collection)
+            .into_iter()
+            .flat_map(|points| points.values())
+            .filter(|point| point.vector.codes.len() == vector.len())
+            .map(|point| SearchResult {
+                id: point.id.clone(),
+                score: cosine_similarity(vector, &point.vector.dequantize()),
+                payload: point.payload.clone().into_iter().collect(),
+            })
+            .collect();
+        results.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.id.cmp(&b.id)));
+        results.truncate(limit);
+        results
+    }
+
+    pub fn save(&self, path: &Path, encryption: Option<&Encryption>) -> Result<(), VectorIndexError> {
+        let data = serde_json::to_value(self).map_err(MigrationError::from)?;
+        versioning::write_versioned(path, VECTOR_ARCHIVE_FORMAT, VECTOR_ARCHIVE_VERSION, data, encryption)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path, registry: &MigrationRegistry, encryption: Option<&Encryption>) -> Result<Self, VectorIndexError> {
+        let outcome = versioning::read_versioned(path, VECTOR_ARCHIVE_FORMAT, registry, encryption)?;
+        Ok(serde_json::from_value(outcome.data).map_err(MigrationError::from)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector_index::VectorIndexConfig;
+
+    fn index() -> VectorIndex {
+        let mut index = VectorIndex::new(VectorIndexConfig {
+            url: String::new(),
+            api_key: String::new(),
+            default_ttl_secs: None,
+            collection_ttl_secs: Default::default(),
+        });
+        index.create_collection("memories", 3, "test").unwrap();
+        let memories = [("fire", [1.0, 0.2, 0.0], "p1"), ("flood", [0.0, 1.0, 0.3], "p2"), ("feast", [0.5, 0.5, 0.5], "p1")];
+        for (id, vector, player) in memories {
+            let mut point = VectorPoint::new(id, vector.to_vec());
+            point.payload.insert("player_id".to_string(), Value::from(player));
+            index.upsert("memories", point).unwrap();
+        }
+        index
+    }
+
+    fn ids(results: &[SearchResult]) -> Vec<&str> {
+        results.iter().map(|r| r.id.as_str()).collect()
+    }
+
+    #[test]
+    fn quantized_vectors_stay_within_half_a_step_of_the_original() {
+        let vector = vec![0.83, -0.41, 0.07, -1.2, 0.0, 0.5501];
+        let quantized = QuantizedVector::quantize(&vector);
+        assert_eq!(quantized.codes[3], -127);
+        for (original, restored) in vector.iter().zip(quantized.dequantize()) {
+            assert!((original - restored).abs() <= quantized.scale / 2.0 + 1e-6, "{} came back as {}", original, restored);
+        }
+        assert!(cosine_similarity(&vector, &quantized.dequantize()) > 0.99);
+
+        // Non-finite components become zero and an all-zero vector survives
+        assert_eq!(QuantizedVector::quantize(&[f32::NAN, 2.0]).dequantize(), [0.0, 2.0]);
+        assert_eq!(QuantizedVector::quantize(&[0.0, 0.0]).dequantize(), [0.0, 0.0]);
+    }
+
+    #[test]
+    fn only_points_deleted_longer_than_the_grace_period_are_archived() {
+        let mut index = index();
+        let mut archive = VectorArchive::new();
+        index.soft_delete("memories", &["fire".to_string()], 100).unwrap();
+        index.soft_delete("memories", &["flood".to_string()], 150).unwrap();
+
+        assert_eq!(archive.archive_deleted(&mut index, 60, 159), ArchiveReport::default());
+        let report = archive.archive_deleted(&mut index, 60, 160);
+        assert_eq!(report.total_archived(), 1);
+        assert_eq!(archive.ids("memories"), ["fire"]);
+        let archived = archive.get("memories", "fire").unwrap();
+        assert_eq!((archived.deleted_at, archived.archived_at), (100, 160));
+        assert!(!archived.payload.contains_key(DELETED_AT_FIELD));
+
+        let live = &index.collection("memories").unwrap().points;
+        assert!(!live.contains_key("fire"));
+        assert!(live.contains_key("flood") && live.contains_key("feast"));
+        assert_eq!(archive.archive_deleted(&mut index, 60, 210).archived["memories"], 1);
+        assert_eq!(archive.len(), 2);
+    }
+
+    #[test]
+    fn resurrected_points_are_searchable_again() {
+        let mut index = index();
+        let mut archive = VectorArchive::new();
+        archive.archive(&mut index, "memories", &["fire".to_string()], 500).unwrap();
+        assert_eq!(ids(&index.search("memories", &[1.0, 0.2, 0.0], 1).unwrap()), ["feast"]);
+
+        let point = archive.resurrect(&mut index, "memories", "fire", 900).unwrap();
+        assert_eq!(point.payload[ARCHIVED_AT_FIELD], 500);
+        assert_eq!(point.payload[RESURRECTED_AT_FIELD], 900);
+        assert_eq!(point.payload["player_id"], "p1");
+        assert!(archive.is_empty());
+        assert_eq!(ids(&index.search("memories", &[1.0, 0.2, 0.0], 1).unwrap()), ["fire"]);
+        assert!(matches!(archive.resurrect(&mut index, "memories", "fire", 901), Err(VectorIndexError::PointNotFound(_))));
+    }
+
+    #[test]
+    fn archived_memories_can_be_searched_and_purged() {
+        let mut index = index();
+        let mut archive = VectorArchive::new();
+        let all: Vec<String> = ["fire", "flood", "feast"].map(String::from).to_vec();
+        assert_eq!(archive.archive(&mut index, "memories", &all, 10).unwrap(), 3);
+
+        let results = archive.search("memories", &[0.0, 1.0, 0.3], 2);
+        assert_eq!(ids(&results), ["flood", "feast"]);
+        assert!(results[0].score > 0.99);
+        assert!(archive.search("memories", &[1.0, 0.0], 5).is_empty());
+        assert!(archive.search("quests", &[1.0, 0.0, 0.0], 5).is_empty());
+
+        assert_eq!(archive.purge("memories", &["flood".to_string(), "missing".to_string()]), 1);
+        assert_eq!(ids(&archive.search("memories", &[0.0, 1.0, 0.3], 5)), ["feast", "fire"]);
+    }
+
+    #[test]
+    fn archives_round_trip_through_their_file() {
+        let mut index = index();
+        let mut archive = VectorArchive::new();
+        archive.archive(&mut index, "memories", &["fire".to_string(), "feast".to_string()], 10).unwrap();
+        let registry = MigrationRegistry::with_builtin();
+        let encryption = Encryption::with_passphrase("archive test");
+
+        for (name, encryption) in [("plain", None), ("encrypted", Some(&encryption))] {
+            let path = std::env::temp_dir().join(format!("arcadia-archive-{}-{}.json", std::process::id(), name));
+            archive.save(&path, encryption).unwrap();
+            let loaded = VectorArchive::load(&path, &registry, encryption).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(loaded.ids("memories"), ["feast", "fire"]);
+            let (original, restored) = (archive.get("memories", "fire").unwrap(), loaded.get("memories", "fire").unwrap());
+            assert_eq!(restored.vector.codes, original.vector.codes);
+            assert_eq!(restored.payload, original.payload);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+// Authentication
+//
+// Players sign in through the provider named in the manifest's [authentication] section. Once the
+// provider has vouched for a player, the game's login flow opens a session with the token it
+// handed to the client; subsystems that take requests from players (leaderboard submissions) check
+// that token here instead of keeping their own. Sessions end on logout or after the session TTL.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+// Sessions last a day unless configured otherwise
+pub const DEFAULT_SESSION_TTL_SECS: u64 = 24 * 3600;
+
+// Authentication configuration
+#[derive(Debug, Clone, Deserialize)]
+pub struct AuthenticationConfig {
+    pub provider: String,
+    pub credentials: Credentials,
+}
+
+// Credentials definition
+#[derive(Debug, Clone, Deserialize)]
+pub struct Credentials {
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+#[derive(Debug, Clone)]
+struct Session {
+    token: String,
+    // Unix seconds
+    expires_at: u64,
+}
+
+// Authentication module
+#[derive(Debug)]
+pub struct Authentication {
+    config: AuthenticationConfig,
+    session_ttl_secs: u64,
+    // Player -> their current session
+    sessions: HashMap<String, Session>,
+}
+
+impl Authentication {
+    pub fn new(config: AuthenticationConfig) -> Self {
+        Authentication { config, session_ttl_secs: DEFAULT_SESSION_TTL_SECS, sessions: HashMap::new() }
+    }
+
+    pub fn with_session_ttl(mut self, secs: u64) -> Self {
+        self.session_ttl_secs = secs;
+        self
+    }
+
+    pub fn provider(&self) -> &str {
+        &self.config.provider
+    }
+
+    // Start a session for `player` with the token issued at login; replaces an earlier session
+    pub fn begin_session(&mut self, player: &str, token: &str, now: u64) {
+        let session = Session { token: token.to_string(), expires_at: now.saturating_add(self.session_ttl_secs) };
+        self.sessions.insert(player.to_string(), session);
+    }
+
+    pub fn end_session(&mut self, player: &str) {
+        self.sessions.remove(player);
+    }
+
+    // Whether `token` is the player's current, unexpired session token
+    pub fn validate(&self, player: &str, token: &str, now: u64) -> bool {
+        self.sessions
+            .get(player)
+            .is_some_and(|session| now < session.expires_at && constant_time_eq(session.token.as_bytes(), token.as_bytes()))
+    }
+
+    // Drop expired sessions; returns the number dropped
+    pub fn prune(&mut self, now: u64) -> usize {
+        let before = self.sessions.len();
+        self.sessions.retain(|_, session| now < session.expires_at);
+        before - self.sessions.len()
+    }
+}
+
+// Compare every byte so timing does not reveal how much of the token matched
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn auth() -> Authentication {
+        let credentials = Credentials { client_id: "arcadia".to_string(), client_secret: "secret".to_string() };
+        Authentication::new(AuthenticationConfig { provider: "steam".to_string(), credentials }).with_session_ttl(60)
+    }
+
+    #[test]
+    fn sessions_accept_only_the_current_unexpired_token() {
+        let mut auth = auth();
+        auth.begin_session("p1", "t1", 100);
+        assert!(auth.validate("p1", "t1", 159));
+        assert!(!auth.validate("p1", "t1", 160));
+        assert!(!auth.validate("p1", "t2", 100));
+        assert!(!auth.validate("p2", "t1", 100));
+
+        auth.begin_session("p1", "t2", 100);
+        assert!(!auth.validate("p1", "t1", 100));
+        auth.end_session("p1");
+        assert!(!auth.validate("p1", "t2", 100));
+    }
+
+    #[test]
+    fn prune_drops_expired_sessions() {
+        let mut auth = auth();
+        auth.begin_session("p1", "t1", 0);
+        auth.begin_session("p2", "t2", 100);
+        assert_eq!(auth.prune(120), 1);
+        assert!(auth.validate("p2", "t2", 120));
+    }
+}
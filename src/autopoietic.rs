@@ -0,0 +1,469 @@
+// Autopoietic component graph
+//
+// Game systems that organise themselves (economies, factions, NPC routines, the services behind
+// them) are tracked as components in a dependency graph. Each component carries a health state,
+// fed from the resilience HealthRegistry, and an activity level. `detect_patterns` looks for the
+// structures that emerge as the graph grows:
+//
+//   cluster   a group of components far more connected to each other than to the rest
+//   cycle     components that depend on each other in a loop (feedback)
+//   hub       a component many others depend on
+//
+// The graph exports to JSON for designer tooling and the debug server's dashboard, and to
+// Graphviz DOT with clusters drawn as boxes, cycle edges highlighted, hubs outlined and nodes
+// coloured by health.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::resilience::{HealthRegistry, HealthState};
+
+// Smallest group reported as a cluster
+const MIN_CLUSTER: usize = 3;
+// Internal edge density a cluster must reach
+const MIN_DENSITY: f32 = 0.5;
+// Hubs have at least this many dependents, and at least twice the mean
+const MIN_HUB_DEPENDENTS: usize = 3;
+
+#[derive(Debug, Clone)]
+pub struct Component {
+    pub id: String,
+    pub kind: String,
+    pub health: HealthState,
+    // 0..1, how busy the component has been lately
+    pub activity: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PatternKind {
+    Cluster,
+    Cycle,
+    Hub,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EmergentPattern {
+    pub id: String,
+    pub kind: PatternKind,
+    pub members: Vec<String>,
+    // Density for clusters, member count over graph size for cycles, dependent share for hubs
+    pub strength: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ComponentGraph {
+    components: BTreeMap<String, Component>,
+    // (dependent, dependency)
+    edges: BTreeSet<(String, String)>,
+}
+
+impl ComponentGraph {
+    pub fn new() -> Self {
+        ComponentGraph::default()
+    }
+
+    pub fn add_component(&mut self, id: &str, kind: &str) {
+        self.components.entry(id.to_string()).or_insert_with(|| Component {
+            id: id.to_string(),
+            kind: kind.to_string(),
+            health: HealthState::Healthy,
+            activity: 0.0,
+        });
+    }
+
+    // `dependent` needs `dependency`; unknown ids are added with kind "unknown"
+    pub fn depend(&mut self, dependent: &str, dependency: &str) {
+        if dependent == dependency {
+            return;
+        }
+        self.add_component(dependent, "unknown");
+        self.add_component(dependency, "unknown");
+        self.edges.insert((dependent.to_string(), dependency.to_string()));
+    }
+
+    pub fn remove_component(&mut self, id: &str) -> Option<Component> {
+        self.edges.retain(|(from, to)| from != id && to != id);
+        self.components.remove(id)
+    }
+
+    pub fn set_health(&mut self, id: &str, health: HealthState) {
+        if let Some(component) = self.components.get_mut(id) {
+            component.health = health;
+        }
+    }
+
+    pub fn set_activity(&mut self, id: &str, activity: f32) {
+        if let Some(component) = self.components.get_mut(id) {
+            component.activity = activity.clamp(0.0, 1.0);
+        }
+    }
+
+    // Copy health from the registry for components named after a subsystem
+    pub fn sync_health(&mut self, registry: &HealthRegistry) {
+        for (subsystem, state) in registry.states() {
+            self.set_health(subsystem, state.clone());
+        }
+    }
+
+    pub fn component(&self, id: &str) -> Option<&Component> {
+        self.components.get(id)
+    }
+
+    pub fn components(&self) -> impl Iterator<Item = &Component> {
+        self.components.values()
+    }
+
+    pub fn dependencies<'a>(&'a self, id: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.edges.iter().filter(move |(from, _)| from == id).map(|(_, to)| to.as_str())
+    }
+
+    pub fn dependents<'a>(&'a self, id: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.edges.iter().filter(move |(_, to)| to == id).map(|(from, _)| from.as_str())
+    }
+
+    pub fn detect_patterns(&self) -> Vec<EmergentPattern> {
+        let mut patterns = Vec::new();
+        for (i, members) in self.clusters().into_iter().enumerate() {
+            let strength = self.density(&members);
+            patterns.push(EmergentPattern { id: format!("cluster-{}", i), kind: PatternKind::Cluster, members, strength });
+        }
+        let total = self.components.len().max(1) as f32;
+        for (i, members) in self.cycles().into_iter().enumerate() {
+            let strength = members.len() as f32 / total;
+            patterns.push(EmergentPattern { id: format!("cycle-{}", i), kind: PatternKind::Cycle, members, strength });
+        }
+        let mean = self.edges.len() as f32 / total;
+        let threshold = (MIN_HUB_DEPENDENTS as f32).max(mean * 2.0);
+        for id in self.components.keys() {
+            let dependents = self.dependents(id).count();
+            if dependents as f32 >= threshold {
+                patterns.push(EmergentPattern {
+                    id: format!("hub-{}", id),
+                    kind: PatternKind::Hub,
+                    members: vec![id.clone()],
+                    strength: dependents as f32 / (total - 1.0).max(1.0),
+                });
+            }
+        }
+        patterns
+    }
+
+    pub fn to_json(&self, patterns: &[EmergentPattern]) -> Value {
+        let nodes: Vec<Value> = self
+            .components
+            .values()
+            .map(|component| {
+                let member_of: Vec<&str> = patterns
+                    .iter()
+                    .filter(|pattern| pattern.members.contains(&component.id))
+                    .map(|pattern| pattern.id.as_str())
+                    .collect();
+                json!({
+                    "id": component.id,
+                    "kind": component.kind,
+                    "health": component.health.label(),
+                    "health_reason": health_reason(&component.health),
+                    "color": health_color(&component.health),
+                    "activity": component.activity,
+                    "patterns": member_of,
+                })
+            })
+            .collect();
+        let edges: Vec<Value> = self
+            .edges
+            .iter()
+            .map(|(from, to)| json!({ "from": from, "to": to, "cycle": in_cycle(patterns, from, to) }))
+            .collect();
+        json!({ "nodes": nodes, "edges": edges, "patterns": patterns })
+    }
+
+    // Clusters become DOT subgraphs; DOT cannot nest overlapping ones, so a component is drawn in
+    // the first cluster that contains it
+    pub fn to_dot(&self, patterns: &[EmergentPattern]) -> String {
+        let mut dot = String::new();
+        let _ = writeln!(dot, "digraph components {{");
+        let _ = writeln!(dot, "  rankdir=LR;");
+        let _ = writeln!(dot, "  node [shape=box, style=filled, fontname=\"monospace\", fontsize=10];");
+
+        let hubs: BTreeSet<&str> = patterns
+            .iter()
+            .filter(|pattern| pattern.kind == PatternKind::Hub)
+            .flat_map(|pattern| pattern.members.iter().map(String::as_str))
+            .collect();
+        let mut placed = BTreeSet::new();
+        for pattern in patterns.iter().filter(|pattern| pattern.kind == PatternKind::Cluster) {
+            let _ = writeln!(dot, "  subgraph \"cluster_{}\" {{", escape(&pattern.id));
+            let _ = writeln!(dot, "    label=\"{} ({:.2})\"; style=rounded; color=steelblue;", escape(&pattern.id), pattern.strength);
+            for member in &pattern.members {
+                if placed.insert(member.as_str()) {
+                    if let Some(component) = self.components.get(member) {
+                        let _ = writeln!(dot, "    {}", node_line(component, hubs.contains(member.as_str())));
+                    }
+                }
+            }
+            let _ = writeln!(dot, "  }}");
+        }
+        for component in self.components.values().filter(|component| !placed.contains(component.id.as_str())) {
+            let _ = writeln!(dot, "  {}", node_line(component, hubs.contains(component.id.as_str())));
+        }
+        for (from, to) in &self.edges {
+            let style = if in_cycle(patterns, from, to) { " [color=red, penwidth=2]" } else { "" };
+            let _ = writeln!(dot, "  \"{}\" -> \"{}\"{};", escape(from), escape(to), style);
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn density(&self, members: &[String]) -> f32 {
+        let n = members.len();
+        if n < 2 {
+            return 0.0;
+        }
+        let internal = self.edges.iter().filter(|(from, to)| members.contains(from) && members.contains(to)).count();
+        (internal as f32 / (n * (n - 1)) as f32 * 2.0).min(1.0)
+    }
+
+    // Undirected neighbours, ignoring edge direction
+    fn neighbours(&self) -> BTreeMap<&str, BTreeSet<&str>> {
+        let mut neighbours: BTreeMap<&str, BTreeSet<&str>> = self.components.keys().map(|id| (id.as_str(), BTreeSet::new())).collect();
+        for (from, to) in &self.edges {
+            neighbours.entry(from).or_default().insert(to);
+            neighbours.entry(to).or_default().insert(from);
+        }
+        neighbours
+    }
+
+    // Label propagation over the undirected graph, then keep the dense groups. Ties go to the
+    // smallest label so the result is stable between runs.
+    fn clusters(&self) -> Vec<Vec<String>> {
+        let neighbours = self.neighbours();
+        let mut labels: BTreeMap<&str, &str> = neighbours.keys().map(|id| (*id, *id)).collect();
+        for _ in 0..20 {
+            let mut changed = false;
+            for (id, around) in &neighbours {
+                let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+                for neighbour in around {
+                    *counts.entry(labels[neighbour]).or_default() += 1;
+                }
+                let Some(best) = counts.values().max().copied() else { continue };
+                let current = labels[id];
+                if counts.get(current) == Some(&best) {
+                    continue;
+                }
+                if let Some((label, _)) = counts.into_iter().find(|(_, count)| *count == best) {
+                    labels.insert(id, label);
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        let mut groups: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+        for (id, label) in labels {
+            groups.entry(label).or_default().push(id.to_string());
+        }
+        groups
+            .into_values()
+            .filter(|members| members.len() >= MIN_CLUSTER && self.density(members) >= MIN_DENSITY)
+            .collect()
+    }
+
+    // Strongly connected components with more than one member (Tarjan)
+    fn cycles(&self) -> Vec<Vec<String>> {
+        struct Tarjan<'a> {
+            graph: &'a ComponentGraph,
+            index: BTreeMap<&'a str, usize>,
+            low: BTreeMap<&'a str, usize>,
+            stack: Vec<&'a str>,
+            on_stack: BTreeSet<&'a str>,
+            found: Vec<Vec<String>>,
+        }
+
+        impl<'a> Tarjan<'a> {
+            fn visit(&mut self, id: &'a str) {
+                let order = self.index.len();
+                self.index.insert(id, order);
+                self.low.insert(id, order);
+                self.stack.push(id);
+                self.on_stack.insert(id);
+                for next in self.graph.dependencies(id) {
+                    if !self.index.contains_key(next) {
+                        self.visit(next);
+                        let low = self.low[id].min(self.low[next]);
+                        self.low.insert(id, low);
+                    } else if self.on_stack.contains(next) {
+                        let low = self.low[id].min(self.index[next]);
+                        self.low.insert(id, low);
+                    }
+                }
+                if self.low[id] == self.index[id] {
+                    let mut members = Vec::new();
+                    while let Some(member) = self.stack.pop() {
+                        self.on_stack.remove(member);
+                        members.push(member.to_string());
+                        if member == id {
+                            break;
+                        }
+                    }
+                    if members.len() > 1 {
+                        members.sort();
+                        self.found.push(members);
+                    }
+                }
+            }
+        }
+
+        let mut tarjan = Tarjan {
+            graph: self,
+            index: BTreeMap::new(),
+            low: BTreeMap::new(),
+            stack: Vec::new(),
+            on_stack: BTreeSet::new(),
+            found: Vec::new(),
+        };
+        for id in self.components.keys() {
+            if !tarjan.index.contains_key(id.as_str()) {
+                tarjan.visit(id);
+            }
+        }
+        tarjan.found.sort();
+        tarjan.found
+    }
+}
+
+fn in_cycle(patterns: &[EmergentPattern], from: &str, to: &str) -> bool {
+    patterns.iter().any(|pattern| {
+        pattern.kind == PatternKind::Cycle
+            && pattern.members.iter().any(|member| member == from)
+            && pattern.members.iter().any(|member| member == to)
+    })
+}
+
+fn node_line(component: &Component, hub: bool) -> String {
+    let mut label = format!("{}\n{}", component.id, component.kind);
+    if let Some(reason) = health_reason(&component.health) {
+        let _ = write!(label, "\n{}", reason);
+    }
+    let outline = if hub { ", peripheries=2, penwidth=2" } else { "" };
+    format!(
+        "\"{}\" [label=\"{}\", fillcolor=\"{}\"{}];",
+        escape(&component.id),
+        escape(&label),
+        health_color(&component.health),
+        outline
+    )
+}
+
+fn health_color(health: &HealthState) -> &'static str {
+    match health {
+        HealthState::Healthy => "palegreen",
+        HealthState::Degraded { .. } => "gold",
+        HealthState::Down { .. } => "tomato",
+    }
+}
+
+fn health_reason(health: &HealthState) -> Option<&str> {
+    match health {
+        HealthState::Healthy => None,
+        HealthState::Degraded { reason } | HealthState::Down { reason } => Some(reason),
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A tight market feedback loop, and a store four unrelated systems lean on
+    fn graph() -> ComponentGraph {
+        let mut graph = ComponentGraph::new();
+        graph.add_component("market", "economy");
+        graph.depend("market", "traders");
+        graph.depend("traders", "prices");
+        graph.depend("prices", "market");
+        graph.depend("market", "prices");
+        for id in ["quests", "factions", "routines", "dialogue"] {
+            graph.depend(id, "store");
+        }
+        graph
+    }
+
+    fn members(patterns: &[EmergentPattern], kind: PatternKind) -> Vec<Vec<&str>> {
+        patterns
+            .iter()
+            .filter(|pattern| pattern.kind == kind)
+            .map(|pattern| pattern.members.iter().map(String::as_str).collect())
+            .collect()
+    }
+
+    #[test]
+    fn clusters_cycles_and_hubs_emerge() {
+        let patterns = graph().detect_patterns();
+        assert_eq!(members(&patterns, PatternKind::Cluster), vec![vec!["market", "prices", "traders"]]);
+        assert_eq!(members(&patterns, PatternKind::Cycle), vec![vec!["market", "prices", "traders"]]);
+        assert_eq!(members(&patterns, PatternKind::Hub), vec![vec!["store"]]);
+        let hub = patterns.iter().find(|pattern| pattern.kind == PatternKind::Hub).unwrap();
+        assert_eq!(hub.strength, 4.0 / 7.0);
+    }
+
+    #[test]
+    fn edges_track_components() {
+        let mut graph = graph();
+        graph.depend("store", "store");
+        assert_eq!(graph.dependencies("store").count(), 0);
+        assert_eq!(graph.component("quests").unwrap().kind, "unknown");
+        assert_eq!(graph.component("market").unwrap().kind, "economy");
+
+        graph.remove_component("store");
+        assert_eq!(graph.dependencies("quests").count(), 0);
+        assert!(members(&graph.detect_patterns(), PatternKind::Hub).is_empty());
+        // Without prices the loop is broken and two components are too few for a cluster
+        graph.remove_component("prices");
+        assert!(graph.detect_patterns().is_empty());
+    }
+
+    #[test]
+    fn json_export_carries_health_and_pattern_membership() {
+        let mut graph = graph();
+        let mut registry = HealthRegistry::new();
+        registry.report_failure("store", &"connection refused");
+        graph.sync_health(&registry);
+        graph.set_activity("market", 3.0);
+        let json = graph.to_json(&graph.detect_patterns());
+
+        let node = |id: &str| json["nodes"].as_array().unwrap().iter().find(|node| node["id"] == id).unwrap().clone();
+        let store = node("store");
+        assert_eq!((&store["health"], &store["color"]), (&json!("degraded"), &json!("gold")));
+        assert_eq!(store["health_reason"], "connection refused");
+        assert_eq!(store["patterns"], json!(["hub-store"]));
+        assert_eq!(node("market")["activity"], 1.0);
+        assert_eq!(node("market")["patterns"], json!(["cluster-0", "cycle-0"]));
+
+        let edges = json["edges"].as_array().unwrap();
+        assert_eq!(edges.iter().filter(|edge| edge["cycle"] == true).count(), 4);
+        assert_eq!(json["patterns"].as_array().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn dot_export_draws_clusters_cycles_and_hubs() {
+        let graph = graph();
+        let dot = graph.to_dot(&graph.detect_patterns());
+        assert!(dot.starts_with("digraph components {"));
+        assert_eq!(dot.matches("subgraph \"cluster_cluster-0\"").count(), 1);
+        assert_eq!(dot.matches("[color=red, penwidth=2]").count(), 4);
+        assert_eq!(dot.matches("peripheries=2").count(), 1);
+        assert!(dot.contains("\"quests\" -> \"store\";"));
+        // Every component is drawn exactly once
+        for component in graph.components() {
+            assert_eq!(dot.matches(&format!("  \"{}\" [label", component.id)).count(), 1, "{}", component.id);
+        }
+    }
+}
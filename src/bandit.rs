@@ -0,0 +1,345 @@
+// Multi-armed bandits for content selection
+//
+// Picking which quest to offer or which hint to show is a bandit problem: try an option, see how
+// the player responds, shift towards what works while still exploring. A Bandit chooses among
+// named arms with epsilon-greedy, UCB1 or Thompson sampling. Statistics are kept per context (e.g.
+// "new_player", "stuck_on_puzzle"; "" for none) in agentdb so they survive restarts.
+//
+// Rewards are expected in 0..1; Thompson sampling treats them as fractional successes.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::agentdb::{AgentDb, AgentDbError};
+use crate::namespace::Namespace;
+use crate::rng::Rng;
+
+const ARMS_TABLE: &str = "bandit_arms";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BanditPolicy {
+    // Explore a random arm with probability `epsilon`, otherwise take the best mean
+    EpsilonGreedy { epsilon: f64 },
+    // Best upper confidence bound; every arm is tried once first
+    Ucb1 { exploration: f64 },
+    // Sample each arm's Beta posterior and take the highest draw
+    Thompson,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ArmStats {
+    pub pulls: u64,
+    pub total_reward: f64,
+}
+
+impl ArmStats {
+    pub fn mean(&self) -> f64 {
+        if self.pulls == 0 {
+            0.0
+        } else {
+            self.total_reward / self.pulls as f64
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Choice {
+    pub bandit: String,
+    pub context: String,
+    pub arm: String,
+}
+
+#[derive(Debug)]
+pub enum BanditError {
+    UnknownArm(String),
+    Storage(AgentDbError),
+    Corrupt(serde_json::Error),
+}
+
+impl fmt::Display for BanditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BanditError::UnknownArm(arm) => write!(f, "unknown bandit arm '{}'", arm),
+            BanditError::Storage(err) => write!(f, "{}", err),
+            BanditError::Corrupt(err) => write!(f, "stored bandit statistics are invalid: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for BanditError {}
+
+impl From<AgentDbError> for BanditError {
+    fn from(err: AgentDbError) -> Self {
+        BanditError::Storage(err)
+    }
+}
+
+impl From<serde_json::Error> for BanditError {
+    fn from(err: serde_json::Error) -> Self {
+        BanditError::Corrupt(err)
+    }
+}
+
+pub struct Bandit {
+    name: String,
+    arms: Vec<String>,
+    policy: BanditPolicy,
+    namespace: Namespace,
+    rng: Rng,
+}
+
+impl Bandit {
+    pub fn new(name: &str, arms: &[&str], policy: BanditPolicy, namespace: Namespace, seed: u64) -> Self {
+        Bandit {
+            name: name.to_string(),
+            arms: arms.iter().map(|a| a.to_string()).collect(),
+            policy,
+            namespace,
+            rng: Rng::new(seed),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn arms(&self) -> &[String] {
+        &self.arms
+    }
+
+    pub fn add_arm(&mut self, arm: &str) {
+        if !self.arms.iter().any(|a| a == arm) {
+            self.arms.push(arm.to_string());
+        }
+    }
+
+    pub fn set_policy(&mut self, policy: BanditPolicy) {
+        self.policy = policy;
+    }
+
+    fn key(&self, context: &str, arm: &str) -> String {
+        format!("{}/{}/{}", self.name, context, arm)
+    }
+
+    // Statistics of every arm in a context; unplayed arms have empty stats
+    pub fn stats(&self, db: &AgentDb, context: &str) -> Result<Vec<(String, ArmStats)>, BanditError> {
+        self.arms
+            .iter()
+            .map(|arm| {
+                let stats = match db.get(&self.namespace, ARMS_TABLE, &self.key(context, arm)) {
+                    Some(value) => serde_json::from_value(value.clone())?,
+                    None => ArmStats::default(),
+                };
+                Ok((arm.clone(), stats))
+            })
+            .collect()
+    }
+
+    // None when the bandit has no arms
+    pub fn choose(&mut self, db: &AgentDb, context: &str) -> Result<Option<Choice>, BanditError> {
+        let stats = self.stats(db, context)?;
+        if stats.is_empty() {
+            return Ok(None);
+        }
+        let index = match self.policy {
+            BanditPolicy::EpsilonGreedy { epsilon } => {
+                if self.rng.chance(epsilon) {
+                    self.rng.below(stats.len())
+                } else {
+                    best(&stats, |s| if s.pulls == 0 { f64::INFINITY } else { s.mean() })
+                }
+            }
+            BanditPolicy::Ucb1 { exploration } => {
+                let total: u64 = stats.iter().map(|(_, s)| s.pulls).sum();
+                let log_total = (total.max(1) as f64).ln();
+                best(&stats, |s| {
+                    if s.pulls == 0 {
+                        f64::INFINITY
+                    } else {
+                        s.mean() + exploration * (2.0 * log_total / s.pulls as f64).sqrt()
+                    }
+                })
+            }
+            BanditPolicy::Thompson => {
+                let draws: Vec<f64> = stats
+                    .iter()
+                    .map(|(_, s)| {
+                        let successes = s.total_reward.clamp(0.0, s.pulls as f64);
+                        sample_beta(&mut self.rng, 1.0 + successes, 1.0 + s.pulls as f64 - successes)
+                    })
+                    .collect();
+                (0..draws.len()).max_by(|a, b| draws[*a].total_cmp(&draws[*b])).unwrap_or(0)
+            }
+        };
+        Ok(Some(Choice {
+            bandit: self.name.clone(),
+            context: context.to_string(),
+            arm: stats[index].0.clone(),
+        }))
+    }
+
+    // Record the outcome of a choice; `value` is clamped to 0..1
+    pub fn reward(&self, db: &mut AgentDb, choice: &Choice, value: f64) -> Result<ArmStats, BanditError> {
+        if !self.arms.contains(&choice.arm) {
+            return Err(BanditError::UnknownArm(choice.arm.clone()));
+        }
+        let key = self.key(&choice.context, &choice.arm);
+        let mut stats: ArmStats = match db.get(&self.namespace, ARMS_TABLE, &key) {
+            Some(value) => serde_json::from_value(value.clone())?,
+            None => ArmStats::default(),
+        };
+        stats.pulls += 1;
+        stats.total_reward += if value.is_finite() { value.clamp(0.0, 1.0) } else { 0.0 };
+        db.put(&self.namespace, ARMS_TABLE, &key, serde_json::to_value(&stats)?)?;
+        Ok(stats)
+    }
+
+    // Forget everything learned in a context
+    pub fn reset(&self, db: &mut AgentDb, context: &str) {
+        for arm in &self.arms {
+            db.delete(&self.namespace, ARMS_TABLE, &self.key(context, arm));
+        }
+    }
+}
+
+// Index of the highest score, first arm on ties
+fn best(stats: &[(String, ArmStats)], score: impl Fn(&ArmStats) -> f64) -> usize {
+    let mut best = 0;
+    let mut best_score = f64::NEG_INFINITY;
+    for (i, (_, s)) in stats.iter().enumerate() {
+        let value = score(s);
+        if value > best_score {
+            best = i;
+            best_score = value;
+        }
+    }
+    best
+}
+
+// Gamma(shape, 1) by Marsaglia-Tsang
+fn sample_gamma(rng: &mut Rng, shape: f64) -> f64 {
+    if shape < 1.0 {
+        let u = rng.next_f64().max(f64::MIN_POSITIVE);
+        return sample_gamma(rng, shape + 1.0) * u.powf(1.0 / shape);
+    }
+    let d = shape - 1.0 / 3.0;
+    let c = 1.0 / (9.0 * d).sqrt();
+    loop {
+        let x = rng.normal();
+        let v = (1.0 + c * x).powi(3);
+        if v <= 0.0 {
+            continue;
+        }
+        let u = rng.next_f64().max(f64::MIN_POSITIVE);
+        if u.ln() < 0.5 * x * x + d - d * v + d * v.ln() {
+            return d * v;
+        }
+    }
+}
+
+fn sample_beta(rng: &mut Rng, alpha: f64, beta: f64) -> f64 {
+    let x = sample_gamma(rng, alpha);
+    let y = sample_gamma(rng, beta);
+    if x + y > 0.0 {
+        x / (x + y)
+    } else {
+        0.5
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const ARMS: [&str; 3] = ["fetch", "escort", "puzzle"];
+
+    // Play `rounds` choices where only `winner` pays out; returns pulls per arm
+    fn play(bandit: &mut Bandit, db: &mut AgentDb, winner: &str, rounds: usize) -> Vec<u64> {
+        for _ in 0..rounds {
+            let choice = bandit.choose(db, "").unwrap().unwrap();
+            let reward = if choice.arm == winner { 1.0 } else { 0.0 };
+            bandit.reward(db, &choice, reward).unwrap();
+        }
+        bandit.stats(db, "").unwrap().into_iter().map(|(_, s)| s.pulls).collect()
+    }
+
+    #[test]
+    fn ucb_tries_every_arm_then_exploits() {
+        let mut db = AgentDb::new();
+        let mut bandit = Bandit::new("quests", &ARMS, BanditPolicy::Ucb1 { exploration: 0.5 }, Namespace::default_namespace(), 1);
+        assert_eq!(play(&mut bandit, &mut db, "puzzle", 3), vec![1, 1, 1]);
+        let pulls = play(&mut bandit, &mut db, "puzzle", 200);
+        assert!(pulls[2] > pulls[0] + pulls[1], "{:?}", pulls);
+    }
+
+    #[test]
+    fn greedy_without_exploration_sticks_with_the_best_mean() {
+        let mut db = AgentDb::new();
+        let policy = BanditPolicy::EpsilonGreedy { epsilon: 0.0 };
+        let mut bandit = Bandit::new("quests", &ARMS, policy, Namespace::default_namespace(), 1);
+        let pulls = play(&mut bandit, &mut db, "escort", 50);
+        // Unplayed arms go first, then every pull goes to the only arm that paid
+        assert_eq!(pulls, vec![1, 48, 1]);
+    }
+
+    #[test]
+    fn thompson_sampling_converges_on_the_winner() {
+        let mut db = AgentDb::new();
+        let mut bandit = Bandit::new("quests", &ARMS, BanditPolicy::Thompson, Namespace::default_namespace(), 7);
+        let pulls = play(&mut bandit, &mut db, "fetch", 300);
+        assert!(pulls[0] > 250, "{:?}", pulls);
+    }
+
+    #[test]
+    fn contexts_are_independent_and_resettable() {
+        let mut db = AgentDb::new();
+        let mut bandit = Bandit::new("hints", &["text", "arrow"], BanditPolicy::Thompson, Namespace::default_namespace(), 3);
+        let choice = Choice { bandit: "hints".to_string(), context: "stuck".to_string(), arm: "arrow".to_string() };
+        bandit.reward(&mut db, &choice, 0.5).unwrap();
+        // Rewards are clamped to 0..1 and non-finite rewards count as a miss
+        bandit.reward(&mut db, &choice, 7.0).unwrap();
+        let stats = bandit.reward(&mut db, &choice, f64::NAN).unwrap();
+        assert_eq!(stats, ArmStats { pulls: 3, total_reward: 1.5 });
+        assert_eq!(stats.mean(), 0.5);
+        assert!(bandit.stats(&db, "").unwrap().iter().all(|(_, s)| s.pulls == 0));
+
+        bandit.reset(&mut db, "stuck");
+        assert!(bandit.stats(&db, "stuck").unwrap().iter().all(|(_, s)| *s == ArmStats::default()));
+
+        // Arms added later start with empty stats
+        bandit.add_arm("glow");
+        bandit.add_arm("glow");
+        assert_eq!(bandit.arms().len(), 3);
+        assert_eq!(bandit.choose(&db, "stuck").unwrap().unwrap().context, "stuck");
+    }
+
+    #[test]
+    fn bad_arms_and_storage_are_reported() {
+        let mut db = AgentDb::new();
+        let namespace = Namespace::default_namespace();
+        let mut empty = Bandit::new("none", &[], BanditPolicy::Thompson, namespace.clone(), 1);
+        assert!(empty.choose(&db, "").unwrap().is_none());
+
+        let mut bandit = Bandit::new("quests", &ARMS, BanditPolicy::Ucb1 { exploration: 1.0 }, namespace.clone(), 1);
+        let stray = Choice { bandit: "quests".to_string(), context: String::new(), arm: "raid".to_string() };
+        assert!(matches!(bandit.reward(&mut db, &stray, 1.0), Err(BanditError::UnknownArm(arm)) if arm == "raid"));
+
+        db.put(&namespace, ARMS_TABLE, "quests//escort", json!("not stats")).unwrap();
+        assert!(matches!(bandit.stats(&db, ""), Err(BanditError::Corrupt(_))));
+        assert!(matches!(bandit.choose(&db, ""), Err(BanditError::Corrupt(_))));
+    }
+
+    #[test]
+    fn beta_samples_track_the_posterior_mean() {
+        let mut rng = Rng::new(11);
+        for (alpha, beta) in [(1.0, 1.0), (9.0, 1.0), (0.5, 4.0)] {
+            let draws: Vec<f64> = (0..4000).map(|_| sample_beta(&mut rng, alpha, beta)).collect();
+            assert!(draws.iter().all(|x| (0.0..=1.0).contains(x)));
+            let mean = draws.iter().sum::<f64>() / draws.len() as f64;
+            assert!((mean - alpha / (alpha + beta)).abs() < 0.03, "{} {} {}", alpha, beta, mean);
+        }
+    }
+}
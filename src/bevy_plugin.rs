@@ -0,0 +1,249 @@
+// Bevy integration
+//
+// ArcadiaPlugin (behind the `bevy` feature) exposes the ARCADIA systems to a Bevy app:
+//
+// - the vector index, emotion system, per-entity blackboards and event bus as resources;
+// - entities tagged with ArcadiaAgent get their transform mirrored into their blackboard, and
+//   any component implementing BlackboardSync (registered with `sync_to_blackboard`) writes its
+//   own keys there when it changes;
+// - agents with an AgentWorldState component get their blackboard keys under "state." projected
+//   into a GOAP WorldState every frame, ready for planning;
+// - ToArcadia Bevy events are published on the ARCADIA bus, and ARCADIA events are re-sent as
+//   ArcadiaEvent Bevy events. Events that came from Bevy are not echoed back.
+//
+// Syncing runs in PreUpdate, so game systems in Update see this frame's projections; the event
+// bridge runs in PostUpdate.
+
+use std::sync::{Mutex, MutexGuard};
+
+use bevy::prelude::*;
+use serde_json::Value;
+
+use crate::ai::blackboard::{Blackboard, Blackboards, Projection};
+use crate::ai::coordination::BlackboardValue;
+use crate::ai::goap::WorldState;
+use crate::emotion::EmotionAdaptiveExperiences;
+use crate::events::{self as arcadia_events, EventBus, SubscriptionId};
+use crate::vector_index::{VectorIndex, VectorIndexConfig};
+
+// Source name on ARCADIA events that came from Bevy
+pub const BEVY_SOURCE: &str = "bevy";
+
+// Blackboard keys under this prefix are projected into AgentWorldState
+pub const STATE_PREFIX: &str = "state.";
+
+// The index holds a `Send`-only remote store, so it is kept behind a mutex to be shareable
+#[derive(Resource)]
+pub struct ArcadiaVectorIndex(pub Mutex<VectorIndex>);
+
+impl ArcadiaVectorIndex {
+    pub fn lock(&self) -> MutexGuard<'_, VectorIndex> {
+        self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+#[derive(Resource, Default)]
+pub struct ArcadiaEmotion(pub EmotionAdaptiveExperiences);
+
+#[derive(Resource, Default)]
+pub struct ArcadiaBlackboards(pub Blackboards);
+
+#[derive(Resource)]
+pub struct ArcadiaEvents {
+    pub bus: EventBus,
+    // Everything forwarded to Bevy
+    outbound: SubscriptionId,
+}
+
+// An ARCADIA event, re-sent inside Bevy
+#[derive(Event, Debug, Clone)]
+pub struct ArcadiaEvent(pub arcadia_events::Event);
+
+// Sent by game systems to publish on the ARCADIA bus
+#[derive(Event, Debug, Clone)]
+pub struct ToArcadia {
+    pub topic: String,
+    pub payload: Value,
+}
+
+// Marks an entity as an ARCADIA agent; `id` keys its blackboard
+#[derive(Component, Debug, Clone)]
+pub struct ArcadiaAgent {
+    pub id: String,
+}
+
+// GOAP world state projected from the agent's blackboard
+#[derive(Component)]
+pub struct AgentWorldState {
+    pub state: WorldState,
+    projection: Projection,
+}
+
+impl Default for AgentWorldState {
+    fn default() -> Self {
+        AgentWorldState { state: WorldState::new(), projection: Projection::new(STATE_PREFIX) }
+    }
+}
+
+// A game component that writes into its agent's blackboard when it changes
+pub trait BlackboardSync: Component {
+    fn write(&self, board: &mut Blackboard);
+}
+
+pub struct ArcadiaPlugin {
+    pub vector_index: VectorIndexConfig,
+    pub event_capacity: usize,
+}
+
+impl ArcadiaPlugin {
+    pub fn new(vector_index: VectorIndexConfig) -> Self {
+        ArcadiaPlugin { vector_index, event_capacity: 1024 }
+    }
+}
+
+impl Plugin for ArcadiaPlugin {
+    fn build(&self, app: &mut App) {
+        let mut bus = EventBus::new(self.event_capacity);
+        let outbound = bus.subscribe("");
+        app.insert_resource(ArcadiaVectorIndex(Mutex::new(VectorIndex::new(self.vector_index.clone()))))
+            .init_resource::<ArcadiaEmotion>()
+            .init_resource::<ArcadiaBlackboards>()
+            .insert_resource(ArcadiaEvents { bus, outbound })
+            .add_event::<ArcadiaEvent>()
+            .add_event::<ToArcadia>()
+            .add_systems(PreUpdate, (sync_transforms, project_world_state).chain())
+            .add_systems(PostUpdate, (forward_to_arcadia, forward_to_bevy).chain());
+    }
+}
+
+pub trait ArcadiaAppExt {
+    // Mirror a component into agents' blackboards whenever it changes
+    fn sync_to_blackboard<T: BlackboardSync>(&mut self) -> &mut Self;
+}
+
+impl ArcadiaAppExt for App {
+    fn sync_to_blackboard<T: BlackboardSync>(&mut self) -> &mut Self {
+        self.add_systems(PreUpdate, sync_component::<T>.before(project_world_state))
+    }
+}
+
+fn sync_transforms(
+    agents: Query<(&ArcadiaAgent, &GlobalTransform), Changed<GlobalTransform>>,
+    mut boards: ResMut<ArcadiaBlackboards>,
+) {
+    for (agent, transform) in &agents {
+        let position = transform.translation().to_array();
+        boards.0.entity(&agent.id).set("position", BlackboardValue::Position(position), BEVY_SOURCE);
+    }
+}
+
+fn sync_component<T: BlackboardSync>(
+    agents: Query<(&ArcadiaAgent, &T), Changed<T>>,
+    mut boards: ResMut<ArcadiaBlackboards>,
+) {
+    for (agent, component) in &agents {
+        component.write(boards.0.entity(&agent.id));
+    }
+}
+
+fn project_world_state(mut agents: Query<(&ArcadiaAgent, &mut AgentWorldState)>, mut boards: ResMut<ArcadiaBlackboards>) {
+    for (agent, mut world) in &mut agents {
+        let AgentWorldState { state, projection } = &mut *world;
+        projection.sync(boards.0.entity(&agent.id), state);
+    }
+}
+
+fn forward_to_arcadia(mut incoming: EventReader<ToArcadia>, mut events: ResMut<ArcadiaEvents>) {
+    for event in incoming.read() {
+        events.bus.emit(&event.topic, BEVY_SOURCE, event.payload.clone());
+    }
+}
+
+fn forward_to_bevy(mut events: ResMut<ArcadiaEvents>, mut outgoing: EventWriter<ArcadiaEvent>) {
+    let outbound = events.outbound;
+    for event in events.bus.drain(outbound) {
+        if event.source != BEVY_SOURCE {
+            outgoing.send(ArcadiaEvent(event));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::ai::goap::StateValue;
+
+    #[derive(Component)]
+    struct Alert(bool);
+
+    impl BlackboardSync for Alert {
+        fn write(&self, board: &mut Blackboard) {
+            board.set("state.alert", BlackboardValue::Bool(self.0), BEVY_SOURCE);
+        }
+    }
+
+    #[derive(Resource, Default)]
+    struct Seen(Vec<String>);
+
+    fn collect(mut events: EventReader<ArcadiaEvent>, mut seen: ResMut<Seen>) {
+        seen.0.extend(events.read().map(|event| event.0.topic.clone()));
+    }
+
+    // Headless app: no window or renderer, just the plugin's schedules
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins(ArcadiaPlugin::new(VectorIndexConfig {
+            url: String::new(),
+            api_key: String::new(),
+            default_ttl_secs: None,
+            collection_ttl_secs: Default::default(),
+        }))
+        .sync_to_blackboard::<Alert>()
+        .init_resource::<Seen>()
+        .add_systems(Update, collect);
+        app
+    }
+
+    #[test]
+    fn agent_components_reach_the_blackboard_and_world_state() {
+        let mut app = app();
+        let guard = app
+            .world_mut()
+            .spawn((
+                ArcadiaAgent { id: "guard".to_string() },
+                GlobalTransform::from_translation(Vec3::new(1.0, 2.0, 3.0)),
+                Alert(true),
+                AgentWorldState::default(),
+            ))
+            .id();
+        app.update();
+
+        let board = app.world().resource::<ArcadiaBlackboards>().0.get("guard").unwrap();
+        assert_eq!(board.get_position("position"), Some([1.0, 2.0, 3.0]));
+        let world = &app.world().get::<AgentWorldState>(guard).unwrap().state;
+        assert_eq!(world.get("alert"), Some(&StateValue::Bool(true)));
+
+        app.world_mut().get_mut::<Alert>(guard).unwrap().0 = false;
+        app.update();
+        let world = &app.world().get::<AgentWorldState>(guard).unwrap().state;
+        assert_eq!(world.get("alert"), Some(&StateValue::Bool(false)));
+    }
+
+    #[test]
+    fn events_cross_both_ways_without_echo() {
+        let mut app = app();
+        let player_events = app.world_mut().resource_mut::<ArcadiaEvents>().bus.subscribe("player.");
+        app.world_mut().resource_mut::<ArcadiaEvents>().bus.emit("quest.completed", "director", json!({}));
+        app.world_mut().send_event(ToArcadia { topic: "player.hit".to_string(), payload: json!({ "damage": 4 }) });
+        app.update();
+        app.update();
+
+        let received = app.world_mut().resource_mut::<ArcadiaEvents>().bus.drain(player_events);
+        assert_eq!(received.len(), 1);
+        assert_eq!((received[0].source.as_str(), &received[0].payload), (BEVY_SOURCE, &json!({ "damage": 4 })));
+        // Only the event raised on the ARCADIA side is re-sent into Bevy
+        assert_eq!(app.world().resource::<Seen>().0, vec!["quest.completed".to_string()]);
+    }
+}
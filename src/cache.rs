@@ -0,0 +1,140 @@
+// Namespaced LRU cache
+//
+// Used for embeddings, search results and generated content. Keys are qualified with their
+// namespace so tenants cannot collide, and each namespace can be capped separately.
+
+use std::collections::HashMap;
+
+use crate::namespace::{Namespace, NamespaceQuota};
+
+// Per-entry bookkeeping (hash slot, clock, string header) in memory estimates
+const ENTRY_OVERHEAD: usize = 64;
+
+struct CacheEntry<V> {
+    value: V,
+    last_used: u64,
+}
+
+pub struct Cache<V> {
+    capacity: usize,
+    entries: HashMap<String, CacheEntry<V>>,
+    quotas: HashMap<Namespace, NamespaceQuota>,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+}
+
+impl<V: Clone> Cache<V> {
+    pub fn new(capacity: usize) -> Self {
+        Cache {
+            capacity: capacity.max(1),
+            entries: HashMap::new(),
+            quotas: HashMap::new(),
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn set_quota(&mut self, namespace: &Namespace, quota: NamespaceQuota) {
+        self.quotas.insert(namespace.clone(), quota);
+    }
+
+    pub fn get(&mut self, namespace: &Namespace, key: &str) -> Option<V> {
+        self.clock += 1;
+        match self.entries.get_mut(&namespace.qualify(key)) {
+            Some(entry) => {
+                entry.last_used = self.clock;
+                self.hits += 1;
+                Some(entry.value.clone())
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    // Insert a value, evicting the least recently used entry of the namespace (when over its
+    // quota) or of the whole cache (when over capacity)
+    pub fn insert(&mut self, namespace: &Namespace, key: &str, value: V) {
+        self.clock += 1;
+        let qualified = namespace.qualify(key);
+        if !self.entries.contains_key(&qualified) {
+            if let Some(limit) = self.quotas.get(namespace).and_then(|q| q.max_cache_entries) {
+                if self.namespace_len(namespace) >= limit {
+                    self.evict_lru(Some(namespace));
+                }
+            }
+            if self.entries.len() >= self.capacity {
+                self.evict_lru(None);
+            }
+        }
+        self.entries.insert(qualified, CacheEntry { value, last_used: self.clock });
+    }
+
+    pub fn remove(&mut self, namespace: &Namespace, key: &str) -> Option<V> {
+        self.entries.remove(&namespace.qualify(key)).map(|e| e.value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn namespace_len(&self, namespace: &Namespace) -> usize {
+        self.entries.keys().filter(|k| namespace.owns(k)).count()
+    }
+
+    pub fn hit_rate(&self) -> f32 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f32 / total as f32
+        }
+    }
+
+    pub fn purge_namespace(&mut self, namespace: &Namespace) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|key, _| !namespace.owns(key));
+        before - self.entries.len()
+    }
+
+    // Approximate memory held, given the size of one value
+    pub fn estimated_bytes(&self, value_bytes: usize) -> usize {
+        self.entries.keys().map(|k| ENTRY_OVERHEAD + k.len() + value_bytes).sum()
+    }
+
+    // Evict least recently used entries until about `bytes` are freed; returns the entries removed
+    pub fn shrink_by(&mut self, bytes: usize, value_bytes: usize) -> usize {
+        let mut by_age: Vec<(u64, String)> = self.entries.iter().map(|(k, e)| (e.last_used, k.clone())).collect();
+        by_age.sort();
+        let mut freed = 0;
+        let mut removed = 0;
+        for (_, key) in by_age {
+            if freed >= bytes {
+                break;
+            }
+            self.entries.remove(&key);
+            freed += ENTRY_OVERHEAD + key.len() + value_bytes;
+            removed += 1;
+        }
+        removed
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn evict_lru(&mut self, namespace: Option<&Namespace>) {
+        let oldest = self
+            .entries
+            .iter()
+            .filter(|(key, _)| namespace.is_none_or(|ns| ns.owns(key)))
+            .min_by_key(|(_, entry)| entry.last_used)
+            .map(|(key, _)| key.clone());
+        if let Some(key) = oldest {
+            self.entries.remove(&key);
+        }
+    }
+}
@@ -0,0 +1,227 @@
+// Build info and capability reporting
+//
+// What this build of the engine can do, in one introspectable value: version, enabled cargo
+// features, target (native or WASM), SIMD instruction sets detected at runtime, which vector
+// index backend a config points at, and the schema versions of persisted formats. Tools show it
+// and bug reports attach it, so nobody has to guess what the reporter was running.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::archive::{VECTOR_ARCHIVE_FORMAT, VECTOR_ARCHIVE_VERSION};
+use crate::player_model::{PLAYER_MODEL_FORMAT, PLAYER_MODEL_VERSION};
+use crate::vector_index::VectorIndexConfig;
+use crate::versioning::{
+    AGENTDB_FORMAT, AGENTDB_VERSION, SAVE_FORMAT, SAVE_VERSION, VECTOR_SNAPSHOT_FORMAT, VECTOR_SNAPSHOT_VERSION,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VectorBackend {
+    // No URL configured: everything stays in process memory
+    InMemory,
+    Qdrant,
+    Pgvector,
+    Unknown,
+}
+
+impl VectorBackend {
+    // Inferred from the configured URL scheme
+    pub fn from_url(url: &str) -> Self {
+        let url = url.trim().to_ascii_lowercase();
+        if url.is_empty() || url.starts_with("memory:") {
+            VectorBackend::InMemory
+        } else if url.starts_with("postgres://") || url.starts_with("postgresql://") {
+            VectorBackend::Pgvector
+        } else if url.starts_with("http://") || url.starts_with("https://") || url.starts_with("qdrant://") {
+            VectorBackend::Qdrant
+        } else {
+            VectorBackend::Unknown
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Capabilities {
+    pub version: &'static str,
+    pub features: Vec<&'static str>,
+    pub target_arch: &'static str,
+    pub target_os: &'static str,
+    pub wasm: bool,
+    pub debug_build: bool,
+    // Instruction sets available on this machine, detected when the report was made
+    pub simd: Vec<&'static str>,
+    pub vector_backend: VectorBackend,
+    // Persisted format -> current schema version
+    pub formats: BTreeMap<&'static str, u32>,
+}
+
+// Report for this build, assuming the default (in-memory) vector backend
+pub fn capabilities() -> Capabilities {
+    Capabilities {
+        version: env!("CARGO_PKG_VERSION"),
+        features: enabled_features(),
+        target_arch: std::env::consts::ARCH,
+        target_os: std::env::consts::OS,
+        wasm: cfg!(target_family = "wasm"),
+        debug_build: cfg!(debug_assertions),
+        simd: detect_simd(),
+        vector_backend: VectorBackend::InMemory,
+        formats: BTreeMap::from([
+            (AGENTDB_FORMAT, AGENTDB_VERSION),
+            (VECTOR_SNAPSHOT_FORMAT, VECTOR_SNAPSHOT_VERSION),
+            (VECTOR_ARCHIVE_FORMAT, VECTOR_ARCHIVE_VERSION),
+            (SAVE_FORMAT, SAVE_VERSION),
+            (PLAYER_MODEL_FORMAT, PLAYER_MODEL_VERSION),
+        ]),
+    }
+}
+
+// Report including the backend a vector index config points at
+pub fn capabilities_for(config: &VectorIndexConfig) -> Capabilities {
+    Capabilities { vector_backend: VectorBackend::from_url(&config.url), ..capabilities() }
+}
+
+impl Capabilities {
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.contains(&feature)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+fn enabled_features() -> Vec<&'static str> {
+    let mut features = Vec::new();
+    if cfg!(feature = "deterministic") {
+        features.push("deterministic");
+    }
+    if cfg!(feature = "gpu") {
+        features.push("gpu");
+    }
+    if cfg!(feature = "local-embeddings") {
+        features.push("local-embeddings");
+    }
+    if cfg!(feature = "bevy") {
+        features.push("bevy");
+    }
+    if cfg!(feature = "debug-server") {
+        features.push("debug-server");
+    }
+    if cfg!(feature = "godot") {
+        features.push("godot");
+    }
+    if cfg!(feature = "python") {
+        features.push("python");
+    }
+    features
+}
+
+#[allow(unused_mut)]
+fn detect_simd() -> Vec<&'static str> {
+    let mut found = Vec::new();
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        if std::arch::is_x86_feature_detected!("sse2") {
+            found.push("sse2");
+        }
+        if std::arch::is_x86_feature_detected!("sse4.1") {
+            found.push("sse4.1");
+        }
+        if std::arch::is_x86_feature_detected!("avx2") {
+            found.push("avx2");
+        }
+        if std::arch::is_x86_feature_detected!("fma") {
+            found.push("fma");
+        }
+        if std::arch::is_x86_feature_detected!("avx512f") {
+            found.push("avx512f");
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        if std::arch::is_aarch64_feature_detected!("neon") {
+            found.push("neon");
+        }
+    }
+    #[cfg(all(target_family = "wasm", target_feature = "simd128"))]
+    found.push("simd128");
+    found
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "arcadia {}{}", self.version, if self.debug_build { " (debug)" } else { "" })?;
+        writeln!(f, "target: {}-{}{}", self.target_arch, self.target_os, if self.wasm { " (wasm)" } else { "" })?;
+        writeln!(f, "features: {}", list(&self.features))?;
+        writeln!(f, "simd: {}", list(&self.simd))?;
+        writeln!(f, "vector backend: {:?}", self.vector_backend)?;
+        let formats: Vec<String> = self.formats.iter().map(|(name, version)| format!("{} v{}", name, version)).collect();
+        writeln!(f, "formats: {}", formats.join(", "))
+    }
+}
+
+fn list(items: &[&str]) -> String {
+    if items.is_empty() {
+        "none".to_string()
+    } else {
+        items.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backend_is_inferred_from_the_url_scheme() {
+        for (url, backend) in [
+            ("", VectorBackend::InMemory),
+            ("memory://local", VectorBackend::InMemory),
+            ("  HTTPS://qdrant.internal:6333 ", VectorBackend::Qdrant),
+            ("qdrant://localhost", VectorBackend::Qdrant),
+            ("postgresql://db/vectors", VectorBackend::Pgvector),
+            ("postgres://db/vectors", VectorBackend::Pgvector),
+            ("redis://cache", VectorBackend::Unknown),
+        ] {
+            assert_eq!(VectorBackend::from_url(url), backend, "{:?}", url);
+        }
+    }
+
+    #[test]
+    fn report_matches_the_build() {
+        let report = capabilities();
+        assert_eq!(report.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(report.has_feature("deterministic"), cfg!(feature = "deterministic"));
+        assert!(!report.has_feature("no-such-feature"));
+        assert_eq!(report.formats.get(SAVE_FORMAT), Some(&SAVE_VERSION));
+        assert_eq!(report.formats.len(), 5);
+        #[cfg(target_arch = "x86_64")]
+        assert!(report.simd.contains(&"sse2"));
+
+        let config = VectorIndexConfig {
+            url: "http://localhost:6333".to_string(),
+            api_key: String::new(),
+            default_ttl_secs: None,
+            collection_ttl_secs: Default::default(),
+        };
+        assert_eq!(capabilities_for(&config).vector_backend, VectorBackend::Qdrant);
+    }
+
+    #[test]
+    fn report_renders_as_text_and_json() {
+        let mut report = capabilities();
+        report.features.clear();
+        let text = report.to_string();
+        assert!(text.starts_with(&format!("arcadia {}", report.version)));
+        assert!(text.contains("features: none\n"));
+        assert!(text.contains(&format!("{} v{}", AGENTDB_FORMAT, AGENTDB_VERSION)));
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["vector_backend"], "in_memory");
+        assert_eq!(json["formats"][PLAYER_MODEL_FORMAT], PLAYER_MODEL_VERSION);
+    }
+}
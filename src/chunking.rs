@@ -0,0 +1,222 @@
+// Text chunking for long documents
+//
+// Lore documents are too long to embed whole and a single vector for a whole book says little
+// about any one page. Text is split on word boundaries into chunks that fit a token budget,
+// preferring to break after a sentence or paragraph, with optional overlap so facts spanning a
+// boundary land in both chunks. Hierarchical chunking first cuts large parent sections and then
+// small child chunks inside them: children are embedded for precise matching, the parent is what
+// gets handed to the LLM as context.
+
+use crate::cost::estimate_tokens;
+
+#[derive(Debug, Clone)]
+pub struct ChunkConfig {
+    pub max_tokens: usize,
+    // Tokens repeated from the end of one chunk at the start of the next
+    pub overlap_tokens: usize,
+    // Parent section size; None means each chunk is its own parent
+    pub parent_max_tokens: Option<usize>,
+}
+
+impl Default for ChunkConfig {
+    fn default() -> Self {
+        ChunkConfig { max_tokens: 128, overlap_tokens: 16, parent_max_tokens: Some(512) }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub index: usize,
+    pub text: String,
+    // Byte range in the source text
+    pub start: usize,
+    pub end: usize,
+    pub tokens: u64,
+    // Index of the parent section, for child chunks
+    pub parent: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ChunkedDocument {
+    pub parents: Vec<Chunk>,
+    pub children: Vec<Chunk>,
+}
+
+impl ChunkedDocument {
+    pub fn parent_of(&self, child: &Chunk) -> Option<&Chunk> {
+        self.parents.get(child.parent?)
+    }
+}
+
+// Byte spans of the words in `text`
+fn words(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_whitespace(), start) {
+            (true, Some(s)) => {
+                spans.push((s, i));
+                start = None;
+            }
+            (false, None) => start = Some(i),
+            _ => {}
+        }
+    }
+    if let Some(s) = start {
+        spans.push((s, text.len()));
+    }
+    spans
+}
+
+// A good place to end a chunk: after a sentence, or before a blank line
+fn is_break(text: &str, word: (usize, usize)) -> bool {
+    text[..word.1].ends_with(['.', '!', '?', '"', ':']) || text[word.1..].starts_with("\n\n")
+}
+
+// Split into chunks of at most `max_tokens` (a single oversized word still gets its own chunk)
+pub fn split(text: &str, max_tokens: usize, overlap_tokens: usize) -> Vec<Chunk> {
+    let words = words(text);
+    let max_tokens = max_tokens.max(1) as u64;
+    let mut chunks = Vec::new();
+    let mut first = 0;
+    while first < words.len() {
+        let start = words[first].0;
+        // Longest run of words that fits
+        let mut last = first;
+        while last + 1 < words.len() && estimate_tokens(&text[start..words[last + 1].1]) <= max_tokens {
+            last += 1;
+        }
+        // Pull back to a sentence or paragraph end if that keeps at least half the chunk
+        if last + 1 < words.len() {
+            let min_last = first + (last - first) / 2;
+            if let Some(cut) = (min_last..=last).rev().find(|&i| is_break(text, words[i])) {
+                last = cut;
+            }
+        }
+        let end = words[last].1;
+        chunks.push(Chunk {
+            index: chunks.len(),
+            text: text[start..end].to_string(),
+            start,
+            end,
+            tokens: estimate_tokens(&text[start..end]),
+            parent: None,
+        });
+        if last + 1 >= words.len() {
+            break;
+        }
+        // Step back over the overlap, always moving forward by at least one word
+        let mut next = last + 1;
+        while next > first + 1 && overlap_tokens > 0 && estimate_tokens(&text[words[next - 1].0..end]) <= overlap_tokens as u64 {
+            next -= 1;
+        }
+        first = next;
+    }
+    chunks
+}
+
+// Parent sections and the child chunks inside them; child offsets refer to the whole text
+pub fn chunk_document(text: &str, config: &ChunkConfig) -> ChunkedDocument {
+    let Some(parent_max) = config.parent_max_tokens else {
+        let children = split(text, config.max_tokens, config.overlap_tokens);
+        let parents = children.clone();
+        let children = children.into_iter().map(|c| Chunk { parent: Some(c.index), ..c }).collect();
+        return ChunkedDocument { parents, children };
+    };
+    let parents = split(text, parent_max.max(config.max_tokens), 0);
+    let mut children = Vec::new();
+    for parent in &parents {
+        for child in split(&parent.text, config.max_tokens, config.overlap_tokens) {
+            children.push(Chunk {
+                index: children.len(),
+                start: parent.start + child.start,
+                end: parent.start + child.end,
+                parent: Some(parent.index),
+                ..child
+            });
+        }
+    }
+    ChunkedDocument { parents, children }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lore() -> String {
+        (0..40).map(|i| format!("The keep at Ashford fell in winter number {}.", i)).collect::<Vec<_>>().join(" ")
+    }
+
+    #[test]
+    fn chunks_fit_the_budget_and_point_back_into_the_source() {
+        let text = lore();
+        let chunks = split(&text, 32, 0);
+        assert!(chunks.len() > 1);
+        for (i, chunk) in chunks.iter().enumerate() {
+            assert_eq!(chunk.index, i);
+            assert_eq!(&text[chunk.start..chunk.end], chunk.text);
+            assert!(chunk.tokens <= 32);
+        }
+        // Without overlap every word appears exactly once
+        let rejoined = chunks.iter().map(|c| c.text.as_str()).collect::<Vec<_>>().join(" ");
+        assert_eq!(rejoined, text);
+    }
+
+    #[test]
+    fn chunks_prefer_to_end_on_a_sentence() {
+        let chunks = split(&lore(), 32, 0);
+        for chunk in &chunks[..chunks.len() - 1] {
+            assert!(chunk.text.ends_with('.'), "{:?}", chunk.text);
+        }
+    }
+
+    #[test]
+    fn overlap_repeats_the_tail_of_the_previous_chunk() {
+        let text = lore();
+        let chunks = split(&text, 32, 8);
+        for pair in chunks.windows(2) {
+            assert!(pair[1].start < pair[0].end);
+            assert!(pair[1].start > pair[0].start);
+            assert!(estimate_tokens(&text[pair[1].start..pair[0].end]) <= 8);
+        }
+    }
+
+    #[test]
+    fn empty_text_and_oversized_words() {
+        assert!(split("", 16, 4).is_empty());
+        assert!(split("  \n\n ", 16, 4).is_empty());
+
+        let long = "x".repeat(200);
+        let text = format!("a {} b", long);
+        let chunks = split(&text, 4, 0);
+        assert_eq!(chunks.iter().map(|c| c.text.as_str()).collect::<Vec<_>>(), vec!["a", long.as_str(), "b"]);
+        // Overlap as large as the chunk still makes progress
+        assert!(split("one two three four five six", 2, 10).last().unwrap().text.ends_with("six"));
+    }
+
+    #[test]
+    fn children_sit_inside_their_parent_sections() {
+        let text = lore();
+        let config = ChunkConfig { max_tokens: 24, overlap_tokens: 4, parent_max_tokens: Some(96) };
+        let document = chunk_document(&text, &config);
+        assert!(document.parents.len() > 1);
+        assert!(document.children.len() > document.parents.len());
+        for (i, child) in document.children.iter().enumerate() {
+            assert_eq!(child.index, i);
+            assert_eq!(&text[child.start..child.end], child.text);
+            let parent = document.parent_of(child).unwrap();
+            assert!(parent.start <= child.start && child.end <= parent.end);
+        }
+    }
+
+    #[test]
+    fn without_parents_each_chunk_is_its_own_section() {
+        let text = lore();
+        let config = ChunkConfig { max_tokens: 24, overlap_tokens: 0, parent_max_tokens: None };
+        let document = chunk_document(&text, &config);
+        assert_eq!(document.parents.len(), document.children.len());
+        for child in &document.children {
+            assert_eq!(document.parent_of(child).unwrap().text, child.text);
+        }
+    }
+}
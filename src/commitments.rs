@@ -0,0 +1,600 @@
+// Commitments
+//
+// Promises and deals between NPCs and the player, with a deadline and fulfillment conditions
+// checked against the GameWorld. A commitment is usually created from a promise or deal fact that
+// the conversation summarizer recorded in the knowledge graph (see dialogue/summary.rs); the
+// designer or quest script supplies the conditions that mean "done":
+//
+//   let id = tracker.add(Commitment::from_fact(&kb, "fact:c17#0", now)?
+//       .condition(WorldCondition::component("zara", "inventory", "/medicine", CompareOp::Ge, json!(1))));
+//
+// `evaluate` runs every tick or so. An open commitment whose conditions all hold is kept; one whose
+// deadline passes first is broken. Commitments without conditions are resolved by hand with
+// `fulfill` or `breach`. Each resolution makes the creditor react to the debtor: gratitude or a
+// grudge moves their relationship affinity, and breaking a high-stakes promise, or breaking
+// promises to the same NPC repeatedly, sends bounty hunters. Resolutions are published as
+// "social.commitment" events ({ "commitment", "debtor", "creditor", "outcome", "reactions" }) for
+// the game to act on. Commitments a player is party to are exported and erased with the rest of
+// their data (security/privacy.rs).
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::agentdb::{AgentDb, AgentDbError};
+use crate::dialogue::summary::{FactKind, FACT_PREFIX};
+use crate::dialogue::tree::{compare_ord, CompareOp};
+use crate::events::EventBus;
+use crate::namespace::Namespace;
+use crate::relationships::Relationships;
+use crate::symbolic::SymbolicComputing;
+use crate::world::GameWorld;
+
+pub const COMMITMENT_TOPIC: &str = "social.commitment";
+
+const COMMITMENTS_TABLE: &str = "commitments";
+
+// A check against the world. `global` names a world global; otherwise `entity` (and optionally its
+// `component`, and a JSON pointer into it) names the input. With CompareOp::Exists the input only
+// has to be present.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorldCondition {
+    #[serde(default)]
+    pub global: Option<String>,
+    #[serde(default)]
+    pub entity: Option<String>,
+    #[serde(default)]
+    pub component: Option<String>,
+    #[serde(default)]
+    pub pointer: Option<String>,
+    pub op: CompareOp,
+    #[serde(default)]
+    pub value: Value,
+}
+
+impl WorldCondition {
+    pub fn global(key: &str, op: CompareOp, value: Value) -> Self {
+        WorldCondition { global: Some(key.to_string()), entity: None, component: None, pointer: None, op, value }
+    }
+
+    pub fn component(entity: &str, component: &str, pointer: &str, op: CompareOp, value: Value) -> Self {
+        WorldCondition {
+            global: None,
+            entity: Some(entity.to_string()),
+            component: Some(component.to_string()),
+            pointer: (!pointer.is_empty()).then(|| pointer.to_string()),
+            op,
+            value,
+        }
+    }
+
+    // The entity exists (CompareOp::Exists) or is gone (CompareOp::Ne)
+    pub fn entity(entity: &str, op: CompareOp) -> Self {
+        WorldCondition { global: None, entity: Some(entity.to_string()), component: None, pointer: None, op, value: Value::Null }
+    }
+
+    pub fn is_met(&self, world: &GameWorld) -> bool {
+        let actual = if let Some(key) = &self.global {
+            world.global(key).cloned()
+        } else if let Some(id) = &self.entity {
+            let entity = world.entity(id);
+            match &self.component {
+                None => {
+                    return match self.op {
+                        CompareOp::Ne => entity.is_none(),
+                        _ => entity.is_some(),
+                    }
+                }
+                Some(component) => {
+                    let value = entity.and_then(|entity| entity.component(component));
+                    match &self.pointer {
+                        Some(pointer) => value.and_then(|value| value.pointer(pointer)).cloned(),
+                        None => value.cloned(),
+                    }
+                }
+            }
+        } else {
+            return false;
+        };
+        match (actual, self.op) {
+            (None, _) => false,
+            (Some(_), CompareOp::Exists) => true,
+            (Some(actual), op) => compare_values(&actual, op, &self.value),
+        }
+    }
+}
+
+fn compare_values(actual: &Value, op: CompareOp, expected: &Value) -> bool {
+    match (actual, expected) {
+        (Value::Number(a), Value::Number(b)) => match (a.as_f64(), b.as_f64()) {
+            (Some(a), Some(b)) => compare_ord(a.partial_cmp(&b), op),
+            _ => false,
+        },
+        (Value::String(a), Value::String(b)) => compare_ord(Some(a.cmp(b)), op),
+        (a, b) => match op {
+            CompareOp::Eq => a == b,
+            CompareOp::Ne => a != b,
+            _ => false,
+        },
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum CommitmentStatus {
+    Open,
+    Kept { at: f64 },
+    Broken { at: f64 },
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Commitment {
+    pub id: String,
+    // Who owes something, and to whom
+    pub debtor: String,
+    pub creditor: String,
+    pub kind: FactKind,
+    pub description: String,
+    // All must hold for the commitment to count as kept
+    pub conditions: Vec<WorldCondition>,
+    // Game time in seconds; None never expires
+    pub deadline: Option<f64>,
+    pub made_at: f64,
+    // How much the creditor cares, 0..1; scales reactions
+    pub stakes: f32,
+    // Fact concept it was created from, for provenance
+    pub source_fact: Option<String>,
+    pub status: CommitmentStatus,
+}
+
+impl Commitment {
+    pub fn new(id: &str, debtor: &str, creditor: &str, description: &str, made_at: f64) -> Self {
+        Commitment {
+            id: id.to_string(),
+            debtor: debtor.to_string(),
+            creditor: creditor.to_string(),
+            kind: FactKind::Promise,
+            description: description.to_string(),
+            conditions: Vec::new(),
+            deadline: None,
+            made_at,
+            stakes: 0.5,
+            source_fact: None,
+            status: CommitmentStatus::Open,
+        }
+    }
+
+    // From a promise or deal fact recorded by ConversationSummary::record. The deadline comes from
+    // the fact's `due_in_hours`, counted in game hours from `now`.
+    pub fn from_fact(kb: &SymbolicComputing, fact: &str, now: f64) -> Result<Self, CommitmentError> {
+        let concept = kb.concept(fact).filter(|_| fact.starts_with(FACT_PREFIX));
+        let concept = concept.ok_or_else(|| CommitmentError::UnknownFact(fact.to_string()))?;
+        let property = |key: &str| concept.properties.get(key).cloned();
+        let kind = match property("kind").as_deref() {
+            Some("promise") => FactKind::Promise,
+            Some("deal") => FactKind::Deal,
+            _ => return Err(CommitmentError::NotACommitment(fact.to_string())),
+        };
+        let debtor = property("subject").unwrap_or_default();
+        let creditor = property("object").unwrap_or_default();
+        let mut description = format!("{} {} {}", debtor, property("relation").unwrap_or_default(), creditor);
+        if let Some(item) = property("item") {
+            description = format!("{} ({})", description, item);
+        }
+        let mut commitment = Commitment::new(fact.trim_start_matches(FACT_PREFIX), &debtor, &creditor, &description, now);
+        commitment.kind = kind;
+        commitment.deadline = property("due_in_hours").and_then(|hours| hours.parse::<f64>().ok()).map(|hours| now + hours * 3600.0);
+        commitment.source_fact = Some(fact.to_string());
+        Ok(commitment)
+    }
+
+    pub fn condition(mut self, condition: WorldCondition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+
+    pub fn deadline(mut self, deadline: f64) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    pub fn stakes(mut self, stakes: f32) -> Self {
+        self.stakes = stakes.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.status == CommitmentStatus::Open
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "reaction", rename_all = "snake_case")]
+pub enum Reaction {
+    Gratitude { affinity: f32 },
+    Grudge { affinity: f32 },
+    BountyHunters,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Resolution {
+    pub commitment: String,
+    pub debtor: String,
+    pub creditor: String,
+    pub outcome: CommitmentStatus,
+    pub reactions: Vec<Reaction>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReactionPolicy {
+    // Affinity change of the creditor towards the debtor at stakes 1; scaled down with stakes
+    pub gratitude: f32,
+    pub grudge: f32,
+    // Broken commitments at or above these stakes send bounty hunters
+    pub bounty_stakes: f32,
+    // As do this many broken commitments to the same creditor
+    pub bounty_after_breaks: u32,
+}
+
+impl Default for ReactionPolicy {
+    fn default() -> Self {
+        ReactionPolicy { gratitude: 0.3, grudge: -0.45, bounty_stakes: 0.9, bounty_after_breaks: 3 }
+    }
+}
+
+#[derive(Debug)]
+pub enum CommitmentError {
+    UnknownFact(String),
+    NotACommitment(String),
+    UnknownCommitment(String),
+    AlreadyResolved(String),
+    Storage(AgentDbError),
+    Corrupt(serde_json::Error),
+}
+
+impl fmt::Display for CommitmentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommitmentError::UnknownFact(fact) => write!(f, "no fact '{}' in the knowledge graph", fact),
+            CommitmentError::NotACommitment(fact) => write!(f, "fact '{}' is not a promise or deal", fact),
+            CommitmentError::UnknownCommitment(id) => write!(f, "unknown commitment '{}'", id),
+            CommitmentError::AlreadyResolved(id) => write!(f, "commitment '{}' is no longer open", id),
+            CommitmentError::Storage(err) => write!(f, "{}", err),
+            CommitmentError::Corrupt(err) => write!(f, "stored commitment is invalid: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for CommitmentError {}
+
+impl From<AgentDbError> for CommitmentError {
+    fn from(err: AgentDbError) -> Self {
+        CommitmentError::Storage(err)
+    }
+}
+
+impl From<serde_json::Error> for CommitmentError {
+    fn from(err: serde_json::Error) -> Self {
+        CommitmentError::Corrupt(err)
+    }
+}
+
+#[derive(Default)]
+pub struct CommitmentTracker {
+    commitments: BTreeMap<String, Commitment>,
+    policy: ReactionPolicy,
+    // (debtor, creditor) -> broken commitments
+    breaks: BTreeMap<(String, String), u32>,
+    pending: Vec<Resolution>,
+}
+
+impl CommitmentTracker {
+    pub fn new(policy: ReactionPolicy) -> Self {
+        CommitmentTracker { policy, ..CommitmentTracker::default() }
+    }
+
+    // Replaces a commitment with the same id; returns the id
+    pub fn add(&mut self, commitment: Commitment) -> String {
+        let id = commitment.id.clone();
+        self.commitments.insert(id.clone(), commitment);
+        id
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Commitment> {
+        self.commitments.get(id)
+    }
+
+    pub fn open(&self) -> impl Iterator<Item = &Commitment> {
+        self.commitments.values().filter(|commitment| commitment.is_open())
+    }
+
+    // Commitments `debtor` owes anyone, e.g. to remind the player in dialogue
+    pub fn owed_by<'a>(&'a self, debtor: &'a str) -> impl Iterator<Item = &'a Commitment> + 'a {
+        self.open().filter(move |commitment| commitment.debtor == debtor)
+    }
+
+    // Every commitment `party` owes or is owed, resolved ones included
+    pub fn involving<'a>(&'a self, party: &'a str) -> impl Iterator<Item = &'a Commitment> + 'a {
+        self.commitments.values().filter(move |c| c.debtor == party || c.creditor == party)
+    }
+
+    // Number of (debtor, creditor) break counts kept for pairs including `party`
+    pub fn breaks_involving(&self, party: &str) -> usize {
+        self.breaks.keys().filter(|(debtor, creditor)| debtor == party || creditor == party).count()
+    }
+
+    // Number of resolutions involving `party` still waiting to be published
+    pub fn pending_involving(&self, party: &str) -> usize {
+        self.pending.iter().filter(|r| r.debtor == party || r.creditor == party).count()
+    }
+
+    // Drop every commitment, break count and unpublished resolution involving `party` (privacy
+    // erasure); returns the number of entries removed
+    pub fn forget_party(&mut self, party: &str) -> usize {
+        let before = self.commitments.len() + self.breaks.len() + self.pending.len();
+        self.commitments.retain(|_, c| c.debtor != party && c.creditor != party);
+        self.breaks.retain(|(debtor, creditor), _| debtor != party && creditor != party);
+        self.pending.retain(|resolution| resolution.debtor != party && resolution.creditor != party);
+        before - self.commitments.len() - self.breaks.len() - self.pending.len()
+    }
+
+    pub fn broken_count(&self, debtor: &str, creditor: &str) -> u32 {
+        self.breaks.get(&(debtor.to_string(), creditor.to_string())).copied().unwrap_or(0)
+    }
+
+    // Resolve open commitments: kept when every condition holds, broken once the deadline passed
+    pub fn evaluate(&mut self, world: &GameWorld, relationships: &mut Relationships, now: f64) -> Vec<Resolution> {
+        let due: Vec<(String, CommitmentStatus)> = self
+            .open()
+            .filter_map(|commitment| {
+                if !commitment.conditions.is_empty() && commitment.conditions.iter().all(|c| c.is_met(world)) {
+                    Some((commitment.id.clone(), CommitmentStatus::Kept { at: now }))
+                } else if commitment.deadline.is_some_and(|deadline| now >= deadline) {
+                    Some((commitment.id.clone(), CommitmentStatus::Broken { at: now }))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        due.into_iter().filter_map(|(id, outcome)| self.resolve(&id, outcome, relationships, now).ok()).collect()
+    }
+
+    pub fn fulfill(&mut self, id: &str, relationships: &mut Relationships, now: f64) -> Result<Resolution, CommitmentError> {
+        self.resolve(id, CommitmentStatus::Kept { at: now }, relationships, now)
+    }
+
+    pub fn breach(&mut self, id: &str, relationships: &mut Relationships, now: f64) -> Result<Resolution, CommitmentError> {
+        self.resolve(id, CommitmentStatus::Broken { at: now }, relationships, now)
+    }
+
+    // Withdraw a commitment without consequences, e.g. when the creditor died
+    pub fn cancel(&mut self, id: &str) -> Result<(), CommitmentError> {
+        let commitment = self.commitments.get_mut(id).ok_or_else(|| CommitmentError::UnknownCommitment(id.to_string()))?;
+        if commitment.is_open() {
+            commitment.status = CommitmentStatus::Cancelled;
+        }
+        Ok(())
+    }
+
+    fn resolve(&mut self, id: &str, outcome: CommitmentStatus, relationships: &mut Relationships, now: f64) -> Result<Resolution, CommitmentError> {
+        let commitment = self.commitments.get_mut(id).ok_or_else(|| CommitmentError::UnknownCommitment(id.to_string()))?;
+        if !commitment.is_open() {
+            return Err(CommitmentError::AlreadyResolved(id.to_string()));
+        }
+        commitment.status = outcome;
+        let (debtor, creditor, stakes) = (commitment.debtor.clone(), commitment.creditor.clone(), commitment.stakes);
+        // Even trivial promises matter a little
+        let scale = 0.25 + 0.75 * stakes;
+        let mut reactions = Vec::new();
+        match outcome {
+            CommitmentStatus::Kept { .. } => {
+                let affinity = self.policy.gratitude * scale;
+                relationships.interact(&creditor, &debtor, affinity, now, None);
+                reactions.push(Reaction::Gratitude { affinity });
+            }
+            CommitmentStatus::Broken { .. } => {
+                let affinity = self.policy.grudge * scale;
+                relationships.interact(&creditor, &debtor, affinity, now, None);
+                reactions.push(Reaction::Grudge { affinity });
+                let breaks = self.breaks.entry((debtor.clone(), creditor.clone())).or_default();
+                *breaks += 1;
+                if stakes >= self.policy.bounty_stakes || *breaks >= self.policy.bounty_after_breaks.max(1) {
+                    reactions.push(Reaction::BountyHunters);
+                }
+            }
+            CommitmentStatus::Open | CommitmentStatus::Cancelled => {}
+        }
+        let resolution = Resolution { commitment: id.to_string(), debtor, creditor, outcome, reactions };
+        self.pending.push(resolution.clone());
+        Ok(resolution)
+    }
+
+    // Publish resolutions on COMMITMENT_TOPIC; returns the number published
+    pub fn publish(&mut self, events: &mut EventBus) -> usize {
+        let count = self.pending.len();
+        for resolution in self.pending.drain(..) {
+            events.emit(COMMITMENT_TOPIC, &resolution.creditor, json!(resolution));
+        }
+        count
+    }
+
+    // Stored rows of commitments the tracker no longer holds are removed, so an erased player's
+    // promises do not come back on the next restore
+    pub fn save(&self, db: &mut AgentDb, namespace: &Namespace) -> Result<(), CommitmentError> {
+        let stale: Vec<String> = db
+            .scan(namespace, COMMITMENTS_TABLE)
+            .map(|(id, _)| id.clone())
+            .filter(|id| !self.commitments.contains_key(id))
+            .collect();
+        for id in stale {
+            db.delete(namespace, COMMITMENTS_TABLE, &id);
+        }
+        for (id, commitment) in &self.commitments {
+            db.put(namespace, COMMITMENTS_TABLE, id, serde_json::to_value(commitment)?)?;
+        }
+        Ok(())
+    }
+
+    // Load stored commitments; the break counts are rebuilt from all broken ones held afterwards
+    pub fn restore(&mut self, db: &AgentDb, namespace: &Namespace) -> Result<usize, CommitmentError> {
+        let mut restored = 0;
+        for (_, value) in db.scan(namespace, COMMITMENTS_TABLE) {
+            let commitment: Commitment = serde_json::from_value(value.clone())?;
+            self.commitments.insert(commitment.id.clone(), commitment);
+            restored += 1;
+        }
+        self.breaks.clear();
+        for commitment in self.commitments.values() {
+            if matches!(commitment.status, CommitmentStatus::Broken { .. }) {
+                *self.breaks.entry((commitment.debtor.clone(), commitment.creditor.clone())).or_default() += 1;
+            }
+        }
+        Ok(restored)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::Entity;
+
+    fn world_with_medicine(count: u32) -> GameWorld {
+        let mut world = GameWorld::new();
+        world.spawn("zara", Entity::new("npc").with("inventory", json!({ "medicine": count })));
+        world.set_global("gate", json!("open"));
+        world
+    }
+
+    fn medicine_promise(id: &str) -> Commitment {
+        Commitment::new(id, "p1", "zara", "bring medicine", 0.0)
+            .condition(WorldCondition::component("zara", "inventory", "/medicine", CompareOp::Ge, json!(1)))
+            .deadline(100.0)
+    }
+
+    #[test]
+    fn conditions_check_globals_components_and_entities() {
+        let world = world_with_medicine(2);
+        assert!(WorldCondition::global("gate", CompareOp::Eq, json!("open")).is_met(&world));
+        assert!(!WorldCondition::global("gate", CompareOp::Eq, json!("shut")).is_met(&world));
+        assert!(!WorldCondition::global("weather", CompareOp::Exists, Value::Null).is_met(&world));
+        assert!(WorldCondition::component("zara", "inventory", "/medicine", CompareOp::Gt, json!(1)).is_met(&world));
+        assert!(!WorldCondition::component("zara", "inventory", "/medicine", CompareOp::Gt, json!(2)).is_met(&world));
+        assert!(WorldCondition::component("zara", "inventory", "", CompareOp::Exists, Value::Null).is_met(&world));
+        assert!(!WorldCondition::component("zara", "inventory", "/herbs", CompareOp::Exists, Value::Null).is_met(&world));
+        assert!(WorldCondition::entity("zara", CompareOp::Exists).is_met(&world));
+        assert!(WorldCondition::entity("bandit", CompareOp::Ne).is_met(&world));
+        assert!(!WorldCondition::entity("zara", CompareOp::Ne).is_met(&world));
+    }
+
+    #[test]
+    fn evaluate_keeps_met_commitments_and_breaks_overdue_ones() {
+        let mut tracker = CommitmentTracker::new(ReactionPolicy::default());
+        let mut relationships = Relationships::default();
+        tracker.add(medicine_promise("c1"));
+        tracker.add(Commitment::new("c2", "p1", "zara", "open the gate", 0.0)
+            .condition(WorldCondition::global("gate", CompareOp::Eq, json!("shut")))
+            .deadline(100.0));
+
+        assert!(tracker.evaluate(&world_with_medicine(0), &mut relationships, 50.0).is_empty());
+        let resolutions = tracker.evaluate(&world_with_medicine(1), &mut relationships, 60.0);
+        assert_eq!(resolutions.len(), 1);
+        assert_eq!(resolutions[0].commitment, "c1");
+        assert_eq!(tracker.get("c1").unwrap().status, CommitmentStatus::Kept { at: 60.0 });
+        assert!(relationships.affinity("zara", "p1") > 0.0);
+
+        let resolutions = tracker.evaluate(&world_with_medicine(1), &mut relationships, 101.0);
+        assert_eq!(resolutions.len(), 1);
+        assert_eq!(tracker.get("c2").unwrap().status, CommitmentStatus::Broken { at: 101.0 });
+        assert_eq!(tracker.broken_count("p1", "zara"), 1);
+        assert_eq!(tracker.open().count(), 0);
+    }
+
+    #[test]
+    fn from_fact_reads_promises_and_rejects_other_facts() {
+        let mut kb = SymbolicComputing::new();
+        let properties =
+            [("kind", "promise"), ("subject", "p1"), ("relation", "promised"), ("object", "zara"), ("item", "medicine"), ("due_in_hours", "2")];
+        for (key, value) in properties {
+            kb.set_property("fact:c1#0", key, value);
+        }
+        kb.set_property("fact:c1#1", "kind", "preference");
+
+        let commitment = Commitment::from_fact(&kb, "fact:c1#0", 1000.0).unwrap();
+        assert_eq!(commitment.id, "c1#0");
+        assert_eq!((commitment.debtor.as_str(), commitment.creditor.as_str()), ("p1", "zara"));
+        assert_eq!(commitment.description, "p1 promised zara (medicine)");
+        assert_eq!(commitment.deadline, Some(1000.0 + 2.0 * 3600.0));
+        assert_eq!(commitment.source_fact.as_deref(), Some("fact:c1#0"));
+        assert!(matches!(Commitment::from_fact(&kb, "fact:c1#1", 0.0), Err(CommitmentError::NotACommitment(_))));
+        assert!(matches!(Commitment::from_fact(&kb, "fact:c1#9", 0.0), Err(CommitmentError::UnknownFact(_))));
+    }
+
+    #[test]
+    fn high_stakes_or_repeated_breaks_send_bounty_hunters() {
+        let mut tracker = CommitmentTracker::new(ReactionPolicy::default());
+        let mut relationships = Relationships::default();
+        tracker.add(Commitment::new("big", "p1", "zara", "return the sword", 0.0).stakes(1.0));
+        let resolution = tracker.breach("big", &mut relationships, 1.0).unwrap();
+        assert_eq!(resolution.reactions, vec![Reaction::Grudge { affinity: -0.45 }, Reaction::BountyHunters]);
+
+        for id in ["a", "b", "c"] {
+            tracker.add(Commitment::new(id, "p1", "omar", "small favour", 0.0).stakes(0.0));
+        }
+        assert!(!tracker.breach("a", &mut relationships, 1.0).unwrap().reactions.contains(&Reaction::BountyHunters));
+        assert!(!tracker.breach("b", &mut relationships, 1.0).unwrap().reactions.contains(&Reaction::BountyHunters));
+        assert!(tracker.breach("c", &mut relationships, 1.0).unwrap().reactions.contains(&Reaction::BountyHunters));
+        assert!(matches!(tracker.breach("c", &mut relationships, 2.0), Err(CommitmentError::AlreadyResolved(_))));
+    }
+
+    #[test]
+    fn publish_emits_each_resolution_once() {
+        let mut tracker = CommitmentTracker::new(ReactionPolicy::default());
+        let mut relationships = Relationships::default();
+        let mut events = EventBus::new(16);
+        let subscription = events.subscribe("social.");
+        tracker.add(Commitment::new("c1", "p1", "zara", "bring medicine", 0.0));
+        tracker.fulfill("c1", &mut relationships, 5.0).unwrap();
+
+        assert_eq!(tracker.publish(&mut events), 1);
+        assert_eq!(tracker.publish(&mut events), 0);
+        let published = events.drain(subscription);
+        assert_eq!(published.len(), 1);
+        assert_eq!(published[0].topic, COMMITMENT_TOPIC);
+        assert_eq!(published[0].source, "zara");
+        assert_eq!(published[0].payload["outcome"]["status"], "kept");
+    }
+
+    #[test]
+    fn erasure_drops_unpublished_resolutions() {
+        let mut tracker = CommitmentTracker::new(ReactionPolicy::default());
+        let mut relationships = Relationships::default();
+        let mut events = EventBus::new(16);
+        tracker.add(Commitment::new("c1", "p1", "zara", "bring medicine", 0.0));
+        tracker.breach("c1", &mut relationships, 5.0).unwrap();
+
+        assert_eq!(tracker.pending_involving("p1"), 1);
+        assert_eq!(tracker.forget_party("p1"), 3);
+        assert_eq!(tracker.publish(&mut events), 0);
+    }
+
+    #[test]
+    fn restoring_twice_does_not_double_count_breaks() {
+        let mut tracker = CommitmentTracker::new(ReactionPolicy::default());
+        let mut relationships = Relationships::default();
+        tracker.add(Commitment::new("c1", "p1", "zara", "bring medicine", 0.0));
+        tracker.breach("c1", &mut relationships, 5.0).unwrap();
+        let namespace = Namespace::new("world").unwrap();
+        let mut db = AgentDb::new();
+        tracker.save(&mut db, &namespace).unwrap();
+
+        assert_eq!(tracker.restore(&db, &namespace).unwrap(), 1);
+        assert_eq!(tracker.restore(&db, &namespace).unwrap(), 1);
+        assert_eq!(tracker.broken_count("p1", "zara"), 1);
+        let mut fresh = CommitmentTracker::new(ReactionPolicy::default());
+        fresh.restore(&db, &namespace).unwrap();
+        assert_eq!(fresh.broken_count("p1", "zara"), 1);
+    }
+}
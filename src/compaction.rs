@@ -0,0 +1,268 @@
+// Background compaction of the vector index
+//
+// Deletes and overwrites leave the in-memory collections with oversized tables and the dedup
+// windows holding ids that no longer exist, and the snapshot on disk keeps describing the old
+// state until it is rewritten. Compaction fixes both, but it costs frame time, so it only runs in
+// low-activity windows: the scheduler is fed each tick's duration, and once the smoothed load has
+// stayed below a threshold for a number of ticks it compacts one fragmented collection per tick.
+// After the last collection it rewrites the snapshot. A busy tick pauses the cycle where it is.
+//
+// Progress is published as "vector_index.compaction" events (phase, collection, done, total).
+
+use std::collections::VecDeque;
+use std::path::PathBuf;
+
+use serde_json::json;
+
+use crate::events::EventBus;
+use crate::vector_index::{CompactionStats, VectorIndex, VectorIndexError};
+
+pub const COMPACTION_TOPIC: &str = "vector_index.compaction";
+
+#[derive(Debug, Clone)]
+pub struct CompactionConfig {
+    // Collections with churn at or above this share of their size are compacted
+    pub min_fragmentation: f64,
+    // Frame budget; load is tick duration divided by this
+    pub tick_budget_ms: f64,
+    // Smoothed load below this counts as quiet
+    pub quiet_load: f64,
+    // Consecutive quiet ticks before compaction starts
+    pub quiet_ticks: u32,
+    // Rewritten after every completed cycle, when set
+    pub snapshot_path: Option<PathBuf>,
+}
+
+impl Default for CompactionConfig {
+    fn default() -> Self {
+        CompactionConfig {
+            min_fragmentation: 0.2,
+            tick_budget_ms: 16.0,
+            quiet_load: 0.5,
+            quiet_ticks: 120,
+            snapshot_path: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompactionStep {
+    // Nothing to do, or not quiet enough
+    Idle,
+    Compacted { collection: String, stats: CompactionStats, done: usize, total: usize },
+    SnapshotWritten,
+    Failed(String),
+}
+
+pub struct CompactionScheduler {
+    config: CompactionConfig,
+    load: f64,
+    quiet_streak: u32,
+    // Collections left in the current cycle
+    pending: VecDeque<String>,
+    total: usize,
+    in_cycle: bool,
+}
+
+impl CompactionScheduler {
+    pub fn new(config: CompactionConfig) -> Self {
+        CompactionScheduler { config, load: 0.0, quiet_streak: 0, pending: VecDeque::new(), total: 0, in_cycle: false }
+    }
+
+    // Feed one tick's duration
+    pub fn record_tick(&mut self, duration_ms: f64) {
+        let load = duration_ms / self.config.tick_budget_ms.max(f64::EPSILON);
+        self.load = self.load * 0.9 + load * 0.1;
+        if self.load < self.config.quiet_load && load < 1.0 {
+            self.quiet_streak = self.quiet_streak.saturating_add(1);
+        } else {
+            self.quiet_streak = 0;
+        }
+    }
+
+    pub fn is_quiet(&self) -> bool {
+        self.quiet_streak >= self.config.quiet_ticks
+    }
+
+    pub fn load(&self) -> f64 {
+        self.load
+    }
+
+    // (done, total) of the current cycle, if one is running
+    pub fn progress(&self) -> Option<(usize, usize)> {
+        self.in_cycle.then(|| (self.total - self.pending.len(), self.total))
+    }
+
+    // Do at most one unit of work; call once per tick after record_tick
+    pub fn step(&mut self, index: &mut VectorIndex, mut events: Option<&mut EventBus>) -> CompactionStep {
+        if !self.is_quiet() {
+            return CompactionStep::Idle;
+        }
+        if !self.in_cycle {
+            let mut fragmented: Vec<String> = index
+                .collection_names()
+                .into_iter()
+                .filter(|name| index.fragmentation(name).is_ok_and(|f| f >= self.config.min_fragmentation))
+                .map(str::to_string)
+                .collect();
+            if fragmented.is_empty() {
+                return CompactionStep::Idle;
+            }
+            fragmented.sort();
+            self.total = fragmented.len();
+            self.pending = fragmented.into();
+            self.in_cycle = true;
+            publish(events.as_deref_mut(), "started", None, 0, self.total);
+        }
+
+        let Some(collection) = self.pending.pop_front() else {
+            return self.finish(index, events);
+        };
+        let done = self.total - self.pending.len();
+        match index.compact_collection(&collection) {
+            Ok(stats) => {
+                publish(events, "collection", Some(&collection), done, self.total);
+                CompactionStep::Compacted { collection, stats, done, total: self.total }
+            }
+            // Dropped since the cycle started
+            Err(VectorIndexError::CollectionNotFound(_)) => CompactionStep::Idle,
+            Err(err) => CompactionStep::Failed(err.to_string()),
+        }
+    }
+
+    fn finish(&mut self, index: &VectorIndex, events: Option<&mut EventBus>) -> CompactionStep {
+        self.in_cycle = false;
+        let step = match &self.config.snapshot_path {
+            Some(path) => match index.save_snapshot(path, None) {
+                Ok(()) => CompactionStep::SnapshotWritten,
+                Err(err) => CompactionStep::Failed(err.to_string()),
+            },
+            None => CompactionStep::Idle,
+        };
+        publish(events, "finished", None, self.total, self.total);
+        step
+    }
+}
+
+fn publish(events: Option<&mut EventBus>, phase: &str, collection: Option<&str>, done: usize, total: usize) {
+    if let Some(events) = events {
+        events.emit(
+            COMPACTION_TOPIC,
+            "vector_index",
+            json!({ "phase": phase, "collection": collection, "done": done, "total": total }),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector_index::{VectorIndexConfig, VectorPoint};
+
+    fn config() -> CompactionConfig {
+        CompactionConfig { quiet_ticks: 3, ..CompactionConfig::default() }
+    }
+
+    // `churned` collections had half their points deleted; `steady` was only ever inserted into
+    fn index(churned: &[&str]) -> VectorIndex {
+        let mut index = VectorIndex::new(VectorIndexConfig {
+            url: String::new(),
+            api_key: String::new(),
+            default_ttl_secs: None,
+            collection_ttl_secs: Default::default(),
+        });
+        for name in churned.iter().chain(&["steady"]) {
+            index.create_collection(name, 2, "test").unwrap();
+            for i in 0..10 {
+                index.upsert(name, VectorPoint::new(&format!("p{}", i), vec![i as f32, 1.0])).unwrap();
+            }
+        }
+        for name in churned {
+            let ids: Vec<String> = (0..5).map(|i| format!("p{}", i)).collect();
+            index.delete(name, &ids).unwrap();
+        }
+        index
+    }
+
+    fn quiet(scheduler: &mut CompactionScheduler) {
+        for _ in 0..3 {
+            scheduler.record_tick(1.0);
+        }
+    }
+
+    #[test]
+    fn only_a_run_of_light_ticks_counts_as_quiet() {
+        let mut scheduler = CompactionScheduler::new(config());
+        quiet(&mut scheduler);
+        assert!(scheduler.is_quiet());
+        assert!(scheduler.load() > 0.0 && scheduler.load() < scheduler.config.quiet_load);
+
+        // One frame over budget restarts the count even though the average stays low
+        scheduler.record_tick(20.0);
+        assert!(!scheduler.is_quiet());
+        scheduler.record_tick(1.0);
+        scheduler.record_tick(1.0);
+        assert!(!scheduler.is_quiet());
+        scheduler.record_tick(1.0);
+        assert!(scheduler.is_quiet());
+    }
+
+    #[test]
+    fn a_cycle_compacts_fragmented_collections_then_finishes() {
+        let mut index = index(&["lore", "memories"]);
+        let mut bus = EventBus::new(16);
+        let sub = bus.subscribe(COMPACTION_TOPIC);
+        let mut scheduler = CompactionScheduler::new(config());
+        assert_eq!(scheduler.step(&mut index, None), CompactionStep::Idle);
+
+        quiet(&mut scheduler);
+        match scheduler.step(&mut index, Some(&mut bus)) {
+            CompactionStep::Compacted { collection, stats, done, total } => {
+                assert_eq!((collection.as_str(), done, total), ("lore", 1, 2));
+                assert_eq!((stats.points, stats.churn), (5, 5));
+            }
+            other => panic!("expected a compaction, got {:?}", other),
+        }
+        assert_eq!(index.fragmentation("lore").unwrap(), 0.0);
+        assert_eq!(scheduler.progress(), Some((1, 2)));
+
+        // A busy tick pauses the cycle where it is
+        scheduler.record_tick(40.0);
+        assert_eq!(scheduler.step(&mut index, Some(&mut bus)), CompactionStep::Idle);
+        quiet(&mut scheduler);
+        assert!(matches!(scheduler.step(&mut index, Some(&mut bus)), CompactionStep::Compacted { done: 2, .. }));
+        assert_eq!(scheduler.step(&mut index, Some(&mut bus)), CompactionStep::Idle);
+        assert_eq!(scheduler.progress(), None);
+
+        let phases: Vec<String> = bus.drain(sub).into_iter().map(|e| e.payload["phase"].as_str().unwrap().to_string()).collect();
+        assert_eq!(phases, vec!["started", "collection", "collection", "finished"]);
+        // Nothing left to compact
+        assert_eq!(scheduler.step(&mut index, None), CompactionStep::Idle);
+        assert!(bus.drain(sub).is_empty());
+    }
+
+    #[test]
+    fn snapshot_is_rewritten_after_the_cycle() {
+        let path = std::env::temp_dir().join(format!("arcadia-compaction-{}.json", std::process::id()));
+        let mut index = index(&["lore"]);
+        let mut scheduler = CompactionScheduler::new(CompactionConfig { snapshot_path: Some(path.clone()), ..config() });
+        quiet(&mut scheduler);
+        assert!(matches!(scheduler.step(&mut index, None), CompactionStep::Compacted { .. }));
+        assert_eq!(scheduler.step(&mut index, None), CompactionStep::SnapshotWritten);
+        assert!(path.exists());
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn collections_dropped_mid_cycle_are_skipped() {
+        let mut index = index(&["lore", "memories"]);
+        let mut scheduler = CompactionScheduler::new(config());
+        quiet(&mut scheduler);
+        scheduler.step(&mut index, None);
+        index.drop_collection("memories").unwrap();
+        assert_eq!(scheduler.step(&mut index, None), CompactionStep::Idle);
+        assert_eq!(scheduler.progress(), Some((2, 2)));
+        scheduler.step(&mut index, None);
+        assert_eq!(scheduler.progress(), None);
+    }
+}
@@ -0,0 +1,865 @@
+// aiTOML configuration schema
+//
+// The manifest is checked against a declarative schema before it is deserialized, so a typo or
+// an out-of-range value produces a readable diagnostic instead of a serde error (or, worse, a
+// silently ignored key). Unknown keys are warnings with a "did you mean" suggestion; missing
+// required keys, wrong types and out-of-range numbers are errors. Cross-field checks cover what a
+// single key cannot, such as the vector dimension matching the embedding model.
+//
+// The effective configuration is layered: schema defaults, then the file, then
+// ARCADIA__SECTION__KEY environment variables (containers rarely get to edit TOML), then
+// overrides set by the embedding application. Each value remembers which layer set it, and
+// diagnostics and the printed configuration report that provenance.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use toml::{Table, Value};
+
+use crate::validation::ValidationReport;
+use crate::security::encryption::StaticKey;
+use crate::security::residency;
+use crate::workflow::schedule;
+
+pub const ENV_PREFIX: &str = "ARCADIA__";
+
+#[derive(Debug, Clone)]
+pub enum Kind {
+    String,
+    Integer,
+    // Integers are accepted too
+    Float,
+    Boolean,
+    Table(Vec<Field>),
+    Array(Box<Kind>),
+    // Arbitrary keys, every value of the given kind
+    Map(Box<Kind>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub name: &'static str,
+    pub kind: Kind,
+    pub required: bool,
+    // Inclusive bounds for numbers
+    pub range: Option<(f64, f64)>,
+    pub default: Option<Value>,
+    // Values that must never be printed
+    pub secret: bool,
+}
+
+impl Field {
+    pub fn required(name: &'static str, kind: Kind) -> Self {
+        Field { name, kind, required: true, range: None, default: None, secret: false }
+    }
+
+    pub fn optional(name: &'static str, kind: Kind) -> Self {
+        Field { required: false, ..Field::required(name, kind) }
+    }
+
+    pub fn range(mut self, min: f64, max: f64) -> Self {
+        self.range = Some((min, max));
+        self
+    }
+
+    pub fn default(mut self, value: impl Into<Value>) -> Self {
+        self.default = Some(value.into());
+        self
+    }
+
+    pub fn secret(mut self) -> Self {
+        self.secret = true;
+        self
+    }
+}
+
+// Schema of the aiTOML manifest
+pub fn schema() -> Vec<Field> {
+    vec![
+        Field::required(
+            "vector_index",
+            Kind::Table(vec![
+                Field::required("url", Kind::String),
+                Field::required("api_key", Kind::String).secret(),
+                Field::optional("default_ttl_secs", Kind::Integer).range(1.0, u32::MAX as f64),
+                Field::optional("collection_ttl_secs", Kind::Map(Box::new(Kind::Integer))),
+                Field::optional("embedding_model", Kind::String),
+                Field::optional("vector_dimension", Kind::Integer).range(1.0, 65536.0),
+            ]),
+        ),
+        Field::required(
+            "authentication",
+            Kind::Table(vec![
+                Field::required("provider", Kind::String),
+                Field::required(
+                    "credentials",
+                    Kind::Table(vec![
+                        Field::required("client_id", Kind::String),
+                        Field::required("client_secret", Kind::String).secret(),
+                    ]),
+                ),
+            ]),
+        ),
+        Field::optional(
+            "code_dna",
+            Kind::Table(vec![
+                Field::optional("setting", Kind::String),
+                Field::optional("technology", Kind::String),
+                Field::optional("time_scale", Kind::Float).range(0.01, 100.0).default(1.0),
+                Field::optional("entropy_rate", Kind::Float).range(0.0, 1.0).default(0.1),
+            ]),
+        ),
+        Field::optional(
+            "memory",
+            Kind::Table(vec![
+                Field::required("soft_limit_mb", Kind::Integer).range(1.0, 1048576.0),
+                Field::optional("target_ratio", Kind::Float).range(0.1, 1.0),
+                Field::optional("elevated_ratio", Kind::Float).range(0.1, 1.0),
+            ]),
+        ),
+        Field::optional(
+            "memory_budget",
+            Kind::Table(vec![
+                Field::optional("max_points", Kind::Integer).range(1.0, u32::MAX as f64),
+                Field::optional("entities", Kind::Map(Box::new(Kind::Integer))),
+                Field::optional("entity_field", Kind::String),
+                Field::optional("similarity", Kind::Float).range(-1.0, 1.0),
+                Field::optional("cluster_size", Kind::Integer).range(2.0, 1000.0),
+                Field::optional("keep_importance", Kind::Float).range(0.0, 1.0),
+                Field::optional("summary_words", Kind::Integer).range(1.0, 10000.0),
+            ]),
+        ),
+        Field::optional(
+            "behavior_profiles",
+            Kind::Table(vec![
+                Field::required("default", Kind::String),
+                Field::optional("crossfade_secs", Kind::Float).range(0.0, 60.0),
+                Field::required(
+                    "profiles",
+                    Kind::Map(Box::new(Kind::Table(vec![
+                        Field::optional("actions", Kind::Array(Box::new(Kind::String))),
+                        Field::optional("action_costs", Kind::Map(Box::new(Kind::Float))),
+                        Field::optional("utility_weights", Kind::Map(Box::new(Kind::Float))),
+                        Field::optional(
+                            "emotion_target",
+                            Kind::Table(vec![
+                                Field::required("tension", Kind::Float).range(0.0, 1.0),
+                                Field::required("valence", Kind::Float).range(-1.0, 1.0),
+                                Field::required("energy", Kind::Float).range(0.0, 1.0),
+                            ]),
+                        ),
+                        Field::optional("dialogue_tone", Kind::String),
+                    ]))),
+                ),
+            ]),
+        ),
+        Field::optional(
+            "data_residency",
+            Kind::Table(vec![
+                Field::required("default_region", Kind::String),
+                Field::optional("strict", Kind::Boolean),
+                Field::required(
+                    "backends",
+                    Kind::Map(Box::new(Kind::Table(vec![
+                        Field::required("kind", Kind::String),
+                        Field::optional("url", Kind::String),
+                        Field::optional("path", Kind::String),
+                        Field::optional("namespace", Kind::String),
+                    ]))),
+                ),
+                Field::required("regions", Kind::Map(Box::new(Kind::Map(Box::new(Kind::String))))),
+            ]),
+        ),
+        Field::optional(
+            "guardrails",
+            Kind::Map(Box::new(Kind::Table(vec![
+                Field::required("min", Kind::Float),
+                Field::required("max", Kind::Float),
+                Field::required("baseline", Kind::Float),
+            ]))),
+        ),
+        Field::optional(
+            "workflow_triggers",
+            Kind::Map(Box::new(Kind::Table(vec![
+                Field::required("workflow", Kind::String),
+                Field::required("source", Kind::String),
+                Field::optional("route", Kind::String),
+                Field::optional("topic", Kind::String),
+                Field::optional("secret", Kind::String).secret(),
+                Field::optional("signature_header", Kind::String),
+                Field::optional("timestamp_header", Kind::String),
+                Field::optional("max_skew_secs", Kind::Integer).range(1.0, 86400.0),
+                Field::optional("context", Kind::Map(Box::new(Kind::String))),
+            ]))),
+        ),
+        Field::optional(
+            "workflow_schedules",
+            Kind::Map(Box::new(Kind::Table(vec![
+                Field::required("workflow", Kind::String),
+                Field::required("triggers", Kind::Array(Box::new(Kind::String))),
+                Field::optional("missed_runs", Kind::String),
+                Field::optional("max_catch_up", Kind::Integer).range(1.0, 10000.0),
+                Field::optional("grace_secs", Kind::Integer).range(0.0, u32::MAX as f64),
+            ]))),
+        ),
+        Field::optional(
+            "logging",
+            Kind::Table(vec![
+                Field::optional("level", Kind::String),
+                Field::optional("overrides", Kind::Map(Box::new(Kind::String))),
+                Field::optional("json", Kind::Boolean),
+                Field::optional("stderr", Kind::Boolean),
+                Field::optional("ring_buffer", Kind::Integer).range(0.0, 1_000_000.0),
+                Field::optional(
+                    "file",
+                    Kind::Table(vec![
+                        Field::required("path", Kind::String),
+                        Field::optional("max_bytes", Kind::Integer).range(1024.0, i64::MAX as f64),
+                        Field::optional("max_files", Kind::Integer).range(0.0, 1000.0),
+                    ]),
+                ),
+            ]),
+        ),
+        Field::optional(
+            "encryption",
+            Kind::Table(vec![
+                Field::optional("passphrase", Kind::String).secret(),
+                Field::optional("key", Kind::String).secret(),
+                Field::optional("allow_plaintext", Kind::Boolean),
+            ]),
+        ),
+        Field::optional(
+            "debug_server",
+            Kind::Table(vec![
+                Field::optional("bind", Kind::String),
+                Field::required("token", Kind::String).secret(),
+                Field::optional("max_clients", Kind::Integer).range(1.0, 64.0),
+                Field::optional("auth_timeout_secs", Kind::Integer).range(1.0, 3600.0),
+                Field::optional("max_queued", Kind::Integer).range(1.0, 65536.0),
+            ]),
+        ),
+        Field::required(
+            "game_elements",
+            Kind::Map(Box::new(Kind::Table(vec![
+                Field::required("element_type", Kind::String),
+                Field::optional("properties", Kind::Map(Box::new(Kind::String))),
+            ]))),
+        ),
+    ]
+}
+
+// Validate a parsed manifest against the schema plus cross-field rules
+pub fn validate(config: &Value) -> ValidationReport {
+    let mut report = ValidationReport::new();
+    let fields = schema();
+    check_kind(config, &Kind::Table(fields), "", &mut report);
+    check_embedding_dimension(config, &mut report);
+    check_data_residency(config, &mut report);
+    check_guardrails(config, &mut report);
+    check_workflow_triggers(config, &mut report);
+    check_workflow_schedules(config, &mut report);
+    check_logging(config, &mut report);
+    check_encryption(config, &mut report);
+    report
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+fn kind_name(kind: &Kind) -> &'static str {
+    match kind {
+        Kind::String => "a string",
+        Kind::Integer => "an integer",
+        Kind::Float => "a number",
+        Kind::Boolean => "a boolean",
+        Kind::Array(_) => "an array",
+        Kind::Table(_) | Kind::Map(_) => "a table",
+    }
+}
+
+fn check_kind(value: &Value, kind: &Kind, path: &str, report: &mut ValidationReport) {
+    let matches = matches!(
+        (kind, value),
+        (Kind::String, Value::String(_))
+            | (Kind::Integer, Value::Integer(_))
+            | (Kind::Boolean, Value::Boolean(_))
+            | (Kind::Float, Value::Float(_) | Value::Integer(_))
+            | (Kind::Array(_), Value::Array(_))
+            | (Kind::Table(_) | Kind::Map(_), Value::Table(_))
+    );
+    if !matches {
+        report.error(path, format!("expected {}, found {}", kind_name(kind), value.type_str()));
+        return;
+    }
+    match (kind, value) {
+        (Kind::Table(fields), Value::Table(table)) => {
+            for field in fields {
+                let field_path = join(path, field.name);
+                match table.get(field.name) {
+                    Some(value) => {
+                        check_kind(value, &field.kind, &field_path, report);
+                        check_range(value, field, &field_path, report);
+                    }
+                    None if field.required => report.error(&field_path, "missing required key"),
+                    None => {}
+                }
+            }
+            for key in table.keys() {
+                if !fields.iter().any(|f| f.name == key) {
+                    let message = match suggest(key, fields.iter().map(|f| f.name)) {
+                        Some(name) => format!("unknown key, did you mean '{}'?", name),
+                        None => "unknown key".to_string(),
+                    };
+                    report.warning(&join(path, key), message);
+                }
+            }
+        }
+        (Kind::Map(inner), Value::Table(table)) => {
+            for (key, value) in table {
+                check_kind(value, inner, &join(path, key), report);
+            }
+        }
+        (Kind::Array(inner), Value::Array(items)) => {
+            for (i, value) in items.iter().enumerate() {
+                check_kind(value, inner, &format!("{}[{}]", path, i), report);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn check_range(value: &Value, field: &Field, path: &str, report: &mut ValidationReport) {
+    let (Some((min, max)), Some(number)) = (field.range, as_number(value)) else {
+        return;
+    };
+    if !(min..=max).contains(&number) {
+        report.error(path, format!("{} is out of range {}..={}", number, min, max));
+    }
+}
+
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Integer(v) => Some(*v as f64),
+        Value::Float(v) => Some(*v),
+        _ => None,
+    }
+}
+
+// Dimension produced by an embedding model, where it is known
+pub fn model_dimension(model: &str) -> Option<usize> {
+    if let Some(dimension) = model.strip_prefix("hash-") {
+        return dimension.parse().ok();
+    }
+    match model {
+        "text-embedding-ada-002" | "text-embedding-3-small" => Some(1536),
+        "text-embedding-3-large" => Some(3072),
+        "all-MiniLM-L6-v2" | "bge-small-en-v1.5" => Some(384),
+        "all-mpnet-base-v2" | "bge-base-en-v1.5" => Some(768),
+        _ => None,
+    }
+}
+
+fn check_embedding_dimension(config: &Value, report: &mut ValidationReport) {
+    let section = config.get("vector_index");
+    let model = section.and_then(|s| s.get("embedding_model")).and_then(Value::as_str);
+    let dimension = section.and_then(|s| s.get("vector_dimension")).and_then(Value::as_integer);
+    match (model, dimension) {
+        (Some(model), Some(dimension)) => match model_dimension(model) {
+            Some(expected) if expected as i64 != dimension => report.error(
+                "vector_index.vector_dimension",
+                format!("{} does not match embedding model '{}', which produces {}", dimension, model, expected),
+            ),
+            None => report.warning(
+                "vector_index.embedding_model",
+                format!("unknown model '{}'; vector_dimension cannot be checked", model),
+            ),
+            _ => {}
+        },
+        (Some(model), None) if model_dimension(model).is_none() => report.warning(
+            "vector_index.embedding_model",
+            format!("unknown model '{}'; set vector_dimension explicitly", model),
+        ),
+        _ => {}
+    }
+}
+
+// Routes must resolve: known backend kinds, categories and backends, every category covered
+fn check_data_residency(config: &Value, report: &mut ValidationReport) {
+    let Some(residency) = config.get("data_residency") else { return };
+    let parsed: Result<residency::ResidencyConfig, _> = residency.clone().try_into();
+    match parsed {
+        Ok(parsed) => {
+            if let Err(err) = residency::ResidencyPolicy::from_config(&parsed) {
+                report.error("data_residency", err.to_string());
+            }
+        }
+        // Shape errors are already reported by the schema, except the backend kind
+        Err(_) => {
+            let backends = residency.get("backends").and_then(Value::as_table).into_iter().flatten();
+            for (name, backend) in backends {
+                if let Some(kind) = backend.get("kind").and_then(Value::as_str) {
+                    if !matches!(kind, "qdrant" | "agentdb" | "local") {
+                        report.error(
+                            &format!("data_residency.backends.{}.kind", name),
+                            format!("unknown kind '{}'; expected \"qdrant\", \"agentdb\" or \"local\"", kind),
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+// A safe range must be ordered and contain its baseline
+fn check_guardrails(config: &Value, report: &mut ValidationReport) {
+    let Some(guardrails) = config.get("guardrails").and_then(Value::as_table) else { return };
+    for (parameter, range) in guardrails {
+        let path = format!("guardrails.{}", parameter);
+        let (Some(min), Some(max), Some(baseline)) = (
+            range.get("min").and_then(as_number),
+            range.get("max").and_then(as_number),
+            range.get("baseline").and_then(as_number),
+        ) else {
+            continue;
+        };
+        if min > max {
+            report.error(&path, format!("min {} is above max {}", min, max));
+        } else if baseline < min || baseline > max {
+            report.error(&format!("{}.baseline", path), format!("{} is outside {}..{}", baseline, min, max));
+        }
+    }
+}
+
+// Each trigger needs the key its source uses: a route for "http", a topic for "queue"; http
+// triggers also need a secret, since anyone who can reach the port could start them otherwise
+fn check_workflow_triggers(config: &Value, report: &mut ValidationReport) {
+    let Some(triggers) = config.get("workflow_triggers").and_then(Value::as_table) else { return };
+    for (name, trigger) in triggers {
+        let path = format!("workflow_triggers.{}", name);
+        let source = trigger.get("source").and_then(Value::as_str).unwrap_or("");
+        let needed = match source {
+            "http" => "route",
+            "queue" => "topic",
+            "" => continue,
+            other => {
+                report.error(&format!("{}.source", path), format!("unknown source '{}'; expected \"http\" or \"queue\"", other));
+                continue;
+            }
+        };
+        match trigger.get(needed).and_then(Value::as_str) {
+            None => report.error(&path, format!("{} triggers need '{}'", source, needed)),
+            Some(route) if needed == "route" && !route.starts_with('/') => {
+                report.error(&format!("{}.route", path), format!("'{}' must start with '/'", route))
+            }
+            _ => {}
+        }
+        if source == "http" && trigger.get("secret").and_then(Value::as_str).unwrap_or("").is_empty() {
+            report.error(&format!("{}.secret", path), "http triggers need a secret to verify payloads with");
+        }
+    }
+}
+
+// Every trigger must be a cron expression that parses, and the missed-run policy must be known
+fn check_workflow_schedules(config: &Value, report: &mut ValidationReport) {
+    let Some(schedules) = config.get("workflow_schedules").and_then(Value::as_table) else { return };
+    for (name, schedule) in schedules {
+        let path = format!("workflow_schedules.{}", name);
+        let triggers = schedule.get("triggers").and_then(Value::as_array);
+        if triggers.is_some_and(|triggers| triggers.is_empty()) {
+            report.error(&format!("{}.triggers", path), "at least one trigger is needed");
+        }
+        for (i, trigger) in triggers.into_iter().flatten().enumerate() {
+            if let Some(Err(err)) = trigger.as_str().map(schedule::parse_trigger) {
+                report.error(&format!("{}.triggers[{}]", path, i), err.to_string());
+            }
+        }
+        if let Some(policy) = schedule.get("missed_runs").and_then(Value::as_str) {
+            if !matches!(policy, "skip" | "run_once" | "catch_up") {
+                report.error(
+                    &format!("{}.missed_runs", path),
+                    format!("unknown policy '{}'; expected \"skip\", \"run_once\" or \"catch_up\"", policy),
+                );
+            }
+        }
+    }
+}
+
+// Log levels must be ones tracing knows, including the per-target overrides
+fn check_logging(config: &Value, report: &mut ValidationReport) {
+    let Some(logging) = config.get("logging") else { return };
+    let overrides = logging.get("overrides").and_then(Value::as_table);
+    let levels = logging
+        .get("level")
+        .map(|level| ("logging.level".to_string(), level))
+        .into_iter()
+        .chain(overrides.into_iter().flatten().map(|(target, level)| (format!("logging.overrides.{}", target), level)));
+    for (path, level) in levels {
+        if let Some(level) = level.as_str() {
+            if !matches!(level.to_lowercase().as_str(), "error" | "warn" | "info" | "debug" | "trace" | "off") {
+                report.error(&path, format!("unknown level '{}'; expected error, warn, info, debug, trace or off", level));
+            }
+        }
+    }
+}
+
+// Exactly one key source, and a raw key has to decode
+fn check_encryption(config: &Value, report: &mut ValidationReport) {
+    let Some(encryption) = config.get("encryption") else { return };
+    match (encryption.get("passphrase"), encryption.get("key").and_then(Value::as_str)) {
+        (Some(_), Some(_)) => report.error("encryption", "set either 'passphrase' or 'key', not both"),
+        (None, None) => report.error("encryption", "needs a 'passphrase' or a 'key'"),
+        (None, Some(key)) if StaticKey::from_hex(key).is_err() => report.error("encryption.key", "expected 64 hex characters"),
+        _ => {}
+    }
+}
+
+// Closest candidate within a small edit distance
+fn suggest<'a>(key: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let limit = (key.chars().count() / 3).clamp(1, 3);
+    candidates
+        .map(|candidate| (edit_distance(key, candidate), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.to_lowercase().chars().collect();
+    let b: Vec<char> = b.to_lowercase().chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+// Table of every schema default
+pub fn defaults() -> Value {
+    Value::Table(table_defaults(&schema()))
+}
+
+fn table_defaults(fields: &[Field]) -> Table {
+    let mut table = Table::new();
+    for field in fields {
+        if let Some(default) = &field.default {
+            table.insert(field.name.to_string(), default.clone());
+        } else if let Kind::Table(children) = &field.kind {
+            let children = table_defaults(children);
+            if !children.is_empty() {
+                table.insert(field.name.to_string(), Value::Table(children));
+            }
+        }
+    }
+    table
+}
+
+// Overlay `top` onto `base`, merging tables key by key
+pub fn merge(base: &mut Value, top: &Value) {
+    match (base, top) {
+        (Value::Table(base), Value::Table(top)) => {
+            for (key, value) in top {
+                match base.get_mut(key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (base, top) => *base = top.clone(),
+    }
+}
+
+// Where a configuration value came from, lowest precedence first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigSource {
+    Default,
+    File(String),
+    // Name of the environment variable
+    Env(String),
+    // Set by the embedding application at runtime
+    Override,
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::Default => write!(f, "default"),
+            ConfigSource::File(name) => write!(f, "file {}", name),
+            ConfigSource::Env(var) => write!(f, "env {}", var),
+            ConfigSource::Override => write!(f, "override"),
+        }
+    }
+}
+
+// Configuration built from layers (defaults < file < environment < overrides), remembering
+// which layer set each value. Layers are applied in call order, so add them lowest first.
+#[derive(Debug, Clone)]
+pub struct LayeredConfig {
+    value: Value,
+    // Dotted path of every leaf value -> layer that set it
+    provenance: BTreeMap<String, ConfigSource>,
+}
+
+impl Default for LayeredConfig {
+    fn default() -> Self {
+        LayeredConfig::new()
+    }
+}
+
+impl LayeredConfig {
+    // Starts from the schema defaults
+    pub fn new() -> Self {
+        let mut config = LayeredConfig { value: Value::Table(Table::new()), provenance: BTreeMap::new() };
+        config.apply(&defaults(), ConfigSource::Default);
+        config
+    }
+
+    pub fn file(mut self, name: &str, contents: &Value) -> Self {
+        self.apply(contents, ConfigSource::File(name.to_string()));
+        self
+    }
+
+    // ARCADIA__VECTOR_INDEX__API_KEY=... sets vector_index.api_key. Field names are matched
+    // case-insensitively, but map keys keep their case, so ARCADIA__MEMORY_BUDGET__ENTITIES__Smith
+    // budgets the NPC "Smith". Values of string keys are taken as is; others are read as integers,
+    // floats or booleans when they parse as one.
+    pub fn env(mut self, vars: impl Iterator<Item = (String, String)>) -> Self {
+        for (name, raw) in vars {
+            let Some(path) = name.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let keys = env_keys(path);
+            if keys.iter().any(String::is_empty) {
+                continue;
+            }
+            let value = match kind_at(&keys) {
+                Some(Kind::String) => Value::String(raw),
+                _ => parse_env_value(&raw),
+            };
+            self.apply(&nest(&keys, value), ConfigSource::Env(name.clone()));
+        }
+        self
+    }
+
+    // Programmatic override of a dotted path, e.g. set("vector_index.url", "http://qdrant:6333")
+    pub fn set(&mut self, path: &str, value: impl Into<Value>) {
+        let keys: Vec<String> = path.split('.').map(str::to_string).collect();
+        self.apply(&nest(&keys, value.into()), ConfigSource::Override);
+    }
+
+    fn apply(&mut self, patch: &Value, source: ConfigSource) {
+        merge(&mut self.value, patch);
+        let mut leaves = Vec::new();
+        leaf_paths(patch, "", &mut leaves);
+        for path in leaves {
+            // A value replacing a table (or the other way round) invalidates the old entries
+            let nested = format!("{}.", path);
+            self.provenance.retain(|existing, _| !existing.starts_with(&nested) && !path.starts_with(&format!("{}.", existing)));
+            self.provenance.insert(path, source.clone());
+        }
+    }
+
+    pub fn value(&self) -> &Value {
+        &self.value
+    }
+
+    pub fn into_value(self) -> Value {
+        self.value
+    }
+
+    // Layer that set `path`, or the value containing it
+    pub fn source(&self, path: &str) -> Option<&ConfigSource> {
+        let mut path = path;
+        loop {
+            if let Some(source) = self.provenance.get(path) {
+                return Some(source);
+            }
+            path = &path[..path.rfind('.')?];
+        }
+    }
+
+    pub fn provenance(&self) -> &BTreeMap<String, ConfigSource> {
+        &self.provenance
+    }
+
+    // Schema validation with each finding attributed to the layer that set the value
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = validate(&self.value);
+        for issue in &mut report.issues {
+            if let Some(source) = self.source(&issue.path) {
+                issue.message = format!("{} (from {})", issue.message, source);
+            }
+        }
+        report
+    }
+
+    // The effective configuration as TOML with secrets masked, followed by where each value came from
+    pub fn print(&self) -> String {
+        let mut value = self.value.clone();
+        redact(&mut value, &Kind::Table(schema()));
+        let mut out = toml::to_string_pretty(&value).unwrap_or_default();
+        out.push_str("\n# provenance\n");
+        for (path, source) in &self.provenance {
+            out.push_str(&format!("# {} <- {}\n", path, source));
+        }
+        out
+    }
+}
+
+// {"a": {"b": value}} from ["a", "b"]
+fn nest(keys: &[String], value: Value) -> Value {
+    keys.iter().rev().fold(value, |inner, key| {
+        let mut table = Table::new();
+        table.insert(key.clone(), inner);
+        Value::Table(table)
+    })
+}
+
+fn leaf_paths(value: &Value, path: &str, out: &mut Vec<String>) {
+    match value {
+        Value::Table(table) if !table.is_empty() => {
+            for (key, value) in table {
+                leaf_paths(value, &join(path, key), out);
+            }
+        }
+        _ if !path.is_empty() => out.push(path.to_string()),
+        _ => {}
+    }
+}
+
+// Path segments of an environment variable: schema fields lowercased, map keys as written
+fn env_keys(path: &str) -> Vec<String> {
+    let mut kind = Some(Kind::Table(schema()));
+    path.split("__")
+        .map(|segment| {
+            let (key, next) = match kind.take() {
+                Some(Kind::Map(inner)) => (segment.to_string(), Some(*inner)),
+                Some(Kind::Table(fields)) => {
+                    let key = segment.to_lowercase();
+                    let next = fields.into_iter().find(|f| f.name == key).map(|f| f.kind);
+                    (key, next)
+                }
+                _ => (segment.to_lowercase(), None),
+            };
+            kind = next;
+            key
+        })
+        .collect()
+}
+
+// Schema kind of the key at a path, if the schema declares it
+fn kind_at(keys: &[String]) -> Option<Kind> {
+    let mut kind = Kind::Table(schema());
+    for key in keys {
+        kind = match kind {
+            Kind::Table(fields) => fields.into_iter().find(|f| f.name == key)?.kind,
+            Kind::Map(inner) => *inner,
+            _ => return None,
+        };
+    }
+    Some(kind)
+}
+
+fn parse_env_value(raw: &str) -> Value {
+    if let Ok(v) = raw.parse::<i64>() {
+        Value::Integer(v)
+    } else if let Ok(v) = raw.parse::<f64>() {
+        Value::Float(v)
+    } else if let Ok(v) = raw.parse::<bool>() {
+        Value::Boolean(v)
+    } else {
+        Value::String(raw.to_string())
+    }
+}
+
+// Defaults, then the file, then the process environment
+pub fn effective_config(file: &Value) -> Value {
+    LayeredConfig::new().file("config.toml", file).env(std::env::vars()).into_value()
+}
+
+// The effective configuration as TOML, with secrets masked and provenance listed
+pub fn print_effective_config(file: &Value) -> String {
+    LayeredConfig::new().file("config.toml", file).env(std::env::vars()).print()
+}
+
+fn redact(value: &mut Value, kind: &Kind) {
+    match (kind, value) {
+        (Kind::Table(fields), Value::Table(table)) => {
+            for field in fields {
+                if let Some(value) = table.get_mut(field.name) {
+                    if field.secret {
+                        *value = Value::String("********".to_string());
+                    } else {
+                        redact(value, &field.kind);
+                    }
+                }
+            }
+        }
+        (Kind::Map(inner), Value::Table(table)) => {
+            for (_, value) in table.iter_mut() {
+                redact(value, inner);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn env(vars: &[(&str, &str)]) -> Value {
+        let vars = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect::<Vec<_>>();
+        LayeredConfig::new().env(vars.into_iter()).into_value()
+    }
+
+    #[test]
+    fn env_lowercases_fields_but_keeps_map_keys() {
+        let config = env(&[
+            ("ARCADIA__VECTOR_INDEX__API_KEY", "k"),
+            ("ARCADIA__MEMORY_BUDGET__ENTITIES__Smith_NPC", "12"),
+            ("ARCADIA__GAME_ELEMENTS__Forge__PROPERTIES__HeatLevel", "high"),
+        ]);
+        assert_eq!(config["vector_index"]["api_key"].as_str(), Some("k"));
+        assert_eq!(config["memory_budget"]["entities"]["Smith_NPC"].as_integer(), Some(12));
+        assert_eq!(config["game_elements"]["Forge"]["properties"]["HeatLevel"].as_str(), Some("high"));
+    }
+
+    fn file(toml: &str) -> LayeredConfig {
+        LayeredConfig::new().file("test.toml", &toml.parse::<Table>().map(Value::Table).unwrap())
+    }
+
+    #[test]
+    fn print_redacts_encryption_and_debug_server_secrets() {
+        let printed = file("[encryption]\npassphrase = \"hunter2\"\n[debug_server]\ntoken = \"letmein\"\n").print();
+        assert!(!printed.contains("hunter2") && !printed.contains("letmein"));
+        assert_eq!(printed.matches("********").count(), 2);
+    }
+
+    #[test]
+    fn logging_and_encryption_sections_are_checked() {
+        let config = file("[logging]\nlevel = \"loud\"\n[logging.overrides]\n\"arcadia::gpu\" = \"debug\"\n[encryption]\nkey = \"abc\"\n");
+        let report = config.validate();
+        let paths: Vec<&str> = report.errors().map(|issue| issue.path.as_str()).collect();
+        assert!(paths.contains(&"logging.level"));
+        assert!(!paths.iter().any(|path| path.starts_with("logging.overrides")));
+        assert!(paths.contains(&"encryption.key"));
+        assert!(!paths.iter().any(|path| path.starts_with("debug_server")));
+    }
+
+    #[test]
+    fn env_keys_past_the_schema_are_lowercased() {
+        assert_eq!(env_keys("UNKNOWN__Key"), vec!["unknown", "key"]);
+    }
+}
@@ -0,0 +1,448 @@
+// API cost tracking
+//
+// Every paid call (chat completion, embedding) is priced from its token counts and charged to
+// the subsystem that made it (dialogue, search, ingest). Subsystems get a spending budget per
+// period with a warning threshold, a hard cutoff and an optional request rate limit. Callers
+// ask for admission before a call; the budgeted wrappers do this automatically and switch to a
+// fallback (cache, mock, canned text) instead of failing when a budget is exhausted.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::embeddings::{Embedder, EmbeddingError};
+use crate::generation::TextGenerator;
+
+// USD per 1000 tokens
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelPricing {
+    pub input_per_1k: f64,
+    pub output_per_1k: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct PricingTable {
+    models: HashMap<String, ModelPricing>,
+    // Used for models missing from the table so unknown spend is never counted as free
+    pub fallback: ModelPricing,
+}
+
+impl Default for PricingTable {
+    fn default() -> Self {
+        let mut table = PricingTable {
+            models: HashMap::new(),
+            fallback: ModelPricing { input_per_1k: 0.01, output_per_1k: 0.03 },
+        };
+        table.set("gpt-3.5-turbo", 0.0005, 0.0015);
+        table.set("gpt-4o-mini", 0.00015, 0.0006);
+        table.set("gpt-4o", 0.0025, 0.01);
+        table.set("text-embedding-3-small", 0.00002, 0.0);
+        table.set("text-embedding-3-large", 0.00013, 0.0);
+        table.set("text-embedding-ada-002", 0.0001, 0.0);
+        table
+    }
+}
+
+impl PricingTable {
+    pub fn set(&mut self, model: &str, input_per_1k: f64, output_per_1k: f64) {
+        self.models.insert(model.to_string(), ModelPricing { input_per_1k, output_per_1k });
+    }
+
+    pub fn cost(&self, model: &str, input_tokens: u64, output_tokens: u64) -> f64 {
+        let pricing = self.models.get(model).unwrap_or(&self.fallback);
+        (input_tokens as f64 * pricing.input_per_1k + output_tokens as f64 * pricing.output_per_1k) / 1000.0
+    }
+}
+
+// Rough token estimate for providers that do not report usage
+pub fn estimate_tokens(text: &str) -> u64 {
+    (text.chars().count() as u64 / 4).max(1)
+}
+
+#[derive(Debug, Clone)]
+pub struct Budget {
+    pub limit_usd: f64,
+    // Fraction of the limit at which Admission::Warn starts
+    pub warn_fraction: f64,
+    // Spend resets after this long
+    pub period: Duration,
+    pub max_requests_per_minute: Option<u32>,
+}
+
+impl Budget {
+    pub fn new(limit_usd: f64, period: Duration) -> Self {
+        Budget {
+            limit_usd,
+            warn_fraction: 0.8,
+            period,
+            max_requests_per_minute: None,
+        }
+    }
+
+    pub fn rate_limit(mut self, requests_per_minute: u32) -> Self {
+        self.max_requests_per_minute = Some(requests_per_minute);
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Admission {
+    Allow,
+    // Allowed, but the subsystem is past its warning threshold
+    Warn { spent: f64, limit: f64 },
+    RateLimited { retry_after: Duration },
+    // Budget exhausted; use the fallback until the period resets
+    Cutoff { spent: f64, limit: f64 },
+}
+
+impl Admission {
+    pub fn is_allowed(&self) -> bool {
+        matches!(self, Admission::Allow | Admission::Warn { .. })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SubsystemUsage {
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    // Spend in the current budget period
+    pub period_usd: f64,
+    pub total_usd: f64,
+    // Calls served by a fallback because of the budget
+    pub degraded_calls: u64,
+}
+
+struct SubsystemState {
+    usage: SubsystemUsage,
+    period_start: Instant,
+    recent_requests: VecDeque<Instant>,
+}
+
+impl SubsystemState {
+    fn new() -> Self {
+        SubsystemState {
+            usage: SubsystemUsage::default(),
+            period_start: Instant::now(),
+            recent_requests: VecDeque::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CostError {
+    BudgetExceeded { subsystem: String, spent: f64, limit: f64 },
+    RateLimited { subsystem: String, retry_after: Duration },
+}
+
+impl fmt::Display for CostError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CostError::BudgetExceeded { subsystem, spent, limit } => {
+                write!(f, "'{}' spent ${:.4} of its ${:.4} budget", subsystem, spent, limit)
+            }
+            CostError::RateLimited { subsystem, retry_after } => {
+                write!(f, "'{}' is rate limited, retry in {:?}", subsystem, retry_after)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CostError {}
+
+pub struct CostTracker {
+    pricing: PricingTable,
+    budgets: HashMap<String, Budget>,
+    subsystems: HashMap<String, SubsystemState>,
+}
+
+impl CostTracker {
+    pub fn new(pricing: PricingTable) -> Self {
+        CostTracker {
+            pricing,
+            budgets: HashMap::new(),
+            subsystems: HashMap::new(),
+        }
+    }
+
+    pub fn set_budget(&mut self, subsystem: &str, budget: Budget) {
+        self.budgets.insert(subsystem.to_string(), budget);
+    }
+
+    fn state(&mut self, subsystem: &str) -> &mut SubsystemState {
+        let state = self
+            .subsystems
+            .entry(subsystem.to_string())
+            .or_insert_with(SubsystemState::new);
+        if let Some(budget) = self.budgets.get(subsystem) {
+            if state.period_start.elapsed() >= budget.period {
+                state.period_start = Instant::now();
+                state.usage.period_usd = 0.0;
+            }
+        }
+        state
+    }
+
+    // Ask before making a paid call; an allowed admission counts towards the rate limit
+    pub fn admit(&mut self, subsystem: &str) -> Admission {
+        let budget = self.budgets.get(subsystem).cloned();
+        let state = self.state(subsystem);
+        let budget = match budget {
+            Some(budget) => budget,
+            None => return Admission::Allow,
+        };
+
+        let spent = state.usage.period_usd;
+        if spent >= budget.limit_usd {
+            return Admission::Cutoff { spent, limit: budget.limit_usd };
+        }
+        if let Some(rpm) = budget.max_requests_per_minute {
+            let minute = Duration::from_secs(60);
+            while state.recent_requests.front().is_some_and(|t| t.elapsed() >= minute) {
+                state.recent_requests.pop_front();
+            }
+            if state.recent_requests.len() >= rpm as usize {
+                let oldest = state.recent_requests.front().copied().unwrap_or_else(Instant::now);
+                return Admission::RateLimited { retry_after: minute.saturating_sub(oldest.elapsed()) };
+            }
+            state.recent_requests.push_back(Instant::now());
+        }
+        if spent >= budget.limit_usd * budget.warn_fraction {
+            Admission::Warn { spent, limit: budget.limit_usd }
+        } else {
+            Admission::Allow
+        }
+    }
+
+    // admit() as a Result, for callers without a fallback
+    pub fn require(&mut self, subsystem: &str) -> Result<Admission, CostError> {
+        match self.admit(subsystem) {
+            Admission::Cutoff { spent, limit } => Err(CostError::BudgetExceeded { subsystem: subsystem.to_string(), spent, limit }),
+            Admission::RateLimited { retry_after } => Err(CostError::RateLimited { subsystem: subsystem.to_string(), retry_after }),
+            admission => Ok(admission),
+        }
+    }
+
+    // Charge a completed call; returns its cost in USD
+    pub fn record(&mut self, subsystem: &str, model: &str, input_tokens: u64, output_tokens: u64) -> f64 {
+        let cost = self.pricing.cost(model, input_tokens, output_tokens);
+        let usage = &mut self.state(subsystem).usage;
+        usage.requests += 1;
+        usage.input_tokens += input_tokens;
+        usage.output_tokens += output_tokens;
+        usage.period_usd += cost;
+        usage.total_usd += cost;
+        cost
+    }
+
+    pub fn record_degraded(&mut self, subsystem: &str) {
+        self.state(subsystem).usage.degraded_calls += 1;
+    }
+
+    pub fn usage(&self, subsystem: &str) -> Option<&SubsystemUsage> {
+        self.subsystems.get(subsystem).map(|s| &s.usage)
+    }
+
+    pub fn total_usd(&self) -> f64 {
+        self.subsystems.values().map(|s| s.usage.total_usd).sum()
+    }
+
+    // Running totals as "cost.<subsystem>.<field>" metrics, e.g. for MetricSnapshotAdapter
+    pub fn metrics(&self) -> HashMap<String, f64> {
+        let mut metrics = HashMap::new();
+        for (name, state) in &self.subsystems {
+            let usage = &state.usage;
+            metrics.insert(format!("cost.{}.usd", name), usage.total_usd);
+            metrics.insert(format!("cost.{}.period_usd", name), usage.period_usd);
+            metrics.insert(format!("cost.{}.requests", name), usage.requests as f64);
+            metrics.insert(format!("cost.{}.tokens", name), (usage.input_tokens + usage.output_tokens) as f64);
+            metrics.insert(format!("cost.{}.degraded", name), usage.degraded_calls as f64);
+            if let Some(budget) = self.budgets.get(name) {
+                metrics.insert(format!("cost.{}.budget_used", name), usage.period_usd / budget.limit_usd.max(f64::EPSILON));
+            }
+        }
+        metrics.insert("cost.total.usd".to_string(), self.total_usd());
+        metrics
+    }
+}
+
+// TextGenerator that charges a subsystem and falls back once the budget is exhausted
+pub struct BudgetedGenerator<G: TextGenerator, F: TextGenerator> {
+    pub inner: G,
+    pub fallback: F,
+    pub model: String,
+    pub subsystem: String,
+    pub tracker: Arc<Mutex<CostTracker>>,
+}
+
+impl<G: TextGenerator, F: TextGenerator> TextGenerator for BudgetedGenerator<G, F> {
+    fn generate(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let admission = self.tracker.lock().unwrap().admit(&self.subsystem);
+        if !admission.is_allowed() {
+            self.tracker.lock().unwrap().record_degraded(&self.subsystem);
+            return self.fallback.generate(prompt);
+        }
+        let output = self.inner.generate(prompt)?;
+        self.tracker.lock().unwrap().record(
+            &self.subsystem,
+            &self.model,
+            estimate_tokens(prompt),
+            estimate_tokens(&output),
+        );
+        Ok(output)
+    }
+}
+
+// Embedder that charges a subsystem and falls back once the budget is exhausted. The fallback
+// must produce vectors of the same dimension (a cache, or a local model of matching size).
+pub struct BudgetedEmbedder<E: Embedder, F: Embedder> {
+    pub inner: E,
+    pub fallback: F,
+    pub subsystem: String,
+    pub tracker: Arc<Mutex<CostTracker>>,
+}
+
+impl<E: Embedder, F: Embedder> Embedder for BudgetedEmbedder<E, F> {
+    fn model(&self) -> &str {
+        self.inner.model()
+    }
+
+    fn dimension(&self) -> usize {
+        self.inner.dimension()
+    }
+
+    fn embed(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+        let admission = self.tracker.lock().unwrap().admit(&self.subsystem);
+        if !admission.is_allowed() {
+            self.tracker.lock().unwrap().record_degraded(&self.subsystem);
+            let vector = self.fallback.embed(text)?;
+            if vector.len() != self.dimension() {
+                return Err(EmbeddingError::DimensionMismatch { expected: self.dimension(), found: vector.len() });
+            }
+            return Ok(vector);
+        }
+        let vector = self.inner.embed(text)?;
+        self.tracker
+            .lock()
+            .unwrap()
+            .record(&self.subsystem, self.inner.model(), estimate_tokens(text), 0);
+        Ok(vector)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::HashEmbedder;
+
+    struct Fixed(&'static str);
+
+    impl TextGenerator for Fixed {
+        fn generate(&self, _prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+            Ok(self.0.to_string())
+        }
+    }
+
+    fn tracker(subsystem: &str, budget: Budget) -> CostTracker {
+        let mut pricing = PricingTable::default();
+        pricing.set("test", 1.0, 2.0);
+        let mut tracker = CostTracker::new(pricing);
+        tracker.set_budget(subsystem, budget);
+        tracker
+    }
+
+    #[test]
+    fn unknown_models_are_priced_at_the_fallback() {
+        let pricing = PricingTable::default();
+        assert!((pricing.cost("gpt-4o", 1000, 1000) - 0.0125).abs() < 1e-12);
+        assert!((pricing.cost("mystery", 1000, 1000) - 0.04).abs() < 1e-12);
+        assert_eq!(estimate_tokens(""), 1);
+        assert_eq!(estimate_tokens("twelve chars"), 3);
+    }
+
+    #[test]
+    fn admission_warns_then_cuts_off() {
+        let mut tracker = tracker("dialogue", Budget::new(1.0, Duration::from_secs(3600)));
+        assert_eq!(tracker.admit("dialogue"), Admission::Allow);
+        assert_eq!(tracker.record("dialogue", "test", 500, 150), 0.8);
+        assert_eq!(tracker.admit("dialogue"), Admission::Warn { spent: 0.8, limit: 1.0 });
+        tracker.record("dialogue", "test", 200, 0);
+        assert!(!tracker.admit("dialogue").is_allowed());
+        assert!(matches!(tracker.require("dialogue"), Err(CostError::BudgetExceeded { .. })));
+        // Subsystems without a budget are never limited
+        assert_eq!(tracker.admit("search"), Admission::Allow);
+    }
+
+    #[test]
+    fn spend_resets_each_period_but_totals_accumulate() {
+        let mut tracker = tracker("ingest", Budget::new(0.5, Duration::ZERO));
+        tracker.record("ingest", "test", 1000, 0);
+        assert!(tracker.admit("ingest").is_allowed());
+        let usage = tracker.usage("ingest").unwrap();
+        assert_eq!(usage.period_usd, 0.0);
+        assert_eq!(usage.total_usd, 1.0);
+    }
+
+    #[test]
+    fn rate_limit_counts_admitted_requests() {
+        let mut tracker = tracker("search", Budget::new(10.0, Duration::from_secs(3600)).rate_limit(2));
+        assert!(tracker.admit("search").is_allowed());
+        assert!(tracker.admit("search").is_allowed());
+        match tracker.require("search") {
+            Err(CostError::RateLimited { retry_after, .. }) => assert!(retry_after <= Duration::from_secs(60)),
+            other => panic!("expected a rate limit, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn metrics_report_usage_and_budget_share() {
+        let mut tracker = tracker("dialogue", Budget::new(2.0, Duration::from_secs(3600)));
+        tracker.record("dialogue", "test", 1000, 0);
+        tracker.record("search", "test", 0, 500);
+        tracker.record_degraded("dialogue");
+        let metrics = tracker.metrics();
+        assert_eq!(metrics["cost.dialogue.budget_used"], 0.5);
+        assert_eq!(metrics["cost.dialogue.degraded"], 1.0);
+        assert_eq!(metrics["cost.search.tokens"], 500.0);
+        assert_eq!(metrics["cost.total.usd"], 2.0);
+        assert!(!metrics.contains_key("cost.search.budget_used"));
+    }
+
+    #[test]
+    fn budgeted_generator_falls_back_when_exhausted() {
+        let tracker = Arc::new(Mutex::new(tracker("dialogue", Budget::new(0.01, Duration::from_secs(3600)))));
+        let generator = BudgetedGenerator {
+            inner: Fixed("a paid reply"),
+            fallback: Fixed("canned"),
+            model: "test".to_string(),
+            subsystem: "dialogue".to_string(),
+            tracker: tracker.clone(),
+        };
+        assert_eq!(generator.generate("a prompt of forty characters, give or ta").unwrap(), "a paid reply");
+        assert_eq!(generator.generate("again").unwrap(), "canned");
+        let tracker = tracker.lock().unwrap();
+        let usage = tracker.usage("dialogue").unwrap();
+        assert_eq!((usage.requests, usage.input_tokens, usage.output_tokens), (1, 10, 3));
+        assert_eq!(usage.degraded_calls, 1);
+    }
+
+    #[test]
+    fn budgeted_embedder_rejects_fallbacks_of_another_dimension() {
+        let mut tracker = tracker("search", Budget::new(1.0, Duration::from_secs(3600)));
+        tracker.record("search", "test", 1000, 0);
+        let tracker = Arc::new(Mutex::new(tracker));
+        let embedder = |fallback| BudgetedEmbedder {
+            inner: HashEmbedder::new(8),
+            fallback: HashEmbedder::new(fallback),
+            subsystem: "search".to_string(),
+            tracker: tracker.clone(),
+        };
+        assert_eq!(embedder(8).embed("lantern").unwrap().len(), 8);
+        assert!(matches!(
+            embedder(4).embed("lantern"),
+            Err(EmbeddingError::DimensionMismatch { expected: 8, found: 4 })
+        ));
+        assert_eq!(tracker.lock().unwrap().usage("search").unwrap().degraded_calls, 2);
+    }
+}
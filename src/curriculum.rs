@@ -0,0 +1,363 @@
+// Curriculum training for NPC agents
+//
+// A curriculum is an ordered list of training scenarios of increasing difficulty. An agent trains
+// on its current stage until its recent evaluation metrics meet the stage's graduation criteria,
+// then moves on. Scenarios can be generated from a procgen seed, and per-agent progress is kept in
+// agentdb so training survives restarts.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::agentdb::{AgentDb, AgentDbError};
+use crate::namespace::Namespace;
+use crate::rng::Rng;
+
+const PROGRESS_TABLE: &str = "curriculum_progress";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scenario {
+    pub name: String,
+    // 0..1
+    pub difficulty: f32,
+    // Procgen seed the scenario world is built from
+    pub seed: u64,
+    pub parameters: HashMap<String, f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Comparison {
+    AtLeast,
+    AtMost,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Threshold {
+    pub metric: String,
+    pub comparison: Comparison,
+    pub value: f64,
+}
+
+// When an agent may leave a stage: every threshold must hold for the mean of the last `window`
+// evaluations, after at least `min_episodes` episodes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GraduationCriteria {
+    pub thresholds: Vec<Threshold>,
+    pub min_episodes: u32,
+    pub window: usize,
+}
+
+impl GraduationCriteria {
+    pub fn new(min_episodes: u32, window: usize) -> Self {
+        GraduationCriteria {
+            thresholds: Vec::new(),
+            min_episodes,
+            window: window.max(1),
+        }
+    }
+
+    pub fn at_least(mut self, metric: &str, value: f64) -> Self {
+        self.thresholds.push(Threshold { metric: metric.to_string(), comparison: Comparison::AtLeast, value });
+        self
+    }
+
+    pub fn at_most(mut self, metric: &str, value: f64) -> Self {
+        self.thresholds.push(Threshold { metric: metric.to_string(), comparison: Comparison::AtMost, value });
+        self
+    }
+
+    pub fn is_met(&self, episodes: u32, recent: &[HashMap<String, f64>]) -> bool {
+        if episodes < self.min_episodes || recent.len() < self.window {
+            return false;
+        }
+        let window = &recent[recent.len() - self.window..];
+        self.thresholds.iter().all(|t| {
+            let values: Vec<f64> = window.iter().filter_map(|m| m.get(&t.metric).copied()).collect();
+            if values.len() < self.window {
+                return false;
+            }
+            let mean = values.iter().sum::<f64>() / values.len() as f64;
+            match t.comparison {
+                Comparison::AtLeast => mean >= t.value,
+                Comparison::AtMost => mean <= t.value,
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Stage {
+    pub scenario: Scenario,
+    pub criteria: GraduationCriteria,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Curriculum {
+    pub name: String,
+    pub stages: Vec<Stage>,
+}
+
+impl Curriculum {
+    pub fn new(name: &str) -> Self {
+        Curriculum { name: name.to_string(), stages: Vec::new() }
+    }
+
+    pub fn stage(mut self, scenario: Scenario, criteria: GraduationCriteria) -> Self {
+        self.stages.push(Stage { scenario, criteria });
+        self
+    }
+
+    // Generate `stages` scenarios from a procgen seed. Difficulty rises linearly from
+    // `difficulty.0` to `difficulty.1`; each parameter is interpolated across its range by
+    // difficulty with a little per-stage jitter.
+    pub fn generate(
+        name: &str,
+        base_seed: u64,
+        stages: usize,
+        difficulty: (f32, f32),
+        parameter_ranges: &HashMap<String, (f64, f64)>,
+        criteria: GraduationCriteria,
+    ) -> Self {
+        let mut rng = Rng::new(base_seed);
+        let mut curriculum = Curriculum::new(name);
+        for i in 0..stages {
+            let t = if stages > 1 { i as f32 / (stages - 1) as f32 } else { 1.0 };
+            let level = difficulty.0 + (difficulty.1 - difficulty.0) * t;
+            let parameters = parameter_ranges
+                .iter()
+                .map(|(key, (min, max))| {
+                    let jitter = rng.range(-0.05, 0.05);
+                    let unit = (level as f64 + jitter).clamp(0.0, 1.0);
+                    (key.clone(), min + (max - min) * unit)
+                })
+                .collect();
+            curriculum.stages.push(Stage {
+                scenario: Scenario {
+                    name: format!("{}-{}", name, i + 1),
+                    difficulty: level,
+                    seed: rng.next_u64(),
+                    parameters,
+                },
+                criteria: criteria.clone(),
+            });
+        }
+        curriculum
+    }
+}
+
+// Persisted progress of one agent through one curriculum
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AgentProgress {
+    pub agent_id: String,
+    pub curriculum: String,
+    pub stage: usize,
+    pub episodes_in_stage: u32,
+    pub total_episodes: u32,
+    // Most recent evaluations in the current stage
+    pub recent: Vec<HashMap<String, f64>>,
+    // (stage index, episodes it took) for each graduated stage
+    pub history: Vec<(usize, u32)>,
+    pub completed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProgressUpdate {
+    Continuing { stage: usize },
+    Graduated { from: usize, to: usize },
+    Completed,
+}
+
+#[derive(Debug)]
+pub enum CurriculumError {
+    UnknownCurriculum(String),
+    NotEnrolled(String),
+    Storage(AgentDbError),
+    Corrupt(serde_json::Error),
+}
+
+impl fmt::Display for CurriculumError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CurriculumError::UnknownCurriculum(name) => write!(f, "unknown curriculum '{}'", name),
+            CurriculumError::NotEnrolled(agent) => write!(f, "agent '{}' is not enrolled in a curriculum", agent),
+            CurriculumError::Storage(err) => write!(f, "{}", err),
+            CurriculumError::Corrupt(err) => write!(f, "stored curriculum progress is invalid: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for CurriculumError {}
+
+impl From<AgentDbError> for CurriculumError {
+    fn from(err: AgentDbError) -> Self {
+        CurriculumError::Storage(err)
+    }
+}
+
+impl From<serde_json::Error> for CurriculumError {
+    fn from(err: serde_json::Error) -> Self {
+        CurriculumError::Corrupt(err)
+    }
+}
+
+pub struct CurriculumTracker {
+    namespace: Namespace,
+    curricula: HashMap<String, Curriculum>,
+}
+
+impl CurriculumTracker {
+    pub fn new(namespace: Namespace) -> Self {
+        CurriculumTracker { namespace, curricula: HashMap::new() }
+    }
+
+    pub fn add_curriculum(&mut self, curriculum: Curriculum) {
+        self.curricula.insert(curriculum.name.clone(), curriculum);
+    }
+
+    // Start (or restart) an agent at the first stage
+    pub fn enroll(&self, db: &mut AgentDb, agent_id: &str, curriculum: &str) -> Result<(), CurriculumError> {
+        if !self.curricula.contains_key(curriculum) {
+            return Err(CurriculumError::UnknownCurriculum(curriculum.to_string()));
+        }
+        let progress = AgentProgress {
+            agent_id: agent_id.to_string(),
+            curriculum: curriculum.to_string(),
+            stage: 0,
+            episodes_in_stage: 0,
+            total_episodes: 0,
+            recent: Vec::new(),
+            history: Vec::new(),
+            completed: false,
+        };
+        self.save(db, &progress)
+    }
+
+    pub fn progress(&self, db: &AgentDb, agent_id: &str) -> Result<AgentProgress, CurriculumError> {
+        let value = db
+            .get(&self.namespace, PROGRESS_TABLE, agent_id)
+            .ok_or_else(|| CurriculumError::NotEnrolled(agent_id.to_string()))?;
+        Ok(serde_json::from_value(value.clone())?)
+    }
+
+    // Scenario the agent should train on next (None once the curriculum is complete)
+    pub fn current_scenario(&self, db: &AgentDb, agent_id: &str) -> Result<Option<Scenario>, CurriculumError> {
+        let progress = self.progress(db, agent_id)?;
+        if progress.completed {
+            return Ok(None);
+        }
+        let curriculum = self.curriculum(&progress.curriculum)?;
+        Ok(curriculum.stages.get(progress.stage).map(|s| s.scenario.clone()))
+    }
+
+    // Record one evaluated training episode and advance the agent if it graduated
+    pub fn record_episode(
+        &self,
+        db: &mut AgentDb,
+        agent_id: &str,
+        metrics: HashMap<String, f64>,
+    ) -> Result<ProgressUpdate, CurriculumError> {
+        let mut progress = self.progress(db, agent_id)?;
+        if progress.completed {
+            return Ok(ProgressUpdate::Completed);
+        }
+        let curriculum = self.curriculum(&progress.curriculum)?;
+        let stage = match curriculum.stages.get(progress.stage) {
+            Some(stage) => stage,
+            None => {
+                progress.completed = true;
+                self.save(db, &progress)?;
+                return Ok(ProgressUpdate::Completed);
+            }
+        };
+
+        progress.episodes_in_stage += 1;
+        progress.total_episodes += 1;
+        progress.recent.push(metrics);
+        if progress.recent.len() > stage.criteria.window {
+            progress.recent.remove(0);
+        }
+
+        let update = if stage.criteria.is_met(progress.episodes_in_stage, &progress.recent) {
+            let from = progress.stage;
+            progress.history.push((from, progress.episodes_in_stage));
+            progress.stage += 1;
+            progress.episodes_in_stage = 0;
+            progress.recent.clear();
+            if progress.stage >= curriculum.stages.len() {
+                progress.completed = true;
+                ProgressUpdate::Completed
+            } else {
+                ProgressUpdate::Graduated { from, to: progress.stage }
+            }
+        } else {
+            ProgressUpdate::Continuing { stage: progress.stage }
+        };
+        self.save(db, &progress)?;
+        Ok(update)
+    }
+
+    fn curriculum(&self, name: &str) -> Result<&Curriculum, CurriculumError> {
+        self.curricula
+            .get(name)
+            .ok_or_else(|| CurriculumError::UnknownCurriculum(name.to_string()))
+    }
+
+    fn save(&self, db: &mut AgentDb, progress: &AgentProgress) -> Result<(), CurriculumError> {
+        db.put(&self.namespace, PROGRESS_TABLE, &progress.agent_id, serde_json::to_value(progress)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn win_rate(value: f64) -> HashMap<String, f64> {
+        HashMap::from([("win_rate".to_string(), value)])
+    }
+
+    #[test]
+    fn generated_stages_ramp_difficulty_within_parameter_ranges() {
+        let ranges = HashMap::from([("enemies".to_string(), (1.0, 10.0))]);
+        let curriculum = Curriculum::generate("combat", 9, 3, (0.2, 0.8), &ranges, GraduationCriteria::new(1, 1));
+        let levels: Vec<f32> = curriculum.stages.iter().map(|s| s.scenario.difficulty).collect();
+        assert_eq!(levels, vec![0.2, 0.5, 0.8]);
+        for stage in &curriculum.stages {
+            let enemies = stage.scenario.parameters["enemies"];
+            let expected = 1.0 + 9.0 * stage.scenario.difficulty as f64;
+            assert!((enemies - expected).abs() <= 0.45 + 1e-6, "{} vs {}", enemies, expected);
+        }
+        let again = Curriculum::generate("combat", 9, 3, (0.2, 0.8), &ranges, GraduationCriteria::new(1, 1));
+        assert_eq!(again.stages[2].scenario.seed, curriculum.stages[2].scenario.seed);
+    }
+
+    #[test]
+    fn agents_graduate_on_the_recent_window_and_progress_persists() {
+        let criteria = GraduationCriteria::new(3, 2).at_least("win_rate", 0.7);
+        let curriculum = Curriculum::generate("combat", 1, 2, (0.1, 0.9), &HashMap::new(), criteria);
+        let mut tracker = CurriculumTracker::new(Namespace::new("save-1").unwrap());
+        tracker.add_curriculum(curriculum);
+        let mut db = AgentDb::new();
+        assert!(matches!(tracker.enroll(&mut db, "wolf", "missing"), Err(CurriculumError::UnknownCurriculum(_))));
+        tracker.enroll(&mut db, "wolf", "combat").unwrap();
+
+        // A strong first episode is not enough before min_episodes, a weak one drags the mean down
+        let updates: Vec<ProgressUpdate> = [0.9, 0.9, 0.3, 0.9, 0.8]
+            .iter()
+            .map(|v| tracker.record_episode(&mut db, "wolf", win_rate(*v)).unwrap())
+            .collect();
+        assert_eq!(updates[2], ProgressUpdate::Continuing { stage: 0 });
+        assert_eq!(updates[3], ProgressUpdate::Continuing { stage: 0 });
+        assert_eq!(updates[4], ProgressUpdate::Graduated { from: 0, to: 1 });
+        assert_eq!(tracker.current_scenario(&db, "wolf").unwrap().unwrap().name, "combat-2");
+
+        for _ in 0..3 {
+            tracker.record_episode(&mut db, "wolf", win_rate(1.0)).unwrap();
+        }
+        let progress = tracker.progress(&db, "wolf").unwrap();
+        assert!(progress.completed);
+        assert_eq!(progress.history, vec![(0, 5), (1, 3)]);
+        assert_eq!(tracker.record_episode(&mut db, "wolf", win_rate(1.0)).unwrap(), ProgressUpdate::Completed);
+    }
+}
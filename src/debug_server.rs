@@ -0,0 +1,509 @@
+// Live debugging server
+//
+// With the `debug-server` feature a game can open a TCP port that external inspectors connect
+// to. The protocol is newline-delimited JSON. Requests are {"id", "cmd", ...} and get a response
+// {"id", "ok", "result"} or {"id", "ok": false, "error"}; subscribed streams arrive as
+// {"stream": "decision", "npc", "decision"}.
+//
+//   auth       {"token"}              must be the first request; a wrong token closes the socket
+//   entities                          entity ids
+//   subscribe  {"npc"}                stream that NPC's decisions; unsubscribe stops it
+//   inspect    {"entity", "prefix"?}  blackboard entries
+//   override   {"entity", "key", "value"} / clear {"entity", "key"}
+//   pause, resume, step {"ticks"}     step only works while paused
+//   components {"format"?}            component graph with emergent patterns, "json" or "dot"
+//   aggregate  {"collection", "filter"?, "aggregations"}  payload counts and statistics of a
+//                                     vector collection, e.g. [{"op": "count_by", "field": "topic"}]
+//   moderation                        chat moderation stats: reviews, cache hits, verdicts, appeals
+//
+// Sockets are served on background threads, but requests are only executed inside `poll`, which
+// the game calls from its loop, so the engine state is never touched from another thread. Replies
+// and streams go through a bounded per-client queue drained by that client's writer thread, so a
+// slow inspector never stalls the game: a client whose queue is full is disconnected. Request lines
+// longer than MAX_LINE close the connection, as does not authenticating within `auth_timeout`.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender, SyncSender, TrySendError};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::ai::blackboard::{self, Blackboard};
+use crate::aggregation::{Aggregation, PayloadFilter};
+use crate::autopoietic::ComponentGraph;
+use crate::multiplayer::moderation::ModerationStats;
+use crate::vector_index::VectorIndex;
+
+// Writer recorded on blackboard changes made from the inspector
+pub const DEBUG_WRITER: &str = "debugger";
+
+// Longest request line in bytes
+pub const MAX_LINE: usize = 64 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct DebugServerConfig {
+    pub bind: String,
+    // Required; the server refuses to start without one
+    pub token: String,
+    pub max_clients: usize,
+    // Connections that have not sent a valid auth request by then are closed
+    pub auth_timeout: Duration,
+    // Outgoing messages buffered per client before it is dropped as too slow
+    pub max_queued: usize,
+}
+
+impl DebugServerConfig {
+    pub fn new(token: &str) -> Self {
+        DebugServerConfig {
+            bind: "127.0.0.1:7878".to_string(),
+            token: token.to_string(),
+            max_clients: 4,
+            auth_timeout: Duration::from_secs(5),
+            max_queued: 256,
+        }
+    }
+}
+
+// What the game exposes to the inspector
+pub trait DebugTarget {
+    fn entities(&self) -> Vec<String>;
+    fn blackboard(&mut self, entity: &str) -> Option<&mut Blackboard>;
+    // Advance the simulation by `ticks` while it is paused
+    fn step(&mut self, ticks: u32);
+    // Games without an autopoietic component graph keep the default
+    fn component_graph(&self) -> Option<&ComponentGraph> {
+        None
+    }
+    fn vector_index(&self) -> Option<&VectorIndex> {
+        None
+    }
+    // Usually ModerationDesk::stats of the chat relay's moderator
+    fn moderation(&self) -> Option<ModerationStats> {
+        None
+    }
+}
+
+enum Incoming {
+    Connected(u64, TcpStream),
+    Line(u64, String),
+    Disconnected(u64),
+}
+
+struct Client {
+    stream: TcpStream,
+    outbox: SyncSender<Vec<u8>>,
+    connected_at: Instant,
+    authenticated: bool,
+    subscriptions: HashSet<String>,
+}
+
+pub struct DebugServer {
+    config: DebugServerConfig,
+    addr: SocketAddr,
+    incoming: Receiver<Incoming>,
+    clients: HashMap<u64, Client>,
+    paused: bool,
+    stop: Arc<AtomicBool>,
+}
+
+impl DebugServer {
+    pub fn start(config: DebugServerConfig) -> io::Result<Self> {
+        if config.token.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "debug server needs an auth token"));
+        }
+        let listener = TcpListener::bind(&config.bind)?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let (tx, incoming) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let accept_stop = Arc::clone(&stop);
+        thread::Builder::new()
+            .name("arcadia-debug".to_string())
+            .spawn(move || accept_loop(listener, tx, accept_stop))?;
+        Ok(DebugServer { config, addr, incoming, clients: HashMap::new(), paused: false, stop })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    // The game should skip its own ticks while paused; the inspector steps instead
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn client_count(&self) -> usize {
+        self.clients.len()
+    }
+
+    // Serve pending requests; call once per frame from the game loop
+    pub fn poll(&mut self, target: &mut dyn DebugTarget) {
+        while let Ok(message) = self.incoming.try_recv() {
+            match message {
+                Incoming::Connected(id, stream) => {
+                    if self.clients.len() >= self.config.max_clients {
+                        let _ = stream.shutdown(Shutdown::Both);
+                    } else if let Some(client) = self.connect(stream) {
+                        self.clients.insert(id, client);
+                    }
+                }
+                Incoming::Line(id, line) => self.handle(id, &line, target),
+                Incoming::Disconnected(id) => self.disconnect(id),
+            }
+        }
+        let now = Instant::now();
+        let expired: Vec<u64> = self
+            .clients
+            .iter()
+            .filter(|(_, c)| !c.authenticated && now.duration_since(c.connected_at) >= self.config.auth_timeout)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            self.disconnect(id);
+        }
+    }
+
+    fn connect(&self, stream: TcpStream) -> Option<Client> {
+        let mut writer = stream.try_clone().ok()?;
+        let (outbox, queue) = mpsc::sync_channel::<Vec<u8>>(self.config.max_queued.max(1));
+        thread::Builder::new()
+            .name("arcadia-debug-writer".to_string())
+            .spawn(move || {
+                for line in queue {
+                    if writer.write_all(&line).is_err() {
+                        break;
+                    }
+                }
+                let _ = writer.shutdown(Shutdown::Both);
+            })
+            .ok()?;
+        Some(Client { stream, outbox, connected_at: Instant::now(), authenticated: false, subscriptions: HashSet::new() })
+    }
+
+    // Closing the socket also unblocks the client's writer thread
+    fn disconnect(&mut self, client_id: u64) {
+        if let Some(client) = self.clients.remove(&client_id) {
+            let _ = client.stream.shutdown(Shutdown::Both);
+        }
+    }
+
+    // Forward a decision to clients subscribed to `npc`
+    pub fn publish_decision(&mut self, npc: &str, decision: Value) {
+        let message = json!({ "stream": "decision", "npc": npc, "decision": decision });
+        let subscribers: Vec<u64> = self
+            .clients
+            .iter()
+            .filter(|(_, c)| c.authenticated && c.subscriptions.contains(npc))
+            .map(|(id, _)| *id)
+            .collect();
+        for id in subscribers {
+            self.send(id, &message);
+        }
+    }
+
+    fn handle(&mut self, client_id: u64, line: &str, target: &mut dyn DebugTarget) {
+        let Some(authenticated) = self.clients.get(&client_id).map(|c| c.authenticated) else { return };
+        let request: Value = match serde_json::from_str(line) {
+            Ok(request) => request,
+            Err(err) => {
+                self.send(client_id, &json!({ "ok": false, "error": format!("invalid JSON: {}", err) }));
+                return;
+            }
+        };
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let cmd = request.get("cmd").and_then(Value::as_str).unwrap_or("");
+
+        if !authenticated {
+            let token = request.get("token").and_then(Value::as_str).unwrap_or("");
+            if cmd == "auth" && constant_time_eq(token.as_bytes(), self.config.token.as_bytes()) {
+                if let Some(client) = self.clients.get_mut(&client_id) {
+                    client.authenticated = true;
+                }
+                self.send(client_id, &json!({ "id": id, "ok": true, "result": null }));
+            } else {
+                self.send(client_id, &json!({ "id": id, "ok": false, "error": "not authenticated" }));
+                // Dropping the queue lets the writer flush the error before it closes the socket
+                self.clients.remove(&client_id);
+            }
+            return;
+        }
+
+        let response = match self.execute(client_id, cmd, &request, target) {
+            Ok(result) => json!({ "id": id, "ok": true, "result": result }),
+            Err(error) => json!({ "id": id, "ok": false, "error": error }),
+        };
+        self.send(client_id, &response);
+    }
+
+    fn execute(&mut self, client_id: u64, cmd: &str, request: &Value, target: &mut dyn DebugTarget) -> Result<Value, String> {
+        let arg = |name: &str| request.get(name).and_then(Value::as_str).ok_or_else(|| format!("'{}' is required", name));
+        match cmd {
+            "entities" => Ok(json!(target.entities())),
+            "subscribe" | "unsubscribe" => {
+                let npc = arg("npc")?.to_string();
+                let client = self.clients.get_mut(&client_id).ok_or("client gone")?;
+                if cmd == "subscribe" {
+                    client.subscriptions.insert(npc);
+                } else {
+                    client.subscriptions.remove(&npc);
+                }
+                Ok(json!(client.subscriptions.iter().collect::<Vec<_>>()))
+            }
+            "inspect" => {
+                let prefix = request.get("prefix").and_then(Value::as_str).unwrap_or("");
+                let board = board(target, arg("entity")?)?;
+                let entries: serde_json::Map<String, Value> = board
+                    .entries()
+                    .filter(|(key, _)| key.starts_with(prefix))
+                    .map(|(key, value)| (key.clone(), blackboard::to_json(value)))
+                    .collect();
+                Ok(Value::Object(entries))
+            }
+            "override" => {
+                let value = request.get("value").and_then(blackboard::from_json).ok_or("'value' must be a bool, number, string or [x, y, z]")?;
+                let changed = board(target, arg("entity")?)?.set(arg("key")?, value, DEBUG_WRITER);
+                Ok(json!(changed))
+            }
+            "clear" => {
+                let removed = board(target, arg("entity")?)?.remove(arg("key")?, DEBUG_WRITER);
+                Ok(json!(removed.is_some()))
+            }
+            "pause" => {
+                self.paused = true;
+                Ok(Value::Null)
+            }
+            "resume" => {
+                self.paused = false;
+                Ok(Value::Null)
+            }
+            "step" => {
+                if !self.paused {
+                    return Err("pause the simulation before stepping".to_string());
+                }
+                let ticks = request.get("ticks").and_then(Value::as_u64).unwrap_or(1).min(u32::MAX as u64) as u32;
+                target.step(ticks);
+                Ok(json!(ticks))
+            }
+            "components" => {
+                let graph = target.component_graph().ok_or("no component graph")?;
+                let patterns = graph.detect_patterns();
+                match request.get("format").and_then(Value::as_str).unwrap_or("json") {
+                    "json" => Ok(graph.to_json(&patterns)),
+                    "dot" => Ok(json!(graph.to_dot(&patterns))),
+                    other => Err(format!("unknown format '{}'; expected \"json\" or \"dot\"", other)),
+                }
+            }
+            "aggregate" => {
+                let index = target.vector_index().ok_or("no vector index")?;
+                let collection = request.get("collection").and_then(Value::as_str).ok_or("'collection' is required")?;
+                let filter: PayloadFilter = match request.get("filter") {
+                    Some(filter) => PayloadFilter { equals: serde_json::from_value(filter.clone()).map_err(|e| e.to_string())? },
+                    None => PayloadFilter::new(),
+                };
+                let aggregations: Vec<Aggregation> =
+                    serde_json::from_value(request.get("aggregations").cloned().unwrap_or_else(|| json!([{ "op": "count" }])))
+                        .map_err(|e| e.to_string())?;
+                let results = index.aggregate(collection, &filter, &aggregations).map_err(|e| e.to_string())?;
+                Ok(json!(results))
+            }
+            "moderation" => {
+                let stats = target.moderation().ok_or("no chat moderation")?;
+                let mut result = json!(stats);
+                result["hit_rate"] = json!(stats.hit_rate());
+                Ok(result)
+            }
+            "" => Err("'cmd' is required".to_string()),
+            other => Err(format!("unknown command '{}'", other)),
+        }
+    }
+
+    // Queue a message without blocking; a client that has fallen too far behind is dropped
+    fn send(&mut self, client_id: u64, message: &Value) {
+        let Some(client) = self.clients.get(&client_id) else { return };
+        let mut line = message.to_string();
+        line.push('\n');
+        if let Err(TrySendError::Full(_) | TrySendError::Disconnected(_)) = client.outbox.try_send(line.into_bytes()) {
+            self.disconnect(client_id);
+        }
+    }
+}
+
+impl Drop for DebugServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for client in self.clients.values() {
+            let _ = client.stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+fn board<'a>(target: &'a mut dyn DebugTarget, entity: &str) -> Result<&'a mut Blackboard, String> {
+    target.blackboard(entity).ok_or_else(|| format!("unknown entity '{}'", entity))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+// Next request line, or None at end of stream, on a read error, on invalid UTF-8 or once a line
+// runs past MAX_LINE without a newline
+fn read_line(reader: &mut impl BufRead) -> Option<String> {
+    let mut buf = Vec::new();
+    let read = reader.take(MAX_LINE as u64 + 1).read_until(b'\n', &mut buf).ok()?;
+    if read == 0 || (buf.len() > MAX_LINE && buf.last() != Some(&b'\n')) {
+        return None;
+    }
+    let mut line = String::from_utf8(buf).ok()?;
+    line.truncate(line.trim_end_matches(['\r', '\n']).len());
+    Some(line)
+}
+
+fn accept_loop(listener: TcpListener, tx: Sender<Incoming>, stop: Arc<AtomicBool>) {
+    let mut next_id = 0u64;
+    while !stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((stream, _)) => {
+                next_id += 1;
+                let id = next_id;
+                let Ok(reader) = stream.try_clone() else { continue };
+                let _ = stream.set_nonblocking(false);
+                let _ = stream.set_nodelay(true);
+                if tx.send(Incoming::Connected(id, stream)).is_err() {
+                    return;
+                }
+                let tx = tx.clone();
+                thread::spawn(move || {
+                    let mut reader = BufReader::new(reader);
+                    while let Some(line) = read_line(&mut reader) {
+                        if !line.trim().is_empty() && tx.send(Incoming::Line(id, line)).is_err() {
+                            return;
+                        }
+                    }
+                    let _ = tx.send(Incoming::Disconnected(id));
+                });
+            }
+            // WouldBlock while idle; other accept errors are transient (e.g. out of descriptors)
+            Err(_) => thread::sleep(Duration::from_millis(50)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Game;
+
+    impl DebugTarget for Game {
+        fn entities(&self) -> Vec<String> {
+            vec!["guard".to_string()]
+        }
+        fn blackboard(&mut self, _entity: &str) -> Option<&mut Blackboard> {
+            None
+        }
+        fn step(&mut self, _ticks: u32) {}
+    }
+
+    fn server(configure: impl FnOnce(&mut DebugServerConfig)) -> DebugServer {
+        let mut config = DebugServerConfig::new("secret");
+        config.bind = "127.0.0.1:0".to_string();
+        configure(&mut config);
+        DebugServer::start(config).unwrap()
+    }
+
+    fn poll_until(server: &mut DebugServer, done: impl Fn(&DebugServer) -> bool) -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            server.poll(&mut Game);
+            if done(server) {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        false
+    }
+
+    fn request(stream: &mut TcpStream, reader: &mut BufReader<TcpStream>, line: &str) -> Value {
+        stream.write_all(format!("{}\n", line).as_bytes()).unwrap();
+        let mut response = String::new();
+        reader.read_line(&mut response).unwrap();
+        serde_json::from_str(&response).unwrap()
+    }
+
+    #[test]
+    fn authenticated_requests_are_answered() {
+        let mut server = server(|_| {});
+        let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        assert!(poll_until(&mut server, |s| s.client_count() == 1));
+        let handle = thread::spawn(move || {
+            let auth = request(&mut stream, &mut reader, r#"{"id": 1, "cmd": "auth", "token": "secret"}"#);
+            let entities = request(&mut stream, &mut reader, r#"{"id": 2, "cmd": "entities"}"#);
+            (auth, entities)
+        });
+        while !handle.is_finished() {
+            server.poll(&mut Game);
+            thread::sleep(Duration::from_millis(5));
+        }
+        let (auth, entities) = handle.join().unwrap();
+        assert_eq!(auth["ok"], json!(true));
+        assert_eq!(entities["result"], json!(["guard"]));
+    }
+
+    #[test]
+    fn unauthenticated_clients_time_out() {
+        let mut server = server(|config| config.auth_timeout = Duration::from_millis(50));
+        let stream = TcpStream::connect(server.local_addr()).unwrap();
+        assert!(poll_until(&mut server, |s| s.client_count() == 1));
+        assert!(poll_until(&mut server, |s| s.client_count() == 0));
+        let mut rest = Vec::new();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        assert_eq!((&stream).read_to_end(&mut rest).unwrap(), 0);
+    }
+
+    #[test]
+    fn overlong_lines_close_the_connection() {
+        let mut server = server(|_| {});
+        let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+        assert!(poll_until(&mut server, |s| s.client_count() == 1));
+        let _ = stream.write_all(&vec![b'a'; MAX_LINE + 16]);
+        assert!(poll_until(&mut server, |s| s.client_count() == 0));
+    }
+
+    #[test]
+    fn read_line_caps_length() {
+        let long = format!("{}\n", "a".repeat(MAX_LINE + 1));
+        assert_eq!(read_line(&mut BufReader::new(long.as_bytes())), None);
+        let exact = format!("{}\r\n{{}}", "a".repeat(MAX_LINE - 1));
+        let mut reader = BufReader::new(exact.as_bytes());
+        assert_eq!(read_line(&mut reader).map(|line| line.len()), Some(MAX_LINE - 1));
+        assert_eq!(read_line(&mut reader).as_deref(), Some("{}"));
+        assert_eq!(read_line(&mut reader), None);
+    }
+
+    #[test]
+    fn clients_that_stop_reading_are_dropped() {
+        let mut server = server(|config| config.max_queued = 2);
+        let mut stream = TcpStream::connect(server.local_addr()).unwrap();
+        assert!(poll_until(&mut server, |s| s.client_count() == 1));
+        stream.write_all(b"{\"cmd\": \"auth\", \"token\": \"secret\"}\n{\"cmd\": \"subscribe\", \"npc\": \"guard\"}\n").unwrap();
+        assert!(poll_until(&mut server, |s| s.clients.values().any(|c| !c.subscriptions.is_empty())));
+
+        // The client never reads, so the socket buffer fills, then the queue
+        let decision = json!({ "reasoning": "x".repeat(1 << 20) });
+        let start = Instant::now();
+        for _ in 0..256 {
+            server.publish_decision("guard", decision.clone());
+            if server.client_count() == 0 {
+                break;
+            }
+        }
+        assert_eq!(server.client_count(), 0);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
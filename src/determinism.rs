@@ -0,0 +1,596 @@
+// Determinism harness
+//
+// Replays and lockstep multiplayer need every peer to compute bit-identical simulation state.
+// The harness runs a scripted simulation for N ticks from a seed, hashes each system's state
+// after every tick and records the hashes as a golden file. Running the same script on another
+// platform (or after a refactor) and comparing against the golden file reports the first tick
+// that diverged and which system diverged first, rather than just "the replay desynced".
+//
+// Systems are ticked in a fixed order and share one seeded Rng, so the script itself is
+// reproducible. State is hashed with FNV-1a over explicit little-endian encodings; floats are
+// hashed by their bit patterns, and maps are hashed in sorted key order. Float-based systems are
+// only expected to match across platforms when built with the "deterministic" feature.
+//
+//   determinism record <golden.json> [ticks] [seed]
+//   determinism check <golden.json>
+//
+// fixtures/determinism/scripted.json is a 120-tick recording of the built-in script with seed 7;
+// this module's tests replay it, so a change to any system's state or its Rng use shows up as a
+// failing test at the tick it first diverges. Re-record it when such a change is intended.
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::ai::goap::{Action, Goal, Planner, WorldState};
+use crate::ai::influence::{InfluenceMap, InfluenceSource, RESOURCES, THREAT};
+use crate::economy::{Economy, Good, Market, TraderStrategy};
+use crate::fixed::Fixed;
+use crate::rng::Rng;
+use crate::spatial::GridSpec;
+
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+pub struct StateHasher {
+    hash: u64,
+}
+
+impl StateHasher {
+    pub fn new() -> Self {
+        StateHasher { hash: FNV_OFFSET }
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.hash ^= *byte as u64;
+            self.hash = self.hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn i64(&mut self, value: i64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.bytes(&[value as u8]);
+    }
+
+    pub fn f32(&mut self, value: f32) {
+        self.bytes(&value.to_bits().to_le_bytes());
+    }
+
+    pub fn f64(&mut self, value: f64) {
+        self.bytes(&value.to_bits().to_le_bytes());
+    }
+
+    // Length-prefixed so ("ab", "c") and ("a", "bc") hash differently
+    pub fn str(&mut self, value: &str) {
+        self.u64(value.len() as u64);
+        self.bytes(value.as_bytes());
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+impl Default for StateHasher {
+    fn default() -> Self {
+        StateHasher::new()
+    }
+}
+
+// One part of the simulation under test
+pub trait SimSystem {
+    fn name(&self) -> &str;
+    fn tick(&mut self, tick: u64, rng: &mut Rng);
+    fn hash(&self, hasher: &mut StateHasher);
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GoldenRun {
+    // Where the recording was made, e.g. "x86_64-linux"
+    pub platform: String,
+    pub features: Vec<String>,
+    pub seed: u64,
+    pub systems: Vec<String>,
+    // Per tick, one hash per system in `systems` order
+    pub hashes: Vec<Vec<u64>>,
+}
+
+impl GoldenRun {
+    pub fn ticks(&self) -> usize {
+        self.hashes.len()
+    }
+
+    // Hash of the whole world at a tick
+    pub fn world_hash(&self, tick: usize) -> Option<u64> {
+        let mut hasher = StateHasher::new();
+        for hash in self.hashes.get(tick)? {
+            hasher.u64(*hash);
+        }
+        Some(hasher.finish())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, DeterminismError> {
+        let text = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&text)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), DeterminismError> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub tick: usize,
+    // First system in tick order whose hash differs
+    pub system: String,
+    pub expected: u64,
+    pub actual: u64,
+    // Every system that differs at that tick
+    pub also_diverged: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DeterminismReport {
+    pub golden_platform: String,
+    pub platform: String,
+    pub ticks_compared: usize,
+    pub divergence: Option<Divergence>,
+}
+
+impl DeterminismReport {
+    pub fn is_deterministic(&self) -> bool {
+        self.divergence.is_none()
+    }
+}
+
+impl fmt::Display for DeterminismReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.divergence {
+            None => writeln!(
+                f,
+                "{} ticks identical ({} vs golden from {})",
+                self.ticks_compared, self.platform, self.golden_platform
+            ),
+            Some(d) => {
+                writeln!(
+                    f,
+                    "diverged at tick {}: {} expected {:016x}, got {:016x} ({} vs golden from {})",
+                    d.tick, d.system, d.expected, d.actual, self.platform, self.golden_platform
+                )?;
+                if !d.also_diverged.is_empty() {
+                    writeln!(f, "  also diverged at that tick: {}", d.also_diverged.join(", "))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum DeterminismError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+    // The golden file was recorded with a different set of systems
+    SystemMismatch { expected: Vec<String>, actual: Vec<String> },
+    // Returned by the `check` command so it can exit non-zero
+    Diverged(Box<DeterminismReport>),
+    Usage(String),
+}
+
+impl fmt::Display for DeterminismError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeterminismError::Io(err) => write!(f, "golden file io error: {}", err),
+            DeterminismError::Parse(err) => write!(f, "invalid golden file: {}", err),
+            DeterminismError::SystemMismatch { expected, actual } => write!(
+                f,
+                "golden file covers systems [{}] but the harness runs [{}]",
+                expected.join(", "),
+                actual.join(", ")
+            ),
+            DeterminismError::Diverged(report) => write!(f, "{}", report.to_string().trim_end()),
+            DeterminismError::Usage(usage) => write!(f, "{}", usage),
+        }
+    }
+}
+
+impl Error for DeterminismError {}
+
+impl From<std::io::Error> for DeterminismError {
+    fn from(err: std::io::Error) -> Self {
+        DeterminismError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for DeterminismError {
+    fn from(err: serde_json::Error) -> Self {
+        DeterminismError::Parse(err)
+    }
+}
+
+pub struct Harness {
+    seed: u64,
+    rng: Rng,
+    systems: Vec<Box<dyn SimSystem>>,
+}
+
+impl Harness {
+    pub fn new(seed: u64) -> Self {
+        Harness { seed, rng: Rng::new(seed), systems: Vec::new() }
+    }
+
+    // The built-in script: economy, influence map and GOAP planning fed by one Rng
+    pub fn scripted(seed: u64) -> Self {
+        Harness::new(seed)
+            .with_system(RngSystem { draws: Vec::new() })
+            .with_system(EconomySystem::new())
+            .with_system(InfluenceSystem::new())
+            .with_system(PlanningSystem::new())
+            .with_system(FixedSystem { accumulator: Fixed::ZERO })
+    }
+
+    pub fn with_system(mut self, system: impl SimSystem + 'static) -> Self {
+        self.systems.push(Box::new(system));
+        self
+    }
+
+    pub fn system_names(&self) -> Vec<String> {
+        self.systems.iter().map(|s| s.name().to_string()).collect()
+    }
+
+    pub fn run(&mut self, ticks: usize) -> GoldenRun {
+        let mut hashes = Vec::with_capacity(ticks);
+        for tick in 0..ticks {
+            hashes.push(self.step(tick as u64));
+        }
+        GoldenRun {
+            platform: platform(),
+            features: crate::capabilities::capabilities().features.into_iter().map(str::to_string).collect(),
+            seed: self.seed,
+            systems: self.system_names(),
+            hashes,
+        }
+    }
+
+    // Replay the golden file's seed and tick count, stopping at the first divergent tick
+    pub fn compare(mut self, golden: &GoldenRun) -> Result<DeterminismReport, DeterminismError> {
+        let systems = self.system_names();
+        if systems != golden.systems {
+            return Err(DeterminismError::SystemMismatch { expected: golden.systems.clone(), actual: systems });
+        }
+        self.rng = Rng::new(golden.seed);
+        self.seed = golden.seed;
+        let mut report =
+            DeterminismReport { golden_platform: golden.platform.clone(), platform: platform(), ticks_compared: 0, divergence: None };
+        for (tick, expected) in golden.hashes.iter().enumerate() {
+            let actual = self.step(tick as u64);
+            report.ticks_compared += 1;
+            let differing: Vec<usize> = (0..systems.len()).filter(|&i| expected.get(i) != actual.get(i)).collect();
+            if let Some(&first) = differing.first() {
+                report.divergence = Some(Divergence {
+                    tick,
+                    system: systems[first].clone(),
+                    expected: expected.get(first).copied().unwrap_or(0),
+                    actual: actual[first],
+                    also_diverged: differing[1..].iter().map(|&i| systems[i].clone()).collect(),
+                });
+                break;
+            }
+        }
+        Ok(report)
+    }
+
+    fn step(&mut self, tick: u64) -> Vec<u64> {
+        self.systems
+            .iter_mut()
+            .map(|system| {
+                system.tick(tick, &mut self.rng);
+                let mut hasher = StateHasher::new();
+                system.hash(&mut hasher);
+                hasher.finish()
+            })
+            .collect()
+    }
+}
+
+pub fn platform() -> String {
+    format!("{}-{}", std::env::consts::ARCH, std::env::consts::OS)
+}
+
+// `determinism record <golden.json> [ticks] [seed]` / `determinism check <golden.json>`
+pub fn run_determinism_command(args: &[String]) -> Result<String, DeterminismError> {
+    let usage = || DeterminismError::Usage("usage: determinism record <golden.json> [ticks] [seed] | check <golden.json>".to_string());
+    let path = Path::new(args.get(1).ok_or_else(usage)?);
+    match args.first().map(String::as_str) {
+        Some("record") => {
+            let ticks = args.get(2).and_then(|a| a.parse().ok()).unwrap_or(600);
+            let seed = args.get(3).and_then(|a| a.parse().ok()).unwrap_or(1);
+            let golden = Harness::scripted(seed).run(ticks);
+            golden.save(path)?;
+            Ok(format!("recorded {} ticks of [{}] on {} to {}\n", ticks, golden.systems.join(", "), golden.platform, path.display()))
+        }
+        Some("check") => {
+            let golden = GoldenRun::load(path)?;
+            let report = Harness::scripted(golden.seed).compare(&golden)?;
+            if report.is_deterministic() {
+                Ok(report.to_string())
+            } else {
+                Err(DeterminismError::Diverged(Box::new(report)))
+            }
+        }
+        _ => Err(usage()),
+    }
+}
+
+// Raw draws from the shared stream, so a divergent Rng is reported as such
+struct RngSystem {
+    draws: Vec<u64>,
+}
+
+impl SimSystem for RngSystem {
+    fn name(&self) -> &str {
+        "rng"
+    }
+
+    fn tick(&mut self, _tick: u64, rng: &mut Rng) {
+        self.draws = (0..4).map(|_| rng.next_u64()).collect();
+    }
+
+    fn hash(&self, hasher: &mut StateHasher) {
+        for draw in &self.draws {
+            hasher.u64(*draw);
+        }
+    }
+}
+
+// One trader per region, since traders sharing a market restock in map order
+struct EconomySystem {
+    economy: Economy,
+    regions: Vec<String>,
+    player_money: f64,
+}
+
+impl EconomySystem {
+    fn new() -> Self {
+        let mut economy = Economy::new();
+        let goods = [("grain", 2.0, 0.8), ("iron", 9.0, 1.2)];
+        for (id, base_price, elasticity) in goods {
+            economy.add_good(Good { id: id.to_string(), base_price, elasticity });
+        }
+        let regions: Vec<String> = ["vale", "harbor", "peaks"].iter().map(|r| r.to_string()).collect();
+        for (i, region) in regions.iter().enumerate() {
+            economy.add_region(region);
+            for (good, _, _) in goods {
+                let _ = economy.set_market(region, good, Market::new(100.0 + 40.0 * i as f64, 80.0 + 30.0 * i as f64));
+            }
+            let _ = economy.add_trader(&format!("trader_{}", region), region, 500.0, TraderStrategy::default());
+        }
+        EconomySystem { economy, regions, player_money: 10_000.0 }
+    }
+}
+
+impl SimSystem for EconomySystem {
+    fn name(&self) -> &str {
+        "economy"
+    }
+
+    fn tick(&mut self, _tick: u64, rng: &mut Rng) {
+        self.economy.tick(0.25);
+        if rng.chance(0.3) {
+            let region = &self.regions[rng.below(self.regions.len())];
+            let good = if rng.chance(0.5) { "grain" } else { "iron" };
+            let quantity = 1.0 + rng.below(3) as f64;
+            let trader = format!("trader_{}", region);
+            if rng.chance(0.5) {
+                let _ = self.economy.buy(&trader, good, quantity, &mut self.player_money);
+            } else {
+                let _ = self.economy.sell(&trader, good, quantity, &mut self.player_money);
+            }
+        }
+    }
+
+    fn hash(&self, hasher: &mut StateHasher) {
+        hasher.f64(self.player_money);
+        let mut regions: Vec<_> = self.economy.regions().collect();
+        regions.sort_by(|a, b| a.name.cmp(&b.name));
+        for region in regions {
+            hasher.str(&region.name);
+            let mut markets: Vec<_> = region.markets.iter().collect();
+            markets.sort_by(|a, b| a.0.cmp(b.0));
+            for (good, market) in markets {
+                hasher.str(good);
+                hasher.f64(market.supply);
+                hasher.f64(market.demand);
+                hasher.f64(market.price);
+            }
+        }
+        let mut traders = self.economy.trader_ids();
+        traders.sort();
+        for id in traders {
+            let Some(trader) = self.economy.trader(id) else { continue };
+            hasher.str(id);
+            hasher.f64(trader.money);
+            for goods in [&trader.inventory, &trader.prices] {
+                let mut goods: Vec<_> = goods.iter().collect();
+                goods.sort_by(|a, b| a.0.cmp(b.0));
+                for (good, value) in goods {
+                    hasher.str(good);
+                    hasher.f64(*value);
+                }
+            }
+        }
+    }
+}
+
+// Wandering threat and resource sources over a small grid
+struct InfluenceSystem {
+    map: InfluenceMap,
+    positions: Vec<[f32; 2]>,
+}
+
+impl InfluenceSystem {
+    fn new() -> Self {
+        let mut map = InfluenceMap::with_default_layers(GridSpec::new(16, 16, 4.0));
+        let positions = vec![[8.0, 8.0], [40.0, 20.0], [20.0, 52.0]];
+        for (i, position) in positions.iter().enumerate() {
+            let layer = if i == 0 { RESOURCES } else { THREAT };
+            map.set_source(&format!("source_{}", i), InfluenceSource::new(layer, *position, 1.0, 12.0));
+        }
+        InfluenceSystem { map, positions }
+    }
+}
+
+impl SimSystem for InfluenceSystem {
+    fn name(&self) -> &str {
+        "influence"
+    }
+
+    fn tick(&mut self, _tick: u64, rng: &mut Rng) {
+        for (i, position) in self.positions.iter_mut().enumerate() {
+            position[0] = (position[0] + rng.range(-2.0, 2.0) as f32).clamp(0.0, 63.0);
+            position[1] = (position[1] + rng.range(-2.0, 2.0) as f32).clamp(0.0, 63.0);
+            self.map.move_source(&format!("source_{}", i), *position);
+        }
+        self.map.update(0.1);
+    }
+
+    fn hash(&self, hasher: &mut StateHasher) {
+        let grid = *self.map.grid();
+        let mut layers: Vec<&str> = self.map.layer_names().collect();
+        layers.sort();
+        for layer in layers {
+            hasher.str(layer);
+            for index in 0..grid.len() {
+                hasher.f32(self.map.value_at(layer, grid.cell_at(index)));
+            }
+        }
+    }
+}
+
+// Re-plans every tick from randomly perturbed facts
+struct PlanningSystem {
+    planner: Planner,
+    state: WorldState,
+    last_plan: Vec<String>,
+    last_cost: f32,
+}
+
+impl PlanningSystem {
+    fn new() -> Self {
+        let planner = Planner::new(vec![
+            Action::new("fetch_axe", 2.0).requires("axe_nearby", true).effect("has_axe", true),
+            Action::new("chop", 1.5).requires("has_axe", true).effect("has_wood", true),
+            Action::new("scavenge", 4.0).effect("has_wood", true),
+            Action::new("build_fire", 1.0).requires("has_wood", true).effect("warm", true),
+            Action::new("flee", 0.5).requires("threat_high", true).effect("threat_high", false),
+        ])
+        .expect("built-in action costs are valid");
+        PlanningSystem { planner, state: WorldState::new(), last_plan: Vec::new(), last_cost: 0.0 }
+    }
+}
+
+impl SimSystem for PlanningSystem {
+    fn name(&self) -> &str {
+        "goap"
+    }
+
+    fn tick(&mut self, _tick: u64, rng: &mut Rng) {
+        self.state = WorldState::new()
+            .with("axe_nearby", rng.chance(0.6))
+            .with("has_axe", rng.chance(0.2))
+            .with("threat_high", rng.chance(0.3))
+            .with("warm", false);
+        let goal = Goal::new("stay_warm", 1.0).wants("warm", true).wants("threat_high", false);
+        let plan = self.planner.plan(&self.state, &goal);
+        self.last_plan = plan.as_ref().map(|p| p.action_names().into_iter().map(str::to_string).collect()).unwrap_or_default();
+        self.last_cost = plan.map_or(-1.0, |p| p.cost);
+    }
+
+    fn hash(&self, hasher: &mut StateHasher) {
+        for (key, value) in self.state.iter() {
+            hasher.str(key);
+            hasher.str(&format!("{:?}", value));
+        }
+        for action in &self.last_plan {
+            hasher.str(action);
+        }
+        hasher.f32(self.last_cost);
+    }
+}
+
+// Fixed-point arithmetic on random inputs
+struct FixedSystem {
+    accumulator: Fixed,
+}
+
+impl SimSystem for FixedSystem {
+    fn name(&self) -> &str {
+        "fixed"
+    }
+
+    fn tick(&mut self, _tick: u64, rng: &mut Rng) {
+        let weights: Vec<Fixed> = (0..4).map(|_| Fixed::from_ratio(1 + rng.below(100) as i64, 7)).collect();
+        let sample = Fixed::entropy(&weights) + Fixed::from_ratio(rng.below(1000) as i64, 1000).sqrt();
+        self.accumulator = (self.accumulator + sample) / Fixed::from_int(2);
+    }
+
+    fn hash(&self, hasher: &mut StateHasher) {
+        hasher.i64(self.accumulator.raw());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPTED: &str = include_str!("../fixtures/determinism/scripted.json");
+
+    #[test]
+    fn scripted_run_matches_golden_file() {
+        let golden: GoldenRun = serde_json::from_str(SCRIPTED).unwrap();
+        assert_eq!((golden.seed, golden.ticks()), (7, 120));
+        let report = Harness::scripted(golden.seed).compare(&golden).unwrap();
+        assert!(report.is_deterministic(), "{}", report);
+        assert_eq!(report.ticks_compared, 120);
+    }
+
+    #[test]
+    fn recording_twice_gives_the_same_hashes() {
+        assert_eq!(Harness::scripted(3).run(20).hashes, Harness::scripted(3).run(20).hashes);
+        assert_ne!(Harness::scripted(3).run(20).hashes, Harness::scripted(4).run(20).hashes);
+    }
+
+    #[test]
+    fn compare_reports_the_first_divergent_tick_and_system() {
+        let mut golden: GoldenRun = serde_json::from_str(SCRIPTED).unwrap();
+        golden.hashes[40][1] ^= 1;
+        golden.hashes[40][3] ^= 1;
+        golden.hashes[60][0] ^= 1;
+        let report = Harness::scripted(golden.seed).compare(&golden).unwrap();
+        let divergence = report.divergence.unwrap();
+        assert_eq!((divergence.tick, divergence.system.as_str()), (40, "economy"));
+        assert_eq!(divergence.also_diverged, vec!["goap".to_string()]);
+        assert_eq!(report.ticks_compared, 41);
+    }
+
+    #[test]
+    fn compare_rejects_a_different_system_list() {
+        let mut golden: GoldenRun = serde_json::from_str(SCRIPTED).unwrap();
+        golden.systems.pop();
+        let err = Harness::scripted(golden.seed).compare(&golden).unwrap_err();
+        assert!(matches!(err, DeterminismError::SystemMismatch { .. }));
+    }
+}
@@ -0,0 +1,528 @@
+// Ambient barks
+//
+// Short one-liners background NPCs say to make a place feel alive ("Storm's coming in.",
+// "Keep your voice down."). Designers author template pools in TOML, keyed by trigger (idle,
+// combat, weather, ...) and filtered by the same world state conditions dialogue trees use, plus
+// a mood range. Lines may contain {placeholders} filled from the caller's variables.
+//
+// Loading screens can top the pool up with generated lines: `pregenerate` asks the LLM for a
+// batch per trigger and mood and stores them in the vector index with the mood as the vector, so
+// at runtime the closest generated lines for the current mood are one search away.
+//
+// A bark isn't repeated within `radius` of where it was last said until its cooldown has passed.
+//
+// With a content rating enforcer (`with_rating`) and a listener in the context, only lines the
+// listener's rating profile allows are candidates: authored lines are checked by their tags,
+// generated ones by the rating lexicon, and every check is audited.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::dialogue::tree::{Condition, DialogueContext};
+use crate::emotion::MoodVector;
+use crate::events::EventBus;
+use crate::generation::TextGenerator;
+use crate::prompts::{self, PromptError, PromptRegistry};
+use crate::rating::{ContentRating, ContentTags, Surface};
+use crate::rng::Rng;
+use crate::vector_index::{VectorIndex, VectorIndexError, VectorPoint, TEXT_FIELD};
+
+pub const BARK_COLLECTION: &str = "barks";
+pub const BARK_TOPIC: &str = "dialogue.bark";
+
+const TRIGGER_FIELD: &str = "trigger";
+
+fn default_weight() -> f64 {
+    1.0
+}
+
+// One authored line
+#[derive(Debug, Clone, Deserialize)]
+pub struct BarkTemplate {
+    pub trigger: String,
+    pub text: String,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    // Mood range the line fits; unset bounds are open
+    #[serde(default)]
+    pub min_tension: Option<f32>,
+    #[serde(default)]
+    pub max_tension: Option<f32>,
+    #[serde(default)]
+    pub min_valence: Option<f32>,
+    #[serde(default)]
+    pub max_valence: Option<f32>,
+    #[serde(default = "default_weight")]
+    pub weight: f64,
+    #[serde(default)]
+    pub tags: ContentTags,
+}
+
+impl BarkTemplate {
+    fn fits(&self, mood: MoodVector) -> bool {
+        self.min_tension.is_none_or(|min| mood.tension >= min)
+            && self.max_tension.is_none_or(|max| mood.tension <= max)
+            && self.min_valence.is_none_or(|min| mood.valence >= min)
+            && self.max_valence.is_none_or(|max| mood.valence <= max)
+    }
+
+    fn render(&self, vars: &HashMap<String, String>) -> Option<String> {
+        let mut text = self.text.clone();
+        for (key, value) in vars {
+            text = text.replace(&format!("{{{}}}", key), value);
+        }
+        // A placeholder the caller can't fill would be read out verbatim
+        (!text.contains('{')).then_some(text)
+    }
+}
+
+// Templates as loaded from TOML ([[bark]] tables)
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct BarkPool {
+    #[serde(default, rename = "bark")]
+    pub templates: Vec<BarkTemplate>,
+}
+
+impl BarkPool {
+    pub fn from_toml(contents: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(contents)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BarkConfig {
+    pub cooldown_secs: f64,
+    // Distance within which the same line counts as a repeat
+    pub radius: f32,
+    // Chance of using a generated line when both kinds are available
+    pub generated_share: f64,
+    // Generated lines considered per bark
+    pub generated_candidates: usize,
+    pub max_words: usize,
+}
+
+impl Default for BarkConfig {
+    fn default() -> Self {
+        BarkConfig { cooldown_secs: 60.0, radius: 25.0, generated_share: 0.5, generated_candidates: 8, max_words: 14 }
+    }
+}
+
+// Where and when a bark would be said
+#[derive(Debug, Clone)]
+pub struct BarkContext<'a> {
+    pub speaker: &'a str,
+    // Player within earshot whose content rating applies
+    pub listener: Option<&'a str>,
+    pub trigger: &'a str,
+    pub position: (f32, f32),
+    pub mood: MoodVector,
+    pub world: &'a DialogueContext,
+    pub vars: &'a HashMap<String, String>,
+    // Seconds, on any monotonic clock
+    pub now: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bark {
+    pub speaker: String,
+    pub text: String,
+    pub generated: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Spoken {
+    text: String,
+    position: (f32, f32),
+    at: f64,
+}
+
+// A batch of lines to generate for one trigger and mood
+#[derive(Debug, Clone)]
+pub struct BarkRequest {
+    pub trigger: String,
+    pub mood: MoodVector,
+    // Setting and speaker description for the prompt
+    pub setting: String,
+    pub count: usize,
+}
+
+#[derive(Debug)]
+pub enum BarkError {
+    Generation(String),
+    Index(VectorIndexError),
+}
+
+impl fmt::Display for BarkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BarkError::Generation(msg) => write!(f, "bark generation failed: {}", msg),
+            BarkError::Index(err) => write!(f, "bark storage failed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for BarkError {}
+
+impl From<VectorIndexError> for BarkError {
+    fn from(err: VectorIndexError) -> Self {
+        BarkError::Index(err)
+    }
+}
+
+// Mood as a point for the bark collection; the constant component keeps neutral moods off the origin
+fn mood_vector(mood: MoodVector) -> Vec<f32> {
+    vec![mood.tension, mood.valence, mood.energy, 1.0]
+}
+
+pub struct AmbientBarks {
+    pool: BarkPool,
+    config: BarkConfig,
+    recent: VecDeque<Spoken>,
+    prompts: Option<Arc<PromptRegistry>>,
+    rating: Option<Arc<Mutex<ContentRating>>>,
+}
+
+impl AmbientBarks {
+    pub fn new(pool: BarkPool, config: BarkConfig) -> Self {
+        AmbientBarks { pool, config, recent: VecDeque::new(), prompts: None, rating: None }
+    }
+
+    // Only bark lines the listener's content rating allows
+    pub fn with_rating(mut self, rating: Arc<Mutex<ContentRating>>) -> Self {
+        self.rating = Some(rating);
+        self
+    }
+
+    // Whether the listener may hear `text`; true without an enforcer or a listener
+    fn permits(&self, ctx: &BarkContext, text: &str, tags: Option<&ContentTags>) -> bool {
+        let (Some(rating), Some(listener)) = (&self.rating, ctx.listener) else { return true };
+        let mut rating = rating.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match tags {
+            Some(tags) => rating.check(listener, Surface::Dialogue, text, tags),
+            None => rating.check_generated(listener, Surface::Dialogue, text, text, None),
+        }
+    }
+
+    // Render the generation prompt through a shared registry instead of the built-in template
+    pub fn with_prompts(mut self, prompts: Arc<PromptRegistry>) -> Self {
+        self.prompts = Some(prompts);
+        self
+    }
+
+    fn said_nearby(&self, text: &str, position: (f32, f32)) -> bool {
+        let r2 = self.config.radius * self.config.radius;
+        self.recent.iter().any(|s| {
+            let (dx, dy) = (s.position.0 - position.0, s.position.1 - position.1);
+            s.text == text && dx * dx + dy * dy <= r2
+        })
+    }
+
+    fn authored(&self, ctx: &BarkContext) -> Vec<(String, f64)> {
+        self.pool
+            .templates
+            .iter()
+            .filter(|t| t.trigger == ctx.trigger && t.fits(ctx.mood) && t.conditions.iter().all(|c| c.is_met(ctx.world)))
+            .filter_map(|t| Some((t.render(ctx.vars)?, t)))
+            .filter(|(text, _)| !self.said_nearby(text, ctx.position))
+            .filter(|(text, t)| self.permits(ctx, text, Some(&t.tags)))
+            .map(|(text, t)| (text, t.weight))
+            .collect()
+    }
+
+    fn generated(&self, ctx: &BarkContext, index: &VectorIndex) -> Vec<String> {
+        let Ok(hits) = index.search(BARK_COLLECTION, &mood_vector(ctx.mood), self.config.generated_candidates * 4) else {
+            return Vec::new();
+        };
+        hits.into_iter()
+            .filter(|hit| hit.payload.get(TRIGGER_FIELD).and_then(Value::as_str) == Some(ctx.trigger))
+            .filter_map(|hit| hit.payload.get(TEXT_FIELD).and_then(Value::as_str).map(str::to_string))
+            .filter(|text| !self.said_nearby(text, ctx.position))
+            .take(self.config.generated_candidates)
+            .filter(|text| self.permits(ctx, text, None))
+            .collect()
+    }
+
+    // Pick a line for `ctx`, or None if everything that fits was said nearby too recently
+    pub fn bark(
+        &mut self,
+        ctx: &BarkContext,
+        index: Option<&VectorIndex>,
+        rng: &mut Rng,
+        events: Option<&mut EventBus>,
+    ) -> Option<Bark> {
+        self.recent.retain(|s| ctx.now - s.at < self.config.cooldown_secs);
+
+        let authored = self.authored(ctx);
+        let generated = index.map(|index| self.generated(ctx, index)).unwrap_or_default();
+        let use_generated = !generated.is_empty() && (authored.is_empty() || rng.chance(self.config.generated_share));
+        let (text, is_generated) = if use_generated {
+            (rng.choose(&generated)?.clone(), true)
+        } else {
+            let weights: Vec<f64> = authored.iter().map(|(_, w)| *w).collect();
+            (authored[rng.weighted_index(&weights)?].0.clone(), false)
+        };
+
+        self.recent.push_back(Spoken { text: text.clone(), position: ctx.position, at: ctx.now });
+        if let Some(events) = events {
+            events.emit(
+                BARK_TOPIC,
+                ctx.speaker,
+                json!({ "trigger": ctx.trigger, "text": text, "generated": is_generated }),
+            );
+        }
+        Some(Bark { speaker: ctx.speaker.to_string(), text, generated: is_generated })
+    }
+
+    // Generate lines for each request and store them in the bark collection; meant for loading
+    // screens. Returns how many lines were stored.
+    pub fn pregenerate<G: TextGenerator>(
+        &self,
+        generator: &G,
+        index: &mut VectorIndex,
+        requests: &[BarkRequest],
+    ) -> Result<usize, BarkError> {
+        if !index.collection_names().contains(&BARK_COLLECTION) {
+            index.create_collection(BARK_COLLECTION, 4, "mood")?;
+        }
+        let mut stored = 0;
+        for request in requests {
+            let prompt = bark_prompt(request, self.config.max_words, self.prompts.as_deref())
+                .map_err(|err| BarkError::Generation(err.to_string()))?;
+            let raw = generator.generate(&prompt).map_err(|err| BarkError::Generation(err.to_string()))?;
+            let mut lines = parse_lines(&raw, self.config.max_words);
+            lines.dedup();
+            for line in lines.into_iter().take(request.count) {
+                let id = format!("bark-{}-{}", request.trigger, index.len(BARK_COLLECTION)?);
+                let mut point = VectorPoint::new(&id, mood_vector(request.mood));
+                point.payload.insert(TEXT_FIELD.to_string(), Value::from(line));
+                point.payload.insert(TRIGGER_FIELD.to_string(), Value::from(request.trigger.as_str()));
+                // Lines of one batch share a vector, so near-duplicate detection doesn't apply
+                index.upsert(BARK_COLLECTION, point)?;
+                stored += 1;
+            }
+        }
+        Ok(stored)
+    }
+}
+
+fn bark_prompt(request: &BarkRequest, max_words: usize, registry: Option<&PromptRegistry>) -> Result<String, PromptError> {
+    let vars = [
+        ("count", json!(request.count)),
+        ("setting", json!(request.setting)),
+        ("trigger", json!(request.trigger)),
+        ("tension", json!(request.mood.tension)),
+        ("valence", json!(request.mood.valence)),
+        ("energy", json!(request.mood.energy)),
+        ("max_words", json!(max_words)),
+    ];
+    prompts::render(registry, prompts::BARK_LINES, &vars)
+}
+
+// One bark per line, with list markers and quotes stripped; over-long lines are dropped
+fn parse_lines(raw: &str, max_words: usize) -> Vec<String> {
+    raw.lines()
+        .map(|line| {
+            line.trim()
+                .trim_start_matches(|c: char| c.is_ascii_digit() || matches!(c, '-' | '*' | '.' | ')'))
+                .trim()
+                .trim_matches('"')
+                .trim()
+        })
+        .filter(|line| !line.is_empty() && line.split_whitespace().count() <= max_words)
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::prompts::PromptTemplate;
+    use crate::rating::{Rating, RatingProfile};
+    use crate::vector_index::VectorIndexConfig;
+
+    const POOL: &str = r#"
+[[bark]]
+trigger = "idle"
+text = "Storm's coming in."
+
+[[bark]]
+trigger = "idle"
+text = "Another body in the canal, throat cut."
+tags = { descriptors = { violence = "strong", blood = "moderate" } }
+"#;
+
+    fn ctx<'a>(listener: Option<&'a str>, world: &'a DialogueContext, vars: &'a HashMap<String, String>) -> BarkContext<'a> {
+        BarkContext {
+            speaker: "dockhand",
+            listener,
+            trigger: "idle",
+            position: (0.0, 0.0),
+            mood: MoodVector { tension: 0.5, valence: 0.0, energy: 0.5 },
+            world,
+            vars,
+            now: 0.0,
+        }
+    }
+
+    #[test]
+    fn listener_only_hears_lines_their_rating_allows() {
+        let rating = Arc::new(Mutex::new(ContentRating::new(RatingProfile::new(Rating::Everyone), 64)));
+        let config = BarkConfig { cooldown_secs: 0.0, ..BarkConfig::default() };
+        let mut barks = AmbientBarks::new(BarkPool::from_toml(POOL).unwrap(), config).with_rating(rating.clone());
+        let (world, vars) = (DialogueContext::default(), HashMap::new());
+        let mut rng = Rng::new(7);
+        for i in 0..20 {
+            let mut ctx = ctx(Some("kid"), &world, &vars);
+            ctx.now = i as f64;
+            assert_eq!(barks.bark(&ctx, None, &mut rng, None).unwrap().text, "Storm's coming in.");
+        }
+        assert!(rating.lock().unwrap().audit_log().any(|e| !e.allowed && e.player == "kid"));
+    }
+
+    #[test]
+    fn no_listener_means_no_check() {
+        let rating = Arc::new(Mutex::new(ContentRating::new(RatingProfile::new(Rating::Everyone), 64)));
+        let config = BarkConfig { cooldown_secs: 0.0, ..BarkConfig::default() };
+        let mut barks = AmbientBarks::new(BarkPool::from_toml(POOL).unwrap(), config).with_rating(rating.clone());
+        let (world, vars) = (DialogueContext::default(), HashMap::new());
+        let mut rng = Rng::new(7);
+        let mut heard = HashSet::new();
+        for i in 0..40 {
+            let mut ctx = ctx(None, &world, &vars);
+            ctx.now = i as f64;
+            heard.insert(barks.bark(&ctx, None, &mut rng, None).unwrap().text);
+        }
+        assert_eq!(heard.len(), 2);
+        assert_eq!(rating.lock().unwrap().audit_log().count(), 0);
+    }
+
+    const MOODY: &str = r#"
+[[bark]]
+trigger = "idle"
+text = "Quiet night, {name}."
+max_tension = 0.3
+
+[[bark]]
+trigger = "idle"
+text = "Keep your voice down."
+min_tension = 0.6
+conditions = [{ state = "curfew", op = "eq", value = true }]
+
+[[bark]]
+trigger = "combat"
+text = "To arms!"
+"#;
+
+    struct Fixed(&'static str);
+
+    impl TextGenerator for Fixed {
+        fn generate(&self, _prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+            Ok(self.0.to_string())
+        }
+    }
+
+    fn index() -> VectorIndex {
+        VectorIndex::new(VectorIndexConfig {
+            url: String::new(),
+            api_key: String::new(),
+            default_ttl_secs: None,
+            collection_ttl_secs: Default::default(),
+        })
+    }
+
+    fn request(tension: f32, count: usize) -> BarkRequest {
+        BarkRequest {
+            trigger: "idle".to_string(),
+            mood: MoodVector { tension, valence: 0.0, energy: 0.5 },
+            setting: "harbour town".to_string(),
+            count,
+        }
+    }
+
+    #[test]
+    fn templates_are_filtered_by_trigger_mood_conditions_and_placeholders() {
+        let mut barks = AmbientBarks::new(BarkPool::from_toml(MOODY).unwrap(), BarkConfig::default());
+        let (world, no_vars) = (DialogueContext::default(), HashMap::new());
+        let named = HashMap::from([("name".to_string(), "Ines".to_string())]);
+        let mut curfew = DialogueContext::default();
+        curfew.state.set("curfew", true);
+        let mut rng = Rng::new(3);
+        let at = |tension, world, vars| BarkContext {
+            mood: MoodVector { tension, valence: 0.0, energy: 0.5 },
+            ..ctx(None, world, vars)
+        };
+
+        // The only calm line can't be filled in without a name
+        assert_eq!(barks.bark(&at(0.1, &world, &no_vars), None, &mut rng, None), None);
+        assert_eq!(barks.bark(&at(0.1, &world, &named), None, &mut rng, None).unwrap().text, "Quiet night, Ines.");
+        // Nothing fits a middling mood
+        assert_eq!(barks.bark(&at(0.5, &curfew, &named), None, &mut rng, None), None);
+        // The tense line also needs its world state
+        assert_eq!(barks.bark(&at(0.9, &world, &named), None, &mut rng, None), None);
+        assert_eq!(barks.bark(&at(0.9, &curfew, &named), None, &mut rng, None).unwrap().text, "Keep your voice down.");
+    }
+
+    #[test]
+    fn lines_are_not_repeated_nearby_until_the_cooldown_passes() {
+        let mut barks = AmbientBarks::new(BarkPool::from_toml(MOODY).unwrap(), BarkConfig::default());
+        let (world, vars) = (DialogueContext::default(), HashMap::new());
+        let mut rng = Rng::new(3);
+        let mut bus = EventBus::new(8);
+        let sub = bus.subscribe(BARK_TOPIC);
+        let combat = |position, now| BarkContext { trigger: "combat", position, now, ..ctx(None, &world, &vars) };
+
+        let bark = barks.bark(&combat((0.0, 0.0), 0.0), None, &mut rng, Some(&mut bus)).unwrap();
+        assert_eq!(bark, Bark { speaker: "dockhand".to_string(), text: "To arms!".to_string(), generated: false });
+        assert_eq!(bus.drain(sub)[0].payload["text"], "To arms!");
+
+        assert_eq!(barks.bark(&combat((10.0, 0.0), 30.0), None, &mut rng, None), None);
+        // Out of earshot of the first one, and later back in range once it cooled down
+        assert!(barks.bark(&combat((100.0, 0.0), 30.0), None, &mut rng, None).is_some());
+        assert!(barks.bark(&combat((0.0, 0.0), 61.0), None, &mut rng, None).is_some());
+    }
+
+    #[test]
+    fn pregenerated_lines_are_cleaned_and_found_by_mood() {
+        let config = BarkConfig { max_words: 5, generated_candidates: 2, ..BarkConfig::default() };
+        let mut barks = AmbientBarks::new(BarkPool::default(), config);
+        let mut index = index();
+        let raw = "1. \"Nets won't mend themselves.\"\n- Gulls are loud today.\n\n\
+                   * This line rambles on for far too many words.\n";
+        assert_eq!(barks.pregenerate(&Fixed(raw), &mut index, &[request(0.1, 5)]).unwrap(), 2);
+        assert_eq!(barks.pregenerate(&Fixed("Blades out.\nSomething's wrong."), &mut index, &[request(0.9, 1)]).unwrap(), 1);
+        assert_eq!(index.len(BARK_COLLECTION).unwrap(), 3);
+
+        // Without authored lines the nearest generated ones are used
+        let (world, vars) = (DialogueContext::default(), HashMap::new());
+        let mut calm = ctx(None, &world, &vars);
+        calm.mood.tension = 0.1;
+        let bark = barks.bark(&calm, Some(&index), &mut Rng::new(5), None).unwrap();
+        assert!(bark.generated);
+        assert!(["Nets won't mend themselves.", "Gulls are loud today."].contains(&bark.text.as_str()));
+
+        let mut other = ctx(None, &world, &vars);
+        other.trigger = "combat";
+        assert_eq!(barks.bark(&other, Some(&index), &mut Rng::new(5), None), None);
+    }
+
+    #[test]
+    fn bark_prompts_render_through_a_registry_override() {
+        let builtin = bark_prompt(&request(0.84, 3), 6, None).unwrap();
+        assert!(builtin.starts_with("Write 3 different short lines"));
+        assert!(builtin.contains("Mood: tension 0.8 (0-1), valence 0.0 (-1..1)"));
+
+        let mut registry = PromptRegistry::with_builtin();
+        let template = prompts::builtin(prompts::BARK_LINES).unwrap();
+        let text = "{{count}} grumbles about {{trigger}} in a {{setting}}, {{max_words}} words, \
+                    mood {{tension}}/{{valence}}/{{energy}}";
+        registry.register(PromptTemplate { version: 2, text: text.to_string(), ..template }).unwrap();
+        let overridden = bark_prompt(&request(0.5, 2), 6, Some(&registry)).unwrap();
+        assert_eq!(overridden, "2 grumbles about idle in a harbour town, 6 words, mood 0.5/0.0/0.5");
+    }
+}
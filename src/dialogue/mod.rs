@@ -0,0 +1,6 @@
+// Dialogue: designer-authored trees with LLM-driven branches
+
+pub mod barks;
+pub mod summary;
+pub mod tools;
+pub mod tree;
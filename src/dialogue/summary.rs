@@ -0,0 +1,379 @@
+// Conversation summaries
+//
+// When an NPC-player conversation ends, a summarization step asks the language model for a short
+// summary and a list of structured facts ("player promised to deliver medicine to Zara"), each a
+// subject-relation-object triple with a kind and a confidence. The output is schema-checked like
+// any other generated content (generation.rs), so malformed answers are repaired or retried.
+//
+// `record` writes the facts into the SymbolicComputing knowledge graph twice over: as a plain
+// relation between the two concepts, which traversal and pattern queries see, and as a fact
+// concept ("fact:<conversation>#<n>") carrying the kind, the parties, the deadline and provenance:
+// the conversation id and the ids of the vector memories it came from. `remember` stores the
+// summary itself in the vector index first, so the fact always links back to a retrievable memory.
+// Promises and deals recorded this way are what commitment tracking later reasons about.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::embeddings::Embedder;
+use crate::generation::{ContentGenerator, ContentKind, ContentTemplate, GenerationError, TextGenerator};
+use crate::prompts;
+use crate::symbolic::{RelationType, SymbolicComputing};
+use crate::vector_index::{VectorIndex, VectorIndexError, PLAYER_FIELD};
+
+pub const SUMMARY_TEMPLATE: &str = "conversation_facts";
+
+// Prefix of the concepts holding extracted facts
+pub const FACT_PREFIX: &str = "fact:";
+
+// Payload field of a stored summary listing the conversation it summarizes
+pub const CONVERSATION_FIELD: &str = "conversation_id";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FactKind {
+    Fact,
+    // Subject committed to doing something for object
+    Promise,
+    // Mutual agreement; both sides owe something
+    Deal,
+    Request,
+    Opinion,
+}
+
+impl FactKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FactKind::Fact => "fact",
+            FactKind::Promise => "promise",
+            FactKind::Deal => "deal",
+            FactKind::Request => "request",
+            FactKind::Opinion => "opinion",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExtractedFact {
+    pub subject: String,
+    // Short verb phrase, e.g. "promised_to_deliver"
+    pub relation: String,
+    pub object: String,
+    pub kind: FactKind,
+    #[serde(default = "default_confidence")]
+    pub confidence: f32,
+    // What the commitment is about, e.g. "medicine"
+    #[serde(default)]
+    pub item: Option<String>,
+    // Free-text deadline as spoken ("before the next full moon") and the model's estimate in hours
+    #[serde(default)]
+    pub deadline: Option<String>,
+    #[serde(default)]
+    pub due_in_hours: Option<f64>,
+}
+
+fn default_confidence() -> f32 {
+    0.7
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConversationSummary {
+    pub conversation_id: String,
+    pub npc: String,
+    pub player: String,
+    pub summary: String,
+    pub facts: Vec<ExtractedFact>,
+    // Vector memories the facts were extracted from
+    pub memory_ids: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum SummaryError {
+    Generation(GenerationError),
+    Memory(VectorIndexError),
+    Malformed(serde_json::Error),
+}
+
+impl fmt::Display for SummaryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SummaryError::Generation(err) => write!(f, "conversation summary failed: {}", err),
+            SummaryError::Memory(err) => write!(f, "could not store conversation summary: {}", err),
+            SummaryError::Malformed(err) => write!(f, "conversation summary has an unexpected shape: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for SummaryError {}
+
+impl From<GenerationError> for SummaryError {
+    fn from(err: GenerationError) -> Self {
+        SummaryError::Generation(err)
+    }
+}
+
+impl From<VectorIndexError> for SummaryError {
+    fn from(err: VectorIndexError) -> Self {
+        SummaryError::Memory(err)
+    }
+}
+
+impl From<serde_json::Error> for SummaryError {
+    fn from(err: serde_json::Error) -> Self {
+        SummaryError::Malformed(err)
+    }
+}
+
+// Template asking for {"summary", "facts": [...]}
+pub fn summary_template(max_facts: usize) -> ContentTemplate {
+    let schema = json!({
+        "type": "object",
+        "required": ["summary", "facts"],
+        "properties": {
+            "summary": { "type": "string", "minLength": 1, "maxLength": 600 },
+            "facts": {
+                "type": "array", "maxItems": max_facts,
+                "items": {
+                    "type": "object",
+                    "required": ["subject", "relation", "object", "kind"],
+                    "properties": {
+                        "subject": { "type": "string", "minLength": 1, "maxLength": 64 },
+                        "relation": { "type": "string", "minLength": 1, "maxLength": 64 },
+                        "object": { "type": "string", "minLength": 1, "maxLength": 64 },
+                        "kind": { "type": "string", "enum": ["fact", "promise", "deal", "request", "opinion"] },
+                        "confidence": { "type": "number", "minimum": 0, "maximum": 1 },
+                        "item": { "type": "string", "maxLength": 64 },
+                        "deadline": { "type": "string", "maxLength": 120 },
+                        "due_in_hours": { "type": "number", "minimum": 0 }
+                    }
+                }
+            }
+        }
+    });
+    let prompt = prompts::builtin(prompts::CONVERSATION_SUMMARY).map(|template| template.text).unwrap_or_default();
+    ContentTemplate::new(SUMMARY_TEMPLATE, ContentKind::Custom, &prompt, schema)
+}
+
+pub struct ConversationSummarizer<G: TextGenerator> {
+    generator: ContentGenerator<G>,
+}
+
+impl<G: TextGenerator> ConversationSummarizer<G> {
+    pub fn new(generator: G) -> Self {
+        let mut generator = ContentGenerator::new(generator);
+        generator.register(summary_template(12));
+        ConversationSummarizer { generator }
+    }
+
+    // Summarize a finished conversation, e.g. DialogueSession::transcript()
+    pub fn summarize(
+        &self,
+        conversation_id: &str,
+        npc: &str,
+        player: &str,
+        transcript: &[(String, String)],
+    ) -> Result<ConversationSummary, SummaryError> {
+        let lines: Vec<String> = transcript.iter().map(|(speaker, line)| format!("{}: {}", speaker, line)).collect();
+        let vars = HashMap::from([
+            ("npc".to_string(), npc.to_string()),
+            ("player".to_string(), player.to_string()),
+            ("transcript".to_string(), lines.join("\n")),
+        ]);
+        let content = self.generator.generate(SUMMARY_TEMPLATE, &vars)?;
+        let summary = content.value.get("summary").and_then(Value::as_str).unwrap_or_default().to_string();
+        let facts: Vec<ExtractedFact> = serde_json::from_value(content.value.get("facts").cloned().unwrap_or(json!([])))?;
+        Ok(ConversationSummary {
+            conversation_id: conversation_id.to_string(),
+            npc: npc.to_string(),
+            player: player.to_string(),
+            summary,
+            facts,
+            memory_ids: Vec::new(),
+        })
+    }
+}
+
+impl ConversationSummary {
+    // Store the summary as an NPC memory and link it as provenance; returns the memory id
+    pub fn remember(&mut self, index: &mut VectorIndex, collection: &str, embedder: &dyn Embedder) -> Result<String, SummaryError> {
+        let id = format!("conversation:{}", self.conversation_id);
+        let payload = HashMap::from([
+            (CONVERSATION_FIELD.to_string(), Value::from(self.conversation_id.clone())),
+            (PLAYER_FIELD.to_string(), Value::from(self.player.clone())),
+            ("npc".to_string(), Value::from(self.npc.clone())),
+            ("facts".to_string(), Value::from(self.facts.len())),
+        ]);
+        index.store_text(collection, &id, &self.summary, payload, embedder)?;
+        if !self.memory_ids.contains(&id) {
+            self.memory_ids.push(id.clone());
+        }
+        Ok(id)
+    }
+
+    // Insert every fact into the knowledge graph; returns the fact concept names in order
+    pub fn record(&self, kb: &mut SymbolicComputing) -> Vec<String> {
+        let mut names = Vec::with_capacity(self.facts.len());
+        for (n, fact) in self.facts.iter().enumerate() {
+            let relation = RelationType::parse(&relation_name(&fact.relation));
+            kb.add_relation(&fact.subject, relation.clone(), &fact.object, fact.confidence.clamp(0.0, 1.0));
+
+            let name = format!("{}{}#{}", FACT_PREFIX, self.conversation_id, n);
+            let mut properties = vec![
+                ("kind", fact.kind.as_str().to_string()),
+                ("subject", fact.subject.clone()),
+                ("relation", relation.to_string()),
+                ("object", fact.object.clone()),
+                ("confidence", fact.confidence.to_string()),
+                ("npc", self.npc.clone()),
+                ("player", self.player.clone()),
+                ("conversation", self.conversation_id.clone()),
+                ("memories", self.memory_ids.join(",")),
+            ];
+            if let Some(item) = &fact.item {
+                properties.push(("item", item.clone()));
+            }
+            if let Some(deadline) = &fact.deadline {
+                properties.push(("deadline", deadline.clone()));
+            }
+            if let Some(hours) = fact.due_in_hours {
+                properties.push(("due_in_hours", hours.to_string()));
+            }
+            for (key, value) in properties {
+                kb.set_property(&name, key, &value);
+            }
+            kb.add_relation(&name, RelationType::Custom("About".to_string()), &fact.subject, 1.0);
+            kb.add_relation(&name, RelationType::Custom("About".to_string()), &fact.object, 1.0);
+            names.push(name);
+        }
+        names
+    }
+}
+
+// "promised to deliver" / "promised_to_deliver" -> "PromisedToDeliver", so model phrasing maps onto
+// relation names like the built-in ones
+pub fn relation_name(phrase: &str) -> String {
+    phrase
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect::<String>(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::embeddings::HashEmbedder;
+    use crate::vector_index::VectorIndexConfig;
+
+    const ANSWER: &str = r#"{
+        "summary": "Zara asked for medicine; the player promised to bring it.",
+        "facts": [
+            {"subject": "player", "relation": "promised to deliver", "object": "Zara", "kind": "promise",
+             "item": "medicine", "deadline": "before the full moon", "due_in_hours": 48},
+            {"subject": "Zara", "relation": "Likes", "object": "player", "kind": "opinion", "confidence": 0.9}
+        ]
+    }"#;
+
+    // Answers with a fixed response and keeps the prompts it was sent
+    struct Recording {
+        answer: &'static str,
+        prompts: RefCell<Vec<String>>,
+    }
+
+    impl TextGenerator for &Recording {
+        fn generate(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+            self.prompts.borrow_mut().push(prompt.to_string());
+            Ok(self.answer.to_string())
+        }
+    }
+
+    fn transcript() -> Vec<(String, String)> {
+        vec![
+            ("Zara".to_string(), "The fever is spreading. I need medicine.".to_string()),
+            ("player".to_string(), "I'll bring some before the full moon.".to_string()),
+        ]
+    }
+
+    fn summary() -> ConversationSummary {
+        let generator = Recording { answer: ANSWER, prompts: RefCell::new(Vec::new()) };
+        ConversationSummarizer::new(&generator).summarize("c42", "zara", "p1", &transcript()).unwrap()
+    }
+
+    #[test]
+    fn relation_phrases_become_relation_names() {
+        assert_eq!(relation_name("promised to deliver"), "PromisedToDeliver");
+        assert_eq!(relation_name("promised_to_deliver"), "PromisedToDeliver");
+        assert_eq!(relation_name("Likes"), "Likes");
+        assert_eq!(relation_name(" -- "), "");
+    }
+
+    #[test]
+    fn summaries_parse_facts_from_the_transcript() {
+        let generator = Recording { answer: ANSWER, prompts: RefCell::new(Vec::new()) };
+        let summary = ConversationSummarizer::new(&generator).summarize("c42", "zara", "p1", &transcript()).unwrap();
+        assert!(generator.prompts.borrow()[0].contains("player: I'll bring some before the full moon."));
+        assert_eq!(summary.summary, "Zara asked for medicine; the player promised to bring it.");
+        assert_eq!(summary.facts.len(), 2);
+        assert_eq!((summary.facts[0].kind, summary.facts[0].confidence), (FactKind::Promise, 0.7));
+        assert_eq!(summary.facts[0].item.as_deref(), Some("medicine"));
+        assert_eq!(summary.facts[1].confidence, 0.9);
+
+        let rambling = Recording { answer: "They talked about the weather.", prompts: RefCell::new(Vec::new()) };
+        let err = ConversationSummarizer::new(&rambling).summarize("c43", "zara", "p1", &transcript()).unwrap_err();
+        assert!(matches!(err, SummaryError::Generation(_)));
+    }
+
+    #[test]
+    fn remembered_summaries_are_stored_once_as_provenance() {
+        let mut index = VectorIndex::new(VectorIndexConfig {
+            url: String::new(),
+            api_key: String::new(),
+            default_ttl_secs: None,
+            collection_ttl_secs: Default::default(),
+        });
+        index.create_collection("npc_zara", 16, "test").unwrap();
+        let embedder = HashEmbedder::new(16);
+        let mut summary = summary();
+        let id = summary.remember(&mut index, "npc_zara", &embedder).unwrap();
+        summary.remember(&mut index, "npc_zara", &embedder).unwrap();
+        assert_eq!(id, "conversation:c42");
+        assert_eq!(summary.memory_ids, vec!["conversation:c42"]);
+
+        let stored = index.get("npc_zara", &id).unwrap().unwrap();
+        assert_eq!(stored.payload[CONVERSATION_FIELD], "c42");
+        assert_eq!(stored.payload[PLAYER_FIELD], "p1");
+        assert_eq!(stored.payload["facts"], 2);
+        assert!(summary.remember(&mut index, "missing", &embedder).is_err());
+    }
+
+    #[test]
+    fn facts_become_relations_and_fact_concepts() {
+        let mut summary = summary();
+        summary.memory_ids.push("conversation:c42".to_string());
+        let mut kb = SymbolicComputing::new();
+        let names = summary.record(&mut kb);
+        assert_eq!(names, vec!["fact:c42#0", "fact:c42#1"]);
+
+        let promised = RelationType::Custom("PromisedToDeliver".to_string());
+        assert!(kb.has_relation("player", &promised, "Zara"));
+        assert!(kb.has_relation("Zara", &RelationType::Likes, "player"));
+        assert!(kb.has_relation("fact:c42#0", &RelationType::Custom("About".to_string()), "Zara"));
+
+        assert_eq!(kb.property("fact:c42#0", "kind"), Some("promise"));
+        assert_eq!(kb.property("fact:c42#0", "relation"), Some("PromisedToDeliver"));
+        assert_eq!(kb.property("fact:c42#0", "due_in_hours"), Some("48"));
+        assert_eq!(kb.property("fact:c42#0", "memories"), Some("conversation:c42"));
+        assert_eq!(kb.property("fact:c42#1", "item"), None);
+    }
+}
@@ -0,0 +1,554 @@
+// Function calling for NPCs
+//
+// An NPC that says "here, take this potion" should actually hand over the potion. The dialogue
+// engine exposes selected engine actions (give_item, start_quest, open_shop, or anything the game
+// registers) as tools: a name, a description and a JSON schema for the arguments. Specs are
+// rendered in the OpenAI or Anthropic function-calling format, or as a prompt section for models
+// without native tool support, which then answer with {"tool": ..., "arguments": ...}.
+//
+// A model's tool calls go through three gates before anything happens:
+//
+//   1. the tool must be exposed in this conversation (a merchant can open_shop, a guard cannot)
+//   2. the arguments must match the tool's schema (generation.rs validation)
+//   3. every game rule registered for the tool must pass (does the NPC own the item, is the quest
+//      available), reported like lore rules into a ValidationReport
+//
+// Accepted calls are executed by the tool's handler; the default one emits a "dialogue.action"
+// event with the NPC, the player, the tool and the arguments, for the game systems that own
+// inventories and quests to apply. Every call produces a ToolResult, errors included, which is
+// formatted back for the model (OpenAI tool message, Anthropic tool_result block, or text) so it
+// can explain a refusal in character. `ToolBridge::converse` runs that loop over any
+// TextGenerator, feeding back each round's calls followed by their results; a call made where no
+// tool is exposed is refused like any other unexposed call and never ends up in the reply.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{self, Write as _};
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::events::EventBus;
+use crate::generation::{extract_json, validate, TextGenerator};
+use crate::validation::ValidationReport;
+
+pub const ACTION_TOPIC: &str = "dialogue.action";
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ToolSpec {
+    pub name: String,
+    pub description: String,
+    // JSON schema of the arguments object
+    pub parameters: Value,
+}
+
+impl ToolSpec {
+    pub fn new(name: &str, description: &str, parameters: Value) -> Self {
+        ToolSpec { name: name.to_string(), description: description.to_string(), parameters }
+    }
+
+    pub fn give_item() -> Self {
+        ToolSpec::new(
+            "give_item",
+            "Give an item from your inventory to the player.",
+            json!({
+                "type": "object",
+                "required": ["item"],
+                "properties": {
+                    "item": { "type": "string", "minLength": 1 },
+                    "quantity": { "type": "integer", "minimum": 1, "maximum": 99 }
+                }
+            }),
+        )
+    }
+
+    pub fn start_quest() -> Self {
+        ToolSpec::new(
+            "start_quest",
+            "Offer the player a quest you can give.",
+            json!({
+                "type": "object",
+                "required": ["quest_id"],
+                "properties": { "quest_id": { "type": "string", "minLength": 1 } }
+            }),
+        )
+    }
+
+    pub fn open_shop() -> Self {
+        ToolSpec::new(
+            "open_shop",
+            "Open your shop so the player can trade with you.",
+            json!({
+                "type": "object",
+                "properties": { "shop_id": { "type": "string" } }
+            }),
+        )
+    }
+
+    // {"type": "function", "function": {...}} for OpenAI-style chat APIs
+    pub fn to_openai(&self) -> Value {
+        json!({
+            "type": "function",
+            "function": { "name": self.name, "description": self.description, "parameters": self.parameters }
+        })
+    }
+
+    // {"name", "description", "input_schema"} for Anthropic-style APIs
+    pub fn to_anthropic(&self) -> Value {
+        json!({ "name": self.name, "description": self.description, "input_schema": self.parameters })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ToolCall {
+    // Provider-assigned id, or "call-<n>" for prompted models
+    pub id: String,
+    pub name: String,
+    pub arguments: Value,
+}
+
+impl ToolCall {
+    // One line for prompted models, in the form they call tools with
+    pub fn line(&self) -> String {
+        json!({ "id": self.id, "tool": self.name, "arguments": self.arguments }).to_string()
+    }
+}
+
+// A call together with who is making it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ToolInvocation {
+    pub call: ToolCall,
+    pub npc: String,
+    pub player: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ToolResult {
+    pub call_id: String,
+    pub name: String,
+    pub ok: bool,
+    // Handler output, or {"error": ...}
+    pub content: Value,
+}
+
+impl ToolResult {
+    fn failed(call: &ToolCall, error: &ToolError) -> Self {
+        ToolResult { call_id: call.id.clone(), name: call.name.clone(), ok: false, content: json!({ "error": error.to_string() }) }
+    }
+
+    pub fn to_openai(&self) -> Value {
+        json!({ "role": "tool", "tool_call_id": self.call_id, "content": self.content.to_string() })
+    }
+
+    pub fn to_anthropic(&self) -> Value {
+        json!({ "type": "tool_result", "tool_use_id": self.call_id, "content": self.content.to_string(), "is_error": !self.ok })
+    }
+
+    // One line for prompted models
+    pub fn line(&self) -> String {
+        let status = if self.ok { "ok" } else { "failed" };
+        format!("{} ({}) {}: {}", self.name, self.call_id, status, self.content)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ToolError {
+    UnknownTool(String),
+    NotExposed(String),
+    InvalidArguments { tool: String, report: ValidationReport },
+    // A game rule refused the call
+    Rejected { tool: String, report: ValidationReport },
+    TooManyCalls(usize),
+    Failed { tool: String, reason: String },
+}
+
+impl fmt::Display for ToolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ToolError::UnknownTool(name) => write!(f, "there is no tool '{}'", name),
+            ToolError::NotExposed(name) => write!(f, "tool '{}' is not available in this conversation", name),
+            ToolError::InvalidArguments { tool, report } => write!(f, "invalid arguments for '{}': {}", tool, issues(report)),
+            ToolError::Rejected { tool, report } => write!(f, "'{}' is not allowed: {}", tool, issues(report)),
+            ToolError::TooManyCalls(max) => write!(f, "at most {} tool calls per turn", max),
+            ToolError::Failed { tool, reason } => write!(f, "'{}' failed: {}", tool, reason),
+        }
+    }
+}
+
+impl std::error::Error for ToolError {}
+
+fn issues(report: &ValidationReport) -> String {
+    report.errors().map(|issue| issue.to_string()).collect::<Vec<_>>().join("; ")
+}
+
+// A game rule a tool call must satisfy, e.g. "the NPC owns the item"
+pub trait ToolRule {
+    fn name(&self) -> &str;
+    fn check(&self, invocation: &ToolInvocation, report: &mut ValidationReport);
+}
+
+// Restricts a string argument to a set of values, e.g. the items an NPC carries
+pub struct AllowedValues {
+    pub field: String,
+    pub values: BTreeSet<String>,
+}
+
+impl AllowedValues {
+    pub fn new(field: &str, values: &[&str]) -> Self {
+        AllowedValues { field: field.to_string(), values: values.iter().map(|v| v.to_string()).collect() }
+    }
+}
+
+impl ToolRule for AllowedValues {
+    fn name(&self) -> &str {
+        "allowed_values"
+    }
+
+    fn check(&self, invocation: &ToolInvocation, report: &mut ValidationReport) {
+        if let Some(value) = invocation.call.arguments.get(&self.field).and_then(Value::as_str) {
+            if !self.values.contains(value) {
+                report.error(&self.field, format!("'{}' is not available", value));
+            }
+        }
+    }
+}
+
+// Carries out an accepted call
+pub trait ToolHandler {
+    fn execute(&mut self, invocation: &ToolInvocation, events: &mut EventBus) -> Result<Value, String>;
+}
+
+impl<F: FnMut(&ToolInvocation, &mut EventBus) -> Result<Value, String>> ToolHandler for F {
+    fn execute(&mut self, invocation: &ToolInvocation, events: &mut EventBus) -> Result<Value, String> {
+        self(invocation, events)
+    }
+}
+
+// Emits the call on ACTION_TOPIC for the owning game system; the model is told it was requested
+pub struct EmitAction;
+
+impl ToolHandler for EmitAction {
+    fn execute(&mut self, invocation: &ToolInvocation, events: &mut EventBus) -> Result<Value, String> {
+        let payload = json!({
+            "npc": invocation.npc,
+            "player": invocation.player,
+            "tool": invocation.call.name,
+            "call_id": invocation.call.id,
+            "arguments": invocation.call.arguments,
+        });
+        events.emit(ACTION_TOPIC, &invocation.npc, payload);
+        Ok(json!({ "status": "requested" }))
+    }
+}
+
+struct Tool {
+    spec: ToolSpec,
+    rules: Vec<Box<dyn ToolRule + Send>>,
+    handler: Box<dyn ToolHandler + Send>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ToolConfig {
+    pub max_calls_per_turn: usize,
+    // Model round trips in `converse` before giving up on a final answer
+    pub max_rounds: usize,
+}
+
+impl Default for ToolConfig {
+    fn default() -> Self {
+        ToolConfig { max_calls_per_turn: 3, max_rounds: 3 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolTurn {
+    // The model's final reply, without tool calls
+    pub reply: String,
+    pub results: Vec<ToolResult>,
+    pub rounds: usize,
+}
+
+#[derive(Default)]
+pub struct ToolBridge {
+    tools: BTreeMap<String, Tool>,
+    // Tools the current conversation may use; empty exposes none
+    exposed: BTreeSet<String>,
+    pub config: ToolConfig,
+}
+
+impl ToolBridge {
+    pub fn new() -> Self {
+        ToolBridge::default()
+    }
+
+    // Register a tool executed by EmitAction
+    pub fn register(&mut self, spec: ToolSpec) {
+        self.register_with(spec, EmitAction);
+    }
+
+    pub fn register_with(&mut self, spec: ToolSpec, handler: impl ToolHandler + Send + 'static) {
+        let name = spec.name.clone();
+        self.tools.insert(name, Tool { spec, rules: Vec::new(), handler: Box::new(handler) });
+    }
+
+    // Add a game rule to a registered tool; false when there is no such tool
+    pub fn rule(&mut self, tool: &str, rule: impl ToolRule + Send + 'static) -> bool {
+        match self.tools.get_mut(tool) {
+            Some(tool) => {
+                tool.rules.push(Box::new(rule));
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Replace the rules of a tool, e.g. with the current NPC's inventory
+    pub fn clear_rules(&mut self, tool: &str) {
+        if let Some(tool) = self.tools.get_mut(tool) {
+            tool.rules.clear();
+        }
+    }
+
+    // Choose the tools for the conversation about to start
+    pub fn expose(&mut self, names: &[&str]) {
+        self.exposed = names.iter().filter(|name| self.tools.contains_key(**name)).map(|name| name.to_string()).collect();
+    }
+
+    pub fn exposed(&self) -> Vec<&ToolSpec> {
+        self.exposed.iter().filter_map(|name| self.tools.get(name)).map(|tool| &tool.spec).collect()
+    }
+
+    pub fn openai_tools(&self) -> Value {
+        Value::Array(self.exposed().iter().map(|spec| spec.to_openai()).collect())
+    }
+
+    pub fn anthropic_tools(&self) -> Value {
+        Value::Array(self.exposed().iter().map(|spec| spec.to_anthropic()).collect())
+    }
+
+    // Prompt section describing the exposed tools to a model without native tool calls
+    pub fn prompt_section(&self) -> String {
+        let exposed = self.exposed();
+        if exposed.is_empty() {
+            return String::new();
+        }
+        let mut section = String::from("You can act in the world with these tools:\n");
+        for spec in exposed {
+            let _ = writeln!(section, "- {}: {} Arguments (JSON schema): {}", spec.name, spec.description, spec.parameters);
+        }
+        section.push_str(
+            "To use a tool, answer with JSON only: {\"tool\": \"<name>\", \"arguments\": {...}} \
+             (or {\"tool_calls\": [...]} for several). Otherwise answer in character as plain text.\n",
+        );
+        section
+    }
+
+    // Check one call against exposure, schema and rules
+    pub fn check(&self, invocation: &ToolInvocation) -> Result<(), ToolError> {
+        let name = &invocation.call.name;
+        let tool = self.tools.get(name).ok_or_else(|| ToolError::UnknownTool(name.clone()))?;
+        if !self.exposed.contains(name) {
+            return Err(ToolError::NotExposed(name.clone()));
+        }
+        let report = validate(&invocation.call.arguments, &tool.spec.parameters);
+        if !report.is_valid() {
+            return Err(ToolError::InvalidArguments { tool: name.clone(), report });
+        }
+        let mut report = ValidationReport::new();
+        for rule in &tool.rules {
+            rule.check(invocation, &mut report);
+        }
+        if !report.is_valid() {
+            return Err(ToolError::Rejected { tool: name.clone(), report });
+        }
+        Ok(())
+    }
+
+    // Check and run the calls of one model turn; every call gets a result
+    pub fn execute(&mut self, npc: &str, player: &str, calls: Vec<ToolCall>, events: &mut EventBus) -> Vec<ToolResult> {
+        let max = self.config.max_calls_per_turn;
+        let mut results = Vec::with_capacity(calls.len());
+        for (n, call) in calls.into_iter().enumerate() {
+            if n >= max {
+                results.push(ToolResult::failed(&call, &ToolError::TooManyCalls(max)));
+                continue;
+            }
+            let invocation = ToolInvocation { call, npc: npc.to_string(), player: player.to_string() };
+            if let Err(err) = self.check(&invocation) {
+                results.push(ToolResult::failed(&invocation.call, &err));
+                continue;
+            }
+            let tool = self.tools.get_mut(&invocation.call.name).expect("checked above");
+            let call = &invocation.call;
+            results.push(match tool.handler.execute(&invocation, events) {
+                Ok(content) => ToolResult { call_id: call.id.clone(), name: call.name.clone(), ok: true, content },
+                Err(reason) => ToolResult::failed(call, &ToolError::Failed { tool: call.name.clone(), reason }),
+            });
+        }
+        results
+    }
+
+    // One NPC turn with a prompted model: tool calls are executed and their results fed back
+    // until the model answers in plain text or `max_rounds` is reached
+    pub fn converse<G: TextGenerator>(
+        &mut self,
+        generator: &G,
+        prompt: &str,
+        npc: &str,
+        player: &str,
+        events: &mut EventBus,
+    ) -> Result<ToolTurn, Box<dyn std::error::Error>> {
+        let section = self.prompt_section();
+        let mut context = if section.is_empty() { prompt.to_string() } else { format!("{}\n\n{}", section, prompt) };
+        let mut results = Vec::new();
+        for round in 1..=self.config.max_rounds.max(1) {
+            let raw = generator.generate(&context)?;
+            let calls = parse_tool_calls(&raw);
+            if calls.is_empty() {
+                return Ok(ToolTurn { reply: raw.trim().to_string(), results, rounds: round });
+            }
+            // The model sees its own calls before their results
+            context.push_str("\n\nYour tool calls:\n");
+            for call in &calls {
+                context.push_str(&call.line());
+                context.push('\n');
+            }
+            let turn = self.execute(npc, player, calls, events);
+            context.push_str("Tool results:\n");
+            for result in &turn {
+                context.push_str(&result.line());
+                context.push('\n');
+            }
+            context.push_str("Now reply to the player in character, as plain text.\n");
+            results.extend(turn);
+        }
+        // Out of rounds while the model still wanted tools; say nothing rather than leak JSON
+        Ok(ToolTurn { reply: String::new(), results, rounds: self.config.max_rounds.max(1) })
+    }
+}
+
+// Tool calls in a model response: OpenAI "tool_calls", Anthropic "tool_use" content blocks, or
+// the prompted {"tool", "arguments"} form. Plain text yields none.
+pub fn parse_tool_calls(raw: &str) -> Vec<ToolCall> {
+    let Some(value) = extract_json(raw) else { return Vec::new() };
+    let mut calls = Vec::new();
+    collect_calls(&value, &mut calls);
+    for (n, call) in calls.iter_mut().enumerate() {
+        if call.id.is_empty() {
+            call.id = format!("call-{}", n + 1);
+        }
+    }
+    calls
+}
+
+fn collect_calls(value: &Value, calls: &mut Vec<ToolCall>) {
+    match value {
+        Value::Array(items) => items.iter().for_each(|item| collect_calls(item, calls)),
+        Value::Object(object) => {
+            let id = object.get("id").and_then(Value::as_str).unwrap_or_default().to_string();
+            // OpenAI: {"id", "type": "function", "function": {"name", "arguments": "<json string>"}}
+            if let Some(function) = object.get("function") {
+                if let Some(name) = function.get("name").and_then(Value::as_str) {
+                    let arguments = match function.get("arguments") {
+                        Some(Value::String(text)) => serde_json::from_str(text).unwrap_or(Value::String(text.clone())),
+                        Some(other) => other.clone(),
+                        None => json!({}),
+                    };
+                    calls.push(ToolCall { id, name: name.to_string(), arguments });
+                }
+                return;
+            }
+            // Anthropic: {"type": "tool_use", "id", "name", "input"}
+            if object.get("type").and_then(Value::as_str) == Some("tool_use") {
+                if let Some(name) = object.get("name").and_then(Value::as_str) {
+                    let arguments = object.get("input").cloned().unwrap_or_else(|| json!({}));
+                    calls.push(ToolCall { id, name: name.to_string(), arguments });
+                }
+                return;
+            }
+            // Prompted: {"tool", "arguments"}
+            if let Some(name) = object.get("tool").and_then(Value::as_str) {
+                let arguments = object.get("arguments").cloned().unwrap_or_else(|| json!({}));
+                calls.push(ToolCall { id, name: name.to_string(), arguments });
+                return;
+            }
+            // Wrappers: {"tool_calls": [...]}, {"content": [...]}, {"message": {...}}, {"choices": [...]}
+            for key in ["tool_calls", "content", "message", "choices"] {
+                if let Some(inner) = object.get(key) {
+                    collect_calls(inner, calls);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    // Answers with the scripted responses in turn and keeps every prompt it was given
+    struct Scripted {
+        responses: RefCell<Vec<&'static str>>,
+        prompts: RefCell<Vec<String>>,
+    }
+
+    impl Scripted {
+        fn new(responses: &[&'static str]) -> Self {
+            Scripted { responses: RefCell::new(responses.iter().rev().copied().collect()), prompts: RefCell::new(Vec::new()) }
+        }
+    }
+
+    impl TextGenerator for Scripted {
+        fn generate(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+            self.prompts.borrow_mut().push(prompt.to_string());
+            Ok(self.responses.borrow_mut().pop().unwrap_or("...").to_string())
+        }
+    }
+
+    const GIVE_POTION: &str = r#"{"tool": "give_item", "arguments": {"item": "potion", "quantity": 1}}"#;
+
+    #[test]
+    fn emitted_actions_are_reported_as_requested() {
+        let mut bridge = ToolBridge::new();
+        bridge.register(ToolSpec::give_item());
+        bridge.expose(&["give_item"]);
+        let mut events = EventBus::new(16);
+        let sub = events.subscribe(ACTION_TOPIC);
+        let results = bridge.execute("alchemist", "p1", parse_tool_calls(GIVE_POTION), &mut events);
+        assert_eq!(results.len(), 1);
+        assert!(results[0].ok, "{:?}", results[0]);
+        assert_eq!(results[0].content, json!({ "status": "requested" }));
+        assert_eq!(events.drain(sub).len(), 1);
+    }
+
+    #[test]
+    fn follow_up_context_carries_the_call_before_its_result() {
+        let mut bridge = ToolBridge::new();
+        bridge.register(ToolSpec::give_item());
+        bridge.expose(&["give_item"]);
+        let generator = Scripted::new(&[GIVE_POTION, "Here, take this potion."]);
+        let turn = bridge.converse(&generator, "Player: I'm hurt.", "alchemist", "p1", &mut EventBus::new(16)).unwrap();
+        assert_eq!((turn.reply.as_str(), turn.rounds), ("Here, take this potion.", 2));
+
+        let follow_up = &generator.prompts.borrow()[1];
+        let call = follow_up.find(r#""tool":"give_item""#).expect("call in context");
+        let result = follow_up.find("give_item (call-1) ok").expect("result in context");
+        assert!(call < result);
+    }
+
+    #[test]
+    fn calls_without_exposed_tools_are_refused_not_spoken() {
+        let mut bridge = ToolBridge::new();
+        bridge.register(ToolSpec::give_item());
+        let generator = Scripted::new(&[GIVE_POTION, "I have nothing for you."]);
+        let mut events = EventBus::new(16);
+        let sub = events.subscribe(ACTION_TOPIC);
+        let turn = bridge.converse(&generator, "Player: Give me a potion.", "guard", "p1", &mut events).unwrap();
+        assert_eq!(turn.reply, "I have nothing for you.");
+        assert_eq!(turn.results.len(), 1);
+        assert!(!turn.results[0].ok);
+        assert!(events.drain(sub).is_empty());
+    }
+}
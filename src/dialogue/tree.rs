@@ -0,0 +1,758 @@
+// Hybrid dialogue trees
+//
+// Designers author branching conversations in TOML. Choices and jumps can be gated on world
+// state facts and relationship values. Nodes marked as AI hand the conversation to the LLM with
+// explicit constraints (goal, persona, forbidden topics, turn limit) and return to the authored
+// tree through an exit node. The validator checks node references and that every state key and
+// relationship a condition mentions is one the game actually provides.
+//
+// A session given a content rating enforcer (`with_rating`) checks everything it shows the player
+// against their rating profile, and each decision lands in the enforcer's audit log: node lines and
+// choices by their authored tags, AI lines by the rating lexicon. Withheld choices are not offered,
+// a withheld node line reads as None, and a withheld AI line is replaced by the fallback line.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::ai::goap::{StateValue, WorldState};
+use crate::generation::TextGenerator;
+use crate::prompts::{self, PromptError, PromptRegistry};
+use crate::rating::{ContentRating, ContentTags, Surface};
+use crate::validation::ValidationReport;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    // Key is present, whatever its value
+    Exists,
+}
+
+// A gate on a choice or jump. Exactly one of `state` / `relationship` names the input.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Condition {
+    #[serde(default)]
+    pub state: Option<String>,
+    // Id of the character whose relationship value with the player is compared
+    #[serde(default)]
+    pub relationship: Option<String>,
+    pub op: CompareOp,
+    #[serde(default)]
+    pub value: Value,
+}
+
+impl Condition {
+    pub fn is_met(&self, ctx: &DialogueContext) -> bool {
+        if let Some(key) = &self.state {
+            return match (ctx.state.get(key), self.op) {
+                (None, _) => false,
+                (Some(_), CompareOp::Exists) => true,
+                (Some(actual), op) => compare_state(actual, op, &self.value),
+            };
+        }
+        if let Some(character) = &self.relationship {
+            return match (ctx.relationships.get(character), self.op) {
+                (None, _) => false,
+                (Some(_), CompareOp::Exists) => true,
+                (Some(actual), op) => match self.value.as_f64() {
+                    Some(expected) => compare_ord((*actual as f64).partial_cmp(&expected), op),
+                    None => false,
+                },
+            };
+        }
+        false
+    }
+}
+
+fn compare_state(actual: &StateValue, op: CompareOp, expected: &Value) -> bool {
+    match (actual, expected) {
+        (StateValue::Bool(a), Value::Bool(b)) => match op {
+            CompareOp::Eq => a == b,
+            CompareOp::Ne => a != b,
+            _ => false,
+        },
+        (StateValue::Int(a), Value::Number(b)) => match b.as_i64() {
+            Some(b) => compare_ord(Some(a.cmp(&b)), op),
+            None => b.as_f64().is_some_and(|b| compare_ord((*a as f64).partial_cmp(&b), op)),
+        },
+        (StateValue::Text(a), Value::String(b)) => compare_ord(Some(a.as_str().cmp(b.as_str())), op),
+        _ => op == CompareOp::Ne,
+    }
+}
+
+pub(crate) fn compare_ord(ordering: Option<std::cmp::Ordering>, op: CompareOp) -> bool {
+    use std::cmp::Ordering::*;
+    match (ordering, op) {
+        (None, _) => false,
+        (Some(o), CompareOp::Eq) => o == Equal,
+        (Some(o), CompareOp::Ne) => o != Equal,
+        (Some(o), CompareOp::Lt) => o == Less,
+        (Some(o), CompareOp::Le) => o != Greater,
+        (Some(o), CompareOp::Gt) => o == Greater,
+        (Some(o), CompareOp::Ge) => o != Less,
+        (Some(_), CompareOp::Exists) => true,
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Choice {
+    pub text: String,
+    pub next: String,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+    #[serde(default)]
+    pub tags: ContentTags,
+}
+
+// Constraints for a node where the LLM improvises
+#[derive(Debug, Clone, Deserialize)]
+pub struct AiNode {
+    // What the NPC is trying to achieve in this exchange
+    pub goal: String,
+    #[serde(default)]
+    pub persona: Option<String>,
+    #[serde(default)]
+    pub forbidden_topics: Vec<String>,
+    #[serde(default = "default_max_turns")]
+    pub max_turns: u32,
+    #[serde(default = "default_max_words")]
+    pub max_words: usize,
+    // Authored node the conversation returns to when the AI segment ends
+    pub exit: String,
+    // Line used when the model fails or breaks a constraint
+    #[serde(default)]
+    pub fallback_line: Option<String>,
+}
+
+fn default_max_turns() -> u32 {
+    3
+}
+
+fn default_max_words() -> usize {
+    60
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DialogueNode {
+    pub id: String,
+    #[serde(default)]
+    pub speaker: Option<String>,
+    #[serde(default)]
+    pub text: Option<String>,
+    #[serde(default)]
+    pub choices: Vec<Choice>,
+    // Automatic jump when there are no choices
+    #[serde(default)]
+    pub next: Option<String>,
+    #[serde(default)]
+    pub ai: Option<AiNode>,
+    // Content descriptors of `text`
+    #[serde(default)]
+    pub tags: ContentTags,
+}
+
+impl DialogueNode {
+    pub fn is_end(&self) -> bool {
+        self.choices.is_empty() && self.next.is_none() && self.ai.is_none()
+    }
+
+    fn targets(&self) -> Vec<(String, &str)> {
+        let mut targets: Vec<(String, &str)> = self
+            .choices
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (format!("choices[{}].next", i), c.next.as_str()))
+            .collect();
+        if let Some(next) = &self.next {
+            targets.push(("next".to_string(), next));
+        }
+        if let Some(ai) = &self.ai {
+            targets.push(("ai.exit".to_string(), &ai.exit));
+        }
+        targets
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DialogueTree {
+    pub id: String,
+    pub start: String,
+    pub nodes: Vec<DialogueNode>,
+}
+
+// State keys and characters the game exposes to dialogue conditions
+#[derive(Debug, Clone, Default)]
+pub struct DialogueSchema {
+    pub state_keys: HashSet<String>,
+    pub characters: HashSet<String>,
+}
+
+impl DialogueSchema {
+    pub fn new() -> Self {
+        DialogueSchema::default()
+    }
+
+    pub fn state_key(mut self, key: &str) -> Self {
+        self.state_keys.insert(key.to_string());
+        self
+    }
+
+    pub fn character(mut self, id: &str) -> Self {
+        self.characters.insert(id.to_string());
+        self
+    }
+}
+
+impl DialogueTree {
+    pub fn from_toml(contents: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(contents)
+    }
+
+    pub fn node(&self, id: &str) -> Option<&DialogueNode> {
+        self.nodes.iter().find(|n| n.id == id)
+    }
+
+    pub fn validate(&self, schema: &DialogueSchema) -> ValidationReport {
+        let mut report = ValidationReport::new();
+        let mut ids = HashSet::new();
+        for node in &self.nodes {
+            if !ids.insert(node.id.as_str()) {
+                report.error(&format!("nodes.{}", node.id), "duplicate node id");
+            }
+        }
+        if !ids.contains(self.start.as_str()) {
+            report.error("start", format!("start node '{}' does not exist", self.start));
+        }
+
+        for node in &self.nodes {
+            let path = format!("nodes.{}", node.id);
+            for (field, target) in node.targets() {
+                if !ids.contains(target) {
+                    report.error(&format!("{}.{}", path, field), format!("unknown node '{}'", target));
+                }
+            }
+            if node.next.is_some() && !node.choices.is_empty() {
+                report.warning(&path, "has both choices and next; next is only used when no choice is available");
+            }
+            if node.text.is_none() && node.ai.is_none() {
+                report.warning(&path, "has neither text nor an ai section");
+            }
+            if let Some(ai) = &node.ai {
+                if ai.goal.trim().is_empty() {
+                    report.error(&format!("{}.ai.goal", path), "must not be empty");
+                }
+                if ai.max_turns == 0 {
+                    report.error(&format!("{}.ai.max_turns", path), "must be at least 1");
+                }
+            }
+            for (i, choice) in node.choices.iter().enumerate() {
+                for (j, condition) in choice.conditions.iter().enumerate() {
+                    let cpath = format!("{}.choices[{}].conditions[{}]", path, i, j);
+                    validate_condition(condition, schema, &cpath, &mut report);
+                }
+            }
+        }
+
+        for id in self.unreachable() {
+            report.warning(&format!("nodes.{}", id), "unreachable from start");
+        }
+        report
+    }
+
+    fn unreachable(&self) -> Vec<&str> {
+        let mut seen = HashSet::new();
+        let mut queue = VecDeque::from([self.start.as_str()]);
+        while let Some(id) = queue.pop_front() {
+            if !seen.insert(id) {
+                continue;
+            }
+            if let Some(node) = self.node(id) {
+                queue.extend(node.targets().into_iter().map(|(_, t)| t));
+            }
+        }
+        self.nodes
+            .iter()
+            .map(|n| n.id.as_str())
+            .filter(|id| !seen.contains(id))
+            .collect()
+    }
+}
+
+fn validate_condition(condition: &Condition, schema: &DialogueSchema, path: &str, report: &mut ValidationReport) {
+    match (&condition.state, &condition.relationship) {
+        (Some(key), None) => {
+            if !schema.state_keys.contains(key) {
+                report.error(&format!("{}.state", path), format!("unknown state key '{}'", key));
+            }
+        }
+        (None, Some(character)) => {
+            if !schema.characters.contains(character) {
+                report.error(&format!("{}.relationship", path), format!("unknown character '{}'", character));
+            }
+            if condition.op != CompareOp::Exists && !condition.value.is_number() {
+                report.error(&format!("{}.value", path), "relationship conditions compare against a number");
+            }
+        }
+        (Some(_), Some(_)) => report.error(path, "set either state or relationship, not both"),
+        (None, None) => report.error(path, "must reference a state key or a relationship"),
+    }
+    if condition.op != CompareOp::Exists && condition.value.is_null() {
+        report.error(&format!("{}.value", path), "missing value to compare against");
+    }
+}
+
+// What conditions are evaluated against
+#[derive(Debug, Clone, Default)]
+pub struct DialogueContext {
+    pub state: WorldState,
+    // Relationship with the player per character, typically -1..1
+    pub relationships: HashMap<String, f32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DialogueError {
+    UnknownNode(String),
+    InvalidChoice(usize),
+    NotAnAiNode(String),
+    Finished,
+}
+
+impl fmt::Display for DialogueError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DialogueError::UnknownNode(id) => write!(f, "unknown dialogue node '{}'", id),
+            DialogueError::InvalidChoice(i) => write!(f, "choice {} is not available", i),
+            DialogueError::NotAnAiNode(id) => write!(f, "node '{}' is not an AI node", id),
+            DialogueError::Finished => write!(f, "the conversation has ended"),
+        }
+    }
+}
+
+impl std::error::Error for DialogueError {}
+
+// Result of an AI turn
+#[derive(Debug, Clone)]
+pub struct AiReply {
+    pub line: String,
+    // True when the model output was replaced by the fallback line
+    pub fell_back: bool,
+    // True when the segment ended and the session moved to the exit node
+    pub exited: bool,
+}
+
+// A conversation in progress over a tree
+pub struct DialogueSession<'a> {
+    tree: &'a DialogueTree,
+    current: Option<String>,
+    ai_turns: u32,
+    transcript: Vec<(String, String)>,
+    prompts: Option<Arc<PromptRegistry>>,
+    // Enforcer and the player being talked to
+    rating: Option<(Arc<Mutex<ContentRating>>, String)>,
+}
+
+impl<'a> DialogueSession<'a> {
+    pub fn start(tree: &'a DialogueTree) -> Result<Self, DialogueError> {
+        tree.node(&tree.start)
+            .ok_or_else(|| DialogueError::UnknownNode(tree.start.clone()))?;
+        Ok(DialogueSession {
+            tree,
+            current: Some(tree.start.clone()),
+            ai_turns: 0,
+            transcript: Vec::new(),
+            prompts: None,
+            rating: None,
+        })
+    }
+
+    // Render AI turns through a shared registry instead of the built-in template
+    pub fn with_prompts(mut self, prompts: Arc<PromptRegistry>) -> Self {
+        self.prompts = Some(prompts);
+        self
+    }
+
+    // Check what the session shows `player` against their rating profile
+    pub fn with_rating(mut self, rating: Arc<Mutex<ContentRating>>, player: &str) -> Self {
+        self.rating = Some((rating, player.to_string()));
+        self
+    }
+
+    // Whether the player may see `content`; true without an enforcer
+    fn permits(&self, content: &str, tags: Option<&ContentTags>) -> bool {
+        let Some((rating, player)) = &self.rating else { return true };
+        let mut rating = rating.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match tags {
+            Some(tags) => rating.check(player, Surface::Dialogue, content, tags),
+            None => rating.check_generated(player, Surface::Dialogue, content, content, None),
+        }
+    }
+
+    pub fn current(&self) -> Option<&'a DialogueNode> {
+        self.current.as_deref().and_then(|id| self.tree.node(id))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.current().is_none_or(|n| n.is_end())
+    }
+
+    // (speaker, line) pairs spoken so far in AI segments
+    pub fn transcript(&self) -> &[(String, String)] {
+        &self.transcript
+    }
+
+    // The current node's line, None when it has none or the player's rating withholds it
+    pub fn line(&self) -> Option<&'a str> {
+        let node = self.current()?;
+        let text = node.text.as_deref()?;
+        self.permits(text, Some(&node.tags)).then_some(text)
+    }
+
+    // Choices of the current node whose conditions hold and that the player's rating permits,
+    // with their original indices
+    pub fn available_choices(&self, ctx: &DialogueContext) -> Vec<(usize, &'a Choice)> {
+        self.current()
+            .map(|node| {
+                node.choices
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, c)| c.conditions.iter().all(|cond| cond.is_met(ctx)))
+                    .filter(|(_, c)| self.permits(&c.text, Some(&c.tags)))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn choose(&mut self, index: usize, ctx: &DialogueContext) -> Result<&'a DialogueNode, DialogueError> {
+        let next = self
+            .available_choices(ctx)
+            .into_iter()
+            .find(|(i, _)| *i == index)
+            .map(|(_, c)| c.next.clone())
+            .ok_or(DialogueError::InvalidChoice(index))?;
+        self.goto(&next)
+    }
+
+    // Follow the current node's automatic jump
+    pub fn advance(&mut self) -> Result<&'a DialogueNode, DialogueError> {
+        let next = self
+            .current()
+            .and_then(|n| n.next.clone())
+            .ok_or(DialogueError::Finished)?;
+        self.goto(&next)
+    }
+
+    fn goto(&mut self, id: &str) -> Result<&'a DialogueNode, DialogueError> {
+        let node = self.tree.node(id).ok_or_else(|| DialogueError::UnknownNode(id.to_string()))?;
+        self.current = Some(id.to_string());
+        self.ai_turns = 0;
+        Ok(node)
+    }
+
+    // Let the LLM answer the player inside an AI node, enforcing the node's constraints
+    pub fn ai_turn<G: TextGenerator>(&mut self, generator: &G, player_input: &str) -> Result<AiReply, DialogueError> {
+        let node = self.current().ok_or(DialogueError::Finished)?;
+        let ai = node.ai.as_ref().ok_or_else(|| DialogueError::NotAnAiNode(node.id.clone()))?;
+        let speaker = node.speaker.clone().unwrap_or_else(|| "npc".to_string());
+
+        // A prompt that fails to render falls back like a failed generation
+        let generated = ai_prompt(node, ai, &self.transcript, player_input, self.prompts.as_deref())
+            .ok()
+            .and_then(|prompt| generator.generate(&prompt).ok())
+            .map(|text| text.trim().to_string())
+            .filter(|text| !text.is_empty() && !breaks_constraints(text, ai))
+            .map(|text| truncate_words(&text, ai.max_words))
+            .filter(|text| self.permits(text, None));
+        let fell_back = generated.is_none();
+        let line = match generated {
+            Some(text) => text,
+            None => ai
+                .fallback_line
+                .clone()
+                .or_else(|| node.text.clone())
+                .unwrap_or_else(|| "...".to_string()),
+        };
+
+        self.transcript.push(("player".to_string(), player_input.to_string()));
+        self.transcript.push((speaker, line.clone()));
+        self.ai_turns += 1;
+
+        let exited = self.ai_turns >= ai.max_turns;
+        if exited {
+            let exit = ai.exit.clone();
+            self.goto(&exit)?;
+        }
+        Ok(AiReply { line, fell_back, exited })
+    }
+}
+
+fn ai_prompt(
+    node: &DialogueNode,
+    ai: &AiNode,
+    transcript: &[(String, String)],
+    player_input: &str,
+    registry: Option<&PromptRegistry>,
+) -> Result<String, PromptError> {
+    let transcript: String = transcript.iter().map(|(speaker, line)| format!("{}: {}\n", speaker, line)).collect();
+    let vars = [
+        ("persona", json!(ai.persona)),
+        ("scene", json!(node.text)),
+        ("goal", json!(ai.goal)),
+        ("forbidden", json!(ai.forbidden_topics)),
+        ("max_words", json!(ai.max_words)),
+        ("transcript", json!(transcript)),
+        ("player_input", json!(player_input)),
+    ];
+    prompts::render(registry, prompts::DIALOGUE_REPLY, &vars)
+}
+
+fn breaks_constraints(text: &str, ai: &AiNode) -> bool {
+    let lower = text.to_lowercase();
+    ai.forbidden_topics.iter().any(|t| lower.contains(&t.to_lowercase()))
+}
+
+fn truncate_words(text: &str, max_words: usize) -> String {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    if words.len() <= max_words {
+        text.to_string()
+    } else {
+        words[..max_words].join(" ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rating::{Rating, RatingProfile};
+
+    struct Fixed(&'static str);
+
+    impl TextGenerator for Fixed {
+        fn generate(&self, _prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+            Ok(self.0.to_string())
+        }
+    }
+
+    const TREE: &str = r#"
+id = "guard"
+start = "gate"
+
+[[nodes]]
+id = "gate"
+speaker = "guard"
+text = "Halt. State your business."
+
+[[nodes.choices]]
+text = "Trade."
+next = "talk"
+
+[[nodes.choices]]
+text = "Draw your sword."
+next = "talk"
+tags = { descriptors = { violence = "strong" } }
+
+[[nodes]]
+id = "talk"
+speaker = "guard"
+text = "He lies in a pool of blood."
+tags = { descriptors = { blood = "strong" } }
+
+[nodes.ai]
+goal = "Turn the player away"
+exit = "end"
+fallback_line = "Move along."
+
+[[nodes]]
+id = "end"
+"#;
+
+    fn rating() -> Arc<Mutex<ContentRating>> {
+        Arc::new(Mutex::new(ContentRating::new(RatingProfile::new(Rating::Everyone), 64)))
+    }
+
+    #[test]
+    fn withheld_choices_are_not_offered_and_audited() {
+        let tree = DialogueTree::from_toml(TREE).unwrap();
+        let rating = rating();
+        let session = DialogueSession::start(&tree).unwrap().with_rating(rating.clone(), "kid");
+        let choices = session.available_choices(&DialogueContext::default());
+        assert_eq!(choices.len(), 1);
+        assert_eq!(choices[0].1.text, "Trade.");
+
+        let rating = rating.lock().unwrap();
+        let denied: Vec<&str> = rating.audit_log().filter(|e| !e.allowed).map(|e| e.content.as_str()).collect();
+        assert_eq!(denied, vec!["Draw your sword."]);
+        assert!(rating.audit_log().all(|e| e.player == "kid" && e.surface == Surface::Dialogue));
+    }
+
+    #[test]
+    fn withheld_node_line_reads_as_none() {
+        let tree = DialogueTree::from_toml(TREE).unwrap();
+        let mut session = DialogueSession::start(&tree).unwrap().with_rating(rating(), "kid");
+        assert_eq!(session.line(), Some("Halt. State your business."));
+        session.choose(0, &DialogueContext::default()).unwrap();
+        assert_eq!(session.line(), None);
+
+        let mut unrated = DialogueSession::start(&tree).unwrap();
+        unrated.choose(0, &DialogueContext::default()).unwrap();
+        assert_eq!(unrated.line(), Some("He lies in a pool of blood."));
+    }
+
+    #[test]
+    fn withheld_ai_line_falls_back() {
+        let tree = DialogueTree::from_toml(TREE).unwrap();
+        let rating = rating();
+        let mut session = DialogueSession::start(&tree).unwrap().with_rating(rating.clone(), "kid");
+        session.choose(0, &DialogueContext::default()).unwrap();
+
+        let reply = session.ai_turn(&Fixed("Leave or I will kill you and stab your friends."), "hi").unwrap();
+        assert!(reply.fell_back);
+        assert_eq!(reply.line, "Move along.");
+        assert!(rating.lock().unwrap().audit_log().any(|e| !e.allowed && e.content.contains("kill")));
+
+        let reply = session.ai_turn(&Fixed("The captain is not receiving visitors."), "please").unwrap();
+        assert!(!reply.fell_back);
+        assert_eq!(reply.line, "The captain is not receiving visitors.");
+    }
+
+    const GATED: &str = r#"
+id = "smith"
+start = "hello"
+
+[[nodes]]
+id = "hello"
+speaker = "smith"
+text = "Need something forged?"
+
+[[nodes.choices]]
+text = "Show me the royal blade."
+next = "vault"
+conditions = [{ state = "quest_stage", op = "ge", value = 3 }]
+
+[[nodes.choices]]
+text = "Any discount for a friend?"
+next = "chat"
+conditions = [{ relationship = "smith", op = "gt", value = 0.5 }]
+
+[[nodes.choices]]
+text = "Just browsing."
+next = "chat"
+
+[[nodes]]
+id = "vault"
+text = "Few have seen it."
+next = "bye"
+
+[[nodes]]
+id = "chat"
+speaker = "smith"
+text = "The forge is hot today."
+
+[nodes.ai]
+goal = "Sell the player a sword"
+forbidden_topics = ["dragon"]
+max_turns = 2
+max_words = 4
+exit = "bye"
+fallback_line = "Take a look around."
+
+[[nodes]]
+id = "bye"
+text = "Safe travels."
+"#;
+
+    fn offered(session: &DialogueSession, ctx: &DialogueContext) -> Vec<usize> {
+        session.available_choices(ctx).into_iter().map(|(i, _)| i).collect()
+    }
+
+    #[test]
+    fn conditions_gate_choices_on_state_and_relationships() {
+        let tree = DialogueTree::from_toml(GATED).unwrap();
+        let mut session = DialogueSession::start(&tree).unwrap();
+        let mut ctx = DialogueContext::default();
+        assert_eq!(offered(&session, &ctx), vec![2]);
+        assert_eq!(session.choose(0, &ctx).unwrap_err(), DialogueError::InvalidChoice(0));
+
+        ctx.state.set("quest_stage", 3i64);
+        ctx.relationships.insert("smith".to_string(), 0.8);
+        assert_eq!(offered(&session, &ctx), vec![0, 1, 2]);
+
+        assert_eq!(session.choose(0, &ctx).unwrap().id, "vault");
+        assert_eq!(session.advance().unwrap().id, "bye");
+        assert!(session.is_finished());
+        assert_eq!(session.advance().unwrap_err(), DialogueError::Finished);
+    }
+
+    #[test]
+    fn conditions_on_missing_or_mismatched_values_fail() {
+        let ctx = DialogueContext { state: WorldState::new().with("door", "open"), ..Default::default() };
+        let condition = |state: &str, op, value| Condition { state: Some(state.to_string()), relationship: None, op, value };
+        assert!(condition("door", CompareOp::Exists, Value::Null).is_met(&ctx));
+        assert!(condition("door", CompareOp::Eq, json!("open")).is_met(&ctx));
+        assert!(!condition("door", CompareOp::Eq, json!(true)).is_met(&ctx));
+        assert!(condition("door", CompareOp::Ne, json!(true)).is_met(&ctx));
+        assert!(!condition("key", CompareOp::Exists, Value::Null).is_met(&ctx));
+    }
+
+    #[test]
+    fn validator_flags_bad_references_and_unknown_inputs() {
+        let schema = DialogueSchema::new().state_key("quest_stage").character("smith");
+        let tree = DialogueTree::from_toml(GATED).unwrap();
+        let report = tree.validate(&schema);
+        assert!(report.is_valid(), "{:?}", report);
+
+        let broken = GATED
+            .replace("next = \"vault\"", "next = \"cellar\"")
+            .replace("state = \"quest_stage\"", "state = \"questStage\"")
+            .replace("relationship = \"smith\"", "relationship = \"smithy\"");
+        let report = DialogueTree::from_toml(&broken).unwrap().validate(&schema);
+        let errors: Vec<&str> = report.errors().map(|e| e.path.as_str()).collect();
+        assert_eq!(
+            errors,
+            vec![
+                "nodes.hello.choices[0].next",
+                "nodes.hello.choices[0].conditions[0].state",
+                "nodes.hello.choices[1].conditions[0].relationship",
+            ]
+        );
+        assert!(report.warnings().any(|w| w.path == "nodes.vault" && w.message.contains("unreachable")));
+    }
+
+    #[test]
+    fn ai_segment_enforces_constraints_and_returns_through_exit() {
+        let tree = DialogueTree::from_toml(GATED).unwrap();
+        let mut session = DialogueSession::start(&tree).unwrap();
+        session.choose(2, &DialogueContext::default()).unwrap();
+
+        let reply = session.ai_turn(&Fixed("A dragon scale blade, cheap!"), "what's new?").unwrap();
+        assert!(reply.fell_back);
+        assert_eq!(reply.line, "Take a look around.");
+        assert!(!reply.exited);
+
+        let reply = session.ai_turn(&Fixed("  This fine steel sword suits you well.  "), "hmm").unwrap();
+        assert!(!reply.fell_back);
+        assert_eq!(reply.line, "This fine steel sword");
+        assert!(reply.exited);
+        assert_eq!(session.current().unwrap().id, "bye");
+        assert_eq!(session.transcript().len(), 4);
+        assert_eq!(session.transcript()[1], ("smith".to_string(), "Take a look around.".to_string()));
+        assert!(matches!(session.ai_turn(&Fixed("hi"), "hi"), Err(DialogueError::NotAnAiNode(id)) if id == "bye"));
+    }
+
+    #[test]
+    fn ai_defaults_apply_when_omitted() {
+        let tree = DialogueTree::from_toml(TREE).unwrap();
+        let ai = tree.node("talk").unwrap().ai.as_ref().unwrap();
+        assert_eq!(ai.max_turns, default_max_turns());
+        assert_eq!(ai.max_words, default_max_words());
+        assert!(tree.node("end").unwrap().is_end());
+    }
+}
@@ -0,0 +1,417 @@
+// Economy simulation
+//
+// Each region has a market per good with supply and demand; prices follow a constant-elasticity
+// curve around the good's base price. NPC traders hold stock and money, restock from their
+// region and set their own prices from the market price and their strategy. Money entering
+// (loot, quest rewards) and leaving (fees, repairs) the economy is tracked so inflation can be
+// watched. Trader strategies are plain gene vectors so the evolutionary system can tune them.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Good {
+    pub id: String,
+    pub base_price: f64,
+    // How strongly price reacts to the demand/supply ratio
+    pub elasticity: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Market {
+    pub supply: f64,
+    pub demand: f64,
+    // Levels supply and demand drift back to without trade
+    pub baseline_supply: f64,
+    pub baseline_demand: f64,
+    pub price: f64,
+}
+
+impl Market {
+    pub fn new(supply: f64, demand: f64) -> Self {
+        Market {
+            supply,
+            demand,
+            baseline_supply: supply,
+            baseline_demand: demand,
+            price: 0.0,
+        }
+    }
+
+    fn reprice(&mut self, good: &Good) {
+        let ratio = self.demand.max(0.01) / self.supply.max(0.01);
+        self.price = good.base_price * ratio.powf(good.elasticity);
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Region {
+    pub name: String,
+    pub markets: HashMap<String, Market>,
+}
+
+// Tunable merchant behaviour
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TraderStrategy {
+    // Margin over market price when selling
+    pub markup: f64,
+    // Fraction of the gap to the target price closed per day
+    pub adjustment_rate: f64,
+    // Units of each good the trader tries to keep in stock
+    pub stock_target: f64,
+    // Extra markup per unit of stock below target
+    pub scarcity_premium: f64,
+}
+
+impl Default for TraderStrategy {
+    fn default() -> Self {
+        TraderStrategy {
+            markup: 0.2,
+            adjustment_rate: 0.3,
+            stock_target: 10.0,
+            scarcity_premium: 0.02,
+        }
+    }
+}
+
+impl TraderStrategy {
+    // Gene vector for the evolutionary system
+    pub fn to_genes(self) -> Vec<f64> {
+        vec![self.markup, self.adjustment_rate, self.stock_target, self.scarcity_premium]
+    }
+
+    // Inverse of to_genes, clamped to sane ranges
+    pub fn from_genes(genes: &[f64]) -> Self {
+        let gene = |i: usize, default: f64| genes.get(i).copied().unwrap_or(default);
+        let defaults = TraderStrategy::default();
+        TraderStrategy {
+            markup: gene(0, defaults.markup).clamp(0.0, 2.0),
+            adjustment_rate: gene(1, defaults.adjustment_rate).clamp(0.01, 1.0),
+            stock_target: gene(2, defaults.stock_target).clamp(1.0, 500.0),
+            scarcity_premium: gene(3, defaults.scarcity_premium).clamp(0.0, 0.5),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Trader {
+    pub id: String,
+    pub region: String,
+    pub money: f64,
+    pub inventory: HashMap<String, f64>,
+    pub prices: HashMap<String, f64>,
+    pub strategy: TraderStrategy,
+    // Sales revenue minus restocking cost since the last fitness reset
+    pub profit: f64,
+}
+
+// Money entering and leaving the economy, by reason
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MoneyLedger {
+    pub sources: HashMap<String, f64>,
+    pub sinks: HashMap<String, f64>,
+}
+
+impl MoneyLedger {
+    pub fn total_sources(&self) -> f64 {
+        self.sources.values().sum()
+    }
+
+    pub fn total_sinks(&self) -> f64 {
+        self.sinks.values().sum()
+    }
+
+    // Positive means money supply is growing (inflationary pressure)
+    pub fn net_flow(&self) -> f64 {
+        self.total_sources() - self.total_sinks()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EconomyError {
+    UnknownGood(String),
+    UnknownRegion(String),
+    UnknownTrader(String),
+    OutOfStock { good: String, available: f64 },
+    InsufficientFunds { needed: f64, available: f64 },
+}
+
+impl fmt::Display for EconomyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EconomyError::UnknownGood(id) => write!(f, "unknown good '{}'", id),
+            EconomyError::UnknownRegion(id) => write!(f, "unknown region '{}'", id),
+            EconomyError::UnknownTrader(id) => write!(f, "unknown trader '{}'", id),
+            EconomyError::OutOfStock { good, available } => write!(f, "only {} {} in stock", available, good),
+            EconomyError::InsufficientFunds { needed, available } => {
+                write!(f, "needs {:.2} but only {:.2} available", needed, available)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EconomyError {}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Economy {
+    goods: HashMap<String, Good>,
+    regions: HashMap<String, Region>,
+    traders: HashMap<String, Trader>,
+    ledger: MoneyLedger,
+}
+
+impl Economy {
+    pub fn new() -> Self {
+        Economy::default()
+    }
+
+    pub fn add_good(&mut self, good: Good) {
+        self.goods.insert(good.id.clone(), good);
+    }
+
+    pub fn add_region(&mut self, name: &str) {
+        self.regions.entry(name.to_string()).or_insert_with(|| Region {
+            name: name.to_string(),
+            markets: HashMap::new(),
+        });
+    }
+
+    pub fn set_market(&mut self, region: &str, good: &str, market: Market) -> Result<(), EconomyError> {
+        let good_def = self.goods.get(good).ok_or_else(|| EconomyError::UnknownGood(good.to_string()))?;
+        let region = self
+            .regions
+            .get_mut(region)
+            .ok_or_else(|| EconomyError::UnknownRegion(region.to_string()))?;
+        let mut market = market;
+        market.reprice(good_def);
+        region.markets.insert(good.to_string(), market);
+        Ok(())
+    }
+
+    pub fn add_trader(&mut self, id: &str, region: &str, money: f64, strategy: TraderStrategy) -> Result<(), EconomyError> {
+        if !self.regions.contains_key(region) {
+            return Err(EconomyError::UnknownRegion(region.to_string()));
+        }
+        self.traders.insert(
+            id.to_string(),
+            Trader {
+                id: id.to_string(),
+                region: region.to_string(),
+                money,
+                inventory: HashMap::new(),
+                prices: HashMap::new(),
+                strategy,
+                profit: 0.0,
+            },
+        );
+        self.record_source("trader_seed_capital", money);
+        Ok(())
+    }
+
+    pub fn region(&self, name: &str) -> Option<&Region> {
+        self.regions.get(name)
+    }
+
+    pub fn regions(&self) -> impl Iterator<Item = &Region> {
+        self.regions.values()
+    }
+
+    pub fn trader(&self, id: &str) -> Option<&Trader> {
+        self.traders.get(id)
+    }
+
+    pub fn market_price(&self, region: &str, good: &str) -> Option<f64> {
+        self.regions.get(region)?.markets.get(good).map(|m| m.price)
+    }
+
+    pub fn ledger(&self) -> &MoneyLedger {
+        &self.ledger
+    }
+
+    // Money created by the game (loot drops, quest rewards, bounties)
+    pub fn record_source(&mut self, reason: &str, amount: f64) {
+        *self.ledger.sources.entry(reason.to_string()).or_default() += amount.max(0.0);
+    }
+
+    // Money destroyed by the game (repair fees, taxes, fast travel)
+    pub fn record_sink(&mut self, reason: &str, amount: f64) {
+        *self.ledger.sinks.entry(reason.to_string()).or_default() += amount.max(0.0);
+    }
+
+    // Advance the simulation by `days` of game time
+    pub fn tick(&mut self, days: f64) {
+        let drift = 1.0 - (-days * 0.5).exp();
+        for region in self.regions.values_mut() {
+            for (good_id, market) in region.markets.iter_mut() {
+                market.supply += (market.baseline_supply - market.supply) * drift;
+                market.demand += (market.baseline_demand - market.demand) * drift;
+                if let Some(good) = self.goods.get(good_id) {
+                    market.reprice(good);
+                }
+            }
+        }
+
+        for trader in self.traders.values_mut() {
+            let region = match self.regions.get_mut(&trader.region) {
+                Some(region) => region,
+                None => continue,
+            };
+            // Restocking spends money, so goods are visited in a fixed order to keep replays identical
+            let mut markets: Vec<(&String, &mut Market)> = region.markets.iter_mut().collect();
+            markets.sort_by(|a, b| a.0.cmp(b.0));
+            for (good_id, market) in markets {
+                // Restock from the regional market up to the stock target
+                let stock = trader.inventory.entry(good_id.clone()).or_default();
+                let wanted = (trader.strategy.stock_target - *stock).max(0.0).min(market.supply * 0.1);
+                let affordable = if market.price > 0.0 { (trader.money / market.price).floor() } else { 0.0 };
+                let bought = wanted.min(affordable);
+                if bought > 0.0 {
+                    *stock += bought;
+                    market.supply -= bought;
+                    trader.money -= bought * market.price;
+                    trader.profit -= bought * market.price;
+                }
+
+                let shortage = (trader.strategy.stock_target - *stock).max(0.0);
+                let target = market.price * (1.0 + trader.strategy.markup + shortage * trader.strategy.scarcity_premium);
+                let price = trader.prices.entry(good_id.clone()).or_insert(target);
+                let rate = (trader.strategy.adjustment_rate * days).min(1.0);
+                *price += (target - *price) * rate;
+            }
+        }
+    }
+
+    // A player buys from a trader; returns the total price paid
+    pub fn buy(&mut self, trader_id: &str, good: &str, quantity: f64, player_money: &mut f64) -> Result<f64, EconomyError> {
+        let trader = self
+            .traders
+            .get_mut(trader_id)
+            .ok_or_else(|| EconomyError::UnknownTrader(trader_id.to_string()))?;
+        let price = *trader.prices.get(good).ok_or_else(|| EconomyError::UnknownGood(good.to_string()))?;
+        let stock = trader.inventory.get(good).copied().unwrap_or(0.0);
+        if stock < quantity {
+            return Err(EconomyError::OutOfStock { good: good.to_string(), available: stock });
+        }
+        let total = price * quantity;
+        if *player_money < total {
+            return Err(EconomyError::InsufficientFunds { needed: total, available: *player_money });
+        }
+        *player_money -= total;
+        trader.money += total;
+        trader.profit += total;
+        *trader.inventory.entry(good.to_string()).or_default() -= quantity;
+        if let Some(market) = self.regions.get_mut(&trader.region).and_then(|r| r.markets.get_mut(good)) {
+            market.demand += quantity;
+        }
+        Ok(total)
+    }
+
+    // A player sells to a trader, who pays the market price less its markup
+    pub fn sell(&mut self, trader_id: &str, good: &str, quantity: f64, player_money: &mut f64) -> Result<f64, EconomyError> {
+        let market_price = {
+            let trader = self
+                .traders
+                .get(trader_id)
+                .ok_or_else(|| EconomyError::UnknownTrader(trader_id.to_string()))?;
+            self.market_price(&trader.region, good)
+                .ok_or_else(|| EconomyError::UnknownGood(good.to_string()))?
+        };
+        let trader = self.traders.get_mut(trader_id).expect("trader checked above");
+        let offer = market_price / (1.0 + trader.strategy.markup) * quantity;
+        if trader.money < offer {
+            return Err(EconomyError::InsufficientFunds { needed: offer, available: trader.money });
+        }
+        trader.money -= offer;
+        trader.profit -= offer;
+        *trader.inventory.entry(good.to_string()).or_default() += quantity;
+        *player_money += offer;
+        if let Some(market) = self.regions.get_mut(&trader.region).and_then(|r| r.markets.get_mut(good)) {
+            market.supply += quantity;
+        }
+        Ok(offer)
+    }
+
+    // Hook for the evolutionary system: profit since the last reset plus stock value
+    pub fn trader_fitness(&self, trader_id: &str) -> Option<f64> {
+        let trader = self.traders.get(trader_id)?;
+        let stock_value: f64 = trader
+            .inventory
+            .iter()
+            .filter_map(|(good, qty)| self.market_price(&trader.region, good).map(|p| p * qty))
+            .sum();
+        Some(trader.profit + stock_value * 0.5)
+    }
+
+    pub fn set_trader_strategy(&mut self, trader_id: &str, strategy: TraderStrategy) -> Result<(), EconomyError> {
+        let trader = self
+            .traders
+            .get_mut(trader_id)
+            .ok_or_else(|| EconomyError::UnknownTrader(trader_id.to_string()))?;
+        trader.strategy = strategy;
+        Ok(())
+    }
+
+    pub fn reset_fitness(&mut self) {
+        for trader in self.traders.values_mut() {
+            trader.profit = 0.0;
+        }
+    }
+
+    pub fn trader_ids(&self) -> Vec<&str> {
+        self.traders.keys().map(String::as_str).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn economy() -> Economy {
+        let mut economy = Economy::new();
+        economy.add_good(Good { id: "iron".to_string(), base_price: 10.0, elasticity: 1.0 });
+        economy.add_region("hollow");
+        economy.set_market("hollow", "iron", Market::new(100.0, 100.0)).unwrap();
+        economy.add_trader("smith", "hollow", 500.0, TraderStrategy::default()).unwrap();
+        economy
+    }
+
+    #[test]
+    fn prices_follow_demand_and_drift_back() {
+        let mut economy = economy();
+        assert_eq!(economy.market_price("hollow", "iron"), Some(10.0));
+        economy.tick(1.0);
+        let mut gold = 1000.0;
+        let stock = economy.trader("smith").unwrap().inventory["iron"];
+        assert_eq!(stock, 10.0);
+        let paid = economy.buy("smith", "iron", 5.0, &mut gold).unwrap();
+        assert_eq!(gold, 1000.0 - paid);
+        assert!(matches!(economy.buy("smith", "iron", 50.0, &mut gold), Err(EconomyError::OutOfStock { .. })));
+
+        // The purchase raised demand, restocking lowered supply: dearer until the market recovers
+        let market = &economy.region("hollow").unwrap().markets["iron"];
+        assert_eq!((market.supply, market.demand), (90.0, 105.0));
+        economy.tick(0.0);
+        let raised = economy.market_price("hollow", "iron").unwrap();
+        assert!(raised > 11.0);
+        economy.tick(30.0);
+        assert!(economy.market_price("hollow", "iron").unwrap() < raised);
+    }
+
+    #[test]
+    fn ledger_and_genes_round_trip() {
+        let mut economy = economy();
+        economy.record_source("loot", 40.0);
+        economy.record_sink("repairs", 15.0);
+        economy.record_sink("refund", -5.0);
+        assert_eq!(economy.ledger().net_flow(), 525.0);
+
+        let strategy = TraderStrategy { markup: 0.5, adjustment_rate: 0.2, stock_target: 12.0, scarcity_premium: 0.01 };
+        let back = TraderStrategy::from_genes(&strategy.to_genes());
+        assert_eq!(back.to_genes(), strategy.to_genes());
+        // Out-of-range genes are clamped
+        assert_eq!(TraderStrategy::from_genes(&[9.0]).markup, 2.0);
+        assert!(matches!(economy.set_trader_strategy("ghost", back), Err(EconomyError::UnknownTrader(_))));
+    }
+}
@@ -0,0 +1,464 @@
+// Embedding model migration
+//
+// Moving a collection to a new embedding model happens in phases:
+//   1. dual-write: new content and deletes go to both the old and the new collection
+//   2. backfill: existing points are re-embedded into the new collection, rate limited
+//   3. verify: sample queries are run against both collections and recall is compared
+//   4. cut over: reads switch to the new collection, once no point is left failed or pending
+//   5. cleanup: the old collection is dropped
+
+use std::collections::{HashMap, HashSet};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+
+use crate::embeddings::Embedder;
+use crate::vector_index::{VectorIndex, VectorIndexError, VectorPoint, TEXT_FIELD};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationPhase {
+    DualWrite,
+    Verified,
+    CutOver,
+    Cleaned,
+}
+
+// Backfill progress, suitable for progress bars and logs
+#[derive(Debug, Clone)]
+pub struct MigrationProgress {
+    pub total: usize,
+    pub migrated: usize,
+    // Points without source text cannot be re-embedded
+    pub skipped: Vec<String>,
+    pub failed: Vec<String>,
+    pub started_at: Instant,
+}
+
+impl MigrationProgress {
+    pub fn remaining(&self) -> usize {
+        self.total - self.migrated - self.skipped.len() - self.failed.len()
+    }
+
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            (self.total - self.remaining()) as f32 / self.total as f32
+        }
+    }
+
+    pub fn eta(&self) -> Option<Duration> {
+        let done = self.total - self.remaining();
+        if done == 0 {
+            return None;
+        }
+        let per_point = self.started_at.elapsed() / done as u32;
+        Some(per_point * self.remaining() as u32)
+    }
+}
+
+// Result of comparing old and new collections on sample queries
+#[derive(Debug, Clone)]
+pub struct RecallReport {
+    pub queries: usize,
+    pub k: usize,
+    // Mean overlap between old and new top-k results, 0..1
+    pub recall: f32,
+    pub worst_queries: Vec<(String, f32)>,
+}
+
+#[derive(Debug)]
+pub enum MigrationError {
+    Index(VectorIndexError),
+    WrongPhase { expected: MigrationPhase, actual: MigrationPhase },
+    BackfillIncomplete(usize),
+    // Points whose re-embedding failed; `retry_failed` queues them again
+    BackfillFailed(usize),
+    RecallTooLow { recall: f32, required: f32 },
+}
+
+impl std::fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MigrationError::Index(err) => write!(f, "{}", err),
+            MigrationError::WrongPhase { expected, actual } => {
+                write!(f, "migration is in phase {:?}, expected {:?}", actual, expected)
+            }
+            MigrationError::BackfillIncomplete(remaining) => write!(f, "{} points still need re-embedding", remaining),
+            MigrationError::BackfillFailed(failed) => write!(f, "{} points failed to re-embed", failed),
+            MigrationError::RecallTooLow { recall, required } => {
+                write!(f, "recall {:.3} is below the required {:.3}", recall, required)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<VectorIndexError> for MigrationError {
+    fn from(err: VectorIndexError) -> Self {
+        MigrationError::Index(err)
+    }
+}
+
+pub struct EmbeddingMigration {
+    source: String,
+    target: String,
+    phase: MigrationPhase,
+    pending: Vec<String>,
+    progress: MigrationProgress,
+    // Maximum re-embedding calls per second during backfill
+    max_per_second: u32,
+    last_batch: Option<Instant>,
+    recall: Option<RecallReport>,
+}
+
+impl EmbeddingMigration {
+    // Creates the target collection and queues every existing point for re-embedding
+    pub fn start(
+        index: &mut VectorIndex,
+        source: &str,
+        target: &str,
+        new_embedder: &dyn Embedder,
+        max_per_second: u32,
+    ) -> Result<Self, MigrationError> {
+        let pending = index.ids(source)?;
+        index.create_collection(target, new_embedder.dimension(), new_embedder.model())?;
+        Ok(EmbeddingMigration {
+            source: source.to_string(),
+            target: target.to_string(),
+            phase: MigrationPhase::DualWrite,
+            progress: MigrationProgress {
+                total: pending.len(),
+                migrated: 0,
+                skipped: Vec::new(),
+                failed: Vec::new(),
+                started_at: Instant::now(),
+            },
+            pending,
+            max_per_second: max_per_second.max(1),
+            last_batch: None,
+            recall: None,
+        })
+    }
+
+    pub fn phase(&self) -> MigrationPhase {
+        self.phase
+    }
+
+    pub fn progress(&self) -> &MigrationProgress {
+        &self.progress
+    }
+
+    pub fn recall(&self) -> Option<&RecallReport> {
+        self.recall.as_ref()
+    }
+
+    // Collection reads should go to right now
+    pub fn active_collection(&self) -> &str {
+        match self.phase {
+            MigrationPhase::DualWrite | MigrationPhase::Verified => &self.source,
+            MigrationPhase::CutOver | MigrationPhase::Cleaned => &self.target,
+        }
+    }
+
+    // Store new content; before cutover it is written to both collections
+    pub fn store_text(
+        &mut self,
+        index: &mut VectorIndex,
+        old_embedder: &dyn Embedder,
+        new_embedder: &dyn Embedder,
+        id: &str,
+        text: &str,
+        payload: HashMap<String, Value>,
+    ) -> Result<(), MigrationError> {
+        match self.phase {
+            MigrationPhase::DualWrite | MigrationPhase::Verified => {
+                index.store_text(&self.source, id, text, payload.clone(), old_embedder)?;
+                index.store_text(&self.target, id, text, payload, new_embedder)?;
+                // A fresh write supersedes any queued or failed backfill of the same id
+                if let Some(pos) = self.pending.iter().position(|p| p == id) {
+                    self.pending.remove(pos);
+                    self.progress.migrated += 1;
+                } else if let Some(pos) = self.progress.failed.iter().position(|p| p == id) {
+                    self.progress.failed.remove(pos);
+                    self.progress.migrated += 1;
+                }
+            }
+            MigrationPhase::CutOver | MigrationPhase::Cleaned => {
+                index.store_text(&self.target, id, text, payload, new_embedder)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Delete points; before cutover they are deleted from both collections so a point removed after
+    // its backfill does not come back with the new collection. Returns the number removed from the
+    // collection reads go to.
+    pub fn delete(&mut self, index: &mut VectorIndex, ids: &[String]) -> Result<usize, MigrationError> {
+        match self.phase {
+            MigrationPhase::DualWrite | MigrationPhase::Verified => {
+                let removed = index.delete(&self.source, ids)?;
+                index.delete(&self.target, ids)?;
+                for id in ids {
+                    // Nothing left to re-embed, same as a point deleted before its batch ran
+                    let queued = self.pending.iter().position(|p| p == id).map(|pos| self.pending.remove(pos));
+                    let failed = self.progress.failed.iter().position(|p| p == id).map(|pos| self.progress.failed.remove(pos));
+                    if let Some(id) = queued.or(failed) {
+                        self.progress.skipped.push(id);
+                    }
+                }
+                Ok(removed)
+            }
+            MigrationPhase::CutOver | MigrationPhase::Cleaned => Ok(index.delete(&self.target, ids)?),
+        }
+    }
+
+    // Re-embeds up to `batch_size` queued points. Returns the number processed, or 0 when the
+    // rate limit has not yet allowed another batch.
+    pub fn backfill_step(
+        &mut self,
+        index: &mut VectorIndex,
+        new_embedder: &dyn Embedder,
+        batch_size: usize,
+    ) -> Result<usize, MigrationError> {
+        let batch_size = batch_size.clamp(1, self.max_per_second as usize);
+        if let Some(last) = self.last_batch {
+            let min_interval = Duration::from_secs_f64(batch_size as f64 / self.max_per_second as f64);
+            if last.elapsed() < min_interval {
+                return Ok(0);
+            }
+        }
+        self.last_batch = Some(Instant::now());
+
+        let take = batch_size.min(self.pending.len());
+        let batch: Vec<String> = self.pending.drain(..take).collect();
+        for id in &batch {
+            let point = match index.get(&self.source, id)? {
+                Some(point) => point.clone(),
+                // Deleted since the migration started
+                None => {
+                    self.progress.skipped.push(id.clone());
+                    continue;
+                }
+            };
+            let text = match point.text() {
+                Some(text) => text.to_string(),
+                None => {
+                    self.progress.skipped.push(id.clone());
+                    continue;
+                }
+            };
+            match new_embedder.embed(&text) {
+                Ok(vector) => {
+                    let mut payload = point.payload;
+                    payload.insert(TEXT_FIELD.to_string(), Value::String(text));
+                    // Sparse vectors come from the text, not the dense model, so they carry over
+                    let point = VectorPoint { id: id.clone(), vector, sparse: point.sparse, payload, version: 0 };
+                    index.upsert(&self.target, point)?;
+                    self.progress.migrated += 1;
+                }
+                Err(_) => self.progress.failed.push(id.clone()),
+            }
+        }
+        Ok(batch.len())
+    }
+
+    // Runs the backfill to completion on the current thread, sleeping to honour the rate limit.
+    // Intended for a background thread or a maintenance command.
+    pub fn run_backfill(
+        &mut self,
+        index: &mut VectorIndex,
+        new_embedder: &dyn Embedder,
+        batch_size: usize,
+        mut on_progress: impl FnMut(&MigrationProgress),
+    ) -> Result<(), MigrationError> {
+        while !self.pending.is_empty() {
+            if self.backfill_step(index, new_embedder, batch_size)? == 0 {
+                thread::sleep(Duration::from_millis(50));
+                continue;
+            }
+            on_progress(&self.progress);
+        }
+        Ok(())
+    }
+
+    // Re-queue points whose re-embedding failed
+    pub fn retry_failed(&mut self) {
+        self.pending.append(&mut self.progress.failed);
+    }
+
+    // Compares top-k results of old vs new collections. With no queries given, the stored texts
+    // of up to `sample_size` evenly spaced source points are used as queries.
+    pub fn verify(
+        &mut self,
+        index: &VectorIndex,
+        old_embedder: &dyn Embedder,
+        new_embedder: &dyn Embedder,
+        queries: &[&str],
+        sample_size: usize,
+        k: usize,
+    ) -> Result<&RecallReport, MigrationError> {
+        self.check_backfilled()?;
+
+        let mut sample: Vec<String> = queries.iter().map(|q| q.to_string()).collect();
+        if sample.is_empty() {
+            let ids = index.ids(&self.source)?;
+            let step = (ids.len() / sample_size.max(1)).max(1);
+            for id in ids.iter().step_by(step).take(sample_size) {
+                if let Some(text) = index.get(&self.source, id)?.and_then(|p| p.text()) {
+                    sample.push(text.to_string());
+                }
+            }
+        }
+
+        let mut scores = Vec::with_capacity(sample.len());
+        for query in &sample {
+            let old: HashSet<String> = index
+                .search_text(&self.source, query, k, old_embedder)?
+                .into_iter()
+                .map(|r| r.id)
+                .collect();
+            let new = index.search_text(&self.target, query, k, new_embedder)?;
+            let overlap = new.iter().filter(|r| old.contains(&r.id)).count();
+            let recall = if old.is_empty() { 1.0 } else { overlap as f32 / old.len() as f32 };
+            scores.push((query.clone(), recall));
+        }
+
+        let recall = if scores.is_empty() {
+            1.0
+        } else {
+            scores.iter().map(|(_, r)| r).sum::<f32>() / scores.len() as f32
+        };
+        scores.sort_by(|a, b| a.1.total_cmp(&b.1));
+        scores.truncate(5);
+
+        self.phase = MigrationPhase::Verified;
+        self.recall = Some(RecallReport { queries: sample.len(), k, recall, worst_queries: scores });
+        Ok(self.recall.as_ref().expect("recall set above"))
+    }
+
+    // Switch reads to the new collection once verified recall meets the threshold
+    pub fn cut_over(&mut self, min_recall: f32) -> Result<(), MigrationError> {
+        if self.phase != MigrationPhase::Verified {
+            return Err(MigrationError::WrongPhase { expected: MigrationPhase::Verified, actual: self.phase });
+        }
+        // Never switch reads while points are missing from the target
+        self.check_backfilled()?;
+        let recall = self.recall.as_ref().map(|r| r.recall).unwrap_or(0.0);
+        if recall < min_recall {
+            return Err(MigrationError::RecallTooLow { recall, required: min_recall });
+        }
+        self.phase = MigrationPhase::CutOver;
+        Ok(())
+    }
+
+    // Every source point is in the target collection or was skipped
+    fn check_backfilled(&self) -> Result<(), MigrationError> {
+        if !self.pending.is_empty() {
+            return Err(MigrationError::BackfillIncomplete(self.pending.len()));
+        }
+        if !self.progress.failed.is_empty() {
+            return Err(MigrationError::BackfillFailed(self.progress.failed.len()));
+        }
+        Ok(())
+    }
+
+    // Drop the old collection after cutover
+    pub fn cleanup(&mut self, index: &mut VectorIndex) -> Result<(), MigrationError> {
+        if self.phase != MigrationPhase::CutOver {
+            return Err(MigrationError::WrongPhase { expected: MigrationPhase::CutOver, actual: self.phase });
+        }
+        index.drop_collection(&self.source)?;
+        self.phase = MigrationPhase::Cleaned;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+    use crate::embeddings::{EmbeddingError, HashEmbedder};
+    use crate::vector_index::VectorIndexConfig;
+
+    // HashEmbedder that fails on texts containing "flaky" until told to recover
+    struct Flaky {
+        inner: HashEmbedder,
+        failing: Cell<bool>,
+    }
+
+    impl Embedder for Flaky {
+        fn model(&self) -> &str {
+            "flaky"
+        }
+
+        fn dimension(&self) -> usize {
+            self.inner.dimension()
+        }
+
+        fn embed(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+            if self.failing.get() && text.contains("flaky") {
+                return Err(EmbeddingError::Provider("timeout".to_string()));
+            }
+            self.inner.embed(text)
+        }
+    }
+
+    fn index(old: &dyn Embedder, texts: &[(&str, &str)]) -> VectorIndex {
+        let mut index = VectorIndex::new(VectorIndexConfig {
+            url: String::new(),
+            api_key: String::new(),
+            default_ttl_secs: None,
+            collection_ttl_secs: Default::default(),
+        });
+        index.create_collection("memories", old.dimension(), old.model()).unwrap();
+        for (id, text) in texts {
+            index.store_text("memories", id, text, HashMap::new(), old).unwrap();
+        }
+        index
+    }
+
+    #[test]
+    fn deletes_during_dual_write_reach_the_target() {
+        let (old, new) = (HashEmbedder::new(16), HashEmbedder::new(32));
+        let mut index = index(&old, &[("a", "the old mill burned"), ("b", "wolves on the road"), ("c", "a quiet harbour")]);
+        let mut migration = EmbeddingMigration::start(&mut index, "memories", "memories_v2", &new, 1000).unwrap();
+        migration.backfill_step(&mut index, &new, 2).unwrap();
+        assert!(index.get("memories_v2", "a").unwrap().is_some());
+
+        // "a" was backfilled, "c" is still queued
+        let ids = ["a".to_string(), "c".to_string()];
+        assert_eq!(migration.delete(&mut index, &ids).unwrap(), 2);
+        assert!(index.get("memories_v2", "a").unwrap().is_none());
+        assert_eq!(migration.progress().skipped, vec!["c".to_string()]);
+        assert_eq!(migration.progress().remaining(), 0);
+
+        migration.verify(&index, &old, &new, &["wolves"], 0, 3).unwrap();
+        migration.cut_over(0.0).unwrap();
+        assert_eq!(index.ids("memories_v2").unwrap(), vec!["b".to_string()]);
+        assert_eq!(migration.delete(&mut index, &["b".to_string()]).unwrap(), 1);
+        assert!(index.ids("memories_v2").unwrap().is_empty());
+    }
+
+    #[test]
+    fn failed_points_block_verification_and_cutover() {
+        let old = HashEmbedder::new(16);
+        let new = Flaky { inner: HashEmbedder::new(32), failing: Cell::new(true) };
+        let mut index = index(&old, &[("a", "a flaky memory"), ("b", "a steady memory")]);
+        let mut migration = EmbeddingMigration::start(&mut index, "memories", "memories_v2", &new, 1000).unwrap();
+        migration.backfill_step(&mut index, &new, 10).unwrap();
+        assert_eq!(migration.progress().failed, vec!["a".to_string()]);
+        assert!(matches!(migration.verify(&index, &old, &new, &[], 4, 2), Err(MigrationError::BackfillFailed(1))));
+        assert!(matches!(migration.cut_over(0.0), Err(MigrationError::WrongPhase { .. })));
+
+        new.failing.set(false);
+        migration.retry_failed();
+        migration.run_backfill(&mut index, &new, 10, |_| {}).unwrap();
+        migration.verify(&index, &old, &new, &[], 4, 2).unwrap();
+        migration.cut_over(0.0).unwrap();
+        assert_eq!(migration.active_collection(), "memories_v2");
+    }
+}
@@ -0,0 +1,108 @@
+// Text embeddings
+//
+// The vector index stores whatever an Embedder produces. OpenAI models are the default in
+// production; HashEmbedder is a deterministic offline stand-in for development and tooling, and
+// with the `local-embeddings` feature LocalEmbedder (local_embeddings/) runs a sentence-transformer
+// in process, so a game needs no API key at all.
+
+use std::fmt;
+
+#[derive(Debug)]
+pub enum EmbeddingError {
+    Provider(String),
+    DimensionMismatch { expected: usize, found: usize },
+}
+
+impl fmt::Display for EmbeddingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmbeddingError::Provider(err) => write!(f, "embedding provider error: {}", err),
+            EmbeddingError::DimensionMismatch { expected, found } => {
+                write!(f, "embedding has {} dimensions, expected {}", found, expected)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EmbeddingError {}
+
+// Turns text into fixed-size vectors
+pub trait Embedder {
+    // Model identifier, e.g. "text-embedding-3-small"
+    fn model(&self) -> &str;
+    fn dimension(&self) -> usize;
+    fn embed(&self, text: &str) -> Result<Vec<f32>, EmbeddingError>;
+
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+        texts.iter().map(|text| self.embed(text)).collect()
+    }
+}
+
+// Deterministic bag-of-words feature hashing embedder (no network, no API key)
+pub struct HashEmbedder {
+    model: String,
+    dimension: usize,
+}
+
+impl HashEmbedder {
+    pub fn new(dimension: usize) -> Self {
+        HashEmbedder {
+            model: format!("hash-{}", dimension),
+            dimension,
+        }
+    }
+}
+
+impl Embedder for HashEmbedder {
+    fn model(&self) -> &str {
+        &self.model
+    }
+
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn embed(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+        let mut vector = vec![0.0f32; self.dimension];
+        for word in text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+            let hash = fnv1a(&word.to_lowercase());
+            let slot = (hash % self.dimension as u64) as usize;
+            let sign = if hash & (1 << 63) == 0 { 1.0 } else { -1.0 };
+            vector[slot] += sign;
+        }
+        normalize(&mut vector);
+        Ok(vector)
+    }
+}
+
+pub fn fnv1a(text: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in text.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+pub fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+}
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let mut dot = 0.0;
+    let mut norm_a = 0.0;
+    let mut norm_b = 0.0;
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a.sqrt() * norm_b.sqrt())
+    }
+}
@@ -0,0 +1,289 @@
+// Emotion-driven audio direction
+//
+// The AudioDirector follows the target mood published by the emotion system and drives music
+// stems and ambient sound parameters through an AudioAdapter supplied by the game. The mood is
+// smoothed over time, layers switch on and off with separate thresholds (hysteresis) and a
+// minimum dwell time, and volumes fade rather than jump.
+
+use crate::emotion::MoodVector;
+
+// Implemented by the game's audio backend (FMOD, Wwise, Kira, engine mixer)
+pub trait AudioAdapter {
+    fn set_layer_volume(&mut self, layer: &str, volume: f32);
+    fn set_ambient_parameter(&mut self, parameter: &str, value: f32);
+    // One-shot stinger when a layer switches on
+    fn play_cue(&mut self, _cue: &str) {}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MoodAxis {
+    Tension,
+    Valence,
+    Energy,
+}
+
+impl MoodAxis {
+    // Axis value mapped to 0..1
+    fn unit(self, mood: &MoodVector) -> f32 {
+        match self {
+            MoodAxis::Tension => mood.tension,
+            MoodAxis::Valence => (mood.valence + 1.0) / 2.0,
+            MoodAxis::Energy => mood.energy,
+        }
+    }
+}
+
+// A music stem whose activation is a weighted sum of the mood axes
+#[derive(Debug, Clone)]
+pub struct MusicLayer {
+    pub name: String,
+    pub tension_weight: f32,
+    pub valence_weight: f32,
+    pub energy_weight: f32,
+    pub bias: f32,
+    // Switch on at or above this activation...
+    pub on_threshold: f32,
+    // ...and off only at or below this one
+    pub off_threshold: f32,
+    pub cue: Option<String>,
+}
+
+impl MusicLayer {
+    pub fn new(name: &str, on_threshold: f32, off_threshold: f32) -> Self {
+        MusicLayer {
+            name: name.to_string(),
+            tension_weight: 0.0,
+            valence_weight: 0.0,
+            energy_weight: 0.0,
+            bias: 0.0,
+            on_threshold,
+            off_threshold: off_threshold.min(on_threshold),
+            cue: None,
+        }
+    }
+
+    pub fn weights(mut self, tension: f32, valence: f32, energy: f32, bias: f32) -> Self {
+        self.tension_weight = tension;
+        self.valence_weight = valence;
+        self.energy_weight = energy;
+        self.bias = bias;
+        self
+    }
+
+    pub fn cue(mut self, cue: &str) -> Self {
+        self.cue = Some(cue.to_string());
+        self
+    }
+
+    pub fn activation(&self, mood: &MoodVector) -> f32 {
+        (self.bias
+            + self.tension_weight * mood.tension
+            + self.valence_weight * mood.valence
+            + self.energy_weight * mood.energy)
+            .clamp(0.0, 1.0)
+    }
+}
+
+// Ambient parameter driven linearly by one mood axis (e.g. wind intensity from energy)
+#[derive(Debug, Clone)]
+pub struct AmbientMapping {
+    pub parameter: String,
+    pub axis: MoodAxis,
+    pub min: f32,
+    pub max: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct AudioDirectorConfig {
+    // Seconds for the smoothed mood to close ~63% of the gap to the target
+    pub smoothing_time: f32,
+    // Seconds a layer must stay in its state before it may switch again
+    pub min_dwell: f32,
+    // Volume change per second when fading layers
+    pub fade_rate: f32,
+    // Ambient changes smaller than this are not sent to the adapter
+    pub ambient_epsilon: f32,
+}
+
+impl Default for AudioDirectorConfig {
+    fn default() -> Self {
+        AudioDirectorConfig {
+            smoothing_time: 2.0,
+            min_dwell: 4.0,
+            fade_rate: 0.5,
+            ambient_epsilon: 0.01,
+        }
+    }
+}
+
+struct LayerState {
+    layer: MusicLayer,
+    active: bool,
+    volume: f32,
+    since_switch: f32,
+}
+
+struct AmbientState {
+    mapping: AmbientMapping,
+    last_sent: Option<f32>,
+}
+
+pub struct AudioDirector {
+    config: AudioDirectorConfig,
+    layers: Vec<LayerState>,
+    ambient: Vec<AmbientState>,
+    mood: Option<MoodVector>,
+}
+
+impl AudioDirector {
+    pub fn new(config: AudioDirectorConfig) -> Self {
+        AudioDirector {
+            config,
+            layers: Vec::new(),
+            ambient: Vec::new(),
+            mood: None,
+        }
+    }
+
+    pub fn add_layer(&mut self, layer: MusicLayer) {
+        self.layers.push(LayerState {
+            layer,
+            active: false,
+            volume: 0.0,
+            // Allow the first switch immediately
+            since_switch: f32::MAX,
+        });
+    }
+
+    pub fn add_ambient(&mut self, mapping: AmbientMapping) {
+        self.ambient.push(AmbientState { mapping, last_sent: None });
+    }
+
+    // Smoothed mood the director is currently playing
+    pub fn current_mood(&self) -> Option<MoodVector> {
+        self.mood
+    }
+
+    pub fn active_layers(&self) -> Vec<&str> {
+        self.layers
+            .iter()
+            .filter(|l| l.active)
+            .map(|l| l.layer.name.as_str())
+            .collect()
+    }
+
+    // Call once per frame with the emotion system's target mood and the frame time in seconds
+    pub fn update(&mut self, target: MoodVector, dt: f32, adapter: &mut dyn AudioAdapter) {
+        let mood = match self.mood {
+            None => target,
+            Some(current) => {
+                let amount = 1.0 - (-dt / self.config.smoothing_time.max(f32::EPSILON)).exp();
+                current.lerp(target, amount)
+            }
+        };
+        self.mood = Some(mood);
+
+        for state in &mut self.layers {
+            state.since_switch += dt;
+            let activation = state.layer.activation(&mood);
+            let may_switch = state.since_switch >= self.config.min_dwell;
+            if !state.active && activation >= state.layer.on_threshold && may_switch {
+                state.active = true;
+                state.since_switch = 0.0;
+                if let Some(cue) = &state.layer.cue {
+                    adapter.play_cue(cue);
+                }
+            } else if state.active && activation <= state.layer.off_threshold && may_switch {
+                state.active = false;
+                state.since_switch = 0.0;
+            }
+
+            let target_volume = if state.active { activation.max(0.2) } else { 0.0 };
+            let step = self.config.fade_rate * dt;
+            let previous = state.volume;
+            state.volume = if state.volume < target_volume {
+                (state.volume + step).min(target_volume)
+            } else {
+                (state.volume - step).max(target_volume)
+            };
+            if state.volume != previous {
+                adapter.set_layer_volume(&state.layer.name, state.volume);
+            }
+        }
+
+        for state in &mut self.ambient {
+            let mapping = &state.mapping;
+            let value = mapping.min + (mapping.max - mapping.min) * mapping.axis.unit(&mood);
+            let changed = state
+                .last_sent
+                .is_none_or(|last| (last - value).abs() >= self.config.ambient_epsilon);
+            if changed {
+                adapter.set_ambient_parameter(&mapping.parameter, value);
+                state.last_sent = Some(value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Mixer {
+        volumes: Vec<(String, f32)>,
+        ambient: Vec<(String, f32)>,
+        cues: Vec<String>,
+    }
+
+    impl AudioAdapter for Mixer {
+        fn set_layer_volume(&mut self, layer: &str, volume: f32) {
+            self.volumes.push((layer.to_string(), volume));
+        }
+
+        fn set_ambient_parameter(&mut self, parameter: &str, value: f32) {
+            self.ambient.push((parameter.to_string(), value));
+        }
+
+        fn play_cue(&mut self, cue: &str) {
+            self.cues.push(cue.to_string());
+        }
+    }
+
+    fn director() -> AudioDirector {
+        let config = AudioDirectorConfig { smoothing_time: 0.001, min_dwell: 1.0, fade_rate: 0.5, ambient_epsilon: 0.05 };
+        let mut director = AudioDirector::new(config);
+        director.add_layer(MusicLayer::new("combat", 0.6, 0.3).weights(1.0, 0.0, 0.0, 0.0).cue("drums_hit"));
+        director
+    }
+
+    #[test]
+    fn layers_switch_with_hysteresis_and_dwell() {
+        let mut director = director();
+        let mut mixer = Mixer::default();
+        director.update(MoodVector::new(0.8, 0.0, 0.0), 0.1, &mut mixer);
+        assert_eq!(director.active_layers(), vec!["combat"]);
+        assert_eq!(mixer.cues, vec!["drums_hit"]);
+
+        // Below the off threshold it first waits out the dwell; between the thresholds it holds
+        director.update(MoodVector::new(0.1, 0.0, 0.0), 0.5, &mut mixer);
+        assert_eq!(director.active_layers(), vec!["combat"]);
+        director.update(MoodVector::new(0.4, 0.0, 0.0), 2.0, &mut mixer);
+        assert_eq!(director.active_layers(), vec!["combat"]);
+        director.update(MoodVector::new(0.1, 0.0, 0.0), 0.1, &mut mixer);
+        assert!(director.active_layers().is_empty());
+        assert_eq!(mixer.cues.len(), 1);
+    }
+
+    #[test]
+    fn volumes_fade_and_small_ambient_changes_are_skipped() {
+        let mut director = director();
+        director.add_ambient(AmbientMapping { parameter: "wind".to_string(), axis: MoodAxis::Energy, min: 0.0, max: 10.0 });
+        let mut mixer = Mixer::default();
+        director.update(MoodVector::new(1.0, 0.0, 0.5), 0.2, &mut mixer);
+        director.update(MoodVector::new(1.0, 0.0, 0.502), 0.2, &mut mixer);
+        let volumes: Vec<f32> = mixer.volumes.iter().map(|(_, v)| *v).collect();
+        assert_eq!(volumes, vec![0.1, 0.2]);
+        assert_eq!(mixer.ambient, vec![("wind".to_string(), 5.0)]);
+    }
+}
@@ -0,0 +1,306 @@
+// Difficulty damping
+//
+// Dynamic difficulty adjustment reacts to the player's measured mood, and left alone it reacts
+// too fast: a tense fight lowers difficulty, the relief raises it again, and the player feels the
+// game yo-yo. A DifficultyController sits between whatever proposes a new difficulty and the
+// applied DifficultySettings and damps the changes:
+//
+//   deadband      requested changes smaller than this are ignored
+//   rate limit    the level moves at most `max_rate_per_sec` per second since the last change,
+//                 and at most `max_step` in one go
+//   bands         difficulty is divided into bands at `band_edges`; leaving a band requires going
+//                 `hysteresis` past its edge, so small wobbles around an edge do not flip it
+//   dwell         a band is kept for at least `min_dwell_secs` once entered
+//
+// Every request is kept in a bounded history with what limited it, for tuning. Applied changes
+// are queued as "difficulty.changed" events ({ "from", "to", "reason" }), the format session
+// analytics and the anomaly detector already consume.
+
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::analytics::DIFFICULTY_TOPIC;
+use crate::events::EventBus;
+
+// Changes smaller than this after limiting are treated as no change
+const EPSILON: f32 = 1e-4;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DampingConfig {
+    pub deadband: f32,
+    pub max_rate_per_sec: f32,
+    pub max_step: f32,
+    // Ascending band boundaries within 0..1
+    pub band_edges: Vec<f32>,
+    pub hysteresis: f32,
+    pub min_dwell_secs: f64,
+    pub history: usize,
+}
+
+impl Default for DampingConfig {
+    fn default() -> Self {
+        DampingConfig {
+            deadband: 0.02,
+            max_rate_per_sec: 0.01,
+            max_step: 0.1,
+            band_edges: vec![0.25, 0.5, 0.75],
+            hysteresis: 0.05,
+            min_dwell_secs: 60.0,
+            history: 64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct DifficultySettings {
+    // 0 (easiest) .. 1 (hardest)
+    pub level: f32,
+    // Index into the bands formed by `band_edges`
+    pub band: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Limit {
+    Deadband,
+    Rate,
+    Dwell,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "outcome", rename_all = "snake_case")]
+pub enum AdjustmentOutcome {
+    Applied,
+    // Applied partially
+    Limited { by: Limit },
+    // Nothing changed
+    Suppressed { by: Limit },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DifficultyAdjustment {
+    // Seconds, on the clock passed to `request`
+    pub at: f64,
+    pub requested: f32,
+    pub from: f32,
+    pub to: f32,
+    pub band: usize,
+    pub reason: String,
+    #[serde(flatten)]
+    pub outcome: AdjustmentOutcome,
+}
+
+pub struct DifficultyController {
+    config: DampingConfig,
+    settings: DifficultySettings,
+    last_change: f64,
+    band_since: f64,
+    history: VecDeque<DifficultyAdjustment>,
+    pending: Vec<DifficultyAdjustment>,
+}
+
+impl DifficultyController {
+    pub fn new(config: DampingConfig, level: f32, now: f64) -> Self {
+        let level = level.clamp(0.0, 1.0);
+        let band = config.band_edges.iter().filter(|&&edge| level >= edge).count();
+        DifficultyController {
+            config,
+            settings: DifficultySettings { level, band },
+            last_change: now,
+            band_since: now,
+            history: VecDeque::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    pub fn settings(&self) -> DifficultySettings {
+        self.settings
+    }
+
+    pub fn config(&self) -> &DampingConfig {
+        &self.config
+    }
+
+    // Takes effect on the next request; the current band is kept
+    pub fn set_config(&mut self, config: DampingConfig) {
+        self.config = config;
+    }
+
+    // Oldest first
+    pub fn history(&self) -> impl Iterator<Item = &DifficultyAdjustment> {
+        self.history.iter()
+    }
+
+    // Ask for a new level; returns what was actually done
+    pub fn request(&mut self, target: f32, reason: &str, now: f64) -> DifficultyAdjustment {
+        let target = target.clamp(0.0, 1.0);
+        let from = self.settings.level;
+        let delta = target - from;
+
+        let (to, limit) = if delta.abs() < self.config.deadband {
+            (from, Some(Limit::Deadband))
+        } else {
+            let elapsed = (now - self.last_change).max(0.0) as f32;
+            let allowed = (self.config.max_rate_per_sec * elapsed).min(self.config.max_step);
+            let mut limit = (delta.abs() > allowed).then_some(Limit::Rate);
+            let mut level = from + delta.clamp(-allowed, allowed);
+            if let Some(held) = self.cross_bands(level, now) {
+                level = held;
+                limit = Some(Limit::Dwell);
+            }
+            (level, limit)
+        };
+
+        let changed = (to - from).abs() >= EPSILON;
+        let outcome = match (changed, limit) {
+            (true, None) => AdjustmentOutcome::Applied,
+            (true, Some(by)) => AdjustmentOutcome::Limited { by },
+            (false, by) => AdjustmentOutcome::Suppressed { by: by.unwrap_or(Limit::Deadband) },
+        };
+        if changed {
+            self.settings.level = to;
+            self.last_change = now;
+        }
+        let adjustment = DifficultyAdjustment {
+            at: now,
+            requested: target,
+            from,
+            to: self.settings.level,
+            band: self.settings.band,
+            reason: reason.to_string(),
+            outcome,
+        };
+        if changed {
+            self.pending.push(adjustment.clone());
+        }
+        if self.history.len() >= self.config.history.max(1) {
+            self.history.pop_front();
+        }
+        self.history.push_back(adjustment.clone());
+        adjustment
+    }
+
+    // Move the band along with `level`, Schmitt-trigger style: the band only changes once the
+    // level is `hysteresis` past its edge. While the dwell time forbids leaving the band, the
+    // level is held at that threshold.
+    fn cross_bands(&mut self, level: f32, now: f64) -> Option<f32> {
+        let edges = &self.config.band_edges;
+        let h = self.config.hysteresis;
+        loop {
+            let band = self.settings.band;
+            let (edge, up) = match (edges.get(band), band.checked_sub(1).and_then(|b| edges.get(b))) {
+                (Some(&upper), _) if level > upper => (upper, true),
+                (_, Some(&lower)) if level < lower => (lower, false),
+                _ => return None,
+            };
+            let threshold = if up { edge + h } else { edge - h };
+            let past = if up { level > threshold } else { level < threshold };
+            if !past {
+                return None;
+            }
+            if now - self.band_since < self.config.min_dwell_secs {
+                return Some(threshold);
+            }
+            self.settings.band = if up { band + 1 } else { band - 1 };
+            self.band_since = now;
+        }
+    }
+
+    // Publish applied changes on DIFFICULTY_TOPIC; returns the number published
+    pub fn publish(&mut self, events: &mut EventBus) -> usize {
+        let count = self.pending.len();
+        for adjustment in self.pending.drain(..) {
+            events.emit(
+                DIFFICULTY_TOPIC,
+                "difficulty",
+                json!({
+                    "from": adjustment.from,
+                    "to": adjustment.to,
+                    "reason": adjustment.reason,
+                    "requested": adjustment.requested,
+                    "band": adjustment.band,
+                }),
+            );
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Rate limiting out of the way, one band edge at 0.5
+    fn banded() -> DampingConfig {
+        DampingConfig {
+            deadband: 0.0,
+            max_rate_per_sec: 10.0,
+            max_step: 1.0,
+            band_edges: vec![0.5],
+            ..DampingConfig::default()
+        }
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn small_requests_fall_in_the_deadband() {
+        let mut controller = DifficultyController::new(DampingConfig::default(), 0.4, 0.0);
+        let adjustment = controller.request(0.41, "tension", 100.0);
+        assert_eq!(adjustment.outcome, AdjustmentOutcome::Suppressed { by: Limit::Deadband });
+        assert_eq!(controller.settings().level, 0.4);
+    }
+
+    #[test]
+    fn changes_are_rate_limited_and_capped_per_step() {
+        let mut controller = DifficultyController::new(DampingConfig::default(), 0.4, 0.0);
+        let adjustment = controller.request(0.1, "deaths", 5.0);
+        assert_eq!(adjustment.outcome, AdjustmentOutcome::Limited { by: Limit::Rate });
+        assert!(close(adjustment.to, 0.35));
+        // Long after the last change the step cap applies instead
+        assert!(close(controller.request(0.1, "deaths", 500.0).to, 0.25));
+        assert_eq!(controller.request(0.2, "deaths", 1_000.0).outcome, AdjustmentOutcome::Applied);
+        assert!(close(controller.settings().level, 0.2));
+    }
+
+    #[test]
+    fn bands_need_hysteresis_and_dwell_to_change() {
+        let mut controller = DifficultyController::new(banded(), 0.4, 0.0);
+        assert_eq!(controller.settings().band, 0);
+
+        // Just over the edge is not past the hysteresis margin
+        assert_eq!(controller.request(0.52, "flow", 100.0).band, 0);
+        assert_eq!(controller.request(0.6, "flow", 101.0).band, 1);
+
+        // Straight back down is held at the threshold until the dwell time is up
+        let held = controller.request(0.3, "frustration", 110.0);
+        assert_eq!(held.outcome, AdjustmentOutcome::Limited { by: Limit::Dwell });
+        assert!(close(held.to, 0.45));
+        assert_eq!(held.band, 1);
+        let dropped = controller.request(0.3, "frustration", 200.0);
+        assert_eq!((dropped.outcome, dropped.band), (AdjustmentOutcome::Applied, 0));
+    }
+
+    #[test]
+    fn history_is_bounded_and_only_changes_are_published() {
+        let mut bus = EventBus::new(8);
+        let sub = bus.subscribe(DIFFICULTY_TOPIC);
+        let mut controller = DifficultyController::new(DampingConfig { history: 2, ..banded() }, 0.4, 0.0);
+        controller.request(0.3, "deaths", 1.0);
+        controller.request(0.3, "deaths", 2.0);
+        controller.request(0.2, "deaths", 3.0);
+        assert_eq!(controller.history().map(|a| a.at).collect::<Vec<_>>(), vec![2.0, 3.0]);
+
+        assert_eq!(controller.publish(&mut bus), 2);
+        let events = bus.drain(sub);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[1].payload["reason"], "deaths");
+        assert!(close(events[1].payload["to"].as_f64().unwrap() as f32, 0.2));
+        assert_eq!(controller.publish(&mut bus), 0);
+    }
+}
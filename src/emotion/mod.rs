@@ -0,0 +1,244 @@
+// Emotion-adaptive experiences
+//
+// Tracks the emotional state of players and decides the mood the game should steer towards.
+// Presentation systems (audio, visuals) read the published target mood and adapt to it.
+
+use std::collections::HashMap;
+
+use crate::environment::{DayPhase, Weather};
+use crate::sharding::{ContentionMetrics, ShardedStore};
+
+pub mod audio;
+pub mod difficulty;
+pub mod sentiment;
+pub mod timeline;
+pub mod visual;
+
+// Position in mood space
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct MoodVector {
+    // 0 (relaxed) .. 1 (unbearable)
+    pub tension: f32,
+    // -1 (negative) .. 1 (positive)
+    pub valence: f32,
+    // 0 (calm) .. 1 (frantic)
+    pub energy: f32,
+}
+
+impl MoodVector {
+    pub fn new(tension: f32, valence: f32, energy: f32) -> Self {
+        MoodVector { tension, valence, energy }.clamped()
+    }
+
+    pub fn clamped(self) -> Self {
+        MoodVector {
+            tension: self.tension.clamp(0.0, 1.0),
+            valence: self.valence.clamp(-1.0, 1.0),
+            energy: self.energy.clamp(0.0, 1.0),
+        }
+    }
+
+    // Move `amount` (0..1) of the way towards `target`
+    pub fn lerp(self, target: MoodVector, amount: f32) -> Self {
+        let t = amount.clamp(0.0, 1.0);
+        MoodVector {
+            tension: self.tension + (target.tension - self.tension) * t,
+            valence: self.valence + (target.valence - self.valence) * t,
+            energy: self.energy + (target.energy - self.energy) * t,
+        }
+    }
+
+    pub fn distance(&self, other: &MoodVector) -> f32 {
+        ((self.tension - other.tension).powi(2)
+            + (self.valence - other.valence).powi(2)
+            + (self.energy - other.energy).powi(2))
+        .sqrt()
+    }
+}
+
+// Environmental conditions the emotion system takes into account
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EnvironmentalFactor {
+    Weather(Weather),
+    TimeOfDay(DayPhase),
+}
+
+impl EnvironmentalFactor {
+    // How the factor colours the mood on its own
+    pub fn mood_bias(&self) -> MoodVector {
+        match self {
+            EnvironmentalFactor::Weather(Weather::Clear) => MoodVector { tension: -0.05, valence: 0.1, energy: 0.0 },
+            EnvironmentalFactor::Weather(Weather::Cloudy) => MoodVector { tension: 0.0, valence: -0.05, energy: -0.05 },
+            EnvironmentalFactor::Weather(Weather::Rain) => MoodVector { tension: 0.05, valence: -0.1, energy: -0.05 },
+            EnvironmentalFactor::Weather(Weather::Storm) => MoodVector { tension: 0.2, valence: -0.15, energy: 0.2 },
+            EnvironmentalFactor::Weather(Weather::Fog) => MoodVector { tension: 0.15, valence: -0.05, energy: -0.1 },
+            EnvironmentalFactor::Weather(Weather::Snow) => MoodVector { tension: 0.0, valence: 0.05, energy: -0.1 },
+            EnvironmentalFactor::TimeOfDay(DayPhase::Dawn) => MoodVector { tension: -0.05, valence: 0.05, energy: 0.0 },
+            EnvironmentalFactor::TimeOfDay(DayPhase::Day) => MoodVector { tension: 0.0, valence: 0.05, energy: 0.05 },
+            EnvironmentalFactor::TimeOfDay(DayPhase::Dusk) => MoodVector { tension: 0.05, valence: 0.0, energy: -0.05 },
+            EnvironmentalFactor::TimeOfDay(DayPhase::Night) => MoodVector { tension: 0.1, valence: -0.05, energy: -0.1 },
+        }
+    }
+}
+
+// Where an estimate of a player's mood came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MeasurementSource {
+    // What the player types or says
+    InputPattern,
+    // How the player plays (deaths, pace, retries)
+    Gameplay,
+    // Heart rate, camera and other sensors
+    Biometric,
+    // Explicit answers to in-game prompts
+    SelfReport,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EmotionMeasurement {
+    pub source: MeasurementSource,
+    pub mood: MoodVector,
+    // 0..1, how far the measurement moves the estimate
+    pub confidence: f32,
+}
+
+#[derive(Debug, Default)]
+pub struct EmotionAdaptiveExperiences {
+    // Latest estimated mood per player
+    player_moods: HashMap<String, MoodVector>,
+    // Most recent measurement per player and source
+    measurements: HashMap<(String, MeasurementSource), EmotionMeasurement>,
+    // Mood the game is currently steering towards
+    target_mood: MoodVector,
+    // Incremented on every publish so consumers can detect changes cheaply
+    revision: u64,
+    weather: Option<Weather>,
+    time_of_day: Option<DayPhase>,
+}
+
+impl EmotionAdaptiveExperiences {
+    pub fn new() -> Self {
+        EmotionAdaptiveExperiences::default()
+    }
+
+    pub fn set_player_mood(&mut self, player_id: &str, mood: MoodVector) {
+        self.player_moods.insert(player_id.to_string(), mood.clamped());
+    }
+
+    pub fn player_mood(&self, player_id: &str) -> Option<MoodVector> {
+        self.player_moods.get(player_id).copied()
+    }
+
+    // Blend a measurement into the player's estimate, weighted by its confidence
+    pub fn record_measurement(&mut self, player_id: &str, measurement: EmotionMeasurement) {
+        let mood = match self.player_moods.get(player_id) {
+            Some(current) => current.lerp(measurement.mood, measurement.confidence),
+            None => measurement.mood,
+        };
+        self.player_moods.insert(player_id.to_string(), mood.clamped());
+        self.measurements
+            .insert((player_id.to_string(), measurement.source), measurement);
+    }
+
+    pub fn last_measurement(&self, player_id: &str, source: MeasurementSource) -> Option<&EmotionMeasurement> {
+        self.measurements.get(&(player_id.to_string(), source))
+    }
+
+    pub fn measurements_for<'a>(&'a self, player_id: &'a str) -> impl Iterator<Item = &'a EmotionMeasurement> + 'a {
+        self.measurements
+            .iter()
+            .filter(move |((player, _), _)| player == player_id)
+            .map(|(_, m)| m)
+    }
+
+    // Drop everything known about a player's emotions; returns the number of entries removed
+    pub fn forget_player(&mut self, player_id: &str) -> usize {
+        let before = self.measurements.len();
+        self.measurements.retain(|(player, _), _| player != player_id);
+        let removed = before - self.measurements.len();
+        removed + usize::from(self.player_moods.remove(player_id).is_some())
+    }
+
+    pub fn publish_target_mood(&mut self, mood: MoodVector) {
+        self.target_mood = mood.clamped();
+        self.revision += 1;
+    }
+
+    pub fn target_mood(&self) -> MoodVector {
+        self.target_mood
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn set_environmental_factor(&mut self, factor: EnvironmentalFactor) {
+        match factor {
+            EnvironmentalFactor::Weather(weather) => self.weather = Some(weather),
+            EnvironmentalFactor::TimeOfDay(phase) => self.time_of_day = Some(phase),
+        }
+    }
+
+    pub fn environmental_factors(&self) -> Vec<EnvironmentalFactor> {
+        let mut factors = Vec::new();
+        factors.extend(self.weather.map(EnvironmentalFactor::Weather));
+        factors.extend(self.time_of_day.map(EnvironmentalFactor::TimeOfDay));
+        factors
+    }
+
+    // Combined mood bias of the current environment
+    pub fn environment_mood_bias(&self) -> MoodVector {
+        self.environmental_factors()
+            .iter()
+            .map(EnvironmentalFactor::mood_bias)
+            .fold(MoodVector::default(), |acc, b| MoodVector {
+                tension: acc.tension + b.tension,
+                valence: acc.valence + b.valence,
+                energy: acc.energy + b.energy,
+            })
+    }
+}
+
+// Moods of every NPC in the world, sharded so world-wide updates run in parallel
+pub struct NpcMoods {
+    moods: ShardedStore<MoodVector>,
+}
+
+impl NpcMoods {
+    pub fn new() -> Self {
+        NpcMoods { moods: ShardedStore::for_this_machine() }
+    }
+
+    pub fn set(&self, npc: &str, mood: MoodVector) {
+        self.moods.insert(npc, mood.clamped());
+    }
+
+    pub fn get(&self, npc: &str) -> Option<MoodVector> {
+        self.moods.get(npc)
+    }
+
+    pub fn remove(&self, npc: &str) -> Option<MoodVector> {
+        self.moods.remove(npc)
+    }
+
+    // Nudge one NPC, e.g. after a hit or a kind word
+    pub fn nudge(&self, npc: &str, towards: MoodVector, amount: f32) {
+        self.moods.with(npc, |mood| *mood = mood.lerp(towards, amount));
+    }
+
+    // Per-tick relaxation of every NPC towards `baseline` plus the environment bias
+    pub fn relax(&self, baseline: MoodVector, bias: MoodVector, rate: f32) {
+        let target = MoodVector::new(baseline.tension + bias.tension, baseline.valence + bias.valence, baseline.energy + bias.energy);
+        self.moods.update_all(|_, mood| *mood = mood.lerp(target, rate));
+    }
+
+    pub fn contention(&self) -> ContentionMetrics {
+        self.moods.metrics()
+    }
+}
+
+impl Default for NpcMoods {
+    fn default() -> Self {
+        NpcMoods::new()
+    }
+}
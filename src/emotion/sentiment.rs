@@ -0,0 +1,349 @@
+// Sentiment and intent of player text input
+//
+// A small lexicon classifier scores valence and arousal and tags intents (insult, flattery,
+// threat, question) so NPCs can pick a reaction without a model round trip. An optional model
+// provider can refine the result; its answer is blended with the rules by confidence. Results
+// feed the emotion system as InputPattern measurements.
+
+use std::sync::Arc;
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::emotion::{EmotionAdaptiveExperiences, EmotionMeasurement, MeasurementSource, MoodVector};
+use crate::generation::{extract_json, TextGenerator};
+use crate::prompts::{self, PromptRegistry};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntentTag {
+    Insult,
+    Flattery,
+    Threat,
+    Question,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SentimentResult {
+    // -1 (hostile) .. 1 (warm)
+    pub valence: f32,
+    // 0 (flat) .. 1 (agitated)
+    pub arousal: f32,
+    pub intents: Vec<IntentTag>,
+    // 0..1
+    pub confidence: f32,
+}
+
+impl SentimentResult {
+    pub fn has_intent(&self, intent: IntentTag) -> bool {
+        self.intents.contains(&intent)
+    }
+
+    // Mood estimate for the emotion system
+    pub fn to_measurement(&self) -> EmotionMeasurement {
+        let threat = if self.has_intent(IntentTag::Threat) { 0.3 } else { 0.0 };
+        EmotionMeasurement {
+            source: MeasurementSource::InputPattern,
+            mood: MoodVector::new((-self.valence).max(0.0) * 0.6 + threat, self.valence, self.arousal),
+            confidence: self.confidence,
+        }
+    }
+}
+
+// Optional model-backed classifier (hosted API, local model)
+pub trait SentimentModel {
+    fn classify(&self, text: &str) -> Result<SentimentResult, Box<dyn std::error::Error>>;
+}
+
+#[derive(Deserialize)]
+struct ModelAnswer {
+    valence: f32,
+    arousal: f32,
+    #[serde(default)]
+    intents: Vec<IntentTag>,
+    #[serde(default = "default_model_confidence")]
+    confidence: f32,
+}
+
+fn default_model_confidence() -> f32 {
+    0.7
+}
+
+// Asks an LLM for a JSON classification
+pub struct LlmSentimentModel<G: TextGenerator> {
+    pub generator: G,
+    // Built-in prompt when None
+    pub prompts: Option<Arc<PromptRegistry>>,
+}
+
+impl<G: TextGenerator> LlmSentimentModel<G> {
+    pub fn new(generator: G) -> Self {
+        LlmSentimentModel { generator, prompts: None }
+    }
+
+    pub fn with_prompts(mut self, prompts: Arc<PromptRegistry>) -> Self {
+        self.prompts = Some(prompts);
+        self
+    }
+}
+
+impl<G: TextGenerator> SentimentModel for LlmSentimentModel<G> {
+    fn classify(&self, text: &str) -> Result<SentimentResult, Box<dyn std::error::Error>> {
+        let prompt = prompts::render(self.prompts.as_deref(), prompts::SENTIMENT, &[("message", json!(text))])?;
+        let output = self.generator.generate(&prompt)?;
+        let value = extract_json(&output).ok_or("model did not return JSON")?;
+        let answer: ModelAnswer = serde_json::from_value(value)?;
+        Ok(SentimentResult {
+            valence: answer.valence.clamp(-1.0, 1.0),
+            arousal: answer.arousal.clamp(0.0, 1.0),
+            intents: answer.intents,
+            confidence: answer.confidence.clamp(0.0, 1.0),
+        })
+    }
+}
+
+const POSITIVE: &[(&str, f32)] = &[
+    ("thanks", 0.6), ("thank", 0.6), ("great", 0.6), ("good", 0.4), ("love", 0.8), ("like", 0.3),
+    ("nice", 0.4), ("awesome", 0.7), ("kind", 0.5), ("please", 0.2), ("happy", 0.6), ("friend", 0.4),
+];
+
+const NEGATIVE: &[(&str, f32)] = &[
+    ("hate", 0.8), ("bad", 0.4), ("terrible", 0.7), ("awful", 0.7), ("annoying", 0.5), ("boring", 0.4),
+    ("angry", 0.6), ("useless", 0.6), ("sad", 0.5), ("worst", 0.7), ("liar", 0.6), ("scam", 0.6),
+];
+
+const INSULTS: &[&str] = &["idiot", "stupid", "fool", "moron", "dumb", "ugly", "pathetic", "worthless", "coward"];
+
+const FLATTERY: &[&str] = &["beautiful", "wise", "brave", "handsome", "clever", "amazing", "finest", "best", "legendary"];
+
+const THREATS: &[&str] = &["kill", "die", "destroy", "hurt", "burn", "regret", "threaten", "stab", "attack"];
+
+const QUESTION_WORDS: &[&str] = &["who", "what", "where", "when", "why", "how", "which", "can", "could", "would", "do", "does", "is", "are"];
+
+const NEGATIONS: &[&str] = &["not", "no", "never", "don't", "dont", "isn't", "isnt", "aren't", "wasn't", "hardly"];
+
+const INTENSIFIERS: &[&str] = &["very", "really", "so", "extremely", "totally", "absolutely"];
+
+pub struct SentimentAnalyzer {
+    model: Option<Box<dyn SentimentModel>>,
+    // Model results below this confidence are ignored
+    pub model_min_confidence: f32,
+}
+
+impl Default for SentimentAnalyzer {
+    fn default() -> Self {
+        SentimentAnalyzer {
+            model: None,
+            model_min_confidence: 0.3,
+        }
+    }
+}
+
+impl SentimentAnalyzer {
+    pub fn new() -> Self {
+        SentimentAnalyzer::default()
+    }
+
+    pub fn with_model(mut self, model: Box<dyn SentimentModel>) -> Self {
+        self.model = Some(model);
+        self
+    }
+
+    pub fn analyze(&self, text: &str) -> SentimentResult {
+        let rules = classify_rules(text);
+        let model = self
+            .model
+            .as_ref()
+            .and_then(|m| m.classify(text).ok())
+            .filter(|r| r.confidence >= self.model_min_confidence);
+        match model {
+            Some(model) => blend(&rules, &model),
+            None => rules,
+        }
+    }
+
+    // Analyze a player's message and record it with the emotion system
+    pub fn observe(&self, player_id: &str, text: &str, emotion: &mut EmotionAdaptiveExperiences) -> SentimentResult {
+        let result = self.analyze(text);
+        emotion.record_measurement(player_id, result.to_measurement());
+        result
+    }
+}
+
+fn tag(intent: IntentTag, intents: &mut Vec<IntentTag>) {
+    if !intents.contains(&intent) {
+        intents.push(intent);
+    }
+}
+
+fn lookup(words: &[(&str, f32)], word: &str) -> Option<f32> {
+    words.iter().find(|(w, _)| *w == word).map(|(_, s)| *s)
+}
+
+// Lexicon pass with negation and intensifier handling
+pub fn classify_rules(text: &str) -> SentimentResult {
+    let lower = text.to_lowercase();
+    let words: Vec<&str> = lower
+        .split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|w| !w.is_empty())
+        .collect();
+
+    let mut score = 0.0f32;
+    let mut hits = 0usize;
+    let mut intents = Vec::new();
+    for (i, word) in words.iter().enumerate() {
+        let negated = (i > 0 && NEGATIONS.contains(&words[i - 1])) || (i > 1 && NEGATIONS.contains(&words[i - 2]));
+        let boost = if i > 0 && INTENSIFIERS.contains(&words[i - 1]) { 1.5 } else { 1.0 };
+        let sign = if negated { -0.7 } else { 1.0 };
+        if let Some(s) = lookup(POSITIVE, word) {
+            score += s * boost * sign;
+            hits += 1;
+        } else if let Some(s) = lookup(NEGATIVE, word) {
+            score -= s * boost * sign;
+            hits += 1;
+        }
+        if INSULTS.contains(word) && !negated {
+            score -= 0.8 * boost;
+            hits += 1;
+            tag(IntentTag::Insult, &mut intents);
+        }
+        if FLATTERY.contains(word) && !negated {
+            score += 0.6 * boost;
+            hits += 1;
+            tag(IntentTag::Flattery, &mut intents);
+        }
+        if THREATS.contains(word) && !negated {
+            score -= 0.7 * boost;
+            hits += 1;
+            tag(IntentTag::Threat, &mut intents);
+        }
+    }
+    if lower.trim_end().ends_with('?') || words.first().is_some_and(|w| QUESTION_WORDS.contains(w)) {
+        tag(IntentTag::Question, &mut intents);
+    }
+
+    let exclamations = text.matches('!').count() as f32;
+    let letters: Vec<char> = text.chars().filter(|c| c.is_alphabetic()).collect();
+    let caps_ratio = if letters.len() >= 4 {
+        letters.iter().filter(|c| c.is_uppercase()).count() as f32 / letters.len() as f32
+    } else {
+        0.0
+    };
+    let arousal = (0.15 * exclamations.min(4.0) + caps_ratio * 0.6 + 0.1 * hits as f32).clamp(0.0, 1.0);
+
+    SentimentResult {
+        valence: (score / (1.0 + 0.3 * hits as f32)).clamp(-1.0, 1.0),
+        arousal,
+        intents,
+        // More lexicon hits means more evidence; short neutral text stays uncertain
+        confidence: (0.2 + 0.2 * hits as f32).min(0.8),
+    }
+}
+
+fn blend(rules: &SentimentResult, model: &SentimentResult) -> SentimentResult {
+    let total = (rules.confidence + model.confidence).max(f32::EPSILON);
+    let w = model.confidence / total;
+    let mut intents = rules.intents.clone();
+    for intent in &model.intents {
+        tag(*intent, &mut intents);
+    }
+    SentimentResult {
+        valence: rules.valence * (1.0 - w) + model.valence * w,
+        arousal: rules.arousal * (1.0 - w) + model.arousal * w,
+        intents,
+        confidence: rules.confidence.max(model.confidence),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Fixed(&'static str);
+
+    impl TextGenerator for Fixed {
+        fn generate(&self, _prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+            Ok(self.0.to_string())
+        }
+    }
+
+    struct Canned(SentimentResult);
+
+    impl SentimentModel for Canned {
+        fn classify(&self, _text: &str) -> Result<SentimentResult, Box<dyn std::error::Error>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    fn canned(valence: f32, intents: Vec<IntentTag>, confidence: f32) -> Box<dyn SentimentModel> {
+        Box::new(Canned(SentimentResult { valence, arousal: 0.0, intents, confidence }))
+    }
+
+    #[test]
+    fn rules_score_valence_and_tag_intents() {
+        let warm = classify_rules("Thanks, you are so wise");
+        assert!(warm.valence > 0.5);
+        assert!(warm.has_intent(IntentTag::Flattery));
+
+        let hostile = classify_rules("You stupid fool, I will kill you!");
+        assert!(hostile.valence < -0.5);
+        assert_eq!(hostile.intents, vec![IntentTag::Insult, IntentTag::Threat]);
+
+        let neutral = classify_rules("where is the inn");
+        assert_eq!(neutral.valence, 0.0);
+        assert_eq!(neutral.intents, vec![IntentTag::Question]);
+        assert!(neutral.confidence < warm.confidence);
+    }
+
+    #[test]
+    fn negation_flips_sentiment_and_suppresses_intents() {
+        assert!(classify_rules("this is good").valence > 0.0);
+        assert!(classify_rules("this is not good").valence < 0.0);
+        assert!(!classify_rules("you are no fool").has_intent(IntentTag::Insult));
+        assert!(classify_rules("really great").valence > classify_rules("great").valence);
+    }
+
+    #[test]
+    fn shouting_raises_arousal() {
+        let calm = classify_rules("open the gate");
+        let loud = classify_rules("OPEN THE GATE!!!");
+        assert!(loud.arousal > calm.arousal + 0.5);
+    }
+
+    #[test]
+    fn model_is_blended_by_confidence_and_ignored_when_unsure() {
+        let text = "whatever you say";
+        let rules = classify_rules(text);
+
+        let unsure = SentimentAnalyzer::new().with_model(canned(-1.0, vec![IntentTag::Threat], 0.1));
+        assert_eq!(unsure.analyze(text), rules);
+
+        let sure = SentimentAnalyzer::new().with_model(canned(-1.0, vec![IntentTag::Threat], 0.6));
+        let result = sure.analyze(text);
+        assert!((result.valence - -0.75).abs() < 1e-5, "{}", result.valence);
+        assert!(result.has_intent(IntentTag::Threat));
+        assert_eq!(result.confidence, 0.6);
+    }
+
+    #[test]
+    fn llm_model_parses_and_clamps_json() {
+        let model = LlmSentimentModel::new(Fixed(r#"Sure: {"valence": -3, "arousal": 0.4, "intents": ["insult"]}"#));
+        let result = model.classify("you smell").unwrap();
+        assert_eq!(result.valence, -1.0);
+        assert_eq!(result.intents, vec![IntentTag::Insult]);
+        assert_eq!(result.confidence, default_model_confidence());
+
+        assert!(LlmSentimentModel::new(Fixed("no idea")).classify("hm").is_err());
+        let analyzer = SentimentAnalyzer::new().with_model(Box::new(LlmSentimentModel::new(Fixed("no idea"))));
+        assert_eq!(analyzer.analyze("hm"), classify_rules("hm"));
+    }
+
+    #[test]
+    fn observe_records_an_input_measurement() {
+        let mut emotion = EmotionAdaptiveExperiences::new();
+        SentimentAnalyzer::new().observe("p1", "I will burn this town!", &mut emotion);
+        let measurement = emotion.last_measurement("p1", MeasurementSource::InputPattern).unwrap();
+        assert!(measurement.mood.valence < 0.0);
+        assert!(measurement.mood.tension > 0.3);
+    }
+}
@@ -0,0 +1,380 @@
+// Emotion timelines
+//
+// A downsampled history of how each player and NPC felt, kept so narrative and quest systems can
+// call back to emotionally significant moments ("remember when you barely escaped the reactor?").
+// Samples land in fine buckets (10 s by default) for the last hour; older buckets are merged into
+// coarse ones (5 min) kept for a week, then dropped. A bucket stores the mean mood plus, for every
+// feeling, its strongest sample with the time and the context tag recorded with it, so peaks
+// survive downsampling even though the average flattens. A player's timeline is part of their
+// data for export and erasure requests (security/privacy.rs).
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::emotion::MoodVector;
+
+// Named readings of a mood, for queries
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Feeling {
+    Fear,
+    Joy,
+    Calm,
+    Excitement,
+}
+
+pub const FEELINGS: [Feeling; 4] = [Feeling::Fear, Feeling::Joy, Feeling::Calm, Feeling::Excitement];
+
+impl Feeling {
+    // 0..1, how strongly `mood` reads as this feeling
+    pub fn score(self, mood: &MoodVector) -> f32 {
+        let negative = (-mood.valence).max(0.0);
+        let positive = mood.valence.max(0.0);
+        match self {
+            Feeling::Fear => mood.tension * 0.6 + negative * 0.4,
+            Feeling::Joy => positive * 0.7 + mood.energy * 0.3,
+            Feeling::Calm => (1.0 - mood.tension) * 0.6 + (1.0 - mood.energy) * 0.4,
+            Feeling::Excitement => mood.energy * 0.6 + mood.tension * 0.2 + positive * 0.2,
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TimelineConfig {
+    pub fine_secs: f64,
+    // How long fine buckets are kept before being merged into coarse ones
+    pub fine_span_secs: f64,
+    pub coarse_secs: f64,
+    pub coarse_span_secs: f64,
+}
+
+impl Default for TimelineConfig {
+    fn default() -> Self {
+        TimelineConfig { fine_secs: 10.0, fine_span_secs: 3600.0, coarse_secs: 300.0, coarse_span_secs: 7.0 * 86400.0 }
+    }
+}
+
+// A remembered high point of one feeling
+#[derive(Debug, Clone, PartialEq)]
+pub struct Moment {
+    pub at: f64,
+    pub score: f32,
+    pub mood: MoodVector,
+    // What was going on, e.g. "reactor_escape"
+    pub context: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+struct Bucket {
+    start: f64,
+    // Time of the latest sample
+    last: f64,
+    samples: u32,
+    mean: MoodVector,
+    // Strongest sample per feeling, indexed by Feeling
+    peaks: [Option<Moment>; 4],
+}
+
+impl Bucket {
+    fn new(start: f64) -> Self {
+        Bucket { start, last: start, samples: 0, mean: MoodVector::default(), peaks: [None, None, None, None] }
+    }
+
+    fn add(&mut self, at: f64, mood: MoodVector, context: Option<&str>) {
+        self.samples += 1;
+        self.last = self.last.max(at);
+        self.mean = self.mean.lerp(mood, 1.0 / self.samples as f32);
+        for feeling in FEELINGS {
+            let score = feeling.score(&mood);
+            let slot = &mut self.peaks[feeling.index()];
+            if slot.as_ref().is_none_or(|p| score > p.score) {
+                *slot = Some(Moment { at, score, mood, context: context.map(str::to_string) });
+            }
+        }
+    }
+
+    fn merge(&mut self, other: Bucket) {
+        let total = self.samples + other.samples;
+        if total == 0 {
+            return;
+        }
+        self.mean = self.mean.lerp(other.mean, other.samples as f32 / total as f32);
+        self.samples = total;
+        self.last = self.last.max(other.last);
+        for (slot, peak) in self.peaks.iter_mut().zip(other.peaks) {
+            if let Some(peak) = peak {
+                if slot.as_ref().is_none_or(|p| peak.score > p.score) {
+                    *slot = Some(peak);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Timeline {
+    // Both oldest first; every coarse bucket is older than every fine one
+    coarse: VecDeque<Bucket>,
+    fine: VecDeque<Bucket>,
+}
+
+impl Timeline {
+    fn buckets(&self) -> impl Iterator<Item = &Bucket> {
+        self.coarse.iter().chain(self.fine.iter())
+    }
+}
+
+// A point of a downsampled series
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimelinePoint {
+    pub start: f64,
+    pub samples: u32,
+    pub mean: MoodVector,
+}
+
+#[derive(Debug, Default)]
+pub struct EmotionTimelines {
+    config: TimelineConfig,
+    timelines: HashMap<String, Timeline>,
+}
+
+impl EmotionTimelines {
+    pub fn new(config: TimelineConfig) -> Self {
+        EmotionTimelines { config, timelines: HashMap::new() }
+    }
+
+    // Record how `entity` feels at `at`; samples are expected in time order per entity
+    pub fn record(&mut self, entity: &str, at: f64, mood: MoodVector, context: Option<&str>) {
+        let config = self.config;
+        let timeline = self.timelines.entry(entity.to_string()).or_default();
+        let start = (at / config.fine_secs).floor() * config.fine_secs;
+        match timeline.fine.back_mut() {
+            Some(last) if last.start >= start => last.add(at, mood, context),
+            _ => {
+                let mut bucket = Bucket::new(start);
+                bucket.add(at, mood, context);
+                timeline.fine.push_back(bucket);
+            }
+        }
+        compact(timeline, &config, at);
+    }
+
+    // Mean mood per bucket between `from` and `to`, oldest first
+    pub fn series(&self, entity: &str, from: f64, to: f64) -> Vec<TimelinePoint> {
+        self.timelines
+            .get(entity)
+            .into_iter()
+            .flat_map(Timeline::buckets)
+            .filter(|b| b.start <= to && b.last >= from)
+            .map(|b| TimelinePoint { start: b.start, samples: b.samples, mean: b.mean })
+            .collect()
+    }
+
+    // The `k` strongest moments of `feeling` between `from` and `to`, strongest first. Moments
+    // closer than `min_gap_secs` to a stronger one are skipped, so one long scare counts once.
+    pub fn peaks(&self, entity: &str, feeling: Feeling, from: f64, to: f64, k: usize, min_gap_secs: f64) -> Vec<Moment> {
+        let mut candidates: Vec<&Moment> = self
+            .timelines
+            .get(entity)
+            .into_iter()
+            .flat_map(Timeline::buckets)
+            .filter_map(|b| b.peaks[feeling.index()].as_ref())
+            .filter(|m| m.at >= from && m.at <= to)
+            .collect();
+        candidates.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.at.total_cmp(&b.at)));
+        let mut chosen: Vec<Moment> = Vec::new();
+        for moment in candidates {
+            if chosen.len() == k {
+                break;
+            }
+            if chosen.iter().all(|c| (c.at - moment.at).abs() >= min_gap_secs) {
+                chosen.push(moment.clone());
+            }
+        }
+        chosen
+    }
+
+    // Strongest moments of `feeling` within the last `window_secs`, e.g. peak fear in the last hour
+    pub fn recent_peaks(&self, entity: &str, feeling: Feeling, now: f64, window_secs: f64, k: usize) -> Vec<Moment> {
+        self.peaks(entity, feeling, now - window_secs, now, k, self.config.fine_secs * 3.0)
+    }
+
+    // The strongest moment of `feeling` tagged with `context`, if the timeline still holds one
+    pub fn moment_with(&self, entity: &str, feeling: Feeling, context: &str) -> Option<Moment> {
+        self.timelines
+            .get(entity)?
+            .buckets()
+            .filter_map(|b| b.peaks[feeling.index()].as_ref())
+            .filter(|m| m.context.as_deref() == Some(context))
+            .max_by(|a, b| a.score.total_cmp(&b.score))
+            .cloned()
+    }
+
+    // Approximate memory held by all timelines
+    pub fn estimated_bytes(&self) -> usize {
+        self.timelines
+            .iter()
+            .map(|(entity, t)| {
+                let contexts: usize =
+                    t.buckets().flat_map(|b| b.peaks.iter().flatten()).filter_map(|m| m.context.as_ref()).map(String::len).sum();
+                entity.len() + (t.coarse.len() + t.fine.len()) * std::mem::size_of::<Bucket>() + contexts
+            })
+            .sum()
+    }
+
+    // Give memory back under pressure: fold fine buckets into coarse ones, then drop the oldest
+    // coarse buckets until about `bytes` are freed
+    pub fn downsample(&mut self, bytes: usize) {
+        let before = self.estimated_bytes();
+        let eager = TimelineConfig { fine_span_secs: 0.0, ..self.config };
+        for timeline in self.timelines.values_mut() {
+            if let Some(latest) = timeline.fine.back().map(|b| b.start) {
+                compact(timeline, &eager, latest);
+            }
+        }
+        while before.saturating_sub(self.estimated_bytes()) < bytes {
+            let mut dropped = false;
+            for timeline in self.timelines.values_mut() {
+                dropped |= timeline.coarse.pop_front().is_some();
+            }
+            if !dropped {
+                break;
+            }
+        }
+    }
+
+    pub fn forget(&mut self, entity: &str) -> bool {
+        self.timelines.remove(entity).is_some()
+    }
+}
+
+// Merge fine buckets that aged out into coarse ones and drop coarse buckets past retention
+fn compact(timeline: &mut Timeline, config: &TimelineConfig, now: f64) {
+    while timeline.fine.front().is_some_and(|b| b.start + config.fine_secs <= now - config.fine_span_secs) {
+        let Some(bucket) = timeline.fine.pop_front() else {
+            break;
+        };
+        let start = (bucket.start / config.coarse_secs).floor() * config.coarse_secs;
+        match timeline.coarse.back_mut() {
+            Some(last) if last.start >= start => last.merge(bucket),
+            _ => {
+                let mut coarse = Bucket::new(start);
+                coarse.merge(bucket);
+                timeline.coarse.push_back(coarse);
+            }
+        }
+    }
+    while timeline.coarse.front().is_some_and(|b| b.start + config.coarse_secs <= now - config.coarse_span_secs) {
+        timeline.coarse.pop_front();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fear reads exactly `level`
+    fn scared(level: f32) -> MoodVector {
+        MoodVector::new(level, -level, 0.0)
+    }
+
+    // Fine buckets of 10 s for a minute, coarse buckets of a minute for five
+    fn short() -> TimelineConfig {
+        TimelineConfig { fine_secs: 10.0, fine_span_secs: 60.0, coarse_secs: 60.0, coarse_span_secs: 300.0 }
+    }
+
+    fn times(moments: &[Moment]) -> Vec<f64> {
+        moments.iter().map(|m| m.at).collect()
+    }
+
+    #[test]
+    fn peaks_closer_than_the_gap_to_a_stronger_one_are_skipped() {
+        let mut timelines = EmotionTimelines::new(TimelineConfig::default());
+        for (at, level) in [(0.0, 0.5), (10.0, 0.9), (20.0, 0.8), (100.0, 0.6)] {
+            timelines.record("p1", at, scared(level), None);
+        }
+        assert_eq!(times(&timelines.peaks("p1", Feeling::Fear, 0.0, 200.0, 3, 30.0)), [10.0, 100.0]);
+        assert_eq!(times(&timelines.peaks("p1", Feeling::Fear, 0.0, 200.0, 3, 0.0)), [10.0, 20.0, 100.0]);
+        assert_eq!(times(&timelines.peaks("p1", Feeling::Fear, 15.0, 200.0, 1, 30.0)), [20.0]);
+        assert!(timelines.peaks("p2", Feeling::Fear, 0.0, 200.0, 3, 0.0).is_empty());
+    }
+
+    #[test]
+    fn recent_peaks_only_look_inside_the_window() {
+        let mut timelines = EmotionTimelines::new(TimelineConfig::default());
+        timelines.record("p1", 0.0, scared(0.9), None);
+        timelines.record("p1", 1000.0, scared(0.4), None);
+        timelines.record("p1", 1030.0, scared(0.5), None);
+        let recent = timelines.recent_peaks("p1", Feeling::Fear, 1040.0, 100.0, 5);
+        assert_eq!(times(&recent), [1030.0, 1000.0]);
+        assert!((recent[0].score - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn moments_are_found_by_their_context_tag() {
+        let mut timelines = EmotionTimelines::new(TimelineConfig::default());
+        timelines.record("p1", 0.0, scared(0.6), Some("reactor_escape"));
+        timelines.record("p1", 30.0, scared(0.95), Some("reactor_escape"));
+        timelines.record("p1", 60.0, scared(0.99), Some("boss_fight"));
+
+        let escape = timelines.moment_with("p1", Feeling::Fear, "reactor_escape").unwrap();
+        assert_eq!(escape.at, 30.0);
+        assert_eq!(escape.context.as_deref(), Some("reactor_escape"));
+        assert!(timelines.moment_with("p1", Feeling::Fear, "wedding").is_none());
+        assert!(timelines.moment_with("p2", Feeling::Fear, "reactor_escape").is_none());
+    }
+
+    #[test]
+    fn aged_fine_buckets_merge_into_coarse_ones_keeping_their_peaks() {
+        let mut timelines = EmotionTimelines::new(short());
+        for step in 0..=12 {
+            let at = f64::from(step) * 10.0;
+            let (level, context) = if step == 2 { (0.9, "ambush") } else { (0.2, "walk") };
+            timelines.record("p1", at, scared(level), Some(context));
+        }
+        let series = timelines.series("p1", 0.0, 120.0);
+        // 0..50 s folded into one coarse bucket, 60..120 s still fine
+        let buckets: Vec<(f64, u32)> = series.iter().map(|p| (p.start, p.samples)).collect();
+        assert_eq!(buckets.len(), 8);
+        assert_eq!(buckets[..2], [(0.0, 6), (60.0, 1)]);
+        let expected_tension = (0.9 + 5.0 * 0.2) / 6.0;
+        assert!((series[0].mean.tension - expected_tension).abs() < 1e-5);
+        let ambush = timelines.moment_with("p1", Feeling::Fear, "ambush").unwrap();
+        assert_eq!(ambush.at, 20.0);
+    }
+
+    #[test]
+    fn coarse_buckets_past_retention_are_dropped() {
+        let mut timelines = EmotionTimelines::new(short());
+        timelines.record("p1", 0.0, scared(0.9), Some("ambush"));
+        timelines.record("p1", 200.0, scared(0.3), None);
+        assert!(timelines.moment_with("p1", Feeling::Fear, "ambush").is_some());
+
+        timelines.record("p1", 500.0, scared(0.3), None);
+        assert!(timelines.moment_with("p1", Feeling::Fear, "ambush").is_none());
+        assert_eq!(timelines.series("p1", 0.0, 500.0).iter().map(|p| p.start).collect::<Vec<_>>(), [180.0, 500.0]);
+    }
+
+    #[test]
+    fn downsampling_folds_fine_buckets_then_drops_the_oldest_coarse_ones() {
+        let mut timelines = EmotionTimelines::new(short());
+        for step in 0..6 {
+            timelines.record("p1", f64::from(step) * 10.0, scared(0.5), None);
+        }
+        let before = timelines.estimated_bytes();
+        timelines.downsample(0);
+        let starts = |timelines: &EmotionTimelines| -> Vec<f64> {
+            timelines.series("p1", 0.0, 60.0).iter().map(|p| p.start).collect()
+        };
+        assert_eq!(starts(&timelines), [0.0, 50.0]);
+        assert!(timelines.estimated_bytes() < before);
+
+        timelines.downsample(usize::MAX);
+        // The newest bucket is never folded, so it survives
+        assert_eq!(starts(&timelines), [50.0]);
+        assert!(timelines.forget("p1"));
+        assert_eq!(timelines.estimated_bytes(), 0);
+    }
+}
@@ -0,0 +1,308 @@
+// Emotion-driven visual direction
+//
+// The VisualDirector turns the target mood from the emotion system, and the current difficulty
+// from dynamic difficulty adjustment, into post-processing targets: bloom, colour grading warmth,
+// vignette and fog density. Each parameter is a weighted sum of the inputs shaped by a response
+// curve and mapped onto the renderer's range, then eased towards that target over its own
+// smoothing time so grading never snaps.
+//
+// Renderer integrations read one VisualParams per frame; it is a plain #[repr(C)] struct of
+// floats so it can be copied across FFI as is.
+
+use serde::Serialize;
+
+use crate::emotion::MoodVector;
+
+// What the renderer applies this frame
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+pub struct VisualParams {
+    pub bloom: f32,
+    // -1 (cold, blue) .. 1 (warm, amber)
+    pub warmth: f32,
+    pub vignette: f32,
+    pub fog_density: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VisualParam {
+    Bloom,
+    Warmth,
+    Vignette,
+    FogDensity,
+}
+
+impl VisualParam {
+    pub const ALL: [VisualParam; 4] = [VisualParam::Bloom, VisualParam::Warmth, VisualParam::Vignette, VisualParam::FogDensity];
+
+    fn get(self, params: &VisualParams) -> f32 {
+        match self {
+            VisualParam::Bloom => params.bloom,
+            VisualParam::Warmth => params.warmth,
+            VisualParam::Vignette => params.vignette,
+            VisualParam::FogDensity => params.fog_density,
+        }
+    }
+
+    fn set(self, params: &mut VisualParams, value: f32) {
+        match self {
+            VisualParam::Bloom => params.bloom = value,
+            VisualParam::Warmth => params.warmth = value,
+            VisualParam::Vignette => params.vignette = value,
+            VisualParam::FogDensity => params.fog_density = value,
+        }
+    }
+}
+
+// Shape applied to a parameter's 0..1 drive before it is mapped onto its range
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResponseCurve {
+    Linear,
+    // Slow start: small mood changes barely register
+    EaseIn,
+    // Fast start, then saturates
+    EaseOut,
+    SmoothStep,
+}
+
+impl ResponseCurve {
+    pub fn apply(self, x: f32) -> f32 {
+        let x = x.clamp(0.0, 1.0);
+        match self {
+            ResponseCurve::Linear => x,
+            ResponseCurve::EaseIn => x * x,
+            ResponseCurve::EaseOut => 1.0 - (1.0 - x) * (1.0 - x),
+            ResponseCurve::SmoothStep => x * x * (3.0 - 2.0 * x),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct VisualChannel {
+    pub param: VisualParam,
+    pub tension_weight: f32,
+    pub valence_weight: f32,
+    pub energy_weight: f32,
+    pub difficulty_weight: f32,
+    pub bias: f32,
+    pub curve: ResponseCurve,
+    // Output range the 0..1 drive is mapped onto
+    pub min: f32,
+    pub max: f32,
+    // Seconds to close ~63% of the gap to the target
+    pub smoothing_time: f32,
+}
+
+impl VisualChannel {
+    pub fn new(param: VisualParam, min: f32, max: f32) -> Self {
+        VisualChannel {
+            param,
+            tension_weight: 0.0,
+            valence_weight: 0.0,
+            energy_weight: 0.0,
+            difficulty_weight: 0.0,
+            bias: 0.0,
+            curve: ResponseCurve::Linear,
+            min,
+            max,
+            smoothing_time: 1.5,
+        }
+    }
+
+    pub fn weights(mut self, tension: f32, valence: f32, energy: f32, bias: f32) -> Self {
+        self.tension_weight = tension;
+        self.valence_weight = valence;
+        self.energy_weight = energy;
+        self.bias = bias;
+        self
+    }
+
+    pub fn difficulty(mut self, weight: f32) -> Self {
+        self.difficulty_weight = weight;
+        self
+    }
+
+    pub fn curve(mut self, curve: ResponseCurve) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    pub fn smoothing(mut self, seconds: f32) -> Self {
+        self.smoothing_time = seconds;
+        self
+    }
+
+    pub fn target(&self, mood: &MoodVector, difficulty: f32) -> f32 {
+        let drive = self.bias
+            + self.tension_weight * mood.tension
+            + self.valence_weight * mood.valence
+            + self.energy_weight * mood.energy
+            + self.difficulty_weight * difficulty;
+        self.min + (self.max - self.min) * self.curve.apply(drive)
+    }
+}
+
+pub struct VisualDirector {
+    channels: Vec<VisualChannel>,
+    difficulty: f32,
+    target: VisualParams,
+    current: Option<VisualParams>,
+}
+
+impl VisualDirector {
+    pub fn new() -> Self {
+        VisualDirector { channels: Vec::new(), difficulty: 0.5, target: VisualParams::default(), current: None }
+    }
+
+    // A starting point: tension darkens the edges and thickens fog, positive valence warms the
+    // grade and adds bloom, harder difficulty tightens the vignette
+    pub fn with_defaults() -> Self {
+        let mut director = VisualDirector::new();
+        director.add_channel(
+            VisualChannel::new(VisualParam::Bloom, 0.0, 1.0)
+                .weights(-0.2, 0.4, 0.3, 0.3)
+                .curve(ResponseCurve::EaseOut),
+        );
+        director.add_channel(
+            VisualChannel::new(VisualParam::Warmth, -1.0, 1.0)
+                .weights(-0.3, 0.5, 0.0, 0.55)
+                .smoothing(4.0),
+        );
+        director.add_channel(
+            VisualChannel::new(VisualParam::Vignette, 0.0, 0.8)
+                .weights(0.8, -0.2, 0.0, 0.0)
+                .difficulty(0.3)
+                .curve(ResponseCurve::EaseIn)
+                .smoothing(1.0),
+        );
+        director.add_channel(
+            VisualChannel::new(VisualParam::FogDensity, 0.0, 0.6)
+                .weights(0.5, -0.3, -0.2, 0.2)
+                .curve(ResponseCurve::SmoothStep)
+                .smoothing(6.0),
+        );
+        director
+    }
+
+    // Replaces any channel already driving the same parameter
+    pub fn add_channel(&mut self, channel: VisualChannel) {
+        self.channels.retain(|c| c.param != channel.param);
+        self.channels.push(channel);
+    }
+
+    // Current DDA difficulty, 0 (easiest) .. 1 (hardest)
+    pub fn set_difficulty(&mut self, difficulty: f32) {
+        self.difficulty = difficulty.clamp(0.0, 1.0);
+    }
+
+    // Where the parameters are heading
+    pub fn target(&self) -> VisualParams {
+        self.target
+    }
+
+    // Last values handed to the renderer
+    pub fn current(&self) -> VisualParams {
+        self.current.unwrap_or(self.target)
+    }
+
+    // Ease every post-processing parameter towards what `mood` and the difficulty call for, `dt`
+    // seconds on, and return the values to hand to the renderer this frame. The first update
+    // jumps straight to the targets.
+    pub fn update(&mut self, mood: MoodVector, dt: f32) -> VisualParams {
+        let mut current = self.current.unwrap_or_default();
+        for channel in &self.channels {
+            let target = channel.target(&mood, self.difficulty);
+            channel.param.set(&mut self.target, target);
+            let value = match self.current {
+                None => target,
+                Some(previous) => {
+                    let from = channel.param.get(&previous);
+                    let amount = 1.0 - (-dt / channel.smoothing_time.max(f32::EPSILON)).exp();
+                    from + (target - from) * amount
+                }
+            };
+            channel.param.set(&mut current, value);
+        }
+        self.current = Some(current);
+        current
+    }
+}
+
+impl Default for VisualDirector {
+    fn default() -> Self {
+        VisualDirector::with_defaults()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mood(tension: f32, valence: f32) -> MoodVector {
+        MoodVector { tension, valence, energy: 0.5 }
+    }
+
+    #[test]
+    fn curves_are_clamped_and_keep_their_endpoints() {
+        for curve in [ResponseCurve::Linear, ResponseCurve::EaseIn, ResponseCurve::EaseOut, ResponseCurve::SmoothStep] {
+            assert_eq!(curve.apply(-1.0), 0.0);
+            assert_eq!(curve.apply(2.0), 1.0);
+        }
+        assert_eq!(ResponseCurve::EaseIn.apply(0.5), 0.25);
+        assert_eq!(ResponseCurve::EaseOut.apply(0.5), 0.75);
+        assert_eq!(ResponseCurve::SmoothStep.apply(0.5), 0.5);
+    }
+
+    #[test]
+    fn channel_target_maps_weighted_drive_onto_its_range() {
+        let channel = VisualChannel::new(VisualParam::Warmth, -1.0, 1.0).weights(0.0, 0.5, 0.0, 0.5);
+        assert_eq!(channel.target(&mood(0.0, 1.0), 0.0), 1.0);
+        assert_eq!(channel.target(&mood(0.0, -1.0), 0.0), -1.0);
+        assert_eq!(channel.target(&mood(0.0, 0.0), 0.0), 0.0);
+
+        let vignette = VisualChannel::new(VisualParam::Vignette, 0.0, 0.8).difficulty(1.0);
+        assert_eq!(vignette.target(&mood(0.0, 0.0), 0.25), 0.2);
+    }
+
+    #[test]
+    fn first_update_snaps_then_later_ones_ease() {
+        let mut director = VisualDirector::new();
+        director.add_channel(VisualChannel::new(VisualParam::Bloom, 0.0, 1.0).weights(1.0, 0.0, 0.0, 0.0).smoothing(1.0));
+        let first = director.update(mood(0.0, 0.0), 0.016);
+        assert_eq!(first.bloom, 0.0);
+
+        // One smoothing time closes ~63% of the gap
+        let eased = director.update(mood(1.0, 0.0), 1.0);
+        assert_eq!(director.target().bloom, 1.0);
+        assert!((eased.bloom - (1.0 - (-1.0f32).exp())).abs() < 1e-5);
+        assert_eq!(director.current(), eased);
+        for _ in 0..100 {
+            director.update(mood(1.0, 0.0), 0.1);
+        }
+        assert!((director.current().bloom - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn defaults_darken_under_tension_and_difficulty() {
+        let mut calm = VisualDirector::with_defaults();
+        let calm = calm.update(mood(0.0, 0.8), 0.016);
+        let mut tense = VisualDirector::default();
+        tense.set_difficulty(1.0);
+        let tense = tense.update(mood(1.0, -0.8), 0.016);
+
+        assert!(tense.vignette > calm.vignette);
+        assert!(tense.fog_density > calm.fog_density);
+        assert!(tense.warmth < calm.warmth);
+        assert!(tense.bloom < calm.bloom);
+    }
+
+    #[test]
+    fn adding_a_channel_replaces_the_one_for_that_param() {
+        let mut director = VisualDirector::with_defaults();
+        director.add_channel(VisualChannel::new(VisualParam::FogDensity, 0.9, 0.9));
+        assert_eq!(director.update(mood(0.0, 0.0), 0.016).fog_density, 0.9);
+        // Parameters nobody drives stay at zero
+        let mut empty = VisualDirector::new();
+        assert_eq!(empty.update(mood(1.0, 1.0), 0.016), VisualParams::default());
+    }
+}
@@ -0,0 +1,288 @@
+// Environment simulation: day/night cycle and weather
+//
+// Game time advances at the CodeDNA time scale. Weather follows a Markov chain whose transition
+// probabilities and spell lengths come from the biome's climate. Phase and weather changes are
+// pushed to the emotion system as EnvironmentalFactors and published on the event bus.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::emotion::{EmotionAdaptiveExperiences, EnvironmentalFactor};
+use crate::events::EventBus;
+use crate::rng::Rng;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Weather {
+    Clear,
+    Cloudy,
+    Rain,
+    Storm,
+    Fog,
+    Snow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DayPhase {
+    Dawn,
+    Day,
+    Dusk,
+    Night,
+}
+
+impl DayPhase {
+    pub fn at_hour(hour: f64) -> Self {
+        match hour {
+            h if (5.0..7.0).contains(&h) => DayPhase::Dawn,
+            h if (7.0..18.0).contains(&h) => DayPhase::Day,
+            h if (18.0..20.0).contains(&h) => DayPhase::Dusk,
+            _ => DayPhase::Night,
+        }
+    }
+}
+
+// Weather behaviour of a biome
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BiomeClimate {
+    pub name: String,
+    // Row of the Markov chain: weather -> [(next weather, relative weight)]
+    pub transitions: HashMap<Weather, Vec<(Weather, f64)>>,
+    // Mean length of a weather spell in game hours
+    pub mean_spell_hours: f64,
+}
+
+impl BiomeClimate {
+    pub fn temperate() -> Self {
+        use Weather::*;
+        BiomeClimate {
+            name: "temperate".to_string(),
+            transitions: HashMap::from([
+                (Clear, vec![(Clear, 5.0), (Cloudy, 3.0), (Fog, 1.0)]),
+                (Cloudy, vec![(Clear, 3.0), (Cloudy, 2.0), (Rain, 3.0), (Fog, 1.0)]),
+                (Rain, vec![(Cloudy, 4.0), (Rain, 2.0), (Storm, 1.0)]),
+                (Storm, vec![(Rain, 3.0), (Cloudy, 1.0)]),
+                (Fog, vec![(Clear, 2.0), (Cloudy, 2.0)]),
+                (Snow, vec![(Cloudy, 1.0)]),
+            ]),
+            mean_spell_hours: 6.0,
+        }
+    }
+
+    pub fn desert() -> Self {
+        use Weather::*;
+        BiomeClimate {
+            name: "desert".to_string(),
+            transitions: HashMap::from([
+                (Clear, vec![(Clear, 12.0), (Cloudy, 1.0), (Storm, 0.5)]),
+                (Cloudy, vec![(Clear, 4.0), (Rain, 0.5)]),
+                (Rain, vec![(Cloudy, 1.0)]),
+                (Storm, vec![(Clear, 2.0), (Cloudy, 1.0)]),
+                (Fog, vec![(Clear, 1.0)]),
+                (Snow, vec![(Clear, 1.0)]),
+            ]),
+            mean_spell_hours: 12.0,
+        }
+    }
+
+    pub fn arctic() -> Self {
+        use Weather::*;
+        BiomeClimate {
+            name: "arctic".to_string(),
+            transitions: HashMap::from([
+                (Clear, vec![(Clear, 2.0), (Cloudy, 3.0), (Snow, 2.0)]),
+                (Cloudy, vec![(Clear, 1.0), (Snow, 4.0), (Fog, 1.0)]),
+                (Snow, vec![(Snow, 3.0), (Cloudy, 2.0), (Storm, 1.0)]),
+                (Storm, vec![(Snow, 3.0)]),
+                (Fog, vec![(Cloudy, 2.0), (Snow, 1.0)]),
+                (Rain, vec![(Snow, 1.0)]),
+            ]),
+            mean_spell_hours: 8.0,
+        }
+    }
+
+    fn next(&self, current: Weather, rng: &mut Rng) -> Weather {
+        match self.transitions.get(&current) {
+            Some(row) if !row.is_empty() => {
+                let weights: Vec<f64> = row.iter().map(|(_, w)| *w).collect();
+                rng.weighted_index(&weights).map_or(current, |i| row[i].0)
+            }
+            _ => current,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EnvironmentChange {
+    DayPhase { from: DayPhase, to: DayPhase },
+    Weather { from: Weather, to: Weather },
+    NewDay(u64),
+}
+
+pub struct EnvironmentSimulation {
+    // CodeDNA time scale: game seconds per real second
+    time_scale: f64,
+    climate: BiomeClimate,
+    // Hours since the start of day 0
+    game_hours: f64,
+    phase: DayPhase,
+    weather: Weather,
+    spell_remaining: f64,
+    rng: Rng,
+}
+
+impl EnvironmentSimulation {
+    pub fn new(time_scale: f32, climate: BiomeClimate, start_hour: f64, seed: u64) -> Self {
+        let mut rng = Rng::new(seed);
+        let spell_remaining = sample_spell(&climate, &mut rng);
+        EnvironmentSimulation {
+            time_scale: time_scale.max(0.0) as f64,
+            climate,
+            game_hours: start_hour.rem_euclid(24.0),
+            phase: DayPhase::at_hour(start_hour.rem_euclid(24.0)),
+            weather: Weather::Clear,
+            spell_remaining,
+            rng,
+        }
+    }
+
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.time_scale = time_scale.max(0.0) as f64;
+    }
+
+    pub fn set_climate(&mut self, climate: BiomeClimate) {
+        self.climate = climate;
+    }
+
+    pub fn hour_of_day(&self) -> f64 {
+        self.game_hours % 24.0
+    }
+
+    pub fn day(&self) -> u64 {
+        (self.game_hours / 24.0) as u64
+    }
+
+    pub fn phase(&self) -> DayPhase {
+        self.phase
+    }
+
+    pub fn weather(&self) -> Weather {
+        self.weather
+    }
+
+    // Sun height for lighting, -1 (midnight) .. 1 (noon)
+    pub fn sun_elevation(&self) -> f64 {
+        -((self.hour_of_day() / 24.0) * std::f64::consts::TAU).cos()
+    }
+
+    // Advance by `dt` real seconds, returning the changes that happened
+    pub fn update(
+        &mut self,
+        dt: f64,
+        emotion: Option<&mut EmotionAdaptiveExperiences>,
+        events: Option<&mut EventBus>,
+    ) -> Vec<EnvironmentChange> {
+        let mut changes = Vec::new();
+        let elapsed_hours = dt * self.time_scale / 3600.0;
+        let previous_day = self.day();
+        self.game_hours += elapsed_hours;
+        if self.day() != previous_day {
+            changes.push(EnvironmentChange::NewDay(self.day()));
+        }
+
+        let phase = DayPhase::at_hour(self.hour_of_day());
+        if phase != self.phase {
+            changes.push(EnvironmentChange::DayPhase { from: self.phase, to: phase });
+            self.phase = phase;
+        }
+
+        self.spell_remaining -= elapsed_hours;
+        while self.spell_remaining <= 0.0 {
+            let next = self.climate.next(self.weather, &mut self.rng);
+            if next != self.weather {
+                changes.push(EnvironmentChange::Weather { from: self.weather, to: next });
+                self.weather = next;
+            }
+            self.spell_remaining += sample_spell(&self.climate, &mut self.rng);
+        }
+
+        if let Some(emotion) = emotion {
+            for change in &changes {
+                match change {
+                    EnvironmentChange::DayPhase { to, .. } => {
+                        emotion.set_environmental_factor(EnvironmentalFactor::TimeOfDay(*to))
+                    }
+                    EnvironmentChange::Weather { to, .. } => {
+                        emotion.set_environmental_factor(EnvironmentalFactor::Weather(*to))
+                    }
+                    EnvironmentChange::NewDay(_) => {}
+                }
+            }
+        }
+        if let Some(events) = events {
+            for change in &changes {
+                let (topic, payload) = match change {
+                    EnvironmentChange::DayPhase { from, to } => ("environment.day_phase", json!({ "from": from, "to": to })),
+                    EnvironmentChange::Weather { from, to } => ("environment.weather", json!({ "from": from, "to": to, "biome": self.climate.name })),
+                    EnvironmentChange::NewDay(day) => ("environment.new_day", json!({ "day": day })),
+                };
+                events.emit(topic, "environment", payload);
+            }
+        }
+        changes
+    }
+}
+
+// Exponentially distributed spell length around the climate mean
+fn sample_spell(climate: &BiomeClimate, rng: &mut Rng) -> f64 {
+    let u = rng.next_f64().max(f64::MIN_POSITIVE);
+    (-u.ln() * climate.mean_spell_hours).max(0.25)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Alternates clear and rain every spell
+    fn alternating() -> BiomeClimate {
+        BiomeClimate {
+            name: "test".to_string(),
+            transitions: HashMap::from([(Weather::Clear, vec![(Weather::Rain, 1.0)]), (Weather::Rain, vec![(Weather::Clear, 1.0)])]),
+            mean_spell_hours: 1000.0,
+        }
+    }
+
+    #[test]
+    fn day_phases_and_new_days_reach_the_emotion_system_and_bus() {
+        // One real second is one game hour
+        let mut environment = EnvironmentSimulation::new(3600.0, alternating(), 17.5, 1);
+        let mut emotion = EmotionAdaptiveExperiences::new();
+        let mut events = EventBus::new(16);
+        let phases = events.subscribe("environment.day_phase");
+
+        let changes = environment.update(1.0, Some(&mut emotion), Some(&mut events));
+        assert!(changes.contains(&EnvironmentChange::DayPhase { from: DayPhase::Day, to: DayPhase::Dusk }));
+        assert_eq!(emotion.environmental_factors(), vec![EnvironmentalFactor::TimeOfDay(DayPhase::Dusk)]);
+        assert_eq!(events.drain(phases)[0].payload, json!({ "from": "Day", "to": "Dusk" }));
+
+        let changes = environment.update(6.0, None, None);
+        assert!(changes.contains(&EnvironmentChange::NewDay(1)));
+        assert_eq!(environment.phase(), DayPhase::Night);
+        assert!((environment.hour_of_day() - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn weather_follows_the_climate_chain() {
+        // A zero mean leaves every spell at the 15 minute floor
+        let mut climate = alternating();
+        climate.mean_spell_hours = 0.0;
+        let mut environment = EnvironmentSimulation::new(3600.0, climate, 12.0, 4);
+        let changes = environment.update(24.0, None, None);
+        let weather: Vec<&EnvironmentChange> = changes.iter().filter(|c| matches!(c, EnvironmentChange::Weather { .. })).collect();
+        assert_eq!(weather.len(), 96);
+        assert_eq!(weather[0], &EnvironmentChange::Weather { from: Weather::Clear, to: Weather::Rain });
+        assert_eq!(weather[1], &EnvironmentChange::Weather { from: Weather::Rain, to: Weather::Clear });
+        assert_eq!(environment.weather(), Weather::Clear);
+        assert_eq!(environment.day(), 1);
+    }
+}
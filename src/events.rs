@@ -0,0 +1,149 @@
+// Engine event bus
+//
+// Subsystems publish events on dotted topics ("environment.weather", "social.milestone") and
+// consumers subscribe to topic prefixes. Delivery is pull-based: each subscription has its own
+// bounded inbox that the consumer drains on its own schedule, so publishers never call into
+// other subsystems while holding their locks.
+
+use std::collections::{HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Event {
+    pub topic: String,
+    // Subsystem or entity that raised the event
+    pub source: String,
+    pub payload: Value,
+    // Sequence number assigned by the bus
+    pub sequence: u64,
+}
+
+impl Event {
+    pub fn new(topic: &str, source: &str, payload: Value) -> Self {
+        Event {
+            topic: topic.to_string(),
+            source: source.to_string(),
+            payload,
+            sequence: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u64);
+
+struct Subscription {
+    prefix: String,
+    inbox: VecDeque<Event>,
+    dropped: u64,
+}
+
+pub struct EventBus {
+    subscriptions: HashMap<SubscriptionId, Subscription>,
+    next_id: u64,
+    sequence: u64,
+    // Oldest events are dropped once an inbox holds this many
+    inbox_capacity: usize,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        EventBus::new(1024)
+    }
+}
+
+impl EventBus {
+    pub fn new(inbox_capacity: usize) -> Self {
+        EventBus {
+            subscriptions: HashMap::new(),
+            next_id: 1,
+            sequence: 0,
+            inbox_capacity: inbox_capacity.max(1),
+        }
+    }
+
+    // Subscribe to every topic starting with `prefix` ("" for everything)
+    pub fn subscribe(&mut self, prefix: &str) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id);
+        self.next_id += 1;
+        self.subscriptions.insert(
+            id,
+            Subscription {
+                prefix: prefix.to_string(),
+                inbox: VecDeque::new(),
+                dropped: 0,
+            },
+        );
+        id
+    }
+
+    pub fn unsubscribe(&mut self, id: SubscriptionId) {
+        self.subscriptions.remove(&id);
+    }
+
+    // Returns the number of subscriptions the event was delivered to
+    pub fn publish(&mut self, mut event: Event) -> usize {
+        self.sequence += 1;
+        event.sequence = self.sequence;
+        let mut delivered = 0;
+        for subscription in self.subscriptions.values_mut() {
+            if !event.topic.starts_with(&subscription.prefix) {
+                continue;
+            }
+            if subscription.inbox.len() >= self.inbox_capacity {
+                subscription.inbox.pop_front();
+                subscription.dropped += 1;
+            }
+            subscription.inbox.push_back(event.clone());
+            delivered += 1;
+        }
+        delivered
+    }
+
+    pub fn emit(&mut self, topic: &str, source: &str, payload: Value) -> usize {
+        self.publish(Event::new(topic, source, payload))
+    }
+
+    pub fn drain(&mut self, id: SubscriptionId) -> Vec<Event> {
+        self.subscriptions
+            .get_mut(&id)
+            .map(|s| s.inbox.drain(..).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn pending(&self, id: SubscriptionId) -> usize {
+        self.subscriptions.get(&id).map_or(0, |s| s.inbox.len())
+    }
+
+    // Events lost to inbox overflow for a subscription
+    pub fn dropped(&self, id: SubscriptionId) -> u64 {
+        self.subscriptions.get(&id).map_or(0, |s| s.dropped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn subscribers_get_matching_topics_and_lose_the_oldest_on_overflow() {
+        let mut bus = EventBus::new(2);
+        let weather = bus.subscribe("environment.weather");
+        let everything = bus.subscribe("");
+        for day in 1..=3 {
+            bus.emit("environment.new_day", "environment", json!({ "day": day }));
+        }
+        assert_eq!(bus.emit("environment.weather", "environment", json!("rain")), 2);
+
+        let seen: Vec<u64> = bus.drain(everything).iter().map(|e| e.sequence).collect();
+        assert_eq!(seen, vec![3, 4]);
+        assert_eq!(bus.dropped(everything), 2);
+        assert_eq!(bus.pending(weather), 1);
+        bus.unsubscribe(weather);
+        assert_eq!(bus.emit("environment.weather", "environment", json!("clear")), 1);
+        assert!(bus.drain(weather).is_empty());
+    }
+}
@@ -0,0 +1,511 @@
+// C ABI for native engine plugins
+//
+// A stable C interface so Unity (P/Invoke) and Unreal (C++) can embed ARCADIA as a native
+// library. Every function returns an ArcadiaStatus; on failure the message is available from
+// arcadia_last_error() on the same thread. Subsystems are opaque handles created and freed
+// through this API. Structured data crosses the boundary as JSON strings, which the library
+// allocates and the caller releases with arcadia_string_free().
+//
+// Panics are caught at the boundary and reported as ARCADIA_STATUS_PANIC. The declarations are
+// mirrored by hand in include/arcadia.h; cbindgen.toml holds the settings for regenerating it
+// from this file with cbindgen, after which the header carries cbindgen's own notice.
+//
+// The API is only compiled with the `ffi` feature. Engines load it as a shared library, so build
+// with the library target's crate-type set to include "cdylib" (`crate-type = ["cdylib",
+// "rlib"]` under [lib]), or once off with `cargo rustc --lib --features ffi --crate-type cdylib`.
+
+// Pointer arguments follow the contract in arcadia.h: null or valid, strings NUL-terminated
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::ai::goap::{Action, Goal, Planner, WorldState};
+use crate::dialogue::tree::{DialogueContext, DialogueError, DialogueSession, DialogueTree};
+use crate::emotion::{EmotionAdaptiveExperiences, MoodVector};
+use crate::generation::TextGenerator;
+use crate::vector_index::{VectorIndex, VectorIndexConfig, VectorIndexError, VectorPoint};
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArcadiaStatus {
+    Ok = 0,
+    NullPointer = 1,
+    InvalidUtf8 = 2,
+    InvalidArgument = 3,
+    NotFound = 4,
+    Failed = 5,
+    Panic = 6,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ArcadiaMood {
+    pub tension: f32,
+    pub valence: f32,
+    pub energy: f32,
+}
+
+impl From<ArcadiaMood> for MoodVector {
+    fn from(mood: ArcadiaMood) -> Self {
+        MoodVector::new(mood.tension, mood.valence, mood.energy)
+    }
+}
+
+impl From<MoodVector> for ArcadiaMood {
+    fn from(mood: MoodVector) -> Self {
+        ArcadiaMood { tension: mood.tension, valence: mood.valence, energy: mood.energy }
+    }
+}
+
+// Returns the generated text for `prompt`; the string must stay valid until the callback is
+// called again or the calling function returns. Null means generation failed.
+pub type ArcadiaGenerateFn = Option<unsafe extern "C" fn(user_data: *mut c_void, prompt: *const c_char) -> *const c_char>;
+
+// A conversation owning its tree
+pub struct ArcadiaDialogue {
+    session: DialogueSession<'static>,
+    // Allocated in arcadia_dialogue_start, freed in arcadia_dialogue_free after the session
+    tree: *mut DialogueTree,
+}
+
+struct FfiError {
+    status: ArcadiaStatus,
+    message: String,
+}
+
+impl FfiError {
+    fn new(status: ArcadiaStatus, message: impl Into<String>) -> Self {
+        FfiError { status, message: message.into() }
+    }
+}
+
+impl From<VectorIndexError> for FfiError {
+    fn from(err: VectorIndexError) -> Self {
+        let status = match err {
+            VectorIndexError::CollectionNotFound(_) | VectorIndexError::PointNotFound(_) => ArcadiaStatus::NotFound,
+            VectorIndexError::CollectionExists(_)
+            | VectorIndexError::InvalidName(_)
+            | VectorIndexError::DimensionMismatch { .. }
+            | VectorIndexError::InvalidCursor(_) => ArcadiaStatus::InvalidArgument,
+            _ => ArcadiaStatus::Failed,
+        };
+        FfiError::new(status, err.to_string())
+    }
+}
+
+impl From<DialogueError> for FfiError {
+    fn from(err: DialogueError) -> Self {
+        let status = match err {
+            DialogueError::UnknownNode(_) => ArcadiaStatus::NotFound,
+            DialogueError::InvalidChoice(_) | DialogueError::NotAnAiNode(_) => ArcadiaStatus::InvalidArgument,
+            DialogueError::Finished => ArcadiaStatus::Failed,
+        };
+        FfiError::new(status, err.to_string())
+    }
+}
+
+impl From<serde_json::Error> for FfiError {
+    fn from(err: serde_json::Error) -> Self {
+        FfiError::new(ArcadiaStatus::InvalidArgument, format!("invalid JSON: {}", err))
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: &str) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+// Run an API body, converting errors and panics into a status
+fn guard(body: impl FnOnce() -> Result<(), FfiError>) -> ArcadiaStatus {
+    match panic::catch_unwind(AssertUnwindSafe(body)) {
+        Ok(Ok(())) => ArcadiaStatus::Ok,
+        Ok(Err(err)) => {
+            set_last_error(&err.message);
+            err.status
+        }
+        Err(_) => {
+            set_last_error("panic inside ARCADIA");
+            ArcadiaStatus::Panic
+        }
+    }
+}
+
+unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a str, FfiError> {
+    if ptr.is_null() {
+        return Err(FfiError::new(ArcadiaStatus::NullPointer, format!("{} is null", name)));
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|_| FfiError::new(ArcadiaStatus::InvalidUtf8, format!("{} is not valid UTF-8", name)))
+}
+
+unsafe fn handle<'a, T>(ptr: *mut T, name: &str) -> Result<&'a mut T, FfiError> {
+    ptr.as_mut().ok_or_else(|| FfiError::new(ArcadiaStatus::NullPointer, format!("{} is null", name)))
+}
+
+unsafe fn write_out<T>(out: *mut T, value: T) -> Result<(), FfiError> {
+    if out.is_null() {
+        return Err(FfiError::new(ArcadiaStatus::NullPointer, "output pointer is null"));
+    }
+    out.write(value);
+    Ok(())
+}
+
+unsafe fn write_json(out: *mut *mut c_char, value: &Value) -> Result<(), FfiError> {
+    let text = CString::new(value.to_string()).map_err(|_| FfiError::new(ArcadiaStatus::Failed, "output contains NUL"))?;
+    write_out(out, text.into_raw())
+}
+
+// Message of the last failed call on this thread, or null; valid until the next failure
+#[no_mangle]
+pub extern "C" fn arcadia_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |m| m.as_ptr()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn arcadia_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+// Vector search
+
+#[no_mangle]
+pub unsafe extern "C" fn arcadia_vector_index_new(
+    url: *const c_char,
+    api_key: *const c_char,
+    out: *mut *mut VectorIndex,
+) -> ArcadiaStatus {
+    guard(|| {
+        let config = VectorIndexConfig {
+            url: str_arg(url, "url")?.to_string(),
+            api_key: str_arg(api_key, "api_key")?.to_string(),
+            default_ttl_secs: None,
+            collection_ttl_secs: HashMap::new(),
+        };
+        write_out(out, Box::into_raw(Box::new(VectorIndex::new(config))))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn arcadia_vector_index_free(index: *mut VectorIndex) {
+    if !index.is_null() {
+        drop(Box::from_raw(index));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn arcadia_vector_create_collection(
+    index: *mut VectorIndex,
+    name: *const c_char,
+    dimension: usize,
+    model: *const c_char,
+) -> ArcadiaStatus {
+    guard(|| {
+        let index = handle(index, "index")?;
+        index.create_collection(str_arg(name, "name")?, dimension, str_arg(model, "model")?)?;
+        Ok(())
+    })
+}
+
+// `payload_json` may be null; otherwise it must be a JSON object
+#[no_mangle]
+pub unsafe extern "C" fn arcadia_vector_upsert(
+    index: *mut VectorIndex,
+    collection: *const c_char,
+    id: *const c_char,
+    vector: *const f32,
+    len: usize,
+    payload_json: *const c_char,
+) -> ArcadiaStatus {
+    guard(|| {
+        let index = handle(index, "index")?;
+        if vector.is_null() {
+            return Err(FfiError::new(ArcadiaStatus::NullPointer, "vector is null"));
+        }
+        let mut point = VectorPoint::new(str_arg(id, "id")?, std::slice::from_raw_parts(vector, len).to_vec());
+        if !payload_json.is_null() {
+            point.payload = serde_json::from_str(str_arg(payload_json, "payload_json")?)?;
+        }
+        index.upsert(str_arg(collection, "collection")?, point)?;
+        Ok(())
+    })
+}
+
+// Writes a JSON array of {"id", "score", "payload"} to `out_json`
+#[no_mangle]
+pub unsafe extern "C" fn arcadia_vector_search(
+    index: *mut VectorIndex,
+    collection: *const c_char,
+    vector: *const f32,
+    len: usize,
+    limit: usize,
+    out_json: *mut *mut c_char,
+) -> ArcadiaStatus {
+    guard(|| {
+        let index = handle(index, "index")?;
+        if vector.is_null() {
+            return Err(FfiError::new(ArcadiaStatus::NullPointer, "vector is null"));
+        }
+        let hits = index.search(str_arg(collection, "collection")?, std::slice::from_raw_parts(vector, len), limit)?;
+        let hits: Vec<Value> = hits
+            .into_iter()
+            .map(|hit| json!({ "id": hit.id, "score": hit.score, "payload": hit.payload }))
+            .collect();
+        write_json(out_json, &Value::from(hits))
+    })
+}
+
+// Emotion
+
+#[no_mangle]
+pub extern "C" fn arcadia_emotion_new() -> *mut EmotionAdaptiveExperiences {
+    Box::into_raw(Box::new(EmotionAdaptiveExperiences::new()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn arcadia_emotion_free(emotion: *mut EmotionAdaptiveExperiences) {
+    if !emotion.is_null() {
+        drop(Box::from_raw(emotion));
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn arcadia_emotion_set_player_mood(
+    emotion: *mut EmotionAdaptiveExperiences,
+    player: *const c_char,
+    mood: ArcadiaMood,
+) -> ArcadiaStatus {
+    guard(|| {
+        handle(emotion, "emotion")?.set_player_mood(str_arg(player, "player")?, mood.into());
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn arcadia_emotion_publish_target(
+    emotion: *mut EmotionAdaptiveExperiences,
+    mood: ArcadiaMood,
+) -> ArcadiaStatus {
+    guard(|| {
+        handle(emotion, "emotion")?.publish_target_mood(mood.into());
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn arcadia_emotion_target(
+    emotion: *mut EmotionAdaptiveExperiences,
+    out: *mut ArcadiaMood,
+) -> ArcadiaStatus {
+    guard(|| {
+        let target = handle(emotion, "emotion")?.target_mood();
+        write_out(out, target.into())
+    })
+}
+
+// GOAP planning
+
+#[derive(Deserialize)]
+struct PlanRequest {
+    actions: Vec<Action>,
+    goal: Goal,
+    #[serde(default)]
+    state: WorldState,
+}
+
+// `request_json` is {"actions": [...], "goal": {...}, "state": {...}}; writes
+// {"goal", "actions": [names], "cost"}, or returns ARCADIA_STATUS_NOT_FOUND when no plan exists
+#[no_mangle]
+pub unsafe extern "C" fn arcadia_goap_plan(request_json: *const c_char, out_json: *mut *mut c_char) -> ArcadiaStatus {
+    guard(|| {
+        let request: PlanRequest = serde_json::from_str(str_arg(request_json, "request_json")?)?;
+        let plan = Planner::new(request.actions)
+            .map_err(|err| FfiError::new(ArcadiaStatus::InvalidArgument, err.to_string()))?
+            .plan(&request.state, &request.goal)
+            .ok_or_else(|| FfiError::new(ArcadiaStatus::NotFound, format!("no plan reaches goal '{}'", request.goal.name)))?;
+        write_json(out_json, &json!({ "goal": plan.goal, "actions": plan.action_names(), "cost": plan.cost }))
+    })
+}
+
+// NPC dialogue
+
+struct CallbackGenerator {
+    callback: unsafe extern "C" fn(*mut c_void, *const c_char) -> *const c_char,
+    user_data: *mut c_void,
+}
+
+impl TextGenerator for CallbackGenerator {
+    fn generate(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+        let prompt = CString::new(prompt.replace('\0', " "))?;
+        let text = unsafe { (self.callback)(self.user_data, prompt.as_ptr()) };
+        if text.is_null() {
+            return Err("generator callback returned null".into());
+        }
+        Ok(unsafe { CStr::from_ptr(text) }.to_string_lossy().into_owned())
+    }
+}
+
+fn node_json(dialogue: &ArcadiaDialogue) -> Value {
+    match dialogue.session.current() {
+        Some(node) => json!({
+            "id": node.id,
+            "speaker": node.speaker,
+            "text": node.text,
+            "choices": node.choices.iter().enumerate().map(|(i, c)| json!({ "index": i, "text": c.text })).collect::<Vec<_>>(),
+            "ai": node.ai.is_some(),
+            "finished": dialogue.session.is_finished(),
+        }),
+        None => json!({ "finished": true }),
+    }
+}
+
+unsafe fn dialogue_context(context_json: *const c_char) -> Result<DialogueContext, FfiError> {
+    #[derive(Deserialize)]
+    struct Context {
+        #[serde(default)]
+        state: WorldState,
+        #[serde(default)]
+        relationships: HashMap<String, f32>,
+    }
+    if context_json.is_null() {
+        return Ok(DialogueContext::default());
+    }
+    let context: Context = serde_json::from_str(str_arg(context_json, "context_json")?)?;
+    Ok(DialogueContext { state: context.state, relationships: context.relationships })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn arcadia_dialogue_start(tree_toml: *const c_char, out: *mut *mut ArcadiaDialogue) -> ArcadiaStatus {
+    guard(|| {
+        let tree = DialogueTree::from_toml(str_arg(tree_toml, "tree_toml")?)
+            .map_err(|err| FfiError::new(ArcadiaStatus::InvalidArgument, format!("invalid dialogue tree: {}", err)))?;
+        let tree_ptr = Box::into_raw(Box::new(tree));
+        let session = match DialogueSession::start(&*tree_ptr) {
+            Ok(session) => session,
+            Err(err) => {
+                drop(Box::from_raw(tree_ptr));
+                return Err(err.into());
+            }
+        };
+        write_out(out, Box::into_raw(Box::new(ArcadiaDialogue { session, tree: tree_ptr })))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn arcadia_dialogue_free(dialogue: *mut ArcadiaDialogue) {
+    if !dialogue.is_null() {
+        let dialogue = Box::from_raw(dialogue);
+        let tree = dialogue.tree;
+        // The session borrows the tree, so it goes first
+        drop(dialogue);
+        drop(Box::from_raw(tree));
+    }
+}
+
+// Writes {"id", "speaker", "text", "choices": [{"index", "text"}], "ai", "finished"}
+#[no_mangle]
+pub unsafe extern "C" fn arcadia_dialogue_current(dialogue: *mut ArcadiaDialogue, out_json: *mut *mut c_char) -> ArcadiaStatus {
+    guard(|| write_json(out_json, &node_json(handle(dialogue, "dialogue")?)))
+}
+
+// `context_json` may be null or {"state": {...}, "relationships": {"id": value}}
+#[no_mangle]
+pub unsafe extern "C" fn arcadia_dialogue_choose(
+    dialogue: *mut ArcadiaDialogue,
+    index: usize,
+    context_json: *const c_char,
+) -> ArcadiaStatus {
+    guard(|| {
+        let context = dialogue_context(context_json)?;
+        handle(dialogue, "dialogue")?.session.choose(index, &context)?;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn arcadia_dialogue_advance(dialogue: *mut ArcadiaDialogue) -> ArcadiaStatus {
+    guard(|| {
+        handle(dialogue, "dialogue")?.session.advance()?;
+        Ok(())
+    })
+}
+
+// Let the NPC answer inside an AI node; writes {"line", "fell_back", "exited"}
+#[no_mangle]
+pub unsafe extern "C" fn arcadia_dialogue_ai_turn(
+    dialogue: *mut ArcadiaDialogue,
+    generate: ArcadiaGenerateFn,
+    user_data: *mut c_void,
+    player_input: *const c_char,
+    out_json: *mut *mut c_char,
+) -> ArcadiaStatus {
+    guard(|| {
+        let dialogue = handle(dialogue, "dialogue")?;
+        let callback = generate.ok_or_else(|| FfiError::new(ArcadiaStatus::NullPointer, "generate is null"))?;
+        let generator = CallbackGenerator { callback, user_data };
+        let reply = dialogue.session.ai_turn(&generator, str_arg(player_input, "player_input")?)?;
+        write_json(out_json, &json!({ "line": reply.line, "fell_back": reply.fell_back, "exited": reply.exited }))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn c(text: &str) -> CString {
+        CString::new(text).unwrap()
+    }
+
+    unsafe fn take_string(s: *mut c_char) -> String {
+        let text = CStr::from_ptr(s).to_str().unwrap().to_string();
+        arcadia_string_free(s);
+        text
+    }
+
+    #[test]
+    fn vector_round_trip_through_the_c_api() {
+        unsafe {
+            let mut index = ptr::null_mut();
+            let (url, key, name, model) = (c(""), c(""), c("npc"), c("test"));
+            assert_eq!(arcadia_vector_index_new(url.as_ptr(), key.as_ptr(), &mut index), ArcadiaStatus::Ok);
+            assert_eq!(arcadia_vector_create_collection(index, name.as_ptr(), 2, model.as_ptr()), ArcadiaStatus::Ok);
+
+            let (id, payload) = (c("mira"), c(r#"{"role": "smith"}"#));
+            let vector = [1.0f32, 0.0];
+            let status = arcadia_vector_upsert(index, name.as_ptr(), id.as_ptr(), vector.as_ptr(), 2, payload.as_ptr());
+            assert_eq!(status, ArcadiaStatus::Ok);
+
+            let mut out = ptr::null_mut();
+            assert_eq!(arcadia_vector_search(index, name.as_ptr(), vector.as_ptr(), 2, 5, &mut out), ArcadiaStatus::Ok);
+            let hits: Value = serde_json::from_str(&take_string(out)).unwrap();
+            assert_eq!(hits[0]["id"], "mira");
+            assert_eq!(hits[0]["payload"]["role"], "smith");
+            arcadia_vector_index_free(index);
+        }
+    }
+
+    #[test]
+    fn failures_are_reported_through_last_error() {
+        unsafe {
+            let name = c("npc");
+            let vector = [1.0f32];
+            let status = arcadia_vector_search(ptr::null_mut(), name.as_ptr(), vector.as_ptr(), 1, 5, ptr::null_mut());
+            assert_eq!(status, ArcadiaStatus::NullPointer);
+            let message = CStr::from_ptr(arcadia_last_error()).to_str().unwrap();
+            assert!(message.contains("index"), "{}", message);
+        }
+    }
+}
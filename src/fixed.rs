@@ -0,0 +1,397 @@
+// Deterministic fixed-point math
+//
+// Lockstep multiplayer needs every peer to compute bit-identical simulation results, which
+// floating point does not guarantee across compilers, CPUs and libm implementations. Fixed is a
+// Q32.32 number backed by an i64 whose arithmetic, sqrt, ln and exp use only integer operations,
+// so results are identical on every target. Simulation-critical code is written against the
+// `Scalar` alias: plain f32 by default, Fixed with the "deterministic" feature enabled.
+// `verify_conformance` replays a fixed workload and compares it with the reference digest so
+// each build target can check it agrees with the others.
+
+use std::fmt;
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
+
+use serde::{Deserialize, Serialize};
+
+const FRAC_BITS: u32 = 32;
+const ONE_RAW: i64 = 1 << FRAC_BITS;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Fixed(i64);
+
+impl Fixed {
+    pub const ZERO: Fixed = Fixed(0);
+    pub const ONE: Fixed = Fixed(ONE_RAW);
+    pub const MAX: Fixed = Fixed(i64::MAX);
+    pub const MIN: Fixed = Fixed(i64::MIN);
+    // ln(2) and e rounded to 32 fractional bits
+    pub const LN_2: Fixed = Fixed(2_977_044_472);
+    pub const E: Fixed = Fixed(11_674_931_555);
+
+    pub const fn from_raw(raw: i64) -> Self {
+        Fixed(raw)
+    }
+
+    pub const fn raw(self) -> i64 {
+        self.0
+    }
+
+    pub const fn from_int(value: i32) -> Self {
+        Fixed((value as i64) << FRAC_BITS)
+    }
+
+    // numerator / denominator, for authoring constants without floats
+    pub fn from_ratio(numerator: i64, denominator: i64) -> Self {
+        Fixed::from_int128(((numerator as i128) << FRAC_BITS) / denominator as i128)
+    }
+
+    // Exact for every finite f32 in range: scaling by a power of two and truncating involves no
+    // rounding that could differ between platforms
+    pub fn from_f32(value: f32) -> Self {
+        Fixed::from_f64(value as f64)
+    }
+
+    pub fn from_f64(value: f64) -> Self {
+        if value.is_nan() {
+            return Fixed::ZERO;
+        }
+        let scaled = value * ONE_RAW as f64;
+        if scaled >= i64::MAX as f64 {
+            Fixed::MAX
+        } else if scaled <= i64::MIN as f64 {
+            Fixed::MIN
+        } else {
+            Fixed(scaled as i64)
+        }
+    }
+
+    // For display and rendering only; never feed the result back into the simulation
+    pub fn to_f64(self) -> f64 {
+        self.0 as f64 / ONE_RAW as f64
+    }
+
+    pub fn to_f32(self) -> f32 {
+        self.to_f64() as f32
+    }
+
+    // Rounds toward negative infinity
+    pub fn to_int(self) -> i64 {
+        self.0 >> FRAC_BITS
+    }
+
+    pub fn abs(self) -> Self {
+        Fixed(self.0.saturating_abs())
+    }
+
+    pub fn min(self, other: Fixed) -> Fixed {
+        Ord::min(self, other)
+    }
+
+    pub fn max(self, other: Fixed) -> Fixed {
+        Ord::max(self, other)
+    }
+
+    pub fn clamp(self, low: Fixed, high: Fixed) -> Fixed {
+        Ord::clamp(self, low, high)
+    }
+
+    fn from_int128(value: i128) -> Self {
+        Fixed(value.clamp(i64::MIN as i128, i64::MAX as i128) as i64)
+    }
+
+    pub fn sqrt(self) -> Fixed {
+        if self.0 <= 0 {
+            return Fixed::ZERO;
+        }
+        Fixed(isqrt((self.0 as u128) << FRAC_BITS) as i64)
+    }
+
+    // Natural log; non-positive inputs return MIN
+    pub fn ln(self) -> Fixed {
+        match self.log2() {
+            Some(log2) => log2 * Fixed::LN_2,
+            None => Fixed::MIN,
+        }
+    }
+
+    pub fn log2(self) -> Option<Fixed> {
+        if self.0 <= 0 {
+            return None;
+        }
+        // Integer part from the position of the highest set bit
+        let msb = 63 - self.0.leading_zeros() as i64;
+        let int_part = msb - FRAC_BITS as i64;
+        // Normalise into [1, 2) and extract fraction bits by repeated squaring
+        let mut y: u128 = if int_part >= 0 {
+            (self.0 as u128) >> int_part
+        } else {
+            (self.0 as u128) << -int_part
+        };
+        let mut frac: i64 = 0;
+        for bit in (0..FRAC_BITS).rev() {
+            y = (y * y) >> FRAC_BITS;
+            if y >= 2 * ONE_RAW as u128 {
+                y >>= 1;
+                frac |= 1 << bit;
+            }
+        }
+        Some(Fixed((int_part << FRAC_BITS) + frac))
+    }
+
+    // e^x, saturating at MAX
+    pub fn exp(self) -> Fixed {
+        // e^x = 2^(x / ln 2) = 2^k * 2^f with f in [0, 1)
+        let power = self / Fixed::LN_2;
+        let k = power.to_int();
+        if k >= 31 {
+            return Fixed::MAX;
+        }
+        if k < -(FRAC_BITS as i64) {
+            return Fixed::ZERO;
+        }
+        let f = power - Fixed::from_int(k as i32);
+        // 2^f = e^(f ln 2) by Taylor series; the argument is below ln 2 so 16 terms are exact
+        // to the last bit
+        let z = f * Fixed::LN_2;
+        let mut term = Fixed::ONE;
+        let mut sum = Fixed::ONE;
+        for n in 1..16 {
+            term = term * z / Fixed::from_int(n);
+            sum += term;
+        }
+        if k >= 0 {
+            Fixed::from_int128((sum.0 as i128) << k)
+        } else {
+            Fixed(sum.0 >> -k)
+        }
+    }
+
+    // Shannon entropy in nats of weights normalised to a distribution
+    pub fn entropy(weights: &[Fixed]) -> Fixed {
+        let total = weights.iter().fold(Fixed::ZERO, |acc, &w| acc + w.max(Fixed::ZERO));
+        if total <= Fixed::ZERO {
+            return Fixed::ZERO;
+        }
+        weights
+            .iter()
+            .filter(|w| **w > Fixed::ZERO)
+            .map(|w| {
+                let p = *w / total;
+                -(p * p.ln())
+            })
+            .fold(Fixed::ZERO, |acc, h| acc + h)
+    }
+}
+
+fn isqrt(n: u128) -> u128 {
+    if n < 2 {
+        return n;
+    }
+    // Newton's method from an upper bound; converges monotonically downward
+    let mut x = 1u128 << ((128 - n.leading_zeros()).div_ceil(2));
+    loop {
+        let next = (x + n / x) / 2;
+        if next >= x {
+            return x;
+        }
+        x = next;
+    }
+}
+
+impl fmt::Display for Fixed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.6}", self.to_f64())
+    }
+}
+
+impl Add for Fixed {
+    type Output = Fixed;
+    fn add(self, rhs: Fixed) -> Fixed {
+        Fixed(self.0.saturating_add(rhs.0))
+    }
+}
+
+impl AddAssign for Fixed {
+    fn add_assign(&mut self, rhs: Fixed) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Fixed;
+    fn sub(self, rhs: Fixed) -> Fixed {
+        Fixed(self.0.saturating_sub(rhs.0))
+    }
+}
+
+impl SubAssign for Fixed {
+    fn sub_assign(&mut self, rhs: Fixed) {
+        *self = *self - rhs;
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Fixed;
+    fn mul(self, rhs: Fixed) -> Fixed {
+        Fixed::from_int128((self.0 as i128 * rhs.0 as i128) >> FRAC_BITS)
+    }
+}
+
+impl Div for Fixed {
+    type Output = Fixed;
+    // Division by zero saturates toward the sign of the dividend
+    fn div(self, rhs: Fixed) -> Fixed {
+        if rhs.0 == 0 {
+            return match self.0.signum() {
+                1 => Fixed::MAX,
+                -1 => Fixed::MIN,
+                _ => Fixed::ZERO,
+            };
+        }
+        Fixed::from_int128(((self.0 as i128) << FRAC_BITS) / rhs.0 as i128)
+    }
+}
+
+impl Neg for Fixed {
+    type Output = Fixed;
+    fn neg(self) -> Fixed {
+        Fixed(self.0.saturating_neg())
+    }
+}
+
+// Operations simulation code needs from its number type
+pub trait SimScalar:
+    Copy + PartialEq + PartialOrd + fmt::Debug + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self>
+{
+    const ZERO: Self;
+    const ONE: Self;
+    fn from_f32(value: f32) -> Self;
+    fn from_count(count: usize) -> Self;
+    fn to_f32(self) -> f32;
+    fn total_cmp(&self, other: &Self) -> std::cmp::Ordering;
+}
+
+impl SimScalar for f32 {
+    const ZERO: f32 = 0.0;
+    const ONE: f32 = 1.0;
+
+    fn from_f32(value: f32) -> Self {
+        value
+    }
+
+    fn from_count(count: usize) -> Self {
+        count as f32
+    }
+
+    fn to_f32(self) -> f32 {
+        self
+    }
+
+    fn total_cmp(&self, other: &Self) -> std::cmp::Ordering {
+        f32::total_cmp(self, other)
+    }
+}
+
+impl SimScalar for Fixed {
+    const ZERO: Fixed = Fixed::ZERO;
+    const ONE: Fixed = Fixed::ONE;
+
+    fn from_f32(value: f32) -> Self {
+        Fixed::from_f32(value)
+    }
+
+    fn from_count(count: usize) -> Self {
+        Fixed::from_int128((count as i128) << FRAC_BITS)
+    }
+
+    fn to_f32(self) -> f32 {
+        Fixed::to_f32(self)
+    }
+
+    fn total_cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.cmp(other)
+    }
+}
+
+// Number type for simulation-critical systems (GOAP costs, entropy, combat)
+#[cfg(feature = "deterministic")]
+pub type Scalar = Fixed;
+#[cfg(not(feature = "deterministic"))]
+pub type Scalar = f32;
+
+// Digest of `conformance_workload` on the reference target (x86_64 linux)
+pub const CONFORMANCE_DIGEST: u64 = 0xe252_aae2_4635_4715;
+
+// Exercise every operation over a spread of inputs and fold the raw results into an FNV-1a hash
+pub fn conformance_workload() -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut feed = |value: Fixed| {
+        for byte in value.raw().to_le_bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    };
+    let inputs: Vec<Fixed> = (-40..=40)
+        .map(|i| Fixed::from_ratio(i * 7919, 1013))
+        .chain([Fixed::from_f32(0.1), Fixed::from_f32(1e-6), Fixed::from_f32(12345.678)])
+        .collect();
+    for (i, &a) in inputs.iter().enumerate() {
+        let b = inputs[(i * 31 + 7) % inputs.len()];
+        feed(a + b);
+        feed(a - b);
+        feed(a * b);
+        feed(a / b);
+        feed(a.abs().sqrt());
+        feed(a.abs().ln());
+        feed((a / Fixed::from_int(8)).exp());
+    }
+    feed(Fixed::entropy(&inputs));
+    hash
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConformanceMismatch {
+    pub expected: u64,
+    pub actual: u64,
+}
+
+impl fmt::Display for ConformanceMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "fixed-point conformance digest {:016x} differs from reference {:016x}",
+            self.actual, self.expected
+        )
+    }
+}
+
+impl std::error::Error for ConformanceMismatch {}
+
+// Run at startup of lockstep sessions or in CI on every target
+pub fn verify_conformance() -> Result<(), ConformanceMismatch> {
+    let actual = conformance_workload();
+    if actual == CONFORMANCE_DIGEST {
+        Ok(())
+    } else {
+        Err(ConformanceMismatch { expected: CONFORMANCE_DIGEST, actual })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fails on any target whose fixed-point results drift from the reference
+    #[test]
+    fn conformance_digest_matches_reference() {
+        assert_eq!(conformance_workload(), CONFORMANCE_DIGEST, "digest {:016x}", conformance_workload());
+        assert!(verify_conformance().is_ok());
+    }
+
+    #[test]
+    fn mismatch_names_both_digests() {
+        let err = ConformanceMismatch { expected: CONFORMANCE_DIGEST, actual: 1 };
+        assert_eq!(err.to_string(), "fixed-point conformance digest 0000000000000001 differs from reference e252aae246354715");
+    }
+}
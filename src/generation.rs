@@ -0,0 +1,599 @@
+// Content generation templates
+//
+// Designers describe structured content (item stats, quests, dialogue beats) as templates with a
+// JSON schema. LLM output is parsed, validated against the schema, repaired where possible and
+// retried with the validation errors fed back to the model when it cannot be repaired.
+//
+// Content generated for a specific player (`generate_for`) is checked against their content rating
+// before it is handed out when the generator has an enforcer (`with_rating`); quests and dialogue
+// beats are checked on their own surfaces, everything else as procgen. Withheld content is an error
+// and the decision is in the enforcer's audit log either way.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::rating::{ContentRating, Surface};
+use crate::validation::ValidationReport;
+
+// Anything that can turn a prompt into text (OpenAI, local model, mock)
+pub trait TextGenerator {
+    fn generate(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>>;
+}
+
+// Kind of content a template produces
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentKind {
+    Item,
+    Quest,
+    Dialogue,
+    Custom,
+}
+
+// A designer-authored generation template
+#[derive(Debug, Clone, Deserialize)]
+pub struct ContentTemplate {
+    pub name: String,
+    pub kind: ContentKind,
+    // Prompt with {{variable}} placeholders
+    pub prompt: String,
+    // JSON schema (object/array/string/integer/number/boolean subset)
+    pub schema: Value,
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+}
+
+fn default_max_attempts() -> u32 {
+    3
+}
+
+// Templates as loaded from the [generation] section of the aiTOML manifest
+#[derive(Debug, Default, Deserialize)]
+pub struct TemplateSet {
+    #[serde(default)]
+    pub templates: Vec<ContentTemplate>,
+}
+
+impl TemplateSet {
+    pub fn from_toml(contents: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(contents)
+    }
+}
+
+impl ContentTemplate {
+    pub fn new(name: &str, kind: ContentKind, prompt: &str, schema: Value) -> Self {
+        ContentTemplate {
+            name: name.to_string(),
+            kind,
+            prompt: prompt.to_string(),
+            schema,
+            max_attempts: default_max_attempts(),
+        }
+    }
+
+    // Item with numeric stat ranges, e.g. [("damage", 1.0, 50.0), ("weight", 0.1, 20.0)]
+    pub fn item_stats(name: &str, stats: &[(&str, f64, f64)]) -> Self {
+        let mut properties = Map::new();
+        for (stat, min, max) in stats {
+            properties.insert(stat.to_string(), json!({ "type": "number", "minimum": min, "maximum": max }));
+        }
+        let stat_names: Vec<&str> = stats.iter().map(|s| s.0).collect();
+        let schema = json!({
+            "type": "object",
+            "required": ["name", "description", "stats"],
+            "properties": {
+                "name": { "type": "string", "minLength": 1, "maxLength": 64 },
+                "description": { "type": "string", "maxLength": 512 },
+                "stats": { "type": "object", "required": stat_names, "properties": properties }
+            }
+        });
+        Self::new(name, ContentKind::Item, "Create a {{theme}} item for a {{setting}} game.", schema)
+    }
+
+    // Quest with objectives and rewards
+    pub fn quest_structure(name: &str, max_objectives: usize) -> Self {
+        let schema = json!({
+            "type": "object",
+            "required": ["title", "giver", "objectives", "reward"],
+            "properties": {
+                "title": { "type": "string", "minLength": 1, "maxLength": 80 },
+                "giver": { "type": "string", "minLength": 1 },
+                "objectives": {
+                    "type": "array", "minItems": 1, "maxItems": max_objectives,
+                    "items": {
+                        "type": "object",
+                        "required": ["kind", "target"],
+                        "properties": {
+                            "kind": { "type": "string", "enum": ["fetch", "kill", "escort", "talk", "explore"] },
+                            "target": { "type": "string" },
+                            "count": { "type": "integer", "minimum": 1, "maximum": 99 }
+                        }
+                    }
+                },
+                "reward": { "type": "integer", "minimum": 0 }
+            }
+        });
+        Self::new(name, ContentKind::Quest, "Write a quest given by {{giver}} in {{location}}.", schema)
+    }
+
+    // Ordered dialogue beats with a speaker and emotional tone
+    pub fn dialogue_beats(name: &str, max_beats: usize) -> Self {
+        let schema = json!({
+            "type": "array", "minItems": 1, "maxItems": max_beats,
+            "items": {
+                "type": "object",
+                "required": ["speaker", "line"],
+                "properties": {
+                    "speaker": { "type": "string", "minLength": 1 },
+                    "line": { "type": "string", "minLength": 1, "maxLength": 280 },
+                    "tone": { "type": "string", "enum": ["neutral", "friendly", "hostile", "fearful", "sad", "excited"] }
+                }
+            }
+        });
+        Self::new(name, ContentKind::Dialogue, "Write a conversation between {{speakers}} about {{topic}}.", schema)
+    }
+
+    // Substitute {{variable}} placeholders and append the output contract
+    pub fn render(&self, vars: &HashMap<String, String>) -> String {
+        let mut prompt = self.prompt.clone();
+        for (key, value) in vars {
+            prompt = prompt.replace(&format!("{{{{{}}}}}", key), value);
+        }
+        format!(
+            "{}\n\nRespond with JSON only, matching this JSON schema:\n{}",
+            prompt, self.schema
+        )
+    }
+}
+
+// Validated content plus how it was obtained
+#[derive(Debug, Clone)]
+pub struct GeneratedContent {
+    pub template: String,
+    pub value: Value,
+    pub attempts: u32,
+    pub repaired: bool,
+    // Warnings that did not block acceptance
+    pub report: ValidationReport,
+}
+
+#[derive(Debug)]
+pub enum GenerationError {
+    UnknownTemplate(String),
+    Provider(String),
+    InvalidOutput { attempts: u32, report: ValidationReport },
+    // Valid output the player's content rating does not allow
+    Withheld { template: String, player: String },
+}
+
+impl fmt::Display for GenerationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenerationError::UnknownTemplate(name) => write!(f, "unknown generation template '{}'", name),
+            GenerationError::Provider(err) => write!(f, "text generation failed: {}", err),
+            GenerationError::InvalidOutput { attempts, report } => {
+                write!(f, "output still invalid after {} attempts:\n{}", attempts, report)
+            }
+            GenerationError::Withheld { template, player } => {
+                write!(f, "content from template '{}' withheld from '{}' by their content rating", template, player)
+            }
+        }
+    }
+}
+
+impl std::error::Error for GenerationError {}
+
+// Generates template-conforming content through a TextGenerator
+pub struct ContentGenerator<G: TextGenerator> {
+    generator: G,
+    templates: HashMap<String, ContentTemplate>,
+    rating: Option<Arc<Mutex<ContentRating>>>,
+}
+
+impl<G: TextGenerator> ContentGenerator<G> {
+    pub fn new(generator: G) -> Self {
+        ContentGenerator {
+            generator,
+            templates: HashMap::new(),
+            rating: None,
+        }
+    }
+
+    // Check content generated for a player against their rating profile
+    pub fn with_rating(mut self, rating: Arc<Mutex<ContentRating>>) -> Self {
+        self.rating = Some(rating);
+        self
+    }
+
+    pub fn register(&mut self, template: ContentTemplate) {
+        self.templates.insert(template.name.clone(), template);
+    }
+
+    pub fn template(&self, name: &str) -> Option<&ContentTemplate> {
+        self.templates.get(name)
+    }
+
+    pub fn generate(&self, name: &str, vars: &HashMap<String, String>) -> Result<GeneratedContent, GenerationError> {
+        let template = self
+            .templates
+            .get(name)
+            .ok_or_else(|| GenerationError::UnknownTemplate(name.to_string()))?;
+
+        let base_prompt = template.render(vars);
+        let mut prompt = base_prompt.clone();
+        let mut last_report = ValidationReport::new();
+        let attempts = template.max_attempts.max(1);
+
+        for attempt in 1..=attempts {
+            let raw = self
+                .generator
+                .generate(&prompt)
+                .map_err(|e| GenerationError::Provider(e.to_string()))?;
+
+            let mut value = match extract_json(&raw) {
+                Some(value) => value,
+                None => {
+                    last_report = ValidationReport::new();
+                    last_report.error("", "response did not contain parseable JSON");
+                    prompt = repair_prompt(&base_prompt, &raw, &last_report);
+                    continue;
+                }
+            };
+
+            let report = validate(&value, &template.schema);
+            if report.is_valid() {
+                return Ok(GeneratedContent { template: name.to_string(), value, attempts: attempt, repaired: false, report });
+            }
+
+            // Try a local repair before spending another LLM call
+            if repair(&mut value, &template.schema) {
+                let repaired_report = validate(&value, &template.schema);
+                if repaired_report.is_valid() {
+                    return Ok(GeneratedContent {
+                        template: name.to_string(),
+                        value,
+                        attempts: attempt,
+                        repaired: true,
+                        report: repaired_report,
+                    });
+                }
+            }
+
+            prompt = repair_prompt(&base_prompt, &raw, &report);
+            last_report = report;
+        }
+
+        Err(GenerationError::InvalidOutput { attempts, report: last_report })
+    }
+
+    // Generate content to show `player`, withheld when their content rating does not allow it
+    pub fn generate_for(&self, player: &str, name: &str, vars: &HashMap<String, String>) -> Result<GeneratedContent, GenerationError> {
+        let content = self.generate(name, vars)?;
+        let Some(rating) = &self.rating else { return Ok(content) };
+        let surface = match self.templates.get(name).map(|template| template.kind) {
+            Some(ContentKind::Quest) => Surface::Quest,
+            Some(ContentKind::Dialogue) => Surface::Dialogue,
+            _ => Surface::Procgen,
+        };
+        let text = content.value.to_string();
+        let allowed = rating
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .check_generated(player, surface, &text, &text, None);
+        if !allowed {
+            return Err(GenerationError::Withheld { template: name.to_string(), player: player.to_string() });
+        }
+        Ok(content)
+    }
+}
+
+fn repair_prompt(base_prompt: &str, previous: &str, report: &ValidationReport) -> String {
+    format!(
+        "{}\n\nYour previous answer was:\n{}\n\nIt was rejected for these reasons:\n{}Fix every problem and respond with JSON only.",
+        base_prompt, previous, report
+    )
+}
+
+// Pull the first JSON document out of an LLM response (handles ``` fences and surrounding prose)
+pub fn extract_json(raw: &str) -> Option<Value> {
+    let trimmed = raw.trim();
+    if let Ok(value) = serde_json::from_str(trimmed) {
+        return Some(value);
+    }
+    for (open, close) in [('{', '}'), ('[', ']')] {
+        if let (Some(start), Some(end)) = (trimmed.find(open), trimmed.rfind(close)) {
+            if start < end {
+                if let Ok(value) = serde_json::from_str(&trimmed[start..=end]) {
+                    return Some(value);
+                }
+            }
+        }
+    }
+    None
+}
+
+// Validate a value against the supported JSON schema subset
+pub fn validate(value: &Value, schema: &Value) -> ValidationReport {
+    let mut report = ValidationReport::new();
+    validate_at(value, schema, "", &mut report);
+    report
+}
+
+fn validate_at(value: &Value, schema: &Value, path: &str, report: &mut ValidationReport) {
+    let expected = schema.get("type").and_then(Value::as_str).unwrap_or("any");
+    let type_ok = match expected {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        _ => true,
+    };
+    if !type_ok {
+        report.error(path, format!("expected {}, found {}", expected, type_name(value)));
+        return;
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array) {
+        if !allowed.contains(value) {
+            report.error(path, format!("{} is not one of {}", value, Value::Array(allowed.clone())));
+        }
+    }
+
+    match value {
+        Value::Object(map) => {
+            if let Some(required) = schema.get("required").and_then(Value::as_array) {
+                for key in required.iter().filter_map(Value::as_str) {
+                    if !map.contains_key(key) {
+                        report.error(&join(path, key), "missing required field");
+                    }
+                }
+            }
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for (key, child) in map {
+                match properties.and_then(|p| p.get(key)) {
+                    Some(child_schema) => validate_at(child, child_schema, &join(path, key), report),
+                    None if properties.is_some() => report.warning(&join(path, key), "unexpected field"),
+                    None => {}
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+                if (items.len() as u64) < min {
+                    report.error(path, format!("expected at least {} items, found {}", min, items.len()));
+                }
+            }
+            if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+                if items.len() as u64 > max {
+                    report.error(path, format!("expected at most {} items, found {}", max, items.len()));
+                }
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    validate_at(item, item_schema, &format!("{}[{}]", path, i), report);
+                }
+            }
+        }
+        Value::String(s) => {
+            let len = s.chars().count() as u64;
+            if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+                if len < min {
+                    report.error(path, format!("shorter than {} characters", min));
+                }
+            }
+            if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+                if len > max {
+                    report.error(path, format!("longer than {} characters", max));
+                }
+            }
+        }
+        Value::Number(n) => {
+            let n = n.as_f64().unwrap_or(0.0);
+            if let Some(min) = schema.get("minimum").and_then(Value::as_f64) {
+                if n < min {
+                    report.error(path, format!("{} is below minimum {}", n, min));
+                }
+            }
+            if let Some(max) = schema.get("maximum").and_then(Value::as_f64) {
+                if n > max {
+                    report.error(path, format!("{} is above maximum {}", n, max));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+// Fix mechanical problems in place: clamp numbers, truncate strings/arrays, coerce numeric strings.
+// Returns true if anything was changed.
+pub fn repair(value: &mut Value, schema: &Value) -> bool {
+    let expected = schema.get("type").and_then(Value::as_str).unwrap_or("any");
+    let mut changed = false;
+
+    // Coerce "12" -> 12 and 12.0 -> 12 where the schema asks for numbers
+    if expected == "number" || expected == "integer" {
+        if let Some(parsed) = value.as_str().and_then(|s| s.trim().parse::<f64>().ok()) {
+            *value = json!(parsed);
+            changed = true;
+        }
+        if expected == "integer" {
+            if let Some(f) = value.as_f64().filter(|_| !(value.is_i64() || value.is_u64())) {
+                *value = json!(f.round() as i64);
+                changed = true;
+            }
+        }
+    }
+
+    match value {
+        Value::Number(n) => {
+            let original = n.as_f64().unwrap_or(0.0);
+            let mut clamped = original;
+            if let Some(min) = schema.get("minimum").and_then(Value::as_f64) {
+                clamped = clamped.max(min);
+            }
+            if let Some(max) = schema.get("maximum").and_then(Value::as_f64) {
+                clamped = clamped.min(max);
+            }
+            if clamped != original {
+                *value = if expected == "integer" { json!(clamped.round() as i64) } else { json!(clamped) };
+                changed = true;
+            }
+        }
+        Value::String(s) => {
+            if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+                if s.chars().count() as u64 > max {
+                    *s = s.chars().take(max as usize).collect();
+                    changed = true;
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+                if items.len() as u64 > max {
+                    items.truncate(max as usize);
+                    changed = true;
+                }
+            }
+            if let Some(item_schema) = schema.get("items") {
+                for item in items.iter_mut() {
+                    changed |= repair(item, item_schema);
+                }
+            }
+        }
+        Value::Object(map) => {
+            if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+                let before = map.len();
+                map.retain(|key, _| properties.contains_key(key));
+                changed |= map.len() != before;
+                for (key, child) in map.iter_mut() {
+                    changed |= repair(child, &properties[key]);
+                }
+            }
+        }
+        _ => {}
+    }
+    changed
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rating::{Rating, RatingProfile};
+
+    struct Fixed(&'static str);
+
+    impl TextGenerator for Fixed {
+        fn generate(&self, _prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+            Ok(self.0.to_string())
+        }
+    }
+
+    // Replies in order and records the prompts it was given
+    struct Scripted {
+        replies: std::cell::RefCell<Vec<String>>,
+        prompts: std::cell::RefCell<Vec<String>>,
+    }
+
+    impl Scripted {
+        fn new(replies: &[&str]) -> Self {
+            let replies = replies.iter().map(|reply| reply.to_string()).collect();
+            Scripted { replies: std::cell::RefCell::new(replies), prompts: Default::default() }
+        }
+    }
+
+    impl TextGenerator for Scripted {
+        fn generate(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+            self.prompts.borrow_mut().push(prompt.to_string());
+            Ok(self.replies.borrow_mut().remove(0))
+        }
+    }
+
+    fn generator(output: &'static str, rating: &Arc<Mutex<ContentRating>>) -> ContentGenerator<Fixed> {
+        let mut generator = ContentGenerator::new(Fixed(output)).with_rating(rating.clone());
+        generator.register(ContentTemplate::dialogue_beats("beats", 4));
+        generator
+    }
+
+    fn rating() -> Arc<Mutex<ContentRating>> {
+        let mut rating = ContentRating::new(RatingProfile::new(Rating::Mature), 64);
+        rating.set_parental_profile("kid", RatingProfile::new(Rating::Everyone));
+        Arc::new(Mutex::new(rating))
+    }
+
+    #[test]
+    fn generate_for_withholds_content_above_the_players_rating() {
+        let rating = rating();
+        let output = r#"[{"speaker": "bandit", "line": "I will kill you, then kill your horse."}]"#;
+        let generator = generator(output, &rating);
+        let vars = HashMap::new();
+
+        let err = generator.generate_for("kid", "beats", &vars).unwrap_err();
+        assert!(matches!(err, GenerationError::Withheld { ref player, .. } if player == "kid"));
+        assert!(generator.generate_for("adult", "beats", &vars).is_ok());
+
+        let rating = rating.lock().unwrap();
+        let log: Vec<(&str, bool, Surface)> = rating.audit_log().map(|e| (e.player.as_str(), e.allowed, e.surface)).collect();
+        assert_eq!(log, vec![("kid", false, Surface::Dialogue), ("adult", true, Surface::Dialogue)]);
+    }
+
+    #[test]
+    fn out_of_range_output_is_repaired_without_another_call() {
+        let item = json!({
+            "name": "Ashen Blade",
+            "description": "Warm to the touch",
+            "stats": { "damage": "75", "weight": 2.5 },
+            "lore": "forged in the first war"
+        });
+        let raw = format!("Here you go:\n```json\n{}\n```", item);
+        let mut generator = ContentGenerator::new(Scripted::new(&[&raw]));
+        generator.register(ContentTemplate::item_stats("sword", &[("damage", 1.0, 50.0), ("weight", 0.1, 20.0)]));
+
+        let content = generator.generate("sword", &HashMap::new()).unwrap();
+        assert!(content.repaired);
+        assert_eq!(content.attempts, 1);
+        assert_eq!(content.value["stats"]["damage"], json!(50.0));
+        assert!(content.value.get("lore").is_none());
+    }
+
+    #[test]
+    fn validation_errors_are_fed_back_until_attempts_run_out() {
+        let empty = r#"{"title": "Lost Ring", "giver": "Mira", "objectives": [], "reward": 5}"#;
+        let scripted = Scripted::new(&["no json here", empty, "[]"]);
+        let mut generator = ContentGenerator::new(scripted);
+        generator.register(ContentTemplate::quest_structure("quest", 3));
+        let vars: HashMap<String, String> =
+            [("giver", "Mira"), ("location", "Emberfall")].iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+
+        let err = generator.generate("quest", &vars).unwrap_err();
+        assert!(matches!(err, GenerationError::InvalidOutput { attempts: 3, .. }));
+        let prompts = generator.generator.prompts.borrow();
+        assert!(prompts[0].starts_with("Write a quest given by Mira in Emberfall."));
+        assert!(prompts[1].contains("did not contain parseable JSON"));
+        assert!(prompts[2].contains("objectives") && prompts[2].contains("at least 1 items"));
+    }
+}
@@ -0,0 +1,634 @@
+// NPC genomes and populations
+//
+// An evolved NPC behaviour (a trader strategy, a raid boss's aggression and ability weights) is a
+// genome: a vector of numbers described by the archetype's GenomeSchema, which names each gene
+// and gives its valid range. A Population holds the genomes of one archetype with their lineage
+// and fitness.
+//
+// Populations survive restarts either as JSON files (`save` / `load`) or as records in agentdb
+// (`persist` / `restore`), so they can ride along with the rest of the world state.
+//
+// Genomes can also be shared between players and servers. `export` writes a self-describing
+// GenomeExchange document: genes are stored by name rather than position, the schema version is
+// recorded, and a checksum catches truncated or hand-edited files. `import` validates such a
+// document against the local schema before anything joins the population: wrong archetype,
+// unknown format or a bad checksum rejects the file, and each genome is checked for missing,
+// unknown, non-finite and out-of-range genes. Out-of-range values are clamped or rejected
+// depending on the ImportPolicy. Imported genomes get fresh ids and remember where they came from.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::agentdb::{AgentDb, AgentDbError};
+use crate::determinism::StateHasher;
+use crate::economy::TraderStrategy;
+use crate::namespace::Namespace;
+use crate::vector_index::unix_now;
+
+pub const EXCHANGE_FORMAT: &str = "arcadia.genomes";
+pub const EXCHANGE_VERSION: u32 = 1;
+
+const POPULATIONS_TABLE: &str = "genome_populations";
+// Exchange documents larger than this are refused before parsing
+const MAX_EXCHANGE_BYTES: usize = 4 << 20;
+const MAX_LABEL_CHARS: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GeneSpec {
+    pub name: String,
+    pub min: f64,
+    pub max: f64,
+    pub default: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenomeSchema {
+    pub archetype: String,
+    // Bump when genes change meaning; imports from other versions are still matched by name
+    pub version: u32,
+    pub genes: Vec<GeneSpec>,
+}
+
+impl GenomeSchema {
+    pub fn new(archetype: &str, version: u32) -> Self {
+        GenomeSchema { archetype: archetype.to_string(), version, genes: Vec::new() }
+    }
+
+    pub fn gene(mut self, name: &str, min: f64, max: f64, default: f64) -> Self {
+        self.genes.push(GeneSpec { name: name.to_string(), min, max, default: default.clamp(min, max) });
+        self
+    }
+
+    // Matches TraderStrategy::to_genes
+    pub fn trader() -> Self {
+        let defaults = TraderStrategy::default();
+        GenomeSchema::new("trader", 1)
+            .gene("markup", 0.0, 2.0, defaults.markup)
+            .gene("adjustment_rate", 0.01, 1.0, defaults.adjustment_rate)
+            .gene("stock_target", 1.0, 500.0, defaults.stock_target)
+            .gene("scarcity_premium", 0.0, 0.5, defaults.scarcity_premium)
+    }
+
+    pub fn defaults(&self) -> Vec<f64> {
+        self.genes.iter().map(|gene| gene.default).collect()
+    }
+
+    // Problems with a positional gene vector, empty when it fits the schema
+    pub fn check(&self, genes: &[f64]) -> Vec<String> {
+        let mut problems = Vec::new();
+        if genes.len() != self.genes.len() {
+            problems.push(format!("expected {} genes, found {}", self.genes.len(), genes.len()));
+        }
+        for (spec, value) in self.genes.iter().zip(genes) {
+            if !value.is_finite() {
+                problems.push(format!("{} is not a finite number", spec.name));
+            } else if *value < spec.min || *value > spec.max {
+                problems.push(format!("{} = {} is outside {}..{}", spec.name, value, spec.min, spec.max));
+            }
+        }
+        problems
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Genome {
+    pub id: u64,
+    // Ordered as the population schema's genes
+    pub genes: Vec<f64>,
+    pub generation: u32,
+    pub parents: Vec<u64>,
+    pub fitness: Option<f64>,
+    #[serde(default)]
+    pub label: Option<String>,
+    // Author of the exchange document an imported genome came from
+    #[serde(default)]
+    pub origin: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Population {
+    pub schema: GenomeSchema,
+    pub generation: u32,
+    genomes: Vec<Genome>,
+    next_id: u64,
+}
+
+#[derive(Debug)]
+pub enum GenomeError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Storage(AgentDbError),
+    // A saved population whose genomes do not fit its own schema
+    Corrupt(String),
+    InvalidGenes(Vec<String>),
+    UnsupportedFormat { format: String, version: u32 },
+    ArchetypeMismatch { expected: String, found: String },
+    ChecksumMismatch,
+    TooLarge { bytes: usize, limit: usize },
+}
+
+impl fmt::Display for GenomeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GenomeError::Io(err) => write!(f, "genome file error: {}", err),
+            GenomeError::Json(err) => write!(f, "genome document is not valid JSON: {}", err),
+            GenomeError::Storage(err) => write!(f, "genome storage error: {}", err),
+            GenomeError::Corrupt(reason) => write!(f, "saved population is corrupt: {}", reason),
+            GenomeError::InvalidGenes(problems) => write!(f, "invalid genes: {}", problems.join("; ")),
+            GenomeError::UnsupportedFormat { format, version } => {
+                write!(f, "unsupported genome document '{}' version {}", format, version)
+            }
+            GenomeError::ArchetypeMismatch { expected, found } => {
+                write!(f, "genomes are for '{}', this population is '{}'", found, expected)
+            }
+            GenomeError::ChecksumMismatch => write!(f, "genome document checksum does not match its contents"),
+            GenomeError::TooLarge { bytes, limit } => write!(f, "genome document is {} bytes, limit is {}", bytes, limit),
+        }
+    }
+}
+
+impl Error for GenomeError {}
+
+impl From<std::io::Error> for GenomeError {
+    fn from(err: std::io::Error) -> Self {
+        GenomeError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for GenomeError {
+    fn from(err: serde_json::Error) -> Self {
+        GenomeError::Json(err)
+    }
+}
+
+impl From<AgentDbError> for GenomeError {
+    fn from(err: AgentDbError) -> Self {
+        GenomeError::Storage(err)
+    }
+}
+
+impl Population {
+    pub fn new(schema: GenomeSchema) -> Self {
+        Population { schema, generation: 0, genomes: Vec::new(), next_id: 1 }
+    }
+
+    pub fn archetype(&self) -> &str {
+        &self.schema.archetype
+    }
+
+    pub fn genomes(&self) -> &[Genome] {
+        &self.genomes
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Genome> {
+        self.genomes.iter().find(|genome| genome.id == id)
+    }
+
+    // Genes must fit the schema; returns the new genome's id
+    pub fn add(&mut self, genes: Vec<f64>, parents: &[u64]) -> Result<u64, GenomeError> {
+        let problems = self.schema.check(&genes);
+        if !problems.is_empty() {
+            return Err(GenomeError::InvalidGenes(problems));
+        }
+        Ok(self.push(genes, parents.to_vec(), None, None))
+    }
+
+    fn push(&mut self, genes: Vec<f64>, parents: Vec<u64>, label: Option<String>, origin: Option<String>) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.genomes.push(Genome { id, genes, generation: self.generation, parents, fitness: None, label, origin });
+        id
+    }
+
+    pub fn remove(&mut self, id: u64) -> Option<Genome> {
+        let index = self.genomes.iter().position(|genome| genome.id == id)?;
+        Some(self.genomes.remove(index))
+    }
+
+    pub fn set_fitness(&mut self, id: u64, fitness: f64) -> bool {
+        match self.genomes.iter_mut().find(|genome| genome.id == id) {
+            Some(genome) => {
+                genome.fitness = Some(fitness);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Highest fitness first; unscored genomes last
+    pub fn ranked(&self) -> Vec<&Genome> {
+        let mut ranked: Vec<&Genome> = self.genomes.iter().collect();
+        ranked.sort_by(|a, b| {
+            let key = |genome: &Genome| genome.fitness.unwrap_or(f64::NEG_INFINITY);
+            key(b).total_cmp(&key(a)).then(a.id.cmp(&b.id))
+        });
+        ranked
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), GenomeError> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, GenomeError> {
+        let population: Population = serde_json::from_slice(&fs::read(path)?)?;
+        population.verify()?;
+        Ok(population)
+    }
+
+    // Stored under the archetype name, so one record per archetype and namespace
+    pub fn persist(&self, db: &mut AgentDb, namespace: &Namespace) -> Result<(), GenomeError> {
+        db.put(namespace, POPULATIONS_TABLE, self.archetype(), serde_json::to_value(self)?)?;
+        Ok(())
+    }
+
+    pub fn restore(db: &AgentDb, namespace: &Namespace, archetype: &str) -> Result<Option<Self>, GenomeError> {
+        let Some(value) = db.get(namespace, POPULATIONS_TABLE, archetype) else { return Ok(None) };
+        let population: Population = serde_json::from_value(value.clone())?;
+        population.verify()?;
+        Ok(Some(population))
+    }
+
+    pub fn stored_archetypes(db: &AgentDb, namespace: &Namespace) -> Vec<String> {
+        db.scan(namespace, POPULATIONS_TABLE).map(|(archetype, _)| archetype.clone()).collect()
+    }
+
+    fn verify(&self) -> Result<(), GenomeError> {
+        for genome in &self.genomes {
+            let problems = self.schema.check(&genome.genes);
+            if !problems.is_empty() {
+                return Err(GenomeError::Corrupt(format!("genome {}: {}", genome.id, problems.join("; "))));
+            }
+            if genome.id >= self.next_id {
+                return Err(GenomeError::Corrupt(format!("genome id {} is not below next id {}", genome.id, self.next_id)));
+            }
+        }
+        Ok(())
+    }
+
+    // Exchange document with the given genomes, in the order given; unknown ids are skipped
+    pub fn export(&self, ids: &[u64], author: &str, description: &str) -> GenomeExchange {
+        let genomes = ids
+            .iter()
+            .filter_map(|id| self.get(*id))
+            .map(|genome| SharedGenome {
+                label: genome.label.clone(),
+                generation: genome.generation,
+                fitness: genome.fitness,
+                genes: self.schema.genes.iter().zip(&genome.genes).map(|(spec, value)| (spec.name.clone(), *value)).collect(),
+            })
+            .collect();
+        let mut exchange = GenomeExchange {
+            format: EXCHANGE_FORMAT.to_string(),
+            version: EXCHANGE_VERSION,
+            archetype: self.schema.archetype.clone(),
+            schema_version: self.schema.version,
+            author: author.to_string(),
+            description: description.to_string(),
+            exported_at: unix_now(),
+            genomes,
+            checksum: String::new(),
+        };
+        exchange.checksum = exchange.compute_checksum();
+        exchange
+    }
+
+    // The `count` fittest genomes
+    pub fn export_best(&self, count: usize, author: &str, description: &str) -> GenomeExchange {
+        let ids: Vec<u64> = self.ranked().into_iter().take(count).map(|genome| genome.id).collect();
+        self.export(&ids, author, description)
+    }
+
+    // Validate the document against this population's schema and add the genomes that pass.
+    // Document-level problems fail the whole import; genome-level ones reject that genome only.
+    pub fn import(&mut self, exchange: &GenomeExchange, policy: &ImportPolicy) -> Result<ImportReport, GenomeError> {
+        if exchange.format != EXCHANGE_FORMAT || exchange.version > EXCHANGE_VERSION {
+            return Err(GenomeError::UnsupportedFormat { format: exchange.format.clone(), version: exchange.version });
+        }
+        if exchange.archetype != self.schema.archetype {
+            return Err(GenomeError::ArchetypeMismatch { expected: self.schema.archetype.clone(), found: exchange.archetype.clone() });
+        }
+        if exchange.checksum != exchange.compute_checksum() {
+            return Err(GenomeError::ChecksumMismatch);
+        }
+
+        let mut report = ImportReport::default();
+        if exchange.schema_version != self.schema.version {
+            report.warnings.push(format!(
+                "exported with schema version {}, local is {}; genes matched by name",
+                exchange.schema_version, self.schema.version
+            ));
+        }
+        let origin = sanitize(&exchange.author);
+        for (index, shared) in exchange.genomes.iter().enumerate() {
+            if report.imported.len() >= policy.max_genomes {
+                report.rejected.push((index, format!("import limit of {} genomes reached", policy.max_genomes)));
+                continue;
+            }
+            match self.validate_shared(shared, policy) {
+                Ok((genes, clamped)) => {
+                    if clamped > 0 {
+                        report.warnings.push(format!("genome {}: {} genes clamped into range", index, clamped));
+                    }
+                    let label = shared.label.as_deref().map(sanitize).filter(|label| !label.is_empty());
+                    let id = self.push(genes, Vec::new(), label, Some(origin.clone()));
+                    if let Some(genome) = self.genomes.last_mut() {
+                        genome.fitness = shared.fitness.filter(|f| f.is_finite() && policy.keep_fitness);
+                    }
+                    report.imported.push(id);
+                }
+                Err(problems) => report.rejected.push((index, problems.join("; "))),
+            }
+        }
+        Ok(report)
+    }
+
+    fn validate_shared(&self, shared: &SharedGenome, policy: &ImportPolicy) -> Result<(Vec<f64>, usize), Vec<String>> {
+        let mut problems = Vec::new();
+        let mut clamped = 0;
+        for name in shared.genes.keys() {
+            if !self.schema.genes.iter().any(|spec| &spec.name == name) {
+                problems.push(format!("unknown gene '{}'", name));
+            }
+        }
+        let mut genes = Vec::with_capacity(self.schema.genes.len());
+        for spec in &self.schema.genes {
+            let value = match shared.genes.get(&spec.name) {
+                Some(value) => *value,
+                None if policy.fill_missing => spec.default,
+                None => {
+                    problems.push(format!("missing gene '{}'", spec.name));
+                    continue;
+                }
+            };
+            if !value.is_finite() {
+                problems.push(format!("{} is not a finite number", spec.name));
+            } else if value < spec.min || value > spec.max {
+                if policy.clamp_out_of_range {
+                    clamped += 1;
+                    genes.push(value.clamp(spec.min, spec.max));
+                } else {
+                    problems.push(format!("{} = {} is outside {}..{}", spec.name, value, spec.min, spec.max));
+                }
+            } else {
+                genes.push(value);
+            }
+        }
+        if problems.is_empty() {
+            Ok((genes, clamped))
+        } else {
+            Err(problems)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportPolicy {
+    // Clamp out-of-range genes instead of rejecting the genome
+    pub clamp_out_of_range: bool,
+    // Use the schema default for genes the document lacks (e.g. from an older schema)
+    pub fill_missing: bool,
+    // Keep the exporter's fitness; otherwise imported genomes start unscored
+    pub keep_fitness: bool,
+    pub max_genomes: usize,
+}
+
+impl Default for ImportPolicy {
+    fn default() -> Self {
+        ImportPolicy { clamp_out_of_range: false, fill_missing: false, keep_fitness: false, max_genomes: 64 }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportReport {
+    // Ids assigned in this population
+    pub imported: Vec<u64>,
+    // (index in the document, reason)
+    pub rejected: Vec<(usize, String)>,
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SharedGenome {
+    #[serde(default)]
+    pub label: Option<String>,
+    pub generation: u32,
+    #[serde(default)]
+    pub fitness: Option<f64>,
+    pub genes: BTreeMap<String, f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenomeExchange {
+    pub format: String,
+    pub version: u32,
+    pub archetype: String,
+    pub schema_version: u32,
+    pub author: String,
+    pub description: String,
+    pub exported_at: u64,
+    pub genomes: Vec<SharedGenome>,
+    // Hex FNV-1a over every other field, numbers at 12 significant digits
+    pub checksum: String,
+}
+
+impl GenomeExchange {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    pub fn from_json(source: &str) -> Result<Self, GenomeError> {
+        if source.len() > MAX_EXCHANGE_BYTES {
+            return Err(GenomeError::TooLarge { bytes: source.len(), limit: MAX_EXCHANGE_BYTES });
+        }
+        Ok(serde_json::from_str(source)?)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), GenomeError> {
+        fs::write(path, self.to_json())?;
+        Ok(())
+    }
+
+    pub fn read(path: &Path) -> Result<Self, GenomeError> {
+        let bytes = fs::metadata(path)?.len() as usize;
+        if bytes > MAX_EXCHANGE_BYTES {
+            return Err(GenomeError::TooLarge { bytes, limit: MAX_EXCHANGE_BYTES });
+        }
+        GenomeExchange::from_json(&fs::read_to_string(path)?)
+    }
+
+    fn compute_checksum(&self) -> String {
+        let mut hasher = StateHasher::new();
+        hasher.str(&self.format);
+        hasher.u64(self.version as u64);
+        hasher.str(&self.archetype);
+        hasher.u64(self.schema_version as u64);
+        hasher.str(&self.author);
+        hasher.str(&self.description);
+        hasher.u64(self.exported_at);
+        hasher.u64(self.genomes.len() as u64);
+        for genome in &self.genomes {
+            hasher.str(genome.label.as_deref().unwrap_or(""));
+            hasher.u64(genome.generation as u64);
+            hasher.str(&genome.fitness.map(digits).unwrap_or_default());
+            hasher.u64(genome.genes.len() as u64);
+            for (name, value) in &genome.genes {
+                hasher.str(name);
+                hasher.str(&digits(*value));
+            }
+        }
+        format!("{:016x}", hasher.finish())
+    }
+}
+
+// JSON float parsing may be a bit off in the last place, so values are hashed at 12 digits
+fn digits(value: f64) -> String {
+    format!("{:.12e}", value)
+}
+
+// Printable, single-line and bounded, since labels and authors come from strangers
+fn sanitize(text: &str) -> String {
+    text.chars().filter(|c| !c.is_control()).take(MAX_LABEL_CHARS).collect::<String>().trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> GenomeSchema {
+        GenomeSchema::new("raid_boss", 2).gene("aggression", 0.0, 1.0, 0.5).gene("enrage_hp", 0.05, 0.5, 0.2)
+    }
+
+    fn population() -> Population {
+        let mut population = Population::new(schema());
+        let calm = population.add(vec![0.2, 0.1], &[]).unwrap();
+        let fierce = population.add(vec![0.9, 0.3], &[]).unwrap();
+        population.add(vec![0.5, 0.2], &[calm, fierce]).unwrap();
+        population.set_fitness(calm, 3.0);
+        population.set_fitness(fierce, 8.0);
+        population
+    }
+
+    fn shared(genes: &[(&str, f64)]) -> SharedGenome {
+        SharedGenome {
+            label: None,
+            generation: 0,
+            fitness: None,
+            genes: genes.iter().map(|(name, value)| (name.to_string(), *value)).collect(),
+        }
+    }
+
+    // A document from another server, checksummed like `export` does
+    fn document(genomes: Vec<SharedGenome>) -> GenomeExchange {
+        let mut exchange = Population::new(schema()).export(&[], "guild-7", "");
+        exchange.genomes = genomes;
+        exchange.checksum = exchange.compute_checksum();
+        exchange
+    }
+
+    #[test]
+    fn genes_are_checked_against_the_schema() {
+        let mut population = Population::new(schema());
+        assert_eq!(population.schema.defaults(), vec![0.5, 0.2]);
+        let GenomeError::InvalidGenes(problems) = population.add(vec![1.5, f64::NAN], &[]).unwrap_err() else {
+            panic!("expected invalid genes");
+        };
+        assert_eq!(problems, vec!["aggression = 1.5 is outside 0..1", "enrage_hp is not a finite number"]);
+        assert!(matches!(population.add(vec![0.5], &[]), Err(GenomeError::InvalidGenes(_))));
+        assert!(GenomeSchema::trader().check(&GenomeSchema::trader().defaults()).is_empty());
+    }
+
+    #[test]
+    fn ranking_puts_unscored_genomes_last() {
+        let population = population();
+        assert_eq!(population.ranked().iter().map(|g| g.id).collect::<Vec<_>>(), vec![2, 1, 3]);
+        assert_eq!(population.get(3).unwrap().parents, vec![1, 2]);
+    }
+
+    #[test]
+    fn populations_round_trip_through_files_and_agentdb() {
+        let path = std::env::temp_dir().join(format!("arcadia-genome-{}.json", std::process::id()));
+        let population = population();
+        population.save(&path).unwrap();
+        assert_eq!(Population::load(&path).unwrap().genomes().len(), 3);
+
+        // Hand-edited out of range
+        let edited = fs::read_to_string(&path).unwrap().replacen("0.9", "9.0", 1);
+        fs::write(&path, edited).unwrap();
+        assert!(matches!(Population::load(&path), Err(GenomeError::Corrupt(_))));
+        let _ = fs::remove_file(&path);
+
+        let (mut db, namespace) = (AgentDb::new(), Namespace::default_namespace());
+        population.persist(&mut db, &namespace).unwrap();
+        assert_eq!(Population::stored_archetypes(&db, &namespace), vec!["raid_boss"]);
+        let restored = Population::restore(&db, &namespace, "raid_boss").unwrap().unwrap();
+        assert_eq!(restored.get(2).unwrap().fitness, Some(8.0));
+        assert!(Population::restore(&db, &namespace, "trader").unwrap().is_none());
+    }
+
+    #[test]
+    fn exported_genomes_import_with_fresh_ids_and_origin() {
+        let exchange = population().export_best(2, "ines\u{7}", "best of season 3");
+        assert_eq!(exchange.genomes[0].genes["aggression"], 0.9);
+        let parsed = GenomeExchange::from_json(&exchange.to_json()).unwrap();
+
+        let mut local = Population::new(schema());
+        local.add(vec![0.5, 0.2], &[]).unwrap();
+        let report = local.import(&parsed, &ImportPolicy::default()).unwrap();
+        assert_eq!(report.imported, vec![2, 3]);
+        assert!(report.rejected.is_empty() && report.warnings.is_empty());
+        let imported = local.get(2).unwrap();
+        assert_eq!((imported.origin.as_deref(), imported.fitness), (Some("ines"), None));
+
+        let keep = ImportPolicy { keep_fitness: true, ..ImportPolicy::default() };
+        let report = local.import(&parsed, &keep).unwrap();
+        assert_eq!(local.get(report.imported[0]).unwrap().fitness, Some(8.0));
+    }
+
+    #[test]
+    fn bad_documents_are_rejected_whole() {
+        let mut local = Population::new(schema());
+        let mut tampered = population().export(&[1], "ines", "");
+        tampered.genomes[0].genes.insert("aggression".to_string(), 1.0);
+        assert!(matches!(local.import(&tampered, &ImportPolicy::default()), Err(GenomeError::ChecksumMismatch)));
+
+        let other = Population::new(GenomeSchema::trader()).export(&[], "ines", "");
+        assert!(matches!(local.import(&other, &ImportPolicy::default()), Err(GenomeError::ArchetypeMismatch { .. })));
+        let mut future = document(Vec::new());
+        future.version = EXCHANGE_VERSION + 1;
+        assert!(matches!(local.import(&future, &ImportPolicy::default()), Err(GenomeError::UnsupportedFormat { .. })));
+        assert!(matches!(GenomeExchange::from_json("{"), Err(GenomeError::Json(_))));
+        assert!(local.genomes().is_empty());
+    }
+
+    #[test]
+    fn bad_genomes_are_rejected_one_by_one() {
+        let mut exchange = document(vec![
+            shared(&[("aggression", 0.4), ("enrage_hp", 0.1)]),
+            shared(&[("aggression", 0.4), ("enrage_hp", 0.1), ("teleport", 1.0)]),
+            shared(&[("aggression", 0.4)]),
+            shared(&[("aggression", 3.0), ("enrage_hp", 0.1)]),
+        ]);
+        exchange.schema_version = 1;
+        exchange.checksum = exchange.compute_checksum();
+
+        let mut strict = Population::new(schema());
+        let report = strict.import(&exchange, &ImportPolicy::default()).unwrap();
+        assert_eq!(report.imported.len(), 1);
+        let reasons: Vec<&str> = report.rejected.iter().map(|(_, reason)| reason.as_str()).collect();
+        assert_eq!(reasons, vec!["unknown gene 'teleport'", "missing gene 'enrage_hp'", "aggression = 3 is outside 0..1"]);
+        assert!(report.warnings[0].contains("schema version 1, local is 2"));
+
+        let lenient = ImportPolicy { clamp_out_of_range: true, fill_missing: true, max_genomes: 2, ..ImportPolicy::default() };
+        let mut population = Population::new(schema());
+        let report = population.import(&exchange, &lenient).unwrap();
+        assert_eq!(report.imported.len(), 2);
+        assert_eq!(population.get(report.imported[1]).unwrap().genes, vec![0.4, 0.2]);
+        assert_eq!(report.rejected.last().unwrap().1, "import limit of 2 genomes reached");
+    }
+}
@@ -0,0 +1,421 @@
+// Godot integration
+//
+// With the `godot` feature the engine builds as a GDExtension (godot-rust) that registers:
+//
+// - ArcadiaNpc, a Node holding one NPC brain: GOAP facts, actions and goals (re-planned when
+//   facts change), a mood, and an authored dialogue conversation. Decisions, dialogue lines and
+//   mood changes are emitted as signals.
+// - ArcadiaDialogueTree, a Resource holding dialogue tree TOML so trees live in .tres files.
+// - ArcadiaDirector, a Node owning the emotion system and the visual director; every frame it
+//   emits the post-processing targets, and adaptation events when the target mood is published.
+//
+// Actions and goals are given as JSON in the same shape the engine serialises them, so they can
+// be authored once and shared with other integrations.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use godot::classes::{INode, IResource, Node, Resource};
+use godot::prelude::*;
+
+use crate::ai::goap::{Action, Goal, Plan, Planner, StateValue, WorldState};
+use crate::dialogue::tree::{DialogueContext, DialogueSession, DialogueTree};
+use crate::emotion::visual::VisualDirector;
+use crate::emotion::{EmotionAdaptiveExperiences, MoodVector};
+
+struct ArcadiaExtension;
+
+#[gdextension]
+unsafe impl ExtensionLibrary for ArcadiaExtension {}
+
+// Dialogue trees are static assets, so each distinct source is parsed once and kept for the life
+// of the process; sessions can then borrow them for 'static
+fn intern_tree(source: &str) -> Result<&'static DialogueTree, String> {
+    static TREES: OnceLock<Mutex<HashMap<String, &'static DialogueTree>>> = OnceLock::new();
+    let mut trees = TREES.get_or_init(Default::default).lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let Some(tree) = trees.get(source) {
+        return Ok(tree);
+    }
+    let tree: &'static DialogueTree = Box::leak(Box::new(DialogueTree::from_toml(source).map_err(|err| err.to_string())?));
+    trees.insert(source.to_string(), tree);
+    Ok(tree)
+}
+
+fn parse_actions(json: &str) -> Result<Planner, String> {
+    let actions = serde_json::from_str::<Vec<Action>>(json).map_err(|err| err.to_string())?;
+    Planner::new(actions).map_err(|err| err.to_string())
+}
+
+// Highest priority first
+fn parse_goals(json: &str) -> Result<Vec<Goal>, String> {
+    let mut goals = serde_json::from_str::<Vec<Goal>>(json).map_err(|err| err.to_string())?;
+    goals.sort_by(|a, b| b.priority.total_cmp(&a.priority));
+    Ok(goals)
+}
+
+// Plan for the first goal in `goals` that is unmet and reachable
+fn choose_plan(planner: &Planner, goals: &[Goal], facts: &WorldState) -> Option<Plan> {
+    goals
+        .iter()
+        .filter(|goal| !facts.satisfies(&goal.conditions))
+        .find_map(|goal| planner.plan(facts, goal))
+}
+
+fn state_value(value: &Variant) -> Option<StateValue> {
+    if let Ok(v) = value.try_to::<bool>() {
+        return Some(StateValue::Bool(v));
+    }
+    if let Ok(v) = value.try_to::<i64>() {
+        return Some(StateValue::Int(v));
+    }
+    value.try_to::<GString>().ok().map(|v| StateValue::Text(v.to_string()))
+}
+
+#[derive(GodotClass)]
+#[class(base = Resource, init)]
+pub struct ArcadiaDialogueTree {
+    base: Base<Resource>,
+    // Dialogue tree TOML
+    #[export]
+    source: GString,
+}
+
+#[godot_api]
+impl IResource for ArcadiaDialogueTree {}
+
+#[derive(GodotClass)]
+#[class(base = Node)]
+pub struct ArcadiaNpc {
+    base: Base<Node>,
+    #[export]
+    npc_id: GString,
+    planner: Planner,
+    goals: Vec<Goal>,
+    facts: WorldState,
+    // Facts changed since the last plan
+    replan: bool,
+    mood: MoodVector,
+    dialogue: Option<DialogueSession<'static>>,
+    relationships: HashMap<String, f32>,
+}
+
+#[godot_api]
+impl INode for ArcadiaNpc {
+    fn init(base: Base<Node>) -> Self {
+        ArcadiaNpc {
+            base,
+            npc_id: GString::new(),
+            planner: Planner::new(Vec::new()).expect("an empty action list is valid"),
+            goals: Vec::new(),
+            facts: WorldState::new(),
+            replan: false,
+            mood: MoodVector::default(),
+            dialogue: None,
+            relationships: HashMap::new(),
+        }
+    }
+
+    fn process(&mut self, _delta: f64) {
+        if self.replan {
+            self.decide();
+        }
+    }
+}
+
+#[godot_api]
+impl ArcadiaNpc {
+    #[signal]
+    fn decision_made(goal: GString, actions: PackedStringArray);
+
+    // No goal is reachable from the current facts
+    #[signal]
+    fn decision_failed();
+
+    #[signal]
+    fn line_spoken(speaker: GString, text: GString);
+
+    #[signal]
+    fn dialogue_finished();
+
+    #[signal]
+    fn mood_changed(tension: f32, valence: f32, energy: f32);
+
+    // Accepts bool, int or String values
+    #[func]
+    fn set_fact(&mut self, key: GString, value: Variant) -> bool {
+        let Some(value) = state_value(&value) else {
+            godot_warn!("fact '{}' must be a bool, int or String", key);
+            return false;
+        };
+        if self.facts.get(&key.to_string()) != Some(&value) {
+            self.facts.set(&key.to_string(), value);
+            self.replan = true;
+        }
+        true
+    }
+
+    #[func]
+    fn clear_fact(&mut self, key: GString) {
+        if self.facts.remove(&key.to_string()).is_some() {
+            self.replan = true;
+        }
+    }
+
+    // JSON array of engine actions ({"name", "cost", "preconditions", "effects"})
+    #[func]
+    fn load_actions(&mut self, json: GString) -> bool {
+        match parse_actions(&json.to_string()) {
+            Ok(planner) => {
+                self.planner = planner;
+                self.replan = true;
+                true
+            }
+            Err(err) => {
+                godot_error!("invalid actions: {}", err);
+                false
+            }
+        }
+    }
+
+    // JSON array of engine goals ({"name", "priority", "conditions"}); highest priority first
+    #[func]
+    fn load_goals(&mut self, json: GString) -> bool {
+        match parse_goals(&json.to_string()) {
+            Ok(goals) => {
+                self.goals = goals;
+                self.replan = true;
+                true
+            }
+            Err(err) => {
+                godot_error!("invalid goals: {}", err);
+                false
+            }
+        }
+    }
+
+    // Plan for the highest-priority unmet goal that has a plan; returns its action names
+    #[func]
+    fn decide(&mut self) -> PackedStringArray {
+        self.replan = false;
+        match choose_plan(&self.planner, &self.goals, &self.facts) {
+            Some(plan) => {
+                let actions: PackedStringArray = plan.action_names().into_iter().map(GString::from).collect();
+                let goal = GString::from(plan.goal.as_str());
+                self.base_mut().emit_signal("decision_made".into(), &[goal.to_variant(), actions.to_variant()]);
+                actions
+            }
+            None => {
+                self.base_mut().emit_signal("decision_failed".into(), &[]);
+                PackedStringArray::new()
+            }
+        }
+    }
+
+    #[func]
+    fn set_mood(&mut self, tension: f32, valence: f32, energy: f32) {
+        let mood = MoodVector::new(tension, valence, energy);
+        if mood != self.mood {
+            self.mood = mood;
+            self.base_mut().emit_signal(
+                "mood_changed".into(),
+                &[mood.tension.to_variant(), mood.valence.to_variant(), mood.energy.to_variant()],
+            );
+        }
+    }
+
+    // Relationship with the player, used by dialogue conditions
+    #[func]
+    fn set_relationship(&mut self, character: GString, value: f32) {
+        self.relationships.insert(character.to_string(), value);
+    }
+
+    #[func]
+    fn start_dialogue(&mut self, tree: Gd<ArcadiaDialogueTree>) -> bool {
+        let source = tree.bind().source.to_string();
+        let session = intern_tree(&source).and_then(|tree| DialogueSession::start(tree).map_err(|err| err.to_string()));
+        match session {
+            Ok(session) => {
+                self.dialogue = Some(session);
+                self.announce_line();
+                true
+            }
+            Err(err) => {
+                godot_error!("cannot start dialogue: {}", err);
+                false
+            }
+        }
+    }
+
+    // Texts of the choices available now; pass the index of one to `choose`
+    #[func]
+    fn dialogue_choices(&self) -> Dictionary {
+        let mut choices = Dictionary::new();
+        if let Some(session) = &self.dialogue {
+            for (index, choice) in session.available_choices(&self.dialogue_context()) {
+                choices.set(index as i64, GString::from(choice.text.as_str()));
+            }
+        }
+        choices
+    }
+
+    #[func]
+    fn choose(&mut self, index: i64) -> bool {
+        let context = self.dialogue_context();
+        let Some(session) = self.dialogue.as_mut() else {
+            return false;
+        };
+        let chosen = usize::try_from(index).ok().map(|i| session.choose(i, &context));
+        match chosen {
+            Some(Ok(_)) => {
+                self.announce_line();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Follow an automatic jump from the current node
+    #[func]
+    fn advance(&mut self) -> bool {
+        let advanced = self.dialogue.as_mut().map(|session| session.advance().is_ok()).unwrap_or(false);
+        if advanced {
+            self.announce_line();
+        }
+        advanced
+    }
+}
+
+impl ArcadiaNpc {
+    fn dialogue_context(&self) -> DialogueContext {
+        DialogueContext { state: self.facts.clone(), relationships: self.relationships.clone() }
+    }
+
+    fn announce_line(&mut self) {
+        let Some(session) = &self.dialogue else { return };
+        let line = session.current().and_then(|node| {
+            let text = node.text.clone()?;
+            Some((node.speaker.clone().unwrap_or_else(|| self.npc_id.to_string()), text))
+        });
+        let finished = session.is_finished();
+        if let Some((speaker, text)) = line {
+            self.base_mut().emit_signal(
+                "line_spoken".into(),
+                &[GString::from(speaker).to_variant(), GString::from(text).to_variant()],
+            );
+        }
+        if finished {
+            self.dialogue = None;
+            self.base_mut().emit_signal("dialogue_finished".into(), &[]);
+        }
+    }
+}
+
+#[derive(GodotClass)]
+#[class(base = Node)]
+pub struct ArcadiaDirector {
+    base: Base<Node>,
+    emotion: EmotionAdaptiveExperiences,
+    visuals: VisualDirector,
+}
+
+#[godot_api]
+impl INode for ArcadiaDirector {
+    fn init(base: Base<Node>) -> Self {
+        ArcadiaDirector { base, emotion: EmotionAdaptiveExperiences::new(), visuals: VisualDirector::with_defaults() }
+    }
+
+    fn process(&mut self, delta: f64) {
+        let params = self.visuals.update(self.emotion.target_mood(), delta as f32);
+        self.base_mut().emit_signal(
+            "visuals_updated".into(),
+            &[
+                params.bloom.to_variant(),
+                params.warmth.to_variant(),
+                params.vignette.to_variant(),
+                params.fog_density.to_variant(),
+            ],
+        );
+    }
+}
+
+#[godot_api]
+impl ArcadiaDirector {
+    // Post-processing targets for this frame
+    #[signal]
+    fn visuals_updated(bloom: f32, warmth: f32, vignette: f32, fog_density: f32);
+
+    // The mood the game steers towards changed
+    #[signal]
+    fn adaptation(tension: f32, valence: f32, energy: f32, revision: i64);
+
+    #[func]
+    fn set_player_mood(&mut self, player: GString, tension: f32, valence: f32, energy: f32) {
+        self.emotion.set_player_mood(&player.to_string(), MoodVector::new(tension, valence, energy));
+    }
+
+    #[func]
+    fn publish_target_mood(&mut self, tension: f32, valence: f32, energy: f32) {
+        self.emotion.publish_target_mood(MoodVector::new(tension, valence, energy));
+        let mood = self.emotion.target_mood();
+        let revision = self.emotion.revision() as i64;
+        self.base_mut().emit_signal(
+            "adaptation".into(),
+            &[mood.tension.to_variant(), mood.valence.to_variant(), mood.energy.to_variant(), revision.to_variant()],
+        );
+    }
+
+    // Current difficulty from DDA, 0..1
+    #[func]
+    fn set_difficulty(&mut self, difficulty: f32) {
+        self.visuals.set_difficulty(difficulty);
+    }
+}
+
+// Godot objects need a running engine, so these cover the brain logic the nodes delegate to
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TREE: &str = r#"
+id = "smith"
+start = "hello"
+
+[[nodes]]
+id = "hello"
+text = "Need something forged?"
+"#;
+
+    #[test]
+    fn trees_are_parsed_once_per_source() {
+        let first = intern_tree(TREE).unwrap();
+        assert!(std::ptr::eq(first, intern_tree(TREE).unwrap()));
+        assert!(!std::ptr::eq(first, intern_tree(&TREE.replace("forged", "mended")).unwrap()));
+        assert!(intern_tree("not = [toml").is_err());
+    }
+
+    #[test]
+    fn the_highest_priority_reachable_goal_is_planned() {
+        let actions = vec![
+            Action::new("draw_sword", 1.0).effect("armed", true),
+            Action::new("attack", 1.0).requires("armed", true).effect("enemy_down", true),
+        ];
+        let planner = parse_actions(&serde_json::to_string(&actions).unwrap()).unwrap();
+        let goals = vec![
+            Goal::new("rest", 1.0).wants("rested", true),
+            Goal::new("fight", 5.0).wants("enemy_down", true),
+            Goal::new("flee", 9.0).wants("safe", true),
+        ];
+        let goals = parse_goals(&serde_json::to_string(&goals).unwrap()).unwrap();
+        assert_eq!(goals.iter().map(|g| g.name.as_str()).collect::<Vec<_>>(), vec!["flee", "fight", "rest"]);
+
+        // Fleeing has no plan, so the NPC fights
+        let mut facts = WorldState::new();
+        let plan = choose_plan(&planner, &goals, &facts).unwrap();
+        assert_eq!((plan.goal.as_str(), plan.action_names()), ("fight", vec!["draw_sword", "attack"]));
+
+        facts.set("enemy_down", true);
+        assert!(choose_plan(&planner, &goals, &facts).is_none());
+        assert!(parse_actions("{}").is_err());
+        assert!(parse_goals("[{\"name\": \"x\"}]").is_err());
+    }
+}
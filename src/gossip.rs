@@ -0,0 +1,407 @@
+// Gossip
+//
+// Facts from the symbolic knowledge base spread between NPCs who meet. Each NPC holds beliefs:
+// a fact plus who told them, where it originally came from and how many retellings it went
+// through. When two NPCs are within talking distance they may pass on what they know; every
+// retelling loses some confidence and may get the details wrong (the object swapped for a
+// similar concept, likes heard as dislikes), so what the player tells the blacksmith reaches the
+// tavern a day later, possibly garbled, and NPCs can say who they heard it from.
+//
+// Beliefs are kept in ordered maps so a run with a seeded Rng spreads gossip the same way.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::events::EventBus;
+use crate::rng::Rng;
+use crate::spatial::SpatialIndex;
+use crate::symbolic::{RelationType, SymbolicComputing};
+
+pub const SPREAD_TOPIC: &str = "gossip.spread";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Fact {
+    pub subject: String,
+    pub relation: String,
+    pub object: String,
+}
+
+impl Fact {
+    pub fn new(subject: &str, relation: &RelationType, object: &str) -> Self {
+        Fact { subject: subject.to_string(), relation: relation.to_string(), object: object.to_string() }
+    }
+
+    // Every relation in the knowledge base that starts at `subject`
+    pub fn about(kb: &SymbolicComputing, subject: &str) -> Vec<Fact> {
+        kb.relations_from(subject).map(|r| Fact::new(&r.from, &r.relation, &r.to)).collect()
+    }
+
+    pub fn relation_type(&self) -> RelationType {
+        RelationType::parse(&self.relation)
+    }
+
+    // Beliefs about the same question ("who does the smith like?") conflict
+    fn key(&self) -> (String, String) {
+        (self.subject.clone(), self.relation.clone())
+    }
+}
+
+impl fmt::Display for Fact {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.subject, self.relation, self.object)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Belief {
+    pub fact: Fact,
+    // Who this NPC heard it from
+    pub source: String,
+    // Who first said it, e.g. "player"
+    pub origin: String,
+    // Retellings between the origin and this NPC
+    pub hops: u32,
+    pub confidence: f32,
+    pub learned_at: f64,
+    // Somewhere along the chain the fact was changed
+    pub distorted: bool,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GossipConfig {
+    // How close two NPCs must be to talk
+    pub radius: f32,
+    // Chance per belief per encounter of passing it on, scaled by confidence
+    pub share_chance: f64,
+    // Chance a retelling gets the fact wrong
+    pub distortion_chance: f64,
+    // Confidence kept per retelling
+    pub confidence_falloff: f32,
+    // Beliefs below this are not repeated
+    pub min_confidence: f32,
+    // Seconds before the same two NPCs gossip again
+    pub cooldown_secs: f64,
+}
+
+impl Default for GossipConfig {
+    fn default() -> Self {
+        GossipConfig {
+            radius: 3.0,
+            share_chance: 0.5,
+            distortion_chance: 0.1,
+            confidence_falloff: 0.85,
+            min_confidence: 0.2,
+            cooldown_secs: 300.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transmission {
+    pub from: String,
+    pub to: String,
+    pub belief: Belief,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Gossip {
+    config: GossipConfig,
+    // NPC -> what it believes, one belief per (subject, relation)
+    beliefs: BTreeMap<String, BTreeMap<(String, String), Belief>>,
+    // Last encounter per unordered pair
+    #[serde(skip)]
+    last_met: HashMap<(String, String), f64>,
+}
+
+impl Gossip {
+    pub fn new(config: GossipConfig) -> Self {
+        Gossip { config, ..Gossip::default() }
+    }
+
+    // An NPC learns something first hand (saw it, or the player told them)
+    pub fn learn(&mut self, npc: &str, fact: Fact, source: &str, now: f64) -> bool {
+        let belief = Belief {
+            fact,
+            source: source.to_string(),
+            origin: source.to_string(),
+            hops: 0,
+            confidence: 1.0,
+            learned_at: now,
+            distorted: false,
+        };
+        self.accept(npc, belief)
+    }
+
+    // Keep the new belief unless the NPC already holds a more confident answer to the same question
+    fn accept(&mut self, npc: &str, belief: Belief) -> bool {
+        let known = self.beliefs.entry(npc.to_string()).or_default();
+        let key = belief.fact.key();
+        match known.get(&key) {
+            Some(existing) if existing.fact == belief.fact && existing.confidence >= belief.confidence => false,
+            Some(existing) if existing.fact != belief.fact && existing.confidence > belief.confidence => false,
+            _ => {
+                known.insert(key, belief);
+                true
+            }
+        }
+    }
+
+    pub fn beliefs(&self, npc: &str) -> impl Iterator<Item = &Belief> {
+        self.beliefs.get(npc).into_iter().flat_map(|b| b.values())
+    }
+
+    // What `npc` believes about `subject`'s `relation`, if anything
+    pub fn belief(&self, npc: &str, subject: &str, relation: &RelationType) -> Option<&Belief> {
+        self.beliefs.get(npc)?.get(&(subject.to_string(), relation.to_string()))
+    }
+
+    // NPCs holding exactly this fact
+    pub fn who_knows(&self, fact: &Fact) -> Vec<&str> {
+        self.beliefs
+            .iter()
+            .filter(|(_, known)| known.get(&fact.key()).is_some_and(|b| &b.fact == fact))
+            .map(|(npc, _)| npc.as_str())
+            .collect()
+    }
+
+    // Let NPCs near each other exchange what they know; `kb` supplies plausible distortions
+    pub fn tick(
+        &mut self,
+        spatial: &SpatialIndex,
+        kb: &SymbolicComputing,
+        now: f64,
+        rng: &mut Rng,
+        mut events: Option<&mut EventBus>,
+    ) -> Vec<Transmission> {
+        let config = self.config;
+        let mut encounters = Vec::new();
+        for npc in self.beliefs.keys() {
+            let Some(position) = spatial.position(npc) else {
+                continue;
+            };
+            let mut nearby: Vec<String> =
+                spatial.query_radius(position, config.radius).into_iter().map(|(id, _)| id).filter(|id| id != npc).collect();
+            nearby.sort();
+            for other in nearby {
+                let pair = if *npc < other { (npc.clone(), other.clone()) } else { (other.clone(), npc.clone()) };
+                if self.last_met.get(&pair).is_some_and(|t| now - t < config.cooldown_secs) {
+                    continue;
+                }
+                self.last_met.insert(pair, now);
+                encounters.push((npc.clone(), other));
+            }
+        }
+
+        let mut spread = Vec::new();
+        for (speaker, listener) in encounters {
+            for (from, to) in [(speaker.clone(), listener.clone()), (listener, speaker)] {
+                let mut told = Vec::new();
+                // Nobody repeats a story to the one who told it
+                let retellable = self.beliefs(&from).filter(|b| b.confidence >= config.min_confidence && b.source != to);
+                for belief in retellable {
+                    if rng.chance(config.share_chance * belief.confidence as f64) {
+                        told.push(retell(belief, &from, kb, &config, now, rng));
+                    }
+                }
+                for belief in told {
+                    if self.accept(&to, belief.clone()) {
+                        if let Some(events) = events.as_deref_mut() {
+                            events.emit(
+                                SPREAD_TOPIC,
+                                &from,
+                                json!({
+                                    "from": from,
+                                    "to": to,
+                                    "fact": belief.fact.to_string(),
+                                    "origin": belief.origin,
+                                    "hops": belief.hops,
+                                    "distorted": belief.distorted,
+                                }),
+                            );
+                        }
+                        spread.push(Transmission { from: from.clone(), to: to.clone(), belief });
+                    }
+                }
+            }
+        }
+        spread
+    }
+
+    // Forget an NPC's beliefs, e.g. when it is removed from the world
+    pub fn forget(&mut self, npc: &str) {
+        self.beliefs.remove(npc);
+        self.last_met.retain(|(a, b), _| a != npc && b != npc);
+    }
+}
+
+// The belief as `teller` passes it on
+fn retell(belief: &Belief, teller: &str, kb: &SymbolicComputing, config: &GossipConfig, now: f64, rng: &mut Rng) -> Belief {
+    let mut fact = belief.fact.clone();
+    let mut distorted = belief.distorted;
+    if rng.chance(config.distortion_chance) {
+        if let Some(garbled) = distort(&fact, kb, rng) {
+            fact = garbled;
+            distorted = true;
+        }
+    }
+    Belief {
+        fact,
+        source: teller.to_string(),
+        origin: belief.origin.clone(),
+        hops: belief.hops + 1,
+        confidence: belief.confidence * config.confidence_falloff,
+        learned_at: now,
+        distorted,
+    }
+}
+
+// Swap the object for a sibling concept (same IsA parent), or flip likes and dislikes
+fn distort(fact: &Fact, kb: &SymbolicComputing, rng: &mut Rng) -> Option<Fact> {
+    let mut siblings: Vec<&str> = kb
+        .relations_from(&fact.object)
+        .filter(|r| r.relation == RelationType::IsA)
+        .flat_map(|parent| kb.relations_to(&parent.to).filter(|r| r.relation == RelationType::IsA))
+        .map(|r| r.from.as_str())
+        .filter(|name| *name != fact.object && *name != fact.subject)
+        .collect();
+    siblings.sort();
+    siblings.dedup();
+    let flipped = match fact.relation_type() {
+        RelationType::Likes => Some(RelationType::Dislikes),
+        RelationType::Dislikes => Some(RelationType::Likes),
+        _ => None,
+    };
+    match flipped {
+        Some(relation) if siblings.is_empty() || rng.chance(0.5) => Some(Fact::new(&fact.subject, &relation, &fact.object)),
+        _ => rng.choose(&siblings).map(|object| Fact { object: object.to_string(), ..fact.clone() }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spatial::GridSpec;
+
+    fn owns(object: &str) -> Fact {
+        Fact::new("smith", &RelationType::Owns, object)
+    }
+
+    // Every belief is shared and nothing gets garbled
+    fn faithful() -> GossipConfig {
+        GossipConfig {
+            share_chance: 1.0,
+            distortion_chance: 0.0,
+            confidence_falloff: 0.5,
+            min_confidence: 0.3,
+            cooldown_secs: 100.0,
+            ..GossipConfig::default()
+        }
+    }
+
+    fn armory() -> SymbolicComputing {
+        let mut kb = SymbolicComputing::new();
+        kb.add_relation("sword", RelationType::IsA, "weapon", 1.0);
+        kb.add_relation("axe", RelationType::IsA, "weapon", 1.0);
+        kb.add_relation("smith", RelationType::Owns, "sword", 1.0);
+        kb
+    }
+
+    #[test]
+    fn more_confident_beliefs_win_conflicts() {
+        let mut gossip = Gossip::new(GossipConfig::default());
+        assert!(gossip.learn("guard", owns("sword"), "player", 0.0));
+        assert!(!gossip.learn("guard", owns("sword"), "player", 1.0));
+
+        let first_hand = gossip.belief("guard", "smith", &RelationType::Owns).unwrap().clone();
+        let hearsay = Belief { confidence: 0.5, ..first_hand };
+        assert!(!gossip.accept("guard", Belief { fact: owns("axe"), ..hearsay.clone() }));
+        assert!(gossip.accept("maid", Belief { fact: owns("axe"), ..hearsay.clone() }));
+        assert!(gossip.accept("maid", Belief { confidence: 0.9, ..hearsay }));
+        assert_eq!(gossip.who_knows(&owns("sword")), vec!["guard", "maid"]);
+        assert_eq!(Fact::about(&armory(), "smith"), vec![owns("sword")]);
+    }
+
+    #[test]
+    fn facts_spread_between_nearby_npcs_and_lose_confidence() {
+        let mut spatial = SpatialIndex::new(GridSpec::new(64, 64, 1.0));
+        spatial.set_position("smith", [0.0, 0.0]);
+        spatial.set_position("barkeep", [1.0, 0.0]);
+        spatial.set_position("hermit", [50.0, 50.0]);
+        let mut bus = EventBus::new(16);
+        let sub = bus.subscribe(SPREAD_TOPIC);
+        let (kb, mut rng) = (armory(), Rng::new(1));
+        let mut gossip = Gossip::new(faithful());
+        gossip.learn("smith", owns("sword"), "player", 0.0);
+
+        let spread = gossip.tick(&spatial, &kb, 0.0, &mut rng, Some(&mut bus));
+        assert_eq!(spread.len(), 1);
+        let heard = &spread[0].belief;
+        assert_eq!((spread[0].from.as_str(), spread[0].to.as_str()), ("smith", "barkeep"));
+        assert_eq!((heard.source.as_str(), heard.origin.as_str()), ("smith", "player"));
+        assert_eq!((heard.hops, heard.confidence), (1, 0.5));
+        assert!(gossip.belief("hermit", "smith", &RelationType::Owns).is_none());
+        assert_eq!(bus.drain(sub)[0].payload["fact"], "smith Owns sword");
+
+        // The barkeep carries it across town; the maid hears it too weakly to repeat it to the hermit
+        spatial.set_position("barkeep", [20.0, 20.0]);
+        spatial.set_position("maid", [21.0, 20.0]);
+        spatial.set_position("hermit", [23.5, 20.0]);
+        let mut now = 0.0;
+        while gossip.belief("maid", "smith", &RelationType::Owns).is_none() && now < 2000.0 {
+            now += 100.0;
+            gossip.tick(&spatial, &kb, now, &mut rng, None);
+        }
+        let heard = gossip.belief("maid", "smith", &RelationType::Owns).unwrap();
+        assert_eq!((heard.source.as_str(), heard.hops, heard.confidence), ("barkeep", 2, 0.25));
+        for _ in 0..10 {
+            now += 100.0;
+            gossip.tick(&spatial, &kb, now, &mut rng, None);
+        }
+        assert!(gossip.belief("hermit", "smith", &RelationType::Owns).is_none());
+    }
+
+    #[test]
+    fn the_same_pair_waits_out_the_cooldown() {
+        let mut spatial = SpatialIndex::new(GridSpec::new(16, 16, 1.0));
+        spatial.set_position("smith", [0.0, 0.0]);
+        spatial.set_position("barkeep", [1.0, 0.0]);
+        let (kb, mut rng) = (armory(), Rng::new(1));
+        let mut gossip = Gossip::new(faithful());
+        gossip.learn("barkeep", Fact::new("mayor", &RelationType::Likes, "ale"), "player", 0.0);
+        assert_eq!(gossip.tick(&spatial, &kb, 0.0, &mut rng, None).len(), 1);
+        gossip.learn("smith", owns("sword"), "player", 10.0);
+        assert!(gossip.tick(&spatial, &kb, 50.0, &mut rng, None).is_empty());
+        assert_eq!(gossip.tick(&spatial, &kb, 100.0, &mut rng, None).len(), 1);
+
+        gossip.forget("barkeep");
+        assert_eq!(gossip.beliefs("barkeep").count(), 0);
+        assert!(gossip.last_met.is_empty());
+    }
+
+    #[test]
+    fn distortions_swap_siblings_or_flip_likes() {
+        let kb = armory();
+        let mut rng = Rng::new(3);
+        assert_eq!(distort(&owns("sword"), &kb, &mut rng), Some(owns("axe")));
+        let likes = Fact::new("smith", &RelationType::Likes, "ale");
+        assert_eq!(distort(&likes, &kb, &mut rng), Some(Fact::new("smith", &RelationType::Dislikes, "ale")));
+        assert_eq!(distort(&owns("anvil"), &kb, &mut rng), None);
+
+        let config = GossipConfig { distortion_chance: 1.0, ..faithful() };
+        let original = Belief {
+            fact: owns("sword"),
+            source: "player".to_string(),
+            origin: "player".to_string(),
+            hops: 0,
+            confidence: 1.0,
+            learned_at: 0.0,
+            distorted: false,
+        };
+        let retold = retell(&original, "smith", &kb, &config, 5.0, &mut rng);
+        assert_eq!((retold.fact, retold.distorted, retold.source.as_str()), (owns("axe"), true, "smith"));
+    }
+}
@@ -0,0 +1,584 @@
+// Guard mode
+//
+// Actions proposed by an LLM (through the dialogue tool bridge) or by a planner are not trusted
+// with the live world. Guard mode runs each one first against a throwaway clone of the GameWorld:
+// a simulator registered for the action name applies its effect to the clone, the clone is
+// diffed against the original, and every world rule inspects the before/after pair and the
+// resulting WorldDelta. Rules report into a ValidationReport like lore and tool rules do; any
+// error rejects the action, warnings are kept on the verdict. Actions without a simulator are
+// rejected by default, since nothing can be said about what they would do.
+//
+// Built-in rules cover the usual absurd outcomes: a numeric component leaving its range (negative
+// health, gold above a cap), a quantity that should be conserved appearing from nowhere, a single
+// change that is implausibly large, writes to protected entities or globals, and actions touching
+// far more of the world than any single action should.
+//
+// An accepted verdict carries the delta it produced. `commit` applies it in strict mode, so if the
+// live world moved on since the simulation the conflict is reported instead of applying effects
+// computed from stale state. `Guarded` wraps a tool handler so NPC tool calls are reviewed before
+// they run; a rejection goes back to the model as a failed tool result it can explain in
+// character. It reviews against the published world snapshot (snapshot.rs) without locking, or,
+// when committing, against the simulation's back buffer, where the delta is then applied in place
+// of running the handler. Verdicts are counted for diagnostics and published as "guard.verdict"
+// events.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::dialogue::tools::{ToolHandler, ToolInvocation};
+use crate::events::EventBus;
+use crate::snapshot::{WorldReader, WorldSnapshots};
+use crate::validation::ValidationReport;
+use crate::world::{ApplyMode, ApplyReport, Change, DeltaConflicts, GameWorld, WorldDelta};
+
+pub const VERDICT_TOPIC: &str = "guard.verdict";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProposedAction {
+    // Who proposed it, e.g. "llm" or "planner"
+    pub source: String,
+    // Entity performing the action
+    pub actor: String,
+    pub name: String,
+    pub arguments: Value,
+    pub target: Option<String>,
+}
+
+impl ProposedAction {
+    pub fn new(source: &str, actor: &str, name: &str, arguments: Value) -> Self {
+        ProposedAction {
+            source: source.to_string(),
+            actor: actor.to_string(),
+            name: name.to_string(),
+            arguments,
+            target: None,
+        }
+    }
+
+    pub fn target(mut self, target: &str) -> Self {
+        self.target = Some(target.to_string());
+        self
+    }
+
+    // An NPC tool call; the NPC acts on the player it is talking to
+    pub fn from_invocation(invocation: &ToolInvocation) -> Self {
+        ProposedAction::new("llm", &invocation.npc, &invocation.call.name, invocation.call.arguments.clone())
+            .target(&invocation.player)
+    }
+}
+
+// Applies an action's effect to a sandboxed world
+pub trait ActionSimulator {
+    fn simulate(&self, action: &ProposedAction, world: &mut GameWorld) -> Result<(), String>;
+}
+
+impl<F: Fn(&ProposedAction, &mut GameWorld) -> Result<(), String>> ActionSimulator for F {
+    fn simulate(&self, action: &ProposedAction, world: &mut GameWorld) -> Result<(), String> {
+        self(action, world)
+    }
+}
+
+pub trait WorldRule {
+    fn name(&self) -> &str;
+    fn check(&self, before: &GameWorld, after: &GameWorld, delta: &WorldDelta, report: &mut ValidationReport);
+}
+
+// Numeric component values written by the action must stay within [min, max]
+pub struct ComponentRange {
+    pub component: String,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl ComponentRange {
+    pub fn new(component: &str, min: f64, max: f64) -> Self {
+        ComponentRange { component: component.to_string(), min, max }
+    }
+}
+
+impl WorldRule for ComponentRange {
+    fn name(&self) -> &str {
+        "component_range"
+    }
+
+    fn check(&self, _before: &GameWorld, _after: &GameWorld, delta: &WorldDelta, report: &mut ValidationReport) {
+        for (id, _, new) in numeric_writes(delta, &self.component) {
+            if !(self.min..=self.max).contains(&new) {
+                report.error(
+                    &format!("entities.{}.{}", id, self.component),
+                    format!("{} is outside {}..={}", new, self.min, self.max),
+                );
+            }
+        }
+    }
+}
+
+// The world-wide total of a numeric component may not change (items and gold only move)
+pub struct Conserved {
+    pub component: String,
+    pub tolerance: f64,
+}
+
+impl Conserved {
+    pub fn new(component: &str) -> Self {
+        Conserved { component: component.to_string(), tolerance: 1e-6 }
+    }
+}
+
+impl WorldRule for Conserved {
+    fn name(&self) -> &str {
+        "conserved"
+    }
+
+    fn check(&self, before: &GameWorld, after: &GameWorld, _delta: &WorldDelta, report: &mut ValidationReport) {
+        let old = component_total(before, &self.component);
+        let new = component_total(after, &self.component);
+        // A NaN or infinite total compares as unchanged, so it is a violation of its own
+        if !new.is_finite() {
+            report.error(&self.component, format!("total became {}", new));
+        } else if (new - old).abs() > self.tolerance {
+            report.error(&self.component, format!("total changed from {} to {}", old, new));
+        }
+    }
+}
+
+// No single write may move a numeric component by more than `limit`
+pub struct MaxChange {
+    pub component: String,
+    pub limit: f64,
+}
+
+impl MaxChange {
+    pub fn new(component: &str, limit: f64) -> Self {
+        MaxChange { component: component.to_string(), limit }
+    }
+}
+
+impl WorldRule for MaxChange {
+    fn name(&self) -> &str {
+        "max_change"
+    }
+
+    fn check(&self, _before: &GameWorld, _after: &GameWorld, delta: &WorldDelta, report: &mut ValidationReport) {
+        for (id, old, new) in numeric_writes(delta, &self.component) {
+            let step = (new - old.unwrap_or(0.0)).abs();
+            if step > self.limit {
+                report.error(
+                    &format!("entities.{}.{}", id, self.component),
+                    format!("changed by {}, more than the limit of {}", step, self.limit),
+                );
+            }
+        }
+    }
+}
+
+// Paths the action may not touch, matched on change paths such as "entities.player-1" (the whole
+// entity), "entities.king.title" or "globals.weather"
+pub struct Protected {
+    pub paths: Vec<String>,
+}
+
+impl Protected {
+    pub fn new(paths: &[&str]) -> Self {
+        Protected { paths: paths.iter().map(|p| p.to_string()).collect() }
+    }
+}
+
+impl WorldRule for Protected {
+    fn name(&self) -> &str {
+        "protected"
+    }
+
+    fn check(&self, _before: &GameWorld, _after: &GameWorld, delta: &WorldDelta, report: &mut ValidationReport) {
+        for change in &delta.changes {
+            let path = change.path();
+            let protected = self
+                .paths
+                .iter()
+                .any(|p| path == *p || path.starts_with(&format!("{}.", p)) || p.starts_with(&format!("{}.", path)));
+            if protected {
+                report.error(&path, "is protected");
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GuardConfig {
+    // Actions changing more than this many components, entities or globals are rejected
+    pub max_changes: usize,
+    // Reject actions with no registered simulator; when false they pass with a warning
+    pub reject_unknown: bool,
+}
+
+impl Default for GuardConfig {
+    fn default() -> Self {
+        GuardConfig { max_changes: 32, reject_unknown: true }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Verdict {
+    pub action: ProposedAction,
+    pub accepted: bool,
+    // What the action did to the sandbox; applied by `Guard::commit`
+    pub delta: WorldDelta,
+    pub report: ValidationReport,
+}
+
+impl Verdict {
+    pub fn summary(&self) -> String {
+        if self.accepted {
+            return format!("'{}' accepted with {} changes", self.action.name, self.delta.changes.len());
+        }
+        let reasons: Vec<String> = self.report.errors().map(|i| i.to_string()).collect();
+        format!("'{}' rejected: {}", self.action.name, reasons.join("; "))
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "action": self.action,
+            "accepted": self.accepted,
+            "changes": self.delta.changes.iter().map(Change::path).collect::<Vec<_>>(),
+            "issues": self.report.issues.iter().map(|i| i.to_string()).collect::<Vec<_>>(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct GuardStats {
+    pub reviewed: u64,
+    pub accepted: u64,
+    pub rejected: u64,
+    // Rejections per rule name ("simulation", "unknown_action" and "max_changes" included)
+    pub rejections: BTreeMap<String, u64>,
+}
+
+#[derive(Debug)]
+pub enum GuardError {
+    Rejected(String),
+    // The live world changed since the simulation
+    Stale(DeltaConflicts),
+}
+
+impl fmt::Display for GuardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GuardError::Rejected(summary) => write!(f, "{}", summary),
+            GuardError::Stale(conflicts) => write!(f, "world changed since simulation: {}", conflicts),
+        }
+    }
+}
+
+impl std::error::Error for GuardError {}
+
+impl From<DeltaConflicts> for GuardError {
+    fn from(err: DeltaConflicts) -> Self {
+        GuardError::Stale(err)
+    }
+}
+
+#[derive(Default)]
+pub struct Guard {
+    simulators: BTreeMap<String, Box<dyn ActionSimulator + Send + Sync>>,
+    rules: Vec<Box<dyn WorldRule + Send + Sync>>,
+    pub config: GuardConfig,
+    // Verdicts not yet published
+    pending: Vec<Verdict>,
+    stats: GuardStats,
+}
+
+impl Guard {
+    pub fn new() -> Self {
+        Guard::default()
+    }
+
+    pub fn register(&mut self, action: &str, simulator: impl ActionSimulator + Send + Sync + 'static) {
+        self.simulators.insert(action.to_string(), Box::new(simulator));
+    }
+
+    pub fn rule(&mut self, rule: impl WorldRule + Send + Sync + 'static) {
+        self.rules.push(Box::new(rule));
+    }
+
+    pub fn has_simulator(&self, action: &str) -> bool {
+        self.simulators.contains_key(action)
+    }
+
+    // Run the action on a copy of `world`; the world itself is never modified
+    pub fn simulate(&self, world: &GameWorld, action: &ProposedAction) -> Verdict {
+        self.simulate_with_rules(world, action).0
+    }
+
+    // Simulate a sequence (e.g. a plan), each action seeing the effects of the ones before. Stops
+    // after the first rejection; the accepted prefix can be committed in order.
+    pub fn simulate_plan(&self, world: &GameWorld, actions: &[ProposedAction]) -> Vec<Verdict> {
+        let mut sandbox = world.clone();
+        let mut verdicts = Vec::new();
+        for action in actions {
+            let verdict = self.simulate(&sandbox, action);
+            let accepted = verdict.accepted;
+            if accepted {
+                // The delta was built against this sandbox, so it cannot conflict
+                let _ = sandbox.apply_delta(&verdict.delta, ApplyMode::Force);
+            }
+            verdicts.push(verdict);
+            if !accepted {
+                break;
+            }
+        }
+        verdicts
+    }
+
+    // Simulate, count the verdict and queue it for publishing
+    pub fn review(&mut self, world: &GameWorld, action: &ProposedAction) -> Verdict {
+        let (verdict, failed) = self.simulate_with_rules(world, action);
+        self.stats.reviewed += 1;
+        if verdict.accepted {
+            self.stats.accepted += 1;
+        } else {
+            self.stats.rejected += 1;
+            for rule in failed {
+                *self.stats.rejections.entry(rule).or_insert(0) += 1;
+            }
+        }
+        self.pending.push(verdict.clone());
+        verdict
+    }
+
+    // Apply an accepted verdict to the live world
+    pub fn commit(&self, world: &mut GameWorld, verdict: &Verdict) -> Result<ApplyReport, GuardError> {
+        if !verdict.accepted {
+            return Err(GuardError::Rejected(verdict.summary()));
+        }
+        Ok(world.apply_delta(&verdict.delta, ApplyMode::Strict)?)
+    }
+
+    pub fn stats(&self) -> &GuardStats {
+        &self.stats
+    }
+
+    pub fn publish(&mut self, events: &mut EventBus) -> usize {
+        let pending = std::mem::take(&mut self.pending);
+        for verdict in &pending {
+            events.emit(VERDICT_TOPIC, &verdict.action.actor, verdict.to_json());
+        }
+        pending.len()
+    }
+
+    // The verdict plus the names of the checks that produced errors
+    fn simulate_with_rules(&self, world: &GameWorld, action: &ProposedAction) -> (Verdict, Vec<String>) {
+        let mut report = ValidationReport::new();
+        let mut failed = Vec::new();
+        let mut sandbox = world.clone();
+
+        match self.simulators.get(&action.name) {
+            Some(simulator) => {
+                if let Err(err) = simulator.simulate(action, &mut sandbox) {
+                    report.error("", format!("simulation failed: {}", err));
+                    failed.push("simulation".to_string());
+                }
+            }
+            None if self.config.reject_unknown => {
+                report.error("", format!("no simulator for '{}'", action.name));
+                failed.push("unknown_action".to_string());
+            }
+            None => report.warning("", format!("no simulator for '{}'; effects unchecked", action.name)),
+        }
+
+        let delta = world.diff(&sandbox);
+        if failed.is_empty() {
+            if delta.changes.len() > self.config.max_changes {
+                report.error(
+                    "",
+                    format!("{} changes, more than the limit of {}", delta.changes.len(), self.config.max_changes),
+                );
+                failed.push("max_changes".to_string());
+            }
+            for rule in &self.rules {
+                let mut findings = ValidationReport::new();
+                rule.check(world, &sandbox, &delta, &mut findings);
+                if !findings.is_valid() {
+                    failed.push(rule.name().to_string());
+                }
+                report.merge(findings);
+            }
+        }
+
+        let accepted = report.is_valid();
+        (Verdict { action: action.clone(), accepted, delta, report }, failed)
+    }
+}
+
+// Tool handler that reviews each call with the guard before handing it to `inner`. With
+// `committing`, the simulated delta is also applied to the simulation's back buffer.
+pub struct Guarded<H> {
+    guard: Arc<Mutex<Guard>>,
+    world: WorldReader,
+    inner: H,
+    commit: Option<Arc<Mutex<WorldSnapshots>>>,
+}
+
+impl<H: ToolHandler> Guarded<H> {
+    pub fn new(guard: Arc<Mutex<Guard>>, world: WorldReader, inner: H) -> Self {
+        Guarded { guard, world, inner, commit: None }
+    }
+
+    // Apply accepted actions' simulated deltas to the simulation's back buffer instead of running
+    // the wrapped handler
+    pub fn committing(mut self, snapshots: Arc<Mutex<WorldSnapshots>>) -> Self {
+        self.commit = Some(snapshots);
+        self
+    }
+}
+
+impl<H: ToolHandler> ToolHandler for Guarded<H> {
+    fn execute(&mut self, invocation: &ToolInvocation, events: &mut EventBus) -> Result<Value, String> {
+        let action = ProposedAction::from_invocation(invocation);
+        let mut guard = self.guard.lock().map_err(|_| "guard lock poisoned".to_string())?;
+        match &self.commit {
+            Some(snapshots) => {
+                let mut snapshots = snapshots.lock().map_err(|_| "world lock poisoned".to_string())?;
+                let verdict = guard.review(snapshots.world(), &action);
+                if !verdict.accepted {
+                    return Err(verdict.summary());
+                }
+                // The simulated delta is the action's effect; running the handler too would apply
+                // it twice
+                let applied = snapshots
+                    .world_mut()
+                    .apply_delta(&verdict.delta, ApplyMode::Strict)
+                    .map_err(|e| e.to_string())?;
+                Ok(json!({ "status": "applied", "changes": applied.applied }))
+            }
+            None => {
+                let verdict = guard.review(&self.world.load().world, &action);
+                if !verdict.accepted {
+                    return Err(verdict.summary());
+                }
+                drop(guard);
+                self.inner.execute(invocation, events)
+            }
+        }
+    }
+}
+
+// (entity, old, new) for every numeric write of `component` in the delta
+fn numeric_writes<'a>(delta: &'a WorldDelta, component: &'a str) -> impl Iterator<Item = (&'a str, Option<f64>, f64)> + 'a {
+    delta.changes.iter().filter_map(move |change| match change {
+        Change::ComponentSet { id, component: c, old, new } if c == component => {
+            new.as_f64().map(|new| (id.as_str(), old.as_ref().and_then(Value::as_f64), new))
+        }
+        Change::EntityAdded { id, entity } => entity
+            .component(component)
+            .and_then(Value::as_f64)
+            .map(|new| (id.as_str(), None, new)),
+        _ => None,
+    })
+}
+
+fn component_total(world: &GameWorld, component: &str) -> f64 {
+    world.entities().filter_map(|(_, e)| e.component(component).and_then(Value::as_f64)).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dialogue::tools::ToolCall;
+    use crate::world::Entity;
+
+    fn world() -> GameWorld {
+        let mut world = GameWorld::new();
+        world.spawn("mira", Entity::new("npc").with("gold", json!(10.0)));
+        world.spawn("player", Entity::new("player").with("gold", json!(5.0)));
+        world
+    }
+
+    // Moves `amount` gold from the actor to the target
+    fn pay(action: &ProposedAction, world: &mut GameWorld) -> Result<(), String> {
+        let amount = action.arguments["amount"].as_f64().ok_or("amount missing")?;
+        let target = action.target.as_deref().ok_or("no target")?;
+        let gold = |world: &GameWorld, id: &str| world.entity(id).and_then(|e| e.component("gold")).and_then(Value::as_f64);
+        let (from, to) = (gold(world, &action.actor).unwrap_or(0.0), gold(world, target).unwrap_or(0.0));
+        world.set_component(&action.actor, "gold", json!(from - amount));
+        world.set_component(target, "gold", json!(to + amount));
+        Ok(())
+    }
+
+    fn guard() -> Guard {
+        let mut guard = Guard::new();
+        guard.register("pay", pay);
+        guard.rule(Conserved::new("gold"));
+        guard
+    }
+
+    fn invocation(amount: Value) -> ToolInvocation {
+        ToolInvocation {
+            call: ToolCall { id: "call-1".to_string(), name: "pay".to_string(), arguments: json!({ "amount": amount }) },
+            npc: "mira".to_string(),
+            player: "player".to_string(),
+        }
+    }
+
+    #[test]
+    fn non_finite_totals_break_conservation() {
+        let guard = guard();
+        let transfer = ProposedAction::new("llm", "mira", "pay", json!({ "amount": 3.0 })).target("player");
+        assert!(guard.simulate(&world(), &transfer).accepted);
+
+        // JSON has no NaN, but totals can still overflow; inf - inf is NaN, which never exceeds
+        // the tolerance
+        let mut before = world();
+        before.set_component("mira", "gold", json!(f64::MAX));
+        before.set_component("player", "gold", json!(f64::MAX));
+        let mut after = before.clone();
+        after.spawn("thief", Entity::new("npc").with("gold", json!(f64::MAX)));
+        let mut report = ValidationReport::default();
+        Conserved::new("gold").check(&before, &after, &before.diff(&after), &mut report);
+        assert!(!report.is_valid());
+    }
+
+    #[test]
+    fn committing_applies_the_delta_without_running_the_handler() {
+        let snapshots = Arc::new(Mutex::new(WorldSnapshots::new(world())));
+        let reader = snapshots.lock().unwrap().reader();
+        let runs = Arc::new(Mutex::new(0));
+        let counter = Arc::clone(&runs);
+        let handler = move |_: &ToolInvocation, _: &mut EventBus| {
+            *counter.lock().unwrap() += 1;
+            Ok(json!({ "status": "paid" }))
+        };
+        let mut tool = Guarded::new(Arc::new(Mutex::new(guard())), reader, handler).committing(Arc::clone(&snapshots));
+        let mut events = EventBus::new(16);
+
+        let result = tool.execute(&invocation(json!(4.0)), &mut events).unwrap();
+        assert_eq!(result["status"], "applied");
+        assert_eq!(*runs.lock().unwrap(), 0);
+        let snapshots = snapshots.lock().unwrap();
+        assert_eq!(snapshots.world().entity("mira").and_then(|e| e.component("gold")), Some(&json!(6.0)));
+        assert_eq!(snapshots.world().entity("player").and_then(|e| e.component("gold")), Some(&json!(9.0)));
+    }
+
+    #[test]
+    fn reviewing_runs_the_handler_only_for_accepted_actions() {
+        let snapshots = WorldSnapshots::new(world());
+        let runs = Arc::new(Mutex::new(0));
+        let counter = Arc::clone(&runs);
+        let handler = move |_: &ToolInvocation, _: &mut EventBus| {
+            *counter.lock().unwrap() += 1;
+            Ok(json!({ "status": "paid" }))
+        };
+        let mut tool = Guarded::new(Arc::new(Mutex::new(guard())), snapshots.reader(), handler);
+        let mut events = EventBus::new(16);
+
+        assert_eq!(tool.execute(&invocation(json!(2.0)), &mut events).unwrap()["status"], "paid");
+        assert!(tool.execute(&invocation(json!("lots")), &mut events).is_err());
+        assert_eq!(*runs.lock().unwrap(), 1);
+        // Reviews never touch the world
+        assert_eq!(snapshots.world().entity("mira").and_then(|e| e.component("gold")), Some(&json!(10.0)));
+    }
+}
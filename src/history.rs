@@ -0,0 +1,468 @@
+// World history
+//
+// Major events (wars, discoveries, deaths, foundings) go into an ordered ledger with the game day
+// they happened on, the region and the participants. The ledger only grows: events can be
+// backdated, for lore written after the fact, and are slotted into place by day, but never edited
+// or removed, so what NPCs and generated content say about the past stays consistent.
+//
+//   ledger.query(&HistoryQuery::new().region("iron_hills").between(3.0, 10.0))
+//
+// answers "what happened in the Iron Hills between day 3 and 10". Events are also embedded into a
+// vector collection (`embed`, incrementally) for semantic recall ("the old war with the dwarves"),
+// and can be turned into facts for the knowledge graph (`lore_facts`): a death marks its
+// participants dead, so the LoreChecker rejects generated quests handed out by a dead NPC.
+// `chronicle` renders events as dated lines to put into generation prompts.
+//
+// Recorded events are published as "world.history" events.
+
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::agentdb::{AgentDb, AgentDbError};
+use crate::embeddings::Embedder;
+use crate::events::EventBus;
+use crate::lore::Fact;
+use crate::namespace::Namespace;
+use crate::symbolic::RelationType;
+use crate::vector_index::{VectorIndex, VectorIndexError};
+
+pub const HISTORY_TOPIC: &str = "world.history";
+
+// Well-known event kinds; anything else is allowed too
+pub const WAR: &str = "war";
+pub const DISCOVERY: &str = "discovery";
+pub const DEATH: &str = "death";
+pub const FOUNDING: &str = "founding";
+pub const TREATY: &str = "treaty";
+pub const DISASTER: &str = "disaster";
+
+// Payload field holding the ledger sequence number of an embedded event
+pub const HISTORY_SEQ_FIELD: &str = "history_seq";
+
+const HISTORY_TABLE: &str = "world_history";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoricalEvent {
+    // Order of recording; assigned by the ledger
+    #[serde(default)]
+    pub seq: u64,
+    // Game day, fractional for time of day
+    pub day: f64,
+    pub kind: String,
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub region: Option<String>,
+    #[serde(default)]
+    pub participants: Vec<String>,
+}
+
+impl HistoricalEvent {
+    pub fn new(day: f64, kind: &str, title: &str) -> Self {
+        HistoricalEvent {
+            seq: 0,
+            day,
+            kind: kind.to_string(),
+            title: title.to_string(),
+            description: String::new(),
+            region: None,
+            participants: Vec::new(),
+        }
+    }
+
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = description.to_string();
+        self
+    }
+
+    pub fn region(mut self, region: &str) -> Self {
+        self.region = Some(region.to_string());
+        self
+    }
+
+    pub fn participant(mut self, participant: &str) -> Self {
+        self.participants.push(participant.to_string());
+        self
+    }
+
+    // One line for prompts and tooling, e.g. "Day 3 [iron_hills] war: The Iron War begins (Thorin, Mira)"
+    pub fn line(&self) -> String {
+        let mut line = format!("Day {}", format_day(self.day));
+        if let Some(region) = &self.region {
+            let _ = write!(line, " [{}]", region);
+        }
+        let _ = write!(line, " {}: {}", self.kind, self.title);
+        if !self.participants.is_empty() {
+            let _ = write!(line, " ({})", self.participants.join(", "));
+        }
+        if !self.description.is_empty() {
+            let _ = write!(line, " - {}", self.description);
+        }
+        line
+    }
+}
+
+fn format_day(day: f64) -> String {
+    if day.fract() == 0.0 {
+        format!("{}", day as i64)
+    } else {
+        format!("{:.1}", day)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HistoryQuery {
+    pub region: Option<String>,
+    // Inclusive bounds in game days
+    pub from_day: Option<f64>,
+    pub to_day: Option<f64>,
+    pub participant: Option<String>,
+    pub kinds: Vec<String>,
+    // Most recent first instead of chronological
+    pub newest_first: bool,
+    pub limit: Option<usize>,
+}
+
+impl HistoryQuery {
+    pub fn new() -> Self {
+        HistoryQuery::default()
+    }
+
+    pub fn region(mut self, region: &str) -> Self {
+        self.region = Some(region.to_string());
+        self
+    }
+
+    pub fn between(mut self, from_day: f64, to_day: f64) -> Self {
+        self.from_day = Some(from_day);
+        self.to_day = Some(to_day);
+        self
+    }
+
+    pub fn since(mut self, from_day: f64) -> Self {
+        self.from_day = Some(from_day);
+        self
+    }
+
+    pub fn involving(mut self, participant: &str) -> Self {
+        self.participant = Some(participant.to_string());
+        self
+    }
+
+    pub fn kind(mut self, kind: &str) -> Self {
+        self.kinds.push(kind.to_string());
+        self
+    }
+
+    pub fn newest_first(mut self) -> Self {
+        self.newest_first = true;
+        self
+    }
+
+    pub fn limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn matches(&self, event: &HistoricalEvent) -> bool {
+        self.region.as_ref().is_none_or(|region| event.region.as_ref() == Some(region))
+            && self.from_day.is_none_or(|from| event.day >= from)
+            && self.to_day.is_none_or(|to| event.day <= to)
+            && self.participant.as_ref().is_none_or(|p| event.participants.contains(p))
+            && (self.kinds.is_empty() || self.kinds.contains(&event.kind))
+    }
+}
+
+#[derive(Debug)]
+pub enum HistoryError {
+    // Days must be finite
+    InvalidDay(f64),
+    Memory(VectorIndexError),
+    Storage(AgentDbError),
+    Corrupt(serde_json::Error),
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HistoryError::InvalidDay(day) => write!(f, "invalid history day {}", day),
+            HistoryError::Memory(err) => write!(f, "{}", err),
+            HistoryError::Storage(err) => write!(f, "{}", err),
+            HistoryError::Corrupt(err) => write!(f, "stored history event is invalid: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for HistoryError {}
+
+impl From<VectorIndexError> for HistoryError {
+    fn from(err: VectorIndexError) -> Self {
+        HistoryError::Memory(err)
+    }
+}
+
+impl From<AgentDbError> for HistoryError {
+    fn from(err: AgentDbError) -> Self {
+        HistoryError::Storage(err)
+    }
+}
+
+impl From<serde_json::Error> for HistoryError {
+    fn from(err: serde_json::Error) -> Self {
+        HistoryError::Corrupt(err)
+    }
+}
+
+#[derive(Default)]
+pub struct HistoryLedger {
+    // Ordered by (day, seq)
+    events: Vec<HistoricalEvent>,
+    next_seq: u64,
+    // Sorted seqs of the events already embedded
+    embedded: Vec<u64>,
+    pending: Vec<HistoricalEvent>,
+}
+
+impl HistoryLedger {
+    pub fn new() -> Self {
+        HistoryLedger { next_seq: 1, ..HistoryLedger::default() }
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    // Append an event; returns its sequence number
+    pub fn record(&mut self, mut event: HistoricalEvent) -> Result<u64, HistoryError> {
+        if !event.day.is_finite() {
+            return Err(HistoryError::InvalidDay(event.day));
+        }
+        event.seq = self.next_seq.max(1);
+        self.next_seq = event.seq + 1;
+        let at = self.events.partition_point(|existing| existing.day <= event.day);
+        self.events.insert(at, event.clone());
+        let seq = event.seq;
+        self.pending.push(event);
+        Ok(seq)
+    }
+
+    pub fn get(&self, seq: u64) -> Option<&HistoricalEvent> {
+        self.events.iter().find(|event| event.seq == seq)
+    }
+
+    // Chronological
+    pub fn events(&self) -> &[HistoricalEvent] {
+        &self.events
+    }
+
+    pub fn query(&self, query: &HistoryQuery) -> Vec<&HistoricalEvent> {
+        let matching = self.events.iter().filter(|event| query.matches(event));
+        let limit = query.limit.unwrap_or(usize::MAX);
+        if query.newest_first {
+            matching.rev().take(limit).collect()
+        } else {
+            matching.take(limit).collect()
+        }
+    }
+
+    // Dated lines for the matching events, for generation prompts
+    pub fn chronicle(&self, query: &HistoryQuery) -> String {
+        self.query(query).iter().map(|event| event.line()).collect::<Vec<_>>().join("\n")
+    }
+
+    // Knowledge graph facts implied by an event, to check generated lore against with LoreChecker
+    pub fn lore_facts(event: &HistoricalEvent) -> Vec<Fact> {
+        let mut facts = Vec::new();
+        if event.kind == DEATH {
+            for participant in &event.participants {
+                facts.push(Fact::Property { subject: participant.clone(), key: "status".to_string(), value: "dead".to_string() });
+            }
+        }
+        if let Some(region) = &event.region {
+            let concept = format!("history:{}", event.seq);
+            facts.push(Fact::Relation { from: concept, relation: RelationType::LocatedIn, to: region.clone() });
+        }
+        facts
+    }
+
+    // Embed every event not embedded yet into `collection`; returns the number embedded
+    pub fn embed(&mut self, index: &mut VectorIndex, collection: &str, embedder: &dyn Embedder) -> Result<usize, HistoryError> {
+        let mut embedded = 0;
+        for event in &self.events {
+            if self.embedded.binary_search(&event.seq).is_ok() {
+                continue;
+            }
+            let mut payload = HashMap::from([
+                (HISTORY_SEQ_FIELD.to_string(), Value::from(event.seq)),
+                ("day".to_string(), Value::from(event.day)),
+                ("kind".to_string(), Value::from(event.kind.clone())),
+                ("participants".to_string(), json!(event.participants)),
+            ]);
+            if let Some(region) = &event.region {
+                payload.insert("region".to_string(), Value::from(region.clone()));
+            }
+            index.store_text(collection, &format!("history:{}", event.seq), &event.line(), payload, embedder)?;
+            let at = self.embedded.partition_point(|seq| *seq < event.seq);
+            self.embedded.insert(at, event.seq);
+            embedded += 1;
+        }
+        Ok(embedded)
+    }
+
+    // Events most similar to a description, best first
+    pub fn recall(
+        &self,
+        index: &VectorIndex,
+        collection: &str,
+        description: &str,
+        limit: usize,
+        embedder: &dyn Embedder,
+    ) -> Result<Vec<(&HistoricalEvent, f32)>, HistoryError> {
+        let hits = index.search_text(collection, description, limit, embedder)?;
+        Ok(hits
+            .into_iter()
+            .filter_map(|hit| {
+                let seq = hit.payload.get(HISTORY_SEQ_FIELD).and_then(Value::as_u64)?;
+                Some((self.get(seq)?, hit.score))
+            })
+            .collect())
+    }
+
+    // Publish recorded events on HISTORY_TOPIC; returns the number published
+    pub fn publish(&mut self, events: &mut EventBus) -> usize {
+        let count = self.pending.len();
+        for event in self.pending.drain(..) {
+            events.emit(HISTORY_TOPIC, &event.kind, json!(event));
+        }
+        count
+    }
+
+    pub fn save(&self, db: &mut AgentDb, namespace: &Namespace) -> Result<(), HistoryError> {
+        for event in &self.events {
+            db.put(namespace, HISTORY_TABLE, &format!("{:012}", event.seq), serde_json::to_value(event)?)?;
+        }
+        Ok(())
+    }
+
+    // Rebuild the ledger from agentdb; embedding state is not stored, so `embed` re-embeds
+    // (upserting the same ids)
+    pub fn restore(db: &AgentDb, namespace: &Namespace) -> Result<Self, HistoryError> {
+        let mut ledger = HistoryLedger::new();
+        for (_, value) in db.scan(namespace, HISTORY_TABLE) {
+            let event: HistoricalEvent = serde_json::from_value(value.clone())?;
+            ledger.next_seq = ledger.next_seq.max(event.seq + 1);
+            let at = ledger.events.partition_point(|existing| (existing.day, existing.seq) <= (event.day, event.seq));
+            ledger.events.insert(at, event);
+        }
+        Ok(ledger)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::HashEmbedder;
+    use crate::vector_index::VectorIndexConfig;
+
+    fn ledger() -> HistoryLedger {
+        let mut ledger = HistoryLedger::new();
+        for event in [
+            HistoricalEvent::new(3.0, WAR, "The Iron War begins").region("iron_hills").participant("Thorin"),
+            HistoricalEvent::new(12.5, DEATH, "Thorin falls at the pass").region("iron_hills").participant("Thorin"),
+            HistoricalEvent::new(7.0, DISCOVERY, "Silver found under Oakmere").region("oakmere"),
+            // Backdated lore, written after the fact
+            HistoricalEvent::new(1.0, FOUNDING, "Oakmere is founded").region("oakmere").description("by river folk"),
+        ] {
+            ledger.record(event).unwrap();
+        }
+        ledger
+    }
+
+    fn titles(events: Vec<&HistoricalEvent>) -> Vec<&str> {
+        events.into_iter().map(|event| event.title.as_str()).collect()
+    }
+
+    #[test]
+    fn backdated_events_are_slotted_in_by_day() {
+        let mut ledger = ledger();
+        let days: Vec<(f64, u64)> = ledger.events().iter().map(|event| (event.day, event.seq)).collect();
+        assert_eq!(days, vec![(1.0, 4), (3.0, 1), (7.0, 3), (12.5, 2)]);
+        // Same day keeps recording order
+        let seq = ledger.record(HistoricalEvent::new(3.0, TREATY, "Truce at dawn")).unwrap();
+        assert_eq!(ledger.events()[2].seq, seq);
+        assert!(matches!(ledger.record(HistoricalEvent::new(f64::NAN, WAR, "?")), Err(HistoryError::InvalidDay(_))));
+        assert_eq!(ledger.len(), 5);
+    }
+
+    #[test]
+    fn queries_filter_by_region_days_people_and_kind() {
+        let ledger = ledger();
+        let iron_hills = HistoryQuery::new().region("iron_hills").between(3.0, 10.0);
+        assert_eq!(titles(ledger.query(&iron_hills)), vec!["The Iron War begins"]);
+        assert_eq!(titles(ledger.query(&HistoryQuery::new().involving("Thorin").kind(DEATH))), vec!["Thorin falls at the pass"]);
+        let latest = HistoryQuery::new().since(2.0).newest_first().limit(2);
+        assert_eq!(titles(ledger.query(&latest)), vec!["Thorin falls at the pass", "Silver found under Oakmere"]);
+
+        let chronicle = ledger.chronicle(&HistoryQuery::new().region("oakmere"));
+        assert_eq!(
+            chronicle,
+            "Day 1 [oakmere] founding: Oakmere is founded - by river folk\nDay 7 [oakmere] discovery: Silver found under Oakmere"
+        );
+        assert_eq!(ledger.get(2).unwrap().line(), "Day 12.5 [iron_hills] death: Thorin falls at the pass (Thorin)");
+    }
+
+    #[test]
+    fn deaths_mark_participants_dead() {
+        let ledger = ledger();
+        let facts = HistoryLedger::lore_facts(ledger.get(2).unwrap());
+        assert_eq!(facts.len(), 2);
+        let Fact::Property { subject, key, value } = &facts[0] else { panic!("expected a property") };
+        assert_eq!((subject.as_str(), key.as_str(), value.as_str()), ("Thorin", "status", "dead"));
+        assert!(matches!(&facts[1], Fact::Relation { from, to, .. } if from == "history:2" && to == "iron_hills"));
+        assert!(HistoryLedger::lore_facts(&HistoricalEvent::new(1.0, WAR, "Unplaced skirmish")).is_empty());
+    }
+
+    #[test]
+    fn events_are_embedded_once_and_recalled() {
+        let mut index = VectorIndex::new(VectorIndexConfig {
+            url: String::new(),
+            api_key: String::new(),
+            default_ttl_secs: None,
+            collection_ttl_secs: Default::default(),
+        });
+        index.create_collection("history", 64, "test").unwrap();
+        let embedder = HashEmbedder::new(64);
+        let mut ledger = ledger();
+        assert_eq!(ledger.embed(&mut index, "history", &embedder).unwrap(), 4);
+        ledger.record(HistoricalEvent::new(20.0, DISASTER, "The river floods Oakmere")).unwrap();
+        assert_eq!(ledger.embed(&mut index, "history", &embedder).unwrap(), 1);
+        assert_eq!(index.len("history").unwrap(), 5);
+
+        let recalled = ledger.recall(&index, "history", "silver found", 1, &embedder).unwrap();
+        assert_eq!(recalled[0].0.title, "Silver found under Oakmere");
+    }
+
+    #[test]
+    fn ledgers_publish_and_survive_a_restart() {
+        let mut ledger = ledger();
+        let mut events = EventBus::new(16);
+        let subscription = events.subscribe("world.");
+        assert_eq!(ledger.publish(&mut events), 4);
+        assert_eq!(ledger.publish(&mut events), 0);
+        assert_eq!(events.drain(subscription)[0].source, WAR);
+
+        let (mut db, namespace) = (AgentDb::new(), Namespace::default_namespace());
+        ledger.save(&mut db, &namespace).unwrap();
+        let mut restored = HistoryLedger::restore(&db, &namespace).unwrap();
+        assert_eq!(restored.events(), ledger.events());
+        assert_eq!(restored.record(HistoricalEvent::new(30.0, WAR, "Second Iron War")).unwrap(), 5);
+    }
+}
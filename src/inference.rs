@@ -0,0 +1,477 @@
+// Background inference jobs
+//
+// LLM calls take seconds, so NPC logic submits them to an InferenceTaskPool instead of blocking.
+// Jobs are prioritised (dialogue before ambient chatter), each provider has its own concurrency
+// cap, and jobs can be cancelled individually or by owner, e.g. when the player walks away from a
+// conversation. Queued jobs are dropped on cancel; a job already talking to a provider cannot be
+// interrupted, so its result is discarded instead. Finished jobs are collected by the game loop
+// and published on the event bus as "inference.completed", "inference.failed" or
+// "inference.cancelled".
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+
+use crate::events::EventBus;
+use crate::generation::TextGenerator;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Priority {
+    Ambient,
+    Background,
+    Dialogue,
+    Critical,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct JobId(pub u64);
+
+#[derive(Debug, Clone)]
+pub struct InferenceRequest {
+    pub provider: String,
+    pub prompt: String,
+    pub priority: Priority,
+    // Who the job is for (NPC or conversation id), used for bulk cancellation
+    pub owner: Option<String>,
+    // Free-form label echoed back with the result
+    pub tag: String,
+}
+
+impl InferenceRequest {
+    pub fn new(provider: &str, prompt: &str, priority: Priority) -> Self {
+        InferenceRequest {
+            provider: provider.to_string(),
+            prompt: prompt.to_string(),
+            priority,
+            owner: None,
+            tag: String::new(),
+        }
+    }
+
+    pub fn owned_by(mut self, owner: &str) -> Self {
+        self.owner = Some(owner.to_string());
+        self
+    }
+
+    pub fn tagged(mut self, tag: &str) -> Self {
+        self.tag = tag.to_string();
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JobOutcome {
+    Completed(String),
+    Failed(String),
+    Cancelled,
+}
+
+#[derive(Debug, Clone)]
+pub struct JobResult {
+    pub id: JobId,
+    pub provider: String,
+    pub owner: Option<String>,
+    pub tag: String,
+    pub outcome: JobOutcome,
+    // Time spent queued plus running
+    pub elapsed: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InferenceError {
+    UnknownProvider(String),
+    ShutDown,
+}
+
+impl fmt::Display for InferenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InferenceError::UnknownProvider(name) => write!(f, "no inference provider named '{}'", name),
+            InferenceError::ShutDown => write!(f, "inference pool is shut down"),
+        }
+    }
+}
+
+impl std::error::Error for InferenceError {}
+
+pub type SharedGenerator = Arc<dyn TextGenerator + Send + Sync>;
+
+struct Provider {
+    generator: SharedGenerator,
+    max_concurrent: usize,
+    in_flight: usize,
+}
+
+struct Job {
+    id: JobId,
+    request: InferenceRequest,
+    submitted: Instant,
+}
+
+#[derive(Default)]
+struct State {
+    providers: HashMap<String, Provider>,
+    queue: Vec<Job>,
+    // Running job -> (owner, cancelled)
+    running: HashMap<JobId, (Option<String>, bool)>,
+    finished: Vec<JobResult>,
+    next_id: u64,
+    shutdown: bool,
+}
+
+impl State {
+    // Highest priority, then oldest, among jobs whose provider has spare capacity
+    fn take_next(&mut self) -> Option<Job> {
+        let index = self
+            .queue
+            .iter()
+            .enumerate()
+            .filter(|(_, job)| {
+                self.providers
+                    .get(&job.request.provider)
+                    .is_some_and(|p| p.in_flight < p.max_concurrent)
+            })
+            .max_by(|(_, a), (_, b)| a.request.priority.cmp(&b.request.priority).then(b.id.cmp(&a.id)))
+            .map(|(index, _)| index)?;
+        Some(self.queue.remove(index))
+    }
+}
+
+struct Shared {
+    state: Mutex<State>,
+    wake: Condvar,
+}
+
+pub struct InferenceTaskPool {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl InferenceTaskPool {
+    pub fn new(worker_count: usize) -> Self {
+        let shared = Arc::new(Shared { state: Mutex::new(State::default()), wake: Condvar::new() });
+        let workers = (0..worker_count.max(1))
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || worker_loop(&shared))
+            })
+            .collect();
+        InferenceTaskPool { shared, workers }
+    }
+
+    // Register a provider with its concurrency cap (replaces an existing one of the same name)
+    pub fn add_provider(&self, name: &str, generator: SharedGenerator, max_concurrent: usize) {
+        let mut state = self.shared.state.lock().unwrap();
+        let in_flight = state.providers.get(name).map_or(0, |p| p.in_flight);
+        state.providers.insert(
+            name.to_string(),
+            Provider { generator, max_concurrent: max_concurrent.max(1), in_flight },
+        );
+        self.shared.wake.notify_all();
+    }
+
+    pub fn submit(&self, request: InferenceRequest) -> Result<JobId, InferenceError> {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.shutdown {
+            return Err(InferenceError::ShutDown);
+        }
+        if !state.providers.contains_key(&request.provider) {
+            return Err(InferenceError::UnknownProvider(request.provider));
+        }
+        let id = JobId(state.next_id);
+        state.next_id += 1;
+        state.queue.push(Job { id, request, submitted: Instant::now() });
+        self.shared.wake.notify_one();
+        Ok(id)
+    }
+
+    // Returns false if the job already finished
+    pub fn cancel(&self, id: JobId) -> bool {
+        let mut state = self.shared.state.lock().unwrap();
+        cancel_where(&mut state, |job_id, _| job_id == id) > 0
+    }
+
+    // Cancel every queued and running job for an owner; returns how many were cancelled
+    pub fn cancel_owner(&self, owner: &str) -> usize {
+        let mut state = self.shared.state.lock().unwrap();
+        cancel_where(&mut state, |_, job_owner| job_owner == Some(owner))
+    }
+
+    pub fn queued(&self) -> usize {
+        self.shared.state.lock().unwrap().queue.len()
+    }
+
+    pub fn running(&self) -> usize {
+        self.shared.state.lock().unwrap().running.len()
+    }
+
+    // Results finished since the last call
+    pub fn take_finished(&self) -> Vec<JobResult> {
+        std::mem::take(&mut self.shared.state.lock().unwrap().finished)
+    }
+
+    // Publish finished jobs on the bus; call once per game tick
+    pub fn deliver(&self, events: &mut EventBus) -> usize {
+        let finished = self.take_finished();
+        for result in &finished {
+            let mut payload = json!({
+                "job": result.id.0,
+                "provider": result.provider,
+                "owner": result.owner,
+                "tag": result.tag,
+                "elapsed_ms": result.elapsed.as_millis() as u64,
+            });
+            let topic = match &result.outcome {
+                JobOutcome::Completed(text) => {
+                    payload["text"] = json!(text);
+                    "inference.completed"
+                }
+                JobOutcome::Failed(error) => {
+                    payload["error"] = json!(error);
+                    "inference.failed"
+                }
+                JobOutcome::Cancelled => "inference.cancelled",
+            };
+            events.emit(topic, "inference", payload);
+        }
+        finished.len()
+    }
+
+    // Stop accepting jobs, cancel the queue and wait for running jobs to return
+    pub fn shutdown(mut self) {
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        {
+            let mut state = self.shared.state.lock().unwrap();
+            state.shutdown = true;
+            cancel_where(&mut state, |_, _| true);
+        }
+        self.shared.wake.notify_all();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Drop for InferenceTaskPool {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+fn cancel_where(state: &mut State, matches: impl Fn(JobId, Option<&str>) -> bool) -> usize {
+    let mut cancelled = 0;
+    let (dropped, kept): (Vec<Job>, Vec<Job>) = std::mem::take(&mut state.queue)
+        .into_iter()
+        .partition(|job| matches(job.id, job.request.owner.as_deref()));
+    state.queue = kept;
+    for job in dropped {
+        cancelled += 1;
+        state.finished.push(JobResult {
+            id: job.id,
+            provider: job.request.provider,
+            owner: job.request.owner,
+            tag: job.request.tag,
+            outcome: JobOutcome::Cancelled,
+            elapsed: job.submitted.elapsed(),
+        });
+    }
+    for (id, (owner, flagged)) in state.running.iter_mut() {
+        if !*flagged && matches(*id, owner.as_deref()) {
+            *flagged = true;
+            cancelled += 1;
+        }
+    }
+    cancelled
+}
+
+fn worker_loop(shared: &Shared) {
+    loop {
+        let (job, generator) = {
+            let mut state = shared.state.lock().unwrap();
+            loop {
+                if state.shutdown {
+                    return;
+                }
+                if let Some(job) = state.take_next() {
+                    let provider = state.providers.get_mut(&job.request.provider).expect("provider checked in take_next");
+                    provider.in_flight += 1;
+                    let generator = provider.generator.clone();
+                    state.running.insert(job.id, (job.request.owner.clone(), false));
+                    break (job, generator);
+                }
+                state = shared.wake.wait(state).unwrap();
+            }
+        };
+
+        let outcome = match generator.generate(&job.request.prompt) {
+            Ok(text) => JobOutcome::Completed(text),
+            Err(err) => JobOutcome::Failed(err.to_string()),
+        };
+
+        let mut state = shared.state.lock().unwrap();
+        if let Some(provider) = state.providers.get_mut(&job.request.provider) {
+            provider.in_flight = provider.in_flight.saturating_sub(1);
+        }
+        let cancelled = state.running.remove(&job.id).is_some_and(|(_, cancelled)| cancelled);
+        state.finished.push(JobResult {
+            id: job.id,
+            provider: job.request.provider,
+            owner: job.request.owner,
+            tag: job.request.tag,
+            outcome: if cancelled { JobOutcome::Cancelled } else { outcome },
+            elapsed: job.submitted.elapsed(),
+        });
+        // A provider slot just freed up
+        shared.wake.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Blocks every call until opened and records prompts in the order they ran
+    #[derive(Default)]
+    struct Gate {
+        open: Mutex<bool>,
+        opened: Condvar,
+        prompts: Mutex<Vec<String>>,
+    }
+
+    impl Gate {
+        fn open(&self) {
+            *self.open.lock().unwrap() = true;
+            self.opened.notify_all();
+        }
+
+        fn prompts(&self) -> Vec<String> {
+            self.prompts.lock().unwrap().clone()
+        }
+    }
+
+    impl TextGenerator for Gate {
+        fn generate(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+            let mut open = self.open.lock().unwrap();
+            while !*open {
+                open = self.opened.wait(open).unwrap();
+            }
+            self.prompts.lock().unwrap().push(prompt.to_string());
+            match prompt {
+                "fail" => Err("model overloaded".into()),
+                _ => Ok(prompt.to_uppercase()),
+            }
+        }
+    }
+
+    fn wait_until(what: &str, mut done: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !done() {
+            assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+            thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    // Finished results by job id, collected until `count` have arrived
+    fn collect(pool: &InferenceTaskPool, count: usize) -> HashMap<JobId, JobOutcome> {
+        let mut results = HashMap::new();
+        wait_until("results", || {
+            results.extend(pool.take_finished().into_iter().map(|r| (r.id, r.outcome)));
+            results.len() >= count
+        });
+        results
+    }
+
+    fn pool(gate: &Arc<Gate>, workers: usize, max_concurrent: usize) -> InferenceTaskPool {
+        let pool = InferenceTaskPool::new(workers);
+        pool.add_provider("llm", gate.clone(), max_concurrent);
+        pool
+    }
+
+    #[test]
+    fn higher_priority_jobs_run_first_then_oldest() {
+        let gate = Arc::new(Gate::default());
+        let pool = pool(&gate, 1, 1);
+        pool.submit(InferenceRequest::new("llm", "blocker", Priority::Background)).unwrap();
+        wait_until("blocker to start", || pool.running() == 1);
+        for (prompt, priority) in [
+            ("ambient", Priority::Ambient),
+            ("dialogue-1", Priority::Dialogue),
+            ("critical", Priority::Critical),
+            ("dialogue-2", Priority::Dialogue),
+        ] {
+            pool.submit(InferenceRequest::new("llm", prompt, priority)).unwrap();
+        }
+        assert_eq!(pool.queued(), 4);
+
+        gate.open();
+        collect(&pool, 5);
+        assert_eq!(gate.prompts(), vec!["blocker", "critical", "dialogue-1", "dialogue-2", "ambient"]);
+    }
+
+    #[test]
+    fn provider_concurrency_is_capped() {
+        let gate = Arc::new(Gate::default());
+        let pool = pool(&gate, 3, 2);
+        for prompt in ["a", "b", "c"] {
+            pool.submit(InferenceRequest::new("llm", prompt, Priority::Dialogue)).unwrap();
+        }
+        wait_until("two jobs to start", || pool.running() == 2);
+        thread::sleep(Duration::from_millis(20));
+        assert_eq!((pool.running(), pool.queued()), (2, 1));
+        gate.open();
+        assert_eq!(collect(&pool, 3).len(), 3);
+    }
+
+    #[test]
+    fn cancelled_jobs_are_dropped_or_discarded() {
+        let gate = Arc::new(Gate::default());
+        let pool = pool(&gate, 1, 1);
+        let running = pool.submit(InferenceRequest::new("llm", "greet", Priority::Dialogue).owned_by("npc-1")).unwrap();
+        wait_until("greet to start", || pool.running() == 1);
+        let queued = pool.submit(InferenceRequest::new("llm", "follow-up", Priority::Dialogue).owned_by("npc-1")).unwrap();
+        let other = pool.submit(InferenceRequest::new("llm", "bark", Priority::Ambient).owned_by("npc-2")).unwrap();
+
+        assert_eq!(pool.cancel_owner("npc-1"), 2);
+        assert!(!pool.cancel(queued));
+        gate.open();
+        let results = collect(&pool, 3);
+        assert_eq!(results[&running], JobOutcome::Cancelled);
+        assert_eq!(results[&queued], JobOutcome::Cancelled);
+        assert_eq!(results[&other], JobOutcome::Completed("BARK".to_string()));
+        // The running job still reached the provider; only its result was thrown away
+        assert_eq!(gate.prompts(), vec!["greet", "bark"]);
+    }
+
+    #[test]
+    fn results_are_published_on_the_bus() {
+        let gate = Arc::new(Gate::default());
+        gate.open();
+        let pool = pool(&gate, 1, 1);
+        assert_eq!(
+            pool.submit(InferenceRequest::new("nope", "hi", Priority::Dialogue)).unwrap_err(),
+            InferenceError::UnknownProvider("nope".to_string())
+        );
+        pool.submit(InferenceRequest::new("llm", "hello", Priority::Dialogue).tagged("greeting")).unwrap();
+        wait_until("hello", || pool.shared.state.lock().unwrap().finished.len() == 1);
+        pool.submit(InferenceRequest::new("llm", "fail", Priority::Dialogue)).unwrap();
+        wait_until("fail", || pool.shared.state.lock().unwrap().finished.len() == 2);
+
+        let mut events = EventBus::new(16);
+        let sub = events.subscribe("inference.");
+        assert_eq!(pool.deliver(&mut events), 2);
+        let published = events.drain(sub);
+        assert_eq!(published[0].topic, "inference.completed");
+        assert_eq!(published[0].payload["text"], "HELLO");
+        assert_eq!(published[0].payload["tag"], "greeting");
+        assert_eq!(published[1].topic, "inference.failed");
+        assert_eq!(published[1].payload["error"], "model overloaded");
+        pool.shutdown();
+    }
+}
@@ -0,0 +1,548 @@
+// Leaderboards and match results
+//
+// Scores are submitted per board and season; each player keeps their best score of the season
+// (higher or lower is better, per board). Submissions must carry the player's current session token,
+// checked by the auth layer (auth.rs), and scores outside the board's allowed range are refused.
+// Board names and player ids may not contain '/', which separates them in storage keys. Starting a new
+// season leaves the old one readable for history. Finished matches are stored as MatchResults and
+// may submit every placement to a board at once.
+//
+// Storage goes through the LeaderboardBackend trait so a game can put boards in Postgres, SQLite
+// or Redis; AgentDbBackend keeps them in agentdb, which is what the engine ships with. Backends
+// also find and erase everything stored about one player, so scores and placements are covered by
+// privacy requests (security/privacy.rs).
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::agentdb::{AgentDb, AgentDbError};
+use crate::auth::Authentication;
+use crate::namespace::Namespace;
+
+const ENTRIES_TABLE: &str = "leaderboard_entries";
+const SEASONS_TABLE: &str = "leaderboard_seasons";
+const MATCHES_TABLE: &str = "match_results";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScoreOrder {
+    HigherIsBetter,
+    // Times, strokes
+    LowerIsBetter,
+}
+
+#[derive(Debug, Clone)]
+pub struct BoardConfig {
+    pub name: String,
+    pub order: ScoreOrder,
+    // Scores outside this range are rejected as implausible
+    pub min_score: f64,
+    pub max_score: f64,
+}
+
+impl BoardConfig {
+    pub fn new(name: &str, order: ScoreOrder) -> Self {
+        BoardConfig { name: name.to_string(), order, min_score: f64::MIN, max_score: f64::MAX }
+    }
+
+    pub fn with_range(mut self, min_score: f64, max_score: f64) -> Self {
+        self.min_score = min_score;
+        self.max_score = max_score;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScoreEntry {
+    pub player: String,
+    pub score: f64,
+    // Unix seconds
+    pub submitted_at: u64,
+    pub match_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RankedEntry {
+    // 1-based; tied scores share a rank
+    pub rank: usize,
+    pub entry: ScoreEntry,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Page {
+    pub season: u32,
+    pub entries: Vec<RankedEntry>,
+    pub total: usize,
+    // Offset of the next page, None on the last one
+    pub next_offset: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MatchResult {
+    pub match_id: String,
+    pub finished_at: u64,
+    // Player -> score, in finishing order
+    pub placements: Vec<(String, f64)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Submission {
+    pub player: String,
+    pub score: f64,
+    // Session token issued to the player at login
+    pub token: String,
+    pub match_id: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum LeaderboardError {
+    UnknownBoard(String),
+    InvalidId(String),
+    Unauthenticated(String),
+    InvalidScore { board: String, score: f64 },
+    Backend(String),
+    Storage(AgentDbError),
+    Corrupt(serde_json::Error),
+}
+
+impl fmt::Display for LeaderboardError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LeaderboardError::UnknownBoard(name) => write!(f, "unknown leaderboard '{}'", name),
+            LeaderboardError::InvalidId(id) => write!(f, "'{}' is not a valid board name or player id", id),
+            LeaderboardError::Unauthenticated(player) => write!(f, "score submission for '{}' is not authenticated", player),
+            LeaderboardError::InvalidScore { board, score } => write!(f, "score {} is not valid on '{}'", score, board),
+            LeaderboardError::Backend(err) => write!(f, "leaderboard backend error: {}", err),
+            LeaderboardError::Storage(err) => write!(f, "{}", err),
+            LeaderboardError::Corrupt(err) => write!(f, "stored leaderboard data is invalid: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for LeaderboardError {}
+
+impl From<AgentDbError> for LeaderboardError {
+    fn from(err: AgentDbError) -> Self {
+        LeaderboardError::Storage(err)
+    }
+}
+
+impl From<serde_json::Error> for LeaderboardError {
+    fn from(err: serde_json::Error) -> Self {
+        LeaderboardError::Corrupt(err)
+    }
+}
+
+pub trait LeaderboardBackend {
+    fn entry(&self, board: &str, season: u32, player: &str) -> Result<Option<ScoreEntry>, LeaderboardError>;
+    fn put_entry(&mut self, board: &str, season: u32, entry: &ScoreEntry) -> Result<(), LeaderboardError>;
+    fn entries(&self, board: &str, season: u32) -> Result<Vec<ScoreEntry>, LeaderboardError>;
+    fn season(&self, board: &str) -> Result<u32, LeaderboardError>;
+    fn set_season(&mut self, board: &str, season: u32) -> Result<(), LeaderboardError>;
+    fn put_match(&mut self, result: &MatchResult) -> Result<(), LeaderboardError>;
+    fn get_match(&self, match_id: &str) -> Result<Option<MatchResult>, LeaderboardError>;
+    // Every season entry of the player, as (board, season, entry)
+    fn player_entries(&self, player: &str) -> Result<Vec<(String, u32, ScoreEntry)>, LeaderboardError>;
+    // Every match result the player placed in
+    fn player_matches(&self, player: &str) -> Result<Vec<MatchResult>, LeaderboardError>;
+    // Remove the player's entries and their placements from match results; returns the number of
+    // entries and placements removed
+    fn delete_player(&mut self, player: &str) -> Result<usize, LeaderboardError>;
+}
+
+pub struct AgentDbBackend {
+    pub db: AgentDb,
+    pub namespace: Namespace,
+}
+
+impl AgentDbBackend {
+    pub fn new(db: AgentDb, namespace: Namespace) -> Self {
+        AgentDbBackend { db, namespace }
+    }
+}
+
+fn entry_prefix(board: &str, season: u32) -> String {
+    format!("{}/{}/", board, season)
+}
+
+impl LeaderboardBackend for AgentDbBackend {
+    fn entry(&self, board: &str, season: u32, player: &str) -> Result<Option<ScoreEntry>, LeaderboardError> {
+        let key = format!("{}{}", entry_prefix(board, season), player);
+        match self.db.get(&self.namespace, ENTRIES_TABLE, &key) {
+            Some(value) => Ok(Some(serde_json::from_value(value.clone())?)),
+            None => Ok(None),
+        }
+    }
+
+    fn put_entry(&mut self, board: &str, season: u32, entry: &ScoreEntry) -> Result<(), LeaderboardError> {
+        let key = format!("{}{}", entry_prefix(board, season), entry.player);
+        self.db.put(&self.namespace, ENTRIES_TABLE, &key, serde_json::to_value(entry)?)?;
+        Ok(())
+    }
+
+    fn entries(&self, board: &str, season: u32) -> Result<Vec<ScoreEntry>, LeaderboardError> {
+        let prefix = entry_prefix(board, season);
+        self.db
+            .scan_prefix(&self.namespace, ENTRIES_TABLE, &prefix)
+            .map(|(_, value)| Ok(serde_json::from_value(value.clone())?))
+            .collect()
+    }
+
+    fn season(&self, board: &str) -> Result<u32, LeaderboardError> {
+        match self.db.get(&self.namespace, SEASONS_TABLE, board) {
+            Some(value) => Ok(serde_json::from_value(value.clone())?),
+            None => Ok(1),
+        }
+    }
+
+    fn set_season(&mut self, board: &str, season: u32) -> Result<(), LeaderboardError> {
+        self.db.put(&self.namespace, SEASONS_TABLE, board, serde_json::to_value(season)?)?;
+        Ok(())
+    }
+
+    fn put_match(&mut self, result: &MatchResult) -> Result<(), LeaderboardError> {
+        self.db.put(&self.namespace, MATCHES_TABLE, &result.match_id, serde_json::to_value(result)?)?;
+        Ok(())
+    }
+
+    fn get_match(&self, match_id: &str) -> Result<Option<MatchResult>, LeaderboardError> {
+        match self.db.get(&self.namespace, MATCHES_TABLE, match_id) {
+            Some(value) => Ok(Some(serde_json::from_value(value.clone())?)),
+            None => Ok(None),
+        }
+    }
+
+    fn player_entries(&self, player: &str) -> Result<Vec<(String, u32, ScoreEntry)>, LeaderboardError> {
+        let mut found = Vec::new();
+        for (key, value) in self.db.scan(&self.namespace, ENTRIES_TABLE) {
+            let entry: ScoreEntry = serde_json::from_value(value.clone())?;
+            if entry.player != player {
+                continue;
+            }
+            // Keys are "board/season/player"
+            let scope = key.strip_suffix(player).unwrap_or(key).trim_end_matches('/');
+            let Some((board, season)) = scope.rsplit_once('/') else { continue };
+            let season = season.parse().map_err(|_| LeaderboardError::Backend(format!("malformed entry key '{}'", key)))?;
+            found.push((board.to_string(), season, entry));
+        }
+        Ok(found)
+    }
+
+    fn player_matches(&self, player: &str) -> Result<Vec<MatchResult>, LeaderboardError> {
+        let mut found = Vec::new();
+        for (_, value) in self.db.scan(&self.namespace, MATCHES_TABLE) {
+            let result: MatchResult = serde_json::from_value(value.clone())?;
+            if result.placements.iter().any(|(placed, _)| placed == player) {
+                found.push(result);
+            }
+        }
+        Ok(found)
+    }
+
+    fn delete_player(&mut self, player: &str) -> Result<usize, LeaderboardError> {
+        let entries: Vec<String> = self
+            .db
+            .scan(&self.namespace, ENTRIES_TABLE)
+            .filter(|(_, value)| value.get("player").and_then(serde_json::Value::as_str) == Some(player))
+            .map(|(key, _)| key.clone())
+            .collect();
+        let mut removed = 0;
+        for key in entries {
+            removed += usize::from(self.db.delete(&self.namespace, ENTRIES_TABLE, &key).is_some());
+        }
+        for mut result in self.player_matches(player)? {
+            let before = result.placements.len();
+            result.placements.retain(|(placed, _)| placed != player);
+            removed += before - result.placements.len();
+            self.put_match(&result)?;
+        }
+        Ok(removed)
+    }
+}
+
+pub struct Leaderboards<B: LeaderboardBackend> {
+    backend: B,
+    boards: HashMap<String, BoardConfig>,
+}
+
+impl<B: LeaderboardBackend> Leaderboards<B> {
+    pub fn new(backend: B) -> Self {
+        Leaderboards { backend, boards: HashMap::new() }
+    }
+
+    pub fn add_board(&mut self, config: BoardConfig) -> Result<(), LeaderboardError> {
+        check_id(&config.name)?;
+        self.boards.insert(config.name.clone(), config);
+        Ok(())
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
+    fn board(&self, name: &str) -> Result<&BoardConfig, LeaderboardError> {
+        self.boards.get(name).ok_or_else(|| LeaderboardError::UnknownBoard(name.to_string()))
+    }
+
+    pub fn current_season(&self, board: &str) -> Result<u32, LeaderboardError> {
+        self.board(board)?;
+        self.backend.season(board)
+    }
+
+    // Authenticate and record a score; returns whether it became the player's season best
+    pub fn submit(
+        &mut self,
+        auth: &Authentication,
+        board: &str,
+        submission: &Submission,
+        now: u64,
+    ) -> Result<bool, LeaderboardError> {
+        if !auth.validate(&submission.player, &submission.token, now) {
+            return Err(LeaderboardError::Unauthenticated(submission.player.clone()));
+        }
+        self.record(board, &submission.player, submission.score, submission.match_id.clone(), now)
+    }
+
+    fn record(&mut self, board: &str, player: &str, score: f64, match_id: Option<String>, now: u64) -> Result<bool, LeaderboardError> {
+        let config = self.board(board)?;
+        check_id(player)?;
+        if !score.is_finite() || score < config.min_score || score > config.max_score {
+            return Err(LeaderboardError::InvalidScore { board: board.to_string(), score });
+        }
+        let order = config.order;
+        let season = self.backend.season(board)?;
+        let improved = match self.backend.entry(board, season, player)? {
+            Some(previous) => better(order, score, previous.score),
+            None => true,
+        };
+        if improved {
+            let entry = ScoreEntry { player: player.to_string(), score, submitted_at: now, match_id };
+            self.backend.put_entry(board, season, &entry)?;
+        }
+        Ok(improved)
+    }
+
+    // Store a finished match and post every placement to `board`. Match results come from the
+    // authoritative server, so they are not checked against player tokens.
+    pub fn record_match(&mut self, result: &MatchResult, board: Option<&str>) -> Result<(), LeaderboardError> {
+        for (player, _) in &result.placements {
+            check_id(player)?;
+        }
+        self.backend.put_match(result)?;
+        if let Some(board) = board {
+            for (player, score) in &result.placements {
+                self.record(board, player, *score, Some(result.match_id.clone()), result.finished_at)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn match_result(&self, match_id: &str) -> Result<Option<MatchResult>, LeaderboardError> {
+        self.backend.get_match(match_id)
+    }
+
+    // Close the current season; earlier seasons stay readable
+    pub fn new_season(&mut self, board: &str) -> Result<u32, LeaderboardError> {
+        let season = self.current_season(board)? + 1;
+        self.backend.set_season(board, season)?;
+        Ok(season)
+    }
+
+    // Ranked entries of a season (the current one if None)
+    pub fn page(&self, board: &str, season: Option<u32>, offset: usize, limit: usize) -> Result<Page, LeaderboardError> {
+        let order = self.board(board)?.order;
+        let season = match season {
+            Some(season) => season,
+            None => self.backend.season(board)?,
+        };
+        let ranked = rank(order, self.backend.entries(board, season)?);
+        let total = ranked.len();
+        let entries: Vec<RankedEntry> = ranked.into_iter().skip(offset).take(limit).collect();
+        let end = offset + entries.len();
+        Ok(Page { season, entries, total, next_offset: if end < total { Some(end) } else { None } })
+    }
+
+    pub fn rank_of(&self, board: &str, player: &str) -> Result<Option<RankedEntry>, LeaderboardError> {
+        let order = self.board(board)?.order;
+        let season = self.backend.season(board)?;
+        Ok(rank(order, self.backend.entries(board, season)?).into_iter().find(|r| r.entry.player == player))
+    }
+}
+
+// Board names and player ids are joined with '/' into storage keys, so they may not contain it
+fn check_id(id: &str) -> Result<(), LeaderboardError> {
+    if id.is_empty() || id.contains('/') {
+        return Err(LeaderboardError::InvalidId(id.to_string()));
+    }
+    Ok(())
+}
+
+fn better(order: ScoreOrder, a: f64, b: f64) -> bool {
+    match order {
+        ScoreOrder::HigherIsBetter => a > b,
+        ScoreOrder::LowerIsBetter => a < b,
+    }
+}
+
+// Best first; earlier submissions win ties in position but share the rank
+fn rank(order: ScoreOrder, mut entries: Vec<ScoreEntry>) -> Vec<RankedEntry> {
+    entries.sort_by(|a, b| {
+        let by_score = match order {
+            ScoreOrder::HigherIsBetter => b.score.total_cmp(&a.score),
+            ScoreOrder::LowerIsBetter => a.score.total_cmp(&b.score),
+        };
+        by_score.then(a.submitted_at.cmp(&b.submitted_at)).then_with(|| a.player.cmp(&b.player))
+    });
+    let mut ranked: Vec<RankedEntry> = Vec::with_capacity(entries.len());
+    for (i, entry) in entries.into_iter().enumerate() {
+        let rank = match ranked.last() {
+            Some(previous) if previous.entry.score == entry.score => previous.rank,
+            _ => i + 1,
+        };
+        ranked.push(RankedEntry { rank, entry });
+    }
+    ranked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::{AuthenticationConfig, Credentials};
+
+    fn auth() -> Authentication {
+        let credentials = Credentials { client_id: "arcadia".to_string(), client_secret: "secret".to_string() };
+        let config = AuthenticationConfig { provider: "steam".to_string(), credentials };
+        let mut auth = Authentication::new(config).with_session_ttl(100);
+        auth.begin_session("p1", "t1", 0);
+        auth
+    }
+
+    fn boards() -> Leaderboards<AgentDbBackend> {
+        let mut boards = Leaderboards::new(AgentDbBackend::new(AgentDb::new(), Namespace::new("arena").unwrap()));
+        boards.add_board(BoardConfig::new("kills", ScoreOrder::HigherIsBetter).with_range(0.0, 500.0)).unwrap();
+        boards.add_board(BoardConfig::new("laps", ScoreOrder::LowerIsBetter)).unwrap();
+        boards
+    }
+
+    fn submission(player: &str, score: f64, token: &str) -> Submission {
+        Submission { player: player.to_string(), score, token: token.to_string(), match_id: None }
+    }
+
+    fn entry(player: &str, score: f64, submitted_at: u64) -> ScoreEntry {
+        ScoreEntry { player: player.to_string(), score, submitted_at, match_id: None }
+    }
+
+    #[test]
+    fn submissions_need_a_valid_unexpired_session() {
+        let (auth, mut boards) = (auth(), boards());
+        // Wrong token, another player's token, and an expired session
+        for (player, token, now) in [("p1", "wrong", 5), ("p2", "t1", 5), ("p1", "t1", 100)] {
+            let refused = boards.submit(&auth, "kills", &submission(player, 10.0, token), now);
+            assert!(matches!(refused, Err(LeaderboardError::Unauthenticated(_))));
+        }
+        assert!(boards.page("kills", None, 0, 10).unwrap().entries.is_empty());
+        assert!(boards.submit(&auth, "kills", &submission("p1", 10.0, "t1"), 5).unwrap());
+        let unknown = boards.submit(&auth, "darts", &submission("p1", 10.0, "t1"), 5);
+        assert!(matches!(unknown, Err(LeaderboardError::UnknownBoard(_))));
+    }
+
+    #[test]
+    fn scores_outside_the_board_range_are_refused() {
+        let (auth, mut boards) = (auth(), boards());
+        for score in [-1.0, 500.5, f64::NAN, f64::INFINITY] {
+            let refused = boards.submit(&auth, "kills", &submission("p1", score, "t1"), 5);
+            assert!(matches!(refused, Err(LeaderboardError::InvalidScore { .. })), "{} was accepted", score);
+        }
+        assert!(boards.submit(&auth, "kills", &submission("p1", 500.0, "t1"), 5).unwrap());
+        assert_eq!(boards.page("kills", None, 0, 10).unwrap().total, 1);
+    }
+
+    #[test]
+    fn each_board_keeps_the_best_score_in_its_own_order() {
+        let (auth, mut boards) = (auth(), boards());
+        for (score, improved) in [(30.0, true), (20.0, false), (40.0, true)] {
+            assert_eq!(boards.submit(&auth, "kills", &submission("p1", score, "t1"), 5).unwrap(), improved);
+        }
+        for (score, improved) in [(90.5, true), (95.0, false), (88.0, true)] {
+            assert_eq!(boards.submit(&auth, "laps", &submission("p1", score, "t1"), 5).unwrap(), improved);
+        }
+        assert_eq!(boards.rank_of("kills", "p1").unwrap().unwrap().entry.score, 40.0);
+        assert_eq!(boards.rank_of("laps", "p1").unwrap().unwrap().entry.score, 88.0);
+    }
+
+    #[test]
+    fn a_new_season_starts_empty_and_keeps_the_old_one_readable() {
+        let (auth, mut boards) = (auth(), boards());
+        boards.submit(&auth, "kills", &submission("p1", 40.0, "t1"), 5).unwrap();
+        assert_eq!(boards.new_season("kills").unwrap(), 2);
+        assert_eq!(boards.current_season("kills").unwrap(), 2);
+        assert_eq!(boards.current_season("laps").unwrap(), 1);
+        assert!(boards.rank_of("kills", "p1").unwrap().is_none());
+        // A lower score is a season best again
+        assert!(boards.submit(&auth, "kills", &submission("p1", 10.0, "t1"), 6).unwrap());
+        assert_eq!(boards.page("kills", Some(1), 0, 10).unwrap().entries[0].entry.score, 40.0);
+        assert_eq!(boards.page("kills", None, 0, 10).unwrap().season, 2);
+    }
+
+    #[test]
+    fn pages_report_the_next_offset_until_the_last_one() {
+        let mut boards = boards();
+        let placements = (0..5).map(|i| (format!("p{}", i), f64::from(i) * 10.0)).collect();
+        boards.record_match(&MatchResult { match_id: "m1".to_string(), finished_at: 5, placements }, Some("kills")).unwrap();
+
+        let first = boards.page("kills", None, 0, 2).unwrap();
+        assert_eq!(first.total, 5);
+        assert_eq!(first.entries.iter().map(|r| r.entry.player.as_str()).collect::<Vec<_>>(), ["p4", "p3"]);
+        assert_eq!(first.next_offset, Some(2));
+        let last = boards.page("kills", None, 4, 2).unwrap();
+        assert_eq!(last.entries.len(), 1);
+        assert_eq!(last.entries[0].rank, 5);
+        assert_eq!(last.next_offset, None);
+        assert!(boards.page("kills", None, 10, 2).unwrap().entries.is_empty());
+    }
+
+    #[test]
+    fn tied_scores_share_a_rank_and_earlier_submissions_come_first() {
+        let ranked = rank(
+            ScoreOrder::HigherIsBetter,
+            vec![entry("late", 50.0, 9), entry("low", 10.0, 1), entry("early", 50.0, 3), entry("best", 70.0, 5)],
+        );
+        let ranks: Vec<(&str, usize)> = ranked.iter().map(|r| (r.entry.player.as_str(), r.rank)).collect();
+        assert_eq!(ranks, [("best", 1), ("early", 2), ("late", 2), ("low", 4)]);
+
+        let ranked = rank(ScoreOrder::LowerIsBetter, vec![entry("b", 3.0, 1), entry("a", 3.0, 1), entry("c", 1.0, 2)]);
+        let ranks: Vec<(&str, usize)> = ranked.iter().map(|r| (r.entry.player.as_str(), r.rank)).collect();
+        assert_eq!(ranks, [("c", 1), ("a", 2), ("b", 2)]);
+    }
+
+    #[test]
+    fn rank_of_finds_the_player_in_the_current_season() {
+        let mut boards = boards();
+        let placements = vec![("p1".to_string(), 30.0), ("p2".to_string(), 30.0), ("p3".to_string(), 50.0)];
+        boards.record_match(&MatchResult { match_id: "m1".to_string(), finished_at: 5, placements }, Some("kills")).unwrap();
+        let ranked = boards.rank_of("kills", "p2").unwrap().unwrap();
+        assert_eq!(ranked.rank, 2);
+        assert_eq!(ranked.entry.match_id.as_deref(), Some("m1"));
+        assert!(boards.rank_of("kills", "p9").unwrap().is_none());
+    }
+
+    #[test]
+    fn ids_containing_the_key_separator_are_rejected() {
+        let mut auth = auth();
+        let mut boards = boards();
+        let refused = boards.add_board(BoardConfig::new("a/1", ScoreOrder::HigherIsBetter));
+        assert!(matches!(refused, Err(LeaderboardError::InvalidId(_))));
+        auth.begin_session("p/2", "t2", 0);
+        let refused = boards.submit(&auth, "kills", &submission("p/2", 10.0, "t2"), 5);
+        assert!(matches!(refused, Err(LeaderboardError::InvalidId(_))));
+        let placements = vec![("p1".to_string(), 1.0), ("x/y".to_string(), 2.0)];
+        let refused = boards.record_match(&MatchResult { match_id: "m1".to_string(), finished_at: 5, placements }, None);
+        assert!(matches!(refused, Err(LeaderboardError::InvalidId(_))));
+        assert!(boards.match_result("m1").unwrap().is_none());
+    }
+}
@@ -0,0 +1,410 @@
+// Entity lifecycle
+//
+// Every subsystem keeps per-entity state in its own map (blackboards, perception memories, mood
+// estimates, vector memories, world components). Instead of each caller remembering to clean all
+// of them up, subsystems register with a LifecycleRegistry and are told when an entity spawns,
+// despawns or is persisted. On despawn a subsystem releases the entity's state and may hand back
+// a JSON snapshot, which the registry archives; spawning the same id again hands the snapshot back
+// so the entity comes back as it left.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::ai::blackboard::{to_json, Blackboards};
+use crate::ai::coordination::BlackboardValue;
+use crate::emotion::{EmotionAdaptiveExperiences, MoodVector};
+use crate::perception::PerceptionSystem;
+use crate::vector_index::{unix_now, VectorIndex};
+use crate::world::{Entity, GameWorld};
+
+pub trait EntityLifecycle {
+    // Key of this subsystem's snapshot in an archive
+    fn subsystem(&self) -> &str;
+
+    // `restored` is this subsystem's snapshot from when the entity was last archived
+    fn spawned(&mut self, _entity: &str, _restored: Option<&Value>) {}
+
+    // Release the entity's state, returning a snapshot worth archiving
+    fn despawned(&mut self, entity: &str) -> Option<Value>;
+
+    // Snapshot the entity's state without releasing it
+    fn persist(&self, _entity: &str) -> Option<Value> {
+        None
+    }
+}
+
+pub type SharedLifecycle = Arc<Mutex<dyn EntityLifecycle + Send>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DespawnMode {
+    // Keep the snapshots so the entity can be restored later
+    Archive,
+    // Drop everything (the entity is gone for good)
+    Release,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EntityArchive {
+    pub entity: String,
+    // Unix seconds
+    pub archived_at: u64,
+    // Subsystem name -> snapshot
+    pub state: BTreeMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LifecycleReport {
+    pub entity: String,
+    // Subsystems that were notified
+    pub notified: usize,
+    // Subsystems that produced (or received) a snapshot
+    pub snapshots: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct LifecycleRegistry {
+    hooks: Vec<SharedLifecycle>,
+    archive: HashMap<String, EntityArchive>,
+}
+
+impl LifecycleRegistry {
+    pub fn new() -> Self {
+        LifecycleRegistry::default()
+    }
+
+    pub fn register(&mut self, hook: SharedLifecycle) {
+        self.hooks.push(hook);
+    }
+
+    pub fn subsystems(&self) -> Vec<String> {
+        self.hooks.iter().map(|h| h.lock().unwrap().subsystem().to_string()).collect()
+    }
+
+    // Notify every subsystem; an archived entity gets its snapshots back and leaves the archive
+    pub fn spawn(&mut self, entity: &str) -> LifecycleReport {
+        let archived = self.archive.remove(entity);
+        let mut report = LifecycleReport { entity: entity.to_string(), ..Default::default() };
+        for hook in &self.hooks {
+            let mut hook = hook.lock().unwrap();
+            let restored = archived.as_ref().and_then(|a| a.state.get(hook.subsystem()));
+            if restored.is_some() {
+                report.snapshots.push(hook.subsystem().to_string());
+            }
+            hook.spawned(entity, restored);
+            report.notified += 1;
+        }
+        report
+    }
+
+    pub fn despawn(&mut self, entity: &str, mode: DespawnMode) -> LifecycleReport {
+        let mut report = LifecycleReport { entity: entity.to_string(), ..Default::default() };
+        let mut state = BTreeMap::new();
+        for hook in &self.hooks {
+            let mut hook = hook.lock().unwrap();
+            if let Some(snapshot) = hook.despawned(entity) {
+                report.snapshots.push(hook.subsystem().to_string());
+                state.insert(hook.subsystem().to_string(), snapshot);
+            }
+            report.notified += 1;
+        }
+        match mode {
+            DespawnMode::Archive => {
+                self.archive.insert(
+                    entity.to_string(),
+                    EntityArchive { entity: entity.to_string(), archived_at: unix_now(), state },
+                );
+            }
+            DespawnMode::Release => {
+                self.archive.remove(entity);
+            }
+        }
+        report
+    }
+
+    // Snapshot a live entity across subsystems, e.g. for a save game
+    pub fn persist(&self, entity: &str) -> EntityArchive {
+        let state = self
+            .hooks
+            .iter()
+            .filter_map(|hook| {
+                let hook = hook.lock().unwrap();
+                hook.persist(entity).map(|snapshot| (hook.subsystem().to_string(), snapshot))
+            })
+            .collect();
+        EntityArchive { entity: entity.to_string(), archived_at: unix_now(), state }
+    }
+
+    pub fn archived(&self, entity: &str) -> Option<&EntityArchive> {
+        self.archive.get(entity)
+    }
+
+    // Load an archive (e.g. from a save game) so the next spawn restores it
+    pub fn restore_archive(&mut self, archive: EntityArchive) {
+        self.archive.insert(archive.entity.clone(), archive);
+    }
+
+    pub fn forget(&mut self, entity: &str) -> Option<EntityArchive> {
+        self.archive.remove(entity)
+    }
+}
+
+impl EntityLifecycle for Blackboards {
+    fn subsystem(&self) -> &str {
+        "blackboard"
+    }
+
+    fn spawned(&mut self, entity: &str, restored: Option<&Value>) {
+        let board = self.entity(entity);
+        if let Some(Value::Object(entries)) = restored {
+            for (key, value) in entries {
+                if let Some(value) = from_json(value) {
+                    board.set(key, value, "lifecycle");
+                }
+            }
+        }
+    }
+
+    fn despawned(&mut self, entity: &str) -> Option<Value> {
+        let board = self.remove(entity)?;
+        Some(board_json(board.entries()))
+    }
+
+    fn persist(&self, entity: &str) -> Option<Value> {
+        self.get(entity).map(|board| board_json(board.entries()))
+    }
+}
+
+fn board_json<'a>(entries: impl Iterator<Item = (&'a String, &'a BlackboardValue)>) -> Value {
+    Value::Object(entries.map(|(key, value)| (key.clone(), to_json(value))).collect())
+}
+
+// Inverse of blackboard::to_json; entity references come back as text
+fn from_json(value: &Value) -> Option<BlackboardValue> {
+    match value {
+        Value::Bool(v) => Some(BlackboardValue::Bool(*v)),
+        Value::Number(n) => match n.as_i64() {
+            Some(v) => Some(BlackboardValue::Int(v)),
+            None => n.as_f64().map(|v| BlackboardValue::Float(v as f32)),
+        },
+        Value::String(v) => Some(BlackboardValue::Text(v.clone())),
+        Value::Array(items) if items.len() == 3 => {
+            let mut position = [0.0f32; 3];
+            for (slot, item) in position.iter_mut().zip(items) {
+                *slot = item.as_f64()? as f32;
+            }
+            Some(BlackboardValue::Position(position))
+        }
+        _ => None,
+    }
+}
+
+impl EntityLifecycle for PerceptionSystem {
+    fn subsystem(&self) -> &str {
+        "perception"
+    }
+
+    // Perceivers are configured by the caller on spawn; memories are not worth keeping
+    fn despawned(&mut self, entity: &str) -> Option<Value> {
+        self.remove_perceiver(entity);
+        None
+    }
+}
+
+impl EntityLifecycle for EmotionAdaptiveExperiences {
+    fn subsystem(&self) -> &str {
+        "emotion"
+    }
+
+    fn spawned(&mut self, entity: &str, restored: Option<&Value>) {
+        let number = |key: &str| restored.and_then(|r| r.get(key)).and_then(Value::as_f64).map(|v| v as f32);
+        if let (Some(tension), Some(valence), Some(energy)) = (number("tension"), number("valence"), number("energy")) {
+            self.set_player_mood(entity, MoodVector::new(tension, valence, energy));
+        }
+    }
+
+    fn despawned(&mut self, entity: &str) -> Option<Value> {
+        let snapshot = self.persist(entity);
+        self.forget_player(entity);
+        snapshot
+    }
+
+    fn persist(&self, entity: &str) -> Option<Value> {
+        let mood = self.player_mood(entity)?;
+        Some(json!({ "tension": mood.tension, "valence": mood.valence, "energy": mood.energy }))
+    }
+}
+
+impl EntityLifecycle for GameWorld {
+    fn subsystem(&self) -> &str {
+        "world"
+    }
+
+    fn spawned(&mut self, entity: &str, restored: Option<&Value>) {
+        if let Some(restored) = restored.and_then(|r| serde_json::from_value::<Entity>(r.clone()).ok()) {
+            self.spawn(entity, restored);
+        }
+    }
+
+    fn despawned(&mut self, entity: &str) -> Option<Value> {
+        self.despawn(entity).and_then(|e| serde_json::to_value(e).ok())
+    }
+
+    fn persist(&self, entity: &str) -> Option<Value> {
+        self.entity(entity).and_then(|e| serde_json::to_value(e).ok())
+    }
+}
+
+// Releases an entity's vector memories: points in `collection` whose payload `field` names it.
+// Embeddings are not archived; they can be rebuilt from the stored text.
+pub struct VectorMemoryLifecycle {
+    pub index: Arc<Mutex<VectorIndex>>,
+    pub collection: String,
+    pub field: String,
+}
+
+impl EntityLifecycle for VectorMemoryLifecycle {
+    fn subsystem(&self) -> &str {
+        "vector_memory"
+    }
+
+    fn despawned(&mut self, entity: &str) -> Option<Value> {
+        let mut index = self.index.lock().unwrap();
+        let ids: Vec<String> = index
+            .collection(&self.collection)
+            .ok()?
+            .points
+            .values()
+            .filter(|p| p.payload.get(&self.field).and_then(Value::as_str) == Some(entity))
+            .map(|p| p.id.clone())
+            .collect();
+        if !ids.is_empty() {
+            let _ = index.delete(&self.collection, &ids);
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector_index::{VectorIndexConfig, VectorPoint};
+
+    fn shared<T: EntityLifecycle + Send + 'static>(hook: T) -> Arc<Mutex<T>> {
+        Arc::new(Mutex::new(hook))
+    }
+
+    fn registry(hooks: Vec<SharedLifecycle>) -> LifecycleRegistry {
+        let mut registry = LifecycleRegistry::new();
+        for hook in hooks {
+            registry.register(hook);
+        }
+        registry
+    }
+
+    #[test]
+    fn archived_entities_come_back_as_they_left() {
+        let boards = shared(Blackboards::new());
+        let moods = shared(EmotionAdaptiveExperiences::new());
+        let mut registry = registry(vec![boards.clone(), moods.clone(), shared(PerceptionSystem::new())]);
+        assert_eq!(registry.subsystems(), vec!["blackboard", "emotion", "perception"]);
+
+        {
+            let mut boards = boards.lock().unwrap();
+            let board = boards.entity("npc-1");
+            board.set("alert", BlackboardValue::Bool(true), "test");
+            board.set("gold", BlackboardValue::Int(12), "test");
+            board.set("post", BlackboardValue::Position([1.0, 2.0, 3.0]), "test");
+        }
+        moods.lock().unwrap().set_player_mood("npc-1", MoodVector::new(0.5, -0.25, 0.75));
+
+        let report = registry.despawn("npc-1", DespawnMode::Archive);
+        assert_eq!((report.notified, report.snapshots), (3, vec!["blackboard".to_string(), "emotion".to_string()]));
+        assert!(boards.lock().unwrap().get("npc-1").is_none());
+        assert!(moods.lock().unwrap().player_mood("npc-1").is_none());
+        assert_eq!(registry.archived("npc-1").unwrap().state.len(), 2);
+
+        let report = registry.spawn("npc-1");
+        assert_eq!(report.snapshots.len(), 2);
+        assert!(registry.archived("npc-1").is_none());
+        let boards = boards.lock().unwrap();
+        let board = boards.get("npc-1").unwrap();
+        assert_eq!(board.get_bool("alert"), Some(true));
+        assert_eq!(board.get_int("gold"), Some(12));
+        assert_eq!(board.get_position("post"), Some([1.0, 2.0, 3.0]));
+        let mood = moods.lock().unwrap().player_mood("npc-1").unwrap();
+        assert_eq!((mood.tension, mood.valence, mood.energy), (0.5, -0.25, 0.75));
+    }
+
+    #[test]
+    fn release_drops_state_and_any_old_archive() {
+        let world = shared(GameWorld::new());
+        let mut registry = registry(vec![world.clone()]);
+        world.lock().unwrap().spawn("crate-7", Entity::new("crate"));
+        registry.despawn("crate-7", DespawnMode::Archive);
+        assert!(registry.archived("crate-7").is_some());
+
+        registry.spawn("crate-7");
+        assert_eq!(world.lock().unwrap().entity("crate-7").unwrap().kind, "crate");
+        registry.despawn("crate-7", DespawnMode::Release);
+        assert!(registry.archived("crate-7").is_none());
+        assert!(registry.spawn("crate-7").snapshots.is_empty());
+        assert!(world.lock().unwrap().entity("crate-7").is_none());
+    }
+
+    #[test]
+    fn persisted_archives_can_be_reloaded() {
+        let world = shared(GameWorld::new());
+        let mut registry = registry(vec![world.clone()]);
+        world.lock().unwrap().spawn("hero", Entity::new("player"));
+        let save = registry.persist("hero");
+        // Persisting leaves the live entity alone
+        assert!(world.lock().unwrap().entity("hero").is_some());
+
+        world.lock().unwrap().despawn("hero");
+        let json = serde_json::to_string(&save).unwrap();
+        registry.restore_archive(serde_json::from_str(&json).unwrap());
+        registry.spawn("hero");
+        assert_eq!(world.lock().unwrap().entity("hero").unwrap().kind, "player");
+
+        registry.restore_archive(save);
+        assert_eq!(registry.forget("hero").unwrap().entity, "hero");
+        assert!(registry.forget("hero").is_none());
+    }
+
+    #[test]
+    fn snapshot_values_convert_back_to_blackboard_values() {
+        assert_eq!(from_json(&json!(3)), Some(BlackboardValue::Int(3)));
+        assert_eq!(from_json(&json!(0.5)), Some(BlackboardValue::Float(0.5)));
+        assert_eq!(from_json(&json!("gate")), Some(BlackboardValue::Text("gate".to_string())));
+        assert_eq!(from_json(&json!([1, 2, 3])), Some(BlackboardValue::Position([1.0, 2.0, 3.0])));
+        assert_eq!(from_json(&json!([1, "x", 3])), None);
+        assert_eq!(from_json(&json!({ "nested": true })), None);
+    }
+
+    #[test]
+    fn vector_memories_of_the_entity_are_deleted() {
+        let mut index = VectorIndex::new(VectorIndexConfig {
+            url: String::new(),
+            api_key: String::new(),
+            default_ttl_secs: None,
+            collection_ttl_secs: Default::default(),
+        });
+        index.create_collection("memories", 2, "test").unwrap();
+        for (id, npc) in [("m1", "smith"), ("m2", "baker"), ("m3", "smith")] {
+            let mut point = VectorPoint::new(id, vec![1.0, 0.0]);
+            point.payload.insert("npc".to_string(), json!(npc));
+            index.upsert("memories", point).unwrap();
+        }
+        let index = Arc::new(Mutex::new(index));
+        let memories = VectorMemoryLifecycle { index: index.clone(), collection: "memories".to_string(), field: "npc".to_string() };
+        let mut registry = registry(vec![shared(memories)]);
+
+        assert!(registry.despawn("smith", DespawnMode::Archive).snapshots.is_empty());
+        let index = index.lock().unwrap();
+        let left: Vec<&String> = index.collection("memories").unwrap().points.keys().collect();
+        assert_eq!(left, vec!["m2"]);
+    }
+}
@@ -0,0 +1,526 @@
+// LLM provider routing
+//
+// Dialogue, summaries and content generation all talk to a TextGenerator, so none of them should
+// care which vendor answers. A ProviderRouter holds several chat providers (OpenAI, Anthropic, a
+// local model, a mock) and is itself a TextGenerator. For each request it ranks the providers by
+// a policy:
+//
+//   CheapestWithin { max_latency }   lowest estimated cost among providers expected to answer in
+//                                    time; slower ones are only tried after all of those failed
+//   Fastest                          lowest expected latency
+//   Ordered(names)                   the listed providers in that order, nothing else
+//
+// and tries them in turn until one answers. A provider that reports a rate limit (ProviderError::
+// RateLimited, or an error mentioning "429" / "rate limit") is skipped until its retry time; one
+// that fails `max_consecutive_failures` times in a row cools down for `failure_cooldown`.
+//
+// Cost is estimated from the provider's pricing and token estimates (cost.rs), latency from an
+// exponential moving average of observed calls, seeded with the provider's declared latency. A
+// failed call counts with its elapsed time, but never as faster than the provider was expected to
+// be, so a provider that keeps timing out drops down the ranking while one failing instantly does
+// not look fast.
+// Per-provider requests, successes, failures, rate limits, latency and spend are kept in
+// `metrics`; with `with_cost_tracker` every answered call is also charged to a subsystem budget.
+// Failovers are published as "llm.failover" events.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use serde_json::json;
+
+use crate::cost::{estimate_tokens, CostTracker, ModelPricing};
+use crate::events::EventBus;
+use crate::generation::TextGenerator;
+
+pub const FAILOVER_TOPIC: &str = "llm.failover";
+
+// Weight of the newest observation in the latency average
+const LATENCY_ALPHA: f64 = 0.2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderKind {
+    OpenAi,
+    Anthropic,
+    Local,
+    Other,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProviderSpec {
+    pub name: String,
+    pub kind: ProviderKind,
+    pub model: String,
+    pub pricing: ModelPricing,
+    // Latency assumed until calls have been observed
+    pub expected_latency: Duration,
+    // Output length assumed when estimating cost
+    pub expected_output_tokens: u64,
+}
+
+impl ProviderSpec {
+    pub fn new(name: &str, kind: ProviderKind, model: &str) -> Self {
+        ProviderSpec {
+            name: name.to_string(),
+            kind,
+            model: model.to_string(),
+            pricing: ModelPricing { input_per_1k: 0.0, output_per_1k: 0.0 },
+            expected_latency: Duration::from_secs(2),
+            expected_output_tokens: 150,
+        }
+    }
+
+    pub fn pricing(mut self, input_per_1k: f64, output_per_1k: f64) -> Self {
+        self.pricing = ModelPricing { input_per_1k, output_per_1k };
+        self
+    }
+
+    pub fn latency(mut self, expected: Duration) -> Self {
+        self.expected_latency = expected;
+        self
+    }
+
+    pub fn output_tokens(mut self, tokens: u64) -> Self {
+        self.expected_output_tokens = tokens;
+        self
+    }
+}
+
+// Errors providers can return so the router can tell them apart from other failures
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProviderError {
+    RateLimited { retry_after: Option<Duration> },
+    Unavailable(String),
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProviderError::RateLimited { retry_after: Some(after) } => write!(f, "rate limited, retry in {:?}", after),
+            ProviderError::RateLimited { retry_after: None } => write!(f, "rate limited"),
+            ProviderError::Unavailable(reason) => write!(f, "provider unavailable: {}", reason),
+        }
+    }
+}
+
+impl Error for ProviderError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RoutingPolicy {
+    CheapestWithin { max_latency: Duration },
+    Fastest,
+    Ordered(Vec<String>),
+}
+
+#[derive(Debug, Clone)]
+pub struct RouterConfig {
+    // Cool-down for rate limits that do not say how long to wait
+    pub rate_limit_cooldown: Duration,
+    pub max_consecutive_failures: u32,
+    pub failure_cooldown: Duration,
+}
+
+impl Default for RouterConfig {
+    fn default() -> Self {
+        RouterConfig {
+            rate_limit_cooldown: Duration::from_secs(20),
+            max_consecutive_failures: 3,
+            failure_cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct ProviderMetrics {
+    pub requests: u64,
+    pub successes: u64,
+    pub failures: u64,
+    pub rate_limited: u64,
+    // Ranked but not called because it was cooling down
+    pub skipped: u64,
+    pub consecutive_failures: u32,
+    // Moving average over calls, failures included; 0 until the first one
+    pub latency_ms: f64,
+    pub cost_usd: f64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl ProviderMetrics {
+    fn observe_latency(&mut self, ms: f64) {
+        self.latency_ms = if self.latency_ms > 0.0 { self.latency_ms + LATENCY_ALPHA * (ms - self.latency_ms) } else { ms };
+    }
+
+    pub fn success_rate(&self) -> f64 {
+        if self.requests == 0 {
+            1.0
+        } else {
+            self.successes as f64 / self.requests as f64
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attempt {
+    pub provider: String,
+    pub error: String,
+    pub rate_limited: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Routed {
+    pub text: String,
+    pub provider: String,
+    pub model: String,
+    pub latency: Duration,
+    pub cost_usd: f64,
+    // Providers that failed before this one answered
+    pub failed: Vec<Attempt>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RouterError {
+    // Nothing registered, or everything eligible is cooling down
+    NoProvider,
+    UnknownProvider(String),
+    AllFailed(Vec<Attempt>),
+}
+
+impl fmt::Display for RouterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RouterError::NoProvider => write!(f, "no LLM provider is available"),
+            RouterError::UnknownProvider(name) => write!(f, "unknown LLM provider '{}'", name),
+            RouterError::AllFailed(attempts) => {
+                write!(f, "all LLM providers failed:")?;
+                for attempt in attempts {
+                    write!(f, " {}: {};", attempt.provider, attempt.error)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for RouterError {}
+
+struct Provider {
+    spec: ProviderSpec,
+    generator: Box<dyn TextGenerator + Send + Sync>,
+}
+
+#[derive(Default)]
+struct RouterState {
+    metrics: HashMap<String, ProviderMetrics>,
+    cooldown_until: HashMap<String, Instant>,
+    failovers: Vec<(String, Attempt)>,
+}
+
+pub struct ProviderRouter {
+    providers: Vec<Provider>,
+    pub policy: RoutingPolicy,
+    pub config: RouterConfig,
+    state: Mutex<RouterState>,
+    cost: Option<(Arc<Mutex<CostTracker>>, String)>,
+}
+
+impl ProviderRouter {
+    pub fn new(policy: RoutingPolicy) -> Self {
+        ProviderRouter {
+            providers: Vec::new(),
+            policy,
+            config: RouterConfig::default(),
+            state: Mutex::new(RouterState::default()),
+            cost: None,
+        }
+    }
+
+    pub fn with_config(mut self, config: RouterConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    // Charge answered calls to `subsystem`
+    pub fn with_cost_tracker(mut self, tracker: Arc<Mutex<CostTracker>>, subsystem: &str) -> Self {
+        self.cost = Some((tracker, subsystem.to_string()));
+        self
+    }
+
+    // Register a provider; one with the same name is replaced
+    pub fn register(&mut self, spec: ProviderSpec, generator: impl TextGenerator + Send + Sync + 'static) {
+        self.providers.retain(|provider| provider.spec.name != spec.name);
+        self.providers.push(Provider { spec, generator: Box::new(generator) });
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        let before = self.providers.len();
+        self.providers.retain(|provider| provider.spec.name != name);
+        self.providers.len() != before
+    }
+
+    pub fn providers(&self) -> Vec<&ProviderSpec> {
+        self.providers.iter().map(|provider| &provider.spec).collect()
+    }
+
+    fn state(&self) -> MutexGuard<'_, RouterState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    // Expected latency: observed average once there is one, else the declared latency
+    pub fn expected_latency(&self, name: &str) -> Option<Duration> {
+        let spec = &self.providers.iter().find(|provider| provider.spec.name == name)?.spec;
+        let observed = self.state().metrics.get(name).map_or(0.0, |metrics| metrics.latency_ms);
+        Some(if observed > 0.0 { Duration::from_secs_f64(observed / 1000.0) } else { spec.expected_latency })
+    }
+
+    fn estimated_cost(spec: &ProviderSpec, prompt: &str) -> f64 {
+        (estimate_tokens(prompt) as f64 * spec.pricing.input_per_1k
+            + spec.expected_output_tokens as f64 * spec.pricing.output_per_1k)
+            / 1000.0
+    }
+
+    // Providers in the order a request under `policy` would try them, cooling-down ones included
+    pub fn rank(&self, prompt: &str, policy: &RoutingPolicy) -> Result<Vec<&ProviderSpec>, RouterError> {
+        let latency = |spec: &ProviderSpec| self.expected_latency(&spec.name).unwrap_or(spec.expected_latency);
+        let mut ranked: Vec<&ProviderSpec> = self.providers.iter().map(|provider| &provider.spec).collect();
+        match policy {
+            RoutingPolicy::CheapestWithin { max_latency } => {
+                ranked.sort_by(|a, b| {
+                    let (a_late, b_late) = (latency(a) > *max_latency, latency(b) > *max_latency);
+                    a_late.cmp(&b_late).then_with(|| {
+                        if a_late {
+                            latency(a).cmp(&latency(b))
+                        } else {
+                            ProviderRouter::estimated_cost(a, prompt).total_cmp(&ProviderRouter::estimated_cost(b, prompt))
+                        }
+                    })
+                });
+            }
+            RoutingPolicy::Fastest => ranked.sort_by_key(|spec| latency(spec)),
+            RoutingPolicy::Ordered(names) => {
+                ranked = names
+                    .iter()
+                    .map(|name| {
+                        self.providers
+                            .iter()
+                            .find(|provider| &provider.spec.name == name)
+                            .map(|provider| &provider.spec)
+                            .ok_or_else(|| RouterError::UnknownProvider(name.clone()))
+                    })
+                    .collect::<Result<_, _>>()?;
+            }
+        }
+        Ok(ranked)
+    }
+
+    // Answer `prompt` with the router's policy
+    pub fn route(&self, prompt: &str) -> Result<Routed, RouterError> {
+        self.route_with(prompt, &self.policy)
+    }
+
+    pub fn route_with(&self, prompt: &str, policy: &RoutingPolicy) -> Result<Routed, RouterError> {
+        let ranked: Vec<String> = self.rank(prompt, policy)?.into_iter().map(|spec| spec.name.clone()).collect();
+        let mut failed = Vec::new();
+        let mut tried = false;
+        for name in ranked {
+            let Some(provider) = self.providers.iter().find(|provider| provider.spec.name == name) else { continue };
+            if self.cooling_down(&name) {
+                self.state().metrics.entry(name).or_default().skipped += 1;
+                continue;
+            }
+            tried = true;
+            let started = Instant::now();
+            let result = provider.generator.generate(prompt);
+            let latency = started.elapsed();
+            match result {
+                Ok(text) => {
+                    self.record_failovers(&failed, &name);
+                    return Ok(self.succeeded(&provider.spec, prompt, text, latency, failed));
+                }
+                Err(err) => failed.push(self.failed(&provider.spec, err.as_ref(), latency)),
+            }
+        }
+        self.record_failovers(&failed, "");
+        if tried {
+            Err(RouterError::AllFailed(failed))
+        } else {
+            Err(RouterError::NoProvider)
+        }
+    }
+
+    // Each failed attempt hands over to the next one tried, the last to `answered_by`
+    fn record_failovers(&self, failed: &[Attempt], answered_by: &str) {
+        let mut state = self.state();
+        for (n, attempt) in failed.iter().enumerate() {
+            let next = failed.get(n + 1).map_or(answered_by, |next| next.provider.as_str());
+            state.failovers.push((next.to_string(), attempt.clone()));
+        }
+    }
+
+    fn cooling_down(&self, name: &str) -> bool {
+        let mut state = self.state();
+        match state.cooldown_until.get(name) {
+            Some(until) if *until > Instant::now() => true,
+            Some(_) => {
+                state.cooldown_until.remove(name);
+                false
+            }
+            None => false,
+        }
+    }
+
+    fn succeeded(&self, spec: &ProviderSpec, prompt: &str, text: String, latency: Duration, failed: Vec<Attempt>) -> Routed {
+        let (input_tokens, output_tokens) = (estimate_tokens(prompt), estimate_tokens(&text));
+        let cost_usd =
+            (input_tokens as f64 * spec.pricing.input_per_1k + output_tokens as f64 * spec.pricing.output_per_1k) / 1000.0;
+        {
+            let mut state = self.state();
+            let metrics = state.metrics.entry(spec.name.clone()).or_default();
+            metrics.requests += 1;
+            metrics.successes += 1;
+            metrics.consecutive_failures = 0;
+            metrics.observe_latency(latency.as_secs_f64() * 1000.0);
+            metrics.cost_usd += cost_usd;
+            metrics.input_tokens += input_tokens;
+            metrics.output_tokens += output_tokens;
+        }
+        if let Some((tracker, subsystem)) = &self.cost {
+            tracker.lock().unwrap().record(subsystem, &spec.model, input_tokens, output_tokens);
+        }
+        Routed { text, provider: spec.name.clone(), model: spec.model.clone(), latency, cost_usd, failed }
+    }
+
+    fn failed(&self, spec: &ProviderSpec, err: &(dyn Error + 'static), elapsed: Duration) -> Attempt {
+        let (rate_limited, retry_after) = match err.downcast_ref::<ProviderError>() {
+            Some(ProviderError::RateLimited { retry_after }) => (true, *retry_after),
+            Some(ProviderError::Unavailable(_)) => (false, None),
+            None => (is_rate_limit_message(&err.to_string()), None),
+        };
+        let mut state = self.state();
+        let metrics = state.metrics.entry(spec.name.clone()).or_default();
+        metrics.requests += 1;
+        metrics.failures += 1;
+        metrics.consecutive_failures += 1;
+        let expected_ms = if metrics.latency_ms > 0.0 { metrics.latency_ms } else { spec.expected_latency.as_secs_f64() * 1000.0 };
+        metrics.observe_latency((elapsed.as_secs_f64() * 1000.0).max(expected_ms));
+        let cooldown = if rate_limited {
+            metrics.rate_limited += 1;
+            Some(retry_after.unwrap_or(self.config.rate_limit_cooldown))
+        } else if metrics.consecutive_failures >= self.config.max_consecutive_failures.max(1) {
+            metrics.consecutive_failures = 0;
+            Some(self.config.failure_cooldown)
+        } else {
+            None
+        };
+        if let Some(cooldown) = cooldown {
+            state.cooldown_until.insert(spec.name.clone(), Instant::now() + cooldown);
+        }
+        Attempt { provider: spec.name.clone(), error: err.to_string(), rate_limited }
+    }
+
+    pub fn metrics(&self, name: &str) -> Option<ProviderMetrics> {
+        self.state().metrics.get(name).copied()
+    }
+
+    // Running totals as "llm.<provider>.<field>" metrics, like CostTracker::metrics
+    pub fn metrics_map(&self) -> HashMap<String, f64> {
+        let state = self.state();
+        let mut map = HashMap::new();
+        for (name, metrics) in &state.metrics {
+            map.insert(format!("llm.{}.requests", name), metrics.requests as f64);
+            map.insert(format!("llm.{}.success_rate", name), metrics.success_rate());
+            map.insert(format!("llm.{}.rate_limited", name), metrics.rate_limited as f64);
+            map.insert(format!("llm.{}.latency_ms", name), metrics.latency_ms);
+            map.insert(format!("llm.{}.usd", name), metrics.cost_usd);
+        }
+        map
+    }
+
+    // Lift a cool-down early, e.g. after an operator fixed credentials
+    pub fn reset_cooldown(&self, name: &str) -> bool {
+        self.state().cooldown_until.remove(name).is_some()
+    }
+
+    // Publish failovers on FAILOVER_TOPIC; returns the number published
+    pub fn publish(&mut self, events: &mut EventBus) -> usize {
+        let failovers = std::mem::take(&mut self.state().failovers);
+        for (next, attempt) in &failovers {
+            let payload = json!({
+                "failed": attempt.provider,
+                "error": attempt.error,
+                "rate_limited": attempt.rate_limited,
+                // Empty when no provider was left to try
+                "next": next,
+            });
+            events.emit(FAILOVER_TOPIC, &attempt.provider, payload);
+        }
+        failovers.len()
+    }
+}
+
+impl TextGenerator for ProviderRouter {
+    fn generate(&self, prompt: &str) -> Result<String, Box<dyn Error>> {
+        Ok(self.route(prompt)?.text)
+    }
+}
+
+fn is_rate_limit_message(message: &str) -> bool {
+    let lower = message.to_lowercase();
+    lower.contains("429") || lower.contains("rate limit") || lower.contains("too many requests")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    // Fails after `delay`, like a provider timing out
+    struct Failing(Duration);
+
+    impl TextGenerator for Failing {
+        fn generate(&self, _prompt: &str) -> Result<String, Box<dyn Error>> {
+            thread::sleep(self.0);
+            Err(Box::new(ProviderError::Unavailable("timed out".to_string())))
+        }
+    }
+
+    struct Answering;
+
+    impl TextGenerator for Answering {
+        fn generate(&self, _prompt: &str) -> Result<String, Box<dyn Error>> {
+            Ok("hello".to_string())
+        }
+    }
+
+    fn spec(name: &str, latency_ms: u64) -> ProviderSpec {
+        ProviderSpec::new(name, ProviderKind::Other, name).latency(Duration::from_millis(latency_ms))
+    }
+
+    #[test]
+    fn timeouts_push_a_provider_down_the_ranking() {
+        let mut router = ProviderRouter::new(RoutingPolicy::Fastest);
+        router.register(spec("timing-out", 5), Failing(Duration::from_millis(60)));
+        router.register(spec("steady", 30), Answering);
+        assert_eq!(router.rank("hi", &RoutingPolicy::Fastest).unwrap()[0].name, "timing-out");
+
+        let routed = router.route("hi").unwrap();
+        assert_eq!((routed.provider.as_str(), routed.failed.len()), ("steady", 1));
+        assert!(router.expected_latency("timing-out").unwrap() >= Duration::from_millis(60));
+        assert_eq!(router.rank("hi", &RoutingPolicy::Fastest).unwrap()[0].name, "steady");
+    }
+
+    #[test]
+    fn fast_failures_do_not_look_fast() {
+        let mut router = ProviderRouter::new(RoutingPolicy::Fastest);
+        router.register(spec("refusing", 500), Failing(Duration::ZERO));
+        assert!(router.route("hi").is_err());
+        let metrics = router.metrics("refusing").unwrap();
+        assert_eq!((metrics.failures, metrics.latency_ms), (1, 500.0));
+        assert_eq!(router.expected_latency("refusing"), Some(Duration::from_millis(500)));
+    }
+}
@@ -0,0 +1,109 @@
+// candle-backed sentence-transformer
+//
+// Runs a BERT-style encoder on the CPU and mean-pools the token embeddings over the attention
+// mask, then L2-normalises, which is what sentence-transformers does for the MiniLM family. Batches
+// are padded to their longest input and truncated at the spec's `max_tokens`.
+
+use candle_core::{DType, Device, Tensor};
+use candle_nn::VarBuilder;
+use candle_transformers::models::bert::{BertModel, Config, DTYPE};
+use tokenizers::{PaddingParams, PaddingStrategy, Tokenizer, TruncationParams};
+
+use super::{ModelCache, ModelFiles, ModelSpec};
+use crate::embeddings::{normalize, Embedder, EmbeddingError};
+
+pub struct LocalEmbedder {
+    model: BertModel,
+    tokenizer: Tokenizer,
+    device: Device,
+    name: String,
+    dimension: usize,
+}
+
+impl LocalEmbedder {
+    // Fetch the model through the cache if needed, then load it
+    pub fn new(cache: &ModelCache, spec: &ModelSpec) -> Result<Self, EmbeddingError> {
+        let files = cache.ensure(spec).map_err(provider)?;
+        LocalEmbedder::load(&files)
+    }
+
+    pub fn load(files: &ModelFiles) -> Result<Self, EmbeddingError> {
+        let path = |name: &str| {
+            files.path(name).ok_or_else(|| EmbeddingError::Provider(format!("model {} has no {}", files.spec.name, name)))
+        };
+        let config_text = std::fs::read_to_string(path("config.json")?).map_err(provider)?;
+        let config: Config = serde_json::from_str(&config_text).map_err(provider)?;
+
+        let mut tokenizer = Tokenizer::from_file(path("tokenizer.json")?).map_err(provider)?;
+        tokenizer.with_padding(Some(PaddingParams { strategy: PaddingStrategy::BatchLongest, ..Default::default() }));
+        tokenizer
+            .with_truncation(Some(TruncationParams { max_length: files.spec.max_tokens, ..Default::default() }))
+            .map_err(provider)?;
+
+        let device = Device::Cpu;
+        // Safety: the weights file is owned by the cache and not modified while mapped
+        let weights = unsafe { VarBuilder::from_mmaped_safetensors(&[path("model.safetensors")?], DTYPE, &device) }
+            .map_err(provider)?;
+        let model = BertModel::load(weights, &config).map_err(provider)?;
+        Ok(LocalEmbedder {
+            model,
+            tokenizer,
+            device,
+            name: format!("local-{}", files.spec.name),
+            dimension: files.spec.dimension,
+        })
+    }
+
+    fn encode(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, candle_core::Error> {
+        let encodings = self.tokenizer.encode_batch(texts.to_vec(), true).map_err(candle_core::Error::msg)?;
+        let mut ids = Vec::with_capacity(encodings.len());
+        let mut masks = Vec::with_capacity(encodings.len());
+        for encoding in &encodings {
+            ids.push(Tensor::new(encoding.get_ids(), &self.device)?);
+            masks.push(Tensor::new(encoding.get_attention_mask(), &self.device)?);
+        }
+        let ids = Tensor::stack(&ids, 0)?;
+        let mask = Tensor::stack(&masks, 0)?;
+        let type_ids = ids.zeros_like()?;
+        let hidden = self.model.forward(&ids, &type_ids, Some(&mask))?;
+
+        // Mean over the real (unpadded) tokens
+        let mask = mask.to_dtype(DType::F32)?.unsqueeze(2)?;
+        let summed = hidden.broadcast_mul(&mask)?.sum(1)?;
+        let counts = mask.sum(1)?.clamp(1e-9, f32::MAX)?;
+        summed.broadcast_div(&counts)?.to_vec2::<f32>()
+    }
+}
+
+impl Embedder for LocalEmbedder {
+    fn model(&self) -> &str {
+        &self.name
+    }
+
+    fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    fn embed(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+        let mut vectors = self.embed_batch(&[text])?;
+        vectors.pop().ok_or_else(|| EmbeddingError::Provider("model returned no embedding".to_string()))
+    }
+
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, EmbeddingError> {
+        if texts.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut vectors = self.encode(texts).map_err(provider)?;
+        for vector in &mut vectors {
+            if vector.len() != self.dimension {
+                return Err(EmbeddingError::DimensionMismatch { expected: self.dimension, found: vector.len() });
+            }
+            normalize(vector);
+        }
+        Ok(vectors)
+    }
+}
+
+fn provider(err: impl std::fmt::Display) -> EmbeddingError {
+    EmbeddingError::Provider(err.to_string())
+}
@@ -0,0 +1,487 @@
+// Local embedding models
+//
+// Hobby projects shouldn't need an API key just to give NPCs a memory. With the `local-embeddings`
+// feature a small sentence-transformer (all-MiniLM-L6-v2 by default, 384 dimensions) runs in
+// process on candle, and LocalEmbedder is a drop-in Embedder for the vector index. The model
+// runs on the CPU and stays Apache-2.0 end to end: no AGPL inference server or Python runtime.
+//
+// Model files are looked after by a ModelCache, which is always compiled so tooling can prefetch
+// models in builds without the feature:
+//
+//   let cache = ModelCache::from_env();
+//   let files = cache.ensure(&ModelSpec::all_minilm_l6_v2())?;
+//
+// Files live under <cache root>/<model>/<revision>/ and are downloaded at most once, to a ".part"
+// file that is renamed into place once its SHA-256 matches. A spec can pin checksums; files
+// without one are trusted on first use, with the hash recorded in a lock file next to the model
+// and every later load checked against it, so a corrupt or swapped file is caught either way.
+// In offline mode (`with_offline`, or ARCADIA_OFFLINE=1) nothing is fetched and a missing model
+// is an error naming the files to copy in by hand. Downloads go through a ModelFetcher; the
+// default shells out to curl, so no HTTP client is linked in.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+#[cfg(feature = "local-embeddings")]
+mod candle;
+
+#[cfg(feature = "local-embeddings")]
+pub use candle::LocalEmbedder;
+
+const LOCK_FILE: &str = "arcadia-model.lock.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelFile {
+    // Name inside the model directory, e.g. "model.safetensors"
+    pub name: String,
+    pub url: String,
+    // Lowercase hex; None trusts the first download
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModelSpec {
+    pub name: String,
+    pub revision: String,
+    pub dimension: usize,
+    // Longer inputs are truncated
+    pub max_tokens: usize,
+    pub files: Vec<ModelFile>,
+}
+
+impl ModelSpec {
+    // A sentence-transformer in the Hugging Face layout: config.json, tokenizer.json and
+    // model.safetensors
+    pub fn hugging_face(repo: &str, revision: &str, dimension: usize, max_tokens: usize) -> Self {
+        let files = ["config.json", "tokenizer.json", "model.safetensors"]
+            .iter()
+            .map(|name| ModelFile {
+                name: name.to_string(),
+                url: format!("https://huggingface.co/{}/resolve/{}/{}", repo, revision, name),
+                sha256: None,
+            })
+            .collect();
+        ModelSpec {
+            name: repo.rsplit('/').next().unwrap_or(repo).to_string(),
+            revision: revision.to_string(),
+            dimension,
+            max_tokens,
+            files,
+        }
+    }
+
+    pub fn all_minilm_l6_v2() -> Self {
+        ModelSpec::hugging_face("sentence-transformers/all-MiniLM-L6-v2", "main", 384, 256)
+    }
+
+    // Pin the checksum of one file
+    pub fn with_checksum(mut self, file: &str, sha256: &str) -> Self {
+        if let Some(entry) = self.files.iter_mut().find(|entry| entry.name == file) {
+            entry.sha256 = Some(sha256.to_ascii_lowercase());
+        }
+        self
+    }
+
+    pub fn file(&self, name: &str) -> Option<&ModelFile> {
+        self.files.iter().find(|file| file.name == name)
+    }
+}
+
+// Paths of a model's files once they are all present and verified
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelFiles {
+    pub spec: ModelSpec,
+    pub dir: PathBuf,
+    pub paths: BTreeMap<String, PathBuf>,
+    // Files fetched by this call, as opposed to found in the cache
+    pub downloaded: Vec<String>,
+}
+
+impl ModelFiles {
+    pub fn path(&self, name: &str) -> Option<&Path> {
+        self.paths.get(name).map(PathBuf::as_path)
+    }
+}
+
+#[derive(Debug)]
+pub enum ModelCacheError {
+    // Offline and the files are not in the cache
+    Offline { model: String, missing: Vec<String> },
+    Download { url: String, reason: String },
+    ChecksumMismatch { file: String, expected: String, found: String },
+    Io(io::Error),
+    Lock(serde_json::Error),
+}
+
+impl fmt::Display for ModelCacheError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModelCacheError::Offline { model, missing } => {
+                write!(f, "model {} is not cached and offline mode is on (missing: {})", model, missing.join(", "))
+            }
+            ModelCacheError::Download { url, reason } => write!(f, "could not download {}: {}", url, reason),
+            ModelCacheError::ChecksumMismatch { file, expected, found } => {
+                write!(f, "{} has sha256 {}, expected {}", file, found, expected)
+            }
+            ModelCacheError::Io(err) => write!(f, "model cache: {}", err),
+            ModelCacheError::Lock(err) => write!(f, "model lock file is invalid: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ModelCacheError {}
+
+impl From<io::Error> for ModelCacheError {
+    fn from(err: io::Error) -> Self {
+        ModelCacheError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for ModelCacheError {
+    fn from(err: serde_json::Error) -> Self {
+        ModelCacheError::Lock(err)
+    }
+}
+
+// Downloads one URL to a local file
+pub trait ModelFetcher {
+    fn fetch(&self, url: &str, dest: &Path) -> Result<(), String>;
+}
+
+// Runs `curl`, which every desktop platform we ship on has
+pub struct CurlFetcher;
+
+impl ModelFetcher for CurlFetcher {
+    fn fetch(&self, url: &str, dest: &Path) -> Result<(), String> {
+        let output = Command::new("curl")
+            .args(["--fail", "--location", "--silent", "--show-error", "--retry", "3", "--output"])
+            .arg(dest)
+            .arg(url)
+            .output()
+            .map_err(|err| format!("could not run curl: {}", err))?;
+        if output.status.success() {
+            Ok(())
+        } else {
+            Err(String::from_utf8_lossy(&output.stderr).trim().to_string())
+        }
+    }
+}
+
+// State of one cached file, for tooling
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileStatus {
+    Missing,
+    Verified { sha256: String },
+    // Present, no pinned or recorded checksum to compare against
+    Unverified { sha256: String },
+    Corrupt { expected: String, found: String },
+}
+
+pub struct ModelCache {
+    pub root: PathBuf,
+    pub offline: bool,
+    fetcher: Box<dyn ModelFetcher>,
+}
+
+impl ModelCache {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        ModelCache { root: root.into(), offline: false, fetcher: Box::new(CurlFetcher) }
+    }
+
+    // Root from ARCADIA_MODEL_CACHE, else the platform cache directory; offline when
+    // ARCADIA_OFFLINE is set to anything but "0" or "false"
+    pub fn from_env() -> Self {
+        let root = std::env::var_os("ARCADIA_MODEL_CACHE").map(PathBuf::from).unwrap_or_else(default_root);
+        let offline = std::env::var("ARCADIA_OFFLINE").is_ok_and(|value| !matches!(value.as_str(), "" | "0" | "false"));
+        ModelCache::new(root).with_offline(offline)
+    }
+
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    pub fn with_fetcher(mut self, fetcher: impl ModelFetcher + 'static) -> Self {
+        self.fetcher = Box::new(fetcher);
+        self
+    }
+
+    pub fn model_dir(&self, spec: &ModelSpec) -> PathBuf {
+        self.root.join(sanitize(&spec.name)).join(sanitize(&spec.revision))
+    }
+
+    // Make sure every file of the model is present and matches its checksum, downloading what is
+    // missing or corrupt unless offline
+    pub fn ensure(&self, spec: &ModelSpec) -> Result<ModelFiles, ModelCacheError> {
+        let dir = self.model_dir(spec);
+        let mut lock = read_lock(&dir)?;
+        let statuses = self.status_in(spec, &dir, &lock)?;
+
+        if self.offline {
+            let missing: Vec<String> = statuses
+                .iter()
+                .filter(|(_, status)| matches!(status, FileStatus::Missing))
+                .map(|(name, _)| name.clone())
+                .collect();
+            if !missing.is_empty() {
+                return Err(ModelCacheError::Offline { model: spec.name.clone(), missing });
+            }
+        }
+
+        fs::create_dir_all(&dir)?;
+        let mut paths = BTreeMap::new();
+        let mut downloaded = Vec::new();
+        for (file, (_, status)) in spec.files.iter().zip(statuses) {
+            let path = dir.join(&file.name);
+            let sha256 = match status {
+                FileStatus::Verified { sha256 } | FileStatus::Unverified { sha256 } => sha256,
+                FileStatus::Corrupt { expected, found } if self.offline => {
+                    return Err(ModelCacheError::ChecksumMismatch { file: file.name.clone(), expected, found });
+                }
+                FileStatus::Missing | FileStatus::Corrupt { .. } => {
+                    let sha256 = self.download(file, &path, expected_hash(file, &lock))?;
+                    downloaded.push(file.name.clone());
+                    sha256
+                }
+            };
+            lock.insert(file.name.clone(), sha256);
+            paths.insert(file.name.clone(), path);
+        }
+        write_lock(&dir, &lock)?;
+        Ok(ModelFiles { spec: spec.clone(), dir, paths, downloaded })
+    }
+
+    // Check the cached files without downloading anything
+    pub fn status(&self, spec: &ModelSpec) -> Result<Vec<(String, FileStatus)>, ModelCacheError> {
+        let dir = self.model_dir(spec);
+        let lock = read_lock(&dir)?;
+        self.status_in(spec, &dir, &lock)
+    }
+
+    fn status_in(
+        &self,
+        spec: &ModelSpec,
+        dir: &Path,
+        lock: &BTreeMap<String, String>,
+    ) -> Result<Vec<(String, FileStatus)>, ModelCacheError> {
+        let mut statuses = Vec::with_capacity(spec.files.len());
+        for file in &spec.files {
+            let path = dir.join(&file.name);
+            let status = if !path.is_file() {
+                FileStatus::Missing
+            } else {
+                let found = sha256_file(&path)?;
+                match expected_hash(file, lock) {
+                    Some(expected) if expected == found => FileStatus::Verified { sha256: found },
+                    Some(expected) => FileStatus::Corrupt { expected: expected.to_string(), found },
+                    None => FileStatus::Unverified { sha256: found },
+                }
+            };
+            statuses.push((file.name.clone(), status));
+        }
+        Ok(statuses)
+    }
+
+    fn download(&self, file: &ModelFile, path: &Path, expected: Option<&str>) -> Result<String, ModelCacheError> {
+        let partial = path.with_extension("part");
+        let _ = fs::remove_file(&partial);
+        self.fetcher
+            .fetch(&file.url, &partial)
+            .map_err(|reason| ModelCacheError::Download { url: file.url.clone(), reason })?;
+        let found = sha256_file(&partial)?;
+        if let Some(expected) = expected {
+            if expected != found {
+                let _ = fs::remove_file(&partial);
+                return Err(ModelCacheError::ChecksumMismatch {
+                    file: file.name.clone(),
+                    expected: expected.to_string(),
+                    found,
+                });
+            }
+        }
+        fs::rename(&partial, path)?;
+        Ok(found)
+    }
+
+    // Delete a model's files; returns whether anything was there
+    pub fn evict(&self, spec: &ModelSpec) -> Result<bool, ModelCacheError> {
+        let dir = self.model_dir(spec);
+        if !dir.exists() {
+            return Ok(false);
+        }
+        fs::remove_dir_all(dir)?;
+        Ok(true)
+    }
+}
+
+// A pinned checksum wins over the one recorded on first use
+fn expected_hash<'a>(file: &'a ModelFile, lock: &'a BTreeMap<String, String>) -> Option<&'a str> {
+    file.sha256.as_deref().or_else(|| lock.get(&file.name).map(String::as_str))
+}
+
+fn read_lock(dir: &Path) -> Result<BTreeMap<String, String>, ModelCacheError> {
+    match fs::read_to_string(dir.join(LOCK_FILE)) {
+        Ok(text) => Ok(serde_json::from_str(&text)?),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+fn write_lock(dir: &Path, lock: &BTreeMap<String, String>) -> Result<(), ModelCacheError> {
+    fs::write(dir.join(LOCK_FILE), serde_json::to_string_pretty(lock)?)?;
+    Ok(())
+}
+
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+fn default_root() -> PathBuf {
+    let base = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .unwrap_or_else(std::env::temp_dir);
+    base.join("arcadia").join("models")
+}
+
+// Model names and revisions become directory names
+fn sanitize(part: &str) -> String {
+    part.chars().map(|c| if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+
+    // sha256("abc")
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    // Serves fixed contents per URL and records what was fetched
+    #[derive(Clone, Default)]
+    struct Served {
+        contents: BTreeMap<String, Vec<u8>>,
+        fetched: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl ModelFetcher for Served {
+        fn fetch(&self, url: &str, dest: &Path) -> Result<(), String> {
+            self.fetched.borrow_mut().push(url.to_string());
+            let content = self.contents.get(url).ok_or_else(|| "404".to_string())?;
+            fs::write(dest, content).map_err(|err| err.to_string())
+        }
+    }
+
+    fn file(name: &str) -> ModelFile {
+        ModelFile { name: name.to_string(), url: format!("https://models.test/{}", name), sha256: None }
+    }
+
+    fn spec() -> ModelSpec {
+        let files = vec![file("a"), file("b")];
+        ModelSpec { name: "tiny/model".to_string(), revision: "v1".to_string(), dimension: 4, max_tokens: 8, files }
+    }
+
+    fn served() -> Served {
+        let contents = BTreeMap::from([
+            ("https://models.test/a".to_string(), b"abc".to_vec()),
+            ("https://models.test/b".to_string(), b"weights".to_vec()),
+        ]);
+        Served { contents, fetched: Rc::default() }
+    }
+
+    fn cache(test: &str, fetcher: &Served) -> ModelCache {
+        let root = std::env::temp_dir().join(format!("arcadia-models-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        ModelCache::new(root).with_fetcher(fetcher.clone())
+    }
+
+    #[test]
+    fn hugging_face_specs_point_at_the_repo() {
+        let spec = ModelSpec::all_minilm_l6_v2().with_checksum("config.json", "ABCD");
+        assert_eq!((spec.name.as_str(), spec.dimension), ("all-MiniLM-L6-v2", 384));
+        let config = spec.file("config.json").unwrap();
+        assert_eq!(config.url, "https://huggingface.co/sentence-transformers/all-MiniLM-L6-v2/resolve/main/config.json");
+        assert_eq!(config.sha256.as_deref(), Some("abcd"));
+        assert_eq!(spec.file("model.safetensors").unwrap().sha256, None);
+    }
+
+    #[test]
+    fn files_are_downloaded_once_and_trusted_on_first_use() {
+        let served = served();
+        let cache = cache("once", &served);
+        let files = cache.ensure(&spec()).unwrap();
+        assert_eq!(files.downloaded, vec!["a", "b"]);
+        assert_eq!(files.dir, cache.root.join("tiny_model").join("v1"));
+        assert_eq!(fs::read(files.path("b").unwrap()).unwrap(), b"weights");
+        assert!(!files.dir.join("b.part").exists());
+
+        assert!(cache.ensure(&spec()).unwrap().downloaded.is_empty());
+        assert_eq!(served.fetched.borrow().len(), 2);
+        let status = cache.status(&spec()).unwrap();
+        assert_eq!(status[0], ("a".to_string(), FileStatus::Verified { sha256: ABC_SHA256.to_string() }));
+
+        // A swapped file no longer matches the hash recorded on first use and is fetched again
+        fs::write(files.path("a").unwrap(), b"evil").unwrap();
+        assert!(matches!(cache.status(&spec()).unwrap()[0].1, FileStatus::Corrupt { .. }));
+        assert_eq!(cache.ensure(&spec()).unwrap().downloaded, vec!["a"]);
+        assert!(cache.evict(&spec()).unwrap());
+        assert!(!cache.evict(&spec()).unwrap());
+    }
+
+    #[test]
+    fn pinned_checksums_reject_bad_downloads() {
+        let served = served();
+        let cache = cache("pinned", &served);
+        let pinned = spec().with_checksum("a", ABC_SHA256).with_checksum("b", &"0".repeat(64));
+        let err = cache.ensure(&pinned).unwrap_err();
+        assert!(matches!(err, ModelCacheError::ChecksumMismatch { ref file, .. } if file == "b"));
+        let dir = cache.model_dir(&pinned);
+        assert!(dir.join("a").exists());
+        assert!(!dir.join("b").exists() && !dir.join("b.part").exists());
+
+        let missing = ModelSpec { files: vec![file("c")], ..spec() };
+        assert!(matches!(cache.ensure(&missing), Err(ModelCacheError::Download { .. })));
+        let _ = fs::remove_dir_all(&cache.root);
+    }
+
+    #[test]
+    fn offline_mode_never_fetches() {
+        let served = served();
+        let offline = cache("offline", &served).with_offline(true);
+        let Err(ModelCacheError::Offline { missing, .. }) = offline.ensure(&spec()) else { panic!("expected offline error") };
+        assert_eq!(missing, vec!["a", "b"]);
+        assert!(served.fetched.borrow().is_empty());
+
+        // Copied in by hand
+        let dir = offline.model_dir(&spec());
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a"), b"abc").unwrap();
+        fs::write(dir.join("b"), b"weights").unwrap();
+        assert!(offline.ensure(&spec()).unwrap().downloaded.is_empty());
+        let pinned = spec().with_checksum("b", &"0".repeat(64));
+        assert!(matches!(offline.ensure(&pinned), Err(ModelCacheError::ChecksumMismatch { .. })));
+        assert!(served.fetched.borrow().is_empty());
+        let _ = fs::remove_dir_all(&offline.root);
+    }
+}
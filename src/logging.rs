@@ -0,0 +1,456 @@
+// Engine logging
+//
+// A tracing layer with engine-level configuration: plain or JSON output, per-subsystem level
+// overrides keyed by tracing target prefix ("arcadia::vector_index") that can be changed while
+// the game runs, an in-memory ring buffer of recent records for diagnostics, and an optional
+// size-rotated log file so shipped games can collect logs from player machines.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::field::{Field, Visit};
+use tracing::subscriber::Interest;
+use tracing::{Event, Level, Metadata, Subscriber};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FileSinkConfig {
+    pub path: PathBuf,
+    // Rotate once the active file reaches this size
+    #[serde(default = "default_max_bytes")]
+    pub max_bytes: u64,
+    // Rotated files kept (path.1 .. path.N)
+    #[serde(default = "default_max_files")]
+    pub max_files: usize,
+}
+
+fn default_max_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_max_files() -> usize {
+    5
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    // "error", "warn", "info", "debug", "trace" or "off"
+    pub level: String,
+    // Target prefix -> level, e.g. "arcadia::vector_index" = "debug"
+    pub overrides: HashMap<String, String>,
+    pub json: bool,
+    pub stderr: bool,
+    // Records kept in memory for diagnostics (0 disables)
+    pub ring_buffer: usize,
+    pub file: Option<FileSinkConfig>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            level: "info".to_string(),
+            overrides: HashMap::new(),
+            json: false,
+            stderr: true,
+            ring_buffer: 1000,
+            file: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum LoggingError {
+    InvalidLevel(String),
+    Io(io::Error),
+    AlreadyInitialized(String),
+}
+
+impl fmt::Display for LoggingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoggingError::InvalidLevel(level) => write!(f, "invalid log level '{}'", level),
+            LoggingError::Io(err) => write!(f, "log file error: {}", err),
+            LoggingError::AlreadyInitialized(err) => write!(f, "logging already initialized: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for LoggingError {}
+
+impl From<io::Error> for LoggingError {
+    fn from(err: io::Error) -> Self {
+        LoggingError::Io(err)
+    }
+}
+
+fn parse_level(level: &str) -> Result<LevelFilter, LoggingError> {
+    LevelFilter::from_str(level).map_err(|_| LoggingError::InvalidLevel(level.to_string()))
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LogRecord {
+    // Unix milliseconds
+    pub timestamp_ms: u64,
+    pub level: String,
+    pub target: String,
+    pub message: String,
+    pub fields: BTreeMap<String, Value>,
+}
+
+impl LogRecord {
+    fn to_line(&self, json: bool) -> String {
+        if json {
+            return serde_json::to_string(self).unwrap_or_default();
+        }
+        let mut line = format!("{} {:>5} {}: {}", self.timestamp_ms, self.level, self.target, self.message);
+        for (key, value) in &self.fields {
+            line.push_str(&format!(" {}={}", key, value));
+        }
+        line
+    }
+}
+
+// Filter for LogHandle::recent
+#[derive(Debug, Clone, Default)]
+pub struct LogQuery {
+    pub min_level: Option<Level>,
+    pub target_prefix: Option<String>,
+    pub contains: Option<String>,
+    // Newest records first, at most this many (0 = all)
+    pub limit: usize,
+}
+
+struct RotatingFile {
+    config: FileSinkConfig,
+    file: File,
+    written: u64,
+}
+
+impl RotatingFile {
+    fn open(config: FileSinkConfig) -> io::Result<Self> {
+        if let Some(parent) = config.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&config.path)?;
+        let written = file.metadata()?.len();
+        Ok(RotatingFile { config, file, written })
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.config.path.clone().into_os_string();
+        name.push(format!(".{}", n));
+        PathBuf::from(name)
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        if self.written + line.len() as u64 + 1 > self.config.max_bytes && self.written > 0 {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.written += line.len() as u64 + 1;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let _ = fs::remove_file(self.rotated(self.config.max_files));
+        for n in (1..self.config.max_files).rev() {
+            let _ = fs::rename(self.rotated(n), self.rotated(n + 1));
+        }
+        if self.config.max_files > 0 {
+            fs::rename(&self.config.path, self.rotated(1))?;
+        } else {
+            fs::remove_file(&self.config.path)?;
+        }
+        self.file = OpenOptions::new().create(true).append(true).open(&self.config.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+struct Shared {
+    default_level: RwLock<LevelFilter>,
+    overrides: RwLock<HashMap<String, LevelFilter>>,
+    json: bool,
+    stderr: bool,
+    ring: Mutex<VecDeque<LogRecord>>,
+    ring_capacity: usize,
+    file: Option<Mutex<RotatingFile>>,
+}
+
+impl Shared {
+    // Longest matching target prefix wins
+    fn level_for(&self, target: &str) -> LevelFilter {
+        let overrides = self.overrides.read().unwrap();
+        overrides
+            .iter()
+            .filter(|(prefix, _)| target.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(*self.default_level.read().unwrap())
+    }
+}
+
+// Runtime control over the installed logger
+#[derive(Clone)]
+pub struct LogHandle {
+    shared: Arc<Shared>,
+}
+
+impl LogHandle {
+    pub fn set_level(&self, level: &str) -> Result<(), LoggingError> {
+        *self.shared.default_level.write().unwrap() = parse_level(level)?;
+        Ok(())
+    }
+
+    // Override the level for a subsystem (target prefix)
+    pub fn set_override(&self, target: &str, level: &str) -> Result<(), LoggingError> {
+        let level = parse_level(level)?;
+        self.shared.overrides.write().unwrap().insert(target.to_string(), level);
+        Ok(())
+    }
+
+    pub fn clear_override(&self, target: &str) {
+        self.shared.overrides.write().unwrap().remove(target);
+    }
+
+    pub fn recent(&self, query: &LogQuery) -> Vec<LogRecord> {
+        let ring = self.shared.ring.lock().unwrap();
+        let matches = ring.iter().rev().filter(|r| {
+            query
+                .min_level
+                .is_none_or(|min| Level::from_str(&r.level).map_or(true, |level| level <= min))
+                && query.target_prefix.as_ref().is_none_or(|p| r.target.starts_with(p.as_str()))
+                && query.contains.as_ref().is_none_or(|s| r.message.contains(s.as_str()))
+        });
+        match query.limit {
+            0 => matches.cloned().collect(),
+            limit => matches.take(limit).cloned().collect(),
+        }
+    }
+
+    // Recent records as JSON lines, for attaching to bug reports
+    pub fn export_recent(&self) -> String {
+        self.recent(&LogQuery::default())
+            .iter()
+            .rev()
+            .map(|r| r.to_line(true))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[derive(Default)]
+struct FieldCollector {
+    message: String,
+    fields: BTreeMap<String, Value>,
+}
+
+impl Visit for FieldCollector {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            self.fields.insert(field.name().to_string(), Value::String(format!("{:?}", value)));
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.fields.insert(field.name().to_string(), Value::String(value.to_string()));
+        }
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.fields.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.fields.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.fields.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.fields.insert(field.name().to_string(), Value::from(value));
+    }
+}
+
+pub struct EngineLogLayer {
+    shared: Arc<Shared>,
+}
+
+impl<S: Subscriber> Layer<S> for EngineLogLayer {
+    // Levels change at runtime, so never let tracing cache a callsite decision
+    fn register_callsite(&self, _metadata: &'static Metadata<'static>) -> Interest {
+        Interest::sometimes()
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, _ctx: Context<'_, S>) -> bool {
+        *metadata.level() <= self.shared.level_for(metadata.target())
+    }
+
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut collector = FieldCollector::default();
+        event.record(&mut collector);
+        let metadata = event.metadata();
+        let record = LogRecord {
+            timestamp_ms: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis() as u64),
+            level: metadata.level().to_string(),
+            target: metadata.target().to_string(),
+            message: collector.message,
+            fields: collector.fields,
+        };
+
+        let line = record.to_line(self.shared.json);
+        if self.shared.stderr {
+            eprintln!("{}", line);
+        }
+        if let Some(file) = &self.shared.file {
+            // Logging must never take the game down
+            let _ = file.lock().unwrap().write_line(&line);
+        }
+        if self.shared.ring_capacity > 0 {
+            let mut ring = self.shared.ring.lock().unwrap();
+            if ring.len() >= self.shared.ring_capacity {
+                ring.pop_front();
+            }
+            ring.push_back(record);
+        }
+    }
+}
+
+// Build the layer and its control handle without installing it
+pub fn layer(config: &LogConfig) -> Result<(EngineLogLayer, LogHandle), LoggingError> {
+    let mut overrides = HashMap::new();
+    for (target, level) in &config.overrides {
+        overrides.insert(target.clone(), parse_level(level)?);
+    }
+    let file = match &config.file {
+        Some(file) => Some(Mutex::new(RotatingFile::open(file.clone())?)),
+        None => None,
+    };
+    let shared = Arc::new(Shared {
+        default_level: RwLock::new(parse_level(&config.level)?),
+        overrides: RwLock::new(overrides),
+        json: config.json,
+        stderr: config.stderr,
+        ring: Mutex::new(VecDeque::with_capacity(config.ring_buffer.min(4096))),
+        ring_capacity: config.ring_buffer,
+        file,
+    });
+    Ok((EngineLogLayer { shared: shared.clone() }, LogHandle { shared }))
+}
+
+// Install the engine logger as the global tracing subscriber
+pub fn init(config: &LogConfig) -> Result<LogHandle, LoggingError> {
+    let (layer, handle) = layer(config)?;
+    tracing_subscriber::registry()
+        .with(layer)
+        .try_init()
+        .map_err(|e| LoggingError::AlreadyInitialized(e.to_string()))?;
+    Ok(handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> LogConfig {
+        LogConfig { stderr: false, ring_buffer: 3, ..Default::default() }
+    }
+
+    fn messages(handle: &LogHandle, query: &LogQuery) -> Vec<String> {
+        handle.recent(query).into_iter().map(|r| r.message).collect()
+    }
+
+    #[test]
+    fn overrides_pick_the_longest_prefix_and_change_at_runtime() {
+        let mut config = config();
+        config.overrides.insert("arcadia".to_string(), "warn".to_string());
+        config.overrides.insert("arcadia::vector_index".to_string(), "debug".to_string());
+        let (layer, handle) = layer(&config).unwrap();
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(target: "arcadia::dialogue", "hidden");
+            tracing::debug!(target: "arcadia::vector_index::search", hits = 3, "search done");
+            tracing::info!(target: "game", "default level");
+            handle.set_override("arcadia::dialogue", "info").unwrap();
+            tracing::info!(target: "arcadia::dialogue", "now shown");
+            handle.clear_override("arcadia::dialogue");
+            handle.set_level("error").unwrap();
+            tracing::info!(target: "game", "muted");
+        });
+
+        assert_eq!(messages(&handle, &LogQuery::default()), vec!["now shown", "default level", "search done"]);
+        let query = LogQuery { target_prefix: Some("arcadia::vector".to_string()), ..Default::default() };
+        let search = &handle.recent(&query)[0];
+        assert_eq!(search.level, "DEBUG");
+        assert_eq!(search.fields["hits"], 3);
+    }
+
+    #[test]
+    fn ring_buffer_keeps_the_newest_records_and_filters_queries() {
+        let (layer, handle) = layer(&config()).unwrap();
+        tracing::subscriber::with_default(tracing_subscriber::registry().with(layer), || {
+            tracing::info!("one");
+            tracing::warn!("two");
+            tracing::error!("three");
+            tracing::warn!("four");
+        });
+
+        assert_eq!(messages(&handle, &LogQuery::default()), vec!["four", "three", "two"]);
+        let warnings = LogQuery { min_level: Some(Level::WARN), limit: 2, ..Default::default() };
+        assert_eq!(messages(&handle, &warnings), vec!["four", "three"]);
+        let errors = LogQuery { min_level: Some(Level::ERROR), ..Default::default() };
+        assert_eq!(messages(&handle, &errors), vec!["three"]);
+        let lookup = LogQuery { contains: Some("tw".to_string()), ..Default::default() };
+        assert_eq!(messages(&handle, &lookup), vec!["two"]);
+
+        let exported: Vec<Value> = handle.export_recent().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        let oldest_first: Vec<&str> = exported.iter().map(|r| r["message"].as_str().unwrap()).collect();
+        assert_eq!(oldest_first, vec!["two", "three", "four"]);
+    }
+
+    #[test]
+    fn file_sink_rotates_by_size() {
+        let dir = std::env::temp_dir().join(format!("arcadia-logging-{}", std::process::id()));
+        let path = dir.join("game.log");
+        let mut file = RotatingFile::open(FileSinkConfig { path: path.clone(), max_bytes: 10, max_files: 2 }).unwrap();
+        for line in ["first", "second", "third", "fourth"] {
+            file.write_line(line).unwrap();
+        }
+
+        let read = |p: &PathBuf| fs::read_to_string(p).unwrap();
+        assert_eq!(read(&path), "fourth\n");
+        assert_eq!(read(&file.rotated(1)), "third\n");
+        assert_eq!(read(&file.rotated(2)), "second\n");
+        assert!(!file.rotated(3).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn invalid_levels_are_rejected() {
+        let mut config = config();
+        config.level = "loud".to_string();
+        assert!(matches!(layer(&config), Err(LoggingError::InvalidLevel(level)) if level == "loud"));
+
+        let (_, handle) = layer(&LogConfig::default()).unwrap();
+        assert!(handle.set_override("arcadia", "verbose").is_err());
+    }
+}
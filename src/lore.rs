@@ -0,0 +1,334 @@
+// World lore consistency checks
+//
+// New facts coming out of content generation are checked against the established knowledge graph
+// before they are accepted, so a character declared dead cannot hand out quests and an NPC
+// cannot both love and hate the same faction. Facts in one batch are also checked against the ones
+// before them, so a quest cannot kill its giver and have them hand it out in the same breath.
+
+use crate::symbolic::{RelationType, SymbolicComputing};
+use crate::validation::ValidationReport;
+
+// A candidate fact proposed by generated content
+#[derive(Debug, Clone)]
+pub enum Fact {
+    // subject.key = value (e.g. Mira.status = dead)
+    Property { subject: String, key: String, value: String },
+    // from --relation--> to
+    Relation { from: String, relation: RelationType, to: String },
+    // actor performs an action (give_quest, speak, trade), optionally on a target
+    Action { actor: String, action: String, target: Option<String> },
+}
+
+impl Fact {
+    // Concepts the fact mentions
+    pub fn subjects(&self) -> Vec<&str> {
+        match self {
+            Fact::Property { subject, .. } => vec![subject],
+            Fact::Relation { from, to, .. } => vec![from, to],
+            Fact::Action { actor, target, .. } => {
+                let mut subjects = vec![actor.as_str()];
+                subjects.extend(target.as_deref());
+                subjects
+            }
+        }
+    }
+}
+
+// A single consistency rule
+pub trait LoreRule {
+    fn name(&self) -> &str;
+    fn check(&self, kb: &SymbolicComputing, fact: &Fact, path: &str, report: &mut ValidationReport);
+}
+
+// Dead characters cannot act or form new relationships
+pub struct DeadCannotAct {
+    pub status_key: String,
+    pub dead_value: String,
+}
+
+impl Default for DeadCannotAct {
+    fn default() -> Self {
+        DeadCannotAct {
+            status_key: "status".to_string(),
+            dead_value: "dead".to_string(),
+        }
+    }
+}
+
+impl DeadCannotAct {
+    fn is_dead(&self, kb: &SymbolicComputing, name: &str) -> bool {
+        kb.property(name, &self.status_key) == Some(self.dead_value.as_str())
+    }
+}
+
+impl LoreRule for DeadCannotAct {
+    fn name(&self) -> &str {
+        "dead_cannot_act"
+    }
+
+    fn check(&self, kb: &SymbolicComputing, fact: &Fact, path: &str, report: &mut ValidationReport) {
+        match fact {
+            Fact::Action { actor, action, .. } if self.is_dead(kb, actor) => {
+                report.error(path, format!("{} is {} and cannot {}", actor, self.dead_value, action));
+            }
+            Fact::Relation { from, relation, to } if self.is_dead(kb, from) => {
+                report.error(path, format!("{} is {} and cannot gain relation {:?} to {}", from, self.dead_value, relation, to));
+            }
+            _ => {}
+        }
+    }
+}
+
+// Established properties may not be silently overwritten (e.g. a character's home town)
+pub struct ImmutableProperties {
+    pub keys: Vec<String>,
+}
+
+impl LoreRule for ImmutableProperties {
+    fn name(&self) -> &str {
+        "immutable_properties"
+    }
+
+    fn check(&self, kb: &SymbolicComputing, fact: &Fact, path: &str, report: &mut ValidationReport) {
+        if let Fact::Property { subject, key, value } = fact {
+            if !self.keys.contains(key) {
+                return;
+            }
+            if let Some(existing) = kb.property(subject, key) {
+                if existing != value {
+                    report.error(path, format!("{}.{} is established as '{}', not '{}'", subject, key, existing, value));
+                }
+            }
+        }
+    }
+}
+
+// Pairs of relation types that cannot hold between the same two concepts
+pub struct ExclusiveRelations {
+    pub pairs: Vec<(RelationType, RelationType)>,
+}
+
+impl Default for ExclusiveRelations {
+    fn default() -> Self {
+        ExclusiveRelations {
+            pairs: vec![(RelationType::Likes, RelationType::Dislikes)],
+        }
+    }
+}
+
+impl LoreRule for ExclusiveRelations {
+    fn name(&self) -> &str {
+        "exclusive_relations"
+    }
+
+    fn check(&self, kb: &SymbolicComputing, fact: &Fact, path: &str, report: &mut ValidationReport) {
+        if let Fact::Relation { from, relation, to } = fact {
+            for (a, b) in &self.pairs {
+                let opposite = if relation == a {
+                    b
+                } else if relation == b {
+                    a
+                } else {
+                    continue;
+                };
+                if kb.has_relation(from, opposite, to) {
+                    report.error(path, format!("{} already has {:?} towards {}, contradicting {:?}", from, opposite, to, relation));
+                }
+            }
+        }
+    }
+}
+
+// Facts about concepts the world has never heard of are worth a second look
+pub struct UnknownConcepts;
+
+impl LoreRule for UnknownConcepts {
+    fn name(&self) -> &str {
+        "unknown_concepts"
+    }
+
+    fn check(&self, kb: &SymbolicComputing, fact: &Fact, path: &str, report: &mut ValidationReport) {
+        for subject in fact.subjects() {
+            if kb.concept(subject).is_none() {
+                report.warning(path, format!("'{}' is not established lore", subject));
+            }
+        }
+    }
+}
+
+// What to do with content that fails the consistency pass
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConsistencyMode {
+    // Reject conflicting content
+    Block,
+    // Accept it but hand the report to designers/moderation
+    Flag,
+}
+
+#[derive(Debug)]
+pub enum LoreVerdict {
+    Accepted(ValidationReport),
+    Flagged(ValidationReport),
+    Blocked(ValidationReport),
+}
+
+impl LoreVerdict {
+    pub fn is_accepted(&self) -> bool {
+        !matches!(self, LoreVerdict::Blocked(_))
+    }
+
+    pub fn report(&self) -> &ValidationReport {
+        match self {
+            LoreVerdict::Accepted(r) | LoreVerdict::Flagged(r) | LoreVerdict::Blocked(r) => r,
+        }
+    }
+}
+
+// Runs lore rules over candidate facts
+pub struct LoreChecker {
+    rules: Vec<Box<dyn LoreRule>>,
+    mode: ConsistencyMode,
+}
+
+impl LoreChecker {
+    pub fn new(mode: ConsistencyMode) -> Self {
+        LoreChecker { rules: Vec::new(), mode }
+    }
+
+    // Checker with the built-in rules enabled
+    pub fn with_default_rules(mode: ConsistencyMode) -> Self {
+        let mut checker = LoreChecker::new(mode);
+        checker.add_rule(Box::new(DeadCannotAct::default()));
+        checker.add_rule(Box::new(ExclusiveRelations::default()));
+        checker.add_rule(Box::new(UnknownConcepts));
+        checker
+    }
+
+    pub fn add_rule(&mut self, rule: Box<dyn LoreRule>) {
+        self.rules.push(rule);
+    }
+
+    pub fn check(&self, kb: &SymbolicComputing, facts: &[Fact]) -> ValidationReport {
+        let mut report = ValidationReport::new();
+        // Lore as it would be with the batch's earlier facts committed
+        let mut staged = (facts.len() > 1).then(|| kb.clone());
+        for (i, fact) in facts.iter().enumerate() {
+            let path = format!("facts[{}]", i);
+            let kb = staged.as_ref().unwrap_or(kb);
+            for rule in &self.rules {
+                rule.check(kb, fact, &path, &mut report);
+            }
+            if let Some(staged) = staged.as_mut() {
+                commit_fact(staged, fact);
+            }
+        }
+        report
+    }
+
+    pub fn review(&self, kb: &SymbolicComputing, facts: &[Fact]) -> LoreVerdict {
+        let report = self.check(kb, facts);
+        if report.is_valid() {
+            LoreVerdict::Accepted(report)
+        } else {
+            match self.mode {
+                ConsistencyMode::Block => LoreVerdict::Blocked(report),
+                ConsistencyMode::Flag => LoreVerdict::Flagged(report),
+            }
+        }
+    }
+
+    // Review facts and write them into the knowledge graph unless they were blocked
+    pub fn review_and_commit(&self, kb: &mut SymbolicComputing, facts: &[Fact]) -> LoreVerdict {
+        let verdict = self.review(kb, facts);
+        if verdict.is_accepted() {
+            for fact in facts {
+                commit_fact(kb, fact);
+            }
+        }
+        verdict
+    }
+}
+
+fn commit_fact(kb: &mut SymbolicComputing, fact: &Fact) {
+    match fact {
+        Fact::Property { subject, key, value } => kb.set_property(subject, key, value),
+        Fact::Relation { from, relation, to } => kb.add_relation(from, relation.clone(), to, 1.0),
+        // Actions are events, not lasting facts
+        Fact::Action { .. } => {}
+    }
+}
+
+// Facts implied by a quest produced from the `quest_structure` generation template
+pub fn quest_facts(quest: &serde_json::Value) -> Vec<Fact> {
+    let mut facts = Vec::new();
+    let giver = quest.get("giver").and_then(|g| g.as_str());
+    if let Some(giver) = giver {
+        facts.push(Fact::Action {
+            actor: giver.to_string(),
+            action: "give_quest".to_string(),
+            target: None,
+        });
+    }
+    let objectives = quest.get("objectives").and_then(|o| o.as_array());
+    for objective in objectives.into_iter().flatten() {
+        let kind = objective.get("kind").and_then(|k| k.as_str());
+        let target = objective.get("target").and_then(|t| t.as_str());
+        if let (Some("talk" | "escort"), Some(target)) = (kind, target) {
+            // The other party has to be around to be talked to or escorted
+            facts.push(Fact::Action {
+                actor: target.to_string(),
+                action: "appear_in_quest".to_string(),
+                target: giver.map(str::to_string),
+            });
+        }
+    }
+    facts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kb() -> SymbolicComputing {
+        let mut kb = SymbolicComputing::new();
+        kb.set_property("Mira", "status", "alive");
+        kb.add_concept("Guild");
+        kb
+    }
+
+    fn relation(relation: RelationType) -> Fact {
+        Fact::Relation { from: "Mira".to_string(), relation, to: "Guild".to_string() }
+    }
+
+    fn error_paths(report: &ValidationReport) -> Vec<&str> {
+        report.errors().map(|issue| issue.path.as_str()).collect()
+    }
+
+    #[test]
+    fn contradictions_within_a_batch_are_caught() {
+        let checker = LoreChecker::with_default_rules(ConsistencyMode::Block);
+        let report = checker.check(&kb(), &[relation(RelationType::Likes), relation(RelationType::Dislikes)]);
+        assert_eq!(error_paths(&report), vec!["facts[1]"]);
+
+        let killed_then_acts = [
+            Fact::Property { subject: "Mira".to_string(), key: "status".to_string(), value: "dead".to_string() },
+            Fact::Action { actor: "Mira".to_string(), action: "give_quest".to_string(), target: None },
+        ];
+        let mut kb = kb();
+        assert!(!checker.review_and_commit(&mut kb, &killed_then_acts).is_accepted());
+        assert_eq!(kb.property("Mira", "status"), Some("alive"));
+    }
+
+    #[test]
+    fn order_matters_and_lore_is_untouched_by_checks() {
+        let checker = LoreChecker::with_default_rules(ConsistencyMode::Block);
+        let kb = kb();
+        let acts_then_dies = [
+            Fact::Action { actor: "Mira".to_string(), action: "give_quest".to_string(), target: None },
+            Fact::Property { subject: "Mira".to_string(), key: "status".to_string(), value: "dead".to_string() },
+        ];
+        assert!(checker.check(&kb, &acts_then_dies).is_valid());
+        assert!(!kb.has_relation("Mira", &RelationType::Likes, "Guild"));
+        assert_eq!(kb.property("Mira", "status"), Some("alive"));
+    }
+}
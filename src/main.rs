@@ -6,8 +6,90 @@
 // Import necessary crates and modules
 use std::fs::File;
 use std::io::prelude::*;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use serde::Deserialize;
+use auth::{Authentication, AuthenticationConfig};
+use emotion::EmotionAdaptiveExperiences;
+use symbolic::SymbolicComputing;
+use vector_index::{VectorIndex, VectorIndexConfig};
+
+// Engine subsystems
+mod agentdb;
+mod aggregation;
+mod ai;
+mod analytics;
+mod anomaly;
+mod archive;
+mod auth;
+mod autopoietic;
+mod bandit;
+#[cfg(feature = "bevy")]
+mod bevy_plugin;
+mod cache;
+mod capabilities;
+mod chunking;
+mod commitments;
+mod compaction;
+mod config;
+mod cost;
+mod curriculum;
+#[cfg(feature = "debug-server")]
+mod debug_server;
+mod determinism;
+mod dialogue;
+mod economy;
+mod embedding_migration;
+mod embeddings;
+mod emotion;
+mod environment;
+mod events;
+#[cfg(feature = "ffi")]
+mod ffi;
+mod fixed;
+mod generation;
+mod genome;
+#[cfg(feature = "godot")]
+mod godot_ext;
+mod gossip;
+mod guard;
+mod history;
+mod inference;
+mod leaderboards;
+mod lifecycle;
+mod llm_router;
+mod local_embeddings;
+mod logging;
+mod lore;
+mod memory;
+mod memory_budget;
+mod multiplayer;
+mod namespace;
+mod paris;
+mod perception;
+mod player_model;
+mod prompts;
+#[cfg(feature = "python")]
+mod python;
+mod rating;
+mod relationships;
+mod resilience;
+mod retrieval_eval;
+mod rng;
+mod rumors;
+mod search_tuning;
+mod security;
+mod sharding;
+mod similarity;
+mod snapshot;
+mod sparse;
+mod spatial;
+mod streaming;
+mod symbolic;
+mod validation;
+mod vector_index;
+mod versioning;
+mod workflow;
+mod world;
 
 // AiTomL manifest definition
 #[derive(Debug, Deserialize)]
@@ -15,47 +97,34 @@ struct AiToml {
     vector_index: VectorIndexConfig,
     authentication: AuthenticationConfig,
     game_elements: HashMap<String, GameElement>,
-}
-
-// Vector Index configuration
-#[derive(Debug, Deserialize)]
-struct VectorIndexConfig {
-    url: String,
-    api_key: String,
-}
-
-// Authentication configuration
-#[derive(Debug, Deserialize)]
-struct AuthenticationConfig {
-    provider: String,
-    credentials: Credentials,
-}
-
-// Credentials definition
-#[derive(Debug, Deserialize)]
-struct Credentials {
-    client_id: String,
-    client_secret: String,
+    #[serde(default)]
+    behavior_profiles: Option<ai::profiles::ProfileSet>,
+    #[serde(default)]
+    memory: Option<memory::MemoryConfig>,
+    #[serde(default)]
+    memory_budget: Option<memory_budget::BudgetConfig>,
+    #[serde(default)]
+    workflow_triggers: BTreeMap<String, workflow::triggers::TriggerConfig>,
+    #[serde(default)]
+    data_residency: Option<security::residency::ResidencyConfig>,
+    #[serde(default)]
+    guardrails: BTreeMap<String, paris::guardrails::SafeRange>,
+    #[serde(default)]
+    workflow_schedules: BTreeMap<String, workflow::schedule::ScheduleConfig>,
+    #[serde(default)]
+    logging: Option<logging::LogConfig>,
+    #[serde(default)]
+    encryption: Option<security::encryption::EncryptionConfig>,
 }
 
 // Game elements definition
 #[derive(Debug, Deserialize)]
 struct GameElement {
     element_type: String,
+    #[serde(default)]
     properties: HashMap<String, String>,
 }
 
-// Authentication module
-struct Authentication {
-    // TODO: Implement authentication using the specified provider and credentials
-}
-
-impl Authentication {
-    pub fn new(config: AuthenticationConfig) -> Self {
-        // TODO: Initialize and configure the authentication module
-    }
-}
-
 // Game elements module
 struct GameElements {
     // TODO: Implement game elements and their interactions with the Vector Index
@@ -152,18 +221,17 @@ impl CodeDNA {
             natural_laws.to_vec(),
         )
     }
-}
 
-// Example function to apply the CodeDNA attributes to the game world
-pub fn apply_code_dna_to_game_world(&self, game_world: &mut GameWorld) {
-    game_world.set_setting(&self.0);
-    game_world.set_technology(&self.1);
-    game_world.set_physics_laws(&self.2);
-    game_world.set_themes(&self.3);
-    game_world.set_time_scale(self.4);
-    game_world.set_entropy_rate(self.5);
-    game_world.set_natural_laws(&self.6);
-}
+    // Example function to apply the CodeDNA attributes to the game world
+    pub fn apply_code_dna_to_game_world(&self, game_world: &mut GameWorld) {
+        game_world.set_setting(&self.0);
+        game_world.set_technology(&self.1);
+        game_world.set_physics_laws(&self.2);
+        game_world.set_themes(&self.3);
+        game_world.set_time_scale(self.4);
+        game_world.set_entropy_rate(self.5);
+        game_world.set_natural_laws(&self.6);
+    }
 }
 
 // Functional components
@@ -181,11 +249,6 @@ struct NeoCortexReasoning {
 // TODO: Implement neo-cortex higher-order reasoning
 }
 
-// Symbolic or sub-symbolic computing
-struct SymbolicComputing {
-// TODO: Implement symbolic or sub-symbolic computing
-}
-
 // Autopoetic processing
 struct AutopoeticProcessing {
 // TODO: Implement autopoetic processing
@@ -211,11 +274,6 @@ struct Entropy {
 // TODO: Implement entropy
 }
 
-// Emotion-adaptive experiences
-struct EmotionAdaptiveExperiences {
-// TODO: Implement emotion-adaptive experiences
-}
-
 // Social constructs
 struct SocialConstructs {
 // TODO: Implement social constructs
@@ -260,7 +318,7 @@ impl AdvancedAdaptiveProceduralGamingSystem {
     pub fn new(config: AiToml) -> Self {
         AdvancedAdaptiveProceduralGamingSystem {
             vector_index: VectorIndex::new(config.vector_index),
-            auth: Auth::new(config.authentication),
+            auth: Authentication::new(config.authentication),
             game_elements: GameElements::new(config.game_elements),
             openai_api: OpenAiApi::new(config.vector_index.api_key.clone()),
             unreal_engine_api: UnrealEngineApi::new(),
@@ -272,13 +330,69 @@ impl AdvancedAdaptiveProceduralGamingSystem {
 
 // Main entry point
 fn main() {
+    // `migrate [--dry-run] [--format=<format>] <file>...` upgrades persisted files and exits
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("migrate") {
+        match versioning::run_migrate_command(&args[1..]) {
+            Ok(report) => print!("{}", report),
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // `capabilities` prints what this build supports, for tools and bug reports
+    if args.first().map(String::as_str) == Some("capabilities") {
+        print!("{}", capabilities::capabilities());
+        return;
+    }
+
+    // `determinism record|check <golden.json>` records or verifies per-tick simulation hashes
+    if args.first().map(String::as_str) == Some("determinism") {
+        match determinism::run_determinism_command(&args[1..]) {
+            Ok(report) => print!("{}", report),
+            Err(err) => {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // `bench-sharding [entities] [threads]` compares a global lock with sharded entity state
+    if args.first().map(String::as_str) == Some("bench-sharding") {
+        let arg = |i: usize, default: usize| args.get(i).and_then(|a| a.parse().ok()).unwrap_or(default);
+        println!("{}", sharding::contention_benchmark(arg(1, 10_000), arg(2, 8), 20));
+        return;
+    }
+
+    // `bench-similarity [queries] [corpus] [dim]` compares the CPU and GPU batch similarity paths
+    if args.first().map(String::as_str) == Some("bench-similarity") {
+        similarity::run_bench_command(&args[1..]);
+        return;
+    }
+
     // Read AiTomL configuration
     let mut file = File::open("config.toml").expect("Unable to open the config.toml file");
     let mut contents = String::new();
     file.read_to_string(&mut contents).expect("Unable to read the config.toml file");
     
-    // Parse AiTomL configuration
-    let config: AiToml = toml::from_str(&contents).expect("Unable to parse the config.toml file");
+    // Parse AiTomL configuration, overlay defaults and environment, and check it against the schema
+    let file = contents.parse::<toml::Table>().map(toml::Value::Table).expect("Unable to parse the config.toml file");
+    let layered = config::LayeredConfig::new().file("config.toml", &file).env(std::env::vars());
+    let report = layered.validate();
+    eprint!("{}", report);
+    if !report.is_valid() {
+        std::process::exit(1);
+    }
+    // `config` prints the effective configuration and exits
+    if args.first().map(String::as_str) == Some("config") {
+        print!("{}", layered.print());
+        return;
+    }
+    let config: AiToml = layered.into_value().try_into().expect("Unable to parse the config.toml file");
     
     // Initialize the AdvancedAdaptiveProceduralGamingSystem with the configuration
     let game_system = AdvancedAdaptiveProceduralGamingSystem::new(config);
@@ -286,10 +400,8 @@ fn main() {
     // TODO: Add main game logic and interaction with the OpenAI API GPT-3.5-Turbo and Unreal Engine 5
 }
 
-
-    // TODO: Implement methods for interacting with the GPT-3.5-Turbo API
-    // Example: pub async fn generate_text(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> { ... }
-}
+// TODO: Implement methods for interacting with the GPT-3.5-Turbo API
+// Example: pub async fn generate_text(&self, prompt: &str) -> Result<String, Box<dyn std::error::Error>> { ... }
 
 // Note that the actual implementation of the OpenAi struct and methods to interact with the GPT-3.5-Turbo API
 // will require the use of an asynchronous HTTP client, like reqwest or surf, along with the necessary error handling.
@@ -0,0 +1,419 @@
+// Memory accounting and pressure-based eviction
+//
+// Constrained targets (WASM, consoles) have a fixed memory budget and no swap, so running out is
+// a crash. Subsystems holding large amounts of data (vector store, caches, emotion history)
+// report an estimate of their size through MemoryAccounted. The accountant sums the estimates
+// against a soft limit from the [memory] config section; above the limit it asks subsystems to
+// give memory back, cheapest loss first (cold cache entries before downsampled history before
+// stored memories), until usage is under the target again. Pressure listeners are told about
+// every level change so games can react too (drop texture quality, stop prefetching).
+//
+// Estimates are approximate: payload sizes are measured, allocator and hash map overhead is not.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::cache::Cache;
+use crate::emotion::timeline::EmotionTimelines;
+use crate::events::EventBus;
+use crate::vector_index::{unix_now, VectorIndex};
+
+pub const PRESSURE_TOPIC: &str = "memory.pressure";
+
+pub trait MemoryAccounted {
+    fn subsystem(&self) -> &str;
+
+    fn estimated_bytes(&self) -> usize;
+
+    // Lower goes first; an eviction that loses nothing players would notice should be low
+    fn eviction_priority(&self) -> u32 {
+        100
+    }
+
+    // Try to free about `bytes`; returns an estimate of what was freed
+    fn evict(&mut self, _bytes: usize) -> usize {
+        0
+    }
+}
+
+pub type SharedAccounted = Arc<Mutex<dyn MemoryAccounted + Send>>;
+
+fn default_target_ratio() -> f64 {
+    0.8
+}
+
+fn default_elevated_ratio() -> f64 {
+    0.9
+}
+
+// The [memory] section of the aiTOML manifest
+#[derive(Debug, Clone, Deserialize)]
+pub struct MemoryConfig {
+    pub soft_limit_mb: u64,
+    // Evict down to this share of the limit
+    #[serde(default = "default_target_ratio")]
+    pub target_ratio: f64,
+    // Share of the limit at which pressure is reported as elevated
+    #[serde(default = "default_elevated_ratio")]
+    pub elevated_ratio: f64,
+}
+
+impl MemoryConfig {
+    pub fn soft_limit_bytes(&self) -> usize {
+        (self.soft_limit_mb as usize).saturating_mul(1024 * 1024)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Pressure {
+    Normal,
+    Elevated,
+    // Over the soft limit
+    Critical,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemoryReport {
+    pub limit: usize,
+    pub used: usize,
+    pub pressure: Pressure,
+    pub by_subsystem: BTreeMap<String, usize>,
+    // Subsystem -> bytes freed in this check
+    pub evicted: BTreeMap<String, usize>,
+}
+
+impl fmt::Display for MemoryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "memory: {} of {} ({:?})", format_bytes(self.used), format_bytes(self.limit), self.pressure)?;
+        for (subsystem, bytes) in &self.by_subsystem {
+            write!(f, "  {}: {}", subsystem, format_bytes(*bytes))?;
+            if let Some(freed) = self.evicted.get(subsystem) {
+                write!(f, " (freed {})", format_bytes(*freed))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+fn format_bytes(bytes: usize) -> String {
+    if bytes >= 1 << 20 {
+        format!("{:.1} MiB", bytes as f64 / (1 << 20) as f64)
+    } else {
+        format!("{:.1} KiB", bytes as f64 / 1024.0)
+    }
+}
+
+type PressureListener = Box<dyn FnMut(Pressure, &MemoryReport) + Send>;
+
+pub struct MemoryAccountant {
+    config: MemoryConfig,
+    subsystems: Vec<SharedAccounted>,
+    listeners: Vec<PressureListener>,
+    pressure: Pressure,
+}
+
+impl MemoryAccountant {
+    pub fn new(config: MemoryConfig) -> Self {
+        MemoryAccountant { config, subsystems: Vec::new(), listeners: Vec::new(), pressure: Pressure::Normal }
+    }
+
+    pub fn register(&mut self, subsystem: SharedAccounted) {
+        self.subsystems.push(subsystem);
+    }
+
+    // Called whenever the pressure level changes
+    pub fn on_pressure(&mut self, listener: impl FnMut(Pressure, &MemoryReport) + Send + 'static) {
+        self.listeners.push(Box::new(listener));
+    }
+
+    pub fn pressure(&self) -> Pressure {
+        self.pressure
+    }
+
+    fn usage(&self) -> BTreeMap<String, usize> {
+        let mut usage = BTreeMap::new();
+        for subsystem in &self.subsystems {
+            let subsystem = subsystem.lock().unwrap();
+            *usage.entry(subsystem.subsystem().to_string()).or_insert(0) += subsystem.estimated_bytes();
+        }
+        usage
+    }
+
+    fn level(&self, used: usize) -> Pressure {
+        let limit = self.config.soft_limit_bytes();
+        if used > limit {
+            Pressure::Critical
+        } else if used as f64 > limit as f64 * self.config.elevated_ratio {
+            Pressure::Elevated
+        } else {
+            Pressure::Normal
+        }
+    }
+
+    // Measure, evict if over the soft limit, and notify listeners; call once per frame or tick
+    pub fn check(&mut self, events: Option<&mut EventBus>) -> MemoryReport {
+        let limit = self.config.soft_limit_bytes();
+        let mut by_subsystem = self.usage();
+        let mut used: usize = by_subsystem.values().sum();
+        let mut evicted = BTreeMap::new();
+
+        if used > limit {
+            let target = (limit as f64 * self.config.target_ratio) as usize;
+            let mut order: Vec<&SharedAccounted> = self.subsystems.iter().collect();
+            order.sort_by_key(|s| s.lock().unwrap().eviction_priority());
+            for subsystem in order {
+                if used <= target {
+                    break;
+                }
+                let mut subsystem = subsystem.lock().unwrap();
+                let freed = subsystem.evict(used - target).min(used);
+                if freed > 0 {
+                    used -= freed;
+                    *evicted.entry(subsystem.subsystem().to_string()).or_insert(0) += freed;
+                }
+            }
+            by_subsystem = self.usage();
+            used = by_subsystem.values().sum();
+        }
+
+        let pressure = self.level(used);
+        let report = MemoryReport { limit, used, pressure, by_subsystem, evicted };
+        if pressure != self.pressure || !report.evicted.is_empty() {
+            if let Some(events) = events {
+                events.emit(
+                    PRESSURE_TOPIC,
+                    "memory",
+                    json!({ "pressure": format!("{:?}", pressure), "used": used, "limit": limit, "evicted": report.evicted }),
+                );
+            }
+        }
+        if pressure != self.pressure {
+            self.pressure = pressure;
+            for listener in &mut self.listeners {
+                listener(pressure, &report);
+            }
+        }
+        report
+    }
+}
+
+// Rough size of a JSON value, for payload estimates
+pub fn json_bytes(value: &Value) -> usize {
+    match value {
+        Value::Null | Value::Bool(_) | Value::Number(_) => 16,
+        Value::String(s) => 24 + s.len(),
+        Value::Array(items) => 24 + items.iter().map(json_bytes).sum::<usize>(),
+        Value::Object(map) => 48 + map.iter().map(|(k, v)| 24 + k.len() + json_bytes(v)).sum::<usize>(),
+    }
+}
+
+// Cache entries are cheap to recreate, so the cache gives memory back first
+pub struct AccountedCache<V> {
+    pub name: String,
+    pub cache: Cache<V>,
+    // Estimated size of one value
+    pub value_bytes: usize,
+}
+
+impl<V: Clone> MemoryAccounted for AccountedCache<V> {
+    fn subsystem(&self) -> &str {
+        &self.name
+    }
+
+    fn estimated_bytes(&self) -> usize {
+        self.cache.estimated_bytes(self.value_bytes)
+    }
+
+    fn eviction_priority(&self) -> u32 {
+        10
+    }
+
+    fn evict(&mut self, bytes: usize) -> usize {
+        let before = self.estimated_bytes();
+        self.cache.shrink_by(bytes, self.value_bytes);
+        before.saturating_sub(self.estimated_bytes())
+    }
+}
+
+impl MemoryAccounted for EmotionTimelines {
+    fn subsystem(&self) -> &str {
+        "emotion_history"
+    }
+
+    fn estimated_bytes(&self) -> usize {
+        self.estimated_bytes()
+    }
+
+    // Coarser history loses detail, not moments
+    fn eviction_priority(&self) -> u32 {
+        20
+    }
+
+    fn evict(&mut self, bytes: usize) -> usize {
+        let before = self.estimated_bytes();
+        self.downsample(bytes);
+        before.saturating_sub(self.estimated_bytes())
+    }
+}
+
+impl MemoryAccounted for VectorIndex {
+    fn subsystem(&self) -> &str {
+        "vector_index"
+    }
+
+    fn estimated_bytes(&self) -> usize {
+        self.collection_names()
+            .into_iter()
+            .filter_map(|name| self.collection(name).ok())
+            .flat_map(|c| c.points.values())
+            .map(|p| 64 + p.id.len() + p.vector.len() * 4 + p.payload.iter().map(|(k, v)| k.len() + json_bytes(v)).sum::<usize>())
+            .sum()
+    }
+
+    // Only expired points are dropped; live memories are never evicted to make room
+    fn eviction_priority(&self) -> u32 {
+        90
+    }
+
+    fn evict(&mut self, _bytes: usize) -> usize {
+        let before = MemoryAccounted::estimated_bytes(self);
+        self.collect_garbage(unix_now());
+        before.saturating_sub(MemoryAccounted::estimated_bytes(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::namespace::Namespace;
+
+    const MIB: usize = 1024 * 1024;
+
+    // A subsystem holding `bytes`, of which `spare` can be given back
+    struct Pool {
+        name: &'static str,
+        bytes: usize,
+        spare: usize,
+        priority: u32,
+    }
+
+    impl MemoryAccounted for Pool {
+        fn subsystem(&self) -> &str {
+            self.name
+        }
+
+        fn estimated_bytes(&self) -> usize {
+            self.bytes
+        }
+
+        fn eviction_priority(&self) -> u32 {
+            self.priority
+        }
+
+        fn evict(&mut self, bytes: usize) -> usize {
+            let freed = bytes.min(self.spare);
+            self.spare -= freed;
+            self.bytes -= freed;
+            freed
+        }
+    }
+
+    fn pool(name: &'static str, bytes: usize, spare: usize, priority: u32) -> Arc<Mutex<Pool>> {
+        Arc::new(Mutex::new(Pool { name, bytes, spare, priority }))
+    }
+
+    fn config() -> MemoryConfig {
+        MemoryConfig { soft_limit_mb: 1, target_ratio: 0.8, elevated_ratio: 0.9 }
+    }
+
+    #[test]
+    fn listeners_hear_each_pressure_change_once() {
+        let vectors = pool("vectors", MIB / 2, 0, 90);
+        let mut accountant = MemoryAccountant::new(config());
+        accountant.register(vectors.clone());
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        accountant.on_pressure(move |pressure, _| sink.lock().unwrap().push(pressure));
+
+        assert_eq!(accountant.check(None).pressure, Pressure::Normal);
+        vectors.lock().unwrap().bytes = MIB * 95 / 100;
+        assert_eq!(accountant.check(None).pressure, Pressure::Elevated);
+        accountant.check(None);
+        vectors.lock().unwrap().bytes = 2 * MIB;
+        let report = accountant.check(None);
+        assert_eq!((report.pressure, report.used), (Pressure::Critical, 2 * MIB));
+        assert!(report.evicted.is_empty());
+        assert_eq!(accountant.pressure(), Pressure::Critical);
+        assert_eq!(*seen.lock().unwrap(), vec![Pressure::Elevated, Pressure::Critical]);
+    }
+
+    #[test]
+    fn eviction_takes_the_cheapest_loss_first_down_to_the_target() {
+        let mut accountant = MemoryAccountant::new(config());
+        let vectors = pool("vectors", 500_000, 500_000, 90);
+        accountant.register(vectors.clone());
+        accountant.register(pool("history", 400_000, 400_000, 20));
+        accountant.register(pool("cache", 300_000, 100_000, 10));
+        let mut bus = EventBus::new(4);
+        let sub = bus.subscribe(PRESSURE_TOPIC);
+
+        let report = accountant.check(Some(&mut bus));
+        let target = (MIB as f64 * 0.8) as usize;
+        assert_eq!(report.used, target);
+        assert_eq!(report.pressure, Pressure::Normal);
+        assert_eq!(report.evicted.get("cache"), Some(&100_000));
+        assert_eq!(report.evicted.get("history"), Some(&(1_200_000 - target - 100_000)));
+        assert!(!report.evicted.contains_key("vectors"));
+        assert_eq!(vectors.lock().unwrap().bytes, 500_000);
+        assert_eq!(report.by_subsystem["cache"], 200_000);
+
+        let events = bus.drain(sub);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].payload["pressure"], "Normal");
+        assert_eq!(events[0].payload["evicted"]["cache"], 100_000);
+        // Nothing changed, so nothing is published
+        accountant.check(Some(&mut bus));
+        assert!(bus.drain(sub).is_empty());
+    }
+
+    #[test]
+    fn caches_give_back_their_coldest_entries() {
+        let namespace = Namespace::default_namespace();
+        let mut cache = Cache::new(16);
+        for i in 0..10 {
+            cache.insert(&namespace, &format!("k{}", i), i);
+        }
+        cache.get(&namespace, "k0");
+        let mut accounted = AccountedCache { name: "responses".to_string(), cache, value_bytes: 100 };
+        let entry = accounted.estimated_bytes() / 10;
+        let freed = accounted.evict(2 * entry + 1);
+        assert_eq!(freed, 3 * entry);
+        assert_eq!(accounted.cache.len(), 7);
+        // k0 was used recently and survives; k1..k3 were the coldest
+        assert_eq!(accounted.cache.get(&namespace, "k0"), Some(0));
+        assert_eq!(accounted.cache.get(&namespace, "k3"), None);
+        assert_eq!(accounted.cache.get(&namespace, "k4"), Some(4));
+    }
+
+    #[test]
+    fn config_defaults_sizes_and_report_text() {
+        let config: MemoryConfig = toml::from_str("soft_limit_mb = 64").unwrap();
+        assert_eq!((config.target_ratio, config.elevated_ratio), (0.8, 0.9));
+        assert_eq!(config.soft_limit_bytes(), 64 * MIB);
+
+        assert_eq!(json_bytes(&json!(null)), 16);
+        assert_eq!(json_bytes(&json!("abcd")), 28);
+        assert_eq!(json_bytes(&json!([1, 2])), 56);
+        assert_eq!(json_bytes(&json!({ "ab": true })), 48 + 24 + 2 + 16);
+
+        let mut accountant = MemoryAccountant::new(MemoryConfig { soft_limit_mb: 2, ..config });
+        accountant.register(pool("cache", 3 * 1024, 0, 10));
+        let text = accountant.check(None).to_string();
+        assert_eq!(text, "memory: 3.0 KiB of 2.0 MiB (Normal)\n  cache: 3.0 KiB\n");
+    }
+}
@@ -0,0 +1,480 @@
+// Per-entity memory budgets
+//
+// A long-running server keeps adding NPC memories (conversation summaries, observations, gossip)
+// and never forgets any of them. Each NPC gets a budget instead: a maximum number of points in
+// the memory collection, from the [memory_budget] section of aiTOML with per-NPC overrides. When
+// an NPC is over budget its least important memories are consolidated rather than dropped: the
+// least important one seeds a cluster, joined by the most similar of the other memories, and the
+// cluster is summarized into a single memory that replaces the originals.
+//
+// Importance is the memory's "importance" payload field (0..1, 0.5 when absent); memories at or
+// above `keep_importance` are never touched. A cluster only mixes memories about the same player,
+// so deleting a player's data (privacy.rs) still finds everything about them. Seeds further up
+// the importance order are tried when the least important memory has nothing similar; when no
+// memory has, the least important ones about the same player are merged regardless, and when no
+// player has two mergeable memories the least important memory is evicted (soft-deleted, so it
+// can still be archived or restored), because the budget has to hold. Soft-deleted and expired
+// memories do not count against the budget.
+//
+// The summary comes from the MEMORY_CONSOLIDATION prompt through any TextGenerator; without one,
+// or when generation fails, the memories are joined extractively. The consolidated memory keeps
+// the highest importance of its members and lists the original ids in "consolidated_from" (also
+// across repeated consolidations), so provenance links to them (summary facts, history entries)
+// can still be followed. Every consolidation is published as a "memory.consolidated" event and
+// every eviction as "memory.evicted".
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::embeddings::{cosine_similarity, Embedder};
+use crate::events::EventBus;
+use crate::generation::TextGenerator;
+use crate::prompts::{self, PromptRegistry};
+use crate::vector_index::{VectorIndex, VectorIndexError, VectorPoint, PLAYER_FIELD, TEXT_FIELD};
+
+pub const IMPORTANCE_FIELD: &str = "importance";
+pub const CONSOLIDATED_FROM_FIELD: &str = "consolidated_from";
+pub const CONSOLIDATION_TOPIC: &str = "memory.consolidated";
+pub const EVICTION_TOPIC: &str = "memory.evicted";
+
+// Importance of memories stored without one
+const DEFAULT_IMPORTANCE: f64 = 0.5;
+
+fn default_max_points() -> usize {
+    200
+}
+
+fn default_entity_field() -> String {
+    "npc".to_string()
+}
+
+fn default_similarity() -> f32 {
+    0.75
+}
+
+fn default_cluster_size() -> usize {
+    8
+}
+
+fn default_keep_importance() -> f64 {
+    0.9
+}
+
+fn default_summary_words() -> usize {
+    60
+}
+
+// The [memory_budget] section of the aiTOML manifest
+#[derive(Debug, Clone, Deserialize)]
+pub struct BudgetConfig {
+    // Memories per entity unless overridden
+    #[serde(default = "default_max_points")]
+    pub max_points: usize,
+    // Per-entity budgets
+    #[serde(default)]
+    pub entities: HashMap<String, usize>,
+    // Payload field naming the entity a memory belongs to
+    #[serde(default = "default_entity_field")]
+    pub entity_field: String,
+    // Cosine similarity at which a memory joins the seed's cluster
+    #[serde(default = "default_similarity")]
+    pub similarity: f32,
+    // Most memories merged into one
+    #[serde(default = "default_cluster_size")]
+    pub cluster_size: usize,
+    #[serde(default = "default_keep_importance")]
+    pub keep_importance: f64,
+    #[serde(default = "default_summary_words")]
+    pub summary_words: usize,
+}
+
+impl Default for BudgetConfig {
+    fn default() -> Self {
+        BudgetConfig {
+            max_points: default_max_points(),
+            entities: HashMap::new(),
+            entity_field: default_entity_field(),
+            similarity: default_similarity(),
+            cluster_size: default_cluster_size(),
+            keep_importance: default_keep_importance(),
+            summary_words: default_summary_words(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Consolidation {
+    pub entity: String,
+    // Id of the new memory
+    pub memory: String,
+    pub merged: Vec<String>,
+    pub summary: String,
+    // False when the extractive fallback was used
+    pub generated: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct BudgetReport {
+    pub entity: String,
+    pub budget: usize,
+    pub before: usize,
+    pub after: usize,
+    pub consolidations: Vec<Consolidation>,
+    // Memories soft-deleted because nothing could be merged
+    pub evicted: Vec<String>,
+}
+
+impl BudgetReport {
+    pub fn within_budget(&self) -> bool {
+        self.after <= self.budget
+    }
+}
+
+pub struct MemoryBudgets {
+    pub config: BudgetConfig,
+    prompts: Option<PromptRegistry>,
+    // Consolidations not yet published
+    pending: Vec<Consolidation>,
+    // (entity, memory) evictions not yet published
+    pending_evictions: Vec<(String, String)>,
+}
+
+impl MemoryBudgets {
+    pub fn new(config: BudgetConfig) -> Self {
+        MemoryBudgets { config, prompts: None, pending: Vec::new(), pending_evictions: Vec::new() }
+    }
+
+    pub fn with_prompts(mut self, registry: PromptRegistry) -> Self {
+        self.prompts = Some(registry);
+        self
+    }
+
+    pub fn set_budget(&mut self, entity: &str, max_points: usize) {
+        self.config.entities.insert(entity.to_string(), max_points);
+    }
+
+    pub fn budget(&self, entity: &str) -> usize {
+        self.config.entities.get(entity).copied().unwrap_or(self.config.max_points)
+    }
+
+    // Searchable memory counts per entity in the collection
+    pub fn usage(&self, index: &VectorIndex, collection: &str, now: u64) -> Result<HashMap<String, usize>, VectorIndexError> {
+        let mut usage = HashMap::new();
+        for point in index.collection(collection)?.points.values().filter(|p| p.is_searchable(now)) {
+            if let Some(entity) = point.payload.get(&self.config.entity_field).and_then(Value::as_str) {
+                *usage.entry(entity.to_string()).or_insert(0) += 1;
+            }
+        }
+        Ok(usage)
+    }
+
+    // Consolidate one entity's memories until it is within budget (or nothing more can be merged)
+    pub fn enforce(
+        &mut self,
+        index: &mut VectorIndex,
+        collection: &str,
+        entity: &str,
+        generator: Option<&dyn TextGenerator>,
+        embedder: &dyn Embedder,
+        now: u64,
+    ) -> Result<BudgetReport, VectorIndexError> {
+        let budget = self.budget(entity);
+        let mut memories = self.memories(index, collection, entity, now)?;
+        let mut report = BudgetReport { entity: entity.to_string(), budget, before: memories.len(), ..Default::default() };
+
+        while memories.len() > budget {
+            let excess = memories.len() - budget;
+            if let Some(cluster) = self.pick_cluster(&memories, excess) {
+                let consolidation = self.consolidate(index, collection, entity, &cluster, generator, embedder)?;
+                self.pending.push(consolidation.clone());
+                report.consolidations.push(consolidation);
+            } else {
+                // Memories are least important first, so these are the cheapest to lose
+                let evict: Vec<String> = memories
+                    .iter()
+                    .filter(|p| importance(p) < self.config.keep_importance)
+                    .take(excess)
+                    .map(|p| p.id.clone())
+                    .collect();
+                if evict.is_empty() {
+                    break;
+                }
+                index.soft_delete(collection, &evict, now)?;
+                self.pending_evictions.extend(evict.iter().map(|id| (entity.to_string(), id.clone())));
+                report.evicted.extend(evict);
+            }
+            memories = self.memories(index, collection, entity, now)?;
+        }
+        report.after = memories.len();
+        Ok(report)
+    }
+
+    // Enforce the budget of every entity over it, e.g. from a periodic maintenance task
+    pub fn enforce_all(
+        &mut self,
+        index: &mut VectorIndex,
+        collection: &str,
+        generator: Option<&dyn TextGenerator>,
+        embedder: &dyn Embedder,
+        now: u64,
+    ) -> Result<Vec<BudgetReport>, VectorIndexError> {
+        let mut over: Vec<String> = self
+            .usage(index, collection, now)?
+            .into_iter()
+            .filter(|(entity, count)| *count > self.budget(entity))
+            .map(|(entity, _)| entity)
+            .collect();
+        over.sort();
+        let mut reports = Vec::with_capacity(over.len());
+        for entity in over {
+            reports.push(self.enforce(index, collection, &entity, generator, embedder, now)?);
+        }
+        Ok(reports)
+    }
+
+    pub fn publish(&mut self, events: &mut EventBus) -> usize {
+        let pending = std::mem::take(&mut self.pending);
+        for consolidation in &pending {
+            events.emit(
+                CONSOLIDATION_TOPIC,
+                &consolidation.entity,
+                json!({
+                    "entity": consolidation.entity,
+                    "memory": consolidation.memory,
+                    "merged": consolidation.merged,
+                    "generated": consolidation.generated,
+                }),
+            );
+        }
+        let evictions = std::mem::take(&mut self.pending_evictions);
+        for (entity, memory) in &evictions {
+            events.emit(EVICTION_TOPIC, entity, json!({ "entity": entity, "memory": memory }));
+        }
+        pending.len() + evictions.len()
+    }
+
+    // The entity's searchable memories, least important first (ties by id, for a stable order)
+    fn memories(&self, index: &VectorIndex, collection: &str, entity: &str, now: u64) -> Result<Vec<VectorPoint>, VectorIndexError> {
+        let mut memories: Vec<VectorPoint> = index
+            .collection(collection)?
+            .points
+            .values()
+            .filter(|p| p.is_searchable(now))
+            .filter(|p| p.payload.get(&self.config.entity_field).and_then(Value::as_str) == Some(entity))
+            .cloned()
+            .collect();
+        memories.sort_by(|a, b| importance(a).total_cmp(&importance(b)).then_with(|| a.id.cmp(&b.id)));
+        Ok(memories)
+    }
+
+    // A low-importance memory plus its closest neighbours about the same player. Seeds are tried
+    // least important first; if none has a neighbour above the similarity threshold, the least
+    // important seed is merged with the next least important memories instead. Merging n memories
+    // frees n - 1 slots, so the cluster does not grow past what the excess needs.
+    fn pick_cluster(&self, memories: &[VectorPoint], excess: usize) -> Option<Vec<VectorPoint>> {
+        let candidates: Vec<&VectorPoint> =
+            memories.iter().filter(|p| importance(p) < self.config.keep_importance).collect();
+        let size = self.config.cluster_size.max(2).min(excess + 1);
+        let mut fallback = None;
+        for (n, seed) in candidates.iter().enumerate() {
+            let player = seed.payload.get(PLAYER_FIELD);
+            let peers: Vec<&VectorPoint> =
+                candidates[n + 1..].iter().copied().filter(|p| p.payload.get(PLAYER_FIELD) == player).collect();
+            let mut similar: Vec<(&VectorPoint, f32)> = peers
+                .iter()
+                .map(|p| (*p, cosine_similarity(&seed.vector, &p.vector)))
+                .filter(|(_, similarity)| *similarity >= self.config.similarity)
+                .collect();
+            if !similar.is_empty() {
+                similar.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.id.cmp(&b.0.id)));
+                let mut cluster = vec![(*seed).clone()];
+                cluster.extend(similar.into_iter().take(size - 1).map(|(p, _)| p.clone()));
+                return Some(cluster);
+            }
+            if fallback.is_none() && !peers.is_empty() {
+                let mut cluster = vec![(*seed).clone()];
+                cluster.extend(peers.into_iter().take(size - 1).cloned());
+                fallback = Some(cluster);
+            }
+        }
+        fallback
+    }
+
+    fn consolidate(
+        &self,
+        index: &mut VectorIndex,
+        collection: &str,
+        entity: &str,
+        cluster: &[VectorPoint],
+        generator: Option<&dyn TextGenerator>,
+        embedder: &dyn Embedder,
+    ) -> Result<Consolidation, VectorIndexError> {
+        let texts: Vec<&str> = cluster.iter().filter_map(VectorPoint::text).collect();
+        let generated = generator.and_then(|generator| self.summarize(generator, entity, &texts));
+        let (summary, from_model) = match generated {
+            Some(summary) => (summary, true),
+            None => (extractive_summary(&texts, self.config.summary_words), false),
+        };
+
+        let merged: Vec<String> = cluster.iter().map(|p| p.id.clone()).collect();
+        let mut payload = HashMap::from([
+            (self.config.entity_field.clone(), Value::from(entity)),
+            (TEXT_FIELD.to_string(), Value::from(summary.clone())),
+            (IMPORTANCE_FIELD.to_string(), Value::from(cluster.iter().map(importance).fold(0.0, f64::max))),
+            (CONSOLIDATED_FROM_FIELD.to_string(), json!(originals_of(cluster))),
+        ]);
+        if let Some(player) = cluster[0].payload.get(PLAYER_FIELD) {
+            payload.insert(PLAYER_FIELD.to_string(), player.clone());
+        }
+        let id = consolidated_id(entity, &merged);
+        let mut point = VectorPoint::new(&id, embedder.embed(&summary)?);
+        point.payload = payload;
+
+        index.upsert(collection, point)?;
+        let originals: Vec<String> = merged.iter().filter(|m| **m != id).cloned().collect();
+        index.delete(collection, &originals)?;
+        Ok(Consolidation { entity: entity.to_string(), memory: id, merged, summary, generated: from_model })
+    }
+
+    fn summarize(&self, generator: &dyn TextGenerator, entity: &str, texts: &[&str]) -> Option<String> {
+        let memories: String = texts.iter().map(|text| format!("- {}\n", text)).collect();
+        let prompt = prompts::render(
+            self.prompts.as_ref(),
+            prompts::MEMORY_CONSOLIDATION,
+            &[
+                ("npc", Value::from(entity)),
+                ("max_words", Value::from(self.config.summary_words)),
+                ("memories", Value::from(memories)),
+            ],
+        )
+        .ok()?;
+        let summary = generator.generate(&prompt).ok()?;
+        let summary = summary.trim();
+        (!summary.is_empty()).then(|| summary.to_string())
+    }
+}
+
+// Ids of the original memories behind a cluster; a consolidated member contributes its own list,
+// so provenance survives repeated consolidation
+fn originals_of(cluster: &[VectorPoint]) -> Vec<String> {
+    let mut originals = Vec::new();
+    for point in cluster {
+        match point.payload.get(CONSOLIDATED_FROM_FIELD).and_then(Value::as_array) {
+            Some(ids) => originals.extend(ids.iter().filter_map(Value::as_str).map(str::to_string)),
+            None => originals.push(point.id.clone()),
+        }
+    }
+    originals
+}
+
+fn importance(point: &VectorPoint) -> f64 {
+    point.payload.get(IMPORTANCE_FIELD).and_then(Value::as_f64).unwrap_or(DEFAULT_IMPORTANCE)
+}
+
+// Every memory's text in order, `max_words` shared between them (at least 8 words each)
+fn extractive_summary(texts: &[&str], max_words: usize) -> String {
+    let per_memory = (max_words / texts.len().max(1)).max(8);
+    texts
+        .iter()
+        .map(|text| {
+            let words: Vec<&str> = text.split_whitespace().collect();
+            if words.len() > per_memory {
+                format!("{}...", words[..per_memory].join(" "))
+            } else {
+                words.join(" ")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" / ")
+}
+
+// Stable id from the merged ids, so consolidating the same set twice cannot duplicate it
+fn consolidated_id(entity: &str, merged: &[String]) -> String {
+    let mut hasher = Sha256::new();
+    for id in merged {
+        hasher.update(id.as_bytes());
+        hasher.update([0]);
+    }
+    let digest = hasher.finalize();
+    let hex: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("consolidated:{}:{}", entity, hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::HashEmbedder;
+    use crate::vector_index::VectorIndexConfig;
+
+    const NOW: u64 = 1_000;
+
+    fn index(memories: &[(&str, &str, f64, [f32; 2])]) -> VectorIndex {
+        let mut index = VectorIndex::new(VectorIndexConfig {
+            url: String::new(),
+            api_key: String::new(),
+            default_ttl_secs: None,
+            collection_ttl_secs: Default::default(),
+        });
+        index.create_collection("memories", 2, "test").unwrap();
+        for (id, player, importance, vector) in memories {
+            let mut point = VectorPoint::new(id, vector.to_vec());
+            point.payload.insert("npc".to_string(), json!("smith"));
+            point.payload.insert(PLAYER_FIELD.to_string(), json!(player));
+            point.payload.insert(TEXT_FIELD.to_string(), json!(format!("memory {}", id)));
+            point.payload.insert(IMPORTANCE_FIELD.to_string(), json!(importance));
+            index.upsert("memories", point).unwrap();
+        }
+        index
+    }
+
+    fn budgets(max_points: usize) -> MemoryBudgets {
+        MemoryBudgets::new(BudgetConfig { max_points, ..Default::default() })
+    }
+
+    #[test]
+    fn similar_memories_about_the_same_player_are_consolidated() {
+        let mut index = index(&[("a", "p1", 0.1, [1.0, 0.0]), ("b", "p1", 0.2, [0.99, 0.1]), ("c", "p2", 0.3, [0.0, 1.0])]);
+        let report = budgets(2).enforce(&mut index, "memories", "smith", None, &HashEmbedder::new(2), NOW).unwrap();
+        assert_eq!(report.consolidations.len(), 1);
+        assert_eq!(report.consolidations[0].merged, vec!["a", "b"]);
+        assert!(report.evicted.is_empty());
+        assert_eq!(report.after, 2);
+    }
+
+    #[test]
+    fn deleted_memories_do_not_count_against_the_budget() {
+        let mut index = index(&[("a", "p1", 0.1, [1.0, 0.0]), ("b", "p1", 0.2, [1.0, 0.0]), ("c", "p2", 0.3, [0.0, 1.0])]);
+        index.soft_delete("memories", &["a".to_string()], NOW).unwrap();
+        let mut budgets = budgets(2);
+        assert_eq!(budgets.usage(&index, "memories", NOW).unwrap()["smith"], 2);
+        let report = budgets.enforce(&mut index, "memories", "smith", None, &HashEmbedder::new(2), NOW).unwrap();
+        assert!(report.consolidations.is_empty() && report.evicted.is_empty());
+    }
+
+    #[test]
+    fn least_important_memory_is_evicted_when_no_player_has_two() {
+        let mut index = index(&[("a", "p1", 0.4, [1.0, 0.0]), ("b", "p2", 0.1, [1.0, 0.0]), ("c", "p3", 0.95, [0.0, 1.0])]);
+        let mut budgets = budgets(2);
+        let report = budgets.enforce(&mut index, "memories", "smith", None, &HashEmbedder::new(2), NOW).unwrap();
+        assert!(report.consolidations.is_empty());
+        assert_eq!(report.evicted, vec!["b"]);
+        assert!(report.within_budget());
+        assert_eq!(index.get("memories", "b").unwrap().unwrap().deleted_at(), Some(NOW));
+
+        let mut events = EventBus::new(8);
+        let subscription = events.subscribe(EVICTION_TOPIC);
+        assert_eq!(budgets.publish(&mut events), 1);
+        assert_eq!(events.drain(subscription)[0].payload, json!({ "entity": "smith", "memory": "b" }));
+    }
+
+    #[test]
+    fn memories_kept_by_importance_are_never_evicted() {
+        let mut index = index(&[("a", "p1", 0.95, [1.0, 0.0]), ("b", "p2", 0.99, [1.0, 0.0])]);
+        let report = budgets(1).enforce(&mut index, "memories", "smith", None, &HashEmbedder::new(2), NOW).unwrap();
+        assert!(report.evicted.is_empty());
+        assert!(!report.within_budget());
+    }
+}
@@ -0,0 +1,442 @@
+// Chat relay
+//
+// Chat is server-authoritative: clients only submit messages, and the relay decides who receives
+// them. Messages go to everyone (global), the sender's party, or players within earshot
+// (proximity). Every message passes flood protection (a per-player token bucket plus repeat
+// detection, with a temporary mute for persistent offenders) and then the moderation filter,
+// which may let it through, mask parts of it, or reject it. Wrapping the filter in a
+// CachedModerator (moderation.rs) caches its verdicts and adds overrides and appeals.
+//
+// Proximity chat is also audible to NPCs registered as listeners: each one in range gets a
+// "chat.overheard" event, and the message is emitted as a "chat" sound into the perception system
+// so NPCs notice the speaker like any other noise.
+//
+// The relay remembers each participant's last message and mute state; privacy erasure removes the
+// participant (security/privacy.rs).
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::events::EventBus;
+use crate::perception::{PerceptionSystem, SoundStimulus};
+use crate::spatial::{distance, SpatialIndex};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChatChannel {
+    Global,
+    // The sender's current party
+    Party,
+    // Players (and listening NPCs) within the configured radius of the sender
+    Proximity,
+}
+
+impl fmt::Display for ChatChannel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChatChannel::Global => write!(f, "global"),
+            ChatChannel::Party => write!(f, "party"),
+            ChatChannel::Proximity => write!(f, "proximity"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ModerationVerdict {
+    Allow,
+    // Deliver this text instead of the original
+    Mask(String),
+    Reject(String),
+}
+
+// Server-side content check applied to every message before it is relayed
+pub trait ChatModerator {
+    fn review(&self, sender: &str, text: &str) -> ModerationVerdict;
+}
+
+// Masks listed words with asterisks and rejects messages containing blocked phrases.
+// Matching is case-insensitive on whole words.
+#[derive(Debug, Clone, Default)]
+pub struct WordFilter {
+    masked: BTreeSet<String>,
+    blocked: Vec<String>,
+}
+
+impl WordFilter {
+    pub fn new() -> Self {
+        WordFilter::default()
+    }
+
+    pub fn mask(mut self, words: &[&str]) -> Self {
+        self.masked.extend(words.iter().map(|w| w.to_lowercase()));
+        self
+    }
+
+    pub fn block(mut self, phrases: &[&str]) -> Self {
+        self.blocked.extend(phrases.iter().map(|p| p.to_lowercase()));
+        self
+    }
+}
+
+impl ChatModerator for WordFilter {
+    fn review(&self, _sender: &str, text: &str) -> ModerationVerdict {
+        let words = words(text);
+        if let Some(phrase) = self.blocked.iter().find(|p| contains_phrase(&words, p)) {
+            return ModerationVerdict::Reject(format!("contains blocked phrase '{}'", phrase));
+        }
+        let mut masked = false;
+        let mut out = String::with_capacity(text.len());
+        let mut word = String::new();
+        let mut flush = |word: &mut String, out: &mut String| {
+            if self.masked.contains(&word.to_lowercase()) {
+                out.push_str(&"*".repeat(word.chars().count()));
+                masked = true;
+            } else {
+                out.push_str(word);
+            }
+            word.clear();
+        };
+        for c in text.chars() {
+            if c.is_alphanumeric() {
+                word.push(c);
+            } else {
+                flush(&mut word, &mut out);
+                out.push(c);
+            }
+        }
+        flush(&mut word, &mut out);
+        if masked {
+            ModerationVerdict::Mask(out)
+        } else {
+            ModerationVerdict::Allow
+        }
+    }
+}
+
+// Lowercased alphanumeric runs, the same word boundaries masking uses
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+// Whether the phrase's words appear consecutively, so "ass" does not match "class"
+fn contains_phrase(words: &[String], phrase: &str) -> bool {
+    let phrase = self::words(phrase);
+    !phrase.is_empty() && words.windows(phrase.len()).any(|window| window == phrase.as_slice())
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FloodConfig {
+    pub messages_per_sec: f64,
+    pub burst: f64,
+    // The same text again within this many seconds counts as flooding
+    pub repeat_window: f64,
+    // Flood violations (within one mute period) before the player is muted
+    pub mute_after: u32,
+    pub mute_secs: f64,
+}
+
+impl Default for FloodConfig {
+    fn default() -> Self {
+        FloodConfig { messages_per_sec: 1.0, burst: 5.0, repeat_window: 10.0, mute_after: 3, mute_secs: 60.0 }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ChatConfig {
+    pub proximity_radius: f32,
+    pub max_length: usize,
+    // Level of proximity chat as a perception sound, in dB at one unit
+    pub speech_db: f32,
+    pub flood: FloodConfig,
+}
+
+impl Default for ChatConfig {
+    fn default() -> Self {
+        ChatConfig { proximity_radius: 15.0, max_length: 280, speech_db: 60.0, flood: FloodConfig::default() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChatError {
+    UnknownPlayer(String),
+    NoParty(String),
+    // Player has no position in the spatial index
+    NotPlaced(String),
+    Empty,
+    TooLong(usize),
+    Flooding(String),
+    Muted { player: String, until: f64 },
+    Rejected { player: String, reason: String },
+}
+
+impl fmt::Display for ChatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChatError::UnknownPlayer(id) => write!(f, "player '{}' is not in chat", id),
+            ChatError::NoParty(id) => write!(f, "player '{}' is not in a party", id),
+            ChatError::NotPlaced(id) => write!(f, "player '{}' has no position for proximity chat", id),
+            ChatError::Empty => write!(f, "chat message is empty"),
+            ChatError::TooLong(max) => write!(f, "chat message is longer than {} characters", max),
+            ChatError::Flooding(id) => write!(f, "player '{}' is sending messages too quickly", id),
+            ChatError::Muted { player, until } => write!(f, "player '{}' is muted until {:.0}", player, until),
+            ChatError::Rejected { player, reason } => write!(f, "message from '{}' rejected: {}", player, reason),
+        }
+    }
+}
+
+impl std::error::Error for ChatError {}
+
+#[derive(Debug, Clone)]
+pub struct ChatMessage {
+    pub sequence: u64,
+    pub sender: String,
+    pub channel: ChatChannel,
+    // Text as delivered, after moderation
+    pub text: String,
+    pub masked: bool,
+    pub sent_at: f64,
+    // Players the message is delivered to, including the sender
+    pub recipients: Vec<String>,
+    // Listening NPCs within earshot of a proximity message
+    pub overheard_by: Vec<String>,
+}
+
+// Optional subsystems a message is forwarded to
+#[derive(Default)]
+pub struct ChatHooks<'a> {
+    pub events: Option<&'a mut EventBus>,
+    pub perception: Option<&'a mut PerceptionSystem>,
+}
+
+struct Participant {
+    party: Option<String>,
+    tokens: f64,
+    last_refill: Option<f64>,
+    last_text: Option<(String, f64)>,
+    violations: u32,
+    muted_until: Option<f64>,
+}
+
+pub struct ChatRelay {
+    config: ChatConfig,
+    moderator: Box<dyn ChatModerator + Send>,
+    participants: HashMap<String, Participant>,
+    listeners: BTreeSet<String>,
+    sequence: u64,
+}
+
+impl ChatRelay {
+    pub fn new(config: ChatConfig, moderator: Box<dyn ChatModerator + Send>) -> Self {
+        ChatRelay { config, moderator, participants: HashMap::new(), listeners: BTreeSet::new(), sequence: 0 }
+    }
+
+    pub fn join(&mut self, player: &str) {
+        let burst = self.config.flood.burst;
+        self.participants.entry(player.to_string()).or_insert(Participant {
+            party: None,
+            tokens: burst,
+            last_refill: None,
+            last_text: None,
+            violations: 0,
+            muted_until: None,
+        });
+    }
+
+    pub fn leave(&mut self, player: &str) -> bool {
+        self.participants.remove(player).is_some()
+    }
+
+    pub fn is_participant(&self, player: &str) -> bool {
+        self.participants.contains_key(player)
+    }
+
+    // What the relay holds about a participant (party, flood and mute state, last message), for
+    // privacy exports
+    pub fn participant_data(&self, player: &str) -> Option<Value> {
+        let participant = self.participants.get(player)?;
+        Some(json!({
+            "party": participant.party,
+            "last_message": participant.last_text.as_ref().map(|(text, at)| json!({ "text": text, "sent_at": at })),
+            "violations": participant.violations,
+            "muted_until": participant.muted_until,
+        }))
+    }
+
+    pub fn set_party(&mut self, player: &str, party: Option<&str>) -> Result<(), ChatError> {
+        let participant = self.participants.get_mut(player).ok_or_else(|| ChatError::UnknownPlayer(player.to_string()))?;
+        participant.party = party.map(str::to_string);
+        Ok(())
+    }
+
+    // Let an NPC overhear proximity chat
+    pub fn listen(&mut self, npc: &str) {
+        self.listeners.insert(npc.to_string());
+    }
+
+    pub fn stop_listening(&mut self, npc: &str) {
+        self.listeners.remove(npc);
+    }
+
+    // Moderator action; a zero duration lifts the mute
+    pub fn mute(&mut self, player: &str, now: f64, secs: f64) -> Result<(), ChatError> {
+        let participant = self.participants.get_mut(player).ok_or_else(|| ChatError::UnknownPlayer(player.to_string()))?;
+        participant.muted_until = if secs > 0.0 { Some(now + secs) } else { None };
+        participant.violations = 0;
+        Ok(())
+    }
+
+    pub fn muted_until(&self, player: &str, now: f64) -> Option<f64> {
+        self.participants.get(player)?.muted_until.filter(|until| *until > now)
+    }
+
+    // Validate, moderate and route a message at time `now` (seconds)
+    pub fn send(
+        &mut self,
+        sender: &str,
+        channel: ChatChannel,
+        text: &str,
+        now: f64,
+        spatial: &SpatialIndex,
+        mut hooks: ChatHooks,
+    ) -> Result<ChatMessage, ChatError> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(ChatError::Empty);
+        }
+        if text.chars().count() > self.config.max_length {
+            return Err(ChatError::TooLong(self.config.max_length));
+        }
+        self.check_flood(sender, text, now, hooks.events.as_deref_mut())?;
+
+        let (text, masked) = match self.moderator.review(sender, text) {
+            ModerationVerdict::Allow => (text.to_string(), false),
+            ModerationVerdict::Mask(masked) => (masked, true),
+            ModerationVerdict::Reject(reason) => {
+                if let Some(events) = hooks.events.as_deref_mut() {
+                    events.emit("chat.rejected", sender, json!({ "player": sender, "reason": reason }));
+                }
+                return Err(ChatError::Rejected { player: sender.to_string(), reason });
+            }
+        };
+
+        let mut position = None;
+        let mut overheard_by = Vec::new();
+        let mut recipients: Vec<String> = match channel {
+            ChatChannel::Global => self.participants.keys().cloned().collect(),
+            ChatChannel::Party => {
+                let party = self.participants[sender].party.clone().ok_or_else(|| ChatError::NoParty(sender.to_string()))?;
+                self.participants
+                    .iter()
+                    .filter(|(_, p)| p.party.as_deref() == Some(party.as_str()))
+                    .map(|(id, _)| id.clone())
+                    .collect()
+            }
+            ChatChannel::Proximity => {
+                let origin = spatial.position(sender).ok_or_else(|| ChatError::NotPlaced(sender.to_string()))?;
+                position = Some(origin);
+                let radius = self.config.proximity_radius;
+                let in_range = |id: &String| id == sender || spatial.position(id).is_some_and(|p| distance(origin, p) <= radius);
+                overheard_by = self.listeners.iter().filter(|id| in_range(id)).cloned().collect();
+                self.participants.keys().filter(|id| in_range(id)).cloned().collect()
+            }
+        };
+        recipients.sort();
+
+        self.sequence += 1;
+        let message = ChatMessage {
+            sequence: self.sequence,
+            sender: sender.to_string(),
+            channel,
+            text,
+            masked,
+            sent_at: now,
+            recipients,
+            overheard_by,
+        };
+
+        if let Some(events) = hooks.events.as_deref_mut() {
+            let mut payload = json!({
+                "channel": message.channel.to_string(),
+                "text": message.text,
+                "recipients": message.recipients,
+            });
+            if let Some(position) = position {
+                payload["position"] = json!(position);
+            }
+            events.emit("chat.message", sender, payload);
+            for npc in &message.overheard_by {
+                events.emit(
+                    "chat.overheard",
+                    sender,
+                    json!({ "entity": npc, "speaker": sender, "text": message.text }),
+                );
+            }
+        }
+        if let (Some(perception), Some(position)) = (hooks.perception.as_deref_mut(), position) {
+            perception.emit_sound(SoundStimulus::new(sender, position, self.config.speech_db, "chat"));
+        }
+        Ok(message)
+    }
+
+    fn check_flood(&mut self, sender: &str, text: &str, now: f64, events: Option<&mut EventBus>) -> Result<(), ChatError> {
+        let flood = self.config.flood;
+        let participant = self.participants.get_mut(sender).ok_or_else(|| ChatError::UnknownPlayer(sender.to_string()))?;
+        if let Some(until) = participant.muted_until {
+            if until > now {
+                return Err(ChatError::Muted { player: sender.to_string(), until });
+            }
+            participant.muted_until = None;
+            participant.violations = 0;
+        }
+
+        let elapsed = participant.last_refill.map_or(0.0, |last| (now - last).max(0.0));
+        participant.tokens = (participant.tokens + elapsed * flood.messages_per_sec).min(flood.burst);
+        participant.last_refill = Some(now);
+        let repeated = participant
+            .last_text
+            .as_ref()
+            .is_some_and(|(last, at)| last.eq_ignore_ascii_case(text) && now - at < flood.repeat_window);
+
+        if participant.tokens < 1.0 || repeated {
+            participant.violations += 1;
+            if participant.violations >= flood.mute_after {
+                let until = now + flood.mute_secs;
+                participant.muted_until = Some(until);
+                participant.violations = 0;
+                if let Some(events) = events {
+                    events.emit("chat.muted", sender, json!({ "player": sender, "until": until }));
+                }
+                return Err(ChatError::Muted { player: sender.to_string(), until });
+            }
+            return Err(ChatError::Flooding(sender.to_string()));
+        }
+        participant.tokens -= 1.0;
+        participant.last_text = Some((text.to_string(), now));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocked_phrases_match_whole_words_only() {
+        let filter = WordFilter::new().block(&["ass", "free gold"]);
+        assert_eq!(filter.review("p1", "Pick a class, any class"), ModerationVerdict::Allow);
+        assert_eq!(filter.review("p1", "Freedom! Gold for all"), ModerationVerdict::Allow);
+        assert!(matches!(filter.review("p1", "what an ASS."), ModerationVerdict::Reject(_)));
+        assert!(matches!(filter.review("p1", "FREE   gold at the docks"), ModerationVerdict::Reject(_)));
+    }
+
+    #[test]
+    fn masked_words_keep_their_length() {
+        let filter = WordFilter::new().mask(&["darn"]);
+        assert_eq!(filter.review("p1", "Darn, darned door"), ModerationVerdict::Mask("****, darned door".to_string()));
+    }
+}
@@ -0,0 +1,370 @@
+// Interest management
+//
+// Clients (players, spectators, debug UIs) should only receive what is relevant to them. Each
+// subscriber declares interests: specific entities, fixed regions, an area around an entity that
+// moves with it, or event topics. Events and world replication deltas are filtered per subscriber
+// using the spatial index, queued, and handed out under a per-subscriber rate cap.
+//
+// When an entity enters a subscriber's area of interest it is sent in full (as EntityAdded); when
+// it leaves, the subscriber gets an Exited update so the client can drop it.
+
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+use serde_json::Value;
+
+use crate::events::Event;
+use crate::spatial::{distance, SpatialIndex};
+use crate::world::{Change, GameWorld, WorldDelta};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Interest {
+    Entity(String),
+    Region { center: [f32; 2], radius: f32 },
+    // Area that moves with an entity, typically the subscriber's own avatar
+    Around { entity: String, radius: f32 },
+    // Events whose topic starts with this prefix, wherever they happen
+    Topic(String),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RateCap {
+    pub updates_per_sec: f64,
+    pub burst: f64,
+    // Queued updates beyond this are dropped, oldest first
+    pub max_queued: usize,
+}
+
+impl Default for RateCap {
+    fn default() -> Self {
+        RateCap { updates_per_sec: 30.0, burst: 60.0, max_queued: 512 }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Update {
+    Event(Event),
+    Replication(WorldDelta),
+    Exited(String),
+}
+
+struct Subscriber {
+    interests: Vec<Interest>,
+    cap: RateCap,
+    tokens: f64,
+    last_refill: Option<f64>,
+    queue: VecDeque<Update>,
+    // Entities the subscriber currently knows about
+    visible: BTreeSet<String>,
+    dropped: u64,
+}
+
+impl Subscriber {
+    fn push(&mut self, update: Update) {
+        self.queue.push_back(update);
+        while self.queue.len() > self.cap.max_queued {
+            self.queue.pop_front();
+            self.dropped += 1;
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct InterestManager {
+    subscribers: HashMap<String, Subscriber>,
+}
+
+impl InterestManager {
+    pub fn new() -> Self {
+        InterestManager::default()
+    }
+
+    pub fn subscribe(&mut self, id: &str, cap: RateCap) {
+        self.subscribers.insert(
+            id.to_string(),
+            Subscriber {
+                interests: Vec::new(),
+                cap,
+                tokens: cap.burst,
+                last_refill: None,
+                queue: VecDeque::new(),
+                visible: BTreeSet::new(),
+                dropped: 0,
+            },
+        );
+    }
+
+    pub fn unsubscribe(&mut self, id: &str) -> bool {
+        self.subscribers.remove(id).is_some()
+    }
+
+    pub fn add_interest(&mut self, id: &str, interest: Interest) -> bool {
+        match self.subscribers.get_mut(id) {
+            Some(subscriber) => {
+                subscriber.interests.push(interest);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn clear_interests(&mut self, id: &str) {
+        if let Some(subscriber) = self.subscribers.get_mut(id) {
+            subscriber.interests.clear();
+        }
+    }
+
+    // Entities a subscriber is interested in right now
+    pub fn relevant_entities(&self, id: &str, spatial: &SpatialIndex) -> BTreeSet<String> {
+        self.subscribers.get(id).map(|s| relevant(&s.interests, spatial)).unwrap_or_default()
+    }
+
+    // Queue each event for the subscribers it is relevant to. An event is located by a
+    // "position" [x, y] in its payload, or else by the position of its source entity.
+    pub fn route_events(&mut self, events: &[Event], spatial: &SpatialIndex) {
+        for subscriber in self.subscribers.values_mut() {
+            let entities = relevant(&subscriber.interests, spatial);
+            for event in events {
+                let by_topic = subscriber
+                    .interests
+                    .iter()
+                    .any(|i| matches!(i, Interest::Topic(prefix) if event.topic.starts_with(prefix.as_str())));
+                let subject = event.payload.get("entity").and_then(Value::as_str).unwrap_or(&event.source);
+                let by_entity = entities.contains(subject) || entities.contains(&event.source);
+                let by_place = event_position(event, spatial)
+                    .is_some_and(|position| in_area(&subscriber.interests, position, spatial));
+                if by_topic || by_entity || by_place {
+                    subscriber.push(Update::Event(event.clone()));
+                }
+            }
+        }
+    }
+
+    // Queue each subscriber's slice of a world delta: changes to relevant entities plus globals,
+    // full state for entities that just became relevant and Exited for ones that stopped being
+    pub fn replicate(&mut self, delta: &WorldDelta, world: &GameWorld, spatial: &SpatialIndex) {
+        for subscriber in self.subscribers.values_mut() {
+            let now_visible = relevant(&subscriber.interests, spatial);
+            let entered: BTreeSet<&String> = now_visible.difference(&subscriber.visible).collect();
+            // Entered entities are sent in full, which already includes this delta's changes
+            let mut changes: Vec<Change> = entered
+                .iter()
+                .filter_map(|id| world.entity(id).map(|e| Change::EntityAdded { id: id.to_string(), entity: e.clone() }))
+                .collect();
+            changes.extend(
+                delta
+                    .changes
+                    .iter()
+                    .filter(|change| match change.entity_id() {
+                        Some(id) => now_visible.contains(id) && !entered.iter().any(|e| e.as_str() == id),
+                        None => true,
+                    })
+                    .cloned(),
+            );
+            let exited: Vec<String> = subscriber.visible.difference(&now_visible).cloned().collect();
+
+            if !changes.is_empty() {
+                subscriber.push(Update::Replication(WorldDelta {
+                    base_tick: delta.base_tick,
+                    target_tick: delta.target_tick,
+                    changes,
+                }));
+            }
+            for id in exited {
+                subscriber.push(Update::Exited(id));
+            }
+            subscriber.visible = now_visible;
+        }
+    }
+
+    // Updates the rate cap allows at time `now` (seconds); the rest stay queued
+    pub fn poll(&mut self, id: &str, now: f64) -> Vec<Update> {
+        let Some(subscriber) = self.subscribers.get_mut(id) else {
+            return Vec::new();
+        };
+        let elapsed = subscriber.last_refill.map_or(0.0, |last| (now - last).max(0.0));
+        subscriber.tokens = (subscriber.tokens + elapsed * subscriber.cap.updates_per_sec).min(subscriber.cap.burst);
+        subscriber.last_refill = Some(now);
+        let count = (subscriber.tokens.floor() as usize).min(subscriber.queue.len());
+        subscriber.tokens -= count as f64;
+        subscriber.queue.drain(..count).collect()
+    }
+
+    pub fn queued(&self, id: &str) -> usize {
+        self.subscribers.get(id).map_or(0, |s| s.queue.len())
+    }
+
+    pub fn dropped(&self, id: &str) -> u64 {
+        self.subscribers.get(id).map_or(0, |s| s.dropped)
+    }
+}
+
+fn relevant(interests: &[Interest], spatial: &SpatialIndex) -> BTreeSet<String> {
+    let mut entities = BTreeSet::new();
+    for interest in interests {
+        match interest {
+            Interest::Entity(id) => {
+                entities.insert(id.clone());
+            }
+            Interest::Region { center, radius } => {
+                entities.extend(spatial.query_radius(*center, *radius).into_iter().map(|(id, _)| id));
+            }
+            Interest::Around { entity, radius } => {
+                if let Some(center) = spatial.position(entity) {
+                    entities.extend(spatial.query_radius(center, *radius).into_iter().map(|(id, _)| id));
+                }
+                entities.insert(entity.clone());
+            }
+            Interest::Topic(_) => {}
+        }
+    }
+    entities
+}
+
+fn in_area(interests: &[Interest], position: [f32; 2], spatial: &SpatialIndex) -> bool {
+    interests.iter().any(|interest| match interest {
+        Interest::Region { center, radius } => distance(*center, position) <= *radius,
+        Interest::Around { entity, radius } => spatial.position(entity).is_some_and(|c| distance(c, position) <= *radius),
+        _ => false,
+    })
+}
+
+fn event_position(event: &Event, spatial: &SpatialIndex) -> Option<[f32; 2]> {
+    match event.payload.get("position").and_then(Value::as_array) {
+        Some(coords) if coords.len() >= 2 => Some([coords[0].as_f64()? as f32, coords[1].as_f64()? as f32]),
+        _ => spatial.position(&event.source),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::spatial::GridSpec;
+    use crate::world::Entity;
+
+    fn spatial() -> SpatialIndex {
+        let mut spatial = SpatialIndex::new(GridSpec::new(20, 20, 1.0));
+        spatial.set_position("hero", [2.0, 2.0]);
+        spatial.set_position("goblin", [4.0, 2.0]);
+        spatial.set_position("dragon", [15.0, 15.0]);
+        spatial
+    }
+
+    fn manager(interests: &[(&str, Interest)], cap: RateCap) -> InterestManager {
+        let mut manager = InterestManager::new();
+        for (id, interest) in interests {
+            if !manager.add_interest(id, interest.clone()) {
+                manager.subscribe(id, cap);
+                manager.add_interest(id, interest.clone());
+            }
+        }
+        manager
+    }
+
+    fn topics(updates: &[Update]) -> Vec<&str> {
+        updates
+            .iter()
+            .filter_map(|u| match u {
+                Update::Event(event) => Some(event.topic.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn interests_select_entities_by_id_region_and_avatar() {
+        let spatial = spatial();
+        let around = Interest::Around { entity: "hero".to_string(), radius: 3.0 };
+        let mut manager = manager(&[("p1", around)], RateCap::default());
+        let names = |m: &InterestManager| m.relevant_entities("p1", &spatial).into_iter().collect::<Vec<_>>();
+        assert_eq!(names(&manager), vec!["goblin", "hero"]);
+
+        manager.add_interest("p1", Interest::Region { center: [15.0, 15.0], radius: 1.0 });
+        assert_eq!(names(&manager), vec!["dragon", "goblin", "hero"]);
+        manager.clear_interests("p1");
+        manager.add_interest("p1", Interest::Entity("ghost".to_string()));
+        assert_eq!(names(&manager), vec!["ghost"]);
+
+        assert!(manager.relevant_entities("nobody", &spatial).is_empty());
+        assert!(!manager.add_interest("nobody", Interest::Topic("x".to_string())));
+    }
+
+    #[test]
+    fn events_reach_subscribers_by_entity_place_or_topic() {
+        let spatial = spatial();
+        let mut manager = manager(
+            &[
+                ("p1", Interest::Around { entity: "hero".to_string(), radius: 3.0 }),
+                ("spectator", Interest::Topic("quest.".to_string())),
+            ],
+            RateCap::default(),
+        );
+        let events = [
+            Event::new("combat.hit", "goblin", json!({})),
+            Event::new("combat.roar", "dragon", json!({})),
+            Event::new("fx.explosion", "world", json!({ "position": [3.0, 3.0] })),
+            Event::new("loot.drop", "world", json!({ "entity": "goblin" })),
+            Event::new("quest.done", "dragon", json!({})),
+        ];
+        manager.route_events(&events, &spatial);
+        assert_eq!(topics(&manager.poll("p1", 0.0)), vec!["combat.hit", "fx.explosion", "loot.drop"]);
+        assert_eq!(topics(&manager.poll("spectator", 0.0)), vec!["quest.done"]);
+    }
+
+    #[test]
+    fn replication_sends_entries_changes_and_exits() {
+        let mut spatial = spatial();
+        let mut world = GameWorld::new();
+        for (id, kind) in [("hero", "player"), ("goblin", "monster"), ("dragon", "monster")] {
+            world.spawn(id, Entity::new(kind));
+        }
+        let around = Interest::Around { entity: "hero".to_string(), radius: 3.0 };
+        let mut manager = manager(&[("p1", around)], RateCap::default());
+        let health = |id: &str| Change::ComponentSet {
+            id: id.to_string(),
+            component: "health".to_string(),
+            old: None,
+            new: json!(5),
+        };
+        let weather = Change::GlobalSet { key: "weather".to_string(), old: None, new: json!("rain") };
+        let delta = |changes| WorldDelta { base_tick: 1, target_tick: 2, changes };
+
+        manager.replicate(&delta(vec![health("goblin"), health("dragon"), weather]), &world, &spatial);
+        let updates = manager.poll("p1", 0.0);
+        let Update::Replication(first) = &updates[0] else { panic!("expected replication, got {:?}", updates) };
+        // Entered entities come in full, so their own changes are not repeated
+        assert!(matches!(&first.changes[0], Change::EntityAdded { id, .. } if id == "goblin"));
+        assert!(matches!(&first.changes[1], Change::EntityAdded { id, .. } if id == "hero"));
+        assert!(matches!(&first.changes[2], Change::GlobalSet { key, .. } if key == "weather"));
+        assert_eq!(first.changes.len(), 3);
+
+        manager.replicate(&delta(vec![health("goblin")]), &world, &spatial);
+        let Update::Replication(second) = &manager.poll("p1", 0.0)[0] else { panic!("expected replication") };
+        assert_eq!(second.changes, vec![health("goblin")]);
+
+        spatial.set_position("goblin", [10.0, 10.0]);
+        manager.replicate(&delta(vec![health("goblin")]), &world, &spatial);
+        let updates = manager.poll("p1", 0.0);
+        assert_eq!(updates.len(), 1);
+        assert!(matches!(&updates[0], Update::Exited(id) if id == "goblin"));
+    }
+
+    #[test]
+    fn polling_respects_the_rate_cap_and_queue_limit() {
+        let cap = RateCap { updates_per_sec: 2.0, burst: 3.0, max_queued: 5 };
+        let mut manager = manager(&[("p1", Interest::Topic("tick".to_string()))], cap);
+        let events: Vec<Event> = (0..7).map(|i| Event::new(&format!("tick.{}", i), "server", json!({}))).collect();
+        manager.route_events(&events, &spatial());
+        assert_eq!((manager.queued("p1"), manager.dropped("p1")), (5, 2));
+
+        // The oldest updates were dropped
+        assert_eq!(topics(&manager.poll("p1", 0.0)), vec!["tick.2", "tick.3", "tick.4"]);
+        assert_eq!(manager.poll("p1", 0.5).len(), 1);
+        assert_eq!(manager.poll("p1", 100.0).len(), 1);
+        assert!(manager.poll("nobody", 0.0).is_empty());
+
+        assert!(manager.unsubscribe("p1"));
+        assert_eq!(manager.queued("p1"), 0);
+    }
+}
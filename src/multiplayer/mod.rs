@@ -0,0 +1,8 @@
+// Multiplayer and collaborative experiences
+
+pub mod chat;
+pub mod interest;
+pub mod moderation;
+pub mod p2p;
+pub mod prediction;
+pub mod session;
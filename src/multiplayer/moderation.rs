@@ -0,0 +1,687 @@
+// Moderation cache and appeals
+//
+// Moderation calls can be slow or billed per request, and the same lines ("gg", "lol", a
+// popular meme) arrive thousands of times. CachedModerator wraps any ChatModerator and remembers
+// its verdict per content id, the SHA-256 of the trimmed text, so each distinct message is
+// reviewed once. The cache holds `capacity` verdicts and forgets the oldest first; masked and
+// rejected content ids are remembered apart from it, so they can be appealed after eviction.
+//
+// Moderators are occasionally wrong, so verdicts can be overridden. The relay owns the moderator,
+// so everything beyond reviewing goes through a ModerationDesk handle, which shares its state:
+//
+//   let moderator = CachedModerator::new(filter, 10_000);
+//   let desk = moderator.desk();
+//   let relay = ChatRelay::new(config, Box::new(moderator));
+//   let appeal = desk.appeal("p1", "that shot was sick", "it's praise", now)?;
+//   desk.resolve_appeal(appeal, "mod_anna", true, "slang", now)?;
+//
+// An overturned appeal (or a direct `override_content`) whitelists the content id: it is allowed
+// from then on without asking the moderator. Every override, revocation, appeal and resolution is
+// written to an audit trail with who did it and when, published as "moderation.audit" events and
+// stored in agentdb together with the whitelist, the moderated content ids and the appeals. `stats` counts reviews, cache
+// hits and verdicts for diagnostics; the debug server's "moderation" command returns it. A
+// player's appeals and the audit entries of their actions are part of their data for export and
+// erasure requests (security/privacy.rs).
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+
+use super::chat::{ChatModerator, ModerationVerdict};
+use crate::agentdb::{AgentDb, AgentDbError};
+use crate::events::EventBus;
+use crate::namespace::Namespace;
+
+pub const MODERATION_AUDIT_TOPIC: &str = "moderation.audit";
+
+const MODERATION_TABLE: &str = "moderation";
+const OVERRIDES_KEY: &str = "overrides";
+const MODERATED_KEY: &str = "moderated";
+const COUNTERS_KEY: &str = "counters";
+const APPEAL_PREFIX: &str = "appeal:";
+const AUDIT_PREFIX: &str = "audit:";
+
+// Cache key and the id appeals and overrides refer to
+pub fn content_id(text: &str) -> String {
+    Sha256::digest(text.trim().as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AppealStatus {
+    Pending,
+    // The original verdict stands
+    Upheld,
+    // The content is whitelisted
+    Overturned,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Appeal {
+    pub id: u64,
+    pub content_id: String,
+    pub player: String,
+    pub text: String,
+    pub verdict: ModerationVerdict,
+    pub reason: String,
+    pub filed_at: f64,
+    pub status: AppealStatus,
+    #[serde(default)]
+    pub reviewer: Option<String>,
+    #[serde(default)]
+    pub resolved_at: Option<f64>,
+    #[serde(default)]
+    pub note: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Override {
+    pub reviewer: String,
+    pub note: String,
+    pub at: f64,
+    // Appeal that led to it, if any
+    #[serde(default)]
+    pub appeal: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    Override,
+    Revoke,
+    AppealFiled,
+    AppealUpheld,
+    AppealOverturned,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub seq: u64,
+    pub at: f64,
+    // Player or reviewer
+    pub actor: String,
+    pub action: AuditAction,
+    pub content_id: String,
+    #[serde(default)]
+    pub appeal: Option<u64>,
+    #[serde(default)]
+    pub note: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct ModerationStats {
+    pub reviews: u64,
+    pub cache_hits: u64,
+    pub moderator_calls: u64,
+    // Reviews answered from the whitelist
+    pub whitelisted: u64,
+    pub allowed: u64,
+    pub masked: u64,
+    pub rejected: u64,
+    pub evictions: u64,
+    pub cached_verdicts: usize,
+    pub overrides: usize,
+    pub appeals_pending: usize,
+    pub appeals_upheld: usize,
+    pub appeals_overturned: usize,
+}
+
+impl ModerationStats {
+    // Share of reviews that did not reach the moderator
+    pub fn hit_rate(&self) -> f64 {
+        if self.reviews == 0 {
+            0.0
+        } else {
+            (self.cache_hits + self.whitelisted) as f64 / self.reviews as f64
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ModerationError {
+    UnknownAppeal(u64),
+    // Nothing was masked or rejected for this content, so there is nothing to appeal
+    NotModerated(String),
+    AlreadyAllowed(String),
+    AlreadyResolved(u64),
+    Storage(AgentDbError),
+    Corrupt(serde_json::Error),
+}
+
+impl fmt::Display for ModerationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModerationError::UnknownAppeal(id) => write!(f, "unknown appeal {}", id),
+            ModerationError::NotModerated(id) => write!(f, "content {} was not masked or rejected", id),
+            ModerationError::AlreadyAllowed(id) => write!(f, "content {} is already whitelisted", id),
+            ModerationError::AlreadyResolved(id) => write!(f, "appeal {} is already resolved", id),
+            ModerationError::Storage(err) => write!(f, "{}", err),
+            ModerationError::Corrupt(err) => write!(f, "stored moderation record is invalid: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ModerationError {}
+
+impl From<AgentDbError> for ModerationError {
+    fn from(err: AgentDbError) -> Self {
+        ModerationError::Storage(err)
+    }
+}
+
+impl From<serde_json::Error> for ModerationError {
+    fn from(err: serde_json::Error) -> Self {
+        ModerationError::Corrupt(err)
+    }
+}
+
+#[derive(Default)]
+struct ModerationState {
+    capacity: usize,
+    verdicts: HashMap<String, ModerationVerdict>,
+    // Insertion order of `verdicts`, oldest first
+    order: VecDeque<String>,
+    // Masked or rejected content, kept past eviction so it can still be appealed
+    moderated: HashMap<String, ModerationVerdict>,
+    overrides: BTreeMap<String, Override>,
+    appeals: BTreeMap<u64, Appeal>,
+    audit: Vec<AuditEntry>,
+    // Audit entries not yet published
+    pending: Vec<AuditEntry>,
+    next_appeal: u64,
+    next_audit: u64,
+    stats: ModerationStats,
+}
+
+impl ModerationState {
+    fn cache(&mut self, id: String, verdict: ModerationVerdict) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.verdicts.insert(id.clone(), verdict).is_none() {
+            self.order.push_back(id);
+        }
+        while self.verdicts.len() > self.capacity {
+            let Some(oldest) = self.order.pop_front() else { break };
+            self.verdicts.remove(&oldest);
+            self.stats.evictions += 1;
+        }
+    }
+
+    fn count(&mut self, verdict: &ModerationVerdict) {
+        match verdict {
+            ModerationVerdict::Allow => self.stats.allowed += 1,
+            ModerationVerdict::Mask(_) => self.stats.masked += 1,
+            ModerationVerdict::Reject(_) => self.stats.rejected += 1,
+        }
+    }
+
+    fn log(&mut self, at: f64, actor: &str, action: AuditAction, content_id: &str, appeal: Option<u64>, note: &str) {
+        self.next_audit += 1;
+        let entry = AuditEntry {
+            seq: self.next_audit,
+            at,
+            actor: actor.to_string(),
+            action,
+            content_id: content_id.to_string(),
+            appeal,
+            note: note.to_string(),
+        };
+        self.audit.push(entry.clone());
+        self.pending.push(entry);
+    }
+
+    fn whitelist(&mut self, content_id: &str, reviewer: &str, note: &str, at: f64, appeal: Option<u64>) {
+        self.overrides.insert(
+            content_id.to_string(),
+            Override { reviewer: reviewer.to_string(), note: note.to_string(), at, appeal },
+        );
+        self.verdicts.remove(content_id);
+        self.order.retain(|id| id != content_id);
+        self.moderated.remove(content_id);
+    }
+}
+
+// Id counters, stored so ids of erased appeals and audit entries are never handed out again
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+struct Counters {
+    next_appeal: u64,
+    next_audit: u64,
+}
+
+// Verdict cache in front of another moderator
+pub struct CachedModerator<M: ChatModerator> {
+    inner: M,
+    state: Arc<Mutex<ModerationState>>,
+}
+
+impl<M: ChatModerator> CachedModerator<M> {
+    // `capacity` verdicts are kept; 0 disables caching but keeps overrides and stats
+    pub fn new(inner: M, capacity: usize) -> Self {
+        let state = ModerationState { capacity, next_appeal: 1, ..ModerationState::default() };
+        CachedModerator { inner, state: Arc::new(Mutex::new(state)) }
+    }
+
+    // Handle for overrides, appeals and stats that stays usable after the moderator is boxed
+    pub fn desk(&self) -> ModerationDesk {
+        ModerationDesk { state: Arc::clone(&self.state) }
+    }
+}
+
+impl<M: ChatModerator> ChatModerator for CachedModerator<M> {
+    fn review(&self, sender: &str, text: &str) -> ModerationVerdict {
+        let id = content_id(text);
+        {
+            let mut state = lock(&self.state);
+            state.stats.reviews += 1;
+            if state.overrides.contains_key(&id) {
+                state.stats.whitelisted += 1;
+                state.stats.allowed += 1;
+                return ModerationVerdict::Allow;
+            }
+            if let Some(verdict) = state.verdicts.get(&id).cloned() {
+                state.stats.cache_hits += 1;
+                state.count(&verdict);
+                return verdict;
+            }
+        }
+        // Not holding the lock while the (possibly slow) moderator runs
+        let verdict = self.inner.review(sender, text);
+        let mut state = lock(&self.state);
+        state.stats.moderator_calls += 1;
+        state.count(&verdict);
+        if verdict != ModerationVerdict::Allow {
+            state.moderated.insert(id.clone(), verdict.clone());
+        }
+        state.cache(id, verdict.clone());
+        verdict
+    }
+}
+
+fn lock(state: &Mutex<ModerationState>) -> MutexGuard<'_, ModerationState> {
+    // Nothing in the state can be left half-updated by a panic, so a poisoned lock is still usable
+    state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[derive(Clone)]
+pub struct ModerationDesk {
+    state: Arc<Mutex<ModerationState>>,
+}
+
+impl ModerationDesk {
+    // Cached verdict for some content, if it has been reviewed and not evicted
+    pub fn verdict(&self, content_id: &str) -> Option<ModerationVerdict> {
+        lock(&self.state).verdicts.get(content_id).cloned()
+    }
+
+    pub fn is_whitelisted(&self, content_id: &str) -> bool {
+        lock(&self.state).overrides.contains_key(content_id)
+    }
+
+    pub fn overrides(&self) -> BTreeMap<String, Override> {
+        lock(&self.state).overrides.clone()
+    }
+
+    // Allow some content from now on, whatever the moderator said
+    pub fn override_content(&self, content_id: &str, reviewer: &str, note: &str, now: f64) {
+        let mut state = lock(&self.state);
+        state.whitelist(content_id, reviewer, note, now, None);
+        state.log(now, reviewer, AuditAction::Override, content_id, None, note);
+    }
+
+    // Take content off the whitelist; the moderator reviews it again next time
+    pub fn revoke_override(&self, content_id: &str, reviewer: &str, note: &str, now: f64) -> bool {
+        let mut state = lock(&self.state);
+        if state.overrides.remove(content_id).is_none() {
+            return false;
+        }
+        state.log(now, reviewer, AuditAction::Revoke, content_id, None, note);
+        true
+    }
+
+    // File an appeal against the verdict the moderator gave `text`; returns the appeal id
+    pub fn appeal(&self, player: &str, text: &str, reason: &str, now: f64) -> Result<u64, ModerationError> {
+        let id = content_id(text);
+        let mut state = lock(&self.state);
+        if state.overrides.contains_key(&id) {
+            return Err(ModerationError::AlreadyAllowed(id));
+        }
+        let Some(verdict) = state.moderated.get(&id).cloned() else {
+            return Err(ModerationError::NotModerated(id));
+        };
+        // One open appeal per content is enough
+        if let Some(existing) = state.appeals.values().find(|a| a.content_id == id && a.status == AppealStatus::Pending) {
+            return Ok(existing.id);
+        }
+        let appeal_id = state.next_appeal.max(1);
+        state.next_appeal = appeal_id + 1;
+        state.appeals.insert(
+            appeal_id,
+            Appeal {
+                id: appeal_id,
+                content_id: id.clone(),
+                player: player.to_string(),
+                text: text.trim().to_string(),
+                verdict,
+                reason: reason.to_string(),
+                filed_at: now,
+                status: AppealStatus::Pending,
+                reviewer: None,
+                resolved_at: None,
+                note: String::new(),
+            },
+        );
+        state.log(now, player, AuditAction::AppealFiled, &id, Some(appeal_id), reason);
+        Ok(appeal_id)
+    }
+
+    // Decide an appeal; overturning whitelists the content
+    pub fn resolve_appeal(&self, appeal_id: u64, reviewer: &str, overturn: bool, note: &str, now: f64) -> Result<Appeal, ModerationError> {
+        let mut state = lock(&self.state);
+        let appeal = state.appeals.get_mut(&appeal_id).ok_or(ModerationError::UnknownAppeal(appeal_id))?;
+        if appeal.status != AppealStatus::Pending {
+            return Err(ModerationError::AlreadyResolved(appeal_id));
+        }
+        appeal.status = if overturn { AppealStatus::Overturned } else { AppealStatus::Upheld };
+        appeal.reviewer = Some(reviewer.to_string());
+        appeal.resolved_at = Some(now);
+        appeal.note = note.to_string();
+        let appeal = appeal.clone();
+        let action = if overturn {
+            state.whitelist(&appeal.content_id, reviewer, note, now, Some(appeal_id));
+            AuditAction::AppealOverturned
+        } else {
+            AuditAction::AppealUpheld
+        };
+        state.log(now, reviewer, action, &appeal.content_id, Some(appeal_id), note);
+        Ok(appeal)
+    }
+
+    pub fn appeal_by_id(&self, appeal_id: u64) -> Option<Appeal> {
+        lock(&self.state).appeals.get(&appeal_id).cloned()
+    }
+
+    // Oldest first
+    pub fn pending_appeals(&self) -> Vec<Appeal> {
+        lock(&self.state).appeals.values().filter(|a| a.status == AppealStatus::Pending).cloned().collect()
+    }
+
+    pub fn audit(&self) -> Vec<AuditEntry> {
+        lock(&self.state).audit.clone()
+    }
+
+    pub fn audit_for(&self, content_id: &str) -> Vec<AuditEntry> {
+        lock(&self.state).audit.iter().filter(|entry| entry.content_id == content_id).cloned().collect()
+    }
+
+    // Appeals the player filed, oldest first
+    pub fn appeals_by(&self, player: &str) -> Vec<Appeal> {
+        lock(&self.state).appeals.values().filter(|a| a.player == player).cloned().collect()
+    }
+
+    // Audit entries of actions the player took (filing appeals)
+    pub fn audit_by(&self, actor: &str) -> Vec<AuditEntry> {
+        lock(&self.state).audit.iter().filter(|entry| entry.actor == actor).cloned().collect()
+    }
+
+    // Drop the player's appeals and the audit entries of their actions (privacy erasure); returns
+    // the number removed. Overrides that resulted from their appeals stay, they hold no text.
+    pub fn forget_player(&self, player: &str) -> usize {
+        let mut state = lock(&self.state);
+        let before = state.appeals.len() + state.audit.len();
+        state.appeals.retain(|_, appeal| appeal.player != player);
+        state.audit.retain(|entry| entry.actor != player);
+        state.pending.retain(|entry| entry.actor != player);
+        before - state.appeals.len() - state.audit.len()
+    }
+
+    pub fn stats(&self) -> ModerationStats {
+        let state = lock(&self.state);
+        let mut stats = state.stats;
+        stats.cached_verdicts = state.verdicts.len();
+        stats.overrides = state.overrides.len();
+        let count = |status| state.appeals.values().filter(|a| a.status == status).count();
+        stats.appeals_pending = count(AppealStatus::Pending);
+        stats.appeals_upheld = count(AppealStatus::Upheld);
+        stats.appeals_overturned = count(AppealStatus::Overturned);
+        stats
+    }
+
+    // Drop cached verdicts, e.g. after the moderator's rules changed; overrides stay
+    pub fn clear_cache(&self) -> usize {
+        let mut state = lock(&self.state);
+        state.order.clear();
+        let cleared = state.verdicts.len();
+        state.verdicts.clear();
+        cleared
+    }
+
+    // Publish new audit entries on MODERATION_AUDIT_TOPIC; returns the number published
+    pub fn publish(&mut self, events: &mut EventBus) -> usize {
+        let pending = std::mem::take(&mut lock(&self.state).pending);
+        for entry in &pending {
+            events.emit(MODERATION_AUDIT_TOPIC, &entry.actor, json!(entry));
+        }
+        pending.len()
+    }
+
+    // Store the whitelist, moderated content ids, appeals, audit trail and id counters; cached
+    // verdicts are not kept. Stored appeals and audit entries the desk no longer holds (erased with
+    // a player's data) are removed.
+    pub fn save(&self, db: &mut AgentDb, namespace: &Namespace) -> Result<(), ModerationError> {
+        let state = lock(&self.state);
+        let mut current: Vec<String> = state.appeals.keys().map(|id| format!("{}{:012}", APPEAL_PREFIX, id)).collect();
+        current.extend(state.audit.iter().map(|entry| format!("{}{:012}", AUDIT_PREFIX, entry.seq)));
+        let stale: Vec<String> = db
+            .scan(namespace, MODERATION_TABLE)
+            .map(|(key, _)| key.clone())
+            .filter(|key| key.starts_with(APPEAL_PREFIX) || key.starts_with(AUDIT_PREFIX))
+            .filter(|key| !current.contains(key))
+            .collect();
+        for key in stale {
+            db.delete(namespace, MODERATION_TABLE, &key);
+        }
+        db.put(namespace, MODERATION_TABLE, OVERRIDES_KEY, serde_json::to_value(&state.overrides)?)?;
+        let moderated: BTreeMap<&String, &ModerationVerdict> = state.moderated.iter().collect();
+        db.put(namespace, MODERATION_TABLE, MODERATED_KEY, serde_json::to_value(moderated)?)?;
+        let counters = Counters { next_appeal: state.next_appeal, next_audit: state.next_audit };
+        db.put(namespace, MODERATION_TABLE, COUNTERS_KEY, serde_json::to_value(counters)?)?;
+        for appeal in state.appeals.values() {
+            db.put(namespace, MODERATION_TABLE, &format!("{}{:012}", APPEAL_PREFIX, appeal.id), serde_json::to_value(appeal)?)?;
+        }
+        for entry in &state.audit {
+            db.put(namespace, MODERATION_TABLE, &format!("{}{:012}", AUDIT_PREFIX, entry.seq), serde_json::to_value(entry)?)?;
+        }
+        Ok(())
+    }
+
+    // Load what `save` stored, replacing the whitelist, moderated content, appeals and audit trail
+    pub fn restore(&self, db: &AgentDb, namespace: &Namespace) -> Result<(), ModerationError> {
+        let overrides: BTreeMap<String, Override> = match db.get(namespace, MODERATION_TABLE, OVERRIDES_KEY) {
+            Some(value) => serde_json::from_value(value.clone())?,
+            None => BTreeMap::new(),
+        };
+        let moderated: HashMap<String, ModerationVerdict> = match db.get(namespace, MODERATION_TABLE, MODERATED_KEY) {
+            Some(value) => serde_json::from_value(value.clone())?,
+            None => HashMap::new(),
+        };
+        let counters: Option<Counters> =
+            db.get(namespace, MODERATION_TABLE, COUNTERS_KEY).map(|value| serde_json::from_value(value.clone())).transpose()?;
+        let mut appeals = BTreeMap::new();
+        for (_, value) in db.scan_prefix(namespace, MODERATION_TABLE, APPEAL_PREFIX) {
+            let appeal: Appeal = serde_json::from_value(value.clone())?;
+            appeals.insert(appeal.id, appeal);
+        }
+        let mut audit = Vec::new();
+        for (_, value) in db.scan_prefix(namespace, MODERATION_TABLE, AUDIT_PREFIX) {
+            audit.push(serde_json::from_value::<AuditEntry>(value.clone())?);
+        }
+
+        let mut state = lock(&self.state);
+        for id in overrides.keys() {
+            state.verdicts.remove(id);
+        }
+        state.order.retain(|id| !overrides.contains_key(id));
+        // Stores written before the counters were saved fall back to the highest surviving ids
+        state.next_appeal = counters.map_or_else(|| appeals.keys().next_back().map_or(1, |id| id + 1), |c| c.next_appeal);
+        state.next_audit = counters.map_or_else(|| audit.last().map_or(0, |entry| entry.seq), |c| c.next_audit);
+        state.moderated = moderated;
+        state.overrides = overrides;
+        state.appeals = appeals;
+        state.audit = audit;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    // Rejects anything calling someone a noob and counts its calls
+    struct Strict(Cell<usize>);
+
+    impl ChatModerator for &Strict {
+        fn review(&self, _sender: &str, text: &str) -> ModerationVerdict {
+            self.0.set(self.0.get() + 1);
+            if text.contains("noob") {
+                ModerationVerdict::Reject("insult".to_string())
+            } else {
+                ModerationVerdict::Allow
+            }
+        }
+    }
+
+    #[test]
+    fn verdicts_are_cached_until_evicted() {
+        let strict = Strict(Cell::new(0));
+        let moderator = CachedModerator::new(&strict, 2);
+        assert_eq!(moderator.review("p1", "gg"), ModerationVerdict::Allow);
+        assert_eq!(moderator.review("p2", "  gg "), ModerationVerdict::Allow);
+        assert_eq!(strict.0.get(), 1);
+
+        moderator.review("p1", "lol");
+        moderator.review("p1", "you noob");
+        assert_eq!(moderator.desk().verdict(&content_id("gg")), None);
+        moderator.review("p1", "gg");
+        assert_eq!(strict.0.get(), 4);
+        let stats = moderator.desk().stats();
+        assert_eq!((stats.cache_hits, stats.evictions, stats.cached_verdicts), (1, 2, 2));
+    }
+
+    #[test]
+    fn overrides_skip_the_moderator_until_revoked() {
+        let strict = Strict(Cell::new(0));
+        let moderator = CachedModerator::new(&strict, 8);
+        let desk = moderator.desk();
+        let id = content_id("noob tube incoming");
+        assert!(matches!(moderator.review("p1", "noob tube incoming"), ModerationVerdict::Reject(_)));
+
+        desk.override_content(&id, "mod_anna", "weapon name", 10.0);
+        assert_eq!(moderator.review("p1", "noob tube incoming"), ModerationVerdict::Allow);
+        assert_eq!((strict.0.get(), desk.stats().whitelisted), (1, 1));
+
+        assert!(desk.revoke_override(&id, "mod_anna", "changed our minds", 20.0));
+        assert!(!desk.revoke_override(&id, "mod_anna", "again", 21.0));
+        assert!(matches!(moderator.review("p1", "noob tube incoming"), ModerationVerdict::Reject(_)));
+        assert_eq!(strict.0.get(), 2);
+        let actions: Vec<AuditAction> = desk.audit_for(&id).into_iter().map(|entry| entry.action).collect();
+        assert_eq!(actions, vec![AuditAction::Override, AuditAction::Revoke]);
+    }
+
+    #[test]
+    fn overturned_appeals_whitelist_the_content() {
+        let strict = Strict(Cell::new(0));
+        let moderator = CachedModerator::new(&strict, 8);
+        let desk = moderator.desk();
+        moderator.review("p1", "noob tube incoming");
+        moderator.review("p1", "gg");
+        assert!(matches!(desk.appeal("p1", "gg", "?", 1.0), Err(ModerationError::NotModerated(_))));
+
+        let appeal = desk.appeal("p1", "noob tube incoming", "it's a weapon", 1.0).unwrap();
+        // A second appeal while the first is open returns the same one
+        assert_eq!(desk.appeal("p2", "noob tube incoming", "me too", 2.0).unwrap(), appeal);
+        assert_eq!(desk.pending_appeals().len(), 1);
+
+        let resolved = desk.resolve_appeal(appeal, "mod_anna", true, "weapon name", 3.0).unwrap();
+        assert_eq!((resolved.status, resolved.reviewer.as_deref()), (AppealStatus::Overturned, Some("mod_anna")));
+        assert!(desk.is_whitelisted(&content_id("noob tube incoming")));
+        assert_eq!(desk.overrides()[&content_id("noob tube incoming")].appeal, Some(appeal));
+        assert!(matches!(desk.resolve_appeal(appeal, "mod_anna", false, "", 4.0), Err(ModerationError::AlreadyResolved(_))));
+        assert!(matches!(desk.appeal("p1", "noob tube incoming", "", 5.0), Err(ModerationError::AlreadyAllowed(_))));
+        assert!(matches!(desk.resolve_appeal(99, "mod_anna", true, "", 5.0), Err(ModerationError::UnknownAppeal(99))));
+
+        moderator.review("p3", "total noob");
+        let upheld = desk.appeal("p3", "total noob", "was joking", 6.0).unwrap();
+        assert_eq!(desk.resolve_appeal(upheld, "mod_anna", false, "", 7.0).unwrap().status, AppealStatus::Upheld);
+        assert!(!desk.is_whitelisted(&content_id("total noob")));
+    }
+
+    #[test]
+    fn moderated_content_stays_appealable_without_a_cache() {
+        let strict = Strict(Cell::new(0));
+        let moderator = CachedModerator::new(&strict, 0);
+        let desk = moderator.desk();
+        moderator.review("p1", "noob");
+        assert_eq!(desk.verdict(&content_id("noob")), None);
+        assert!(desk.appeal("p1", "noob", "context", 1.0).is_ok());
+
+        let evicting = CachedModerator::new(&strict, 1);
+        evicting.review("p1", "noob");
+        evicting.review("p1", "lol");
+        assert!(evicting.desk().appeal("p1", "noob", "context", 1.0).is_ok());
+    }
+
+    #[test]
+    fn stats_count_reviews_verdicts_and_appeals() {
+        let strict = Strict(Cell::new(0));
+        let moderator = CachedModerator::new(&strict, 8);
+        let desk = moderator.desk();
+        for text in ["gg", "gg", "noob", "noob"] {
+            moderator.review("p1", text);
+        }
+        desk.appeal("p1", "noob", "", 1.0).unwrap();
+        let stats = desk.stats();
+        assert_eq!((stats.reviews, stats.moderator_calls, stats.cache_hits), (4, 2, 2));
+        assert_eq!((stats.allowed, stats.rejected, stats.appeals_pending), (2, 2, 1));
+        assert_eq!(stats.hit_rate(), 0.5);
+        assert_eq!(desk.clear_cache(), 2);
+        assert_eq!(desk.stats().cached_verdicts, 0);
+    }
+
+    #[test]
+    fn desks_survive_a_restart_without_reusing_ids() {
+        let strict = Strict(Cell::new(0));
+        let moderator = CachedModerator::new(&strict, 8);
+        let mut desk = moderator.desk();
+        moderator.review("p1", "noob tube");
+        moderator.review("p2", "noob");
+        let kept = desk.appeal("p1", "noob tube", "weapon", 1.0).unwrap();
+        desk.resolve_appeal(kept, "mod_anna", true, "weapon", 2.0).unwrap();
+        let erased = desk.appeal("p2", "noob", "joke", 3.0).unwrap();
+        // The newest appeal and audit entry go with the player's data
+        assert_eq!(desk.forget_player("p2"), 2);
+
+        let mut events = EventBus::new(8);
+        let subscription = events.subscribe("moderation.");
+        assert_eq!(desk.publish(&mut events), 2);
+        assert_eq!(events.drain(subscription)[0].source, "p1");
+
+        let (mut db, namespace) = (AgentDb::new(), Namespace::default_namespace());
+        desk.save(&mut db, &namespace).unwrap();
+        let fresh = CachedModerator::new(&strict, 8).desk();
+        fresh.restore(&db, &namespace).unwrap();
+        assert!(fresh.is_whitelisted(&content_id("noob tube")));
+        assert_eq!(fresh.appeal_by_id(kept).unwrap().status, AppealStatus::Overturned);
+        assert_eq!(fresh.audit(), desk.audit());
+
+        let next = fresh.appeal("p3", "noob", "again", 4.0).unwrap();
+        assert!(next > erased);
+        assert!(fresh.audit().last().unwrap().seq > 3);
+    }
+}
@@ -0,0 +1,400 @@
+// Peer-to-peer sessions
+//
+// For small co-op games without a dedicated server, one peer (the host) is authoritative and the
+// others send it their inputs. Peers that cannot reach each other directly (both behind NAT) talk
+// through a configured relay. Every peer tracks membership and heartbeats; when the host goes
+// quiet the remaining peers migrate authority to the longest-standing peer (ties broken by id),
+// which every peer computes the same way without extra negotiation. A migration epoch lets peers
+// discard messages from a former host.
+//
+// Replication goes through the same InterestManager API as dedicated sessions; only the current
+// host may replicate.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde_json::json;
+
+use crate::events::EventBus;
+use crate::multiplayer::interest::{InterestManager, RateCap, Update};
+use crate::spatial::SpatialIndex;
+use crate::world::{GameWorld, WorldDelta};
+
+#[derive(Debug, Clone)]
+pub struct RelayConfig {
+    // host:port of the relay server
+    pub address: String,
+    // Relay sessions are keyed by this token
+    pub token: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct P2pConfig {
+    pub max_peers: usize,
+    // Seconds without a heartbeat before a peer is considered gone
+    pub peer_timeout: f64,
+    pub relay: Option<RelayConfig>,
+    pub rate_cap: RateCap,
+}
+
+impl Default for P2pConfig {
+    fn default() -> Self {
+        P2pConfig { max_peers: 8, peer_timeout: 5.0, relay: None, rate_cap: RateCap::default() }
+    }
+}
+
+// What a connectivity probe found out about a peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reachability {
+    Direct,
+    // Hole punching failed; traffic has to go through the relay
+    BehindNat,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Route {
+    Direct,
+    Relayed(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct Peer {
+    pub id: String,
+    pub joined_at: f64,
+    pub last_seen: f64,
+    // Round trip time in seconds, if measured
+    pub rtt: Option<f64>,
+    pub route: Route,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum P2pError {
+    SessionFull(usize),
+    AlreadyJoined(String),
+    UnknownPeer(String),
+    // Peer is behind NAT and no relay is configured
+    Unreachable(String),
+    // Only the host may do this; carries the current host
+    NotHost(String),
+    // Message from a host of an earlier epoch
+    StaleEpoch { received: u32, current: u32 },
+}
+
+impl fmt::Display for P2pError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            P2pError::SessionFull(max) => write!(f, "session is full ({} peers)", max),
+            P2pError::AlreadyJoined(id) => write!(f, "peer '{}' is already in the session", id),
+            P2pError::UnknownPeer(id) => write!(f, "peer '{}' is not in the session", id),
+            P2pError::Unreachable(id) => write!(f, "peer '{}' is behind NAT and no relay is configured", id),
+            P2pError::NotHost(host) => write!(f, "only the host ('{}') can do this", host),
+            P2pError::StaleEpoch { received, current } => {
+                write!(f, "message from host epoch {} but the session is at epoch {}", received, current)
+            }
+        }
+    }
+}
+
+impl std::error::Error for P2pError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum P2pChange {
+    PeerLeft(String),
+    HostMigrated { from: String, to: String, epoch: u32 },
+}
+
+// One peer's view of a P2P session
+pub struct P2pSession {
+    pub id: String,
+    local: String,
+    host: String,
+    epoch: u32,
+    config: P2pConfig,
+    peers: BTreeMap<String, Peer>,
+    interest: InterestManager,
+}
+
+impl P2pSession {
+    // Start a session with the local peer as host
+    pub fn host(id: &str, local: &str, config: P2pConfig, now: f64) -> Self {
+        let mut session = P2pSession::empty(id, local, local, config);
+        session.insert(local, now, Route::Direct);
+        session
+    }
+
+    // Join someone else's session; `members` are (peer, joined_at) pairs as reported by the host
+    pub fn join(id: &str, local: &str, host: &str, members: &[(&str, f64)], config: P2pConfig, now: f64) -> Self {
+        let mut session = P2pSession::empty(id, local, host, config);
+        for (peer, joined_at) in members {
+            session.insert(peer, *joined_at, Route::Direct);
+        }
+        if !session.peers.contains_key(local) {
+            session.insert(local, now, Route::Direct);
+        }
+        for peer in session.peers.values_mut() {
+            peer.last_seen = now;
+        }
+        session
+    }
+
+    fn empty(id: &str, local: &str, host: &str, config: P2pConfig) -> Self {
+        P2pSession {
+            id: id.to_string(),
+            local: local.to_string(),
+            host: host.to_string(),
+            epoch: 0,
+            config,
+            peers: BTreeMap::new(),
+            interest: InterestManager::new(),
+        }
+    }
+
+    fn insert(&mut self, peer: &str, now: f64, route: Route) {
+        self.peers.insert(
+            peer.to_string(),
+            Peer { id: peer.to_string(), joined_at: now, last_seen: now, rtt: None, route },
+        );
+        self.interest.subscribe(peer, self.config.rate_cap);
+    }
+
+    pub fn local(&self) -> &str {
+        &self.local
+    }
+
+    pub fn current_host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn is_host(&self) -> bool {
+        self.local == self.host
+    }
+
+    pub fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
+    pub fn peers(&self) -> impl Iterator<Item = &Peer> {
+        self.peers.values()
+    }
+
+    pub fn route(&self, peer: &str) -> Option<&Route> {
+        self.peers.get(peer).map(|p| &p.route)
+    }
+
+    // Admit a peer, picking a direct or relayed route from the probe result
+    pub fn add_peer(&mut self, peer: &str, reachability: Reachability, now: f64) -> Result<&Route, P2pError> {
+        if self.peers.contains_key(peer) {
+            return Err(P2pError::AlreadyJoined(peer.to_string()));
+        }
+        if self.peers.len() >= self.config.max_peers {
+            return Err(P2pError::SessionFull(self.config.max_peers));
+        }
+        let route = match (reachability, &self.config.relay) {
+            (Reachability::Direct, _) => Route::Direct,
+            (Reachability::BehindNat, Some(relay)) => Route::Relayed(relay.address.clone()),
+            (Reachability::BehindNat, None) => return Err(P2pError::Unreachable(peer.to_string())),
+        };
+        self.insert(peer, now, route);
+        Ok(&self.peers[peer].route)
+    }
+
+    pub fn remove_peer(&mut self, peer: &str) -> Option<Peer> {
+        self.interest.unsubscribe(peer);
+        self.peers.remove(peer)
+    }
+
+    pub fn heartbeat(&mut self, peer: &str, now: f64, rtt: Option<f64>) -> Result<(), P2pError> {
+        let entry = self.peers.get_mut(peer).ok_or_else(|| P2pError::UnknownPeer(peer.to_string()))?;
+        entry.last_seen = entry.last_seen.max(now);
+        if rtt.is_some() {
+            entry.rtt = rtt;
+        }
+        Ok(())
+    }
+
+    // Reject authoritative messages stamped with an old host epoch. A newer epoch means this
+    // peer missed a migration and should adopt `sender` as host.
+    pub fn accept_from_host(&mut self, sender: &str, epoch: u32) -> Result<(), P2pError> {
+        if epoch < self.epoch {
+            return Err(P2pError::StaleEpoch { received: epoch, current: self.epoch });
+        }
+        if epoch > self.epoch {
+            self.epoch = epoch;
+            self.host = sender.to_string();
+        }
+        Ok(())
+    }
+
+    // Drop timed-out peers and migrate the host if it was one of them
+    pub fn tick(&mut self, now: f64, events: Option<&mut EventBus>) -> Vec<P2pChange> {
+        let timeout = self.config.peer_timeout;
+        let gone: Vec<String> = self
+            .peers
+            .values()
+            .filter(|p| p.id != self.local && now - p.last_seen > timeout)
+            .map(|p| p.id.clone())
+            .collect();
+        let mut changes = Vec::new();
+        for peer in gone {
+            self.remove_peer(&peer);
+            changes.push(P2pChange::PeerLeft(peer));
+        }
+        if !self.peers.contains_key(&self.host) {
+            if let Some(next) = self.successor() {
+                self.epoch += 1;
+                let from = std::mem::replace(&mut self.host, next.clone());
+                changes.push(P2pChange::HostMigrated { from, to: next, epoch: self.epoch });
+            }
+        }
+        if let Some(events) = events {
+            for change in &changes {
+                match change {
+                    P2pChange::PeerLeft(peer) => {
+                        events.emit("multiplayer.peer_left", &self.id, json!({ "peer": peer }));
+                    }
+                    P2pChange::HostMigrated { from, to, epoch } => {
+                        events.emit(
+                            "multiplayer.host_migrated",
+                            &self.id,
+                            json!({ "from": from, "to": to, "epoch": epoch }),
+                        );
+                    }
+                }
+            }
+        }
+        changes
+    }
+
+    // Longest-standing remaining peer, ties broken by id
+    fn successor(&self) -> Option<String> {
+        self.peers
+            .values()
+            .min_by(|a, b| a.joined_at.total_cmp(&b.joined_at).then_with(|| a.id.cmp(&b.id)))
+            .map(|p| p.id.clone())
+    }
+
+    // Interests per peer, same as for a dedicated session
+    pub fn interests(&mut self) -> &mut InterestManager {
+        &mut self.interest
+    }
+
+    pub fn replicate(&mut self, delta: &WorldDelta, world: &GameWorld, spatial: &SpatialIndex) -> Result<(), P2pError> {
+        if !self.is_host() {
+            return Err(P2pError::NotHost(self.host.clone()));
+        }
+        self.interest.replicate(delta, world, spatial);
+        Ok(())
+    }
+
+    // Updates to send to `peer` now, along with the route to send them on
+    pub fn poll(&mut self, peer: &str, now: f64) -> Result<(Route, Vec<Update>), P2pError> {
+        if !self.is_host() {
+            return Err(P2pError::NotHost(self.host.clone()));
+        }
+        let route = self.peers.get(peer).ok_or_else(|| P2pError::UnknownPeer(peer.to_string()))?.route.clone();
+        Ok((route, self.interest.poll(peer, now)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::multiplayer::interest::Interest;
+    use crate::spatial::GridSpec;
+    use crate::world::Entity;
+
+    fn relayed() -> P2pConfig {
+        P2pConfig {
+            max_peers: 3,
+            relay: Some(RelayConfig { address: "relay.example:7777".to_string(), token: "t".to_string() }),
+            ..P2pConfig::default()
+        }
+    }
+
+    // Alice hosts; bob and carol joined after her
+    fn members() -> [(&'static str, f64); 3] {
+        [("alice", 0.0), ("bob", 1.0), ("carol", 2.0)]
+    }
+
+    #[test]
+    fn peers_get_direct_or_relayed_routes() {
+        let mut session = P2pSession::host("s1", "alice", relayed(), 0.0);
+        assert!(session.is_host());
+        assert_eq!(session.add_peer("bob", Reachability::Direct, 1.0), Ok(&Route::Direct));
+        assert_eq!(
+            session.add_peer("carol", Reachability::BehindNat, 1.0),
+            Ok(&Route::Relayed("relay.example:7777".to_string()))
+        );
+        assert_eq!(session.add_peer("bob", Reachability::Direct, 1.0), Err(P2pError::AlreadyJoined("bob".to_string())));
+        assert_eq!(session.add_peer("dave", Reachability::Direct, 1.0), Err(P2pError::SessionFull(3)));
+
+        let mut no_relay = P2pSession::host("s2", "alice", P2pConfig::default(), 0.0);
+        assert_eq!(no_relay.add_peer("bob", Reachability::BehindNat, 1.0), Err(P2pError::Unreachable("bob".to_string())));
+        assert!(no_relay.route("bob").is_none());
+    }
+
+    #[test]
+    fn silent_host_is_replaced_by_the_longest_standing_peer() {
+        let mut bus = EventBus::new(16);
+        let sub = bus.subscribe("multiplayer.");
+        let mut bob = P2pSession::join("s1", "bob", "alice", &members(), P2pConfig::default(), 10.0);
+        let mut carol = P2pSession::join("s1", "carol", "alice", &members(), P2pConfig::default(), 10.0);
+        bob.heartbeat("carol", 14.0, Some(0.05)).unwrap();
+        carol.heartbeat("bob", 14.0, None).unwrap();
+        assert!(bob.tick(14.0, None).is_empty());
+
+        let migrated = P2pChange::HostMigrated { from: "alice".to_string(), to: "bob".to_string(), epoch: 1 };
+        assert_eq!(bob.tick(16.0, Some(&mut bus)), vec![P2pChange::PeerLeft("alice".to_string()), migrated.clone()]);
+        // Every peer reaches the same decision on its own
+        assert_eq!(carol.tick(16.0, None)[1], migrated);
+        assert!(bob.is_host() && !carol.is_host());
+        assert_eq!((carol.current_host(), carol.epoch()), ("bob", 1));
+
+        let topics: Vec<String> = bus.drain(sub).into_iter().map(|e| e.topic).collect();
+        assert_eq!(topics, vec!["multiplayer.peer_left", "multiplayer.host_migrated"]);
+        assert_eq!(bob.peers().find(|p| p.id == "carol").unwrap().rtt, Some(0.05));
+    }
+
+    #[test]
+    fn ties_on_join_time_go_to_the_lowest_id() {
+        let members = [("host", 0.0), ("zed", 1.0), ("amy", 1.0)];
+        let mut session = P2pSession::join("s1", "zed", "host", &members, P2pConfig::default(), 0.0);
+        session.remove_peer("host");
+        assert!(matches!(&session.tick(0.0, None)[0], P2pChange::HostMigrated { to, .. } if to == "amy"));
+    }
+
+    #[test]
+    fn host_messages_are_checked_against_the_epoch() {
+        let mut session = P2pSession::join("s1", "carol", "alice", &members(), P2pConfig::default(), 0.0);
+        // A newer epoch means this peer missed a migration
+        session.accept_from_host("bob", 2).unwrap();
+        assert_eq!((session.current_host(), session.epoch()), ("bob", 2));
+        assert_eq!(session.accept_from_host("alice", 1), Err(P2pError::StaleEpoch { received: 1, current: 2 }));
+        assert_eq!(session.heartbeat("dave", 1.0, None), Err(P2pError::UnknownPeer("dave".to_string())));
+    }
+
+    #[test]
+    fn only_the_host_replicates() {
+        let mut world = GameWorld::new();
+        world.spawn("chest", Entity::new("container"));
+        let mut spatial = SpatialIndex::new(GridSpec::new(8, 8, 1.0));
+        spatial.set_position("chest", [1.0, 1.0]);
+        let delta = WorldDelta { base_tick: 0, target_tick: 1, changes: Vec::new() };
+
+        let mut guest = P2pSession::join("s1", "bob", "alice", &members(), relayed(), 0.0);
+        assert_eq!(guest.replicate(&delta, &world, &spatial), Err(P2pError::NotHost("alice".to_string())));
+        assert!(guest.poll("alice", 0.0).is_err());
+
+        let mut host = P2pSession::host("s1", "alice", relayed(), 0.0);
+        host.add_peer("bob", Reachability::BehindNat, 0.0).unwrap();
+        host.interests().add_interest("bob", Interest::Entity("chest".to_string()));
+        host.replicate(&delta, &world, &spatial).unwrap();
+        let (route, updates) = host.poll("bob", 0.0).unwrap();
+        assert_eq!(route, Route::Relayed("relay.example:7777".to_string()));
+        assert!(matches!(&updates[..], [Update::Replication(_)]));
+        assert_eq!(host.poll("dave", 0.0).unwrap_err(), P2pError::UnknownPeer("dave".to_string()));
+
+        host.remove_peer("bob");
+        assert_eq!(host.interests().queued("bob"), 0);
+    }
+}
@@ -0,0 +1,368 @@
+// Latency compensation
+//
+// Helpers for the usual trio of techniques:
+// - client prediction: inputs are numbered, applied locally right away and kept in an InputBuffer
+//   until the server acknowledges them; on each authoritative update the client resets to the
+//   server state and replays the inputs the server has not seen yet (ClientPredictor)
+// - server rewind: the server records recent entity positions so a hit can be checked against
+//   where the target was when the shooter fired, up to a configurable maximum rewind (LagHistory)
+// - smoothing: a reconciled position that differs from what was drawn is blended in over a short
+//   time instead of snapping, unless the error is too large to hide (CorrectionSmoother)
+//
+// Game state and inputs are the caller's own types; the movement step is passed in as a function
+// so client and server run exactly the same simulation.
+
+use std::collections::{HashMap, VecDeque};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SequencedInput<I> {
+    pub sequence: u32,
+    // Simulation time the input covers, in seconds
+    pub dt: f32,
+    pub input: I,
+}
+
+pub struct InputBuffer<I> {
+    pending: VecDeque<SequencedInput<I>>,
+    next_sequence: u32,
+    capacity: usize,
+}
+
+impl<I: Clone> InputBuffer<I> {
+    // Inputs beyond `capacity` unacknowledged ones are dropped, oldest first
+    pub fn new(capacity: usize) -> Self {
+        InputBuffer { pending: VecDeque::new(), next_sequence: 1, capacity: capacity.max(1) }
+    }
+
+    // Number and store an input; the returned copy is what gets sent to the server
+    pub fn push(&mut self, input: I, dt: f32) -> SequencedInput<I> {
+        let sequenced = SequencedInput { sequence: self.next_sequence, dt, input };
+        self.next_sequence = self.next_sequence.wrapping_add(1);
+        self.pending.push_back(sequenced.clone());
+        while self.pending.len() > self.capacity {
+            self.pending.pop_front();
+        }
+        sequenced
+    }
+
+    // Drop every input up to and including `sequence`
+    pub fn acknowledge(&mut self, sequence: u32) {
+        while self.pending.front().is_some_and(|i| !is_newer(i.sequence, sequence)) {
+            self.pending.pop_front();
+        }
+    }
+
+    pub fn pending(&self) -> impl Iterator<Item = &SequencedInput<I>> {
+        self.pending.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+// Sequence comparison that survives wrap-around
+fn is_newer(a: u32, b: u32) -> bool {
+    a != b && a.wrapping_sub(b) < u32::MAX / 2
+}
+
+pub struct ClientPredictor<S, I> {
+    state: S,
+    inputs: InputBuffer<I>,
+    last_acknowledged: Option<u32>,
+}
+
+impl<S: Clone, I: Clone> ClientPredictor<S, I> {
+    pub fn new(initial: S, buffer_capacity: usize) -> Self {
+        ClientPredictor { state: initial, inputs: InputBuffer::new(buffer_capacity), last_acknowledged: None }
+    }
+
+    // Current predicted state
+    pub fn state(&self) -> &S {
+        &self.state
+    }
+
+    pub fn pending(&self) -> usize {
+        self.inputs.len()
+    }
+
+    // Apply an input locally and return it for sending to the server
+    pub fn predict(&mut self, input: I, dt: f32, step: impl Fn(&S, &I, f32) -> S) -> SequencedInput<I> {
+        self.state = step(&self.state, &input, dt);
+        self.inputs.push(input, dt)
+    }
+
+    // Adopt the server's state as of input `last_processed` and replay the newer inputs.
+    // Returns the state that was predicted before, so the caller can smooth the difference.
+    // Updates older than one already applied are ignored (returns None).
+    pub fn reconcile(&mut self, server_state: S, last_processed: u32, step: impl Fn(&S, &I, f32) -> S) -> Option<S> {
+        if self.last_acknowledged.is_some_and(|last| is_newer(last, last_processed)) {
+            return None;
+        }
+        self.last_acknowledged = Some(last_processed);
+        self.inputs.acknowledge(last_processed);
+        let previous = std::mem::replace(&mut self.state, server_state);
+        for input in self.inputs.pending() {
+            self.state = step(&self.state, &input.input, input.dt);
+        }
+        Some(previous)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RewindConfig {
+    // Furthest back a hit may be validated, in seconds
+    pub max_rewind: f64,
+    // Extra allowance on the hit radius for interpolation error
+    pub tolerance: f32,
+}
+
+impl Default for RewindConfig {
+    fn default() -> Self {
+        RewindConfig { max_rewind: 0.25, tolerance: 0.1 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HitCheck {
+    Hit { distance: f32, rewound_to: f64 },
+    Miss { distance: f32, rewound_to: f64 },
+    // No recorded position for the target at that time
+    Unknown,
+}
+
+// Recent authoritative positions per entity, for rewinding hit checks
+pub struct LagHistory {
+    config: RewindConfig,
+    // Oldest first
+    frames: VecDeque<(f64, HashMap<String, [f32; 3]>)>,
+}
+
+impl LagHistory {
+    pub fn new(config: RewindConfig) -> Self {
+        LagHistory { config, frames: VecDeque::new() }
+    }
+
+    // Record the positions at server time `now`; frames older than the rewind window are dropped
+    pub fn record(&mut self, now: f64, positions: HashMap<String, [f32; 3]>) {
+        self.frames.push_back((now, positions));
+        // Keep one frame past the window so the oldest allowed time can still be interpolated
+        while self.frames.len() > 2 && self.frames[1].0 < now - self.config.max_rewind {
+            self.frames.pop_front();
+        }
+    }
+
+    // Where `entity` was at `time`, clamped to the rewind window, interpolated between frames
+    pub fn position_at(&self, entity: &str, time: f64) -> Option<([f32; 3], f64)> {
+        let (latest, _) = self.frames.back()?;
+        let time = time.clamp(latest - self.config.max_rewind, *latest);
+        let after = self.frames.iter().position(|(t, _)| *t >= time)?;
+        let (t1, frame1) = &self.frames[after];
+        let p1 = *frame1.get(entity)?;
+        if after == 0 || *t1 == time {
+            return Some((p1, time));
+        }
+        let (t0, frame0) = &self.frames[after - 1];
+        let Some(p0) = frame0.get(entity) else {
+            return Some((p1, time));
+        };
+        let alpha = ((time - t0) / (t1 - t0)) as f32;
+        Some(([lerp(p0[0], p1[0], alpha), lerp(p0[1], p1[1], alpha), lerp(p0[2], p1[2], alpha)], time))
+    }
+
+    // Check a shot at `point` against the target's rewound position. `fired_at` is the server
+    // time the shooter saw, typically now minus their latency and interpolation delay.
+    pub fn validate_hit(&self, target: &str, fired_at: f64, point: [f32; 3], radius: f32) -> HitCheck {
+        match self.position_at(target, fired_at) {
+            Some((position, rewound_to)) => {
+                let distance = distance3(position, point);
+                if distance <= radius + self.config.tolerance {
+                    HitCheck::Hit { distance, rewound_to }
+                } else {
+                    HitCheck::Miss { distance, rewound_to }
+                }
+            }
+            None => HitCheck::Unknown,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SmoothingConfig {
+    // Fraction of the remaining error removed per second
+    pub rate: f32,
+    // Errors larger than this are applied immediately
+    pub snap_distance: f32,
+}
+
+impl Default for SmoothingConfig {
+    fn default() -> Self {
+        SmoothingConfig { rate: 10.0, snap_distance: 3.0 }
+    }
+}
+
+// Hides reconciliation corrections by drawing the entity offset from its true position and
+// shrinking the offset over time
+#[derive(Debug, Clone)]
+pub struct CorrectionSmoother {
+    config: SmoothingConfig,
+    offset: [f32; 3],
+}
+
+impl CorrectionSmoother {
+    pub fn new(config: SmoothingConfig) -> Self {
+        CorrectionSmoother { config, offset: [0.0; 3] }
+    }
+
+    // The position jumped from `before` to `after`; returns true when it was snapped
+    pub fn correct(&mut self, before: [f32; 3], after: [f32; 3]) -> bool {
+        let shown = [before[0] + self.offset[0], before[1] + self.offset[1], before[2] + self.offset[2]];
+        let error = [shown[0] - after[0], shown[1] - after[1], shown[2] - after[2]];
+        if distance3(error, [0.0; 3]) > self.config.snap_distance {
+            self.offset = [0.0; 3];
+            true
+        } else {
+            self.offset = error;
+            false
+        }
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        let keep = (-self.config.rate * dt.max(0.0)).exp();
+        for axis in &mut self.offset {
+            *axis *= keep;
+            if axis.abs() < 1e-4 {
+                *axis = 0.0;
+            }
+        }
+    }
+
+    // Where to draw an entity whose simulated position is `position`
+    pub fn render_position(&self, position: [f32; 3]) -> [f32; 3] {
+        [position[0] + self.offset[0], position[1] + self.offset[1], position[2] + self.offset[2]]
+    }
+
+    pub fn offset(&self) -> [f32; 3] {
+        self.offset
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn distance3(a: [f32; 3], b: [f32; 3]) -> f32 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // One-dimensional movement: position += velocity * dt
+    fn step(position: &f32, velocity: &f32, dt: f32) -> f32 {
+        position + velocity * dt
+    }
+
+    fn at(x: f32) -> HashMap<String, [f32; 3]> {
+        HashMap::from([("target".to_string(), [x, 0.0, 0.0])])
+    }
+
+    #[test]
+    fn input_buffer_numbers_caps_and_acknowledges() {
+        let mut buffer = InputBuffer::new(3);
+        let sequences: Vec<u32> = (0..4).map(|i| buffer.push(i, 0.1).sequence).collect();
+        assert_eq!(sequences, vec![1, 2, 3, 4]);
+        assert_eq!(buffer.pending().map(|i| i.input).collect::<Vec<_>>(), vec![1, 2, 3]);
+
+        buffer.acknowledge(3);
+        assert_eq!(buffer.pending().map(|i| i.sequence).collect::<Vec<_>>(), vec![4]);
+        buffer.acknowledge(9);
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn sequence_order_survives_wrap_around() {
+        assert!(is_newer(2, 1));
+        assert!(is_newer(1, u32::MAX));
+        assert!(!is_newer(u32::MAX, 1));
+        assert!(!is_newer(5, 5));
+
+        let mut buffer = InputBuffer::new(8);
+        buffer.next_sequence = u32::MAX;
+        assert_eq!(buffer.push('a', 0.1).sequence, u32::MAX);
+        assert_eq!(buffer.push('b', 0.1).sequence, 0);
+        buffer.acknowledge(u32::MAX);
+        assert_eq!(buffer.len(), 1);
+    }
+
+    #[test]
+    fn reconcile_replays_unacknowledged_inputs() {
+        let mut client = ClientPredictor::new(0.0f32, 16);
+        for _ in 0..3 {
+            client.predict(1.0, 1.0, step);
+        }
+        assert_eq!((*client.state(), client.pending()), (3.0, 3));
+
+        // The server was slowed down during input 1
+        assert_eq!(client.reconcile(0.5, 1, step), Some(3.0));
+        assert_eq!((*client.state(), client.pending()), (2.5, 2));
+
+        // Updates older than the last one applied are ignored
+        assert_eq!(client.reconcile(0.0, 0, step), None);
+        assert_eq!(*client.state(), 2.5);
+        assert_eq!(client.reconcile(2.5, 3, step), Some(2.5));
+        assert_eq!(client.pending(), 0);
+    }
+
+    #[test]
+    fn lag_history_interpolates_within_the_rewind_window() {
+        let mut history = LagHistory::new(RewindConfig { max_rewind: 0.25, tolerance: 0.1 });
+        for tick in 0..=10 {
+            history.record(tick as f64 * 0.1, at(tick as f32));
+        }
+        let (position, time) = history.position_at("target", 0.85).unwrap();
+        assert!((position[0] - 8.5).abs() < 1e-4 && (time - 0.85).abs() < 1e-9);
+        // Older requests are clamped to the window; only one frame beyond it is kept
+        let (position, time) = history.position_at("target", 0.0).unwrap();
+        assert!((position[0] - 7.5).abs() < 1e-4 && (time - 0.75).abs() < 1e-9);
+        assert_eq!(history.frames.len(), 4);
+        assert!(history.position_at("ghost", 0.9).is_none());
+    }
+
+    #[test]
+    fn hits_are_checked_against_the_rewound_position() {
+        let mut history = LagHistory::new(RewindConfig::default());
+        history.record(1.0, at(0.0));
+        history.record(1.1, at(1.0));
+        // The target has moved on, but was at x=0.5 when the shot was fired
+        assert!(matches!(history.validate_hit("target", 1.05, [0.5, 0.0, 0.0], 0.2), HitCheck::Hit { .. }));
+        match history.validate_hit("target", 1.1, [0.5, 0.0, 0.0], 0.2) {
+            HitCheck::Miss { distance, rewound_to } => assert_eq!((distance, rewound_to), (0.5, 1.1)),
+            other => panic!("expected a miss, got {:?}", other),
+        }
+        assert_eq!(history.validate_hit("ghost", 1.1, [0.0; 3], 1.0), HitCheck::Unknown);
+        assert_eq!(LagHistory::new(RewindConfig::default()).validate_hit("target", 0.0, [0.0; 3], 1.0), HitCheck::Unknown);
+    }
+
+    #[test]
+    fn small_corrections_are_smoothed_and_large_ones_snap() {
+        let mut smoother = CorrectionSmoother::new(SmoothingConfig { rate: 10.0, snap_distance: 3.0 });
+        assert!(!smoother.correct([1.0, 0.0, 0.0], [0.0, 0.0, 0.0]));
+        // Drawn where it was before the correction, then eased towards the true position
+        assert_eq!(smoother.render_position([0.0; 3]), [1.0, 0.0, 0.0]);
+        smoother.update(0.1);
+        assert!((smoother.offset()[0] - (-1.0f32).exp()).abs() < 1e-5);
+        for _ in 0..20 {
+            smoother.update(0.1);
+        }
+        assert_eq!(smoother.offset(), [0.0; 3]);
+
+        assert!(smoother.correct([10.0, 0.0, 0.0], [0.0, 0.0, 0.0]));
+        assert_eq!(smoother.offset(), [0.0; 3]);
+    }
+}
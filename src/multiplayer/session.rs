@@ -0,0 +1,233 @@
+// Multiplayer sessions
+//
+// A session is the server's authoritative view of the players in one shared world instance.
+// Client-reported actions go through anti-cheat validation (when enabled) before they change
+// the authoritative state; enforcement verdicts are applied here, so a kicked player is removed
+// and a banned player cannot rejoin the session.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use serde_json::json;
+
+use crate::events::EventBus;
+use crate::security::anti_cheat::{AntiCheat, ClientAction, Enforcement, Verdict};
+use crate::security::SecurityFeatures;
+
+#[derive(Debug, Clone)]
+pub struct PlayerState {
+    pub id: String,
+    pub position: [f32; 3],
+    pub state: String,
+    pub joined_at: f64,
+    pub last_action_at: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionError {
+    SessionFull(usize),
+    AlreadyJoined(String),
+    Banned(String),
+    UnknownPlayer(String),
+    Rejected { player: String, rules: Vec<String> },
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::SessionFull(max) => write!(f, "session is full ({} players)", max),
+            SessionError::AlreadyJoined(id) => write!(f, "player '{}' is already in the session", id),
+            SessionError::Banned(id) => write!(f, "player '{}' is banned from the session", id),
+            SessionError::UnknownPlayer(id) => write!(f, "player '{}' is not in the session", id),
+            SessionError::Rejected { player, rules } => {
+                write!(f, "action from '{}' rejected by {}", player, rules.join(", "))
+            }
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+pub struct Session {
+    pub id: String,
+    max_players: usize,
+    players: HashMap<String, PlayerState>,
+    banned: HashSet<String>,
+    anti_cheat: Option<AntiCheat>,
+}
+
+impl Session {
+    pub fn new(id: &str, max_players: usize, security: &SecurityFeatures) -> Self {
+        Session {
+            id: id.to_string(),
+            max_players,
+            players: HashMap::new(),
+            banned: HashSet::new(),
+            anti_cheat: security.anti_cheat(),
+        }
+    }
+
+    // Access to register game-specific rules
+    pub fn anti_cheat_mut(&mut self) -> Option<&mut AntiCheat> {
+        self.anti_cheat.as_mut()
+    }
+
+    pub fn join(&mut self, player: &str, position: [f32; 3], now: f64) -> Result<(), SessionError> {
+        if self.banned.contains(player) {
+            return Err(SessionError::Banned(player.to_string()));
+        }
+        if self.players.contains_key(player) {
+            return Err(SessionError::AlreadyJoined(player.to_string()));
+        }
+        if self.players.len() >= self.max_players {
+            return Err(SessionError::SessionFull(self.max_players));
+        }
+        self.players.insert(
+            player.to_string(),
+            PlayerState {
+                id: player.to_string(),
+                position,
+                state: "idle".to_string(),
+                joined_at: now,
+                last_action_at: now,
+            },
+        );
+        // Seed the validator with the spawn position so the first move is checked too
+        if let Some(anti_cheat) = &mut self.anti_cheat {
+            anti_cheat.teleport(player, position, now);
+        }
+        Ok(())
+    }
+
+    pub fn leave(&mut self, player: &str) -> Option<PlayerState> {
+        if let Some(anti_cheat) = &mut self.anti_cheat {
+            anti_cheat.forget(player);
+        }
+        self.players.remove(player)
+    }
+
+    pub fn player(&self, id: &str) -> Option<&PlayerState> {
+        self.players.get(id)
+    }
+
+    pub fn players(&self) -> impl Iterator<Item = &PlayerState> {
+        self.players.values()
+    }
+
+    pub fn is_banned(&self, player: &str) -> bool {
+        self.banned.contains(player)
+    }
+
+    // Validate and apply a client-reported action
+    pub fn apply(&mut self, action: ClientAction, mut events: Option<&mut EventBus>) -> Result<Verdict, SessionError> {
+        if !self.players.contains_key(&action.player) {
+            return Err(SessionError::UnknownPlayer(action.player.clone()));
+        }
+        let verdict = match &mut self.anti_cheat {
+            Some(anti_cheat) => anti_cheat.validate(&action, events.as_deref_mut()),
+            None => Verdict { accepted: true, violations: Vec::new(), escalated_to: None },
+        };
+
+        match verdict.escalated_to {
+            Some(Enforcement::Kick) => self.remove_for(&action.player, "kicked", events.as_deref_mut()),
+            Some(Enforcement::Ban) => {
+                self.banned.insert(action.player.clone());
+                self.remove_for(&action.player, "banned", events);
+            }
+            _ => {}
+        }
+
+        if !verdict.accepted {
+            return Err(SessionError::Rejected {
+                player: action.player.clone(),
+                rules: verdict.violations.iter().map(|v| v.rule.clone()).collect(),
+            });
+        }
+        if let Some(player) = self.players.get_mut(&action.player) {
+            if let Some(position) = action.position {
+                player.position = position;
+            }
+            if let Some(state) = action.new_state {
+                player.state = state;
+            }
+            player.last_action_at = action.timestamp;
+        }
+        Ok(verdict)
+    }
+
+    fn remove_for(&mut self, player: &str, reason: &str, events: Option<&mut EventBus>) {
+        if self.players.remove(player).is_some() {
+            if let Some(events) = events {
+                events.emit("multiplayer.player_removed", &self.id, json!({ "player": player, "reason": reason }));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::anti_cheat::AntiCheatConfig;
+
+    fn secured(config: AntiCheatConfig) -> SecurityFeatures {
+        SecurityFeatures { anti_cheat_enabled: true, anti_cheat: config }
+    }
+
+    #[test]
+    fn join_enforces_capacity_and_uniqueness() {
+        let mut session = Session::new("s", 1, &SecurityFeatures::default());
+        session.join("a", [0.0; 3], 0.0).unwrap();
+        assert_eq!(session.join("a", [0.0; 3], 0.0), Err(SessionError::AlreadyJoined("a".to_string())));
+        assert_eq!(session.join("b", [0.0; 3], 0.0), Err(SessionError::SessionFull(1)));
+        assert_eq!(session.leave("a").unwrap().id, "a");
+        session.join("b", [0.0; 3], 1.0).unwrap();
+        assert!(session.anti_cheat_mut().is_none());
+    }
+
+    #[test]
+    fn accepted_actions_update_player_state() {
+        let mut session = Session::new("s", 4, &secured(AntiCheatConfig::default()));
+        session.join("a", [0.0; 3], 0.0).unwrap();
+        session.apply(ClientAction::new("a", "move", 1.0).at([5.0, 0.0, 0.0]).entering("running"), None).unwrap();
+        let player = session.player("a").unwrap();
+        assert_eq!(player.position, [5.0, 0.0, 0.0]);
+        assert_eq!(player.state, "running");
+        assert_eq!(player.last_action_at, 1.0);
+
+        assert_eq!(
+            session.apply(ClientAction::new("z", "move", 1.0), None).unwrap_err(),
+            SessionError::UnknownPlayer("z".to_string())
+        );
+    }
+
+    #[test]
+    fn rejected_actions_leave_state_untouched() {
+        let mut session = Session::new("s", 4, &secured(AntiCheatConfig::default()));
+        session.join("a", [0.0; 3], 0.0).unwrap();
+        let err = session.apply(ClientAction::new("a", "move", 1.0).at([500.0, 0.0, 0.0]), None).unwrap_err();
+        assert_eq!(err, SessionError::Rejected { player: "a".to_string(), rules: vec!["speed_limit".to_string()] });
+        assert_eq!(session.player("a").unwrap().position, [0.0; 3]);
+    }
+
+    #[test]
+    fn kicks_remove_and_bans_block_rejoining() {
+        let config = AntiCheatConfig { warn_at: 0.5, kick_at: 1.0, ban_at: 1.5, ..Default::default() };
+        let mut session = Session::new("s", 4, &secured(config));
+        let mut events = EventBus::new(16);
+        let sub = events.subscribe("multiplayer.");
+
+        session.join("a", [0.0; 3], 0.0).unwrap();
+        session.apply(ClientAction::new("a", "move", 1.0).at([500.0, 0.0, 0.0]), Some(&mut events)).unwrap_err();
+        assert!(session.player("a").is_none());
+        assert!(!session.is_banned("a"));
+        assert_eq!(events.drain(sub)[0].payload["reason"], "kicked");
+
+        // A kick keeps the validator's history, so suspicion carries over into the rejoin
+        session.join("a", [0.0; 3], 2.0).unwrap();
+        for t in [3.0, 4.0] {
+            let _ = session.apply(ClientAction::new("a", "move", t).at([500.0 * t as f32, 0.0, 0.0]), Some(&mut events));
+        }
+        assert!(session.is_banned("a"));
+        assert_eq!(session.join("a", [0.0; 3], 5.0), Err(SessionError::Banned("a".to_string())));
+    }
+}
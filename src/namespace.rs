@@ -0,0 +1,190 @@
+// Namespaces (tenants)
+//
+// Several games or save slots can share one Qdrant instance and one engine process. Every key a
+// store writes is qualified with its namespace ("save-2__memories"), quotas are enforced per
+// namespace and a whole namespace can be purged in one call. The separator is reserved: vector
+// collection names may only contain it once, right after a namespace, so a name that is not
+// qualified is never charged to a namespace.
+
+use std::fmt;
+
+use crate::agentdb::AgentDb;
+use crate::cache::Cache;
+use crate::vector_index::{VectorIndex, VectorIndexError};
+
+pub const SEPARATOR: &str = "__";
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Namespace(String);
+
+#[derive(Debug)]
+pub enum NamespaceError {
+    Invalid(String),
+}
+
+impl fmt::Display for NamespaceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NamespaceError::Invalid(name) => {
+                write!(f, "invalid namespace '{}': use letters, digits and '-' only", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for NamespaceError {}
+
+impl Namespace {
+    // Namespaces may only contain letters, digits and '-', so the separator is unambiguous
+    pub fn new(name: &str) -> Result<Self, NamespaceError> {
+        if !is_valid_name(name) {
+            return Err(NamespaceError::Invalid(name.to_string()));
+        }
+        Ok(Namespace(name.to_string()))
+    }
+
+    pub fn default_namespace() -> Self {
+        Namespace("default".to_string())
+    }
+
+    pub fn name(&self) -> &str {
+        &self.0
+    }
+
+    // "memories" -> "save-2__memories"
+    pub fn qualify(&self, key: &str) -> String {
+        format!("{}{}{}", self.0, SEPARATOR, key)
+    }
+
+    pub fn owns(&self, qualified: &str) -> bool {
+        qualified
+            .strip_prefix(self.0.as_str())
+            .is_some_and(|rest| rest.starts_with(SEPARATOR))
+    }
+
+    // Strip the namespace prefix from a qualified key
+    pub fn local<'a>(&self, qualified: &'a str) -> Option<&'a str> {
+        qualified.strip_prefix(self.0.as_str())?.strip_prefix(SEPARATOR)
+    }
+}
+
+impl fmt::Display for Namespace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+// Namespace part of a qualified key ("<namespace>__<local>", the local part without a separator of
+// its own), if it has one
+pub fn namespace_of(qualified: &str) -> Option<&str> {
+    let (namespace, local) = qualified.split_once(SEPARATOR)?;
+    (is_valid_name(namespace) && !local.is_empty() && !local.contains(SEPARATOR)).then_some(namespace)
+}
+
+// Names without the separator are plain; names with it must be qualified ones
+pub fn is_valid_key(name: &str) -> bool {
+    !name.contains(SEPARATOR) || namespace_of(name).is_some()
+}
+
+// Per-namespace resource limits (none = unlimited)
+#[derive(Debug, Clone, Default)]
+pub struct NamespaceQuota {
+    pub max_collections: Option<usize>,
+    pub max_points: Option<usize>,
+    pub max_records: Option<usize>,
+    pub max_cache_entries: Option<usize>,
+}
+
+// What purge_namespace removed
+#[derive(Debug, Clone, Default)]
+pub struct PurgeReport {
+    pub collections: usize,
+    pub points: usize,
+    pub records: usize,
+    pub cache_entries: usize,
+}
+
+// Remove everything a namespace owns from the vector index, agentdb and cache
+pub fn purge_namespace<V: Clone>(
+    namespace: &Namespace,
+    index: &mut VectorIndex,
+    db: &mut AgentDb,
+    cache: &mut Cache<V>,
+) -> Result<PurgeReport, VectorIndexError> {
+    let (collections, points) = index.purge_namespace(namespace)?;
+    Ok(PurgeReport {
+        collections,
+        points,
+        records: db.purge_namespace(namespace),
+        cache_entries: cache.purge_namespace(namespace),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    use crate::vector_index::{VectorIndexConfig, VectorPoint};
+
+    fn ns(name: &str) -> Namespace {
+        Namespace::new(name).unwrap()
+    }
+
+    #[test]
+    fn qualified_keys_belong_to_exactly_one_namespace() {
+        assert!(Namespace::new("save_2").is_err());
+        assert!(Namespace::new("").is_err());
+        let save = ns("save");
+        let qualified = save.qualify("memories");
+        assert_eq!(qualified, "save__memories");
+        assert_eq!(namespace_of(&qualified), Some("save"));
+        assert_eq!(namespace_of("memories"), None);
+        // Anything else containing the separator belongs to no namespace and is not a valid name
+        for malformed in ["boss fight__arena", "__arena", "save__", "save__a__b"] {
+            assert_eq!(namespace_of(malformed), None, "{}", malformed);
+            assert!(!is_valid_key(malformed), "{}", malformed);
+        }
+        assert!(is_valid_key("memories") && is_valid_key(&qualified) && is_valid_key("save_2"));
+        assert_eq!(save.local(&qualified), Some("memories"));
+        // A namespace that merely starts with the same letters owns nothing of ours
+        assert!(!ns("sav").owns(&qualified));
+        assert!(!save.owns(&ns("save-2").qualify("memories")));
+    }
+
+    #[test]
+    fn quotas_and_purges_stay_inside_their_namespace() {
+        let (one, two) = (ns("save-1"), ns("save-2"));
+        let mut index = VectorIndex::new(VectorIndexConfig {
+            url: String::new(),
+            api_key: String::new(),
+            default_ttl_secs: None,
+            collection_ttl_secs: Default::default(),
+        });
+        let mut db = AgentDb::new();
+        let mut cache = Cache::new(16);
+        db.set_quota(&one, NamespaceQuota { max_records: Some(1), ..Default::default() });
+
+        for namespace in [&one, &two] {
+            let collection = namespace.qualify("memories");
+            index.create_collection(&collection, 2, "test").unwrap();
+            index.upsert(&collection, VectorPoint::new("a", vec![1.0, 0.0])).unwrap();
+            db.put(namespace, "npcs", "mira", json!({ "mood": "calm" })).unwrap();
+            cache.insert(namespace, "q", 1);
+        }
+        assert!(db.put(&one, "npcs", "thorne", json!({})).is_err());
+        assert!(db.put(&two, "npcs", "thorne", json!({})).is_ok());
+
+        let report = purge_namespace(&one, &mut index, &mut db, &mut cache).unwrap();
+        assert_eq!((report.collections, report.points, report.records, report.cache_entries), (1, 1, 1, 1));
+        assert_eq!(index.collection_names(), vec!["save-2__memories"]);
+        assert!(matches!(index.create_collection("save-2__memories__old", 2, "test"), Err(VectorIndexError::InvalidName(_))));
+        assert_eq!(db.record_count(&two), 2);
+        assert_eq!(cache.get(&two, "q"), Some(1));
+        assert_eq!(cache.get(&one, "q"), None);
+    }
+}
@@ -0,0 +1,326 @@
+// PARIS checkpoints
+//
+// Named snapshots of learned parameters with the score they earned, so a bad update can be undone.
+// The usual loop: checkpoint the current (known good) parameters with their metrics, apply an
+// update, then report the metrics observed afterwards. If the score dropped by more than the
+// allowed regression the registry restores the active checkpoint automatically.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::paris::layers::PolicyLayer;
+use crate::paris::optimization::Objective;
+use crate::vector_index::unix_now;
+
+// Anything holding learned parameters that can be snapshotted and restored
+pub trait Checkpointable {
+    fn export_parameters(&self) -> HashMap<String, f64>;
+    fn import_parameters(&mut self, parameters: &HashMap<String, f64>);
+}
+
+impl Checkpointable for HashMap<String, f64> {
+    fn export_parameters(&self) -> HashMap<String, f64> {
+        self.clone()
+    }
+
+    fn import_parameters(&mut self, parameters: &HashMap<String, f64>) {
+        *self = parameters.clone();
+    }
+}
+
+impl Checkpointable for PolicyLayer {
+    fn export_parameters(&self) -> HashMap<String, f64> {
+        self.values.iter().map(|(name, value)| (name.clone(), *value as f64)).collect()
+    }
+
+    fn import_parameters(&mut self, parameters: &HashMap<String, f64>) {
+        self.values = parameters.iter().map(|(name, value)| (name.clone(), *value as f32)).collect();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CheckpointId(pub u64);
+
+impl fmt::Display for CheckpointId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "checkpoint-{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub id: CheckpointId,
+    pub name: String,
+    // Unix seconds
+    pub created_at: u64,
+    pub parameters: HashMap<String, f64>,
+    // Feedback metrics observed with these parameters
+    pub metrics: HashMap<String, f64>,
+    pub score: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CheckpointError {
+    NotFound(CheckpointId),
+    NoActiveCheckpoint,
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::NotFound(id) => write!(f, "{} not found", id),
+            CheckpointError::NoActiveCheckpoint => write!(f, "no active checkpoint to compare against"),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+// Roll back when a post-update score is more than `max_regression` below the active checkpoint's
+#[derive(Debug, Clone)]
+pub struct AutoRollback {
+    pub objective: Objective,
+    pub max_regression: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RegressionCheck {
+    Accepted { score: f64 },
+    RolledBack { to: CheckpointId, score: f64, baseline: f64 },
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct CheckpointRegistry {
+    checkpoints: Vec<Checkpoint>,
+    active: Option<CheckpointId>,
+    next_id: u64,
+    // Oldest checkpoints beyond this are dropped (the active one is always kept)
+    max_checkpoints: usize,
+    #[serde(skip)]
+    auto: Option<AutoRollback>,
+}
+
+impl CheckpointRegistry {
+    pub fn new(max_checkpoints: usize) -> Self {
+        CheckpointRegistry {
+            checkpoints: Vec::new(),
+            active: None,
+            next_id: 1,
+            max_checkpoints: max_checkpoints.max(1),
+            auto: None,
+        }
+    }
+
+    pub fn with_auto_rollback(mut self, objective: Objective, max_regression: f64) -> Self {
+        self.auto = Some(AutoRollback { objective, max_regression });
+        self
+    }
+
+    // Snapshot the current parameters and make the checkpoint active. The score comes from the
+    // auto-rollback objective when one is set.
+    pub fn save(&mut self, name: &str, source: &dyn Checkpointable, metrics: HashMap<String, f64>) -> CheckpointId {
+        let id = CheckpointId(self.next_id);
+        self.next_id += 1;
+        let score = self.auto.as_ref().map(|auto| auto.objective.score(&metrics));
+        self.checkpoints.push(Checkpoint {
+            id,
+            name: name.to_string(),
+            created_at: unix_now(),
+            parameters: source.export_parameters(),
+            metrics,
+            score,
+        });
+        self.active = Some(id);
+        self.prune();
+        id
+    }
+
+    pub fn set_score(&mut self, id: CheckpointId, score: f64) -> Result<(), CheckpointError> {
+        self.get_mut(id)?.score = Some(score);
+        Ok(())
+    }
+
+    pub fn get(&self, id: CheckpointId) -> Result<&Checkpoint, CheckpointError> {
+        self.checkpoints.iter().find(|c| c.id == id).ok_or(CheckpointError::NotFound(id))
+    }
+
+    fn get_mut(&mut self, id: CheckpointId) -> Result<&mut Checkpoint, CheckpointError> {
+        self.checkpoints.iter_mut().find(|c| c.id == id).ok_or(CheckpointError::NotFound(id))
+    }
+
+    pub fn find(&self, name: &str) -> Option<&Checkpoint> {
+        self.checkpoints.iter().rev().find(|c| c.name == name)
+    }
+
+    pub fn active(&self) -> Option<&Checkpoint> {
+        self.active.and_then(|id| self.get(id).ok())
+    }
+
+    // Oldest first
+    pub fn checkpoints(&self) -> &[Checkpoint] {
+        &self.checkpoints
+    }
+
+    pub fn best(&self) -> Option<&Checkpoint> {
+        self.checkpoints
+            .iter()
+            .filter(|c| c.score.is_some())
+            .max_by(|a, b| a.score.unwrap_or(f64::MIN).total_cmp(&b.score.unwrap_or(f64::MIN)))
+    }
+
+    // Restore a checkpoint's parameters into `target` and make it active
+    pub fn rollback(&mut self, id: CheckpointId, target: &mut dyn Checkpointable) -> Result<&Checkpoint, CheckpointError> {
+        let checkpoint = self.get(id)?;
+        target.import_parameters(&checkpoint.parameters);
+        self.active = Some(id);
+        self.get(id)
+    }
+
+    // Report metrics observed after an update; rolls `target` back to the active checkpoint if the
+    // score regressed too far. Without auto-rollback configured every update is accepted.
+    pub fn check_update(
+        &mut self,
+        metrics: &HashMap<String, f64>,
+        target: &mut dyn Checkpointable,
+    ) -> Result<RegressionCheck, CheckpointError> {
+        let Some(auto) = &self.auto else {
+            return Ok(RegressionCheck::Accepted { score: 0.0 });
+        };
+        let score = auto.objective.score(metrics);
+        let active = self.active().ok_or(CheckpointError::NoActiveCheckpoint)?;
+        let Some(baseline) = active.score else {
+            return Ok(RegressionCheck::Accepted { score });
+        };
+        if score >= baseline - auto.max_regression {
+            return Ok(RegressionCheck::Accepted { score });
+        }
+        let to = active.id;
+        self.rollback(to, target)?;
+        Ok(RegressionCheck::RolledBack { to, score, baseline })
+    }
+
+    fn prune(&mut self) {
+        while self.checkpoints.len() > self.max_checkpoints {
+            let Some(index) = self.checkpoints.iter().position(|c| Some(c.id) != self.active) else {
+                break;
+            };
+            self.checkpoints.remove(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(pairs: &[(&str, f64)]) -> HashMap<String, f64> {
+        pairs.iter().map(|(k, v)| (k.to_string(), *v)).collect()
+    }
+
+    #[test]
+    fn rollback_restores_saved_parameters() {
+        let mut registry = CheckpointRegistry::new(8);
+        let mut live = params(&[("spawn_rate", 1.0)]);
+        let first = registry.save("baseline", &live, HashMap::new());
+        live.insert("spawn_rate".to_string(), 3.0);
+        let second = registry.save("baseline", &live, HashMap::new());
+        assert_eq!(registry.active().unwrap().id, second);
+        // Names can repeat; find returns the newest
+        assert_eq!(registry.find("baseline").unwrap().id, second);
+
+        live.insert("spawn_rate".to_string(), 9.0);
+        assert_eq!(registry.rollback(first, &mut live).unwrap().id, first);
+        assert_eq!(live["spawn_rate"], 1.0);
+        assert_eq!(registry.active().unwrap().id, first);
+        assert_eq!(registry.rollback(CheckpointId(99), &mut live).unwrap_err(), CheckpointError::NotFound(CheckpointId(99)));
+    }
+
+    #[test]
+    fn regressions_beyond_the_margin_roll_back() {
+        let objective = Objective::new().weight("retention", 1.0);
+        let mut registry = CheckpointRegistry::new(8).with_auto_rollback(objective, 0.1);
+        let mut live = params(&[("difficulty", 0.5)]);
+        assert_eq!(
+            registry.check_update(&params(&[("retention", 0.9)]), &mut live).unwrap_err(),
+            CheckpointError::NoActiveCheckpoint
+        );
+
+        let good = registry.save("good", &live, params(&[("retention", 0.8)]));
+        assert_eq!(registry.get(good).unwrap().score, Some(0.8));
+        live.insert("difficulty".to_string(), 0.9);
+        assert_eq!(
+            registry.check_update(&params(&[("retention", 0.75)]), &mut live).unwrap(),
+            RegressionCheck::Accepted { score: 0.75 }
+        );
+        assert_eq!(live["difficulty"], 0.9);
+
+        match registry.check_update(&params(&[("retention", 0.5)]), &mut live).unwrap() {
+            RegressionCheck::RolledBack { to, score, baseline } => assert_eq!((to, score, baseline), (good, 0.5, 0.8)),
+            other => panic!("expected a rollback, got {:?}", other),
+        }
+        assert_eq!(live["difficulty"], 0.5);
+    }
+
+    #[test]
+    fn updates_without_a_baseline_score_are_accepted() {
+        let mut live = params(&[("difficulty", 0.5)]);
+        let mut manual = CheckpointRegistry::new(8);
+        manual.save("manual", &live, HashMap::new());
+        assert_eq!(manual.check_update(&HashMap::new(), &mut live).unwrap(), RegressionCheck::Accepted { score: 0.0 });
+
+        let objective = Objective::new().weight("retention", 1.0);
+        let mut unscored = CheckpointRegistry::new(8).with_auto_rollback(objective, 0.0);
+        let id = unscored.save("unscored", &live, HashMap::new());
+        unscored.get_mut(id).unwrap().score = None;
+        assert_eq!(
+            unscored.check_update(&params(&[("retention", -5.0)]), &mut live).unwrap(),
+            RegressionCheck::Accepted { score: -5.0 }
+        );
+    }
+
+    #[test]
+    fn oldest_checkpoints_are_dropped_past_the_cap() {
+        let mut registry = CheckpointRegistry::new(2);
+        let live = params(&[("x", 0.0)]);
+        let first = registry.save("a", &live, HashMap::new());
+        let second = registry.save("b", &live, HashMap::new());
+        let third = registry.save("c", &live, HashMap::new());
+        let ids: Vec<CheckpointId> = registry.checkpoints().iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec![second, third]);
+        assert_eq!(registry.get(first).unwrap_err(), CheckpointError::NotFound(first));
+        assert!(registry.find("a").is_none());
+    }
+
+    #[test]
+    fn best_ignores_unscored_checkpoints() {
+        let mut registry = CheckpointRegistry::new(8);
+        let live = params(&[("x", 0.0)]);
+        let low = registry.save("low", &live, HashMap::new());
+        let high = registry.save("high", &live, HashMap::new());
+        registry.save("unscored", &live, HashMap::new());
+        assert!(registry.best().is_none());
+        registry.set_score(low, 0.2).unwrap();
+        registry.set_score(high, 0.7).unwrap();
+        assert_eq!(registry.best().unwrap().id, high);
+        assert!(registry.set_score(CheckpointId(42), 1.0).is_err());
+    }
+
+    #[test]
+    fn policy_layers_and_registries_round_trip() {
+        let mut policy = PolicyLayer::default();
+        policy.values.insert("aggression".to_string(), 0.25);
+        let mut registry = CheckpointRegistry::new(4);
+        let id = registry.save("policy", &policy, HashMap::new());
+        policy.values.clear();
+
+        let json = serde_json::to_string(&registry).unwrap();
+        let mut restored: CheckpointRegistry = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.active().unwrap().id, id);
+        restored.rollback(id, &mut policy).unwrap();
+        assert_eq!(policy.values["aggression"], 0.25);
+        // Ids keep counting after a reload
+        assert_eq!(restored.save("next", &policy, HashMap::new()), CheckpointId(2));
+    }
+}
@@ -0,0 +1,343 @@
+// PARIS feedback
+//
+// Adapters pull raw feedback from the game (telemetry events, metric snapshots, explicit player
+// ratings). Samples are kept in per-metric sliding windows and summarized into typed
+// FeedbackSignals that learning and optimization consume.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FeedbackSource {
+    Telemetry,
+    Metrics,
+    PlayerRating,
+}
+
+#[derive(Debug, Clone)]
+pub struct FeedbackSample {
+    pub metric: String,
+    pub value: f64,
+    pub source: FeedbackSource,
+    pub at: Instant,
+}
+
+impl FeedbackSample {
+    pub fn new(metric: &str, value: f64, source: FeedbackSource) -> Self {
+        FeedbackSample {
+            metric: metric.to_string(),
+            value,
+            source,
+            at: Instant::now(),
+        }
+    }
+}
+
+// Produces feedback samples when polled
+pub trait FeedbackAdapter {
+    fn source(&self) -> FeedbackSource;
+    fn poll(&mut self) -> Vec<FeedbackSample>;
+}
+
+// Game telemetry events: each numeric field becomes "<event>.<field>", plus an "<event>.count"
+#[derive(Default)]
+pub struct TelemetryAdapter {
+    queue: Vec<(String, HashMap<String, f64>)>,
+}
+
+impl TelemetryAdapter {
+    pub fn event(&mut self, name: &str, fields: HashMap<String, f64>) {
+        self.queue.push((name.to_string(), fields));
+    }
+}
+
+impl FeedbackAdapter for TelemetryAdapter {
+    fn source(&self) -> FeedbackSource {
+        FeedbackSource::Telemetry
+    }
+
+    fn poll(&mut self) -> Vec<FeedbackSample> {
+        let mut samples = Vec::new();
+        for (name, fields) in self.queue.drain(..) {
+            samples.push(FeedbackSample::new(&format!("{}.count", name), 1.0, FeedbackSource::Telemetry));
+            for (field, value) in fields {
+                samples.push(FeedbackSample::new(&format!("{}.{}", name, field), value, FeedbackSource::Telemetry));
+            }
+        }
+        samples
+    }
+}
+
+// Periodic metric snapshots (frame time, session length, deaths per hour)
+#[derive(Default)]
+pub struct MetricSnapshotAdapter {
+    snapshots: Vec<HashMap<String, f64>>,
+}
+
+impl MetricSnapshotAdapter {
+    pub fn snapshot(&mut self, metrics: HashMap<String, f64>) {
+        self.snapshots.push(metrics);
+    }
+}
+
+impl FeedbackAdapter for MetricSnapshotAdapter {
+    fn source(&self) -> FeedbackSource {
+        FeedbackSource::Metrics
+    }
+
+    fn poll(&mut self) -> Vec<FeedbackSample> {
+        self.snapshots
+            .drain(..)
+            .flat_map(|snapshot| snapshot.into_iter())
+            .map(|(metric, value)| FeedbackSample::new(&metric, value, FeedbackSource::Metrics))
+            .collect()
+    }
+}
+
+// Explicit player ratings on a 1..=max scale, normalized to 0..1 as "rating.<category>"
+pub struct PlayerRatingAdapter {
+    pub max_rating: u8,
+    ratings: Vec<(String, String, u8)>,
+}
+
+impl PlayerRatingAdapter {
+    pub fn new(max_rating: u8) -> Self {
+        PlayerRatingAdapter {
+            max_rating: max_rating.max(2),
+            ratings: Vec::new(),
+        }
+    }
+
+    pub fn rate(&mut self, player_id: &str, category: &str, rating: u8) {
+        self.ratings
+            .push((player_id.to_string(), category.to_string(), rating.clamp(1, self.max_rating)));
+    }
+}
+
+impl FeedbackAdapter for PlayerRatingAdapter {
+    fn source(&self) -> FeedbackSource {
+        FeedbackSource::PlayerRating
+    }
+
+    fn poll(&mut self) -> Vec<FeedbackSample> {
+        let scale = (self.max_rating - 1) as f64;
+        self.ratings
+            .drain(..)
+            .map(|(_, category, rating)| {
+                FeedbackSample::new(
+                    &format!("rating.{}", category),
+                    (rating - 1) as f64 / scale,
+                    FeedbackSource::PlayerRating,
+                )
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct WindowConfig {
+    // Samples older than this are dropped from the window
+    pub window: Duration,
+    // Hard cap on samples kept per metric
+    pub max_samples: usize,
+    // Smoothing factor for the exponentially weighted moving average (0..1)
+    pub ewma_alpha: f64,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        WindowConfig {
+            window: Duration::from_secs(300),
+            max_samples: 4096,
+            ewma_alpha: 0.1,
+        }
+    }
+}
+
+// Summary of one metric over the current window
+#[derive(Debug, Clone)]
+pub struct FeedbackSignal {
+    pub metric: String,
+    pub source: FeedbackSource,
+    pub count: usize,
+    pub mean: f64,
+    pub min: f64,
+    pub max: f64,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    // Smoothed over all samples ever seen, not only the window
+    pub ewma: f64,
+}
+
+impl FeedbackSignal {
+    // Recent direction: positive when the smoothed value is above the window mean
+    pub fn trend(&self) -> f64 {
+        self.ewma - self.mean
+    }
+}
+
+struct MetricWindow {
+    source: FeedbackSource,
+    samples: VecDeque<(Instant, f64)>,
+    ewma: Option<f64>,
+}
+
+pub struct FeedbackManager {
+    config: WindowConfig,
+    adapters: Vec<Box<dyn FeedbackAdapter>>,
+    windows: HashMap<String, MetricWindow>,
+}
+
+impl FeedbackManager {
+    pub fn new(config: WindowConfig) -> Self {
+        FeedbackManager {
+            config,
+            adapters: Vec::new(),
+            windows: HashMap::new(),
+        }
+    }
+
+    pub fn add_adapter(&mut self, adapter: Box<dyn FeedbackAdapter>) {
+        self.adapters.push(adapter);
+    }
+
+    // Record a sample directly, bypassing adapters
+    pub fn record(&mut self, sample: FeedbackSample) {
+        let alpha = self.config.ewma_alpha;
+        let window = self.windows.entry(sample.metric).or_insert_with(|| MetricWindow {
+            source: sample.source,
+            samples: VecDeque::new(),
+            ewma: None,
+        });
+        window.ewma = Some(match window.ewma {
+            Some(prev) => prev + alpha * (sample.value - prev),
+            None => sample.value,
+        });
+        window.samples.push_back((sample.at, sample.value));
+        while window.samples.len() > self.config.max_samples {
+            window.samples.pop_front();
+        }
+    }
+
+    // Poll every adapter, ingest the samples and return the number ingested
+    pub fn collect(&mut self) -> usize {
+        let samples: Vec<FeedbackSample> = self.adapters.iter_mut().flat_map(|a| a.poll()).collect();
+        let count = samples.len();
+        for sample in samples {
+            self.record(sample);
+        }
+        self.evict(Instant::now());
+        count
+    }
+
+    fn evict(&mut self, now: Instant) {
+        let window = self.config.window;
+        for metric in self.windows.values_mut() {
+            while metric
+                .samples
+                .front()
+                .is_some_and(|(at, _)| now.duration_since(*at) > window)
+            {
+                metric.samples.pop_front();
+            }
+        }
+    }
+
+    pub fn signal(&self, metric: &str) -> Option<FeedbackSignal> {
+        let window = self.windows.get(metric)?;
+        if window.samples.is_empty() {
+            return None;
+        }
+        let mut values: Vec<f64> = window.samples.iter().map(|(_, v)| *v).collect();
+        values.sort_by(f64::total_cmp);
+        let count = values.len();
+        Some(FeedbackSignal {
+            metric: metric.to_string(),
+            source: window.source,
+            count,
+            mean: values.iter().sum::<f64>() / count as f64,
+            min: values[0],
+            max: values[count - 1],
+            p50: percentile(&values, 0.50),
+            p90: percentile(&values, 0.90),
+            p99: percentile(&values, 0.99),
+            ewma: window.ewma.unwrap_or(values[0]),
+        })
+    }
+
+    // Signals for every metric with samples in the window
+    pub fn signals(&self) -> Vec<FeedbackSignal> {
+        let mut signals: Vec<FeedbackSignal> = self.windows.keys().filter_map(|m| self.signal(m)).collect();
+        signals.sort_by(|a, b| a.metric.cmp(&b.metric));
+        signals
+    }
+
+    pub fn signals_from(&self, source: FeedbackSource) -> Vec<FeedbackSignal> {
+        self.signals().into_iter().filter(|s| s.source == source).collect()
+    }
+
+    // Window means keyed by metric, the shape OptimizationManager::report expects
+    pub fn metric_means(&self) -> HashMap<String, f64> {
+        self.signals().into_iter().map(|s| (s.metric, s.mean)).collect()
+    }
+}
+
+// Nearest-rank percentile of sorted values
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adapters_feed_named_and_normalized_signals() {
+        let mut telemetry = TelemetryAdapter::default();
+        telemetry.event("death", HashMap::from([("time".to_string(), 30.0)]));
+        telemetry.event("death", HashMap::from([("time".to_string(), 90.0)]));
+        let mut ratings = PlayerRatingAdapter::new(5);
+        ratings.rate("p1", "fun", 5);
+        ratings.rate("p2", "fun", 9);
+        ratings.rate("p3", "fun", 1);
+
+        let mut manager = FeedbackManager::new(WindowConfig::default());
+        manager.add_adapter(Box::new(telemetry));
+        manager.add_adapter(Box::new(ratings));
+        assert_eq!(manager.collect(), 7);
+
+        let deaths = manager.signal("death.time").unwrap();
+        assert_eq!((deaths.count, deaths.mean, deaths.min, deaths.max), (2, 60.0, 30.0, 90.0));
+        assert_eq!(manager.signal("death.count").unwrap().count, 2);
+        // Out-of-range ratings are clamped to the scale
+        let fun = manager.signal("rating.fun").unwrap();
+        assert_eq!((fun.min, fun.max), (0.0, 1.0));
+        assert_eq!(manager.signals_from(FeedbackSource::PlayerRating).len(), 1);
+        assert_eq!(manager.collect(), 0);
+    }
+
+    #[test]
+    fn windows_drop_old_and_excess_samples() {
+        let config = WindowConfig { window: Duration::from_secs(60), max_samples: 3, ewma_alpha: 0.5 };
+        let mut manager = FeedbackManager::new(config);
+        let mut stale = FeedbackSample::new("fps", 10.0, FeedbackSource::Metrics);
+        stale.at = Instant::now() - Duration::from_secs(120);
+        manager.record(stale);
+        for value in [50.0, 60.0, 70.0] {
+            manager.record(FeedbackSample::new("fps", value, FeedbackSource::Metrics));
+        }
+        // The cap already pushed the stale sample out; the EWMA still remembers it
+        let fps = manager.signal("fps").unwrap();
+        assert_eq!((fps.count, fps.min, fps.p50), (3, 50.0, 60.0));
+        assert_eq!(fps.ewma, 57.5);
+
+        let mut old = FeedbackSample::new("latency", 5.0, FeedbackSource::Metrics);
+        old.at = Instant::now() - Duration::from_secs(120);
+        manager.record(old);
+        manager.collect();
+        assert!(manager.signal("latency").is_none());
+        assert_eq!(manager.metric_means().get("fps"), Some(&60.0));
+    }
+}
@@ -0,0 +1,75 @@
+// PARIS framework
+//
+// Ties the feedback windows to the layer stack. One learning cycle collects feedback from the
+// adapters, feeds the window mean of every metric into the perception layer as an observation and
+// runs the stack once; the commands that come out of actuation are returned for the game to apply.
+// When cycles run is up to the caller or a CycleDriver (see scheduler). Command values are
+// clamped by the guardrails before they are returned, and each clamp is fed back down the stack.
+
+use std::time::{Duration, Instant};
+
+use crate::paris::feedback::FeedbackManager;
+use crate::paris::guardrails::Guardrails;
+use crate::paris::layers::{LayerKind, LayerManager, LayerMessage};
+
+#[derive(Debug, Clone, Default)]
+pub struct CycleReport {
+    pub cycle: u64,
+    // Feedback samples collected by this cycle
+    pub samples: usize,
+    pub observations: usize,
+    pub commands: Vec<LayerMessage>,
+    // Commands whose value the guardrails changed
+    pub clamped: usize,
+    pub elapsed: Duration,
+}
+
+pub struct ParisFramework {
+    pub feedback: FeedbackManager,
+    pub layers: LayerManager,
+    pub guardrails: Guardrails,
+    cycles: u64,
+}
+
+impl ParisFramework {
+    pub fn new(feedback: FeedbackManager, layers: LayerManager) -> Self {
+        ParisFramework { feedback, layers, guardrails: Guardrails::new(), cycles: 0 }
+    }
+
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    // Poll the feedback adapters without running a cycle; returns the samples ingested
+    pub fn ingest(&mut self) -> usize {
+        self.feedback.collect()
+    }
+
+    pub fn process_cycle(&mut self) -> CycleReport {
+        let started = Instant::now();
+        let samples = self.ingest();
+        let signals = self.feedback.signals();
+        for signal in &signals {
+            self.layers.send(
+                LayerKind::Perception,
+                LayerMessage::Observation { source: "feedback".to_string(), key: signal.metric.clone(), value: signal.mean as f32 },
+            );
+        }
+        self.layers.update();
+        self.cycles += 1;
+        let mut commands = self.layers.drain_outputs();
+        let feedback = self.guardrails.clamp_commands(&mut commands);
+        let clamped = feedback.len();
+        for message in feedback {
+            self.layers.send(LayerKind::Actuation, message);
+        }
+        CycleReport {
+            cycle: self.cycles,
+            samples,
+            observations: signals.len(),
+            commands,
+            clamped,
+            elapsed: started.elapsed(),
+        }
+    }
+}
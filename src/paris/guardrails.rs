@@ -0,0 +1,310 @@
+// PARIS guardrails
+//
+// Adaptive systems can drift into absurd states: an optimizer that learned enemies should deal
+// 0.0001 damage, or a policy that pushed a spawn rate to 50x. Guardrails declare a safe range and
+// a baseline for each adapted parameter. Every value leaving the adaptive stack is clamped into
+// its range (non-finite values fall back to the baseline), and each clamp is recorded as a
+// violation that is logged and published on the event bus, so designers see which loops push
+// against their limits.
+//
+// Ranges come from the `[guardrails.<parameter>]` tables in aiTOML or are added in code:
+//
+//   [guardrails.enemy_damage]
+//   min = 0.25
+//   max = 4.0
+//   baseline = 1.0
+//
+// When players report broken balance, `emergency_reset` writes every baseline back into the
+// adapted parameters (anything Checkpointable, such as the policy layer) in one call.
+// `report_broken_balance` counts reports from distinct players within a window and says when
+// enough have come in to warrant that reset.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::events::EventBus;
+use crate::paris::checkpoints::Checkpointable;
+use crate::paris::layers::LayerMessage;
+
+pub const VIOLATION_TOPIC: &str = "paris.guardrail";
+pub const RESET_TOPIC: &str = "paris.emergency_reset";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SafeRange {
+    pub min: f64,
+    pub max: f64,
+    // Value restored by an emergency reset
+    pub baseline: f64,
+}
+
+impl SafeRange {
+    pub fn new(min: f64, max: f64, baseline: f64) -> Self {
+        SafeRange { min, max, baseline: baseline.clamp(min, max) }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GuardrailViolation {
+    pub parameter: String,
+    // What the adaptive system asked for; NaN and infinities are reported as null
+    pub requested: f64,
+    pub applied: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ResetReport {
+    pub reason: String,
+    // (parameter, value before, baseline)
+    pub restored: Vec<(String, f64, f64)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BalanceReportPolicy {
+    // Distinct players needed within `window_secs` before a reset is recommended
+    pub threshold: usize,
+    pub window_secs: u64,
+}
+
+impl Default for BalanceReportPolicy {
+    fn default() -> Self {
+        BalanceReportPolicy { threshold: 5, window_secs: 600 }
+    }
+}
+
+#[derive(Default)]
+pub struct Guardrails {
+    ranges: BTreeMap<String, SafeRange>,
+    pending: Vec<GuardrailViolation>,
+    resets: Vec<ResetReport>,
+    // Total clamps per parameter since startup
+    counts: BTreeMap<String, u64>,
+    report_policy: BalanceReportPolicy,
+    // (unix seconds, player)
+    reports: VecDeque<(u64, String)>,
+}
+
+impl Guardrails {
+    pub fn new() -> Self {
+        Guardrails::default()
+    }
+
+    pub fn from_config(ranges: &BTreeMap<String, SafeRange>) -> Self {
+        let mut guardrails = Guardrails::new();
+        for (parameter, range) in ranges {
+            guardrails.set_range(parameter, *range);
+        }
+        guardrails
+    }
+
+    pub fn range(mut self, parameter: &str, min: f64, max: f64, baseline: f64) -> Self {
+        self.set_range(parameter, SafeRange::new(min, max, baseline));
+        self
+    }
+
+    pub fn set_range(&mut self, parameter: &str, range: SafeRange) {
+        self.ranges.insert(parameter.to_string(), SafeRange::new(range.min, range.max, range.baseline));
+    }
+
+    pub fn report_policy(mut self, policy: BalanceReportPolicy) -> Self {
+        self.report_policy = policy;
+        self
+    }
+
+    pub fn get(&self, parameter: &str) -> Option<&SafeRange> {
+        self.ranges.get(parameter)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    // Value to actually apply; parameters without a declared range pass through unchanged
+    pub fn clamp(&mut self, parameter: &str, value: f64) -> f64 {
+        let Some(range) = self.ranges.get(parameter).copied() else { return value };
+        let applied = if value.is_finite() { value.clamp(range.min, range.max) } else { range.baseline };
+        if applied != value {
+            tracing::warn!(parameter, requested = value, applied, "adaptive parameter outside its safe range");
+            *self.counts.entry(parameter.to_string()).or_default() += 1;
+            self.pending.push(GuardrailViolation {
+                parameter: parameter.to_string(),
+                requested: value,
+                applied,
+                min: range.min,
+                max: range.max,
+            });
+        }
+        applied
+    }
+
+    pub fn clamp_all(&mut self, values: &mut HashMap<String, f64>) -> usize {
+        let before = self.pending.len();
+        for (parameter, value) in values.iter_mut() {
+            *value = self.clamp(parameter, *value);
+        }
+        self.pending.len() - before
+    }
+
+    // Clamp the values of PARIS commands in place; returns the feedback messages for the layer
+    // stack, one per clamped command, so the learning side knows it was overruled
+    pub fn clamp_commands(&mut self, commands: &mut [LayerMessage]) -> Vec<LayerMessage> {
+        let mut feedback = Vec::new();
+        for command in commands.iter_mut() {
+            if let LayerMessage::Command { parameter, value, .. } = command {
+                let applied = self.clamp(parameter, *value as f64) as f32;
+                if applied != *value {
+                    feedback.push(LayerMessage::Feedback { key: format!("guardrail.{}", parameter), value: applied });
+                    *value = applied;
+                }
+            }
+        }
+        feedback
+    }
+
+    // Restore every guarded parameter the target holds to its baseline
+    pub fn emergency_reset(&mut self, target: &mut dyn Checkpointable, reason: &str) -> ResetReport {
+        let mut parameters = target.export_parameters();
+        let mut restored = Vec::new();
+        for (parameter, range) in &self.ranges {
+            if let Some(value) = parameters.get_mut(parameter) {
+                restored.push((parameter.clone(), *value, range.baseline));
+                *value = range.baseline;
+            }
+        }
+        target.import_parameters(&parameters);
+        tracing::warn!(reason, parameters = restored.len(), "emergency reset of adaptive parameters");
+        self.reports.clear();
+        let report = ResetReport { reason: reason.to_string(), restored };
+        self.resets.push(report.clone());
+        report
+    }
+
+    // Baselines of all guarded parameters, e.g. to seed a fresh policy
+    pub fn baselines(&self) -> HashMap<String, f64> {
+        self.ranges.iter().map(|(parameter, range)| (parameter.clone(), range.baseline)).collect()
+    }
+
+    // Record a player's "balance is broken" report; true once enough distinct players reported
+    // within the window that an emergency reset is warranted
+    pub fn report_broken_balance(&mut self, player: &str, now: u64) -> bool {
+        let window = self.report_policy.window_secs;
+        while self.reports.front().is_some_and(|(at, _)| now.saturating_sub(*at) > window) {
+            self.reports.pop_front();
+        }
+        if !self.reports.iter().any(|(_, reporter)| reporter == player) {
+            self.reports.push_back((now, player.to_string()));
+        }
+        self.reports.len() >= self.report_policy.threshold.max(1)
+    }
+
+    pub fn violation_counts(&self) -> &BTreeMap<String, u64> {
+        &self.counts
+    }
+
+    pub fn pending(&self) -> &[GuardrailViolation] {
+        &self.pending
+    }
+
+    // Publish violations on VIOLATION_TOPIC and resets on RESET_TOPIC; returns the number published
+    pub fn publish(&mut self, events: &mut EventBus) -> usize {
+        let count = self.pending.len() + self.resets.len();
+        for violation in self.pending.drain(..) {
+            events.emit(VIOLATION_TOPIC, &violation.parameter, json!(violation));
+        }
+        for reset in self.resets.drain(..) {
+            events.emit(RESET_TOPIC, "guardrails", json!(reset));
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn guardrails() -> Guardrails {
+        Guardrails::new().range("enemy_damage", 0.25, 4.0, 1.0).range("spawn_rate", 0.5, 2.0, 1.0)
+    }
+
+    fn command(parameter: &str, value: f32) -> LayerMessage {
+        LayerMessage::Command { target: "spawner".to_string(), parameter: parameter.to_string(), value }
+    }
+
+    #[test]
+    fn values_are_clamped_into_their_range() {
+        let mut guardrails = guardrails();
+        assert_eq!(guardrails.clamp("enemy_damage", 0.0001), 0.25);
+        assert_eq!(guardrails.clamp("enemy_damage", 2.0), 2.0);
+        assert_eq!(guardrails.clamp("spawn_rate", 50.0), 2.0);
+        // Non-finite values fall back to the baseline; unguarded parameters pass through
+        assert_eq!(guardrails.clamp("spawn_rate", f64::NAN), 1.0);
+        assert_eq!(guardrails.clamp("loot_bonus", 99.0), 99.0);
+
+        assert_eq!(guardrails.pending().len(), 3);
+        assert_eq!(guardrails.pending()[0].requested, 0.0001);
+        assert_eq!(guardrails.violation_counts().get("spawn_rate"), Some(&2));
+        // A baseline outside the range is pulled into it
+        assert_eq!(SafeRange::new(0.0, 1.0, 3.0).baseline, 1.0);
+    }
+
+    #[test]
+    fn clamp_all_and_commands_report_what_was_overruled() {
+        let mut guardrails = guardrails();
+        let mut values: HashMap<String, f64> = [("enemy_damage".to_string(), 9.0), ("spawn_rate".to_string(), 1.5)].into();
+        assert_eq!(guardrails.clamp_all(&mut values), 1);
+        assert_eq!(values["enemy_damage"], 4.0);
+
+        let mut commands = vec![command("spawn_rate", 0.1), command("enemy_damage", 1.5)];
+        let feedback = guardrails.clamp_commands(&mut commands);
+        assert_eq!(commands[0], command("spawn_rate", 0.5));
+        assert_eq!(feedback, vec![LayerMessage::Feedback { key: "guardrail.spawn_rate".to_string(), value: 0.5 }]);
+    }
+
+    #[test]
+    fn emergency_reset_restores_baselines() {
+        let mut guardrails = guardrails();
+        let mut adapted: HashMap<String, f64> =
+            [("enemy_damage".to_string(), 3.5), ("loot_bonus".to_string(), 2.0)].into();
+        let report = guardrails.emergency_reset(&mut adapted, "players reported one-shot bosses");
+        assert_eq!(report.restored, vec![("enemy_damage".to_string(), 3.5, 1.0)]);
+        assert_eq!(adapted["enemy_damage"], 1.0);
+        assert_eq!(adapted["loot_bonus"], 2.0);
+        assert_eq!(guardrails.baselines().len(), 2);
+    }
+
+    #[test]
+    fn distinct_reports_within_the_window_trigger_a_reset() {
+        let policy = BalanceReportPolicy { threshold: 3, window_secs: 60 };
+        let mut guardrails = guardrails().report_policy(policy);
+        assert!(!guardrails.report_broken_balance("ana", 0));
+        // The same player twice counts once
+        assert!(!guardrails.report_broken_balance("ana", 10));
+        assert!(!guardrails.report_broken_balance("bo", 20));
+        // Ana's report has aged out
+        assert!(!guardrails.report_broken_balance("cy", 70));
+        assert!(guardrails.report_broken_balance("dee", 75));
+
+        guardrails.emergency_reset(&mut HashMap::new(), "reports");
+        assert!(!guardrails.report_broken_balance("eve", 80));
+    }
+
+    #[test]
+    fn violations_and_resets_are_published() {
+        let mut events = EventBus::new(16);
+        let subscription = events.subscribe("paris.");
+        let mut guardrails = Guardrails::from_config(&[("spawn_rate".to_string(), SafeRange::new(0.5, 2.0, 1.0))].into());
+        guardrails.clamp("spawn_rate", f64::INFINITY);
+        guardrails.emergency_reset(&mut HashMap::new(), "manual");
+
+        assert_eq!(guardrails.publish(&mut events), 2);
+        let published = events.drain(subscription);
+        assert_eq!((published[0].topic.as_str(), published[0].source.as_str()), (VIOLATION_TOPIC, "spawn_rate"));
+        assert!(published[0].payload["requested"].is_null());
+        assert_eq!(published[1].topic, RESET_TOPIC);
+        assert!(guardrails.pending().is_empty());
+        assert_eq!(guardrails.publish(&mut events), 0);
+    }
+}
@@ -0,0 +1,490 @@
+// PARIS layer stack
+//
+// Layers are ordered perception -> memory -> learning -> policy -> actuation. A layer only talks
+// to its neighbours: forward messages move towards actuation, feedback moves back towards
+// perception. Each update processes queued messages layer by layer within a per-layer budget;
+// anything over budget stays queued for the next update.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum LayerKind {
+    Perception,
+    Memory,
+    Learning,
+    Policy,
+    Actuation,
+}
+
+impl LayerKind {
+    pub const ALL: [LayerKind; 5] = [
+        LayerKind::Perception,
+        LayerKind::Memory,
+        LayerKind::Learning,
+        LayerKind::Policy,
+        LayerKind::Actuation,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+// Typed messages passed between layers
+#[derive(Debug, Clone, PartialEq)]
+pub enum LayerMessage {
+    // Raw measurement from the game (perception -> memory)
+    Observation { source: String, key: String, value: f32 },
+    // Aggregated view of recent observations (memory -> learning)
+    Summary { key: String, mean: f32, samples: usize },
+    // Learned estimate of how a parameter should move (learning -> policy)
+    Estimate { parameter: String, delta: f32, confidence: f32 },
+    // Chosen adjustment (policy -> actuation)
+    Decision { parameter: String, value: f32 },
+    // Instruction leaving the stack (actuation -> game)
+    Command { target: String, parameter: String, value: f32 },
+    // Feedback flowing back down the stack (e.g. "command rejected")
+    Feedback { key: String, value: f32 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    // Towards actuation
+    Forward,
+    // Towards perception
+    Backward,
+}
+
+// Messages a layer emits while processing
+#[derive(Debug, Default)]
+pub struct Outbox {
+    forward: Vec<LayerMessage>,
+    backward: Vec<LayerMessage>,
+}
+
+impl Outbox {
+    pub fn forward(&mut self, message: LayerMessage) {
+        self.forward.push(message);
+    }
+
+    pub fn backward(&mut self, message: LayerMessage) {
+        self.backward.push(message);
+    }
+}
+
+pub trait Layer {
+    fn kind(&self) -> LayerKind;
+
+    fn name(&self) -> &str;
+
+    // Handle one message arriving from a neighbouring layer (or injected from outside)
+    fn process(&mut self, message: LayerMessage, from: Direction, out: &mut Outbox);
+
+    // Called once per update after the inbox has been processed
+    fn tick(&mut self, _out: &mut Outbox) {}
+}
+
+// Per-layer processing limits for a single update
+#[derive(Debug, Clone, Copy)]
+pub struct LayerBudget {
+    pub max_messages: usize,
+    pub max_time: Duration,
+}
+
+impl Default for LayerBudget {
+    fn default() -> Self {
+        LayerBudget {
+            max_messages: 256,
+            max_time: Duration::from_millis(2),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LayerStats {
+    pub processed: u64,
+    pub sent_forward: u64,
+    pub sent_backward: u64,
+    // Updates in which the budget ran out before the inbox was empty
+    pub budget_exhausted: u64,
+    pub busy_time: Duration,
+}
+
+// Introspection snapshot of a layer
+#[derive(Debug, Clone)]
+pub struct LayerInfo {
+    pub kind: LayerKind,
+    pub name: String,
+    pub queued: usize,
+    pub budget: LayerBudget,
+    pub stats: LayerStats,
+    // Messages processed per second since the manager was created
+    pub throughput: f64,
+}
+
+struct LayerSlot {
+    layer: Box<dyn Layer>,
+    inbox: VecDeque<(LayerMessage, Direction)>,
+    budget: LayerBudget,
+    stats: LayerStats,
+}
+
+pub struct LayerManager {
+    slots: Vec<Option<LayerSlot>>,
+    // Commands leaving the actuation layer
+    outputs: Vec<LayerMessage>,
+    // Feedback leaving the perception layer
+    unhandled_feedback: Vec<LayerMessage>,
+    started: Instant,
+}
+
+impl Default for LayerManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LayerManager {
+    pub fn new() -> Self {
+        LayerManager {
+            slots: LayerKind::ALL.iter().map(|_| None).collect(),
+            outputs: Vec::new(),
+            unhandled_feedback: Vec::new(),
+            started: Instant::now(),
+        }
+    }
+
+    // Manager with the default implementation for every layer
+    pub fn with_default_layers() -> Self {
+        let mut manager = LayerManager::new();
+        manager.add_layer(Box::new(PerceptionLayer));
+        manager.add_layer(Box::new(MemoryLayer::default()));
+        manager.add_layer(Box::new(LearningLayer::default()));
+        manager.add_layer(Box::new(PolicyLayer::default()));
+        manager.add_layer(Box::new(ActuationLayer::default()));
+        manager
+    }
+
+    // Installs a layer in its slot, replacing any layer of the same kind
+    pub fn add_layer(&mut self, layer: Box<dyn Layer>) -> Option<Box<dyn Layer>> {
+        let index = layer.kind().index();
+        let previous = self.slots[index].take();
+        self.slots[index] = Some(LayerSlot {
+            layer,
+            inbox: previous.as_ref().map(|s| s.inbox.clone()).unwrap_or_default(),
+            budget: previous.as_ref().map(|s| s.budget).unwrap_or_default(),
+            stats: LayerStats::default(),
+        });
+        previous.map(|s| s.layer)
+    }
+
+    pub fn set_budget(&mut self, kind: LayerKind, budget: LayerBudget) {
+        if let Some(slot) = &mut self.slots[kind.index()] {
+            slot.budget = budget;
+        }
+    }
+
+    // Queue a message for a layer from outside the stack
+    pub fn send(&mut self, kind: LayerKind, message: LayerMessage) {
+        self.route(kind.index() as isize, message, Direction::Forward);
+    }
+
+    // Process every layer once, perception first
+    pub fn update(&mut self) {
+        for index in 0..self.slots.len() {
+            let mut out = Outbox::default();
+            if let Some(slot) = &mut self.slots[index] {
+                let started = Instant::now();
+                let mut handled = 0;
+                while let Some((message, from)) = slot.inbox.pop_front() {
+                    slot.layer.process(message, from, &mut out);
+                    handled += 1;
+                    if handled >= slot.budget.max_messages || started.elapsed() >= slot.budget.max_time {
+                        break;
+                    }
+                }
+                if !slot.inbox.is_empty() {
+                    slot.stats.budget_exhausted += 1;
+                }
+                slot.layer.tick(&mut out);
+                slot.stats.processed += handled as u64;
+                slot.stats.sent_forward += out.forward.len() as u64;
+                slot.stats.sent_backward += out.backward.len() as u64;
+                slot.stats.busy_time += started.elapsed();
+            }
+            for message in out.forward {
+                self.route(index as isize + 1, message, Direction::Forward);
+            }
+            for message in out.backward {
+                self.route(index as isize - 1, message, Direction::Backward);
+            }
+        }
+    }
+
+    // Deliver to the nearest installed layer in the direction of travel; messages that run off
+    // either end of the stack are collected as outputs/unhandled feedback
+    fn route(&mut self, mut index: isize, message: LayerMessage, direction: Direction) {
+        while index >= 0 && (index as usize) < self.slots.len() {
+            if let Some(slot) = &mut self.slots[index as usize] {
+                slot.inbox.push_back((message, direction));
+                return;
+            }
+            index += if direction == Direction::Forward { 1 } else { -1 };
+        }
+        match direction {
+            Direction::Forward => self.outputs.push(message),
+            Direction::Backward => self.unhandled_feedback.push(message),
+        }
+    }
+
+    pub fn drain_outputs(&mut self) -> Vec<LayerMessage> {
+        std::mem::take(&mut self.outputs)
+    }
+
+    pub fn drain_feedback(&mut self) -> Vec<LayerMessage> {
+        std::mem::take(&mut self.unhandled_feedback)
+    }
+
+    pub fn layers(&self) -> Vec<LayerInfo> {
+        let elapsed = self.started.elapsed().as_secs_f64().max(f64::EPSILON);
+        self.slots
+            .iter()
+            .flatten()
+            .map(|slot| LayerInfo {
+                kind: slot.layer.kind(),
+                name: slot.layer.name().to_string(),
+                queued: slot.inbox.len(),
+                budget: slot.budget,
+                stats: slot.stats.clone(),
+                throughput: slot.stats.processed as f64 / elapsed,
+            })
+            .collect()
+    }
+}
+
+// Forwards observations, dropping non-finite readings
+#[derive(Default)]
+pub struct PerceptionLayer;
+
+impl Layer for PerceptionLayer {
+    fn kind(&self) -> LayerKind {
+        LayerKind::Perception
+    }
+
+    fn name(&self) -> &str {
+        "perception"
+    }
+
+    fn process(&mut self, message: LayerMessage, _from: Direction, out: &mut Outbox) {
+        match message {
+            LayerMessage::Observation { value, .. } if !value.is_finite() => {}
+            message @ LayerMessage::Observation { .. } => out.forward(message),
+            _ => {}
+        }
+    }
+}
+
+// Accumulates observations per key and emits summaries every tick
+#[derive(Default)]
+pub struct MemoryLayer {
+    pending: HashMap<String, (f32, usize)>,
+}
+
+impl Layer for MemoryLayer {
+    fn kind(&self) -> LayerKind {
+        LayerKind::Memory
+    }
+
+    fn name(&self) -> &str {
+        "memory"
+    }
+
+    fn process(&mut self, message: LayerMessage, _from: Direction, out: &mut Outbox) {
+        match message {
+            LayerMessage::Observation { key, value, .. } => {
+                let entry = self.pending.entry(key).or_insert((0.0, 0));
+                entry.0 += value;
+                entry.1 += 1;
+            }
+            feedback @ LayerMessage::Feedback { .. } => out.backward(feedback),
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, out: &mut Outbox) {
+        for (key, (sum, samples)) in self.pending.drain() {
+            out.forward(LayerMessage::Summary { key, mean: sum / samples as f32, samples });
+        }
+    }
+}
+
+// Tracks a target per key and estimates how far the summaries are from it
+pub struct LearningLayer {
+    pub targets: HashMap<String, f32>,
+    pub learning_rate: f32,
+}
+
+impl Default for LearningLayer {
+    fn default() -> Self {
+        LearningLayer {
+            targets: HashMap::new(),
+            learning_rate: 0.1,
+        }
+    }
+}
+
+impl Layer for LearningLayer {
+    fn kind(&self) -> LayerKind {
+        LayerKind::Learning
+    }
+
+    fn name(&self) -> &str {
+        "learning"
+    }
+
+    fn process(&mut self, message: LayerMessage, _from: Direction, out: &mut Outbox) {
+        match message {
+            LayerMessage::Summary { key, mean, samples } => {
+                if let Some(target) = self.targets.get(&key) {
+                    let confidence = 1.0 - 1.0 / (samples as f32 + 1.0);
+                    out.forward(LayerMessage::Estimate {
+                        parameter: key,
+                        delta: (target - mean) * self.learning_rate,
+                        confidence,
+                    });
+                }
+            }
+            feedback @ LayerMessage::Feedback { .. } => out.backward(feedback),
+            _ => {}
+        }
+    }
+}
+
+// Turns confident estimates into absolute parameter decisions
+pub struct PolicyLayer {
+    pub values: HashMap<String, f32>,
+    pub min_confidence: f32,
+}
+
+impl Default for PolicyLayer {
+    fn default() -> Self {
+        PolicyLayer {
+            values: HashMap::new(),
+            min_confidence: 0.5,
+        }
+    }
+}
+
+impl Layer for PolicyLayer {
+    fn kind(&self) -> LayerKind {
+        LayerKind::Policy
+    }
+
+    fn name(&self) -> &str {
+        "policy"
+    }
+
+    fn process(&mut self, message: LayerMessage, _from: Direction, out: &mut Outbox) {
+        match message {
+            LayerMessage::Estimate { parameter, delta, confidence } if confidence >= self.min_confidence => {
+                let value = self.values.entry(parameter.clone()).or_insert(1.0);
+                *value += delta;
+                out.forward(LayerMessage::Decision { parameter, value: *value });
+            }
+            feedback @ LayerMessage::Feedback { .. } => out.backward(feedback),
+            _ => {}
+        }
+    }
+}
+
+// Addresses decisions to the game systems that own the parameters
+pub struct ActuationLayer {
+    pub default_target: String,
+    // Parameter name -> owning system
+    pub routes: HashMap<String, String>,
+}
+
+impl Default for ActuationLayer {
+    fn default() -> Self {
+        ActuationLayer {
+            default_target: "game".to_string(),
+            routes: HashMap::new(),
+        }
+    }
+}
+
+impl Layer for ActuationLayer {
+    fn kind(&self) -> LayerKind {
+        LayerKind::Actuation
+    }
+
+    fn name(&self) -> &str {
+        "actuation"
+    }
+
+    fn process(&mut self, message: LayerMessage, _from: Direction, out: &mut Outbox) {
+        match message {
+            LayerMessage::Decision { parameter, value } => {
+                let target = self.routes.get(&parameter).unwrap_or(&self.default_target).clone();
+                out.forward(LayerMessage::Command { target, parameter, value });
+            }
+            feedback @ LayerMessage::Feedback { .. } => out.backward(feedback),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn observation(value: f32) -> LayerMessage {
+        LayerMessage::Observation { source: "telemetry".to_string(), key: "difficulty".to_string(), value }
+    }
+
+    #[test]
+    fn observations_become_commands_in_one_update() {
+        let mut manager = LayerManager::new();
+        manager.add_layer(Box::new(PerceptionLayer));
+        manager.add_layer(Box::new(MemoryLayer::default()));
+        let mut learning = LearningLayer::default();
+        learning.targets.insert("difficulty".to_string(), 2.0);
+        manager.add_layer(Box::new(learning));
+        manager.add_layer(Box::new(PolicyLayer::default()));
+        let mut actuation = ActuationLayer::default();
+        actuation.routes.insert("difficulty".to_string(), "spawner".to_string());
+        manager.add_layer(Box::new(actuation));
+
+        for value in [0.0, 1.0, f32::NAN] {
+            manager.send(LayerKind::Perception, observation(value));
+        }
+        manager.update();
+        let outputs = manager.drain_outputs();
+        // Mean 0.5 (the NaN is dropped), 1.5 short of the target at rate 0.1, from the default 1.0
+        assert_eq!(
+            outputs,
+            vec![LayerMessage::Command { target: "spawner".to_string(), parameter: "difficulty".to_string(), value: 1.15 }]
+        );
+    }
+
+    #[test]
+    fn budgets_defer_work_and_feedback_skips_missing_layers() {
+        let mut manager = LayerManager::new();
+        manager.add_layer(Box::new(PerceptionLayer));
+        manager.add_layer(Box::new(ActuationLayer::default()));
+        manager.set_budget(LayerKind::Perception, LayerBudget { max_messages: 2, max_time: Duration::from_secs(1) });
+        for value in [1.0, 2.0, 3.0] {
+            manager.send(LayerKind::Perception, observation(value));
+        }
+        manager.send(LayerKind::Actuation, LayerMessage::Feedback { key: "rejected".to_string(), value: 1.0 });
+        manager.update();
+
+        // One observation left over, plus the feedback that skipped the three missing layers
+        let perception = &manager.layers()[0];
+        assert_eq!((perception.queued, perception.stats.processed, perception.stats.budget_exhausted), (2, 2, 1));
+        assert!(manager.drain_feedback().is_empty());
+        manager.update();
+        assert_eq!(manager.layers()[0].queued, 0);
+    }
+}
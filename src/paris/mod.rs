@@ -0,0 +1,12 @@
+// PARIS: Perpetual Adaptive Regenerative Intelligence System
+//
+// Layered feedback loops that let the engine learn from play and adjust itself.
+
+pub mod checkpoints;
+pub mod feedback;
+pub mod framework;
+pub mod guardrails;
+pub mod layers;
+pub mod optimization;
+pub mod prompt_evolution;
+pub mod scheduler;
@@ -0,0 +1,575 @@
+// PARIS optimization
+//
+// Tunes named engine parameters (spawn rates, difficulty multipliers, search thresholds) within
+// bounds. Strategies work in a normalized [0, 1] space and follow an ask/tell protocol: the
+// manager suggests a candidate, the game runs with it, and the score computed from feedback
+// metrics is reported back.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::rng::Rng;
+
+// A tunable engine parameter
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    pub name: String,
+    pub min: f64,
+    pub max: f64,
+    pub initial: f64,
+}
+
+impl Parameter {
+    pub fn new(name: &str, min: f64, max: f64, initial: f64) -> Self {
+        Parameter {
+            name: name.to_string(),
+            min,
+            max,
+            initial: initial.clamp(min, max),
+        }
+    }
+
+    fn normalize(&self, value: f64) -> f64 {
+        if self.max > self.min {
+            ((value - self.min) / (self.max - self.min)).clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+
+    fn denormalize(&self, unit: f64) -> f64 {
+        self.min + unit.clamp(0.0, 1.0) * (self.max - self.min)
+    }
+}
+
+// Weighted sum of feedback metrics; negative weights penalize (e.g. quit rate)
+#[derive(Debug, Clone, Default)]
+pub struct Objective {
+    pub weights: HashMap<String, f64>,
+}
+
+impl Objective {
+    pub fn new() -> Self {
+        Objective::default()
+    }
+
+    pub fn weight(mut self, metric: &str, weight: f64) -> Self {
+        self.weights.insert(metric.to_string(), weight);
+        self
+    }
+
+    // Missing metrics contribute nothing
+    pub fn score(&self, metrics: &HashMap<String, f64>) -> f64 {
+        self.weights
+            .iter()
+            .filter_map(|(metric, weight)| metrics.get(metric).map(|v| v * weight))
+            .sum()
+    }
+}
+
+// Search strategy over the unit hypercube; higher scores are better
+pub trait Strategy {
+    fn name(&self) -> &str;
+    fn propose(&mut self, dimensions: usize, rng: &mut Rng) -> Vec<f64>;
+    fn observe(&mut self, candidate: &[f64], score: f64);
+}
+
+pub struct RandomSearch;
+
+impl Strategy for RandomSearch {
+    fn name(&self) -> &str {
+        "random_search"
+    }
+
+    fn propose(&mut self, dimensions: usize, rng: &mut Rng) -> Vec<f64> {
+        (0..dimensions).map(|_| rng.next_f64()).collect()
+    }
+
+    fn observe(&mut self, _candidate: &[f64], _score: f64) {}
+}
+
+// Gaussian steps around the best point, shrinking the step after repeated failures
+pub struct HillClimbing {
+    current: Option<(Vec<f64>, f64)>,
+    step: f64,
+    min_step: f64,
+    failures: u32,
+    start: Option<Vec<f64>>,
+}
+
+impl HillClimbing {
+    pub fn new(step: f64) -> Self {
+        HillClimbing {
+            current: None,
+            step,
+            min_step: step / 64.0,
+            failures: 0,
+            start: None,
+        }
+    }
+
+    // Start from a known point (normalized) instead of the first random sample
+    pub fn starting_at(mut self, start: Vec<f64>) -> Self {
+        self.start = Some(start);
+        self
+    }
+}
+
+impl Strategy for HillClimbing {
+    fn name(&self) -> &str {
+        "hill_climbing"
+    }
+
+    fn propose(&mut self, dimensions: usize, rng: &mut Rng) -> Vec<f64> {
+        match &self.current {
+            None => self
+                .start
+                .clone()
+                .unwrap_or_else(|| (0..dimensions).map(|_| rng.next_f64()).collect()),
+            Some((best, _)) => best
+                .iter()
+                .map(|x| (x + rng.normal() * self.step).clamp(0.0, 1.0))
+                .collect(),
+        }
+    }
+
+    fn observe(&mut self, candidate: &[f64], score: f64) {
+        match &self.current {
+            Some((_, best)) if score <= *best => {
+                self.failures += 1;
+                if self.failures >= 5 {
+                    self.step = (self.step * 0.5).max(self.min_step);
+                    self.failures = 0;
+                }
+            }
+            _ => {
+                self.current = Some((candidate.to_vec(), score));
+                self.failures = 0;
+            }
+        }
+    }
+}
+
+// Separable CMA-ES (diagonal covariance), suitable for the handful of parameters PARIS tunes
+pub struct CmaEs {
+    mean: Vec<f64>,
+    sigma: f64,
+    cov: Vec<f64>,
+    path_sigma: Vec<f64>,
+    path_cov: Vec<f64>,
+    lambda: usize,
+    weights: Vec<f64>,
+    generation: u32,
+    // Samples of the current generation: (y = step direction, score)
+    samples: Vec<(Vec<f64>, f64)>,
+    pending_y: Vec<Vec<f64>>,
+    initial_sigma: f64,
+}
+
+impl CmaEs {
+    pub fn new(initial_sigma: f64) -> Self {
+        CmaEs {
+            mean: Vec::new(),
+            sigma: initial_sigma,
+            cov: Vec::new(),
+            path_sigma: Vec::new(),
+            path_cov: Vec::new(),
+            lambda: 0,
+            weights: Vec::new(),
+            generation: 0,
+            samples: Vec::new(),
+            pending_y: Vec::new(),
+            initial_sigma,
+        }
+    }
+
+    fn init(&mut self, n: usize) {
+        self.mean = vec![0.5; n];
+        self.cov = vec![1.0; n];
+        self.path_sigma = vec![0.0; n];
+        self.path_cov = vec![0.0; n];
+        self.sigma = self.initial_sigma;
+        self.lambda = 4 + (3.0 * (n as f64).ln()).floor() as usize;
+        let mu = self.lambda / 2;
+        let raw: Vec<f64> = (1..=mu).map(|i| (mu as f64 + 0.5).ln() - (i as f64).ln()).collect();
+        let total: f64 = raw.iter().sum();
+        self.weights = raw.iter().map(|w| w / total).collect();
+    }
+
+    fn update(&mut self) {
+        let n = self.mean.len() as f64;
+        let mu_eff = 1.0 / self.weights.iter().map(|w| w * w).sum::<f64>();
+        let cc = 4.0 / (n + 4.0);
+        let cs = (mu_eff + 2.0) / (n + mu_eff + 5.0);
+        // Separable variant learns faster: scale rank-one/rank-mu rates by (n + 2) / 3
+        let c1 = (2.0 / ((n + 1.3).powi(2) + mu_eff)) * (n + 2.0) / 3.0;
+        let cmu = ((2.0 * (mu_eff - 2.0 + 1.0 / mu_eff)) / ((n + 2.0).powi(2) + mu_eff) * (n + 2.0) / 3.0).min(1.0 - c1);
+        let damps = 1.0 + 2.0 * (((mu_eff - 1.0) / (n + 1.0)).sqrt() - 1.0).max(0.0) + cs;
+        let chi_n = n.sqrt() * (1.0 - 1.0 / (4.0 * n) + 1.0 / (21.0 * n * n));
+
+        self.samples.sort_by(|a, b| b.1.total_cmp(&a.1));
+        let dims = self.mean.len();
+        let mut y_w = vec![0.0; dims];
+        for (w, (y, _)) in self.weights.iter().zip(&self.samples) {
+            for (acc, y) in y_w.iter_mut().zip(y) {
+                *acc += w * y;
+            }
+        }
+
+        for (j, y) in y_w.iter().enumerate() {
+            self.mean[j] = (self.mean[j] + self.sigma * y).clamp(0.0, 1.0);
+            self.path_sigma[j] = (1.0 - cs) * self.path_sigma[j]
+                + (cs * (2.0 - cs) * mu_eff).sqrt() * y / self.cov[j].sqrt();
+        }
+        let ps_norm = self.path_sigma.iter().map(|p| p * p).sum::<f64>().sqrt();
+        let decay = 1.0 - (1.0 - cs).powi(2 * (self.generation as i32 + 1));
+        let h_sigma = if ps_norm / decay.sqrt() / chi_n < 1.4 + 2.0 / (n + 1.0) { 1.0 } else { 0.0 };
+
+        for j in 0..dims {
+            self.path_cov[j] = (1.0 - cc) * self.path_cov[j] + h_sigma * (cc * (2.0 - cc) * mu_eff).sqrt() * y_w[j];
+            let rank_mu: f64 = self
+                .weights
+                .iter()
+                .zip(&self.samples)
+                .map(|(w, (y, _))| w * y[j] * y[j])
+                .sum();
+            self.cov[j] = (1.0 - c1 - cmu) * self.cov[j]
+                + c1 * (self.path_cov[j].powi(2) + (1.0 - h_sigma) * cc * (2.0 - cc) * self.cov[j])
+                + cmu * rank_mu;
+        }
+        self.sigma = (self.sigma * ((cs / damps) * (ps_norm / chi_n - 1.0)).exp()).clamp(1e-6, 1.0);
+        self.samples.clear();
+        self.generation += 1;
+    }
+}
+
+impl Strategy for CmaEs {
+    fn name(&self) -> &str {
+        "cma_es"
+    }
+
+    fn propose(&mut self, dimensions: usize, rng: &mut Rng) -> Vec<f64> {
+        if self.mean.len() != dimensions {
+            self.init(dimensions);
+        }
+        let y: Vec<f64> = self.cov.iter().map(|c| c.sqrt() * rng.normal()).collect();
+        let x = self
+            .mean
+            .iter()
+            .zip(&y)
+            .map(|(m, y)| (m + self.sigma * y).clamp(0.0, 1.0))
+            .collect();
+        self.pending_y.push(y);
+        x
+    }
+
+    fn observe(&mut self, _candidate: &[f64], score: f64) {
+        if self.pending_y.is_empty() {
+            return;
+        }
+        let y = self.pending_y.remove(0);
+        self.samples.push((y, score));
+        if self.samples.len() >= self.lambda {
+            self.update();
+        }
+    }
+}
+
+// Gaussian-process Bayesian optimization with an upper-confidence-bound acquisition
+pub struct BayesianOptimizer {
+    observations: Vec<(Vec<f64>, f64)>,
+    initial_samples: usize,
+    length_scale: f64,
+    noise: f64,
+    exploration: f64,
+    candidates_per_step: usize,
+    max_observations: usize,
+}
+
+impl Default for BayesianOptimizer {
+    fn default() -> Self {
+        BayesianOptimizer {
+            observations: Vec::new(),
+            initial_samples: 5,
+            length_scale: 0.2,
+            noise: 1e-4,
+            exploration: 2.0,
+            candidates_per_step: 256,
+            max_observations: 64,
+        }
+    }
+}
+
+impl BayesianOptimizer {
+    fn kernel(&self, a: &[f64], b: &[f64]) -> f64 {
+        let dist: f64 = a.iter().zip(b).map(|(x, y)| (x - y).powi(2)).sum();
+        (-dist / (2.0 * self.length_scale * self.length_scale)).exp()
+    }
+
+    // Posterior mean and standard deviation at x, given the Cholesky factor of K
+    fn predict(&self, chol: &[Vec<f64>], alpha: &[f64], x: &[f64], mean_offset: f64) -> (f64, f64) {
+        let k: Vec<f64> = self.observations.iter().map(|(o, _)| self.kernel(o, x)).collect();
+        let mean = mean_offset + k.iter().zip(alpha).map(|(a, b)| a * b).sum::<f64>();
+        let v = forward_substitute(chol, &k);
+        let variance = (1.0 - v.iter().map(|x| x * x).sum::<f64>()).max(1e-12);
+        (mean, variance.sqrt())
+    }
+}
+
+impl Strategy for BayesianOptimizer {
+    fn name(&self) -> &str {
+        "bayesian"
+    }
+
+    fn propose(&mut self, dimensions: usize, rng: &mut Rng) -> Vec<f64> {
+        let random = |rng: &mut Rng| -> Vec<f64> { (0..dimensions).map(|_| rng.next_f64()).collect() };
+        if self.observations.len() < self.initial_samples {
+            return random(rng);
+        }
+
+        let n = self.observations.len();
+        let mean_offset = self.observations.iter().map(|(_, s)| s).sum::<f64>() / n as f64;
+        let mut gram = vec![vec![0.0; n]; n];
+        for (i, row) in gram.iter_mut().enumerate() {
+            for (j, cell) in row.iter_mut().enumerate() {
+                *cell = self.kernel(&self.observations[i].0, &self.observations[j].0);
+            }
+            row[i] += self.noise;
+        }
+        let chol = match cholesky(&gram) {
+            Some(chol) => chol,
+            None => return random(rng),
+        };
+        let centered: Vec<f64> = self.observations.iter().map(|(_, s)| s - mean_offset).collect();
+        let alpha = backward_substitute(&chol, &forward_substitute(&chol, &centered));
+
+        let mut best = random(rng);
+        let mut best_ucb = f64::MIN;
+        for _ in 0..self.candidates_per_step {
+            let candidate = random(rng);
+            let (mean, std) = self.predict(&chol, &alpha, &candidate, mean_offset);
+            let ucb = mean + self.exploration * std;
+            if ucb > best_ucb {
+                best_ucb = ucb;
+                best = candidate;
+            }
+        }
+        best
+    }
+
+    fn observe(&mut self, candidate: &[f64], score: f64) {
+        self.observations.push((candidate.to_vec(), score));
+        // Keep the GP small: drop the worst observation once over the cap
+        if self.observations.len() > self.max_observations {
+            if let Some(worst) = self
+                .observations
+                .iter()
+                .enumerate()
+                .min_by(|a, b| a.1 .1.total_cmp(&b.1 .1))
+                .map(|(i, _)| i)
+            {
+                self.observations.remove(worst);
+            }
+        }
+    }
+}
+
+fn cholesky(matrix: &[Vec<f64>]) -> Option<Vec<Vec<f64>>> {
+    let n = matrix.len();
+    let mut l = vec![vec![0.0; n]; n];
+    for i in 0..n {
+        for j in 0..=i {
+            let sum: f64 = (0..j).map(|k| l[i][k] * l[j][k]).sum();
+            if i == j {
+                let diag = matrix[i][i] - sum;
+                if diag <= 0.0 {
+                    return None;
+                }
+                l[i][j] = diag.sqrt();
+            } else {
+                l[i][j] = (matrix[i][j] - sum) / l[j][j];
+            }
+        }
+    }
+    Some(l)
+}
+
+// Solve L x = b
+fn forward_substitute(l: &[Vec<f64>], b: &[f64]) -> Vec<f64> {
+    let mut x = vec![0.0; b.len()];
+    for i in 0..b.len() {
+        let sum: f64 = (0..i).map(|k| l[i][k] * x[k]).sum();
+        x[i] = (b[i] - sum) / l[i][i];
+    }
+    x
+}
+
+// Solve L^T x = b
+fn backward_substitute(l: &[Vec<f64>], b: &[f64]) -> Vec<f64> {
+    let n = b.len();
+    let mut x = vec![0.0; n];
+    for i in (0..n).rev() {
+        let sum: f64 = (i + 1..n).map(|k| l[k][i] * x[k]).sum();
+        x[i] = (b[i] - sum) / l[i][i];
+    }
+    x
+}
+
+#[derive(Debug)]
+pub enum OptimizationError {
+    NoParameters,
+    NoPendingSuggestion,
+}
+
+impl fmt::Display for OptimizationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OptimizationError::NoParameters => write!(f, "no parameters registered"),
+            OptimizationError::NoPendingSuggestion => write!(f, "report() called without a pending suggestion"),
+        }
+    }
+}
+
+impl std::error::Error for OptimizationError {}
+
+// Parameter values tried and the score they got
+pub type Trial = (HashMap<String, f64>, f64);
+
+pub struct OptimizationManager {
+    parameters: Vec<Parameter>,
+    strategy: Box<dyn Strategy>,
+    objective: Objective,
+    rng: Rng,
+    pending: Option<Vec<f64>>,
+    best: Option<Trial>,
+    history: Vec<Trial>,
+}
+
+impl OptimizationManager {
+    pub fn new(strategy: Box<dyn Strategy>, objective: Objective, seed: u64) -> Self {
+        OptimizationManager {
+            parameters: Vec::new(),
+            strategy,
+            objective,
+            rng: Rng::new(seed),
+            pending: None,
+            best: None,
+            history: Vec::new(),
+        }
+    }
+
+    pub fn add_parameter(&mut self, parameter: Parameter) {
+        self.parameters.push(parameter);
+    }
+
+    pub fn parameters(&self) -> &[Parameter] {
+        &self.parameters
+    }
+
+    pub fn strategy_name(&self) -> &str {
+        self.strategy.name()
+    }
+
+    // Swap strategies mid-run; history and best result are kept
+    pub fn set_strategy(&mut self, strategy: Box<dyn Strategy>) {
+        self.strategy = strategy;
+        self.pending = None;
+    }
+
+    // Next parameter values to try
+    pub fn suggest(&mut self) -> Result<HashMap<String, f64>, OptimizationError> {
+        if self.parameters.is_empty() {
+            return Err(OptimizationError::NoParameters);
+        }
+        let unit = self.strategy.propose(self.parameters.len(), &mut self.rng);
+        let values = self.denormalize(&unit);
+        self.pending = Some(unit);
+        Ok(values)
+    }
+
+    // Report the feedback metrics observed while the last suggestion was active
+    pub fn report(&mut self, metrics: &HashMap<String, f64>) -> Result<f64, OptimizationError> {
+        let score = self.objective.score(metrics);
+        self.report_score(score)?;
+        Ok(score)
+    }
+
+    pub fn report_score(&mut self, score: f64) -> Result<(), OptimizationError> {
+        let unit = self.pending.take().ok_or(OptimizationError::NoPendingSuggestion)?;
+        self.strategy.observe(&unit, score);
+        let values = self.denormalize(&unit);
+        if self.best.as_ref().is_none_or(|(_, best)| score > *best) {
+            self.best = Some((values.clone(), score));
+        }
+        self.history.push((values, score));
+        Ok(())
+    }
+
+    // Offline optimization against a closure (simulation harness, recorded metrics)
+    pub fn optimize(
+        &mut self,
+        iterations: usize,
+        mut evaluate: impl FnMut(&HashMap<String, f64>) -> HashMap<String, f64>,
+    ) -> Result<Option<&Trial>, OptimizationError> {
+        for _ in 0..iterations {
+            let candidate = self.suggest()?;
+            let metrics = evaluate(&candidate);
+            self.report(&metrics)?;
+        }
+        Ok(self.best.as_ref())
+    }
+
+    pub fn best(&self) -> Option<&Trial> {
+        self.best.as_ref()
+    }
+
+    pub fn history(&self) -> &[Trial] {
+        &self.history
+    }
+
+    // Initial values, normalized, e.g. for HillClimbing::starting_at
+    pub fn initial_point(&self) -> Vec<f64> {
+        self.parameters.iter().map(|p| p.normalize(p.initial)).collect()
+    }
+
+    fn denormalize(&self, unit: &[f64]) -> HashMap<String, f64> {
+        self.parameters
+            .iter()
+            .zip(unit)
+            .map(|(p, u)| (p.name.clone(), p.denormalize(*u)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Peaks at difficulty 0.7, spawn_rate 2.0
+    fn run(strategy: Box<dyn Strategy>, iterations: usize) -> f64 {
+        let mut manager = OptimizationManager::new(strategy, Objective::new().weight("fun", 1.0), 11);
+        manager.add_parameter(Parameter::new("difficulty", 0.0, 1.0, 0.1));
+        manager.add_parameter(Parameter::new("spawn_rate", 0.0, 4.0, 0.5));
+        let best = manager
+            .optimize(iterations, |values| {
+                let fun = -(values["difficulty"] - 0.7).powi(2) - ((values["spawn_rate"] - 2.0) / 4.0).powi(2);
+                HashMap::from([("fun".to_string(), fun)])
+            })
+            .unwrap()
+            .cloned()
+            .unwrap();
+        assert_eq!(manager.history().len(), iterations);
+        best.1
+    }
+
+    #[test]
+    fn cma_es_converges_on_the_peak() {
+        assert!(run(Box::new(CmaEs::new(0.3)), 200) > -0.01);
+    }
+
+    #[test]
+    fn bayesian_optimizer_beats_the_starting_point() {
+        let start = -(0.1f64 - 0.7).powi(2) - ((0.5f64 - 2.0) / 4.0).powi(2);
+        assert!(run(Box::new(BayesianOptimizer::default()), 30) > start + 0.2);
+    }
+}
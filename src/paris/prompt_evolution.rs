@@ -0,0 +1,365 @@
+// Prompt evolution for NPC dialogue
+//
+// Each NPC archetype (merchant, guard, quest giver) keeps a small population of system prompt
+// variants. Conversations are assigned a variant, scored from player feedback (engagement,
+// conversation length, sentiment) and, once every variant has enough trials, the weakest half is
+// replaced by mutations of the strongest. The best variant is promoted to champion and receives
+// most of the traffic.
+
+use std::collections::HashMap;
+
+use crate::generation::TextGenerator;
+use crate::paris::feedback::FeedbackManager;
+use crate::rng::Rng;
+
+#[derive(Debug, Clone)]
+pub struct PromptVariant {
+    pub id: u64,
+    pub archetype: String,
+    pub system_prompt: String,
+    pub generation: u32,
+    pub parent: Option<u64>,
+    pub trials: u32,
+    pub total_score: f64,
+}
+
+impl PromptVariant {
+    pub fn mean_score(&self) -> f64 {
+        if self.trials == 0 {
+            0.0
+        } else {
+            self.total_score / self.trials as f64
+        }
+    }
+}
+
+// Feedback gathered from one conversation
+#[derive(Debug, Clone, Copy)]
+pub struct ConversationOutcome {
+    // 0..1, e.g. fraction of turns the player answered rather than walked away
+    pub engagement: f64,
+    pub turns: u32,
+    // -1..1 from the sentiment classifier
+    pub sentiment: f64,
+}
+
+impl ConversationOutcome {
+    // Build an outcome from the "<prefix>.engagement", "<prefix>.turns" and "<prefix>.sentiment"
+    // telemetry windows, e.g. prefix "dialogue.variant-12"
+    pub fn from_feedback(feedback: &FeedbackManager, prefix: &str) -> Option<Self> {
+        let mean = |field: &str| feedback.signal(&format!("{}.{}", prefix, field)).map(|s| s.mean);
+        Some(ConversationOutcome {
+            engagement: mean("engagement")?,
+            turns: mean("turns").unwrap_or(0.0).round() as u32,
+            sentiment: mean("sentiment").unwrap_or(0.0),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ScoreWeights {
+    pub engagement: f64,
+    pub length: f64,
+    pub sentiment: f64,
+    // Conversations longer than this stop earning length score
+    pub target_turns: u32,
+}
+
+impl Default for ScoreWeights {
+    fn default() -> Self {
+        ScoreWeights {
+            engagement: 0.5,
+            length: 0.2,
+            sentiment: 0.3,
+            target_turns: 8,
+        }
+    }
+}
+
+impl ScoreWeights {
+    pub fn score(&self, outcome: &ConversationOutcome) -> f64 {
+        let length = (outcome.turns as f64 / self.target_turns.max(1) as f64).min(1.0);
+        let sentiment = (outcome.sentiment.clamp(-1.0, 1.0) + 1.0) / 2.0;
+        self.engagement * outcome.engagement.clamp(0.0, 1.0) + self.length * length + self.sentiment * sentiment
+    }
+}
+
+// Produces a new prompt variant from a parent
+pub trait PromptMutator {
+    fn mutate(&self, prompt: &str, rng: &mut Rng) -> String;
+}
+
+// Adds, swaps or removes style directives at the end of the prompt
+pub struct DirectiveMutator {
+    pub directives: Vec<String>,
+}
+
+impl Default for DirectiveMutator {
+    fn default() -> Self {
+        DirectiveMutator {
+            directives: [
+                "Keep replies under three sentences.",
+                "Ask the player a question in return.",
+                "Refer to something the player did earlier.",
+                "Use vivid, sensory language.",
+                "Speak plainly and directly.",
+                "Hint at a secret you are keeping.",
+                "Show your emotions openly.",
+            ]
+            .iter()
+            .map(|d| d.to_string())
+            .collect(),
+        }
+    }
+}
+
+impl PromptMutator for DirectiveMutator {
+    fn mutate(&self, prompt: &str, rng: &mut Rng) -> String {
+        let present: Vec<&String> = self.directives.iter().filter(|d| prompt.contains(d.as_str())).collect();
+        let absent: Vec<&String> = self.directives.iter().filter(|d| !prompt.contains(d.as_str())).collect();
+        match (rng.below(3), rng.choose(&present), rng.choose(&absent)) {
+            (0, Some(old), Some(new)) => prompt.replacen(old.as_str(), new, 1),
+            (1, Some(old), _) => prompt.replacen(old.as_str(), "", 1).trim().to_string(),
+            (_, _, Some(new)) => format!("{} {}", prompt.trim(), new),
+            _ => prompt.to_string(),
+        }
+    }
+}
+
+// Asks an LLM to rewrite the prompt; falls back to the parent prompt on failure
+pub struct LlmMutator<G: TextGenerator> {
+    pub generator: G,
+}
+
+impl<G: TextGenerator> PromptMutator for LlmMutator<G> {
+    fn mutate(&self, prompt: &str, _rng: &mut Rng) -> String {
+        let request = format!(
+            "Rewrite this NPC system prompt to make conversations more engaging while keeping the character \
+             and all factual constraints. Reply with the new prompt only.\n\n{}",
+            prompt
+        );
+        match self.generator.generate(&request) {
+            Ok(text) if !text.trim().is_empty() => text.trim().to_string(),
+            _ => prompt.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EvolutionConfig {
+    pub population_size: usize,
+    // Trials every variant needs before a selection round
+    pub min_trials: u32,
+    // Share of traffic the champion receives once one is promoted
+    pub champion_traffic: f64,
+    pub weights: ScoreWeights,
+}
+
+impl Default for EvolutionConfig {
+    fn default() -> Self {
+        EvolutionConfig {
+            population_size: 6,
+            min_trials: 20,
+            champion_traffic: 0.7,
+            weights: ScoreWeights::default(),
+        }
+    }
+}
+
+// Result of a selection round
+#[derive(Debug, Clone)]
+pub struct EvolutionReport {
+    pub archetype: String,
+    pub champion: u64,
+    pub champion_score: f64,
+    pub culled: Vec<u64>,
+    pub spawned: Vec<u64>,
+}
+
+pub struct PromptEvolution {
+    config: EvolutionConfig,
+    mutator: Box<dyn PromptMutator>,
+    rng: Rng,
+    next_id: u64,
+    populations: HashMap<String, Vec<PromptVariant>>,
+    champions: HashMap<String, u64>,
+}
+
+impl PromptEvolution {
+    pub fn new(config: EvolutionConfig, mutator: Box<dyn PromptMutator>, seed: u64) -> Self {
+        PromptEvolution {
+            config,
+            mutator,
+            rng: Rng::new(seed),
+            next_id: 1,
+            populations: HashMap::new(),
+            champions: HashMap::new(),
+        }
+    }
+
+    // Start a population from a designer-written prompt plus mutations of it
+    pub fn seed(&mut self, archetype: &str, base_prompt: &str) {
+        let mut population = vec![self.new_variant(archetype, base_prompt.to_string(), 0, None)];
+        while population.len() < self.config.population_size.max(1) {
+            let prompt = self.mutator.mutate(base_prompt, &mut self.rng);
+            population.push(self.new_variant(archetype, prompt, 0, Some(population[0].id)));
+        }
+        self.champions.insert(archetype.to_string(), population[0].id);
+        self.populations.insert(archetype.to_string(), population);
+    }
+
+    fn new_variant(&mut self, archetype: &str, system_prompt: String, generation: u32, parent: Option<u64>) -> PromptVariant {
+        let id = self.next_id;
+        self.next_id += 1;
+        PromptVariant {
+            id,
+            archetype: archetype.to_string(),
+            system_prompt,
+            generation,
+            parent,
+            trials: 0,
+            total_score: 0.0,
+        }
+    }
+
+    // Pick the prompt for a new conversation. Under-tested variants get traffic first; after
+    // that the champion gets `champion_traffic` and the rest is spread over challengers.
+    pub fn assign(&mut self, archetype: &str) -> Option<(u64, String)> {
+        let population = self.populations.get(archetype)?;
+        let min_trials = self.config.min_trials;
+        if let Some(untested) = population.iter().filter(|v| v.trials < min_trials).min_by_key(|v| v.trials) {
+            return Some((untested.id, untested.system_prompt.clone()));
+        }
+        let champion = self.champions.get(archetype).copied();
+        let variant = if self.rng.chance(self.config.champion_traffic) {
+            population.iter().find(|v| Some(v.id) == champion)
+        } else {
+            let challengers: Vec<&PromptVariant> = population.iter().filter(|v| Some(v.id) != champion).collect();
+            self.rng.choose(&challengers).copied()
+        }
+        .or_else(|| population.first())?;
+        Some((variant.id, variant.system_prompt.clone()))
+    }
+
+    // Record the outcome of a conversation that used `variant_id`
+    pub fn record(&mut self, variant_id: u64, outcome: &ConversationOutcome) -> bool {
+        let score = self.config.weights.score(outcome);
+        for variant in self.populations.values_mut().flatten() {
+            if variant.id == variant_id {
+                variant.trials += 1;
+                variant.total_score += score;
+                return true;
+            }
+        }
+        false
+    }
+
+    // Run a selection round if every variant has enough trials
+    pub fn evolve(&mut self, archetype: &str) -> Option<EvolutionReport> {
+        let min_trials = self.config.min_trials;
+        let mut population = self.populations.remove(archetype)?;
+        if population.iter().any(|v| v.trials < min_trials) {
+            self.populations.insert(archetype.to_string(), population);
+            return None;
+        }
+
+        population.sort_by(|a, b| b.mean_score().total_cmp(&a.mean_score()));
+        let survivors = population.len().div_ceil(2);
+        let culled: Vec<u64> = population.drain(survivors..).map(|v| v.id).collect();
+        let mut spawned = Vec::new();
+        let mut parent_index = 0;
+        while population.len() < self.config.population_size.max(1) {
+            let parent = population[parent_index % survivors].clone();
+            let prompt = self.mutator.mutate(&parent.system_prompt, &mut self.rng);
+            let child = self.new_variant(archetype, prompt, parent.generation + 1, Some(parent.id));
+            spawned.push(child.id);
+            population.push(child);
+            parent_index += 1;
+        }
+
+        let champion = &population[0];
+        let report = EvolutionReport {
+            archetype: archetype.to_string(),
+            champion: champion.id,
+            champion_score: champion.mean_score(),
+            culled,
+            spawned,
+        };
+        self.champions.insert(archetype.to_string(), champion.id);
+        self.populations.insert(archetype.to_string(), population);
+        Some(report)
+    }
+
+    pub fn champion(&self, archetype: &str) -> Option<&PromptVariant> {
+        let id = self.champions.get(archetype)?;
+        self.populations.get(archetype)?.iter().find(|v| v.id == *id)
+    }
+
+    pub fn population(&self, archetype: &str) -> &[PromptVariant] {
+        self.populations.get(archetype).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    pub fn archetypes(&self) -> impl Iterator<Item = &String> {
+        self.populations.keys()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Appends a generation marker, so children are distinguishable from parents
+    struct Suffix;
+
+    impl PromptMutator for Suffix {
+        fn mutate(&self, prompt: &str, _rng: &mut Rng) -> String {
+            format!("{} +", prompt)
+        }
+    }
+
+    fn outcome(engagement: f64) -> ConversationOutcome {
+        ConversationOutcome { engagement, turns: 8, sentiment: 0.0 }
+    }
+
+    #[test]
+    fn untested_variants_get_traffic_before_selection() {
+        let config = EvolutionConfig { population_size: 3, min_trials: 2, ..Default::default() };
+        let mut evolution = PromptEvolution::new(config, Box::new(Suffix), 3);
+        evolution.seed("merchant", "You sell wares.");
+        assert_eq!(evolution.population("merchant").len(), 3);
+
+        let mut seen = Vec::new();
+        for _ in 0..6 {
+            let (id, _) = evolution.assign("merchant").unwrap();
+            seen.push(id);
+            evolution.record(id, &outcome(0.5));
+        }
+        seen.sort();
+        assert_eq!(seen, vec![1, 1, 2, 2, 3, 3]);
+        assert!(evolution.population("merchant").iter().all(|v| v.trials == 2));
+    }
+
+    #[test]
+    fn selection_replaces_the_weakest_half_with_children_of_the_best() {
+        let config = EvolutionConfig { population_size: 4, min_trials: 1, ..Default::default() };
+        let mut evolution = PromptEvolution::new(config, Box::new(Suffix), 3);
+        evolution.seed("guard", "Halt.");
+        assert!(evolution.evolve("guard").is_none());
+
+        for (id, engagement) in [(1, 0.2), (2, 0.9), (3, 0.1), (4, 0.6)] {
+            assert!(evolution.record(id, &outcome(engagement)));
+        }
+        let report = evolution.evolve("guard").unwrap();
+        assert_eq!(report.champion, 2);
+        let mut culled = report.culled.clone();
+        culled.sort();
+        assert_eq!(culled, vec![1, 3]);
+        assert_eq!(report.spawned, vec![5, 6]);
+
+        let champion = evolution.champion("guard").unwrap();
+        assert_eq!(champion.id, 2);
+        let child = evolution.population("guard").iter().find(|v| v.id == 5).unwrap();
+        assert_eq!((child.parent, child.generation), (Some(2), 1));
+        assert_eq!(child.system_prompt, format!("{} +", champion.system_prompt));
+    }
+}
@@ -0,0 +1,268 @@
+// PARIS cycle scheduling
+//
+// A CycleDriver decides, once per game tick, whether the framework should run a learning cycle:
+// only when asked (on demand), on a fixed interval, once enough new feedback has arrived, or when
+// events on chosen topics are published. Any policy also runs after an explicit request().
+//
+// Overload protection: a due cycle is skipped when the time the game has already spent this tick
+// plus the expected cycle cost would exceed the tick budget. The cycle stays due and is retried on
+// the next tick; after `max_deferrals` skips in a row it runs regardless so learning cannot starve.
+
+use std::time::{Duration, Instant};
+
+use crate::events::{EventBus, SubscriptionId};
+use crate::paris::framework::{CycleReport, ParisFramework};
+
+#[derive(Debug, Clone)]
+pub enum CyclePolicy {
+    OnDemand,
+    Interval(Duration),
+    // Run once this many feedback samples arrived since the last cycle
+    FeedbackVolume { min_samples: usize },
+    // Run when an event whose topic starts with one of these prefixes is published
+    Events(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OverloadConfig {
+    // Time per game tick the game plus PARIS may use
+    pub tick_budget: Duration,
+    pub max_deferrals: u32,
+}
+
+impl Default for OverloadConfig {
+    fn default() -> Self {
+        OverloadConfig { tick_budget: Duration::from_millis(16), max_deferrals: 30 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    OverBudget,
+}
+
+#[derive(Debug)]
+pub enum TickOutcome {
+    Idle,
+    Ran(CycleReport),
+    Skipped(SkipReason),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DriverStats {
+    pub cycles: u64,
+    pub skipped: u64,
+    // Smoothed cycle duration used to predict the next one
+    pub expected_cycle_time: Duration,
+}
+
+pub struct CycleDriver {
+    policy: CyclePolicy,
+    overload: OverloadConfig,
+    last_run: Option<Instant>,
+    samples_since_cycle: usize,
+    requested: bool,
+    deferrals: u32,
+    subscriptions: Vec<SubscriptionId>,
+    stats: DriverStats,
+}
+
+impl CycleDriver {
+    pub fn new(policy: CyclePolicy, overload: OverloadConfig) -> Self {
+        CycleDriver {
+            policy,
+            overload,
+            last_run: None,
+            samples_since_cycle: 0,
+            requested: false,
+            deferrals: 0,
+            subscriptions: Vec::new(),
+            stats: DriverStats::default(),
+        }
+    }
+
+    pub fn policy(&self) -> &CyclePolicy {
+        &self.policy
+    }
+
+    // Switch policies, moving event subscriptions over to the new topics
+    pub fn set_policy(&mut self, policy: CyclePolicy, bus: &mut EventBus) {
+        self.detach(bus);
+        self.policy = policy;
+        self.listen(bus);
+    }
+
+    // Subscribe to the policy's event topics; needed for CyclePolicy::Events
+    pub fn listen(&mut self, bus: &mut EventBus) {
+        if let CyclePolicy::Events(topics) = &self.policy {
+            self.subscriptions = topics.iter().map(|topic| bus.subscribe(topic)).collect();
+        }
+    }
+
+    pub fn detach(&mut self, bus: &mut EventBus) {
+        for subscription in self.subscriptions.drain(..) {
+            bus.unsubscribe(subscription);
+        }
+    }
+
+    // Run a cycle on the next tick regardless of policy
+    pub fn request(&mut self) {
+        self.requested = true;
+    }
+
+    pub fn stats(&self) -> &DriverStats {
+        &self.stats
+    }
+
+    // Call once per game tick with the time already spent in this tick
+    pub fn tick(&mut self, framework: &mut ParisFramework, bus: Option<&mut EventBus>, used_this_tick: Duration) -> TickOutcome {
+        if let Some(bus) = bus {
+            for subscription in &self.subscriptions {
+                if !bus.drain(*subscription).is_empty() {
+                    self.requested = true;
+                }
+            }
+        }
+        if let CyclePolicy::FeedbackVolume { .. } = self.policy {
+            self.samples_since_cycle += framework.ingest();
+        }
+        if !self.is_due() {
+            return TickOutcome::Idle;
+        }
+
+        let over_budget = used_this_tick + self.stats.expected_cycle_time > self.overload.tick_budget;
+        if over_budget && self.deferrals < self.overload.max_deferrals {
+            self.deferrals += 1;
+            self.stats.skipped += 1;
+            return TickOutcome::Skipped(SkipReason::OverBudget);
+        }
+
+        let report = framework.process_cycle();
+        self.last_run = Some(Instant::now());
+        self.samples_since_cycle = 0;
+        self.requested = false;
+        self.deferrals = 0;
+        self.stats.cycles += 1;
+        self.stats.expected_cycle_time = if self.stats.cycles == 1 {
+            report.elapsed
+        } else {
+            (self.stats.expected_cycle_time * 3 + report.elapsed) / 4
+        };
+        TickOutcome::Ran(report)
+    }
+
+    fn is_due(&self) -> bool {
+        if self.requested {
+            return true;
+        }
+        match &self.policy {
+            CyclePolicy::OnDemand | CyclePolicy::Events(_) => false,
+            CyclePolicy::Interval(interval) => self.last_run.is_none_or(|at| at.elapsed() >= *interval),
+            CyclePolicy::FeedbackVolume { min_samples } => self.samples_since_cycle >= (*min_samples).max(1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::paris::feedback::{FeedbackAdapter, FeedbackManager, FeedbackSample, FeedbackSource, WindowConfig};
+    use crate::paris::layers::LayerManager;
+
+    // Yields one sample per poll
+    struct Trickle;
+
+    impl FeedbackAdapter for Trickle {
+        fn source(&self) -> FeedbackSource {
+            FeedbackSource::Metrics
+        }
+
+        fn poll(&mut self) -> Vec<FeedbackSample> {
+            vec![FeedbackSample::new("fps", 60.0, FeedbackSource::Metrics)]
+        }
+    }
+
+    fn framework() -> ParisFramework {
+        let mut feedback = FeedbackManager::new(WindowConfig::default());
+        feedback.add_adapter(Box::new(Trickle));
+        ParisFramework::new(feedback, LayerManager::new())
+    }
+
+    fn ran(outcome: &TickOutcome) -> bool {
+        matches!(outcome, TickOutcome::Ran(_))
+    }
+
+    #[test]
+    fn on_demand_runs_only_when_requested() {
+        let mut paris = framework();
+        let mut driver = CycleDriver::new(CyclePolicy::OnDemand, OverloadConfig::default());
+        assert!(matches!(driver.tick(&mut paris, None, Duration::ZERO), TickOutcome::Idle));
+        driver.request();
+        assert!(ran(&driver.tick(&mut paris, None, Duration::ZERO)));
+        assert!(matches!(driver.tick(&mut paris, None, Duration::ZERO), TickOutcome::Idle));
+        assert_eq!((driver.stats().cycles, paris.cycles()), (1, 1));
+    }
+
+    #[test]
+    fn interval_runs_immediately_then_waits() {
+        let mut paris = framework();
+        let mut driver = CycleDriver::new(CyclePolicy::Interval(Duration::from_secs(3600)), OverloadConfig::default());
+        assert!(ran(&driver.tick(&mut paris, None, Duration::ZERO)));
+        assert!(matches!(driver.tick(&mut paris, None, Duration::ZERO), TickOutcome::Idle));
+
+        let mut eager = CycleDriver::new(CyclePolicy::Interval(Duration::ZERO), OverloadConfig::default());
+        assert!(ran(&eager.tick(&mut paris, None, Duration::ZERO)));
+        assert!(ran(&eager.tick(&mut paris, None, Duration::ZERO)));
+    }
+
+    #[test]
+    fn feedback_volume_waits_for_enough_samples() {
+        let mut paris = framework();
+        let mut driver = CycleDriver::new(CyclePolicy::FeedbackVolume { min_samples: 3 }, OverloadConfig::default());
+        let outcomes: Vec<bool> = (0..6).map(|_| ran(&driver.tick(&mut paris, None, Duration::ZERO))).collect();
+        // Samples polled by the cycle itself do not count towards the next one
+        assert_eq!(outcomes, vec![false, false, true, false, false, true]);
+    }
+
+    #[test]
+    fn events_trigger_cycles_and_follow_policy_changes() {
+        let mut paris = framework();
+        let mut bus = EventBus::new(16);
+        let mut driver = CycleDriver::new(CyclePolicy::Events(vec!["combat.".to_string()]), OverloadConfig::default());
+        driver.listen(&mut bus);
+
+        bus.emit("quest.done", "test", json!({}));
+        assert!(matches!(driver.tick(&mut paris, Some(&mut bus), Duration::ZERO), TickOutcome::Idle));
+        bus.emit("combat.hit", "test", json!({}));
+        assert!(ran(&driver.tick(&mut paris, Some(&mut bus), Duration::ZERO)));
+        assert!(matches!(driver.tick(&mut paris, Some(&mut bus), Duration::ZERO), TickOutcome::Idle));
+
+        driver.set_policy(CyclePolicy::Events(vec!["quest.".to_string()]), &mut bus);
+        assert_eq!(bus.emit("combat.hit", "test", json!({})), 0);
+        bus.emit("quest.done", "test", json!({}));
+        assert!(ran(&driver.tick(&mut paris, Some(&mut bus), Duration::ZERO)));
+
+        driver.detach(&mut bus);
+        assert_eq!(bus.emit("quest.done", "test", json!({})), 0);
+    }
+
+    #[test]
+    fn over_budget_cycles_are_deferred_then_forced() {
+        let mut paris = framework();
+        let overload = OverloadConfig { tick_budget: Duration::from_millis(16), max_deferrals: 2 };
+        let mut driver = CycleDriver::new(CyclePolicy::OnDemand, overload);
+        driver.request();
+        let busy = Duration::from_millis(20);
+        assert!(matches!(driver.tick(&mut paris, None, busy), TickOutcome::Skipped(SkipReason::OverBudget)));
+        assert!(matches!(driver.tick(&mut paris, None, busy), TickOutcome::Skipped(SkipReason::OverBudget)));
+        assert!(ran(&driver.tick(&mut paris, None, busy)));
+        assert_eq!((driver.stats().skipped, driver.stats().cycles), (2, 1));
+
+        // The deferral count resets after a cycle runs
+        driver.request();
+        assert!(matches!(driver.tick(&mut paris, None, busy), TickOutcome::Skipped(_)));
+        assert!(ran(&driver.tick(&mut paris, None, Duration::ZERO)));
+    }
+}
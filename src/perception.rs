@@ -0,0 +1,347 @@
+// NPC perception
+//
+// Each perceiver has a vision cone (range and field of view around its facing) checked for
+// occlusion against the spatial index, and a hearing threshold. Sounds are emitted as stimuli
+// whose loudness falls off with distance (inverse square, in decibels) and is muffled when
+// walls are in the way. Every update aggregates what each NPC sensed into one percept per target,
+// keeping the strongest sense, and refreshes a memory of last-known positions that fades over
+// time. Planners read it through WorldState facts ("sees_<id>", "knows_<id>_location").
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::ai::goap::WorldState;
+use crate::spatial::{distance, SpatialIndex};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct VisionCone {
+    pub range: f32,
+    // Full field of view in degrees
+    pub fov_degrees: f32,
+    // Targets this close are noticed regardless of facing
+    pub proximity_range: f32,
+}
+
+impl Default for VisionCone {
+    fn default() -> Self {
+        VisionCone { range: 20.0, fov_degrees: 110.0, proximity_range: 1.5 }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct HearingConfig {
+    // Quietest audible level in dB after attenuation
+    pub threshold_db: f32,
+    // Extra loss when the sound path is blocked
+    pub occlusion_db: f32,
+}
+
+impl Default for HearingConfig {
+    fn default() -> Self {
+        HearingConfig { threshold_db: 20.0, occlusion_db: 15.0 }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Perceiver {
+    pub position: [f32; 2],
+    // Unit vector the NPC is looking along
+    pub facing: [f32; 2],
+    pub vision: VisionCone,
+    pub hearing: HearingConfig,
+}
+
+impl Perceiver {
+    pub fn new(position: [f32; 2], facing: [f32; 2]) -> Self {
+        Perceiver { position, facing, vision: VisionCone::default(), hearing: HearingConfig::default() }
+    }
+
+    fn in_cone(&self, target: [f32; 2]) -> bool {
+        let offset = [target[0] - self.position[0], target[1] - self.position[1]];
+        let length = (offset[0] * offset[0] + offset[1] * offset[1]).sqrt();
+        if length <= self.vision.proximity_range {
+            return true;
+        }
+        if length > self.vision.range {
+            return false;
+        }
+        let facing_length = (self.facing[0] * self.facing[0] + self.facing[1] * self.facing[1]).sqrt().max(f32::EPSILON);
+        let cos = (offset[0] * self.facing[0] + offset[1] * self.facing[1]) / (length * facing_length);
+        cos >= (self.vision.fov_degrees.to_radians() / 2.0).cos()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SoundStimulus {
+    pub source: String,
+    pub position: [f32; 2],
+    // Level in dB at one world unit from the source
+    pub loudness_db: f32,
+    // What made the noise, e.g. "footstep", "gunshot"
+    pub tag: String,
+}
+
+impl SoundStimulus {
+    pub fn new(source: &str, position: [f32; 2], loudness_db: f32, tag: &str) -> Self {
+        SoundStimulus { source: source.to_string(), position, loudness_db, tag: tag.to_string() }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sense {
+    Sight,
+    Hearing,
+}
+
+#[derive(Debug, Clone)]
+pub struct Percept {
+    pub target: String,
+    pub sense: Sense,
+    pub position: [f32; 2],
+    // 0..1; sight is 1 up close and falls with distance, hearing scales with level over threshold
+    pub strength: f32,
+    pub tag: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LastKnown {
+    pub position: [f32; 2],
+    pub tick: u64,
+    pub sense: Sense,
+    pub confidence: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PerceptionMemory {
+    last_known: HashMap<String, LastKnown>,
+    current: HashMap<String, Percept>,
+}
+
+impl PerceptionMemory {
+    pub fn last_known(&self, target: &str) -> Option<&LastKnown> {
+        self.last_known.get(target)
+    }
+
+    // What was sensed on the latest update
+    pub fn current(&self) -> impl Iterator<Item = &Percept> {
+        self.current.values()
+    }
+
+    pub fn senses(&self, target: &str) -> Option<&Percept> {
+        self.current.get(target)
+    }
+}
+
+pub struct PerceptionSystem {
+    perceivers: HashMap<String, Perceiver>,
+    memories: HashMap<String, PerceptionMemory>,
+    sounds: Vec<SoundStimulus>,
+    // Ticks until a last-known position is forgotten
+    pub memory_ticks: u64,
+}
+
+impl PerceptionSystem {
+    pub fn new() -> Self {
+        PerceptionSystem {
+            perceivers: HashMap::new(),
+            memories: HashMap::new(),
+            sounds: Vec::new(),
+            memory_ticks: 600,
+        }
+    }
+
+    pub fn add_perceiver(&mut self, id: &str, perceiver: Perceiver) {
+        self.perceivers.insert(id.to_string(), perceiver);
+        self.memories.entry(id.to_string()).or_default();
+    }
+
+    pub fn remove_perceiver(&mut self, id: &str) {
+        self.perceivers.remove(id);
+        self.memories.remove(id);
+    }
+
+    pub fn perceiver_mut(&mut self, id: &str) -> Option<&mut Perceiver> {
+        self.perceivers.get_mut(id)
+    }
+
+    pub fn memory(&self, id: &str) -> Option<&PerceptionMemory> {
+        self.memories.get(id)
+    }
+
+    // Queue a sound; it is heard on the next update
+    pub fn emit_sound(&mut self, sound: SoundStimulus) {
+        self.sounds.push(sound);
+    }
+
+    // Sense everything in the index, consume queued sounds and refresh memories
+    pub fn update(&mut self, tick: u64, index: &SpatialIndex) {
+        let sounds = std::mem::take(&mut self.sounds);
+        for (id, perceiver) in &self.perceivers {
+            let mut percepts: HashMap<String, Percept> = HashMap::new();
+            let mut keep = |percept: Percept| {
+                let stronger = percepts.get(&percept.target).is_none_or(|p| percept.strength > p.strength);
+                if stronger {
+                    percepts.insert(percept.target.clone(), percept);
+                }
+            };
+
+            for (target, d) in index.query_radius(perceiver.position, perceiver.vision.range) {
+                if &target == id {
+                    continue;
+                }
+                let position = index.position(&target).unwrap_or(perceiver.position);
+                if perceiver.in_cone(position) && index.line_of_sight(perceiver.position, position) {
+                    let strength = 1.0 - (d / perceiver.vision.range.max(f32::EPSILON)).min(1.0) * 0.5;
+                    keep(Percept { target, sense: Sense::Sight, position, strength, tag: None });
+                }
+            }
+
+            for sound in sounds.iter().filter(|s| &s.source != id) {
+                let d = distance(perceiver.position, sound.position).max(1.0);
+                let mut level = sound.loudness_db - 20.0 * d.log10();
+                if !index.line_of_sight(perceiver.position, sound.position) {
+                    level -= perceiver.hearing.occlusion_db;
+                }
+                if level >= perceiver.hearing.threshold_db {
+                    keep(Percept {
+                        target: sound.source.clone(),
+                        sense: Sense::Hearing,
+                        position: sound.position,
+                        // Heard sounds never count as strongly as a clear sighting
+                        strength: ((level - perceiver.hearing.threshold_db) / 40.0).clamp(0.05, 0.9),
+                        tag: Some(sound.tag.clone()),
+                    });
+                }
+            }
+
+            let memory = self.memories.entry(id.clone()).or_default();
+            for percept in percepts.values() {
+                memory.last_known.insert(
+                    percept.target.clone(),
+                    LastKnown { position: percept.position, tick, sense: percept.sense, confidence: percept.strength },
+                );
+            }
+            let memory_ticks = self.memory_ticks;
+            memory.last_known.retain(|_, known| tick.saturating_sub(known.tick) <= memory_ticks);
+            memory.current = percepts;
+        }
+    }
+
+    // Write perception facts about `target` into a planner state
+    pub fn project(&self, id: &str, target: &str, tick: u64, state: &mut WorldState) {
+        let memory = self.memories.get(id);
+        let current = memory.and_then(|m| m.senses(target));
+        state.set(&format!("sees_{}", target), current.is_some_and(|p| p.sense == Sense::Sight));
+        state.set(&format!("hears_{}", target), current.is_some_and(|p| p.sense == Sense::Hearing));
+        match memory.and_then(|m| m.last_known(target)) {
+            Some(known) => {
+                state.set(&format!("knows_{}_location", target), true);
+                state.set(&format!("{}_last_seen_ticks", target), tick.saturating_sub(known.tick) as i64);
+            }
+            None => {
+                state.set(&format!("knows_{}_location", target), false);
+                state.remove(&format!("{}_last_seen_ticks", target));
+            }
+        }
+    }
+}
+
+impl Default for PerceptionSystem {
+    fn default() -> Self {
+        PerceptionSystem::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ai::goap::StateValue;
+    use crate::spatial::GridSpec;
+
+    // Guard at (5.5, 5.5) looking along +x
+    fn setup() -> (PerceptionSystem, SpatialIndex) {
+        let mut system = PerceptionSystem::new();
+        system.add_perceiver("guard", Perceiver::new([5.5, 5.5], [1.0, 0.0]));
+        let mut index = SpatialIndex::new(GridSpec::new(20, 20, 1.0));
+        index.set_position("guard", [5.5, 5.5]);
+        (system, index)
+    }
+
+    fn sense(system: &PerceptionSystem, target: &str) -> Option<Sense> {
+        system.memory("guard").unwrap().senses(target).map(|p| p.sense)
+    }
+
+    #[test]
+    fn sight_needs_the_cone_or_proximity_and_a_clear_line() {
+        let (mut system, mut index) = setup();
+        index.set_position("ahead", [12.5, 6.5]);
+        index.set_position("behind", [0.5, 5.5]);
+        index.set_position("close", [4.5, 5.5]);
+        system.update(1, &index);
+        assert_eq!(sense(&system, "ahead"), Some(Sense::Sight));
+        assert_eq!(sense(&system, "behind"), None);
+        assert_eq!(sense(&system, "close"), Some(Sense::Sight));
+        assert_eq!(sense(&system, "guard"), None);
+        let close = system.memory("guard").unwrap().senses("close").unwrap().strength;
+        let ahead = system.memory("guard").unwrap().senses("ahead").unwrap().strength;
+        assert!(close > ahead);
+
+        index.set_blocked((9, 6), true);
+        index.set_blocked((9, 5), true);
+        system.update(2, &index);
+        assert_eq!(sense(&system, "ahead"), None);
+    }
+
+    #[test]
+    fn sounds_fall_off_with_distance_and_walls() {
+        let (mut system, mut index) = setup();
+        system.emit_sound(SoundStimulus::new("thief", [5.5, 15.5], 50.0, "footstep"));
+        system.update(1, &index);
+        let heard = system.memory("guard").unwrap().senses("thief").unwrap();
+        assert_eq!(heard.sense, Sense::Hearing);
+        assert_eq!(heard.tag.as_deref(), Some("footstep"));
+
+        // Sounds are consumed by the update that hears them
+        system.update(2, &index);
+        assert_eq!(sense(&system, "thief"), None);
+
+        index.set_blocked((5, 10), true);
+        system.emit_sound(SoundStimulus::new("thief", [5.5, 15.5], 50.0, "footstep"));
+        system.update(3, &index);
+        assert_eq!(sense(&system, "thief"), None);
+    }
+
+    #[test]
+    fn sighting_beats_hearing_the_same_target() {
+        let (mut system, mut index) = setup();
+        index.set_position("thief", [8.5, 5.5]);
+        system.emit_sound(SoundStimulus::new("thief", [8.5, 5.5], 60.0, "gunshot"));
+        system.update(1, &index);
+        assert_eq!(sense(&system, "thief"), Some(Sense::Sight));
+    }
+
+    #[test]
+    fn last_known_positions_fade_and_project_into_the_planner() {
+        let (mut system, mut index) = setup();
+        system.memory_ticks = 10;
+        index.set_position("thief", [8.5, 5.5]);
+        system.update(1, &index);
+        index.remove("thief");
+        system.update(5, &index);
+
+        let mut state = WorldState::new();
+        system.project("guard", "thief", 5, &mut state);
+        assert_eq!(state.get("sees_thief"), Some(&StateValue::Bool(false)));
+        assert_eq!(state.get("knows_thief_location"), Some(&StateValue::Bool(true)));
+        assert_eq!(state.get("thief_last_seen_ticks"), Some(&StateValue::Int(4)));
+        assert_eq!(system.memory("guard").unwrap().last_known("thief").unwrap().position, [8.5, 5.5]);
+
+        system.update(20, &index);
+        system.project("guard", "thief", 20, &mut state);
+        assert_eq!(state.get("knows_thief_location"), Some(&StateValue::Bool(false)));
+        assert_eq!(state.get("thief_last_seen_ticks"), None);
+    }
+}
@@ -0,0 +1,477 @@
+// Player model
+//
+// One place that describes a player to the adaptive systems: a skill rating per activity
+// (Elo-style with an uncertainty that shrinks as evidence accumulates), a playstyle estimate from
+// what the player spends time on (explorer / achiever / socializer), a bounded history of mood
+// samples and free-form preference weights. Difficulty adjustment, procedural generation and quest
+// generation read it; models are persisted per player as versioned JSON files.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::emotion::{EmotionAdaptiveExperiences, MoodVector};
+use crate::versioning::{self, MigrationError, MigrationRegistry};
+
+pub const PLAYER_MODEL_FORMAT: &str = "player_model";
+pub const PLAYER_MODEL_VERSION: u32 = 1;
+
+// Mood samples kept per player
+const MOOD_HISTORY: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SkillRating {
+    pub rating: f64,
+    // Uncertainty in rating points; new players start wide
+    pub deviation: f64,
+    pub matches: u32,
+}
+
+impl Default for SkillRating {
+    fn default() -> Self {
+        SkillRating { rating: 1500.0, deviation: 350.0, matches: 0 }
+    }
+}
+
+impl SkillRating {
+    const MIN_DEVIATION: f64 = 50.0;
+    const MAX_K: f64 = 64.0;
+
+    // Probability of beating a challenge of the given rating
+    pub fn expected(&self, challenge: f64) -> f64 {
+        1.0 / (1.0 + 10f64.powf((challenge - self.rating) / 400.0))
+    }
+
+    // `score` is 1 for a win, 0 for a loss, anything between for partial success
+    pub fn update(&mut self, challenge: f64, score: f64) {
+        // Uncertain ratings move faster
+        let k = Self::MAX_K * (self.deviation / 350.0).max(0.25);
+        self.rating += k * (score.clamp(0.0, 1.0) - self.expected(challenge));
+        self.deviation = (self.deviation * 0.95).max(Self::MIN_DEVIATION);
+        self.matches += 1;
+    }
+
+    // Pessimistic estimate used for matchmaking and difficulty
+    pub fn conservative(&self) -> f64 {
+        self.rating - 2.0 * self.deviation
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Playstyle {
+    Explorer,
+    Achiever,
+    Socializer,
+}
+
+// What the player did, fed to the playstyle estimate
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayerActivity {
+    Discovered,
+    CompletedObjective,
+    Collected,
+    Conversed,
+    Grouped,
+    Traded,
+}
+
+impl PlayerActivity {
+    fn playstyle(self) -> Playstyle {
+        match self {
+            PlayerActivity::Discovered => Playstyle::Explorer,
+            PlayerActivity::CompletedObjective | PlayerActivity::Collected => Playstyle::Achiever,
+            PlayerActivity::Conversed | PlayerActivity::Grouped | PlayerActivity::Traded => Playstyle::Socializer,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct PlaystyleScores {
+    pub explorer: f64,
+    pub achiever: f64,
+    pub socializer: f64,
+}
+
+impl PlaystyleScores {
+    // Older activity counts for less; each new observation decays the rest by this factor
+    const DECAY: f64 = 0.99;
+
+    fn observe(&mut self, style: Playstyle, weight: f64) {
+        self.explorer *= Self::DECAY;
+        self.achiever *= Self::DECAY;
+        self.socializer *= Self::DECAY;
+        match style {
+            Playstyle::Explorer => self.explorer += weight,
+            Playstyle::Achiever => self.achiever += weight,
+            Playstyle::Socializer => self.socializer += weight,
+        }
+    }
+
+    // Shares summing to 1 (even split with no data)
+    pub fn normalized(&self) -> PlaystyleScores {
+        let total = self.explorer + self.achiever + self.socializer;
+        if total <= 0.0 {
+            let third = 1.0 / 3.0;
+            return PlaystyleScores { explorer: third, achiever: third, socializer: third };
+        }
+        PlaystyleScores {
+            explorer: self.explorer / total,
+            achiever: self.achiever / total,
+            socializer: self.socializer / total,
+        }
+    }
+
+    pub fn dominant(&self) -> Option<Playstyle> {
+        let scores = [
+            (Playstyle::Explorer, self.explorer),
+            (Playstyle::Achiever, self.achiever),
+            (Playstyle::Socializer, self.socializer),
+        ];
+        scores
+            .into_iter()
+            .filter(|(_, score)| *score > 0.0)
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(style, _)| style)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MoodSample {
+    // Unix seconds
+    pub at: u64,
+    pub tension: f32,
+    pub valence: f32,
+    pub energy: f32,
+}
+
+impl MoodSample {
+    pub fn mood(&self) -> MoodVector {
+        MoodVector::new(self.tension, self.valence, self.energy)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PlayerModel {
+    pub player_id: String,
+    pub skills: BTreeMap<String, SkillRating>,
+    pub playstyle: PlaystyleScores,
+    pub mood_history: VecDeque<MoodSample>,
+    // Designer-defined weights, e.g. "combat" = 0.8, "puzzles" = 0.2
+    pub preferences: BTreeMap<String, f32>,
+}
+
+impl PlayerModel {
+    pub fn new(player_id: &str) -> Self {
+        PlayerModel { player_id: player_id.to_string(), ..PlayerModel::default() }
+    }
+
+    pub fn skill(&self, activity: &str) -> SkillRating {
+        self.skills.get(activity).copied().unwrap_or_default()
+    }
+
+    pub fn record_outcome(&mut self, activity: &str, challenge: f64, score: f64) {
+        self.skills.entry(activity.to_string()).or_default().update(challenge, score);
+    }
+
+    pub fn record_activity(&mut self, activity: PlayerActivity) {
+        self.playstyle.observe(activity.playstyle(), 1.0);
+    }
+
+    pub fn record_mood(&mut self, at: u64, mood: MoodVector) {
+        if self.mood_history.len() >= MOOD_HISTORY {
+            self.mood_history.pop_front();
+        }
+        self.mood_history.push_back(MoodSample { at, tension: mood.tension, valence: mood.valence, energy: mood.energy });
+    }
+
+    // Copy the emotion system's current estimate into the history
+    pub fn sample_mood(&mut self, emotion: &EmotionAdaptiveExperiences, at: u64) -> bool {
+        match emotion.player_mood(&self.player_id) {
+            Some(mood) => {
+                self.record_mood(at, mood);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Mean mood over samples at or after `since`
+    pub fn average_mood(&self, since: u64) -> Option<MoodVector> {
+        let recent: Vec<&MoodSample> = self.mood_history.iter().filter(|s| s.at >= since).collect();
+        if recent.is_empty() {
+            return None;
+        }
+        let n = recent.len() as f32;
+        Some(MoodVector::new(
+            recent.iter().map(|s| s.tension).sum::<f32>() / n,
+            recent.iter().map(|s| s.valence).sum::<f32>() / n,
+            recent.iter().map(|s| s.energy).sum::<f32>() / n,
+        ))
+    }
+
+    pub fn set_preference(&mut self, key: &str, weight: f32) {
+        self.preferences.insert(key.to_string(), weight.clamp(0.0, 1.0));
+    }
+
+    // Move a preference towards 1 (liked) or 0 (disliked) by `amount`
+    pub fn nudge_preference(&mut self, key: &str, liked: bool, amount: f32) {
+        let current = self.preferences.get(key).copied().unwrap_or(0.5);
+        let target = if liked { 1.0 } else { 0.0 };
+        self.set_preference(key, current + (target - current) * amount.clamp(0.0, 1.0));
+    }
+
+    // Challenge rating the player should win about `success_rate` of the time
+    pub fn recommended_challenge(&self, activity: &str, success_rate: f64) -> f64 {
+        let p = success_rate.clamp(0.01, 0.99);
+        self.skill(activity).rating + 400.0 * ((1.0 - p) / p).log10()
+    }
+
+    // Template variables for generation prompts (quests, procgen)
+    pub fn template_vars(&self) -> HashMap<String, String> {
+        let mut vars = HashMap::new();
+        vars.insert("player_id".to_string(), self.player_id.clone());
+        let style = self.playstyle.dominant().map_or("balanced".to_string(), |s| {
+            serde_json::to_value(s).ok().and_then(|v| v.as_str().map(str::to_string)).unwrap_or_default()
+        });
+        vars.insert("playstyle".to_string(), style);
+        let mut liked: Vec<(&String, &f32)> = self.preferences.iter().filter(|(_, w)| **w >= 0.6).collect();
+        liked.sort_by(|a, b| b.1.total_cmp(a.1));
+        vars.insert("preferences".to_string(), liked.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>().join(", "));
+        for (activity, skill) in &self.skills {
+            vars.insert(format!("skill_{}", activity), format!("{:.0}", skill.rating));
+        }
+        vars
+    }
+}
+
+#[derive(Debug)]
+pub enum PlayerModelError {
+    Io(std::io::Error),
+    Migration(MigrationError),
+    Serialization(serde_json::Error),
+}
+
+impl fmt::Display for PlayerModelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlayerModelError::Io(err) => write!(f, "player model io error: {}", err),
+            PlayerModelError::Migration(err) => write!(f, "player model file error: {}", err),
+            PlayerModelError::Serialization(err) => write!(f, "invalid player model: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for PlayerModelError {}
+
+impl From<std::io::Error> for PlayerModelError {
+    fn from(err: std::io::Error) -> Self {
+        PlayerModelError::Io(err)
+    }
+}
+
+impl From<MigrationError> for PlayerModelError {
+    fn from(err: MigrationError) -> Self {
+        PlayerModelError::Migration(err)
+    }
+}
+
+impl From<serde_json::Error> for PlayerModelError {
+    fn from(err: serde_json::Error) -> Self {
+        PlayerModelError::Serialization(err)
+    }
+}
+
+// Player models in memory, optionally backed by one file per player in a directory
+pub struct PlayerModelStore {
+    dir: Option<PathBuf>,
+    models: HashMap<String, PlayerModel>,
+    registry: MigrationRegistry,
+}
+
+impl PlayerModelStore {
+    pub fn in_memory() -> Self {
+        PlayerModelStore { dir: None, models: HashMap::new(), registry: MigrationRegistry::with_builtin() }
+    }
+
+    pub fn open(dir: &Path) -> Result<Self, PlayerModelError> {
+        std::fs::create_dir_all(dir)?;
+        Ok(PlayerModelStore { dir: Some(dir.to_path_buf()), ..PlayerModelStore::in_memory() })
+    }
+
+    fn path(&self, player_id: &str) -> Option<PathBuf> {
+        let safe: String = player_id
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .collect();
+        self.dir.as_ref().map(|dir| dir.join(format!("{}.json", safe)))
+    }
+
+    pub fn get(&self, player_id: &str) -> Option<&PlayerModel> {
+        self.models.get(player_id)
+    }
+
+    // Current model from memory or disk without loading it into the store
+    pub fn read(&self, player_id: &str) -> Result<Option<PlayerModel>, PlayerModelError> {
+        if let Some(model) = self.models.get(player_id) {
+            return Ok(Some(model.clone()));
+        }
+        match self.path(player_id).filter(|p| p.exists()) {
+            Some(path) => {
+                let outcome = versioning::read_versioned(&path, PLAYER_MODEL_FORMAT, &self.registry, None)?;
+                Ok(Some(serde_json::from_value(outcome.data)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    // Loaded model, read from disk or created fresh on first use
+    pub fn model(&mut self, player_id: &str) -> Result<&mut PlayerModel, PlayerModelError> {
+        if !self.models.contains_key(player_id) {
+            let model = self.read(player_id)?.unwrap_or_else(|| PlayerModel::new(player_id));
+            self.models.insert(player_id.to_string(), model);
+        }
+        Ok(self.models.get_mut(player_id).expect("inserted above"))
+    }
+
+    pub fn save(&self, player_id: &str) -> Result<(), PlayerModelError> {
+        if let (Some(path), Some(model)) = (self.path(player_id), self.models.get(player_id)) {
+            let data: Value = serde_json::to_value(model)?;
+            versioning::write_versioned(&path, PLAYER_MODEL_FORMAT, PLAYER_MODEL_VERSION, data, None)?;
+        }
+        Ok(())
+    }
+
+    pub fn save_all(&self) -> Result<(), PlayerModelError> {
+        for player_id in self.models.keys() {
+            self.save(player_id)?;
+        }
+        Ok(())
+    }
+
+    // Forget a player in memory and on disk; returns true if anything existed
+    pub fn remove(&mut self, player_id: &str) -> Result<bool, PlayerModelError> {
+        let mut removed = self.models.remove(player_id).is_some();
+        if let Some(path) = self.path(player_id).filter(|p| p.exists()) {
+            std::fs::remove_file(path)?;
+            removed = true;
+        }
+        Ok(removed)
+    }
+
+    pub fn contains(&self, player_id: &str) -> bool {
+        self.models.contains_key(player_id) || self.path(player_id).is_some_and(|p| p.exists())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skill_moves_towards_results_and_settles() {
+        let mut skill = SkillRating::default();
+        assert_eq!(skill.expected(1500.0), 0.5);
+        skill.update(1500.0, 1.0);
+        assert_eq!(skill.rating, 1532.0);
+        let early_gain = skill.rating - 1500.0;
+        for _ in 0..100 {
+            skill.update(skill.rating, 0.5);
+        }
+        assert_eq!(skill.deviation, SkillRating::MIN_DEVIATION);
+        let before = skill.rating;
+        skill.update(before, 1.0);
+        assert!(skill.rating - before < early_gain);
+        assert!(skill.conservative() < skill.rating);
+    }
+
+    #[test]
+    fn recommended_challenge_matches_the_success_rate() {
+        let mut model = PlayerModel::new("p1");
+        model.record_outcome("combat", 1500.0, 1.0);
+        let challenge = model.recommended_challenge("combat", 0.7);
+        assert!((model.skill("combat").expected(challenge) - 0.7).abs() < 1e-9);
+        assert!(challenge < model.skill("combat").rating);
+        assert_eq!(model.recommended_challenge("puzzles", 0.5), 1500.0);
+    }
+
+    #[test]
+    fn playstyle_follows_recent_activity() {
+        let mut model = PlayerModel::new("p1");
+        assert_eq!(model.playstyle.dominant(), None);
+        assert!((model.playstyle.normalized().explorer - 1.0 / 3.0).abs() < 1e-12);
+        model.record_activity(PlayerActivity::Discovered);
+        model.record_activity(PlayerActivity::Conversed);
+        model.record_activity(PlayerActivity::Traded);
+        assert_eq!(model.playstyle.dominant(), Some(Playstyle::Socializer));
+        let shares = model.playstyle.normalized();
+        assert!((shares.explorer + shares.achiever + shares.socializer - 1.0).abs() < 1e-12);
+        assert_eq!(shares.achiever, 0.0);
+    }
+
+    #[test]
+    fn mood_history_is_bounded_and_averaged_by_time() {
+        let mut model = PlayerModel::new("p1");
+        for at in 0..(MOOD_HISTORY as u64 + 10) {
+            model.record_mood(at, MoodVector::new(0.2, 0.0, 0.5));
+        }
+        assert_eq!(model.mood_history.len(), MOOD_HISTORY);
+        assert_eq!(model.mood_history.front().unwrap().at, 10);
+
+        model.record_mood(1000, MoodVector::new(0.8, 0.0, 0.5));
+        let recent = model.average_mood(1000).unwrap();
+        assert!((recent.tension - 0.8).abs() < 1e-6);
+        assert!(model.average_mood(2000).is_none());
+
+        let mut emotion = EmotionAdaptiveExperiences::new();
+        assert!(!model.sample_mood(&emotion, 1001));
+        emotion.set_player_mood("p1", MoodVector::new(0.1, 0.1, 0.1));
+        assert!(model.sample_mood(&emotion, 1001));
+    }
+
+    #[test]
+    fn template_vars_describe_the_player() {
+        let mut model = PlayerModel::new("p1");
+        model.set_preference("combat", 0.7);
+        model.set_preference("puzzles", 2.0);
+        model.set_preference("crafting", 0.3);
+        model.nudge_preference("stealth", true, 0.5);
+        assert_eq!(model.preferences["stealth"], 0.75);
+        model.record_activity(PlayerActivity::CompletedObjective);
+        model.record_outcome("combat", 1500.0, 0.0);
+
+        let vars = model.template_vars();
+        assert_eq!(vars["playstyle"], "achiever");
+        assert_eq!(vars["preferences"], "puzzles, stealth, combat");
+        assert_eq!(vars["skill_combat"], "1468");
+        assert_eq!(PlayerModel::new("p2").template_vars()["playstyle"], "balanced");
+    }
+
+    #[test]
+    fn store_persists_models_per_player() {
+        let dir = std::env::temp_dir().join(format!("arcadia-player-model-{}", std::process::id()));
+        let mut store = PlayerModelStore::open(&dir).unwrap();
+        store.model("p/1").unwrap().record_outcome("combat", 1500.0, 1.0);
+        assert!(store.contains("p/1"));
+        store.save_all().unwrap();
+        assert!(dir.join("p_1.json").exists());
+
+        let reopened = PlayerModelStore::open(&dir).unwrap();
+        assert!(reopened.get("p/1").is_none());
+        assert_eq!(reopened.read("p/1").unwrap().unwrap().skill("combat").matches, 1);
+        assert!(reopened.read("p2").unwrap().is_none());
+
+        assert!(store.remove("p/1").unwrap());
+        assert!(!store.contains("p/1"));
+        assert!(!store.remove("p/1").unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let mut memory = PlayerModelStore::in_memory();
+        memory.model("p1").unwrap();
+        memory.save("p1").unwrap();
+        assert!(memory.contains("p1"));
+    }
+}
@@ -0,0 +1,731 @@
+// Prompt template registry
+//
+// Prompts live here instead of as string literals next to each call: a named template with a
+// version, typed variables and the text, where {{name}} is replaced by a variable and
+// {{#name}}...{{/name}} is kept only when the variable is set and not empty:
+//
+//   {{#persona}}You are {{persona}}.
+//   {{/persona}}Your goal in this exchange: {{goal}}
+//
+// Templates are checked when registered (every placeholder declared, every variable used, sections
+// balanced) and values when rendered: a missing required variable, an undeclared one or one of
+// the wrong type is an error rather than a prompt with "{{goal}}" left in it. Numbers can carry a
+// precision, lists are joined with ", ".
+//
+// A registry keeps every version of a template. The newest one is used unless another is pinned,
+// so a prompt change can be rolled back without a build. Each render is counted per template
+// version together with the prompt's estimated tokens (cost.rs), and `record_completion` adds the
+// answer's, so prompt changes show up in token spend.
+//
+// The engine's own prompts (barks, AI dialogue turns, sentiment, conversation summaries, query
+// expansion, memory consolidation) are the built-in templates; subsystems given a registry render
+// through it, so games can override them with a newer version, e.g. from a [[prompts]] TOML file.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Write as _};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::cost::estimate_tokens;
+
+pub const BARK_LINES: &str = "barks.generate";
+pub const DIALOGUE_REPLY: &str = "dialogue.ai_reply";
+pub const CONVERSATION_SUMMARY: &str = "dialogue.summary";
+pub const SENTIMENT: &str = "emotion.sentiment";
+pub const QUERY_EXPANSION: &str = "search.expand_query";
+pub const MEMORY_CONSOLIDATION: &str = "memory.consolidate";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VarType {
+    Text,
+    Integer,
+    Number,
+    Bool,
+    List,
+}
+
+impl fmt::Display for VarType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            VarType::Text => "text",
+            VarType::Integer => "integer",
+            VarType::Number => "number",
+            VarType::Bool => "bool",
+            VarType::List => "list",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromptVar {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: VarType,
+    #[serde(default = "default_required")]
+    pub required: bool,
+    // Decimal places for numbers
+    #[serde(default)]
+    pub precision: Option<usize>,
+    #[serde(default)]
+    pub description: String,
+}
+
+fn default_required() -> bool {
+    true
+}
+
+impl PromptVar {
+    pub fn new(name: &str, ty: VarType) -> Self {
+        PromptVar { name: name.to_string(), ty, required: true, precision: None, description: String::new() }
+    }
+
+    pub fn text(name: &str) -> Self {
+        PromptVar::new(name, VarType::Text)
+    }
+
+    pub fn integer(name: &str) -> Self {
+        PromptVar::new(name, VarType::Integer)
+    }
+
+    pub fn number(name: &str, precision: usize) -> Self {
+        PromptVar { precision: Some(precision), ..PromptVar::new(name, VarType::Number) }
+    }
+
+    pub fn list(name: &str) -> Self {
+        PromptVar::new(name, VarType::List)
+    }
+
+    pub fn optional(mut self) -> Self {
+        self.required = false;
+        self
+    }
+
+    pub fn describe(mut self, description: &str) -> Self {
+        self.description = description.to_string();
+        self
+    }
+
+    fn format(&self, value: &Value) -> Result<String, PromptError> {
+        let wrong = || PromptError::WrongType { variable: self.name.clone(), expected: self.ty };
+        match (self.ty, value) {
+            (VarType::Text, Value::String(text)) => Ok(text.clone()),
+            (VarType::Integer, Value::Number(n)) if n.is_i64() || n.is_u64() => Ok(n.to_string()),
+            (VarType::Number, Value::Number(n)) => {
+                let n = n.as_f64().ok_or_else(wrong)?;
+                Ok(match self.precision {
+                    Some(precision) => format!("{:.*}", precision, n),
+                    None => n.to_string(),
+                })
+            }
+            (VarType::Bool, Value::Bool(b)) => Ok(b.to_string()),
+            (VarType::List, Value::Array(items)) => Ok(items
+                .iter()
+                .map(|item| match item {
+                    Value::String(text) => text.clone(),
+                    other => other.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(", ")),
+            _ => Err(wrong()),
+        }
+    }
+}
+
+// Whether a {{#section}} is kept
+fn is_set(value: Option<&Value>) -> bool {
+    match value {
+        None | Some(Value::Null) | Some(Value::Bool(false)) => false,
+        Some(Value::String(text)) => !text.is_empty(),
+        Some(Value::Array(items)) => !items.is_empty(),
+        Some(_) => true,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    Var(String),
+    Section(String, Vec<Part>),
+}
+
+fn parse(text: &str) -> Result<Vec<Part>, PromptError> {
+    let mut stack: Vec<(String, Vec<Part>)> = vec![(String::new(), Vec::new())];
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let end = rest[start..].find("}}").ok_or_else(|| PromptError::Syntax("unclosed '{{'".to_string()))? + start;
+        if start > 0 {
+            stack.last_mut().expect("root frame").1.push(Part::Literal(rest[..start].to_string()));
+        }
+        let tag = rest[start + 2..end].trim();
+        if let Some(name) = tag.strip_prefix('#') {
+            stack.push((name.trim().to_string(), Vec::new()));
+        } else if let Some(name) = tag.strip_prefix('/') {
+            let (open, parts) = stack.pop().filter(|_| !stack.is_empty()).ok_or_else(|| {
+                PromptError::Syntax(format!("'{{{{/{}}}}}' without a matching section", name.trim()))
+            })?;
+            if open != name.trim() {
+                return Err(PromptError::Syntax(format!("section '{}' closed by '{}'", open, name.trim())));
+            }
+            stack.last_mut().expect("root frame").1.push(Part::Section(open, parts));
+        } else if tag.is_empty() {
+            return Err(PromptError::Syntax("empty placeholder".to_string()));
+        } else {
+            stack.last_mut().expect("root frame").1.push(Part::Var(tag.to_string()));
+        }
+        rest = &rest[end + 2..];
+    }
+    if !rest.is_empty() {
+        stack.last_mut().expect("root frame").1.push(Part::Literal(rest.to_string()));
+    }
+    if stack.len() > 1 {
+        return Err(PromptError::Syntax(format!("section '{}' is never closed", stack[stack.len() - 1].0)));
+    }
+    Ok(stack.pop().map(|(_, parts)| parts).unwrap_or_default())
+}
+
+fn collect_names(parts: &[Part], names: &mut Vec<String>) {
+    for part in parts {
+        match part {
+            Part::Literal(_) => {}
+            Part::Var(name) => names.push(name.clone()),
+            Part::Section(name, inner) => {
+                names.push(name.clone());
+                collect_names(inner, names);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    pub name: String,
+    #[serde(default = "default_version")]
+    pub version: u32,
+    pub text: String,
+    #[serde(default)]
+    pub vars: Vec<PromptVar>,
+    #[serde(default)]
+    pub description: String,
+}
+
+fn default_version() -> u32 {
+    1
+}
+
+impl PromptTemplate {
+    pub fn new(name: &str, version: u32, text: &str) -> Self {
+        PromptTemplate { name: name.to_string(), version, text: text.to_string(), vars: Vec::new(), description: String::new() }
+    }
+
+    pub fn var(mut self, var: PromptVar) -> Self {
+        self.vars.push(var);
+        self
+    }
+
+    pub fn describe(mut self, description: &str) -> Self {
+        self.description = description.to_string();
+        self
+    }
+
+    pub fn variable(&self, name: &str) -> Option<&PromptVar> {
+        self.vars.iter().find(|var| var.name == name)
+    }
+
+    // Syntax, and placeholders against declared variables
+    pub fn check(&self) -> Result<(), PromptError> {
+        let mut used = Vec::new();
+        collect_names(&parse(&self.text)?, &mut used);
+        if let Some(name) = used.iter().find(|name| self.variable(name).is_none()) {
+            return Err(PromptError::UndeclaredPlaceholder { template: self.name.clone(), variable: name.clone() });
+        }
+        if let Some(var) = self.vars.iter().find(|var| !used.contains(&var.name)) {
+            return Err(PromptError::UnusedVariable { template: self.name.clone(), variable: var.name.clone() });
+        }
+        Ok(())
+    }
+
+    pub fn render(&self, vars: &[(&str, Value)]) -> Result<String, PromptError> {
+        let values: HashMap<&str, &Value> = vars.iter().map(|(name, value)| (*name, value)).collect();
+        if let Some((name, _)) = vars.iter().find(|(name, _)| self.variable(name).is_none()) {
+            return Err(PromptError::UnknownVariable { template: self.name.clone(), variable: name.to_string() });
+        }
+        if let Some(var) = self.vars.iter().find(|var| var.required && !values.contains_key(var.name.as_str())) {
+            return Err(PromptError::MissingVariable { template: self.name.clone(), variable: var.name.clone() });
+        }
+        let mut formatted = HashMap::new();
+        for var in &self.vars {
+            if let Some(value) = values.get(var.name.as_str()).filter(|value| !value.is_null()) {
+                formatted.insert(var.name.as_str(), var.format(value)?);
+            }
+        }
+        let mut out = String::with_capacity(self.text.len());
+        render_parts(&parse(&self.text)?, &values, &formatted, &mut out);
+        Ok(out)
+    }
+}
+
+fn render_parts(parts: &[Part], values: &HashMap<&str, &Value>, formatted: &HashMap<&str, String>, out: &mut String) {
+    for part in parts {
+        match part {
+            Part::Literal(text) => out.push_str(text),
+            Part::Var(name) => out.push_str(formatted.get(name.as_str()).map_or("", String::as_str)),
+            Part::Section(name, inner) => {
+                if is_set(values.get(name.as_str()).copied()) {
+                    render_parts(inner, values, formatted, out);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PromptError {
+    UnknownTemplate(String),
+    UnknownVersion { template: String, version: u32 },
+    // A different text was registered under the same name and version
+    VersionConflict { template: String, version: u32 },
+    Syntax(String),
+    UndeclaredPlaceholder { template: String, variable: String },
+    UnusedVariable { template: String, variable: String },
+    MissingVariable { template: String, variable: String },
+    UnknownVariable { template: String, variable: String },
+    WrongType { variable: String, expected: VarType },
+    Load(String),
+}
+
+impl fmt::Display for PromptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PromptError::UnknownTemplate(name) => write!(f, "unknown prompt template '{}'", name),
+            PromptError::UnknownVersion { template, version } => {
+                write!(f, "prompt template '{}' has no version {}", template, version)
+            }
+            PromptError::VersionConflict { template, version } => {
+                write!(f, "prompt template '{}' version {} is already registered with a different text", template, version)
+            }
+            PromptError::Syntax(err) => write!(f, "prompt template syntax: {}", err),
+            PromptError::UndeclaredPlaceholder { template, variable } => {
+                write!(f, "prompt template '{}' uses undeclared variable '{}'", template, variable)
+            }
+            PromptError::UnusedVariable { template, variable } => {
+                write!(f, "prompt template '{}' declares '{}' but never uses it", template, variable)
+            }
+            PromptError::MissingVariable { template, variable } => {
+                write!(f, "prompt template '{}' needs variable '{}'", template, variable)
+            }
+            PromptError::UnknownVariable { template, variable } => {
+                write!(f, "prompt template '{}' has no variable '{}'", template, variable)
+            }
+            PromptError::WrongType { variable, expected } => write!(f, "prompt variable '{}' must be {}", variable, expected),
+            PromptError::Load(err) => write!(f, "invalid prompt file: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for PromptError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenderedPrompt {
+    pub name: String,
+    pub version: u32,
+    pub text: String,
+    pub tokens: u64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct TokenStats {
+    pub renders: u64,
+    pub prompt_tokens: u64,
+    pub min_prompt_tokens: u64,
+    pub max_prompt_tokens: u64,
+    pub completions: u64,
+    pub completion_tokens: u64,
+}
+
+impl TokenStats {
+    pub fn mean_prompt_tokens(&self) -> f64 {
+        if self.renders == 0 {
+            0.0
+        } else {
+            self.prompt_tokens as f64 / self.renders as f64
+        }
+    }
+
+    pub fn mean_completion_tokens(&self) -> f64 {
+        if self.completions == 0 {
+            0.0
+        } else {
+            self.completion_tokens as f64 / self.completions as f64
+        }
+    }
+}
+
+// Templates as loaded from a prompts TOML file: [[prompts]] tables
+#[derive(Debug, Default, Deserialize)]
+pub struct PromptSet {
+    #[serde(default)]
+    pub prompts: Vec<PromptTemplate>,
+}
+
+#[derive(Default)]
+pub struct PromptRegistry {
+    // name -> version -> template
+    templates: BTreeMap<String, BTreeMap<u32, PromptTemplate>>,
+    pinned: HashMap<String, u32>,
+    stats: Mutex<HashMap<(String, u32), TokenStats>>,
+}
+
+impl PromptRegistry {
+    pub fn new() -> Self {
+        PromptRegistry::default()
+    }
+
+    // Registry holding the engine's built-in prompts
+    pub fn with_builtin() -> Self {
+        let mut registry = PromptRegistry::new();
+        for template in builtin_templates() {
+            registry.register(template).expect("built-in prompt templates are valid");
+        }
+        registry
+    }
+
+    // Add a template version; registering an identical one again is a no-op
+    pub fn register(&mut self, template: PromptTemplate) -> Result<(), PromptError> {
+        template.check()?;
+        let versions = self.templates.entry(template.name.clone()).or_default();
+        if let Some(existing) = versions.get(&template.version) {
+            if *existing != template {
+                return Err(PromptError::VersionConflict { template: template.name, version: template.version });
+            }
+            return Ok(());
+        }
+        versions.insert(template.version, template);
+        Ok(())
+    }
+
+    // Register every template of a [[prompts]] TOML file; returns how many there were
+    pub fn load_toml(&mut self, contents: &str) -> Result<usize, PromptError> {
+        let set: PromptSet = toml::from_str(contents).map_err(|err| PromptError::Load(err.to_string()))?;
+        let count = set.prompts.len();
+        for template in set.prompts {
+            self.register(template)?;
+        }
+        Ok(count)
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.templates.keys().map(String::as_str).collect()
+    }
+
+    pub fn versions(&self, name: &str) -> Vec<u32> {
+        self.templates.get(name).map(|versions| versions.keys().copied().collect()).unwrap_or_default()
+    }
+
+    // The version renders use: the pinned one, else the newest
+    pub fn active_version(&self, name: &str) -> Option<u32> {
+        self.pinned.get(name).copied().or_else(|| self.templates.get(name)?.keys().next_back().copied())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PromptTemplate> {
+        self.version(name, self.active_version(name)?)
+    }
+
+    pub fn version(&self, name: &str, version: u32) -> Option<&PromptTemplate> {
+        self.templates.get(name)?.get(&version)
+    }
+
+    // Use `version` until unpinned, e.g. to roll back a prompt change
+    pub fn pin(&mut self, name: &str, version: u32) -> Result<(), PromptError> {
+        if self.version(name, version).is_none() {
+            return Err(PromptError::UnknownVersion { template: name.to_string(), version });
+        }
+        self.pinned.insert(name.to_string(), version);
+        Ok(())
+    }
+
+    pub fn unpin(&mut self, name: &str) -> bool {
+        self.pinned.remove(name).is_some()
+    }
+
+    pub fn render(&self, name: &str, vars: &[(&str, Value)]) -> Result<RenderedPrompt, PromptError> {
+        let template = self.get(name).ok_or_else(|| PromptError::UnknownTemplate(name.to_string()))?;
+        let text = template.render(vars)?;
+        let tokens = estimate_tokens(&text);
+        let mut stats = self.stats.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let entry = stats.entry((template.name.clone(), template.version)).or_default();
+        entry.min_prompt_tokens = if entry.renders == 0 { tokens } else { entry.min_prompt_tokens.min(tokens) };
+        entry.max_prompt_tokens = entry.max_prompt_tokens.max(tokens);
+        entry.renders += 1;
+        entry.prompt_tokens += tokens;
+        Ok(RenderedPrompt { name: template.name.clone(), version: template.version, text, tokens })
+    }
+
+    // Count the model's answer to a rendered prompt
+    pub fn record_completion(&self, prompt: &RenderedPrompt, completion: &str) {
+        let mut stats = self.stats.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let entry = stats.entry((prompt.name.clone(), prompt.version)).or_default();
+        entry.completions += 1;
+        entry.completion_tokens += estimate_tokens(completion);
+    }
+
+    // Statistics per version of one template
+    pub fn stats(&self, name: &str) -> Vec<(u32, TokenStats)> {
+        let stats = self.stats.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut found: Vec<(u32, TokenStats)> =
+            stats.iter().filter(|((template, _), _)| template == name).map(|((_, version), s)| (*version, *s)).collect();
+        found.sort_by_key(|(version, _)| *version);
+        found
+    }
+
+    // "prompt.<name>.v<version>.<field>" metrics, like CostTracker::metrics
+    pub fn metrics(&self) -> HashMap<String, f64> {
+        let stats = self.stats.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut metrics = HashMap::new();
+        for ((name, version), s) in stats.iter() {
+            let key = |field: &str| format!("prompt.{}.v{}.{}", name, version, field);
+            metrics.insert(key("renders"), s.renders as f64);
+            metrics.insert(key("prompt_tokens_mean"), s.mean_prompt_tokens());
+            metrics.insert(key("completion_tokens_mean"), s.mean_completion_tokens());
+        }
+        metrics
+    }
+
+    // Human-readable overview for tooling
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        for name in self.names() {
+            let active = self.active_version(name).unwrap_or_default();
+            let pinned = if self.pinned.contains_key(name) { " (pinned)" } else { "" };
+            let _ = writeln!(report, "{} v{}{}", name, active, pinned);
+            for (version, s) in self.stats(name) {
+                let _ = writeln!(
+                    report,
+                    "  v{}: {} renders, {:.0} prompt tokens avg ({}-{}), {:.0} completion tokens avg",
+                    version,
+                    s.renders,
+                    s.mean_prompt_tokens(),
+                    s.min_prompt_tokens,
+                    s.max_prompt_tokens,
+                    s.mean_completion_tokens()
+                );
+            }
+        }
+        report
+    }
+}
+
+// Render through `registry` when there is one, else with the built-in template
+pub fn render(registry: Option<&PromptRegistry>, name: &str, vars: &[(&str, Value)]) -> Result<String, PromptError> {
+    match registry {
+        Some(registry) => registry.render(name, vars).map(|prompt| prompt.text),
+        None => builtin(name).ok_or_else(|| PromptError::UnknownTemplate(name.to_string()))?.render(vars),
+    }
+}
+
+pub fn builtin(name: &str) -> Option<PromptTemplate> {
+    builtin_templates().into_iter().find(|template| template.name == name)
+}
+
+pub fn builtin_templates() -> Vec<PromptTemplate> {
+    vec![
+        PromptTemplate::new(
+            BARK_LINES,
+            1,
+            "Write {{count}} different short lines a background character might say to themselves or a passer-by.\n\
+             Setting: {{setting}}\nSituation: {{trigger}}\n\
+             Mood: tension {{tension}} (0-1), valence {{valence}} (-1..1), energy {{energy}} (0-1)\n\
+             At most {{max_words}} words each. One line per line, no numbering, no quotes, no speaker names.",
+        )
+        .describe("Ambient bark lines pregenerated into the bark collection")
+        .var(PromptVar::integer("count"))
+        .var(PromptVar::text("setting"))
+        .var(PromptVar::text("trigger"))
+        .var(PromptVar::number("tension", 1))
+        .var(PromptVar::number("valence", 1))
+        .var(PromptVar::number("energy", 1))
+        .var(PromptVar::integer("max_words")),
+        PromptTemplate::new(
+            DIALOGUE_REPLY,
+            1,
+            "{{#persona}}You are {{persona}}.\n{{/persona}}\
+             {{#scene}}Scene: {{scene}}\n{{/scene}}\
+             Your goal in this exchange: {{goal}}\n\
+             {{#forbidden}}Never mention: {{forbidden}}\n{{/forbidden}}\
+             Reply in character in at most {{max_words}} words.\n\n\
+             {{transcript}}player: {{player_input}}\n",
+        )
+        .describe("One NPC turn inside an AI dialogue node")
+        .var(PromptVar::text("persona").optional())
+        .var(PromptVar::text("scene").optional())
+        .var(PromptVar::text("goal"))
+        .var(PromptVar::list("forbidden").optional())
+        .var(PromptVar::integer("max_words"))
+        .var(PromptVar::text("transcript").describe("Earlier turns, one \"speaker: line\" per line"))
+        .var(PromptVar::text("player_input")),
+        PromptTemplate::new(
+            CONVERSATION_SUMMARY,
+            1,
+            "Summarize this conversation between {{npc}} (an NPC) and {{player}} (the player), then list \
+             the facts it established as subject/relation/object triples. Call the participants exactly \
+             \"{{npc}}\" and \"{{player}}\". Mark promises, deals and requests as such, with any deadline.\n\n\
+             {{transcript}}",
+        )
+        .describe("Summary and structured facts of a finished conversation")
+        .var(PromptVar::text("npc"))
+        .var(PromptVar::text("player"))
+        .var(PromptVar::text("transcript")),
+        PromptTemplate::new(
+            SENTIMENT,
+            1,
+            "Classify the player message below. Reply with JSON only: {\"valence\": -1..1, \"arousal\": 0..1, \
+             \"intents\": [\"insult\"|\"flattery\"|\"threat\"|\"question\"], \"confidence\": 0..1}\n\nMessage: {{message}}",
+        )
+        .describe("Sentiment and intent of a player message")
+        .var(PromptVar::text("message")),
+        PromptTemplate::new(
+            QUERY_EXPANSION,
+            1,
+            "Rewrite the following search query in {{count}} different ways, one per line, without numbering:\n{{query}}",
+        )
+        .describe("Paraphrases of a memory search query")
+        .var(PromptVar::integer("count"))
+        .var(PromptVar::text("query")),
+        PromptTemplate::new(
+            MEMORY_CONSOLIDATION,
+            1,
+            "These are memories of {{npc}}. Merge them into one memory of at most {{max_words}} words, written \
+             as {{npc}} would remember it. Keep names, places, promises and what the player did; drop \
+             repetition. Answer with the memory only.\n\n{{memories}}",
+        )
+        .describe("Several related NPC memories condensed into one when the NPC is over its memory budget")
+        .var(PromptVar::text("npc"))
+        .var(PromptVar::integer("max_words"))
+        .var(PromptVar::text("memories").describe("One \"- memory\" per line")),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn greeting(version: u32, text: &str) -> PromptTemplate {
+        PromptTemplate::new("npc.greet", version, text).var(PromptVar::text("name")).var(PromptVar::text("title").optional())
+    }
+
+    #[test]
+    fn sections_are_kept_only_when_their_variable_is_set() {
+        let template = PromptTemplate::new(DIALOGUE_REPLY, 1, "{{#persona}}You are {{persona}}.\n{{/persona}}Goal: {{goal}}")
+            .var(PromptVar::text("persona").optional())
+            .var(PromptVar::text("goal"));
+        assert_eq!(template.render(&[("goal", json!("sell"))]).unwrap(), "Goal: sell");
+        assert_eq!(template.render(&[("persona", json!("")), ("goal", json!("sell"))]).unwrap(), "Goal: sell");
+        let rendered = template.render(&[("persona", json!("Zara")), ("goal", json!("sell"))]).unwrap();
+        assert_eq!(rendered, "You are Zara.\nGoal: sell");
+        assert_eq!(template.render(&[("persona", Value::Null), ("goal", json!("sell"))]).unwrap(), "Goal: sell");
+    }
+
+    #[test]
+    fn values_are_formatted_by_type() {
+        let template = PromptTemplate::new("mood", 1, "{{tension}} {{count}} {{calm}} {{tags}}")
+            .var(PromptVar::number("tension", 2))
+            .var(PromptVar::integer("count"))
+            .var(PromptVar::new("calm", VarType::Bool))
+            .var(PromptVar::list("tags"));
+        let vars = [("tension", json!(0.456)), ("count", json!(3)), ("calm", json!(false)), ("tags", json!(["ember", 7]))];
+        assert_eq!(template.render(&vars).unwrap(), "0.46 3 false ember, 7");
+
+        let err = template.render(&[("tension", json!(0.5)), ("count", json!(1.5)), ("calm", json!(true)), ("tags", json!([]))]);
+        assert_eq!(err, Err(PromptError::WrongType { variable: "count".to_string(), expected: VarType::Integer }));
+    }
+
+    #[test]
+    fn templates_are_checked_when_registered() {
+        let unclosed = greeting(1, "{{#title}}{{title}} {{name}}");
+        assert!(matches!(unclosed.check(), Err(PromptError::Syntax(_))));
+        assert!(matches!(greeting(1, "{{#title}}{{title}}{{/name}} {{name}}").check(), Err(PromptError::Syntax(_))));
+        assert!(matches!(greeting(1, "Hi {{ }}").check(), Err(PromptError::Syntax(_))));
+        assert_eq!(
+            greeting(1, "Hi {{name}}, {{title}} of {{town}}").check(),
+            Err(PromptError::UndeclaredPlaceholder { template: "npc.greet".to_string(), variable: "town".to_string() })
+        );
+        assert_eq!(
+            greeting(1, "Hi {{name}}").check(),
+            Err(PromptError::UnusedVariable { template: "npc.greet".to_string(), variable: "title".to_string() })
+        );
+    }
+
+    #[test]
+    fn renders_reject_missing_and_unknown_variables() {
+        let template = greeting(1, "Hi {{#title}}{{title}} {{/title}}{{name}}");
+        let missing = template.render(&[("title", json!("Lady"))]);
+        assert!(matches!(missing, Err(PromptError::MissingVariable { ref variable, .. }) if variable == "name"));
+        let unknown = template.render(&[("name", json!("Zara")), ("mood", json!("calm"))]);
+        assert!(matches!(unknown, Err(PromptError::UnknownVariable { ref variable, .. }) if variable == "mood"));
+        assert_eq!(template.render(&[("name", json!("Zara")), ("title", json!("Lady"))]).unwrap(), "Hi Lady Zara");
+    }
+
+    #[test]
+    fn newest_version_is_used_unless_pinned() {
+        let mut registry = PromptRegistry::new();
+        registry.register(greeting(1, "Hi {{name}}{{title}}")).unwrap();
+        let loaded = registry
+            .load_toml(
+                r#"
+[[prompts]]
+name = "npc.greet"
+version = 2
+text = "Well met, {{#title}}{{title}} {{/title}}{{name}}"
+vars = [{ name = "name", type = "text" }, { name = "title", type = "text", required = false }]
+"#,
+            )
+            .unwrap();
+        assert_eq!(loaded, 1);
+        assert_eq!(registry.versions("npc.greet"), vec![1, 2]);
+        assert_eq!(registry.render("npc.greet", &[("name", json!("Zara"))]).unwrap().text, "Well met, Zara");
+
+        registry.pin("npc.greet", 1).unwrap();
+        assert_eq!(registry.render("npc.greet", &[("name", json!("Zara"))]).unwrap().version, 1);
+        assert!(registry.unpin("npc.greet"));
+        assert_eq!(registry.active_version("npc.greet"), Some(2));
+
+        assert!(matches!(registry.pin("npc.greet", 9), Err(PromptError::UnknownVersion { version: 9, .. })));
+        assert!(registry.register(greeting(1, "Hi {{name}}{{title}}")).is_ok());
+        assert!(matches!(registry.register(greeting(1, "Hey {{name}}{{title}}")), Err(PromptError::VersionConflict { .. })));
+        assert!(matches!(registry.load_toml("[[prompts]]\nname = 3"), Err(PromptError::Load(_))));
+        assert!(matches!(registry.render("npc.wave", &[]), Err(PromptError::UnknownTemplate(_))));
+    }
+
+    #[test]
+    fn renders_and_completions_are_counted_per_version() {
+        let mut registry = PromptRegistry::new();
+        registry.register(greeting(1, "Hi {{name}}{{title}}")).unwrap();
+        let short = registry.render("npc.greet", &[("name", json!("Al"))]).unwrap();
+        let long = registry.render("npc.greet", &[("name", json!("Bartholomew the Unready"))]).unwrap();
+        registry.record_completion(&short, "Hello there, traveller!");
+
+        let (version, stats) = registry.stats("npc.greet")[0];
+        assert_eq!(version, 1);
+        assert_eq!((stats.renders, stats.completions), (2, 1));
+        assert_eq!((stats.min_prompt_tokens, stats.max_prompt_tokens), (short.tokens, long.tokens));
+        assert_eq!(stats.completion_tokens, estimate_tokens("Hello there, traveller!"));
+        assert_eq!(registry.metrics()["prompt.npc.greet.v1.renders"], 2.0);
+        assert!(registry.report().starts_with("npc.greet v1\n  v1: 2 renders"));
+    }
+
+    #[test]
+    fn builtin_prompts_are_valid_and_used_without_a_registry() {
+        let registry = PromptRegistry::with_builtin();
+        assert_eq!(registry.names().len(), builtin_templates().len());
+        let vars = [("message", json!("you fool"))];
+        let direct = render(None, SENTIMENT, &vars).unwrap();
+        assert!(direct.ends_with("Message: you fool"));
+        assert_eq!(render(Some(&registry), SENTIMENT, &vars).unwrap(), direct);
+        assert!(matches!(render(None, "npc.wave", &[]), Err(PromptError::UnknownTemplate(_))));
+    }
+}
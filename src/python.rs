@@ -0,0 +1,423 @@
+// Python bindings
+//
+// With the `python` feature the engine builds as a Python extension module (`import arcadia`)
+// for tooling and notebooks. It exposes the real engine data structures rather than re-parsing
+// their files: vector index snapshots, AgentDB files and player models, log files and session
+// summaries as telemetry, and the economy simulation as a harness to run strategies against.
+//
+// Structured values (payloads, records, summaries) are handed over as plain dicts and lists, so
+// they drop straight into pandas. Engine errors are raised as arcadia.ArcadiaError.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use serde_json::{json, Value};
+
+use crate::agentdb::AgentDb;
+use crate::economy::{Economy, TraderStrategy};
+use crate::namespace::Namespace;
+use crate::player_model::{PlayerModel, PlayerModelStore};
+use crate::vector_index::{VectorIndex, VectorIndexConfig, VectorPoint};
+use crate::versioning::MigrationRegistry;
+
+create_exception!(arcadia, ArcadiaError, PyException);
+
+fn engine_err(err: impl std::fmt::Display) -> PyErr {
+    ArcadiaError::new_err(err.to_string())
+}
+
+// JSON is the lingua franca: Python's json module does the conversion both ways
+fn to_py(py: Python<'_>, value: &Value) -> PyResult<PyObject> {
+    Ok(py.import_bound("json")?.call_method1("loads", (value.to_string(),))?.unbind())
+}
+
+fn from_py(obj: &Bound<'_, PyAny>) -> PyResult<Value> {
+    let text: String = obj.py().import_bound("json")?.call_method1("dumps", (obj,))?.extract()?;
+    serde_json::from_str(&text).map_err(engine_err)
+}
+
+fn namespace(name: &str) -> PyResult<Namespace> {
+    Namespace::new(name).map_err(engine_err)
+}
+
+#[pyclass(name = "VectorIndex")]
+pub struct PyVectorIndex {
+    index: VectorIndex,
+}
+
+#[pymethods]
+impl PyVectorIndex {
+    #[new]
+    #[pyo3(signature = (url = "", api_key = ""))]
+    fn new(url: &str, api_key: &str) -> Self {
+        let config = VectorIndexConfig {
+            url: url.to_string(),
+            api_key: api_key.to_string(),
+            default_ttl_secs: None,
+            collection_ttl_secs: Default::default(),
+        };
+        PyVectorIndex { index: VectorIndex::new(config) }
+    }
+
+    // Open a snapshot written by the engine, upgrading old versions
+    #[staticmethod]
+    fn load_snapshot(path: PathBuf) -> PyResult<Self> {
+        let mut index = PyVectorIndex::new("", "");
+        index
+            .index
+            .restore_snapshot(&path, &MigrationRegistry::with_builtin(), None)
+            .map_err(engine_err)?;
+        Ok(index)
+    }
+
+    fn save_snapshot(&self, path: PathBuf) -> PyResult<()> {
+        self.index.save_snapshot(&path, None).map_err(engine_err)
+    }
+
+    fn collections(&self) -> Vec<String> {
+        self.index.collection_names().into_iter().map(str::to_string).collect()
+    }
+
+    fn create_collection(&mut self, name: &str, dimension: usize, model: &str) -> PyResult<()> {
+        self.index.create_collection(name, dimension, model).map_err(engine_err)
+    }
+
+    #[pyo3(signature = (collection, id, vector, payload = None))]
+    fn upsert(&mut self, collection: &str, id: &str, vector: Vec<f32>, payload: Option<&Bound<'_, PyDict>>) -> PyResult<()> {
+        let mut point = VectorPoint::new(id, vector);
+        if let Some(payload) = payload {
+            point.payload = serde_json::from_value(from_py(payload.as_any())?).map_err(engine_err)?;
+        }
+        self.index.upsert(collection, point).map_err(engine_err)
+    }
+
+    // [{"id", "score", "payload"}], best first
+    #[pyo3(signature = (collection, vector, limit = 10))]
+    fn search(&self, py: Python<'_>, collection: &str, vector: Vec<f32>, limit: usize) -> PyResult<PyObject> {
+        let hits = self.index.search(collection, &vector, limit).map_err(engine_err)?;
+        let hits: Vec<Value> = hits
+            .into_iter()
+            .map(|hit| json!({ "id": hit.id, "score": hit.score, "payload": hit.payload }))
+            .collect();
+        to_py(py, &Value::from(hits))
+    }
+
+    // Every point as {"id", "vector", "payload", "version"}, for dataframes
+    fn points(&self, py: Python<'_>, collection: &str) -> PyResult<PyObject> {
+        let collection = self.index.collection(collection).map_err(engine_err)?;
+        let points = serde_json::to_value(collection.points.values().collect::<Vec<_>>()).map_err(engine_err)?;
+        to_py(py, &points)
+    }
+
+    fn __len__(&self) -> usize {
+        self.index
+            .collection_names()
+            .into_iter()
+            .filter_map(|name| self.index.len(name).ok())
+            .sum()
+    }
+}
+
+#[pyclass(name = "AgentDb")]
+pub struct PyAgentDb {
+    db: AgentDb,
+}
+
+#[pymethods]
+impl PyAgentDb {
+    #[staticmethod]
+    fn load(path: PathBuf) -> PyResult<Self> {
+        let db = AgentDb::load_migrating(&path, &MigrationRegistry::with_builtin(), None).map_err(engine_err)?;
+        Ok(PyAgentDb { db })
+    }
+
+    fn tables(&self, namespace_name: &str) -> PyResult<Vec<String>> {
+        Ok(self.db.tables(&namespace(namespace_name)?).into_iter().map(str::to_string).collect())
+    }
+
+    fn get(&self, py: Python<'_>, namespace_name: &str, table: &str, key: &str) -> PyResult<Option<PyObject>> {
+        self.db
+            .get(&namespace(namespace_name)?, table, key)
+            .map(|value| to_py(py, value))
+            .transpose()
+    }
+
+    // {key: record} for a table, optionally only keys starting with `prefix`
+    #[pyo3(signature = (namespace_name, table, prefix = None))]
+    fn scan(&self, py: Python<'_>, namespace_name: &str, table: &str, prefix: Option<&str>) -> PyResult<PyObject> {
+        let namespace = namespace(namespace_name)?;
+        let records: serde_json::Map<String, Value> = match prefix {
+            Some(prefix) => self.db.scan_prefix(&namespace, table, prefix).map(|(k, v)| (k.clone(), v.clone())).collect(),
+            None => self.db.scan(&namespace, table).map(|(k, v)| (k.clone(), v.clone())).collect(),
+        };
+        to_py(py, &Value::Object(records))
+    }
+
+    // [(namespace, table, record)] for everything stored about a player
+    fn player_records(&self, py: Python<'_>, player_id: &str) -> PyResult<Vec<(String, String, PyObject)>> {
+        self.db
+            .player_records(player_id)
+            .into_iter()
+            .map(|(namespace, table, value)| Ok((namespace.to_string(), table.to_string(), to_py(py, value)?)))
+            .collect()
+    }
+}
+
+#[pyclass(name = "PlayerModel")]
+pub struct PyPlayerModel {
+    model: PlayerModel,
+}
+
+#[pymethods]
+impl PyPlayerModel {
+    // Read one player's model from a player model directory
+    #[staticmethod]
+    fn load(dir: PathBuf, player_id: &str) -> PyResult<Self> {
+        if !dir.is_dir() {
+            return Err(engine_err(format!("{} is not a directory", dir.display())));
+        }
+        let store = PlayerModelStore::open(&dir).map_err(engine_err)?;
+        let model = store
+            .read(player_id)
+            .map_err(engine_err)?
+            .ok_or_else(|| engine_err(format!("no model for player '{}'", player_id)))?;
+        Ok(PyPlayerModel { model })
+    }
+
+    #[getter]
+    fn player_id(&self) -> &str {
+        &self.model.player_id
+    }
+
+    // (rating, deviation, matches)
+    fn skill(&self, activity: &str) -> (f64, f64, u32) {
+        let skill = self.model.skill(activity);
+        (skill.rating, skill.deviation, skill.matches)
+    }
+
+    fn recommended_challenge(&self, activity: &str, success_rate: f64) -> f64 {
+        self.model.recommended_challenge(activity, success_rate)
+    }
+
+    // (tension, valence, energy) averaged over samples since `since` (unix seconds)
+    #[pyo3(signature = (since = 0))]
+    fn average_mood(&self, since: u64) -> Option<(f32, f32, f32)> {
+        self.model.average_mood(since).map(|m| (m.tension, m.valence, m.energy))
+    }
+
+    fn to_dict(&self, py: Python<'_>) -> PyResult<PyObject> {
+        to_py(py, &serde_json::to_value(&self.model).map_err(engine_err)?)
+    }
+}
+
+// Simulation harness over the economy: load a saved economy, swap trader strategies, run it
+#[pyclass(name = "EconomySim")]
+pub struct PyEconomySim {
+    economy: Economy,
+}
+
+#[pymethods]
+impl PyEconomySim {
+    #[staticmethod]
+    fn from_json(text: &str) -> PyResult<Self> {
+        Ok(PyEconomySim { economy: serde_json::from_str(text).map_err(engine_err)? })
+    }
+
+    fn to_json(&self) -> PyResult<String> {
+        serde_json::to_string(&self.economy).map_err(engine_err)
+    }
+
+    fn trader_ids(&self) -> Vec<String> {
+        self.economy.trader_ids().into_iter().map(str::to_string).collect()
+    }
+
+    fn set_trader_strategy(&mut self, trader_id: &str, genes: Vec<f64>) -> PyResult<()> {
+        self.economy
+            .set_trader_strategy(trader_id, TraderStrategy::from_genes(&genes))
+            .map_err(engine_err)
+    }
+
+    fn trader_fitness(&self, trader_id: &str) -> Option<f64> {
+        self.economy.trader_fitness(trader_id)
+    }
+
+    fn reset_fitness(&mut self) {
+        self.economy.reset_fitness();
+    }
+
+    fn tick(&mut self, days: f64) {
+        self.economy.tick(days);
+    }
+
+    // Run `steps` ticks of `days` each; returns {"region/good": [price per step]}
+    fn run(&mut self, days: f64, steps: usize) -> BTreeMap<String, Vec<f64>> {
+        price_series(&mut self.economy, days, steps)
+    }
+
+    // (sources, sinks, net flow) of money so far
+    fn ledger(&self) -> (f64, f64, f64) {
+        let ledger = self.economy.ledger();
+        (ledger.total_sources(), ledger.total_sinks(), ledger.net_flow())
+    }
+}
+
+fn price_series(economy: &mut Economy, days: f64, steps: usize) -> BTreeMap<String, Vec<f64>> {
+    let mut series: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+    for _ in 0..steps {
+        economy.tick(days);
+        for region in economy.regions() {
+            for (good, market) in &region.markets {
+                series.entry(format!("{}/{}", region.name, good)).or_default().push(market.price);
+            }
+        }
+    }
+    series
+}
+
+// Log records from an engine JSON log file and its rotations (path.N .. path.1, then path),
+// oldest first; optionally only at or above `min_level` and under `target_prefix`
+#[pyfunction]
+#[pyo3(signature = (path, min_level = None, target_prefix = None))]
+fn read_logs(py: Python<'_>, path: PathBuf, min_level: Option<&str>, target_prefix: Option<&str>) -> PyResult<PyObject> {
+    to_py(py, &Value::from(log_records(path, min_level, target_prefix)?))
+}
+
+fn log_records(path: PathBuf, min_level: Option<&str>, target_prefix: Option<&str>) -> std::io::Result<Vec<Value>> {
+    let rank = |level: &str| ["trace", "debug", "info", "warn", "error"].iter().position(|l| l.eq_ignore_ascii_case(level));
+    let min_rank = min_level.and_then(rank).unwrap_or(0);
+
+    let mut files: Vec<PathBuf> = (1..)
+        .map(|i| PathBuf::from(format!("{}.{}", path.display(), i)))
+        .take_while(|p| p.exists())
+        .collect();
+    files.reverse();
+    files.push(path);
+
+    let mut records = Vec::new();
+    for file in files.iter().filter(|f| f.exists()) {
+        for line in fs::read_to_string(file)?.lines() {
+            // Plain-text lines and partial writes are skipped
+            let Ok(record) = serde_json::from_str::<Value>(line) else { continue };
+            let level = record.get("level").and_then(Value::as_str).unwrap_or("");
+            let target = record.get("target").and_then(Value::as_str).unwrap_or("");
+            if rank(level).unwrap_or(0) >= min_rank && target_prefix.is_none_or(|p| target.starts_with(p)) {
+                records.push(record);
+            }
+        }
+    }
+    Ok(records)
+}
+
+fn read_summary(path: &Path) -> PyResult<Value> {
+    serde_json::from_str(&fs::read_to_string(path)?).map_err(engine_err)
+}
+
+// Session summaries as columns: session_id, player_id, duration_secs, dropped_events and one
+// column per telemetry key (missing values are None)
+#[pyfunction]
+fn telemetry_table(py: Python<'_>, paths: Vec<PathBuf>) -> PyResult<PyObject> {
+    let summaries = paths.iter().map(|p| read_summary(p.as_path())).collect::<PyResult<Vec<_>>>()?;
+    to_py(py, &serde_json::to_value(telemetry_columns(&summaries)).map_err(engine_err)?)
+}
+
+fn telemetry_columns(summaries: &[Value]) -> BTreeMap<String, Vec<Value>> {
+    let mut keys: Vec<String> = summaries
+        .iter()
+        .filter_map(|s| s.get("telemetry").and_then(Value::as_object))
+        .flat_map(|t| t.keys().cloned())
+        .collect();
+    keys.sort();
+    keys.dedup();
+
+    let mut table: BTreeMap<String, Vec<Value>> = BTreeMap::new();
+    for summary in summaries {
+        let field = |name: &str| summary.get(name).cloned().unwrap_or(Value::Null);
+        let duration = match (summary.get("started_at").and_then(Value::as_u64), summary.get("ended_at").and_then(Value::as_u64)) {
+            (Some(start), Some(end)) => Value::from(end.saturating_sub(start)),
+            _ => Value::Null,
+        };
+        table.entry("session_id".to_string()).or_default().push(field("session_id"));
+        table.entry("player_id".to_string()).or_default().push(field("player_id"));
+        table.entry("duration_secs".to_string()).or_default().push(duration);
+        table.entry("dropped_events".to_string()).or_default().push(field("dropped_events"));
+        for key in &keys {
+            let value = summary.get("telemetry").and_then(|t| t.get(key)).cloned().unwrap_or(Value::Null);
+            table.entry(key.clone()).or_default().push(value);
+        }
+    }
+    table
+}
+
+#[pymodule]
+fn arcadia(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("ArcadiaError", m.py().get_type_bound::<ArcadiaError>())?;
+    m.add_class::<PyVectorIndex>()?;
+    m.add_class::<PyAgentDb>()?;
+    m.add_class::<PyPlayerModel>()?;
+    m.add_class::<PyEconomySim>()?;
+    m.add_function(wrap_pyfunction!(read_logs, m)?)?;
+    m.add_function(wrap_pyfunction!(telemetry_table, m)?)?;
+    Ok(())
+}
+
+// The helpers behind the bindings are plain Rust, so these run without a Python interpreter
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::economy::{Good, Market};
+
+    #[test]
+    fn logs_are_read_oldest_rotation_first_and_filtered() {
+        let dir = std::env::temp_dir().join(format!("arcadia-python-logs-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("engine.log");
+        let record = |level: &str, target: &str, msg: &str| json!({ "level": level, "target": target, "msg": msg }).to_string();
+        fs::write(dir.join("engine.log.1"), record("WARN", "arcadia::npc", "old") + "\nnot json\n").unwrap();
+        let current = [
+            record("INFO", "arcadia::npc", "chatty"),
+            record("ERROR", "arcadia::npc", "new"),
+            record("ERROR", "arcadia::net", "elsewhere"),
+        ];
+        fs::write(&path, current.join("\n")).unwrap();
+
+        let msgs = |records: Vec<Value>| records.iter().map(|r| r["msg"].as_str().unwrap().to_string()).collect::<Vec<_>>();
+        assert_eq!(msgs(log_records(path.clone(), None, None).unwrap()), vec!["old", "chatty", "new", "elsewhere"]);
+        assert_eq!(msgs(log_records(path.clone(), Some("warn"), Some("arcadia::npc")).unwrap()), vec!["old", "new"]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn telemetry_columns_line_up_across_sessions() {
+        let summaries = [
+            json!({ "session_id": "a", "player_id": "p1", "started_at": 100, "ended_at": 160, "dropped_events": 0,
+                    "telemetry": { "deaths": 3 } }),
+            json!({ "session_id": "b", "player_id": "p2", "started_at": 200, "telemetry": { "quests": 1 } }),
+        ];
+        let table = telemetry_columns(&summaries);
+        assert_eq!(
+            table.keys().map(String::as_str).collect::<Vec<_>>(),
+            vec!["deaths", "dropped_events", "duration_secs", "player_id", "quests", "session_id"]
+        );
+        assert_eq!(table["duration_secs"], vec![json!(60), Value::Null]);
+        assert_eq!(table["deaths"], vec![json!(3), Value::Null]);
+        assert_eq!(table["quests"], vec![Value::Null, json!(1)]);
+    }
+
+    #[test]
+    fn price_series_has_one_entry_per_step_and_market() {
+        let mut economy = Economy::new();
+        economy.add_good(Good { id: "iron".to_string(), base_price: 10.0, elasticity: 1.0 });
+        economy.add_region("hollow");
+        economy.set_market("hollow", "iron", Market::new(50.0, 100.0)).unwrap();
+        let series = price_series(&mut economy, 1.0, 3);
+        assert_eq!(series.keys().collect::<Vec<_>>(), vec!["hollow/iron"]);
+        assert_eq!(series["hollow/iron"].len(), 3);
+        // Short supply keeps iron above its base price
+        assert!(series["hollow/iron"].iter().all(|price| *price > 10.0));
+    }
+}
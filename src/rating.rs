@@ -0,0 +1,510 @@
+// Content rating and parental controls
+//
+// Content items (dialogue lines, quests, procedurally generated output) carry descriptors such
+// as violence or language, each with an intensity. The descriptors imply an age rating. Every
+// player has a rating profile: a rating ceiling plus optional per-descriptor limits, set by the
+// player or, for parental controls, by a guardian. Dialogue, quest and procgen systems ask the
+// enforcer before presenting content; authored content is tagged by designers, generated text is
+// tagged by a keyword lexicon when it has no tags of its own.
+//
+// Every decision is written to an audit log so compliance reports can show what was checked,
+// what was withheld from whom, and why. Profiles and audit entries name the player, so both are
+// exported and erased with the rest of a player's data (security/privacy.rs).
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::vector_index::unix_now;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Descriptor {
+    Violence,
+    Blood,
+    Language,
+    Sexual,
+    Drugs,
+    Gambling,
+    Horror,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Intensity {
+    Mild,
+    Moderate,
+    Strong,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rating {
+    Everyone,
+    Everyone10,
+    Teen,
+    Mature,
+    Adult,
+}
+
+impl Rating {
+    // Lowest rating that permits `descriptor` at `intensity`
+    pub fn required_for(descriptor: Descriptor, intensity: Intensity) -> Rating {
+        use Descriptor::*;
+        use Intensity::*;
+        match (descriptor, intensity) {
+            (Violence | Horror, Mild) => Rating::Everyone,
+            (Violence | Horror | Language, Moderate) | (Language | Blood, Mild) => Rating::Everyone10,
+            (Violence | Horror, Strong) | (Blood, Moderate) | (Sexual | Drugs | Gambling, Mild) => Rating::Teen,
+            (Language | Blood, Strong) | (Sexual | Drugs | Gambling, Moderate) | (Drugs | Gambling, Strong) => {
+                Rating::Mature
+            }
+            (Sexual, Strong) => Rating::Adult,
+        }
+    }
+}
+
+impl fmt::Display for Rating {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Rating::Everyone => "E",
+            Rating::Everyone10 => "E10+",
+            Rating::Teen => "T",
+            Rating::Mature => "M",
+            Rating::Adult => "AO",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Descriptors attached to a piece of content
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ContentTags {
+    pub descriptors: BTreeMap<Descriptor, Intensity>,
+}
+
+impl ContentTags {
+    pub fn new() -> Self {
+        ContentTags::default()
+    }
+
+    pub fn with(mut self, descriptor: Descriptor, intensity: Intensity) -> Self {
+        self.add(descriptor, intensity);
+        self
+    }
+
+    // Keeps the stronger intensity when the descriptor is already present
+    pub fn add(&mut self, descriptor: Descriptor, intensity: Intensity) {
+        let entry = self.descriptors.entry(descriptor).or_insert(intensity);
+        *entry = (*entry).max(intensity);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.descriptors.is_empty()
+    }
+
+    pub fn rating(&self) -> Rating {
+        self.descriptors
+            .iter()
+            .map(|(d, i)| Rating::required_for(*d, *i))
+            .max()
+            .unwrap_or(Rating::Everyone)
+    }
+}
+
+// Tags generated text that arrives without descriptors
+pub trait ContentTagger {
+    fn tag(&self, text: &str) -> ContentTags;
+}
+
+// Keyword lists per descriptor; the number of hits decides the intensity
+#[derive(Debug, Clone, Default)]
+pub struct LexiconTagger {
+    words: Vec<(Descriptor, String)>,
+}
+
+impl LexiconTagger {
+    pub fn new() -> Self {
+        LexiconTagger::default()
+    }
+
+    // A small built-in lexicon, meant to be extended per game
+    pub fn with_defaults() -> Self {
+        let mut tagger = LexiconTagger::new();
+        tagger.add(Descriptor::Violence, &["kill", "stab", "slaughter", "murder", "behead", "shoot"]);
+        tagger.add(Descriptor::Blood, &["blood", "bleeding", "gore", "entrails"]);
+        tagger.add(Descriptor::Language, &["damn", "hell", "bastard", "shit", "fuck"]);
+        tagger.add(Descriptor::Drugs, &["drunk", "opium", "narcotic", "overdose"]);
+        tagger.add(Descriptor::Gambling, &["wager", "bet", "casino", "dice game"]);
+        tagger.add(Descriptor::Horror, &["corpse", "scream", "rotting", "undead"]);
+        tagger
+    }
+
+    pub fn add(&mut self, descriptor: Descriptor, words: &[&str]) {
+        self.words.extend(words.iter().map(|w| (descriptor, w.to_lowercase())));
+    }
+}
+
+impl ContentTagger for LexiconTagger {
+    fn tag(&self, text: &str) -> ContentTags {
+        // Whole words only, so "shell" does not count as "hell"
+        let split = |text: &str| -> Vec<String> {
+            text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).map(str::to_lowercase).collect()
+        };
+        let words = split(text);
+        let mut hits: BTreeMap<Descriptor, usize> = BTreeMap::new();
+        for (descriptor, entry) in &self.words {
+            // Entries may be phrases; every position where all their words follow in order is a hit
+            let phrase = split(entry);
+            let count = match phrase.len() {
+                0 => 0,
+                len => words.windows(len).filter(|window| *window == phrase.as_slice()).count(),
+            };
+            if count > 0 {
+                *hits.entry(*descriptor).or_default() += count;
+            }
+        }
+        let mut tags = ContentTags::new();
+        for (descriptor, count) in hits {
+            let intensity = match count {
+                1 => Intensity::Mild,
+                2..=3 => Intensity::Moderate,
+                _ => Intensity::Strong,
+            };
+            tags.add(descriptor, intensity);
+        }
+        tags
+    }
+}
+
+// What a player may see
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RatingProfile {
+    pub ceiling: Rating,
+    // Stricter limits for single descriptors, e.g. no blood at all even under a Teen ceiling
+    #[serde(default)]
+    pub limits: BTreeMap<Descriptor, Option<Intensity>>,
+    // Set by a guardian; the player cannot change it
+    #[serde(default)]
+    pub parental: bool,
+}
+
+impl RatingProfile {
+    pub fn new(ceiling: Rating) -> Self {
+        RatingProfile { ceiling, limits: BTreeMap::new(), parental: false }
+    }
+
+    // Allow `descriptor` up to `max`; None forbids it entirely
+    pub fn limit(mut self, descriptor: Descriptor, max: Option<Intensity>) -> Self {
+        self.limits.insert(descriptor, max);
+        self
+    }
+
+    pub fn parental(mut self) -> Self {
+        self.parental = true;
+        self
+    }
+
+    // Reasons the content is not allowed; empty when it is
+    pub fn violations(&self, tags: &ContentTags) -> Vec<String> {
+        let mut reasons = Vec::new();
+        let rating = tags.rating();
+        if rating > self.ceiling {
+            reasons.push(format!("rated {} above ceiling {}", rating, self.ceiling));
+        }
+        for (descriptor, intensity) in &tags.descriptors {
+            match self.limits.get(descriptor) {
+                Some(None) => reasons.push(format!("{:?} is not allowed", descriptor)),
+                Some(Some(max)) if intensity > max => {
+                    reasons.push(format!("{:?} {:?} exceeds limit {:?}", descriptor, intensity, max))
+                }
+                _ => {}
+            }
+        }
+        reasons
+    }
+}
+
+// Where content is about to be shown
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Surface {
+    Dialogue,
+    Quest,
+    Procgen,
+    Chat,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RatingError {
+    // A parental profile can only be changed with the guardian's consent
+    ProfileLocked(String),
+}
+
+impl fmt::Display for RatingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RatingError::ProfileLocked(player) => write!(f, "rating profile of '{}' is locked by parental controls", player),
+        }
+    }
+}
+
+impl std::error::Error for RatingError {}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AuditEntry {
+    pub at: u64,
+    pub player: String,
+    pub surface: Surface,
+    pub content: String,
+    pub rating: Rating,
+    pub allowed: bool,
+    pub reasons: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct AuditReport {
+    pub checks: usize,
+    pub denied: usize,
+    pub denied_by_surface: BTreeMap<Surface, usize>,
+    pub denied_by_rating: BTreeMap<Rating, usize>,
+    // Players who had content withheld
+    pub players_affected: usize,
+    // Entries for denied content, oldest first
+    pub denials: Vec<AuditEntry>,
+}
+
+impl AuditReport {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+pub struct ContentRating {
+    default_profile: RatingProfile,
+    profiles: HashMap<String, RatingProfile>,
+    tagger: Box<dyn ContentTagger + Send + Sync>,
+    audit: VecDeque<AuditEntry>,
+    audit_capacity: usize,
+}
+
+impl ContentRating {
+    // `default_profile` applies to players without a profile of their own
+    pub fn new(default_profile: RatingProfile, audit_capacity: usize) -> Self {
+        ContentRating {
+            default_profile,
+            profiles: HashMap::new(),
+            tagger: Box::new(LexiconTagger::with_defaults()),
+            audit: VecDeque::new(),
+            audit_capacity,
+        }
+    }
+
+    pub fn with_tagger(mut self, tagger: Box<dyn ContentTagger + Send + Sync>) -> Self {
+        self.tagger = tagger;
+        self
+    }
+
+    pub fn profile(&self, player: &str) -> &RatingProfile {
+        self.profiles.get(player).unwrap_or(&self.default_profile)
+    }
+
+    // The profile set for `player`, None when the default applies
+    pub fn own_profile(&self, player: &str) -> Option<&RatingProfile> {
+        self.profiles.get(player)
+    }
+
+    // Change a player's own profile; refused while parental controls are on
+    pub fn set_profile(&mut self, player: &str, profile: RatingProfile) -> Result<(), RatingError> {
+        if self.profiles.get(player).is_some_and(|p| p.parental) {
+            return Err(RatingError::ProfileLocked(player.to_string()));
+        }
+        self.profiles.insert(player.to_string(), profile);
+        Ok(())
+    }
+
+    // Guardian path; the caller has verified the guardian, e.g. through the platform account
+    pub fn set_parental_profile(&mut self, player: &str, profile: RatingProfile) {
+        self.profiles.insert(player.to_string(), profile.parental());
+    }
+
+    pub fn clear_parental_profile(&mut self, player: &str) {
+        self.profiles.remove(player);
+    }
+
+    // Whether `player` may see `content`; the decision is audited
+    pub fn check(&mut self, player: &str, surface: Surface, content: &str, tags: &ContentTags) -> bool {
+        let reasons = self.profile(player).violations(tags);
+        let allowed = reasons.is_empty();
+        self.record(AuditEntry {
+            at: unix_now(),
+            player: player.to_string(),
+            surface,
+            content: content.to_string(),
+            rating: tags.rating(),
+            allowed,
+            reasons,
+        });
+        allowed
+    }
+
+    // Check generated text; `tags` from the generator win over the lexicon
+    pub fn check_generated(
+        &mut self,
+        player: &str,
+        surface: Surface,
+        content: &str,
+        text: &str,
+        tags: Option<&ContentTags>,
+    ) -> bool {
+        let tags = match tags {
+            Some(tags) if !tags.is_empty() => tags.clone(),
+            _ => self.tagger.tag(text),
+        };
+        self.check(player, surface, content, &tags)
+    }
+
+    // Keep the items `player` may see, e.g. dialogue choices or quest offers
+    pub fn permitted<T>(&mut self, player: &str, surface: Surface, items: Vec<(String, ContentTags, T)>) -> Vec<T> {
+        items
+            .into_iter()
+            .filter_map(|(content, tags, item)| self.check(player, surface, &content, &tags).then_some(item))
+            .collect()
+    }
+
+    fn record(&mut self, entry: AuditEntry) {
+        if self.audit_capacity == 0 {
+            return;
+        }
+        while self.audit.len() >= self.audit_capacity {
+            self.audit.pop_front();
+        }
+        self.audit.push_back(entry);
+    }
+
+    pub fn audit_log(&self) -> impl Iterator<Item = &AuditEntry> {
+        self.audit.iter()
+    }
+
+    // Drop the player's profile and audit entries (privacy erasure); returns the number removed
+    pub fn forget_player(&mut self, player: &str) -> usize {
+        let before = self.audit.len();
+        self.audit.retain(|entry| entry.player != player);
+        before - self.audit.len() + usize::from(self.profiles.remove(player).is_some())
+    }
+
+    // Summary of decisions made in [from, to] (unix seconds)
+    pub fn audit_report(&self, from: u64, to: u64) -> AuditReport {
+        let mut report = AuditReport::default();
+        let mut affected: Vec<&str> = Vec::new();
+        for entry in self.audit.iter().filter(|e| e.at >= from && e.at <= to) {
+            report.checks += 1;
+            if entry.allowed {
+                continue;
+            }
+            report.denied += 1;
+            *report.denied_by_surface.entry(entry.surface).or_default() += 1;
+            *report.denied_by_rating.entry(entry.rating).or_default() += 1;
+            if !affected.contains(&entry.player.as_str()) {
+                affected.push(&entry.player);
+            }
+            report.denials.push(entry.clone());
+        }
+        report.players_affected = affected.len();
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Descriptor::*;
+    use Intensity::*;
+
+    const DESCRIPTORS: [Descriptor; 7] = [Violence, Blood, Language, Sexual, Drugs, Gambling, Horror];
+
+    #[test]
+    fn required_ratings_follow_the_descriptor_matrix() {
+        let expected = [
+            (Violence, [Rating::Everyone, Rating::Everyone10, Rating::Teen]),
+            (Blood, [Rating::Everyone10, Rating::Teen, Rating::Mature]),
+            (Language, [Rating::Everyone10, Rating::Everyone10, Rating::Mature]),
+            (Sexual, [Rating::Teen, Rating::Mature, Rating::Adult]),
+            (Drugs, [Rating::Teen, Rating::Mature, Rating::Mature]),
+            (Gambling, [Rating::Teen, Rating::Mature, Rating::Mature]),
+            (Horror, [Rating::Everyone, Rating::Everyone10, Rating::Teen]),
+        ];
+        assert_eq!(expected.map(|(d, _)| d), DESCRIPTORS);
+        for (descriptor, ratings) in expected {
+            for (intensity, rating) in [Mild, Moderate, Strong].into_iter().zip(ratings) {
+                assert_eq!(Rating::required_for(descriptor, intensity), rating, "{:?} {:?}", descriptor, intensity);
+            }
+        }
+        assert_eq!(ContentTags::new().rating(), Rating::Everyone);
+        assert_eq!(ContentTags::new().with(Violence, Mild).with(Blood, Moderate).rating(), Rating::Teen);
+    }
+
+    #[test]
+    fn the_lexicon_matches_whole_words_and_counts_hits() {
+        let tagger = LexiconTagger::with_defaults();
+        assert!(tagger.tag("The shell of the killer whale").is_empty());
+
+        let tags = tagger.tag("Kill them! KILL them all, then stab the guard. Blood everywhere.");
+        assert_eq!(tags.descriptors[&Violence], Moderate);
+        assert_eq!(tags.descriptors[&Blood], Mild);
+        let tags = tagger.tag("kill, stab, murder, shoot");
+        assert_eq!(tags.descriptors[&Violence], Strong);
+        // Repeats of one word each count
+        assert_eq!(tagger.tag("blood blood blood blood").descriptors[&Blood], Strong);
+        // Phrases match across punctuation
+        assert_eq!(tagger.tag("Join the dice-game tonight").descriptors[&Gambling], Mild);
+
+        let mut custom = LexiconTagger::new();
+        custom.add(Horror, &["Ghoul"]);
+        assert_eq!(custom.tag("a ghoul and another GHOUL").descriptors[&Horror], Moderate);
+    }
+
+    #[test]
+    fn parental_profiles_cannot_be_changed_by_the_player() {
+        let mut rating = ContentRating::new(RatingProfile::new(Rating::Mature), 16);
+        rating.set_profile("p1", RatingProfile::new(Rating::Teen)).unwrap();
+        assert_eq!(rating.profile("p1").ceiling, Rating::Teen);
+        assert_eq!(rating.profile("p2").ceiling, Rating::Mature);
+        assert!(rating.own_profile("p2").is_none());
+
+        rating.set_parental_profile("p1", RatingProfile::new(Rating::Everyone10).limit(Blood, None));
+        let refused = rating.set_profile("p1", RatingProfile::new(Rating::Adult));
+        assert_eq!(refused, Err(RatingError::ProfileLocked("p1".to_string())));
+        assert_eq!(rating.profile("p1").ceiling, Rating::Everyone10);
+        assert!(!rating.check("p1", Surface::Dialogue, "line-1", &ContentTags::new().with(Blood, Mild)));
+
+        rating.clear_parental_profile("p1");
+        rating.set_profile("p1", RatingProfile::new(Rating::Adult)).unwrap();
+        assert!(rating.check("p1", Surface::Dialogue, "line-1", &ContentTags::new().with(Blood, Mild)));
+    }
+
+    #[test]
+    fn audit_reports_summarize_denials() {
+        let mut rating = ContentRating::new(RatingProfile::new(Rating::Teen), 16);
+        rating.set_profile("kid", RatingProfile::new(Rating::Everyone).limit(Violence, Some(Mild))).unwrap();
+        let gore = ContentTags::new().with(Blood, Strong);
+        let brawl = ContentTags::new().with(Violence, Moderate);
+
+        assert!(!rating.check("kid", Surface::Quest, "quest-1", &brawl));
+        assert!(!rating.check("kid", Surface::Dialogue, "line-1", &gore));
+        assert!(!rating.check("adult", Surface::Dialogue, "line-1", &gore));
+        assert!(rating.check("adult", Surface::Quest, "quest-1", &brawl));
+        assert!(!rating.check_generated("adult", Surface::Procgen, "gen-1", "Blood, blood, blood and more blood", None));
+
+        let report = rating.audit_report(0, u64::MAX);
+        assert_eq!((report.checks, report.denied, report.players_affected), (5, 4, 2));
+        assert_eq!(report.denied_by_surface[&Surface::Dialogue], 2);
+        assert_eq!(report.denied_by_rating[&Rating::Mature], 3);
+        assert_eq!(report.denials[0].reasons, ["rated E10+ above ceiling E", "Violence Moderate exceeds limit Mild"]);
+        assert_eq!(rating.audit_report(0, 0), AuditReport::default());
+
+        assert_eq!(rating.forget_player("kid"), 3);
+        assert_eq!(rating.audit_report(0, u64::MAX).players_affected, 1);
+    }
+}
@@ -0,0 +1,381 @@
+// Relationships
+//
+// How each character feels about each other character (the player included): affinity in -1..1
+// and familiarity in 0..1. Interactions move affinity; time without interaction lets both fade
+// back towards indifference, after a grace period and with a configurable half-life, so old
+// friendships cool and grudges are eventually forgotten.
+//
+// Affinity is bucketed into standings (hostile .. ally). Crossing into another standing publishes
+// a "social.milestone" event ("became_friends", "turned_hostile", ...); a small hysteresis margin
+// keeps a value hovering at a threshold from firing milestones back and forth. Every interaction
+// also publishes "social.relationship_changed" for analytics.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::analytics::RELATIONSHIP_TOPIC;
+use crate::events::EventBus;
+
+pub const MILESTONE_TOPIC: &str = "social.milestone";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Standing {
+    Hostile,
+    Unfriendly,
+    Neutral,
+    Friendly,
+    Ally,
+}
+
+impl Standing {
+    // Milestone name for moving from `from` to this standing
+    fn milestone(self, from: Standing) -> &'static str {
+        match (from, self) {
+            (_, Standing::Hostile) => "turned_hostile",
+            (_, Standing::Ally) => "became_allies",
+            (f, Standing::Friendly) if f < Standing::Friendly => "became_friends",
+            (_, Standing::Friendly) => "drifted_apart",
+            (f, Standing::Unfriendly) if f > Standing::Unfriendly => "fell_out",
+            (_, Standing::Unfriendly) => "reconciling",
+            (f, Standing::Neutral) if f > Standing::Neutral => "grew_distant",
+            (_, Standing::Neutral) => "made_peace",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RelationshipConfig {
+    // Lower bounds of the standings above hostile
+    pub unfriendly: f32,
+    pub neutral: f32,
+    pub friendly: f32,
+    pub ally: f32,
+    // How far past a threshold affinity must go before the standing changes
+    pub hysteresis: f32,
+    // Seconds after the last interaction before decay starts
+    pub grace_secs: f64,
+    // Seconds for affinity and familiarity to halve once decaying
+    pub half_life_secs: f64,
+}
+
+impl Default for RelationshipConfig {
+    fn default() -> Self {
+        RelationshipConfig {
+            unfriendly: -0.6,
+            neutral: -0.2,
+            friendly: 0.2,
+            ally: 0.6,
+            hysteresis: 0.05,
+            grace_secs: 3600.0,
+            half_life_secs: 7.0 * 86400.0,
+        }
+    }
+}
+
+impl RelationshipConfig {
+    fn standing(&self, affinity: f32) -> Standing {
+        if affinity >= self.ally {
+            Standing::Ally
+        } else if affinity >= self.friendly {
+            Standing::Friendly
+        } else if affinity >= self.neutral {
+            Standing::Neutral
+        } else if affinity >= self.unfriendly {
+            Standing::Unfriendly
+        } else {
+            Standing::Hostile
+        }
+    }
+
+    // Standing after a change, staying at `current` unless the value is clearly past a threshold
+    fn next_standing(&self, current: Standing, affinity: f32) -> Standing {
+        let raw = self.standing(affinity);
+        if raw == current {
+            return current;
+        }
+        let shifted = if raw > current { affinity - self.hysteresis } else { affinity + self.hysteresis };
+        let confirmed = self.standing(shifted);
+        if (raw > current && confirmed > current) || (raw < current && confirmed < current) {
+            confirmed
+        } else {
+            current
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Relationship {
+    pub affinity: f32,
+    pub familiarity: f32,
+    pub standing: Standing,
+    pub interactions: u32,
+    pub since: f64,
+    pub last_interaction: f64,
+    // When decay was last applied
+    last_decay: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Milestone {
+    pub from_entity: String,
+    pub to_entity: String,
+    pub name: &'static str,
+    pub previous: Standing,
+    pub standing: Standing,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Relationships {
+    config: RelationshipConfig,
+    // Owner -> other -> how the owner feels about the other
+    graph: HashMap<String, HashMap<String, Relationship>>,
+}
+
+impl Relationships {
+    pub fn new(config: RelationshipConfig) -> Self {
+        Relationships { config, graph: HashMap::new() }
+    }
+
+    pub fn get(&self, from: &str, to: &str) -> Option<&Relationship> {
+        self.graph.get(from)?.get(to)
+    }
+
+    pub fn affinity(&self, from: &str, to: &str) -> f32 {
+        self.get(from, to).map_or(0.0, |r| r.affinity)
+    }
+
+    // `from`'s attitude towards `to` changes by `delta`; familiarity grows with every interaction
+    pub fn interact(&mut self, from: &str, to: &str, delta: f32, now: f64, events: Option<&mut EventBus>) -> Option<Milestone> {
+        let config = self.config;
+        let relationship = self.graph.entry(from.to_string()).or_default().entry(to.to_string()).or_insert(Relationship {
+            affinity: 0.0,
+            familiarity: 0.0,
+            standing: config.standing(0.0),
+            interactions: 0,
+            since: now,
+            last_interaction: now,
+            last_decay: now,
+        });
+        let before = relationship.affinity;
+        relationship.affinity = (relationship.affinity + delta).clamp(-1.0, 1.0);
+        relationship.familiarity = (relationship.familiarity + 0.1 * (1.0 - relationship.familiarity)).min(1.0);
+        relationship.interactions += 1;
+        relationship.last_interaction = now;
+        relationship.last_decay = now;
+        let change = relationship.affinity - before;
+        let milestone = update_standing(&config, from, to, relationship);
+
+        if let Some(events) = events {
+            events.emit(RELATIONSHIP_TOPIC, from, json!({ "npc": from, "other": to, "delta": change }));
+            if let Some(milestone) = &milestone {
+                emit_milestone(events, milestone);
+            }
+        }
+        milestone
+    }
+
+    // Fade relationships that have not been touched for longer than the grace period
+    pub fn decay(&mut self, now: f64, mut events: Option<&mut EventBus>) -> Vec<Milestone> {
+        let config = self.config;
+        let mut milestones = Vec::new();
+        for (from, others) in &mut self.graph {
+            for (to, relationship) in others.iter_mut() {
+                let start = relationship.last_decay.max(relationship.last_interaction + config.grace_secs);
+                if now <= start || config.half_life_secs <= 0.0 {
+                    continue;
+                }
+                let keep = 0.5f64.powf((now - start) / config.half_life_secs) as f32;
+                relationship.affinity *= keep;
+                relationship.familiarity *= keep;
+                relationship.last_decay = now;
+                if let Some(milestone) = update_standing(&config, from, to, relationship) {
+                    if let Some(events) = events.as_deref_mut() {
+                        emit_milestone(events, &milestone);
+                    }
+                    milestones.push(milestone);
+                }
+            }
+        }
+        milestones
+    }
+
+    // The `k` characters `entity` likes most, best first; only positive affinity counts
+    pub fn closest_allies(&self, entity: &str, k: usize) -> Vec<(&str, &Relationship)> {
+        self.ranked(entity, k, |r| r.affinity > 0.0, |a, b| b.affinity.total_cmp(&a.affinity))
+    }
+
+    pub fn worst_enemies(&self, entity: &str, k: usize) -> Vec<(&str, &Relationship)> {
+        self.ranked(entity, k, |r| r.affinity < 0.0, |a, b| a.affinity.total_cmp(&b.affinity))
+    }
+
+    // Everyone `entity` has a given standing with
+    pub fn with_standing(&self, entity: &str, standing: Standing) -> Vec<&str> {
+        let mut found: Vec<&str> = self
+            .graph
+            .get(entity)
+            .into_iter()
+            .flatten()
+            .filter(|(_, r)| r.standing == standing)
+            .map(|(other, _)| other.as_str())
+            .collect();
+        found.sort();
+        found
+    }
+
+    fn ranked(
+        &self,
+        entity: &str,
+        k: usize,
+        keep: impl Fn(&Relationship) -> bool,
+        order: impl Fn(&Relationship, &Relationship) -> std::cmp::Ordering,
+    ) -> Vec<(&str, &Relationship)> {
+        let mut found: Vec<(&str, &Relationship)> = self
+            .graph
+            .get(entity)
+            .into_iter()
+            .flatten()
+            .filter(|(_, r)| keep(r))
+            .map(|(other, r)| (other.as_str(), r))
+            .collect();
+        found.sort_by(|a, b| order(a.1, b.1).then_with(|| a.0.cmp(b.0)));
+        found.truncate(k);
+        found
+    }
+
+    // Drop every relationship from and towards `entity`
+    pub fn forget(&mut self, entity: &str) {
+        self.graph.remove(entity);
+        for others in self.graph.values_mut() {
+            others.remove(entity);
+        }
+    }
+}
+
+fn update_standing(config: &RelationshipConfig, from: &str, to: &str, relationship: &mut Relationship) -> Option<Milestone> {
+    let previous = relationship.standing;
+    let standing = config.next_standing(previous, relationship.affinity);
+    if standing == previous {
+        return None;
+    }
+    relationship.standing = standing;
+    Some(Milestone {
+        from_entity: from.to_string(),
+        to_entity: to.to_string(),
+        name: standing.milestone(previous),
+        previous,
+        standing,
+    })
+}
+
+fn emit_milestone(events: &mut EventBus, milestone: &Milestone) {
+    events.emit(
+        MILESTONE_TOPIC,
+        &milestone.from_entity,
+        json!({
+            "entity": milestone.from_entity,
+            "other": milestone.to_entity,
+            "milestone": milestone.name,
+            "from": milestone.previous,
+            "to": milestone.standing,
+        }),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: f64 = 86400.0;
+
+    #[test]
+    fn standings_change_only_clearly_past_a_threshold() {
+        let mut bus = EventBus::new(16);
+        let sub = bus.subscribe("social.");
+        let mut social = Relationships::new(RelationshipConfig::default());
+        assert!(social.interact("mira", "player", 0.22, 0.0, Some(&mut bus)).is_none());
+        assert_eq!(social.get("mira", "player").unwrap().standing, Standing::Neutral);
+
+        let milestone = social.interact("mira", "player", 0.1, 1.0, Some(&mut bus)).unwrap();
+        assert_eq!(milestone.name, "became_friends");
+        assert_eq!((milestone.previous, milestone.standing), (Standing::Neutral, Standing::Friendly));
+        // Dipping just under the threshold keeps the friendship
+        assert!(social.interact("mira", "player", -0.14, 2.0, None).is_none());
+
+        let relationship = social.get("mira", "player").unwrap();
+        assert_eq!(relationship.interactions, 3);
+        assert!((relationship.familiarity - 0.271).abs() < 1e-5);
+        // Attitudes are one-way
+        assert!(social.get("player", "mira").is_none());
+
+        let events = bus.drain(sub);
+        let topics: Vec<&str> = events.iter().map(|e| e.topic.as_str()).collect();
+        assert_eq!(topics, vec![RELATIONSHIP_TOPIC, RELATIONSHIP_TOPIC, MILESTONE_TOPIC]);
+        assert_eq!(events[2].payload["milestone"], "became_friends");
+        assert_eq!(events[2].payload["to"], "friendly");
+    }
+
+    #[test]
+    fn milestone_names_follow_the_direction_of_change() {
+        use Standing::*;
+        for (from, to, name) in [
+            (Neutral, Hostile, "turned_hostile"),
+            (Friendly, Ally, "became_allies"),
+            (Neutral, Friendly, "became_friends"),
+            (Ally, Friendly, "drifted_apart"),
+            (Neutral, Unfriendly, "fell_out"),
+            (Hostile, Unfriendly, "reconciling"),
+            (Friendly, Neutral, "grew_distant"),
+            (Unfriendly, Neutral, "made_peace"),
+        ] {
+            assert_eq!(to.milestone(from), name);
+        }
+    }
+
+    #[test]
+    fn untouched_relationships_decay_after_the_grace_period() {
+        let config = RelationshipConfig { grace_secs: DAY, half_life_secs: DAY, ..RelationshipConfig::default() };
+        let mut social = Relationships::new(config);
+        social.interact("mira", "player", 0.32, 0.0, None);
+        assert!(social.decay(DAY, None).is_empty());
+        assert_eq!(social.affinity("mira", "player"), 0.32);
+
+        // Two half-day steps decay as much as one full day
+        social.decay(1.5 * DAY, None);
+        assert!(social.decay(2.0 * DAY, None).is_empty());
+        assert!((social.affinity("mira", "player") - 0.16).abs() < 1e-5);
+
+        let mut bus = EventBus::new(4);
+        let sub = bus.subscribe(MILESTONE_TOPIC);
+        let milestones = social.decay(3.0 * DAY, Some(&mut bus));
+        assert_eq!(milestones.len(), 1);
+        assert_eq!(milestones[0].name, "grew_distant");
+        assert_eq!(bus.drain(sub).len(), 1);
+        assert!((social.get("mira", "player").unwrap().familiarity - 0.025).abs() < 1e-5);
+    }
+
+    #[test]
+    fn rankings_and_forgetting() {
+        let mut social = Relationships::new(RelationshipConfig::default());
+        for (other, delta) in [("bram", 0.4), ("cole", 0.9), ("dara", -0.7), ("eli", -0.3), ("finn", 0.4)] {
+            social.interact("mira", other, delta, 0.0, None);
+        }
+        social.interact("mira", "cole", 5.0, 1.0, None);
+        assert_eq!(social.affinity("mira", "cole"), 1.0);
+
+        let allies: Vec<&str> = social.closest_allies("mira", 2).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(allies, vec!["cole", "bram"]);
+        let enemies: Vec<&str> = social.worst_enemies("mira", 5).into_iter().map(|(name, _)| name).collect();
+        assert_eq!(enemies, vec!["dara", "eli"]);
+        assert_eq!(social.with_standing("mira", Standing::Friendly), vec!["bram", "finn"]);
+        assert_eq!(social.with_standing("mira", Standing::Hostile), vec!["dara"]);
+
+        social.interact("cole", "mira", 0.5, 0.0, None);
+        social.forget("mira");
+        assert!(social.get("cole", "mira").is_none());
+        assert!(social.closest_allies("mira", 3).is_empty());
+    }
+}
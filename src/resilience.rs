@@ -0,0 +1,621 @@
+// Resilience and degraded modes
+//
+// External services (Qdrant, OpenAI) can disappear mid-session. Each subsystem reports call
+// outcomes to a shared HealthRegistry, which moves it between Healthy, Degraded and Down after
+// configurable failure streaks and back once calls succeed again. Wrappers keep the game
+// running meanwhile: the vector index keeps serving from its in-memory copy while remote writes
+// are queued for replay, dialogue falls back to canned lines, and background tasks restart under
+// a supervisor with exponential backoff. Health transitions are published on the event bus.
+
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::events::EventBus;
+use crate::generation::TextGenerator;
+use crate::rng::Rng;
+use crate::vector_index::{RemoteStore, VectorIndexError, VectorPoint};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HealthState {
+    Healthy,
+    // Working on a fallback
+    Degraded { reason: String },
+    // Fallback only, the primary is considered unreachable
+    Down { reason: String },
+}
+
+impl HealthState {
+    pub fn is_healthy(&self) -> bool {
+        matches!(self, HealthState::Healthy)
+    }
+
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            HealthState::Healthy => "healthy",
+            HealthState::Degraded { .. } => "degraded",
+            HealthState::Down { .. } => "down",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthTransition {
+    pub subsystem: String,
+    pub from: HealthState,
+    pub to: HealthState,
+}
+
+#[derive(Debug, Clone)]
+pub struct HealthPolicy {
+    // Consecutive failures before Degraded / Down
+    pub degrade_after: u32,
+    pub down_after: u32,
+    // Consecutive successes needed to become Healthy again
+    pub recover_after: u32,
+}
+
+impl Default for HealthPolicy {
+    fn default() -> Self {
+        HealthPolicy {
+            degrade_after: 1,
+            down_after: 5,
+            recover_after: 2,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct SubsystemHealth {
+    state: HealthState,
+    failures: u32,
+    successes: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct HealthRegistry {
+    policies: HashMap<String, HealthPolicy>,
+    subsystems: HashMap<String, SubsystemHealth>,
+    // Transitions not yet published
+    transitions: Vec<HealthTransition>,
+}
+
+impl HealthRegistry {
+    pub fn new() -> Self {
+        HealthRegistry::default()
+    }
+
+    pub fn shared() -> Arc<Mutex<HealthRegistry>> {
+        Arc::new(Mutex::new(HealthRegistry::new()))
+    }
+
+    pub fn set_policy(&mut self, subsystem: &str, policy: HealthPolicy) {
+        self.policies.insert(subsystem.to_string(), policy);
+    }
+
+    pub fn state(&self, subsystem: &str) -> HealthState {
+        self.subsystems
+            .get(subsystem)
+            .map_or(HealthState::Healthy, |s| s.state.clone())
+    }
+
+    pub fn is_healthy(&self, subsystem: &str) -> bool {
+        self.state(subsystem).is_healthy()
+    }
+
+    pub fn states(&self) -> Vec<(&str, &HealthState)> {
+        let mut states: Vec<(&str, &HealthState)> =
+            self.subsystems.iter().map(|(name, s)| (name.as_str(), &s.state)).collect();
+        states.sort_by(|a, b| a.0.cmp(b.0));
+        states
+    }
+
+    fn entry(&mut self, subsystem: &str) -> (&HealthPolicy, &mut SubsystemHealth) {
+        let policy = self.policies.entry(subsystem.to_string()).or_default();
+        let health = self.subsystems.entry(subsystem.to_string()).or_insert(SubsystemHealth {
+            state: HealthState::Healthy,
+            failures: 0,
+            successes: 0,
+        });
+        (policy, health)
+    }
+
+    pub fn report_success(&mut self, subsystem: &str) -> Option<HealthTransition> {
+        let (policy, health) = self.entry(subsystem);
+        health.failures = 0;
+        health.successes += 1;
+        let recover_after = policy.recover_after;
+        if health.state.is_healthy() || health.successes < recover_after {
+            return None;
+        }
+        let from = std::mem::replace(&mut health.state, HealthState::Healthy);
+        self.transition(subsystem, from, HealthState::Healthy)
+    }
+
+    pub fn report_failure(&mut self, subsystem: &str, error: &dyn fmt::Display) -> Option<HealthTransition> {
+        let (policy, health) = self.entry(subsystem);
+        health.successes = 0;
+        health.failures += 1;
+        let reason = error.to_string();
+        let next = if health.failures >= policy.down_after {
+            HealthState::Down { reason }
+        } else if health.failures >= policy.degrade_after {
+            HealthState::Degraded { reason }
+        } else {
+            return None;
+        };
+        if next.label() == health.state.label() {
+            health.state = next;
+            return None;
+        }
+        let from = std::mem::replace(&mut health.state, next.clone());
+        self.transition(subsystem, from, next)
+    }
+
+    fn transition(&mut self, subsystem: &str, from: HealthState, to: HealthState) -> Option<HealthTransition> {
+        let transition = HealthTransition { subsystem: subsystem.to_string(), from, to };
+        self.transitions.push(transition.clone());
+        Some(transition)
+    }
+
+    // Publish pending transitions as "resilience.degraded" / "resilience.down" /
+    // "resilience.recovered" events; returns the number published
+    pub fn publish(&mut self, events: &mut EventBus) -> usize {
+        let count = self.transitions.len();
+        for t in self.transitions.drain(..) {
+            let topic = match t.to {
+                HealthState::Healthy => "resilience.recovered",
+                HealthState::Degraded { .. } => "resilience.degraded",
+                HealthState::Down { .. } => "resilience.down",
+            };
+            let reason = match &t.to {
+                HealthState::Degraded { reason } | HealthState::Down { reason } => Some(reason.clone()),
+                HealthState::Healthy => None,
+            };
+            events.emit(
+                topic,
+                &t.subsystem,
+                json!({ "subsystem": t.subsystem, "from": t.from.label(), "to": t.to.label(), "reason": reason }),
+            );
+        }
+        count
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    pub multiplier: f64,
+    // Fraction of random spread added to each delay so restarts do not synchronize
+    pub jitter: f64,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            initial: Duration::from_millis(200),
+            max: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+}
+
+impl Backoff {
+    // Delay before retry number `attempt` (0-based)
+    pub fn delay(&self, attempt: u32, rng: &mut Rng) -> Duration {
+        let base = self.initial.as_secs_f64() * self.multiplier.powi(attempt.min(32) as i32);
+        let capped = base.min(self.max.as_secs_f64());
+        let spread = 1.0 + self.jitter * (rng.next_f64() * 2.0 - 1.0);
+        Duration::from_secs_f64((capped * spread).max(0.0))
+    }
+}
+
+// Run `op` until it succeeds or `max_attempts` is reached, sleeping between attempts
+pub fn retry_with_backoff<T, E>(backoff: &Backoff, max_attempts: u32, mut op: impl FnMut() -> Result<T, E>) -> Result<T, E> {
+    let mut rng = Rng::from_time();
+    let mut attempt = 0;
+    loop {
+        match op() {
+            Ok(value) => return Ok(value),
+            Err(err) if attempt + 1 >= max_attempts => return Err(err),
+            Err(_) => {
+                thread::sleep(backoff.delay(attempt, &mut rng));
+                attempt += 1;
+            }
+        }
+    }
+}
+
+// Restarts a background task whenever it fails, with backoff, until stopped
+pub struct Supervisor {
+    stop: Arc<AtomicBool>,
+    restarts: Arc<AtomicUsize>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Supervisor {
+    // `task` runs one unit of work per call; Ok(false) means it finished for good
+    pub fn spawn<F>(name: &str, backoff: Backoff, health: Arc<Mutex<HealthRegistry>>, mut task: F) -> Self
+    where
+        F: FnMut() -> Result<bool, Box<dyn Error + Send + Sync>> + Send + 'static,
+    {
+        let stop = Arc::new(AtomicBool::new(false));
+        let restarts = Arc::new(AtomicUsize::new(0));
+        let name = name.to_string();
+        let (stop_flag, restart_count) = (stop.clone(), restarts.clone());
+        let handle = thread::spawn(move || {
+            let mut rng = Rng::from_time();
+            let mut attempt = 0;
+            while !stop_flag.load(Ordering::Relaxed) {
+                match task() {
+                    Ok(true) => {
+                        attempt = 0;
+                        if let Ok(mut health) = health.lock() {
+                            health.report_success(&name);
+                        }
+                    }
+                    Ok(false) => return,
+                    Err(err) => {
+                        if let Ok(mut health) = health.lock() {
+                            health.report_failure(&name, &err);
+                        }
+                        restart_count.fetch_add(1, Ordering::Relaxed);
+                        thread::sleep(backoff.delay(attempt, &mut rng));
+                        attempt += 1;
+                    }
+                }
+            }
+        });
+        Supervisor { stop, restarts, handle: Some(handle) }
+    }
+
+    pub fn restarts(&self) -> usize {
+        self.restarts.load(Ordering::Relaxed)
+    }
+
+    pub fn stop(mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+enum PendingWrite {
+    Create(String, usize),
+    Drop(String),
+    Upsert(String, Vec<VectorPoint>),
+    Delete(String, Vec<String>),
+}
+
+// RemoteStore wrapper that never fails a write: while the remote is unreachable the index keeps
+// serving from memory and writes are queued, then replayed in order once the remote answers again
+pub struct ResilientRemote {
+    inner: Box<dyn RemoteStore>,
+    health: Arc<Mutex<HealthRegistry>>,
+    subsystem: String,
+    pending: Mutex<VecDeque<PendingWrite>>,
+    // Oldest writes are dropped beyond this many
+    max_pending: usize,
+}
+
+impl ResilientRemote {
+    pub fn new(inner: Box<dyn RemoteStore>, health: Arc<Mutex<HealthRegistry>>) -> Self {
+        ResilientRemote {
+            inner,
+            health,
+            subsystem: "vector_index".to_string(),
+            pending: Mutex::new(VecDeque::new()),
+            max_pending: 10_000,
+        }
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    fn apply(&self, write: &PendingWrite) -> Result<(), VectorIndexError> {
+        match write {
+            PendingWrite::Create(name, dimension) => self.inner.create_collection(name, *dimension),
+            PendingWrite::Drop(name) => self.inner.drop_collection(name),
+            PendingWrite::Upsert(collection, points) => self.inner.upsert(collection, points),
+            PendingWrite::Delete(collection, ids) => self.inner.delete(collection, ids),
+        }
+    }
+
+    // Replay queued writes in order; stops at the first failure
+    pub fn flush(&self) -> Result<usize, VectorIndexError> {
+        let mut pending = self.pending.lock().unwrap();
+        let mut replayed = 0;
+        while let Some(write) = pending.front() {
+            self.apply(write)?;
+            pending.pop_front();
+            replayed += 1;
+        }
+        Ok(replayed)
+    }
+
+    fn submit(&self, write: PendingWrite) -> Result<(), VectorIndexError> {
+        let result = self.flush().and_then(|_| self.apply(&write));
+        let mut health = self.health.lock().unwrap();
+        match result {
+            Ok(()) => {
+                health.report_success(&self.subsystem);
+            }
+            Err(err) => {
+                health.report_failure(&self.subsystem, &err);
+                let mut pending = self.pending.lock().unwrap();
+                if pending.len() >= self.max_pending {
+                    pending.pop_front();
+                }
+                pending.push_back(write);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl RemoteStore for ResilientRemote {
+    fn create_collection(&self, name: &str, dimension: usize) -> Result<(), VectorIndexError> {
+        self.submit(PendingWrite::Create(name.to_string(), dimension))
+    }
+
+    fn drop_collection(&self, name: &str) -> Result<(), VectorIndexError> {
+        self.submit(PendingWrite::Drop(name.to_string()))
+    }
+
+    fn upsert(&self, collection: &str, points: &[VectorPoint]) -> Result<(), VectorIndexError> {
+        self.submit(PendingWrite::Upsert(collection.to_string(), points.to_vec()))
+    }
+
+    fn delete(&self, collection: &str, ids: &[String]) -> Result<(), VectorIndexError> {
+        self.submit(PendingWrite::Delete(collection.to_string(), ids.to_vec()))
+    }
+}
+
+// Rotates through designer-written lines; the last-resort dialogue backend
+pub struct CannedDialogue {
+    lines: Vec<String>,
+    next: AtomicUsize,
+}
+
+impl CannedDialogue {
+    pub fn new(lines: &[&str]) -> Self {
+        CannedDialogue {
+            lines: lines.iter().map(|l| l.to_string()).collect(),
+            next: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl TextGenerator for CannedDialogue {
+    fn generate(&self, _prompt: &str) -> Result<String, Box<dyn Error>> {
+        if self.lines.is_empty() {
+            return Ok("...".to_string());
+        }
+        let i = self.next.fetch_add(1, Ordering::Relaxed) % self.lines.len();
+        Ok(self.lines[i].clone())
+    }
+}
+
+// Uses the primary generator while it is healthy and the fallback otherwise. While Down the
+// primary is only probed every `probe_every` calls so a dead service is not hammered.
+pub struct FallbackGenerator<P: TextGenerator, F: TextGenerator> {
+    pub primary: P,
+    pub fallback: F,
+    pub subsystem: String,
+    pub health: Arc<Mutex<HealthRegistry>>,
+    pub probe_every: usize,
+    calls: AtomicUsize,
+}
+
+impl<P: TextGenerator, F: TextGenerator> FallbackGenerator<P, F> {
+    pub fn new(primary: P, fallback: F, subsystem: &str, health: Arc<Mutex<HealthRegistry>>) -> Self {
+        FallbackGenerator {
+            primary,
+            fallback,
+            subsystem: subsystem.to_string(),
+            health,
+            probe_every: 10,
+            calls: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl<P: TextGenerator, F: TextGenerator> TextGenerator for FallbackGenerator<P, F> {
+    fn generate(&self, prompt: &str) -> Result<String, Box<dyn Error>> {
+        let call = self.calls.fetch_add(1, Ordering::Relaxed);
+        let down = matches!(self.health.lock().unwrap().state(&self.subsystem), HealthState::Down { .. });
+        if down && !call.is_multiple_of(self.probe_every.max(1)) {
+            return self.fallback.generate(prompt);
+        }
+        match self.primary.generate(prompt) {
+            Ok(text) => {
+                self.health.lock().unwrap().report_success(&self.subsystem);
+                Ok(text)
+            }
+            Err(err) => {
+                self.health.lock().unwrap().report_failure(&self.subsystem, &err);
+                self.fallback.generate(prompt)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Remote that records applied writes and fails while `up` is false
+    struct Flaky {
+        up: Arc<AtomicBool>,
+        applied: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl Flaky {
+        fn write(&self, what: String) -> Result<(), VectorIndexError> {
+            if !self.up.load(Ordering::Relaxed) {
+                return Err(VectorIndexError::Remote("connection refused".to_string()));
+            }
+            self.applied.lock().unwrap().push(what);
+            Ok(())
+        }
+    }
+
+    impl RemoteStore for Flaky {
+        fn create_collection(&self, name: &str, _dimension: usize) -> Result<(), VectorIndexError> {
+            self.write(format!("create {}", name))
+        }
+
+        fn drop_collection(&self, name: &str) -> Result<(), VectorIndexError> {
+            self.write(format!("drop {}", name))
+        }
+
+        fn upsert(&self, collection: &str, points: &[VectorPoint]) -> Result<(), VectorIndexError> {
+            self.write(format!("upsert {} {}", collection, points.len()))
+        }
+
+        fn delete(&self, collection: &str, ids: &[String]) -> Result<(), VectorIndexError> {
+            self.write(format!("delete {} {}", collection, ids.join(",")))
+        }
+    }
+
+    // Counts the calls it fails
+    #[derive(Default)]
+    struct Failing(AtomicUsize);
+
+    impl TextGenerator for Failing {
+        fn generate(&self, _prompt: &str) -> Result<String, Box<dyn Error>> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Err("503 service unavailable".into())
+        }
+    }
+
+    #[test]
+    fn failure_streaks_degrade_then_down_and_successes_recover() {
+        let mut registry = HealthRegistry::new();
+        registry.set_policy("openai", HealthPolicy { degrade_after: 2, down_after: 3, recover_after: 2 });
+        assert!(registry.report_failure("openai", &"timeout").is_none());
+        let degraded = registry.report_failure("openai", &"timeout").unwrap();
+        assert_eq!(degraded.to, HealthState::Degraded { reason: "timeout".to_string() });
+        assert_eq!(registry.report_failure("openai", &"refused").unwrap().to.label(), "down");
+        // Staying down refreshes the reason without another transition
+        assert!(registry.report_failure("openai", &"dns").is_none());
+        assert_eq!(registry.state("openai"), HealthState::Down { reason: "dns".to_string() });
+
+        assert!(registry.report_success("openai").is_none());
+        assert!(registry.report_success("openai").unwrap().to.is_healthy());
+        assert!(registry.is_healthy("qdrant"));
+
+        let mut events = EventBus::new(16);
+        let sub = events.subscribe("resilience.");
+        assert_eq!(registry.publish(&mut events), 3);
+        let published = events.drain(sub);
+        let topics: Vec<&str> = published.iter().map(|e| e.topic.as_str()).collect();
+        assert_eq!(topics, vec!["resilience.degraded", "resilience.down", "resilience.recovered"]);
+        assert_eq!(published[1].payload["reason"], "refused");
+        assert_eq!(registry.publish(&mut events), 0);
+    }
+
+    #[test]
+    fn backoff_grows_to_the_cap_within_jitter() {
+        let backoff = Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(1),
+            multiplier: 2.0,
+            jitter: 0.0,
+        };
+        let mut rng = Rng::new(1);
+        let delays: Vec<u128> = (0..6).map(|attempt| backoff.delay(attempt, &mut rng).as_millis()).collect();
+        assert_eq!(delays, vec![100, 200, 400, 800, 1000, 1000]);
+
+        let jittered = Backoff { jitter: 0.5, ..backoff };
+        for _ in 0..100 {
+            let delay = jittered.delay(0, &mut rng).as_secs_f64();
+            assert!((0.05..=0.15).contains(&delay), "{}", delay);
+        }
+    }
+
+    #[test]
+    fn retry_stops_at_success_or_the_attempt_limit() {
+        let backoff = Backoff { initial: Duration::ZERO, ..Default::default() };
+        let mut calls = 0;
+        let result = retry_with_backoff(&backoff, 5, || {
+            calls += 1;
+            if calls < 3 { Err(calls) } else { Ok(calls) }
+        });
+        assert_eq!(result, Ok(3));
+
+        calls = 0;
+        let result: Result<(), i32> = retry_with_backoff(&backoff, 2, || {
+            calls += 1;
+            Err(calls)
+        });
+        assert_eq!(result, Err(2));
+    }
+
+    #[test]
+    fn supervisor_restarts_failed_tasks_until_done() {
+        let health = HealthRegistry::shared();
+        let backoff = Backoff { initial: Duration::from_millis(1), jitter: 0.0, ..Default::default() };
+        let mut runs = 0;
+        let supervisor = Supervisor::spawn("indexer", backoff, health.clone(), move || {
+            runs += 1;
+            match runs {
+                1 | 2 => Err("crashed".into()),
+                3 | 4 => Ok(true),
+                _ => Ok(false),
+            }
+        });
+        while !supervisor.handle.as_ref().unwrap().is_finished() {
+            thread::yield_now();
+        }
+        assert_eq!(supervisor.restarts(), 2);
+        assert!(health.lock().unwrap().is_healthy("indexer"));
+        supervisor.stop();
+    }
+
+    #[test]
+    fn resilient_remote_queues_writes_and_replays_them_in_order() {
+        let up = Arc::new(AtomicBool::new(false));
+        let applied = Arc::new(Mutex::new(Vec::new()));
+        let health = HealthRegistry::shared();
+        let flaky = Flaky { up: up.clone(), applied: applied.clone() };
+        let remote = ResilientRemote::new(Box::new(flaky), health.clone());
+
+        remote.create_collection("lore", 2).unwrap();
+        remote.upsert("lore", &[VectorPoint::new("a", vec![1.0, 0.0])]).unwrap();
+        assert_eq!(remote.pending(), 2);
+        assert!(!health.lock().unwrap().is_healthy("vector_index"));
+
+        up.store(true, Ordering::Relaxed);
+        remote.delete("lore", &["a".to_string()]).unwrap();
+        assert_eq!(remote.pending(), 0);
+        assert_eq!(*applied.lock().unwrap(), vec!["create lore", "upsert lore 1", "delete lore a"]);
+    }
+
+    #[test]
+    fn fallback_generator_probes_a_down_primary_sparingly() {
+        let health = HealthRegistry::shared();
+        let policy = HealthPolicy { degrade_after: 1, down_after: 1, recover_after: 1 };
+        health.lock().unwrap().set_policy("dialogue", policy);
+        let canned = CannedDialogue::new(&["Hm.", "Aye."]);
+        let mut generator = FallbackGenerator::new(Failing::default(), canned, "dialogue", health.clone());
+        generator.probe_every = 3;
+
+        let lines: Vec<String> = (0..4).map(|_| generator.generate("hello").unwrap()).collect();
+        assert_eq!(lines, vec!["Hm.", "Aye.", "Hm.", "Aye."]);
+        assert!(matches!(health.lock().unwrap().state("dialogue"), HealthState::Down { .. }));
+        // Calls 0 and 3 probed the primary; 1 and 2 went straight to the fallback
+        assert_eq!(generator.primary.0.load(Ordering::Relaxed), 2);
+        assert_eq!(CannedDialogue::new(&[]).generate("").unwrap(), "...");
+    }
+}
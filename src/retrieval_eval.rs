@@ -0,0 +1,244 @@
+// Retrieval quality evaluation
+//
+// Golden datasets pair queries with the memories that should come back for them. A dataset is run
+// against a retrieval backend, either a mock (an in-memory VectorIndex seeded with the dataset's
+// documents and a deterministic embedder) or a live index, and scored with recall@k and mean
+// reciprocal rank. Thresholds turn the scores into a pass/fail check for CI, so a refactor that
+// hurts retrieval fails the build instead of surfacing as an NPC forgetting things in-game. The
+// checked-in datasets live under fixtures/retrieval and are checked by this module's tests.
+
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::embeddings::Embedder;
+use crate::vector_index::{VectorIndex, VectorIndexConfig, VectorIndexError};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GoldenDocument {
+    pub id: String,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GoldenCase {
+    pub query: String,
+    // Ids of the memories that should be retrieved
+    pub expected: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GoldenDataset {
+    pub name: String,
+    pub k: usize,
+    // Corpus used to seed a mock backend; a live backend already holds it
+    #[serde(default)]
+    pub documents: Vec<GoldenDocument>,
+    pub cases: Vec<GoldenCase>,
+}
+
+#[derive(Debug)]
+pub enum EvalError {
+    Io(std::io::Error),
+    Parse(serde_json::Error),
+    Index(VectorIndexError),
+    Backend(String),
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::Io(err) => write!(f, "dataset io error: {}", err),
+            EvalError::Parse(err) => write!(f, "invalid golden dataset: {}", err),
+            EvalError::Index(err) => write!(f, "{}", err),
+            EvalError::Backend(err) => write!(f, "retrieval backend error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+impl From<std::io::Error> for EvalError {
+    fn from(err: std::io::Error) -> Self {
+        EvalError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for EvalError {
+    fn from(err: serde_json::Error) -> Self {
+        EvalError::Parse(err)
+    }
+}
+
+impl From<VectorIndexError> for EvalError {
+    fn from(err: VectorIndexError) -> Self {
+        EvalError::Index(err)
+    }
+}
+
+impl GoldenDataset {
+    pub fn load(path: &Path) -> Result<Self, EvalError> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), EvalError> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+// Anything that returns ranked memory ids for a query
+pub trait RetrievalBackend {
+    fn retrieve(&self, query: &str, k: usize) -> Result<Vec<String>, Box<dyn Error>>;
+}
+
+// A collection of a VectorIndex searched with search_text
+pub struct IndexBackend<'a> {
+    pub index: &'a VectorIndex,
+    pub collection: String,
+    pub embedder: &'a dyn Embedder,
+}
+
+impl RetrievalBackend for IndexBackend<'_> {
+    fn retrieve(&self, query: &str, k: usize) -> Result<Vec<String>, Box<dyn Error>> {
+        let results = self.index.search_text(&self.collection, query, k, self.embedder)?;
+        Ok(results.into_iter().map(|r| r.id).collect())
+    }
+}
+
+// Fresh in-memory index holding the dataset's documents in collection "golden"
+pub fn mock_index(dataset: &GoldenDataset, embedder: &dyn Embedder) -> Result<VectorIndex, EvalError> {
+    let mut index = VectorIndex::new(VectorIndexConfig {
+        url: String::new(),
+        api_key: String::new(),
+        default_ttl_secs: None,
+        collection_ttl_secs: Default::default(),
+    });
+    index.create_collection("golden", embedder.dimension(), embedder.model())?;
+    for document in &dataset.documents {
+        index.store_text("golden", &document.id, &document.text, Default::default(), embedder)?;
+    }
+    Ok(index)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CaseResult {
+    pub query: String,
+    pub retrieved: Vec<String>,
+    // Share of expected ids found in the top k
+    pub recall: f64,
+    // 1 / rank of the first expected id, 0 when none was found
+    pub reciprocal_rank: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EvalReport {
+    pub dataset: String,
+    pub k: usize,
+    pub recall_at_k: f64,
+    pub mrr: f64,
+    pub cases: Vec<CaseResult>,
+}
+
+impl EvalReport {
+    // Cases that retrieved none of their expected memories
+    pub fn misses(&self) -> impl Iterator<Item = &CaseResult> {
+        self.cases.iter().filter(|c| c.reciprocal_rank == 0.0)
+    }
+}
+
+pub fn evaluate(dataset: &GoldenDataset, backend: &dyn RetrievalBackend) -> Result<EvalReport, EvalError> {
+    let k = dataset.k.max(1);
+    let mut cases = Vec::with_capacity(dataset.cases.len());
+    for case in &dataset.cases {
+        let mut retrieved = backend.retrieve(&case.query, k).map_err(|err| EvalError::Backend(err.to_string()))?;
+        retrieved.truncate(k);
+        let found = case.expected.iter().filter(|id| retrieved.contains(id)).count();
+        let recall = if case.expected.is_empty() { 1.0 } else { found as f64 / case.expected.len() as f64 };
+        let reciprocal_rank = retrieved
+            .iter()
+            .position(|id| case.expected.contains(id))
+            .map_or(0.0, |rank| 1.0 / (rank + 1) as f64);
+        cases.push(CaseResult { query: case.query.clone(), retrieved, recall, reciprocal_rank });
+    }
+    let n = cases.len().max(1) as f64;
+    Ok(EvalReport {
+        dataset: dataset.name.clone(),
+        k,
+        recall_at_k: cases.iter().map(|c| c.recall).sum::<f64>() / n,
+        mrr: cases.iter().map(|c| c.reciprocal_rank).sum::<f64>() / n,
+        cases,
+    })
+}
+
+// Minimum acceptable scores
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Thresholds {
+    pub min_recall_at_k: f64,
+    pub min_mrr: f64,
+}
+
+impl Thresholds {
+    // Allow scores to drop by at most `tolerance` from a recorded baseline report
+    pub fn from_baseline(baseline: &EvalReport, tolerance: f64) -> Self {
+        Thresholds {
+            min_recall_at_k: (baseline.recall_at_k - tolerance).max(0.0),
+            min_mrr: (baseline.mrr - tolerance).max(0.0),
+        }
+    }
+
+    // One message per metric below its threshold
+    pub fn check(&self, report: &EvalReport) -> Result<(), Vec<String>> {
+        let mut failures = Vec::new();
+        if report.recall_at_k < self.min_recall_at_k {
+            failures.push(format!(
+                "{}: recall@{} {:.3} is below {:.3}",
+                report.dataset, report.k, report.recall_at_k, self.min_recall_at_k
+            ));
+        }
+        if report.mrr < self.min_mrr {
+            failures.push(format!("{}: MRR {:.3} is below {:.3}", report.dataset, report.mrr, self.min_mrr));
+        }
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(failures)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::HashEmbedder;
+
+    const NPC_MEMORIES: &str = include_str!("../fixtures/retrieval/npc_memories.json");
+
+    // The mock backend below scores 1.0 on both; one slipped case fails the check
+    const NPC_MEMORIES_THRESHOLDS: Thresholds = Thresholds { min_recall_at_k: 0.95, min_mrr: 0.95 };
+
+    #[test]
+    fn golden_dataset_meets_thresholds() {
+        let dataset: GoldenDataset = serde_json::from_str(NPC_MEMORIES).unwrap();
+        let embedder = HashEmbedder::new(256);
+        let index = mock_index(&dataset, &embedder).unwrap();
+        let backend = IndexBackend { index: &index, collection: "golden".to_string(), embedder: &embedder };
+        let report = evaluate(&dataset, &backend).unwrap();
+        assert_eq!(report.cases.len(), dataset.cases.len());
+        if let Err(failures) = NPC_MEMORIES_THRESHOLDS.check(&report) {
+            let misses: Vec<&str> = report.misses().map(|c| c.query.as_str()).collect();
+            panic!("{}; missed: {:?}", failures.join("; "), misses);
+        }
+    }
+
+    #[test]
+    fn thresholds_report_each_metric_below_baseline() {
+        let report = EvalReport { dataset: "d".to_string(), k: 3, recall_at_k: 0.5, mrr: 0.25, cases: Vec::new() };
+        let baseline = EvalReport { recall_at_k: 0.9, mrr: 0.8, ..report.clone() };
+        let failures = Thresholds::from_baseline(&baseline, 0.1).check(&report).unwrap_err();
+        assert_eq!(failures, vec!["d: recall@3 0.500 is below 0.800", "d: MRR 0.250 is below 0.700"]);
+        assert!(Thresholds::from_baseline(&report, 0.0).check(&report).is_ok());
+    }
+}
@@ -0,0 +1,253 @@
+// Trust-weighted recall for rumors
+//
+// An NPC answering "have you heard anything about the mine?" should lean on what the captain told
+// them over what a known liar in the tavern said. Memories carry their source in the payload: who
+// said it, the faction they speak for and how trustworthy the source seemed when it was stored
+// (for gossip, the belief's confidence after retellings). At recall time the listener's own view
+// is mixed in from the relationship graph: their affinity towards the source, and towards the
+// source's faction, which is kept as an entity of its own ("faction:miners_guild").
+//
+// `recall` over-fetches by similarity, scores each hit's trust in 0..1 from whichever of the three
+// signals exist, discounts its similarity by
+//
+//   adjusted = score * (1 - weight + weight * trust)
+//
+// drops hits below `min_trust` and re-ranks. Memories without a source count as first-hand and are
+// fully trusted.
+
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+use crate::gossip::Belief;
+use crate::relationships::Relationships;
+use crate::vector_index::{SearchResult, VectorIndex, VectorIndexError};
+
+// Payload fields describing where a memory came from
+pub const SOURCE_FIELD: &str = "source";
+pub const SOURCE_FACTION_FIELD: &str = "source_faction";
+pub const SOURCE_TRUST_FIELD: &str = "source_trust";
+
+// Entity id under which a faction appears in the relationship graph
+pub fn faction_entity(faction: &str) -> String {
+    format!("faction:{}", faction)
+}
+
+// Payload fields for a memory told by `source`
+pub fn source_payload(source: &str, faction: Option<&str>, trust: f32) -> HashMap<String, Value> {
+    let mut payload = HashMap::from([
+        (SOURCE_FIELD.to_string(), Value::from(source)),
+        (SOURCE_TRUST_FIELD.to_string(), Value::from(trust.clamp(0.0, 1.0) as f64)),
+    ]);
+    if let Some(faction) = faction {
+        payload.insert(SOURCE_FACTION_FIELD.to_string(), Value::from(faction));
+    }
+    payload
+}
+
+// Payload fields for a memory of something heard through gossip
+pub fn belief_payload(belief: &Belief, faction: Option<&str>) -> HashMap<String, Value> {
+    source_payload(&belief.source, faction, belief.confidence)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrustWeights {
+    pub stored: f32,
+    pub affinity: f32,
+    pub faction: f32,
+}
+
+impl Default for TrustWeights {
+    fn default() -> Self {
+        TrustWeights { stored: 0.5, affinity: 0.3, faction: 0.2 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecallMode {
+    // Similarity only
+    Plain,
+    // `weight` in 0..1 is how much low trust discounts a hit
+    TrustWeighted { weight: f32, min_trust: f32 },
+}
+
+#[derive(Debug, Clone)]
+pub struct TrustedRecall {
+    pub result: SearchResult,
+    pub source: Option<String>,
+    pub trust: f32,
+    pub adjusted_score: f32,
+}
+
+pub struct TrustModel<'a> {
+    pub weights: TrustWeights,
+    relationships: Option<&'a Relationships>,
+}
+
+impl<'a> TrustModel<'a> {
+    pub fn new(relationships: Option<&'a Relationships>) -> Self {
+        TrustModel { weights: TrustWeights::default(), relationships }
+    }
+
+    pub fn with_weights(mut self, weights: TrustWeights) -> Self {
+        self.weights = weights;
+        self
+    }
+
+    // How far `listener` trusts a memory with this payload, 0..1
+    pub fn trust(&self, listener: &str, payload: &HashMap<String, Value>) -> f32 {
+        let Some(source) = payload.get(SOURCE_FIELD).and_then(Value::as_str) else { return 1.0 };
+        if source == listener {
+            return 1.0;
+        }
+        // (signal in 0..1, weight) for each signal that exists
+        let mut signals = Vec::with_capacity(3);
+        if let Some(stored) = payload.get(SOURCE_TRUST_FIELD).and_then(Value::as_f64) {
+            signals.push((stored as f32, self.weights.stored));
+        }
+        if let Some(relationships) = self.relationships {
+            if let Some(relationship) = relationships.get(listener, source) {
+                signals.push(((relationship.affinity + 1.0) / 2.0, self.weights.affinity));
+            }
+            let faction = payload.get(SOURCE_FACTION_FIELD).and_then(Value::as_str);
+            if let Some(relationship) = faction.and_then(|faction| relationships.get(listener, &faction_entity(faction))) {
+                signals.push(((relationship.affinity + 1.0) / 2.0, self.weights.faction));
+            }
+        }
+        let total: f32 = signals.iter().map(|(_, weight)| weight.max(0.0)).sum();
+        if total <= 0.0 {
+            // A source nobody knows anything about
+            return 0.5;
+        }
+        let trust = signals.iter().map(|(signal, weight)| signal.clamp(0.0, 1.0) * weight.max(0.0)).sum::<f32>() / total;
+        trust.clamp(0.0, 1.0)
+    }
+
+    // Top `limit` memories for `listener`, ranked by similarity discounted by source trust
+    pub fn recall(
+        &self,
+        index: &VectorIndex,
+        collection: &str,
+        listener: &str,
+        vector: &[f32],
+        limit: usize,
+        mode: RecallMode,
+    ) -> Result<Vec<TrustedRecall>, VectorIndexError> {
+        let (weight, min_trust, depth) = match mode {
+            RecallMode::Plain => (0.0, 0.0, limit),
+            // Discounting can promote hits from below the plain top `limit`
+            RecallMode::TrustWeighted { weight, min_trust } => {
+                (weight.clamp(0.0, 1.0), min_trust, limit.saturating_mul(3).max(limit + 10))
+            }
+        };
+        let mut recalled: Vec<TrustedRecall> = index
+            .search(collection, vector, depth)?
+            .into_iter()
+            .map(|result| {
+                let trust = self.trust(listener, &result.payload);
+                let source = result.payload.get(SOURCE_FIELD).and_then(Value::as_str).map(str::to_string);
+                let adjusted_score = result.score * (1.0 - weight + weight * trust);
+                TrustedRecall { result, source, trust, adjusted_score }
+            })
+            .filter(|recall| recall.trust >= min_trust)
+            .collect();
+        recalled.sort_by(|a, b| b.adjusted_score.total_cmp(&a.adjusted_score).then_with(|| a.result.id.cmp(&b.result.id)));
+        recalled.truncate(limit);
+        Ok(recalled)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gossip::Fact;
+    use crate::relationships::RelationshipConfig;
+    use crate::symbolic::RelationType;
+    use crate::vector_index::{VectorIndexConfig, VectorPoint};
+
+    fn relationships() -> Relationships {
+        let mut relationships = Relationships::new(RelationshipConfig::default());
+        relationships.interact("guard", "captain", 0.6, 0.0, None);
+        relationships.interact("guard", "liar", -0.8, 0.0, None);
+        relationships.interact("guard", &faction_entity("miners_guild"), 1.0, 0.0, None);
+        relationships
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-5
+    }
+
+    #[test]
+    fn payloads_record_the_source() {
+        let payload = source_payload("captain", Some("watch"), 1.7);
+        assert_eq!(payload[SOURCE_FIELD], "captain");
+        assert_eq!(payload[SOURCE_FACTION_FIELD], "watch");
+        assert_eq!(payload[SOURCE_TRUST_FIELD], 1.0);
+
+        let fact = Fact::new("mine", &RelationType::Custom("Flooded".to_string()), "lower_shaft");
+        let belief = Belief {
+            fact,
+            source: "liar".to_string(),
+            origin: "player".to_string(),
+            hops: 3,
+            confidence: 0.25,
+            learned_at: 0.0,
+            distorted: true,
+        };
+        let payload = belief_payload(&belief, None);
+        assert_eq!((payload[SOURCE_FIELD].as_str(), payload[SOURCE_TRUST_FIELD].as_f64()), (Some("liar"), Some(0.25)));
+        assert!(!payload.contains_key(SOURCE_FACTION_FIELD));
+    }
+
+    #[test]
+    fn trust_mixes_the_signals_that_exist() {
+        let relationships = relationships();
+        let model = TrustModel::new(Some(&relationships));
+        // First-hand, self-told and unknown sources
+        assert_eq!(model.trust("guard", &HashMap::new()), 1.0);
+        assert_eq!(model.trust("guard", &source_payload("guard", None, 0.0)), 1.0);
+        let stranger = HashMap::from([(SOURCE_FIELD.to_string(), Value::from("stranger"))]);
+        assert_eq!(model.trust("guard", &stranger), 0.5);
+
+        assert!(close(TrustModel::new(None).trust("guard", &source_payload("liar", None, 0.2)), 0.2));
+        // Stored 0.2, affinity -0.8 -> 0.1, guild affinity 1.0 -> 1.0
+        let trust = model.trust("guard", &source_payload("liar", Some("miners_guild"), 0.2));
+        assert!(close(trust, 0.2 * 0.5 + 0.1 * 0.3 + 1.0 * 0.2), "{}", trust);
+        let stored_only = model.with_weights(TrustWeights { stored: 1.0, affinity: 0.0, faction: 0.0 });
+        assert!(close(stored_only.trust("guard", &source_payload("liar", Some("miners_guild"), 0.2)), 0.2));
+    }
+
+    #[test]
+    fn trusted_sources_outrank_closer_gossip() {
+        let mut index = VectorIndex::new(VectorIndexConfig {
+            url: String::new(),
+            api_key: String::new(),
+            default_ttl_secs: None,
+            collection_ttl_secs: Default::default(),
+        });
+        index.create_collection("guard", 2, "test").unwrap();
+        for (id, vector, payload) in [
+            ("tavern", vec![1.0, 0.0], source_payload("liar", None, 0.1)),
+            ("briefing", vec![0.8, 0.6], source_payload("captain", None, 0.9)),
+            ("seen", vec![0.0, 1.0], HashMap::new()),
+        ] {
+            let mut point = VectorPoint::new(id, vector);
+            point.payload = payload;
+            index.upsert("guard", point).unwrap();
+        }
+        let relationships = relationships();
+        let model = TrustModel::new(Some(&relationships));
+        let ids = |recalled: Vec<TrustedRecall>| recalled.into_iter().map(|r| r.result.id).collect::<Vec<_>>();
+
+        let plain = model.recall(&index, "guard", "guard", &[1.0, 0.0], 2, RecallMode::Plain).unwrap();
+        assert_eq!(ids(plain), vec!["tavern", "briefing"]);
+        let weighted = RecallMode::TrustWeighted { weight: 0.8, min_trust: 0.0 };
+        let recalled = model.recall(&index, "guard", "guard", &[1.0, 0.0], 2, weighted).unwrap();
+        assert_eq!(recalled[0].source.as_deref(), Some("captain"));
+        assert!(recalled[0].adjusted_score < recalled[0].result.score);
+        assert_eq!(ids(recalled), vec!["briefing", "tavern"]);
+
+        let picky = RecallMode::TrustWeighted { weight: 0.8, min_trust: 0.5 };
+        assert_eq!(ids(model.recall(&index, "guard", "guard", &[1.0, 0.0], 3, picky).unwrap()), vec!["briefing", "seen"]);
+    }
+}
@@ -0,0 +1,268 @@
+// Search parameter auto-tuning
+//
+// How many memories to retrieve, the score below which a hit is noise and how much the original
+// query outweighs its expansions all decide what an NPC remembers, and nobody tunes them per
+// collection. A SearchTuner treats a grid of parameter sets as the arms of a bandit (see
+// bandit.rs), with the collection plus an optional situation ("dialogue", "combat_barks") as the
+// context, so each collection learns its own defaults.
+//
+//   let tuned = tuner.choose(&db, "npc_zara", "dialogue")?;
+//   let hits = index.search_with("npc_zara", query, &tuned.params.options(), &embedder)?;
+//   ... the NPC answers, the player reacts ...
+//   tuner.feedback(&mut db, tuned.ticket, Feedback::Positive.reward())?;
+//
+// Rewards come from downstream signals in 0..1: did the player rate the answer well, keep talking,
+// accept the quest. Choices whose feedback never arrives are forgotten after `max_pending`.
+// `status` reports the best arm so far and whether it has converged, i.e. has enough pulls and
+// takes the majority of them; `defaults` returns its parameters once it has.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::agentdb::AgentDb;
+use crate::bandit::{ArmStats, Bandit, BanditError, BanditPolicy, Choice};
+use crate::namespace::Namespace;
+use crate::vector_index::SearchOptions;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SearchParams {
+    pub limit: usize,
+    pub min_score: f32,
+    // Fusion weight of the original query against its expansions
+    pub original_weight: f32,
+}
+
+impl SearchParams {
+    // Arm name, stable so stored statistics survive restarts
+    pub fn arm(&self) -> String {
+        format!("k={}/min={:.2}/w={:.2}", self.limit, self.min_score, self.original_weight)
+    }
+
+    pub fn options<'a>(&self) -> SearchOptions<'a> {
+        SearchOptions::new(self.limit).with_min_score(self.min_score).with_original_weight(self.original_weight)
+    }
+
+    // Every combination of the given values
+    pub fn grid(limits: &[usize], min_scores: &[f32], original_weights: &[f32]) -> Vec<SearchParams> {
+        let mut grid = Vec::new();
+        for &limit in limits {
+            for &min_score in min_scores {
+                for &original_weight in original_weights {
+                    grid.push(SearchParams { limit, min_score, original_weight });
+                }
+            }
+        }
+        grid
+    }
+
+    pub fn default_grid() -> Vec<SearchParams> {
+        SearchParams::grid(&[3, 5, 8, 12], &[0.0, 0.25, 0.4], &[1.0, 2.0])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feedback {
+    Positive,
+    Neutral,
+    Negative,
+}
+
+impl Feedback {
+    pub fn reward(self) -> f64 {
+        match self {
+            Feedback::Positive => 1.0,
+            Feedback::Neutral => 0.5,
+            Feedback::Negative => 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TunedSearch {
+    // Pass back to `feedback`
+    pub ticket: u64,
+    pub params: SearchParams,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TuningStatus {
+    pub context: String,
+    pub total_pulls: u64,
+    // Highest mean reward among arms with at least `min_pulls`
+    pub best: Option<(SearchParams, ArmStats)>,
+    pub converged: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct TunerConfig {
+    // Pulls an arm needs before it can be the tuned default
+    pub min_pulls: u64,
+    // Share of the context's pulls the best arm needs for convergence
+    pub convergence_share: f64,
+    pub max_pending: usize,
+}
+
+impl Default for TunerConfig {
+    fn default() -> Self {
+        TunerConfig { min_pulls: 30, convergence_share: 0.5, max_pending: 4096 }
+    }
+}
+
+pub struct SearchTuner {
+    bandit: Bandit,
+    params: BTreeMap<String, SearchParams>,
+    config: TunerConfig,
+    pending: BTreeMap<u64, Choice>,
+    next_ticket: u64,
+}
+
+impl SearchTuner {
+    pub fn new(grid: &[SearchParams], policy: BanditPolicy, namespace: Namespace, seed: u64) -> Self {
+        let params: BTreeMap<String, SearchParams> = grid.iter().map(|params| (params.arm(), *params)).collect();
+        let arms: Vec<&str> = params.keys().map(String::as_str).collect();
+        SearchTuner {
+            bandit: Bandit::new("search_tuning", &arms, policy, namespace, seed),
+            params,
+            config: TunerConfig::default(),
+            pending: BTreeMap::new(),
+            next_ticket: 1,
+        }
+    }
+
+    pub fn with_config(mut self, config: TunerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    fn context(collection: &str, situation: &str) -> String {
+        if situation.is_empty() {
+            collection.to_string()
+        } else {
+            format!("{}:{}", collection, situation)
+        }
+    }
+
+    // Parameters to use for one search; None when the grid is empty
+    pub fn choose(&mut self, db: &AgentDb, collection: &str, situation: &str) -> Result<Option<TunedSearch>, BanditError> {
+        let Some(choice) = self.bandit.choose(db, &SearchTuner::context(collection, situation))? else {
+            return Ok(None);
+        };
+        let params = self.params[&choice.arm];
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+        self.pending.insert(ticket, choice);
+        while self.pending.len() > self.config.max_pending.max(1) {
+            self.pending.pop_first();
+        }
+        Ok(Some(TunedSearch { ticket, params }))
+    }
+
+    // Reward the parameters behind a search; None when the ticket is unknown or already rewarded
+    pub fn feedback(&mut self, db: &mut AgentDb, ticket: u64, reward: f64) -> Result<Option<ArmStats>, BanditError> {
+        let Some(choice) = self.pending.remove(&ticket) else { return Ok(None) };
+        self.bandit.reward(db, &choice, reward).map(Some)
+    }
+
+    pub fn status(&self, db: &AgentDb, collection: &str, situation: &str) -> Result<TuningStatus, BanditError> {
+        let context = SearchTuner::context(collection, situation);
+        let stats = self.bandit.stats(db, &context)?;
+        let total_pulls: u64 = stats.iter().map(|(_, s)| s.pulls).sum();
+        let best = stats
+            .into_iter()
+            .filter(|(_, s)| s.pulls >= self.config.min_pulls)
+            .max_by(|a, b| a.1.mean().total_cmp(&b.1.mean()).then_with(|| b.0.cmp(&a.0)))
+            .map(|(arm, s)| (self.params[&arm], s));
+        let converged =
+            best.as_ref().is_some_and(|(_, s)| s.pulls as f64 >= self.config.convergence_share * total_pulls as f64);
+        Ok(TuningStatus { context, total_pulls, best, converged })
+    }
+
+    // The converged parameters for a collection, if any
+    pub fn defaults(&self, db: &AgentDb, collection: &str, situation: &str) -> Result<Option<SearchParams>, BanditError> {
+        let status = self.status(db, collection, situation)?;
+        Ok(status.best.filter(|_| status.converged).map(|(params, _)| params))
+    }
+
+    // Forget what was learned for a collection, e.g. after its contents were rebuilt
+    pub fn reset(&mut self, db: &mut AgentDb, collection: &str, situation: &str) {
+        let context = SearchTuner::context(collection, situation);
+        self.bandit.reset(db, &context);
+        self.pending.retain(|_, choice| choice.context != context);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tuner(config: TunerConfig) -> SearchTuner {
+        let grid = SearchParams::grid(&[3, 5, 8], &[0.25], &[1.0]);
+        SearchTuner::new(&grid, BanditPolicy::Ucb1 { exploration: 0.5 }, Namespace::default_namespace(), 7).with_config(config)
+    }
+
+    // Players only like answers built from 8 memories
+    fn play(tuner: &mut SearchTuner, db: &mut AgentDb, collection: &str, rounds: usize) {
+        for _ in 0..rounds {
+            let tuned = tuner.choose(db, collection, "dialogue").unwrap().unwrap();
+            let feedback = if tuned.params.limit == 8 { Feedback::Positive } else { Feedback::Negative };
+            tuner.feedback(db, tuned.ticket, feedback.reward()).unwrap();
+        }
+    }
+
+    #[test]
+    fn grids_name_every_combination() {
+        let grid = SearchParams::grid(&[3, 8], &[0.0, 0.4], &[1.0]);
+        let arms: Vec<String> = grid.iter().map(SearchParams::arm).collect();
+        assert_eq!(arms, vec!["k=3/min=0.00/w=1.00", "k=3/min=0.40/w=1.00", "k=8/min=0.00/w=1.00", "k=8/min=0.40/w=1.00"]);
+        assert_eq!(SearchParams::default_grid().len(), 24);
+        assert_eq!(Feedback::Neutral.reward(), 0.5);
+    }
+
+    #[test]
+    fn converges_on_the_rewarded_parameters() {
+        let mut db = AgentDb::new();
+        let mut tuner = tuner(TunerConfig { min_pulls: 20, ..TunerConfig::default() });
+        play(&mut tuner, &mut db, "npc_zara", 10);
+        assert_eq!(tuner.defaults(&db, "npc_zara", "dialogue").unwrap(), None);
+
+        play(&mut tuner, &mut db, "npc_zara", 190);
+        let status = tuner.status(&db, "npc_zara", "dialogue").unwrap();
+        assert_eq!((status.context.as_str(), status.total_pulls), ("npc_zara:dialogue", 200));
+        assert!(status.converged);
+        assert_eq!(tuner.defaults(&db, "npc_zara", "dialogue").unwrap().unwrap().limit, 8);
+        // Other collections and situations learn on their own
+        assert_eq!(tuner.status(&db, "npc_zara", "").unwrap().total_pulls, 0);
+        assert_eq!(tuner.status(&db, "npc_brom", "dialogue").unwrap().best, None);
+    }
+
+    #[test]
+    fn tickets_are_rewarded_once_and_forgotten_past_the_limit() {
+        let mut db = AgentDb::new();
+        let mut tuner = tuner(TunerConfig { max_pending: 2, ..TunerConfig::default() });
+        let first = tuner.choose(&db, "lore", "").unwrap().unwrap();
+        let second = tuner.choose(&db, "lore", "").unwrap().unwrap();
+        let third = tuner.choose(&db, "lore", "").unwrap().unwrap();
+        assert_eq!(tuner.feedback(&mut db, first.ticket, 1.0).unwrap(), None);
+        assert_eq!(tuner.feedback(&mut db, second.ticket, 1.0).unwrap().unwrap().pulls, 1);
+        assert_eq!(tuner.feedback(&mut db, second.ticket, 1.0).unwrap(), None);
+        assert!(tuner.feedback(&mut db, third.ticket, 0.0).unwrap().is_some());
+    }
+
+    #[test]
+    fn reset_forgets_one_context() {
+        let mut db = AgentDb::new();
+        let mut tuner = tuner(TunerConfig::default());
+        play(&mut tuner, &mut db, "npc_zara", 5);
+        play(&mut tuner, &mut db, "npc_brom", 5);
+        let pending = tuner.choose(&db, "npc_zara", "dialogue").unwrap().unwrap();
+
+        tuner.reset(&mut db, "npc_zara", "dialogue");
+        assert_eq!(tuner.status(&db, "npc_zara", "dialogue").unwrap().total_pulls, 0);
+        assert_eq!(tuner.status(&db, "npc_brom", "dialogue").unwrap().total_pulls, 5);
+        assert_eq!(tuner.feedback(&mut db, pending.ticket, 1.0).unwrap(), None);
+
+        let mut empty = SearchTuner::new(&[], BanditPolicy::Thompson, Namespace::default_namespace(), 1);
+        assert_eq!(empty.choose(&db, "lore", "").unwrap(), None);
+    }
+}
@@ -0,0 +1,446 @@
+// Server-side validation of client-reported actions
+//
+// Clients report what they did; the server checks each report against pluggable rules before
+// applying it. Rules look at the action and the player's recent history (last position, action
+// timestamps, current state). Violations add to a per-player suspicion score that decays over
+// time; crossing the configured thresholds escalates enforcement, and every violation and
+// escalation is published on the event bus so moderation tooling can act on it.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::events::EventBus;
+
+// An action as reported by a client
+#[derive(Debug, Clone)]
+pub struct ClientAction {
+    pub player: String,
+    // "move", "attack", "trade", ...
+    pub action: String,
+    // Client time in seconds
+    pub timestamp: f64,
+    pub position: Option<[f32; 3]>,
+    // State the action puts the player in ("dead", "mounted", "in_combat")
+    pub new_state: Option<String>,
+}
+
+impl ClientAction {
+    pub fn new(player: &str, action: &str, timestamp: f64) -> Self {
+        ClientAction {
+            player: player.to_string(),
+            action: action.to_string(),
+            timestamp,
+            position: None,
+            new_state: None,
+        }
+    }
+
+    pub fn at(mut self, position: [f32; 3]) -> Self {
+        self.position = Some(position);
+        self
+    }
+
+    pub fn entering(mut self, state: &str) -> Self {
+        self.new_state = Some(state.to_string());
+        self
+    }
+}
+
+// What the server remembers about a player between actions
+#[derive(Debug, Clone, Default)]
+pub struct PlayerHistory {
+    pub last_position: Option<([f32; 3], f64)>,
+    pub last_timestamp: Option<f64>,
+    // Recent timestamps per action name
+    pub recent: HashMap<String, VecDeque<f64>>,
+    pub state: Option<String>,
+    pub suspicion: f32,
+    pub enforcement: Enforcement,
+}
+
+#[derive(Debug, Clone)]
+pub struct Violation {
+    pub rule: String,
+    pub player: String,
+    pub action: String,
+    // Suspicion added, roughly 0..1 per incident
+    pub severity: f32,
+    pub detail: String,
+}
+
+pub trait CheatRule: Send {
+    fn name(&self) -> &str;
+    fn check(&self, action: &ClientAction, history: &PlayerHistory) -> Option<Violation>;
+    // Seconds of action history the rule needs
+    fn history_window(&self) -> f64 {
+        0.0
+    }
+}
+
+fn violation(rule: &dyn CheatRule, action: &ClientAction, severity: f32, detail: String) -> Violation {
+    Violation {
+        rule: rule.name().to_string(),
+        player: action.player.clone(),
+        action: action.action.clone(),
+        severity,
+        detail,
+    }
+}
+
+// Movement faster than the game allows
+pub struct SpeedLimit {
+    // Units per second
+    pub max_speed: f32,
+    // Fractional allowance for latency jitter
+    pub tolerance: f32,
+}
+
+impl CheatRule for SpeedLimit {
+    fn name(&self) -> &str {
+        "speed_limit"
+    }
+
+    fn check(&self, action: &ClientAction, history: &PlayerHistory) -> Option<Violation> {
+        let position = action.position?;
+        let (last, at) = history.last_position?;
+        let dt = (action.timestamp - at).max(1e-3) as f32;
+        let distance = position
+            .iter()
+            .zip(last.iter())
+            .map(|(a, b)| (a - b).powi(2))
+            .sum::<f32>()
+            .sqrt();
+        let speed = distance / dt;
+        let limit = self.max_speed * (1.0 + self.tolerance);
+        (speed > limit).then(|| {
+            let severity = ((speed / limit) - 1.0).clamp(0.1, 1.0);
+            violation(self, action, severity, format!("moved at {:.1} u/s, limit {:.1}", speed, limit))
+        })
+    }
+}
+
+// Too many actions of one kind in a sliding window
+pub struct RateLimit {
+    pub action: String,
+    pub max_count: usize,
+    pub window_secs: f64,
+}
+
+impl CheatRule for RateLimit {
+    fn name(&self) -> &str {
+        "rate_limit"
+    }
+
+    fn history_window(&self) -> f64 {
+        self.window_secs
+    }
+
+    fn check(&self, action: &ClientAction, history: &PlayerHistory) -> Option<Violation> {
+        if action.action != self.action {
+            return None;
+        }
+        let count = history
+            .recent
+            .get(&action.action)
+            .map_or(0, |times| times.iter().filter(|t| action.timestamp - **t < self.window_secs).count());
+        (count >= self.max_count).then(|| {
+            violation(self, action, 0.3, format!("{} '{}' actions within {}s", count + 1, self.action, self.window_secs))
+        })
+    }
+}
+
+// State changes the game cannot produce (e.g. "dead" -> "attacking")
+pub struct StateTransitions {
+    pub allowed: HashMap<String, HashSet<String>>,
+}
+
+impl StateTransitions {
+    pub fn new() -> Self {
+        StateTransitions { allowed: HashMap::new() }
+    }
+
+    pub fn allow(mut self, from: &str, to: &str) -> Self {
+        self.allowed.entry(from.to_string()).or_default().insert(to.to_string());
+        self
+    }
+}
+
+impl CheatRule for StateTransitions {
+    fn name(&self) -> &str {
+        "state_transition"
+    }
+
+    fn check(&self, action: &ClientAction, history: &PlayerHistory) -> Option<Violation> {
+        let to = action.new_state.as_ref()?;
+        let from = history.state.as_ref()?;
+        if from == to {
+            return None;
+        }
+        // States without a rule are unconstrained
+        let allowed = self.allowed.get(from)?;
+        (!allowed.contains(to)).then(|| violation(self, action, 0.8, format!("'{}' -> '{}' is not possible", from, to)))
+    }
+}
+
+// Client clocks must not run backwards
+pub struct MonotonicTime;
+
+impl CheatRule for MonotonicTime {
+    fn name(&self) -> &str {
+        "monotonic_time"
+    }
+
+    fn check(&self, action: &ClientAction, history: &PlayerHistory) -> Option<Violation> {
+        let last = history.last_timestamp?;
+        (action.timestamp < last).then(|| {
+            violation(self, action, 0.5, format!("timestamp {:.3} is before {:.3}", action.timestamp, last))
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Enforcement {
+    #[default]
+    None,
+    Warn,
+    Kick,
+    Ban,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AntiCheatConfig {
+    pub max_speed: f32,
+    pub speed_tolerance: f32,
+    // Suspicion lost per second of clean play
+    pub decay_per_sec: f32,
+    pub warn_at: f32,
+    pub kick_at: f32,
+    pub ban_at: f32,
+    // Reject actions that violate any rule instead of only scoring them
+    pub reject_violations: bool,
+}
+
+impl Default for AntiCheatConfig {
+    fn default() -> Self {
+        AntiCheatConfig {
+            max_speed: 10.0,
+            speed_tolerance: 0.25,
+            decay_per_sec: 0.01,
+            warn_at: 1.0,
+            kick_at: 3.0,
+            ban_at: 6.0,
+            reject_violations: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Verdict {
+    pub accepted: bool,
+    pub violations: Vec<Violation>,
+    // Set when this action escalated the player's enforcement level
+    pub escalated_to: Option<Enforcement>,
+}
+
+pub struct AntiCheat {
+    config: AntiCheatConfig,
+    rules: Vec<Box<dyn CheatRule>>,
+    players: HashMap<String, PlayerHistory>,
+    // Action timestamps older than this are forgotten
+    history_secs: f64,
+}
+
+impl AntiCheat {
+    pub fn new(config: AntiCheatConfig) -> Self {
+        AntiCheat {
+            config,
+            rules: Vec::new(),
+            players: HashMap::new(),
+            history_secs: 60.0,
+        }
+    }
+
+    pub fn with_default_rules(config: AntiCheatConfig) -> Self {
+        let mut anti_cheat = AntiCheat::new(config);
+        anti_cheat.add_rule(Box::new(SpeedLimit {
+            max_speed: anti_cheat.config.max_speed,
+            tolerance: anti_cheat.config.speed_tolerance,
+        }));
+        anti_cheat.add_rule(Box::new(MonotonicTime));
+        anti_cheat
+    }
+
+    pub fn add_rule(&mut self, rule: Box<dyn CheatRule>) {
+        self.history_secs = self.history_secs.max(rule.history_window());
+        self.rules.push(rule);
+    }
+
+    pub fn history(&self, player: &str) -> Option<&PlayerHistory> {
+        self.players.get(player)
+    }
+
+    // Server-initiated moves (spawn, teleport) bypass the speed check
+    pub fn teleport(&mut self, player: &str, position: [f32; 3], now: f64) {
+        let history = self.players.entry(player.to_string()).or_default();
+        history.last_position = Some((position, now));
+    }
+
+    pub fn forget(&mut self, player: &str) {
+        self.players.remove(player);
+    }
+
+    // Check a reported action; accepted actions update the player's history
+    pub fn validate(&mut self, action: &ClientAction, events: Option<&mut EventBus>) -> Verdict {
+        let history = self.players.entry(action.player.clone()).or_default();
+        let violations: Vec<Violation> = self.rules.iter().filter_map(|r| r.check(action, history)).collect();
+
+        if let Some(last) = history.last_timestamp {
+            let clean = (action.timestamp - last).max(0.0) as f32;
+            history.suspicion = (history.suspicion - clean * self.config.decay_per_sec).max(0.0);
+        }
+        history.suspicion += violations.iter().map(|v| v.severity).sum::<f32>();
+
+        let level = if history.suspicion >= self.config.ban_at {
+            Enforcement::Ban
+        } else if history.suspicion >= self.config.kick_at {
+            Enforcement::Kick
+        } else if history.suspicion >= self.config.warn_at {
+            Enforcement::Warn
+        } else {
+            Enforcement::None
+        };
+        let escalated_to = (level > history.enforcement).then_some(level);
+        history.enforcement = history.enforcement.max(level);
+
+        let accepted = violations.is_empty() || !self.config.reject_violations;
+        if accepted {
+            if let Some(position) = action.position {
+                history.last_position = Some((position, action.timestamp));
+            }
+            if let Some(state) = &action.new_state {
+                history.state = Some(state.clone());
+            }
+            let times = history.recent.entry(action.action.clone()).or_default();
+            times.push_back(action.timestamp);
+            while times.front().is_some_and(|t| action.timestamp - t > self.history_secs) {
+                times.pop_front();
+            }
+        }
+        history.last_timestamp = Some(history.last_timestamp.map_or(action.timestamp, |t| t.max(action.timestamp)));
+
+        if let Some(events) = events {
+            for v in &violations {
+                events.emit(
+                    "security.violation",
+                    "anti_cheat",
+                    json!({ "player": v.player, "rule": v.rule, "action": v.action, "severity": v.severity, "detail": v.detail }),
+                );
+            }
+            if let Some(level) = escalated_to {
+                events.emit(
+                    "security.enforcement",
+                    "anti_cheat",
+                    json!({ "player": action.player, "level": format!("{:?}", level).to_lowercase(), "suspicion": history.suspicion }),
+                );
+            }
+        }
+        Verdict { accepted, violations, escalated_to }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn anti_cheat() -> AntiCheat {
+        let mut anti_cheat = AntiCheat::with_default_rules(AntiCheatConfig::default());
+        anti_cheat.teleport("p1", [0.0; 3], 0.0);
+        anti_cheat
+    }
+
+    fn rules(verdict: &Verdict) -> Vec<&str> {
+        verdict.violations.iter().map(|v| v.rule.as_str()).collect()
+    }
+
+    #[test]
+    fn speed_limit_allows_tolerance_and_rejects_teleports() {
+        let mut anti_cheat = anti_cheat();
+        let verdict = anti_cheat.validate(&ClientAction::new("p1", "move", 1.0).at([12.0, 0.0, 0.0]), None);
+        assert!(verdict.accepted);
+
+        let verdict = anti_cheat.validate(&ClientAction::new("p1", "move", 2.0).at([100.0, 0.0, 0.0]), None);
+        assert!(!verdict.accepted);
+        assert_eq!(rules(&verdict), vec!["speed_limit"]);
+        // A rejected move does not become the new reference position
+        assert_eq!(anti_cheat.history("p1").unwrap().last_position, Some(([12.0, 0.0, 0.0], 1.0)));
+
+        anti_cheat.teleport("p1", [100.0, 0.0, 0.0], 2.0);
+        assert!(anti_cheat.validate(&ClientAction::new("p1", "move", 3.0).at([101.0, 0.0, 0.0]), None).accepted);
+    }
+
+    #[test]
+    fn rate_limit_and_clock_rules() {
+        let mut anti_cheat = anti_cheat();
+        anti_cheat.add_rule(Box::new(RateLimit { action: "attack".to_string(), max_count: 2, window_secs: 1.0 }));
+        assert!(anti_cheat.validate(&ClientAction::new("p1", "attack", 1.0), None).accepted);
+        assert!(anti_cheat.validate(&ClientAction::new("p1", "attack", 1.2), None).accepted);
+        assert_eq!(rules(&anti_cheat.validate(&ClientAction::new("p1", "attack", 1.4), None)), vec!["rate_limit"]);
+        assert!(anti_cheat.validate(&ClientAction::new("p1", "attack", 2.5), None).accepted);
+
+        let verdict = anti_cheat.validate(&ClientAction::new("p1", "emote", 2.0), None);
+        assert_eq!(rules(&verdict), vec!["monotonic_time"]);
+    }
+
+    #[test]
+    fn impossible_state_transitions_are_rejected() {
+        let mut anti_cheat = anti_cheat();
+        anti_cheat.add_rule(Box::new(StateTransitions::new().allow("dead", "respawning")));
+        assert!(anti_cheat.validate(&ClientAction::new("p1", "die", 1.0).entering("dead"), None).accepted);
+        let verdict = anti_cheat.validate(&ClientAction::new("p1", "attack", 2.0).entering("attacking"), None);
+        assert_eq!(rules(&verdict), vec!["state_transition"]);
+        assert!(anti_cheat.validate(&ClientAction::new("p1", "respawn", 3.0).entering("respawning"), None).accepted);
+        // States without a rule are unconstrained
+        assert!(anti_cheat.validate(&ClientAction::new("p1", "attack", 4.0).entering("attacking"), None).accepted);
+    }
+
+    #[test]
+    fn suspicion_escalates_decays_and_is_published() {
+        let config = AntiCheatConfig { warn_at: 0.4, kick_at: 0.9, decay_per_sec: 0.1, ..Default::default() };
+        let mut anti_cheat = AntiCheat::with_default_rules(config);
+        let mut events = EventBus::new(16);
+        let sub = events.subscribe("security.");
+
+        anti_cheat.validate(&ClientAction::new("p1", "move", 10.0), None);
+        let verdict = anti_cheat.validate(&ClientAction::new("p1", "move", 9.0), Some(&mut events));
+        assert_eq!(verdict.escalated_to, Some(Enforcement::Warn));
+        let verdict = anti_cheat.validate(&ClientAction::new("p1", "move", 9.5), Some(&mut events));
+        assert_eq!(verdict.escalated_to, Some(Enforcement::Kick));
+
+        let topics: Vec<String> = events.drain(sub).into_iter().map(|e| e.topic).collect();
+        assert_eq!(
+            topics,
+            vec!["security.violation", "security.enforcement", "security.violation", "security.enforcement"]
+        );
+
+        // Ten clean seconds burn off a full point of suspicion but never lower the enforcement level
+        anti_cheat.validate(&ClientAction::new("p1", "move", 20.0), None);
+        let history = anti_cheat.history("p1").unwrap();
+        assert_eq!(history.suspicion, 0.0);
+        assert_eq!(history.enforcement, Enforcement::Kick);
+    }
+
+    #[test]
+    fn scoring_only_mode_accepts_violations() {
+        let config = AntiCheatConfig { reject_violations: false, ..Default::default() };
+        let mut anti_cheat = AntiCheat::with_default_rules(config);
+        anti_cheat.teleport("p1", [0.0; 3], 0.0);
+        let verdict = anti_cheat.validate(&ClientAction::new("p1", "move", 1.0).at([50.0, 0.0, 0.0]), None);
+        assert!(verdict.accepted);
+        assert_eq!(verdict.violations.len(), 1);
+        assert!(anti_cheat.history("p1").unwrap().suspicion > 0.0);
+    }
+}
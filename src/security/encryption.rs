@@ -0,0 +1,288 @@
+// Encryption at rest
+//
+// Agent memory and save files can hold sensitive player data. Files are sealed with
+// XChaCha20-Poly1305; the key comes from a KeyProvider, either derived from a passphrase with
+// Argon2id or supplied by an external key service. Encrypted files start with a magic header, so
+// loading detects them and decrypts transparently. Once encryption is configured a plaintext file
+// is an error, since a swapped-in unsealed file would otherwise be trusted; while migrating an
+// existing install, `allow_plaintext` lets files from before encryption was switched on load
+// until they are next written, sealed.
+//
+// Layout: MAGIC (8) | salt (16) | nonce (24) | ciphertext + tag
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use argon2::Argon2;
+use serde::Deserialize;
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+
+pub const MAGIC: &[u8; 8] = b"ARCENC01";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = MAGIC.len() + SALT_LEN + NONCE_LEN;
+
+#[derive(Debug)]
+pub enum EncryptionError {
+    // Encrypted data was found but no key was configured
+    MissingKey,
+    // Plaintext data was found while encryption is configured without `allow_plaintext`
+    Unencrypted,
+    KeyDerivation(String),
+    KeyProvider(String),
+    // Wrong key or tampered data
+    Decryption,
+    Truncated,
+    Io(std::io::Error),
+}
+
+impl fmt::Display for EncryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncryptionError::MissingKey => write!(f, "data is encrypted but no key is configured"),
+            EncryptionError::Unencrypted => write!(f, "data is not encrypted but encryption is configured"),
+            EncryptionError::KeyDerivation(err) => write!(f, "key derivation failed: {}", err),
+            EncryptionError::KeyProvider(err) => write!(f, "key provider failed: {}", err),
+            EncryptionError::Decryption => write!(f, "decryption failed: wrong key or corrupted data"),
+            EncryptionError::Truncated => write!(f, "encrypted data is truncated"),
+            EncryptionError::Io(err) => write!(f, "encryption io error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for EncryptionError {}
+
+impl From<std::io::Error> for EncryptionError {
+    fn from(err: std::io::Error) -> Self {
+        EncryptionError::Io(err)
+    }
+}
+
+// Supplies the 256-bit file key; `salt` is stored in each file header
+pub trait KeyProvider: Send + Sync {
+    fn key(&self, salt: &[u8]) -> Result<[u8; 32], EncryptionError>;
+}
+
+// Argon2id over a player- or operator-supplied passphrase
+pub struct PassphraseKey {
+    passphrase: String,
+}
+
+impl PassphraseKey {
+    pub fn new(passphrase: &str) -> Self {
+        PassphraseKey { passphrase: passphrase.to_string() }
+    }
+}
+
+impl KeyProvider for PassphraseKey {
+    fn key(&self, salt: &[u8]) -> Result<[u8; 32], EncryptionError> {
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(self.passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| EncryptionError::KeyDerivation(e.to_string()))?;
+        Ok(key)
+    }
+}
+
+// A raw key from a KMS, keychain or secret store; the salt is ignored
+pub struct StaticKey([u8; 32]);
+
+impl StaticKey {
+    pub fn new(key: [u8; 32]) -> Self {
+        StaticKey(key)
+    }
+
+    // 64 hex characters, e.g. from an environment variable
+    pub fn from_hex(hex: &str) -> Result<Self, EncryptionError> {
+        let hex = hex.trim().as_bytes();
+        if hex.len() != 64 || !hex.iter().all(u8::is_ascii_hexdigit) {
+            return Err(EncryptionError::KeyProvider("expected 64 hex characters".to_string()));
+        }
+        let mut key = [0u8; 32];
+        for (byte, pair) in key.iter_mut().zip(hex.chunks(2)) {
+            *byte = (hex_value(pair[0]) << 4) | hex_value(pair[1]);
+        }
+        Ok(StaticKey(key))
+    }
+}
+
+// `digit` is already known to be an ASCII hex digit
+fn hex_value(digit: u8) -> u8 {
+    match digit {
+        b'0'..=b'9' => digit - b'0',
+        b'a'..=b'f' => digit - b'a' + 10,
+        _ => digit - b'A' + 10,
+    }
+}
+
+impl KeyProvider for StaticKey {
+    fn key(&self, _salt: &[u8]) -> Result<[u8; 32], EncryptionError> {
+        Ok(self.0)
+    }
+}
+
+// The [encryption] section of aiTOML: a passphrase or a raw `key` of 64 hex characters
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct EncryptionConfig {
+    #[serde(default)]
+    pub passphrase: Option<String>,
+    #[serde(default)]
+    pub key: Option<String>,
+    // Migration mode, see `Encryption::allow_plaintext`
+    #[serde(default)]
+    pub allow_plaintext: bool,
+}
+
+impl EncryptionConfig {
+    pub fn build(&self) -> Result<Encryption, EncryptionError> {
+        let encryption = match (&self.passphrase, &self.key) {
+            (Some(passphrase), None) => Encryption::with_passphrase(passphrase),
+            (None, Some(key)) => Encryption::new(Box::new(StaticKey::from_hex(key)?)),
+            _ => return Err(EncryptionError::KeyProvider("set either a passphrase or a key".to_string())),
+        };
+        Ok(if self.allow_plaintext { encryption.allow_plaintext() } else { encryption })
+    }
+}
+
+pub struct Encryption {
+    provider: Box<dyn KeyProvider>,
+    allow_plaintext: bool,
+}
+
+impl Encryption {
+    pub fn new(provider: Box<dyn KeyProvider>) -> Self {
+        Encryption { provider, allow_plaintext: false }
+    }
+
+    // Migration mode: plaintext data is accepted as-is instead of failing with Unencrypted
+    pub fn allow_plaintext(mut self) -> Self {
+        self.allow_plaintext = true;
+        self
+    }
+
+    pub fn with_passphrase(passphrase: &str) -> Self {
+        Encryption::new(Box::new(PassphraseKey::new(passphrase)))
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let key = self.provider.key(&salt)?;
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(&key));
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let mut out = Vec::with_capacity(HEADER_LEN + plaintext.len() + 16);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&salt);
+        // Magic and salt are authenticated alongside the payload
+        let ciphertext = cipher
+            .encrypt(&nonce, Payload { msg: plaintext, aad: &out })
+            .map_err(|_| EncryptionError::Decryption)?;
+        out.extend_from_slice(&nonce);
+        out.extend_from_slice(&ciphertext);
+        Ok(out)
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, EncryptionError> {
+        if !is_encrypted(data) {
+            return if self.allow_plaintext { Ok(data.to_vec()) } else { Err(EncryptionError::Unencrypted) };
+        }
+        if data.len() < HEADER_LEN {
+            return Err(EncryptionError::Truncated);
+        }
+        let salt = &data[MAGIC.len()..MAGIC.len() + SALT_LEN];
+        let nonce = XNonce::from_slice(&data[MAGIC.len() + SALT_LEN..HEADER_LEN]);
+        let key = self.provider.key(salt)?;
+        XChaCha20Poly1305::new(Key::from_slice(&key))
+            .decrypt(nonce, Payload { msg: &data[HEADER_LEN..], aad: &data[..MAGIC.len() + SALT_LEN] })
+            .map_err(|_| EncryptionError::Decryption)
+    }
+}
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+// Write a file, sealing it when encryption is configured
+pub fn write_file(path: &Path, contents: &[u8], encryption: Option<&Encryption>) -> Result<(), EncryptionError> {
+    match encryption {
+        Some(encryption) => fs::write(path, encryption.encrypt(contents)?)?,
+        None => fs::write(path, contents)?,
+    }
+    Ok(())
+}
+
+// Read a file written by write_file; plaintext files only load without encryption configured or
+// with `allow_plaintext`
+pub fn read_file(path: &Path, encryption: Option<&Encryption>) -> Result<Vec<u8>, EncryptionError> {
+    let data = fs::read(path)?;
+    match (is_encrypted(&data), encryption) {
+        (false, None) => Ok(data),
+        (_, Some(encryption)) => encryption.decrypt(&data),
+        (true, None) => Err(EncryptionError::MissingKey),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191A1B1C1D1E1F";
+
+    fn encryption() -> Encryption {
+        Encryption::new(Box::new(StaticKey::from_hex(KEY).unwrap()))
+    }
+
+    fn temp_file(name: &str, contents: &[u8]) -> std::path::PathBuf {
+        let path = std::env::temp_dir().join(format!("arcadia-encryption-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn from_hex_parses_and_rejects_non_hex() {
+        assert_eq!(StaticKey::from_hex(KEY).unwrap().0[31], 0x1f);
+        assert!(StaticKey::from_hex(&KEY[..62]).is_err());
+        assert!(StaticKey::from_hex(&format!("{}zz", &KEY[..62])).is_err());
+        // Multi-byte characters must not panic on a char boundary
+        let wide = format!("{}é", &KEY[..62]);
+        assert_eq!(wide.len(), 64);
+        assert!(StaticKey::from_hex(&wide).is_err());
+    }
+
+    #[test]
+    fn config_builds_from_exactly_one_key_source() {
+        let config = EncryptionConfig { key: Some(KEY.to_string()), allow_plaintext: true, ..Default::default() };
+        let encryption = config.build().unwrap();
+        assert_eq!(encryption.decrypt(b"{}").unwrap(), b"{}");
+        let both = EncryptionConfig { passphrase: Some("p".to_string()), ..config };
+        assert!(both.build().is_err());
+        assert!(EncryptionConfig::default().build().is_err());
+    }
+
+    #[test]
+    fn round_trips_and_rejects_tampering() {
+        let encryption = encryption();
+        let mut sealed = encryption.encrypt(b"memories").unwrap();
+        assert!(is_encrypted(&sealed));
+        assert_eq!(encryption.decrypt(&sealed).unwrap(), b"memories");
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        assert!(matches!(encryption.decrypt(&sealed), Err(EncryptionError::Decryption)));
+    }
+
+    #[test]
+    fn plaintext_files_need_the_migration_flag_once_encryption_is_configured() {
+        let path = temp_file("plain", b"{}");
+        assert!(matches!(read_file(&path, Some(&encryption())), Err(EncryptionError::Unencrypted)));
+        assert_eq!(read_file(&path, Some(&encryption().allow_plaintext())).unwrap(), b"{}");
+        assert_eq!(read_file(&path, None).unwrap(), b"{}");
+
+        write_file(&path, b"{}", Some(&encryption())).unwrap();
+        assert_eq!(read_file(&path, Some(&encryption())).unwrap(), b"{}");
+        assert!(matches!(read_file(&path, None), Err(EncryptionError::MissingKey)));
+        fs::remove_file(path).unwrap();
+    }
+}
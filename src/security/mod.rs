@@ -0,0 +1,29 @@
+// Security and privacy
+//
+// Feature switches for the security subsystems. Each switch builds the corresponding component,
+// so a disabled feature costs nothing at runtime.
+
+use serde::Deserialize;
+
+pub mod anti_cheat;
+pub mod encryption;
+pub mod privacy;
+pub mod residency;
+
+use anti_cheat::{AntiCheat, AntiCheatConfig};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SecurityFeatures {
+    #[serde(default)]
+    pub anti_cheat_enabled: bool,
+    #[serde(default)]
+    pub anti_cheat: AntiCheatConfig,
+}
+
+impl SecurityFeatures {
+    // Validator with the default rule set, or None when anti-cheat is disabled
+    pub fn anti_cheat(&self) -> Option<AntiCheat> {
+        self.anti_cheat_enabled
+            .then(|| AntiCheat::with_default_rules(self.anti_cheat.clone()))
+    }
+}
@@ -0,0 +1,781 @@
+// Player data export and deletion (GDPR access and erasure requests)
+//
+// Player data is spread over several stores: experiences and memories in the vector index and its
+// archive tier, telemetry and progress records in agentdb, emotional profiles and timelines in the
+// emotion system, skill and playstyle estimates in the player model store, promises and deals in
+// the commitment tracker, scores and match placements on the leaderboards, party, mute state and
+// last message in the chat relay, appeals in the moderation desk, rating profiles and their audit
+// trail in the content rating enforcer. Each store implements PlayerDataStore; the PrivacyManager
+// fans a request out to every registered store, bundles exports into one portable JSON archive and
+// re-checks every store after a deletion so the report can state that nothing is left.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::agentdb::AgentDb;
+use crate::archive::VectorArchive;
+use crate::commitments::CommitmentTracker;
+use crate::emotion::timeline::{EmotionTimelines, FEELINGS};
+use crate::emotion::EmotionAdaptiveExperiences;
+use crate::leaderboards::{LeaderboardBackend, Leaderboards};
+use crate::multiplayer::chat::ChatRelay;
+use crate::multiplayer::moderation::ModerationDesk;
+use crate::player_model::PlayerModelStore;
+use crate::rating::{AuditEntry, ContentRating};
+use crate::vector_index::{unix_now, VectorIndex, VectorIndexError, PLAYER_FIELD};
+
+#[derive(Debug)]
+pub enum PrivacyError {
+    Store { store: String, message: String },
+    Io(std::io::Error),
+    Serialization(serde_json::Error),
+}
+
+impl fmt::Display for PrivacyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrivacyError::Store { store, message } => write!(f, "{}: {}", store, message),
+            PrivacyError::Io(err) => write!(f, "privacy io error: {}", err),
+            PrivacyError::Serialization(err) => write!(f, "privacy serialization error: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for PrivacyError {}
+
+impl From<std::io::Error> for PrivacyError {
+    fn from(err: std::io::Error) -> Self {
+        PrivacyError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for PrivacyError {
+    fn from(err: serde_json::Error) -> Self {
+        PrivacyError::Serialization(err)
+    }
+}
+
+fn store_error(store: &str, err: impl fmt::Display) -> PrivacyError {
+    PrivacyError::Store { store: store.to_string(), message: err.to_string() }
+}
+
+// A subsystem holding data about players
+pub trait PlayerDataStore {
+    // Section name in the export archive
+    fn name(&self) -> &str;
+    fn export(&self, player_id: &str) -> Result<Value, PrivacyError>;
+    // Returns the number of items removed
+    fn delete(&mut self, player_id: &str) -> Result<usize, PrivacyError>;
+    // Items still held about the player
+    fn count(&self, player_id: &str) -> usize;
+}
+
+// Points whose payload names the player
+impl PlayerDataStore for VectorIndex {
+    fn name(&self) -> &str {
+        "vectors"
+    }
+
+    fn export(&self, player_id: &str) -> Result<Value, PrivacyError> {
+        let mut collections = serde_json::Map::new();
+        for name in self.collection_names() {
+            let collection = self.collection(name).map_err(|e| store_error("vectors", e))?;
+            let points: Vec<Value> = collection
+                .points
+                .values()
+                .filter(|p| belongs_to(&p.payload, player_id))
+                .map(|p| json!({ "id": p.id, "payload": p.payload }))
+                .collect();
+            if !points.is_empty() {
+                collections.insert(name.to_string(), Value::Array(points));
+            }
+        }
+        Ok(Value::Object(collections))
+    }
+
+    fn delete(&mut self, player_id: &str) -> Result<usize, PrivacyError> {
+        let names: Vec<String> = self.collection_names().into_iter().map(str::to_string).collect();
+        let mut removed = 0;
+        for name in names {
+            let ids = player_point_ids(self, &name, player_id).map_err(|e| store_error("vectors", e))?;
+            if !ids.is_empty() {
+                removed += VectorIndex::delete(self, &name, &ids).map_err(|e| store_error("vectors", e))?;
+            }
+        }
+        Ok(removed)
+    }
+
+    fn count(&self, player_id: &str) -> usize {
+        self.collection_names()
+            .into_iter()
+            .filter_map(|name| player_point_ids(self, name, player_id).ok())
+            .map(|ids| ids.len())
+            .sum()
+    }
+}
+
+fn belongs_to(payload: &std::collections::HashMap<String, Value>, player_id: &str) -> bool {
+    payload.get(PLAYER_FIELD).and_then(Value::as_str) == Some(player_id)
+}
+
+// Archived copies of forgotten memories, which keep their payload
+impl PlayerDataStore for VectorArchive {
+    fn name(&self) -> &str {
+        "archive"
+    }
+
+    fn export(&self, player_id: &str) -> Result<Value, PrivacyError> {
+        let mut collections = serde_json::Map::new();
+        for (collection, point) in self.iter().filter(|(_, point)| archived_for(&point.payload, player_id)) {
+            let entry = collections.entry(collection.to_string()).or_insert_with(|| json!([]));
+            if let Value::Array(points) = entry {
+                points.push(json!({
+                    "id": point.id,
+                    "payload": point.payload,
+                    "deleted_at": point.deleted_at,
+                    "archived_at": point.archived_at,
+                }));
+            }
+        }
+        Ok(Value::Object(collections))
+    }
+
+    fn delete(&mut self, player_id: &str) -> Result<usize, PrivacyError> {
+        let mut targets: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for (collection, point) in self.iter().filter(|(_, point)| archived_for(&point.payload, player_id)) {
+            targets.entry(collection.to_string()).or_default().push(point.id.clone());
+        }
+        Ok(targets.iter().map(|(collection, ids)| self.purge(collection, ids)).sum())
+    }
+
+    fn count(&self, player_id: &str) -> usize {
+        self.iter().filter(|(_, point)| archived_for(&point.payload, player_id)).count()
+    }
+}
+
+fn archived_for(payload: &BTreeMap<String, Value>, player_id: &str) -> bool {
+    payload.get(PLAYER_FIELD).and_then(Value::as_str) == Some(player_id)
+}
+
+fn player_point_ids(index: &VectorIndex, collection: &str, player_id: &str) -> Result<Vec<String>, VectorIndexError> {
+    Ok(index
+        .collection(collection)?
+        .points
+        .values()
+        .filter(|p| belongs_to(&p.payload, player_id))
+        .map(|p| p.id.clone())
+        .collect())
+}
+
+// Records keyed by the player ("player-1", "player-1:session-4") or carrying a player_id field,
+// in any table and namespace
+impl PlayerDataStore for AgentDb {
+    fn name(&self) -> &str {
+        "records"
+    }
+
+    fn export(&self, player_id: &str) -> Result<Value, PrivacyError> {
+        let mut tables = serde_json::Map::new();
+        for (table, key, value) in self.player_records(player_id) {
+            let entry = tables.entry(table.to_string()).or_insert_with(|| json!({}));
+            entry[key] = value.clone();
+        }
+        Ok(Value::Object(tables))
+    }
+
+    fn delete(&mut self, player_id: &str) -> Result<usize, PrivacyError> {
+        let targets: Vec<(String, String)> = self
+            .player_records(player_id)
+            .into_iter()
+            .map(|(table, key, _)| (table.to_string(), key.to_string()))
+            .collect();
+        Ok(targets
+            .iter()
+            .filter(|(table, key)| self.delete_qualified(table, key).is_some())
+            .count())
+    }
+
+    fn count(&self, player_id: &str) -> usize {
+        self.player_records(player_id).len()
+    }
+}
+
+impl PlayerDataStore for EmotionAdaptiveExperiences {
+    fn name(&self) -> &str {
+        "emotional_profile"
+    }
+
+    fn export(&self, player_id: &str) -> Result<Value, PrivacyError> {
+        let mood = self.player_mood(player_id).map(|m| {
+            json!({ "tension": m.tension, "valence": m.valence, "energy": m.energy })
+        });
+        let measurements: Vec<Value> = self
+            .measurements_for(player_id)
+            .map(|m| {
+                json!({
+                    "source": format!("{:?}", m.source),
+                    "tension": m.mood.tension,
+                    "valence": m.mood.valence,
+                    "energy": m.mood.energy,
+                    "confidence": m.confidence,
+                })
+            })
+            .collect();
+        Ok(json!({ "mood": mood, "measurements": measurements }))
+    }
+
+    fn delete(&mut self, player_id: &str) -> Result<usize, PrivacyError> {
+        Ok(self.forget_player(player_id))
+    }
+
+    fn count(&self, player_id: &str) -> usize {
+        usize::from(self.player_mood(player_id).is_some()) + self.measurements_for(player_id).count()
+    }
+}
+
+// The player's mood history, one entry per remaining bucket
+impl PlayerDataStore for EmotionTimelines {
+    fn name(&self) -> &str {
+        "emotion_timeline"
+    }
+
+    fn export(&self, player_id: &str) -> Result<Value, PrivacyError> {
+        let series: Vec<Value> = self
+            .series(player_id, f64::NEG_INFINITY, f64::INFINITY)
+            .into_iter()
+            .map(|p| {
+                json!({
+                    "start": p.start,
+                    "samples": p.samples,
+                    "tension": p.mean.tension,
+                    "valence": p.mean.valence,
+                    "energy": p.mean.energy,
+                })
+            })
+            .collect();
+        let mut peaks = serde_json::Map::new();
+        for feeling in FEELINGS {
+            let moments: Vec<Value> = self
+                .peaks(player_id, feeling, f64::NEG_INFINITY, f64::INFINITY, usize::MAX, 0.0)
+                .into_iter()
+                .map(|m| json!({ "at": m.at, "score": m.score, "context": m.context }))
+                .collect();
+            peaks.insert(format!("{:?}", feeling).to_lowercase(), Value::Array(moments));
+        }
+        Ok(json!({ "series": series, "peaks": peaks }))
+    }
+
+    fn delete(&mut self, player_id: &str) -> Result<usize, PrivacyError> {
+        let buckets = self.count(player_id);
+        self.forget(player_id);
+        Ok(buckets)
+    }
+
+    fn count(&self, player_id: &str) -> usize {
+        self.series(player_id, f64::NEG_INFINITY, f64::INFINITY).len()
+    }
+}
+
+impl PlayerDataStore for PlayerModelStore {
+    fn name(&self) -> &str {
+        "player_model"
+    }
+
+    fn export(&self, player_id: &str) -> Result<Value, PrivacyError> {
+        let model = self.read(player_id).map_err(|e| store_error("player_model", e))?;
+        Ok(serde_json::to_value(model)?)
+    }
+
+    fn delete(&mut self, player_id: &str) -> Result<usize, PrivacyError> {
+        let removed = self.remove(player_id).map_err(|e| store_error("player_model", e))?;
+        Ok(usize::from(removed))
+    }
+
+    fn count(&self, player_id: &str) -> usize {
+        usize::from(self.contains(player_id))
+    }
+}
+
+// Promises and deals the player made or was made, the break counts kept per pair and unpublished
+// resolutions
+impl PlayerDataStore for CommitmentTracker {
+    fn name(&self) -> &str {
+        "commitments"
+    }
+
+    fn export(&self, player_id: &str) -> Result<Value, PrivacyError> {
+        let commitments: Vec<Value> =
+            self.involving(player_id).map(serde_json::to_value).collect::<Result<_, _>>()?;
+        Ok(Value::Array(commitments))
+    }
+
+    fn delete(&mut self, player_id: &str) -> Result<usize, PrivacyError> {
+        Ok(self.forget_party(player_id))
+    }
+
+    fn count(&self, player_id: &str) -> usize {
+        self.involving(player_id).count() + self.breaks_involving(player_id) + self.pending_involving(player_id)
+    }
+}
+
+// Season scores on every board and placements in stored match results
+impl<B: LeaderboardBackend> PlayerDataStore for Leaderboards<B> {
+    fn name(&self) -> &str {
+        "leaderboards"
+    }
+
+    fn export(&self, player_id: &str) -> Result<Value, PrivacyError> {
+        let entries: Vec<Value> = self
+            .backend()
+            .player_entries(player_id)
+            .map_err(|e| store_error("leaderboards", e))?
+            .into_iter()
+            .map(|(board, season, entry)| {
+                json!({
+                    "board": board,
+                    "season": season,
+                    "score": entry.score,
+                    "submitted_at": entry.submitted_at,
+                    "match_id": entry.match_id,
+                })
+            })
+            .collect();
+        let matches = self.backend().player_matches(player_id).map_err(|e| store_error("leaderboards", e))?;
+        Ok(json!({ "entries": entries, "matches": matches }))
+    }
+
+    fn delete(&mut self, player_id: &str) -> Result<usize, PrivacyError> {
+        self.backend_mut().delete_player(player_id).map_err(|e| store_error("leaderboards", e))
+    }
+
+    fn count(&self, player_id: &str) -> usize {
+        let entries = self.backend().player_entries(player_id).map_or(0, |entries| entries.len());
+        let placements = self.backend().player_matches(player_id).map_or(0, |matches| matches.len());
+        entries + placements
+    }
+}
+
+// Chat participation: party, flood and mute state and the last message sent
+impl PlayerDataStore for ChatRelay {
+    fn name(&self) -> &str {
+        "chat"
+    }
+
+    fn export(&self, player_id: &str) -> Result<Value, PrivacyError> {
+        Ok(self.participant_data(player_id).unwrap_or(Value::Null))
+    }
+
+    fn delete(&mut self, player_id: &str) -> Result<usize, PrivacyError> {
+        Ok(usize::from(self.leave(player_id)))
+    }
+
+    fn count(&self, player_id: &str) -> usize {
+        usize::from(self.is_participant(player_id))
+    }
+}
+
+// Moderation appeals the player filed, with their text, and the audit entries of filing them
+impl PlayerDataStore for ModerationDesk {
+    fn name(&self) -> &str {
+        "moderation"
+    }
+
+    fn export(&self, player_id: &str) -> Result<Value, PrivacyError> {
+        Ok(json!({ "appeals": self.appeals_by(player_id), "audit": self.audit_by(player_id) }))
+    }
+
+    fn delete(&mut self, player_id: &str) -> Result<usize, PrivacyError> {
+        Ok(self.forget_player(player_id))
+    }
+
+    fn count(&self, player_id: &str) -> usize {
+        self.appeals_by(player_id).len() + self.audit_by(player_id).len()
+    }
+}
+
+// The player's rating profile and every audited decision about content shown to them
+impl PlayerDataStore for ContentRating {
+    fn name(&self) -> &str {
+        "content_rating"
+    }
+
+    fn export(&self, player_id: &str) -> Result<Value, PrivacyError> {
+        let audit: Vec<&AuditEntry> = self.audit_log().filter(|entry| entry.player == player_id).collect();
+        Ok(json!({ "profile": self.own_profile(player_id), "audit": audit }))
+    }
+
+    fn delete(&mut self, player_id: &str) -> Result<usize, PrivacyError> {
+        Ok(self.forget_player(player_id))
+    }
+
+    fn count(&self, player_id: &str) -> usize {
+        usize::from(self.own_profile(player_id).is_some())
+            + self.audit_log().filter(|entry| entry.player == player_id).count()
+    }
+}
+
+// Portable bundle of everything held about a player
+#[derive(Debug, Clone, Serialize)]
+pub struct PlayerDataArchive {
+    pub player_id: String,
+    // Unix seconds
+    pub exported_at: u64,
+    pub sections: BTreeMap<String, Value>,
+}
+
+impl PlayerDataArchive {
+    pub fn to_json(&self) -> Result<String, PrivacyError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), PrivacyError> {
+        fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DeletionReport {
+    pub player_id: String,
+    pub removed: BTreeMap<String, usize>,
+    // Items still found per store after deletion; empty when the deletion is verified
+    pub remaining: BTreeMap<String, usize>,
+}
+
+impl DeletionReport {
+    pub fn total_removed(&self) -> usize {
+        self.removed.values().sum()
+    }
+
+    pub fn is_verified(&self) -> bool {
+        self.remaining.is_empty()
+    }
+}
+
+#[derive(Default)]
+pub struct PrivacyManager<'a> {
+    stores: Vec<&'a mut dyn PlayerDataStore>,
+}
+
+impl<'a> PrivacyManager<'a> {
+    pub fn new() -> Self {
+        PrivacyManager { stores: Vec::new() }
+    }
+
+    pub fn register(&mut self, store: &'a mut dyn PlayerDataStore) {
+        self.stores.push(store);
+    }
+
+    pub fn export_player_data(&self, player_id: &str) -> Result<PlayerDataArchive, PrivacyError> {
+        let mut sections = BTreeMap::new();
+        for store in &self.stores {
+            sections.insert(store.name().to_string(), store.export(player_id)?);
+        }
+        Ok(PlayerDataArchive {
+            player_id: player_id.to_string(),
+            exported_at: unix_now(),
+            sections,
+        })
+    }
+
+    // Delete from every store, then count again to verify nothing is left
+    pub fn delete_player_data(&mut self, player_id: &str) -> Result<DeletionReport, PrivacyError> {
+        let mut report = DeletionReport { player_id: player_id.to_string(), ..Default::default() };
+        for store in self.stores.iter_mut() {
+            let removed = store.delete(player_id)?;
+            report.removed.insert(store.name().to_string(), removed);
+        }
+        for store in &self.stores {
+            let remaining = store.count(player_id);
+            if remaining > 0 {
+                report.remaining.insert(store.name().to_string(), remaining);
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector_index::{VectorIndexConfig, VectorPoint};
+
+    fn index() -> VectorIndex {
+        let mut index = VectorIndex::new(VectorIndexConfig {
+            url: String::new(),
+            api_key: String::new(),
+            default_ttl_secs: None,
+            collection_ttl_secs: Default::default(),
+        });
+        index.create_collection("memories", 2, "test").unwrap();
+        for (id, player) in [("m1", "p1"), ("m2", "p1"), ("m3", "p2")] {
+            let mut point = VectorPoint::new(id, vec![1.0, 0.0]);
+            point.payload.insert(PLAYER_FIELD.to_string(), json!(player));
+            index.upsert("memories", point).unwrap();
+        }
+        index
+    }
+
+    // Keeps everything it is asked to delete
+    struct Sticky;
+
+    impl PlayerDataStore for Sticky {
+        fn name(&self) -> &str {
+            "sticky"
+        }
+
+        fn export(&self, _player_id: &str) -> Result<Value, PrivacyError> {
+            Err(store_error("sticky", "backend offline"))
+        }
+
+        fn delete(&mut self, _player_id: &str) -> Result<usize, PrivacyError> {
+            Ok(0)
+        }
+
+        fn count(&self, _player_id: &str) -> usize {
+            1
+        }
+    }
+
+    fn records() -> AgentDb {
+        use crate::namespace::Namespace;
+
+        let namespace = Namespace::new("world").unwrap();
+        let mut db = AgentDb::new();
+        db.put(&namespace, "progress", "p1", json!({ "level": 4 })).unwrap();
+        db.put(&namespace, "sessions", "p1:session-4", json!({ "minutes": 30 })).unwrap();
+        db.put(&namespace, "telemetry", "evt-9", json!({ PLAYER_FIELD: "p1", "kind": "death" })).unwrap();
+        db.put(&namespace, "progress", "p10", json!({ "level": 1 })).unwrap();
+        db
+    }
+
+    #[test]
+    fn export_bundles_every_store_into_one_archive() {
+        use crate::emotion::MoodVector;
+
+        let mut db = records();
+        let mut emotion = EmotionAdaptiveExperiences::new();
+        emotion.set_player_mood("p1", MoodVector::new(0.5, 0.0, 0.5));
+        let mut manager = PrivacyManager::new();
+        manager.register(&mut db);
+        manager.register(&mut emotion);
+
+        let archive = manager.export_player_data("p1").unwrap();
+        assert_eq!(archive.sections.keys().collect::<Vec<_>>(), vec!["emotional_profile", "records"]);
+        assert_eq!(archive.sections["emotional_profile"]["mood"]["tension"], 0.5);
+        let tables = archive.sections["records"].as_object().unwrap();
+        assert_eq!(tables.values().map(|t| t.as_object().unwrap().len()).sum::<usize>(), 3);
+
+        let path = std::env::temp_dir().join(format!("arcadia-privacy-{}.json", std::process::id()));
+        archive.write(&path).unwrap();
+        let written: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(written["player_id"], "p1");
+        assert_eq!(written["sections"], serde_json::to_value(&archive.sections).unwrap());
+    }
+
+    #[test]
+    fn deletion_only_touches_the_players_records() {
+        let mut db = records();
+        let report = {
+            let mut manager = PrivacyManager::new();
+            manager.register(&mut db);
+            manager.delete_player_data("p1").unwrap()
+        };
+        assert!(report.is_verified());
+        assert_eq!(report.total_removed(), 3);
+        assert_eq!(PlayerDataStore::count(&db, "p10"), 1);
+    }
+
+    #[test]
+    fn leftovers_and_store_failures_are_reported() {
+        let mut db = records();
+        let mut sticky = Sticky;
+        let mut manager = PrivacyManager::new();
+        manager.register(&mut db);
+        manager.register(&mut sticky);
+
+        let err = manager.export_player_data("p1").unwrap_err();
+        assert_eq!(err.to_string(), "sticky: backend offline");
+
+        let report = manager.delete_player_data("p1").unwrap();
+        assert!(!report.is_verified());
+        assert_eq!(report.removed["records"], 3);
+        assert_eq!(report.removed["sticky"], 0);
+        assert_eq!(report.remaining.into_iter().collect::<Vec<_>>(), vec![("sticky".to_string(), 1)]);
+    }
+
+    #[test]
+    fn deletion_reaches_archived_memories() {
+        let mut index = index();
+        let mut archive = VectorArchive::new();
+        archive.archive(&mut index, "memories", &["m1".to_string()], 100).unwrap();
+        assert_eq!(PlayerDataStore::count(&archive, "p1"), 1);
+        assert_eq!(archive.export("p1").unwrap()["memories"][0]["id"], "m1");
+
+        let report = {
+            let mut manager = PrivacyManager::new();
+            manager.register(&mut index);
+            manager.register(&mut archive);
+            manager.delete_player_data("p1").unwrap()
+        };
+        assert!(report.is_verified());
+        assert_eq!(report.removed["vectors"], 1);
+        assert_eq!(report.removed["archive"], 1);
+        assert!(archive.is_empty());
+        assert_eq!(PlayerDataStore::count(&index, "p2"), 1);
+    }
+
+    #[test]
+    fn deletion_reaches_rating_profiles_and_audit_trail() {
+        use crate::rating::{ContentTags, Rating, RatingProfile, Surface};
+
+        let mut rating = ContentRating::new(RatingProfile::new(Rating::Teen), 100);
+        rating.set_profile("p1", RatingProfile::new(Rating::Everyone)).unwrap();
+        rating.check("p1", Surface::Dialogue, "greeting", &ContentTags::new());
+        rating.check("p2", Surface::Quest, "bandits", &ContentTags::new());
+        assert_eq!(PlayerDataStore::count(&rating, "p1"), 2);
+        assert_eq!(rating.export("p1").unwrap()["audit"][0]["content"], "greeting");
+
+        let mut manager = PrivacyManager::new();
+        manager.register(&mut rating);
+        let report = manager.delete_player_data("p1").unwrap();
+        assert!(report.is_verified());
+        assert_eq!(report.removed["content_rating"], 2);
+        assert_eq!(rating.audit_log().count(), 1);
+        assert!(rating.own_profile("p1").is_none());
+    }
+
+    #[test]
+    fn deletion_reaches_emotion_timelines() {
+        use crate::emotion::timeline::TimelineConfig;
+        use crate::emotion::MoodVector;
+
+        let mut timelines = EmotionTimelines::new(TimelineConfig::default());
+        timelines.record("p1", 5.0, MoodVector::new(0.9, -0.5, 0.8), Some("reactor_escape"));
+        timelines.record("p1", 25.0, MoodVector::new(0.1, 0.5, 0.2), None);
+        timelines.record("npc-1", 5.0, MoodVector::new(0.2, 0.1, 0.3), None);
+        assert_eq!(PlayerDataStore::count(&timelines, "p1"), 2);
+        assert_eq!(timelines.export("p1").unwrap()["peaks"]["fear"][0]["context"], "reactor_escape");
+
+        let mut manager = PrivacyManager::new();
+        manager.register(&mut timelines);
+        let report = manager.delete_player_data("p1").unwrap();
+        assert!(report.is_verified());
+        assert_eq!(report.removed["emotion_timeline"], 2);
+        assert_eq!(PlayerDataStore::count(&timelines, "npc-1"), 1);
+    }
+
+    #[test]
+    fn deletion_reaches_commitments() {
+        use crate::commitments::{Commitment, ReactionPolicy};
+        use crate::namespace::Namespace;
+        use crate::relationships::Relationships;
+
+        let mut tracker = CommitmentTracker::new(ReactionPolicy::default());
+        let mut relationships = Relationships::default();
+        tracker.add(Commitment::new("c1", "p1", "zara", "bring medicine", 0.0));
+        tracker.add(Commitment::new("c2", "zara", "p1", "open the gate", 0.0));
+        tracker.add(Commitment::new("c3", "p2", "zara", "guard the well", 0.0));
+        tracker.breach("c1", &mut relationships, 10.0).unwrap();
+        assert_eq!(PlayerDataStore::count(&tracker, "p1"), 4);
+        assert_eq!(tracker.export("p1").unwrap().as_array().unwrap().len(), 2);
+
+        let namespace = Namespace::new("world").unwrap();
+        let mut db = AgentDb::new();
+        tracker.save(&mut db, &namespace).unwrap();
+        let mut manager = PrivacyManager::new();
+        manager.register(&mut tracker);
+        let report = manager.delete_player_data("p1").unwrap();
+        assert!(report.is_verified());
+        assert_eq!(report.removed["commitments"], 4);
+
+        tracker.save(&mut db, &namespace).unwrap();
+        let mut restored = CommitmentTracker::new(ReactionPolicy::default());
+        assert_eq!(restored.restore(&db, &namespace).unwrap(), 1);
+        assert!(restored.get("c3").is_some());
+    }
+
+    #[test]
+    fn deletion_reaches_leaderboard_entries_and_placements() {
+        use crate::leaderboards::{AgentDbBackend, BoardConfig, MatchResult, ScoreOrder};
+        use crate::namespace::Namespace;
+
+        let backend = AgentDbBackend::new(AgentDb::new(), Namespace::new("arena").unwrap());
+        let mut boards = Leaderboards::new(backend);
+        boards.add_board(BoardConfig::new("duel", ScoreOrder::HigherIsBetter)).unwrap();
+        let result = MatchResult {
+            match_id: "m1".to_string(),
+            finished_at: 10,
+            placements: vec![("p1".to_string(), 30.0), ("p2".to_string(), 20.0)],
+        };
+        boards.record_match(&result, Some("duel")).unwrap();
+        assert_eq!(PlayerDataStore::count(&boards, "p1"), 2);
+        let export = boards.export("p1").unwrap();
+        assert_eq!(export["entries"][0]["board"], "duel");
+        assert_eq!(export["entries"][0]["season"], 1);
+
+        let mut manager = PrivacyManager::new();
+        manager.register(&mut boards);
+        let report = manager.delete_player_data("p1").unwrap();
+        assert!(report.is_verified());
+        assert_eq!(report.removed["leaderboards"], 2);
+        let kept = boards.match_result("m1").unwrap().unwrap();
+        assert_eq!(kept.placements, vec![("p2".to_string(), 20.0)]);
+        assert_eq!(boards.page("duel", None, 0, 10).unwrap().total, 1);
+    }
+
+    #[test]
+    fn deletion_reaches_chat_participants() {
+        use crate::multiplayer::chat::{ChatChannel, ChatConfig, ChatHooks, WordFilter};
+        use crate::spatial::{GridSpec, SpatialIndex};
+
+        let mut chat = ChatRelay::new(ChatConfig::default(), Box::new(WordFilter::new()));
+        chat.join("p1");
+        chat.join("p2");
+        let spatial = SpatialIndex::new(GridSpec::new(4, 4, 1.0));
+        chat.send("p1", ChatChannel::Global, "meet at the well", 1.0, &spatial, ChatHooks::default()).unwrap();
+        assert_eq!(chat.export("p1").unwrap()["last_message"]["text"], "meet at the well");
+
+        let mut manager = PrivacyManager::new();
+        manager.register(&mut chat);
+        let report = manager.delete_player_data("p1").unwrap();
+        assert!(report.is_verified());
+        assert_eq!(report.removed["chat"], 1);
+        assert!(chat.is_participant("p2"));
+    }
+
+    #[test]
+    fn deletion_reaches_moderation_appeals() {
+        use crate::multiplayer::chat::{ChatModerator, WordFilter};
+        use crate::multiplayer::moderation::CachedModerator;
+        use crate::namespace::Namespace;
+
+        let moderator = CachedModerator::new(WordFilter::new().block(&["noob"]), 100);
+        let mut desk = moderator.desk();
+        moderator.review("p1", "you noob");
+        desk.appeal("p1", "you noob", "friendly banter", 1.0).unwrap();
+        assert_eq!(PlayerDataStore::count(&desk, "p1"), 2);
+        assert_eq!(desk.export("p1").unwrap()["appeals"][0]["text"], "you noob");
+
+        let namespace = Namespace::new("chat").unwrap();
+        let mut db = AgentDb::new();
+        desk.save(&mut db, &namespace).unwrap();
+        let mut manager = PrivacyManager::new();
+        manager.register(&mut desk);
+        let report = manager.delete_player_data("p1").unwrap();
+        assert!(report.is_verified());
+        assert_eq!(report.removed["moderation"], 2);
+
+        desk.save(&mut db, &namespace).unwrap();
+        let restored = CachedModerator::new(WordFilter::new(), 100).desk();
+        restored.restore(&db, &namespace).unwrap();
+        assert!(restored.appeals_by("p1").is_empty());
+        assert!(restored.audit_by("p1").is_empty());
+    }
+}
@@ -0,0 +1,461 @@
+// Data residency
+//
+// Console certification and regional law decide where player data may live: EU players' memories
+// in an EU Qdrant cluster, emotional profiles never leaving the device. A ResidencyPolicy maps each
+// player region and data category to a named storage backend, and a Partitioned<T> holds one
+// store handle per backend and hands out the right one for a (player, category) pair. Call sites
+// ask the router for their store instead of holding one, so the routing is enforced in one place:
+// `read` and `write` run an operation on the player's store with the collection or table name
+// qualified by the backend's namespace, and Partitioned<VectorIndex> wraps the player-scoped
+// vector operations (upsert, search, delete) that way.
+//
+//   [data_residency]
+//   default_region = "na"
+//   strict = true                        # players without a region are refused, not defaulted
+//   [data_residency.backends.eu-qdrant]
+//   kind = "qdrant"
+//   url = "https://qdrant.eu.example"
+//   namespace = "eu"                     # partition inside a shared store
+//   [data_residency.backends.device]
+//   kind = "local"                       # local-only: never replicated or uploaded
+//   [data_residency.regions.eu]
+//   default = "eu-qdrant"                # categories not listed below
+//   emotional_profile = "device"
+//
+// Categories match the sections of the privacy export (vectors, records, emotional_profile,
+// player_model), so a Partitioned store can be registered with the PrivacyManager per backend.
+// Anything that copies player data elsewhere (sync, backups, analytics upload) should call
+// `check_transfer` first.
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+
+use serde::Deserialize;
+
+use crate::namespace::{Namespace, NamespaceError};
+use crate::vector_index::{SearchResult, VectorIndex, VectorIndexError, VectorPoint, PLAYER_FIELD};
+
+// Region key in a region table that covers every category not listed
+pub const DEFAULT_CATEGORY_KEY: &str = "default";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DataCategory {
+    // Experiences and memories in the vector index
+    Vectors,
+    // Telemetry and progress records in agentdb
+    Records,
+    EmotionalProfile,
+    PlayerModel,
+}
+
+impl DataCategory {
+    pub const ALL: [DataCategory; 4] =
+        [DataCategory::Vectors, DataCategory::Records, DataCategory::EmotionalProfile, DataCategory::PlayerModel];
+
+    // Same as the PlayerDataStore name of the store holding it
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DataCategory::Vectors => "vectors",
+            DataCategory::Records => "records",
+            DataCategory::EmotionalProfile => "emotional_profile",
+            DataCategory::PlayerModel => "player_model",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        DataCategory::ALL.into_iter().find(|category| category.as_str() == name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BackendKind {
+    Qdrant,
+    Agentdb,
+    // Kept on this machine only
+    Local,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BackendConfig {
+    pub kind: BackendKind,
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub path: Option<String>,
+    // Namespace partition inside a shared store
+    #[serde(default)]
+    pub namespace: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResidencyConfig {
+    pub default_region: String,
+    #[serde(default)]
+    pub strict: bool,
+    pub backends: BTreeMap<String, BackendConfig>,
+    // Region -> category (or "default") -> backend
+    pub regions: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResidencyError {
+    UnknownBackend { region: String, backend: String },
+    UnknownCategory { region: String, category: String },
+    UnknownRegion(String),
+    // A region table that leaves a category without a backend
+    Unrouted { region: String, category: DataCategory },
+    InvalidNamespace(String),
+    // Opening a store for a backend the policy does not declare
+    UndeclaredBackend(String),
+    // Strict policies refuse players that were never assigned a region
+    UnassignedPlayer(String),
+    MissingStore(String),
+    TransferDenied { player: String, category: DataCategory, from: String, to: String },
+    // The routed store itself failed
+    Store(String),
+}
+
+impl fmt::Display for ResidencyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResidencyError::UnknownBackend { region, backend } => {
+                write!(f, "region '{}' routes to unknown backend '{}'", region, backend)
+            }
+            ResidencyError::UnknownCategory { region, category } => {
+                write!(f, "region '{}' names unknown data category '{}'", region, category)
+            }
+            ResidencyError::UnknownRegion(region) => write!(f, "unknown region '{}'", region),
+            ResidencyError::Unrouted { region, category } => {
+                write!(f, "region '{}' has no backend for {}", region, category.as_str())
+            }
+            ResidencyError::InvalidNamespace(err) => write!(f, "{}", err),
+            ResidencyError::UndeclaredBackend(backend) => write!(f, "backend '{}' is not declared in the residency policy", backend),
+            ResidencyError::UnassignedPlayer(player) => write!(f, "player '{}' has no data region", player),
+            ResidencyError::MissingStore(backend) => write!(f, "no store opened for backend '{}'", backend),
+            ResidencyError::TransferDenied { player, category, from, to } => write!(
+                f,
+                "{} of player '{}' must stay in backend '{}', not '{}'",
+                category.as_str(),
+                player,
+                from,
+                to
+            ),
+            ResidencyError::Store(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for ResidencyError {}
+
+impl From<NamespaceError> for ResidencyError {
+    fn from(err: NamespaceError) -> Self {
+        ResidencyError::InvalidNamespace(err.to_string())
+    }
+}
+
+impl From<VectorIndexError> for ResidencyError {
+    fn from(err: VectorIndexError) -> Self {
+        ResidencyError::Store(err.to_string())
+    }
+}
+
+// Where one category of one player's data lives
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    pub region: String,
+    pub backend: String,
+    pub kind: BackendKind,
+    pub namespace: Option<Namespace>,
+}
+
+impl Route {
+    pub fn local_only(&self) -> bool {
+        self.kind == BackendKind::Local
+    }
+
+    // `key` (a collection or table name) inside the backend's namespace partition, if it has one
+    pub fn qualify(&self, key: &str) -> String {
+        match &self.namespace {
+            Some(namespace) => namespace.qualify(key),
+            None => key.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ResidencyPolicy {
+    default_region: String,
+    strict: bool,
+    backends: BTreeMap<String, BackendConfig>,
+    // Fully resolved: every region has every category
+    routes: BTreeMap<(String, DataCategory), String>,
+    players: HashMap<String, String>,
+}
+
+impl ResidencyPolicy {
+    // Every region must route every category (directly or through "default") to a known backend
+    pub fn from_config(config: &ResidencyConfig) -> Result<Self, ResidencyError> {
+        for backend in config.backends.values() {
+            if let Some(namespace) = &backend.namespace {
+                Namespace::new(namespace)?;
+            }
+        }
+        if !config.regions.contains_key(&config.default_region) {
+            return Err(ResidencyError::UnknownRegion(config.default_region.clone()));
+        }
+        let mut routes = BTreeMap::new();
+        for (region, table) in &config.regions {
+            for (key, backend) in table {
+                if key != DEFAULT_CATEGORY_KEY && DataCategory::parse(key).is_none() {
+                    return Err(ResidencyError::UnknownCategory { region: region.clone(), category: key.clone() });
+                }
+                if !config.backends.contains_key(backend) {
+                    return Err(ResidencyError::UnknownBackend { region: region.clone(), backend: backend.clone() });
+                }
+            }
+            for category in DataCategory::ALL {
+                let backend = table
+                    .get(category.as_str())
+                    .or_else(|| table.get(DEFAULT_CATEGORY_KEY))
+                    .ok_or_else(|| ResidencyError::Unrouted { region: region.clone(), category })?;
+                routes.insert((region.clone(), category), backend.clone());
+            }
+        }
+        Ok(ResidencyPolicy {
+            default_region: config.default_region.clone(),
+            strict: config.strict,
+            backends: config.backends.clone(),
+            routes,
+            players: HashMap::new(),
+        })
+    }
+
+    pub fn assign_region(&mut self, player: &str, region: &str) -> Result<(), ResidencyError> {
+        if !self.routes.keys().any(|(known, _)| known == region) {
+            return Err(ResidencyError::UnknownRegion(region.to_string()));
+        }
+        self.players.insert(player.to_string(), region.to_string());
+        Ok(())
+    }
+
+    pub fn region_of(&self, player: &str) -> Result<&str, ResidencyError> {
+        match self.players.get(player) {
+            Some(region) => Ok(region),
+            None if self.strict => Err(ResidencyError::UnassignedPlayer(player.to_string())),
+            None => Ok(&self.default_region),
+        }
+    }
+
+    pub fn route(&self, player: &str, category: DataCategory) -> Result<Route, ResidencyError> {
+        let region = self.region_of(player)?;
+        let backend = &self.routes[&(region.to_string(), category)];
+        let config = &self.backends[backend];
+        Ok(Route {
+            region: region.to_string(),
+            backend: backend.clone(),
+            kind: config.kind,
+            namespace: config.namespace.as_deref().map(Namespace::new).transpose()?,
+        })
+    }
+
+    pub fn backend(&self, name: &str) -> Option<&BackendConfig> {
+        self.backends.get(name)
+    }
+
+    pub fn backend_names(&self) -> impl Iterator<Item = &String> {
+        self.backends.keys()
+    }
+
+    // Refuse copying a player's data anywhere but the backend it is routed to
+    pub fn check_transfer(&self, player: &str, category: DataCategory, destination: &str) -> Result<(), ResidencyError> {
+        let route = self.route(player, category)?;
+        if route.backend == destination {
+            Ok(())
+        } else {
+            Err(ResidencyError::TransferDenied { player: player.to_string(), category, from: route.backend, to: destination.to_string() })
+        }
+    }
+}
+
+// One store handle per backend, chosen by the policy
+pub struct Partitioned<T> {
+    pub policy: ResidencyPolicy,
+    stores: BTreeMap<String, T>,
+}
+
+impl<T> Partitioned<T> {
+    pub fn new(policy: ResidencyPolicy) -> Self {
+        Partitioned { policy, stores: BTreeMap::new() }
+    }
+
+    pub fn open(&mut self, backend: &str, store: T) -> Result<(), ResidencyError> {
+        if self.policy.backend(backend).is_none() {
+            return Err(ResidencyError::UndeclaredBackend(backend.to_string()));
+        }
+        self.stores.insert(backend.to_string(), store);
+        Ok(())
+    }
+
+    pub fn store(&self, player: &str, category: DataCategory) -> Result<(&T, Route), ResidencyError> {
+        let route = self.policy.route(player, category)?;
+        let store = self.stores.get(&route.backend).ok_or_else(|| ResidencyError::MissingStore(route.backend.clone()))?;
+        Ok((store, route))
+    }
+
+    pub fn store_mut(&mut self, player: &str, category: DataCategory) -> Result<(&mut T, Route), ResidencyError> {
+        let route = self.policy.route(player, category)?;
+        let store = self.stores.get_mut(&route.backend).ok_or_else(|| ResidencyError::MissingStore(route.backend.clone()))?;
+        Ok((store, route))
+    }
+
+    // Run `op` on the player's store; it gets `key` qualified for the route
+    pub fn read<R>(&self, player: &str, category: DataCategory, key: &str, op: impl FnOnce(&T, &str) -> R) -> Result<R, ResidencyError> {
+        let (store, route) = self.store(player, category)?;
+        Ok(op(store, &route.qualify(key)))
+    }
+
+    pub fn write<R>(
+        &mut self,
+        player: &str,
+        category: DataCategory,
+        key: &str,
+        op: impl FnOnce(&mut T, &str) -> R,
+    ) -> Result<R, ResidencyError> {
+        let (store, route) = self.store_mut(player, category)?;
+        Ok(op(store, &route.qualify(key)))
+    }
+
+    // Every opened store, e.g. to register each with the PrivacyManager
+    pub fn stores_mut(&mut self) -> impl Iterator<Item = (&String, &mut T)> {
+        self.stores.iter_mut()
+    }
+
+    // Backends some route uses but that were never opened
+    pub fn missing(&self) -> Vec<String> {
+        let mut missing: Vec<String> =
+            self.policy.routes.values().filter(|backend| !self.stores.contains_key(*backend)).cloned().collect();
+        missing.sort();
+        missing.dedup();
+        missing
+    }
+}
+
+// Player-scoped vector operations, routed as DataCategory::Vectors
+impl Partitioned<VectorIndex> {
+    // Create `name` in every opened store, inside each backend's namespace
+    pub fn create_collection(&mut self, name: &str, dimension: usize, model: &str) -> Result<(), ResidencyError> {
+        for (backend, store) in &mut self.stores {
+            let namespace = self.policy.backends[backend].namespace.as_deref().map(Namespace::new).transpose()?;
+            let qualified = namespace.map_or_else(|| name.to_string(), |namespace| namespace.qualify(name));
+            store.create_collection(&qualified, dimension, model)?;
+        }
+        Ok(())
+    }
+
+    // The point is tagged with the player, so privacy export and deletion find it
+    pub fn upsert(&mut self, player: &str, collection: &str, mut point: VectorPoint) -> Result<(), ResidencyError> {
+        point.payload.insert(PLAYER_FIELD.to_string(), player.into());
+        self.write(player, DataCategory::Vectors, collection, |index, collection| index.upsert(collection, point))?.map_err(ResidencyError::from)
+    }
+
+    // Searches the partition holding the player's data
+    pub fn search(&self, player: &str, collection: &str, vector: &[f32], limit: usize) -> Result<Vec<SearchResult>, ResidencyError> {
+        self.read(player, DataCategory::Vectors, collection, |index, collection| index.search(collection, vector, limit))?
+            .map_err(ResidencyError::from)
+    }
+
+    pub fn delete(&mut self, player: &str, collection: &str, ids: &[String]) -> Result<usize, ResidencyError> {
+        self.write(player, DataCategory::Vectors, collection, |index, collection| index.delete(collection, ids))?
+            .map_err(ResidencyError::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector_index::VectorIndexConfig;
+
+    fn policy() -> ResidencyPolicy {
+        let config: ResidencyConfig = toml::from_str(
+            r#"
+            default_region = "na"
+            [backends.na-qdrant]
+            kind = "qdrant"
+            [backends.eu-qdrant]
+            kind = "qdrant"
+            namespace = "eu"
+            [backends.device]
+            kind = "local"
+            [regions.na]
+            default = "na-qdrant"
+            [regions.eu]
+            default = "eu-qdrant"
+            emotional_profile = "device"
+            "#,
+        )
+        .unwrap();
+        ResidencyPolicy::from_config(&config).unwrap()
+    }
+
+    fn index() -> VectorIndex {
+        VectorIndex::new(VectorIndexConfig {
+            url: String::new(),
+            api_key: String::new(),
+            default_ttl_secs: None,
+            collection_ttl_secs: Default::default(),
+        })
+    }
+
+    fn partitioned() -> Partitioned<VectorIndex> {
+        let mut policy = policy();
+        policy.assign_region("anna", "eu").unwrap();
+        let mut stores = Partitioned::new(policy);
+        stores.open("na-qdrant", index()).unwrap();
+        stores.open("eu-qdrant", index()).unwrap();
+        stores.create_collection("memories", 2, "test").unwrap();
+        stores
+    }
+
+    #[test]
+    fn routes_resolve_region_defaults_and_overrides() {
+        let policy = policy();
+        let route = policy.route("bob", DataCategory::Vectors).unwrap();
+        assert_eq!((route.region.as_str(), route.backend.as_str()), ("na", "na-qdrant"));
+        assert_eq!(route.qualify("memories"), "memories");
+
+        let mut policy = policy;
+        policy.assign_region("anna", "eu").unwrap();
+        assert!(policy.route("anna", DataCategory::EmotionalProfile).unwrap().local_only());
+        assert_eq!(policy.route("anna", DataCategory::Vectors).unwrap().qualify("memories"), "eu__memories");
+        assert!(policy.check_transfer("anna", DataCategory::Vectors, "na-qdrant").is_err());
+    }
+
+    #[test]
+    fn eu_player_writes_land_in_the_eu_partition() {
+        let mut stores = partitioned();
+        stores.upsert("anna", "memories", VectorPoint::new("m1", vec![1.0, 0.0])).unwrap();
+        stores.upsert("bob", "memories", VectorPoint::new("m2", vec![1.0, 0.0])).unwrap();
+
+        let (eu, _) = stores.store("anna", DataCategory::Vectors).unwrap();
+        let point = eu.get("eu__memories", "m1").unwrap().unwrap();
+        assert_eq!(point.payload[PLAYER_FIELD], "anna");
+        assert!(eu.get("eu__memories", "m2").unwrap().is_none());
+        let (na, _) = stores.store("bob", DataCategory::Vectors).unwrap();
+        assert!(na.get("memories", "m1").unwrap().is_none());
+
+        let found = stores.search("anna", "memories", &[1.0, 0.0], 5).unwrap();
+        assert_eq!(found.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(), vec!["m1"]);
+        assert_eq!(stores.delete("anna", "memories", &["m1".to_string()]).unwrap(), 1);
+    }
+
+    #[test]
+    fn strict_policies_refuse_unassigned_players() {
+        let mut config: ResidencyConfig =
+            toml::from_str("default_region = \"na\"\n[backends.db]\nkind = \"agentdb\"\n[regions.na]\ndefault = \"db\"\n").unwrap();
+        config.strict = true;
+        let policy = ResidencyPolicy::from_config(&config).unwrap();
+        assert_eq!(policy.route("ghost", DataCategory::Records), Err(ResidencyError::UnassignedPlayer("ghost".to_string())));
+    }
+}
@@ -0,0 +1,314 @@
+// Sharded per-entity state
+//
+// Hot per-entity state (moods, blackboards, perception memories) lives in a fixed number of
+// shards, each behind its own lock, instead of one map behind a global lock. An entity always
+// hashes to the same shard, so single-entity reads and writes only contend with entities in that
+// shard, and a whole-world update runs every shard in parallel: on rayon's pool with the
+// `parallel` feature, on scoped threads otherwise.
+//
+// Every shard counts its lock acquisitions and how many had to wait, with the total time spent
+// waiting, so contention can be measured instead of guessed. `contention_benchmark` compares a
+// single global lock against the sharded layout on a synthetic world.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::Instant;
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+#[derive(Debug, Default)]
+struct Counters {
+    acquisitions: AtomicU64,
+    contended: AtomicU64,
+    wait_nanos: AtomicU64,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ContentionMetrics {
+    pub acquisitions: u64,
+    // Acquisitions that found the lock held
+    pub contended: u64,
+    pub wait_nanos: u64,
+}
+
+impl ContentionMetrics {
+    pub fn contention_rate(&self) -> f64 {
+        if self.acquisitions == 0 {
+            0.0
+        } else {
+            self.contended as f64 / self.acquisitions as f64
+        }
+    }
+
+    fn add(&mut self, other: ContentionMetrics) {
+        self.acquisitions += other.acquisitions;
+        self.contended += other.contended;
+        self.wait_nanos += other.wait_nanos;
+    }
+}
+
+impl fmt::Display for ContentionMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} locks, {} contended ({:.1}%), {:.2} ms waiting",
+            self.acquisitions,
+            self.contended,
+            self.contention_rate() * 100.0,
+            self.wait_nanos as f64 / 1e6
+        )
+    }
+}
+
+struct Shard<T> {
+    entities: Mutex<HashMap<String, T>>,
+    counters: Counters,
+}
+
+impl<T> Shard<T> {
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, T>> {
+        self.counters.acquisitions.fetch_add(1, Ordering::Relaxed);
+        if let Ok(guard) = self.entities.try_lock() {
+            return guard;
+        }
+        let start = Instant::now();
+        let guard = self.entities.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        self.counters.contended.fetch_add(1, Ordering::Relaxed);
+        self.counters.wait_nanos.fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+        guard
+    }
+
+    fn metrics(&self) -> ContentionMetrics {
+        ContentionMetrics {
+            acquisitions: self.counters.acquisitions.load(Ordering::Relaxed),
+            contended: self.counters.contended.load(Ordering::Relaxed),
+            wait_nanos: self.counters.wait_nanos.load(Ordering::Relaxed),
+        }
+    }
+}
+
+pub struct ShardedStore<T> {
+    shards: Vec<Shard<T>>,
+}
+
+impl<T: Send> ShardedStore<T> {
+    // `shards` is rounded up to at least one; a few per core works well
+    pub fn new(shards: usize) -> Self {
+        ShardedStore {
+            shards: (0..shards.max(1))
+                .map(|_| Shard { entities: Mutex::new(HashMap::new()), counters: Counters::default() })
+                .collect(),
+        }
+    }
+
+    // One shard per available core, times four
+    pub fn for_this_machine() -> Self {
+        let cores = thread::available_parallelism().map_or(4, |n| n.get());
+        ShardedStore::new(cores * 4)
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    fn shard(&self, entity: &str) -> &Shard<T> {
+        let mut hasher = DefaultHasher::new();
+        entity.hash(&mut hasher);
+        &self.shards[(hasher.finish() % self.shards.len() as u64) as usize]
+    }
+
+    pub fn insert(&self, entity: &str, value: T) -> Option<T> {
+        self.shard(entity).lock().insert(entity.to_string(), value)
+    }
+
+    pub fn remove(&self, entity: &str) -> Option<T> {
+        self.shard(entity).lock().remove(entity)
+    }
+
+    // Run `f` on one entity's state under its shard lock
+    pub fn with<R>(&self, entity: &str, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        self.shard(entity).lock().get_mut(entity).map(f)
+    }
+
+    pub fn get(&self, entity: &str) -> Option<T>
+    where
+        T: Clone,
+    {
+        self.shard(entity).lock().get(entity).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|s| s.lock().len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Update every entity; shards run in parallel, entities within a shard in turn
+    pub fn update_all<F>(&self, f: F)
+    where
+        F: Fn(&str, &mut T) + Sync,
+    {
+        let update = |shard: &Shard<T>| {
+            for (entity, value) in shard.lock().iter_mut() {
+                f(entity, value);
+            }
+        };
+        #[cfg(feature = "parallel")]
+        self.shards.par_iter().for_each(update);
+        #[cfg(not(feature = "parallel"))]
+        {
+            let workers = thread::available_parallelism().map_or(4, |n| n.get()).min(self.shards.len());
+            let per_worker = self.shards.len().div_ceil(workers);
+            thread::scope(|scope| {
+                for chunk in self.shards.chunks(per_worker) {
+                    scope.spawn(|| chunk.iter().for_each(update));
+                }
+            });
+        }
+    }
+
+    pub fn metrics(&self) -> ContentionMetrics {
+        let mut total = ContentionMetrics::default();
+        for shard in &self.shards {
+            total.add(shard.metrics());
+        }
+        total
+    }
+
+    pub fn reset_metrics(&self) {
+        for shard in &self.shards {
+            shard.counters.acquisitions.store(0, Ordering::Relaxed);
+            shard.counters.contended.store(0, Ordering::Relaxed);
+            shard.counters.wait_nanos.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContentionReport {
+    pub entities: usize,
+    pub threads: usize,
+    pub ticks: usize,
+    pub global_ms: f64,
+    pub global: ContentionMetrics,
+    pub sharded_ms: f64,
+    pub sharded: ContentionMetrics,
+}
+
+impl fmt::Display for ContentionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} entities, {} threads, {} ticks", self.entities, self.threads, self.ticks)?;
+        writeln!(f, "global lock: {:.2} ms, {}", self.global_ms, self.global)?;
+        write!(f, "sharded:     {:.2} ms, {}", self.sharded_ms, self.sharded)
+    }
+}
+
+// Simulated AI tick: every thread updates its slice of entities one lock at a time, once against
+// a single global lock (one shard) and once against the sharded store
+pub fn contention_benchmark(entities: usize, threads: usize, ticks: usize) -> ContentionReport {
+    let ids: Vec<String> = (0..entities).map(|i| format!("npc-{}", i)).collect();
+    let run = |store: &ShardedStore<[f32; 4]>| {
+        for id in &ids {
+            store.insert(id, [0.0; 4]);
+        }
+        store.reset_metrics();
+        let start = Instant::now();
+        for _ in 0..ticks {
+            thread::scope(|scope| {
+                for slice in ids.chunks(entities.div_ceil(threads.max(1)).max(1)) {
+                    scope.spawn(move || {
+                        for id in slice {
+                            store.with(id, |state| {
+                                for (i, v) in state.iter_mut().enumerate() {
+                                    *v = (*v * 0.9 + i as f32 * 0.1).sin();
+                                }
+                            });
+                        }
+                    });
+                }
+            });
+        }
+        (start.elapsed().as_secs_f64() * 1000.0, store.metrics())
+    };
+    let (global_ms, global) = run(&ShardedStore::new(1));
+    let (sharded_ms, sharded) = run(&ShardedStore::new(threads.max(1) * 16));
+    ContentionReport { entities, threads, ticks, global_ms, global, sharded_ms, sharded }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn entities_live_in_one_shard_each() {
+        let store = ShardedStore::<u32>::new(0);
+        assert_eq!(store.shard_count(), 1);
+        let store = ShardedStore::new(8);
+        for i in 0..100 {
+            assert!(store.insert(&format!("npc-{}", i), i).is_none());
+        }
+        assert_eq!(store.len(), 100);
+        assert_eq!(store.insert("npc-7", 70), Some(7));
+        assert_eq!(store.with("npc-7", |v| { *v += 1; *v }), Some(71));
+        assert_eq!(store.get("npc-7"), Some(71));
+        assert_eq!(store.with("ghost", |v| *v), None);
+        assert_eq!(store.remove("npc-7"), Some(71));
+        assert_eq!(store.len(), 99);
+        assert!(ShardedStore::<u8>::for_this_machine().is_empty());
+    }
+
+    #[test]
+    fn update_all_visits_every_entity_once() {
+        let store = ShardedStore::new(16);
+        for i in 0..500 {
+            store.insert(&format!("npc-{}", i), 0u32);
+        }
+        store.update_all(|entity, value| *value += entity.len() as u32);
+        store.update_all(|_, value| *value *= 2);
+        assert_eq!(store.get("npc-0"), Some(10));
+        assert_eq!(store.get("npc-499"), Some(14));
+    }
+
+    #[test]
+    fn lock_waits_are_counted() {
+        let store = ShardedStore::new(4);
+        store.insert("npc", 0);
+        store.reset_metrics();
+        thread::scope(|scope| {
+            store.with("npc", |_| {
+                scope.spawn(|| store.with("npc", |v| *v += 1));
+                // Hold the lock until the other thread is queued behind it
+                while store.metrics().acquisitions < 2 {
+                    thread::yield_now();
+                }
+                thread::sleep(Duration::from_millis(20));
+            });
+        });
+        let metrics = store.metrics();
+        assert_eq!((metrics.acquisitions, metrics.contended), (2, 1));
+        assert!(metrics.wait_nanos > 0);
+        assert_eq!(metrics.contention_rate(), 0.5);
+        assert!(metrics.to_string().starts_with("2 locks, 1 contended (50.0%)"));
+
+        store.reset_metrics();
+        assert_eq!(store.metrics(), ContentionMetrics::default());
+        assert_eq!(ContentionMetrics::default().contention_rate(), 0.0);
+    }
+
+    #[test]
+    fn benchmark_counts_one_lock_per_entity_update() {
+        let report = contention_benchmark(64, 2, 3);
+        assert_eq!((report.global.acquisitions, report.sharded.acquisitions), (192, 192));
+        assert_eq!(report.to_string().lines().count(), 3);
+    }
+}
@@ -0,0 +1,227 @@
+// wgpu compute path for batched cosine similarity
+//
+// Inputs arrive normalised, so the shader only computes dot products: one invocation per
+// (query, corpus row) pair in 8x8 workgroups. Large corpora are split into chunks that respect
+// the adapter's buffer, storage binding and dispatch size limits, given the query batch that is
+// bound alongside each chunk. wgpu reports validation and out-of-memory errors asynchronously
+// (and panics on ones nobody captured), so each dispatch runs inside error scopes and a captured
+// error comes back as SimilarityError::Gpu, which the caller answers by falling back to the CPU.
+
+use wgpu::util::DeviceExt;
+
+use super::{Matrix, SimilarityError};
+
+const WORKGROUP: u32 = 8;
+
+const SHADER: &str = r#"
+struct Dims {
+    queries: u32,
+    corpus: u32,
+    dim: u32,
+    pad: u32,
+}
+
+@group(0) @binding(0) var<uniform> dims: Dims;
+@group(0) @binding(1) var<storage, read> queries: array<f32>;
+@group(0) @binding(2) var<storage, read> corpus: array<f32>;
+@group(0) @binding(3) var<storage, read_write> scores: array<f32>;
+
+@compute @workgroup_size(8, 8)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let q = id.x;
+    let c = id.y;
+    if (q >= dims.queries || c >= dims.corpus) {
+        return;
+    }
+    var acc = 0.0;
+    for (var k = 0u; k < dims.dim; k++) {
+        acc += queries[q * dims.dim + k] * corpus[c * dims.dim + k];
+    }
+    scores[q * dims.corpus + c] = acc;
+}
+"#;
+
+pub struct GpuSimilarity {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    limits: wgpu::Limits,
+}
+
+fn gpu_error(err: impl std::fmt::Display) -> SimilarityError {
+    SimilarityError::Gpu(err.to_string())
+}
+
+impl GpuSimilarity {
+    pub fn open() -> Result<Self, SimilarityError> {
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            compatible_surface: None,
+            force_fallback_adapter: false,
+        }))
+        .ok_or_else(|| gpu_error("no adapter"))?;
+        let limits = adapter.limits();
+        let (device, queue) = pollster::block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("arcadia-similarity"),
+                required_features: wgpu::Features::empty(),
+                required_limits: limits.clone(),
+                memory_hints: wgpu::MemoryHints::Performance,
+            },
+            None,
+        ))
+        .map_err(gpu_error)?;
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("batch-cosine"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("batch-cosine"),
+            layout: None,
+            module: &module,
+            entry_point: "main",
+            compilation_options: Default::default(),
+            cache: None,
+        });
+        Ok(GpuSimilarity { device, queue, pipeline, limits })
+    }
+
+    pub fn batch_cosine(&self, queries: &Matrix, corpus: &Matrix) -> Result<Vec<f32>, SimilarityError> {
+        if queries.rows == 0 || corpus.rows == 0 {
+            return Ok(vec![0.0; queries.rows * corpus.rows]);
+        }
+        let chunk = chunk_rows(&self.limits, queries.rows, corpus.dim)?;
+        let mut scores = vec![0.0f32; queries.rows * corpus.rows];
+        let mut start = 0;
+        while start < corpus.rows {
+            let rows = chunk.min(corpus.rows - start);
+            let part = &corpus.data[start * corpus.dim..(start + rows) * corpus.dim];
+            let chunk_scores = self.dispatch(queries, part, rows)?;
+            for q in 0..queries.rows {
+                let out = q * corpus.rows + start;
+                scores[out..out + rows].copy_from_slice(&chunk_scores[q * rows..(q + 1) * rows]);
+            }
+            start += rows;
+        }
+        Ok(scores)
+    }
+
+    fn dispatch(&self, queries: &Matrix, corpus: &[f32], rows: usize) -> Result<Vec<f32>, SimilarityError> {
+        self.device.push_error_scope(wgpu::ErrorFilter::OutOfMemory);
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let result = self.record_and_read(queries, corpus, rows);
+        let validation = pollster::block_on(self.device.pop_error_scope());
+        let out_of_memory = pollster::block_on(self.device.pop_error_scope());
+        match validation.or(out_of_memory) {
+            Some(err) => Err(gpu_error(err)),
+            None => result,
+        }
+    }
+
+    fn record_and_read(&self, queries: &Matrix, corpus: &[f32], rows: usize) -> Result<Vec<f32>, SimilarityError> {
+        let device = &self.device;
+        let dims = [queries.rows as u32, rows as u32, queries.dim as u32, 0];
+        let dims = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("dims"),
+            contents: bytemuck::cast_slice(&dims),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let query_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("queries"),
+            contents: bytemuck::cast_slice(&queries.data),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let corpus_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("corpus"),
+            contents: bytemuck::cast_slice(corpus),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+        let size = (queries.rows * rows * 4) as u64;
+        let output = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("scores"),
+            size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[
+                wgpu::BindGroupEntry { binding: 0, resource: dims.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 1, resource: query_buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 2, resource: corpus_buffer.as_entire_binding() },
+                wgpu::BindGroupEntry { binding: 3, resource: output.as_entire_binding() },
+            ],
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: None, timestamp_writes: None });
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &bind_group, &[]);
+            pass.dispatch_workgroups(
+                (queries.rows as u32).div_ceil(WORKGROUP),
+                (rows as u32).div_ceil(WORKGROUP),
+                1,
+            );
+        }
+        encoder.copy_buffer_to_buffer(&output, 0, &readback, 0, size);
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = readback.slice(..);
+        let (sender, receiver) = std::sync::mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        device.poll(wgpu::Maintain::Wait);
+        receiver.recv().map_err(gpu_error)?.map_err(gpu_error)?;
+        let scores = bytemuck::cast_slice(&slice.get_mapped_range()).to_vec();
+        readback.unmap();
+        Ok(scores)
+    }
+}
+
+// Corpus rows per dispatch: every buffer must fit both the largest buffer and the largest storage
+// binding the adapter allows, the query batch is bound whole next to each chunk, and the grid
+// must fit the per-dimension workgroup count
+fn chunk_rows(limits: &wgpu::Limits, queries: usize, dim: usize) -> Result<usize, SimilarityError> {
+    let max_bytes = (limits.max_storage_buffer_binding_size as u64).min(limits.max_buffer_size) as usize;
+    let max_rows = limits.max_compute_workgroups_per_dimension as usize * WORKGROUP as usize;
+    if queries > max_rows {
+        return Err(gpu_error("too many queries for one dispatch"));
+    }
+    if queries.saturating_mul(dim.max(1)).saturating_mul(4) > max_bytes {
+        return Err(gpu_error("query batch exceeds the adapter's buffer size limit"));
+    }
+    let by_input = max_bytes / (dim.max(1) * 4);
+    let by_output = max_bytes / (queries.max(1) * 4);
+    Ok(by_input.min(by_output).min(max_rows).max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_respect_the_smaller_of_buffer_and_binding_limits() {
+        let limits = wgpu::Limits { max_storage_buffer_binding_size: 1 << 20, max_buffer_size: 1 << 16, ..Default::default() };
+        // 64 KiB buffers hold 64 corpus rows of dim 256, and 256 scores for each of 64 queries
+        assert_eq!(chunk_rows(&limits, 16, 256).unwrap(), 64);
+        assert_eq!(chunk_rows(&limits, 64, 8).unwrap(), 256);
+    }
+
+    #[test]
+    fn oversized_query_batches_are_refused() {
+        let limits = wgpu::Limits { max_buffer_size: 1 << 16, ..Default::default() };
+        assert!(chunk_rows(&limits, 128, 256).is_err());
+        let limits = wgpu::Limits { max_compute_workgroups_per_dimension: 2, ..Default::default() };
+        assert!(chunk_rows(&limits, 17, 4).is_err());
+    }
+}
@@ -0,0 +1,328 @@
+// Batched cosine similarity
+//
+// Scores a batch of queries against a whole corpus at once (query matrix x corpus matrix). The
+// CPU path normalises both sides and computes dot products in fixed 8-wide lanes, which the
+// compiler turns into SIMD on every target we ship. With the `gpu` feature a wgpu compute path is
+// available for desktop builds holding very large in-memory indexes; it is used only for corpora
+// above a configurable size, where upload costs pay off, and any GPU failure falls back to the
+// CPU so callers always get scores. The size can be set per collection, since where the GPU starts
+// to win depends on the vector dimension.
+//
+// `benchmark` times both paths on synthetic data and reports how far their results differ; the
+// `bench-similarity` command prints it.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Instant;
+
+use crate::rng::Rng;
+
+#[cfg(feature = "gpu")]
+mod gpu;
+
+const LANES: usize = 8;
+
+// Row-major dense matrix, one vector per row
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix {
+    pub rows: usize,
+    pub dim: usize,
+    pub data: Vec<f32>,
+}
+
+impl Matrix {
+    // None when rows differ in length
+    pub fn from_rows<V: AsRef<[f32]>>(rows: &[V]) -> Option<Self> {
+        let dim = rows.first().map_or(0, |r| r.as_ref().len());
+        if rows.iter().any(|r| r.as_ref().len() != dim) {
+            return None;
+        }
+        let data = rows.iter().flat_map(|r| r.as_ref().iter().copied()).collect();
+        Some(Matrix { rows: rows.len(), dim, data })
+    }
+
+    pub fn row(&self, index: usize) -> &[f32] {
+        &self.data[index * self.dim..(index + 1) * self.dim]
+    }
+
+    // Every row scaled to unit length; zero rows stay zero so they score 0 against everything
+    pub fn normalized(&self) -> Matrix {
+        let mut data = self.data.clone();
+        if self.dim > 0 {
+            for row in data.chunks_exact_mut(self.dim) {
+                let norm = dot(row, row).sqrt();
+                if norm > 0.0 {
+                    row.iter_mut().for_each(|x| *x /= norm);
+                }
+            }
+        }
+        Matrix { rows: self.rows, dim: self.dim, data }
+    }
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    let mut acc = [0.0f32; LANES];
+    let (a_chunks, b_chunks) = (a.chunks_exact(LANES), b.chunks_exact(LANES));
+    let tail: f32 = a_chunks.remainder().iter().zip(b_chunks.remainder()).map(|(x, y)| x * y).sum();
+    for (x, y) in a_chunks.zip(b_chunks) {
+        for lane in 0..LANES {
+            acc[lane] += x[lane] * y[lane];
+        }
+    }
+    acc.iter().sum::<f32>() + tail
+}
+
+// Scores of every query against every corpus row, row-major (query index * corpus rows + row)
+pub fn cpu_batch_cosine(queries: &Matrix, corpus: &Matrix) -> Vec<f32> {
+    let queries = queries.normalized();
+    let corpus = corpus.normalized();
+    let mut scores = Vec::with_capacity(queries.rows * corpus.rows);
+    for q in 0..queries.rows {
+        let query = queries.row(q);
+        scores.extend((0..corpus.rows).map(|c| dot(query, corpus.row(c))));
+    }
+    scores
+}
+
+#[derive(Debug)]
+pub enum SimilarityError {
+    DimensionMismatch { queries: usize, corpus: usize },
+    Gpu(String),
+}
+
+impl fmt::Display for SimilarityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SimilarityError::DimensionMismatch { queries, corpus } => {
+                write!(f, "query dimension {} does not match corpus dimension {}", queries, corpus)
+            }
+            SimilarityError::Gpu(message) => write!(f, "gpu similarity failed: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for SimilarityError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    Cpu,
+    Gpu,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchScores {
+    pub queries: usize,
+    pub corpus: usize,
+    // Row-major, see cpu_batch_cosine
+    pub scores: Vec<f32>,
+    pub backend: Backend,
+}
+
+impl BatchScores {
+    pub fn row(&self, query: usize) -> &[f32] {
+        &self.scores[query * self.corpus..(query + 1) * self.corpus]
+    }
+
+    // Best `k` corpus rows for `query` as (row, score), best first
+    pub fn top_k(&self, query: usize, k: usize) -> Vec<(usize, f32)> {
+        let mut ranked: Vec<(usize, f32)> = self.row(query).iter().copied().enumerate().collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        ranked.truncate(k);
+        ranked
+    }
+}
+
+// Picks the CPU or GPU path per call
+pub struct BatchSimilarity {
+    // Corpora with at least this many rows go to the GPU when one is available
+    pub gpu_min_rows: usize,
+    // Per-collection overrides of gpu_min_rows
+    pub collection_min_rows: HashMap<String, usize>,
+    #[cfg(feature = "gpu")]
+    gpu: Option<gpu::GpuSimilarity>,
+}
+
+impl BatchSimilarity {
+    pub const DEFAULT_GPU_MIN_ROWS: usize = 50_000;
+
+    // CPU only
+    pub fn cpu() -> Self {
+        BatchSimilarity {
+            gpu_min_rows: usize::MAX,
+            collection_min_rows: HashMap::new(),
+            #[cfg(feature = "gpu")]
+            gpu: None,
+        }
+    }
+
+    // Uses a GPU adapter for large corpora if the feature is enabled and one can be opened
+    pub fn new(gpu_min_rows: usize) -> Self {
+        BatchSimilarity {
+            gpu_min_rows,
+            collection_min_rows: HashMap::new(),
+            #[cfg(feature = "gpu")]
+            gpu: gpu::GpuSimilarity::open().ok(),
+        }
+    }
+
+    pub fn gpu_available(&self) -> bool {
+        #[cfg(feature = "gpu")]
+        {
+            self.gpu.is_some()
+        }
+        #[cfg(not(feature = "gpu"))]
+        {
+            false
+        }
+    }
+
+    pub fn set_collection_min_rows(&mut self, collection: &str, rows: usize) {
+        self.collection_min_rows.insert(collection.to_string(), rows);
+    }
+
+    // Corpus size at which `collection` goes to the GPU
+    pub fn gpu_min_rows_for(&self, collection: &str) -> usize {
+        self.collection_min_rows.get(collection).copied().unwrap_or(self.gpu_min_rows)
+    }
+
+    pub fn cosine(&self, queries: &Matrix, corpus: &Matrix) -> Result<BatchScores, SimilarityError> {
+        self.cosine_above(self.gpu_min_rows, queries, corpus)
+    }
+
+    // Like `cosine`, with the GPU threshold of `collection`
+    pub fn cosine_for(&self, collection: &str, queries: &Matrix, corpus: &Matrix) -> Result<BatchScores, SimilarityError> {
+        self.cosine_above(self.gpu_min_rows_for(collection), queries, corpus)
+    }
+
+    #[cfg_attr(not(feature = "gpu"), allow(unused_variables))]
+    fn cosine_above(&self, gpu_min_rows: usize, queries: &Matrix, corpus: &Matrix) -> Result<BatchScores, SimilarityError> {
+        if queries.dim != corpus.dim && queries.rows > 0 && corpus.rows > 0 {
+            return Err(SimilarityError::DimensionMismatch { queries: queries.dim, corpus: corpus.dim });
+        }
+        #[cfg(feature = "gpu")]
+        if corpus.rows >= gpu_min_rows {
+            if let Some(gpu) = &self.gpu {
+                match gpu.batch_cosine(&queries.normalized(), &corpus.normalized()) {
+                    Ok(scores) => {
+                        return Ok(BatchScores { queries: queries.rows, corpus: corpus.rows, scores, backend: Backend::Gpu })
+                    }
+                    Err(err) => tracing::warn!("{}; falling back to cpu", err),
+                }
+            }
+        }
+        Ok(self.cosine_cpu(queries, corpus))
+    }
+
+    pub fn cosine_cpu(&self, queries: &Matrix, corpus: &Matrix) -> BatchScores {
+        BatchScores { queries: queries.rows, corpus: corpus.rows, scores: cpu_batch_cosine(queries, corpus), backend: Backend::Cpu }
+    }
+
+    // Force the GPU path, for benchmarks
+    #[cfg(feature = "gpu")]
+    pub fn cosine_gpu(&self, queries: &Matrix, corpus: &Matrix) -> Result<BatchScores, SimilarityError> {
+        let gpu = self.gpu.as_ref().ok_or_else(|| SimilarityError::Gpu("no adapter".to_string()))?;
+        let scores = gpu.batch_cosine(&queries.normalized(), &corpus.normalized())?;
+        Ok(BatchScores { queries: queries.rows, corpus: corpus.rows, scores, backend: Backend::Gpu })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchReport {
+    pub queries: usize,
+    pub corpus: usize,
+    pub dim: usize,
+    // Median milliseconds per batch
+    pub cpu_ms: f64,
+    pub gpu_ms: Option<f64>,
+    // Largest score difference between the paths
+    pub max_abs_diff: Option<f32>,
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} queries x {} rows (dim {}): cpu {:.2} ms", self.queries, self.corpus, self.dim, self.cpu_ms)?;
+        match (self.gpu_ms, self.max_abs_diff) {
+            (Some(gpu), Some(diff)) => write!(f, ", gpu {:.2} ms ({:.1}x), max diff {:.2e}", gpu, self.cpu_ms / gpu, diff),
+            _ => write!(f, ", gpu unavailable"),
+        }
+    }
+}
+
+fn random_matrix(rows: usize, dim: usize, rng: &mut Rng) -> Matrix {
+    Matrix { rows, dim, data: (0..rows * dim).map(|_| rng.range(-1.0, 1.0) as f32).collect() }
+}
+
+fn median_ms(rounds: usize, mut run: impl FnMut()) -> f64 {
+    let mut times: Vec<f64> = (0..rounds.max(1))
+        .map(|_| {
+            let start = Instant::now();
+            run();
+            start.elapsed().as_secs_f64() * 1000.0
+        })
+        .collect();
+    times.sort_by(f64::total_cmp);
+    times[times.len() / 2]
+}
+
+// Time both paths on random data of the given shape
+pub fn benchmark(engine: &BatchSimilarity, queries: usize, corpus: usize, dim: usize, rounds: usize) -> BenchReport {
+    let mut rng = Rng::new(0x5eed);
+    let q = random_matrix(queries, dim, &mut rng);
+    let c = random_matrix(corpus, dim, &mut rng);
+    let cpu_ms = median_ms(rounds, || {
+        engine.cosine_cpu(&q, &c);
+    });
+    #[allow(unused_mut)]
+    let mut report = BenchReport { queries, corpus, dim, cpu_ms, gpu_ms: None, max_abs_diff: None };
+    #[cfg(feature = "gpu")]
+    if let Ok(gpu) = engine.cosine_gpu(&q, &c) {
+        let cpu = engine.cosine_cpu(&q, &c);
+        let diff = cpu.scores.iter().zip(&gpu.scores).map(|(a, b)| (a - b).abs()).fold(0.0f32, f32::max);
+        report.gpu_ms = Some(median_ms(rounds, || {
+            let _ = engine.cosine_gpu(&q, &c);
+        }));
+        report.max_abs_diff = Some(diff);
+    }
+    report
+}
+
+// Handles `bench-similarity [queries] [corpus] [dim]`
+pub fn run_bench_command(args: &[String]) {
+    let arg = |i: usize, default: usize| args.get(i).and_then(|a| a.parse().ok()).unwrap_or(default);
+    let engine = BatchSimilarity::new(0);
+    let shapes = if args.is_empty() {
+        vec![(16, 10_000, 384), (64, 100_000, 384), (256, 200_000, 768)]
+    } else {
+        vec![(arg(0, 64), arg(1, 100_000), arg(2, 384))]
+    };
+    for (queries, corpus, dim) in shapes {
+        println!("{}", benchmark(&engine, queries, corpus, dim, 3));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gpu_threshold_can_be_set_per_collection() {
+        let mut engine = BatchSimilarity::new(1_000);
+        engine.set_collection_min_rows("memories", 10);
+        assert_eq!(engine.gpu_min_rows_for("memories"), 10);
+        assert_eq!(engine.gpu_min_rows_for("lore"), 1_000);
+    }
+
+    #[test]
+    fn cosine_scores_every_pair_and_rejects_mismatched_dimensions() {
+        let engine = BatchSimilarity::cpu();
+        let queries = Matrix::from_rows(&[[1.0, 0.0]]).unwrap();
+        let corpus = Matrix::from_rows(&[[2.0, 0.0], [0.0, 3.0], [0.0, 0.0]]).unwrap();
+        let scores = engine.cosine_for("memories", &queries, &corpus).unwrap();
+        assert_eq!(scores.backend, Backend::Cpu);
+        assert_eq!(scores.row(0), &[1.0, 0.0, 0.0]);
+        assert_eq!(scores.top_k(0, 1), vec![(0, 1.0)]);
+
+        let wide = Matrix::from_rows(&[[1.0, 0.0, 0.0]]).unwrap();
+        assert!(matches!(engine.cosine(&wide, &corpus), Err(SimilarityError::DimensionMismatch { queries: 3, corpus: 2 })));
+    }
+}
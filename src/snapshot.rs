@@ -0,0 +1,365 @@
+// Published snapshots of world state for AI reads
+//
+// AI systems read the world far more often than they change it, and taking an RwLock for every
+// read serialises them at high tick rates. Instead the state they read is double-buffered: the
+// simulation writes to a back buffer, and at the end of the tick `publish` makes it the new
+// front. Readers hold an immutable snapshot (an Arc plus the tick it was published at), so a
+// reader running across a tick boundary keeps a consistent view.
+//
+// The front is a Published<T>, an atomic Arc swap shared with reader threads. `publish` takes
+// `&self`, and `load` never locks: the current snapshot sits in one of two slots picked by an
+// atomic index, a reader registers on the slot, re-checks the index and clones the Arc. A
+// publisher only overwrites the slot readers are not using, after the last reader registered on
+// it has left, so no reader ever sees a snapshot being replaced. Publishers take a mutex among
+// themselves; readers never wait on them.
+//
+// The back buffer is copy-on-write: the first write after a publish clones the state, since the
+// front shares it.
+//
+// WorldSnapshots bundles what the AI systems read: the game world (entities and their
+// components), the emotion view, and each agent's GOAP world state. All three are published as
+// one AiView, so a view never mixes ticks. AI jobs keep a WorldReader and load views from it
+// without touching the simulation.
+
+use std::cell::UnsafeCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::ai::goap::WorldState;
+use crate::emotion::{EmotionAdaptiveExperiences, MoodVector};
+use crate::world::GameWorld;
+
+#[derive(Debug)]
+pub struct Snapshot<T> {
+    tick: u64,
+    state: Arc<T>,
+}
+
+impl<T> Snapshot<T> {
+    // Tick the snapshot was published at
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    // The state itself, e.g. to keep it without the tick
+    pub fn state(&self) -> &Arc<T> {
+        &self.state
+    }
+}
+
+impl<T> Clone for Snapshot<T> {
+    fn clone(&self) -> Self {
+        Snapshot { tick: self.tick, state: Arc::clone(&self.state) }
+    }
+}
+
+impl<T> Deref for Snapshot<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.state
+    }
+}
+
+struct Slot<T> {
+    // Readers between registering on the slot and finishing their clone
+    readers: AtomicUsize,
+    // Written only by a publisher holding `publishing`, and only while no reader is registered
+    snapshot: UnsafeCell<Option<Snapshot<T>>>,
+}
+
+// Atomically swapped snapshot, shared between the publishing thread and readers
+pub struct Published<T> {
+    slots: [Slot<T>; 2],
+    active: AtomicUsize,
+    publishing: Mutex<()>,
+}
+
+// Slots are only written under the protocol above, and a Snapshot hands out shared Arcs
+unsafe impl<T: Send + Sync> Sync for Published<T> {}
+unsafe impl<T: Send + Sync> Send for Published<T> {}
+
+impl<T> Published<T> {
+    pub fn new(initial: T) -> Self {
+        Published {
+            slots: [
+                Slot { readers: AtomicUsize::new(0), snapshot: UnsafeCell::new(Some(Snapshot { tick: 0, state: Arc::new(initial) })) },
+                Slot { readers: AtomicUsize::new(0), snapshot: UnsafeCell::new(None) },
+            ],
+            active: AtomicUsize::new(0),
+            publishing: Mutex::new(()),
+        }
+    }
+
+    // The current snapshot; never blocks
+    pub fn load(&self) -> Snapshot<T> {
+        loop {
+            let active = self.active.load(Ordering::SeqCst);
+            let slot = &self.slots[active];
+            slot.readers.fetch_add(1, Ordering::SeqCst);
+            // Still current after registering, so no publisher can be writing the slot
+            if self.active.load(Ordering::SeqCst) == active {
+                let snapshot = unsafe { (*slot.snapshot.get()).clone() };
+                slot.readers.fetch_sub(1, Ordering::SeqCst);
+                if let Some(snapshot) = snapshot {
+                    return snapshot;
+                }
+            } else {
+                slot.readers.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.load().tick
+    }
+
+    // Make `state` the current snapshot; returns its tick
+    pub fn publish(&self, state: Arc<T>) -> u64 {
+        let _publishing = self.publishing.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let active = self.active.load(Ordering::SeqCst);
+        let next = 1 - active;
+        // Only publishers write slots, and we hold the publishing lock
+        let tick = unsafe { (*self.slots[active].snapshot.get()).as_ref().map_or(0, |s| s.tick) } + 1;
+        self.replace(next, Some(Snapshot { tick, state }));
+        self.active.store(next, Ordering::SeqCst);
+        // Release the previous state once its last reader has its clone
+        self.replace(active, None);
+        tick
+    }
+
+    // Overwrite an inactive slot once the readers still registered on it have left
+    fn replace(&self, index: usize, snapshot: Option<Snapshot<T>>) {
+        let slot = &self.slots[index];
+        while slot.readers.load(Ordering::SeqCst) != 0 {
+            thread::yield_now();
+        }
+        unsafe { *slot.snapshot.get() = snapshot };
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Published<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Published").field("current", &self.load()).finish()
+    }
+}
+
+#[derive(Debug)]
+pub struct DoubleBuffered<T: Clone> {
+    front: Arc<Published<T>>,
+    back: Arc<T>,
+}
+
+impl<T: Clone> DoubleBuffered<T> {
+    pub fn new(initial: T) -> Self {
+        let front = Published::new(initial);
+        let back = Arc::clone(&front.load().state);
+        DoubleBuffered { front: Arc::new(front), back }
+    }
+
+    // Handle for reader threads; loads never lock
+    pub fn reader(&self) -> Arc<Published<T>> {
+        Arc::clone(&self.front)
+    }
+
+    // The published state
+    pub fn snapshot(&self) -> Snapshot<T> {
+        self.front.load()
+    }
+
+    // State being built for the next publish, read-only
+    pub fn back(&self) -> &T {
+        &self.back
+    }
+
+    // State being built for the next publish
+    pub fn write(&mut self) -> &mut T {
+        Arc::make_mut(&mut self.back)
+    }
+
+    // Read the published state while writing the next one
+    pub fn split(&mut self) -> (Snapshot<T>, &mut T) {
+        (self.front.load(), Arc::make_mut(&mut self.back))
+    }
+
+    // Make the back buffer visible to readers; returns the new tick
+    pub fn publish(&mut self) -> u64 {
+        self.front.publish(Arc::clone(&self.back))
+    }
+
+    pub fn published_tick(&self) -> u64 {
+        self.front.tick()
+    }
+}
+
+// What AI systems need from the emotion system
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EmotionView {
+    pub target_mood: MoodVector,
+    pub revision: u64,
+    pub player_moods: HashMap<String, MoodVector>,
+}
+
+impl EmotionView {
+    pub fn capture(emotion: &EmotionAdaptiveExperiences, players: &[&str]) -> Self {
+        EmotionView {
+            target_mood: emotion.target_mood(),
+            revision: emotion.revision(),
+            player_moods: players
+                .iter()
+                .filter_map(|p| emotion.player_mood(p).map(|m| (p.to_string(), m)))
+                .collect(),
+        }
+    }
+}
+
+// One consistent read view for an AI job
+#[derive(Debug, Clone)]
+pub struct AiView {
+    pub world: Arc<GameWorld>,
+    pub emotion: Arc<EmotionView>,
+    pub goap: Arc<HashMap<String, WorldState>>,
+}
+
+impl AiView {
+    pub fn agent_state(&self, agent: &str) -> Option<&WorldState> {
+        self.goap.get(agent)
+    }
+}
+
+// Reader side of WorldSnapshots, cheap to clone into AI jobs
+pub type WorldReader = Arc<Published<AiView>>;
+
+#[derive(Debug)]
+pub struct WorldSnapshots {
+    world: Arc<GameWorld>,
+    emotion: Arc<EmotionView>,
+    // GOAP world state per agent
+    goap: Arc<HashMap<String, WorldState>>,
+    published: WorldReader,
+}
+
+impl WorldSnapshots {
+    pub fn new(world: GameWorld) -> Self {
+        let view = AiView { world: Arc::new(world), emotion: Arc::default(), goap: Arc::default() };
+        WorldSnapshots {
+            world: Arc::clone(&view.world),
+            emotion: Arc::clone(&view.emotion),
+            goap: Arc::clone(&view.goap),
+            published: Arc::new(Published::new(view)),
+        }
+    }
+
+    pub fn reader(&self) -> WorldReader {
+        Arc::clone(&self.published)
+    }
+
+    // View published at the end of the last tick
+    pub fn view(&self) -> Snapshot<AiView> {
+        self.published.load()
+    }
+
+    // Back buffers, written during the tick
+    pub fn world(&self) -> &GameWorld {
+        &self.world
+    }
+
+    pub fn world_mut(&mut self) -> &mut GameWorld {
+        Arc::make_mut(&mut self.world)
+    }
+
+    pub fn emotion_mut(&mut self) -> &mut EmotionView {
+        Arc::make_mut(&mut self.emotion)
+    }
+
+    pub fn goap_mut(&mut self) -> &mut HashMap<String, WorldState> {
+        Arc::make_mut(&mut self.goap)
+    }
+
+    // End of tick: publish everything together so a view never mixes ticks
+    pub fn publish(&self) -> u64 {
+        self.published.publish(Arc::new(AiView {
+            world: Arc::clone(&self.world),
+            emotion: Arc::clone(&self.emotion),
+            goap: Arc::clone(&self.goap),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn readers_see_the_front_until_publish() {
+        let mut buffered = DoubleBuffered::new(1u32);
+        let reader = buffered.reader();
+        *buffered.write() = 2;
+        assert_eq!(*reader.load(), 1);
+        assert_eq!(*buffered.back(), 2);
+
+        let held = reader.load();
+        assert_eq!(buffered.publish(), 1);
+        assert_eq!((*reader.load(), reader.tick()), (2, 1));
+        // A snapshot taken before the publish keeps its state
+        assert_eq!((*held, held.tick()), (1, 0));
+
+        *buffered.write() = 3;
+        assert_eq!(*buffered.snapshot(), 2);
+    }
+
+    #[test]
+    fn publish_and_load_from_other_threads() {
+        let published = Arc::new(Published::new(0u64));
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let published = Arc::clone(&published);
+                thread::spawn(move || {
+                    let mut last = 0;
+                    for _ in 0..20_000 {
+                        let snapshot = published.load();
+                        // The state published at tick n is n
+                        assert_eq!(*snapshot, snapshot.tick());
+                        assert!(snapshot.tick() >= last);
+                        last = snapshot.tick();
+                    }
+                })
+            })
+            .collect();
+        for tick in 1..=2_000u64 {
+            assert_eq!(published.publish(Arc::new(tick)), tick);
+        }
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        assert_eq!(*published.load(), 2_000);
+    }
+
+    #[test]
+    fn views_never_mix_ticks() {
+        let mut snapshots = WorldSnapshots::new(GameWorld::new());
+        let reader = snapshots.reader();
+        let view = thread::spawn(move || {
+            for _ in 0..5_000 {
+                let view = reader.load();
+                let world_tick = view.world.global("tick").and_then(|v| v.as_u64()).unwrap_or(0);
+                assert_eq!(world_tick, view.emotion.revision);
+                assert_eq!(view.agent_state("guard").is_some(), world_tick > 0);
+            }
+        });
+        for tick in 1..=500u64 {
+            snapshots.world_mut().set_global("tick", json!(tick));
+            snapshots.emotion_mut().revision = tick;
+            snapshots.goap_mut().entry("guard".to_string()).or_default();
+            assert_eq!(snapshots.publish(), tick);
+        }
+        view.join().unwrap();
+        assert_eq!(snapshots.view().emotion.revision, 500);
+    }
+}
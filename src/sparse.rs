@@ -0,0 +1,318 @@
+// Sparse term vectors
+//
+// Dense embeddings blur rare proper nouns: "Vel'Kathar" and "Velkarath" land close together, and a
+// name the embedding model never saw contributes little to the vector at all. A sparse vector keeps
+// one weight per term, so an exact name match scores even when the dense neighbourhood is crowded.
+// Points can carry one alongside their dense vector and hybrid search fuses both rankings
+// (vector_index.rs).
+//
+// SparseVector uses the Qdrant layout (parallel `indices` and `values`, indices ascending), so
+// points mirrored to Qdrant map onto its sparse vectors directly. SparseEncoder is the extension
+// point for learned encoders such as SPLADE served elsewhere; TermEncoder is the built-in one: it
+// hashes lowercase terms to ids, saturates repeated terms like BM25 and boosts capitalised words in
+// mid-sentence, which in lore text are mostly names. InvertedIndex is the in-memory search side,
+// with posting lists per term and BM25 idf applied at query time so common terms weigh little.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SparseVector {
+    pub indices: Vec<u32>,
+    pub values: Vec<f32>,
+}
+
+impl SparseVector {
+    // Sums weights of repeated indices and drops zeros
+    pub fn from_pairs(pairs: impl IntoIterator<Item = (u32, f32)>) -> Self {
+        let mut merged: Vec<(u32, f32)> = pairs.into_iter().collect();
+        merged.sort_by_key(|(index, _)| *index);
+        let mut summed: Vec<(u32, f32)> = Vec::with_capacity(merged.len());
+        for (index, value) in merged {
+            match summed.last_mut() {
+                Some((last, total)) if *last == index => *total += value,
+                _ => summed.push((index, value)),
+            }
+        }
+        let (indices, values) = summed.into_iter().filter(|(_, value)| *value != 0.0).unzip();
+        SparseVector { indices, values }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.indices.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u32, f32)> + '_ {
+        self.indices.iter().copied().zip(self.values.iter().copied())
+    }
+
+    pub fn get(&self, index: u32) -> Option<f32> {
+        self.indices.binary_search(&index).ok().map(|i| self.values[i])
+    }
+
+    // Merge walk over the two sorted index lists
+    pub fn dot(&self, other: &SparseVector) -> f32 {
+        let (mut i, mut j, mut sum) = (0, 0, 0.0);
+        while i < self.indices.len() && j < other.indices.len() {
+            match self.indices[i].cmp(&other.indices[j]) {
+                std::cmp::Ordering::Less => i += 1,
+                std::cmp::Ordering::Greater => j += 1,
+                std::cmp::Ordering::Equal => {
+                    sum += self.values[i] * other.values[j];
+                    i += 1;
+                    j += 1;
+                }
+            }
+        }
+        sum
+    }
+
+    // Body of a Qdrant named sparse vector
+    pub fn to_qdrant(&self) -> Value {
+        json!({ "indices": self.indices, "values": self.values })
+    }
+}
+
+// Turns text into a sparse vector. Queries may be encoded differently from documents (SPLADE
+// query encoders often are), so both are separate.
+pub trait SparseEncoder {
+    fn name(&self) -> &str;
+    fn encode(&self, text: &str) -> SparseVector;
+
+    fn encode_query(&self, query: &str) -> SparseVector {
+        self.encode(query)
+    }
+}
+
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "from", "had", "has", "have", "he", "her", "his",
+    "i", "in", "is", "it", "its", "me", "my", "of", "on", "or", "she", "so", "that", "the", "their", "them", "they",
+    "this", "to", "was", "we", "were", "what", "which", "who", "will", "with", "you", "your",
+];
+
+// Hashed-term encoder: no model, no vocabulary file, stable ids across runs
+#[derive(Debug, Clone)]
+pub struct TermEncoder {
+    // BM25 term frequency saturation
+    pub k1: f32,
+    // Weight multiplier for capitalised words that do not start a sentence
+    pub proper_noun_boost: f32,
+    pub min_term_len: usize,
+}
+
+impl Default for TermEncoder {
+    fn default() -> Self {
+        TermEncoder { k1: 1.2, proper_noun_boost: 1.5, min_term_len: 2 }
+    }
+}
+
+impl TermEncoder {
+    pub fn new() -> Self {
+        TermEncoder::default()
+    }
+
+    // (term, looks like a name) for each kept token of one sentence
+    fn terms(&self, text: &str) -> Vec<(String, bool)> {
+        let mut terms = Vec::new();
+        let mut sentence_start = true;
+        for raw in text.split(|c: char| !(c.is_alphanumeric() || c == '\'' || c == '-')) {
+            let token = raw.trim_matches(|c| c == '\'' || c == '-');
+            if token.is_empty() {
+                continue;
+            }
+            let capitalised = token.chars().next().is_some_and(char::is_uppercase);
+            let name = capitalised && !sentence_start;
+            sentence_start = false;
+            let term = token.to_lowercase();
+            if term.chars().count() >= self.min_term_len && !STOPWORDS.contains(&term.as_str()) {
+                terms.push((term, name));
+            }
+        }
+        terms
+    }
+}
+
+impl SparseEncoder for TermEncoder {
+    fn name(&self) -> &str {
+        "terms-fnv1a"
+    }
+
+    fn encode(&self, text: &str) -> SparseVector {
+        let mut counts: HashMap<u32, (f32, bool)> = HashMap::new();
+        for sentence in text.split_inclusive(['.', '!', '?', '\n']) {
+            for (term, name) in self.terms(sentence) {
+                let entry = counts.entry(term_id(&term)).or_insert((0.0, false));
+                entry.0 += 1.0;
+                entry.1 |= name;
+            }
+        }
+        SparseVector::from_pairs(counts.into_iter().map(|(id, (tf, name))| {
+            let weight = tf * (self.k1 + 1.0) / (tf + self.k1);
+            (id, if name { weight * self.proper_noun_boost } else { weight })
+        }))
+    }
+
+    // Names in a query are usually typed however the player likes, so no boost there
+    fn encode_query(&self, query: &str) -> SparseVector {
+        let terms: Vec<u32> = self.terms(query).into_iter().map(|(term, _)| term_id(&term)).collect();
+        SparseVector::from_pairs(terms.into_iter().map(|id| (id, 1.0)))
+    }
+}
+
+// 32-bit FNV-1a of the lowercase term
+pub fn term_id(term: &str) -> u32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    for byte in term.bytes() {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+// Posting lists over the sparse vectors of one collection
+#[derive(Debug, Clone, Default)]
+pub struct InvertedIndex {
+    postings: HashMap<u32, Vec<(String, f32)>>,
+    docs: usize,
+}
+
+impl InvertedIndex {
+    pub fn new() -> Self {
+        InvertedIndex::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.docs
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.docs == 0
+    }
+
+    pub fn insert(&mut self, id: &str, vector: &SparseVector) {
+        if vector.is_empty() {
+            return;
+        }
+        for (index, value) in vector.iter() {
+            self.postings.entry(index).or_default().push((id.to_string(), value));
+        }
+        self.docs += 1;
+    }
+
+    // `vector` must be the one the point was inserted with
+    pub fn remove(&mut self, id: &str, vector: &SparseVector) {
+        if vector.is_empty() {
+            return;
+        }
+        for index in &vector.indices {
+            if let Some(list) = self.postings.get_mut(index) {
+                list.retain(|(doc, _)| doc != id);
+                if list.is_empty() {
+                    self.postings.remove(index);
+                }
+            }
+        }
+        self.docs = self.docs.saturating_sub(1);
+    }
+
+    // BM25 inverse document frequency of a term
+    pub fn idf(&self, index: u32) -> f32 {
+        let df = self.postings.get(&index).map_or(0, Vec::len) as f32;
+        (1.0 + (self.docs as f32 - df + 0.5) / (df + 0.5)).ln()
+    }
+
+    // Top `limit` (id, score) by idf-weighted dot product, over documents `keep` accepts
+    pub fn search(&self, query: &SparseVector, limit: usize, keep: impl Fn(&str) -> bool) -> Vec<(String, f32)> {
+        let mut scores: HashMap<&str, f32> = HashMap::new();
+        for (index, weight) in query.iter() {
+            let Some(list) = self.postings.get(&index) else { continue };
+            let idf = self.idf(index);
+            for (doc, value) in list {
+                *scores.entry(doc.as_str()).or_insert(0.0) += weight * value * idf;
+            }
+        }
+        let mut ranked: Vec<(String, f32)> = scores
+            .into_iter()
+            .filter(|(doc, score)| *score > 0.0 && keep(doc))
+            .map(|(doc, score)| (doc.to_string(), score))
+            .collect();
+        ranked.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        ranked.truncate(limit);
+        ranked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weight(vector: &SparseVector, term: &str) -> Option<f32> {
+        vector.get(term_id(term))
+    }
+
+    #[test]
+    fn pairs_are_sorted_summed_and_zeros_dropped() {
+        let vector = SparseVector::from_pairs([(9, 1.0), (2, 0.5), (9, 2.0), (4, 1.0), (4, -1.0)]);
+        assert_eq!(vector, SparseVector { indices: vec![2, 9], values: vec![0.5, 3.0] });
+        assert_eq!((vector.get(9), vector.get(4)), (Some(3.0), None));
+        assert_eq!(vector.dot(&SparseVector::from_pairs([(1, 5.0), (9, 2.0)])), 6.0);
+        assert_eq!(vector.to_qdrant(), json!({ "indices": [2, 9], "values": [0.5, 3.0] }));
+        assert!(SparseVector::from_pairs([]).is_empty());
+    }
+
+    #[test]
+    fn term_ids_are_fnv1a() {
+        assert_eq!(term_id(""), 0x811c_9dc5);
+        assert_eq!(term_id("a"), 0xe40c_292c);
+    }
+
+    #[test]
+    fn documents_saturate_repeats_and_boost_names() {
+        let encoder = TermEncoder::new();
+        let repeated = encoder.encode("The dragon. The dragon sleeps");
+        assert_eq!(repeated.len(), 2);
+        assert_eq!(weight(&repeated, "dragon"), Some(2.0 * 2.2 / 3.2));
+        assert_eq!(weight(&repeated, "sleeps"), Some(1.0));
+
+        // Capitalised mid-sentence is a name; at the start of a sentence it is not
+        let named = encoder.encode("We fear Vel'Kathar. Dragons sleep x");
+        assert_eq!(weight(&named, "vel'kathar"), Some(1.5));
+        assert_eq!(weight(&named, "dragons"), Some(1.0));
+        assert_eq!((weight(&named, "we"), weight(&named, "x")), (None, None));
+
+        let query = encoder.encode_query("Is it Vel'Kathar? vel'kathar!");
+        assert_eq!(query.len(), 1);
+        assert_eq!(weight(&query, "vel'kathar"), Some(2.0));
+        assert_eq!(encoder.name(), "terms-fnv1a");
+    }
+
+    #[test]
+    fn rare_terms_outweigh_common_ones() {
+        let encoder = TermEncoder::new();
+        let mut index = InvertedIndex::new();
+        let docs = [("a", "Vel'Kathar forged a sword"), ("b", "A sword"), ("c", "A sword and a shield")];
+        for (id, text) in docs {
+            index.insert(id, &encoder.encode(text));
+        }
+        index.insert("empty", &SparseVector::default());
+        assert_eq!(index.len(), 3);
+        assert!(index.idf(term_id("vel'kathar")) > index.idf(term_id("sword")));
+
+        let query = encoder.encode_query("sword of Vel'Kathar");
+        let ranked = index.search(&query, 2, |_| true);
+        assert_eq!(ranked[0].0, "a");
+        assert_eq!(ranked.len(), 2);
+        let others: Vec<String> = index.search(&query, 5, |id| id != "a").into_iter().map(|(id, _)| id).collect();
+        assert_eq!(others, vec!["b", "c"]);
+
+        index.remove("a", &encoder.encode(docs[0].1));
+        assert_eq!(index.len(), 2);
+        assert!(index.search(&encoder.encode_query("Vel'Kathar"), 5, |_| true).is_empty());
+    }
+}
@@ -0,0 +1,270 @@
+// Spatial grid
+//
+// A uniform 2D grid over the ground plane (x, z in world units). Systems that reason about
+// space (influence maps, perception) share one GridSpec so their cells line up. SpatialIndex
+// buckets entity positions by cell for radius queries and answers line-of-sight tests against
+// blocking cells.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+pub type Cell = (usize, usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GridSpec {
+    // World position of the corner of cell (0, 0)
+    pub origin: [f32; 2],
+    pub cell_size: f32,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl GridSpec {
+    pub fn new(width: usize, height: usize, cell_size: f32) -> Self {
+        GridSpec { origin: [0.0, 0.0], cell_size, width, height }
+    }
+
+    pub fn len(&self) -> usize {
+        self.width * self.height
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn index(&self, (x, y): Cell) -> usize {
+        y * self.width + x
+    }
+
+    pub fn cell_at(&self, index: usize) -> Cell {
+        (index % self.width, index / self.width)
+    }
+
+    // Cell containing a world position, or None outside the grid
+    pub fn cell_of(&self, position: [f32; 2]) -> Option<Cell> {
+        let x = ((position[0] - self.origin[0]) / self.cell_size).floor();
+        let y = ((position[1] - self.origin[1]) / self.cell_size).floor();
+        if x < 0.0 || y < 0.0 || x as usize >= self.width || y as usize >= self.height {
+            return None;
+        }
+        Some((x as usize, y as usize))
+    }
+
+    // Nearest cell, clamping positions outside the grid to its edge
+    pub fn clamped_cell_of(&self, position: [f32; 2]) -> Cell {
+        let x = ((position[0] - self.origin[0]) / self.cell_size).floor().max(0.0) as usize;
+        let y = ((position[1] - self.origin[1]) / self.cell_size).floor().max(0.0) as usize;
+        (x.min(self.width.saturating_sub(1)), y.min(self.height.saturating_sub(1)))
+    }
+
+    pub fn center_of(&self, (x, y): Cell) -> [f32; 2] {
+        [
+            self.origin[0] + (x as f32 + 0.5) * self.cell_size,
+            self.origin[1] + (y as f32 + 0.5) * self.cell_size,
+        ]
+    }
+
+    // Cells whose centres lie within `radius` of `position`
+    pub fn cells_within(&self, position: [f32; 2], radius: f32) -> Vec<Cell> {
+        let (min_x, min_y) = self.clamped_cell_of([position[0] - radius, position[1] - radius]);
+        let (max_x, max_y) = self.clamped_cell_of([position[0] + radius, position[1] + radius]);
+        let mut cells = Vec::new();
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                if distance(self.center_of((x, y)), position) <= radius {
+                    cells.push((x, y));
+                }
+            }
+        }
+        cells
+    }
+
+    // 4-connected neighbours inside the grid
+    pub fn neighbors(&self, (x, y): Cell) -> impl Iterator<Item = Cell> + '_ {
+        let candidates = [
+            (x.wrapping_sub(1), y),
+            (x + 1, y),
+            (x, y.wrapping_sub(1)),
+            (x, y + 1),
+        ];
+        candidates.into_iter().filter(move |&(cx, cy)| cx < self.width && cy < self.height)
+    }
+}
+
+pub fn distance(a: [f32; 2], b: [f32; 2]) -> f32 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt()
+}
+
+// Entity positions bucketed by cell, plus cells that block line of sight
+#[derive(Debug, Clone)]
+pub struct SpatialIndex {
+    grid: GridSpec,
+    blocked: Vec<bool>,
+    positions: HashMap<String, [f32; 2]>,
+    buckets: HashMap<usize, Vec<String>>,
+}
+
+impl SpatialIndex {
+    pub fn new(grid: GridSpec) -> Self {
+        SpatialIndex {
+            grid,
+            blocked: vec![false; grid.len()],
+            positions: HashMap::new(),
+            buckets: HashMap::new(),
+        }
+    }
+
+    pub fn grid(&self) -> &GridSpec {
+        &self.grid
+    }
+
+    // Insert or move an entity
+    pub fn set_position(&mut self, id: &str, position: [f32; 2]) {
+        self.remove(id);
+        let bucket = self.grid.index(self.grid.clamped_cell_of(position));
+        self.buckets.entry(bucket).or_default().push(id.to_string());
+        self.positions.insert(id.to_string(), position);
+    }
+
+    pub fn remove(&mut self, id: &str) -> Option<[f32; 2]> {
+        let position = self.positions.remove(id)?;
+        let bucket = self.grid.index(self.grid.clamped_cell_of(position));
+        if let Some(ids) = self.buckets.get_mut(&bucket) {
+            ids.retain(|other| other != id);
+        }
+        Some(position)
+    }
+
+    pub fn position(&self, id: &str) -> Option<[f32; 2]> {
+        self.positions.get(id).copied()
+    }
+
+    pub fn entities(&self) -> impl Iterator<Item = (&String, &[f32; 2])> {
+        self.positions.iter()
+    }
+
+    // Entities within `radius` of `position`, nearest first
+    pub fn query_radius(&self, position: [f32; 2], radius: f32) -> Vec<(String, f32)> {
+        let (min_x, min_y) = self.grid.clamped_cell_of([position[0] - radius, position[1] - radius]);
+        let (max_x, max_y) = self.grid.clamped_cell_of([position[0] + radius, position[1] + radius]);
+        let mut found = Vec::new();
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                for id in self.buckets.get(&self.grid.index((x, y))).into_iter().flatten() {
+                    let d = distance(self.positions[id], position);
+                    if d <= radius {
+                        found.push((id.clone(), d));
+                    }
+                }
+            }
+        }
+        found.sort_by(|a, b| a.1.total_cmp(&b.1));
+        found
+    }
+
+    pub fn set_blocked(&mut self, cell: Cell, blocked: bool) {
+        if cell.0 < self.grid.width && cell.1 < self.grid.height {
+            let index = self.grid.index(cell);
+            self.blocked[index] = blocked;
+        }
+    }
+
+    pub fn is_blocked(&self, cell: Cell) -> bool {
+        self.blocked[self.grid.index(cell)]
+    }
+
+    // True if no blocking cell lies strictly between the two positions' cells
+    pub fn line_of_sight(&self, from: [f32; 2], to: [f32; 2]) -> bool {
+        let start = self.grid.clamped_cell_of(from);
+        let end = self.grid.clamped_cell_of(to);
+        let cells = self.cells_on_line(from, to);
+        !cells.iter().any(|&cell| cell != start && cell != end && self.is_blocked(cell))
+    }
+
+    // Grid cells crossed by the segment, in order (Amanatides-Woo traversal)
+    pub fn cells_on_line(&self, from: [f32; 2], to: [f32; 2]) -> Vec<Cell> {
+        let size = self.grid.cell_size;
+        let local = |p: [f32; 2]| [(p[0] - self.grid.origin[0]) / size, (p[1] - self.grid.origin[1]) / size];
+        let (a, b) = (local(from), local(to));
+        let (mut x, mut y) = self.grid.clamped_cell_of(from);
+        let end = self.grid.clamped_cell_of(to);
+        let (dx, dy) = (b[0] - a[0], b[1] - a[1]);
+        let step_x: isize = if dx > 0.0 { 1 } else { -1 };
+        let step_y: isize = if dy > 0.0 { 1 } else { -1 };
+        let delta_x = if dx != 0.0 { (1.0 / dx).abs() } else { f32::INFINITY };
+        let delta_y = if dy != 0.0 { (1.0 / dy).abs() } else { f32::INFINITY };
+        let boundary = |p: f32, cell: usize, step: isize| if step > 0 { cell as f32 + 1.0 - p } else { p - cell as f32 };
+        let mut t_x = boundary(a[0], x, step_x) * delta_x;
+        let mut t_y = boundary(a[1], y, step_y) * delta_y;
+
+        let mut cells = vec![(x, y)];
+        let limit = self.grid.width + self.grid.height;
+        while (x, y) != end && cells.len() <= limit {
+            if t_x < t_y {
+                x = x.wrapping_add_signed(step_x);
+                t_x += delta_x;
+            } else {
+                y = y.wrapping_add_signed(step_y);
+                t_y += delta_y;
+            }
+            if x >= self.grid.width || y >= self.grid.height {
+                break;
+            }
+            cells.push((x, y));
+        }
+        cells
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grid_maps_positions_to_cells() {
+        let grid = GridSpec { origin: [-2.0, -2.0], cell_size: 2.0, width: 3, height: 2 };
+        assert_eq!(grid.len(), 6);
+        assert_eq!(grid.cell_of([-1.0, 1.0]), Some((0, 1)));
+        assert_eq!(grid.cell_of([4.5, 0.0]), None);
+        assert_eq!(grid.clamped_cell_of([99.0, -99.0]), (2, 0));
+        assert_eq!(grid.center_of((2, 1)), [3.0, 1.0]);
+        assert_eq!(grid.cell_at(grid.index((2, 1))), (2, 1));
+        assert_eq!(grid.neighbors((0, 0)).collect::<Vec<_>>(), vec![(1, 0), (0, 1)]);
+    }
+
+    #[test]
+    fn cells_within_use_cell_centres() {
+        let grid = GridSpec::new(5, 5, 1.0);
+        let mut cells = grid.cells_within([2.5, 2.5], 1.0);
+        cells.sort();
+        assert_eq!(cells, vec![(1, 2), (2, 1), (2, 2), (2, 3), (3, 2)]);
+    }
+
+    #[test]
+    fn radius_queries_return_nearest_first_and_track_moves() {
+        let mut index = SpatialIndex::new(GridSpec::new(10, 10, 1.0));
+        index.set_position("a", [1.0, 1.0]);
+        index.set_position("b", [2.0, 1.0]);
+        index.set_position("c", [8.0, 8.0]);
+        let near: Vec<String> = index.query_radius([2.5, 1.0], 2.0).into_iter().map(|(id, _)| id).collect();
+        assert_eq!(near, vec!["b", "a"]);
+
+        index.set_position("c", [3.0, 1.0]);
+        assert_eq!(index.query_radius([2.5, 1.0], 0.6).len(), 2);
+        assert_eq!(index.remove("c"), Some([3.0, 1.0]));
+        assert_eq!(index.query_radius([8.0, 8.0], 1.0).len(), 0);
+    }
+
+    #[test]
+    fn walls_block_line_of_sight_between_but_not_at_the_ends() {
+        let mut index = SpatialIndex::new(GridSpec::new(5, 5, 1.0));
+        assert_eq!(index.cells_on_line([0.5, 0.5], [3.5, 0.5]), vec![(0, 0), (1, 0), (2, 0), (3, 0)]);
+        index.set_blocked((2, 0), true);
+        assert!(!index.line_of_sight([0.5, 0.5], [3.5, 0.5]));
+        assert!(index.line_of_sight([0.5, 0.5], [0.5, 3.5]));
+        assert!(index.line_of_sight([2.5, 0.5], [3.5, 0.5]));
+        // Out-of-range cells are ignored
+        index.set_blocked((9, 9), true);
+    }
+}
@@ -0,0 +1,616 @@
+// World streaming and AI hibernation
+//
+// A large world cannot keep every NPC's AI running. The world is split into rectangular regions
+// on the ground plane (x, z); only regions near a point of interest (usually the players) are
+// loaded. `update` loads regions that come within `load_radius` of a focus point and unloads the
+// ones further than `unload_radius`, the gap keeping a player on a border from thrashing them.
+//
+// Unloading a region despawns its entities through the LifecycleRegistry, so every registered
+// subsystem (emotion, routines, blackboards, the world entity itself) hands over its state, and
+// the resulting archive goes into the hibernation store together with the time it went to sleep.
+// While hibernating, entities are simulated statistically instead of tick by tick: offscreen
+// models advance the archived state in one step per elapsed interval. Built-in models relax moods
+// towards a baseline, drop routine interruptions that would long have ended, forget stale plan
+// keys from blackboards and let numeric components drift as a random walk (a merchant's gold, a
+// farm's stock). On load the remaining time is caught up the same way and the archive is handed
+// back to the lifecycle, which restores every subsystem.
+//
+// Entities are assigned to regions by position (`place`) or explicitly. One that walks into an
+// unloaded region is hibernated on the next update. Time is in game seconds, supplied by the
+// caller. Each catch-up step draws from an Rng derived from the streamer's seed, the region, the
+// entity and the time caught up to, so a replay with the same seed catches up identically no
+// matter in which order regions are loaded or simulated. The hibernation store, seed included,
+// is kept in agentdb with `save`/`restore`, and every load and unload is published as a
+// "world.streaming" event.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::agentdb::{AgentDb, AgentDbError};
+use crate::ai::schedule::RoutineState;
+use crate::determinism::StateHasher;
+use crate::emotion::MoodVector;
+use crate::events::EventBus;
+use crate::lifecycle::{DespawnMode, EntityArchive, LifecycleRegistry};
+use crate::namespace::Namespace;
+use crate::rng::Rng;
+
+pub const STREAMING_TOPIC: &str = "world.streaming";
+pub const HIBERNATION_TABLE: &str = "hibernation";
+const ENTITY_PREFIX: &str = "entity:";
+const REGION_PREFIX: &str = "region:";
+const SEED_KEY: &str = "seed";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Region {
+    pub id: String,
+    // Corners on the ground plane, (x, z)
+    pub min: [f32; 2],
+    pub max: [f32; 2],
+}
+
+impl Region {
+    pub fn new(id: &str, min: [f32; 2], max: [f32; 2]) -> Self {
+        Region { id: id.to_string(), min, max }
+    }
+
+    pub fn contains(&self, position: [f32; 2]) -> bool {
+        (0..2).all(|i| position[i] >= self.min[i] && position[i] < self.max[i])
+    }
+
+    // Distance from a point to the region's edge; 0 inside
+    pub fn distance(&self, position: [f32; 2]) -> f32 {
+        let dx = (self.min[0] - position[0]).max(position[0] - self.max[0]).max(0.0);
+        let dz = (self.min[1] - position[1]).max(position[1] - self.max[1]).max(0.0);
+        (dx * dx + dz * dz).sqrt()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegionState {
+    Loaded,
+    Hibernating,
+}
+
+// An entity's archived state while its region is unloaded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hibernated {
+    pub region: String,
+    pub archive: EntityArchive,
+    // Game seconds
+    pub since: f64,
+    // Offscreen simulation has been applied up to here
+    pub simulated_until: f64,
+}
+
+// Advances archived state over `elapsed` game seconds in a single step; `asleep` is the total time
+// since the entity was hibernated, this step included
+pub trait OffscreenModel {
+    fn name(&self) -> &str;
+    fn advance(&self, entity: &str, state: &mut BTreeMap<String, Value>, elapsed: f64, asleep: f64, rng: &mut Rng);
+}
+
+// Moods relax exponentially towards a baseline, with optional noise
+pub struct MoodRelaxation {
+    pub baseline: MoodVector,
+    pub half_life_secs: f64,
+    pub noise: f32,
+}
+
+impl MoodRelaxation {
+    pub fn new(baseline: MoodVector, half_life_secs: f64) -> Self {
+        MoodRelaxation { baseline, half_life_secs, noise: 0.0 }
+    }
+
+    pub fn noise(mut self, noise: f32) -> Self {
+        self.noise = noise;
+        self
+    }
+}
+
+impl OffscreenModel for MoodRelaxation {
+    fn name(&self) -> &str {
+        "mood_relaxation"
+    }
+
+    fn advance(&self, _entity: &str, state: &mut BTreeMap<String, Value>, elapsed: f64, _asleep: f64, rng: &mut Rng) {
+        let Some(mood) = state.get_mut("emotion") else { return };
+        let keep = 0.5f64.powf(elapsed / self.half_life_secs.max(f64::EPSILON)) as f32;
+        let mut relax = |key: &str, target: f32| {
+            let value = mood.get(key).and_then(Value::as_f64).map_or(target, |v| v as f32);
+            target + (value - target) * keep + self.noise * rng.normal() as f32
+        };
+        let relaxed = MoodVector::new(
+            relax("tension", self.baseline.tension),
+            relax("valence", self.baseline.valence),
+            relax("energy", self.baseline.energy),
+        );
+        *mood = json!({ "tension": relaxed.tension, "valence": relaxed.valence, "energy": relaxed.energy });
+    }
+}
+
+// Interruptions (combat, a fire) end while nobody watches; after a day away suspended activities
+// are not worth resuming either
+pub struct RoutineCatchUp {
+    pub day_secs: f64,
+}
+
+impl OffscreenModel for RoutineCatchUp {
+    fn name(&self) -> &str {
+        "routine_catch_up"
+    }
+
+    fn advance(&self, _entity: &str, state: &mut BTreeMap<String, Value>, _elapsed: f64, asleep: f64, _rng: &mut Rng) {
+        let Some(routine) = state.get_mut("routine") else { return };
+        let Ok(mut parsed) = serde_json::from_value::<RoutineState>(routine.clone()) else { return };
+        parsed.interruptions.clear();
+        if asleep >= self.day_secs {
+            parsed.suspended.clear();
+        }
+        if let Ok(value) = serde_json::to_value(parsed) {
+            *routine = value;
+        }
+    }
+}
+
+// Blackboard keys (current target, plan step) that are meaningless after `after_secs` away
+pub struct ForgetKeys {
+    pub keys: Vec<String>,
+    pub after_secs: f64,
+}
+
+impl ForgetKeys {
+    pub fn new(keys: &[&str], after_secs: f64) -> Self {
+        ForgetKeys { keys: keys.iter().map(|k| k.to_string()).collect(), after_secs }
+    }
+}
+
+impl OffscreenModel for ForgetKeys {
+    fn name(&self) -> &str {
+        "forget_keys"
+    }
+
+    fn advance(&self, _entity: &str, state: &mut BTreeMap<String, Value>, _elapsed: f64, asleep: f64, _rng: &mut Rng) {
+        if asleep < self.after_secs {
+            return;
+        }
+        if let Some(Value::Object(board)) = state.get_mut("blackboard") {
+            for key in &self.keys {
+                board.remove(key);
+            }
+        }
+    }
+}
+
+// A numeric world component follows a random walk: `rate_per_hour` on average, spreading by
+// `volatility` per square-root hour, clamped to [min, max]
+pub struct ComponentDrift {
+    pub component: String,
+    pub rate_per_hour: f64,
+    pub volatility: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl ComponentDrift {
+    pub fn new(component: &str, rate_per_hour: f64, volatility: f64, min: f64, max: f64) -> Self {
+        ComponentDrift { component: component.to_string(), rate_per_hour, volatility, min, max }
+    }
+}
+
+impl OffscreenModel for ComponentDrift {
+    fn name(&self) -> &str {
+        "component_drift"
+    }
+
+    fn advance(&self, _entity: &str, state: &mut BTreeMap<String, Value>, elapsed: f64, _asleep: f64, rng: &mut Rng) {
+        let Some(value) = state.get_mut("world").and_then(|e| e.get_mut("components")).and_then(|c| c.get_mut(&self.component))
+        else {
+            return;
+        };
+        let Some(current) = value.as_f64() else { return };
+        let hours = elapsed / 3600.0;
+        let drifted = current + self.rate_per_hour * hours + self.volatility * hours.sqrt() * rng.normal();
+        *value = json!(drifted.clamp(self.min, self.max));
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StreamingConfig {
+    pub load_radius: f32,
+    // Larger than load_radius, so regions near the edge are not reloaded every few steps
+    pub unload_radius: f32,
+}
+
+impl Default for StreamingConfig {
+    fn default() -> Self {
+        StreamingConfig { load_radius: 64.0, unload_radius: 96.0 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamingAction {
+    Loaded,
+    Unloaded,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RegionReport {
+    pub region: String,
+    pub action: StreamingAction,
+    pub entities: Vec<String>,
+    // Longest offscreen interval caught up on load, in game seconds
+    pub caught_up_secs: f64,
+    pub at: f64,
+}
+
+#[derive(Debug)]
+pub enum StreamingError {
+    UnknownRegion(String),
+    Storage(AgentDbError),
+    Corrupt(serde_json::Error),
+}
+
+impl fmt::Display for StreamingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StreamingError::UnknownRegion(id) => write!(f, "unknown region '{}'", id),
+            StreamingError::Storage(err) => write!(f, "{}", err),
+            StreamingError::Corrupt(err) => write!(f, "stored hibernation record is invalid: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for StreamingError {}
+
+impl From<AgentDbError> for StreamingError {
+    fn from(err: AgentDbError) -> Self {
+        StreamingError::Storage(err)
+    }
+}
+
+impl From<serde_json::Error> for StreamingError {
+    fn from(err: serde_json::Error) -> Self {
+        StreamingError::Corrupt(err)
+    }
+}
+
+pub struct WorldStreamer {
+    pub config: StreamingConfig,
+    regions: BTreeMap<String, Region>,
+    states: BTreeMap<String, RegionState>,
+    // Entity -> region
+    membership: BTreeMap<String, String>,
+    hibernated: BTreeMap<String, Hibernated>,
+    models: Vec<Box<dyn OffscreenModel + Send>>,
+    seed: u64,
+    // Reports not yet published
+    pending: Vec<RegionReport>,
+}
+
+impl WorldStreamer {
+    pub fn new(config: StreamingConfig, seed: u64) -> Self {
+        WorldStreamer {
+            config,
+            regions: BTreeMap::new(),
+            states: BTreeMap::new(),
+            membership: BTreeMap::new(),
+            hibernated: BTreeMap::new(),
+            models: Vec::new(),
+            seed,
+            pending: Vec::new(),
+        }
+    }
+
+    // New regions start loaded
+    pub fn add_region(&mut self, region: Region) {
+        self.states.entry(region.id.clone()).or_insert(RegionState::Loaded);
+        self.regions.insert(region.id.clone(), region);
+    }
+
+    pub fn model(&mut self, model: impl OffscreenModel + Send + 'static) {
+        self.models.push(Box::new(model));
+    }
+
+    pub fn region_state(&self, region: &str) -> Option<RegionState> {
+        self.states.get(region).copied()
+    }
+
+    pub fn region_at(&self, position: [f32; 2]) -> Option<&Region> {
+        self.regions.values().find(|r| r.contains(position))
+    }
+
+    // Assign an entity to the region containing `position`; outside every region it stays live
+    pub fn place(&mut self, entity: &str, position: [f32; 2]) -> Option<String> {
+        let region = self.region_at(position).map(|r| r.id.clone());
+        match &region {
+            Some(id) => {
+                self.membership.insert(entity.to_string(), id.clone());
+            }
+            None => {
+                self.membership.remove(entity);
+            }
+        }
+        region
+    }
+
+    pub fn assign(&mut self, entity: &str, region: &str) -> Result<(), StreamingError> {
+        if !self.regions.contains_key(region) {
+            return Err(StreamingError::UnknownRegion(region.to_string()));
+        }
+        self.membership.insert(entity.to_string(), region.to_string());
+        Ok(())
+    }
+
+    pub fn region_of(&self, entity: &str) -> Option<&str> {
+        self.membership.get(entity).map(String::as_str)
+    }
+
+    pub fn is_hibernating(&self, entity: &str) -> bool {
+        self.hibernated.contains_key(entity)
+    }
+
+    pub fn hibernated(&self, entity: &str) -> Option<&Hibernated> {
+        self.hibernated.get(entity)
+    }
+
+    pub fn hibernated_count(&self) -> usize {
+        self.hibernated.len()
+    }
+
+    // Despawn the region's entities into the hibernation store
+    pub fn unload_region(&mut self, region: &str, lifecycle: &mut LifecycleRegistry, now: f64) -> Result<RegionReport, StreamingError> {
+        self.check_region(region)?;
+        let members: Vec<String> = self
+            .membership
+            .iter()
+            .filter(|(entity, r)| r.as_str() == region && !self.hibernated.contains_key(*entity))
+            .map(|(entity, _)| entity.clone())
+            .collect();
+        for entity in &members {
+            self.hibernate(entity, region, lifecycle, now);
+        }
+        self.states.insert(region.to_string(), RegionState::Hibernating);
+        Ok(self.report(region, StreamingAction::Unloaded, members, 0.0, now))
+    }
+
+    // Catch the region's entities up to `now` and hand their state back to the lifecycle
+    pub fn load_region(&mut self, region: &str, lifecycle: &mut LifecycleRegistry, now: f64) -> Result<RegionReport, StreamingError> {
+        self.check_region(region)?;
+        let sleeping: Vec<String> =
+            self.hibernated.iter().filter(|(_, h)| h.region == region).map(|(entity, _)| entity.clone()).collect();
+        let mut caught_up: f64 = 0.0;
+        for entity in &sleeping {
+            let mut hibernated = self.hibernated.remove(entity).expect("listed above");
+            caught_up = caught_up.max(now - hibernated.simulated_until);
+            self.advance(entity, &mut hibernated, now);
+            lifecycle.restore_archive(hibernated.archive);
+            lifecycle.spawn(entity);
+        }
+        self.states.insert(region.to_string(), RegionState::Loaded);
+        Ok(self.report(region, StreamingAction::Loaded, sleeping, caught_up.max(0.0), now))
+    }
+
+    // Run offscreen simulation for every hibernating entity up to `now`, e.g. before a save or
+    // when a system wants to inspect sleeping NPCs; loading does this anyway
+    pub fn simulate(&mut self, now: f64) {
+        let entities: Vec<String> = self.hibernated.keys().cloned().collect();
+        for entity in entities {
+            let mut hibernated = self.hibernated.remove(&entity).expect("listed above");
+            self.advance(&entity, &mut hibernated, now);
+            self.hibernated.insert(entity, hibernated);
+        }
+    }
+
+    // Load regions near any focus point, unload those far from all of them, and hibernate live
+    // entities that moved into unloaded regions
+    pub fn update(&mut self, focus: &[[f32; 2]], lifecycle: &mut LifecycleRegistry, now: f64) -> Vec<RegionReport> {
+        let mut reports = Vec::new();
+        let regions: Vec<(String, f32)> = self
+            .regions
+            .values()
+            .map(|r| (r.id.clone(), focus.iter().map(|p| r.distance(*p)).fold(f32::INFINITY, f32::min)))
+            .collect();
+        for (region, distance) in regions {
+            let state = self.states.get(&region).copied();
+            let report = match state {
+                Some(RegionState::Hibernating) if distance <= self.config.load_radius => self.load_region(&region, lifecycle, now),
+                Some(RegionState::Loaded) if distance > self.config.unload_radius => self.unload_region(&region, lifecycle, now),
+                _ => continue,
+            };
+            reports.extend(report.ok());
+        }
+
+        let strays: Vec<(String, String)> = self
+            .membership
+            .iter()
+            .filter(|(entity, region)| {
+                !self.hibernated.contains_key(*entity) && self.states.get(*region) == Some(&RegionState::Hibernating)
+            })
+            .map(|(entity, region)| (entity.clone(), region.clone()))
+            .collect();
+        for (entity, region) in strays {
+            self.hibernate(&entity, &region, lifecycle, now);
+        }
+        reports
+    }
+
+    pub fn publish(&mut self, events: &mut EventBus) -> usize {
+        let pending = std::mem::take(&mut self.pending);
+        for report in &pending {
+            events.emit(STREAMING_TOPIC, &report.region, json!(report));
+        }
+        pending.len()
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    // Store the seed, region states and hibernated entities
+    pub fn save(&self, db: &mut AgentDb, namespace: &Namespace) -> Result<(), StreamingError> {
+        let stale: Vec<String> = db.scan(namespace, HIBERNATION_TABLE).map(|(key, _)| key.clone()).collect();
+        for key in stale {
+            db.delete(namespace, HIBERNATION_TABLE, &key);
+        }
+        db.put(namespace, HIBERNATION_TABLE, SEED_KEY, json!(self.seed))?;
+        for (region, state) in &self.states {
+            db.put(namespace, HIBERNATION_TABLE, &format!("{}{}", REGION_PREFIX, region), serde_json::to_value(state)?)?;
+        }
+        for (entity, hibernated) in &self.hibernated {
+            db.put(namespace, HIBERNATION_TABLE, &format!("{}{}", ENTITY_PREFIX, entity), serde_json::to_value(hibernated)?)?;
+        }
+        Ok(())
+    }
+
+    // Load what `save` stored. Regions must have been added; memberships of hibernated entities
+    // come back with them.
+    pub fn restore(&mut self, db: &AgentDb, namespace: &Namespace) -> Result<(), StreamingError> {
+        let mut states = BTreeMap::new();
+        for (key, value) in db.scan_prefix(namespace, HIBERNATION_TABLE, REGION_PREFIX) {
+            states.insert(key[REGION_PREFIX.len()..].to_string(), serde_json::from_value::<RegionState>(value.clone())?);
+        }
+        let mut hibernated = BTreeMap::new();
+        for (key, value) in db.scan_prefix(namespace, HIBERNATION_TABLE, ENTITY_PREFIX) {
+            hibernated.insert(key[ENTITY_PREFIX.len()..].to_string(), serde_json::from_value::<Hibernated>(value.clone())?);
+        }
+        if let Some(region) = states.keys().find(|r| !self.regions.contains_key(*r)) {
+            return Err(StreamingError::UnknownRegion(region.clone()));
+        }
+        for (entity, h) in &hibernated {
+            self.membership.insert(entity.clone(), h.region.clone());
+        }
+        if let Some(seed) = db.get(namespace, HIBERNATION_TABLE, SEED_KEY).and_then(Value::as_u64) {
+            self.seed = seed;
+        }
+        self.states.extend(states);
+        self.hibernated = hibernated;
+        Ok(())
+    }
+
+    fn check_region(&self, region: &str) -> Result<(), StreamingError> {
+        if !self.regions.contains_key(region) {
+            return Err(StreamingError::UnknownRegion(region.to_string()));
+        }
+        Ok(())
+    }
+
+    fn hibernate(&mut self, entity: &str, region: &str, lifecycle: &mut LifecycleRegistry, now: f64) {
+        lifecycle.despawn(entity, DespawnMode::Archive);
+        let archive = lifecycle.forget(entity).unwrap_or_else(|| EntityArchive {
+            entity: entity.to_string(),
+            ..Default::default()
+        });
+        self.hibernated.insert(
+            entity.to_string(),
+            Hibernated { region: region.to_string(), archive, since: now, simulated_until: now },
+        );
+    }
+
+    fn advance(&mut self, entity: &str, hibernated: &mut Hibernated, now: f64) {
+        let elapsed = now - hibernated.simulated_until;
+        if elapsed <= 0.0 {
+            return;
+        }
+        let mut rng = catch_up_rng(self.seed, &hibernated.region, entity, now);
+        for model in &self.models {
+            model.advance(entity, &mut hibernated.archive.state, elapsed, now - hibernated.since, &mut rng);
+        }
+        hibernated.simulated_until = now;
+    }
+
+    fn report(&mut self, region: &str, action: StreamingAction, entities: Vec<String>, caught_up_secs: f64, at: f64) -> RegionReport {
+        let report = RegionReport { region: region.to_string(), action, entities, caught_up_secs, at };
+        self.pending.push(report.clone());
+        report
+    }
+}
+
+// Rng for one catch-up step, independent of every other entity's steps
+fn catch_up_rng(seed: u64, region: &str, entity: &str, now: f64) -> Rng {
+    let mut hasher = StateHasher::new();
+    hasher.u64(seed);
+    hasher.str(region);
+    hasher.str(entity);
+    hasher.f64(now);
+    Rng::new(hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Appends one draw per step so the draws can be compared
+    struct Draws;
+
+    impl OffscreenModel for Draws {
+        fn name(&self) -> &str {
+            "draws"
+        }
+
+        fn advance(&self, _entity: &str, state: &mut BTreeMap<String, Value>, _elapsed: f64, _asleep: f64, rng: &mut Rng) {
+            let draws = state.entry("draws".to_string()).or_insert_with(|| json!([]));
+            draws.as_array_mut().expect("draws is an array").push(json!(rng.next_u64()));
+        }
+    }
+
+    fn streamer(seed: u64) -> (WorldStreamer, LifecycleRegistry) {
+        let mut streamer = WorldStreamer::new(StreamingConfig::default(), seed);
+        streamer.add_region(Region::new("farm", [0.0, 0.0], [100.0, 100.0]));
+        streamer.add_region(Region::new("mill", [100.0, 0.0], [200.0, 100.0]));
+        streamer.model(Draws);
+        streamer.assign("farmer", "farm").unwrap();
+        streamer.assign("miller", "mill").unwrap();
+        let mut lifecycle = LifecycleRegistry::new();
+        streamer.unload_region("farm", &mut lifecycle, 0.0).unwrap();
+        streamer.unload_region("mill", &mut lifecycle, 0.0).unwrap();
+        (streamer, lifecycle)
+    }
+
+    fn draws(streamer: &WorldStreamer, entity: &str) -> Value {
+        streamer.hibernated(entity).unwrap().archive.state["draws"].clone()
+    }
+
+    #[test]
+    fn catch_up_does_not_depend_on_simulation_order() {
+        let (mut a, _) = streamer(11);
+        a.simulate(60.0);
+        a.simulate(120.0);
+
+        // Same seed, but the miller is caught up on its own first
+        let (mut b, _) = streamer(11);
+        let mut miller = b.hibernated.remove("miller").unwrap();
+        b.advance("miller", &mut miller, 60.0);
+        b.hibernated.insert("miller".to_string(), miller);
+        b.simulate(60.0);
+        b.simulate(120.0);
+
+        assert_eq!(draws(&a, "farmer"), draws(&b, "farmer"));
+        assert_eq!(draws(&a, "miller"), draws(&b, "miller"));
+        assert_ne!(draws(&a, "farmer"), draws(&a, "miller"));
+    }
+
+    #[test]
+    fn restore_brings_back_the_seed() {
+        let (mut original, _) = streamer(11);
+        let (mut db, namespace) = (AgentDb::new(), Namespace::default_namespace());
+        original.save(&mut db, &namespace).unwrap();
+
+        let mut restored = WorldStreamer::new(StreamingConfig::default(), 99);
+        restored.add_region(Region::new("farm", [0.0, 0.0], [100.0, 100.0]));
+        restored.add_region(Region::new("mill", [100.0, 0.0], [200.0, 100.0]));
+        restored.model(Draws);
+        restored.restore(&db, &namespace).unwrap();
+        assert_eq!(restored.seed(), 11);
+
+        original.simulate(300.0);
+        restored.simulate(300.0);
+        assert_eq!(draws(&original, "farmer"), draws(&restored, "farmer"));
+        assert_eq!(restored.region_of("miller"), Some("mill"));
+    }
+}
@@ -0,0 +1,811 @@
+// Symbolic or sub-symbolic computing
+//
+// A small knowledge graph of named concepts with properties and typed, weighted relations between
+// them. NPC reasoning, lore checks and quest generation query it for facts about the world.
+//
+// Traversal queries go beyond find_path: depth limits, relation-type filters, strength
+// thresholds, strongest-chain search (Dijkstra) and chain patterns such as "X IsA Y PartOf Z".
+// Results carry the relations they used so NPCs can explain how they reached a conclusion.
+//
+// Concepts can also be grounded with embeddings, which supports similarity and analogy queries
+// ("king - man + woman") and relating a novel item to the concepts it resembles.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::embeddings::{cosine_similarity, Embedder, EmbeddingError};
+
+// Relationship kinds between concepts
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum RelationType {
+    IsA,
+    PartOf,
+    LocatedIn,
+    Owns,
+    Knows,
+    Likes,
+    Dislikes,
+    Causes,
+    Custom(String),
+}
+
+impl RelationType {
+    pub fn parse(name: &str) -> RelationType {
+        match name {
+            "IsA" => RelationType::IsA,
+            "PartOf" => RelationType::PartOf,
+            "LocatedIn" => RelationType::LocatedIn,
+            "Owns" => RelationType::Owns,
+            "Knows" => RelationType::Knows,
+            "Likes" => RelationType::Likes,
+            "Dislikes" => RelationType::Dislikes,
+            "Causes" => RelationType::Causes,
+            other => RelationType::Custom(other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for RelationType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RelationType::Custom(name) => write!(f, "{}", name),
+            other => write!(f, "{:?}", other),
+        }
+    }
+}
+
+// A named concept (character, place, item, idea) with free-form properties
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Concept {
+    pub name: String,
+    pub properties: HashMap<String, String>,
+}
+
+// Directed relation between two concepts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Relation {
+    pub from: String,
+    pub to: String,
+    pub relation: RelationType,
+    pub strength: f32,
+}
+
+// Knowledge graph of concepts and relations
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SymbolicComputing {
+    concepts: HashMap<String, Concept>,
+    relations: Vec<Relation>,
+    // Grounding embeddings per concept
+    #[serde(default)]
+    embeddings: HashMap<String, Vec<f32>>,
+}
+
+impl SymbolicComputing {
+    pub fn new() -> Self {
+        SymbolicComputing::default()
+    }
+
+    pub fn add_concept(&mut self, name: &str) -> &mut Concept {
+        self.concepts.entry(name.to_string()).or_insert_with(|| Concept {
+            name: name.to_string(),
+            properties: HashMap::new(),
+        })
+    }
+
+    pub fn concept(&self, name: &str) -> Option<&Concept> {
+        self.concepts.get(name)
+    }
+
+    pub fn concepts(&self) -> impl Iterator<Item = &Concept> {
+        self.concepts.values()
+    }
+
+    pub fn remove_concept(&mut self, name: &str) -> Option<Concept> {
+        self.relations.retain(|r| r.from != name && r.to != name);
+        self.embeddings.remove(name);
+        self.concepts.remove(name)
+    }
+
+    pub fn set_property(&mut self, name: &str, key: &str, value: &str) {
+        self.add_concept(name).properties.insert(key.to_string(), value.to_string());
+    }
+
+    pub fn property(&self, name: &str, key: &str) -> Option<&str> {
+        self.concepts.get(name)?.properties.get(key).map(String::as_str)
+    }
+
+    // Adds a relation, creating missing concepts; an existing identical relation has its strength updated
+    pub fn add_relation(&mut self, from: &str, relation: RelationType, to: &str, strength: f32) {
+        self.add_concept(from);
+        self.add_concept(to);
+        if let Some(existing) = self
+            .relations
+            .iter_mut()
+            .find(|r| r.from == from && r.to == to && r.relation == relation)
+        {
+            existing.strength = strength;
+            return;
+        }
+        self.relations.push(Relation {
+            from: from.to_string(),
+            to: to.to_string(),
+            relation,
+            strength,
+        });
+    }
+
+    pub fn remove_relation(&mut self, from: &str, relation: &RelationType, to: &str) -> bool {
+        let before = self.relations.len();
+        self.relations
+            .retain(|r| !(r.from == from && r.to == to && &r.relation == relation));
+        self.relations.len() != before
+    }
+
+    pub fn relations(&self) -> &[Relation] {
+        &self.relations
+    }
+
+    pub fn relations_from<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Relation> + 'a {
+        self.relations.iter().filter(move |r| r.from == name)
+    }
+
+    pub fn relations_to<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Relation> + 'a {
+        self.relations.iter().filter(move |r| r.to == name)
+    }
+
+    pub fn has_relation(&self, from: &str, relation: &RelationType, to: &str) -> bool {
+        self.relations
+            .iter()
+            .any(|r| r.from == from && r.to == to && &r.relation == relation)
+    }
+
+    // Shortest chain of concepts linking `from` to `to` (breadth-first, any relation type)
+    pub fn find_path(&self, from: &str, to: &str) -> Option<Vec<String>> {
+        if !self.concepts.contains_key(from) || !self.concepts.contains_key(to) {
+            return None;
+        }
+        let mut previous: HashMap<&str, &str> = HashMap::new();
+        let mut visited: HashSet<&str> = HashSet::from([from]);
+        let mut queue = VecDeque::from([from]);
+
+        while let Some(current) = queue.pop_front() {
+            if current == to {
+                let mut path = vec![to.to_string()];
+                let mut node = to;
+                while let Some(&prev) = previous.get(node) {
+                    path.push(prev.to_string());
+                    node = prev;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for relation in self.relations_from(current) {
+                if visited.insert(relation.to.as_str()) {
+                    previous.insert(relation.to.as_str(), current);
+                    queue.push_back(relation.to.as_str());
+                }
+            }
+        }
+        None
+    }
+
+    pub fn set_embedding(&mut self, name: &str, embedding: Vec<f32>) {
+        self.add_concept(name);
+        self.embeddings.insert(name.to_string(), embedding);
+    }
+
+    pub fn embedding(&self, name: &str) -> Option<&[f32]> {
+        self.embeddings.get(name).map(Vec::as_slice)
+    }
+
+    // Embed every concept from its name and properties; returns how many were embedded
+    pub fn ground_all(&mut self, embedder: &dyn Embedder) -> Result<usize, EmbeddingError> {
+        let mut names: Vec<&String> = self.concepts.keys().collect();
+        names.sort();
+        let texts: Vec<String> = names.iter().map(|name| grounding_text(&self.concepts[*name])).collect();
+        let vectors = embedder.embed_batch(&texts.iter().map(String::as_str).collect::<Vec<_>>())?;
+        let names: Vec<String> = names.into_iter().cloned().collect();
+        let count = names.len();
+        self.embeddings.extend(names.into_iter().zip(vectors));
+        Ok(count)
+    }
+
+    // The k grounded concepts closest to `name`, most similar first
+    pub fn most_similar(&self, name: &str, k: usize) -> Vec<(String, f32)> {
+        match self.embeddings.get(name) {
+            Some(vector) => self.nearest(vector, k, &[name]),
+            None => Vec::new(),
+        }
+    }
+
+    // Vector-offset analogy: analogy(&["king", "woman"], &["man"], k) answers "king - man + woman".
+    // Empty if any concept is not grounded.
+    pub fn analogy(&self, positive: &[&str], negative: &[&str], k: usize) -> Vec<(String, f32)> {
+        let Some(dimension) = positive.first().and_then(|name| self.embeddings.get(*name)).map(Vec::len) else {
+            return Vec::new();
+        };
+        let mut target = vec![0.0f32; dimension];
+        for (names, sign) in [(positive, 1.0f32), (negative, -1.0)] {
+            for name in names {
+                let Some(vector) = self.embeddings.get(*name).filter(|v| v.len() == dimension) else {
+                    return Vec::new();
+                };
+                for (t, v) in target.iter_mut().zip(vector) {
+                    *t += sign * v;
+                }
+            }
+        }
+        let exclude: Vec<&str> = positive.iter().chain(negative).copied().collect();
+        self.nearest(&target, k, &exclude)
+    }
+
+    // Known concepts a novel item resembles, from a free-text description
+    pub fn relate(&self, description: &str, embedder: &dyn Embedder, k: usize) -> Result<Vec<(String, f32)>, EmbeddingError> {
+        Ok(self.nearest(&embedder.embed(description)?, k, &[]))
+    }
+
+    fn nearest(&self, vector: &[f32], k: usize, exclude: &[&str]) -> Vec<(String, f32)> {
+        let mut scored: Vec<(String, f32)> = self
+            .embeddings
+            .iter()
+            .filter(|(name, embedding)| embedding.len() == vector.len() && !exclude.contains(&name.as_str()))
+            .map(|(name, embedding)| (name.clone(), cosine_similarity(vector, embedding)))
+            .collect();
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        scored.truncate(k);
+        scored
+    }
+
+    // Path with the fewest hops that satisfies the query
+    pub fn find_path_with(&self, from: &str, to: &str, query: &TraversalQuery) -> Option<TraversalPath> {
+        if !self.concepts.contains_key(from) || !self.concepts.contains_key(to) {
+            return None;
+        }
+        let mut previous: HashMap<&str, PathStep> = HashMap::new();
+        let mut depth: HashMap<&str, usize> = HashMap::from([(from, 0)]);
+        let mut queue = VecDeque::from([from]);
+
+        while let Some(current) = queue.pop_front() {
+            if current == to {
+                return Some(self.build_path(from, to, &previous));
+            }
+            let next_depth = depth[current] + 1;
+            if query.max_depth.is_some_and(|max| next_depth > max) {
+                continue;
+            }
+            for (neighbour, step) in self.edges(current, query) {
+                if !depth.contains_key(neighbour) {
+                    depth.insert(neighbour, next_depth);
+                    previous.insert(neighbour, step);
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+        None
+    }
+
+    // Strongest chain between two concepts (Dijkstra). Each relation costs -ln(strength), so the
+    // winning path maximises the product of its strengths. Costs are kept per (concept, hops), so
+    // under a depth limit a cheap but long route to a concept does not hide a shorter one.
+    pub fn strongest_path(&self, from: &str, to: &str, query: &TraversalQuery) -> Option<TraversalPath> {
+        if !self.concepts.contains_key(from) || !self.concepts.contains_key(to) {
+            return None;
+        }
+        let mut cost: HashMap<(&str, usize), f32> = HashMap::from([((from, 0), 0.0)]);
+        let mut previous: HashMap<(&str, usize), PathStep> = HashMap::new();
+        // Fewest hops each concept was expanded with; a later, costlier visit only helps with fewer
+        let mut expanded: HashMap<&str, usize> = HashMap::new();
+        let mut heap = BinaryHeap::from([Frontier { cost: 0.0, hops: 0, concept: from }]);
+
+        while let Some(Frontier { cost: current_cost, hops, concept }) = heap.pop() {
+            if concept == to {
+                return Some(build_hop_path(to, hops, &previous));
+            }
+            if cost.get(&(concept, hops)).is_some_and(|best| current_cost > *best) {
+                continue;
+            }
+            if expanded.get(concept).is_some_and(|fewest| *fewest <= hops) {
+                continue;
+            }
+            expanded.insert(concept, hops);
+            if query.max_depth.is_some_and(|max| hops + 1 > max) {
+                continue;
+            }
+            for (neighbour, step) in self.edges(concept, query) {
+                let next = current_cost - step.strength.clamp(f32::EPSILON, 1.0).ln();
+                let key = (neighbour, hops + 1);
+                if cost.get(&key).is_none_or(|best| next < *best) {
+                    cost.insert(key, next);
+                    previous.insert(key, step);
+                    heap.push(Frontier { cost: next, hops: hops + 1, concept: neighbour });
+                }
+            }
+        }
+        None
+    }
+
+    // Every concept reachable from `from` under the query, with the fewest-hop path to each
+    pub fn reachable(&self, from: &str, query: &TraversalQuery) -> Vec<TraversalPath> {
+        if !self.concepts.contains_key(from) {
+            return Vec::new();
+        }
+        let mut previous: HashMap<&str, PathStep> = HashMap::new();
+        let mut depth: HashMap<&str, usize> = HashMap::from([(from, 0)]);
+        let mut queue = VecDeque::from([from]);
+        let mut order = Vec::new();
+        while let Some(current) = queue.pop_front() {
+            let next_depth = depth[current] + 1;
+            if query.max_depth.is_some_and(|max| next_depth > max) {
+                continue;
+            }
+            for (neighbour, step) in self.edges(current, query) {
+                if !depth.contains_key(neighbour) {
+                    depth.insert(neighbour, next_depth);
+                    previous.insert(neighbour, step);
+                    queue.push_back(neighbour);
+                    order.push(neighbour);
+                }
+            }
+        }
+        order.into_iter().map(|to| self.build_path(from, to, &previous)).collect()
+    }
+
+    // Variable bindings for a chain pattern, e.g. "X IsA Y PartOf Z" or "guard IsA ?what"
+    pub fn match_pattern(&self, pattern: &Pattern, min_strength: f32) -> Vec<PatternMatch> {
+        let mut partial = vec![PatternMatch::default()];
+        for (index, relation) in pattern.relations.iter().enumerate() {
+            let (left, right) = (&pattern.terms[index], &pattern.terms[index + 1]);
+            let mut next = Vec::new();
+            for current in &partial {
+                for r in self.relations.iter().filter(|r| &r.relation == relation && r.strength >= min_strength) {
+                    let mut candidate = current.clone();
+                    if candidate.bind(left, &r.from) && candidate.bind(right, &r.to) {
+                        candidate.steps.push(PathStep {
+                            from: r.from.clone(),
+                            relation: r.relation.clone(),
+                            to: r.to.clone(),
+                            strength: r.strength,
+                            reversed: false,
+                        });
+                        next.push(candidate);
+                    }
+                }
+            }
+            partial = next;
+        }
+        if pattern.relations.is_empty() {
+            return Vec::new();
+        }
+        partial
+    }
+
+    // Relations usable from a concept under the query, as (neighbour, step)
+    fn edges<'a>(&'a self, concept: &'a str, query: &'a TraversalQuery) -> impl Iterator<Item = (&'a str, PathStep)> + 'a {
+        self.relations.iter().filter(move |r| query.allows(r)).filter_map(move |r| {
+            let (neighbour, reversed) = if r.from == concept {
+                (r.to.as_str(), false)
+            } else if query.undirected && r.to == concept {
+                (r.from.as_str(), true)
+            } else {
+                return None;
+            };
+            Some((
+                neighbour,
+                PathStep { from: r.from.clone(), relation: r.relation.clone(), to: r.to.clone(), strength: r.strength, reversed },
+            ))
+        })
+    }
+
+    fn build_path(&self, from: &str, to: &str, previous: &HashMap<&str, PathStep>) -> TraversalPath {
+        let mut steps = Vec::new();
+        let mut concepts = vec![to.to_string()];
+        let mut node = to;
+        while node != from {
+            let Some(step) = previous.get(node) else { break };
+            node = if step.reversed { step.to.as_str() } else { step.from.as_str() };
+            concepts.push(node.to_string());
+            steps.push(step.clone());
+        }
+        concepts.reverse();
+        steps.reverse();
+        TraversalPath { concepts, steps }
+    }
+}
+
+// Constraints for traversal queries; the default follows every relation forwards without limit
+#[derive(Debug, Clone, Default)]
+pub struct TraversalQuery {
+    pub max_depth: Option<usize>,
+    // Only follow these relation types (None = all)
+    pub relation_types: Option<Vec<RelationType>>,
+    pub min_strength: f32,
+    // Also walk relations backwards
+    pub undirected: bool,
+}
+
+impl TraversalQuery {
+    pub fn new() -> Self {
+        TraversalQuery::default()
+    }
+
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    pub fn only(mut self, relation_types: &[RelationType]) -> Self {
+        self.relation_types = Some(relation_types.to_vec());
+        self
+    }
+
+    pub fn min_strength(mut self, strength: f32) -> Self {
+        self.min_strength = strength;
+        self
+    }
+
+    pub fn undirected(mut self) -> Self {
+        self.undirected = true;
+        self
+    }
+
+    fn allows(&self, relation: &Relation) -> bool {
+        relation.strength >= self.min_strength
+            && self.relation_types.as_ref().is_none_or(|types| types.contains(&relation.relation))
+    }
+}
+
+// One relation used by a path; `reversed` when it was walked from `to` back to `from`
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathStep {
+    pub from: String,
+    pub relation: RelationType,
+    pub to: String,
+    pub strength: f32,
+    pub reversed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraversalPath {
+    // Concepts in walk order, starting at the query origin
+    pub concepts: Vec<String>,
+    pub steps: Vec<PathStep>,
+}
+
+impl TraversalPath {
+    pub fn hops(&self) -> usize {
+        self.steps.len()
+    }
+
+    // Product of the relation strengths along the path
+    pub fn strength(&self) -> f32 {
+        self.steps.iter().map(|s| s.strength).product()
+    }
+
+    // The facts used, e.g. "guard IsA soldier (0.90), soldier PartOf army (0.80)"
+    pub fn explain(&self) -> String {
+        explain_steps(&self.steps)
+    }
+}
+
+// "name key value key value", properties in key order so grounding is reproducible
+fn grounding_text(concept: &Concept) -> String {
+    let mut properties: Vec<(&String, &String)> = concept.properties.iter().collect();
+    properties.sort();
+    let mut text = concept.name.replace('_', " ");
+    for (key, value) in properties {
+        text.push_str(&format!(" {} {}", key, value));
+    }
+    text
+}
+
+fn explain_steps(steps: &[PathStep]) -> String {
+    steps
+        .iter()
+        .map(|s| format!("{} {} {} ({:.2})", s.from, s.relation, s.to, s.strength))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+// Walk `previous` back from `to`, reached in `hops` relations
+fn build_hop_path(to: &str, hops: usize, previous: &HashMap<(&str, usize), PathStep>) -> TraversalPath {
+    let mut steps = Vec::with_capacity(hops);
+    let mut concepts = vec![to.to_string()];
+    let mut node = to;
+    for hop in (1..=hops).rev() {
+        let Some(step) = previous.get(&(node, hop)) else { break };
+        node = if step.reversed { step.to.as_str() } else { step.from.as_str() };
+        concepts.push(node.to_string());
+        steps.push(step.clone());
+    }
+    concepts.reverse();
+    steps.reverse();
+    TraversalPath { concepts, steps }
+}
+
+// Dijkstra frontier entry, ordered so the BinaryHeap pops the cheapest first
+struct Frontier<'a> {
+    cost: f32,
+    hops: usize,
+    concept: &'a str,
+}
+
+impl PartialEq for Frontier<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Frontier<'_> {}
+
+impl PartialOrd for Frontier<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Frontier<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.total_cmp(&self.cost).then_with(|| other.concept.cmp(self.concept))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatternTerm {
+    Variable(String),
+    Concept(String),
+}
+
+// Chain pattern: terms joined by relations, "X IsA Y PartOf Z". A term is a variable when it is a
+// single uppercase letter or starts with '?'; anything else names a concept.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pattern {
+    pub terms: Vec<PatternTerm>,
+    pub relations: Vec<RelationType>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatternError(pub String);
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid pattern: {}", self.0)
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+impl Pattern {
+    pub fn parse(pattern: &str) -> Result<Pattern, PatternError> {
+        let tokens: Vec<&str> = pattern.split_whitespace().collect();
+        // Terms and relations alternate, starting and ending with a term
+        let relation_count = tokens.len() / 2;
+        if relation_count == 0 || tokens.len() != 2 * relation_count + 1 {
+            return Err(PatternError(format!("expected 'term Relation term ...', got '{}'", pattern)));
+        }
+        let terms = tokens
+            .iter()
+            .step_by(2)
+            .map(|token| {
+                let is_variable =
+                    token.starts_with('?') || (token.len() == 1 && token.chars().all(|c| c.is_ascii_uppercase()));
+                if is_variable {
+                    PatternTerm::Variable(token.trim_start_matches('?').to_string())
+                } else {
+                    PatternTerm::Concept(token.to_string())
+                }
+            })
+            .collect();
+        let relations = tokens.iter().skip(1).step_by(2).map(|token| RelationType::parse(token)).collect();
+        Ok(Pattern { terms, relations })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PatternMatch {
+    pub bindings: HashMap<String, String>,
+    pub steps: Vec<PathStep>,
+}
+
+impl PatternMatch {
+    pub fn get(&self, variable: &str) -> Option<&str> {
+        self.bindings.get(variable).map(String::as_str)
+    }
+
+    pub fn explain(&self) -> String {
+        explain_steps(&self.steps)
+    }
+
+    // False if the term conflicts with what is already bound
+    fn bind(&mut self, term: &PatternTerm, concept: &str) -> bool {
+        match term {
+            PatternTerm::Concept(name) => name == concept,
+            PatternTerm::Variable(variable) => match self.bindings.get(variable) {
+                Some(bound) => bound == concept,
+                None => {
+                    self.bindings.insert(variable.clone(), concept.to_string());
+                    true
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embeddings::HashEmbedder;
+
+    // guard reaches the keep directly (weak) or through its unit (strong)
+    fn garrison() -> SymbolicComputing {
+        let mut graph = SymbolicComputing::new();
+        graph.add_relation("guard", RelationType::IsA, "soldier", 0.9);
+        graph.add_relation("soldier", RelationType::PartOf, "army", 0.8);
+        graph.add_relation("guard", RelationType::Knows, "captain", 0.3);
+        graph.add_relation("captain", RelationType::PartOf, "army", 0.9);
+        graph.add_relation("army", RelationType::LocatedIn, "keep", 1.0);
+        graph.add_relation("guard", RelationType::LocatedIn, "keep", 0.2);
+        graph
+    }
+
+    fn names(path: &TraversalPath) -> Vec<&str> {
+        path.concepts.iter().map(String::as_str).collect()
+    }
+
+    #[test]
+    fn queries_constrain_the_shortest_path() {
+        let graph = garrison();
+        let any = graph.find_path_with("guard", "keep", &TraversalQuery::new()).unwrap();
+        assert_eq!(names(&any), vec!["guard", "keep"]);
+
+        let strong = TraversalQuery::new().min_strength(0.5);
+        let path = graph.find_path_with("guard", "keep", &strong).unwrap();
+        assert_eq!(names(&path), vec!["guard", "soldier", "army", "keep"]);
+        assert!(graph.find_path_with("guard", "keep", &strong.max_depth(2)).is_none());
+
+        let typed = TraversalQuery::new().only(&[RelationType::IsA, RelationType::PartOf]);
+        assert_eq!(names(&graph.find_path_with("guard", "army", &typed).unwrap()), vec!["guard", "soldier", "army"]);
+        assert!(graph.find_path_with("guard", "keep", &typed).is_none());
+        assert!(graph.find_path_with("guard", "dragon", &TraversalQuery::new()).is_none());
+    }
+
+    #[test]
+    fn strongest_path_maximises_the_strength_product() {
+        let graph = garrison();
+        let path = graph.strongest_path("guard", "keep", &TraversalQuery::new()).unwrap();
+        assert_eq!(names(&path), vec!["guard", "soldier", "army", "keep"]);
+        assert!((path.strength() - 0.72).abs() < 1e-6);
+        assert_eq!(path.explain(), "guard IsA soldier (0.90), soldier PartOf army (0.80), army LocatedIn keep (1.00)");
+
+        // A depth limit forces the weak direct relation
+        let short = graph.strongest_path("guard", "keep", &TraversalQuery::new().max_depth(1)).unwrap();
+        assert_eq!((short.hops(), short.strength()), (1, 0.2));
+    }
+
+    #[test]
+    fn a_cheaper_deeper_route_does_not_hide_a_shorter_one() {
+        let mut graph = SymbolicComputing::new();
+        graph.add_relation("a", RelationType::Knows, "b", 1.0);
+        graph.add_relation("b", RelationType::Knows, "x", 1.0);
+        graph.add_relation("a", RelationType::Knows, "x", 0.5);
+        graph.add_relation("x", RelationType::Knows, "t", 1.0);
+
+        let path = graph.strongest_path("a", "t", &TraversalQuery::new().max_depth(2)).unwrap();
+        assert_eq!(names(&path), vec!["a", "x", "t"]);
+        assert_eq!(path.strength(), 0.5);
+        let free = graph.strongest_path("a", "t", &TraversalQuery::new()).unwrap();
+        assert_eq!(names(&free), vec!["a", "b", "x", "t"]);
+        assert!(graph.strongest_path("a", "t", &TraversalQuery::new().max_depth(1)).is_none());
+    }
+
+    #[test]
+    fn undirected_queries_walk_relations_backwards() {
+        let graph = garrison();
+        assert!(graph.find_path_with("army", "guard", &TraversalQuery::new()).is_none());
+        let path = graph.find_path_with("army", "guard", &TraversalQuery::new().undirected()).unwrap();
+        assert_eq!(names(&path), vec!["army", "soldier", "guard"]);
+        assert!(path.steps.iter().all(|s| s.reversed));
+        // Explanations keep the facts in their stored direction
+        assert_eq!(path.explain(), "soldier PartOf army (0.80), guard IsA soldier (0.90)");
+    }
+
+    #[test]
+    fn reachable_lists_each_concept_once_within_depth() {
+        let graph = garrison();
+        let near: Vec<String> = graph
+            .reachable("guard", &TraversalQuery::new().max_depth(1))
+            .into_iter()
+            .map(|p| p.concepts.last().unwrap().clone())
+            .collect();
+        assert_eq!(near, vec!["soldier", "captain", "keep"]);
+
+        let all = graph.reachable("guard", &TraversalQuery::new());
+        assert_eq!(all.len(), 4);
+        let army = all.iter().find(|p| p.concepts.last().unwrap() == "army").unwrap();
+        assert_eq!(army.hops(), 2);
+        assert!(graph.reachable("dragon", &TraversalQuery::new()).is_empty());
+    }
+
+    #[test]
+    fn patterns_bind_variables_along_the_chain() {
+        let graph = garrison();
+        let matches = graph.match_pattern(&Pattern::parse("X IsA Y PartOf Z").unwrap(), 0.0);
+        assert_eq!(matches.len(), 1);
+        let bound = (matches[0].get("X"), matches[0].get("Y"), matches[0].get("Z"));
+        assert_eq!(bound, (Some("guard"), Some("soldier"), Some("army")));
+        assert_eq!(matches[0].explain(), "guard IsA soldier (0.90), soldier PartOf army (0.80)");
+
+        let members = Pattern::parse("?who PartOf army").unwrap();
+        let found = graph.match_pattern(&members, 0.0);
+        assert_eq!(found.iter().filter_map(|m| m.get("who")).collect::<Vec<_>>(), vec!["soldier", "captain"]);
+        assert_eq!(graph.match_pattern(&members, 0.85).len(), 1);
+
+        // A variable used twice must bind the same concept
+        assert!(graph.match_pattern(&Pattern::parse("X Knows X").unwrap(), 0.0).is_empty());
+        assert!(graph.match_pattern(&Pattern::parse("guard IsA army").unwrap(), 0.0).is_empty());
+    }
+
+    #[test]
+    fn malformed_patterns_are_rejected() {
+        for pattern in ["", "X", "guard IsA", "X IsA Y PartOf"] {
+            assert!(Pattern::parse(pattern).is_err(), "{:?}", pattern);
+        }
+        let pattern = Pattern::parse("?thing Guards gate").unwrap();
+        assert_eq!(pattern.terms, vec![PatternTerm::Variable("thing".to_string()), PatternTerm::Concept("gate".to_string())]);
+        assert_eq!(pattern.relations, vec![RelationType::Custom("Guards".to_string())]);
+    }
+
+    // Axes: royalty, male, female
+    fn court() -> SymbolicComputing {
+        let mut graph = SymbolicComputing::new();
+        graph.set_embedding("king", vec![1.0, 1.0, 0.0]);
+        graph.set_embedding("queen", vec![1.0, 0.0, 1.0]);
+        graph.set_embedding("man", vec![0.0, 1.0, 0.0]);
+        graph.set_embedding("woman", vec![0.0, 0.0, 1.0]);
+        graph.set_embedding("prince", vec![0.9, 1.0, 0.1]);
+        graph
+    }
+
+    #[test]
+    fn similarity_ranks_grounded_concepts_and_skips_itself() {
+        let mut graph = court();
+        let similar = graph.most_similar("king", 2);
+        assert_eq!(similar.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), vec!["prince", "man"]);
+        assert!(similar[0].1 > similar[1].1);
+
+        // Ungrounded concepts and vectors of another size are ignored
+        graph.add_concept("jester");
+        graph.set_embedding("crown", vec![1.0, 0.0]);
+        assert!(graph.most_similar("jester", 3).is_empty());
+        assert!(graph.most_similar("king", 10).iter().all(|(name, _)| name != "crown"));
+    }
+
+    #[test]
+    fn analogies_follow_vector_offsets() {
+        let graph = court();
+        let answer = graph.analogy(&["king", "woman"], &["man"], 1);
+        assert_eq!(answer[0].0, "queen");
+        assert!((answer[0].1 - 1.0).abs() < 1e-6);
+        assert!(graph.analogy(&["king", "dragon"], &["man"], 1).is_empty());
+        assert!(graph.analogy(&[], &["man"], 1).is_empty());
+    }
+
+    #[test]
+    fn grounding_relates_novel_items_to_known_concepts() {
+        let mut graph = SymbolicComputing::new();
+        graph.set_property("iron_sword", "material", "iron");
+        graph.set_property("oak_staff", "material", "wood");
+        assert_eq!(grounding_text(graph.concept("iron_sword").unwrap()), "iron sword material iron");
+
+        let embedder = HashEmbedder::new(64);
+        assert_eq!(graph.ground_all(&embedder).unwrap(), 2);
+        assert_eq!(graph.embedding("oak_staff").map(<[f32]>::len), Some(64));
+        let related = graph.relate("an iron dagger", &embedder, 1).unwrap();
+        assert_eq!(related[0].0, "iron_sword");
+
+        graph.remove_concept("iron_sword");
+        assert!(graph.embedding("iron_sword").is_none());
+    }
+}
@@ -0,0 +1,86 @@
+// Validation reports shared by content, lore and configuration checks
+
+use std::fmt;
+
+// Severity of a single validation finding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+// A single validation finding, located by a dotted path (e.g. "stats.damage")
+#[derive(Debug, Clone)]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    pub path: String,
+    pub message: String,
+}
+
+// Collection of findings produced by a validation pass
+#[derive(Debug, Clone, Default)]
+pub struct ValidationReport {
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    pub fn new() -> Self {
+        ValidationReport { issues: Vec::new() }
+    }
+
+    pub fn error(&mut self, path: &str, message: impl Into<String>) {
+        self.push(Severity::Error, path, message.into());
+    }
+
+    pub fn warning(&mut self, path: &str, message: impl Into<String>) {
+        self.push(Severity::Warning, path, message.into());
+    }
+
+    fn push(&mut self, severity: Severity, path: &str, message: String) {
+        self.issues.push(ValidationIssue {
+            severity,
+            path: path.to_string(),
+            message,
+        });
+    }
+
+    // Valid means no errors; warnings are allowed
+    pub fn is_valid(&self) -> bool {
+        self.errors().next().is_none()
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &ValidationIssue> {
+        self.issues.iter().filter(|i| i.severity == Severity::Error)
+    }
+
+    pub fn warnings(&self) -> impl Iterator<Item = &ValidationIssue> {
+        self.issues.iter().filter(|i| i.severity == Severity::Warning)
+    }
+
+    pub fn merge(&mut self, other: ValidationReport) {
+        self.issues.extend(other.issues);
+    }
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+        if self.path.is_empty() {
+            write!(f, "{}: {}", level, self.message)
+        } else {
+            write!(f, "{}: {}: {}", level, self.path, self.message)
+        }
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for issue in &self.issues {
+            writeln!(f, "{}", issue)?;
+        }
+        Ok(())
+    }
+}
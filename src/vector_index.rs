@@ -0,0 +1,1935 @@
+// Vector Index (VIVIAN)
+//
+// Collections of embedded points with JSON payloads. Search runs against the in-memory copy; when a
+// remote store (Qdrant) is attached every write is mirrored to it so the two stay in sync.
+//
+// Tooling that browses large collections should not pull a whole ranking at once: `search_page`
+// keeps only one page in memory and returns a cursor for the next one, and `search_stream` scores
+// the collection on several threads and hands over result batches as each shard produces them.
+//
+// Points may also carry a sparse term vector (sparse.rs). Once a collection has a sparse encoder,
+// text written to it is encoded on the way in and text searches fuse the dense ranking with a
+// ranking from the collection's inverted index, so rare names in lore are found even when their
+// embeddings are not distinctive. Sparse-only hits are rescored by cosine similarity, so scores
+// and thresholds keep their meaning.
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::cache::Cache;
+use crate::chunking::{chunk_document, ChunkConfig};
+use crate::embeddings::{cosine_similarity, Embedder, EmbeddingError};
+use crate::generation::TextGenerator;
+use crate::namespace::{is_valid_key, namespace_of, Namespace, NamespaceQuota};
+use crate::prompts;
+use crate::resilience::HealthRegistry;
+use crate::security::encryption::Encryption;
+use crate::similarity::{BatchSimilarity, Matrix};
+use crate::sparse::{InvertedIndex, SparseEncoder, SparseVector};
+use crate::versioning::{self, MigrationError, MigrationRegistry, VECTOR_SNAPSHOT_FORMAT, VECTOR_SNAPSHOT_VERSION};
+
+// Payload field holding the source text, needed to re-embed points later
+pub const TEXT_FIELD: &str = "text";
+
+// Payload field holding the expiry time of a point (unix seconds)
+pub const EXPIRES_AT_FIELD: &str = "expires_at";
+
+// Payload field naming the player a point is about, used for data export and deletion
+pub const PLAYER_FIELD: &str = "player_id";
+
+// Payload fields of document chunks: source document id, chunk number, parent section number and text
+pub const DOCUMENT_FIELD: &str = "document_id";
+pub const CHUNK_FIELD: &str = "chunk";
+pub const PARENT_FIELD: &str = "parent";
+pub const PARENT_TEXT_FIELD: &str = "parent_text";
+
+// Payload field marking a soft-deleted point (unix seconds); such points are kept but not searched
+pub const DELETED_AT_FIELD: &str = "deleted_at";
+
+// Payload field counting how often a near-duplicate was stored (DuplicatePolicy::CountFrequency)
+pub const FREQUENCY_FIELD: &str = "frequency";
+
+// Subsystem name used for readiness in the health registry
+pub const HEALTH_SUBSYSTEM: &str = "vector_index";
+
+// Query embeddings kept for search_text; the least recently used is evicted past this
+const QUERY_CACHE_CAPACITY: usize = 1024;
+
+// Vector Index configuration
+#[derive(Debug, Clone, Deserialize)]
+pub struct VectorIndexConfig {
+    pub url: String,
+    pub api_key: String,
+    // Default time-to-live for new points, in seconds (none = keep forever)
+    #[serde(default)]
+    pub default_ttl_secs: Option<u64>,
+    // Per-collection TTL overrides, in seconds
+    #[serde(default)]
+    pub collection_ttl_secs: HashMap<String, u64>,
+}
+
+// A stored vector with its payload
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VectorPoint {
+    pub id: String,
+    pub vector: Vec<f32>,
+    // Term weights for hybrid search (sparse.rs); filled from the text on write when the collection
+    // has a sparse encoder
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sparse: Option<SparseVector>,
+    pub payload: HashMap<String, Value>,
+    // Bumped on every write; 0 means never stored
+    #[serde(default)]
+    pub version: u64,
+}
+
+impl VectorPoint {
+    pub fn new(id: &str, vector: Vec<f32>) -> Self {
+        VectorPoint {
+            id: id.to_string(),
+            vector,
+            sparse: None,
+            payload: HashMap::new(),
+            version: 0,
+        }
+    }
+
+    pub fn with_sparse(mut self, sparse: SparseVector) -> Self {
+        self.sparse = Some(sparse);
+        self
+    }
+
+    pub fn text(&self) -> Option<&str> {
+        self.payload.get(TEXT_FIELD).and_then(Value::as_str)
+    }
+
+    // Expire the point `ttl` from now
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.payload
+            .insert(EXPIRES_AT_FIELD.to_string(), Value::from(unix_now() + ttl.as_secs()));
+        self
+    }
+
+    pub fn expires_at(&self) -> Option<u64> {
+        self.payload.get(EXPIRES_AT_FIELD).and_then(Value::as_u64)
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        self.expires_at().is_some_and(|at| at <= now)
+    }
+
+    pub fn deleted_at(&self) -> Option<u64> {
+        self.payload.get(DELETED_AT_FIELD).and_then(Value::as_u64)
+    }
+
+    // Neither expired nor soft-deleted
+    pub fn is_searchable(&self, now: u64) -> bool {
+        !self.is_expired(now) && self.deleted_at().is_none()
+    }
+}
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// How update() resolves a version mismatch
+pub enum ConflictPolicy<'a> {
+    // Fail with VersionConflict
+    Reject,
+    // Overwrite whatever is stored
+    LastWriterWins,
+    // Combine the stored point with the incoming one
+    Merge(&'a dyn Fn(&VectorPoint, VectorPoint) -> VectorPoint),
+}
+
+// A search hit
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    pub id: String,
+    pub score: f32,
+    pub payload: HashMap<String, Value>,
+}
+
+// Reciprocal rank fusion constant; dampens the advantage of the very top ranks
+const RRF_K: f32 = 60.0;
+
+// How search_with turns one query into several
+pub enum QueryExpansion<'a> {
+    // Search the query as given
+    None,
+    // Rewrite the query through templates containing "{query}", e.g. "Which NPC can {query}"
+    Templates(Vec<String>),
+    // Ask a text generator for up to `variants` paraphrases
+    Generator { generator: &'a dyn TextGenerator, variants: usize },
+}
+
+impl QueryExpansion<'_> {
+    // The original query first, then distinct variants
+    pub fn expand(&self, query: &str) -> Vec<String> {
+        let mut variants = vec![query.to_string()];
+        let mut push = |variant: String| {
+            let variant = variant.trim().to_string();
+            if !variant.is_empty() && !variants.iter().any(|v| v.eq_ignore_ascii_case(&variant)) {
+                variants.push(variant);
+            }
+        };
+        match self {
+            QueryExpansion::None => {}
+            QueryExpansion::Templates(templates) => {
+                for template in templates {
+                    push(template.replace("{query}", query));
+                }
+            }
+            QueryExpansion::Generator { generator, variants: count } => {
+                let vars = [("count", json!(count)), ("query", json!(query))];
+                // Expansion is best effort; a failed generation just searches the original query
+                let prompt = prompts::render(None, prompts::QUERY_EXPANSION, &vars).ok();
+                if let Some(text) = prompt.and_then(|prompt| generator.generate(&prompt).ok()) {
+                    for line in text.lines().take(*count) {
+                        let line = line.trim_start_matches(|c: char| c.is_ascii_digit() || "-*.) ".contains(c));
+                        push(line.to_string());
+                    }
+                }
+            }
+        }
+        variants
+    }
+}
+
+pub struct SearchOptions<'a> {
+    pub limit: usize,
+    pub expansion: QueryExpansion<'a>,
+    // Hits scoring below this are dropped
+    pub min_score: Option<f32>,
+    // Weight of the original query's ranking in fusion; variants weigh 1
+    pub original_weight: f32,
+    // Weight of the sparse ranking when the collection has a sparse encoder; 0 disables it
+    pub sparse_weight: f32,
+}
+
+impl<'a> SearchOptions<'a> {
+    pub fn new(limit: usize) -> Self {
+        SearchOptions { limit, expansion: QueryExpansion::None, min_score: None, original_weight: 1.0, sparse_weight: 1.0 }
+    }
+
+    pub fn with_expansion(mut self, expansion: QueryExpansion<'a>) -> Self {
+        self.expansion = expansion;
+        self
+    }
+
+    pub fn with_min_score(mut self, min_score: f32) -> Self {
+        self.min_score = Some(min_score);
+        self
+    }
+
+    pub fn with_original_weight(mut self, weight: f32) -> Self {
+        self.original_weight = weight;
+        self
+    }
+
+    pub fn with_sparse_weight(mut self, weight: f32) -> Self {
+        self.sparse_weight = weight;
+        self
+    }
+}
+
+// One page of a ranked search. With a cursor, results continue after the last hit of the page that
+// returned it (and `offset` skips further); without one, `offset` counts from the top.
+#[derive(Debug, Clone, Default)]
+pub struct PageRequest {
+    pub limit: usize,
+    pub offset: usize,
+    pub cursor: Option<String>,
+}
+
+impl PageRequest {
+    pub fn new(limit: usize) -> Self {
+        PageRequest { limit, offset: 0, cursor: None }
+    }
+
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn after(mut self, cursor: &str) -> Self {
+        self.cursor = Some(cursor.to_string());
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchPage {
+    pub results: Vec<SearchResult>,
+    // Opaque; None on the last page
+    pub next_cursor: Option<String>,
+    // Searchable points in the collection
+    pub total: usize,
+}
+
+pub struct StreamOptions {
+    // Threads scoring the collection, each over its own slice of the points
+    pub shards: usize,
+    // Results handed over at once
+    pub batch_size: usize,
+    // Results scoring below this are not streamed
+    pub min_score: Option<f32>,
+}
+
+impl Default for StreamOptions {
+    fn default() -> Self {
+        StreamOptions { shards: thread::available_parallelism().map_or(4, |n| n.get()), batch_size: 256, min_score: None }
+    }
+}
+
+impl StreamOptions {
+    pub fn shards(mut self, shards: usize) -> Self {
+        self.shards = shards;
+        self
+    }
+
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    pub fn min_score(mut self, min_score: f32) -> Self {
+        self.min_score = Some(min_score);
+        self
+    }
+}
+
+// What insert() does with a point that nearly matches a recent one
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    // Drop the new point
+    Skip,
+    // Copy the new point's payload fields onto the existing point (with its vectors, when the new
+    // point carries text)
+    MergePayload,
+    // Bump FREQUENCY_FIELD on the existing point
+    CountFrequency,
+}
+
+#[derive(Debug, Clone)]
+pub struct DedupConfig {
+    // Cosine similarity at or above which two points count as duplicates
+    pub threshold: f32,
+    // How many recent inserts each new point is compared against
+    pub window: usize,
+    pub policy: DuplicatePolicy,
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        DedupConfig { threshold: 0.97, window: 256, policy: DuplicatePolicy::CountFrequency }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InsertOutcome {
+    Inserted,
+    Skipped { existing: String, similarity: f32 },
+    Merged { existing: String, similarity: f32 },
+    Counted { existing: String, similarity: f32, frequency: u64 },
+}
+
+// A document search hit, resolved to its parent section
+#[derive(Debug, Clone)]
+pub struct DocumentHit {
+    pub document_id: String,
+    pub parent: usize,
+    // Parent section text, the context to hand to the LLM
+    pub text: String,
+    // Best matching chunk within the section
+    pub chunk_id: String,
+    pub chunk_text: String,
+    pub score: f32,
+}
+
+// In-memory collection of points sharing one embedding model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Collection {
+    pub name: String,
+    pub dimension: usize,
+    pub model: String,
+    // Applied to points stored without an explicit expiry
+    pub default_ttl: Option<Duration>,
+    pub points: HashMap<String, VectorPoint>,
+}
+
+// Outcome of compacting one collection
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CompactionStats {
+    pub points: usize,
+    // Overwrites and deletes since the previous compaction
+    pub churn: usize,
+    // Hash table slots returned to the allocator
+    pub slots_released: usize,
+    // Deleted ids removed from the dedup window
+    pub stale_ids: usize,
+}
+
+// Outcome of a garbage collection pass
+#[derive(Debug, Clone, Default)]
+pub struct GcReport {
+    pub removed: HashMap<String, usize>,
+    pub errors: Vec<String>,
+}
+
+impl GcReport {
+    pub fn total_removed(&self) -> usize {
+        self.removed.values().sum()
+    }
+}
+
+#[derive(Debug)]
+pub enum VectorIndexError {
+    CollectionNotFound(String),
+    CollectionExists(String),
+    InvalidName(String),
+    PointNotFound(String),
+    DimensionMismatch { expected: usize, found: usize },
+    QuotaExceeded { namespace: String, resource: &'static str, limit: usize },
+    VersionConflict { id: String, expected: u64, actual: u64 },
+    Embedding(EmbeddingError),
+    Remote(String),
+    Snapshot(MigrationError),
+    InvalidCursor(String),
+}
+
+impl fmt::Display for VectorIndexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VectorIndexError::CollectionNotFound(name) => write!(f, "collection '{}' not found", name),
+            VectorIndexError::CollectionExists(name) => write!(f, "collection '{}' already exists", name),
+            VectorIndexError::InvalidName(name) => {
+                write!(f, "collection name '{}' may only contain '__' after a namespace (see namespace.rs)", name)
+            }
+            VectorIndexError::PointNotFound(id) => write!(f, "point '{}' not found", id),
+            VectorIndexError::DimensionMismatch { expected, found } => {
+                write!(f, "vector has {} dimensions, collection expects {}", found, expected)
+            }
+            VectorIndexError::QuotaExceeded { namespace, resource, limit } => {
+                write!(f, "namespace '{}' exceeded its quota of {} {}", namespace, limit, resource)
+            }
+            VectorIndexError::VersionConflict { id, expected, actual } => {
+                write!(f, "point '{}' is at version {}, expected {}", id, actual, expected)
+            }
+            VectorIndexError::Embedding(err) => write!(f, "{}", err),
+            VectorIndexError::Remote(err) => write!(f, "remote vector store error: {}", err),
+            VectorIndexError::Snapshot(err) => write!(f, "vector snapshot error: {}", err),
+            VectorIndexError::InvalidCursor(cursor) => write!(f, "invalid search cursor '{}'", cursor),
+        }
+    }
+}
+
+impl std::error::Error for VectorIndexError {}
+
+impl From<EmbeddingError> for VectorIndexError {
+    fn from(err: EmbeddingError) -> Self {
+        VectorIndexError::Embedding(err)
+    }
+}
+
+impl From<MigrationError> for VectorIndexError {
+    fn from(err: MigrationError) -> Self {
+        VectorIndexError::Snapshot(err)
+    }
+}
+
+// Remote vector database mirrored by the index (Qdrant in production)
+pub trait RemoteStore: Send {
+    fn create_collection(&self, name: &str, dimension: usize) -> Result<(), VectorIndexError>;
+    fn drop_collection(&self, name: &str) -> Result<(), VectorIndexError>;
+    fn upsert(&self, collection: &str, points: &[VectorPoint]) -> Result<(), VectorIndexError>;
+    fn delete(&self, collection: &str, ids: &[String]) -> Result<(), VectorIndexError>;
+}
+
+// Per-collection result of the warm-up health check
+#[derive(Debug, Clone)]
+pub struct CollectionHealth {
+    pub name: String,
+    pub points: usize,
+    pub expired: usize,
+    // Points with the wrong dimension or non-finite values
+    pub invalid: usize,
+    // False when the collection was built with a different model than the warm-up embedder
+    pub model_matches: bool,
+}
+
+impl CollectionHealth {
+    pub fn is_healthy(&self) -> bool {
+        self.invalid == 0 && self.model_matches
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct WarmUpReport {
+    // Queries embedded into the query cache
+    pub embedded: usize,
+    // (query, error) for queries that could not be embedded
+    pub failed: Vec<(String, String)>,
+    pub collections: Vec<CollectionHealth>,
+    pub elapsed: Duration,
+}
+
+impl WarmUpReport {
+    pub fn is_ready(&self) -> bool {
+        self.failed.is_empty() && self.collections.iter().all(CollectionHealth::is_healthy)
+    }
+
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems: Vec<String> = self
+            .failed
+            .iter()
+            .map(|(query, err)| format!("could not embed '{}': {}", query, err))
+            .collect();
+        for health in self.collections.iter().filter(|h| !h.is_healthy()) {
+            if health.invalid > 0 {
+                problems.push(format!("{}: {} invalid points", health.name, health.invalid));
+            }
+            if !health.model_matches {
+                problems.push(format!("{}: built with a different embedding model", health.name));
+            }
+        }
+        problems
+    }
+}
+
+pub struct VectorIndex {
+    config: VectorIndexConfig,
+    collections: HashMap<String, Collection>,
+    remote: Option<Box<dyn RemoteStore>>,
+    quotas: HashMap<String, NamespaceQuota>,
+    // Near-duplicate detection per collection, with the ids of its recent inserts
+    dedup: HashMap<String, DedupConfig>,
+    recent: HashMap<String, VecDeque<String>>,
+    // Overwrites and deletes per collection since it was last compacted
+    churn: HashMap<String, usize>,
+    // "model\0query" -> embedding
+    query_cache: Mutex<Cache<Vec<f32>>>,
+    // Per collection: encoder for text written to it, and posting lists over its sparse vectors
+    sparse_encoders: HashMap<String, Arc<dyn SparseEncoder + Send + Sync>>,
+    sparse: HashMap<String, InvertedIndex>,
+}
+
+impl VectorIndex {
+    pub fn new(config: VectorIndexConfig) -> Self {
+        VectorIndex {
+            config,
+            collections: HashMap::new(),
+            remote: None,
+            quotas: HashMap::new(),
+            dedup: HashMap::new(),
+            recent: HashMap::new(),
+            churn: HashMap::new(),
+            query_cache: Mutex::new(Cache::new(QUERY_CACHE_CAPACITY)),
+            sparse_encoders: HashMap::new(),
+            sparse: HashMap::new(),
+        }
+    }
+
+    pub fn with_remote(mut self, remote: Box<dyn RemoteStore>) -> Self {
+        self.remote = Some(remote);
+        self
+    }
+
+    pub fn config(&self) -> &VectorIndexConfig {
+        &self.config
+    }
+
+    // Collections named via Namespace::qualify count against that namespace's quota
+    pub fn set_quota(&mut self, namespace: &Namespace, quota: NamespaceQuota) {
+        self.quotas.insert(namespace.name().to_string(), quota);
+    }
+
+    fn quota_for<'a>(&'a self, collection: &'a str) -> Option<(&'a str, &'a NamespaceQuota)> {
+        let namespace = namespace_of(collection)?;
+        self.quotas.get(namespace).map(|q| (namespace, q))
+    }
+
+    // Names containing "__" must be qualified by a namespace (Namespace::qualify)
+    pub fn create_collection(&mut self, name: &str, dimension: usize, model: &str) -> Result<(), VectorIndexError> {
+        if !is_valid_key(name) {
+            return Err(VectorIndexError::InvalidName(name.to_string()));
+        }
+        if self.collections.contains_key(name) {
+            return Err(VectorIndexError::CollectionExists(name.to_string()));
+        }
+        if let Some((namespace, limit)) = self.quota_for(name).and_then(|(ns, q)| Some((ns, q.max_collections?))) {
+            let owned = self.collections.keys().filter(|c| namespace_of(c) == Some(namespace)).count();
+            if owned >= limit {
+                return Err(VectorIndexError::QuotaExceeded {
+                    namespace: namespace.to_string(),
+                    resource: "collections",
+                    limit,
+                });
+            }
+        }
+        if let Some(remote) = &self.remote {
+            remote.create_collection(name, dimension)?;
+        }
+        let default_ttl = self
+            .config
+            .collection_ttl_secs
+            .get(name)
+            .copied()
+            .or(self.config.default_ttl_secs)
+            .map(Duration::from_secs);
+        self.collections.insert(
+            name.to_string(),
+            Collection {
+                name: name.to_string(),
+                dimension,
+                model: model.to_string(),
+                default_ttl,
+                points: HashMap::new(),
+            },
+        );
+        Ok(())
+    }
+
+    pub fn set_default_ttl(&mut self, collection: &str, ttl: Option<Duration>) -> Result<(), VectorIndexError> {
+        self.collection_mut(collection)?.default_ttl = ttl;
+        Ok(())
+    }
+
+    pub fn drop_collection(&mut self, name: &str) -> Result<Collection, VectorIndexError> {
+        if !self.collections.contains_key(name) {
+            return Err(VectorIndexError::CollectionNotFound(name.to_string()));
+        }
+        if let Some(remote) = &self.remote {
+            remote.drop_collection(name)?;
+        }
+        self.dedup.remove(name);
+        self.recent.remove(name);
+        self.churn.remove(name);
+        self.sparse_encoders.remove(name);
+        self.sparse.remove(name);
+        Ok(self.collections.remove(name).expect("collection checked above"))
+    }
+
+    // Enable (or with None, disable) near-duplicate detection for insert() and store_text()
+    pub fn set_dedup(&mut self, collection: &str, config: Option<DedupConfig>) -> Result<(), VectorIndexError> {
+        self.collection(collection)?;
+        match config {
+            Some(config) => {
+                self.dedup.insert(collection.to_string(), config);
+            }
+            None => {
+                self.dedup.remove(collection);
+                self.recent.remove(collection);
+            }
+        }
+        Ok(())
+    }
+
+    // Encode text written to the collection into sparse vectors (None stops encoding; stored
+    // vectors stay). Points already holding text but no sparse vector are encoded right away.
+    pub fn set_sparse_encoder(
+        &mut self,
+        collection: &str,
+        encoder: Option<Arc<dyn SparseEncoder + Send + Sync>>,
+    ) -> Result<usize, VectorIndexError> {
+        self.collection(collection)?;
+        let Some(encoder) = encoder else {
+            self.sparse_encoders.remove(collection);
+            return Ok(0);
+        };
+        let target = self.collections.get_mut(collection).expect("collection checked above");
+        let mut backfilled = Vec::new();
+        for point in target.points.values_mut().filter(|p| p.sparse.is_none()) {
+            if let Some(text) = point.text() {
+                point.sparse = Some(encoder.encode(text));
+                backfilled.push(point.clone());
+            }
+        }
+        let index = self.sparse.entry(collection.to_string()).or_default();
+        for point in &backfilled {
+            index.insert(&point.id, point.sparse.as_ref().expect("encoded above"));
+        }
+        if let (Some(remote), false) = (&self.remote, backfilled.is_empty()) {
+            remote.upsert(collection, &backfilled)?;
+        }
+        self.sparse_encoders.insert(collection.to_string(), encoder);
+        Ok(backfilled.len())
+    }
+
+    // Store a new point unless it nearly duplicates a recent one, in which case the collection's
+    // duplicate policy applies. Points reusing an existing id are plain overwrites.
+    pub fn insert(&mut self, collection: &str, point: VectorPoint) -> Result<InsertOutcome, VectorIndexError> {
+        let Some(config) = self.dedup.get(collection).cloned() else {
+            self.upsert(collection, point)?;
+            return Ok(InsertOutcome::Inserted);
+        };
+        let target = self.collection(collection)?;
+        if target.points.contains_key(&point.id) {
+            self.upsert(collection, point)?;
+            return Ok(InsertOutcome::Inserted);
+        }
+
+        let now = unix_now();
+        let duplicate = self
+            .recent
+            .get(collection)
+            .into_iter()
+            .flatten()
+            .filter_map(|id| target.points.get(id))
+            .filter(|existing| existing.is_searchable(now) && existing.vector.len() == point.vector.len())
+            .map(|existing| (existing, cosine_similarity(&point.vector, &existing.vector)))
+            .filter(|(_, similarity)| *similarity >= config.threshold)
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(existing, similarity)| (existing.clone(), similarity));
+
+        let Some((mut existing, similarity)) = duplicate else {
+            let id = point.id.clone();
+            self.upsert(collection, point)?;
+            let recent = self.recent.entry(collection.to_string()).or_default();
+            recent.push_back(id);
+            while recent.len() > config.window {
+                recent.pop_front();
+            }
+            return Ok(InsertOutcome::Inserted);
+        };
+
+        let id = existing.id.clone();
+        match config.policy {
+            DuplicatePolicy::Skip => Ok(InsertOutcome::Skipped { existing: id, similarity }),
+            DuplicatePolicy::MergePayload => {
+                // The vectors must keep describing the stored text, so new text brings its own
+                if point.text().is_some() {
+                    existing.vector = point.vector;
+                    existing.sparse = point.sparse;
+                }
+                existing.payload.extend(point.payload);
+                self.upsert(collection, existing)?;
+                Ok(InsertOutcome::Merged { existing: id, similarity })
+            }
+            DuplicatePolicy::CountFrequency => {
+                let frequency = existing.payload.get(FREQUENCY_FIELD).and_then(Value::as_u64).unwrap_or(1) + 1;
+                existing.payload.insert(FREQUENCY_FIELD.to_string(), Value::from(frequency));
+                self.upsert(collection, existing)?;
+                Ok(InsertOutcome::Counted { existing: id, similarity, frequency })
+            }
+        }
+    }
+
+    pub fn collection(&self, name: &str) -> Result<&Collection, VectorIndexError> {
+        self.collections
+            .get(name)
+            .ok_or_else(|| VectorIndexError::CollectionNotFound(name.to_string()))
+    }
+
+    fn collection_mut(&mut self, name: &str) -> Result<&mut Collection, VectorIndexError> {
+        self.collections
+            .get_mut(name)
+            .ok_or_else(|| VectorIndexError::CollectionNotFound(name.to_string()))
+    }
+
+    pub fn collection_names(&self) -> Vec<&str> {
+        self.collections.keys().map(String::as_str).collect()
+    }
+
+    pub fn upsert(&mut self, collection: &str, mut point: VectorPoint) -> Result<(), VectorIndexError> {
+        let target = self.collection(collection)?;
+        if point.vector.len() != target.dimension {
+            return Err(VectorIndexError::DimensionMismatch {
+                expected: target.dimension,
+                found: point.vector.len(),
+            });
+        }
+        if let (None, Some(ttl)) = (point.expires_at(), target.default_ttl) {
+            point = point.with_ttl(ttl);
+        }
+        if let (None, Some(encoder), Some(text)) = (&point.sparse, self.sparse_encoders.get(collection), point.text()) {
+            point.sparse = Some(encoder.encode(text));
+        }
+        if !target.points.contains_key(&point.id) {
+            if let Some((namespace, limit)) = self.quota_for(collection).and_then(|(ns, q)| Some((ns, q.max_points?))) {
+                let used: usize = self
+                    .collections
+                    .values()
+                    .filter(|c| namespace_of(&c.name) == Some(namespace))
+                    .map(|c| c.points.len())
+                    .sum();
+                if used >= limit {
+                    return Err(VectorIndexError::QuotaExceeded {
+                        namespace: namespace.to_string(),
+                        resource: "points",
+                        limit,
+                    });
+                }
+            }
+        }
+        point.version = target.points.get(&point.id).map_or(1, |p| p.version + 1);
+        if let Some(remote) = &self.remote {
+            remote.upsert(collection, std::slice::from_ref(&point))?;
+        }
+        if point.version > 1 {
+            *self.churn.entry(collection.to_string()).or_default() += 1;
+        }
+        let sparse = point.sparse.clone();
+        let id = point.id.clone();
+        let previous = self.collection_mut(collection)?.points.insert(id.clone(), point);
+        if sparse.is_some() || previous.as_ref().is_some_and(|p| p.sparse.is_some()) {
+            let index = self.sparse.entry(collection.to_string()).or_default();
+            if let Some(old) = previous.and_then(|p| p.sparse) {
+                index.remove(&id, &old);
+            }
+            if let Some(new) = &sparse {
+                index.insert(&id, new);
+            }
+        }
+        Ok(())
+    }
+
+    // Compare-and-swap write: succeeds only if the stored version equals `expected_version`
+    // (0 = the point must not exist yet). Returns the new version.
+    pub fn update(&mut self, collection: &str, point: VectorPoint, expected_version: u64) -> Result<u64, VectorIndexError> {
+        self.update_with(collection, point, expected_version, &ConflictPolicy::Reject)
+    }
+
+    pub fn update_with(
+        &mut self,
+        collection: &str,
+        point: VectorPoint,
+        expected_version: u64,
+        policy: &ConflictPolicy,
+    ) -> Result<u64, VectorIndexError> {
+        let current = self.collection(collection)?.points.get(&point.id);
+        let actual = current.map_or(0, |p| p.version);
+        let point = if actual == expected_version {
+            point
+        } else {
+            match policy {
+                ConflictPolicy::Reject => {
+                    return Err(VectorIndexError::VersionConflict { id: point.id, expected: expected_version, actual });
+                }
+                ConflictPolicy::LastWriterWins => point,
+                ConflictPolicy::Merge(merge) => match current {
+                    Some(current) => merge(current, point),
+                    None => point,
+                },
+            }
+        };
+        let id = point.id.clone();
+        self.upsert(collection, point)?;
+        Ok(self.collection(collection)?.points[&id].version)
+    }
+
+    pub fn get(&self, collection: &str, id: &str) -> Result<Option<&VectorPoint>, VectorIndexError> {
+        Ok(self.collection(collection)?.points.get(id))
+    }
+
+    pub fn delete(&mut self, collection: &str, ids: &[String]) -> Result<usize, VectorIndexError> {
+        self.collection(collection)?;
+        if let Some(remote) = &self.remote {
+            remote.delete(collection, ids)?;
+        }
+        let points = &mut self.collection_mut(collection)?.points;
+        let removed: Vec<VectorPoint> = ids.iter().filter_map(|id| points.remove(id.as_str())).collect();
+        if let Some(index) = self.sparse.get_mut(collection) {
+            for point in &removed {
+                if let Some(sparse) = &point.sparse {
+                    index.remove(&point.id, sparse);
+                }
+            }
+        }
+        let removed = removed.len();
+        *self.churn.entry(collection.to_string()).or_default() += removed;
+        Ok(removed)
+    }
+
+    // Hide points from search without losing them; they can be restored with `undelete` or moved
+    // to the archive tier (see archive.rs). Returns the number newly marked.
+    pub fn soft_delete(&mut self, collection: &str, ids: &[String], now: u64) -> Result<usize, VectorIndexError> {
+        let target = self.collection(collection)?;
+        let marked: Vec<VectorPoint> = ids
+            .iter()
+            .filter_map(|id| target.points.get(id.as_str()))
+            .filter(|point| point.deleted_at().is_none())
+            .cloned()
+            .collect();
+        let count = marked.len();
+        for mut point in marked {
+            point.payload.insert(DELETED_AT_FIELD.to_string(), Value::from(now));
+            self.upsert(collection, point)?;
+        }
+        Ok(count)
+    }
+
+    // Make soft-deleted points searchable again; returns the number restored
+    pub fn undelete(&mut self, collection: &str, ids: &[String]) -> Result<usize, VectorIndexError> {
+        let target = self.collection(collection)?;
+        let marked: Vec<VectorPoint> = ids
+            .iter()
+            .filter_map(|id| target.points.get(id.as_str()))
+            .filter(|point| point.deleted_at().is_some())
+            .cloned()
+            .collect();
+        let count = marked.len();
+        for mut point in marked {
+            point.payload.remove(DELETED_AT_FIELD);
+            self.upsert(collection, point)?;
+        }
+        Ok(count)
+    }
+
+    // Soft-deleted point ids with their deletion time, oldest first
+    pub fn deleted(&self, collection: &str) -> Result<Vec<(String, u64)>, VectorIndexError> {
+        let mut deleted: Vec<(String, u64)> = self
+            .collection(collection)?
+            .points
+            .values()
+            .filter_map(|point| Some((point.id.clone(), point.deleted_at()?)))
+            .collect();
+        deleted.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0)));
+        Ok(deleted)
+    }
+
+    // Overwrites and deletes since the last compaction, relative to the collection size
+    pub fn fragmentation(&self, collection: &str) -> Result<f64, VectorIndexError> {
+        let len = self.collection(collection)?.points.len();
+        Ok(self.churn.get(collection).copied().unwrap_or(0) as f64 / len.max(1) as f64)
+    }
+
+    // Release storage left behind by deletes and drop stale ids from the dedup window
+    pub fn compact_collection(&mut self, collection: &str) -> Result<CompactionStats, VectorIndexError> {
+        let target = self.collections.get_mut(collection).ok_or_else(|| VectorIndexError::CollectionNotFound(collection.to_string()))?;
+        let capacity_before = target.points.capacity();
+        target.points.shrink_to_fit();
+        let mut stale_ids = 0;
+        if let Some(recent) = self.recent.get_mut(collection) {
+            let before = recent.len();
+            recent.retain(|id| target.points.contains_key(id));
+            recent.shrink_to_fit();
+            stale_ids = before - recent.len();
+        }
+        Ok(CompactionStats {
+            points: target.points.len(),
+            churn: self.churn.remove(collection).unwrap_or(0),
+            slots_released: capacity_before.saturating_sub(target.points.capacity()),
+            stale_ids,
+        })
+    }
+
+    pub fn len(&self, collection: &str) -> Result<usize, VectorIndexError> {
+        Ok(self.collection(collection)?.points.len())
+    }
+
+    // Point ids in a stable order, for paging through a collection
+    pub fn ids(&self, collection: &str) -> Result<Vec<String>, VectorIndexError> {
+        let mut ids: Vec<String> = self.collection(collection)?.points.keys().cloned().collect();
+        ids.sort();
+        Ok(ids)
+    }
+
+    // Top `limit` points by cosine similarity
+    pub fn search(&self, collection: &str, vector: &[f32], limit: usize) -> Result<Vec<SearchResult>, VectorIndexError> {
+        let target = self.collection(collection)?;
+        if vector.len() != target.dimension {
+            return Err(VectorIndexError::DimensionMismatch {
+                expected: target.dimension,
+                found: vector.len(),
+            });
+        }
+        Ok(rank(target, vector, limit, unix_now()))
+    }
+
+    // Top `limit` points by sparse score (idf-weighted dot product) alone
+    pub fn search_sparse(&self, collection: &str, query: &SparseVector, limit: usize) -> Result<Vec<SearchResult>, VectorIndexError> {
+        let target = self.collection(collection)?;
+        let now = unix_now();
+        let Some(index) = self.sparse.get(collection) else { return Ok(Vec::new()) };
+        Ok(index
+            .search(query, limit, |id| target.points.get(id).is_some_and(|p| p.is_searchable(now)))
+            .into_iter()
+            .map(|(id, score)| SearchResult { payload: target.points[&id].payload.clone(), id, score })
+            .collect())
+    }
+
+    // Dense and sparse rankings fused by reciprocal rank; the sparse ranking weighs `sparse_weight`
+    // against the dense one's 1. Scores are cosine similarities.
+    pub fn search_hybrid(
+        &self,
+        collection: &str,
+        vector: &[f32],
+        sparse: &SparseVector,
+        limit: usize,
+        sparse_weight: f32,
+    ) -> Result<Vec<SearchResult>, VectorIndexError> {
+        let target = self.collection(collection)?;
+        if vector.len() != target.dimension {
+            return Err(VectorIndexError::DimensionMismatch { expected: target.dimension, found: vector.len() });
+        }
+        let depth = limit.saturating_mul(2).max(limit + 5);
+        let now = unix_now();
+        let rankings = vec![
+            rank(target, vector, depth, now),
+            sparse_rank(target, self.sparse.get(collection), vector, sparse, depth, now),
+        ];
+        Ok(fuse(rankings, &[1.0, sparse_weight.max(0.0)], limit))
+    }
+
+    // One page of the ranking for `vector`. Only offset + limit candidates are held while scanning,
+    // and cursors are keyset based, so paging stays consistent while points are added elsewhere in
+    // the ranking.
+    pub fn search_page(&self, collection: &str, vector: &[f32], page: &PageRequest) -> Result<SearchPage, VectorIndexError> {
+        let target = self.collection(collection)?;
+        if vector.len() != target.dimension {
+            return Err(VectorIndexError::DimensionMismatch { expected: target.dimension, found: vector.len() });
+        }
+        let after = page.cursor.as_deref().map(decode_cursor).transpose()?;
+        let now = unix_now();
+        // One extra candidate tells whether another page follows
+        let keep = page.offset.saturating_add(page.limit).saturating_add(1);
+        let mut heap: BinaryHeap<Ranked> = BinaryHeap::new();
+        let mut total = 0;
+        for point in target.points.values().filter(|point| point.is_searchable(now)) {
+            total += 1;
+            let candidate = Ranked { score: cosine_similarity(vector, &point.vector), point };
+            if after.as_ref().is_some_and(|(score, id)| !candidate.ranks_after(*score, id)) {
+                continue;
+            }
+            if heap.len() < keep {
+                heap.push(candidate);
+            } else if heap.peek().is_some_and(|worst| candidate < *worst) {
+                heap.pop();
+                heap.push(candidate);
+            }
+        }
+        let ranked = heap.into_sorted_vec();
+        let more = ranked.len() > page.offset.saturating_add(page.limit);
+        let results: Vec<SearchResult> = ranked
+            .into_iter()
+            .skip(page.offset)
+            .take(page.limit)
+            .map(|hit| SearchResult { id: hit.point.id.clone(), score: hit.score, payload: hit.point.payload.clone() })
+            .collect();
+        let next_cursor = if more { results.last().map(|last| encode_cursor(last.score, &last.id)) } else { None };
+        Ok(SearchPage { results, next_cursor, total })
+    }
+
+    // Score every point and hand results to `on_batch` as the shards produce them, unranked. Returning
+    // false from `on_batch` stops the search. Returns the number of results delivered.
+    pub fn search_stream(
+        &self,
+        collection: &str,
+        vector: &[f32],
+        options: &StreamOptions,
+        mut on_batch: impl FnMut(Vec<SearchResult>) -> bool,
+    ) -> Result<usize, VectorIndexError> {
+        let target = self.collection(collection)?;
+        if vector.len() != target.dimension {
+            return Err(VectorIndexError::DimensionMismatch { expected: target.dimension, found: vector.len() });
+        }
+        let now = unix_now();
+        let points: Vec<&VectorPoint> = target.points.values().collect();
+        let shard_len = points.len().div_ceil(options.shards.max(1)).max(1);
+        let batch_size = options.batch_size.max(1);
+        let min_score = options.min_score.unwrap_or(f32::NEG_INFINITY);
+        let stop = AtomicBool::new(false);
+        let mut delivered = 0;
+        thread::scope(|scope| {
+            // Bounded, so slow consumers hold back the shards instead of buffering the whole ranking
+            let (sender, receiver) = mpsc::sync_channel::<Vec<SearchResult>>(options.shards.max(1));
+            for shard in points.chunks(shard_len) {
+                let sender = sender.clone();
+                let stop = &stop;
+                scope.spawn(move || {
+                    let mut batch = Vec::with_capacity(batch_size);
+                    for point in shard.iter().filter(|point| point.is_searchable(now)) {
+                        if stop.load(AtomicOrdering::Relaxed) {
+                            return;
+                        }
+                        let score = cosine_similarity(vector, &point.vector);
+                        if score < min_score {
+                            continue;
+                        }
+                        batch.push(SearchResult { id: point.id.clone(), score, payload: point.payload.clone() });
+                        if batch.len() >= batch_size && sender.send(std::mem::take(&mut batch)).is_err() {
+                            return;
+                        }
+                    }
+                    if !batch.is_empty() {
+                        let _ = sender.send(batch);
+                    }
+                });
+            }
+            drop(sender);
+            for batch in receiver.iter() {
+                delivered += batch.len();
+                if !on_batch(batch) {
+                    stop.store(true, AtomicOrdering::Relaxed);
+                    break;
+                }
+            }
+            // Dropping the receiver fails any pending send, so blocked shards exit
+        });
+        Ok(delivered)
+    }
+
+    // Top `limit` points for each of several queries, scored in one batch; collections over the
+    // engine's threshold for them go to the GPU when `engine` has one
+    pub fn search_batch(
+        &self,
+        collection: &str,
+        queries: &[Vec<f32>],
+        limit: usize,
+        engine: &BatchSimilarity,
+    ) -> Result<Vec<Vec<SearchResult>>, VectorIndexError> {
+        let target = self.collection(collection)?;
+        if let Some(query) = queries.iter().find(|q| q.len() != target.dimension) {
+            return Err(VectorIndexError::DimensionMismatch { expected: target.dimension, found: query.len() });
+        }
+        let now = unix_now();
+        let mut points: Vec<&VectorPoint> = target.points.values().filter(|p| p.is_searchable(now)).collect();
+        points.sort_by(|a, b| a.id.cmp(&b.id));
+        let dim = target.dimension;
+        let query_matrix = Matrix { rows: queries.len(), dim, data: queries.concat() };
+        let corpus = Matrix { rows: points.len(), dim, data: points.iter().flat_map(|p| p.vector.iter().copied()).collect() };
+        let scores = engine.cosine_for(collection, &query_matrix, &corpus).expect("dimensions checked above");
+        Ok((0..queries.len())
+            .map(|q| {
+                scores
+                    .top_k(q, limit)
+                    .into_iter()
+                    .map(|(row, score)| SearchResult { id: points[row].id.clone(), score, payload: points[row].payload.clone() })
+                    .collect()
+            })
+            .collect())
+    }
+
+    // Embed and store text, keeping the text in the payload
+    pub fn store_text(
+        &mut self,
+        collection: &str,
+        id: &str,
+        text: &str,
+        mut payload: HashMap<String, Value>,
+        embedder: &dyn Embedder,
+    ) -> Result<InsertOutcome, VectorIndexError> {
+        let vector = embedder.embed(text)?;
+        payload.insert(TEXT_FIELD.to_string(), Value::String(text.to_string()));
+        self.insert(collection, VectorPoint { id: id.to_string(), vector, sparse: None, payload, version: 0 })
+    }
+
+    // Chunk a long document and store each child chunk with a reference to its parent section.
+    // Chunks from an earlier version of the same document are replaced. Returns the chunk count.
+    pub fn store_document(
+        &mut self,
+        collection: &str,
+        document_id: &str,
+        text: &str,
+        payload: HashMap<String, Value>,
+        config: &ChunkConfig,
+        embedder: &dyn Embedder,
+    ) -> Result<usize, VectorIndexError> {
+        let document = chunk_document(text, config);
+        let texts: Vec<&str> = document.children.iter().map(|c| c.text.as_str()).collect();
+        let vectors = embedder.embed_batch(&texts)?;
+
+        let stale: Vec<String> = self
+            .collection(collection)?
+            .points
+            .values()
+            .filter(|p| p.payload.get(DOCUMENT_FIELD).and_then(Value::as_str) == Some(document_id))
+            .map(|p| p.id.clone())
+            .collect();
+        if !stale.is_empty() {
+            self.delete(collection, &stale)?;
+        }
+
+        for (chunk, vector) in document.children.iter().zip(vectors) {
+            let parent = chunk.parent.unwrap_or(chunk.index);
+            let mut chunk_payload = payload.clone();
+            chunk_payload.insert(TEXT_FIELD.to_string(), Value::String(chunk.text.clone()));
+            chunk_payload.insert(DOCUMENT_FIELD.to_string(), Value::String(document_id.to_string()));
+            chunk_payload.insert(CHUNK_FIELD.to_string(), Value::from(chunk.index));
+            chunk_payload.insert(PARENT_FIELD.to_string(), Value::from(parent));
+            chunk_payload.insert(PARENT_TEXT_FIELD.to_string(), Value::String(document.parents[parent].text.clone()));
+            let id = format!("{}#{}", document_id, chunk.index);
+            self.upsert(collection, VectorPoint { id, vector, sparse: None, payload: chunk_payload, version: 0 })?;
+        }
+        Ok(document.children.len())
+    }
+
+    // Parent-document retrieval: chunks are matched, their parent sections returned, one hit per section
+    pub fn search_documents(
+        &self,
+        collection: &str,
+        query: &str,
+        limit: usize,
+        embedder: &dyn Embedder,
+    ) -> Result<Vec<DocumentHit>, VectorIndexError> {
+        let vector = self.embed_query(query, embedder)?;
+        let target = self.collection(collection)?;
+        let mut hits: Vec<DocumentHit> = Vec::new();
+        // Ranked best first, so the first chunk seen for a section is its best one
+        for result in self.search(collection, &vector, target.points.len())? {
+            let Some(document_id) = result.payload.get(DOCUMENT_FIELD).and_then(Value::as_str) else {
+                continue;
+            };
+            let parent = result.payload.get(PARENT_FIELD).and_then(Value::as_u64).unwrap_or(0) as usize;
+            if hits.iter().any(|h| h.document_id == document_id && h.parent == parent) {
+                continue;
+            }
+            let text_of = |field: &str| result.payload.get(field).and_then(Value::as_str).unwrap_or_default().to_string();
+            hits.push(DocumentHit {
+                document_id: document_id.to_string(),
+                parent,
+                text: text_of(PARENT_TEXT_FIELD),
+                chunk_text: text_of(TEXT_FIELD),
+                chunk_id: result.id,
+                score: result.score,
+            });
+            if hits.len() >= limit {
+                break;
+            }
+        }
+        Ok(hits)
+    }
+
+    pub fn search_text(
+        &self,
+        collection: &str,
+        query: &str,
+        limit: usize,
+        embedder: &dyn Embedder,
+    ) -> Result<Vec<SearchResult>, VectorIndexError> {
+        let vector = self.embed_query(query, embedder)?;
+        match self.sparse_encoders.get(collection) {
+            Some(encoder) => self.search_hybrid(collection, &vector, &encoder.encode_query(query), limit, 1.0),
+            None => self.search(collection, &vector, limit),
+        }
+    }
+
+    // Text search with options; with query expansion every variant is searched on its own thread
+    // and the rankings are fused, together with the sparse ranking of the original query when the
+    // collection has a sparse encoder
+    pub fn search_with(
+        &self,
+        collection: &str,
+        query: &str,
+        options: &SearchOptions,
+        embedder: &dyn Embedder,
+    ) -> Result<Vec<SearchResult>, VectorIndexError> {
+        let variants = options.expansion.expand(query);
+        let min_score = options.min_score.unwrap_or(f32::NEG_INFINITY);
+        let sparse = match self.sparse_encoders.get(collection) {
+            Some(encoder) if options.sparse_weight > 0.0 => Some(encoder.encode_query(query)),
+            _ => None,
+        };
+        if variants.len() == 1 && sparse.is_none() {
+            let vector = self.embed_query(query, embedder)?;
+            let mut results = self.search(collection, &vector, options.limit)?;
+            results.retain(|result| result.score >= min_score);
+            return Ok(results);
+        }
+        let target = self.collection(collection)?;
+        let mut vectors = Vec::with_capacity(variants.len());
+        for variant in &variants {
+            let vector = self.embed_query(variant, embedder)?;
+            if vector.len() != target.dimension {
+                return Err(VectorIndexError::DimensionMismatch { expected: target.dimension, found: vector.len() });
+            }
+            vectors.push(vector);
+        }
+
+        // Each variant contributes candidates beyond the final limit so fusion has something to work with
+        let depth = options.limit.saturating_mul(2).max(options.limit + 5);
+        let now = unix_now();
+        let mut rankings: Vec<Vec<SearchResult>> = thread::scope(|scope| {
+            let handles: Vec<_> = vectors
+                .iter()
+                .map(|vector| scope.spawn(move || rank(target, vector, depth, now)))
+                .collect();
+            handles.into_iter().map(|handle| handle.join().expect("search thread panicked")).collect()
+        });
+        let mut weights: Vec<f32> =
+            (0..rankings.len()).map(|i| if i == 0 { options.original_weight.max(0.0) } else { 1.0 }).collect();
+        if let Some(sparse) = &sparse {
+            rankings.push(sparse_rank(target, self.sparse.get(collection), &vectors[0], sparse, depth, now));
+            weights.push(options.sparse_weight);
+        }
+        // Fused past the limit so the score threshold does not leave the page short
+        let mut results = fuse(rankings, &weights, depth);
+        results.retain(|result| result.score >= min_score);
+        results.truncate(options.limit);
+        Ok(results)
+    }
+
+    // Query embedding, served from the cache when warmed up or seen before
+    pub fn embed_query(&self, query: &str, embedder: &dyn Embedder) -> Result<Vec<f32>, VectorIndexError> {
+        let key = format!("{}\0{}", embedder.model(), query);
+        let namespace = Namespace::default_namespace();
+        if let Some(vector) = self.query_cache.lock().unwrap().get(&namespace, &key) {
+            return Ok(vector);
+        }
+        let vector = embedder.embed(query)?;
+        self.query_cache.lock().unwrap().insert(&namespace, &key, vector.clone());
+        Ok(vector)
+    }
+
+    // Prepare for the first queries after startup: embed common queries into the query cache,
+    // walk every collection once so its points are resident, and check collection health.
+    // Readiness is reported to the health registry under HEALTH_SUBSYSTEM when one is given.
+    pub fn warm_up(&self, queries: &[&str], embedder: &dyn Embedder, health: Option<&mut HealthRegistry>) -> WarmUpReport {
+        let started = Instant::now();
+        let mut report = WarmUpReport::default();
+        for query in queries {
+            match self.embed_query(query, embedder) {
+                Ok(_) => report.embedded += 1,
+                Err(err) => report.failed.push((query.to_string(), err.to_string())),
+            }
+        }
+
+        let now = unix_now();
+        let mut names: Vec<&String> = self.collections.keys().collect();
+        names.sort();
+        for name in names {
+            let collection = &self.collections[name];
+            let mut check = CollectionHealth {
+                name: name.clone(),
+                points: collection.points.len(),
+                expired: 0,
+                invalid: 0,
+                model_matches: collection.model == embedder.model(),
+            };
+            for point in collection.points.values() {
+                if point.is_expired(now) {
+                    check.expired += 1;
+                }
+                if point.vector.len() != collection.dimension || point.vector.iter().any(|v| !v.is_finite()) {
+                    check.invalid += 1;
+                }
+            }
+            report.collections.push(check);
+        }
+        report.elapsed = started.elapsed();
+
+        if let Some(health) = health {
+            if report.is_ready() {
+                health.report_success(HEALTH_SUBSYSTEM);
+            } else {
+                health.report_failure(HEALTH_SUBSYSTEM, &report.problems().join("; "));
+            }
+        }
+        report
+    }
+
+    // Drop every collection owned by a namespace. Returns (collections, points) removed.
+    pub fn purge_namespace(&mut self, namespace: &Namespace) -> Result<(usize, usize), VectorIndexError> {
+        let owned: Vec<String> = self.collections.keys().filter(|c| namespace.owns(c)).cloned().collect();
+        let mut points = 0;
+        for name in &owned {
+            points += self.drop_collection(name)?.points.len();
+        }
+        Ok((owned.len(), points))
+    }
+
+    // Write every collection to a versioned snapshot file
+    pub fn save_snapshot(&self, path: &std::path::Path, encryption: Option<&Encryption>) -> Result<(), VectorIndexError> {
+        let data = serde_json::to_value(&self.collections).map_err(MigrationError::from)?;
+        versioning::write_versioned(path, VECTOR_SNAPSHOT_FORMAT, VECTOR_SNAPSHOT_VERSION, data, encryption)?;
+        Ok(())
+    }
+
+    // Replace the in-memory collections with a snapshot, upgrading old snapshot versions. The
+    // remote store is not touched; it is expected to hold the same data already.
+    pub fn restore_snapshot(
+        &mut self,
+        path: &std::path::Path,
+        registry: &MigrationRegistry,
+        encryption: Option<&Encryption>,
+    ) -> Result<(), VectorIndexError> {
+        let outcome = versioning::read_versioned(path, VECTOR_SNAPSHOT_FORMAT, registry, encryption)?;
+        self.collections = serde_json::from_value(outcome.data).map_err(MigrationError::from)?;
+        self.sparse.clear();
+        for collection in self.collections.values() {
+            let index = self.sparse.entry(collection.name.clone()).or_default();
+            for point in collection.points.values() {
+                if let Some(sparse) = &point.sparse {
+                    index.insert(&point.id, sparse);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Delete every point whose expiry has passed, from the remote store and memory
+    pub fn collect_garbage(&mut self, now: u64) -> GcReport {
+        let mut report = GcReport::default();
+        let mut expired: Vec<(String, Vec<String>)> = Vec::new();
+        for collection in self.collections.values() {
+            let ids: Vec<String> = collection
+                .points
+                .values()
+                .filter(|p| p.is_expired(now))
+                .map(|p| p.id.clone())
+                .collect();
+            if !ids.is_empty() {
+                expired.push((collection.name.clone(), ids));
+            }
+        }
+
+        for (collection, ids) in expired {
+            match self.delete(&collection, &ids) {
+                Ok(removed) => {
+                    report.removed.insert(collection, removed);
+                }
+                Err(err) => report.errors.push(format!("{}: {}", collection, err)),
+            }
+        }
+        report
+    }
+}
+
+// Top `limit` live points of a collection by cosine similarity
+fn rank(collection: &Collection, vector: &[f32], limit: usize, now: u64) -> Vec<SearchResult> {
+    let mut results: Vec<SearchResult> = collection
+        .points
+        .values()
+        // Expired points are invisible even before GC removes them, soft-deleted ones until restored
+        .filter(|point| point.is_searchable(now))
+        .map(|point| SearchResult {
+            id: point.id.clone(),
+            score: cosine_similarity(vector, &point.vector),
+            payload: point.payload.clone(),
+        })
+        .collect();
+    results.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.id.cmp(&b.id)));
+    results.truncate(limit);
+    results
+}
+
+// Top `limit` live points by sparse score, scored by cosine similarity to `vector` so they fuse
+// with dense rankings on the same scale
+fn sparse_rank(
+    collection: &Collection,
+    index: Option<&InvertedIndex>,
+    vector: &[f32],
+    query: &SparseVector,
+    limit: usize,
+    now: u64,
+) -> Vec<SearchResult> {
+    let Some(index) = index else { return Vec::new() };
+    index
+        .search(query, limit, |id| collection.points.get(id).is_some_and(|p| p.is_searchable(now)))
+        .into_iter()
+        .map(|(id, _)| {
+            let point = &collection.points[&id];
+            SearchResult { score: cosine_similarity(vector, &point.vector), payload: point.payload.clone(), id }
+        })
+        .collect()
+}
+
+// Search candidate ordered by rank: "less" ranks earlier (higher score, then lower id), so a max-heap
+// keeps the worst kept candidate on top
+struct Ranked<'a> {
+    score: f32,
+    point: &'a VectorPoint,
+}
+
+impl Ranked<'_> {
+    fn ranks_after(&self, score: f32, id: &str) -> bool {
+        match self.score.total_cmp(&score) {
+            Ordering::Less => true,
+            Ordering::Equal => self.point.id.as_str() > id,
+            Ordering::Greater => false,
+        }
+    }
+}
+
+impl Ord for Ranked<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.score.total_cmp(&self.score).then_with(|| self.point.id.cmp(&other.point.id))
+    }
+}
+
+impl PartialOrd for Ranked<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Ranked<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Ranked<'_> {}
+
+// Cursor: the exact score bits of the last hit, then its id
+fn encode_cursor(score: f32, id: &str) -> String {
+    format!("{:08x}{}", score.to_bits(), id)
+}
+
+fn decode_cursor(cursor: &str) -> Result<(f32, String), VectorIndexError> {
+    let invalid = || VectorIndexError::InvalidCursor(cursor.to_string());
+    let bits = cursor.get(..8).ok_or_else(invalid)?;
+    let bits = u32::from_str_radix(bits, 16).map_err(|_| invalid())?;
+    Ok((f32::from_bits(bits), cursor[8..].to_string()))
+}
+
+// Weighted reciprocal rank fusion of several rankings. Results are ordered by fused rank, but keep
+// the best cosine score any variant gave them so score thresholds still mean the same thing.
+fn fuse(rankings: Vec<Vec<SearchResult>>, weights: &[f32], limit: usize) -> Vec<SearchResult> {
+    let mut fused: HashMap<String, (f32, SearchResult)> = HashMap::new();
+    for (ranking, weight) in rankings.into_iter().zip(weights) {
+        for (position, result) in ranking.into_iter().enumerate() {
+            let contribution = weight / (RRF_K + position as f32 + 1.0);
+            match fused.get_mut(&result.id) {
+                Some((total, best)) => {
+                    *total += contribution;
+                    if result.score > best.score {
+                        best.score = result.score;
+                    }
+                }
+                None => {
+                    fused.insert(result.id.clone(), (contribution, result));
+                }
+            }
+        }
+    }
+    let mut results: Vec<(f32, SearchResult)> = fused.into_values().collect();
+    results.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.id.cmp(&b.1.id)));
+    results.into_iter().take(limit).map(|(_, result)| result).collect()
+}
+
+// Background garbage collection started by `spawn_gc_task`. Dropping the handle (or calling
+// `stop`) wakes the thread and waits for it, so the index is not kept alive by a forgotten loop
+pub struct GcTask {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl GcTask {
+    pub fn is_running(&self) -> bool {
+        self.handle.as_ref().is_some_and(|handle| !handle.is_finished())
+    }
+
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.stop.store(true, AtomicOrdering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            handle.thread().unpark();
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for GcTask {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+// Runs garbage collection on a background thread every `interval` until the returned handle is
+// stopped or dropped
+pub fn spawn_gc_task(
+    index: Arc<Mutex<VectorIndex>>,
+    interval: Duration,
+    on_report: impl Fn(&GcReport) + Send + 'static,
+) -> GcTask {
+    let stop = Arc::new(AtomicBool::new(false));
+    let stop_flag = stop.clone();
+    let handle = thread::spawn(move || loop {
+        // park_timeout can wake early, so sleep against a deadline; `shutdown` unparks us
+        let deadline = Instant::now() + interval;
+        while !stop_flag.load(AtomicOrdering::Relaxed) {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            thread::park_timeout(deadline - now);
+        }
+        if stop_flag.load(AtomicOrdering::Relaxed) {
+            return;
+        }
+        let report = match index.lock() {
+            Ok(mut index) => index.collect_garbage(unix_now()),
+            // Poisoned lock: the owner panicked, stop collecting
+            Err(_) => return,
+        };
+        if report.total_removed() > 0 || !report.errors.is_empty() {
+            on_report(&report);
+        }
+    });
+    GcTask { stop, handle: Some(handle) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index() -> VectorIndex {
+        VectorIndex::new(VectorIndexConfig {
+            url: String::new(),
+            api_key: String::new(),
+            default_ttl_secs: None,
+            collection_ttl_secs: Default::default(),
+        })
+    }
+
+    // Counts how often the model is actually called
+    struct Counting(std::cell::Cell<usize>);
+
+    impl Embedder for Counting {
+        fn model(&self) -> &str {
+            "counting"
+        }
+
+        fn dimension(&self) -> usize {
+            2
+        }
+
+        fn embed(&self, _text: &str) -> Result<Vec<f32>, EmbeddingError> {
+            self.0.set(self.0.get() + 1);
+            Ok(vec![1.0, 0.0])
+        }
+    }
+
+    #[test]
+    fn query_cache_evicts_the_least_recently_used_query() {
+        let index = index();
+        let embedder = Counting(std::cell::Cell::new(0));
+        for i in 0..QUERY_CACHE_CAPACITY {
+            index.embed_query(&format!("query {}", i), &embedder).unwrap();
+        }
+        // Touch the oldest entry so the next insert evicts "query 1" instead
+        index.embed_query("query 0", &embedder).unwrap();
+        index.embed_query("one too many", &embedder).unwrap();
+        assert_eq!(embedder.0.get(), QUERY_CACHE_CAPACITY + 1);
+        assert_eq!(index.query_cache.lock().unwrap().len(), QUERY_CACHE_CAPACITY);
+
+        index.embed_query("query 0", &embedder).unwrap();
+        assert_eq!(embedder.0.get(), QUERY_CACHE_CAPACITY + 1);
+        index.embed_query("query 1", &embedder).unwrap();
+        assert_eq!(embedder.0.get(), QUERY_CACHE_CAPACITY + 2);
+    }
+
+    fn text_point(id: &str, vector: Vec<f32>, text: &str) -> VectorPoint {
+        let mut point = VectorPoint::new(id, vector);
+        point.payload.insert(TEXT_FIELD.to_string(), Value::from(text));
+        point
+    }
+
+    #[test]
+    fn merged_text_replaces_the_vectors_that_describe_it() {
+        let mut index = index();
+        index.create_collection("lore", 2, "test").unwrap();
+        index.set_sparse_encoder("lore", Some(Arc::new(crate::sparse::TermEncoder::new()))).unwrap();
+        let policy = DedupConfig { threshold: 0.9, window: 8, policy: DuplicatePolicy::MergePayload };
+        index.set_dedup("lore", Some(policy)).unwrap();
+
+        index.insert("lore", text_point("a", vec![1.0, 0.0], "The Ember Gate fell")).unwrap();
+        let outcome = index.insert("lore", text_point("b", vec![0.99, 0.1], "Ember Gate rebuilt by Thorne")).unwrap();
+        assert!(matches!(outcome, InsertOutcome::Merged { ref existing, .. } if existing == "a"));
+
+        let merged = &index.collection("lore").unwrap().points["a"];
+        assert_eq!(merged.text(), Some("Ember Gate rebuilt by Thorne"));
+        assert_eq!(merged.vector, vec![0.99, 0.1]);
+        let expected = crate::sparse::TermEncoder::new().encode("Ember Gate rebuilt by Thorne");
+        assert_eq!(merged.sparse.as_ref(), Some(&expected));
+        let hits = index.sparse["lore"].search(&expected, 5, |_| true);
+        assert_eq!(hits.first().map(|(id, _)| id.as_str()), Some("a"));
+
+        // Payload-only merges keep the stored vectors
+        let mut tag = VectorPoint::new("c", vec![1.0, 0.05]);
+        tag.payload.insert("region".to_string(), Value::from("north"));
+        index.insert("lore", tag).unwrap();
+        let merged = &index.collection("lore").unwrap().points["a"];
+        assert_eq!(merged.vector, vec![0.99, 0.1]);
+        assert_eq!(merged.payload["region"], Value::from("north"));
+    }
+
+    #[test]
+    fn rare_names_are_found_by_the_sparse_ranking() {
+        let mut index = index();
+        index.create_collection("lore", 2, "test").unwrap();
+        // Every text embeds to the same vector, so the dense ranking alone falls back to id order
+        let embedder = Counting(std::cell::Cell::new(0));
+        for (id, text) in [("a", "The gate of the north"), ("b", "Old gate wards"), ("z", "Vel'Kathar sleeps under the gate")] {
+            index.store_text("lore", id, text, HashMap::new(), &embedder).unwrap();
+        }
+        assert_eq!(ids(&index.search_text("lore", "Vel'Kathar", 1, &embedder).unwrap()), vec!["a"]);
+
+        let encoder = Arc::new(crate::sparse::TermEncoder::new());
+        assert_eq!(index.set_sparse_encoder("lore", Some(encoder.clone())).unwrap(), 3);
+        let hits = index.search_text("lore", "Vel'Kathar", 1, &embedder).unwrap();
+        // Fused by rank, scored by cosine similarity
+        assert_eq!((ids(&hits), hits[0].score), (vec!["z"], 1.0));
+        let query = encoder.encode_query("Vel'Kathar");
+        assert_eq!(ids(&index.search_sparse("lore", &query, 5).unwrap()), vec!["z"]);
+        assert_eq!(ids(&index.search_hybrid("lore", &[1.0, 0.0], &query, 1, 0.0).unwrap()), vec!["a"]);
+
+        // Later writes are encoded on the way in
+        index.store_text("lore", "y", "Vel'Kathar woke", HashMap::new(), &embedder).unwrap();
+        assert_eq!(index.search_sparse("lore", &query, 5).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn stale_updates_follow_the_conflict_policy() {
+        let mut index = index();
+        index.create_collection("npc", 2, "test").unwrap();
+        assert_eq!(index.update("npc", text_point("mira", vec![1.0, 0.0], "calm"), 0).unwrap(), 1);
+        assert_eq!(index.update("npc", text_point("mira", vec![1.0, 0.0], "wary"), 1).unwrap(), 2);
+
+        // A writer that read version 1 is now behind
+        let stale = || text_point("mira", vec![0.0, 1.0], "angry");
+        let err = index.update("npc", stale(), 1).unwrap_err();
+        assert!(matches!(err, VectorIndexError::VersionConflict { expected: 1, actual: 2, .. }));
+        assert_eq!(index.get("npc", "mira").unwrap().unwrap().text(), Some("wary"));
+
+        let keep_vector = |current: &VectorPoint, mut incoming: VectorPoint| {
+            incoming.vector = current.vector.clone();
+            incoming
+        };
+        assert_eq!(index.update_with("npc", stale(), 1, &ConflictPolicy::Merge(&keep_vector)).unwrap(), 3);
+        let merged = index.get("npc", "mira").unwrap().unwrap();
+        assert_eq!((merged.text(), merged.vector.as_slice()), (Some("angry"), [1.0, 0.0].as_slice()));
+
+        assert_eq!(index.update_with("npc", stale(), 1, &ConflictPolicy::LastWriterWins).unwrap(), 4);
+        assert_eq!(index.get("npc", "mira").unwrap().unwrap().vector, vec![0.0, 1.0]);
+        assert!(index.update("npc", text_point("thorne", vec![1.0, 0.0], "new"), 3).is_err());
+    }
+
+    // "blade" and "sword" queries point in different directions; anything else sits between
+    struct Keyed;
+
+    impl Embedder for Keyed {
+        fn model(&self) -> &str {
+            "keyed"
+        }
+
+        fn dimension(&self) -> usize {
+            2
+        }
+
+        fn embed(&self, text: &str) -> Result<Vec<f32>, EmbeddingError> {
+            Ok(match text {
+                t if t.contains("blade") => vec![0.0, 1.0],
+                t if t.contains("sword") => vec![1.0, 0.0],
+                _ => vec![0.6, 0.8],
+            })
+        }
+    }
+
+    struct Paraphrases(Result<&'static str, &'static str>);
+
+    impl TextGenerator for Paraphrases {
+        fn generate(&self, _prompt: &str) -> Result<String, Box<dyn std::error::Error>> {
+            self.0.map(str::to_string).map_err(Into::into)
+        }
+    }
+
+    fn armory() -> VectorIndex {
+        let mut index = index();
+        index.create_collection("items", 2, "keyed").unwrap();
+        index.upsert("items", VectorPoint::new("sword", vec![1.0, 0.0])).unwrap();
+        index.upsert("items", VectorPoint::new("blade", vec![0.0, 1.0])).unwrap();
+        index
+    }
+
+    fn ids(results: &[SearchResult]) -> Vec<&str> {
+        results.iter().map(|r| r.id.as_str()).collect()
+    }
+
+    #[test]
+    fn expansion_keeps_the_original_first_and_drops_duplicates() {
+        let templates = QueryExpansion::Templates(vec![
+            "Where can I buy {query}".to_string(),
+            "  {query}  ".to_string(),
+            "WHERE CAN I BUY {query}".to_string(),
+        ]);
+        assert_eq!(templates.expand("a sword"), vec!["a sword", "Where can I buy a sword"]);
+
+        let listed = Paraphrases(Ok("1. a blade\n- a sabre\n\n* a longsword\n4) a rapier"));
+        let generated = QueryExpansion::Generator { generator: &listed, variants: 3 };
+        assert_eq!(generated.expand("a sword"), vec!["a sword", "a blade", "a sabre"]);
+
+        let failing = Paraphrases(Err("rate limited"));
+        let generated = QueryExpansion::Generator { generator: &failing, variants: 3 };
+        assert_eq!(generated.expand("a sword"), vec!["a sword"]);
+    }
+
+    #[test]
+    fn expanded_variants_are_fused_with_their_best_scores() {
+        let index = armory();
+        let plain = index.search_with("items", "sword", &SearchOptions::new(1), &Keyed).unwrap();
+        assert_eq!(ids(&plain), vec!["sword"]);
+
+        let expansion = QueryExpansion::Templates(vec!["blade or {query}".to_string()]);
+        let options = SearchOptions::new(2).with_expansion(expansion);
+        let fused = index.search_with("items", "sword", &options, &Keyed).unwrap();
+        assert_eq!(ids(&fused), vec!["blade", "sword"]);
+        assert!(fused.iter().all(|r| (r.score - 1.0).abs() < 1e-6));
+
+        // Without weight on the original, the variant's ranking decides
+        let expansion = QueryExpansion::Templates(vec!["blade or {query}".to_string()]);
+        let options = SearchOptions::new(1).with_expansion(expansion).with_original_weight(0.0);
+        assert_eq!(ids(&index.search_with("items", "sword", &options, &Keyed).unwrap()), vec!["blade"]);
+    }
+
+    #[test]
+    fn min_score_filters_fused_and_plain_results() {
+        let index = armory();
+        let options = SearchOptions::new(5).with_min_score(0.7);
+        assert_eq!(ids(&index.search_with("items", "axe", &options, &Keyed).unwrap()), vec!["blade"]);
+
+        let expansion = QueryExpansion::Templates(vec!["sword {query}".to_string()]);
+        let options = SearchOptions::new(5).with_expansion(expansion).with_min_score(0.9);
+        assert_eq!(ids(&index.search_with("items", "axe", &options, &Keyed).unwrap()), vec!["sword"]);
+    }
+
+    #[test]
+    fn gc_task_collects_expired_points_and_stops_when_dropped() {
+        let mut index = index();
+        index.create_collection("npc", 2, "test").unwrap();
+        let mut point = VectorPoint::new("stale", vec![1.0, 0.0]);
+        point.payload.insert(EXPIRES_AT_FIELD.to_string(), Value::from(1u64));
+        index.upsert("npc", point).unwrap();
+        let index = Arc::new(Mutex::new(index));
+
+        let (tx, rx) = mpsc::channel();
+        let task = spawn_gc_task(index.clone(), Duration::from_millis(5), move |report| {
+            let _ = tx.send(report.total_removed());
+        });
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(1));
+        assert!(task.is_running());
+        assert_eq!(index.lock().unwrap().len("npc").unwrap(), 0);
+
+        drop(task);
+        // The thread held the only other reference to the index
+        assert_eq!(Arc::strong_count(&index), 1);
+    }
+
+    #[test]
+    fn stopping_does_not_wait_for_the_interval() {
+        let task = spawn_gc_task(Arc::new(Mutex::new(index())), Duration::from_secs(3600), |_| {});
+        let started = Instant::now();
+        task.stop();
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    // p0 is closest to [1, 0] and p9 furthest; t3 ties with p3
+    fn shelf() -> VectorIndex {
+        let mut index = index();
+        index.create_collection("shelf", 2, "test").unwrap();
+        for i in 0..10 {
+            index.upsert("shelf", VectorPoint::new(&format!("p{}", i), vec![10.0 - i as f32, i as f32])).unwrap();
+        }
+        index.upsert("shelf", VectorPoint::new("t3", vec![7.0, 3.0])).unwrap();
+        index
+    }
+
+    #[test]
+    fn cursors_walk_the_whole_ranking_once() {
+        let index = shelf();
+        let mut page = index.search_page("shelf", &[1.0, 0.0], &PageRequest::new(4)).unwrap();
+        assert_eq!(page.total, 11);
+        let mut seen: Vec<String> = page.results.iter().map(|hit| hit.id.clone()).collect();
+        while let Some(cursor) = page.next_cursor.clone() {
+            page = index.search_page("shelf", &[1.0, 0.0], &PageRequest::new(4).after(&cursor)).unwrap();
+            seen.extend(page.results.iter().map(|hit| hit.id.clone()));
+        }
+        assert_eq!(seen, vec!["p0", "p1", "p2", "p3", "t3", "p4", "p5", "p6", "p7", "p8", "p9"]);
+
+        let skipped = index.search_page("shelf", &[1.0, 0.0], &PageRequest::new(2).offset(3)).unwrap();
+        assert_eq!(ids(&skipped.results), vec!["p3", "t3"]);
+        let last = index.search_page("shelf", &[1.0, 0.0], &PageRequest::new(3).offset(8)).unwrap();
+        assert_eq!((last.results.len(), last.next_cursor), (3, None));
+    }
+
+    #[test]
+    fn points_added_before_the_cursor_do_not_shift_later_pages() {
+        let mut index = shelf();
+        let first = index.search_page("shelf", &[1.0, 0.0], &PageRequest::new(3)).unwrap();
+        let cursor = first.next_cursor.unwrap();
+        index.upsert("shelf", VectorPoint::new("new", vec![1.0, 0.0])).unwrap();
+        let second = index.search_page("shelf", &[1.0, 0.0], &PageRequest::new(3).after(&cursor)).unwrap();
+        assert_eq!(ids(&second.results), vec!["p3", "t3", "p4"]);
+
+        for bad in ["zz", "nothex!!p3"] {
+            let err = index.search_page("shelf", &[1.0, 0.0], &PageRequest::new(3).after(bad)).unwrap_err();
+            assert!(matches!(err, VectorIndexError::InvalidCursor(_)));
+        }
+        let err = index.search_page("shelf", &[1.0], &PageRequest::new(3)).unwrap_err();
+        assert!(matches!(err, VectorIndexError::DimensionMismatch { expected: 2, found: 1 }));
+    }
+
+    #[test]
+    fn streams_deliver_every_match_in_batches() {
+        let index = shelf();
+        let options = StreamOptions::default().shards(3).batch_size(2);
+        let mut batches = Vec::new();
+        let delivered = index.search_stream("shelf", &[1.0, 0.0], &options, |batch| {
+            batches.push(batch);
+            true
+        });
+        assert_eq!(delivered.unwrap(), 11);
+        assert!(batches.iter().all(|batch| batch.len() <= 2));
+        let mut streamed: Vec<String> = batches.into_iter().flatten().map(|hit| hit.id).collect();
+        streamed.sort();
+        assert_eq!(streamed.len(), 11);
+
+        let close = StreamOptions::default().shards(2).min_score(0.99);
+        let mut hits = Vec::new();
+        let collect = |batch: Vec<SearchResult>| {
+            hits.extend(batch.into_iter().map(|hit| hit.id));
+            true
+        };
+        index.search_stream("shelf", &[1.0, 0.0], &close, collect).unwrap();
+        hits.sort();
+        assert_eq!(hits, vec!["p0", "p1"]);
+    }
+
+    #[test]
+    fn returning_false_stops_the_stream() {
+        let index = shelf();
+        let options = StreamOptions::default().shards(1).batch_size(1);
+        let mut calls = 0;
+        let delivered = index.search_stream("shelf", &[1.0, 0.0], &options, |_| {
+            calls += 1;
+            false
+        });
+        assert_eq!((delivered.unwrap(), calls), (1, 1));
+        assert!(index.search_stream("missing", &[1.0, 0.0], &options, |_| true).is_err());
+    }
+
+    #[test]
+    fn soft_deleted_points_leave_search_until_undeleted() {
+        let mut index = index();
+        index.create_collection("memories", 2, "test").unwrap();
+        index.upsert("memories", VectorPoint::new("fire", vec![1.0, 0.0])).unwrap();
+        index.upsert("memories", VectorPoint::new("flood", vec![0.9, 0.1])).unwrap();
+        let ids = |results: Vec<SearchResult>| results.into_iter().map(|r| r.id).collect::<Vec<_>>();
+
+        assert_eq!(index.soft_delete("memories", &["fire".to_string(), "gone".to_string()], 50).unwrap(), 1);
+        assert_eq!(index.soft_delete("memories", &["fire".to_string()], 60).unwrap(), 0);
+        assert_eq!(ids(index.search("memories", &[1.0, 0.0], 10).unwrap()), ["flood"]);
+        assert_eq!(index.deleted("memories").unwrap(), [("fire".to_string(), 50)]);
+        assert!(index.collection("memories").unwrap().points.contains_key("fire"));
+
+        assert_eq!(index.undelete("memories", &["fire".to_string(), "flood".to_string()]).unwrap(), 1);
+        assert_eq!(ids(index.search("memories", &[1.0, 0.0], 10).unwrap()), ["fire", "flood"]);
+        assert!(index.deleted("memories").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,421 @@
+// Persisted format versioning and migrations
+//
+// Every persisted file (saves, agentdb, vector snapshots) is wrapped in an envelope naming its
+// format and schema version. Files written before versioning existed have no envelope and are
+// read as version 0; the `migrate` command tells their format from the shape of the data, or takes
+// it from --format. Subsystems register ordered migration steps per format; loading upgrades
+// old files step by step to the current version. The `migrate` command upgrades files on disk
+// and, with --dry-run, runs every step and reports what would change without writing anything.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::archive::{VECTOR_ARCHIVE_FORMAT, VECTOR_ARCHIVE_VERSION};
+use crate::player_model::{PLAYER_MODEL_FORMAT, PLAYER_MODEL_VERSION};
+use crate::security::encryption::{self, Encryption, EncryptionError};
+
+pub const FORMAT_FIELD: &str = "format";
+pub const VERSION_FIELD: &str = "schema_version";
+pub const DATA_FIELD: &str = "data";
+
+// Current schema versions of the engine's own formats
+pub const AGENTDB_FORMAT: &str = "agentdb";
+pub const AGENTDB_VERSION: u32 = 1;
+pub const VECTOR_SNAPSHOT_FORMAT: &str = "vector_snapshot";
+pub const VECTOR_SNAPSHOT_VERSION: u32 = 1;
+pub const SAVE_FORMAT: &str = "save";
+pub const SAVE_VERSION: u32 = 1;
+
+#[derive(Debug)]
+pub enum MigrationError {
+    // No step registered to upgrade from this version
+    MissingStep { format: String, from: u32 },
+    // File is newer than this build understands
+    TooNew { format: String, version: u32, current: u32 },
+    UnknownFormat(String),
+    // Unversioned file whose format could not be told from its contents
+    UnrecognizedLegacy(String),
+    FormatMismatch { expected: String, found: String },
+    Step { format: String, from: u32, message: String },
+    Encryption(EncryptionError),
+    Serialization(serde_json::Error),
+    Usage(String),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::MissingStep { format, from } => write!(f, "no migration for {} v{}", format, from),
+            MigrationError::TooNew { format, version, current } => {
+                write!(f, "{} v{} is newer than supported v{}", format, version, current)
+            }
+            MigrationError::UnknownFormat(format) => write!(f, "unknown format '{}'", format),
+            MigrationError::UnrecognizedLegacy(path) => {
+                write!(f, "cannot tell the format of unversioned file {}; pass --format=<format>", path)
+            }
+            MigrationError::FormatMismatch { expected, found } => write!(f, "expected a {} file, found {}", expected, found),
+            MigrationError::Step { format, from, message } => {
+                write!(f, "migrating {} from v{} failed: {}", format, from, message)
+            }
+            MigrationError::Encryption(err) => write!(f, "{}", err),
+            MigrationError::Serialization(err) => write!(f, "invalid file: {}", err),
+            MigrationError::Usage(usage) => write!(f, "{}", usage),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<EncryptionError> for MigrationError {
+    fn from(err: EncryptionError) -> Self {
+        MigrationError::Encryption(err)
+    }
+}
+
+impl From<serde_json::Error> for MigrationError {
+    fn from(err: serde_json::Error) -> Self {
+        MigrationError::Serialization(err)
+    }
+}
+
+// Wrap data in a version envelope
+pub fn stamp(format: &str, version: u32, data: Value) -> Value {
+    json!({ FORMAT_FIELD: format, VERSION_FIELD: version, DATA_FIELD: data })
+}
+
+// Split an envelope into (format, version, data); legacy files have no format and version 0
+pub fn unstamp(value: Value) -> (Option<String>, u32, Value) {
+    match value {
+        Value::Object(mut map) if map.contains_key(VERSION_FIELD) && map.contains_key(DATA_FIELD) => {
+            let format = map.get(FORMAT_FIELD).and_then(Value::as_str).map(str::to_string);
+            let version = map.get(VERSION_FIELD).and_then(Value::as_u64).unwrap_or(0) as u32;
+            let data = map.remove(DATA_FIELD).unwrap_or(Value::Null);
+            (format, version, data)
+        }
+        other => (None, 0, other),
+    }
+}
+
+type StepFn = Box<dyn Fn(Value) -> Result<Value, String> + Send + Sync>;
+
+struct MigrationStep {
+    description: String,
+    apply: StepFn,
+}
+
+#[derive(Debug, Clone)]
+pub struct MigrationOutcome {
+    pub format: String,
+    pub from: u32,
+    pub to: u32,
+    // Descriptions of the steps applied, in order
+    pub applied: Vec<String>,
+    pub data: Value,
+}
+
+impl MigrationOutcome {
+    pub fn changed(&self) -> bool {
+        self.from != self.to
+    }
+}
+
+#[derive(Default)]
+pub struct MigrationRegistry {
+    current: HashMap<String, u32>,
+    // format -> from version -> step to from + 1
+    steps: HashMap<String, BTreeMap<u32, MigrationStep>>,
+}
+
+impl MigrationRegistry {
+    pub fn new() -> Self {
+        MigrationRegistry::default()
+    }
+
+    // Registry with the engine's formats and their built-in steps
+    pub fn with_builtin() -> Self {
+        let mut registry = MigrationRegistry::new();
+        registry.set_current(AGENTDB_FORMAT, AGENTDB_VERSION);
+        // v0 files are the bare serialized AgentDb; only the envelope is new
+        registry.register(AGENTDB_FORMAT, 0, "wrap unversioned agentdb file", Ok);
+        registry.set_current(VECTOR_SNAPSHOT_FORMAT, VECTOR_SNAPSHOT_VERSION);
+        // As are v0 snapshots: the collections keyed by name
+        registry.register(VECTOR_SNAPSHOT_FORMAT, 0, "wrap unversioned vector snapshot", Ok);
+        registry.set_current(VECTOR_ARCHIVE_FORMAT, VECTOR_ARCHIVE_VERSION);
+        registry.set_current(SAVE_FORMAT, SAVE_VERSION);
+        registry.set_current(PLAYER_MODEL_FORMAT, PLAYER_MODEL_VERSION);
+        registry
+    }
+
+    pub fn set_current(&mut self, format: &str, version: u32) {
+        self.current.insert(format.to_string(), version);
+    }
+
+    pub fn current(&self, format: &str) -> Option<u32> {
+        self.current.get(format).copied()
+    }
+
+    // Register the step upgrading `format` from `from` to `from + 1`
+    pub fn register<F>(&mut self, format: &str, from: u32, description: &str, step: F)
+    where
+        F: Fn(Value) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        self.steps.entry(format.to_string()).or_default().insert(
+            from,
+            MigrationStep {
+                description: description.to_string(),
+                apply: Box::new(step),
+            },
+        );
+    }
+
+    // Upgrade `data` of `format` from version `from` to the current version
+    pub fn migrate(&self, format: &str, from: u32, mut data: Value) -> Result<MigrationOutcome, MigrationError> {
+        let current = self
+            .current(format)
+            .ok_or_else(|| MigrationError::UnknownFormat(format.to_string()))?;
+        if from > current {
+            return Err(MigrationError::TooNew { format: format.to_string(), version: from, current });
+        }
+        let mut applied = Vec::new();
+        for version in from..current {
+            let step = self
+                .steps
+                .get(format)
+                .and_then(|steps| steps.get(&version))
+                .ok_or_else(|| MigrationError::MissingStep { format: format.to_string(), from: version })?;
+            data = (step.apply)(data).map_err(|message| MigrationError::Step {
+                format: format.to_string(),
+                from: version,
+                message,
+            })?;
+            applied.push(format!("v{} -> v{}: {}", version, version + 1, step.description));
+        }
+        Ok(MigrationOutcome { format: format.to_string(), from, to: current, applied, data })
+    }
+
+    // Upgrade a whole file value; `expected` is assumed for legacy files without a format
+    pub fn migrate_value(&self, value: Value, expected: &str) -> Result<MigrationOutcome, MigrationError> {
+        let (format, version, data) = unstamp(value);
+        let format = format.unwrap_or_else(|| expected.to_string());
+        if format != expected {
+            return Err(MigrationError::FormatMismatch { expected: expected.to_string(), found: format });
+        }
+        self.migrate(&format, version, data)
+    }
+}
+
+// Write `data` as the current version of `format`
+pub fn write_versioned(
+    path: &Path,
+    format: &str,
+    version: u32,
+    data: Value,
+    encryption: Option<&Encryption>,
+) -> Result<(), MigrationError> {
+    let bytes = serde_json::to_vec(&stamp(format, version, data))?;
+    encryption::write_file(path, &bytes, encryption)?;
+    Ok(())
+}
+
+// Read a file of `format`, upgrading it in memory to the current version
+pub fn read_versioned(
+    path: &Path,
+    format: &str,
+    registry: &MigrationRegistry,
+    encryption: Option<&Encryption>,
+) -> Result<MigrationOutcome, MigrationError> {
+    let value: Value = serde_json::from_slice(&encryption::read_file(path, encryption)?)?;
+    registry.migrate_value(value, format)
+}
+
+#[derive(Debug, Clone)]
+pub struct FileMigration {
+    pub path: String,
+    pub format: String,
+    pub from: u32,
+    pub to: u32,
+    pub applied: Vec<String>,
+    pub written: bool,
+}
+
+// Format of an unversioned file, told apart by its shape: agentdb files are {"tables": {...}} and
+// vector snapshots map collection names to collections
+pub fn detect_legacy_format(data: &Value) -> Option<&'static str> {
+    let map = data.as_object()?;
+    let is_collection = |value: &Value| {
+        value.get("points").is_some_and(Value::is_object) && value.get("dimension").is_some_and(Value::is_u64)
+    };
+    if map.len() == 1 && map.get("tables").is_some_and(|tables| tables.is_object() && !is_collection(tables)) {
+        return Some(AGENTDB_FORMAT);
+    }
+    map.values().all(is_collection).then_some(VECTOR_SNAPSHOT_FORMAT)
+}
+
+// Upgrade a file on disk in place (keeping its encryption); with `dry_run` nothing is written.
+// `legacy_format` names the format of an unversioned file; when None it is detected from the data.
+pub fn migrate_file(
+    path: &Path,
+    registry: &MigrationRegistry,
+    legacy_format: Option<&str>,
+    dry_run: bool,
+    encryption: Option<&Encryption>,
+) -> Result<FileMigration, MigrationError> {
+    let raw = std::fs::read(path).map_err(EncryptionError::Io)?;
+    let was_encrypted = encryption::is_encrypted(&raw);
+    let value: Value = serde_json::from_slice(&encryption::read_file(path, encryption)?)?;
+    let (format, version, data) = unstamp(value);
+    let format = format
+        .or_else(|| legacy_format.map(str::to_string))
+        .or_else(|| detect_legacy_format(&data).map(str::to_string))
+        .ok_or_else(|| MigrationError::UnrecognizedLegacy(path.display().to_string()))?;
+    let outcome = registry.migrate(&format, version, data)?;
+    let written = !dry_run && outcome.changed();
+    if written {
+        let keep_encryption = if was_encrypted { encryption } else { None };
+        write_versioned(path, &outcome.format, outcome.to, outcome.data, keep_encryption)?;
+    }
+    Ok(FileMigration {
+        path: path.display().to_string(),
+        format: outcome.format,
+        from: outcome.from,
+        to: outcome.to,
+        applied: outcome.applied,
+        written,
+    })
+}
+
+// `migrate [--dry-run] [--format=<format>] <file>...`; --format applies to unversioned files only.
+// Encrypted files use the ARCADIA_SAVE_PASSPHRASE variable, and plaintext files are still read (and
+// stay plaintext) when it is set
+pub fn run_migrate_command(args: &[String]) -> Result<String, MigrationError> {
+    let dry_run = args.iter().any(|a| a == "--dry-run");
+    let legacy_format = args.iter().find_map(|a| a.strip_prefix("--format="));
+    let files: Vec<&String> = args.iter().filter(|a| !a.starts_with("--")).collect();
+    if files.is_empty() {
+        return Err(MigrationError::Usage("usage: migrate [--dry-run] [--format=<format>] <file>...".to_string()));
+    }
+    let encryption = std::env::var("ARCADIA_SAVE_PASSPHRASE")
+        .ok()
+        .map(|p| Encryption::with_passphrase(&p).allow_plaintext());
+    let registry = MigrationRegistry::with_builtin();
+
+    let mut out = String::new();
+    for file in files {
+        let result = migrate_file(Path::new(file), &registry, legacy_format, dry_run, encryption.as_ref())?;
+        if result.from == result.to {
+            out.push_str(&format!("{}: {} v{} is up to date\n", result.path, result.format, result.to));
+            continue;
+        }
+        let action = if dry_run { "would migrate" } else { "migrated" };
+        out.push_str(&format!("{}: {} {} v{} -> v{}\n", result.path, action, result.format, result.from, result.to));
+        for step in &result.applied {
+            out.push_str(&format!("  {}\n", step));
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> MigrationRegistry {
+        let mut registry = MigrationRegistry::new();
+        registry.set_current("quest_log", 2);
+        registry.register("quest_log", 0, "rename title to name", |mut data| {
+            let title = data["title"].take();
+            data["name"] = title;
+            Ok(data)
+        });
+        registry.register("quest_log", 1, "require a name", |data| {
+            if data["name"].is_string() { Ok(data) } else { Err("quest has no name".to_string()) }
+        });
+        registry
+    }
+
+    fn temp_file(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("arcadia-versioning-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn envelopes_round_trip_and_legacy_values_read_as_v0() {
+        let value = stamp("save", 3, json!({ "slot": 1 }));
+        assert_eq!(unstamp(value), (Some("save".to_string()), 3, json!({ "slot": 1 })));
+        assert_eq!(unstamp(json!({ "slot": 1 })), (None, 0, json!({ "slot": 1 })));
+    }
+
+    #[test]
+    fn steps_run_in_order_up_to_the_current_version() {
+        let outcome = registry().migrate("quest_log", 0, json!({ "title": "The Lost Ring" })).unwrap();
+        assert_eq!(outcome.data["name"], "The Lost Ring");
+        assert_eq!(outcome.applied, vec!["v0 -> v1: rename title to name", "v1 -> v2: require a name"]);
+        assert!(outcome.changed());
+        assert!(!registry().migrate("quest_log", 2, json!({})).unwrap().changed());
+    }
+
+    #[test]
+    fn unsupported_versions_and_failing_steps_are_errors() {
+        let registry = registry();
+        assert!(matches!(registry.migrate("quest_log", 3, json!({})), Err(MigrationError::TooNew { current: 2, .. })));
+        assert!(matches!(registry.migrate("inventory", 0, json!({})), Err(MigrationError::UnknownFormat(_))));
+        let err = registry.migrate("quest_log", 1, json!({})).unwrap_err();
+        assert_eq!(err.to_string(), "migrating quest_log from v1 failed: quest has no name");
+
+        let mut gap = MigrationRegistry::new();
+        gap.set_current("save", 2);
+        gap.register("save", 1, "noop", Ok);
+        assert!(matches!(gap.migrate("save", 0, json!({})), Err(MigrationError::MissingStep { from: 0, .. })));
+
+        let err = registry.migrate_value(stamp("save", 1, json!({})), "quest_log").unwrap_err();
+        assert!(matches!(err, MigrationError::FormatMismatch { .. }));
+    }
+
+    #[test]
+    fn legacy_agentdb_files_are_upgraded_on_disk() {
+        let path = temp_file("agentdb.json");
+        std::fs::write(&path, r#"{"tables":{}}"#).unwrap();
+        let registry = MigrationRegistry::with_builtin();
+
+        let dry = migrate_file(&path, &registry, None, true, None).unwrap();
+        assert_eq!((dry.from, dry.to, dry.written), (0, AGENTDB_VERSION, false));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), r#"{"tables":{}}"#);
+
+        let report = run_migrate_command(&[path.display().to_string()]).unwrap();
+        assert!(report.contains("migrated agentdb v0 -> v1"), "{}", report);
+        let outcome = read_versioned(&path, AGENTDB_FORMAT, &registry, None).unwrap();
+        assert_eq!((outcome.from, outcome.data), (AGENTDB_VERSION, json!({ "tables": {} })));
+
+        let report = run_migrate_command(&["--dry-run".to_string(), path.display().to_string()]).unwrap();
+        assert!(report.ends_with("agentdb v1 is up to date\n"), "{}", report);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(run_migrate_command(&["--dry-run".to_string()]), Err(MigrationError::Usage(_))));
+    }
+
+    #[test]
+    fn legacy_files_are_recognized_by_their_shape() {
+        let snapshot = json!({ "lore": { "name": "lore", "dimension": 2, "model": "m", "default_ttl": null, "points": {} } });
+        assert_eq!(detect_legacy_format(&json!({ "tables": {} })), Some(AGENTDB_FORMAT));
+        assert_eq!(detect_legacy_format(&snapshot), Some(VECTOR_SNAPSHOT_FORMAT));
+        // A lone collection named "tables" is still a snapshot
+        assert_eq!(detect_legacy_format(&json!({ "tables": snapshot["lore"] })), Some(VECTOR_SNAPSHOT_FORMAT));
+        assert_eq!(detect_legacy_format(&json!({ "slot": 1 })), None);
+        assert_eq!(detect_legacy_format(&json!([1, 2])), None);
+
+        let path = temp_file("snapshot.json");
+        std::fs::write(&path, snapshot.to_string()).unwrap();
+        let registry = MigrationRegistry::with_builtin();
+        let migrated = migrate_file(&path, &registry, None, false, None).unwrap();
+        assert_eq!((migrated.format.as_str(), migrated.from, migrated.written), (VECTOR_SNAPSHOT_FORMAT, 0, true));
+        assert_eq!(read_versioned(&path, VECTOR_SNAPSHOT_FORMAT, &registry, None).unwrap().data, snapshot);
+
+        std::fs::write(&path, r#"{"slot":1}"#).unwrap();
+        let err = migrate_file(&path, &registry, None, true, None).unwrap_err();
+        assert!(matches!(err, MigrationError::UnrecognizedLegacy(_)));
+        let report = run_migrate_command(&["--dry-run".to_string(), "--format=save".to_string(), path.display().to_string()]);
+        assert!(matches!(report, Err(MigrationError::MissingStep { from: 0, .. })));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
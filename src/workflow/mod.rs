@@ -0,0 +1,774 @@
+// Workflows
+//
+// A workflow is an ordered list of steps, each naming a handler registered with the executor and
+// a JSON input. Workflows are plain data (TOML or JSON) so designers and tools can author them;
+// the code behind each step lives in the handler registry.
+//
+// Steps that call flaky external services can set a timeout, a retry policy with exponential
+// backoff, and a compensation handler. When a step fails for good, the compensations of the
+// steps that already completed run in reverse order (saga pattern), so a half-finished workflow
+// undoes its side effects instead of leaving them behind.
+//
+// Timeouts run the attempt on a separate thread; a timed-out handler cannot be interrupted and is
+// left to finish in the background with its result discarded, so handlers should be idempotent.
+//
+// A run can be given a trigger context (a webhook payload, a queue message); handlers see it in
+// the outputs map under "trigger", next to the outputs of earlier steps. See `triggers`, and
+// `schedule` for workflows started by cron expressions.
+//
+// Long-running workflows can be run through a WorkflowStore, which checkpoints the execution
+// (definition, current step, outputs, attempt counts, compensation progress) to agentdb after
+// every attempt and optionally flushes agentdb to disk. After a restart, in-flight executions are
+// listed and resumed from their last checkpoint; an attempt that was running when the process
+// died is simply retried. Executions can also be cancelled, which compensates completed steps.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::agentdb::AgentDb;
+use crate::namespace::Namespace;
+use crate::resilience::Backoff;
+use crate::rng::Rng;
+
+pub mod schedule;
+pub mod triggers;
+
+const EXECUTIONS_TABLE: &str = "workflow_executions";
+
+// Outputs key holding the context a run was triggered with; no step may use this name
+pub const TRIGGER_KEY: &str = "trigger";
+
+// Step handler: (step input, outputs of earlier steps by step name) -> output
+pub type StepHandler = Arc<dyn Fn(&Value, &Map<String, Value>) -> Result<Value, String> + Send + Sync>;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    // Attempts including the first; 1 means no retries
+    pub max_attempts: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    pub multiplier: f64,
+    pub jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy { max_attempts: 1, initial_backoff_ms: 200, max_backoff_ms: 30_000, multiplier: 2.0, jitter: 0.2 }
+    }
+}
+
+impl RetryPolicy {
+    pub fn attempts(max_attempts: u32) -> Self {
+        RetryPolicy { max_attempts, ..RetryPolicy::default() }
+    }
+
+    pub fn backoff(&self) -> Backoff {
+        Backoff {
+            initial: Duration::from_millis(self.initial_backoff_ms),
+            max: Duration::from_millis(self.max_backoff_ms),
+            multiplier: self.multiplier,
+            jitter: self.jitter,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowStep {
+    pub name: String,
+    // Registered handler to run
+    pub handler: String,
+    #[serde(default)]
+    pub input: Value,
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    // Handler that undoes this step if a later step fails; it receives this step's output
+    #[serde(default)]
+    pub compensation: Option<String>,
+}
+
+impl WorkflowStep {
+    pub fn new(name: &str, handler: &str) -> Self {
+        WorkflowStep {
+            name: name.to_string(),
+            handler: handler.to_string(),
+            input: Value::Null,
+            timeout_ms: None,
+            retry: RetryPolicy::default(),
+            compensation: None,
+        }
+    }
+
+    pub fn input(mut self, input: Value) -> Self {
+        self.input = input;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout_ms = Some(timeout.as_millis() as u64);
+        self
+    }
+
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn compensate_with(mut self, handler: &str) -> Self {
+        self.compensation = Some(handler.to_string());
+        self
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Workflow {
+    pub name: String,
+    #[serde(rename = "step", default)]
+    pub steps: Vec<WorkflowStep>,
+}
+
+impl Workflow {
+    pub fn new(name: &str) -> Self {
+        Workflow { name: name.to_string(), steps: Vec::new() }
+    }
+
+    pub fn step(mut self, step: WorkflowStep) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    // name = "..." followed by [[step]] tables
+    pub fn from_toml(source: &str) -> Result<Self, WorkflowError> {
+        toml::from_str(source).map_err(|err| WorkflowError::Parse(err.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WorkflowError {
+    Parse(String),
+    UnknownHandler { step: String, handler: String },
+    ReservedStepName(String),
+    UnknownExecution(String),
+    // The execution already finished
+    NotInFlight(String),
+    Storage(String),
+}
+
+impl fmt::Display for WorkflowError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorkflowError::Parse(err) => write!(f, "invalid workflow: {}", err),
+            WorkflowError::UnknownHandler { step, handler } => {
+                write!(f, "step '{}' uses unregistered handler '{}'", step, handler)
+            }
+            WorkflowError::ReservedStepName(name) => write!(f, "step name '{}' is reserved", name),
+            WorkflowError::UnknownExecution(id) => write!(f, "no workflow execution '{}'", id),
+            WorkflowError::NotInFlight(id) => write!(f, "workflow execution '{}' has already finished", id),
+            WorkflowError::Storage(err) => write!(f, "workflow storage error: {}", err),
+        }
+    }
+}
+
+impl Error for WorkflowError {}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AttemptOutcome {
+    Succeeded,
+    Failed { error: String },
+    TimedOut { after_ms: u64 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StepRecord {
+    pub step: String,
+    pub attempts: Vec<AttemptOutcome>,
+    // Set once the step's compensation has run
+    pub compensation: Option<AttemptOutcome>,
+}
+
+impl StepRecord {
+    pub fn succeeded(&self) -> bool {
+        self.attempts.last() == Some(&AttemptOutcome::Succeeded)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum WorkflowStatus {
+    Running,
+    // Undoing completed steps after a failure or a cancellation
+    Compensating { failed_step: String, error: String, cancelled: bool },
+    Completed,
+    // A step failed and every completed step with a compensation was undone
+    Compensated { failed_step: String, error: String },
+    // A step failed and at least one compensation failed too; needs manual cleanup
+    CompensationFailed { failed_step: String, error: String },
+    // Cancelled on request; `compensated` is false if an undo failed
+    Cancelled { reason: String, compensated: bool },
+}
+
+impl WorkflowStatus {
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, WorkflowStatus::Running | WorkflowStatus::Compensating { .. })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowRun {
+    pub id: String,
+    pub workflow: String,
+    pub status: WorkflowStatus,
+    // Output of each completed step by step name, plus the trigger context if any
+    pub outputs: Map<String, Value>,
+    pub steps: Vec<StepRecord>,
+}
+
+impl WorkflowRun {
+    // Index of the step to run next, i.e. the number of steps that succeeded
+    pub fn current_step(&self) -> usize {
+        self.steps.iter().take_while(|record| record.succeeded()).count()
+    }
+}
+
+// What the store keeps per execution: the definition it was started with and its progress
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowExecution {
+    pub definition: Workflow,
+    pub run: WorkflowRun,
+    // Unix seconds of the last checkpoint
+    pub updated_at: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExecutionSummary {
+    pub id: String,
+    pub workflow: String,
+    pub status: WorkflowStatus,
+    pub current_step: Option<String>,
+    // Attempts made so far on the current step
+    pub attempts: usize,
+    pub updated_at: u64,
+}
+
+// Checkpoints executions into agentdb under a namespace
+pub struct WorkflowStore {
+    namespace: Namespace,
+    // agentdb is saved here after every checkpoint when set
+    path: Option<PathBuf>,
+}
+
+impl WorkflowStore {
+    pub fn new(namespace: Namespace) -> Self {
+        WorkflowStore { namespace, path: None }
+    }
+
+    pub fn persist_to(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub fn execution(&self, db: &AgentDb, id: &str) -> Option<WorkflowExecution> {
+        db.get(&self.namespace, EXECUTIONS_TABLE, id).and_then(|value| serde_json::from_value(value.clone()).ok())
+    }
+
+    pub fn in_flight(&self, db: &AgentDb) -> Vec<ExecutionSummary> {
+        db.scan(&self.namespace, EXECUTIONS_TABLE)
+            .filter_map(|(_, value)| serde_json::from_value::<WorkflowExecution>(value.clone()).ok())
+            .filter(|execution| !execution.run.status.is_finished())
+            .map(|execution| {
+                let index = execution.run.current_step();
+                ExecutionSummary {
+                    id: execution.run.id.clone(),
+                    workflow: execution.run.workflow.clone(),
+                    status: execution.run.status.clone(),
+                    current_step: execution.definition.steps.get(index).map(|step| step.name.clone()),
+                    attempts: execution.run.steps.get(index).map_or(0, |record| record.attempts.len()),
+                    updated_at: execution.updated_at,
+                }
+            })
+            .collect()
+    }
+
+    // Drop finished executions; returns how many were removed
+    pub fn prune_finished(&self, db: &mut AgentDb) -> usize {
+        let finished: Vec<String> = db
+            .scan(&self.namespace, EXECUTIONS_TABLE)
+            .filter(|(_, value)| {
+                serde_json::from_value::<WorkflowExecution>((*value).clone()).is_ok_and(|e| e.run.status.is_finished())
+            })
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in &finished {
+            db.delete(&self.namespace, EXECUTIONS_TABLE, id);
+        }
+        if !finished.is_empty() {
+            let _ = self.flush(db);
+        }
+        finished.len()
+    }
+
+    fn checkpoint(&self, db: &mut AgentDb, definition: &Workflow, run: &WorkflowRun) -> Result<(), WorkflowError> {
+        let updated_at = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let execution = WorkflowExecution { definition: definition.clone(), run: run.clone(), updated_at };
+        let value = serde_json::to_value(&execution).map_err(|err| WorkflowError::Storage(err.to_string()))?;
+        db.put(&self.namespace, EXECUTIONS_TABLE, &run.id, value).map_err(|err| WorkflowError::Storage(err.to_string()))?;
+        self.flush(db)
+    }
+
+    fn flush(&self, db: &AgentDb) -> Result<(), WorkflowError> {
+        match &self.path {
+            Some(path) => db.save(path).map_err(|err| WorkflowError::Storage(err.to_string())),
+            None => Ok(()),
+        }
+    }
+}
+
+type Checkpoint<'a> = dyn FnMut(&WorkflowRun) -> Result<(), WorkflowError> + 'a;
+
+pub struct WorkflowExecutor {
+    handlers: HashMap<String, StepHandler>,
+    rng: Rng,
+}
+
+impl WorkflowExecutor {
+    pub fn new() -> Self {
+        WorkflowExecutor { handlers: HashMap::new(), rng: Rng::from_time() }
+    }
+
+    pub fn register<F>(&mut self, name: &str, handler: F)
+    where
+        F: Fn(&Value, &Map<String, Value>) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        self.handlers.insert(name.to_string(), Arc::new(handler));
+    }
+
+    // Every handler and compensation the workflow names must be registered
+    pub fn validate(&self, workflow: &Workflow) -> Result<(), WorkflowError> {
+        for step in &workflow.steps {
+            if step.name == TRIGGER_KEY {
+                return Err(WorkflowError::ReservedStepName(step.name.clone()));
+            }
+            for handler in std::iter::once(&step.handler).chain(step.compensation.as_ref()) {
+                if !self.handlers.contains_key(handler) {
+                    return Err(WorkflowError::UnknownHandler { step: step.name.clone(), handler: handler.clone() });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Run to the end in memory
+    pub fn run(&mut self, workflow: &Workflow) -> Result<WorkflowRun, WorkflowError> {
+        self.run_with(workflow, Value::Null)
+    }
+
+    pub fn run_with(&mut self, workflow: &Workflow, trigger: Value) -> Result<WorkflowRun, WorkflowError> {
+        self.validate(workflow)?;
+        let mut run = self.new_run(workflow, trigger);
+        self.drive(workflow, &mut run, &mut |_| Ok(()))?;
+        Ok(run)
+    }
+
+    // Run to the end, checkpointing to the store after every attempt
+    pub fn start(&mut self, workflow: &Workflow, db: &mut AgentDb, store: &WorkflowStore) -> Result<WorkflowRun, WorkflowError> {
+        self.start_with(workflow, Value::Null, db, store)
+    }
+
+    pub fn start_with(
+        &mut self,
+        workflow: &Workflow,
+        trigger: Value,
+        db: &mut AgentDb,
+        store: &WorkflowStore,
+    ) -> Result<WorkflowRun, WorkflowError> {
+        self.validate(workflow)?;
+        let mut run = self.new_run(workflow, trigger);
+        store.checkpoint(db, workflow, &run)?;
+        self.drive(workflow, &mut run, &mut |run| store.checkpoint(db, workflow, run))?;
+        Ok(run)
+    }
+
+    // Continue an in-flight execution from its last checkpoint
+    pub fn resume(&mut self, id: &str, db: &mut AgentDb, store: &WorkflowStore) -> Result<WorkflowRun, WorkflowError> {
+        let WorkflowExecution { definition, mut run, .. } = self.in_flight(id, db, store)?;
+        self.validate(&definition)?;
+        self.drive(&definition, &mut run, &mut |run| store.checkpoint(db, &definition, run))?;
+        Ok(run)
+    }
+
+    // Stop an in-flight execution and compensate the steps it completed
+    pub fn cancel(&mut self, id: &str, reason: &str, db: &mut AgentDb, store: &WorkflowStore) -> Result<WorkflowRun, WorkflowError> {
+        let WorkflowExecution { definition, mut run, .. } = self.in_flight(id, db, store)?;
+        self.validate(&definition)?;
+        if let WorkflowStatus::Running = run.status {
+            let failed_step = definition.steps.get(run.current_step()).map_or_else(String::new, |step| step.name.clone());
+            run.status = WorkflowStatus::Compensating { failed_step, error: reason.to_string(), cancelled: true };
+            store.checkpoint(db, &definition, &run)?;
+        }
+        self.drive(&definition, &mut run, &mut |run| store.checkpoint(db, &definition, run))?;
+        Ok(run)
+    }
+
+    fn in_flight(&self, id: &str, db: &AgentDb, store: &WorkflowStore) -> Result<WorkflowExecution, WorkflowError> {
+        let execution = store.execution(db, id).ok_or_else(|| WorkflowError::UnknownExecution(id.to_string()))?;
+        if execution.run.status.is_finished() {
+            return Err(WorkflowError::NotInFlight(id.to_string()));
+        }
+        Ok(execution)
+    }
+
+    fn new_run(&mut self, workflow: &Workflow, trigger: Value) -> WorkflowRun {
+        let mut outputs = Map::new();
+        if !trigger.is_null() {
+            outputs.insert(TRIGGER_KEY.to_string(), trigger);
+        }
+        WorkflowRun {
+            id: format!("{}-{:012x}", workflow.name, self.rng.next_u64() & 0xffff_ffff_ffff),
+            workflow: workflow.name.clone(),
+            status: WorkflowStatus::Running,
+            outputs,
+            steps: Vec::new(),
+        }
+    }
+
+    // Advance `run` until it finishes, calling `checkpoint` after every change
+    fn drive(&mut self, workflow: &Workflow, run: &mut WorkflowRun, checkpoint: &mut Checkpoint) -> Result<(), WorkflowError> {
+        while run.status == WorkflowStatus::Running {
+            let index = run.current_step();
+            let Some(step) = workflow.steps.get(index) else {
+                run.status = WorkflowStatus::Completed;
+                return checkpoint(run);
+            };
+            if run.steps.len() == index {
+                run.steps.push(StepRecord { step: step.name.clone(), attempts: Vec::new(), compensation: None });
+            }
+            if let Err(error) = self.run_step(step, run, index, checkpoint)? {
+                run.status = WorkflowStatus::Compensating { failed_step: step.name.clone(), error, cancelled: false };
+                checkpoint(run)?;
+            }
+        }
+        if let WorkflowStatus::Compensating { failed_step, error, cancelled } = run.status.clone() {
+            let compensated = self.compensate(workflow, run, checkpoint)?;
+            run.status = match (cancelled, compensated) {
+                (true, compensated) => WorkflowStatus::Cancelled { reason: error, compensated },
+                (false, true) => WorkflowStatus::Compensated { failed_step, error },
+                (false, false) => WorkflowStatus::CompensationFailed { failed_step, error },
+            };
+            checkpoint(run)?;
+        }
+        Ok(())
+    }
+
+    // Attempts left on the step; the outer result is a checkpoint failure, the inner one the step's
+    fn run_step(
+        &mut self,
+        step: &WorkflowStep,
+        run: &mut WorkflowRun,
+        index: usize,
+        checkpoint: &mut Checkpoint,
+    ) -> Result<Result<(), String>, WorkflowError> {
+        let handler = self.handlers[&step.handler].clone();
+        let backoff = step.retry.backoff();
+        let timeout = step.timeout_ms.map(Duration::from_millis);
+        let max_attempts = step.retry.max_attempts.max(1) as usize;
+        let mut last_error = match run.steps[index].attempts.last() {
+            Some(AttemptOutcome::Failed { error }) => error.clone(),
+            Some(AttemptOutcome::TimedOut { after_ms }) => format!("timed out after {} ms", after_ms),
+            _ => "no attempts left".to_string(),
+        };
+        while run.steps[index].attempts.len() < max_attempts {
+            let attempt = run.steps[index].attempts.len();
+            if attempt > 0 {
+                thread::sleep(backoff.delay(attempt as u32 - 1, &mut self.rng));
+            }
+            let outcome = match call(&handler, &step.input, &run.outputs, timeout) {
+                Ok(output) => {
+                    run.outputs.insert(step.name.clone(), output);
+                    AttemptOutcome::Succeeded
+                }
+                Err(Failure::Error(error)) => {
+                    last_error = error.clone();
+                    AttemptOutcome::Failed { error }
+                }
+                Err(Failure::TimedOut(after)) => {
+                    let after_ms = after.as_millis() as u64;
+                    last_error = format!("timed out after {} ms", after_ms);
+                    AttemptOutcome::TimedOut { after_ms }
+                }
+            };
+            let succeeded = outcome == AttemptOutcome::Succeeded;
+            run.steps[index].attempts.push(outcome);
+            checkpoint(run)?;
+            if succeeded {
+                return Ok(Ok(()));
+            }
+        }
+        Ok(Err(last_error))
+    }
+
+    // Undo completed steps in reverse order, skipping any already undone before a restart;
+    // false if any compensation failed
+    fn compensate(&mut self, workflow: &Workflow, run: &mut WorkflowRun, checkpoint: &mut Checkpoint) -> Result<bool, WorkflowError> {
+        for step in workflow.steps.iter().rev() {
+            let (Some(handler), Some(output)) = (&step.compensation, run.outputs.get(&step.name)) else { continue };
+            let Some(index) = run.steps.iter().position(|r| r.step == step.name) else { continue };
+            if run.steps[index].compensation.is_some() {
+                continue;
+            }
+            let handler = self.handlers[handler].clone();
+            let timeout = step.timeout_ms.map(Duration::from_millis);
+            let outcome = match call(&handler, output, &run.outputs, timeout) {
+                Ok(_) => AttemptOutcome::Succeeded,
+                Err(Failure::Error(error)) => AttemptOutcome::Failed { error },
+                Err(Failure::TimedOut(after)) => AttemptOutcome::TimedOut { after_ms: after.as_millis() as u64 },
+            };
+            run.steps[index].compensation = Some(outcome);
+            checkpoint(run)?;
+        }
+        Ok(run.steps.iter().all(|r| r.compensation.as_ref().is_none_or(|c| *c == AttemptOutcome::Succeeded)))
+    }
+}
+
+impl Default for WorkflowExecutor {
+    fn default() -> Self {
+        WorkflowExecutor::new()
+    }
+}
+
+enum Failure {
+    Error(String),
+    TimedOut(Duration),
+}
+
+fn call(handler: &StepHandler, input: &Value, outputs: &Map<String, Value>, timeout: Option<Duration>) -> Result<Value, Failure> {
+    let Some(timeout) = timeout else {
+        return handler(input, outputs).map_err(Failure::Error);
+    };
+    let (tx, rx) = mpsc::channel();
+    let (handler, input, outputs) = (handler.clone(), input.clone(), outputs.clone());
+    thread::spawn(move || {
+        let _ = tx.send(handler(&input, &outputs));
+    });
+    match rx.recv_timeout(timeout) {
+        Ok(result) => result.map_err(Failure::Error),
+        Err(mpsc::RecvTimeoutError::Timeout) => Err(Failure::TimedOut(timeout)),
+        Err(mpsc::RecvTimeoutError::Disconnected) => Err(Failure::Error("step handler panicked".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    use serde_json::json;
+
+    use super::*;
+
+    fn quick_retries(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy { max_attempts, initial_backoff_ms: 1, max_backoff_ms: 1, multiplier: 1.0, jitter: 0.0 }
+    }
+
+    // Handlers that log their name so tests can check call order; compensations log undo_<step>
+    fn executor(log: &Arc<Mutex<Vec<String>>>) -> WorkflowExecutor {
+        let mut executor = WorkflowExecutor::new();
+        for name in ["reserve", "charge", "ship"] {
+            let calls = log.clone();
+            executor.register(name, move |_, _| {
+                calls.lock().unwrap().push(name.to_string());
+                Ok(json!({ "done": name }))
+            });
+            let undos = log.clone();
+            executor.register(&format!("undo_{}", name), move |output, _| {
+                undos.lock().unwrap().push(format!("undo_{}", output["done"].as_str().unwrap()));
+                Ok(Value::Null)
+            });
+        }
+        executor.register("broken", |_, _| Err("carrier unavailable".to_string()));
+        executor
+    }
+
+    fn order() -> Workflow {
+        Workflow::new("order")
+            .step(WorkflowStep::new("reserve", "reserve").compensate_with("undo_reserve"))
+            .step(WorkflowStep::new("charge", "charge").compensate_with("undo_charge").retry(quick_retries(3)))
+    }
+
+    #[test]
+    fn steps_see_earlier_outputs_and_the_trigger() {
+        let mut executor = WorkflowExecutor::new();
+        executor.register("greet", |input, outputs| Ok(json!(format!("{} {}", input, outputs[TRIGGER_KEY]["player"]))));
+        executor.register("shout", |_, outputs| Ok(json!(outputs["greet"].as_str().unwrap().to_uppercase())));
+        let workflow = Workflow::from_toml(
+            r#"
+name = "welcome"
+
+[[step]]
+name = "greet"
+handler = "greet"
+input = "hello"
+
+[[step]]
+name = "shout"
+handler = "shout"
+"#,
+        )
+        .unwrap();
+        let run = executor.run_with(&workflow, json!({ "player": "ines" })).unwrap();
+        assert_eq!(run.status, WorkflowStatus::Completed);
+        assert_eq!(run.outputs["shout"], json!("\"HELLO\" \"INES\""));
+        assert_eq!(run.current_step(), 2);
+    }
+
+    #[test]
+    fn unknown_handlers_and_reserved_names_are_rejected() {
+        let mut executor = executor(&Arc::default());
+        let missing = order().step(WorkflowStep::new("ship", "ship").compensate_with("undo_nothing"));
+        assert_eq!(
+            executor.run(&missing).unwrap_err(),
+            WorkflowError::UnknownHandler { step: "ship".to_string(), handler: "undo_nothing".to_string() }
+        );
+        let reserved = Workflow::new("w").step(WorkflowStep::new(TRIGGER_KEY, "ship"));
+        assert_eq!(executor.run(&reserved).unwrap_err(), WorkflowError::ReservedStepName(TRIGGER_KEY.to_string()));
+        assert!(matches!(Workflow::from_toml("name = 3"), Err(WorkflowError::Parse(_))));
+    }
+
+    #[test]
+    fn flaky_steps_are_retried() {
+        let mut executor = WorkflowExecutor::new();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        executor.register("flaky", move |_, _| match counter.fetch_add(1, Ordering::SeqCst) {
+            0 | 1 => Err("503".to_string()),
+            _ => Ok(json!("ok")),
+        });
+        let workflow = Workflow::new("w").step(WorkflowStep::new("call", "flaky").retry(quick_retries(3)));
+        let run = executor.run(&workflow).unwrap();
+        assert_eq!(run.status, WorkflowStatus::Completed);
+        let failed = AttemptOutcome::Failed { error: "503".to_string() };
+        assert_eq!(run.steps[0].attempts, vec![failed.clone(), failed, AttemptOutcome::Succeeded]);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn a_step_failing_for_good_compensates_completed_steps_in_reverse() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut executor = executor(&log);
+        let workflow = order().step(WorkflowStep::new("ship", "broken").retry(quick_retries(2)));
+        let run = executor.run(&workflow).unwrap();
+        assert_eq!(
+            run.status,
+            WorkflowStatus::Compensated { failed_step: "ship".to_string(), error: "carrier unavailable".to_string() }
+        );
+        assert_eq!(*log.lock().unwrap(), vec!["reserve", "charge", "undo_charge", "undo_reserve"]);
+        assert_eq!(run.steps[2].attempts.len(), 2);
+        assert_eq!(run.steps[0].compensation, Some(AttemptOutcome::Succeeded));
+    }
+
+    #[test]
+    fn a_failed_undo_needs_manual_cleanup() {
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut executor = executor(&log);
+        executor.register("undo_charge", |_, _| Err("refund api down".to_string()));
+        let workflow = order().step(WorkflowStep::new("ship", "broken"));
+        let run = executor.run(&workflow).unwrap();
+        assert!(matches!(run.status, WorkflowStatus::CompensationFailed { .. }));
+        // The other compensations still ran
+        assert_eq!(log.lock().unwrap().last().map(String::as_str), Some("undo_reserve"));
+    }
+
+    #[test]
+    fn slow_attempts_time_out() {
+        let mut executor = WorkflowExecutor::new();
+        executor.register("slow", |_, _| {
+            thread::sleep(Duration::from_millis(200));
+            Ok(Value::Null)
+        });
+        let workflow = Workflow::new("w").step(WorkflowStep::new("call", "slow").timeout(Duration::from_millis(10)));
+        let run = executor.run(&workflow).unwrap();
+        assert_eq!(run.steps[0].attempts, vec![AttemptOutcome::TimedOut { after_ms: 10 }]);
+        assert_eq!(
+            run.status,
+            WorkflowStatus::Compensated { failed_step: "call".to_string(), error: "timed out after 10 ms".to_string() }
+        );
+    }
+
+    // An execution checkpointed as if the process died during the second attempt at "charge"
+    fn interrupted(executor: &mut WorkflowExecutor, db: &mut AgentDb, store: &WorkflowStore) -> String {
+        let workflow = order();
+        let mut run = executor.new_run(&workflow, Value::Null);
+        run.outputs.insert("reserve".to_string(), json!({ "done": "reserve" }));
+        run.steps.push(StepRecord { step: "reserve".to_string(), attempts: vec![AttemptOutcome::Succeeded], compensation: None });
+        let timed_out = AttemptOutcome::TimedOut { after_ms: 5_000 };
+        run.steps.push(StepRecord { step: "charge".to_string(), attempts: vec![timed_out], compensation: None });
+        store.checkpoint(db, &workflow, &run).unwrap();
+        run.id
+    }
+
+    #[test]
+    fn interrupted_executions_resume_after_a_restart() {
+        let path = std::env::temp_dir().join(format!("arcadia-workflows-{}.json", std::process::id()));
+        let store = WorkflowStore::new(Namespace::default_namespace()).persist_to(&path);
+        let id = interrupted(&mut executor(&Arc::default()), &mut AgentDb::new(), &store);
+
+        // A fresh process: reload agentdb and pick up where the run left off
+        let mut db = AgentDb::load(&path).unwrap();
+        let in_flight = store.in_flight(&db);
+        assert_eq!(in_flight.len(), 1);
+        assert_eq!((in_flight[0].current_step.as_deref(), in_flight[0].attempts), (Some("charge"), 1));
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let run = executor(&log).resume(&id, &mut db, &store).unwrap();
+        assert_eq!(run.status, WorkflowStatus::Completed);
+        assert_eq!(*log.lock().unwrap(), vec!["charge"]);
+        assert_eq!(run.steps[1].attempts.len(), 2);
+        assert!(store.in_flight(&AgentDb::load(&path).unwrap()).is_empty());
+
+        assert_eq!(executor(&log).resume(&id, &mut db, &store).unwrap_err(), WorkflowError::NotInFlight(id.clone()));
+        assert_eq!(
+            executor(&log).resume("nope", &mut db, &store).unwrap_err(),
+            WorkflowError::UnknownExecution("nope".to_string())
+        );
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn cancelling_compensates_what_already_ran() {
+        let store = WorkflowStore::new(Namespace::default_namespace());
+        let mut db = AgentDb::new();
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let mut executor = executor(&log);
+        let id = interrupted(&mut executor, &mut db, &store);
+
+        let run = executor.cancel(&id, "player logged out", &mut db, &store).unwrap();
+        assert_eq!(run.status, WorkflowStatus::Cancelled { reason: "player logged out".to_string(), compensated: true });
+        assert_eq!(*log.lock().unwrap(), vec!["undo_reserve"]);
+        assert_eq!(store.execution(&db, &id).unwrap().run.status, run.status);
+    }
+
+    #[test]
+    fn started_runs_are_checkpointed_and_pruned_once_finished() {
+        let store = WorkflowStore::new(Namespace::default_namespace());
+        let mut db = AgentDb::new();
+        let mut executor = executor(&Arc::default());
+        let finished = executor.start(&order(), &mut db, &store).unwrap();
+        assert_eq!(store.execution(&db, &finished.id).unwrap().run.status, WorkflowStatus::Completed);
+        let pending = interrupted(&mut executor, &mut db, &store);
+
+        assert_eq!(store.prune_finished(&mut db), 1);
+        assert!(store.execution(&db, &finished.id).is_none());
+        assert_eq!(store.in_flight(&db)[0].id, pending);
+    }
+}
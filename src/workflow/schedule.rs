@@ -0,0 +1,618 @@
+// Scheduled workflows
+//
+// Maintenance workflows (re-embedding stale content, compacting indexes, decaying relationships)
+// run on cron schedules declared in aiTOML:
+//
+//   [workflow_schedules.reembed]
+//   workflow = "reembed_stale"
+//   triggers = ["cron:0 */6 * * *"]      # minute hour day-of-month month day-of-week, UTC
+//   missed_runs = "run_once"             # or "skip", "catch_up"
+//   max_catch_up = 24                    # catch_up only
+//   grace_secs = 300                     # skip only
+//
+// Fields accept `*`, numbers, names (jan, mon), ranges, steps and lists ("1-5", "*/15",
+// "mon,wed,fri"); day-of-week 0 and 7 are both Sunday. As in cron, when both day fields are
+// restricted a day matching either one fires. The @hourly, @daily, @weekly and @monthly
+// shorthands are accepted too.
+//
+// The game loop calls `due(now)` once per tick. Each due occurrence becomes a TriggerEvent for
+// `WorkflowExecutor::run_with`, exactly like webhook and queue triggers; the caller reports the
+// end of the run with `finished`. While a schedule's previous run is still going, its occurrences
+// are skipped rather than stacked up (overlap prevention). Occurrences that passed unnoticed, for
+// example while the server was down, are handled by the schedule's missed-run policy:
+//
+//   skip       run the latest occurrence only if it is less than `grace_secs` late
+//   run_once   run the latest occurrence once, however late (default)
+//   catch_up   run up to `max_catch_up` of the missed occurrences, oldest first
+//
+// `save` records how far each schedule has been evaluated in the workflow store's agentdb
+// namespace, and `restore` picks up from there after a restart so downtime counts as missed runs.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use super::triggers::TriggerEvent;
+use super::{WorkflowError, WorkflowStore};
+use crate::agentdb::AgentDb;
+
+const SCHEDULES_TABLE: &str = "workflow_schedules";
+
+// Trigger strings carrying a cron expression start with this
+pub const CRON_PREFIX: &str = "cron:";
+
+// Longest stretch of missed occurrences counted in one evaluation
+const MAX_OCCURRENCES: usize = 10_000;
+
+// How far ahead `next_after` looks; covers Feb 29 only expressions across a skipped leap year
+const SEARCH_DAYS: u64 = 366 * 8;
+
+const MONTHS: [&str; 12] = ["jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"];
+const WEEKDAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CronSchedule {
+    pub expression: String,
+    // One bit per allowed value
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // A `*` day field defers to the other one instead of OR-ing with it
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl CronSchedule {
+    pub fn parse(expression: &str) -> Result<Self, ScheduleError> {
+        let invalid = |reason: String| ScheduleError::InvalidCron { expression: expression.to_string(), reason };
+        let expanded = match expression.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            other => other,
+        };
+        let fields: Vec<&str> = expanded.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields[..] else {
+            return Err(invalid(format!("expected 5 fields, found {}", fields.len())));
+        };
+        let mut weekdays = parse_field(weekday, 0, 7, &WEEKDAYS).map_err(|err| invalid(format!("day of week: {}", err)))?;
+        // 7 is Sunday too
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays & !(1 << 7)) | 1;
+        }
+        let schedule = CronSchedule {
+            expression: expression.trim().to_string(),
+            minutes: parse_field(minute, 0, 59, &[]).map_err(|err| invalid(format!("minute: {}", err)))?,
+            hours: parse_field(hour, 0, 23, &[]).map_err(|err| invalid(format!("hour: {}", err)))?,
+            days: parse_field(day, 1, 31, &[]).map_err(|err| invalid(format!("day of month: {}", err)))?,
+            months: parse_field(month, 1, 12, &MONTHS).map_err(|err| invalid(format!("month: {}", err)))?,
+            weekdays,
+            any_day: day.starts_with('*'),
+            any_weekday: weekday.starts_with('*'),
+        };
+        if schedule.next_after(0).is_none() {
+            return Err(invalid("never fires".to_string()));
+        }
+        Ok(schedule)
+    }
+
+    // Whether the minute containing `at` (unix seconds) is an occurrence
+    pub fn matches(&self, at: u64) -> bool {
+        let day = at / 86_400;
+        let minute_of_day = (at % 86_400) / 60;
+        self.day_matches(day) && bit(self.hours, minute_of_day / 60) && bit(self.minutes, minute_of_day % 60)
+    }
+
+    // First occurrence strictly after the minute containing `after`, in unix seconds
+    pub fn next_after(&self, after: u64) -> Option<u64> {
+        let start = (after / 60 + 1) * 60;
+        let first_day = start / 86_400;
+        let first_minute = (start % 86_400) / 60;
+        for day in first_day..first_day + SEARCH_DAYS {
+            if !self.day_matches(day) {
+                continue;
+            }
+            let from = if day == first_day { first_minute } else { 0 };
+            for hour in from / 60..24 {
+                if !bit(self.hours, hour) {
+                    continue;
+                }
+                let from_minute = if day == first_day && hour == from / 60 { from % 60 } else { 0 };
+                if let Some(minute) = (from_minute..60).find(|&minute| bit(self.minutes, minute)) {
+                    return Some(day * 86_400 + hour * 3_600 + minute * 60);
+                }
+            }
+        }
+        None
+    }
+
+    fn day_matches(&self, day: u64) -> bool {
+        let (_, month, day_of_month) = civil_from_days(day);
+        if !bit(self.months, month) {
+            return false;
+        }
+        // 1970-01-01 was a Thursday
+        let dom = bit(self.days, day_of_month);
+        let dow = bit(self.weekdays, (day + 4) % 7);
+        if self.any_day || self.any_weekday {
+            dom && dow
+        } else {
+            dom || dow
+        }
+    }
+}
+
+// "cron:<expression>"; the only trigger kind schedules support so far
+pub fn parse_trigger(trigger: &str) -> Result<CronSchedule, ScheduleError> {
+    match trigger.trim().strip_prefix(CRON_PREFIX) {
+        Some(expression) => CronSchedule::parse(expression),
+        None => Err(ScheduleError::UnsupportedTrigger(trigger.to_string())),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MissedRunPolicy {
+    Skip,
+    #[default]
+    RunOnce,
+    CatchUp,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScheduleConfig {
+    pub workflow: String,
+    pub triggers: Vec<String>,
+    #[serde(default)]
+    pub missed_runs: MissedRunPolicy,
+    #[serde(default = "default_max_catch_up")]
+    pub max_catch_up: usize,
+    #[serde(default = "default_grace_secs")]
+    pub grace_secs: u64,
+}
+
+fn default_max_catch_up() -> usize {
+    24
+}
+
+fn default_grace_secs() -> u64 {
+    300
+}
+
+impl ScheduleConfig {
+    pub fn cron(workflow: &str, expression: &str) -> Self {
+        ScheduleConfig {
+            workflow: workflow.to_string(),
+            triggers: vec![format!("{}{}", CRON_PREFIX, expression)],
+            missed_runs: MissedRunPolicy::default(),
+            max_catch_up: default_max_catch_up(),
+            grace_secs: default_grace_secs(),
+        }
+    }
+
+    pub fn missed_runs(mut self, policy: MissedRunPolicy) -> Self {
+        self.missed_runs = policy;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScheduleError {
+    InvalidCron { expression: String, reason: String },
+    UnsupportedTrigger(String),
+    NoTriggers(String),
+    DuplicateSchedule(String),
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleError::InvalidCron { expression, reason } => write!(f, "invalid cron expression '{}': {}", expression, reason),
+            ScheduleError::UnsupportedTrigger(trigger) => {
+                write!(f, "unsupported schedule trigger '{}'; expected \"{}<expression>\"", trigger, CRON_PREFIX)
+            }
+            ScheduleError::NoTriggers(name) => write!(f, "schedule '{}' has no triggers", name),
+            ScheduleError::DuplicateSchedule(name) => write!(f, "schedule '{}' is already registered", name),
+        }
+    }
+}
+
+impl Error for ScheduleError {}
+
+// What `save` keeps per schedule
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ScheduleMark {
+    evaluated_through: u64,
+    last_fired: Option<u64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduleStatus {
+    pub name: String,
+    pub workflow: String,
+    pub next_due: Option<u64>,
+    pub last_fired: Option<u64>,
+    pub running: bool,
+    // Occurrences dropped because the previous run had not finished
+    pub skipped_overlap: u64,
+    // Occurrences dropped by the missed-run policy
+    pub skipped_missed: u64,
+}
+
+struct Job {
+    config: ScheduleConfig,
+    crons: Vec<CronSchedule>,
+    next_due: Option<u64>,
+    mark: ScheduleMark,
+    running: bool,
+    skipped_overlap: u64,
+    skipped_missed: u64,
+}
+
+impl Job {
+    fn next_after(&self, after: u64) -> Option<u64> {
+        self.crons.iter().filter_map(|cron| cron.next_after(after)).min()
+    }
+}
+
+#[derive(Default)]
+pub struct WorkflowScheduler {
+    jobs: BTreeMap<String, Job>,
+}
+
+impl WorkflowScheduler {
+    pub fn new() -> Self {
+        WorkflowScheduler::default()
+    }
+
+    pub fn from_config(schedules: &BTreeMap<String, ScheduleConfig>, now: u64) -> Result<Self, ScheduleError> {
+        let mut scheduler = WorkflowScheduler::new();
+        for (name, config) in schedules {
+            scheduler.add(name, config.clone(), now)?;
+        }
+        Ok(scheduler)
+    }
+
+    // Occurrences are counted from `now` on
+    pub fn add(&mut self, name: &str, config: ScheduleConfig, now: u64) -> Result<(), ScheduleError> {
+        if self.jobs.contains_key(name) {
+            return Err(ScheduleError::DuplicateSchedule(name.to_string()));
+        }
+        if config.triggers.is_empty() {
+            return Err(ScheduleError::NoTriggers(name.to_string()));
+        }
+        let crons = config.triggers.iter().map(|trigger| parse_trigger(trigger)).collect::<Result<Vec<_>, _>>()?;
+        let mut job = Job {
+            config,
+            crons,
+            next_due: None,
+            mark: ScheduleMark { evaluated_through: now, last_fired: None },
+            running: false,
+            skipped_overlap: 0,
+            skipped_missed: 0,
+        };
+        job.next_due = job.next_after(now);
+        self.jobs.insert(name.to_string(), job);
+        Ok(())
+    }
+
+    // Occurrences that are due at `now`, as trigger events; marks their schedules as running
+    pub fn due(&mut self, now: u64) -> Vec<TriggerEvent> {
+        let mut events = Vec::new();
+        for (name, job) in &mut self.jobs {
+            let Some(first) = job.next_due.filter(|&due| due <= now) else { continue };
+            let mut occurrences = vec![first];
+            while occurrences.len() < MAX_OCCURRENCES {
+                match job.next_after(occurrences[occurrences.len() - 1]) {
+                    Some(next) if next <= now => occurrences.push(next),
+                    _ => break,
+                }
+            }
+            job.next_due = job.next_after(now);
+            job.mark.evaluated_through = now;
+
+            if job.running {
+                job.skipped_overlap += occurrences.len() as u64;
+                continue;
+            }
+            let latest = occurrences.len() - 1;
+            let fire = match job.config.missed_runs {
+                MissedRunPolicy::Skip if now - occurrences[latest] <= job.config.grace_secs => &occurrences[latest..],
+                MissedRunPolicy::Skip => &occurrences[..0],
+                MissedRunPolicy::RunOnce => &occurrences[latest..],
+                MissedRunPolicy::CatchUp => &occurrences[occurrences.len().saturating_sub(job.config.max_catch_up.max(1))..],
+            };
+            job.skipped_missed += (occurrences.len() - fire.len()) as u64;
+            for &scheduled_for in fire {
+                let expression = job.crons.iter().find(|cron| cron.matches(scheduled_for)).map(|cron| cron.expression.clone());
+                events.push(TriggerEvent {
+                    trigger: name.clone(),
+                    workflow: job.config.workflow.clone(),
+                    context: json!({
+                        "schedule": name,
+                        "cron": expression,
+                        "scheduled_for": scheduled_for,
+                        "late_secs": now - scheduled_for,
+                    }),
+                });
+            }
+            if let Some(&last) = fire.last() {
+                job.running = true;
+                job.mark.last_fired = Some(last);
+            }
+        }
+        events
+    }
+
+    // The run started for this schedule is over; its next occurrence may fire
+    pub fn finished(&mut self, name: &str) {
+        if let Some(job) = self.jobs.get_mut(name) {
+            job.running = false;
+        }
+    }
+
+    // Treat schedules whose workflow has an in-flight execution in the store as running, e.g. a
+    // run interrupted by a restart that is being resumed
+    pub fn sync_in_flight(&mut self, db: &AgentDb, store: &WorkflowStore) {
+        let in_flight = store.in_flight(db);
+        for job in self.jobs.values_mut() {
+            job.running = in_flight.iter().any(|execution| execution.workflow == job.config.workflow);
+        }
+    }
+
+    // Continue from the marks `save` left, so occurrences since then count as missed
+    pub fn restore(&mut self, db: &AgentDb, store: &WorkflowStore) {
+        for (name, job) in &mut self.jobs {
+            let mark = db
+                .get(&store.namespace, SCHEDULES_TABLE, name)
+                .and_then(|value| serde_json::from_value::<ScheduleMark>(value.clone()).ok());
+            if let Some(mark) = mark {
+                job.next_due = job.next_after(mark.evaluated_through);
+                job.mark = mark;
+            }
+        }
+        self.sync_in_flight(db, store);
+    }
+
+    pub fn save(&self, db: &mut AgentDb, store: &WorkflowStore) -> Result<(), WorkflowError> {
+        for (name, job) in &self.jobs {
+            let value = serde_json::to_value(&job.mark).map_err(|err| WorkflowError::Storage(err.to_string()))?;
+            db.put(&store.namespace, SCHEDULES_TABLE, name, value).map_err(|err| WorkflowError::Storage(err.to_string()))?;
+        }
+        store.flush(db)
+    }
+
+    pub fn status(&self) -> Vec<ScheduleStatus> {
+        self.jobs
+            .iter()
+            .map(|(name, job)| ScheduleStatus {
+                name: name.clone(),
+                workflow: job.config.workflow.clone(),
+                next_due: job.next_due,
+                last_fired: job.mark.last_fired,
+                running: job.running,
+                skipped_overlap: job.skipped_overlap,
+                skipped_missed: job.skipped_missed,
+            })
+            .collect()
+    }
+}
+
+fn bit(mask: u64, value: u64) -> bool {
+    mask & (1 << value) != 0
+}
+
+// Comma-separated items of `*`, `n`, `a-b`, each optionally followed by `/step`
+fn parse_field(spec: &str, min: u64, max: u64, names: &[&str]) -> Result<u64, String> {
+    let value = |text: &str| -> Result<u64, String> {
+        let lower = text.to_ascii_lowercase();
+        let parsed = match names.iter().position(|name| *name == lower) {
+            // Month names start at 1, weekday names at 0
+            Some(index) => index as u64 + min,
+            None => text.parse::<u64>().map_err(|_| format!("'{}' is not a number", text))?,
+        };
+        if parsed < min || parsed > max {
+            return Err(format!("{} is outside {}-{}", parsed, min, max));
+        }
+        Ok(parsed)
+    };
+    let mut mask = 0u64;
+    for item in spec.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => match step.parse::<u64>() {
+                Ok(step) if step > 0 => (range, Some(step)),
+                _ => return Err(format!("bad step '{}'", step)),
+            },
+            None => (item, None),
+        };
+        let (low, high) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((low, high)) => (value(low)?, value(high)?),
+            // "5/15" runs from 5 to the end of the range
+            None if step.is_some() => (value(range)?, max),
+            None => {
+                let single = value(range)?;
+                (single, single)
+            }
+        };
+        if low > high {
+            return Err(format!("range {}-{} is backwards", low, high));
+        }
+        for v in (low..=high).step_by(step.unwrap_or(1) as usize) {
+            mask |= 1 << v;
+        }
+    }
+    Ok(mask)
+}
+
+// (year, month, day) of a day count since 1970-01-01, proleptic Gregorian
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::namespace::Namespace;
+    use crate::workflow::{Workflow, WorkflowExecutor, WorkflowStep};
+
+    // 2026-10-13, a Tuesday, 00:00 UTC
+    const TUESDAY_13TH: u64 = 20_739 * 86_400;
+    const HOUR: u64 = 3_600;
+
+    fn hourly(policy: MissedRunPolicy) -> WorkflowScheduler {
+        let mut scheduler = WorkflowScheduler::new();
+        scheduler.add("compact", ScheduleConfig::cron("compact_indexes", "0 * * * *").missed_runs(policy), TUESDAY_13TH).unwrap();
+        scheduler
+    }
+
+    fn scheduled_for(events: &[TriggerEvent]) -> Vec<u64> {
+        events.iter().map(|event| (event.context["scheduled_for"].as_u64().unwrap() - TUESDAY_13TH) / HOUR).collect()
+    }
+
+    #[test]
+    fn days_are_converted_to_civil_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+        assert_eq!(civil_from_days(20_739), (2026, 10, 13));
+    }
+
+    #[test]
+    fn expressions_find_their_next_occurrence() {
+        let every_six_hours = CronSchedule::parse("0 */6 * * *").unwrap();
+        assert_eq!(every_six_hours.next_after(0), Some(6 * HOUR));
+        assert_eq!(every_six_hours.next_after(6 * HOUR), Some(12 * HOUR));
+        assert!(every_six_hours.matches(6 * HOUR + 59) && !every_six_hours.matches(6 * HOUR + 60));
+
+        let weekdays = CronSchedule::parse("30 9 * * mon-fri").unwrap();
+        assert_eq!(weekdays.next_after(TUESDAY_13TH), Some(TUESDAY_13TH + 9 * HOUR + 1_800));
+        // Saturday night to Monday morning
+        assert_eq!(weekdays.next_after(TUESDAY_13TH + 4 * 86_400), Some(TUESDAY_13TH + 6 * 86_400 + 9 * HOUR + 1_800));
+        // 7 is another name for Sunday
+        assert_eq!(CronSchedule::parse("0 0 * * 7").unwrap().weekdays, CronSchedule::parse("0 0 * * 0").unwrap().weekdays);
+        assert_eq!(CronSchedule::parse("@daily").unwrap().next_after(TUESDAY_13TH), Some(TUESDAY_13TH + 86_400));
+    }
+
+    #[test]
+    fn restricted_day_fields_fire_on_either() {
+        let noon = TUESDAY_13TH + 12 * HOUR;
+        let thirteenth_or_friday = CronSchedule::parse("0 12 13 * fri").unwrap();
+        assert_eq!(thirteenth_or_friday.next_after(TUESDAY_13TH), Some(noon));
+        assert_eq!(thirteenth_or_friday.next_after(noon), Some(noon + 3 * 86_400));
+        let thirteenth = CronSchedule::parse("0 12 13 * *").unwrap();
+        assert_eq!(thirteenth.next_after(noon), Some(noon + 31 * 86_400));
+    }
+
+    #[test]
+    fn bad_expressions_and_triggers_are_rejected() {
+        for expression in ["0 * * *", "60 * * * *", "0 5-1 * * *", "*/0 * * * *", "0 0 * smarch *", "0 0 30 feb *"] {
+            assert!(matches!(CronSchedule::parse(expression), Err(ScheduleError::InvalidCron { .. })), "{}", expression);
+        }
+        assert_eq!(parse_trigger("every:6h").unwrap_err(), ScheduleError::UnsupportedTrigger("every:6h".to_string()));
+
+        let mut scheduler = hourly(MissedRunPolicy::RunOnce);
+        let config = ScheduleConfig::cron("compact_indexes", "@hourly");
+        assert_eq!(scheduler.add("compact", config.clone(), 0), Err(ScheduleError::DuplicateSchedule("compact".to_string())));
+        let empty = ScheduleConfig { triggers: Vec::new(), ..config };
+        assert_eq!(scheduler.add("empty", empty, 0), Err(ScheduleError::NoTriggers("empty".to_string())));
+    }
+
+    #[test]
+    fn overlapping_occurrences_are_skipped_while_a_run_is_going() {
+        let mut scheduler = hourly(MissedRunPolicy::RunOnce);
+        assert!(scheduler.due(TUESDAY_13TH + 1_800).is_empty());
+        let events = scheduler.due(TUESDAY_13TH + HOUR);
+        assert_eq!(scheduled_for(&events), vec![1]);
+        assert_eq!((events[0].workflow.as_str(), &events[0].context["cron"]), ("compact_indexes", &json!("0 * * * *")));
+
+        assert!(scheduler.due(TUESDAY_13TH + 2 * HOUR).is_empty());
+        scheduler.finished("compact");
+        let events = scheduler.due(TUESDAY_13TH + 3 * HOUR + 60);
+        assert_eq!(scheduled_for(&events), vec![3]);
+        assert_eq!(events[0].context["late_secs"], 60);
+        let status = &scheduler.status()[0];
+        assert_eq!((status.skipped_overlap, status.running, status.next_due), (1, true, Some(TUESDAY_13TH + 4 * HOUR)));
+    }
+
+    #[test]
+    fn missed_run_policies() {
+        let mut run_once = hourly(MissedRunPolicy::RunOnce);
+        assert_eq!(scheduled_for(&run_once.due(TUESDAY_13TH + 5 * HOUR)), vec![5]);
+        assert_eq!(run_once.status()[0].skipped_missed, 4);
+
+        let mut catch_up = WorkflowScheduler::new();
+        let config = ScheduleConfig {
+            max_catch_up: 3,
+            ..ScheduleConfig::cron("compact_indexes", "@hourly").missed_runs(MissedRunPolicy::CatchUp)
+        };
+        catch_up.add("compact", config, TUESDAY_13TH).unwrap();
+        assert_eq!(scheduled_for(&catch_up.due(TUESDAY_13TH + 5 * HOUR)), vec![3, 4, 5]);
+        assert_eq!(catch_up.status()[0].skipped_missed, 2);
+
+        // Within the 300 s grace the occurrence still runs, past it it is dropped
+        let mut skip = hourly(MissedRunPolicy::Skip);
+        assert_eq!(scheduled_for(&skip.due(TUESDAY_13TH + HOUR + 200)), vec![1]);
+        skip.finished("compact");
+        assert!(skip.due(TUESDAY_13TH + 2 * HOUR + 400).is_empty());
+        assert_eq!(skip.status()[0].skipped_missed, 1);
+    }
+
+    #[test]
+    fn downtime_counts_as_missed_after_a_restore() {
+        let store = WorkflowStore::new(Namespace::default_namespace());
+        let mut db = AgentDb::new();
+        let mut before = hourly(MissedRunPolicy::RunOnce);
+        before.due(TUESDAY_13TH + HOUR);
+        before.finished("compact");
+        before.save(&mut db, &store).unwrap();
+
+        // Restarted at 10:00; occurrences from 02:00 on were never evaluated
+        let restart = TUESDAY_13TH + 10 * HOUR + 60;
+        let mut after = WorkflowScheduler::new();
+        after.add("compact", ScheduleConfig::cron("compact_indexes", "0 * * * *"), restart).unwrap();
+        after.restore(&db, &store);
+        assert_eq!(after.status()[0].last_fired, Some(TUESDAY_13TH + HOUR));
+        assert_eq!(scheduled_for(&after.due(restart)), vec![10]);
+        assert_eq!(after.status()[0].skipped_missed, 8);
+    }
+
+    #[test]
+    fn schedules_with_an_in_flight_execution_count_as_running() {
+        let store = WorkflowStore::new(Namespace::default_namespace());
+        let mut db = AgentDb::new();
+        let mut executor = WorkflowExecutor::new();
+        executor.register("noop", |_, _| Ok(serde_json::Value::Null));
+        let workflow = Workflow::new("compact_indexes").step(WorkflowStep::new("compact", "noop"));
+        let run = executor.new_run(&workflow, serde_json::Value::Null);
+        store.checkpoint(&mut db, &workflow, &run).unwrap();
+
+        let schedules: BTreeMap<String, ScheduleConfig> = toml::from_str(
+            r#"
+[compact]
+workflow = "compact_indexes"
+triggers = ["cron:0 * * * *"]
+"#,
+        )
+        .unwrap();
+        let mut scheduler = WorkflowScheduler::from_config(&schedules, TUESDAY_13TH).unwrap();
+        scheduler.sync_in_flight(&db, &store);
+        assert!(scheduler.due(TUESDAY_13TH + HOUR).is_empty());
+        assert_eq!(scheduler.status()[0].skipped_overlap, 1);
+    }
+}
@@ -0,0 +1,585 @@
+// Workflow triggers
+//
+// External systems start workflows through named triggers declared in aiTOML. A trigger is bound
+// to an HTTP route (a webhook) or to a queue topic, names the workflow it starts, and maps fields
+// of the JSON payload into the trigger context the workflow's handlers see:
+//
+//   [workflow_triggers.store_purchase]
+//   workflow = "grant_purchase"
+//   source = "http"                      # or "queue", with topic = "store.orders"
+//   route = "/hooks/store"
+//   secret = "..."                       # HMAC-SHA256 key; required for http triggers
+//   signature_header = "x-signature"     # default x-arcadia-signature
+//   timestamp_header = "x-timestamp"     # default x-arcadia-timestamp
+//   max_skew_secs = 300                  # oldest (or furthest ahead) timestamp accepted
+//   [workflow_triggers.store_purchase.context]
+//   player = "/buyer/id"                 # context key = JSON pointer into the payload
+//
+// Without a context table the whole payload is the context. Signatures are the hex HMAC-SHA256
+// of "<timestamp>.<raw body>", optionally prefixed "sha256=", compared in constant time; the
+// timestamp is unix seconds and travels in its own header, so a captured request cannot be
+// replayed once it is more than `max_skew_secs` old. An http route is reachable by anyone who can
+// reach the port, so the registry refuses http triggers without a secret.
+//
+// WebhookListener serves the HTTP routes on a background thread; accepted payloads are queued and
+// handed to the game by `poll`, so workflows still run on the game's thread. It serves at most
+// MAX_CONNECTIONS requests at once (503 beyond that) and caps header lines and their count as
+// well as the body. Queue messages come either from an external broker client through
+// `accept_message`, or from the in-process event bus through `accept_event` (trusted, so not
+// signed).
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use sha2::Sha256;
+
+use crate::events::Event;
+
+pub const DEFAULT_SIGNATURE_HEADER: &str = "x-arcadia-signature";
+pub const DEFAULT_TIMESTAMP_HEADER: &str = "x-arcadia-timestamp";
+pub const DEFAULT_MAX_SKEW_SECS: u64 = 300;
+
+// Larger webhook bodies are rejected with 413
+const MAX_BODY: usize = 1 << 20;
+// Longer request or header lines, or more headers, are rejected with 431
+const MAX_LINE: usize = 8 * 1024;
+const MAX_HEADERS: usize = 64;
+// Webhook requests served at once; more are turned away with 503
+pub const MAX_CONNECTIONS: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "source", rename_all = "snake_case")]
+pub enum TriggerSource {
+    Http { route: String },
+    Queue { topic: String },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TriggerConfig {
+    pub workflow: String,
+    #[serde(flatten)]
+    pub source: TriggerSource,
+    #[serde(default)]
+    pub secret: Option<String>,
+    #[serde(default = "default_signature_header")]
+    pub signature_header: String,
+    #[serde(default = "default_timestamp_header")]
+    pub timestamp_header: String,
+    #[serde(default = "default_max_skew_secs")]
+    pub max_skew_secs: u64,
+    // Context key -> JSON pointer into the payload
+    #[serde(default)]
+    pub context: BTreeMap<String, String>,
+}
+
+fn default_signature_header() -> String {
+    DEFAULT_SIGNATURE_HEADER.to_string()
+}
+
+fn default_timestamp_header() -> String {
+    DEFAULT_TIMESTAMP_HEADER.to_string()
+}
+
+fn default_max_skew_secs() -> u64 {
+    DEFAULT_MAX_SKEW_SECS
+}
+
+impl TriggerConfig {
+    pub fn http(workflow: &str, route: &str) -> Self {
+        TriggerConfig::new(workflow, TriggerSource::Http { route: route.to_string() })
+    }
+
+    pub fn queue(workflow: &str, topic: &str) -> Self {
+        TriggerConfig::new(workflow, TriggerSource::Queue { topic: topic.to_string() })
+    }
+
+    fn new(workflow: &str, source: TriggerSource) -> Self {
+        TriggerConfig {
+            workflow: workflow.to_string(),
+            source,
+            secret: None,
+            signature_header: default_signature_header(),
+            timestamp_header: default_timestamp_header(),
+            max_skew_secs: DEFAULT_MAX_SKEW_SECS,
+            context: BTreeMap::new(),
+        }
+    }
+
+    pub fn secret(mut self, secret: &str) -> Self {
+        self.secret = Some(secret.to_string());
+        self
+    }
+
+    pub fn map(mut self, key: &str, pointer: &str) -> Self {
+        self.context.insert(key.to_string(), pointer.to_string());
+        self
+    }
+}
+
+// A verified payload, ready to start its workflow with `WorkflowExecutor::run_with`
+#[derive(Debug, Clone, PartialEq)]
+pub struct TriggerEvent {
+    pub trigger: String,
+    pub workflow: String,
+    pub context: Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TriggerError {
+    UnknownTrigger(String),
+    UnknownRoute(String),
+    DuplicateRoute(String),
+    InvalidRoute(String),
+    // An http trigger without a secret
+    Unsigned(String),
+    MissingSignature,
+    BadSignature,
+    MissingTimestamp,
+    StaleTimestamp { timestamp: u64, now: u64 },
+    InvalidPayload(String),
+    MissingField { key: String, pointer: String },
+}
+
+impl fmt::Display for TriggerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TriggerError::UnknownTrigger(name) => write!(f, "unknown trigger '{}'", name),
+            TriggerError::UnknownRoute(route) => write!(f, "no trigger on route '{}'", route),
+            TriggerError::DuplicateRoute(route) => write!(f, "route '{}' is bound to more than one trigger", route),
+            TriggerError::InvalidRoute(route) => write!(f, "route '{}' must start with '/'", route),
+            TriggerError::Unsigned(name) => write!(f, "http trigger '{}' needs a secret", name),
+            TriggerError::MissingSignature => write!(f, "payload is not signed"),
+            TriggerError::BadSignature => write!(f, "payload signature does not match"),
+            TriggerError::MissingTimestamp => write!(f, "signed payload has no valid timestamp"),
+            TriggerError::StaleTimestamp { timestamp, now } => {
+                write!(f, "payload timestamp {} is too far from the current time {}", timestamp, now)
+            }
+            TriggerError::InvalidPayload(err) => write!(f, "payload is not valid JSON: {}", err),
+            TriggerError::MissingField { key, pointer } => write!(f, "payload has nothing at '{}' for '{}'", pointer, key),
+        }
+    }
+}
+
+impl Error for TriggerError {}
+
+#[derive(Debug, Clone, Default)]
+pub struct TriggerRegistry {
+    triggers: BTreeMap<String, TriggerConfig>,
+}
+
+impl TriggerRegistry {
+    pub fn new() -> Self {
+        TriggerRegistry::default()
+    }
+
+    // From the `workflow_triggers` aiTOML section
+    pub fn from_config(triggers: BTreeMap<String, TriggerConfig>) -> Result<Self, TriggerError> {
+        let mut registry = TriggerRegistry::new();
+        for (name, config) in triggers {
+            registry.add(&name, config)?;
+        }
+        Ok(registry)
+    }
+
+    pub fn add(&mut self, name: &str, config: TriggerConfig) -> Result<(), TriggerError> {
+        if let TriggerSource::Http { route } = &config.source {
+            if !route.starts_with('/') {
+                return Err(TriggerError::InvalidRoute(route.clone()));
+            }
+            if config.secret.as_deref().unwrap_or("").is_empty() {
+                return Err(TriggerError::Unsigned(name.to_string()));
+            }
+            if self.route(route).is_some_and(|existing| existing != name) {
+                return Err(TriggerError::DuplicateRoute(route.clone()));
+            }
+        }
+        self.triggers.insert(name.to_string(), config);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&TriggerConfig> {
+        self.triggers.get(name)
+    }
+
+    // Trigger bound to an HTTP route
+    pub fn route(&self, path: &str) -> Option<&str> {
+        self.triggers
+            .iter()
+            .find(|(_, config)| matches!(&config.source, TriggerSource::Http { route } if route == path))
+            .map(|(name, _)| name.as_str())
+    }
+
+    // (trigger, topic) for every queue trigger, to subscribe a broker client with
+    pub fn topics(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.triggers.iter().filter_map(|(name, config)| match &config.source {
+            TriggerSource::Queue { topic } => Some((name.as_str(), topic.as_str())),
+            TriggerSource::Http { .. } => None,
+        })
+    }
+
+    // Verify and map a raw payload for a named trigger; `now` is unix seconds
+    pub fn accept(&self, name: &str, body: &[u8], signed: Option<Signed>, now: u64) -> Result<TriggerEvent, TriggerError> {
+        let config = self.triggers.get(name).ok_or_else(|| TriggerError::UnknownTrigger(name.to_string()))?;
+        if let Some(secret) = &config.secret {
+            let signed = signed.ok_or(TriggerError::MissingSignature)?;
+            let timestamp = signed.timestamp.and_then(|t| t.trim().parse::<u64>().ok()).ok_or(TriggerError::MissingTimestamp)?;
+            if !verify_signature(secret, timestamp, body, signed.signature) {
+                return Err(TriggerError::BadSignature);
+            }
+            if timestamp.abs_diff(now) > config.max_skew_secs {
+                return Err(TriggerError::StaleTimestamp { timestamp, now });
+            }
+        }
+        let payload: Value = serde_json::from_slice(body).map_err(|err| TriggerError::InvalidPayload(err.to_string()))?;
+        self.event(name, config, payload)
+    }
+
+    // Webhook request; header names are matched case-insensitively
+    pub fn accept_http(&self, path: &str, headers: &HashMap<String, String>, body: &[u8], now: u64) -> Result<TriggerEvent, TriggerError> {
+        let name = self.route(path).ok_or_else(|| TriggerError::UnknownRoute(path.to_string()))?;
+        let config = &self.triggers[name];
+        let header = |name: &str| {
+            let name = name.to_lowercase();
+            headers.iter().find(|(key, _)| key.to_lowercase() == name).map(|(_, value)| value.as_str())
+        };
+        let signed = header(&config.signature_header)
+            .map(|signature| Signed { signature, timestamp: header(&config.timestamp_header) });
+        self.accept(name, body, signed, now)
+    }
+
+    // Message from an external broker; one result per trigger subscribed to the topic
+    pub fn accept_message(&self, topic: &str, body: &[u8], signed: Option<Signed>, now: u64) -> Vec<Result<TriggerEvent, TriggerError>> {
+        let names: Vec<&str> = self.topics().filter(|(_, t)| *t == topic).map(|(name, _)| name).collect();
+        names.into_iter().map(|name| self.accept(name, body, signed, now)).collect()
+    }
+
+    // Event from the in-process bus; not signature-checked
+    pub fn accept_event(&self, event: &Event) -> Vec<Result<TriggerEvent, TriggerError>> {
+        let names: Vec<&str> = self.topics().filter(|(_, t)| *t == event.topic).map(|(name, _)| name).collect();
+        names.into_iter().map(|name| self.event(name, &self.triggers[name], event.payload.clone())).collect()
+    }
+
+    fn event(&self, name: &str, config: &TriggerConfig, payload: Value) -> Result<TriggerEvent, TriggerError> {
+        let context = if config.context.is_empty() {
+            payload
+        } else {
+            let mut context = Map::new();
+            for (key, pointer) in &config.context {
+                let value = payload
+                    .pointer(pointer)
+                    .ok_or_else(|| TriggerError::MissingField { key: key.clone(), pointer: pointer.clone() })?;
+                context.insert(key.clone(), value.clone());
+            }
+            Value::Object(context)
+        };
+        Ok(TriggerEvent { trigger: name.to_string(), workflow: config.workflow.clone(), context })
+    }
+}
+
+// The signature and timestamp headers of a payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signed<'a> {
+    pub signature: &'a str,
+    pub timestamp: Option<&'a str>,
+}
+
+// Hex HMAC-SHA256 of "<timestamp>.<body>", as senders should put it in the signature header
+pub fn sign(secret: &str, timestamp: u64, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}.", timestamp).as_bytes());
+    mac.update(body);
+    let tag = mac.finalize().into_bytes();
+    format!("sha256={}", tag.iter().map(|byte| format!("{:02x}", byte)).collect::<String>())
+}
+
+pub fn verify_signature(secret: &str, timestamp: u64, body: &[u8], signature: &str) -> bool {
+    let signature = signature.trim();
+    let Some(tag) = decode_hex(signature.strip_prefix("sha256=").unwrap_or(signature)) else { return false };
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else { return false };
+    mac.update(format!("{}.", timestamp).as_bytes());
+    mac.update(body);
+    mac.verify_slice(&tag).is_ok()
+}
+
+// None for odd lengths too, since the last pair is then cut short
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok()).collect()
+}
+
+// Minimal HTTP/1.1 endpoint for the registry's webhook routes. Only POST is accepted; replies
+// are 202 when the payload was queued, 401 for signature or timestamp failures, 404 for unknown
+// routes, 400 for bad payloads and 503 while MAX_CONNECTIONS requests are already in flight.
+pub struct WebhookListener {
+    addr: SocketAddr,
+    accepted: Receiver<TriggerEvent>,
+    stop: Arc<AtomicBool>,
+}
+
+impl WebhookListener {
+    pub fn start(bind: &str, registry: Arc<TriggerRegistry>) -> io::Result<Self> {
+        let listener = TcpListener::bind(bind)?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let (tx, accepted) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let accept_stop = Arc::clone(&stop);
+        thread::Builder::new()
+            .name("arcadia-webhooks".to_string())
+            .spawn(move || accept_loop(listener, registry, tx, accept_stop))?;
+        Ok(WebhookListener { addr, accepted, stop })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    // Payloads accepted since the last call
+    pub fn poll(&self) -> Vec<TriggerEvent> {
+        self.accepted.try_iter().collect()
+    }
+}
+
+impl Drop for WebhookListener {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+// Releases a connection slot when its request is done
+struct Slot(Arc<AtomicUsize>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+fn accept_loop(listener: TcpListener, registry: Arc<TriggerRegistry>, tx: Sender<TriggerEvent>, stop: Arc<AtomicBool>) {
+    let active = Arc::new(AtomicUsize::new(0));
+    while !stop.load(Ordering::Relaxed) {
+        match listener.accept() {
+            Ok((mut stream, _)) => {
+                if active.fetch_add(1, Ordering::AcqRel) >= MAX_CONNECTIONS {
+                    active.fetch_sub(1, Ordering::AcqRel);
+                    let _ = stream.set_nonblocking(false);
+                    let _ = stream.set_write_timeout(Some(Duration::from_secs(1)));
+                    respond(&mut stream, 503, &json!({ "error": "too many concurrent requests" }));
+                    continue;
+                }
+                let slot = Slot(Arc::clone(&active));
+                let (registry, tx) = (Arc::clone(&registry), tx.clone());
+                thread::spawn(move || {
+                    serve(stream, &registry, &tx);
+                    drop(slot);
+                });
+            }
+            Err(_) => thread::sleep(Duration::from_millis(50)),
+        }
+    }
+}
+
+fn serve(mut stream: TcpStream, registry: &TriggerRegistry, tx: &Sender<TriggerEvent>) {
+    let _ = stream.set_nonblocking(false);
+    let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+    let _ = stream.set_write_timeout(Some(Duration::from_secs(5)));
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (status, body) = match read_request(&stream) {
+        Ok((method, _, _, _)) if method != "POST" => (405, json!({ "error": "only POST is accepted" })),
+        Ok((_, path, headers, body)) => match registry.accept_http(&path, &headers, &body, now) {
+            Ok(event) => {
+                let trigger = event.trigger.clone();
+                let _ = tx.send(event);
+                (202, json!({ "accepted": trigger }))
+            }
+            Err(
+                err @ (TriggerError::MissingSignature
+                | TriggerError::BadSignature
+                | TriggerError::MissingTimestamp
+                | TriggerError::StaleTimestamp { .. }),
+            ) => (401, json!({ "error": err.to_string() })),
+            Err(err @ TriggerError::UnknownRoute(_)) => (404, json!({ "error": err.to_string() })),
+            Err(err) => (400, json!({ "error": err.to_string() })),
+        },
+        Err(status) => (status, json!({ "error": "malformed request" })),
+    };
+    respond(&mut stream, status, &body);
+}
+
+fn respond(stream: &mut TcpStream, status: u16, body: &Value) {
+    let reason = match status {
+        202 => "Accepted",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Bad Request",
+    };
+    let body = body.to_string();
+    let _ = write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    );
+}
+
+type Request = (String, String, HashMap<String, String>, Vec<u8>);
+
+// Request line, headers and body; Err carries the status to reply with
+fn read_request(stream: &TcpStream) -> Result<Request, u16> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    read_line(&mut reader, &mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else { return Err(400) };
+    let path = target.split('?').next().unwrap_or(target).to_string();
+    let method = method.to_string();
+
+    let mut headers = HashMap::new();
+    for count in 0.. {
+        line.clear();
+        read_line(&mut reader, &mut line)?;
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if count == MAX_HEADERS {
+            return Err(431);
+        }
+        if let Some((key, value)) = header.split_once(':') {
+            headers.insert(key.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+    let length: usize = headers.get("content-length").and_then(|v| v.parse().ok()).unwrap_or(0);
+    if length > MAX_BODY {
+        return Err(413);
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).map_err(|_| 400u16)?;
+    Ok((method, path, headers, body))
+}
+
+// One CRLF-terminated line of at most MAX_LINE bytes
+fn read_line(reader: &mut impl BufRead, line: &mut String) -> Result<(), u16> {
+    let read = reader.take(MAX_LINE as u64 + 1).read_line(line).map_err(|_| 400u16)?;
+    if read > MAX_LINE && !line.ends_with('\n') {
+        return Err(431);
+    }
+    if read == 0 {
+        return Err(400);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn registry() -> TriggerRegistry {
+        let mut registry = TriggerRegistry::new();
+        registry.add("purchase", TriggerConfig::http("grant_purchase", "/hooks/store").secret("key").map("player", "/buyer/id")).unwrap();
+        registry
+    }
+
+    fn headers(signature: &str, timestamp: u64) -> HashMap<String, String> {
+        HashMap::from([
+            ("X-Arcadia-Signature".to_string(), signature.to_string()),
+            ("x-arcadia-timestamp".to_string(), timestamp.to_string()),
+        ])
+    }
+
+    #[test]
+    fn signed_payloads_are_mapped_into_the_context() {
+        let body = br#"{"buyer": {"id": "p1"}}"#;
+        let event = registry().accept_http("/hooks/store", &headers(&sign("key", NOW, body), NOW), body, NOW + 10).unwrap();
+        assert_eq!(event.workflow, "grant_purchase");
+        assert_eq!(event.context, json!({ "player": "p1" }));
+    }
+
+    #[test]
+    fn timestamps_are_signed_and_must_be_recent() {
+        let registry = registry();
+        let body = br#"{"buyer": {"id": "p1"}}"#;
+        let signature = sign("key", NOW, body);
+        assert_eq!(
+            registry.accept_http("/hooks/store", &headers(&signature, NOW), body, NOW + DEFAULT_MAX_SKEW_SECS + 1),
+            Err(TriggerError::StaleTimestamp { timestamp: NOW, now: NOW + DEFAULT_MAX_SKEW_SECS + 1 })
+        );
+        // Moving the timestamp forward breaks the signature
+        assert_eq!(registry.accept_http("/hooks/store", &headers(&signature, NOW + 400), body, NOW + 400), Err(TriggerError::BadSignature));
+
+        let unstamped = HashMap::from([("x-arcadia-signature".to_string(), signature)]);
+        assert_eq!(registry.accept_http("/hooks/store", &unstamped, body, NOW), Err(TriggerError::MissingTimestamp));
+        assert_eq!(registry.accept_http("/hooks/store", &HashMap::new(), body, NOW), Err(TriggerError::MissingSignature));
+    }
+
+    #[test]
+    fn http_triggers_need_a_secret() {
+        let mut registry = TriggerRegistry::new();
+        assert_eq!(registry.add("open", TriggerConfig::http("wf", "/hooks/open")), Err(TriggerError::Unsigned("open".to_string())));
+        assert!(registry.add("bus", TriggerConfig::queue("wf", "store.orders")).is_ok());
+    }
+
+    fn request(addr: SocketAddr, raw: &[u8]) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let _ = stream.write_all(raw);
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response);
+        response
+    }
+
+    #[test]
+    fn listener_caps_header_lines_and_count() {
+        let listener = WebhookListener::start("127.0.0.1:0", Arc::new(registry())).unwrap();
+        let long = format!("POST /hooks/store HTTP/1.1\r\nx-long: {}\r\n\r\n", "a".repeat(MAX_LINE));
+        assert!(request(listener.local_addr(), long.as_bytes()).starts_with("HTTP/1.1 431"));
+
+        let many: String = (0..=MAX_HEADERS).map(|i| format!("x-h{}: 1\r\n", i)).collect();
+        let many = format!("POST /hooks/store HTTP/1.1\r\n{}\r\n", many);
+        assert!(request(listener.local_addr(), many.as_bytes()).starts_with("HTTP/1.1 431"));
+    }
+
+    #[test]
+    fn listener_accepts_signed_requests() {
+        let listener = WebhookListener::start("127.0.0.1:0", Arc::new(registry())).unwrap();
+        let body = r#"{"buyer": {"id": "p1"}}"#;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        let raw = format!(
+            "POST /hooks/store HTTP/1.1\r\nx-arcadia-signature: {}\r\nx-arcadia-timestamp: {}\r\nContent-Length: {}\r\n\r\n{}",
+            sign("key", now, body.as_bytes()),
+            now,
+            body.len(),
+            body
+        );
+        assert!(request(listener.local_addr(), raw.as_bytes()).starts_with("HTTP/1.1 202"));
+        assert_eq!(listener.poll().len(), 1);
+    }
+
+    #[test]
+    fn listener_turns_away_connections_over_the_limit() {
+        let listener = WebhookListener::start("127.0.0.1:0", Arc::new(registry())).unwrap();
+        // Idle connections hold their slots until the read timeout
+        let idle: Vec<TcpStream> = (0..MAX_CONNECTIONS).map(|_| TcpStream::connect(listener.local_addr()).unwrap()).collect();
+        thread::sleep(Duration::from_millis(300));
+        assert!(request(listener.local_addr(), b"").starts_with("HTTP/1.1 503"));
+        drop(idle);
+    }
+}
@@ -0,0 +1,423 @@
+// Game world state
+//
+// The authoritative world is a set of entities, each a kind plus named JSON components, and a
+// map of world globals. Two worlds can be diffed into a WorldDelta, a list of changes that record
+// both the old and the new value. Applying a delta checks every old value against the current
+// state, so a delta built against a stale copy is detected as a conflict instead of silently
+// overwriting newer data. This serves network reconciliation and tools comparing two
+// simulation branches.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Entity {
+    pub kind: String,
+    pub components: BTreeMap<String, Value>,
+}
+
+impl Entity {
+    pub fn new(kind: &str) -> Self {
+        Entity { kind: kind.to_string(), components: BTreeMap::new() }
+    }
+
+    pub fn with(mut self, component: &str, value: Value) -> Self {
+        self.components.insert(component.to_string(), value);
+        self
+    }
+
+    pub fn component(&self, name: &str) -> Option<&Value> {
+        self.components.get(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Change {
+    EntityAdded { id: String, entity: Entity },
+    EntityRemoved { id: String, previous: Entity },
+    // Entity kind changed; components are diffed separately
+    KindChanged { id: String, old: String, new: String },
+    ComponentSet { id: String, component: String, old: Option<Value>, new: Value },
+    ComponentRemoved { id: String, component: String, old: Value },
+    GlobalSet { key: String, old: Option<Value>, new: Value },
+    GlobalRemoved { key: String, old: Value },
+}
+
+impl Change {
+    // Dotted location of the change, e.g. "entities.npc-1.health"
+    pub fn path(&self) -> String {
+        match self {
+            Change::EntityAdded { id, .. } | Change::EntityRemoved { id, .. } => format!("entities.{}", id),
+            Change::KindChanged { id, .. } => format!("entities.{}.kind", id),
+            Change::ComponentSet { id, component, .. } | Change::ComponentRemoved { id, component, .. } => {
+                format!("entities.{}.{}", id, component)
+            }
+            Change::GlobalSet { key, .. } | Change::GlobalRemoved { key, .. } => format!("globals.{}", key),
+        }
+    }
+
+    pub fn entity_id(&self) -> Option<&str> {
+        match self {
+            Change::EntityAdded { id, .. }
+            | Change::EntityRemoved { id, .. }
+            | Change::KindChanged { id, .. }
+            | Change::ComponentSet { id, .. }
+            | Change::ComponentRemoved { id, .. } => Some(id),
+            Change::GlobalSet { .. } | Change::GlobalRemoved { .. } => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WorldDelta {
+    pub base_tick: u64,
+    pub target_tick: u64,
+    pub changes: Vec<Change>,
+}
+
+impl WorldDelta {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    // Changes touching one entity
+    pub fn for_entity<'a>(&'a self, id: &'a str) -> impl Iterator<Item = &'a Change> + 'a {
+        self.changes.iter().filter(move |c| c.entity_id() == Some(id))
+    }
+}
+
+// A change whose expected old value does not match the current world
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conflict {
+    pub path: String,
+    pub expected: Option<Value>,
+    pub actual: Option<Value>,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |v: &Option<Value>| v.as_ref().map_or("<absent>".to_string(), |v| v.to_string());
+        write!(f, "{}: expected {}, found {}", self.path, show(&self.expected), show(&self.actual))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApplyMode {
+    // Apply nothing if any change conflicts
+    Strict,
+    // Apply the non-conflicting changes only
+    SkipConflicts,
+    // Apply everything; the delta wins (authoritative server corrections)
+    Force,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ApplyReport {
+    pub applied: usize,
+    pub conflicts: Vec<Conflict>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DeltaConflicts(pub Vec<Conflict>);
+
+impl fmt::Display for DeltaConflicts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} conflicting changes", self.0.len())?;
+        for conflict in &self.0 {
+            write!(f, "\n  {}", conflict)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for DeltaConflicts {}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GameWorld {
+    pub tick: u64,
+    entities: BTreeMap<String, Entity>,
+    globals: BTreeMap<String, Value>,
+}
+
+impl GameWorld {
+    pub fn new() -> Self {
+        GameWorld::default()
+    }
+
+    pub fn spawn(&mut self, id: &str, entity: Entity) -> Option<Entity> {
+        self.entities.insert(id.to_string(), entity)
+    }
+
+    pub fn despawn(&mut self, id: &str) -> Option<Entity> {
+        self.entities.remove(id)
+    }
+
+    pub fn entity(&self, id: &str) -> Option<&Entity> {
+        self.entities.get(id)
+    }
+
+    pub fn entity_mut(&mut self, id: &str) -> Option<&mut Entity> {
+        self.entities.get_mut(id)
+    }
+
+    pub fn entities(&self) -> impl Iterator<Item = (&String, &Entity)> {
+        self.entities.iter()
+    }
+
+    pub fn set_component(&mut self, id: &str, component: &str, value: Value) -> bool {
+        match self.entities.get_mut(id) {
+            Some(entity) => {
+                entity.components.insert(component.to_string(), value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn global(&self, key: &str) -> Option<&Value> {
+        self.globals.get(key)
+    }
+
+    pub fn set_global(&mut self, key: &str, value: Value) {
+        self.globals.insert(key.to_string(), value);
+    }
+
+    // Changes that turn `self` into `other`
+    pub fn diff(&self, other: &GameWorld) -> WorldDelta {
+        let mut changes = Vec::new();
+        for (id, entity) in &self.entities {
+            match other.entities.get(id) {
+                None => changes.push(Change::EntityRemoved { id: id.clone(), previous: entity.clone() }),
+                Some(new) => diff_entity(id, entity, new, &mut changes),
+            }
+        }
+        for (id, entity) in &other.entities {
+            if !self.entities.contains_key(id) {
+                changes.push(Change::EntityAdded { id: id.clone(), entity: entity.clone() });
+            }
+        }
+        diff_maps(&self.globals, &other.globals, &mut changes, |key, old, new| match new {
+            Some(new) => Change::GlobalSet { key, old, new },
+            None => Change::GlobalRemoved { key, old: old.unwrap_or(Value::Null) },
+        });
+        WorldDelta { base_tick: self.tick, target_tick: other.tick, changes }
+    }
+
+    // Conflicts the delta would hit against the current state
+    pub fn check_delta(&self, delta: &WorldDelta) -> Vec<Conflict> {
+        delta.changes.iter().filter_map(|c| self.conflict(c)).collect()
+    }
+
+    pub fn apply_delta(&mut self, delta: &WorldDelta, mode: ApplyMode) -> Result<ApplyReport, DeltaConflicts> {
+        let conflicts = self.check_delta(delta);
+        if mode == ApplyMode::Strict && !conflicts.is_empty() {
+            return Err(DeltaConflicts(conflicts));
+        }
+        let mut applied = 0;
+        for change in &delta.changes {
+            if mode == ApplyMode::SkipConflicts && self.conflict(change).is_some() {
+                continue;
+            }
+            self.apply_change(change);
+            applied += 1;
+        }
+        self.tick = self.tick.max(delta.target_tick);
+        Ok(ApplyReport { applied, conflicts })
+    }
+
+    fn conflict(&self, change: &Change) -> Option<Conflict> {
+        let (expected, actual) = match change {
+            Change::EntityAdded { id, .. } => (None, self.entities.get(id).map(entity_value)),
+            Change::EntityRemoved { id, previous } => (Some(entity_value(previous)), self.entities.get(id).map(entity_value)),
+            Change::KindChanged { id, old, .. } => (
+                Some(Value::String(old.clone())),
+                self.entities.get(id).map(|e| Value::String(e.kind.clone())),
+            ),
+            Change::ComponentSet { id, component, old, .. } => {
+                (old.clone(), self.entities.get(id).and_then(|e| e.components.get(component)).cloned())
+            }
+            Change::ComponentRemoved { id, component, old } => {
+                (Some(old.clone()), self.entities.get(id).and_then(|e| e.components.get(component)).cloned())
+            }
+            Change::GlobalSet { key, old, .. } => (old.clone(), self.globals.get(key).cloned()),
+            Change::GlobalRemoved { key, old } => (Some(old.clone()), self.globals.get(key).cloned()),
+        };
+        (expected != actual).then(|| Conflict { path: change.path(), expected, actual })
+    }
+
+    fn apply_change(&mut self, change: &Change) {
+        match change {
+            Change::EntityAdded { id, entity } => {
+                self.entities.insert(id.clone(), entity.clone());
+            }
+            Change::EntityRemoved { id, .. } => {
+                self.entities.remove(id);
+            }
+            Change::KindChanged { id, new, .. } => {
+                self.entities.entry(id.clone()).or_default().kind = new.clone();
+            }
+            Change::ComponentSet { id, component, new, .. } => {
+                self.entities
+                    .entry(id.clone())
+                    .or_default()
+                    .components
+                    .insert(component.clone(), new.clone());
+            }
+            Change::ComponentRemoved { id, component, .. } => {
+                if let Some(entity) = self.entities.get_mut(id) {
+                    entity.components.remove(component);
+                }
+            }
+            Change::GlobalSet { key, new, .. } => {
+                self.globals.insert(key.clone(), new.clone());
+            }
+            Change::GlobalRemoved { key, .. } => {
+                self.globals.remove(key);
+            }
+        }
+    }
+}
+
+fn entity_value(entity: &Entity) -> Value {
+    serde_json::to_value(entity).unwrap_or(Value::Null)
+}
+
+fn diff_entity(id: &str, old: &Entity, new: &Entity, changes: &mut Vec<Change>) {
+    if old.kind != new.kind {
+        changes.push(Change::KindChanged { id: id.to_string(), old: old.kind.clone(), new: new.kind.clone() });
+    }
+    diff_maps(&old.components, &new.components, changes, |component, old, new| match new {
+        Some(new) => Change::ComponentSet { id: id.to_string(), component, old, new },
+        None => Change::ComponentRemoved { id: id.to_string(), component, old: old.unwrap_or(Value::Null) },
+    });
+}
+
+// Emit a change for every key whose value differs; `make` gets (key, old, new)
+fn diff_maps(
+    old: &BTreeMap<String, Value>,
+    new: &BTreeMap<String, Value>,
+    changes: &mut Vec<Change>,
+    make: impl Fn(String, Option<Value>, Option<Value>) -> Change,
+) {
+    for (key, old_value) in old {
+        match new.get(key) {
+            Some(new_value) if new_value == old_value => {}
+            new_value => changes.push(make(key.clone(), Some(old_value.clone()), new_value.cloned())),
+        }
+    }
+    for (key, new_value) in new {
+        if !old.contains_key(key) {
+            changes.push(make(key.clone(), None, Some(new_value.clone())));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn base() -> GameWorld {
+        let mut world = GameWorld::new();
+        world.tick = 10;
+        world.spawn("npc-1", Entity::new("guard").with("health", json!(100)).with("mood", json!("calm")));
+        world.spawn("chest", Entity::new("container").with("gold", json!(5)));
+        world.set_global("weather", json!("rain"));
+        world
+    }
+
+    fn paths(delta: &WorldDelta) -> Vec<String> {
+        delta.changes.iter().map(Change::path).collect()
+    }
+
+    #[test]
+    fn diff_records_every_change_and_replays_to_the_target() {
+        let old = base();
+        let mut new = old.clone();
+        new.tick = 12;
+        new.set_component("npc-1", "health", json!(80));
+        new.entity_mut("npc-1").unwrap().components.remove("mood");
+        new.entity_mut("npc-1").unwrap().kind = "captain".to_string();
+        new.despawn("chest");
+        new.spawn("wolf", Entity::new("beast"));
+        new.set_global("weather", json!("fog"));
+        new.set_global("alarm", json!(true));
+
+        let delta = old.diff(&new);
+        assert_eq!((delta.base_tick, delta.target_tick), (10, 12));
+        assert_eq!(
+            paths(&delta),
+            vec![
+                "entities.chest",
+                "entities.npc-1.kind",
+                "entities.npc-1.health",
+                "entities.npc-1.mood",
+                "entities.wolf",
+                "globals.weather",
+                "globals.alarm",
+            ]
+        );
+        assert_eq!(delta.for_entity("npc-1").count(), 3);
+
+        let mut replay = old.clone();
+        let report = replay.apply_delta(&delta, ApplyMode::Strict).unwrap();
+        assert_eq!(report.applied, 7);
+        assert_eq!(replay, new);
+        assert!(new.diff(&replay).is_empty());
+    }
+
+    #[test]
+    fn deltas_survive_serialization() {
+        let mut new = base();
+        new.set_component("chest", "gold", json!(0));
+        let delta = base().diff(&new);
+        let json = serde_json::to_value(&delta).unwrap();
+        assert_eq!(json["changes"][0]["op"], "component_set");
+        assert_eq!(serde_json::from_value::<WorldDelta>(json).unwrap(), delta);
+    }
+
+    #[test]
+    fn stale_deltas_conflict_according_to_the_mode() {
+        let mut new = base();
+        new.set_component("npc-1", "health", json!(80));
+        new.set_global("weather", json!("fog"));
+        let delta = base().diff(&new);
+
+        // Someone else changed the guard's health in the meantime
+        let mut current = base();
+        current.set_component("npc-1", "health", json!(90));
+
+        let err = current.clone().apply_delta(&delta, ApplyMode::Strict).unwrap_err();
+        assert_eq!(err.0.len(), 1);
+        assert_eq!(err.to_string(), "1 conflicting changes\n  entities.npc-1.health: expected 100, found 90");
+
+        let mut skipping = current.clone();
+        let report = skipping.apply_delta(&delta, ApplyMode::SkipConflicts).unwrap();
+        assert_eq!((report.applied, report.conflicts.len()), (1, 1));
+        assert_eq!(skipping.entity("npc-1").unwrap().component("health"), Some(&json!(90)));
+        assert_eq!(skipping.global("weather"), Some(&json!("fog")));
+
+        let mut forced = current;
+        let report = forced.apply_delta(&delta, ApplyMode::Force).unwrap();
+        assert_eq!(report.applied, 2);
+        assert_eq!(forced.entity("npc-1").unwrap().component("health"), Some(&json!(80)));
+    }
+
+    #[test]
+    fn adding_an_existing_entity_is_a_conflict() {
+        let mut new = base();
+        new.spawn("wolf", Entity::new("beast"));
+        let delta = base().diff(&new);
+
+        let mut current = base();
+        current.spawn("wolf", Entity::new("dog"));
+        let conflicts = current.check_delta(&delta);
+        assert_eq!(conflicts[0].path, "entities.wolf");
+        assert_eq!(conflicts[0].expected, None);
+        assert_eq!(conflicts[0].actual.as_ref().unwrap()["kind"], "dog");
+    }
+}